@@ -0,0 +1,85 @@
+/*
+ * RemoteJuggler C ABI
+ *
+ * Stable C interface to the RemoteJuggler core library (libremote_juggler_gui),
+ * for Qt/TUI frontends and editor plugins.
+ *
+ * Build the shared library without the GTK frontend:
+ *   cargo build --release --lib --no-default-features
+ *
+ * Ownership:
+ *   - Every `char *` returned by an rj_* function is owned by the caller and
+ *     must be released with rj_string_free(). NULL signals failure.
+ *   - Functions returning int return RJ_OK on success and RJ_ERR on failure.
+ *   - After a failure, rj_last_error() describes the most recent error on the
+ *     calling thread.
+ *
+ * All strings are UTF-8 and nul-terminated.
+ */
+
+#ifndef REMOTE_JUGGLER_H
+#define REMOTE_JUGGLER_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RJ_OK 0
+#define RJ_ERR (-1)
+
+/* Opaque handle to a loaded configuration */
+typedef struct RjConfig RjConfig;
+
+/* Library version string. Statically allocated; do not free. */
+const char *rj_version(void);
+
+/* Copy of the last error message on this thread, or NULL if none */
+char *rj_last_error(void);
+
+/* Free a string returned by any rj_* function. NULL is ignored. */
+void rj_string_free(char *s);
+
+/* Configuration */
+
+/* Load config.json from `path`, or the default location if NULL */
+RjConfig *rj_config_load(const char *path);
+void rj_config_free(RjConfig *config);
+
+/* Name of the current identity, or NULL if none is active */
+char *rj_config_current_identity(const RjConfig *config);
+
+/* All identities as a JSON object keyed by identity name */
+char *rj_config_identities_json(const RjConfig *config);
+
+/* Identities grouped into profiles, as a JSON array */
+char *rj_config_profiles_json(const RjConfig *config);
+
+/* Repository detection */
+
+/* The identity for the repository at `repo` as a JSON object (identity,
+ * remote, url, reason), or NULL if none matches or on failure */
+char *rj_detect_json(const RjConfig *config, const char *repo);
+
+/* Identity switching */
+
+int rj_switch(const char *identity);
+
+/* Key store */
+
+char *rj_keys_status(void);
+char *rj_keys_get(const char *entry_path);
+char *rj_keys_search(const char *query);
+int rj_keys_store(const char *entry_path, const char *value);
+int rj_keys_delete(const char *entry_path);
+
+/* Secret resolution */
+
+/* The first match across every source in the configured order; a name of
+ * the form GitHubApp/<app>/token mints a GitHub App installation token */
+char *rj_resolve(const char *name);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* REMOTE_JUGGLER_H */
@@ -0,0 +1,316 @@
+//! age identities and file encryption
+//!
+//! Generates age identities with `age-keygen`, keeps them in the key store,
+//! and encrypts/decrypts files with the `age` binary using identities
+//! resolved from KDBX. The default entry is the one `keys sops-export`
+//! reads, so a generated identity is immediately usable as a SOPS recipient.
+//!
+//! age only reads identities from files, so decryption writes the secret to
+//! a private (0600) temporary file that is removed as soon as age exits.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cli::{self, RunOptions};
+use crate::error::Error;
+use crate::platform;
+
+/// Key store entry used by `keys sops-export` and SOPS decryption
+pub const DEFAULT_IDENTITY_PATH: &str = "RemoteJuggler/SOPS/age-key";
+
+/// Environment variable SOPS reads an age identity from
+pub const SOPS_AGE_KEY_ENV: &str = "SOPS_AGE_KEY";
+
+const SECRET_PREFIX: &str = "AGE-SECRET-KEY-";
+const AGE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// An age identity and its public recipient
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub secret: String,
+    pub recipient: String,
+}
+
+/// The age binary, overridable like in the CLI
+pub fn age_program() -> String {
+    std::env::var("REMOTE_JUGGLER_AGE_PATH").unwrap_or_else(|_| "age".to_string())
+}
+
+/// The age-keygen binary, overridable like in the CLI
+pub fn age_keygen_program() -> String {
+    std::env::var("REMOTE_JUGGLER_AGE_KEYGEN_PATH").unwrap_or_else(|_| "age-keygen".to_string())
+}
+
+fn age_options() -> RunOptions {
+    RunOptions {
+        timeout: AGE_TIMEOUT,
+        retries: 0,
+        cancel: None,
+    }
+}
+
+/// Extract the secret key from an identity file's contents
+pub fn parse_secret(contents: &str) -> Option<&str> {
+    contents
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with(SECRET_PREFIX))
+}
+
+/// Parse `age-keygen` output into an identity
+///
+/// The public key is printed as a `# public key: age1...` comment.
+pub fn parse_keygen_output(output: &str) -> Option<Identity> {
+    let secret = parse_secret(output)?.to_string();
+    let recipient = output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("# public key:"))
+        .map(|r| r.trim().to_string())?;
+    Some(Identity { secret, recipient })
+}
+
+/// `SOPS_AGE_KEY=...` line, the equivalent of `export --format=sops-age`
+pub fn sops_age_env(secret: &str) -> String {
+    format!("{}={}", SOPS_AGE_KEY_ENV, secret)
+}
+
+/// Where an encrypted/decrypted copy of `input` is written by default
+///
+/// Encrypting appends `.age`; decrypting strips it (or appends
+/// `.decrypted` when there is no `.age` suffix).
+pub fn default_output_path(input: &Path, encrypting: bool) -> PathBuf {
+    let name = input.to_string_lossy();
+    if encrypting {
+        PathBuf::from(format!("{}.age", name))
+    } else {
+        match name.strip_suffix(".age") {
+            Some(stripped) if !stripped.is_empty() => PathBuf::from(stripped),
+            _ => PathBuf::from(format!("{}.decrypted", name)),
+        }
+    }
+}
+
+/// Generate a new identity with age-keygen
+pub fn generate() -> Result<Identity, Error> {
+    let stdout =
+        cli::run_program(&age_keygen_program(), &[], &age_options()).map_err(Error::from_cli)?;
+    parse_keygen_output(&stdout).ok_or(Error::InvalidOutput {
+        program: "age-keygen",
+        message: "no identity in output".to_string(),
+    })
+}
+
+/// Derive the public recipient for a secret key
+pub fn recipient_for(secret: &str) -> Result<String, Error> {
+    let identity_file = SecretFile::create(secret)?;
+    let args = vec![
+        "-y".to_string(),
+        identity_file.path().to_string_lossy().to_string(),
+    ];
+    let stdout =
+        cli::run_program(&age_keygen_program(), &args, &age_options()).map_err(Error::from_cli)?;
+    stdout
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("age1"))
+        .map(str::to_string)
+        .ok_or(Error::InvalidOutput {
+            program: "age-keygen",
+            message: "no recipient in output".to_string(),
+        })
+}
+
+/// Fetch an identity's secret key from the key store
+pub fn load_secret(entry_path: &str) -> Result<String, Error> {
+    let value = cli::run(&cli::args(&["keys", "get", entry_path])).map_err(Error::from_cli)?;
+    parse_secret(&value)
+        .map(str::to_string)
+        .ok_or(Error::InvalidOutput {
+            program: "remote-juggler",
+            message: format!("{} does not contain an age identity", entry_path),
+        })
+}
+
+/// Generate an identity and store it in the key store
+pub fn generate_and_store(entry_path: &str) -> Result<Identity, Error> {
+    let identity = generate()?;
+    cli::store_secret(entry_path, &identity.secret).map_err(Error::from_cli)?;
+    Ok(identity)
+}
+
+/// Encrypt `input` to `output` for the given recipients
+pub fn encrypt(input: &Path, output: &Path, recipients: &[String]) -> Result<(), Error> {
+    let mut args = Vec::new();
+    for recipient in recipients {
+        args.push("-r".to_string());
+        args.push(recipient.clone());
+    }
+    args.push("-o".to_string());
+    args.push(output.to_string_lossy().to_string());
+    args.push(input.to_string_lossy().to_string());
+    cli::run_program(&age_program(), &args, &age_options())
+        .map(|_| ())
+        .map_err(Error::from_cli)
+}
+
+/// Decrypt `input` to `output` with a secret key
+pub fn decrypt(input: &Path, output: &Path, secret: &str) -> Result<(), Error> {
+    let identity_file = SecretFile::create(secret)?;
+    let args = vec![
+        "-d".to_string(),
+        "-i".to_string(),
+        identity_file.path().to_string_lossy().to_string(),
+        "-o".to_string(),
+        output.to_string_lossy().to_string(),
+        input.to_string_lossy().to_string(),
+    ];
+    cli::run_program(&age_program(), &args, &age_options())
+        .map(|_| ())
+        .map_err(Error::from_cli)
+}
+
+/// Encrypt text for the given recipients, ASCII-armored
+pub fn encrypt_text(plaintext: &str, recipients: &[String]) -> Result<String, Error> {
+    let mut args = vec!["-a".to_string()];
+    for recipient in recipients {
+        args.push("-r".to_string());
+        args.push(recipient.clone());
+    }
+    cli::run_program_with_input(&age_program(), &args, &[], plaintext, &age_options())
+        .map_err(Error::from_cli)
+}
+
+/// Decrypt ASCII-armored text with a secret key
+pub fn decrypt_text(armored: &str, secret: &str) -> Result<String, Error> {
+    let identity_file = SecretFile::create(secret)?;
+    let args = vec![
+        "-d".to_string(),
+        "-i".to_string(),
+        identity_file.path().to_string_lossy().to_string(),
+    ];
+    cli::run_program_with_input(&age_program(), &args, &[], armored, &age_options())
+        .map_err(Error::from_cli)
+}
+
+/// Encrypt a file to the identity stored at `entry_path`
+pub fn encrypt_with_stored(entry_path: &str, input: &Path) -> Result<PathBuf, Error> {
+    let recipient = recipient_for(&load_secret(entry_path)?)?;
+    let output = default_output_path(input, true);
+    encrypt(input, &output, &[recipient])?;
+    Ok(output)
+}
+
+/// Decrypt a file with the identity stored at `entry_path`
+pub fn decrypt_with_stored(entry_path: &str, input: &Path) -> Result<PathBuf, Error> {
+    let output = default_output_path(input, false);
+    if output.exists() {
+        return Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", output.display()),
+        )));
+    }
+    decrypt(input, &output, &load_secret(entry_path)?)?;
+    Ok(output)
+}
+
+/// A private temporary file holding secret material, removed on drop
+struct SecretFile {
+    path: PathBuf,
+}
+
+impl SecretFile {
+    fn create(contents: &str) -> Result<Self, Error> {
+        let dir = platform::runtime_dir();
+        let path = dir.join(format!(
+            "remote-juggler-age-{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+        ));
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let write = |mut file: File| writeln!(file, "{}", contents);
+        options.open(&path).and_then(write)?;
+        Ok(Self { path })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SecretFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYGEN_OUTPUT: &str = "# created: 2024-01-01T00:00:00Z\n\
+        # public key: age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p\n\
+        AGE-SECRET-KEY-1QQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQQ\n";
+
+    #[test]
+    fn test_parse_keygen_output() {
+        let identity = parse_keygen_output(KEYGEN_OUTPUT).unwrap();
+        assert!(identity.recipient.starts_with("age1ql3z"));
+        assert!(identity.secret.starts_with("AGE-SECRET-KEY-1"));
+        assert!(parse_keygen_output("garbage").is_none());
+    }
+
+    #[test]
+    fn test_sops_age_env() {
+        assert_eq!(
+            sops_age_env("AGE-SECRET-KEY-1ABC"),
+            "SOPS_AGE_KEY=AGE-SECRET-KEY-1ABC"
+        );
+    }
+
+    #[test]
+    fn test_default_output_path() {
+        let input = Path::new("/tmp/notes.txt");
+        assert_eq!(
+            default_output_path(input, true),
+            PathBuf::from("/tmp/notes.txt.age")
+        );
+        assert_eq!(
+            default_output_path(Path::new("/tmp/notes.txt.age"), false),
+            PathBuf::from("/tmp/notes.txt")
+        );
+        assert_eq!(
+            default_output_path(input, false),
+            PathBuf::from("/tmp/notes.txt.decrypted")
+        );
+    }
+
+    #[test]
+    fn test_secret_file_is_private_and_removed() {
+        let file = SecretFile::create("AGE-SECRET-KEY-1TEST").unwrap();
+        let path = file.path().to_path_buf();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap().trim(),
+            "AGE-SECRET-KEY-1TEST"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        drop(file);
+        assert!(!path.exists());
+    }
+}
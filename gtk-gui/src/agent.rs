@@ -0,0 +1,481 @@
+//! ssh-agent integration
+//!
+//! On an identity switch the identity's private key is added to the running
+//! agent, constrained by the identity's security mode (confirm each use,
+//! limited lifetime), and optionally the other identities' keys are taken
+//! out so ssh can't offer the wrong one first. An identity can name its
+//! own agent (1Password, gpg-agent, any socket) instead of `SSH_AUTH_SOCK`'s.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, Identity, IdentityAgent, SecurityMode};
+use crate::databases::expand_tilde;
+use crate::error::{CliError, Error};
+use crate::platform;
+
+/// `ssh-add -l` exit code when the agent holds no keys
+const EXIT_NO_KEYS: i32 = 1;
+
+/// `ssh-add -l` exit code when no agent is reachable
+const EXIT_NO_AGENT: i32 = 2;
+
+pub fn ssh_add_program() -> String {
+    std::env::var("REMOTE_JUGGLER_SSH_ADD_PATH").unwrap_or_else(|_| "ssh-add".to_string())
+}
+
+pub fn ssh_keygen_program() -> String {
+    std::env::var("REMOTE_JUGGLER_SSH_KEYGEN_PATH").unwrap_or_else(|_| "ssh-keygen".to_string())
+}
+
+/// gpg-agent's ssh socket, as gpgconf reports it
+pub fn gpg_agent_ssh_socket() -> PathBuf {
+    static SOCKET: OnceLock<PathBuf> = OnceLock::new();
+    SOCKET
+        .get_or_init(|| {
+            let args = cli::args(&["--list-dirs", "agent-ssh-socket"]);
+            cli::run_program("gpgconf", &args, &options())
+                .ok()
+                .map(|out| out.trim().to_string())
+                .filter(|out| !out.is_empty())
+                .map(PathBuf::from)
+                .unwrap_or_else(|| expand_tilde("~/.gnupg/S.gpg-agent.ssh"))
+        })
+        .clone()
+}
+
+/// The socket an agent listens on, or None for no agent
+pub fn socket(agent: &IdentityAgent) -> Option<PathBuf> {
+    match agent {
+        IdentityAgent::System => platform::agent_socket(),
+        IdentityAgent::OnePassword => Some(expand_tilde(platform::one_password_agent())),
+        IdentityAgent::GpgAgent => Some(gpg_agent_ssh_socket()),
+        IdentityAgent::None => None,
+        IdentityAgent::Socket(path) => Some(expand_tilde(path)),
+    }
+}
+
+/// The agent as an ssh config `IdentityAgent` value
+pub fn identity_agent_value(agent: &IdentityAgent) -> String {
+    let path = match agent {
+        IdentityAgent::System => return "SSH_AUTH_SOCK".to_string(),
+        IdentityAgent::None => return "none".to_string(),
+        IdentityAgent::OnePassword => platform::one_password_agent().to_string(),
+        IdentityAgent::GpgAgent => gpg_agent_ssh_socket().to_string_lossy().into_owned(),
+        IdentityAgent::Socket(path) => path.clone(),
+    };
+    if path.contains(char::is_whitespace) {
+        format!("\"{}\"", path)
+    } else {
+        path
+    }
+}
+
+/// Restrictions on a key added to the agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Constraints {
+    /// Ask (through ssh-askpass) before each use
+    pub confirm: bool,
+    /// Drop the key from the agent after this long
+    pub lifetime: Option<Duration>,
+}
+
+impl Constraints {
+    pub fn for_mode(mode: &SecurityMode) -> Self {
+        match mode {
+            SecurityMode::MaximumSecurity => Self {
+                confirm: true,
+                lifetime: Some(Duration::from_secs(3600)),
+            },
+            SecurityMode::DeveloperWorkflow => Self {
+                confirm: false,
+                lifetime: Some(Duration::from_secs(8 * 3600)),
+            },
+            SecurityMode::TrustedWorkstation => Self::default(),
+        }
+    }
+
+    /// The identity's security mode, with its own confirm setting on top
+    pub fn for_identity(identity: &Identity) -> Self {
+        let mut constraints = Self::for_mode(&identity.gpg.security_mode);
+        if let Some(confirm) = identity.ssh_policy.confirm {
+            constraints.confirm = confirm;
+        }
+        constraints
+    }
+
+    /// `ssh-add` flags for the constraints
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.confirm {
+            args.push("-c".to_string());
+        }
+        if let Some(lifetime) = self.lifetime {
+            args.push("-t".to_string());
+            args.push(lifetime.as_secs().to_string());
+        }
+        args
+    }
+
+    /// e.g. "confirm each use, expires after 1h"
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.confirm {
+            parts.push("confirm each use".to_string());
+        }
+        if let Some(lifetime) = self.lifetime {
+            parts.push(format!("expires after {}h", lifetime.as_secs() / 3600));
+        }
+        if parts.is_empty() {
+            "no constraints".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// A key held by the agent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedKey {
+    pub bits: u32,
+    /// e.g. `SHA256:...`
+    pub fingerprint: String,
+    pub comment: String,
+    /// e.g. `ED25519-SK`
+    pub key_type: String,
+    /// Public key line from `ssh-add -L`, used to remove the key
+    pub public_key: String,
+}
+
+/// Parse `ssh-add -l` output, pairing each key with its `ssh-add -L` line
+pub fn parse_list(fingerprints: &str, public_keys: &str) -> Vec<LoadedKey> {
+    let public_keys: Vec<&str> = public_keys
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .collect();
+    fingerprints
+        .lines()
+        .filter_map(|line| {
+            let (bits, rest) = line.trim().split_once(' ')?;
+            let (fingerprint, rest) = rest.split_once(' ')?;
+            let (comment, key_type) = match rest.rsplit_once(" (") {
+                Some((comment, key_type)) => (comment, key_type.trim_end_matches(')')),
+                None => (rest, ""),
+            };
+            Some((bits.parse().ok()?, fingerprint, comment, key_type))
+        })
+        .enumerate()
+        .map(|(i, (bits, fingerprint, comment, key_type))| LoadedKey {
+            bits,
+            fingerprint: fingerprint.to_string(),
+            comment: comment.to_string(),
+            key_type: key_type.to_string(),
+            public_key: public_keys.get(i).unwrap_or(&"").to_string(),
+        })
+        .collect()
+}
+
+fn options() -> RunOptions {
+    RunOptions {
+        timeout: Duration::from_secs(10),
+        retries: 0,
+        cancel: None,
+    }
+}
+
+/// Adding a key may wait on a passphrase or confirmation dialog
+fn interactive_options() -> RunOptions {
+    RunOptions {
+        timeout: Duration::from_secs(120),
+        ..options()
+    }
+}
+
+/// The agent's keys, or None if no agent is running; blocks
+pub fn list() -> Result<Option<Vec<LoadedKey>>, Error> {
+    let fingerprints = match ssh_add(&cli::args(&["-l"]), None, &options()) {
+        Ok(out) => out,
+        Err(CliError::NonZeroExit {
+            code: Some(EXIT_NO_KEYS),
+            ..
+        }) => return Ok(Some(Vec::new())),
+        Err(CliError::NonZeroExit {
+            code: Some(EXIT_NO_AGENT),
+            ..
+        }) => return Ok(None),
+        Err(e) => return Err(Error::from_cli(e)),
+    };
+    let public_keys = ssh_add(&cli::args(&["-L"]), None, &options()).map_err(Error::from_cli)?;
+    Ok(Some(parse_list(&fingerprints, &public_keys)))
+}
+
+/// Fingerprint of a key file, as `ssh-add -l` shows it; blocks
+pub fn fingerprint(key: &Path) -> Result<String, Error> {
+    let args = vec!["-lf".to_string(), key.to_string_lossy().into_owned()];
+    let out =
+        cli::run_program(&ssh_keygen_program(), &args, &options()).map_err(Error::from_cli)?;
+    out.split_whitespace()
+        .nth(1)
+        .map(str::to_string)
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "ssh-keygen",
+            message: out.trim().to_string(),
+        })
+}
+
+/// Run ssh-add against `socket`, or the session's agent if None
+fn ssh_add(
+    args: &[String],
+    socket: Option<&Path>,
+    options: &RunOptions,
+) -> Result<String, CliError> {
+    let socket = socket
+        .map(Path::to_path_buf)
+        .or_else(platform::agent_socket)
+        .map(|s| s.to_string_lossy().into_owned());
+    let env: Vec<(&str, &str)> = socket
+        .iter()
+        .map(|s| ("SSH_AUTH_SOCK", s.as_str()))
+        .collect();
+    async_io::block_on(cli::run_program_streaming(
+        &ssh_add_program(),
+        args,
+        &env,
+        options,
+        |_| {},
+    ))
+}
+
+/// Add a private key with constraints to the agent at `socket` (the
+/// session's if None); blocks
+pub fn add(key: &Path, constraints: Constraints, socket: Option<&Path>) -> Result<(), Error> {
+    let mut args = constraints.args();
+    args.push(key.to_string_lossy().into_owned());
+    ssh_add(&args, socket, &interactive_options())
+        .map(|_| ())
+        .map_err(Error::from_cli)
+}
+
+/// Add the keys on a smart card through its PKCS#11 library, replacing
+/// any loaded earlier; blocks
+pub fn add_provider(
+    library: &str,
+    constraints: Constraints,
+    socket: Option<&Path>,
+) -> Result<(), Error> {
+    // The agent refuses a provider it already has
+    let _ = ssh_add(&cli::args(&["-e", library]), socket, &options());
+    let mut args = constraints.args();
+    args.push("-s".to_string());
+    args.push(library.to_string());
+    ssh_add(&args, socket, &interactive_options())
+        .map(|_| ())
+        .map_err(Error::from_cli)
+}
+
+/// Remove a loaded key by its public key; blocks
+pub fn remove(key: &LoadedKey) -> Result<(), Error> {
+    // ssh-add -d takes a public key file; it needn't be next to the private
+    // key
+    let dir = platform::runtime_dir();
+    let path = dir.join(format!("remote-juggler-agent-{}.pub", std::process::id()));
+    std::fs::write(&path, format!("{}\n", key.public_key))?;
+    let args = vec!["-d".to_string(), path.to_string_lossy().into_owned()];
+    let result = ssh_add(&args, None, &options());
+    let _ = std::fs::remove_file(&path);
+    result.map(|_| ()).map_err(Error::from_cli)
+}
+
+/// Remove every key from the agent; blocks
+pub fn remove_all() -> Result<(), Error> {
+    ssh_add(&cli::args(&["-D"]), None, &options())
+        .map(|_| ())
+        .map_err(Error::from_cli)
+}
+
+/// Private key file of each identity, by identity name
+pub fn identity_keys(config: &Config) -> HashMap<String, PathBuf> {
+    config
+        .identities
+        .iter()
+        .filter(|(_, identity)| !identity.ssh_key_path.is_empty())
+        .map(|(name, identity)| (name.clone(), expand_tilde(&identity.ssh_key_path)))
+        .collect()
+}
+
+/// Which identity each loaded key belongs to, by fingerprint; blocks
+pub fn owners(config: &Config) -> HashMap<String, String> {
+    identity_keys(config)
+        .into_iter()
+        .filter_map(|(name, path)| Some((fingerprint(&path).ok()?, name)))
+        .collect()
+}
+
+/// What an identity switch did to the agent
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SwitchReport {
+    /// The key added, with its constraints
+    pub added: Option<(PathBuf, Constraints)>,
+    /// Identities whose keys were removed
+    pub removed: Vec<String>,
+}
+
+impl SwitchReport {
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some((path, constraints)) = &self.added {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            parts.push(format!("added {} ({})", name, constraints.describe()));
+        }
+        if !self.removed.is_empty() {
+            parts.push(format!("removed {}", self.removed.join(", ")));
+        }
+        if parts.is_empty() {
+            "agent unchanged".to_string()
+        } else {
+            parts.join("; ")
+        }
+    }
+}
+
+/// Load `identity`'s key into the agent as configured; blocks
+///
+/// Does nothing when agent loading is turned off, no agent is running, the
+/// identity has no key file or PKCS#11 library, or its agent is 1Password
+/// or none.
+pub fn on_switch(config: &Config, identity: &str) -> Result<SwitchReport, Error> {
+    let settings = &config.settings.ssh_agent;
+    let mut report = SwitchReport::default();
+    let Some(current) = config.identities.get(identity) else {
+        return Ok(report);
+    };
+    let library = current.pkcs11_library();
+    if !settings.add_on_switch || (current.ssh_key_path.is_empty() && library.is_none()) {
+        return Ok(report);
+    }
+    // An identity with its own agent gets the key there; other identities'
+    // keys are only cleared from the session's agent
+    let target = match &current.ssh_policy.agent {
+        Some(agent) if !agent.accepts_keys() => return Ok(report),
+        Some(agent @ (IdentityAgent::GpgAgent | IdentityAgent::Socket(_))) => socket(agent),
+        _ => None,
+    };
+    let loaded = match target {
+        Some(_) => Vec::new(),
+        None => match list()? {
+            Some(loaded) => loaded,
+            None => return Ok(report),
+        },
+    };
+
+    if settings.remove_others {
+        let owners = owners(config);
+        for key in &loaded {
+            match owners.get(&key.fingerprint) {
+                Some(owner) if owner != identity => {
+                    remove(key)?;
+                    report.removed.push(owner.clone());
+                }
+                _ => {}
+            }
+        }
+        report.removed.sort();
+    }
+
+    let constraints = Constraints::for_identity(current);
+    if let Some(library) = library {
+        add_provider(library, constraints, target.as_deref())?;
+        report.added = Some((PathBuf::from(library), constraints));
+        return Ok(report);
+    }
+    let path = expand_tilde(&current.ssh_key_path);
+    // Re-adding replaces the key's constraints, e.g. after a mode change
+    add(&path, constraints, target.as_deref())?;
+    report.added = Some((path, constraints));
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::identity;
+    use crate::config::{GpgConfig, SshPolicy};
+
+    const LIST: &str = "256 SHA256:abcDEF alice@laptop (ED25519)\n\
+        256 SHA256:skKEY work key (ED25519-SK)\n\
+        garbage\n";
+    const PUBLIC: &str = "ssh-ed25519 AAAA alice@laptop\n\
+        sk-ssh-ed25519@openssh.com BBBB work key\n";
+
+    #[test]
+    fn test_parse_list() {
+        let keys = parse_list(LIST, PUBLIC);
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].fingerprint, "SHA256:abcDEF");
+        assert_eq!(keys[0].comment, "alice@laptop");
+        assert_eq!(keys[0].key_type, "ED25519");
+        assert_eq!(keys[1].comment, "work key");
+        assert_eq!(keys[1].key_type, "ED25519-SK");
+        assert_eq!(
+            keys[1].public_key,
+            "sk-ssh-ed25519@openssh.com BBBB work key"
+        );
+        assert!(parse_list("", "").is_empty());
+    }
+
+    #[test]
+    fn test_constraints_by_mode() {
+        let strict = Constraints::for_mode(&SecurityMode::MaximumSecurity);
+        assert_eq!(strict.args(), vec!["-c", "-t", "3600"]);
+        assert_eq!(strict.describe(), "confirm each use, expires after 1h");
+
+        let dev = Constraints::for_mode(&SecurityMode::DeveloperWorkflow);
+        assert_eq!(dev.args(), vec!["-t", "28800"]);
+
+        let trusted = Constraints::for_mode(&SecurityMode::TrustedWorkstation);
+        assert!(trusted.args().is_empty());
+        assert_eq!(trusted.describe(), "no constraints");
+    }
+
+    #[test]
+    fn test_constraints_policy_overrides_mode() {
+        let mut identity = Identity {
+            ssh_key_path: "~/.ssh/work".to_string(),
+            gpg: GpgConfig {
+                security_mode: SecurityMode::TrustedWorkstation,
+                ..Default::default()
+            },
+            ssh_policy: SshPolicy {
+                confirm: Some(true),
+                ..Default::default()
+            },
+            ..identity("gitlab", "work", "gitlab.com", "git", "a@example.com")
+        };
+        assert_eq!(Constraints::for_identity(&identity).args(), vec!["-c"]);
+
+        identity.ssh_policy.confirm = None;
+        assert!(Constraints::for_identity(&identity).args().is_empty());
+    }
+
+    #[test]
+    fn test_switch_report_summary() {
+        let report = SwitchReport {
+            added: Some((
+                PathBuf::from("/home/a/.ssh/gitlab-work-sk"),
+                Constraints::for_mode(&SecurityMode::MaximumSecurity),
+            )),
+            removed: vec!["github-personal".to_string()],
+        };
+        assert_eq!(
+            report.summary(),
+            "added gitlab-work-sk (confirm each use, expires after 1h); removed github-personal"
+        );
+        assert_eq!(SwitchReport::default().summary(), "agent unchanged");
+    }
+}
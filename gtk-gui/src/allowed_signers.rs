@@ -0,0 +1,342 @@
+//! ~/.ssh/allowed_signers for SSH commit signatures
+//!
+//! git checks SSH signatures against an allowed signers file
+//! (`gpg.ssh.allowedSignersFile`). RemoteJuggler keeps a delimited block in
+//! `~/.ssh/allowed_signers` listing each SSH-signing identity's email and
+//! public key, plus the signing keys collaborators publish on GitHub,
+//! GitLab, Gitea or sourcehut, so `git log --show-signature` verifies both. Lines outside the
+//! block are left alone.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::cli::{self, RunOptions};
+use crate::config::{Collaborator, Config, SigningFormat};
+use crate::databases::expand_tilde;
+use crate::error::Error;
+use crate::export;
+use crate::http;
+use crate::platform;
+use crate::provider_api;
+use crate::provider_hosts;
+use crate::pubkeys::{self, Provider};
+
+pub const BEGIN_MARKER: &str = "# BEGIN RemoteJuggler allowed signers";
+pub const END_MARKER: &str = "# END RemoteJuggler allowed signers";
+const NOTICE: &str = "# Generated by RemoteJuggler; edits inside this block are overwritten";
+
+/// The file the CLI also points `gpg.ssh.allowedSignersFile` at
+pub fn path() -> Option<PathBuf> {
+    platform::ssh_dir().map(|dir| dir.join("allowed_signers"))
+}
+
+/// One trusted key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signer {
+    pub email: String,
+    /// Key type and blob, e.g. `ssh-ed25519 AAAA...`
+    pub key: String,
+    /// Where it came from: an identity name or `provider:user`
+    pub source: String,
+}
+
+impl Signer {
+    /// The allowed_signers line, limited to git signatures
+    pub fn line(&self) -> String {
+        format!(
+            "{} namespaces=\"git\" {} {}",
+            self.email, self.key, self.source
+        )
+    }
+}
+
+/// Type and blob of a public key line, dropping its comment
+fn key_part(line: &str) -> Option<String> {
+    let (kind, blob) = pubkeys::key_id(line.trim())?;
+    Some(format!("{} {}", kind, blob))
+}
+
+/// Signers for the identities that sign with SSH keys, sorted by name;
+/// identities whose public key can't be read are listed in the second
+/// half
+pub fn identity_signers(config: &Config) -> (Vec<Signer>, Vec<(String, String)>) {
+    let mut names: Vec<&String> = config.identities.keys().collect();
+    names.sort();
+    let mut signers = Vec::new();
+    let mut failures = Vec::new();
+    for name in names {
+        let identity = &config.identities[name];
+        if identity.gpg.format != SigningFormat::Ssh || identity.email.is_empty() {
+            continue;
+        }
+        let Some(key_path) = identity.signing_key() else {
+            continue;
+        };
+        match std::fs::read_to_string(expand_tilde(&key_path)) {
+            Ok(text) => match key_part(&text) {
+                Some(key) => signers.push(Signer {
+                    email: identity.email.clone(),
+                    key,
+                    source: name.clone(),
+                }),
+                None => failures.push((name.clone(), format!("{} is not a public key", key_path))),
+            },
+            Err(e) => failures.push((name.clone(), format!("{}: {}", key_path, e))),
+        }
+    }
+    (signers, failures)
+}
+
+/// The provider's list of a user's signing keys: GitHub's SSH signing
+/// keys, or GitLab's keys (which are valid for signing unless limited to
+/// authentication)
+pub fn parse_collaborator_keys(provider: Provider, body: &Value) -> Vec<String> {
+    body.as_array()
+        .into_iter()
+        .flatten()
+        .filter(|item| {
+            provider != Provider::GitLab
+                || item.get("usage_type").and_then(Value::as_str) != Some("auth")
+        })
+        .filter_map(|item| key_part(item.get("key")?.as_str()?))
+        .collect()
+}
+
+/// A collaborator's published signing keys; blocks
+pub fn fetch_collaborator(collaborator: &Collaborator) -> Result<Vec<Signer>, Error> {
+    let provider = match Provider::from_name(&collaborator.provider) {
+        Some(provider) if provider != Provider::Bitbucket => provider,
+        _ => {
+            return Err(Error::InvalidOutput {
+                program: "allowed_signers",
+                message: format!(
+                    "{} doesn't publish signing keys",
+                    collaborator.provider.to_lowercase()
+                ),
+            })
+        }
+    };
+    let base = provider_hosts::api_base(
+        &provider_hosts::configured(),
+        provider,
+        &collaborator.hostname,
+    );
+    let keys = match provider {
+        // sourcehut lists a user's keys as an authorized_keys file
+        Provider::Sourcehut => {
+            let url = format!(
+                "{}/~{}.keys",
+                base,
+                collaborator.user.trim_start_matches('~')
+            );
+            http::agent()
+                .get(&url)
+                .call()
+                .map_err(http::map_error)?
+                .into_string()?
+                .lines()
+                .filter_map(key_part)
+                .collect()
+        }
+        _ => {
+            let url = match provider {
+                Provider::GitHub => {
+                    format!("{}/users/{}/ssh_signing_keys", base, collaborator.user)
+                }
+                _ => format!("{}/users/{}/keys", base, collaborator.user),
+            };
+            let body = provider_api::Client::new(provider, &base, None).get_json(&url)?;
+            parse_collaborator_keys(provider, &body)
+        }
+    };
+    let source = format!(
+        "{}:{}",
+        collaborator.provider.to_lowercase(),
+        collaborator.user
+    );
+    Ok(keys
+        .into_iter()
+        .map(|key| Signer {
+            email: collaborator.email.clone(),
+            key,
+            source: source.clone(),
+        })
+        .collect())
+}
+
+/// The managed block, markers included
+pub fn render_block(signers: &[Signer]) -> String {
+    let mut block = format!("{}\n{}\n", BEGIN_MARKER, NOTICE);
+    for signer in signers {
+        block.push_str(&signer.line());
+        block.push('\n');
+    }
+    block.push_str(END_MARKER);
+    block.push('\n');
+    block
+}
+
+/// `text` with its managed block replaced by `block`, or `block` appended;
+/// an unterminated block is replaced through the end of the file
+pub fn apply(text: &str, block: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out: Vec<&str> = match lines.iter().position(|l| l.trim() == BEGIN_MARKER) {
+        Some(begin) => {
+            let end = lines[begin..]
+                .iter()
+                .position(|l| l.trim() == END_MARKER)
+                .map_or(lines.len(), |i| begin + i + 1);
+            lines[..begin]
+                .iter()
+                .copied()
+                .chain(block.lines())
+                .chain(lines[end..].iter().copied())
+                .collect()
+        }
+        None => {
+            let mut out = lines.clone();
+            if out.last().is_some_and(|l| !l.trim().is_empty()) {
+                out.push("");
+            }
+            out.extend(block.lines());
+            out
+        }
+    };
+    out.push("");
+    out.join("\n")
+}
+
+/// What an update wrote
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub path: PathBuf,
+    pub identities: usize,
+    pub collaborators: usize,
+    /// Identities or collaborators left out, with why
+    pub failures: Vec<(String, String)>,
+}
+
+impl Report {
+    pub fn display_text(&self) -> String {
+        let mut text = format!(
+            "{} identity and {} collaborator keys in {}",
+            self.identities,
+            self.collaborators,
+            platform::tilde_path(&self.path)
+        );
+        if !self.failures.is_empty() {
+            let skipped: Vec<&str> = self.failures.iter().map(|(n, _)| n.as_str()).collect();
+            text.push_str(&format!("; skipped {}", skipped.join(", ")));
+        }
+        text
+    }
+}
+
+/// Rewrite the managed block in `path`; blocks
+pub fn update_file(config: &Config, path: &Path) -> Result<Report, Error> {
+    let (mut signers, mut failures) = identity_signers(config);
+    let identities = signers.len();
+    for collaborator in &config.settings.allowed_signers.collaborators {
+        match fetch_collaborator(collaborator) {
+            Ok(keys) => signers.extend(keys),
+            Err(e) => failures.push((collaborator.user.clone(), e.to_string())),
+        }
+    }
+    let collaborators = signers.len() - identities;
+
+    let current = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let proposed = apply(&current, &render_block(&signers));
+    if proposed != current {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        export::write_private(&tmp, &proposed)?;
+        std::fs::rename(&tmp, path)?;
+    }
+    Ok(Report {
+        path: path.to_path_buf(),
+        identities,
+        collaborators,
+        failures,
+    })
+}
+
+/// [`update_file`] for ~/.ssh/allowed_signers, then point the global git
+/// config at it if `settings.allowedSigners.global` is set; blocks
+pub fn update(config: &Config) -> Result<Report, Error> {
+    let path = path().ok_or_else(|| std::io::Error::other("No home directory"))?;
+    let report = update_file(config, &path)?;
+    if config.settings.allowed_signers.global {
+        use_file(&path, None)?;
+    }
+    Ok(report)
+}
+
+/// Set `gpg.ssh.allowedSignersFile` in a repository's config, or the
+/// global config if `repo` is None; blocks
+pub fn use_file(path: &Path, repo: Option<&Path>) -> Result<(), Error> {
+    let value = platform::tilde_path(path);
+    let mut args = match repo {
+        Some(repo) => vec![
+            "-C".to_string(),
+            repo.to_string_lossy().into_owned(),
+            "config".to_string(),
+        ],
+        None => cli::args(&["config", "--global"]),
+    };
+    args.extend(cli::args(&["gpg.ssh.allowedSignersFile", &value]));
+    let options = RunOptions {
+        timeout: Duration::from_secs(10),
+        retries: 0,
+        cancel: None,
+    };
+    cli::run_program("git", &args, &options).map_err(Error::from_cli)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_keeps_hand_written_lines() {
+        let signers = vec![Signer {
+            email: "me@acme.dev".to_string(),
+            key: "ssh-ed25519 AAAAC3Nza".to_string(),
+            source: "github-work".to_string(),
+        }];
+        let block = render_block(&signers);
+        assert!(block.contains("me@acme.dev namespaces=\"git\" ssh-ed25519 AAAAC3Nza github-work"));
+
+        let hand = "friend@example.com ssh-ed25519 AAAAfriend\n";
+        let written = apply(hand, &block);
+        assert!(written.starts_with(hand));
+        assert!(written.ends_with(&format!("{}\n", END_MARKER)));
+        // Rewriting replaces the block in place
+        assert_eq!(apply(&written, &block), written);
+        let emptied = apply(&written, &render_block(&[]));
+        assert!(emptied.starts_with(hand));
+        assert!(!emptied.contains("AAAAC3Nza"));
+    }
+
+    #[test]
+    fn test_parse_collaborator_keys() {
+        let body = serde_json::json!([
+            { "id": 1, "key": "ssh-ed25519 AAAAsign ana@laptop", "usage_type": "auth_and_signing" },
+            { "id": 2, "key": "ssh-rsa AAAAauth", "usage_type": "auth" },
+            { "id": 3, "title": "no key" }
+        ]);
+        assert_eq!(
+            parse_collaborator_keys(Provider::GitLab, &body),
+            vec!["ssh-ed25519 AAAAsign".to_string()]
+        );
+        assert_eq!(parse_collaborator_keys(Provider::GitHub, &body).len(), 2);
+    }
+}
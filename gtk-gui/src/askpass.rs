@@ -0,0 +1,361 @@
+//! SSH_ASKPASS and GIT_ASKPASS prompts, for `remote-juggler-askpass`
+//!
+//! ssh, ssh-add and git started without a terminal (from an editor, a
+//! launcher or the GUI) ask for passphrases, PINs and HTTPS credentials
+//! through the program in `SSH_ASKPASS` or `GIT_ASKPASS`: the prompt is its
+//! only argument and the answer goes to stdout. This module works out what
+//! a prompt asks for. For identities in Trusted Workstation mode a key's
+//! passphrase and an HTTPS username and token are answered from the key
+//! store without asking; everything else, and every other mode, is asked
+//! with GTK by the binary. With the setting on, a switch points the CLI's
+//! commands and the desktop session (the systemd user manager and D-Bus
+//! activation on Linux, launchd on macOS) at the helper, so programs
+//! started afterwards use it.
+
+use std::path::{Path, PathBuf};
+
+use crate::cli;
+use crate::config::{Config, SecurityMode};
+use crate::error::{CliError, Error};
+use crate::passphrase;
+use crate::platform;
+use crate::pubkeys::{self, Provider};
+
+/// Name of the helper binary
+pub const PROGRAM: &str = "remote-juggler-askpass";
+
+/// The variables pointing ssh and git at the helper
+pub const ENV_VARS: [&str; 2] = ["SSH_ASKPASS", "GIT_ASKPASS"];
+
+/// What a prompt asks for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Prompt {
+    /// "Enter passphrase for key '/home/me/.ssh/id_ed25519': "
+    Passphrase { key: PathBuf },
+    /// git's "Username for 'https://github.com': "
+    Username { url: String },
+    /// git's "Password for 'https://me@github.com': "
+    Password { url: String },
+    /// Answered by typing yes or no, e.g. an unknown host key
+    Question,
+    /// Allow or refuse, by exit status (`SSH_ASKPASS_PROMPT=confirm`)
+    Confirm,
+    /// Only shown, e.g. "Confirm user presence" for a security key
+    /// (`SSH_ASKPASS_PROMPT=none`)
+    Notice,
+    /// Anything else, such as a security key PIN
+    Secret,
+}
+
+impl Prompt {
+    /// Classify `text`, with ssh's `SSH_ASKPASS_PROMPT` hint if it set one
+    pub fn parse(text: &str, hint: Option<&str>) -> Self {
+        match hint {
+            Some("confirm") => return Self::Confirm,
+            Some("none") => return Self::Notice,
+            _ => {}
+        }
+        let text = text.trim();
+        if let Some(rest) = text.strip_prefix("Enter passphrase for ") {
+            let rest = rest.strip_prefix("key ").unwrap_or(rest);
+            let key = match rest.strip_prefix('\'') {
+                Some(quoted) => quoted.split('\'').next(),
+                // ssh-add: "Enter passphrase for /path (will confirm each use): "
+                None => rest.split([':', '(']).next().map(str::trim),
+            };
+            if let Some(key) = key.filter(|key| !key.is_empty()) {
+                return Self::Passphrase {
+                    key: PathBuf::from(key),
+                };
+            }
+        }
+        if let Some(url) = quoted_url(text, "Username for ") {
+            return Self::Username { url };
+        }
+        if let Some(url) = quoted_url(text, "Password for ") {
+            return Self::Password { url };
+        }
+        if text.contains("(yes/no") {
+            return Self::Question;
+        }
+        Self::Secret
+    }
+
+    /// Whether the answer is hidden while typed
+    pub fn is_secret(&self) -> bool {
+        matches!(
+            self,
+            Self::Passphrase { .. } | Self::Password { .. } | Self::Secret
+        )
+    }
+}
+
+fn quoted_url(text: &str, prefix: &str) -> Option<String> {
+    let rest = text.strip_prefix(prefix)?.strip_prefix('\'')?;
+    let (url, _) = rest.split_once('\'')?;
+    Some(url.to_string())
+}
+
+/// The user and host in a URL such as `https://me@github.com/org/repo`
+pub fn url_parts(url: &str) -> (Option<&str>, &str) {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split('/').next().unwrap_or(rest);
+    let (user, host) = match authority.rsplit_once('@') {
+        Some((user, host)) => (Some(user), host),
+        None => (None, authority),
+    };
+    let host = host.split(':').next().unwrap_or(host);
+    (user.filter(|user| !user.is_empty()), host)
+}
+
+/// The identity an HTTPS prompt is for: by host, then by the user in the
+/// URL when there is one; the current identity wins when several match
+pub fn identity_for_url(config: &Config, url: &str) -> Option<String> {
+    let (user, host) = url_parts(url);
+    let mut names: Vec<&String> = config
+        .identities
+        .iter()
+        .filter(|(_, identity)| identity.hostname.eq_ignore_ascii_case(host))
+        .filter(|(_, identity)| user.is_none_or(|user| identity.user.eq_ignore_ascii_case(user)))
+        .map(|(name, _)| name)
+        .collect();
+    names.sort_by_key(|name| (**name != config.state.current_identity, *name));
+    names.first().map(|name| name.to_string())
+}
+
+/// The identity whose SSH key is `key`
+pub fn identity_for_key(config: &Config, key: &Path) -> Option<String> {
+    config
+        .identities
+        .iter()
+        .find(|(_, identity)| {
+            !identity.ssh_key_path.is_empty()
+                && platform::expand_tilde(&identity.ssh_key_path) == key
+        })
+        .map(|(name, _)| name.clone())
+}
+
+fn is_trusted(config: &Config, name: &str) -> bool {
+    config
+        .identities
+        .get(name)
+        .is_some_and(|identity| identity.gpg.security_mode == SecurityMode::TrustedWorkstation)
+}
+
+/// The answer from the key store, for an identity in Trusted Workstation
+/// mode; None means ask. Blocks
+pub fn stored_answer(config: &Config, prompt: &Prompt) -> Option<String> {
+    match prompt {
+        Prompt::Passphrase { key } => {
+            let name = identity_for_key(config, key).filter(|name| is_trusted(config, name))?;
+            let path = passphrase::entry_path(key);
+            match cli::run(&cli::args(&["keys", "get", &path])) {
+                Ok(value) => Some(value.trim_end_matches(['\r', '\n']).to_string())
+                    .filter(|value| !value.is_empty()),
+                Err(e) => {
+                    tracing::info!("No stored passphrase for {}'s key: {}", name, e);
+                    None
+                }
+            }
+        }
+        Prompt::Username { url } => {
+            let name = identity_for_url(config, url).filter(|name| is_trusted(config, name))?;
+            Some(config.identities[&name].user.clone()).filter(|user| !user.is_empty())
+        }
+        Prompt::Password { url } => {
+            let name = identity_for_url(config, url).filter(|name| is_trusted(config, name))?;
+            let identity = &config.identities[&name];
+            let provider = Provider::for_identity(identity)?;
+            pubkeys::find_token(&name, identity, provider)
+                .map_err(|e| tracing::info!("No stored token for {}: {}", name, e))
+                .ok()
+        }
+        _ => None,
+    }
+}
+
+/// The helper: next to the running executable, else on PATH
+pub fn program_path() -> Option<PathBuf> {
+    let name = format!("{}{}", PROGRAM, std::env::consts::EXE_SUFFIX);
+    std::env::current_exe()
+        .ok()
+        .map(|exe| exe.with_file_name(&name))
+        .filter(|path| path.is_file())
+        .or_else(|| cli::find_program(&name))
+}
+
+/// Point the CLI's commands at the helper, or with `None` stop doing so
+pub fn apply_to_cli(program: Option<&Path>) {
+    let value = program.map(|p| p.to_string_lossy().into_owned());
+    for var in ENV_VARS {
+        cli::set_session_env(var, value.as_deref());
+    }
+}
+
+/// Export the variables to the desktop session, for programs started
+/// after this; does nothing on Windows. Blocks
+pub fn export_to_session(program: &Path) -> Result<(), Error> {
+    let program = program.to_string_lossy();
+    let options = cli::RunOptions {
+        timeout: std::time::Duration::from_secs(10),
+        retries: 0,
+        cancel: None,
+    };
+    if cfg!(target_os = "macos") {
+        for var in ENV_VARS {
+            let args = cli::args(&["setenv", var, &program]);
+            cli::run_program("launchctl", &args, &options).map_err(Error::from_cli)?;
+        }
+    } else if cfg!(target_os = "linux") {
+        let assignments: Vec<String> = ENV_VARS
+            .iter()
+            .map(|var| format!("{}={}", var, program))
+            .collect();
+        let mut args = vec!["--systemd".to_string()];
+        args.extend(assignments.iter().cloned());
+        if cli::run_program("dbus-update-activation-environment", &args, &options).is_err() {
+            let mut args = cli::args(&["--user", "set-environment"]);
+            args.extend(assignments);
+            cli::run_program("systemctl", &args, &options).map_err(Error::from_cli)?;
+        }
+    }
+    Ok(())
+}
+
+/// With the setting on, point the CLI and the session at the helper after
+/// a switch. Returns whether it did. Blocks
+pub fn on_switch(config: &Config) -> Result<bool, Error> {
+    if !config.settings.askpass {
+        return Ok(false);
+    }
+    let Some(program) = program_path() else {
+        return Err(Error::Cli(CliError::Spawn(format!(
+            "{} not found",
+            PROGRAM
+        ))));
+    };
+    apply_to_cli(Some(&program));
+    export_to_session(&program)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::config::{GpgConfig, Identity};
+
+    #[test]
+    fn test_parse_prompts() {
+        assert_eq!(
+            Prompt::parse("Enter passphrase for key '/home/me/.ssh/id_work': ", None),
+            Prompt::Passphrase {
+                key: PathBuf::from("/home/me/.ssh/id_work")
+            }
+        );
+        assert_eq!(
+            Prompt::parse(
+                "Enter passphrase for /home/me/.ssh/id_work (will confirm each use): ",
+                None
+            ),
+            Prompt::Passphrase {
+                key: PathBuf::from("/home/me/.ssh/id_work")
+            }
+        );
+        assert_eq!(
+            Prompt::parse("Username for 'https://gitlab.com': ", None),
+            Prompt::Username {
+                url: "https://gitlab.com".to_string()
+            }
+        );
+        let password = Prompt::parse("Password for 'https://me@github.com': ", None);
+        assert_eq!(
+            password,
+            Prompt::Password {
+                url: "https://me@github.com".to_string()
+            }
+        );
+        assert!(password.is_secret());
+        assert_eq!(
+            Prompt::parse(
+                "Are you sure you want to continue connecting (yes/no/[fingerprint])? ",
+                None
+            ),
+            Prompt::Question
+        );
+        assert_eq!(
+            Prompt::parse("Allow use of key /home/me/.ssh/id_work?", Some("confirm")),
+            Prompt::Confirm
+        );
+        assert_eq!(
+            Prompt::parse("Confirm user presence for key ED25519-SK", Some("none")),
+            Prompt::Notice
+        );
+        assert_eq!(
+            Prompt::parse("Enter PIN for ED25519-SK key /home/me/.ssh/id_sk: ", None),
+            Prompt::Secret
+        );
+        assert!(!Prompt::Question.is_secret());
+    }
+
+    #[test]
+    fn test_identity_for_url() {
+        assert_eq!(
+            url_parts("https://me@github.com:443/org/repo.git"),
+            (Some("me"), "github.com")
+        );
+        assert_eq!(url_parts("https://gitlab.com"), (None, "gitlab.com"));
+
+        let config = ConfigBuilder::new()
+            .identity(
+                "github-personal",
+                Identity {
+                    ssh_key_path: "/keys/personal".to_string(),
+                    ..identity(
+                        "github",
+                        "github-personal",
+                        "github.com",
+                        "Me",
+                        "me@example.com",
+                    )
+                },
+            )
+            .identity(
+                "github-work",
+                Identity {
+                    ssh_key_path: "/keys/work".to_string(),
+                    gpg: GpgConfig {
+                        security_mode: SecurityMode::TrustedWorkstation,
+                        ..Default::default()
+                    },
+                    ..identity("github", "github-work", "github.com", "Work", "w@acme.dev")
+                },
+            )
+            .current("github-work")
+            .build();
+
+        assert_eq!(
+            identity_for_url(&config, "https://github.com").as_deref(),
+            Some("github-work")
+        );
+        assert_eq!(
+            identity_for_url(&config, "https://me@github.com").as_deref(),
+            Some("github-personal")
+        );
+        assert_eq!(identity_for_url(&config, "https://gitlab.com"), None);
+        assert_eq!(
+            identity_for_key(&config, Path::new("/keys/personal")).as_deref(),
+            Some("github-personal")
+        );
+
+        // Only Trusted Workstation identities are answered without asking
+        let url = "https://work@github.com".to_string();
+        assert_eq!(
+            stored_answer(&config, &Prompt::Username { url }).as_deref(),
+            Some("Work")
+        );
+        let url = "https://me@github.com".to_string();
+        assert_eq!(stored_answer(&config, &Prompt::Username { url }), None);
+        assert_eq!(stored_answer(&config, &Prompt::Secret), None);
+    }
+}
@@ -0,0 +1,239 @@
+//! File attachments on key store entries
+//!
+//! KDBX entries can carry binary attachments, which is where files that are
+//! credentials in their own right belong: kubeconfigs, service-account JSON,
+//! `.p12` bundles. The CLI wraps `keepassxc-cli attachment-*`; this module
+//! builds those commands and manages the local side.
+//!
+//! Opening an attachment exports it into a private download directory under
+//! the user's runtime directory (tmpfs on most Linux systems), from which it
+//! is securely deleted again when no longer needed.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::cli;
+use crate::error::Error;
+use crate::platform;
+
+/// An attachment as listed by `keys attachments --json`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Attachment {
+    pub name: String,
+    /// Human-readable size as reported by KeePassXC, e.g. "4.2 KiB"
+    #[serde(default)]
+    pub size: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AttachmentList {
+    attachments: Vec<Attachment>,
+}
+
+/// Parse the output of `keys attachments <path> --json`
+pub fn parse_list(output: &str) -> Result<Vec<Attachment>, Error> {
+    cli::reported_error(output).map_err(Error::from_cli)?;
+    let json = output
+        .lines()
+        .find(|l| l.trim_start().starts_with('{'))
+        .unwrap_or_default();
+    serde_json::from_str::<AttachmentList>(json)
+        .map(|l| l.attachments)
+        .map_err(|e| Error::InvalidOutput {
+            program: cli::CLI_PROGRAM,
+            message: e.to_string(),
+        })
+}
+
+pub fn list_args(entry: &str) -> Vec<String> {
+    cli::args(&["keys", "attachments", entry, "--json"])
+}
+
+pub fn attach_args(entry: &str, file: &Path, name: &str) -> Vec<String> {
+    cli::args(&[
+        "keys",
+        "attach",
+        entry,
+        &file.to_string_lossy(),
+        "--name",
+        name,
+    ])
+}
+
+pub fn export_args(entry: &str, name: &str, dest: &Path) -> Vec<String> {
+    cli::args(&[
+        "keys",
+        "attachment-export",
+        entry,
+        name,
+        &dest.to_string_lossy(),
+    ])
+}
+
+pub fn detach_args(entry: &str, name: &str) -> Vec<String> {
+    cli::args(&["keys", "detach", entry, name])
+}
+
+/// Make an entry path or attachment name safe to use as one path component
+pub fn sanitize(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    match cleaned.trim() {
+        "" | "." | ".." => "attachment".to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Private directory attachments are downloaded into
+///
+/// Prefers `$XDG_RUNTIME_DIR`, which is memory-backed and removed at logout.
+pub fn download_dir() -> PathBuf {
+    platform::runtime_dir()
+        .join(platform::APP_DIR)
+        .join("attachments")
+}
+
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        // The directory may predate us with wider permissions
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+    }
+    #[cfg(not(unix))]
+    {
+        fs::create_dir_all(dir)
+    }
+}
+
+/// Where an attachment of an entry is downloaded to, creating its directory
+///
+/// Each entry gets its own subdirectory so the file keeps its own name
+/// (and extension, for opening it in the right application).
+pub fn download_path(entry: &str, name: &str) -> io::Result<PathBuf> {
+    let root = download_dir();
+    create_private_dir(&root)?;
+    let dir = root.join(sanitize(entry));
+    create_private_dir(&dir)?;
+    Ok(dir.join(sanitize(name)))
+}
+
+/// Overwrite a file with zeros, flush it to disk, and remove it
+///
+/// This defeats casual recovery from the page cache or a tmpfs; on
+/// copy-on-write filesystems and SSDs the old blocks may survive, which is
+/// why downloads go to the runtime directory in the first place.
+pub fn secure_delete(path: &Path) -> io::Result<()> {
+    let len = fs::metadata(path)?.len();
+    {
+        let mut file: File = OpenOptions::new().write(true).open(path)?;
+        let zeros = [0u8; 8192];
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk = remaining.min(zeros.len() as u64) as usize;
+            file.write_all(&zeros[..chunk])?;
+            remaining -= chunk as u64;
+        }
+        file.sync_all()?;
+    }
+    fs::remove_file(path)
+}
+
+/// Securely delete everything under a directory; returns the files removed
+fn wipe_dir(dir: &Path) -> io::Result<usize> {
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            removed += wipe_dir(&path)?;
+            fs::remove_dir(&path)?;
+        } else {
+            secure_delete(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Securely delete every downloaded attachment
+pub fn wipe_downloads() -> io::Result<usize> {
+    let dir = download_dir();
+    if !dir.exists() {
+        return Ok(0);
+    }
+    wipe_dir(&dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list() {
+        let output = r#"{"entryPath":"RemoteJuggler/K8s/prod","attachments":[{"name":"kubeconfig","size":"4.2 KiB"},{"name":"sa.json","size":""}]}"#;
+        let attachments = parse_list(output).unwrap();
+        assert_eq!(attachments.len(), 2);
+        assert_eq!(attachments[0].name, "kubeconfig");
+        assert_eq!(attachments[0].size, "4.2 KiB");
+
+        assert!(parse_list("[ERROR] Entry not found: RemoteJuggler/K8s/prod").is_err());
+        assert!(parse_list("garbage").is_err());
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("RemoteJuggler/K8s/prod"), "RemoteJuggler_K8s_prod");
+        assert_eq!(sanitize("cert.p12"), "cert.p12");
+        assert_eq!(sanitize(".."), "attachment");
+        assert_eq!(sanitize("a\nb"), "a_b");
+    }
+
+    #[test]
+    fn test_args() {
+        assert_eq!(
+            attach_args("A/B", Path::new("/tmp/kubeconfig"), "kubeconfig"),
+            cli::args(&[
+                "keys",
+                "attach",
+                "A/B",
+                "/tmp/kubeconfig",
+                "--name",
+                "kubeconfig"
+            ])
+        );
+        assert_eq!(
+            detach_args("A/B", "kubeconfig"),
+            cli::args(&["keys", "detach", "A/B", "kubeconfig"])
+        );
+    }
+
+    #[test]
+    fn test_secure_delete() {
+        let dir = std::env::temp_dir().join(format!("rj-attach-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("entry")).unwrap();
+        let file = dir.join("entry").join("sa.json");
+        fs::write(&file, b"{\"private_key\": \"...\"}").unwrap();
+
+        secure_delete(&file).unwrap();
+        assert!(!file.exists());
+
+        fs::write(&file, b"again").unwrap();
+        assert_eq!(wipe_dir(&dir).unwrap(), 1);
+        assert!(!dir.join("entry").exists());
+        fs::remove_dir(&dir).unwrap();
+    }
+}
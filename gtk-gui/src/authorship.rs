@@ -0,0 +1,313 @@
+//! Commits authored with the wrong identity
+//!
+//! Looks through a repository's recent commits for ones whose author email
+//! is another identity's, e.g. the personal address on a work repository,
+//! while the identity detected for the repository is expected. Commits by
+//! anyone else are left alone. HEAD can be amended in place; deeper history
+//! gets a command sequence to review and run by hand, either
+//! `git filter-repo` with a mailmap or a rebase that amends only the
+//! affected commits.
+
+use std::collections::{BTreeSet, HashSet};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cli::{self, RunOptions};
+use crate::config::Config;
+use crate::detect;
+use crate::error::{CliError, Error};
+use crate::ssh_cert::shell_quote;
+
+/// How many commits are checked by default
+pub const DEFAULT_LIMIT: usize = 200;
+
+/// One commit from `git log`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub hash: String,
+    pub author_name: String,
+    pub author_email: String,
+    pub subject: String,
+    /// Whether it has no parent
+    pub is_root: bool,
+    /// Whether a remote-tracking branch contains it
+    pub pushed: bool,
+}
+
+impl Commit {
+    pub fn short(&self) -> &str {
+        &self.hash[..self.hash.len().min(8)]
+    }
+}
+
+/// The `git log` format [`parse_log`] reads
+pub const LOG_FORMAT: &str = "%H%x1f%P%x1f%an%x1f%ae%x1f%s";
+
+/// Parse `git log --format=LOG_FORMAT`, newest first
+pub fn parse_log(text: &str) -> Vec<Commit> {
+    text.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\x1f').collect();
+            let [hash, parents, name, email, subject] = fields[..] else {
+                return None;
+            };
+            Some(Commit {
+                hash: hash.to_string(),
+                author_name: name.to_string(),
+                author_email: email.to_string(),
+                subject: subject.to_string(),
+                is_root: parents.trim().is_empty(),
+                pushed: false,
+            })
+        })
+        .collect()
+}
+
+/// The emails of the identities other than the expected one
+pub fn other_emails(config: &Config, expected: &str) -> HashSet<String> {
+    config
+        .identities
+        .values()
+        .map(|identity| identity.email.to_lowercase())
+        .filter(|email| !email.is_empty() && *email != expected.to_lowercase())
+        .collect()
+}
+
+/// The commits authored with one of `others`
+pub fn find_wrong(commits: Vec<Commit>, others: &HashSet<String>) -> Vec<Commit> {
+    commits
+        .into_iter()
+        .filter(|commit| others.contains(&commit.author_email.to_lowercase()))
+        .collect()
+}
+
+/// The result of checking a repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analysis {
+    /// The identity detected for the repository
+    pub identity: String,
+    pub name: String,
+    pub email: String,
+    /// How many commits were checked
+    pub checked: usize,
+    pub head: String,
+    /// Commits with another identity's email, newest first
+    pub wrong: Vec<Commit>,
+}
+
+impl Analysis {
+    pub fn head_is_wrong(&self) -> bool {
+        self.wrong.first().is_some_and(|c| c.hash == self.head)
+    }
+
+    pub fn pushed(&self) -> usize {
+        self.wrong.iter().filter(|c| c.pushed).count()
+    }
+
+    pub fn display_text(&self) -> String {
+        if self.wrong.is_empty() {
+            return format!(
+                "The last {} commits match {} <{}>",
+                self.checked, self.identity, self.email
+            );
+        }
+        let mut text = format!(
+            "{} of the last {} commits aren't {} <{}>",
+            self.wrong.len(),
+            self.checked,
+            self.identity,
+            self.email
+        );
+        match self.pushed() {
+            0 => {}
+            n => text.push_str(&format!("; {} pushed, rewriting needs a force push", n)),
+        }
+        text
+    }
+
+    /// One line per wrong commit
+    pub fn commit_lines(&self) -> String {
+        self.wrong
+            .iter()
+            .map(|c| {
+                format!(
+                    "{} {} <{}>{} {}",
+                    c.short(),
+                    c.author_name,
+                    c.author_email,
+                    if c.pushed { " (pushed)" } else { "" },
+                    c.subject
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The wrong emails as they appear in the commits
+    fn wrong_emails(&self) -> BTreeSet<&str> {
+        self.wrong.iter().map(|c| c.author_email.as_str()).collect()
+    }
+
+    /// A mailmap giving every wrong email the expected name and email
+    pub fn mailmap(&self) -> String {
+        self.wrong_emails()
+            .into_iter()
+            .map(|wrong| format!("{} <{}> <{}>\n", self.name, self.email, wrong))
+            .collect()
+    }
+
+    /// Commands that rewrite the wrong commits, to review and run in the
+    /// repository
+    pub fn rewrite_commands(&self) -> String {
+        let Some(oldest) = self.wrong.last() else {
+            return String::new();
+        };
+        let author = format!("{} <{}>", self.name, self.email);
+        let patterns: Vec<String> = self
+            .wrong_emails()
+            .into_iter()
+            .map(|email| format!("\"{}\"", email))
+            .collect();
+        let exec = format!(
+            "case \"$(git log -1 --format=%ae)\" in {}) git commit --amend --no-edit --no-verify --author=\"{}\";; esac",
+            patterns.join("|"),
+            author
+        );
+        let base = if oldest.is_root {
+            "--root".to_string()
+        } else {
+            format!("{}^", oldest.short())
+        };
+        let mut out = String::new();
+        if self.pushed() > 0 {
+            out.push_str(
+                "# Some of these commits are pushed: rewriting changes their hashes, so\n\
+                 # the branch needs a force push and collaborators need to rebase.\n\n",
+            );
+        }
+        out.push_str(&format!(
+            "# Every branch, all history, with git filter-repo (it removes the origin\n\
+             # remote afterwards; add it back before pushing):\n\
+             mailmap=\"$(git rev-parse --git-dir)/remote-juggler.mailmap\"\n\
+             cat > \"$mailmap\" <<'EOF'\n{}EOF\n\
+             git filter-repo --force --mailmap \"$mailmap\"\n\n\
+             # Or only the current branch, from {} on:\n\
+             git -c user.name={} -c user.email={} rebase -r {} --exec {}\n",
+            self.mailmap(),
+            oldest.short(),
+            shell_quote(&self.name),
+            shell_quote(&self.email),
+            base,
+            shell_quote(&exec)
+        ));
+        out
+    }
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<String, CliError> {
+    let mut all = vec!["-C".to_string(), repo.to_string_lossy().into_owned()];
+    all.extend(cli::args(args));
+    let options = RunOptions {
+        timeout: Duration::from_secs(30),
+        retries: 0,
+        cancel: None,
+    };
+    cli::run_program("git", &all, &options)
+}
+
+/// Check the last `limit` commits of HEAD against the identity detected
+/// for the repository; blocks
+pub fn analyze(config: &Config, repo: &Path, limit: usize) -> Result<Analysis, Error> {
+    let found = detect::detect(config, repo)?.ok_or_else(|| Error::InvalidOutput {
+        program: "detect",
+        message: "No identity matches the repository".to_string(),
+    })?;
+    let identity = &config.identities[&found.identity];
+    let format = format!("--format={}", LOG_FORMAT);
+    let count = format!("-n{}", limit);
+    let log = git(repo, &["log", &format, &count, "HEAD"]).map_err(Error::from_cli)?;
+    let commits = parse_log(&log);
+    let checked = commits.len();
+    let head = commits.first().map(|c| c.hash.clone()).unwrap_or_default();
+    let mut wrong = find_wrong(commits, &other_emails(config, &identity.email));
+    if !wrong.is_empty() {
+        let unpushed = git(repo, &["rev-list", &count, "HEAD", "--not", "--remotes"])
+            .map_err(Error::from_cli)?;
+        let unpushed: HashSet<&str> = unpushed.lines().map(str::trim).collect();
+        let has_remotes = !detect::remotes(repo)?.is_empty();
+        for commit in &mut wrong {
+            commit.pushed = has_remotes && !unpushed.contains(commit.hash.as_str());
+        }
+    }
+    Ok(Analysis {
+        identity: found.identity,
+        name: identity.user.clone(),
+        email: identity.email.clone(),
+        checked,
+        head,
+        wrong,
+    })
+}
+
+/// Amend HEAD with the expected author, keeping its message and date;
+/// blocks
+pub fn amend_head(repo: &Path, analysis: &Analysis) -> Result<(), Error> {
+    let name = format!("user.name={}", analysis.name);
+    let email = format!("user.email={}", analysis.email);
+    let author = format!("--author={} <{}>", analysis.name, analysis.email);
+    git(
+        repo,
+        &[
+            "-c",
+            &name,
+            "-c",
+            &email,
+            "commit",
+            "--amend",
+            "--no-edit",
+            &author,
+        ],
+    )
+    .map_err(Error::from_cli)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_wrong_commits_and_rewrite_commands() {
+        let log = "c3\x1fb2\x1fMe\x1fME@example.com\x1fFix build\n\
+                   b2\x1fa1\x1fColleague\x1fcol@acme.dev\x1fAdd API\n\
+                   a1\x1f\x1fMe\x1fme@example.com\x1fInitial\n";
+        let commits = parse_log(log);
+        assert_eq!(commits.len(), 3);
+        assert!(commits[2].is_root);
+
+        let others = HashSet::from(["me@example.com".to_string()]);
+        let mut wrong = find_wrong(commits, &others);
+        assert_eq!(wrong.len(), 2);
+        wrong[1].pushed = true;
+        let analysis = Analysis {
+            identity: "gitlab-work".to_string(),
+            name: "Work Me".to_string(),
+            email: "me@acme.dev".to_string(),
+            checked: 3,
+            head: "c3".to_string(),
+            wrong,
+        };
+        assert!(analysis.head_is_wrong());
+        assert_eq!(
+            analysis.mailmap(),
+            "Work Me <me@acme.dev> <ME@example.com>\nWork Me <me@acme.dev> <me@example.com>\n"
+        );
+        let commands = analysis.rewrite_commands();
+        assert!(commands.starts_with("# Some of these commits are pushed"));
+        assert!(commands.contains("git filter-repo --force --mailmap \"$mailmap\""));
+        assert!(commands.contains(
+            "git -c user.name='Work Me' -c user.email='me@acme.dev' rebase -r --root --exec \
+             'case \"$(git log -1 --format=%ae)\" in \"ME@example.com\"|\"me@example.com\")"
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+//! Key store auto-lock on inactivity
+//!
+//! The window reports user activity with [`touch`] and periodically calls
+//! [`lock_if_idle`] with the timeout for the current security mode. Locking
+//! drops the master passwords the GUI holds and every cached CLI result, and
+//! refuses further key store commands until [`unlocked`] is called, so a
+//! hardware auto-unlock doesn't quietly reopen the session.
+
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::cli;
+use crate::cli_cache;
+use crate::databases;
+use crate::keyring::MASTER_PASSWORD_ENV;
+
+/// Idle time and lock state for the session
+#[derive(Debug, Clone, Copy)]
+pub struct IdleState {
+    last_activity: Instant,
+    locked: bool,
+}
+
+impl IdleState {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            last_activity: now,
+            locked: false,
+        }
+    }
+
+    pub fn touch(&mut self, now: Instant) {
+        self.last_activity = now;
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Returns true if the session should lock now; a `None` timeout never
+    /// locks
+    pub fn is_due(&self, timeout: Option<Duration>, now: Instant) -> bool {
+        match timeout {
+            Some(timeout) if !self.locked => now.duration_since(self.last_activity) >= timeout,
+            _ => false,
+        }
+    }
+}
+
+fn state() -> &'static Mutex<IdleState> {
+    static STATE: OnceLock<Mutex<IdleState>> = OnceLock::new();
+    STATE.get_or_init(|| Mutex::new(IdleState::new(Instant::now())))
+}
+
+/// Record user activity
+pub fn touch() {
+    if let Ok(mut state) = state().lock() {
+        state.touch(Instant::now());
+    }
+}
+
+pub fn is_locked() -> bool {
+    state().lock().map(|s| s.is_locked()).unwrap_or(false)
+}
+
+/// Returns true for CLI commands that need the key store open
+pub fn needs_unlock(args: &[String]) -> bool {
+    let parts: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    !matches!(parts.as_slice(), ["keys", "status" | "init", ..]) && parts.first() == Some(&"keys")
+}
+
+/// Lock the session now
+pub fn lock() {
+    if let Ok(mut state) = state().lock() {
+        state.locked = true;
+    }
+    cli::set_session_env(MASTER_PASSWORD_ENV, None);
+    databases::forget_passwords();
+    cli_cache::global().invalidate();
+}
+
+/// Lock if the session has been idle for `timeout`
+///
+/// Returns true if this call locked it.
+pub fn lock_if_idle(timeout: Option<Duration>) -> bool {
+    let due = state()
+        .lock()
+        .map(|s| s.is_due(timeout, Instant::now()))
+        .unwrap_or(false);
+    if due {
+        lock();
+    }
+    due
+}
+
+/// Reopen the session once the user has unlocked it again
+pub fn unlocked() {
+    if let Ok(mut state) = state().lock() {
+        state.locked = false;
+        state.touch(Instant::now());
+    }
+}
+
+/// Human-readable timeout, e.g. "5 minutes" or "never"
+pub fn describe(timeout: Option<Duration>) -> String {
+    match timeout.map(|t| t.as_secs() / 60) {
+        None => "never".to_string(),
+        Some(1) => "1 minute".to_string(),
+        Some(minutes) if minutes % 60 == 0 && minutes >= 120 => {
+            format!("{} hours", minutes / 60)
+        }
+        Some(60) => "1 hour".to_string(),
+        Some(minutes) => format!("{} minutes", minutes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_due() {
+        let start = Instant::now();
+        let mut state = IdleState::new(start);
+        let five = Some(Duration::from_secs(300));
+
+        assert!(!state.is_due(five, start + Duration::from_secs(299)));
+        assert!(state.is_due(five, start + Duration::from_secs(300)));
+        assert!(!state.is_due(None, start + Duration::from_secs(86_400)));
+
+        // Activity restarts the countdown
+        state.touch(start + Duration::from_secs(200));
+        assert!(!state.is_due(five, start + Duration::from_secs(400)));
+
+        // Already locked
+        state.locked = true;
+        assert!(!state.is_due(five, start + Duration::from_secs(86_400)));
+    }
+
+    #[test]
+    fn test_needs_unlock() {
+        assert!(needs_unlock(&cli::args(&["keys", "get", "a/b"])));
+        assert!(needs_unlock(&cli::args(&["keys", "list"])));
+        assert!(!needs_unlock(&cli::args(&["keys", "status"])));
+        assert!(!needs_unlock(&cli::args(&["keys", "init"])));
+        assert!(!needs_unlock(&cli::args(&["status"])));
+        assert!(!needs_unlock(&[]));
+    }
+
+    #[test]
+    fn test_describe() {
+        let minutes = |m: u64| Some(Duration::from_secs(m * 60));
+        assert_eq!(describe(None), "never");
+        assert_eq!(describe(minutes(1)), "1 minute");
+        assert_eq!(describe(minutes(15)), "15 minutes");
+        assert_eq!(describe(minutes(60)), "1 hour");
+        assert_eq!(describe(minutes(90)), "90 minutes");
+        assert_eq!(describe(minutes(240)), "4 hours");
+    }
+}
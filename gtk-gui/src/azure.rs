@@ -0,0 +1,277 @@
+//! Azure Key Vault backend
+//!
+//! Mounts the secrets of one Key Vault into the key namespace as
+//! `Azure/{secret}`. The vault comes from the active identity's
+//! `cloud.azureVault` (a vault name or URL), falling back to
+//! `$AZURE_KEYVAULT_URL`, so a work identity reads the employer's vault.
+//!
+//! Access tokens are minted by the az CLI from its signed-in account;
+//! `az login --use-device-code` covers headless and device-flow sign-in.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::backend::SecretBackend;
+use crate::cli::{self, RunOptions};
+use crate::config::Config;
+use crate::error::Error;
+use crate::http;
+
+/// Namespace prefix for Key Vault secrets
+pub const NAMESPACE: &str = "Azure";
+
+const API_VERSION: &str = "7.4";
+const VAULT_RESOURCE: &str = "https://vault.azure.net";
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The az binary, overridable like the other external tools
+pub fn az_program() -> String {
+    std::env::var("REMOTE_JUGGLER_AZ_PATH").unwrap_or_else(|_| "az".to_string())
+}
+
+/// Vault URL from a vault name or URL, without a trailing slash
+pub fn vault_url(vault: &str) -> String {
+    let vault = vault.trim().trim_end_matches('/');
+    if vault.starts_with("https://") {
+        vault.to_string()
+    } else {
+        format!("https://{}.vault.azure.net", vault)
+    }
+}
+
+/// Vault for the active identity, else `$AZURE_KEYVAULT_URL`
+pub fn current_vault() -> Option<String> {
+    let from_identity = Config::load().ok().and_then(|config| {
+        config
+            .current_identity()
+            .and_then(|identity| identity.cloud.azure_vault.clone())
+    });
+    from_identity
+        .or_else(|| std::env::var("AZURE_KEYVAULT_URL").ok())
+        .filter(|v| !v.trim().is_empty())
+        .map(|v| vault_url(&v))
+}
+
+/// Returns true if `name` is a valid secret name (letters, digits, `-`)
+pub fn is_valid_secret_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 127
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SecretListResponse {
+    #[serde(default)]
+    value: Vec<SecretItem>,
+    next_link: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SecretItem {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct SecretBundle {
+    value: String,
+}
+
+/// Secret name from a secret id (`https://v.vault.azure.net/secrets/{name}`)
+pub fn secret_name(id: &str) -> &str {
+    let rest = id.rsplit_once("/secrets/").map_or(id, |(_, rest)| rest);
+    // Versioned ids end in /{version}
+    rest.split('/').next().unwrap_or(rest)
+}
+
+/// A Key Vault reached with az CLI credentials
+///
+/// The access token is fetched once per instance.
+#[derive(Debug)]
+pub struct AzureBackend {
+    vault: Option<String>,
+    token: Mutex<Option<String>>,
+}
+
+impl AzureBackend {
+    /// `vault` is a vault name or URL
+    pub fn new(vault: Option<&str>) -> Self {
+        Self {
+            vault: vault.map(vault_url),
+            token: Mutex::new(None),
+        }
+    }
+
+    /// The backend for the active identity's vault
+    pub fn from_config() -> Self {
+        Self::new(current_vault().as_deref())
+    }
+
+    /// Vault URL, if one is configured
+    pub fn vault(&self) -> Option<&str> {
+        self.vault.as_deref()
+    }
+
+    fn vault_or_err(&self) -> Result<&str, Error> {
+        self.vault().ok_or_else(|| Error::InvalidOutput {
+            program: "az",
+            message: "no Key Vault; set cloud.azureVault on the identity".to_string(),
+        })
+    }
+
+    fn token(&self) -> Result<String, Error> {
+        if let Some(token) = self.token.lock().ok().and_then(|t| t.clone()) {
+            return Ok(token);
+        }
+        let options = RunOptions {
+            timeout: TOKEN_TIMEOUT,
+            retries: 0,
+            cancel: None,
+        };
+        let args = cli::args(&[
+            "account",
+            "get-access-token",
+            "--resource",
+            VAULT_RESOURCE,
+            "--query",
+            "accessToken",
+            "--output",
+            "tsv",
+        ]);
+        let token = cli::run_program(&az_program(), &args, &options)
+            .map_err(Error::from_cli)?
+            .trim()
+            .to_string();
+        if let Ok(mut cached) = self.token.lock() {
+            *cached = Some(token.clone());
+        }
+        Ok(token)
+    }
+
+    fn secret_url(&self, name: &str) -> Result<String, Error> {
+        if !is_valid_secret_name(name) {
+            return Err(Error::InvalidOutput {
+                program: "Key Vault",
+                message: format!("{} is not a valid secret name", name),
+            });
+        }
+        Ok(format!(
+            "{}/secrets/{}?api-version={}",
+            self.vault_or_err()?,
+            name,
+            API_VERSION
+        ))
+    }
+}
+
+impl SecretBackend for AzureBackend {
+    fn namespace(&self) -> &'static str {
+        NAMESPACE
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Azure Key Vault"
+    }
+
+    fn is_available(&self) -> bool {
+        self.vault.is_some() && cli::find_program(&az_program()).is_some()
+    }
+
+    /// Signed out of az (no token can be minted)
+    fn is_locked(&self) -> bool {
+        self.token().is_err()
+    }
+
+    fn unlock(&self) -> Result<(), Error> {
+        self.token().map(|_| ()).map_err(|_| Error::InvalidOutput {
+            program: "az",
+            message: "run `az login` (or `az login --use-device-code`)".to_string(),
+        })
+    }
+
+    fn list(&self) -> Result<Vec<String>, Error> {
+        let token = self.token()?;
+        let mut url = format!(
+            "{}/secrets?api-version={}",
+            self.vault_or_err()?,
+            API_VERSION
+        );
+        let mut names = Vec::new();
+        loop {
+            let page: SecretListResponse = http::get_json(&url, Some(&token))?;
+            names.extend(page.value.iter().map(|s| secret_name(&s.id).to_string()));
+            match page.next_link.filter(|l| !l.is_empty()) {
+                Some(next) => url = next,
+                None => break,
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn get(&self, name: &str) -> Result<String, Error> {
+        let url = self.secret_url(name)?;
+        let bundle: SecretBundle = http::get_json(&url, Some(&self.token()?))?;
+        Ok(bundle.value)
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), Error> {
+        let url = self.secret_url(name)?;
+        let body = json!({ "value": value });
+        http::put_json::<serde_json::Value>(&url, Some(&self.token()?), &body).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault_url() {
+        assert_eq!(vault_url("acme-kv"), "https://acme-kv.vault.azure.net");
+        assert_eq!(
+            vault_url("https://acme-kv.vault.azure.net/"),
+            "https://acme-kv.vault.azure.net"
+        );
+    }
+
+    #[test]
+    fn test_secret_name() {
+        assert_eq!(
+            secret_name("https://acme-kv.vault.azure.net/secrets/github-token"),
+            "github-token"
+        );
+        assert_eq!(
+            secret_name("https://acme-kv.vault.azure.net/secrets/github-token/abc123"),
+            "github-token"
+        );
+    }
+
+    #[test]
+    fn test_is_valid_secret_name() {
+        assert!(is_valid_secret_name("github-token"));
+        assert!(!is_valid_secret_name("github_token"));
+        assert!(!is_valid_secret_name("work/github"));
+    }
+
+    #[test]
+    fn test_parse_list_response() {
+        let page: SecretListResponse = serde_json::from_str(
+            r#"{"value": [{"id": "https://v.vault.azure.net/secrets/a", "attributes": {}}],
+                "nextLink": "https://v.vault.azure.net/secrets?$skiptoken=x"}"#,
+        )
+        .unwrap();
+        assert_eq!(secret_name(&page.value[0].id), "a");
+        assert!(page.next_link.is_some());
+    }
+
+    #[test]
+    fn test_missing_vault_is_unavailable() {
+        let backend = AzureBackend::new(None);
+        assert!(!backend.is_available());
+        assert!(backend.get("token").is_err());
+    }
+}
@@ -0,0 +1,384 @@
+//! Pluggable secret backends
+//!
+//! The KeePassXC store (driven through the CLI) remains the credential
+//! authority. Other secret stores are mounted into the same key namespace
+//! under a prefix, e.g. `Pass/work/github`, so Search Keys and Get
+//! Credential work across all of them.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::azure::AzureBackend;
+use crate::bitwarden::BitwardenBackend;
+use crate::cli;
+use crate::cli_cache;
+use crate::config::Config;
+use crate::error::Error;
+use crate::gcp::GcpBackend;
+use crate::metrics;
+use crate::pass::PassBackend;
+use crate::progress::ProgressEvent;
+use crate::setec::SetecBackend;
+use crate::tailscale;
+
+/// Key store group that mirrored backend entries are imported under
+pub const IMPORT_GROUP: &str = "RemoteJuggler";
+
+/// A secret store mounted into the key namespace
+pub trait SecretBackend: Send + Sync {
+    /// Namespace prefix for this backend's entries, e.g. `Pass`
+    fn namespace(&self) -> &'static str;
+
+    /// Human-readable name for the UI
+    fn display_name(&self) -> &'static str;
+
+    /// Returns true if the store exists and its tooling is usable
+    fn is_available(&self) -> bool;
+
+    /// All entry names, relative to the namespace
+    fn list(&self) -> Result<Vec<String>, Error>;
+
+    /// The secret value of an entry
+    fn get(&self, name: &str) -> Result<String, Error>;
+
+    /// Create or replace an entry
+    fn set(&self, name: &str, value: &str) -> Result<(), Error>;
+
+    /// Returns false for stores that can only be read from
+    fn is_writable(&self) -> bool {
+        true
+    }
+
+    /// Returns true if the store must be unlocked before reading
+    fn is_locked(&self) -> bool {
+        false
+    }
+
+    /// Unlock the store, prompting through its own pinentry if needed
+    fn unlock(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns true if the store can only be reached over the tailnet
+    fn needs_tailnet(&self) -> bool {
+        false
+    }
+
+    /// Entry names matching `query`
+    fn search(&self, query: &str) -> Result<Vec<String>, Error> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|name| fuzzy_match(query, name))
+            .collect())
+    }
+}
+
+/// Case-insensitive subsequence match, like the CLI's fuzzy search
+pub fn fuzzy_match(query: &str, candidate: &str) -> bool {
+    let candidate = candidate.to_lowercase();
+    let mut chars = candidate.chars();
+    query
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .all(|q| chars.any(|c| c == q))
+}
+
+/// Full key path of an entry, e.g. `Pass/work/github`
+pub fn qualified(backend: &dyn SecretBackend, name: &str) -> String {
+    format!("{}/{}", backend.namespace(), name)
+}
+
+/// All known backends, whether or not they are available
+pub fn all() -> Vec<Box<dyn SecretBackend>> {
+    vec![
+        Box::new(PassBackend::from_env()),
+        Box::new(BitwardenBackend::detect()),
+        Box::new(GcpBackend::from_config()),
+        Box::new(AzureBackend::from_config()),
+        Box::new(SetecBackend::from_env()),
+    ]
+}
+
+/// Find the backend that owns a key path, with the path relative to it
+///
+/// Returns None for paths that belong to the KeePassXC store.
+pub fn route(path: &str) -> Option<(Box<dyn SecretBackend>, String)> {
+    let (prefix, rest) = path.split_once('/')?;
+    find(prefix).map(|b| (b, rest.to_string()))
+}
+
+/// The backend mounted at `namespace`
+pub fn find(namespace: &str) -> Option<Box<dyn SecretBackend>> {
+    all().into_iter().find(|b| b.namespace() == namespace)
+}
+
+/// Namespaces marked tailnet-only in config.json
+pub fn tailnet_only_marked() -> Vec<String> {
+    Config::load()
+        .map(|config| config.settings.tailnet_only_backends)
+        .unwrap_or_default()
+}
+
+/// Whether a store is only reachable over the tailnet: it says so, like
+/// Setec, or its namespace is among `marked`
+pub fn is_tailnet_only(backend: &dyn SecretBackend, marked: &[String]) -> bool {
+    backend.needs_tailnet()
+        || marked
+            .iter()
+            .any(|namespace| namespace.eq_ignore_ascii_case(backend.namespace()))
+}
+
+/// Search every available backend, returning qualified key paths
+///
+/// A failing backend is logged and skipped so one broken store doesn't hide
+/// results from the others. Locked stores are skipped rather than
+/// prompting for a master password mid-search, and tailnet-only stores
+/// while the tailnet is down.
+pub fn search_all(query: &str) -> Vec<String> {
+    let marked = tailnet_only_marked();
+    let mut results = Vec::new();
+    for backend in all().iter().filter(|b| b.is_available() && !b.is_locked()) {
+        if is_tailnet_only(backend.as_ref(), &marked) && tailscale::is_offline() {
+            tracing::debug!("Tailnet offline, not searching {}", backend.display_name());
+            continue;
+        }
+        match backend.search(query) {
+            Ok(names) => {
+                results.extend(names.iter().map(|n| qualified(backend.as_ref(), n)));
+            }
+            Err(e) => {
+                metrics::record_backend_error(backend.display_name());
+                tracing::warn!("{} search failed: {}", backend.display_name(), e);
+            }
+        }
+    }
+    results
+}
+
+/// What to do when an imported entry already exists in the key store
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Keep the existing KDBX entry
+    #[default]
+    Skip,
+    /// Replace the existing KDBX entry
+    Overwrite,
+    /// Import under a new name (`name-2`, `name-3`, ...)
+    Rename,
+}
+
+impl CollisionPolicy {
+    pub fn all() -> &'static [CollisionPolicy] {
+        &[
+            CollisionPolicy::Skip,
+            CollisionPolicy::Overwrite,
+            CollisionPolicy::Rename,
+        ]
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            CollisionPolicy::Skip => "Keep existing",
+            CollisionPolicy::Overwrite => "Overwrite",
+            CollisionPolicy::Rename => "Import as copy",
+        }
+    }
+
+    pub fn from_index(index: u32) -> Self {
+        Self::all().get(index as usize).copied().unwrap_or_default()
+    }
+}
+
+/// Outcome of importing a backend into the key store
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub overwritten: usize,
+    pub renamed: usize,
+    pub skipped: usize,
+    /// Entries that could not be read or stored
+    pub failed: Vec<String>,
+}
+
+impl ImportReport {
+    pub fn summary(&self) -> String {
+        let mut text = format!(
+            "Imported {}, overwrote {}, copied {}, skipped {}",
+            self.imported, self.overwritten, self.renamed, self.skipped
+        );
+        if !self.failed.is_empty() {
+            text.push_str(&format!("; {} failed", self.failed.len()));
+        }
+        text
+    }
+}
+
+/// Strip ANSI color sequences the CLI may add to its output
+pub fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // Skip to the final byte of the CSI sequence
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Parse `keys list` output into entry names, skipping subgroups
+pub fn parse_keys_list(output: &str) -> Vec<String> {
+    strip_ansi(output)
+        .lines()
+        .skip(1)
+        .map(str::trim)
+        .filter(|e| !e.is_empty() && !e.ends_with('/') && *e != "(empty)")
+        .map(str::to_string)
+        .collect()
+}
+
+/// Entry names directly inside a KDBX group; a missing group is empty
+pub fn kdbx_entries(group: &str) -> Vec<String> {
+    cli::run(&cli::args(&["keys", "list", group]))
+        .map(|output| parse_keys_list(&output))
+        .unwrap_or_default()
+}
+
+/// First `name-N` not in `existing`
+pub fn unique_name(name: &str, existing: &HashSet<String>) -> String {
+    (2..)
+        .map(|n| format!("{}-{}", name, n))
+        .find(|candidate| !existing.contains(candidate))
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// Copy every entry of a backend into the key store
+///
+/// Entries land under `RemoteJuggler/{namespace}/...`. This is one-way:
+/// later edits in KDBX are not written back. Blocks while the backend and
+/// the CLI run; `on_event` receives progress events.
+pub fn import_into_kdbx(
+    backend: &dyn SecretBackend,
+    policy: CollisionPolicy,
+    on_event: &mut dyn FnMut(&ProgressEvent),
+) -> Result<ImportReport, Error> {
+    let _span = tracing::info_span!("import", backend = backend.display_name()).entered();
+    if backend.is_locked() {
+        backend.unlock()?;
+    }
+    let names = backend.list()?;
+    let total = names.len() as u64;
+    on_event(&ProgressEvent::Started {
+        operation: format!("import from {}", backend.display_name()),
+        total: Some(total),
+    });
+
+    let root = format!("{}/{}", IMPORT_GROUP, backend.namespace());
+    let mut existing_by_group: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut report = ImportReport::default();
+
+    for (i, name) in names.iter().enumerate() {
+        on_event(&ProgressEvent::Progress {
+            current: i as u64,
+            total: Some(total),
+            item: Some(name.clone()),
+        });
+
+        let full = format!("{}/{}", root, name);
+        let (group, leaf) = full.rsplit_once('/').unwrap_or((root.as_str(), name));
+        let existing = existing_by_group
+            .entry(group.to_string())
+            .or_insert_with(|| kdbx_entries(group).into_iter().collect());
+
+        let collides = existing.contains(leaf);
+        let target = match (collides, policy) {
+            (true, CollisionPolicy::Skip) => {
+                report.skipped += 1;
+                continue;
+            }
+            (true, CollisionPolicy::Rename) => {
+                format!("{}/{}", group, unique_name(leaf, existing))
+            }
+            _ => full.clone(),
+        };
+
+        let stored = backend
+            .get(name)
+            .and_then(|value| cli::store_secret(&target, &value).map_err(Error::from_cli));
+        match stored {
+            Ok(_) => {
+                match (collides, policy) {
+                    (false, _) => report.imported += 1,
+                    (true, CollisionPolicy::Rename) => report.renamed += 1,
+                    (true, _) => report.overwritten += 1,
+                }
+                if let Some((_, leaf)) = target.rsplit_once('/') {
+                    existing.insert(leaf.to_string());
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Failed to import {}: {}", name, e);
+                report.failed.push(name.clone());
+            }
+        }
+    }
+
+    cli_cache::global().invalidate();
+    on_event(&ProgressEvent::Finished {
+        summary: Some(report.summary()),
+    });
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert!(fuzzy_match("gh", "work/github"));
+        assert!(fuzzy_match("GitHub", "work/github"));
+        assert!(fuzzy_match("wk gh", "work/github"));
+        assert!(!fuzzy_match("gitlab", "work/github"));
+        assert!(fuzzy_match("", "anything"));
+    }
+
+    #[test]
+    fn test_parse_keys_list() {
+        let output = "\u{1b}[1mEntries in RemoteJuggler/API:\u{1b}[0m\n\n  GITHUB_TOKEN\n  \u{1b}[34mNested/\u{1b}[0m\n  OPENAI_KEY\n";
+        assert_eq!(parse_keys_list(output), vec!["GITHUB_TOKEN", "OPENAI_KEY"]);
+        assert!(parse_keys_list("Entries in X:\n\n  (empty)\n").is_empty());
+    }
+
+    #[test]
+    fn test_unique_name() {
+        let existing: HashSet<String> = ["token".to_string(), "token-2".to_string()].into();
+        assert_eq!(unique_name("token", &existing), "token-3");
+    }
+
+    #[test]
+    fn test_route() {
+        let (backend, name) = route("Pass/work/github").unwrap();
+        assert_eq!(backend.namespace(), "Pass");
+        assert_eq!(name, "work/github");
+
+        assert!(route("RemoteJuggler/API/KEY").is_none());
+        assert!(route("Pass").is_none());
+    }
+
+    #[test]
+    fn test_is_tailnet_only() {
+        let setec = find("Setec").unwrap();
+        let pass = find("Pass").unwrap();
+        assert!(is_tailnet_only(setec.as_ref(), &[]));
+        assert!(!is_tailnet_only(pass.as_ref(), &[]));
+        assert!(is_tailnet_only(pass.as_ref(), &["pass".to_string()]));
+    }
+}
@@ -0,0 +1,175 @@
+//! remote-juggler-askpass: ssh's and git's prompt without a terminal
+//!
+//! Point ssh and git at it, or turn on "Set askpass on switch" in the GUI
+//! to have each switch do this for the desktop session:
+//!
+//! ```text
+//! export SSH_ASKPASS=/usr/local/bin/remote-juggler-askpass
+//! export GIT_ASKPASS=/usr/local/bin/remote-juggler-askpass
+//! ```
+//!
+//! The prompt is the only argument and the answer goes to stdout; exiting
+//! with a failure status cancels. Logging goes to stderr only.
+
+use std::cell::{Cell, RefCell};
+use std::process::ExitCode;
+use std::rc::Rc;
+
+use gtk4::glib;
+use gtk4::prelude::*;
+use remote_juggler_gui::askpass::{self, Prompt};
+use remote_juggler_gui::config::Config;
+
+fn main() -> ExitCode {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    let text = std::env::args().nth(1).unwrap_or_default();
+    let hint = std::env::var("SSH_ASKPASS_PROMPT").ok();
+    let prompt = Prompt::parse(&text, hint.as_deref());
+
+    if let Some(answer) = stored_answer(&prompt) {
+        println!("{}", answer);
+        return ExitCode::SUCCESS;
+    }
+    match ask(&text, &prompt) {
+        Some(answer) => {
+            if prompt != Prompt::Confirm && prompt != Prompt::Notice {
+                println!("{}", answer);
+            }
+            ExitCode::SUCCESS
+        }
+        None => ExitCode::FAILURE,
+    }
+}
+
+fn stored_answer(prompt: &Prompt) -> Option<String> {
+    let config = Config::load()
+        .map_err(|e| tracing::warn!("No config for a stored answer: {}", e))
+        .ok()?;
+    askpass::stored_answer(&config, prompt)
+}
+
+/// Show the prompt and wait for an answer; None if cancelled
+fn ask(text: &str, prompt: &Prompt) -> Option<String> {
+    if let Err(e) = gtk4::init() {
+        tracing::error!("Can't show the prompt: {}", e);
+        return None;
+    }
+
+    let main_loop = glib::MainLoop::new(None, false);
+    let window = gtk4::Window::builder()
+        .title(match prompt {
+            Prompt::Username { .. } | Prompt::Password { .. } => "Git",
+            _ => "SSH",
+        })
+        .resizable(false)
+        .default_width(400)
+        .build();
+
+    let content = gtk4::Box::new(gtk4::Orientation::Vertical, 12);
+    content.set_margin_top(18);
+    content.set_margin_bottom(18);
+    content.set_margin_start(18);
+    content.set_margin_end(18);
+
+    let description = gtk4::Label::new(Some(text.trim()));
+    description.set_wrap(true);
+    description.set_max_width_chars(50);
+    description.set_xalign(0.0);
+    content.append(&description);
+
+    let secret = gtk4::PasswordEntry::new();
+    secret.set_show_peek_icon(true);
+    let plain = gtk4::Entry::new();
+    let asks_text = !matches!(prompt, Prompt::Confirm | Prompt::Notice);
+    if asks_text && prompt.is_secret() {
+        content.append(&secret);
+    } else if asks_text {
+        content.append(&plain);
+    }
+
+    let buttons = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+    buttons.set_halign(gtk4::Align::End);
+    let cancel = gtk4::Button::with_label(match prompt {
+        Prompt::Confirm => "Deny",
+        Prompt::Notice => "Close",
+        _ => "Cancel",
+    });
+    let ok = gtk4::Button::with_label(match prompt {
+        Prompt::Confirm => "Allow",
+        _ => "OK",
+    });
+    ok.add_css_class("suggested-action");
+    buttons.append(&cancel);
+    if *prompt != Prompt::Notice {
+        buttons.append(&ok);
+    }
+    content.append(&buttons);
+    window.set_child(Some(&content));
+
+    let answer: Rc<RefCell<Option<String>>> = Rc::new(RefCell::new(None));
+    let done = Rc::new(Cell::new(false));
+    let finish: Rc<dyn Fn(Option<String>)> = {
+        let answer = answer.clone();
+        let main_loop = main_loop.clone();
+        let window = window.clone();
+        Rc::new(move |result: Option<String>| {
+            if done.replace(true) {
+                return;
+            }
+            answer.replace(result);
+            window.close();
+            main_loop.quit();
+        })
+    };
+
+    {
+        let finish = finish.clone();
+        cancel.connect_clicked(move |_| finish(None));
+    }
+    {
+        let finish = finish.clone();
+        let secret = secret.clone();
+        let plain = plain.clone();
+        let is_secret = prompt.is_secret();
+        ok.connect_clicked(move |_| {
+            let text = if is_secret {
+                secret.text()
+            } else {
+                plain.text()
+            };
+            finish(Some(text.to_string()));
+        });
+    }
+    {
+        let ok = ok.clone();
+        secret.connect_activate(move |_| ok.emit_clicked());
+    }
+    {
+        let ok = ok.clone();
+        plain.connect_activate(move |_| ok.emit_clicked());
+    }
+    {
+        let finish = finish.clone();
+        window.connect_close_request(move |_| {
+            finish(None);
+            glib::Propagation::Proceed
+        });
+    }
+
+    window.present();
+    if prompt.is_secret() {
+        secret.grab_focus();
+    } else {
+        plain.grab_focus();
+    }
+    main_loop.run();
+    // Let the window unmap before ssh moves on
+    let context = glib::MainContext::default();
+    while context.iteration(false) {}
+
+    let result = answer.borrow_mut().take();
+    result
+}
@@ -0,0 +1,265 @@
+//! pinentry-remote-juggler: gpg-agent's PIN prompt
+//!
+//! Point gpg-agent at it in `~/.gnupg/gpg-agent.conf`:
+//!
+//! ```text
+//! pinentry-program /usr/local/bin/pinentry-remote-juggler
+//! ```
+//!
+//! then `gpgconf --reload gpg-agent`. The Assuan conversation runs over
+//! stdin/stdout, so logging goes to stderr only.
+
+use std::cell::{Cell, RefCell};
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+use gtk4::glib;
+use gtk4::prelude::*;
+use remote_juggler_gui::config::Config;
+use remote_juggler_gui::gpg;
+use remote_juggler_gui::pinentry::{self, Request, Session, Step};
+
+/// What the GTK window asks for
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Prompt {
+    Pin,
+    Confirm { one_button: bool },
+    Message,
+}
+
+/// How the user answered
+enum Answer {
+    Pin(String),
+    Yes,
+    No,
+    Cancelled,
+    TimedOut,
+}
+
+fn main() {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    let stdin = std::io::stdin();
+    let mut out = std::io::stdout().lock();
+    let mut session = Session::default();
+    send(&mut out, &["OK Pleased to meet you".to_string()]);
+
+    for line in stdin.lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let lines = match session.handle(line) {
+            Step::Reply(lines) => lines,
+            Step::GetPin => {
+                let lines = get_pin(&session);
+                session.finish();
+                lines
+            }
+            Step::Confirm { one_button } => {
+                let lines = answer_lines(ask(&session.request, Prompt::Confirm { one_button }));
+                session.finish();
+                lines
+            }
+            Step::Message => answer_lines(ask(&session.request, Prompt::Message)),
+            Step::Bye => {
+                send(&mut out, &["OK closing connection".to_string()]);
+                break;
+            }
+        };
+        send(&mut out, &lines);
+    }
+}
+
+fn send(out: &mut impl Write, lines: &[String]) {
+    for line in lines {
+        if writeln!(out, "{}", line).is_err() {
+            std::process::exit(1);
+        }
+    }
+    let _ = out.flush();
+}
+
+/// Answer GETPIN from the stored PIN when there is one, else ask
+fn get_pin(session: &Session) -> Vec<String> {
+    if let Some(pin) = stored_pin(&session.request) {
+        return session.pin_reply(&pin);
+    }
+    match ask(&session.request, Prompt::Pin) {
+        Answer::Pin(pin) => session.pin_reply(&pin),
+        answer => answer_lines(answer),
+    }
+}
+
+/// The sealed PIN of the identity the key belongs to. Not used when
+/// gpg-agent reports the last PIN was wrong, or when the card has one try
+/// left, so a stale stored PIN can't run down the card's retry counter.
+fn stored_pin(request: &Request) -> Option<String> {
+    if !request.error.is_empty() || request.remaining_attempts().is_some_and(|n| n <= 1) {
+        return None;
+    }
+    let config = Config::load()
+        .map_err(|e| tracing::warn!("No config for a stored PIN: {}", e))
+        .ok()?;
+    let keys = gpg::list_secret_keys().unwrap_or_default();
+    let name = pinentry::identity_for(&config, &keys, request)?;
+    pinentry::stored_pin(&config, &name)
+}
+
+fn answer_lines(answer: Answer) -> Vec<String> {
+    let line = match answer {
+        Answer::Pin(_) | Answer::Yes => "OK".to_string(),
+        Answer::No => pinentry::err(pinentry::ERR_NOT_CONFIRMED, "Not confirmed"),
+        Answer::Cancelled => pinentry::err(pinentry::ERR_CANCELED, "Operation cancelled"),
+        Answer::TimedOut => pinentry::err(pinentry::ERR_TIMEOUT, "Timeout"),
+    };
+    vec![line]
+}
+
+fn or<'a>(text: &'a str, default: &'a str) -> &'a str {
+    if text.is_empty() {
+        default
+    } else {
+        text
+    }
+}
+
+/// Show the prompt and wait for an answer
+fn ask(request: &Request, prompt: Prompt) -> Answer {
+    if let Err(e) = gtk4::init() {
+        tracing::error!("Can't show the PIN prompt: {}", e);
+        return Answer::Cancelled;
+    }
+
+    let main_loop = glib::MainLoop::new(None, false);
+    let window = gtk4::Window::builder()
+        .title(or(&request.title, "RemoteJuggler"))
+        .resizable(false)
+        .default_width(400)
+        .build();
+
+    let content = gtk4::Box::new(gtk4::Orientation::Vertical, 12);
+    content.set_margin_top(18);
+    content.set_margin_bottom(18);
+    content.set_margin_start(18);
+    content.set_margin_end(18);
+
+    let description = gtk4::Label::new(Some(or(&request.description, &request.prompt)));
+    description.set_wrap(true);
+    description.set_max_width_chars(50);
+    description.set_xalign(0.0);
+    content.append(&description);
+
+    let error = gtk4::Label::new(Some(&request.error));
+    error.add_css_class("error");
+    error.set_wrap(true);
+    error.set_xalign(0.0);
+    error.set_visible(!request.error.is_empty());
+    content.append(&error);
+
+    let entry = gtk4::PasswordEntry::new();
+    entry.set_show_peek_icon(true);
+    entry.set_placeholder_text(Some(or(&request.prompt, "PIN")));
+    let repeat = gtk4::PasswordEntry::new();
+    repeat.set_show_peek_icon(true);
+    repeat.set_placeholder_text(Some(&request.repeat));
+    if prompt == Prompt::Pin {
+        content.append(&entry);
+        if !request.repeat.is_empty() {
+            content.append(&repeat);
+        }
+    }
+
+    let buttons = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+    buttons.set_halign(gtk4::Align::End);
+    let cancel = gtk4::Button::with_label(or(&request.cancel, "Cancel"));
+    let not_ok = gtk4::Button::with_label(&request.not_ok);
+    let ok = gtk4::Button::with_label(or(&request.ok, "OK"));
+    ok.add_css_class("suggested-action");
+    match prompt {
+        Prompt::Message | Prompt::Confirm { one_button: true } => {}
+        _ => buttons.append(&cancel),
+    }
+    if matches!(prompt, Prompt::Confirm { one_button: false }) && !request.not_ok.is_empty() {
+        buttons.append(&not_ok);
+    }
+    buttons.append(&ok);
+    content.append(&buttons);
+    window.set_child(Some(&content));
+
+    let answer: Rc<RefCell<Option<Answer>>> = Rc::new(RefCell::new(None));
+    let done = Rc::new(Cell::new(false));
+    let finish: Rc<dyn Fn(Answer)> = {
+        let answer = answer.clone();
+        let main_loop = main_loop.clone();
+        let window = window.clone();
+        Rc::new(move |result: Answer| {
+            if done.replace(true) {
+                return;
+            }
+            answer.replace(Some(result));
+            window.close();
+            main_loop.quit();
+        })
+    };
+
+    {
+        let finish = finish.clone();
+        cancel.connect_clicked(move |_| finish(Answer::Cancelled));
+    }
+    {
+        let finish = finish.clone();
+        not_ok.connect_clicked(move |_| finish(Answer::No));
+    }
+    {
+        let finish = finish.clone();
+        let entry = entry.clone();
+        let repeat = repeat.clone();
+        let error = error.clone();
+        let repeated = !request.repeat.is_empty();
+        ok.connect_clicked(move |_| {
+            if prompt != Prompt::Pin {
+                finish(Answer::Yes);
+            } else if repeated && entry.text() != repeat.text() {
+                error.set_text("The entries don't match");
+                error.set_visible(true);
+                repeat.set_text("");
+                repeat.grab_focus();
+            } else {
+                finish(Answer::Pin(entry.text().to_string()));
+            }
+        });
+    }
+    for field in [&entry, &repeat] {
+        let ok = ok.clone();
+        field.connect_activate(move |_| ok.emit_clicked());
+    }
+    {
+        let finish = finish.clone();
+        window.connect_close_request(move |_| {
+            finish(Answer::Cancelled);
+            glib::Propagation::Proceed
+        });
+    }
+    if request.timeout > 0 {
+        let finish = finish.clone();
+        glib::timeout_add_seconds_local_once(request.timeout, move || finish(Answer::TimedOut));
+    }
+
+    window.present();
+    if prompt == Prompt::Pin {
+        entry.grab_focus();
+    }
+    main_loop.run();
+    // Let the window unmap before gpg-agent moves on
+    let context = glib::MainContext::default();
+    while context.iteration(false) {}
+
+    let result = answer.borrow_mut().take();
+    result.unwrap_or(Answer::Cancelled)
+}
@@ -0,0 +1,143 @@
+//! remote-juggler-shell: prompt segment and directory hooks for shells
+//!
+//! ```text
+//! remote-juggler-shell prompt [--format FORMAT]
+//! eval "$(remote-juggler-shell init bash)"    # or zsh
+//! remote-juggler-shell init fish | source
+//! ```
+//!
+//! `prompt` prints nothing rather than an error, so a broken config never
+//! breaks the shell prompt. It needs no GTK, so it builds without the `gui`
+//! feature.
+
+use std::path::Path;
+use std::process::{Command, ExitCode, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use remote_juggler_gui::cli;
+use remote_juggler_gui::config::Config;
+use remote_juggler_gui::event_hooks;
+use remote_juggler_gui::shell::{self, KeystoreState, PromptCache, Shell};
+
+const USAGE: &str = "Usage:
+  remote-juggler-shell prompt [--format FORMAT]
+  remote-juggler-shell init bash|zsh|fish
+
+FORMAT placeholders: {identity} {provider} {keystore} {locked}";
+
+/// How long `on-cd` waits for event hooks after a switch
+const EVENT_HOOKS_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("prompt") => prompt(&args[1..]),
+        Some("init") => init(args.get(1).map(String::as_str)),
+        // Run by the hooks, not meant to be typed
+        Some("on-cd") => on_cd(args.get(1), args.get(2)),
+        Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn prompt(args: &[String]) -> ExitCode {
+    if args.first().map(String::as_str) == Some("--refresh") {
+        let keystore = match cli::run(&cli::args(&["keys", "status"])) {
+            Ok(output) => KeystoreState::parse(&output),
+            Err(_) => KeystoreState::Unknown,
+        };
+        let cache = PromptCache {
+            keystore,
+            checked_at: now(),
+        };
+        return match cache.save() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(_) => ExitCode::FAILURE,
+        };
+    }
+
+    let format = match args {
+        [flag, format] if flag == "--format" => format.as_str(),
+        [flag] if flag.starts_with("--format=") => &flag["--format=".len()..],
+        _ => shell::DEFAULT_FORMAT,
+    };
+    let Ok(config) = Config::load() else {
+        return ExitCode::SUCCESS;
+    };
+    if config.state.current_identity.is_empty() {
+        return ExitCode::SUCCESS;
+    }
+
+    let mut cache = PromptCache::load();
+    if cache.is_stale(now()) {
+        // Claim the refresh first so prompts meanwhile don't start more
+        cache.checked_at = now();
+        if cache.save().is_ok() {
+            refresh_in_background();
+        }
+    }
+    println!("{}", shell::render(format, &config, cache.keystore));
+    ExitCode::SUCCESS
+}
+
+/// Start `prompt --refresh` detached from the prompt
+fn refresh_in_background() {
+    let Ok(program) = std::env::current_exe() else {
+        return;
+    };
+    let _ = Command::new(program)
+        .args(["prompt", "--refresh"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+}
+
+fn init(name: Option<&str>) -> ExitCode {
+    let Some(shell) = name.and_then(Shell::parse) else {
+        eprintln!("init takes one of: bash, zsh, fish");
+        return ExitCode::from(2);
+    };
+    let program = std::env::current_exe()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| "remote-juggler-shell".to_string());
+    print!("{}", shell.hook(&program));
+    ExitCode::SUCCESS
+}
+
+fn on_cd(dir: Option<&String>, previous: Option<&String>) -> ExitCode {
+    let Some(dir) = dir else {
+        return ExitCode::from(2);
+    };
+    let Ok(config) = Config::load() else {
+        return ExitCode::SUCCESS;
+    };
+    let previous = previous.filter(|p| !p.is_empty()).map(Path::new);
+    let Some(identity) = shell::switch_for(&config, previous, Path::new(dir)) else {
+        return ExitCode::SUCCESS;
+    };
+    match cli::run(&cli::args(&["switch", &identity])) {
+        Ok(_) => {
+            eprintln!("remote-juggler: switched to {}", identity);
+            event_hooks::flush(EVENT_HOOKS_TIMEOUT);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("remote-juggler: can't switch to {}: {}", identity, e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
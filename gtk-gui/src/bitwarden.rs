@@ -0,0 +1,321 @@
+//! Bitwarden backend
+//!
+//! Reads a Bitwarden vault through `rbw` (preferred: its agent caches the
+//! unlocked vault and prompts through pinentry) or the official `bw` CLI
+//! when `BW_SESSION` is set. Items are mounted into the key namespace as
+//! `Bitwarden/{folder}/{name}`; items sharing a name are told apart by
+//! their username, as `{name}@{user}`.
+//!
+//! The backend is read-only. Use `backend::import_into_kdbx` to copy items
+//! into the key store.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::backend::SecretBackend;
+use crate::cli::{self, RunOptions};
+use crate::error::Error;
+
+/// Namespace prefix for Bitwarden items
+pub const NAMESPACE: &str = "Bitwarden";
+
+// Unlocking waits for the user to type the master password
+const UNLOCK_TIMEOUT: Duration = Duration::from_secs(180);
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Which Bitwarden CLI is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Client {
+    Rbw,
+    Bw,
+}
+
+impl Client {
+    fn program(&self) -> String {
+        match self {
+            Client::Rbw => {
+                std::env::var("REMOTE_JUGGLER_RBW_PATH").unwrap_or_else(|_| "rbw".to_string())
+            }
+            Client::Bw => {
+                std::env::var("REMOTE_JUGGLER_BW_PATH").unwrap_or_else(|_| "bw".to_string())
+            }
+        }
+    }
+}
+
+/// A vault item, as listed (without its secret)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    pub name: String,
+    pub user: Option<String>,
+    pub folder: Option<String>,
+    /// Item id (bw only)
+    pub id: Option<String>,
+}
+
+/// Parse `rbw list --fields name,user,folder` (tab-separated)
+pub fn parse_rbw_list(output: &str) -> Vec<Item> {
+    fn non_empty(s: Option<&str>) -> Option<String> {
+        s.map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    }
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut fields = line.split('\t');
+            Item {
+                name: fields.next().unwrap_or_default().trim().to_string(),
+                user: non_empty(fields.next()),
+                folder: non_empty(fields.next()),
+                id: None,
+            }
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct BwFolder {
+    id: Option<String>,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BwItem {
+    id: String,
+    name: String,
+    folder_id: Option<String>,
+    login: Option<BwLogin>,
+}
+
+#[derive(Deserialize)]
+struct BwLogin {
+    username: Option<String>,
+}
+
+/// Parse `bw list items` and `bw list folders` JSON
+pub fn parse_bw_items(items_json: &str, folders_json: &str) -> Result<Vec<Item>, Error> {
+    let invalid = |e: serde_json::Error| Error::InvalidOutput {
+        program: "bw",
+        message: e.to_string(),
+    };
+    let folders: Vec<BwFolder> = serde_json::from_str(folders_json).map_err(invalid)?;
+    let items: Vec<BwItem> = serde_json::from_str(items_json).map_err(invalid)?;
+    let folder_names: HashMap<String, String> = folders
+        .into_iter()
+        .filter_map(|f| Some((f.id?, f.name)))
+        .collect();
+
+    Ok(items
+        .into_iter()
+        .map(|item| Item {
+            name: item.name,
+            user: item.login.and_then(|l| l.username),
+            folder: item.folder_id.and_then(|id| folder_names.get(&id).cloned()),
+            id: Some(item.id),
+        })
+        .collect())
+}
+
+/// Assign each item its key path, disambiguating duplicate names by user
+pub fn assign_keys(items: Vec<Item>) -> Vec<(String, Item)> {
+    let base = |item: &Item| match &item.folder {
+        Some(folder) => format!("{}/{}", folder, item.name),
+        None => item.name.clone(),
+    };
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for item in &items {
+        *counts.entry(base(item)).or_default() += 1;
+    }
+    items
+        .into_iter()
+        .map(|item| {
+            let key = base(&item);
+            let key = match &item.user {
+                Some(user) if counts[&key] > 1 => format!("{}@{}", key, user),
+                _ => key,
+            };
+            (key, item)
+        })
+        .collect()
+}
+
+/// A Bitwarden vault reached through rbw or bw
+///
+/// The item listing is fetched once per instance, so resolving many items
+/// (an import) doesn't re-list the vault for each one.
+#[derive(Debug)]
+pub struct BitwardenBackend {
+    client: Client,
+    items: Mutex<Option<Vec<(String, Item)>>>,
+}
+
+impl BitwardenBackend {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            items: Mutex::new(None),
+        }
+    }
+
+    /// Use bw when a session is exported, otherwise rbw
+    pub fn detect() -> Self {
+        let bw_session = std::env::var_os("BW_SESSION").is_some();
+        if bw_session && cli::find_program(&Client::Bw.program()).is_some() {
+            Self::new(Client::Bw)
+        } else {
+            Self::new(Client::Rbw)
+        }
+    }
+
+    pub fn client(&self) -> Client {
+        self.client
+    }
+
+    fn run(&self, args: &[&str], timeout: Duration) -> Result<String, Error> {
+        let options = RunOptions {
+            timeout,
+            retries: 0,
+            cancel: None,
+        };
+        cli::run_program(&self.client.program(), &cli::args(args), &options)
+            .map_err(Error::from_cli)
+    }
+
+    fn items(&self) -> Result<Vec<(String, Item)>, Error> {
+        if let Some(items) = self.items.lock().ok().and_then(|i| i.clone()) {
+            return Ok(items);
+        }
+        let items = match self.client {
+            Client::Rbw => {
+                parse_rbw_list(&self.run(&["list", "--fields", "name,user,folder"], READ_TIMEOUT)?)
+            }
+            Client::Bw => parse_bw_items(
+                &self.run(&["list", "items"], READ_TIMEOUT)?,
+                &self.run(&["list", "folders"], READ_TIMEOUT)?,
+            )?,
+        };
+        let items = assign_keys(items);
+        if let Ok(mut cached) = self.items.lock() {
+            *cached = Some(items.clone());
+        }
+        Ok(items)
+    }
+}
+
+impl SecretBackend for BitwardenBackend {
+    fn namespace(&self) -> &'static str {
+        NAMESPACE
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Bitwarden"
+    }
+
+    fn is_available(&self) -> bool {
+        cli::find_program(&self.client.program()).is_some()
+    }
+
+    fn list(&self) -> Result<Vec<String>, Error> {
+        Ok(self.items()?.into_iter().map(|(key, _)| key).collect())
+    }
+
+    fn get(&self, name: &str) -> Result<String, Error> {
+        let item = self
+            .items()?
+            .into_iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, item)| item)
+            .ok_or_else(|| Error::InvalidOutput {
+                program: "bitwarden",
+                message: format!("no item named {}", name),
+            })?;
+
+        let output = match self.client {
+            Client::Rbw => {
+                let mut args = vec!["get"];
+                if let Some(folder) = &item.folder {
+                    args.extend(["--folder", folder]);
+                }
+                args.push(&item.name);
+                if let Some(user) = &item.user {
+                    args.push(user);
+                }
+                self.run(&args, READ_TIMEOUT)?
+            }
+            Client::Bw => {
+                let id = item.id.as_deref().unwrap_or(&item.name);
+                self.run(&["get", "password", id], READ_TIMEOUT)?
+            }
+        };
+        Ok(output.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    fn set(&self, _name: &str, _value: &str) -> Result<(), Error> {
+        Err(Error::ReadOnlyBackend("Bitwarden"))
+    }
+
+    fn is_writable(&self) -> bool {
+        false
+    }
+
+    fn is_locked(&self) -> bool {
+        match self.client {
+            // `rbw unlocked` exits non-zero while the agent holds no keys
+            Client::Rbw => self.run(&["unlocked"], READ_TIMEOUT).is_err(),
+            // bw needs BW_SESSION from `bw unlock`, which the user exports
+            Client::Bw => std::env::var_os("BW_SESSION").is_none(),
+        }
+    }
+
+    fn unlock(&self) -> Result<(), Error> {
+        match self.client {
+            Client::Rbw => self.run(&["unlock"], UNLOCK_TIMEOUT).map(|_| ()),
+            Client::Bw => Err(Error::InvalidOutput {
+                program: "bw",
+                message: "run `bw unlock` and export BW_SESSION, or install rbw".to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rbw_list() {
+        let items = parse_rbw_list("GitHub\toctocat\tWork\nNetflix\t\t\n");
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].user.as_deref(), Some("octocat"));
+        assert_eq!(items[0].folder.as_deref(), Some("Work"));
+        assert_eq!(items[1].user, None);
+        assert_eq!(items[1].folder, None);
+    }
+
+    #[test]
+    fn test_parse_bw_items() {
+        let folders = r#"[{"id": "f1", "name": "Work"}, {"id": null, "name": "No Folder"}]"#;
+        let items = r#"[
+            {"id": "i1", "name": "GitHub", "folderId": "f1", "login": {"username": "octocat"}},
+            {"id": "i2", "name": "Wifi", "folderId": null, "login": null}
+        ]"#;
+        let parsed = parse_bw_items(items, folders).unwrap();
+        assert_eq!(parsed[0].folder.as_deref(), Some("Work"));
+        assert_eq!(parsed[0].id.as_deref(), Some("i1"));
+        assert_eq!(parsed[1].folder, None);
+        assert!(parse_bw_items("not json", "[]").is_err());
+    }
+
+    #[test]
+    fn test_assign_keys_disambiguates_duplicates() {
+        let items = parse_rbw_list("GitHub\tme\tWork\nGitHub\tbot\tWork\nGitLab\tme\t\n");
+        let keys: Vec<String> = assign_keys(items).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["Work/GitHub@me", "Work/GitHub@bot", "GitLab"]);
+    }
+}
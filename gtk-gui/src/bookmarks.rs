@@ -0,0 +1,266 @@
+//! Bookmarked repositories opened as their identity
+//!
+//! A bookmark opens a terminal or editor in a repository after switching
+//! to the identity it maps to: the bookmark's own, or the one detection
+//! finds. The repository's git config gets the identity's settings unless
+//! it is excluded from switching, and the spawned process gets them in its
+//! environment too: author and committer, and a `GIT_SSH_COMMAND` offering
+//! only the identity's key, so pushes from it authenticate as the identity
+//! whatever the remote URL says.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::branches;
+use crate::cli;
+use crate::config::{Bookmark, Config, Identity, Settings};
+use crate::detect::{self, IdentityMatch};
+use crate::error::Error;
+use crate::hg;
+use crate::platform;
+use crate::ssh_cert;
+use crate::workspace;
+
+/// Terminals tried in order when `terminalCommand` is empty
+pub const TERMINALS: &[&str] = &[
+    "kgx",
+    "gnome-terminal",
+    "konsole",
+    "xfce4-terminal",
+    "kitty",
+    "alacritty",
+    "foot",
+    "wezterm",
+    "x-terminal-emulator",
+    "xterm",
+];
+
+/// Editors tried in order when `editorCommand` is empty
+pub const EDITORS: &[&str] = &["code", "codium", "zed", "subl", "gnome-text-editor"];
+
+/// What a bookmark opens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Launch {
+    Terminal,
+    Editor,
+}
+
+impl Launch {
+    pub fn display_text(&self) -> &'static str {
+        match self {
+            Self::Terminal => "terminal",
+            Self::Editor => "editor",
+        }
+    }
+
+    fn setting(&self, settings: &Settings) -> String {
+        match self {
+            Self::Terminal => settings.terminal_command.trim().to_string(),
+            Self::Editor => settings.editor_command.trim().to_string(),
+        }
+    }
+
+    fn known(&self) -> &'static [&'static str] {
+        match self {
+            Self::Terminal => TERMINALS,
+            Self::Editor => EDITORS,
+        }
+    }
+}
+
+/// The identity a bookmark opens as
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mapping {
+    /// Set on the bookmark
+    Pinned(String),
+    Detected(IdentityMatch),
+    /// Neither set nor detected
+    Unmapped,
+}
+
+impl Mapping {
+    pub fn identity(&self) -> Option<&str> {
+        match self {
+            Self::Pinned(name) => Some(name),
+            Self::Detected(found) => Some(&found.identity),
+            Self::Unmapped => None,
+        }
+    }
+
+    pub fn display_text(&self) -> String {
+        match self {
+            Self::Pinned(name) => format!("{} (bookmark)", name),
+            Self::Detected(found) => found.display_text(),
+            Self::Unmapped => "No identity detected".to_string(),
+        }
+    }
+}
+
+/// Work out the identity a bookmark opens as; blocks
+pub fn mapping(config: &Config, bookmark: &Bookmark) -> Result<Mapping, Error> {
+    if !bookmark.identity.is_empty() {
+        return Ok(Mapping::Pinned(bookmark.identity.clone()));
+    }
+    let path = platform::expand_tilde(&bookmark.path);
+    Ok(match detect::detect(config, &path)? {
+        Some(found) => Mapping::Detected(found),
+        None => Mapping::Unmapped,
+    })
+}
+
+/// The ssh command offering only the identity's key; None if it names
+/// neither a key file nor a PKCS#11 library
+pub fn ssh_command(identity: &Identity) -> Option<String> {
+    let option = if let Some(provider) = identity.pkcs11_provider.as_deref() {
+        format!("-o PKCS11Provider={}", ssh_cert::shell_quote(provider))
+    } else if !identity.ssh_key_path.is_empty() {
+        let key = platform::expand_tilde(&identity.ssh_key_path);
+        format!("-i {}", ssh_cert::shell_quote(&key.to_string_lossy()))
+    } else {
+        return None;
+    };
+    Some(format!("ssh {} -o IdentitiesOnly=yes", option))
+}
+
+/// The environment a process opened as the identity gets
+pub fn environment(name: &str, identity: &Identity) -> Vec<(String, String)> {
+    let mut env: Vec<(String, String)> = [
+        ("GIT_AUTHOR_NAME", &identity.user),
+        ("GIT_AUTHOR_EMAIL", &identity.email),
+        ("GIT_COMMITTER_NAME", &identity.user),
+        ("GIT_COMMITTER_EMAIL", &identity.email),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value.clone()))
+    .collect();
+    if let Some(command) = ssh_command(identity) {
+        env.push(("GIT_SSH_COMMAND".to_string(), command));
+    }
+    env.push(("REMOTE_JUGGLER_IDENTITY".to_string(), name.to_string()));
+    env
+}
+
+/// The command line for a configured command: `{path}` is replaced with
+/// the repository, which editors get appended otherwise
+pub fn command_line(launch: Launch, command: &str, repo: &Path) -> Vec<String> {
+    let path = repo.to_string_lossy();
+    let mut args: Vec<String> = command
+        .split_whitespace()
+        .map(|arg| arg.replace("{path}", &path))
+        .collect();
+    if launch == Launch::Editor && !command.contains("{path}") {
+        args.push(path.into_owned());
+    }
+    args
+}
+
+/// The command to run: the configured one, else the first known program
+/// on PATH
+pub fn command_for(settings: &Settings, launch: Launch, repo: &Path) -> Option<Vec<String>> {
+    let configured = launch.setting(settings);
+    let command = if configured.is_empty() {
+        launch
+            .known()
+            .iter()
+            .find(|program| cli::find_program(program).is_some())?
+            .to_string()
+    } else {
+        configured
+    };
+    Some(command_line(launch, &command, repo)).filter(|args| !args.is_empty())
+}
+
+/// Write the identity to the repository's git config, unless it is
+/// excluded from switching; whether it was written. Blocks
+pub fn apply(config: &Config, repo: &Path, name: &str) -> Result<bool, Error> {
+    let identity = identity(config, name)?;
+    if config.settings.is_switch_excluded(repo) {
+        return Ok(false);
+    }
+    workspace::apply_identity(repo, identity)?;
+    // A [user] section added after the branch includes would win over them
+    if !config.settings.branch_rules.is_empty() && !hg::is_hg(repo) {
+        branches::apply(config, repo)?;
+    }
+    Ok(true)
+}
+
+/// Spawn a terminal or editor in the repository as the identity; the
+/// program started
+pub fn open(config: &Config, launch: Launch, repo: &Path, name: &str) -> Result<PathBuf, Error> {
+    let identity = identity(config, name)?;
+    let args = command_for(&config.settings, launch, repo).ok_or_else(|| Error::InvalidOutput {
+        program: "bookmarks",
+        message: format!(
+            "No {} found; set {}Command",
+            launch.display_text(),
+            launch.display_text()
+        ),
+    })?;
+    let mut child = Command::new(&args[0])
+        .args(&args[1..])
+        .current_dir(repo)
+        .envs(environment(name, identity))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // Reap it whenever it exits
+    std::thread::spawn(move || child.wait());
+    Ok(PathBuf::from(&args[0]))
+}
+
+fn identity<'a>(config: &'a Config, name: &str) -> Result<&'a Identity, Error> {
+    config
+        .identities
+        .get(name)
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "bookmarks",
+            message: format!("No identity named {}", name),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+
+    #[test]
+    fn test_environment_and_command_line() {
+        let config = ConfigBuilder::new()
+            .identity(
+                "github-work",
+                Identity {
+                    ssh_key_path: "/keys/it's_work".to_string(),
+                    ..identity("github", "github-work", "github.com", "Me", "me@acme.dev")
+                },
+            )
+            .build();
+        let identity = &config.identities["github-work"];
+        let env = environment("github-work", identity);
+        assert!(env.contains(&("GIT_AUTHOR_EMAIL".to_string(), "me@acme.dev".to_string())));
+        assert!(env.contains(&(
+            "GIT_SSH_COMMAND".to_string(),
+            r"ssh -i '/keys/it'\''s_work' -o IdentitiesOnly=yes".to_string()
+        )));
+
+        let repo = Path::new("/src/api");
+        assert_eq!(
+            command_line(Launch::Editor, "code --new-window", repo),
+            vec!["code", "--new-window", "/src/api"]
+        );
+        assert_eq!(
+            command_line(Launch::Terminal, "kitty --directory {path}", repo),
+            vec!["kitty", "--directory", "/src/api"]
+        );
+        assert_eq!(command_line(Launch::Terminal, "kgx", repo), vec!["kgx"]);
+
+        let bookmark = Bookmark {
+            path: "/src/api".to_string(),
+            identity: "github-work".to_string(),
+        };
+        let found = mapping(&config, &bookmark).unwrap();
+        assert_eq!(found.identity(), Some("github-work"));
+        assert_eq!(found.display_text(), "github-work (bookmark)");
+    }
+}
@@ -0,0 +1,266 @@
+//! Identities by branch
+//!
+//! A branch rule gives the branches matching a pattern, e.g. `client-x/`,
+//! an identity of their own inside repositories that otherwise commit as
+//! another. Detection checks the current branch before anything else. To
+//! make git follow the rules too, applying them to a repository adds
+//! `includeIf "onbranch:<pattern>"` sections to its own config, each
+//! including a fragment with the identity's settings and the commit
+//! guard's expected identity and email, so a guarded repository expects
+//! the branch's identity while it is checked out. Rules are written from
+//! the least to the most specific, because git lets the last include win.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cli::{self, RunOptions};
+use crate::config::{BranchRule, Config, Identity};
+use crate::error::{CliError, Error};
+use crate::export;
+use crate::gitconfig;
+use crate::hooks;
+use crate::platform;
+
+/// The rule's pattern as git's `onbranch:` reads it
+pub fn git_pattern(branch: &str) -> String {
+    let branch = branch.trim();
+    if branch.ends_with('/') {
+        format!("{}**", branch)
+    } else {
+        branch.to_string()
+    }
+}
+
+/// Match `text` against a glob where `**` matches anything, `*` anything
+/// but `/` and `?` one character other than `/`
+pub fn wildmatch(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[u8], text: &[u8]) -> bool {
+        match pattern {
+            [] => text.is_empty(),
+            [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+            [b'*', rest @ ..] => {
+                let end = text.iter().position(|&c| c == b'/').unwrap_or(text.len());
+                (0..=end).any(|i| matches(rest, &text[i..]))
+            }
+            [b'?', rest @ ..] => matches!(text, [c, ..] if *c != b'/') && matches(rest, &text[1..]),
+            [p, rest @ ..] => text.first() == Some(p) && matches(rest, &text[1..]),
+        }
+    }
+    matches(pattern.as_bytes(), text.as_bytes())
+}
+
+/// The rules covering `repo` whose identity exists, least specific first:
+/// unscoped rules, then by directory depth and pattern length
+pub fn rules_for<'a>(config: &'a Config, repo: &Path) -> Vec<&'a BranchRule> {
+    let mut rules: Vec<(usize, &BranchRule)> = config
+        .settings
+        .branch_rules
+        .iter()
+        .filter(|rule| !rule.branch.trim().is_empty())
+        .filter(|rule| config.identities.contains_key(&rule.identity))
+        .filter_map(|rule| {
+            if rule.path.is_empty() {
+                return Some((0, rule));
+            }
+            let path = platform::expand_tilde(&rule.path);
+            repo.starts_with(&path)
+                .then(|| (path.components().count(), rule))
+        })
+        .collect();
+    rules.sort_by_key(|(depth, rule)| (*depth, rule.branch.trim().len()));
+    rules.into_iter().map(|(_, rule)| rule).collect()
+}
+
+/// The rule git would apply on `branch`: the last matching one
+pub fn rule_for<'a>(config: &'a Config, repo: &Path, branch: &str) -> Option<&'a BranchRule> {
+    rules_for(config, repo)
+        .into_iter()
+        .rev()
+        .find(|rule| wildmatch(&git_pattern(&rule.branch), branch))
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<String, CliError> {
+    let mut all = vec!["-C".to_string(), repo.to_string_lossy().into_owned()];
+    all.extend(cli::args(args));
+    let options = RunOptions {
+        timeout: Duration::from_secs(10),
+        retries: 0,
+        cancel: None,
+    };
+    cli::run_program("git", &all, &options)
+}
+
+/// The checked-out branch; None with a detached HEAD. Blocks
+pub fn current_branch(repo: &Path) -> Result<Option<String>, Error> {
+    match git(repo, &["symbolic-ref", "--short", "-q", "HEAD"]) {
+        Ok(out) => Ok(Some(out.trim().to_string()).filter(|b| !b.is_empty())),
+        // Exit status 1: HEAD isn't a branch
+        Err(CliError::NonZeroExit { code: Some(1), .. }) => Ok(None),
+        Err(e) => Err(Error::from_cli(e)),
+    }
+}
+
+/// Where the branch fragments are kept
+pub fn fragments_dir() -> Option<PathBuf> {
+    gitconfig::fragments_dir().map(|dir| dir.join("branch"))
+}
+
+/// The fragment for one identity: its settings and the guard's keys
+pub fn render_fragment(name: &str, identity: &Identity) -> String {
+    let guard = [
+        (hooks::IDENTITY_KEY.to_string(), name.to_string()),
+        (
+            hooks::EXPECTED_EMAIL_KEY.to_string(),
+            identity.email.clone(),
+        ),
+    ];
+    format!(
+        "{}{}",
+        gitconfig::render_fragment(name, identity),
+        gitconfig::render_settings(&guard)
+    )
+}
+
+/// Parse `git config --get-regexp '^includeif\.onbranch:.*\.path$'` into
+/// (section, included path)
+pub fn parse_includes(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|line| {
+            let (key, path) = line.trim().split_once(char::is_whitespace)?;
+            let condition = key.strip_prefix("includeif.")?.strip_suffix(".path")?;
+            Some((format!("includeIf.{}", condition), path.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Remove the `onbranch:` includes of our fragments from the repository's
+/// config; blocks
+pub fn clear(repo: &Path) -> Result<(), Error> {
+    let Some(dir) = fragments_dir() else {
+        return Ok(());
+    };
+    let out = match git(
+        repo,
+        &[
+            "config",
+            "--local",
+            "--get-regexp",
+            r"^includeif\.onbranch:.*\.path$",
+        ],
+    ) {
+        Ok(out) => out,
+        // Exit status 1: none
+        Err(CliError::NonZeroExit { code: Some(1), .. }) => return Ok(()),
+        Err(e) => return Err(Error::from_cli(e)),
+    };
+    for (section, path) in parse_includes(&out) {
+        if Path::new(&path).starts_with(&dir) {
+            git(repo, &["config", "--local", "--remove-section", &section])
+                .map_err(Error::from_cli)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the rules covering `repo` to its config, replacing the ones
+/// applied before, and return them; blocks
+pub fn apply(config: &Config, repo: &Path) -> Result<Vec<BranchRule>, Error> {
+    let dir = fragments_dir().ok_or_else(|| Error::InvalidOutput {
+        program: "config",
+        message: "No config directory".to_string(),
+    })?;
+    clear(repo)?;
+    let rules = rules_for(config, repo);
+    for rule in &rules {
+        let fragment = gitconfig::fragment_path(&dir, &rule.identity);
+        std::fs::create_dir_all(&dir)?;
+        export::write_private(
+            &fragment,
+            &render_fragment(&rule.identity, &config.identities[&rule.identity]),
+        )?;
+        let key = format!("includeIf.onbranch:{}.path", git_pattern(&rule.branch));
+        git(
+            repo,
+            &[
+                "config",
+                "--local",
+                "--add",
+                &key,
+                &fragment.to_string_lossy(),
+            ],
+        )
+        .map_err(Error::from_cli)?;
+    }
+    Ok(rules.into_iter().cloned().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+
+    #[test]
+    fn test_branch_patterns_and_rules() {
+        assert_eq!(git_pattern("client-x/"), "client-x/**");
+        assert!(wildmatch("client-x/**", "client-x/feature/login"));
+        assert!(wildmatch("client-x/*", "client-x/login"));
+        assert!(!wildmatch("client-x/*", "client-x/feature/login"));
+        assert!(wildmatch("release-?.*", "release-2.1"));
+        assert!(!wildmatch("client-x/**", "client-y/login"));
+        assert!(wildmatch("main", "main"));
+
+        let rule = |branch: &str, identity: &str, path: &str| BranchRule {
+            branch: branch.to_string(),
+            identity: identity.to_string(),
+            path: path.to_string(),
+        };
+        let config = ConfigBuilder::new()
+            .identity(
+                "github-client",
+                identity(
+                    "github",
+                    "github-client",
+                    "github.com",
+                    "Me",
+                    "me@client-x.com",
+                ),
+            )
+            .identity(
+                "github-work",
+                identity("github", "github-work", "github.com", "Me", "me@acme.dev"),
+            )
+            .settings(|settings| {
+                settings.branch_rules = vec![
+                    rule("client-x/", "github-client", ""),
+                    rule("client-x/internal-*", "github-work", "/src/api"),
+                    rule("other/", "missing", ""),
+                ]
+            })
+            .build();
+        let repo = Path::new("/src/api");
+        assert_eq!(rules_for(&config, repo).len(), 2);
+        assert_eq!(rules_for(&config, Path::new("/src/web")).len(), 1);
+        assert_eq!(
+            rule_for(&config, repo, "client-x/internal-tools").map(|r| r.identity.as_str()),
+            Some("github-work")
+        );
+        assert_eq!(
+            rule_for(&config, repo, "client-x/login").map(|r| r.identity.as_str()),
+            Some("github-client")
+        );
+        assert_eq!(rule_for(&config, repo, "other/x"), None);
+
+        let fragment = render_fragment("github-client", &config.identities["github-client"]);
+        assert!(fragment.contains("\temail = me@client-x.com\n"));
+        assert!(fragment.ends_with(
+            "[remote-juggler]\n\tidentity = github-client\n\texpectedEmail = me@client-x.com\n"
+        ));
+        assert_eq!(
+            parse_includes("includeif.onbranch:client-x/**.path /c/branch/a.gitconfig\n"),
+            vec![(
+                "includeIf.onbranch:client-x/**".to_string(),
+                "/c/branch/a.gitconfig".to_string()
+            )]
+        );
+    }
+}
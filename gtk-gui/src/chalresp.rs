@@ -0,0 +1,277 @@
+//! YubiKey challenge-response key store unlock
+//!
+//! A KDBX database can require a YubiKey HMAC-SHA1 challenge-response in
+//! addition to its master password, so the database can't be opened
+//! without the hardware key. The slot to use is remembered per database
+//! and handed to the CLI through `REMOTE_JUGGLER_KDBX_YUBIKEY`, which it
+//! passes on as keepassxc-cli's `--yubikey`.
+//!
+//! keepassxc-cli can't enroll a YubiKey in a database; that is done once in
+//! KeePassXC (Database Security, Challenge-Response). This module programs
+//! the slot and turns its use on for a database.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{self, RunOptions};
+use crate::cli_cache;
+use crate::error::Error;
+use crate::platform;
+
+/// Environment variable the CLI reads the slot from
+pub const SLOT_ENV: &str = "REMOTE_JUGGLER_KDBX_YUBIKEY";
+
+/// Slots on a YubiKey's OTP application
+pub const SLOTS: [u8; 2] = [1, 2];
+
+/// The slot KeePassXC suggests, since slot 1 usually holds Yubico OTP
+pub const DEFAULT_SLOT: u8 = 2;
+
+/// The slot at `index` in [`SLOTS`], as listed in a combo row
+pub fn slot_from_index(index: u32) -> u8 {
+    SLOTS.get(index as usize).copied().unwrap_or(DEFAULT_SLOT)
+}
+
+/// The ykman binary, overridable like the CLI's
+pub fn ykman_program() -> String {
+    std::env::var("REMOTE_JUGGLER_YKMAN_PATH").unwrap_or_else(|_| "ykman".to_string())
+}
+
+/// The challenge-response a database needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChallengeResponse {
+    pub slot: u8,
+    /// Pins a specific YubiKey when several are plugged in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<u32>,
+}
+
+impl ChallengeResponse {
+    /// The `--yubikey` value, `SLOT` or `SLOT:SERIAL`
+    pub fn env_value(&self) -> String {
+        match self.serial {
+            Some(serial) => format!("{}:{}", self.slot, serial),
+            None => self.slot.to_string(),
+        }
+    }
+}
+
+/// Challenge-response settings by database name, persisted locally
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChallengeStore {
+    #[serde(default)]
+    pub databases: BTreeMap<String, ChallengeResponse>,
+}
+
+impl ChallengeStore {
+    pub fn path() -> Option<PathBuf> {
+        platform::data_dir().map(|d| d.join("challenge-response.json"))
+    }
+
+    pub fn load() -> Self {
+        platform::load_json_state(Self::path())
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        platform::save_json_state(Self::path(), self)
+    }
+}
+
+/// Point the CLI at the challenge-response configured for `database`
+pub fn apply(database: &str) {
+    let store = ChallengeStore::load();
+    let value = store.databases.get(database).map(|c| c.env_value());
+    if cli::session_env_value(SLOT_ENV) != value {
+        cli::set_session_env(SLOT_ENV, value.as_deref());
+        cli_cache::global().invalidate();
+    }
+}
+
+/// Require (or with `None`, stop requiring) a challenge-response for
+/// `database`, and apply it
+pub fn configure(database: &str, response: Option<ChallengeResponse>) -> Result<(), Error> {
+    let mut store = ChallengeStore::load();
+    match response {
+        Some(response) => store.databases.insert(database.to_string(), response),
+        None => store.databases.remove(database),
+    };
+    store.save()?;
+    apply(database);
+    Ok(())
+}
+
+/// What `ykman` reports about the inserted YubiKey
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct YubiKeyInfo {
+    pub serial: Option<u32>,
+    /// Whether slot 1 and slot 2 hold a configuration
+    pub programmed: [bool; 2],
+}
+
+impl YubiKeyInfo {
+    pub fn is_programmed(&self, slot: u8) -> bool {
+        SLOTS
+            .iter()
+            .position(|&s| s == slot)
+            .is_some_and(|i| self.programmed[i])
+    }
+
+    /// e.g. "YubiKey 12345678: slot 1 programmed, slot 2 empty"
+    pub fn summary(&self) -> String {
+        let slots: Vec<String> = SLOTS
+            .iter()
+            .map(|&slot| {
+                let state = if self.is_programmed(slot) {
+                    "programmed"
+                } else {
+                    "empty"
+                };
+                format!("slot {} {}", slot, state)
+            })
+            .collect();
+        match self.serial {
+            Some(serial) => format!("YubiKey {}: {}", serial, slots.join(", ")),
+            None => format!("YubiKey: {}", slots.join(", ")),
+        }
+    }
+}
+
+/// Parse the serial from `ykman info`
+pub fn parse_serial(info: &str) -> Option<u32> {
+    info.lines()
+        .find_map(|line| line.trim().strip_prefix("Serial number:"))
+        .and_then(|serial| serial.trim().parse().ok())
+}
+
+/// Parse slot states from `ykman otp info`
+pub fn parse_otp_info(info: &str) -> [bool; 2] {
+    let mut programmed = [false; 2];
+    for line in info.lines() {
+        let Some((name, state)) = line.split_once(':') else {
+            continue;
+        };
+        let slot = match name.trim() {
+            "Slot 1" => 0,
+            "Slot 2" => 1,
+            _ => continue,
+        };
+        programmed[slot] = state.trim() == "programmed";
+    }
+    programmed
+}
+
+fn ykman_options() -> RunOptions {
+    RunOptions {
+        timeout: Duration::from_secs(15),
+        retries: 0,
+        cancel: None,
+    }
+}
+
+/// Read the inserted YubiKey's serial and slot states; blocks
+pub fn probe() -> Result<YubiKeyInfo, Error> {
+    let program = ykman_program();
+    let options = ykman_options();
+    let info =
+        cli::run_program(&program, &cli::args(&["info"]), &options).map_err(Error::from_cli)?;
+    let otp = cli::run_program(&program, &cli::args(&["otp", "info"]), &options)
+        .map_err(Error::from_cli)?;
+    Ok(YubiKeyInfo {
+        serial: parse_serial(&info),
+        programmed: parse_otp_info(&otp),
+    })
+}
+
+/// `ykman` arguments that program `slot` with a new random HMAC-SHA1 secret
+pub fn program_args(slot: u8, touch: bool) -> Vec<String> {
+    let mut args = cli::args(&["otp", "chalresp", "--generate", "--force"]);
+    if touch {
+        args.push("--touch".to_string());
+    }
+    args.push(slot.to_string());
+    args
+}
+
+/// Program `slot` for challenge-response, replacing what it held; blocks
+///
+/// Databases already enrolled with the slot's old secret can no longer be
+/// opened with it.
+pub fn program_slot(slot: u8, touch: bool) -> Result<(), Error> {
+    cli::run_program(
+        &ykman_program(),
+        &program_args(slot, touch),
+        &ykman_options(),
+    )
+    .map(|_| ())
+    .map_err(Error::from_cli)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_value() {
+        let slot_only = ChallengeResponse {
+            slot: 2,
+            serial: None,
+        };
+        assert_eq!(slot_only.env_value(), "2");
+        let pinned = ChallengeResponse {
+            slot: 1,
+            serial: Some(12345678),
+        };
+        assert_eq!(pinned.env_value(), "1:12345678");
+    }
+
+    #[test]
+    fn test_parse_ykman_output() {
+        let info = "Device type: YubiKey 5 NFC\nSerial number: 12345678\nFirmware version: 5.4.3\n";
+        assert_eq!(parse_serial(info), Some(12345678));
+        assert_eq!(parse_serial("Device type: YubiKey 5\n"), None);
+
+        let otp = "Slot 1: programmed\nSlot 2: empty\n";
+        let key = YubiKeyInfo {
+            serial: parse_serial(info),
+            programmed: parse_otp_info(otp),
+        };
+        assert!(key.is_programmed(1));
+        assert!(!key.is_programmed(2));
+        assert!(!key.is_programmed(3));
+        assert_eq!(
+            key.summary(),
+            "YubiKey 12345678: slot 1 programmed, slot 2 empty"
+        );
+    }
+
+    #[test]
+    fn test_program_args() {
+        assert_eq!(
+            program_args(2, true),
+            vec!["otp", "chalresp", "--generate", "--force", "--touch", "2"]
+        );
+        assert_eq!(
+            program_args(1, false),
+            vec!["otp", "chalresp", "--generate", "--force", "1"]
+        );
+    }
+
+    #[test]
+    fn test_store_round_trip() {
+        let mut store = ChallengeStore::default();
+        store.databases.insert(
+            "work".to_string(),
+            ChallengeResponse {
+                slot: 2,
+                serial: None,
+            },
+        );
+        let json = serde_json::to_string(&store).unwrap();
+        assert!(!json.contains("serial"));
+        let back: ChallengeStore = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, store);
+    }
+}
@@ -0,0 +1,557 @@
+//! CI secrets kept in sync with the key store
+//!
+//! `settings.ciSecrets` maps key store entries to GitHub Actions secrets
+//! and GitLab CI/CD variables, on a repository or project, or on an
+//! organization or group. A sync plans before it writes: each secret is
+//! created, updated, left alone, or reported as drifted when it was
+//! changed on the provider since it was last pushed from here. GitLab
+//! returns variable values, which are compared directly; GitHub never
+//! returns a secret, so what was pushed is remembered as a salted SHA-256
+//! hash (never plaintext) under the local data dir, with the time the
+//! provider reported for it. GitHub secrets are sealed to the repository's
+//! or organization's public key before they leave the machine.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use base64::Engine;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::cli;
+use crate::config::{CiSecret, Config};
+use crate::error::Error;
+use crate::platform;
+use crate::provider_api;
+use crate::provider_hosts;
+use crate::pubkeys::{self, Provider};
+
+/// What applying the plan does to a secret
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    /// Changed on the provider since it was last pushed; applying
+    /// overwrites it
+    Drifted,
+    /// Not on the provider yet
+    Create,
+    /// The key store value differs from the provider's
+    Update,
+    /// Already in sync
+    Unchanged,
+}
+
+impl Change {
+    pub fn display_text(&self) -> &'static str {
+        match self {
+            Self::Drifted => "changed on the provider",
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Unchanged => "in sync",
+        }
+    }
+}
+
+/// What a sync last pushed for one secret
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Synced {
+    pub hash: String,
+    /// The provider's update time after the push; GitHub only
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub updated_at: String,
+}
+
+/// The last pushed state of every secret, by [`key`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncState {
+    pub secrets: BTreeMap<String, Synced>,
+}
+
+impl SyncState {
+    pub fn path() -> Option<PathBuf> {
+        platform::data_dir().map(|d| d.join("ci-secrets.json"))
+    }
+
+    /// The pushed state; an unreadable file is an error rather than empty,
+    /// so a push doesn't drop what the others recorded
+    pub fn load() -> Result<Self, Error> {
+        Ok(platform::try_load_json_state(Self::path())?)
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        Ok(platform::save_json_state(Self::path(), self)?)
+    }
+}
+
+/// Where a secret lives, e.g. "github.com:acme/web:NPM_TOKEN"
+pub fn key(hostname: &str, secret: &CiSecret) -> String {
+    let target = if secret.organization {
+        format!("org/{}", secret.target)
+    } else {
+        secret.target.clone()
+    };
+    format!("{}:{}:{}", hostname.to_lowercase(), target, secret.name)
+}
+
+/// A value's hash, salted with where it is kept
+pub fn hash_value(key: &str, value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(key.as_bytes());
+    hasher.update([0]);
+    hasher.update(value.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// What the provider has for a secret
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Remote {
+    pub updated_at: String,
+    /// GitLab only
+    pub value: Option<String>,
+}
+
+/// Decide what a sync does, from the hash of the key store value, the
+/// provider's secret if it has one, and what was last pushed
+pub fn classify(
+    key: &str,
+    local_hash: &str,
+    remote: Option<&Remote>,
+    last: Option<&Synced>,
+) -> Change {
+    let Some(remote) = remote else {
+        return Change::Create;
+    };
+    let remote_hash = remote.value.as_deref().map(|value| hash_value(key, value));
+    if remote_hash.as_deref() == Some(local_hash) {
+        return Change::Unchanged;
+    }
+    let Some(last) = last else {
+        return Change::Update;
+    };
+    let drifted = match &remote_hash {
+        Some(hash) => *hash != last.hash,
+        None => remote.updated_at != last.updated_at,
+    };
+    if drifted {
+        Change::Drifted
+    } else if remote_hash.is_none() && last.hash == local_hash {
+        Change::Unchanged
+    } else {
+        Change::Update
+    }
+}
+
+/// Seal a value to a base64 X25519 public key, as GitHub expects secrets
+pub fn seal(public_key: &str, value: &str) -> Result<String, Error> {
+    let engine = base64::engine::general_purpose::STANDARD;
+    let invalid = |message: &str| Error::InvalidOutput {
+        program: "GitHub",
+        message: message.to_string(),
+    };
+    let bytes: [u8; 32] = engine
+        .decode(public_key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| invalid("the secrets public key isn't an X25519 key"))?;
+    let sealed = crypto_box::PublicKey::from(bytes)
+        .seal(&mut crypto_box::aead::OsRng, value.as_bytes())
+        .map_err(|_| invalid("sealing the secret failed"))?;
+    Ok(engine.encode(sealed))
+}
+
+/// The secrets endpoint for a secret's target, relative to the API root
+pub fn secrets_path(provider: Provider, secret: &CiSecret) -> Result<String, Error> {
+    let target = secret.target.trim().trim_matches('/');
+    match (provider, secret.organization) {
+        (Provider::GitHub, false) => Ok(format!("/repos/{}/actions/secrets", target)),
+        (Provider::GitHub, true) => Ok(format!("/orgs/{}/actions/secrets", target)),
+        (Provider::GitLab, false) => Ok(format!(
+            "/projects/{}/variables",
+            utf8_percent_encode(target, NON_ALPHANUMERIC)
+        )),
+        (Provider::GitLab, true) => Ok(format!(
+            "/groups/{}/variables",
+            utf8_percent_encode(target, NON_ALPHANUMERIC)
+        )),
+        _ => Err(Error::InvalidOutput {
+            program: provider.display_name(),
+            message: "CI secrets can only be synced to GitHub and GitLab".to_string(),
+        }),
+    }
+}
+
+/// Whether GitLab can mask a value in job logs
+pub fn maskable(value: &str) -> bool {
+    value.len() >= 8
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "@:.~+-_/=".contains(c))
+}
+
+/// A secret's provider, API client and location; blocks on the token
+struct Target {
+    provider: Provider,
+    hostname: String,
+    client: provider_api::Client,
+    url: String,
+}
+
+impl Target {
+    fn new(config: &Config, secret: &CiSecret) -> Result<Self, Error> {
+        let identity =
+            config
+                .identities
+                .get(&secret.identity)
+                .ok_or_else(|| Error::InvalidOutput {
+                    program: "config",
+                    message: format!("no identity named {}", secret.identity),
+                })?;
+        let provider = Provider::for_identity(identity).ok_or_else(|| Error::InvalidOutput {
+            program: "config",
+            message: format!("{} has no CI secrets API", identity.provider),
+        })?;
+        let base =
+            provider_hosts::api_base(&provider_hosts::configured(), provider, &identity.hostname);
+        let url = format!("{}{}", base, secrets_path(provider, secret)?);
+        let token = pubkeys::find_token(&secret.identity, identity, provider)?;
+        Ok(Self {
+            provider,
+            hostname: identity.hostname.clone(),
+            client: provider_api::Client::new(provider, &base, Some(&token)),
+            url,
+        })
+    }
+
+    fn secret_url(&self, name: &str) -> String {
+        format!(
+            "{}/{}",
+            self.url,
+            utf8_percent_encode(name, NON_ALPHANUMERIC)
+        )
+    }
+
+    /// The provider's secret; None if it has none by that name
+    fn remote(&self, name: &str) -> Result<Option<Remote>, Error> {
+        // GitLab answers with the value, which mustn't reach the cache
+        match self.client.get_uncached(&self.secret_url(name)) {
+            Ok(body) => Ok(Some(Remote {
+                updated_at: body["updated_at"].as_str().unwrap_or_default().to_string(),
+                value: body["value"].as_str().map(str::to_string),
+            })),
+            Err(Error::Http { status: 404, .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn push(&self, secret: &CiSecret, value: &str, exists: bool) -> Result<(), Error> {
+        let url = self.secret_url(&secret.name);
+        match self.provider {
+            Provider::GitHub => {
+                let public_key = self.client.get_json(&format!("{}/public-key", self.url))?;
+                let mut body = json!({
+                    "encrypted_value": seal(public_key["key"].as_str().unwrap_or_default(), value)?,
+                    "key_id": public_key["key_id"],
+                });
+                if secret.organization {
+                    // Keep the repositories an existing secret is shared with
+                    let visibility = exists
+                        .then(|| self.client.get_uncached(&url).ok())
+                        .flatten()
+                        .and_then(|current| current["visibility"].as_str().map(str::to_string))
+                        .unwrap_or_else(|| "private".to_string());
+                    body["visibility"] = Value::String(visibility);
+                }
+                self.client.send("PUT", &url, Some(&body))?;
+            }
+            _ if exists => {
+                self.client
+                    .send("PUT", &url, Some(&json!({ "value": value })))?;
+            }
+            _ => {
+                self.client.send(
+                    "POST",
+                    &self.url,
+                    Some(&json!({
+                        "key": secret.name,
+                        "value": value,
+                        "masked": maskable(value),
+                    })),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The key store value for a secret; blocks
+fn read_entry(secret: &CiSecret) -> Result<String, Error> {
+    let value = cli::run(&cli::args(&["keys", "get", &secret.entry])).map_err(Error::from_cli)?;
+    let value = value.trim().to_string();
+    if value.is_empty() {
+        return Err(Error::InvalidOutput {
+            program: "key store",
+            message: format!("{} is empty", secret.entry),
+        });
+    }
+    Ok(value)
+}
+
+/// A configured secret and what a sync would do to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Planned {
+    pub secret: CiSecret,
+    pub change: Change,
+}
+
+impl Planned {
+    /// e.g. "NPM_TOKEN → acme/web: update"
+    pub fn display_text(&self) -> String {
+        format!(
+            "{} → {}{}: {}",
+            self.secret.name,
+            if self.secret.organization { "org " } else { "" },
+            self.secret.target,
+            self.change.display_text()
+        )
+    }
+}
+
+/// What a sync would do, before anything is written
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub planned: Vec<Planned>,
+    /// Secrets whose entry or provider couldn't be read
+    pub failed: Vec<(CiSecret, Error)>,
+}
+
+impl Plan {
+    /// e.g. "1 to create, 1 drifted, 2 in sync"
+    pub fn summary(&self) -> String {
+        let count = |change: Change| self.planned.iter().filter(|p| p.change == change).count();
+        let mut parts: Vec<String> = [
+            (Change::Create, "to create"),
+            (Change::Update, "to update"),
+            (Change::Drifted, "drifted"),
+            (Change::Unchanged, "in sync"),
+        ]
+        .into_iter()
+        .filter(|(change, _)| count(*change) > 0)
+        .map(|(change, text)| format!("{} {}", count(change), text))
+        .collect();
+        if !self.failed.is_empty() {
+            parts.push(format!("{} unreadable", self.failed.len()));
+        }
+        if parts.is_empty() {
+            return "No CI secrets configured".to_string();
+        }
+        parts.join(", ")
+    }
+
+    /// Secrets applying would write
+    pub fn changes(&self) -> Vec<CiSecret> {
+        self.planned
+            .iter()
+            .filter(|p| p.change != Change::Unchanged)
+            .map(|p| p.secret.clone())
+            .collect()
+    }
+}
+
+fn plan_one(config: &Config, state: &SyncState, secret: &CiSecret) -> Result<Change, Error> {
+    let value = read_entry(secret)?;
+    let target = Target::new(config, secret)?;
+    let key = key(&target.hostname, secret);
+    let remote = target.remote(&secret.name)?;
+    Ok(classify(
+        &key,
+        &hash_value(&key, &value),
+        remote.as_ref(),
+        state.secrets.get(&key),
+    ))
+}
+
+/// Compare every configured secret with its provider, drifted first;
+/// nothing is written. Blocks
+pub fn plan(config: &Config) -> Plan {
+    // Without the state every secret just looks changed
+    let state = SyncState::load().unwrap_or_else(|e| {
+        tracing::warn!("{}", e);
+        SyncState::default()
+    });
+    let mut plan = Plan::default();
+    for secret in &config.settings.ci_secrets {
+        match plan_one(config, &state, secret) {
+            Ok(change) => plan.planned.push(Planned {
+                secret: secret.clone(),
+                change,
+            }),
+            Err(e) => plan.failed.push((secret.clone(), e)),
+        }
+    }
+    plan.planned.sort_by_key(|p| p.change);
+    plan
+}
+
+/// Push a secret's key store value to its provider and remember what was
+/// pushed; blocks
+pub fn push(config: &Config, secret: &CiSecret) -> Result<(), Error> {
+    let value = read_entry(secret)?;
+    let target = Target::new(config, secret)?;
+    let exists = target.remote(&secret.name)?.is_some();
+    target.push(secret, &value, exists)?;
+    let key = key(&target.hostname, secret);
+    let updated_at = match target.provider {
+        Provider::GitHub => target
+            .remote(&secret.name)?
+            .map(|remote| remote.updated_at)
+            .unwrap_or_default(),
+        _ => String::new(),
+    };
+    let mut state = SyncState::load()?;
+    state.secrets.insert(
+        key.clone(),
+        Synced {
+            hash: hash_value(&key, &value),
+            updated_at,
+        },
+    );
+    state.save()
+}
+
+/// Push the secrets a plan would change; returns how many were written
+/// and the failures. Blocks
+pub fn apply(config: &Config, secrets: &[CiSecret]) -> (usize, Vec<(CiSecret, Error)>) {
+    let mut pushed = 0;
+    let mut failed = Vec::new();
+    for secret in secrets {
+        match push(config, secret) {
+            Ok(()) => pushed += 1,
+            Err(e) => failed.push((secret.clone(), e)),
+        }
+    }
+    (pushed, failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_classification_and_sealing() {
+        let secret = CiSecret {
+            entry: "RemoteJuggler/CI/NPM_TOKEN".to_string(),
+            name: "NPM_TOKEN".to_string(),
+            target: "acme/web".to_string(),
+            identity: "work".to_string(),
+            organization: false,
+        };
+        let key = key("GitHub.com", &secret);
+        assert_eq!(key, "github.com:acme/web:NPM_TOKEN");
+        let local = hash_value(&key, "npm_new");
+        let pushed = |value: &str, at: &str| Synced {
+            hash: hash_value(&key, value),
+            updated_at: at.to_string(),
+        };
+        let github = |at: &str| Remote {
+            updated_at: at.to_string(),
+            value: None,
+        };
+
+        // GitHub: only the hash and update time to go on
+        assert_eq!(classify(&key, &local, None, None), Change::Create);
+        let t1 = "2026-01-01T00:00:00Z";
+        assert_eq!(
+            classify(&key, &local, Some(&github(t1)), None),
+            Change::Update
+        );
+        assert_eq!(
+            classify(
+                &key,
+                &local,
+                Some(&github(t1)),
+                Some(&pushed("npm_new", t1))
+            ),
+            Change::Unchanged
+        );
+        assert_eq!(
+            classify(
+                &key,
+                &local,
+                Some(&github(t1)),
+                Some(&pushed("npm_old", t1))
+            ),
+            Change::Update
+        );
+        assert_eq!(
+            classify(
+                &key,
+                &local,
+                Some(&github("2026-02-01T00:00:00Z")),
+                Some(&pushed("npm_new", t1))
+            ),
+            Change::Drifted
+        );
+
+        // GitLab: values compare directly
+        let gitlab = |value: &str| Remote {
+            updated_at: String::new(),
+            value: Some(value.to_string()),
+        };
+        assert_eq!(
+            classify(&key, &local, Some(&gitlab("npm_new")), None),
+            Change::Unchanged
+        );
+        assert_eq!(
+            classify(
+                &key,
+                &local,
+                Some(&gitlab("npm_old")),
+                Some(&pushed("npm_old", ""))
+            ),
+            Change::Update
+        );
+        assert_eq!(
+            classify(
+                &key,
+                &local,
+                Some(&gitlab("edited")),
+                Some(&pushed("npm_old", ""))
+            ),
+            Change::Drifted
+        );
+
+        let group = CiSecret {
+            organization: true,
+            target: "acme/platform".to_string(),
+            ..secret.clone()
+        };
+        assert_eq!(
+            secrets_path(Provider::GitLab, &group).unwrap(),
+            "/groups/acme%2Fplatform/variables"
+        );
+        assert_eq!(
+            secrets_path(Provider::GitHub, &secret).unwrap(),
+            "/repos/acme/web/actions/secrets"
+        );
+        assert!(secrets_path(Provider::Gitea, &secret).is_err());
+        assert!(maskable("glpat-abcdefgh"));
+        assert!(!maskable("short"));
+
+        // Sealed boxes open with the matching secret key
+        let secret_key = crypto_box::SecretKey::generate(&mut crypto_box::aead::OsRng);
+        let public_key =
+            base64::engine::general_purpose::STANDARD.encode(secret_key.public_key().as_bytes());
+        let sealed = base64::engine::general_purpose::STANDARD
+            .decode(seal(&public_key, "npm_new").unwrap())
+            .unwrap();
+        assert_eq!(secret_key.unseal(&sealed).unwrap(), b"npm_new");
+        assert!(seal("bm90IGEga2V5", "x").is_err());
+    }
+}
@@ -0,0 +1,873 @@
+//! remote-juggler CLI invocation
+//!
+//! Async wrapper around the `remote-juggler` binary built on async-process,
+//! streaming stdout lines as they arrive. The GTK frontend polls these
+//! futures on the GLib main loop; the C ABI in `ffi.rs` uses the blocking
+//! variants.
+//!
+//! Every invocation has a timeout (the CLI can otherwise block forever on a
+//! pinentry prompt), can be cancelled through a `CancelToken`, and read-only
+//! commands are retried with backoff when they fail transiently.
+//!
+//! Inside a Flatpak sandbox the CLI, and any other program the sandbox
+//! doesn't ship, runs on the host through `flatpak-spawn --host`. Without
+//! permission to do that, programs are tried in the sandbox.
+
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use async_io::Timer;
+use async_process::{Command, Stdio};
+use futures_lite::io::BufReader;
+use futures_lite::{future, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, StreamExt};
+use tracing::Instrument;
+
+use crate::autolock;
+use crate::cli_cache;
+use crate::error::CliError;
+use crate::event_hooks::{self, Event};
+use crate::metrics;
+use crate::platform;
+
+/// Name of the RemoteJuggler CLI binary, resolved via PATH
+pub const CLI_PROGRAM: &str = "remote-juggler";
+
+/// Runs commands on the host from inside a Flatpak sandbox
+pub const FLATPAK_SPAWN: &str = "flatpak-spawn";
+
+/// Exports the `KEY=VALUE` lines read from stdin up to an empty one, then
+/// runs the command. Variables reach host commands this way rather than as
+/// `--env=` arguments so the master password stays out of process listings
+const HOST_ENV_SCRIPT: &str =
+    r#"while IFS= read -r line && [ -n "$line" ]; do export "$line"; done; exec "$@""#;
+
+/// How often a running child is polled for exit, timeout, or cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long to keep reading stderr after the child exits, in case a
+/// process it left running still holds the pipe open
+const STDERR_GRACE: Duration = Duration::from_millis(500);
+
+/// Base delay before the first retry; doubles on each attempt
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Shared flag used to cancel a running invocation from another thread
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation; the child process is killed at the next poll
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Per-invocation execution options
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Kill the command if it runs longer than this
+    pub timeout: Duration,
+    /// Extra attempts after a transient failure
+    pub retries: u32,
+    /// Optional cancellation token
+    pub cancel: Option<CancelToken>,
+}
+
+impl RunOptions {
+    /// Default options for a command: its timeout, and retries only for reads
+    pub fn for_args(args: &[String]) -> Self {
+        Self {
+            timeout: default_timeout(args),
+            retries: if cli_cache::is_mutating(args) { 0 } else { 2 },
+            cancel: None,
+        }
+    }
+
+    /// Attach a cancellation token
+    pub fn with_cancel(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+}
+
+/// Timeout for a command, based on how long it legitimately takes
+///
+/// Commands that may prompt for a PIN get enough time for a human to answer.
+pub fn default_timeout(args: &[String]) -> Duration {
+    let parts: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let secs = match parts.as_slice() {
+        ["keys", "crawl" | "discover" | "ingest" | "import" | "export", ..] => 300,
+        ["keys", "sops-ingest" | "sops-sync" | "sops-export", ..] => 300,
+        ["switch" | "to", ..] | ["pin", ..] | ["keys", "init"] => 120,
+        // Unlocks two databases, each with its own key derivation
+        ["keys", "sync" | "merge", ..] => 120,
+        ["keys", ..] => 60,
+        _ => 30,
+    };
+    Duration::from_secs(secs)
+}
+
+/// Returns whether a failure looks transient and is worth retrying
+pub fn is_transient(stderr: &str) -> bool {
+    let lower = stderr.to_lowercase();
+    [
+        "resource temporarily unavailable",
+        "try again",
+        "database is locked",
+        "connection timed out",
+        "connection reset",
+        "temporary failure",
+    ]
+    .iter()
+    .any(|needle| lower.contains(needle))
+}
+
+/// The failure a CLI command reported on stdout, if any
+///
+/// Most `keys` subcommands print `[ERROR] message` and still exit 0, so
+/// callers that need to know whether a write happened check the output.
+pub fn reported_error(stdout: &str) -> Result<(), CliError> {
+    let Some(line) = stdout.lines().find(|l| l.contains("[ERROR]")) else {
+        return Ok(());
+    };
+    let message = line.split_once("[ERROR]").map_or(line, |(_, rest)| rest);
+    Err(CliError::NonZeroExit {
+        code: None,
+        stderr: strip_ansi(message).trim().to_string(),
+    })
+}
+
+/// Remove ANSI color escapes (`ESC [ ... m`) from CLI output
+fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip through the final byte of the escape sequence
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Run a remote-juggler CLI command with arbitrary args
+///
+/// Returns stdout on success, or stderr if the command exits non-zero.
+pub fn run(args: &[String]) -> Result<String, CliError> {
+    run_with_options(args, &[], &RunOptions::for_args(args))
+}
+
+/// Run a CLI command, serving idempotent reads from the result cache
+///
+/// Mutating commands always run and invalidate the cache, whether or not
+/// they succeed (a failed write may still have partially applied).
+pub fn run_cached(args: &[String]) -> Result<String, CliError> {
+    run_cached_with_options(args, &RunOptions::for_args(args))
+}
+
+/// `run_cached` with explicit options (e.g. a cancellation token)
+pub fn run_cached_with_options(args: &[String], options: &RunOptions) -> Result<String, CliError> {
+    async_io::block_on(run_cached_async(args, options))
+}
+
+/// Run a remote-juggler CLI command with extra environment variables
+pub fn run_with_env(args: &[String], env: &[(&str, &str)]) -> Result<String, CliError> {
+    run_with_options(args, env, &RunOptions::for_args(args))
+}
+
+/// Run a CLI command with timeout, cancellation, and bounded retry
+pub fn run_with_options(
+    args: &[String],
+    env: &[(&str, &str)],
+    options: &RunOptions,
+) -> Result<String, CliError> {
+    async_io::block_on(run_streaming(args, env, options, |_| {}))
+}
+
+/// Async variant of `run_cached_with_options`
+pub async fn run_cached_async(args: &[String], options: &RunOptions) -> Result<String, CliError> {
+    let cache = cli_cache::global();
+    let cacheable = cli_cache::ttl_for(args).is_some();
+    if let Some(stdout) = cache.get(args) {
+        metrics::record_cache("cli", true);
+        return Ok(stdout);
+    }
+    if cacheable {
+        metrics::record_cache("cli", false);
+    }
+
+    let result = run_streaming(args, &[], options, |_| {}).await;
+    if cli_cache::is_mutating(args) {
+        cache.invalidate();
+    } else if let Ok(ref stdout) = result {
+        cache.insert(args, stdout);
+    }
+    result
+}
+
+fn session_env() -> &'static Mutex<Vec<(String, String)>> {
+    static SESSION_ENV: OnceLock<Mutex<Vec<(String, String)>>> = OnceLock::new();
+    SESSION_ENV.get_or_init(Mutex::default)
+}
+
+/// Set (or with `None`, remove) a variable passed to every CLI invocation
+/// for the rest of the session, e.g. the key store master password
+pub fn set_session_env(key: &str, value: Option<&str>) {
+    if let Ok(mut env) = session_env().lock() {
+        env.retain(|(k, _)| k != key);
+        if let Some(value) = value {
+            env.push((key.to_string(), value.to_string()));
+        }
+    }
+}
+
+/// Returns true if a session variable is set
+pub fn has_session_env(key: &str) -> bool {
+    session_env_value(key).is_some()
+}
+
+/// The value of a session variable
+pub fn session_env_value(key: &str) -> Option<String> {
+    let env = session_env().lock().ok()?;
+    env.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone())
+}
+
+/// Session variables followed by per-call ones, which take precedence
+fn with_session_env(env: &[(&str, &str)]) -> Vec<(String, String)> {
+    let mut merged = session_env()
+        .lock()
+        .map(|session| session.clone())
+        .unwrap_or_default();
+    merged.extend(env.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+    merged
+}
+
+/// Run a remote-juggler CLI command, calling `on_line` for each stdout line
+/// as it arrives
+///
+/// The future can be polled from any executor (including the GLib main
+/// loop), so `on_line` may update widgets directly. The full stdout is also
+/// returned on success.
+pub async fn run_streaming<F>(
+    args: &[String],
+    env: &[(&str, &str)],
+    options: &RunOptions,
+    on_line: F,
+) -> Result<String, CliError>
+where
+    F: FnMut(&str),
+{
+    run_cli(args, env, None, options, on_line).await
+}
+
+/// Store `value` at key store entry `path`, writing it to the CLI's stdin
+/// so it never appears on a command line; blocks
+pub fn store_secret(path: &str, value: &str) -> Result<(), CliError> {
+    let args = vec!["keys".to_string(), "store".to_string(), path.to_string()];
+    let input = format!("{}\n", value);
+    let result = async_io::block_on(run_cli(
+        &args,
+        &[],
+        Some(&input),
+        &RunOptions::for_args(&args),
+        |_| {},
+    ));
+    cli_cache::global().invalidate();
+    reported_error(&result?)
+}
+
+/// `run_streaming` with optional stdin input
+async fn run_cli<F>(
+    args: &[String],
+    env: &[(&str, &str)],
+    input: Option<&str>,
+    options: &RunOptions,
+    mut on_line: F,
+) -> Result<String, CliError>
+where
+    F: FnMut(&str),
+{
+    if autolock::is_locked() && autolock::needs_unlock(args) {
+        return Err(CliError::Locked);
+    }
+    let merged = with_session_env(env);
+    let env: Vec<(&str, &str)> = merged
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    let command = metrics::command_label(args);
+    let span = tracing::info_span!(
+        "cli",
+        otel.name = %format!("remote-juggler {}", command),
+        command = %command,
+        error = tracing::field::Empty,
+    );
+    let started = Instant::now();
+    let result = run_program_inner(CLI_PROGRAM, args, &env, input, options, &mut on_line)
+        .instrument(span.clone())
+        .await;
+    metrics::record_cli(args, started.elapsed(), result.is_ok());
+    match (&result, args) {
+        (Err(e), _) => {
+            span.record("error", tracing::field::display(e));
+        }
+        (Ok(_), [command, identity, ..]) if command == "switch" => {
+            event_hooks::emit(Event::IdentitySwitched {
+                identity: identity.clone(),
+            });
+        }
+        (Ok(_), [keys, get, name, ..]) if keys == "keys" && get == "get" => {
+            event_hooks::emit(Event::SecretAccessed {
+                name: name.clone(),
+                source: "KDBX".to_string(),
+            });
+        }
+        _ => {}
+    }
+    result
+}
+
+/// Run an arbitrary program with the same timeout/cancel/retry semantics
+pub async fn run_program_streaming<F>(
+    program: &str,
+    args: &[String],
+    env: &[(&str, &str)],
+    options: &RunOptions,
+    mut on_line: F,
+) -> Result<String, CliError>
+where
+    F: FnMut(&str),
+{
+    run_program_inner(program, args, env, None, options, &mut on_line).await
+}
+
+/// Run a program, writing `input` to its stdin (e.g. a secret for
+/// `pass insert`) instead of passing it on the command line
+pub fn run_program_with_input(
+    program: &str,
+    args: &[String],
+    env: &[(&str, &str)],
+    input: &str,
+    options: &RunOptions,
+) -> Result<String, CliError> {
+    async_io::block_on(run_program_inner(
+        program,
+        args,
+        env,
+        Some(input),
+        options,
+        &mut |_| {},
+    ))
+}
+
+async fn run_program_inner<F>(
+    program: &str,
+    args: &[String],
+    env: &[(&str, &str)],
+    input: Option<&str>,
+    options: &RunOptions,
+    on_line: &mut F,
+) -> Result<String, CliError>
+where
+    F: FnMut(&str),
+{
+    let mut attempt = 0;
+    loop {
+        let result = run_once(program, args, env, input, options, on_line).await;
+        match result {
+            Err(CliError::NonZeroExit { ref stderr, .. })
+                if attempt < options.retries && is_transient(stderr) =>
+            {
+                let delay = RETRY_BACKOFF * 2u32.pow(attempt);
+                tracing::debug!(
+                    "Transient failure running {} {:?}, retrying in {:?}",
+                    program,
+                    args,
+                    delay
+                );
+                if sleep_unless_cancelled(delay, options.cancel.as_ref()).await {
+                    return Err(CliError::Cancelled);
+                }
+                attempt += 1;
+            }
+            other => return other,
+        }
+    }
+}
+
+/// Synchronous variant of `run_program_streaming` without line callbacks
+pub fn run_program(
+    program: &str,
+    args: &[String],
+    options: &RunOptions,
+) -> Result<String, CliError> {
+    async_io::block_on(run_program_streaming(program, args, &[], options, |_| {}))
+}
+
+/// Sleep for `delay`, returning true early if cancellation was requested
+async fn sleep_unless_cancelled(delay: Duration, cancel: Option<&CancelToken>) -> bool {
+    let deadline = Instant::now() + delay;
+    while Instant::now() < deadline {
+        if cancel.is_some_and(|c| c.is_cancelled()) {
+            return true;
+        }
+        Timer::after(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now()))).await;
+    }
+    false
+}
+
+/// What woke the supervision loop in `run_once`
+enum Wake {
+    Line(Option<io::Result<String>>),
+    Tick,
+}
+
+async fn run_once<F>(
+    program: &str,
+    args: &[String],
+    env: &[(&str, &str)],
+    input: Option<&str>,
+    options: &RunOptions,
+    on_line: &mut F,
+) -> Result<String, CliError>
+where
+    F: FnMut(&str),
+{
+    let mut command;
+    let mut input = input.map(str::to_string);
+    if runs_on_host(program) {
+        let (host_args, env_input) = host_command(program, args, env)?;
+        command = Command::new(FLATPAK_SPAWN);
+        command.args(host_args);
+        if !env_input.is_empty() {
+            input = Some(env_input + input.as_deref().unwrap_or_default());
+        }
+    } else {
+        command = Command::new(program);
+        command.args(args).envs(env.iter().copied());
+    }
+    let mut child = command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound if program == CLI_PROGRAM => CliError::NotFound,
+            _ => CliError::Spawn(format!("{}: {}", program, e)),
+        })?;
+
+    // Inputs are small (a secret or two), so write them up front; dropping
+    // the handle closes stdin so the child sees EOF
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin
+            .write_all(input.as_bytes())
+            .await
+            .map_err(|e| CliError::Spawn(format!("{}: {}", program, e)))?;
+    }
+
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let aborted = Cell::new(false);
+    let exited_at = Cell::new(None::<Instant>);
+    let stderr_buf = RefCell::new(Vec::new());
+
+    // Read stdout line-by-line while watching the clock and the cancel
+    // token, then wait for the child to exit under the same checks: closing
+    // stdout doesn't mean it's done. stderr is drained concurrently so a
+    // chatty child can't block on a full pipe
+    let supervise = async {
+        let mut collected = String::new();
+        let mut lines = stdout.map(|stdout| BufReader::new(stdout).lines());
+        let started = Instant::now();
+        loop {
+            let wake = match lines.as_mut() {
+                Some(lines) => {
+                    future::or(async { Wake::Line(lines.next().await) }, async {
+                        Timer::after(POLL_INTERVAL).await;
+                        Wake::Tick
+                    })
+                    .await
+                }
+                None => Wake::Tick,
+            };
+
+            match wake {
+                Wake::Line(Some(Ok(line))) => {
+                    on_line(&line);
+                    collected.push_str(&line);
+                    collected.push('\n');
+                }
+                Wake::Line(Some(Err(_))) | Wake::Line(None) => lines = None,
+                Wake::Tick => {}
+            }
+
+            if lines.is_none() {
+                match child.try_status() {
+                    Ok(Some(status)) => {
+                        exited_at.set(Some(Instant::now()));
+                        return Ok((collected, status));
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        let _ = child.kill();
+                        aborted.set(true);
+                        return Err(CliError::Spawn(format!(
+                            "Failed to wait for command: {}",
+                            e
+                        )));
+                    }
+                }
+            }
+            if options.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+                let _ = child.kill();
+                aborted.set(true);
+                return Err(CliError::Cancelled);
+            }
+            if started.elapsed() >= options.timeout {
+                let _ = child.kill();
+                aborted.set(true);
+                return Err(CliError::Timeout(options.timeout));
+            }
+            if lines.is_none() {
+                Timer::after(POLL_INTERVAL).await;
+            }
+        }
+    };
+    // Stop draining once the child is killed, or shortly after it exits:
+    // grandchildren may still hold the stderr pipe open
+    let drain_stderr = async {
+        let read = async {
+            if let Some(mut stderr) = stderr {
+                let mut chunk = [0u8; 4096];
+                while let Ok(n @ 1..) = stderr.read(&mut chunk).await {
+                    stderr_buf.borrow_mut().extend_from_slice(&chunk[..n]);
+                }
+            }
+        };
+        let give_up = async {
+            while !aborted.get() && exited_at.get().is_none_or(|t| t.elapsed() < STDERR_GRACE) {
+                Timer::after(POLL_INTERVAL).await;
+            }
+        };
+        future::or(read, give_up).await
+    };
+
+    let (supervised, ()) = future::zip(supervise, drain_stderr).await;
+
+    let (stdout, status) = match supervised {
+        Ok(exited) => exited,
+        Err(e) => {
+            // Reap the killed child
+            let _ = child.status().await;
+            return Err(e);
+        }
+    };
+
+    if status.success() {
+        Ok(stdout)
+    } else {
+        Err(CliError::NonZeroExit {
+            code: status.code(),
+            stderr: String::from_utf8_lossy(&stderr_buf.into_inner()).into_owned(),
+        })
+    }
+}
+
+/// Whether `flatpak-spawn` may run commands on the host, which takes the
+/// `--talk-name=org.freedesktop.Flatpak` permission. Asked once
+pub fn can_run_on_host() -> bool {
+    static ALLOWED: OnceLock<bool> = OnceLock::new();
+    *ALLOWED.get_or_init(|| {
+        let allowed = std::process::Command::new(FLATPAK_SPAWN)
+            .args(["--host", "true"])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if !allowed {
+            tracing::warn!("Can't run commands on the host; running them in the sandbox");
+        }
+        allowed
+    })
+}
+
+/// Under Flatpak, the CLI and programs missing from the sandbox run on
+/// the host when that is allowed
+fn runs_on_host(program: &str) -> bool {
+    platform::is_flatpak()
+        && (program == CLI_PROGRAM || find_program(program).is_none())
+        && can_run_on_host()
+}
+
+/// `flatpak-spawn` arguments running `program` on the host, and what to
+/// write to its stdin ahead of any input to set `env` there.
+/// `--watch-bus` ends the host command if the sandboxed side is killed
+pub fn host_command(
+    program: &str,
+    args: &[String],
+    env: &[(&str, &str)],
+) -> Result<(Vec<String>, String), CliError> {
+    let mut host_args = vec!["--host".to_string(), "--watch-bus".to_string()];
+    let mut env_input = String::new();
+    if !env.is_empty() {
+        for (key, value) in env {
+            if key.is_empty() || key.contains(['=', '\n']) || value.contains('\n') {
+                return Err(CliError::Spawn(format!(
+                    "{}: can't pass {} to the host",
+                    program, key
+                )));
+            }
+            env_input.push_str(&format!("{}={}\n", key, value));
+        }
+        env_input.push('\n');
+        host_args.extend(["sh", "-c", HOST_ENV_SCRIPT, "sh"].map(String::from));
+    }
+    host_args.push(program.to_string());
+    host_args.extend(args.iter().cloned());
+    Ok((host_args, env_input))
+}
+
+/// Locate a program on PATH (or at an explicit path) without running it
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    let candidate = Path::new(program);
+    if candidate.components().count() > 1 {
+        return candidate.is_file().then(|| candidate.to_path_buf());
+    }
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// Build an owned argument vector from string slices
+pub fn args(parts: &[&str]) -> Vec<String> {
+    parts.iter().map(|s| s.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_timeouts() {
+        assert_eq!(default_timeout(&args(&["status"])), Duration::from_secs(30));
+        assert_eq!(
+            default_timeout(&args(&["keys", "crawl"])),
+            Duration::from_secs(300)
+        );
+        assert_eq!(
+            default_timeout(&args(&["switch", "github-personal"])),
+            Duration::from_secs(120)
+        );
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient("error: Database is locked"));
+        assert!(is_transient("Resource temporarily unavailable"));
+        assert!(!is_transient("Unknown identity: foo"));
+    }
+
+    #[test]
+    fn test_reported_error() {
+        assert_eq!(reported_error("[OK] Attached kubeconfig\n"), Ok(()));
+        assert_eq!(
+            reported_error("\x1b[31m[ERROR]\x1b[0m Entry not found: A/B\nUse search\n"),
+            Err(CliError::NonZeroExit {
+                code: None,
+                stderr: "Entry not found: A/B".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_session_env() {
+        set_session_env("RJ_TEST_SESSION", Some("one"));
+        set_session_env("RJ_TEST_SESSION", Some("two"));
+        assert!(has_session_env("RJ_TEST_SESSION"));
+        assert_eq!(session_env_value("RJ_TEST_SESSION").as_deref(), Some("two"));
+        let merged = with_session_env(&[("RJ_TEST_CALL", "x")]);
+        let session: Vec<_> = merged
+            .iter()
+            .filter(|(k, _)| k == "RJ_TEST_SESSION")
+            .collect();
+        assert_eq!(session.len(), 1);
+        assert_eq!(session[0].1, "two");
+        assert!(merged.iter().any(|(k, v)| k == "RJ_TEST_CALL" && v == "x"));
+
+        set_session_env("RJ_TEST_SESSION", None);
+        assert!(!has_session_env("RJ_TEST_SESSION"));
+    }
+
+    #[test]
+    fn test_host_command() {
+        let (host_args, env_input) = host_command(CLI_PROGRAM, &args(&["status"]), &[]).unwrap();
+        assert_eq!(
+            host_args,
+            args(&["--host", "--watch-bus", "remote-juggler", "status"])
+        );
+        assert_eq!(env_input, "");
+
+        let env = [("RJ_MASTER_PASSWORD", "p=ss word"), ("LANG", "C")];
+        let (host_args, env_input) = host_command("git", &args(&["fetch"]), &env).unwrap();
+        assert_eq!(
+            &host_args[..5],
+            args(&["--host", "--watch-bus", "sh", "-c", HOST_ENV_SCRIPT])
+        );
+        assert_eq!(&host_args[5..], args(&["sh", "git", "fetch"]));
+        assert!(!host_args.iter().any(|arg| arg.contains("p=ss")));
+        assert_eq!(env_input, "RJ_MASTER_PASSWORD=p=ss word\nLANG=C\n\n");
+
+        assert!(host_command("git", &[], &[("KEY", "two\nlines")]).is_err());
+    }
+
+    #[test]
+    fn test_reads_retry_and_writes_do_not() {
+        assert_eq!(RunOptions::for_args(&args(&["keys", "status"])).retries, 2);
+        assert_eq!(RunOptions::for_args(&args(&["switch", "x"])).retries, 0);
+    }
+
+    #[test]
+    fn test_cancel_before_retry_sleep() {
+        let token = CancelToken::new();
+        token.cancel();
+        assert!(async_io::block_on(sleep_unless_cancelled(
+            Duration::from_secs(5),
+            Some(&token)
+        )));
+    }
+
+    fn sh(script: &str) -> Vec<String> {
+        args(&["-c", script])
+    }
+
+    #[test]
+    fn test_streams_lines_in_order() {
+        let mut seen = Vec::new();
+        let options = RunOptions::for_args(&[]);
+        let stdout = async_io::block_on(run_program_streaming(
+            "sh",
+            &sh("echo one; echo two"),
+            &[],
+            &options,
+            |line| seen.push(line.to_string()),
+        ))
+        .unwrap();
+
+        assert_eq!(seen, vec!["one", "two"]);
+        assert_eq!(stdout, "one\ntwo\n");
+    }
+
+    #[test]
+    fn test_find_program() {
+        assert!(find_program("sh").is_some());
+        assert!(find_program("remote-juggler-no-such-program").is_none());
+        assert!(find_program("/nonexistent/bin/tool").is_none());
+    }
+
+    #[test]
+    fn test_input_is_written_to_stdin() {
+        let options = RunOptions::for_args(&[]);
+        let stdout = run_program_with_input("sh", &sh("cat"), &[], "secret\n", &options).unwrap();
+        assert_eq!(stdout, "secret\n");
+    }
+
+    #[test]
+    fn test_non_zero_exit_captures_stderr() {
+        let options = RunOptions::for_args(&[]);
+        let err = run_program("sh", &sh("echo oops >&2; exit 3"), &options).unwrap_err();
+        assert_eq!(
+            err,
+            CliError::NonZeroExit {
+                code: Some(3),
+                stderr: "oops\n".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_timeout_kills_child() {
+        let options = RunOptions {
+            timeout: Duration::from_millis(200),
+            retries: 0,
+            cancel: None,
+        };
+        let started = Instant::now();
+        let err = run_program("sh", &sh("sleep 10"), &options).unwrap_err();
+        assert_eq!(err, CliError::Timeout(Duration::from_millis(200)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_timeout_after_stdout_closes() {
+        let options = RunOptions {
+            timeout: Duration::from_millis(200),
+            retries: 0,
+            cancel: None,
+        };
+        let started = Instant::now();
+        let err = run_program("sh", &sh("exec >&-; sleep 10"), &options).unwrap_err();
+        assert_eq!(err, CliError::Timeout(Duration::from_millis(200)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_cancel_after_stdout_closes() {
+        let token = CancelToken::new();
+        let options = RunOptions {
+            timeout: Duration::from_secs(30),
+            retries: 0,
+            cancel: Some(token.clone()),
+        };
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            token.cancel();
+        });
+        let started = Instant::now();
+        let err = run_program("sh", &sh("exec >&-; sleep 10"), &options).unwrap_err();
+        canceller.join().unwrap();
+        assert_eq!(err, CliError::Cancelled);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_exit_while_stderr_held_open() {
+        let options = RunOptions::for_args(&[]);
+        let started = Instant::now();
+        let stdout = run_program("sh", &sh("sleep 10 >/dev/null & echo ok"), &options).unwrap();
+        assert_eq!(stdout, "ok\n");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(
+            CliError::Timeout(Duration::from_secs(30)).to_string(),
+            "Timed out after 30s"
+        );
+        assert_eq!(CliError::Cancelled.to_string(), "Cancelled");
+    }
+}
@@ -0,0 +1,175 @@
+//! Result cache for idempotent CLI reads
+//!
+//! The UI rebuilds frequently (focus changes, switches), and each rebuild used
+//! to spawn the same read-only subprocesses again. This caches stdout of
+//! read-only commands keyed by their full argument vector, with a per-command
+//! TTL. Any command not known to be read-only is treated as mutating and
+//! invalidates the whole cache.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// A cached stdout along with when it was captured
+#[derive(Debug, Clone)]
+struct CachedOutput {
+    stdout: String,
+    stored_at: Instant,
+}
+
+/// Thread-safe cache of CLI results keyed by command + args
+#[derive(Debug, Default)]
+pub struct CliCache {
+    entries: Mutex<HashMap<Vec<String>, CachedOutput>>,
+}
+
+/// Returns the TTL for a read-only command, or None if it must not be cached
+///
+/// Secret-returning commands (`keys get`) are never cached.
+pub fn ttl_for(args: &[String]) -> Option<Duration> {
+    let parts: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    match parts.as_slice() {
+        ["status"] => Some(Duration::from_secs(5)),
+        ["list" | "ls", ..] => Some(Duration::from_secs(30)),
+        ["keys", "status"] => Some(Duration::from_secs(10)),
+        ["keys", "search" | "find", ..] => Some(Duration::from_secs(30)),
+        ["keys", "list" | "ls", ..] => Some(Duration::from_secs(30)),
+        ["gpg", "status"] => Some(Duration::from_secs(30)),
+        ["pin", "status" | "check", ..] => Some(Duration::from_secs(30)),
+        _ => None,
+    }
+}
+
+/// Returns whether running this command may change state observed by reads
+pub fn is_mutating(args: &[String]) -> bool {
+    let parts: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    match parts.as_slice() {
+        // Reads that are not cached but don't change anything either
+        ["keys", "get" | "resolve" | "attachments" | "attachment-export" | "export-xml", ..] => {
+            false
+        }
+        ["help" | "version" | "--version" | "-v", ..] => false,
+        _ => ttl_for(args).is_none(),
+    }
+}
+
+impl CliCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a cached result if present and not expired
+    pub fn get(&self, args: &[String]) -> Option<String> {
+        self.get_at(args, Instant::now())
+    }
+
+    fn get_at(&self, args: &[String], now: Instant) -> Option<String> {
+        let ttl = ttl_for(args)?;
+        let entries = self.entries.lock().ok()?;
+        let cached = entries.get(args)?;
+        if now.duration_since(cached.stored_at) < ttl {
+            Some(cached.stdout.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Store a successful result for a cacheable command
+    pub fn insert(&self, args: &[String], stdout: &str) {
+        self.insert_at(args, stdout, Instant::now());
+    }
+
+    fn insert_at(&self, args: &[String], stdout: &str, now: Instant) {
+        if ttl_for(args).is_none() {
+            return;
+        }
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(
+                args.to_vec(),
+                CachedOutput {
+                    stdout: stdout.to_string(),
+                    stored_at: now,
+                },
+            );
+        }
+    }
+
+    /// Drop all cached results
+    pub fn invalidate(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+
+    /// Number of entries currently held (including expired ones)
+    pub fn len(&self) -> usize {
+        self.entries.lock().map(|e| e.len()).unwrap_or(0)
+    }
+
+    /// Returns true if the cache holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Process-wide cache used by `cli::run_cached`
+pub fn global() -> &'static CliCache {
+    static CACHE: OnceLock<CliCache> = OnceLock::new();
+    CACHE.get_or_init(CliCache::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::args;
+
+    #[test]
+    fn test_ttl_for_read_commands() {
+        assert!(ttl_for(&args(&["keys", "status"])).is_some());
+        assert!(ttl_for(&args(&["keys", "search", "github"])).is_some());
+        assert!(ttl_for(&args(&["status"])).is_some());
+        assert!(ttl_for(&args(&["keys", "get", "RemoteJuggler/API/KEY"])).is_none());
+        assert!(ttl_for(&args(&["switch", "github-personal"])).is_none());
+    }
+
+    #[test]
+    fn test_is_mutating() {
+        assert!(is_mutating(&args(&["switch", "github-personal"])));
+        assert!(is_mutating(&args(&["keys", "store", "A", "--value", "b"])));
+        assert!(is_mutating(&args(&["security-mode", "maximum_security"])));
+        assert!(!is_mutating(&args(&["keys", "get", "A"])));
+        assert!(!is_mutating(&args(&["keys", "status"])));
+    }
+
+    #[test]
+    fn test_cache_hit_and_expiry() {
+        let cache = CliCache::new();
+        let key = args(&["keys", "status"]);
+        let start = Instant::now();
+        cache.insert_at(&key, "Exists: yes", start);
+
+        assert_eq!(
+            cache.get_at(&key, start + Duration::from_secs(1)),
+            Some("Exists: yes".to_string())
+        );
+        assert_eq!(cache.get_at(&key, start + Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn test_uncacheable_commands_are_not_stored() {
+        let cache = CliCache::new();
+        cache.insert(&args(&["keys", "get", "A"]), "secret");
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_invalidate_clears_entries() {
+        let cache = CliCache::new();
+        cache.insert(&args(&["status"]), "ok");
+        cache.insert(&args(&["keys", "search", "x"]), "results");
+        assert_eq!(cache.len(), 2);
+
+        cache.invalidate();
+        assert!(cache.is_empty());
+    }
+}
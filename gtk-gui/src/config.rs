@@ -5,10 +5,14 @@
 //! Identities are grouped into Profiles based on provider+user combination.
 //! Each profile can have multiple SSH key variants (regular vs FIDO2/YubiKey).
 
-use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::ConfigError;
+use crate::gpg;
+use crate::platform;
 
 /// Security mode for YubiKey PIN handling
 ///
@@ -77,12 +81,133 @@ impl SecurityMode {
     }
 }
 
+/// Where Trusted Workstation mode keeps an identity's YubiKey PIN
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PinStorageMethod {
+    /// TPM 2.0, sealed to PCR 7 (Linux)
+    Tpm,
+    /// The macOS keychain, whose keys the Secure Enclave protects
+    SecureEnclave,
+    /// The macOS keychain without the Secure Enclave
+    Keychain,
+    /// GNOME Keyring, KWallet or another Secret Service (Linux)
+    SecretService,
+    /// An age-encrypted file, where nothing else is available
+    EncryptedFile,
+}
+
+impl PinStorageMethod {
+    /// Parse a config value; also accepts the backend names older CLI
+    /// versions wrote ("TPM 2.0", "Secure Enclave", "Keychain"). None for
+    /// "" and "none", which mean auto-detect
+    pub fn parse(value: &str) -> Option<Self> {
+        match value
+            .trim()
+            .to_lowercase()
+            .replace([' ', '-'], "_")
+            .as_str()
+        {
+            "tpm" | "tpm_2.0" | "tpm2" => Some(Self::Tpm),
+            "secure_enclave" => Some(Self::SecureEnclave),
+            "keychain" => Some(Self::Keychain),
+            "secret_service" => Some(Self::SecretService),
+            "encrypted_file" => Some(Self::EncryptedFile),
+            _ => None,
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::Tpm => "TPM 2.0",
+            Self::SecureEnclave => "Secure Enclave",
+            Self::Keychain => "macOS keychain",
+            Self::SecretService => "system keyring",
+            Self::EncryptedFile => "encrypted file",
+        }
+    }
+}
+
+fn deserialize_pin_storage_method<'de, D>(
+    deserializer: D,
+) -> Result<Option<PinStorageMethod>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.and_then(|v| PinStorageMethod::parse(&v)))
+}
+
+/// How commits are signed, written to git as `gpg.format`
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SigningFormat {
+    /// A GPG key (`gpg.format=openpgp`)
+    #[default]
+    Gpg,
+    /// The identity's SSH key (`gpg.format=ssh`, git 2.34+)
+    Ssh,
+    /// Sigstore keyless signing through gitsign (`gpg.format=x509`)
+    Gitsign,
+}
+
+impl SigningFormat {
+    pub const ALL: [SigningFormat; 3] = [Self::Gpg, Self::Ssh, Self::Gitsign];
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            SigningFormat::Gpg => "GPG",
+            SigningFormat::Ssh => "SSH",
+            SigningFormat::Gitsign => "Gitsign (keyless)",
+        }
+    }
+}
+
+/// Sigstore endpoints for gitsign; unset fields keep gitsign's defaults
+/// (the public-good instance)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GitsignConfig {
+    /// OIDC issuer to log in with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// Fulcio CA URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fulcio: Option<String>,
+    /// Rekor transparency log URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rekor: Option<String>,
+    /// Dex connector that skips the provider choice, e.g.
+    /// `https://github.com/login/oauth`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connector_id: Option<String>,
+}
+
+/// Settings left at their defaults aren't written, so saving from the GUI
+/// doesn't fill the file with them
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
 /// GPG signing configuration for an identity
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GpgConfig {
     #[serde(default)]
     pub key_id: String,
+    /// Subkey of `key_id` to sign with; gpg otherwise picks the newest
+    /// signing subkey
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_subkey: Option<String>,
+    /// Sign with a GPG key or the identity's SSH key
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub format: SigningFormat,
+    /// Public key to sign with in SSH format; the identity's `sshKeyPath`
+    /// with `.pub` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_key_path: Option<String>,
+    /// Sigstore endpoints in gitsign format
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gitsign: Option<GitsignConfig>,
     #[serde(default)]
     pub sign_commits: bool,
     #[serde(default)]
@@ -92,10 +217,20 @@ pub struct GpgConfig {
     /// Security mode for PIN handling (maximum_security, developer_workflow, trusted_workstation)
     #[serde(default)]
     pub security_mode: SecurityMode,
-    /// PIN storage method for trusted_workstation mode (tpm, secure_enclave, keychain, none)
-    /// Auto-detected if empty
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub pin_storage_method: Option<String>,
+    /// PIN storage for trusted_workstation mode; auto-detected if unset
+    #[serde(
+        default,
+        deserialize_with = "deserialize_pin_storage_method",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub pin_storage_method: Option<PinStorageMethod>,
+    /// The secret key lives on a YubiKey or other OpenPGP card
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hardware_key: bool,
+    /// Keys this crate doesn't model, e.g. ones only the CLI reads; kept
+    /// so saving doesn't drop them
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
 /// A single git identity configuration
@@ -116,6 +251,168 @@ pub struct Identity {
     /// KeePassXC entry path for this identity's credentials
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub keepassxc_entry: Option<String>,
+    /// Cloud secret manager settings used while this identity is active
+    #[serde(default, skip_serializing_if = "CloudConfig::is_empty")]
+    pub cloud: CloudConfig,
+    /// Renewal settings when the SSH key is used with a CA-signed certificate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_certificate: Option<SshCertificateConfig>,
+    /// Environment variable holding this identity's API token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env_var: Option<String>,
+    /// Agent forwarding and key-use policy for this identity's host
+    #[serde(default, skip_serializing_if = "SshPolicy::is_empty")]
+    pub ssh_policy: SshPolicy,
+    /// PKCS#11 library for a smart card key (PIV applet via OpenSC), e.g.
+    /// `/usr/lib/x86_64-linux-gnu/opensc-pkcs11.so`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pkcs11_provider: Option<String>,
+    /// Serial number of the YubiKey this identity's keys are on; switching
+    /// warns, or refuses with `refuseWrongYubikey`, when another is inserted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub yubikey_serial: Option<u32>,
+    /// Repository the identity should be able to read, as `owner/repo` on
+    /// its host or a full git URL; used by Verify Identity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_repo: Option<String>,
+    /// The forge is only reachable over the tailnet; token checks skip the
+    /// identity while Tailscale is down, and switching to it warns
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub tailnet_only: bool,
+    /// Keys this crate doesn't model, e.g. ones only the CLI reads; kept
+    /// so saving doesn't drop them
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+/// How an identity's SSH certificate is renewed, e.g.
+/// `"sshCertificate": { "signCommand": "step ssh certificate me {pubkey} --sign" }`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SshCertificateConfig {
+    /// Shell command that signs the public key; `{key}`, `{pubkey}`,
+    /// `{cert}` and `{identity}` are replaced with quoted values. A
+    /// certificate it prints is written to `{cert}`.
+    #[serde(default)]
+    pub sign_command: String,
+}
+
+/// Per-identity cloud secret manager settings
+///
+/// Lets a work identity point at the employer's project while a personal
+/// identity uses another, e.g.
+/// `"cloud": { "gcpProject": "acme-prod", "azureVault": "acme-kv" }`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudConfig {
+    /// GCP project for Secret Manager
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gcp_project: Option<String>,
+    /// Azure Key Vault name or URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub azure_vault: Option<String>,
+}
+
+impl CloudConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == CloudConfig::default()
+    }
+}
+
+/// When ssh adds a key to the agent on first use (`AddKeysToAgent`)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AddKeysToAgent {
+    /// Leave the agent alone (ssh's default)
+    #[default]
+    No,
+    /// Add the key after its passphrase is entered
+    Yes,
+    /// Ask through ssh-askpass before adding it
+    Ask,
+}
+
+impl AddKeysToAgent {
+    pub const ALL: [AddKeysToAgent; 3] = [Self::No, Self::Yes, Self::Ask];
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            AddKeysToAgent::No => "Don't add",
+            AddKeysToAgent::Yes => "Add on first use",
+            AddKeysToAgent::Ask => "Ask before adding",
+        }
+    }
+}
+
+/// Which agent ssh asks for the identity's key (`IdentityAgent`), e.g. a
+/// work identity whose keys live in 1Password next to personal keys in
+/// the system agent
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdentityAgent {
+    /// Whatever `SSH_AUTH_SOCK` points at
+    System,
+    /// 1Password's SSH agent
+    #[serde(rename = "1password")]
+    OnePassword,
+    /// gpg-agent's ssh support
+    GpgAgent,
+    /// Don't use an agent; ssh reads the key file
+    None,
+    /// Any other agent, by socket path
+    #[serde(untagged)]
+    Socket(String),
+}
+
+impl IdentityAgent {
+    /// The agents with names, in menu order
+    pub const NAMED: [IdentityAgent; 4] =
+        [Self::System, Self::OnePassword, Self::GpgAgent, Self::None];
+
+    pub fn display_name(&self) -> &str {
+        match self {
+            Self::System => "System (SSH_AUTH_SOCK)",
+            Self::OnePassword => "1Password",
+            Self::GpgAgent => "gpg-agent",
+            Self::None => "No agent",
+            Self::Socket(path) => path,
+        }
+    }
+
+    /// Whether keys can be loaded into it with ssh-add; 1Password serves
+    /// keys from its vault
+    pub fn accepts_keys(&self) -> bool {
+        !matches!(self, Self::OnePassword | Self::None)
+    }
+}
+
+/// How an identity's key may be used through the agent, e.g. a work key
+/// that needs confirming on every use while a personal key doesn't:
+/// `"sshPolicy": { "forwardAgent": false, "addKeysToAgent": "yes", "confirm": true }`.
+///
+/// Unset values leave ssh's defaults, or for `confirm` the identity's
+/// security mode, in place.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SshPolicy {
+    /// `ForwardAgent` in the identity's managed Host block
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forward_agent: Option<bool>,
+    /// `AddKeysToAgent` in the identity's managed Host block
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub add_keys_to_agent: Option<AddKeysToAgent>,
+    /// Confirm each use of the key held by the agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm: Option<bool>,
+    /// Agent holding the key, instead of `SSH_AUTH_SOCK`'s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<IdentityAgent>,
+}
+
+impl SshPolicy {
+    pub fn is_empty(&self) -> bool {
+        *self == SshPolicy::default()
+    }
 }
 
 impl Identity {
@@ -129,25 +426,67 @@ impl Identity {
         }
     }
 
-    /// Returns whether this identity has GPG signing enabled
+    /// Returns whether this identity has commit signing enabled
     #[allow(dead_code)]
     pub fn has_gpg_signing(&self) -> bool {
-        !self.gpg.key_id.is_empty() && self.gpg.sign_commits
+        self.gpg.sign_commits
+            && (self.gpg.format == SigningFormat::Gitsign || self.signing_key().is_some())
+    }
+
+    /// The `user.signingkey` value: the GPG key id, or in SSH format the
+    /// public key to sign with; gitsign needs none
+    pub fn signing_key(&self) -> Option<String> {
+        match self.gpg.format {
+            SigningFormat::Gitsign => None,
+            SigningFormat::Gpg if self.gpg.key_id.is_empty() => None,
+            // `!` makes gpg use exactly this subkey
+            SigningFormat::Gpg => Some(match self.gpg.signing_subkey.as_deref() {
+                Some(subkey) if !subkey.is_empty() => gpg::exact(subkey),
+                _ => self.gpg.key_id.clone(),
+            }),
+            SigningFormat::Ssh => match self.gpg.ssh_key_path.as_deref() {
+                Some(path) if !path.is_empty() => Some(path.to_string()),
+                _ if self.ssh_key_path.is_empty() => None,
+                _ if self.ssh_key_path.ends_with(".pub") => Some(self.ssh_key_path.clone()),
+                _ => Some(format!("{}.pub", self.ssh_key_path)),
+            },
+        }
     }
 
     /// Returns whether this identity uses a FIDO2/YubiKey security key
     pub fn is_security_key(&self) -> bool {
         self.host.ends_with("-sk") || self.ssh_key_path.ends_with("-sk")
     }
+
+    /// Returns the PKCS#11 library, if this identity's key is on a smart card
+    pub fn pkcs11_library(&self) -> Option<&str> {
+        self.pkcs11_provider
+            .as_deref()
+            .filter(|library| !library.is_empty())
+    }
+
+    /// Returns which kind of key this identity uses
+    pub fn key_type(&self) -> SshKeyType {
+        if self.pkcs11_library().is_some() {
+            SshKeyType::Pkcs11
+        } else if self.is_security_key() {
+            SshKeyType::Fido2
+        } else {
+            SshKeyType::Regular
+        }
+    }
 }
 
-/// SSH key variant type
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// SSH key variant type, in the order profiles list them
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum SshKeyType {
     /// Regular SSH key (ed25519, RSA, etc.)
     Regular,
     /// FIDO2/YubiKey security key (sk-ed25519, sk-ecdsa)
     Fido2,
+    /// Smart card key through a PKCS#11 library (PIV applet, OpenSC)
+    Pkcs11,
 }
 
 impl SshKeyType {
@@ -156,6 +495,7 @@ impl SshKeyType {
         match self {
             SshKeyType::Regular => "SSH Key",
             SshKeyType::Fido2 => "Security Key (FIDO2)",
+            SshKeyType::Pkcs11 => "Smart Card (PIV)",
         }
     }
 
@@ -163,12 +503,13 @@ impl SshKeyType {
         match self {
             SshKeyType::Regular => "SSH",
             SshKeyType::Fido2 => "SK",
+            SshKeyType::Pkcs11 => "PIV",
         }
     }
 }
 
 /// An SSH key variant within a profile
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SshVariant {
     /// The original identity name in the config
     pub identity_name: String,
@@ -188,7 +529,7 @@ impl SshVariant {
 /// A profile groups identities by provider and user
 ///
 /// Multiple SSH key variants (regular vs FIDO2) are grouped under a single profile.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Profile {
     /// Profile name (e.g., "gitlab-personal", "github-personal")
     pub name: String,
@@ -215,18 +556,27 @@ impl Profile {
         }
     }
 
-    /// Returns whether this profile has GPG signing enabled
+    /// Returns whether this profile has commit signing enabled
     pub fn has_gpg_signing(&self) -> bool {
-        !self.gpg.key_id.is_empty() && self.gpg.sign_commits
+        self.gpg.sign_commits
+            && match self.gpg.format {
+                SigningFormat::Gpg => !self.gpg.key_id.is_empty(),
+                SigningFormat::Ssh => self
+                    .variants
+                    .iter()
+                    .any(|v| v.identity.signing_key().is_some()),
+                SigningFormat::Gitsign => true,
+            }
     }
 
     /// Get the default (preferred) variant - prefers FIDO2 if available
     #[allow(dead_code)]
     pub fn default_variant(&self) -> Option<&SshVariant> {
-        // Prefer FIDO2/security key if available
+        // Prefer a hardware key if available, FIDO2 first
         self.variants
             .iter()
             .find(|v| v.key_type == SshKeyType::Fido2)
+            .or_else(|| self.get_variant(&SshKeyType::Pkcs11))
             .or_else(|| self.variants.first())
     }
 
@@ -248,6 +598,12 @@ impl Profile {
         self.get_variant(&SshKeyType::Fido2)
     }
 
+    /// Get the PKCS#11/smart card variant
+    #[allow(dead_code)]
+    pub fn pkcs11_variant(&self) -> Option<&SshVariant> {
+        self.get_variant(&SshKeyType::Pkcs11)
+    }
+
     /// Returns true if this profile has multiple SSH key variants
     pub fn has_multiple_variants(&self) -> bool {
         self.variants.len() > 1
@@ -266,6 +622,446 @@ pub struct Settings {
     #[serde(rename = "fallbackToSSH")]
     pub fallback_to_ssh: bool,
     pub verbose_logging: bool,
+    /// Source order for the secret resolver, e.g. `["kdbx", "env"]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolve_order: Vec<String>,
+    /// Key store databases besides the default one, e.g.
+    /// `[{ "name": "work", "path": "~/work/keys.kdbx" }]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keystores: Vec<KeystoreDatabase>,
+    /// Minutes of inactivity before the GUI locks the key store, per
+    /// security mode
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub auto_lock: AutoLock,
+    /// What the GUI does with ssh-agent on an identity switch
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub ssh_agent: SshAgentSettings,
+    /// Thresholds for the SSH key audit
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub key_audit: KeyAuditSettings,
+    /// When to warn about expiring GPG signing keys, and by how much to
+    /// extend them
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub gpg_expiry: GpgExpirySettings,
+    /// allowed_signers contents and git wiring
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub allowed_signers: AllowedSignersSettings,
+    /// Signing overrides for repositories under a directory; the CLI
+    /// writes them to the repo's git config on switch and detection
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signing_rules: Vec<SigningRule>,
+    /// Refuse to switch to an identity bound to a YubiKey while a
+    /// different one is inserted, instead of warning
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub refuse_wrong_yubikey: bool,
+    /// The identity for repositories under a directory, whatever their
+    /// remotes say
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub directory_rules: Vec<DirectoryRule>,
+    /// The identity for branches matching a pattern, over whatever the
+    /// directory rules and remotes say
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub branch_rules: Vec<BranchRule>,
+    /// Directories the workspace scan looks for repositories in, e.g.
+    /// `~/code`; `~` is expanded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub workspace_roots: Vec<String>,
+    /// Leave remote URLs alone on switch instead of pointing them at the
+    /// identity's host alias
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep_remote_urls: bool,
+    /// Repositories whose git config `switch` leaves alone, e.g. ones with
+    /// work in progress under another identity; `~` is expanded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub switch_excluded_repos: Vec<String>,
+    /// Email and signing requirements for repositories by remote, checked
+    /// by detection, the commit guard and the GUI
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<OrgPolicy>,
+    /// Repositories listed in the GUI for opening a terminal or editor as
+    /// their identity
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bookmarks: Vec<Bookmark>,
+    /// Terminal bookmarks open, e.g. `kitty`; `{path}` is replaced with the
+    /// repository. Empty uses the first known terminal on PATH
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub terminal_command: String,
+    /// Editor bookmarks open, e.g. `code --new-window`; the repository is
+    /// appended unless `{path}` places it. Empty uses the first known
+    /// editor on PATH
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub editor_command: String,
+    /// Self-hosted instances of a provider, for detection and the key APIs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provider_hosts: Vec<ProviderHost>,
+    /// Key store entries pushed to GitHub Actions secrets and GitLab CI
+    /// variables
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ci_secrets: Vec<CiSecret>,
+    /// Token-authenticated HTTP API on localhost, for editor plugins and
+    /// scripts where D-Bus isn't available
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub rest_api: RestApiSettings,
+    /// Serve org.freedesktop.secrets from the key store while the GUI runs
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub secret_service: bool,
+    /// Point SSH_ASKPASS and GIT_ASKPASS at remote-juggler-askpass on a
+    /// switch, for the CLI and the desktop session
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub askpass: bool,
+    /// Namespaces of mounted stores that are only reachable over the
+    /// tailnet, e.g. `["Azure"]`; the resolver skips them while Tailscale
+    /// is down. Setec always is
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tailnet_only_backends: Vec<String>,
+    /// Prometheus metrics on localhost while the GUI runs
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub metrics: MetricsSettings,
+    /// OpenTelemetry trace export; off without an endpoint
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub telemetry: TelemetrySettings,
+    /// Commands, webhooks and Matrix rooms told about credential events
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub event_hooks: Vec<EventHook>,
+    /// Keys this crate doesn't model, e.g. ones only the CLI reads; kept
+    /// so saving doesn't drop them
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+/// Auto-lock timeouts in minutes for each security mode; 0 never locks
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AutoLock {
+    pub maximum_security: u32,
+    pub developer_workflow: u32,
+    pub trusted_workstation: u32,
+}
+
+impl Default for AutoLock {
+    fn default() -> Self {
+        Self {
+            maximum_security: 5,
+            developer_workflow: 30,
+            trusted_workstation: 0,
+        }
+    }
+}
+
+impl AutoLock {
+    /// Idle time before locking in `mode`, or None to never lock
+    pub fn timeout(&self, mode: &SecurityMode) -> Option<Duration> {
+        let minutes = match mode {
+            SecurityMode::MaximumSecurity => self.maximum_security,
+            SecurityMode::DeveloperWorkflow => self.developer_workflow,
+            SecurityMode::TrustedWorkstation => self.trusted_workstation,
+        };
+        (minutes > 0).then(|| Duration::from_secs(u64::from(minutes) * 60))
+    }
+}
+
+/// ssh-agent handling on an identity switch
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SshAgentSettings {
+    /// Add the new identity's key, constrained by its security mode
+    pub add_on_switch: bool,
+    /// Remove the other identities' keys from the agent
+    pub remove_others: bool,
+}
+
+impl Default for SshAgentSettings {
+    fn default() -> Self {
+        Self {
+            add_on_switch: true,
+            remove_others: false,
+        }
+    }
+}
+
+/// The local HTTP API; off unless enabled
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RestApiSettings {
+    pub enabled: bool,
+    /// Port on 127.0.0.1
+    pub port: u16,
+}
+
+impl Default for RestApiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 7827,
+        }
+    }
+}
+
+/// The Prometheus metrics endpoint; off unless enabled
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MetricsSettings {
+    pub enabled: bool,
+    /// Port on 127.0.0.1
+    pub port: u16,
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 7828,
+        }
+    }
+}
+
+/// Where traces are exported; read at startup
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TelemetrySettings {
+    /// OTLP/HTTP collector, e.g. `http://127.0.0.1:4318`; empty sends
+    /// nothing
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub otlp_endpoint: String,
+    /// Sent with every export, e.g. a vendor's API key header
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Where credential events are delivered; a hook may name several
+/// targets, e.g. `{"events": ["identitySwitched"], "webhook": "https://..."}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EventHook {
+    /// identitySwitched, secretAccessed, keystoreUnlocked or tokenExpiring;
+    /// empty fires on every event
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+    /// Program and arguments, run with the event as JSON on stdin
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+    /// URL the event is POSTed to as JSON
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub webhook: String,
+    /// Matrix room ID the event is posted to, e.g. `!abc:example.org`
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub matrix_room: String,
+    /// Homeserver for `matrix_room`; the room's server if empty
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub matrix_homeserver: String,
+    /// Key store entry with the Matrix access token, or a bearer token for
+    /// the webhook
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub token_entry: String,
+}
+
+/// SSH key audit thresholds
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct KeyAuditSettings {
+    /// Flag keys older than this many days; 0 turns the check off
+    pub max_age_days: u32,
+}
+
+impl Default for KeyAuditSettings {
+    fn default() -> Self {
+        Self { max_age_days: 365 }
+    }
+}
+
+/// Someone whose SSH signing keys are trusted in allowed_signers, e.g.
+/// `{"email": "ana@acme.dev", "provider": "github", "user": "ana"}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Collaborator {
+    /// Commit email their signatures are checked against
+    pub email: String,
+    /// github, gitlab, gitea (also forgejo or codeberg) or sourcehut
+    pub provider: String,
+    /// Username on the provider
+    pub user: String,
+    /// Self-hosted instance; the provider's public host if empty
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hostname: String,
+}
+
+/// What goes in ~/.ssh/allowed_signers and where git is told about it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AllowedSignersSettings {
+    /// Collaborators whose provider-published signing keys are added
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub collaborators: Vec<Collaborator>,
+    /// Set `gpg.ssh.allowedSignersFile` in the global git config on update
+    pub global: bool,
+}
+
+/// GPG signing key expiry warnings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GpgExpirySettings {
+    /// Warn this many days before a signing key expires
+    pub warn_days: u32,
+    /// Days from today a key's expiry is moved to when extended
+    pub extend_days: u32,
+}
+
+impl Default for GpgExpirySettings {
+    fn default() -> Self {
+        Self {
+            warn_days: 30,
+            extend_days: 365,
+        }
+    }
+}
+
+/// Signing policy for repositories under `path`, e.g.
+/// `{"path": "~/work", "sign": true, "keyId": "ABCD1234"}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SigningRule {
+    /// Directory the rule covers; `~` is expanded
+    pub path: String,
+    /// Force signing on or off; unset leaves the identity's setting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sign: Option<bool>,
+    /// GPG key every commit must be signed with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+}
+
+/// The identity for repositories under `path`, e.g.
+/// `{"path": "~/work", "identity": "gitlab-work"}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryRule {
+    /// Directory the rule covers; `~` is expanded
+    pub path: String,
+    pub identity: String,
+}
+
+/// The identity for branches matching `branch`, e.g.
+/// `{"branch": "client-x/", "identity": "github-client-x"}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BranchRule {
+    /// A pattern like git's `onbranch:`; `*` stops at `/`, `**` doesn't,
+    /// and a trailing `/` covers everything below
+    pub branch: String,
+    pub identity: String,
+    /// Only repositories under this directory; `~` is expanded. Empty
+    /// covers every repository
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub path: String,
+}
+
+/// A self-hosted instance of a provider, e.g.
+/// `{"hostname": "ghe.acme.corp", "provider": "github"}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderHost {
+    pub hostname: String,
+    /// "github", "gitlab", "bitbucket", "gitea" (also Forgejo) or
+    /// "sourcehut"
+    pub provider: String,
+    /// API root, when it isn't where the provider keeps it, e.g.
+    /// `https://git.acme.corp/gitlab/api/v4`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub api_url: String,
+    /// Client ID of the OAuth application the GUI signs in with through
+    /// the device flow; github.com and gitlab.com take an entry too
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub oauth_client_id: String,
+}
+
+/// A key store entry kept in a CI secret, e.g.
+/// `{"entry": "RemoteJuggler/CI/NPM_TOKEN", "name": "NPM_TOKEN",
+/// "target": "acme/web", "identity": "work"}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CiSecret {
+    /// Key store path of the value
+    pub entry: String,
+    /// Secret (GitHub) or variable (GitLab) name
+    pub name: String,
+    /// `owner/repo` or the GitLab project path; with `organization`, the
+    /// organization or group
+    pub target: String,
+    /// The identity whose provider and token write the secret
+    pub identity: String,
+    /// Set on the organization (GitHub) or group (GitLab) instead of a
+    /// repository
+    #[serde(default)]
+    pub organization: bool,
+}
+
+/// A bookmarked repository, e.g. `{"path": "~/src/api"}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Bookmark {
+    /// `~` is expanded
+    pub path: String,
+    /// Open as this identity; empty uses the detected one
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub identity: String,
+}
+
+/// Requirements for repositories whose remote matches `pattern`, e.g.
+/// `{"pattern": "github.com/acme-*", "emailDomain": "acme.com",
+/// "requireSigning": true}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OrgPolicy {
+    /// `hostname/path` of the remote, or a leading part of it; `*` matches
+    /// within one path segment
+    pub pattern: String,
+    /// Commits must use an email at this domain; empty allows any
+    #[serde(default)]
+    pub email_domain: String,
+    /// Commits must be signed
+    #[serde(default)]
+    pub require_signing: bool,
+}
+
+impl Settings {
+    /// The signing rule covering `repo`; the most specific path wins
+    pub fn signing_rule(&self, repo: &Path) -> Option<&SigningRule> {
+        self.signing_rules
+            .iter()
+            .filter(|rule| !rule.path.is_empty())
+            .map(|rule| (platform::expand_tilde(&rule.path), rule))
+            .filter(|(path, _)| repo.starts_with(path))
+            .max_by_key(|(path, _)| path.components().count())
+            .map(|(_, rule)| rule)
+    }
+
+    /// Whether `repo` is, or is inside, a repository excluded from
+    /// switching
+    pub fn is_switch_excluded(&self, repo: &Path) -> bool {
+        self.switch_excluded_repos
+            .iter()
+            .filter(|path| !path.is_empty())
+            .any(|path| repo.starts_with(platform::expand_tilde(path)))
+    }
+
+    /// The directory rule covering `repo`; the most specific path wins
+    pub fn directory_rule(&self, repo: &Path) -> Option<&DirectoryRule> {
+        self.directory_rules
+            .iter()
+            .filter(|rule| !rule.path.is_empty() && !rule.identity.is_empty())
+            .map(|rule| (platform::expand_tilde(&rule.path), rule))
+            .filter(|(path, _)| repo.starts_with(path))
+            .max_by_key(|(path, _)| path.components().count())
+            .map(|(_, rule)| rule)
+    }
+}
+
+/// A named key store database
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeystoreDatabase {
+    pub name: String,
+    /// Path to the .kdbx file; `~` is expanded
+    pub path: String,
 }
 
 impl Default for Settings {
@@ -278,6 +1074,34 @@ impl Default for Settings {
             gpg_verify_with_provider: true,
             fallback_to_ssh: true,
             verbose_logging: false,
+            resolve_order: Vec::new(),
+            keystores: Vec::new(),
+            auto_lock: AutoLock::default(),
+            ssh_agent: SshAgentSettings::default(),
+            key_audit: KeyAuditSettings::default(),
+            gpg_expiry: GpgExpirySettings::default(),
+            allowed_signers: AllowedSignersSettings::default(),
+            signing_rules: Vec::new(),
+            refuse_wrong_yubikey: false,
+            directory_rules: Vec::new(),
+            branch_rules: Vec::new(),
+            workspace_roots: Vec::new(),
+            keep_remote_urls: false,
+            switch_excluded_repos: Vec::new(),
+            policies: Vec::new(),
+            bookmarks: Vec::new(),
+            terminal_command: String::new(),
+            editor_command: String::new(),
+            provider_hosts: Vec::new(),
+            ci_secrets: Vec::new(),
+            rest_api: RestApiSettings::default(),
+            secret_service: false,
+            askpass: false,
+            tailnet_only_backends: Vec::new(),
+            metrics: MetricsSettings::default(),
+            telemetry: TelemetrySettings::default(),
+            event_hooks: Vec::new(),
+            extra: HashMap::new(),
         }
     }
 }
@@ -309,32 +1133,90 @@ pub struct Config {
 
 impl Config {
     /// Load configuration from the default path
-    pub fn load() -> Result<Self> {
+    pub fn load() -> Result<Self, ConfigError> {
         let config_path = Self::config_path()?;
         Self::load_from(&config_path)
     }
 
     /// Load configuration from a specific path
-    pub fn load_from(path: &PathBuf) -> Result<Self> {
-        let content = std::fs::read_to_string(path)
-            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-
-        let config: Config = serde_json::from_str(&content).with_context(|| {
-            // Try to get more detailed error
-            match serde_json::from_str::<serde_json::Value>(&content) {
-                Ok(_) => "JSON valid but struct mismatch".to_string(),
-                Err(e) => format!("JSON parse error: {}", e),
-            }
+    pub fn load_from(path: &PathBuf) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.clone(),
+            source,
         })?;
 
+        // serde_json's error already distinguishes syntax errors from
+        // struct mismatches (with line/column)
+        let config: Config =
+            serde_json::from_str(&content).map_err(|source| ConfigError::Parse {
+                path: path.clone(),
+                source,
+            })?;
+
+        Ok(config)
+    }
+
+    /// Write the configuration back to the default path
+    pub fn save(&self) -> Result<(), ConfigError> {
+        self.save_to(&Self::config_path()?)
+    }
+
+    /// Write the configuration to `path`, replacing it atomically
+    ///
+    /// Fields this crate doesn't model, at the top level and in settings,
+    /// identities and their GPG settings, are kept through `extra`.
+    pub fn save_to(&self, path: &PathBuf) -> Result<(), ConfigError> {
+        let write_error = |source| ConfigError::Write {
+            path: path.clone(),
+            source,
+        };
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| write_error(std::io::Error::other(e)))?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json + "\n").map_err(write_error)?;
+        std::fs::rename(&tmp, path).map_err(write_error)
+    }
+
+    /// Load the config, apply `change` and save it, returning the result
+    pub fn update(change: impl FnOnce(&mut Config)) -> Result<Config, ConfigError> {
+        Self::update_at(&Self::config_path()?, change)
+    }
+
+    /// Load the config, apply `change` to identity `name` and save it
+    pub fn update_identity(
+        name: &str,
+        change: impl FnOnce(&mut Identity),
+    ) -> Result<Config, ConfigError> {
+        Self::update_identity_at(&Self::config_path()?, name, change)
+    }
+
+    fn update_at(path: &PathBuf, change: impl FnOnce(&mut Config)) -> Result<Config, ConfigError> {
+        let mut config = Self::load_from(path)?;
+        change(&mut config);
+        config.save_to(path)?;
+        Ok(config)
+    }
+
+    fn update_identity_at(
+        path: &PathBuf,
+        name: &str,
+        change: impl FnOnce(&mut Identity),
+    ) -> Result<Config, ConfigError> {
+        let mut config = Self::load_from(path)?;
+        let identity = config
+            .identities
+            .get_mut(name)
+            .ok_or_else(|| ConfigError::NoIdentity(name.to_string()))?;
+        change(identity);
+        config.save_to(path)?;
         Ok(config)
     }
 
     /// Get the default config file path
-    pub fn config_path() -> Result<PathBuf> {
-        let config_dir = dirs::config_dir().context("Could not determine config directory")?;
+    pub fn config_path() -> Result<PathBuf, ConfigError> {
+        let config_dir = platform::config_dir().ok_or(ConfigError::NoConfigDir)?;
 
-        Ok(config_dir.join("remote-juggler").join("config.json"))
+        Ok(config_dir.join("config.json"))
     }
 
     /// Get a sorted list of identity names
@@ -363,7 +1245,8 @@ impl Config {
     /// Group identities into profiles by provider+user
     ///
     /// Identities with `-sk` suffix are grouped with their non-sk counterpart
-    /// as FIDO2/security key variants.
+    /// as FIDO2/security key variants, and `-piv` ones (with a PKCS#11
+    /// provider) as smart card variants.
     pub fn profiles(&self) -> Vec<Profile> {
         // Group identities by (provider, user) tuple
         let mut profile_map: HashMap<(String, String), Vec<(String, Identity)>> = HashMap::new();
@@ -383,7 +1266,12 @@ impl Config {
                 // Determine the base profile name (without -sk suffix)
                 let base_name = identities
                     .iter()
-                    .map(|(name, _)| name.strip_suffix("-sk").unwrap_or(name).to_string())
+                    .map(|(name, _)| {
+                        name.strip_suffix("-sk")
+                            .or_else(|| name.strip_suffix("-piv"))
+                            .unwrap_or(name)
+                            .to_string()
+                    })
                     .min_by_key(|n| n.len())
                     .unwrap_or_else(|| format!("{}-{}", provider, user));
 
@@ -395,17 +1283,10 @@ impl Config {
                 // Create variants
                 let variants: Vec<SshVariant> = identities
                     .into_iter()
-                    .map(|(name, identity)| {
-                        let key_type = if identity.is_security_key() {
-                            SshKeyType::Fido2
-                        } else {
-                            SshKeyType::Regular
-                        };
-                        SshVariant {
-                            identity_name: name,
-                            key_type,
-                            identity,
-                        }
+                    .map(|(name, identity)| SshVariant {
+                        identity_name: name,
+                        key_type: identity.key_type(),
+                        identity,
                     })
                     .collect();
 
@@ -423,15 +1304,13 @@ impl Config {
         // Sort profiles by name
         profiles.sort_by(|a, b| a.name.cmp(&b.name));
 
-        // Sort variants within each profile (Regular before Fido2)
+        // Sort variants within each profile (Regular, Fido2, then Pkcs11)
         for profile in &mut profiles {
-            profile
-                .variants
-                .sort_by(|a, b| match (&a.key_type, &b.key_type) {
-                    (SshKeyType::Regular, SshKeyType::Fido2) => std::cmp::Ordering::Less,
-                    (SshKeyType::Fido2, SshKeyType::Regular) => std::cmp::Ordering::Greater,
-                    _ => a.identity_name.cmp(&b.identity_name),
-                });
+            profile.variants.sort_by(|a, b| {
+                a.key_type
+                    .cmp(&b.key_type)
+                    .then_with(|| a.identity_name.cmp(&b.identity_name))
+            });
         }
 
         profiles
@@ -479,9 +1358,150 @@ impl Config {
     }
 }
 
+/// Configs and identities for tests, built in code rather than parsed from
+/// JSON
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+
+    /// An identity with only the fields config.json requires: no SSH key,
+    /// no credential source, and defaults for the rest
+    pub(crate) fn identity(
+        provider: &str,
+        host: &str,
+        hostname: &str,
+        user: &str,
+        email: &str,
+    ) -> Identity {
+        Identity {
+            provider: provider.to_string(),
+            host: host.to_string(),
+            hostname: hostname.to_string(),
+            user: user.to_string(),
+            email: email.to_string(),
+            ssh_key_path: String::new(),
+            credential_source: "none".to_string(),
+            organizations: Vec::new(),
+            gpg: GpgConfig::default(),
+            keepassxc_entry: None,
+            cloud: CloudConfig::default(),
+            ssh_certificate: None,
+            token_env_var: None,
+            ssh_policy: SshPolicy::default(),
+            pkcs11_provider: None,
+            yubikey_serial: None,
+            verify_repo: None,
+            tailnet_only: false,
+            extra: HashMap::new(),
+        }
+    }
+
+    /// A config with no identities and default settings, built up a piece
+    /// at a time
+    pub(crate) struct ConfigBuilder(Config);
+
+    impl ConfigBuilder {
+        pub(crate) fn new() -> Self {
+            Self(Config {
+                schema: None,
+                version: "2.0.0".to_string(),
+                generated: String::new(),
+                identities: HashMap::new(),
+                settings: Settings::default(),
+                state: State::default(),
+                extra: HashMap::new(),
+            })
+        }
+
+        pub(crate) fn identity(mut self, name: &str, identity: Identity) -> Self {
+            self.0.identities.insert(name.to_string(), identity);
+            self
+        }
+
+        pub(crate) fn current(mut self, name: &str) -> Self {
+            self.0.state.current_identity = name.to_string();
+            self
+        }
+
+        pub(crate) fn settings(mut self, change: impl FnOnce(&mut Settings)) -> Self {
+            change(&mut self.0.settings);
+            self
+        }
+
+        pub(crate) fn build(self) -> Config {
+            self.0
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use testing::{identity, ConfigBuilder};
+
+    #[test]
+    fn test_save_keeps_cli_fields() {
+        let fixture =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cli-config.json");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        Config::load_from(&fixture).unwrap().save_to(&path).unwrap();
+
+        let saved: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let settings = &saved["settings"];
+        assert_eq!(settings["defaultSecurityMode"], "developer_workflow");
+        assert_eq!(settings["hsmAvailable"], true);
+        assert_eq!(settings["trustedWorkstationRequiresHSM"], true);
+        assert_eq!(settings["useKeePassXC"], true);
+        assert_eq!(settings["keepassxcDatabase"], "~/.remotejuggler/keys.kdbx");
+        assert_eq!(settings["keepassxcAutoUnlock"], true);
+        let work = &saved["identities"]["work"];
+        assert_eq!(work["keychainService"], "remote-juggler.gitlab.work");
+        assert_eq!(work["keePassEntry"], "RemoteJuggler/Tokens/GitLab/work");
+        assert_eq!(work["gpg"]["touchPolicy"], "cached");
+        assert_eq!(work["gpg"]["hardwareKey"], true);
+        assert!(saved["_managed_ssh_hosts"]["hosts"]["gitlab-work"].is_object());
+
+        // GUI settings at their defaults stay out of the file
+        for key in [
+            "autoLock",
+            "sshAgent",
+            "keyAudit",
+            "restApi",
+            "metrics",
+            "secretService",
+        ] {
+            assert!(settings.get(key).is_none(), "{} was written", key);
+        }
+    }
+
+    #[test]
+    fn test_update_identity() {
+        let fixture =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cli-config.json");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        std::fs::copy(&fixture, &path).unwrap();
+
+        let config = Config::update_identity_at(&path, "work", |identity| {
+            identity.tailnet_only = true;
+        })
+        .unwrap();
+        assert!(config.identities["work"].tailnet_only);
+        let saved = Config::load_from(&path).unwrap();
+        assert!(saved.identities["work"].tailnet_only);
+        assert_eq!(
+            saved.identities["work"].extra["keychainService"],
+            "remote-juggler.gitlab.work"
+        );
+
+        let missing = Config::update_identity_at(&path, "nobody", |_| unreachable!());
+        assert!(matches!(missing, Err(ConfigError::NoIdentity(name)) if name == "nobody"));
+
+        Config::update_at(&path, |config| config.settings.keep_remote_urls = true).unwrap();
+        assert!(Config::load_from(&path).unwrap().settings.keep_remote_urls);
+    }
 
     #[test]
     fn test_identity_display_name() {
@@ -496,6 +1516,15 @@ mod tests {
             organizations: vec![],
             gpg: GpgConfig::default(),
             keepassxc_entry: None,
+            cloud: CloudConfig::default(),
+            ssh_certificate: None,
+            token_env_var: None,
+            ssh_policy: SshPolicy::default(),
+            pkcs11_provider: None,
+            yubikey_serial: None,
+            verify_repo: None,
+            tailnet_only: false,
+            extra: HashMap::new(),
         };
 
         assert_eq!(identity.display_name(), "testuser (github)");
@@ -514,6 +1543,15 @@ mod tests {
             organizations: vec![],
             gpg: GpgConfig::default(),
             keepassxc_entry: None,
+            cloud: CloudConfig::default(),
+            ssh_certificate: None,
+            token_env_var: None,
+            ssh_policy: SshPolicy::default(),
+            pkcs11_provider: None,
+            yubikey_serial: None,
+            verify_repo: None,
+            tailnet_only: false,
+            extra: HashMap::new(),
         };
 
         let security_key = Identity {
@@ -527,12 +1565,138 @@ mod tests {
             organizations: vec![],
             gpg: GpgConfig::default(),
             keepassxc_entry: None,
+            cloud: CloudConfig::default(),
+            ssh_certificate: None,
+            token_env_var: None,
+            ssh_policy: SshPolicy::default(),
+            pkcs11_provider: None,
+            yubikey_serial: None,
+            verify_repo: None,
+            tailnet_only: false,
+            extra: HashMap::new(),
         };
 
         assert!(!regular.is_security_key());
         assert!(security_key.is_security_key());
     }
 
+    #[test]
+    fn test_identity_cloud_config() {
+        let json = r#"{
+            "provider": "github", "host": "github-work", "hostname": "github.com",
+            "user": "me", "email": "me@acme.dev", "sshKeyPath": "", "credentialSource": "none",
+            "cloud": { "gcpProject": "acme-prod", "azureVault": "acme-kv" }
+        }"#;
+        let identity: Identity = serde_json::from_str(json).unwrap();
+        assert_eq!(identity.cloud.gcp_project.as_deref(), Some("acme-prod"));
+        assert_eq!(identity.cloud.azure_vault.as_deref(), Some("acme-kv"));
+
+        let plain = Identity {
+            cloud: CloudConfig::default(),
+            ..identity
+        };
+        let serialized = serde_json::to_string(&plain).unwrap();
+        assert!(!serialized.contains("cloud"));
+    }
+
+    #[test]
+    fn test_ssh_signing_key() {
+        let json = r#"{
+            "provider": "github", "host": "github-work", "hostname": "github.com",
+            "user": "me", "email": "me@acme.dev", "sshKeyPath": "~/.ssh/id_ed25519_work",
+            "credentialSource": "none",
+            "gpg": { "keyId": "ABCD1234", "format": "ssh", "signCommits": true }
+        }"#;
+        let mut identity: Identity = serde_json::from_str(json).unwrap();
+        assert_eq!(identity.gpg.format, SigningFormat::Ssh);
+        assert!(identity.has_gpg_signing());
+        assert_eq!(
+            identity.signing_key().as_deref(),
+            Some("~/.ssh/id_ed25519_work.pub")
+        );
+
+        identity.gpg.ssh_key_path = Some("~/.ssh/signing.pub".to_string());
+        assert_eq!(
+            identity.signing_key().as_deref(),
+            Some("~/.ssh/signing.pub")
+        );
+
+        identity.gpg.format = SigningFormat::Gpg;
+        assert_eq!(identity.signing_key().as_deref(), Some("ABCD1234"));
+        identity.gpg.signing_subkey = Some("0xeeee5555ffff6666!".to_string());
+        assert_eq!(identity.signing_key().as_deref(), Some("EEEE5555FFFF6666!"));
+        identity.gpg.key_id.clear();
+        assert!(!identity.has_gpg_signing());
+    }
+
+    #[test]
+    fn test_settings_keystores() {
+        let json = r#"{
+            "defaultProvider": "github", "autoDetect": true, "useKeychain": false,
+            "gpgSign": false, "gpgVerifyWithProvider": false, "fallbackToSSH": true,
+            "verboseLogging": false,
+            "keystores": [{ "name": "work", "path": "~/work/keys.kdbx" }]
+        }"#;
+        let settings: Settings = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            settings.keystores,
+            vec![KeystoreDatabase {
+                name: "work".to_string(),
+                path: "~/work/keys.kdbx".to_string(),
+            }]
+        );
+        let serialized = serde_json::to_string(&Settings::default()).unwrap();
+        assert!(!serialized.contains("keystores"));
+    }
+
+    #[test]
+    fn test_settings_signing_rule() {
+        let json = r#"{
+            "defaultProvider": "github", "autoDetect": true, "useKeychain": false,
+            "gpgSign": true, "gpgVerifyWithProvider": false, "fallbackToSSH": true,
+            "verboseLogging": false,
+            "signingRules": [
+                { "path": "/src/work", "sign": true, "keyId": "ABCD1234" },
+                { "path": "/src/work/scratch", "sign": false }
+            ]
+        }"#;
+        let settings: Settings = serde_json::from_str(json).unwrap();
+        let rule = settings.signing_rule(Path::new("/src/work/api")).unwrap();
+        assert_eq!(rule.key_id.as_deref(), Some("ABCD1234"));
+        let rule = settings
+            .signing_rule(Path::new("/src/work/scratch/tmp"))
+            .unwrap();
+        assert_eq!((rule.sign, rule.key_id.as_deref()), (Some(false), None));
+        // Whole components only
+        assert_eq!(settings.signing_rule(Path::new("/src/workshop")), None);
+        let serialized = serde_json::to_string(&settings).unwrap();
+        assert!(serialized.contains(r#"{"path":"/src/work/scratch","sign":false}"#));
+    }
+
+    #[test]
+    fn test_settings_auto_lock() {
+        let json = r#"{
+            "defaultProvider": "github", "autoDetect": true, "useKeychain": false,
+            "gpgSign": false, "gpgVerifyWithProvider": false, "fallbackToSSH": true,
+            "verboseLogging": false,
+            "autoLock": { "maximumSecurity": 2 }
+        }"#;
+        let settings: Settings = serde_json::from_str(json).unwrap();
+        let lock = &settings.auto_lock;
+        assert_eq!(
+            lock.timeout(&SecurityMode::MaximumSecurity),
+            Some(Duration::from_secs(120))
+        );
+        // Unset modes keep their defaults
+        assert_eq!(
+            lock.timeout(&SecurityMode::DeveloperWorkflow),
+            Some(Duration::from_secs(1800))
+        );
+        assert_eq!(lock.timeout(&SecurityMode::TrustedWorkstation), None);
+        assert_eq!(settings.ssh_agent, SshAgentSettings::default());
+        assert!(settings.ssh_agent.add_on_switch);
+    }
+
     #[test]
     #[ignore] // Requires real config file - run manually with --ignored
     fn test_load_real_config() {
@@ -585,12 +1749,55 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_profiles_group_three_key_types() {
+        let work = |host: &str| Identity {
+            ssh_key_path: format!("~/.ssh/{}", host),
+            ..identity("gitlab", host, "gitlab.com", "Work User", "w@example.com")
+        };
+        let config = ConfigBuilder::new()
+            .identity(
+                "gitlab-work-piv",
+                Identity {
+                    ssh_key_path: String::new(),
+                    pkcs11_provider: Some("/usr/lib/opensc-pkcs11.so".to_string()),
+                    ..work("gitlab-work-piv")
+                },
+            )
+            .identity("gitlab-work-sk", work("gitlab-work-sk"))
+            .identity("gitlab-work", work("gitlab-work"))
+            .build();
+
+        let profiles = config.profiles();
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].name, "gitlab-work");
+        let types: Vec<_> = profiles[0].variants.iter().map(|v| &v.key_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                &SshKeyType::Regular,
+                &SshKeyType::Fido2,
+                &SshKeyType::Pkcs11
+            ]
+        );
+        assert_eq!(
+            profiles[0]
+                .pkcs11_variant()
+                .unwrap()
+                .identity
+                .pkcs11_library(),
+            Some("/usr/lib/opensc-pkcs11.so")
+        );
+    }
+
     #[test]
     fn test_ssh_key_type_display() {
         assert_eq!(SshKeyType::Regular.display_name(), "SSH Key");
         assert_eq!(SshKeyType::Fido2.display_name(), "Security Key (FIDO2)");
         assert_eq!(SshKeyType::Regular.short_name(), "SSH");
         assert_eq!(SshKeyType::Fido2.short_name(), "SK");
+        assert_eq!(SshKeyType::Pkcs11.display_name(), "Smart Card (PIV)");
+        assert_eq!(SshKeyType::Pkcs11.short_name(), "PIV");
     }
 
     #[test]
@@ -607,7 +1814,8 @@ mod tests {
                     profile.name
                 );
 
-                // If has multiple variants, should have both types
+                // If has multiple variants, should have a regular and a
+                // hardware one
                 if profile.has_multiple_variants() {
                     assert!(
                         profile.regular_variant().is_some(),
@@ -615,8 +1823,8 @@ mod tests {
                         profile.name
                     );
                     assert!(
-                        profile.fido2_variant().is_some(),
-                        "Profile {} with multiple variants should have fido2",
+                        profile.fido2_variant().is_some() || profile.pkcs11_variant().is_some(),
+                        "Profile {} with multiple variants should have fido2 or pkcs11",
                         profile.name
                     );
                 }
@@ -6,7 +6,11 @@ use proptest::prelude::*;
 use proptest::strategy::ValueTree;
 use std::collections::HashMap;
 
-use crate::config::{Config, GpgConfig, Identity, Settings, State};
+use crate::config::{
+    AllowedSignersSettings, AutoLock, CloudConfig, Config, GpgConfig, GpgExpirySettings, Identity,
+    KeyAuditSettings, MetricsSettings, PinStorageMethod, RestApiSettings, Settings,
+    SshAgentSettings, SshPolicy, State, TelemetrySettings,
+};
 
 // =============================================================================
 // Custom Strategies
@@ -73,11 +77,13 @@ fn arb_gpg_config() -> impl Strategy<Value = GpgConfig> {
         prop::bool::ANY,
         // security_mode: 0=MaximumSecurity, 1=DeveloperWorkflow, 2=TrustedWorkstation
         prop::sample::select(vec![0u32, 1, 2]),
-        // pin_storage_method: None, or one of "tpm", "secure_enclave", "keychain"
+        // pin_storage_method: None, or any backend
         prop::option::of(prop::sample::select(vec![
-            "tpm".to_string(),
-            "secure_enclave".to_string(),
-            "keychain".to_string(),
+            PinStorageMethod::Tpm,
+            PinStorageMethod::SecureEnclave,
+            PinStorageMethod::Keychain,
+            PinStorageMethod::SecretService,
+            PinStorageMethod::EncryptedFile,
         ])),
     )
         .prop_map(
@@ -89,14 +95,20 @@ fn arb_gpg_config() -> impl Strategy<Value = GpgConfig> {
                 security_mode_idx,
                 pin_storage_method,
             )| {
-                use crate::config::SecurityMode;
+                use crate::config::{SecurityMode, SigningFormat};
                 GpgConfig {
                     key_id: key_id.unwrap_or_default(),
+                    signing_subkey: None,
+                    format: SigningFormat::Gpg,
+                    ssh_key_path: None,
+                    gitsign: None,
                     sign_commits,
                     sign_tags,
                     auto_signoff,
                     security_mode: SecurityMode::from_index(security_mode_idx),
                     pin_storage_method,
+                    hardware_key: false,
+                    extra: HashMap::new(),
                 }
             },
         )
@@ -157,6 +169,15 @@ fn arb_identity() -> impl Strategy<Value = Identity> {
                     organizations,
                     gpg,
                     keepassxc_entry,
+                    cloud: CloudConfig::default(),
+                    ssh_certificate: None,
+                    token_env_var: None,
+                    ssh_policy: SshPolicy::default(),
+                    pkcs11_provider: None,
+                    yubikey_serial: None,
+                    verify_repo: None,
+                    tailnet_only: false,
+                    extra: HashMap::new(),
                 }
             },
         )
@@ -191,6 +212,34 @@ fn arb_settings() -> impl Strategy<Value = Settings> {
                     gpg_verify_with_provider,
                     fallback_to_ssh,
                     verbose_logging,
+                    resolve_order: Vec::new(),
+                    keystores: Vec::new(),
+                    auto_lock: AutoLock::default(),
+                    ssh_agent: SshAgentSettings::default(),
+                    key_audit: KeyAuditSettings::default(),
+                    gpg_expiry: GpgExpirySettings::default(),
+                    allowed_signers: AllowedSignersSettings::default(),
+                    signing_rules: Vec::new(),
+                    refuse_wrong_yubikey: false,
+                    directory_rules: Vec::new(),
+                    branch_rules: Vec::new(),
+                    workspace_roots: Vec::new(),
+                    keep_remote_urls: false,
+                    switch_excluded_repos: Vec::new(),
+                    policies: Vec::new(),
+                    bookmarks: Vec::new(),
+                    terminal_command: String::new(),
+                    editor_command: String::new(),
+                    provider_hosts: Vec::new(),
+                    ci_secrets: Vec::new(),
+                    rest_api: RestApiSettings::default(),
+                    secret_service: false,
+                    askpass: false,
+                    tailnet_only_backends: Vec::new(),
+                    metrics: MetricsSettings::default(),
+                    telemetry: TelemetrySettings::default(),
+                    event_hooks: Vec::new(),
+                    extra: HashMap::new(),
                 }
             },
         )
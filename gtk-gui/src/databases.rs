@@ -0,0 +1,309 @@
+//! Multiple key store databases
+//!
+//! Besides the default database, `settings.keystores` can name others
+//! (personal, work, client-X). One of them is active at a time: the CLI is
+//! pointed at it through `REMOTE_JUGGLER_KDBX_PATH` for the rest of the
+//! session. Each database has its own master password; passwords entered
+//! for the inactive ones are kept in memory so switching back, and
+//! searching across every unlocked database, doesn't prompt again.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use serde::Deserialize;
+
+use crate::cli;
+use crate::cli_cache;
+use crate::config::{Config, KeystoreDatabase};
+use crate::error::Error;
+use crate::event_hooks::{self, Event};
+use crate::keyring::MASTER_PASSWORD_ENV;
+
+/// Environment variable the CLI reads the database path from
+pub const PATH_ENV: &str = "REMOTE_JUGGLER_KDBX_PATH";
+
+/// The CLI's database when `REMOTE_JUGGLER_KDBX_PATH` is unset
+pub const DEFAULT_PATH: &str = "~/.remotejuggler/keys.kdbx";
+
+/// Name shown for the default database
+pub const DEFAULT_NAME: &str = "Default";
+
+pub use crate::platform::expand_tilde;
+
+/// The default database, as the CLI would pick it without an override
+pub fn default_database() -> KeystoreDatabase {
+    KeystoreDatabase {
+        name: DEFAULT_NAME.to_string(),
+        path: std::env::var(PATH_ENV).unwrap_or_else(|_| DEFAULT_PATH.to_string()),
+    }
+}
+
+/// The default database followed by the configured ones
+///
+/// Entries without a name, or pointing at the default file, are skipped.
+pub fn all(keystores: &[KeystoreDatabase]) -> Vec<KeystoreDatabase> {
+    let default = default_database();
+    let default_path = expand_tilde(&default.path);
+    let mut databases = vec![default];
+    for db in keystores {
+        let duplicate = databases.iter().any(|d| d.name == db.name);
+        if db.name.is_empty() || duplicate || expand_tilde(&db.path) == default_path {
+            continue;
+        }
+        databases.push(db.clone());
+    }
+    databases
+}
+
+/// Databases from the loaded config
+pub fn from_config(config: Option<&Config>) -> Vec<KeystoreDatabase> {
+    all(config
+        .map(|c| c.settings.keystores.as_slice())
+        .unwrap_or_default())
+}
+
+/// Which database is active and the passwords known for each
+#[derive(Debug, Default)]
+pub struct Session {
+    /// None for the default database
+    active: Option<String>,
+    passwords: HashMap<String, String>,
+}
+
+impl Session {
+    pub fn active_name(&self) -> &str {
+        self.active.as_deref().unwrap_or(DEFAULT_NAME)
+    }
+
+    /// Switch to `name`, given the active database's current password
+    ///
+    /// Returns the password to use for the new database, if one is known.
+    pub fn switch(&mut self, name: &str, current_password: Option<String>) -> Option<String> {
+        let outgoing = self.active_name().to_string();
+        match current_password {
+            Some(password) => self.passwords.insert(outgoing, password),
+            None => self.passwords.remove(&outgoing),
+        };
+        self.active = (name != DEFAULT_NAME).then(|| name.to_string());
+        self.passwords.get(name).cloned()
+    }
+
+    pub fn remember(&mut self, name: &str, password: &str) {
+        self.passwords
+            .insert(name.to_string(), password.to_string());
+    }
+
+    pub fn password(&self, name: &str) -> Option<&str> {
+        self.passwords.get(name).map(String::as_str)
+    }
+}
+
+fn session() -> &'static Mutex<Session> {
+    static SESSION: OnceLock<Mutex<Session>> = OnceLock::new();
+    SESSION.get_or_init(Mutex::default)
+}
+
+/// Name of the active database
+pub fn active_name() -> String {
+    session()
+        .lock()
+        .map(|s| s.active_name().to_string())
+        .unwrap_or_else(|_| DEFAULT_NAME.to_string())
+}
+
+pub fn is_default_active() -> bool {
+    active_name() == DEFAULT_NAME
+}
+
+/// Point the CLI at another database for the rest of the session
+pub fn activate(db: &KeystoreDatabase) {
+    let Ok(mut session) = session().lock() else {
+        return;
+    };
+    if session.active_name() == db.name {
+        return;
+    }
+    let current = cli::session_env_value(MASTER_PASSWORD_ENV);
+    let password = session.switch(&db.name, current);
+    let path = expand_tilde(&db.path);
+    let is_default = db.name == DEFAULT_NAME;
+    cli::set_session_env(
+        PATH_ENV,
+        (!is_default).then(|| path.to_string_lossy()).as_deref(),
+    );
+    cli::set_session_env(MASTER_PASSWORD_ENV, password.as_deref());
+    // Cached results came from the other database
+    cli_cache::global().invalidate();
+}
+
+/// Use `password` for a database from now on
+pub fn unlock(name: &str, password: &str) {
+    let Ok(mut session) = session().lock() else {
+        return;
+    };
+    session.remember(name, password);
+    if session.active_name() == name {
+        cli::set_session_env(MASTER_PASSWORD_ENV, Some(password));
+        cli_cache::global().invalidate();
+        event_hooks::emit(Event::KeystoreUnlocked {
+            database: name.to_string(),
+        });
+    }
+}
+
+/// Drop the passwords held for every database, e.g. when auto-locking
+pub fn forget_passwords() {
+    if let Ok(mut session) = session().lock() {
+        session.passwords.clear();
+    }
+}
+
+/// Returns true if the GUI holds a password for the database
+///
+/// The default database may still auto-unlock through the HSM without one.
+pub fn has_password(name: &str) -> bool {
+    let Ok(session) = session().lock() else {
+        return false;
+    };
+    if session.active_name() == name {
+        return cli::has_session_env(MASTER_PASSWORD_ENV);
+    }
+    session.password(name).is_some()
+}
+
+/// Environment that points one CLI call at `db`, or None if it is locked
+///
+/// The active database needs no overrides. The default database can always
+/// be tried, since the CLI may unlock it through the HSM.
+pub fn env_for(db: &KeystoreDatabase) -> Option<Vec<(String, String)>> {
+    let session = session().lock().ok()?;
+    if session.active_name() == db.name {
+        return Some(Vec::new());
+    }
+    let mut env = vec![(
+        PATH_ENV.to_string(),
+        expand_tilde(&db.path).to_string_lossy().into_owned(),
+    )];
+    match session.password(&db.name) {
+        Some(password) => env.push((MASTER_PASSWORD_ENV.to_string(), password.to_string())),
+        None if db.name == DEFAULT_NAME => {}
+        None => return None,
+    }
+    Some(env)
+}
+
+/// One result of `keys search --json`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub entry_path: String,
+    pub score: i64,
+    #[serde(default)]
+    pub match_context: String,
+}
+
+#[derive(Deserialize)]
+struct SearchOutput {
+    results: Vec<SearchHit>,
+}
+
+pub fn search_args(query: &str) -> Vec<String> {
+    cli::args(&["keys", "search", query, "--json"])
+}
+
+/// Parse the output of `keys search <query> --json`
+pub fn parse_search(output: &str) -> Result<Vec<SearchHit>, Error> {
+    cli::reported_error(output).map_err(Error::from_cli)?;
+    let json = output
+        .lines()
+        .find(|l| l.trim_start().starts_with('{'))
+        .unwrap_or_default();
+    serde_json::from_str::<SearchOutput>(json)
+        .map(|o| o.results)
+        .map_err(|e| Error::InvalidOutput {
+            program: cli::CLI_PROGRAM,
+            message: e.to_string(),
+        })
+}
+
+/// Combine per-database results, best matches first
+pub fn merge_hits(per_database: Vec<(String, Vec<SearchHit>)>) -> Vec<(String, SearchHit)> {
+    let mut hits: Vec<(String, SearchHit)> = per_database
+        .into_iter()
+        .flat_map(|(db, hits)| hits.into_iter().map(move |hit| (db.clone(), hit)))
+        .collect();
+    hits.sort_by(|a, b| b.1.score.cmp(&a.1.score).then_with(|| a.0.cmp(&b.0)));
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db(name: &str, path: &str) -> KeystoreDatabase {
+        KeystoreDatabase {
+            name: name.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_all_skips_duplicates() {
+        let databases = all(&[
+            db("work", "~/work/keys.kdbx"),
+            db("work", "~/other.kdbx"),
+            db("", "~/nameless.kdbx"),
+            db("client-x", "/srv/client-x.kdbx"),
+        ]);
+        let names: Vec<&str> = databases.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, vec![DEFAULT_NAME, "work", "client-x"]);
+    }
+
+    #[test]
+    fn test_session_switch_keeps_passwords() {
+        let mut session = Session::default();
+        assert_eq!(session.active_name(), DEFAULT_NAME);
+
+        // Leaving the default database remembers its password
+        assert_eq!(session.switch("work", Some("default-pw".to_string())), None);
+        assert_eq!(session.active_name(), "work");
+        session.remember("work", "work-pw");
+
+        assert_eq!(
+            session.switch(DEFAULT_NAME, Some("work-pw".to_string())),
+            Some("default-pw".to_string())
+        );
+        assert_eq!(session.password("work"), Some("work-pw"));
+
+        // Switching away while locked forgets a stale password
+        session.switch("work", None);
+        assert_eq!(session.password(DEFAULT_NAME), None);
+    }
+
+    #[test]
+    fn test_parse_search() {
+        let output = r#"{"query":"git","count":1,"results":[{"entryPath":"RemoteJuggler/API/GITHUB_TOKEN","title":"GITHUB_TOKEN","score":85,"matchContext":"title","matchField":"title"}]}"#;
+        let hits = parse_search(output).unwrap();
+        assert_eq!(hits[0].entry_path, "RemoteJuggler/API/GITHUB_TOKEN");
+        assert_eq!(hits[0].score, 85);
+        assert!(parse_search("[ERROR] Failed to unlock key store").is_err());
+    }
+
+    #[test]
+    fn test_merge_hits_orders_by_score() {
+        let hit = |path: &str, score| SearchHit {
+            entry_path: path.to_string(),
+            score,
+            match_context: String::new(),
+        };
+        let merged = merge_hits(vec![
+            ("Default".to_string(), vec![hit("A", 40)]),
+            ("work".to_string(), vec![hit("B", 90), hit("C", 10)]),
+        ]);
+        let order: Vec<(&str, &str)> = merged
+            .iter()
+            .map(|(db, h)| (db.as_str(), h.entry_path.as_str()))
+            .collect();
+        assert_eq!(order, vec![("work", "B"), ("Default", "A"), ("work", "C")]);
+    }
+}
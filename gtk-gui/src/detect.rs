@@ -0,0 +1,547 @@
+//! Which identity applies to a repository
+//!
+//! A branch rule for the checked-out branch decides first, then a
+//! directory rule covering the repository. Otherwise this
+//! looks at its remotes the way the CLI's `detect` looks at origin: an SSH
+//! host alias that is an identity's `host` wins, then an
+//! identity whose organizations prefix the remote's path, then one on the
+//! hostname that complies with the policy covering the remote, then one on
+//! the same provider and hostname, and last any identity on the provider.
+//! Self-hosted instances are known by provider through `providerHosts`.
+//! Aliases defined in ~/.ssh/config are resolved to their `HostName`, so a
+//! remote using a hand-written alias still finds its hostname. Every
+//! remote is tried, origin first; the most certain match wins. Mercurial
+//! repositories are matched by their paths, default first. A linked
+//! worktree follows the directory rules of its main checkout too, since it
+//! shares that checkout's config and history. The GUI's
+//! repository checks and the C API share this, so frontends, daemons and
+//! shell prompts built on either agree on the answer.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::branches;
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, Identity};
+use crate::error::{CliError, Error};
+use crate::hg;
+use crate::policy;
+use crate::provider_hosts;
+use crate::pubkeys::Provider;
+use crate::ssh_config;
+
+/// A remote URL's host and repository path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteUrl {
+    /// As written: an SSH alias or a hostname, without user or port
+    pub host: String,
+    /// e.g. "acme/platform/api", without `.git`
+    pub path: String,
+}
+
+impl RemoteUrl {
+    /// Parse `git@host:org/repo.git`, `ssh://git@host:22/org/repo.git`,
+    /// `https://host/org/repo` and `git://host/org/repo`
+    pub fn parse(url: &str) -> Option<Self> {
+        let url = url.trim();
+        let (authority, path) = match url.split_once("://") {
+            Some((_, rest)) => rest.split_once('/')?,
+            // scp-like syntax, unless it's a local path
+            None if !url.starts_with('/') && !url.starts_with('.') => url.split_once(':')?,
+            None => return None,
+        };
+        let host = authority.rsplit('@').next().unwrap_or(authority);
+        let host = host.split(':').next().unwrap_or(host).to_lowercase();
+        let path = path
+            .trim_matches('/')
+            .trim_end_matches(".git")
+            .trim_start_matches('~')
+            .to_string();
+        (!host.is_empty() && !path.is_empty()).then_some(Self { host, path })
+    }
+
+    /// The path without the repository name, e.g. "acme/platform"
+    pub fn org_path(&self) -> &str {
+        self.path
+            .rsplit_once('/')
+            .map(|(org, _)| org)
+            .unwrap_or_default()
+    }
+}
+
+/// The provider a hostname or alias belongs to, by name; Codeberg and
+/// Forgejo hosts are Gitea, `git.sr.ht` is sourcehut
+pub fn provider_for(host: &str) -> Option<&'static str> {
+    let host = host.to_lowercase();
+    [
+        ("gitlab", "gitlab"),
+        ("github", "github"),
+        ("bitbucket", "bitbucket"),
+        ("codeberg", "gitea"),
+        ("forgejo", "gitea"),
+        ("gitea", "gitea"),
+        ("sr.ht", "sourcehut"),
+        ("sourcehut", "sourcehut"),
+    ]
+    .into_iter()
+    .find(|(name, _)| host.contains(name))
+    .map(|(_, provider)| provider)
+}
+
+/// A provider name as `provider_for` gives it, e.g. "gitea" for "codeberg"
+fn canonical_provider(name: &str) -> String {
+    Provider::from_name(name)
+        .map(|provider| provider.name().to_string())
+        .unwrap_or_else(|| name.to_lowercase())
+}
+
+/// Why an identity was picked, most certain first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// A branch rule matches the checked-out branch
+    Branch,
+    /// A directory rule covers the repository
+    Directory,
+    /// The remote uses the identity's SSH host alias
+    HostAlias,
+    /// The remote's path is under one of the identity's organizations
+    Organization,
+    /// The identity's email and signing comply with the policy covering
+    /// the remote
+    Policy,
+    /// Same provider and hostname
+    Hostname,
+    /// Only the provider matches
+    Provider,
+}
+
+impl Reason {
+    /// Whether the match is certain rather than inferred
+    pub fn is_exact(&self) -> bool {
+        matches!(
+            self,
+            Self::Branch | Self::Directory | Self::HostAlias | Self::Organization
+        )
+    }
+
+    pub fn display_text(&self) -> &'static str {
+        match self {
+            Self::Branch => "branch rule",
+            Self::Directory => "directory rule",
+            Self::HostAlias => "SSH host alias",
+            Self::Organization => "organization",
+            Self::Policy => "organization policy",
+            Self::Hostname => "provider and hostname",
+            Self::Provider => "provider only",
+        }
+    }
+}
+
+/// The identity that applies to a repository, and why
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IdentityMatch {
+    pub identity: String,
+    /// The remote it was found through, e.g. "origin"; empty for a
+    /// branch or directory rule
+    pub remote: String,
+    /// The remote's URL, the directory rule's path or the branch
+    pub url: String,
+    pub reason: Reason,
+}
+
+impl IdentityMatch {
+    pub fn display_text(&self) -> String {
+        if self.reason == Reason::Directory {
+            return format!("{} (directory rule {})", self.identity, self.url);
+        }
+        format!(
+            "{} ({} of {})",
+            self.identity,
+            self.reason.display_text(),
+            self.remote
+        )
+    }
+}
+
+fn matches_organization(identity: &Identity, org_path: &str) -> bool {
+    !org_path.is_empty()
+        && identity.organizations.iter().any(|org| {
+            // sourcehut paths start with the owner's `~`
+            let org = org.trim_matches('/').trim_start_matches('~');
+            !org.is_empty() && org_path.trim_start_matches('~').starts_with(org)
+        })
+}
+
+/// The identity for one remote URL; `hostnames` maps SSH aliases to their
+/// `HostName`
+pub fn match_url(
+    config: &Config,
+    url: &str,
+    hostnames: &BTreeMap<String, String>,
+) -> Option<(String, Reason)> {
+    let remote = RemoteUrl::parse(url)?;
+    let hostname = hostnames
+        .get(&remote.host)
+        .map(|h| h.to_lowercase())
+        .unwrap_or_else(|| remote.host.clone());
+    let hosts = &config.settings.provider_hosts;
+    let provider = provider_hosts::provider_name(hosts, &hostname)
+        .or_else(|| provider_hosts::provider_name(hosts, &remote.host))
+        .or_else(|| provider_for(&remote.host).map(str::to_string))
+        .or_else(|| provider_for(&hostname).map(str::to_string));
+    let org_path = remote.org_path();
+
+    let mut names: Vec<&String> = config.identities.keys().collect();
+    names.sort();
+    let find = |test: &dyn Fn(&Identity) -> bool| {
+        names
+            .iter()
+            .find(|name| test(&config.identities[name.as_str()]))
+            .map(|name| name.to_string())
+    };
+    let same_provider = |identity: &Identity| {
+        provider.as_deref().is_some_and(|p| {
+            canonical_provider(&identity.provider) == canonical_provider(p)
+                || provider_hosts::provider_name(hosts, &identity.hostname)
+                    .is_some_and(|name| canonical_provider(&name) == canonical_provider(p))
+        })
+    };
+
+    if let Some(name) = find(&|i| !i.host.is_empty() && i.host.eq_ignore_ascii_case(&remote.host)) {
+        return Some((name, Reason::HostAlias));
+    }
+    if let Some(name) = find(&|i| matches_organization(i, org_path)) {
+        return Some((name, Reason::Organization));
+    }
+    let target = format!("{}/{}", hostname, remote.path);
+    if let Some(policy) = policy::policy_for(config, &target) {
+        let complies = |i: &Identity| {
+            i.hostname.eq_ignore_ascii_case(&hostname) && policy::identity_complies(policy, i)
+        };
+        if let Some(name) = find(&complies) {
+            return Some((name, Reason::Policy));
+        }
+    }
+    let on_hostname = |i: &Identity| {
+        i.hostname.eq_ignore_ascii_case(&hostname) && (same_provider(i) || provider.is_none())
+    };
+    if let Some(name) = find(&on_hostname) {
+        return Some((name, Reason::Hostname));
+    }
+    find(&same_provider).map(|name| (name, Reason::Provider))
+}
+
+/// Parse `git config --get-regexp '^remote\..*\.url$'` into (remote, URL),
+/// origin first
+pub fn parse_remotes(text: &str) -> Vec<(String, String)> {
+    let mut remotes: Vec<(String, String)> = text
+        .lines()
+        .filter_map(|line| {
+            let (key, url) = line.trim().split_once(char::is_whitespace)?;
+            let name = key.strip_prefix("remote.")?.strip_suffix(".url")?;
+            Some((name.to_string(), url.trim().to_string()))
+        })
+        .collect();
+    remotes.sort_by_key(|(name, _)| name != "origin");
+    remotes
+}
+
+/// The most certain match among the remotes; earlier remotes win ties
+pub fn detect_with(
+    config: &Config,
+    remotes: &[(String, String)],
+    hostnames: &BTreeMap<String, String>,
+) -> Option<IdentityMatch> {
+    remotes
+        .iter()
+        .filter_map(|(remote, url)| {
+            let (identity, reason) = match_url(config, url, hostnames)?;
+            Some(IdentityMatch {
+                identity,
+                remote: remote.clone(),
+                url: url.clone(),
+                reason,
+            })
+        })
+        .min_by_key(|found| found.reason)
+}
+
+/// What kind of checkout a repository is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checkout {
+    Main,
+    /// A linked worktree (`git worktree add`)
+    Worktree,
+    /// A submodule checked out inside `superproject`
+    Submodule {
+        superproject: PathBuf,
+    },
+}
+
+/// Where a checkout's files and git data are
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// The checkout's top directory
+    pub toplevel: PathBuf,
+    /// The git directory its worktrees share: config, hooks and objects
+    pub common_dir: PathBuf,
+    /// The main checkout's top directory; only a linked worktree's differs
+    /// from `toplevel`
+    pub main: PathBuf,
+    pub checkout: Checkout,
+}
+
+impl Layout {
+    pub fn display_text(&self) -> String {
+        match &self.checkout {
+            Checkout::Main => "repository".to_string(),
+            Checkout::Worktree => format!("worktree of {}", self.main.display()),
+            Checkout::Submodule { superproject } => {
+                format!("submodule of {}", superproject.display())
+            }
+        }
+    }
+}
+
+/// Parse `git rev-parse --path-format=absolute --show-toplevel
+/// --git-common-dir --git-dir --show-superproject-working-tree`
+pub fn parse_layout(text: &str) -> Option<Layout> {
+    let mut lines = text.lines().map(str::trim);
+    let toplevel = PathBuf::from(lines.next().filter(|l| !l.is_empty())?);
+    let common_dir = PathBuf::from(lines.next()?);
+    let git_dir = PathBuf::from(lines.next()?);
+    let superproject = lines.next().filter(|l| !l.is_empty());
+    let (checkout, main) = match superproject {
+        Some(superproject) => (
+            Checkout::Submodule {
+                superproject: PathBuf::from(superproject),
+            },
+            toplevel.clone(),
+        ),
+        None if git_dir != common_dir => {
+            let main = match common_dir.file_name() {
+                Some(name) if name == ".git" => common_dir.parent().map(Path::to_path_buf),
+                _ => None,
+            };
+            (
+                Checkout::Worktree,
+                main.unwrap_or_else(|| common_dir.clone()),
+            )
+        }
+        None => (Checkout::Main, toplevel.clone()),
+    };
+    Some(Layout {
+        toplevel,
+        common_dir,
+        main,
+        checkout,
+    })
+}
+
+fn git_options() -> RunOptions {
+    RunOptions {
+        timeout: Duration::from_secs(10),
+        retries: 0,
+        cancel: None,
+    }
+}
+
+/// The layout of the checkout containing `repo`; blocks
+pub fn layout(repo: &Path) -> Result<Layout, Error> {
+    let args = cli::args(&[
+        "-C",
+        &repo.to_string_lossy(),
+        "rev-parse",
+        "--path-format=absolute",
+        "--show-toplevel",
+        "--git-common-dir",
+        "--git-dir",
+        "--show-superproject-working-tree",
+    ]);
+    let out = cli::run_program("git", &args, &git_options()).map_err(Error::from_cli)?;
+    parse_layout(&out).ok_or_else(|| Error::InvalidOutput {
+        program: "git",
+        message: format!("{} has no working tree", repo.display()),
+    })
+}
+
+/// The `HostName` of each alias in ~/.ssh/config
+pub fn ssh_hostnames() -> BTreeMap<String, String> {
+    ssh_config::path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .map(|text| ssh_config::host_names(&text))
+        .unwrap_or_default()
+}
+
+/// The repository's remotes, origin first, or a Mercurial repository's
+/// paths; blocks
+pub fn remotes(repo: &Path) -> Result<Vec<(String, String)>, Error> {
+    if hg::is_hg(repo) {
+        return hg::remotes(repo);
+    }
+    let args = vec![
+        "-C".to_string(),
+        repo.to_string_lossy().into_owned(),
+        "config".to_string(),
+        "--get-regexp".to_string(),
+        r"^remote\..*\.url$".to_string(),
+    ];
+    match cli::run_program("git", &args, &git_options()) {
+        Ok(out) => Ok(parse_remotes(&out)),
+        // Exit status 1: no remotes
+        Err(CliError::NonZeroExit { code: Some(1), .. }) => Ok(Vec::new()),
+        Err(e) => Err(Error::from_cli(e)),
+    }
+}
+
+/// The identity that applies to the repository at `repo`, None if no
+/// remote matches; blocks
+pub fn detect(config: &Config, repo: &Path) -> Result<Option<IdentityMatch>, Error> {
+    if !config.settings.branch_rules.is_empty() && !hg::is_hg(repo) {
+        let repo_path = repo.canonicalize().unwrap_or_else(|_| repo.to_path_buf());
+        if let Some(branch) = branches::current_branch(repo)? {
+            if let Some(rule) = branches::rule_for(config, &repo_path, &branch) {
+                return Ok(Some(IdentityMatch {
+                    identity: rule.identity.clone(),
+                    remote: String::new(),
+                    url: branch,
+                    reason: Reason::Branch,
+                }));
+            }
+        }
+    }
+    detect_default(config, repo)
+}
+
+/// [`detect`] without the branch rules: the identity the repository
+/// commits as on any other branch; blocks
+pub fn detect_default(config: &Config, repo: &Path) -> Result<Option<IdentityMatch>, Error> {
+    let mut paths = vec![repo.canonicalize().unwrap_or_else(|_| repo.to_path_buf())];
+    if let Ok(layout) = layout(repo) {
+        paths.extend([layout.toplevel, layout.main]);
+    }
+    if let Some(rule) = paths.iter().find_map(|path| {
+        config
+            .settings
+            .directory_rule(path)
+            .filter(|rule| config.identities.contains_key(&rule.identity))
+    }) {
+        return Ok(Some(IdentityMatch {
+            identity: rule.identity.clone(),
+            remote: String::new(),
+            url: rule.path.clone(),
+            reason: Reason::Directory,
+        }));
+    }
+    let remotes = remotes(repo)?;
+    Ok(detect_with(config, &remotes, &ssh_hostnames()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::config::{OrgPolicy, ProviderHost};
+
+    #[test]
+    fn test_detect_by_alias_organization_and_hostname() {
+        let config = ConfigBuilder::new()
+            .identity(
+                "gitlab-work",
+                Identity {
+                    organizations: vec!["acme".to_string()],
+                    ..identity("gitlab", "gitlab-work", "gitlab.com", "Work", "me@acme.dev")
+                },
+            )
+            .identity(
+                "gitlab-personal",
+                identity(
+                    "gitlab",
+                    "gitlab-personal",
+                    "gitlab.com",
+                    "Me",
+                    "me@example.com",
+                ),
+            )
+            .identity(
+                "github-personal",
+                identity(
+                    "github",
+                    "github-personal",
+                    "github.com",
+                    "Me",
+                    "me@example.com",
+                ),
+            )
+            .build();
+        let hostnames = BTreeMap::from([("work".to_string(), "gitlab.com".to_string())]);
+
+        assert_eq!(
+            RemoteUrl::parse("ssh://git@gitlab.com:2222/acme/platform/api.git"),
+            Some(RemoteUrl {
+                host: "gitlab.com".to_string(),
+                path: "acme/platform/api".to_string()
+            })
+        );
+        assert_eq!(RemoteUrl::parse("/srv/git/repo.git"), None);
+        assert_eq!(provider_for("codeberg.org"), Some("gitea"));
+        assert_eq!(provider_for("git.sr.ht"), Some("sourcehut"));
+
+        let remotes = parse_remotes(
+            "remote.upstream.url https://github.com/someone/api.git\n\
+             remote.origin.url work:acme/platform/api.git\n",
+        );
+        assert_eq!(remotes[0].0, "origin");
+        let found = detect_with(&config, &remotes, &hostnames).unwrap();
+        assert_eq!(found.identity, "gitlab-work");
+        assert_eq!(found.reason, Reason::Organization);
+
+        assert_eq!(
+            match_url(&config, "git@github-personal:me/dotfiles.git", &hostnames),
+            Some(("github-personal".to_string(), Reason::HostAlias))
+        );
+        assert_eq!(
+            match_url(&config, "https://gitlab.com/other/repo", &hostnames),
+            Some(("gitlab-personal".to_string(), Reason::Hostname))
+        );
+        assert_eq!(
+            match_url(&config, "https://example.org/x/y", &hostnames),
+            None
+        );
+        let mut config = config;
+        config.settings.provider_hosts.push(ProviderHost {
+            hostname: "example.org".to_string(),
+            provider: "github".to_string(),
+            api_url: String::new(),
+            oauth_client_id: String::new(),
+        });
+        assert_eq!(
+            match_url(&config, "https://example.org/x/y", &hostnames),
+            Some(("github-personal".to_string(), Reason::Provider))
+        );
+        config.settings.policies.push(OrgPolicy {
+            pattern: "gitlab.com/partner-*".to_string(),
+            email_domain: "acme.dev".to_string(),
+            require_signing: false,
+        });
+        assert_eq!(
+            match_url(&config, "https://gitlab.com/partner-labs/repo", &hostnames),
+            Some(("gitlab-work".to_string(), Reason::Policy))
+        );
+
+        let worktree =
+            parse_layout("/src/api-fix\n/src/api/.git\n/src/api/.git/worktrees/api-fix\n").unwrap();
+        assert_eq!(worktree.checkout, Checkout::Worktree);
+        assert_eq!(worktree.main, PathBuf::from("/src/api"));
+        let submodule = parse_layout(
+            "/src/api/lib\n/src/api/.git/modules/lib\n/src/api/.git/modules/lib\n/src/api\n",
+        )
+        .unwrap();
+        assert_eq!(submodule.display_text(), "submodule of /src/api");
+        assert_eq!(submodule.main, PathBuf::from("/src/api/lib"));
+    }
+}
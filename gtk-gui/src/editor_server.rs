@@ -0,0 +1,400 @@
+//! JSON-RPC over stdio for editor extensions
+//!
+//! `remote-juggler-gui --editor-server` reads JSON-RPC 2.0 messages from
+//! stdin, one per line, and writes one line per response to stdout, so a
+//! VS Code or Neovim extension keeps one process running instead of
+//! spawning the CLI for every call. The methods:
+//!
+//! - `initialize`: the protocol version and the methods served
+//! - `status`: the current identity and the configured identities, as the
+//!   local HTTP API's `GET /status` answers
+//! - `switch` with `{"identity": "work"}`: switch identity
+//! - `resolveSecret` with `{"name": "GITHUB_TOKEN"}`: a secret, through the
+//!   composite resolver
+//! - `detectRepo` with `{"path": "/src/api"}`: the identity for a
+//!   repository, or null if nothing matches
+//! - `shutdown`: answer, then stop reading
+//!
+//! Requests are answered in order; notifications (no `id`) get no answer.
+//! The extension that started the server owns the pipe, so there's no
+//! token as with the HTTP API.
+
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::detect::{self, IdentityMatch};
+use crate::error::Error;
+use crate::rest_api::{self, Live};
+
+/// Bumped when a method's parameters or answer change incompatibly
+pub const PROTOCOL_VERSION: u32 = 1;
+
+pub const METHODS: &[&str] = &[
+    "initialize",
+    "status",
+    "switch",
+    "resolveSecret",
+    "detectRepo",
+    "shutdown",
+];
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// Any other failure of the operation
+pub const SERVER_ERROR: i64 = -32000;
+/// No such identity, or no source has the secret
+pub const NOT_FOUND: i64 = -32001;
+pub const KEYSTORE_LOCKED: i64 = -32002;
+
+/// What the methods do beyond the HTTP API's operations
+pub trait Operations: rest_api::Operations {
+    fn detect(&self, config: &Config, repo: &Path) -> Result<Option<IdentityMatch>, Error>;
+}
+
+impl Operations for Live {
+    fn detect(&self, config: &Config, repo: &Path) -> Result<Option<IdentityMatch>, Error> {
+        detect::detect(config, repo)
+    }
+}
+
+/// A JSON-RPC error object, with optional `data`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    fn from_error(e: &Error) -> Self {
+        let code = match e {
+            Error::KeystoreLocked => KEYSTORE_LOCKED,
+            _ => SERVER_ERROR,
+        };
+        Self::new(code, e.to_string())
+    }
+
+    fn to_json(&self) -> Value {
+        let mut error = json!({ "code": self.code, "message": self.message });
+        if let Some(data) = &self.data {
+            error["data"] = data.clone();
+        }
+        error
+    }
+}
+
+fn string_param<'a>(params: &'a Value, name: &str) -> Result<&'a str, RpcError> {
+    params[name]
+        .as_str()
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("expected a string {}", name)))
+}
+
+fn switch(params: &Value, ops: &impl Operations) -> Result<Value, RpcError> {
+    let name = string_param(params, "identity")?;
+    let config = ops.config().map_err(|e| RpcError::from_error(&e))?;
+    if !config.identities.contains_key(name) {
+        return Err(RpcError::new(
+            NOT_FOUND,
+            format!("no identity named {}", name),
+        ));
+    }
+    ops.switch(name).map_err(|e| RpcError::from_error(&e))?;
+    Ok(json!({ "currentIdentity": name }))
+}
+
+fn resolve_secret(params: &Value, ops: &impl Operations) -> Result<Value, RpcError> {
+    let name = string_param(params, "name")?;
+    let resolution = ops.resolve(name);
+    let trail: Vec<String> = resolution.trail.iter().map(|a| a.display_text()).collect();
+    match &resolution.value {
+        Some(value) => Ok(json!({
+            "name": name,
+            "value": value,
+            "source": resolution.winner().map(|a| a.source.display_name()),
+            "trail": trail,
+        })),
+        None => Err(RpcError {
+            data: Some(json!({ "trail": trail })),
+            ..RpcError::new(NOT_FOUND, format!("{} not found", name))
+        }),
+    }
+}
+
+fn detect_repo(params: &Value, ops: &impl Operations) -> Result<Value, RpcError> {
+    let path = string_param(params, "path")?;
+    let config = ops.config().map_err(|e| RpcError::from_error(&e))?;
+    match ops.detect(&config, Path::new(path)) {
+        Ok(found) => Ok(json!(found)),
+        Err(e) => Err(RpcError::from_error(&e)),
+    }
+}
+
+/// Run one method; blocks
+pub fn call(method: &str, params: &Value, ops: &impl Operations) -> Result<Value, RpcError> {
+    match method {
+        "initialize" => Ok(json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "serverInfo": {
+                "name": "remote-juggler",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "methods": METHODS,
+        })),
+        "status" => ops
+            .config()
+            .map(|config| rest_api::status_json(&config))
+            .map_err(|e| RpcError::from_error(&e)),
+        "switch" => switch(params, ops),
+        "resolveSecret" => resolve_secret(params, ops),
+        "detectRepo" => detect_repo(params, ops),
+        "shutdown" => Ok(Value::Null),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("no method {}", method),
+        )),
+    }
+}
+
+/// Answer one message; None for a notification
+pub fn handle(message: &Value, ops: &impl Operations) -> Option<Value> {
+    let id = message.get("id").cloned();
+    let method = message["method"].as_str();
+    let result = match method {
+        Some(method) if message["jsonrpc"] == "2.0" => {
+            let params = message.get("params").cloned().unwrap_or(Value::Null);
+            call(method, &params, ops)
+        }
+        _ => Err(RpcError::new(INVALID_REQUEST, "not a JSON-RPC 2.0 request")),
+    };
+    // A notification is answered only if it couldn't be understood
+    if id.is_none() && method.is_some() {
+        return None;
+    }
+    let id = id.unwrap_or(Value::Null);
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => json!({ "jsonrpc": "2.0", "id": id, "error": e.to_json() }),
+    })
+}
+
+/// Answer one line of input, which may hold a batch
+pub fn handle_line(line: &str, ops: &impl Operations) -> Option<Value> {
+    let message: Value = match serde_json::from_str(line) {
+        Ok(message) => message,
+        Err(e) => {
+            return Some(json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": RpcError::new(PARSE_ERROR, e.to_string()).to_json(),
+            }))
+        }
+    };
+    match message {
+        Value::Array(batch) if batch.is_empty() => Some(json!({
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": RpcError::new(INVALID_REQUEST, "empty batch").to_json(),
+        })),
+        Value::Array(batch) => {
+            let answers: Vec<Value> = batch.iter().filter_map(|m| handle(m, ops)).collect();
+            (!answers.is_empty()).then_some(Value::Array(answers))
+        }
+        message => handle(&message, ops),
+    }
+}
+
+fn is_shutdown(line: &str) -> bool {
+    serde_json::from_str::<Value>(line).is_ok_and(|message| message["method"] == "shutdown")
+}
+
+/// Serve until end of input or `shutdown`; blocks
+pub fn serve(
+    input: impl BufRead,
+    mut output: impl Write,
+    ops: &impl Operations,
+) -> std::io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(answer) = handle_line(&line, ops) {
+            writeln!(output, "{}", answer)?;
+            output.flush()?;
+        }
+        if is_shutdown(&line) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Serve the real operations on stdin and stdout
+pub fn run() -> std::io::Result<()> {
+    serve(std::io::stdin().lock(), std::io::stdout().lock(), &Live)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::databases::SearchHit;
+    use crate::detect::Reason;
+    use crate::resolver::{Attempt, Outcome, Resolution, Source};
+
+    struct Fake;
+
+    impl rest_api::Operations for Fake {
+        fn config(&self) -> Result<Config, Error> {
+            Ok(ConfigBuilder::new()
+                .identity(
+                    "work",
+                    identity("gitlab", "gitlab-work", "gitlab.com", "Work", "me@acme.dev"),
+                )
+                .current("work")
+                .build())
+        }
+
+        fn switch(&self, _identity: &str) -> Result<(), Error> {
+            Err(Error::KeystoreLocked)
+        }
+
+        fn resolve(&self, name: &str) -> Resolution {
+            Resolution {
+                value: (name == "GITHUB_TOKEN").then(|| "ghp_abc".to_string()),
+                trail: vec![Attempt {
+                    source: Source::Env,
+                    location: format!("${}", name),
+                    outcome: if name == "GITHUB_TOKEN" {
+                        Outcome::Found
+                    } else {
+                        Outcome::Missing
+                    },
+                }],
+            }
+        }
+
+        fn search(&self, _query: &str) -> Result<Vec<SearchHit>, Error> {
+            Ok(Vec::new())
+        }
+    }
+
+    impl Operations for Fake {
+        fn detect(&self, _config: &Config, repo: &Path) -> Result<Option<IdentityMatch>, Error> {
+            Ok((repo == Path::new("/src/api")).then(|| IdentityMatch {
+                identity: "work".to_string(),
+                remote: "origin".to_string(),
+                url: "git@gitlab-work:acme/api.git".to_string(),
+                reason: Reason::HostAlias,
+            }))
+        }
+    }
+
+    fn request(method: &str, params: Value) -> Value {
+        handle(
+            &json!({ "jsonrpc": "2.0", "id": 7, "method": method, "params": params }),
+            &Fake,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_methods() {
+        let init = request("initialize", json!({ "clientInfo": { "name": "nvim" } }));
+        assert_eq!(init["id"], 7);
+        assert_eq!(init["result"]["protocolVersion"], PROTOCOL_VERSION);
+        assert_eq!(
+            request("status", Value::Null)["result"]["currentIdentity"],
+            "work"
+        );
+
+        let locked = request("switch", json!({ "identity": "work" }));
+        assert_eq!(locked["error"]["code"], KEYSTORE_LOCKED);
+        let unknown = request("switch", json!({ "identity": "home" }));
+        assert_eq!(unknown["error"]["code"], NOT_FOUND);
+        assert_eq!(
+            request("switch", json!({}))["error"]["code"],
+            INVALID_PARAMS
+        );
+
+        let found = request("resolveSecret", json!({ "name": "GITHUB_TOKEN" }));
+        assert_eq!(found["result"]["value"], "ghp_abc");
+        assert_eq!(found["result"]["source"], "Environment");
+        let missing = request("resolveSecret", json!({ "name": "NPM_TOKEN" }));
+        assert_eq!(missing["error"]["code"], NOT_FOUND);
+        assert_eq!(
+            missing["error"]["data"]["trail"].as_array().unwrap().len(),
+            1
+        );
+
+        let repo = request("detectRepo", json!({ "path": "/src/api" }));
+        assert_eq!(repo["result"]["identity"], "work");
+        assert_eq!(repo["result"]["reason"], "host_alias");
+        assert_eq!(
+            request("detectRepo", json!({ "path": "/tmp" }))["result"],
+            Value::Null
+        );
+
+        assert_eq!(
+            request("rename", Value::Null)["error"]["code"],
+            METHOD_NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn test_framing() {
+        // Notifications get no answer, unless they can't be understood
+        assert_eq!(
+            handle(&json!({ "jsonrpc": "2.0", "method": "status" }), &Fake),
+            None
+        );
+        let invalid = handle(&json!({ "id": 1, "method": "status" }), &Fake).unwrap();
+        assert_eq!(invalid["error"]["code"], INVALID_REQUEST);
+        assert_eq!(invalid["id"], 1);
+        assert_eq!(
+            handle_line("{", &Fake).unwrap()["error"]["code"],
+            PARSE_ERROR
+        );
+        assert_eq!(
+            handle_line("[]", &Fake).unwrap()["error"]["code"],
+            INVALID_REQUEST
+        );
+
+        let batch = handle_line(
+            r#"[{"jsonrpc":"2.0","id":1,"method":"status"},{"jsonrpc":"2.0","method":"status"}]"#,
+            &Fake,
+        )
+        .unwrap();
+        assert_eq!(batch.as_array().unwrap().len(), 1);
+
+        let input = "{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"initialize\"}\n\n\
+                     {\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"shutdown\"}\n\
+                     {\"jsonrpc\":\"2.0\",\"id\":3,\"method\":\"status\"}\n";
+        let mut output = Vec::new();
+        serve(input.as_bytes(), &mut output, &Fake).unwrap();
+        let lines: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1],
+            json!({ "jsonrpc": "2.0", "id": 2, "result": null })
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Error types for RemoteJuggler GUI
+//!
+//! Structured errors let the UI offer targeted remediation (unlock the key
+//! store, insert the YubiKey, install the CLI) instead of dumping raw stderr.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use thiserror::Error;
+
+/// Top-level error for core operations
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Cli(#[from] CliError),
+    /// The KeePassXC key store must be unlocked first
+    #[error("Key store is locked")]
+    KeystoreLocked,
+    /// The operation needs a YubiKey that isn't inserted
+    #[error("YubiKey not detected")]
+    YubiKeyAbsent,
+    /// ssh refused a host whose key is unknown or has changed
+    #[error("Host key verification failed")]
+    HostKeyUnverified,
+    /// The secret backend can't be written to
+    #[error("{0} is read-only")]
+    ReadOnlyBackend(&'static str),
+    /// Reading or writing a local file failed
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// An entry's otpauth:// URI couldn't be used
+    #[error("Invalid otpauth URI: {0}")]
+    InvalidOtpUri(String),
+    /// A remote API answered with an error status
+    #[error("HTTP {status}: {message}")]
+    Http { status: u16, message: String },
+    /// A provider's API budget is used up; nothing was sent
+    #[error("{host} API rate limit reached; it resets {}", reset_text(*reset_at))]
+    RateLimited { host: String, reset_at: u64 },
+    /// A remote API could not be reached
+    #[error("Network error: {0}")]
+    Network(String),
+    /// An external tool succeeded but printed something we couldn't parse
+    #[error("Unexpected output from {program}: {message}")]
+    InvalidOutput {
+        program: &'static str,
+        message: String,
+    },
+}
+
+/// Errors loading the RemoteJuggler config file
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Could not determine config directory")]
+    NoConfigDir,
+    #[error("Failed to read config file: {}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Failed to parse config file {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("Failed to write config file {}: {source}", path.display())]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("No identity named {0}")]
+    NoIdentity(String),
+}
+
+/// Errors invoking the remote-juggler CLI
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CliError {
+    /// The CLI binary isn't on PATH
+    #[error("remote-juggler not found in PATH")]
+    NotFound,
+    /// The CLI couldn't be started for another reason
+    #[error("Failed to execute command: {0}")]
+    Spawn(String),
+    /// The CLI ran and exited unsuccessfully
+    #[error("{}", stderr.trim())]
+    NonZeroExit { code: Option<i32>, stderr: String },
+    /// The CLI didn't finish within its timeout and was killed
+    #[error("Timed out after {}s", .0.as_secs())]
+    Timeout(Duration),
+    /// The caller cancelled the CLI and it was killed
+    #[error("Cancelled")]
+    Cancelled,
+    /// The GUI locked the key store after inactivity; not run
+    #[error("Key store is locked")]
+    Locked,
+}
+
+/// When a rate limit resets, relative to now
+fn reset_text(reset_at: u64) -> String {
+    let minutes = reset_at.saturating_sub(crate::expiry::now()).div_ceil(60);
+    match minutes {
+        0 => "now".to_string(),
+        1 => "in a minute".to_string(),
+        n => format!("in {} minutes", n),
+    }
+}
+
+impl Error {
+    /// Classify a CLI failure, recognizing well-known stderr messages
+    pub fn from_cli(err: CliError) -> Self {
+        if err == CliError::Locked {
+            return Error::KeystoreLocked;
+        }
+        if let CliError::NonZeroExit { ref stderr, .. } = err {
+            let lower = stderr.to_lowercase();
+            if lower.contains("key store is locked")
+                || lower.contains("invalid credentials")
+                || lower.contains("failed to unlock")
+            {
+                return Error::KeystoreLocked;
+            }
+            if lower.contains("no yubikey")
+                || lower.contains("yubikey not")
+                || lower.contains("card not present")
+                || lower.contains("no card")
+            {
+                return Error::YubiKeyAbsent;
+            }
+            if lower.contains("host key verification failed")
+                || lower.contains("remote host identification has changed")
+            {
+                return Error::HostKeyUnverified;
+            }
+        }
+        Error::Cli(err)
+    }
+
+    /// A short hint on how to fix the error, if there is an obvious one
+    pub fn remediation(&self) -> Option<&'static str> {
+        match self {
+            Error::Config(ConfigError::Read { .. }) | Error::Config(ConfigError::NoConfigDir) => {
+                Some("Run `remote-juggler config init` to create a configuration.")
+            }
+            Error::Config(ConfigError::Parse { .. }) => {
+                Some("Check config.json for syntax errors or run `remote-juggler config show`.")
+            }
+            Error::Cli(CliError::NotFound)
+                if crate::platform::is_flatpak() && !crate::cli::can_run_on_host() =>
+            {
+                Some("Allow the app to talk to org.freedesktop.Flatpak so it can run the host's remote-juggler.")
+            }
+            Error::Cli(CliError::NotFound) => {
+                Some("Install the remote-juggler CLI and make sure it is on PATH.")
+            }
+            Error::Cli(CliError::Timeout(_)) => {
+                Some("The CLI may be waiting for a PIN prompt; check for a pinentry window.")
+            }
+            Error::KeystoreLocked => Some(
+                "Unlock the key store with `remote-juggler keys status` or insert your YubiKey.",
+            ),
+            Error::YubiKeyAbsent => Some("Insert your YubiKey and try again."),
+            Error::HostKeyUnverified => {
+                Some("Check the host's key under Host Keys, then try again.")
+            }
+            Error::RateLimited { .. } => {
+                Some("Cached results are shown until then; a signed-in token has a larger budget.")
+            }
+            Error::Http { status: 401, .. } | Error::Http { status: 403, .. } => {
+                Some("Check that you are signed in and have access to the secret store.")
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if the user cancelled the operation
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Error::Cli(CliError::Cancelled))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit(stderr: &str) -> CliError {
+        CliError::NonZeroExit {
+            code: Some(1),
+            stderr: stderr.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_classify_keystore_locked() {
+        let err = Error::from_cli(exit("Error: Key store is locked\n"));
+        assert!(matches!(err, Error::KeystoreLocked));
+        assert!(err.remediation().is_some());
+    }
+
+    #[test]
+    fn test_classify_auto_locked() {
+        assert!(matches!(
+            Error::from_cli(CliError::Locked),
+            Error::KeystoreLocked
+        ));
+    }
+
+    #[test]
+    fn test_classify_yubikey_absent() {
+        let err = Error::from_cli(exit(
+            "gpg: selecting card failed: No such device\nNo YubiKey detected",
+        ));
+        assert!(matches!(err, Error::YubiKeyAbsent));
+    }
+
+    #[test]
+    fn test_classify_host_key() {
+        let err = Error::from_cli(exit(
+            "@@@@@@@@\n@    WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED!     @\n\
+             Host key verification failed.\nfatal: Could not read from remote repository.",
+        ));
+        assert!(matches!(err, Error::HostKeyUnverified));
+        assert!(err.remediation().is_some());
+    }
+
+    #[test]
+    fn test_unrecognized_stderr_is_kept() {
+        let err = Error::from_cli(exit("Unknown identity: foo\n"));
+        assert_eq!(err.to_string(), "Unknown identity: foo");
+        assert!(err.remediation().is_none());
+    }
+
+    #[test]
+    fn test_cancelled() {
+        assert!(Error::from_cli(CliError::Cancelled).is_cancelled());
+        assert!(!Error::YubiKeyAbsent.is_cancelled());
+    }
+}
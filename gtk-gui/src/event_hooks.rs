@@ -0,0 +1,364 @@
+//! Credential event hooks
+//!
+//! `settings.eventHooks` lists commands, webhooks and Matrix rooms to tell
+//! when an identity is switched, a secret is read, the key store is
+//! unlocked or a token comes up for rotation, so a team can watch its
+//! credentials from a chat room or a SIEM. Events come from the GUI, its
+//! background service and timers, the HTTP and editor APIs and
+//! `remote-juggler-shell`, not from the CLI run in a terminal. A command
+//! gets the event as JSON on stdin and in `REMOTE_JUGGLER_EVENT*`
+//! variables; a webhook gets it POSTed as JSON, with a `text` field that
+//! Slack-style incoming webhooks show; a Matrix room gets the text as a
+//! notice. Events name secrets but never carry their values.
+//!
+//! [`emit`] delivers on a thread of its own and returns at once; a failed
+//! delivery is logged, not retried. Reading a hook's token from the key
+//! store is itself a secret access, so nothing emitted while delivering is
+//! delivered again. Short-lived processes call [`flush`] before exiting.
+
+use std::cell::Cell;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::Duration;
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rsa::rand_core::{OsRng, RngCore};
+use serde_json::{json, Value};
+
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, EventHook};
+use crate::error::Error;
+use crate::expiry;
+use crate::http;
+use crate::platform;
+use crate::resolver;
+
+/// How long a hook's command may run
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Names of the events, as written in a hook's `events`
+pub const EVENTS: [&str; 4] = [
+    "identitySwitched",
+    "secretAccessed",
+    "keystoreUnlocked",
+    "tokenExpiring",
+];
+
+/// Something that happened to a credential
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    IdentitySwitched {
+        identity: String,
+    },
+    /// A secret was read from a store; `source` is e.g. "KDBX" or "SOPS"
+    SecretAccessed {
+        name: String,
+        source: String,
+    },
+    /// A master password was given to the session
+    KeystoreUnlocked {
+        database: String,
+    },
+    /// A secret, token or signing key is due for rotation
+    TokenExpiring {
+        name: String,
+        detail: String,
+    },
+    /// Sent to one hook to try it, whatever its events
+    Test,
+}
+
+impl Event {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::IdentitySwitched { .. } => EVENTS[0],
+            Event::SecretAccessed { .. } => EVENTS[1],
+            Event::KeystoreUnlocked { .. } => EVENTS[2],
+            Event::TokenExpiring { .. } => EVENTS[3],
+            Event::Test => "test",
+        }
+    }
+
+    /// One line for people, naming the machine
+    pub fn text(&self, host: &str) -> String {
+        let what = match self {
+            Event::IdentitySwitched { identity } => format!("switched to {}", identity),
+            Event::SecretAccessed { name, source } => format!("read {} from {}", name, source),
+            Event::KeystoreUnlocked { database } => format!("unlocked key store {}", database),
+            Event::TokenExpiring { name, detail } => format!("{}: {}", name, detail),
+            Event::Test => "test event".to_string(),
+        };
+        format!("RemoteJuggler on {}: {}", host, what)
+    }
+
+    /// The JSON hooks receive; `time` is Unix seconds
+    pub fn payload(&self, host: &str, time: u64) -> Value {
+        let mut payload = json!({
+            "event": self.kind(),
+            "host": host,
+            "time": time,
+            "text": self.text(host),
+        });
+        let fields = match self {
+            Event::IdentitySwitched { identity } => json!({ "identity": identity }),
+            Event::SecretAccessed { name, source } => json!({ "name": name, "source": source }),
+            Event::KeystoreUnlocked { database } => json!({ "database": database }),
+            Event::TokenExpiring { name, detail } => json!({ "name": name, "detail": detail }),
+            Event::Test => json!({}),
+        };
+        if let (Some(payload), Value::Object(fields)) = (payload.as_object_mut(), fields) {
+            payload.extend(fields);
+        }
+        payload
+    }
+}
+
+/// Whether `hook` fires on `event`
+pub fn wants(hook: &EventHook, event: &Event) -> bool {
+    matches!(event, Event::Test)
+        || hook.events.is_empty()
+        || hook
+            .events
+            .iter()
+            .any(|name| name.trim().eq_ignore_ascii_case(event.kind()))
+}
+
+/// What a hook delivers to, for the GUI
+pub fn describe(hook: &EventHook) -> String {
+    let mut targets = Vec::new();
+    if let Some(program) = hook.command.first() {
+        targets.push(format!("Run {}", program));
+    }
+    if !hook.webhook.is_empty() {
+        targets.push(format!("POST to {}", hook.webhook));
+    }
+    if !hook.matrix_room.is_empty() {
+        targets.push(format!("Post in {}", hook.matrix_room));
+    }
+    if targets.is_empty() {
+        return "No command, webhook or Matrix room".to_string();
+    }
+    targets.join(", ")
+}
+
+/// A hook that can't be delivered as configured
+fn invalid(message: impl Into<String>) -> Error {
+    Error::Io(std::io::Error::other(message.into()))
+}
+
+/// The URL sending a message to a Matrix room, with transaction `txn`
+pub fn matrix_url(hook: &EventHook, txn: &str) -> Result<String, Error> {
+    let homeserver = if hook.matrix_homeserver.is_empty() {
+        let server = hook
+            .matrix_room
+            .split_once(':')
+            .map(|(_, server)| server)
+            .filter(|server| !server.is_empty())
+            .ok_or_else(|| invalid(format!("{} names no server", hook.matrix_room)))?;
+        format!("https://{}", server)
+    } else {
+        hook.matrix_homeserver.trim_end_matches('/').to_string()
+    };
+    Ok(format!(
+        "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+        homeserver,
+        utf8_percent_encode(&hook.matrix_room, NON_ALPHANUMERIC),
+        txn
+    ))
+}
+
+thread_local! {
+    /// Set while this thread delivers, so its own secret reads aren't events
+    static DELIVERING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the thread as delivering until dropped
+struct Delivering(bool);
+
+impl Delivering {
+    fn start() -> Self {
+        Self(DELIVERING.with(|d| d.replace(true)))
+    }
+}
+
+impl Drop for Delivering {
+    fn drop(&mut self) {
+        DELIVERING.with(|d| d.set(self.0));
+    }
+}
+
+fn token(hook: &EventHook) -> Result<Option<String>, Error> {
+    if hook.token_entry.is_empty() {
+        return Ok(None);
+    }
+    resolver::resolve(&hook.token_entry)
+        .value
+        .map(Some)
+        .ok_or_else(|| invalid(format!("no token in {}", hook.token_entry)))
+}
+
+/// Deliver `event` to every target of `hook` now; blocks
+pub fn deliver(hook: &EventHook, event: &Event) -> Result<(), Error> {
+    let _delivering = Delivering::start();
+    let host = platform::machine_name();
+    let payload = event.payload(&host, expiry::now());
+    let text = event.text(&host);
+
+    if let Some((program, args)) = hook.command.split_first() {
+        let env = [
+            ("REMOTE_JUGGLER_EVENT", event.kind()),
+            ("REMOTE_JUGGLER_EVENT_TEXT", text.as_str()),
+        ];
+        let options = RunOptions {
+            timeout: COMMAND_TIMEOUT,
+            retries: 0,
+            cancel: None,
+        };
+        cli::run_program_with_input(program, args, &env, &payload.to_string(), &options)
+            .map_err(Error::from_cli)?;
+    }
+    if !hook.webhook.is_empty() || !hook.matrix_room.is_empty() {
+        let token = token(hook)?;
+        if !hook.webhook.is_empty() {
+            let mut request = http::agent().post(&hook.webhook);
+            if let Some(token) = &token {
+                request = request.set("Authorization", &format!("Bearer {}", token));
+            }
+            request.send_json(&payload).map_err(http::map_error)?;
+        }
+        if !hook.matrix_room.is_empty() {
+            let token = token
+                .ok_or_else(|| invalid("a Matrix room needs tokenEntry for its access token"))?;
+            let txn = format!("rj-{}-{:08x}", expiry::now(), OsRng.next_u32());
+            http::agent()
+                .put(&matrix_url(hook, &txn)?)
+                .set("Authorization", &format!("Bearer {}", token))
+                .send_json(json!({ "msgtype": "m.notice", "body": text }))
+                .map_err(http::map_error)?;
+        }
+    }
+    Ok(())
+}
+
+/// Deliveries under way
+fn pending() -> &'static (Mutex<usize>, Condvar) {
+    static PENDING: OnceLock<(Mutex<usize>, Condvar)> = OnceLock::new();
+    PENDING.get_or_init(Default::default)
+}
+
+fn finished() {
+    let (count, done) = pending();
+    let mut count = count.lock().unwrap_or_else(|e| e.into_inner());
+    *count = count.saturating_sub(1);
+    done.notify_all();
+}
+
+/// Deliver `event` to the hooks wanting it, in the background
+pub fn emit(event: Event) {
+    if DELIVERING.with(Cell::get) {
+        return;
+    }
+    *pending().0.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+    let spawned = std::thread::Builder::new()
+        .name("event-hooks".to_string())
+        .spawn(move || {
+            let _delivering = Delivering::start();
+            let hooks = Config::load()
+                .map(|config| config.settings.event_hooks)
+                .unwrap_or_default();
+            for hook in hooks.iter().filter(|hook| wants(hook, &event)) {
+                if let Err(e) = deliver(hook, &event) {
+                    tracing::warn!("{} hook ({}) failed: {}", event.kind(), describe(hook), e);
+                }
+            }
+            finished();
+        });
+    if spawned.is_err() {
+        finished();
+    }
+}
+
+/// Wait up to `timeout` for deliveries under way, before exiting
+pub fn flush(timeout: Duration) {
+    let (count, done) = pending();
+    let count = count.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = done.wait_timeout_while(count, timeout, |count| *count > 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload() {
+        let event = Event::SecretAccessed {
+            name: "RemoteJuggler/API/GITHUB_TOKEN".to_string(),
+            source: "KDBX".to_string(),
+        };
+        assert_eq!(
+            event.payload("laptop", 1_767_225_600),
+            json!({
+                "event": "secretAccessed",
+                "host": "laptop",
+                "time": 1_767_225_600,
+                "text": "RemoteJuggler on laptop: read RemoteJuggler/API/GITHUB_TOKEN from KDBX",
+                "name": "RemoteJuggler/API/GITHUB_TOKEN",
+                "source": "KDBX",
+            })
+        );
+        let switched = Event::IdentitySwitched {
+            identity: "work".to_string(),
+        };
+        assert_eq!(switched.payload("ci", 0)["identity"], "work");
+        assert_eq!(Event::Test.payload("ci", 0)["event"], "test");
+    }
+
+    #[test]
+    fn test_wants() {
+        let all = EventHook::default();
+        let switches = EventHook {
+            events: vec!["identitySwitched".to_string(), " TokenExpiring".to_string()],
+            ..EventHook::default()
+        };
+        let unlocked = Event::KeystoreUnlocked {
+            database: "default".to_string(),
+        };
+        let expiring = Event::TokenExpiring {
+            name: "npm".to_string(),
+            detail: "expires in 3 days".to_string(),
+        };
+        assert!(wants(&all, &unlocked));
+        assert!(!wants(&switches, &unlocked));
+        assert!(wants(&switches, &expiring));
+        assert!(wants(&switches, &Event::Test));
+    }
+
+    #[test]
+    fn test_matrix_url_and_describe() {
+        let mut hook = EventHook {
+            matrix_room: "!sec0ps:example.org".to_string(),
+            ..EventHook::default()
+        };
+        assert_eq!(
+            matrix_url(&hook, "rj-1").unwrap(),
+            "https://example.org/_matrix/client/v3/rooms/%21sec0ps%3Aexample%2Eorg/send/m.room.message/rj-1"
+        );
+        hook.matrix_homeserver = "https://matrix.example.org/".to_string();
+        assert!(matrix_url(&hook, "rj-1")
+            .unwrap()
+            .starts_with("https://matrix.example.org/_matrix/"));
+        hook.command = vec!["logger".to_string(), "-t".to_string(), "rj".to_string()];
+        assert_eq!(describe(&hook), "Run logger, Post in !sec0ps:example.org");
+        assert!(matrix_url(
+            &EventHook {
+                matrix_room: "!nohost".to_string(),
+                ..EventHook::default()
+            },
+            "rj-1"
+        )
+        .is_err());
+        assert_eq!(
+            describe(&EventHook::default()),
+            "No command, webhook or Matrix room"
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! Secret expiry and rotation reminders
+//!
+//! Entries can carry an expiry date and/or a rotation interval. They are
+//! kept next to the SOPS baselines in the local data directory, keyed by
+//! entry path, since KDBX expiry isn't exposed by the CLI. Personal access
+//! tokens tracked by the CLI (`token set`) are checked too, from the
+//! `expiresAt` it records in `~/.config/remote-juggler/tokens.json`.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::SecurityMode;
+use crate::platform;
+
+const DAY: u64 = 86_400;
+
+/// Secrets due within this many days are flagged
+pub const WARN_DAYS: u64 = 14;
+
+/// Current Unix time in seconds
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Days since 1970-01-01 for a civil date (proleptic Gregorian)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let month = i64::from(month);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Civil date for days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Parse a `YYYY-MM-DD` date to Unix seconds at midnight UTC
+pub fn parse_date(s: &str) -> Option<u64> {
+    let mut parts = s.trim().splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    // Reject dates that roll over, like 2025-02-30
+    (civil_from_days(days) == (year, month, day))
+        .then(|| u64::try_from(days).ok())
+        .flatten()
+        .map(|d| d * DAY)
+}
+
+/// Format Unix seconds as a `YYYY-MM-DD` date (UTC)
+pub fn format_date(unix_secs: u64) -> String {
+    let (year, month, day) = civil_from_days((unix_secs / DAY) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Expiry and rotation settings for one entry
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryExpiry {
+    /// Hard expiry, e.g. the date a PAT stops working
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Rotate this often, counted from the last rotation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation_days: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_rotated: Option<u64>,
+}
+
+impl EntryExpiry {
+    /// When the entry next needs attention, whichever comes first
+    pub fn due_at(&self) -> Option<u64> {
+        let rotation = self
+            .rotation_days
+            .zip(self.last_rotated)
+            .map(|(days, last)| last + u64::from(days) * DAY);
+        match (self.expires_at, rotation) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// How urgent a reminder is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Expired { days_ago: u64 },
+    DueSoon { days_left: u64 },
+}
+
+impl Urgency {
+    /// Urgency of a due date, or None if it is further than `WARN_DAYS` out
+    pub fn at(due_at: u64, now: u64) -> Option<Self> {
+        Self::within(due_at, now, WARN_DAYS)
+    }
+
+    /// Urgency of a due date, or None if it is further than `warn_days` out
+    pub fn within(due_at: u64, now: u64, warn_days: u64) -> Option<Self> {
+        if due_at <= now {
+            Some(Urgency::Expired {
+                days_ago: (now - due_at) / DAY,
+            })
+        } else {
+            let days_left = (due_at - now).div_ceil(DAY);
+            (days_left <= warn_days).then_some(Urgency::DueSoon { days_left })
+        }
+    }
+
+    pub fn display_text(&self) -> String {
+        match self {
+            Urgency::Expired { days_ago: 0 } => "Expired today".to_string(),
+            Urgency::Expired { days_ago: 1 } => "Expired yesterday".to_string(),
+            Urgency::Expired { days_ago } => format!("Expired {} days ago", days_ago),
+            Urgency::DueSoon { days_left: 1 } => "Due tomorrow".to_string(),
+            Urgency::DueSoon { days_left } => format!("Due in {} days", days_left),
+        }
+    }
+}
+
+/// Where a reminder came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReminderKind {
+    /// A key store entry with expiry metadata
+    Entry,
+    /// A personal access token tracked by the CLI
+    Token,
+    /// A GPG signing key, extended with the security mode of the
+    /// identities using it
+    GpgKey {
+        fingerprint: String,
+        security_mode: SecurityMode,
+    },
+}
+
+/// A secret that needs rotating
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reminder {
+    /// Entry path, `provider:identity` for tokens, or a GPG key's
+    /// description
+    pub name: String,
+    pub kind: ReminderKind,
+    pub due_at: u64,
+    pub urgency: Urgency,
+}
+
+impl Reminder {
+    pub fn display_text(&self) -> String {
+        format!(
+            "{} ({})",
+            self.urgency.display_text(),
+            format_date(self.due_at)
+        )
+    }
+}
+
+/// Expiry metadata for key store entries, persisted locally
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpiryStore {
+    #[serde(default)]
+    pub entries: BTreeMap<String, EntryExpiry>,
+}
+
+impl ExpiryStore {
+    pub fn path() -> Option<PathBuf> {
+        platform::data_dir().map(|d| d.join("expiry.json"))
+    }
+
+    pub fn load() -> Self {
+        platform::load_json_state(Self::path())
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        platform::save_json_state(Self::path(), self)
+    }
+
+    /// Set or clear an entry's expiry settings
+    ///
+    /// A rotation interval without a known last rotation counts from `now`.
+    pub fn set(&mut self, path: &str, mut expiry: EntryExpiry, now: u64) {
+        if expiry.rotation_days.is_some() && expiry.last_rotated.is_none() {
+            expiry.last_rotated = Some(now);
+        }
+        if expiry == EntryExpiry::default() {
+            self.entries.remove(path);
+        } else {
+            self.entries.insert(path.to_string(), expiry);
+        }
+    }
+
+    /// Record that an entry was just rotated; returns false if untracked
+    ///
+    /// Restarts the rotation interval. A hard expiry is dropped, since a new
+    /// secret comes with its own expiry date.
+    pub fn mark_rotated(&mut self, path: &str, now: u64) -> bool {
+        let Some(expiry) = self.entries.get_mut(path) else {
+            return false;
+        };
+        expiry.last_rotated = Some(now);
+        expiry.expires_at = None;
+        if expiry.rotation_days.is_none() {
+            self.entries.remove(path);
+        }
+        true
+    }
+
+    /// Entries that are expired or due within `WARN_DAYS`
+    pub fn reminders(&self, now: u64) -> Vec<Reminder> {
+        self.entries
+            .iter()
+            .filter_map(|(path, expiry)| {
+                let due_at = expiry.due_at()?;
+                Some(Reminder {
+                    name: path.clone(),
+                    kind: ReminderKind::Entry,
+                    due_at,
+                    urgency: Urgency::at(due_at, now)?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Where the CLI keeps token metadata
+pub fn token_metadata_path() -> Option<PathBuf> {
+    platform::config_dir().map(|d| d.join("tokens.json"))
+}
+
+/// Tokens with a known expiry from the CLI's `tokens.json`
+///
+/// Returns `(provider:identity, expiresAt)`; unknown expiry (0) is skipped.
+pub fn parse_token_metadata(json: &str) -> Vec<(String, u64)> {
+    let Ok(value) = serde_json::from_str::<Value>(json) else {
+        return Vec::new();
+    };
+    let Some(tokens) = value.get("tokens").and_then(Value::as_object) else {
+        return Vec::new();
+    };
+    tokens
+        .iter()
+        .filter_map(|(key, meta)| {
+            let expires_at = meta.get("expiresAt").and_then(Value::as_f64)?;
+            (expires_at > 0.0).then(|| (key.clone(), expires_at as u64))
+        })
+        .collect()
+}
+
+/// Token reminders from the CLI's metadata
+pub fn token_reminders(now: u64) -> Vec<Reminder> {
+    let json = token_metadata_path()
+        .and_then(|p| std::fs::read_to_string(p).ok())
+        .unwrap_or_default();
+    parse_token_metadata(&json)
+        .into_iter()
+        .filter_map(|(name, due_at)| {
+            Some(Reminder {
+                name,
+                kind: ReminderKind::Token,
+                due_at,
+                urgency: Urgency::at(due_at, now)?,
+            })
+        })
+        .collect()
+}
+
+/// Everything that needs rotating, most urgent first
+pub fn check(now: u64) -> Vec<Reminder> {
+    let mut reminders = ExpiryStore::load().reminders(now);
+    reminders.extend(token_reminders(now));
+    reminders.sort_by_key(|r| r.due_at);
+    reminders
+}
+
+/// Restart an entry's rotation clock after it was stored anew
+pub fn record_rotation(path: &str) {
+    let mut store = ExpiryStore::load();
+    if store.mark_rotated(path, now()) {
+        if let Err(e) = store.save() {
+            tracing::warn!("Failed to save expiry metadata: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_767_225_600; // 2026-01-01
+
+    #[test]
+    fn test_dates_round_trip() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2026-01-01"), Some(NOW));
+        assert_eq!(format_date(NOW), "2026-01-01");
+        assert_eq!(format_date(parse_date("2024-02-29").unwrap()), "2024-02-29");
+        assert_eq!(parse_date("2025-02-29"), None);
+        assert_eq!(parse_date("2026-13-01"), None);
+        assert_eq!(parse_date("soon"), None);
+    }
+
+    #[test]
+    fn test_due_at_takes_earliest() {
+        let expiry = EntryExpiry {
+            expires_at: Some(NOW + 30 * DAY),
+            rotation_days: Some(10),
+            last_rotated: Some(NOW),
+        };
+        assert_eq!(expiry.due_at(), Some(NOW + 10 * DAY));
+        assert_eq!(EntryExpiry::default().due_at(), None);
+    }
+
+    #[test]
+    fn test_urgency() {
+        assert_eq!(Urgency::at(NOW + 30 * DAY, NOW), None);
+        assert_eq!(
+            Urgency::at(NOW + 3 * DAY, NOW),
+            Some(Urgency::DueSoon { days_left: 3 })
+        );
+        assert_eq!(
+            Urgency::at(NOW - 2 * DAY, NOW),
+            Some(Urgency::Expired { days_ago: 2 })
+        );
+        assert_eq!(
+            Urgency::Expired { days_ago: 0 }.display_text(),
+            "Expired today"
+        );
+    }
+
+    #[test]
+    fn test_store_set_and_rotate() {
+        let mut store = ExpiryStore::default();
+        store.set(
+            "RemoteJuggler/API/GITHUB_TOKEN",
+            EntryExpiry {
+                rotation_days: Some(7),
+                ..Default::default()
+            },
+            NOW - 10 * DAY,
+        );
+        let reminders = store.reminders(NOW);
+        assert_eq!(reminders.len(), 1);
+        assert_eq!(reminders[0].urgency, Urgency::Expired { days_ago: 3 });
+
+        assert!(store.mark_rotated("RemoteJuggler/API/GITHUB_TOKEN", NOW));
+        assert_eq!(
+            store.reminders(NOW)[0].urgency,
+            Urgency::DueSoon { days_left: 7 }
+        );
+        assert!(!store.mark_rotated("untracked", NOW));
+
+        store.set(
+            "RemoteJuggler/API/GITHUB_TOKEN",
+            EntryExpiry::default(),
+            NOW,
+        );
+        assert!(store.entries.is_empty());
+    }
+
+    #[test]
+    fn test_parse_token_metadata() {
+        let json = r#"{
+          "version": "1.0",
+          "tokens": {
+            "github:personal": { "identityName": "personal", "expiresAt": 1767225600.0 },
+            "gitlab:work": { "identityName": "work", "expiresAt": 0.0 }
+          }
+        }"#;
+        assert_eq!(
+            parse_token_metadata(json),
+            vec![("github:personal".to_string(), NOW)]
+        );
+        assert!(parse_token_metadata("not json").is_empty());
+    }
+}
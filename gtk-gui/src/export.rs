@@ -0,0 +1,450 @@
+//! Exporting key store entries to other formats
+//!
+//! The CLI's `keys export` only knows dotenv and its own JSON. This module
+//! takes a KDBX group, or the entries of a search, and renders them as
+//! dotenv, CSV, a JSON object, a SOPS-encrypted dotenv for the stored age
+//! identity, or a Kubernetes Secret sealed with `kubeseal`.
+//!
+//! Each format has its own rules for key names (environment variables,
+//! Secret data keys), so entries are first mapped to output keys; the
+//! mapping is what the export dialog previews before anything is written.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+
+use base64::Engine;
+
+use crate::age;
+use crate::backend;
+use crate::cli::{self, RunOptions};
+use crate::databases;
+use crate::error::Error;
+use crate::sops;
+
+const TOOL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Output format of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Dotenv,
+    Csv,
+    Json,
+    /// Dotenv encrypted with sops for the stored age identity
+    SopsAge,
+    /// Kubernetes Secret manifest sealed with kubeseal
+    SealedSecret,
+}
+
+impl ExportFormat {
+    pub fn all() -> &'static [ExportFormat] {
+        &[
+            ExportFormat::Dotenv,
+            ExportFormat::Csv,
+            ExportFormat::Json,
+            ExportFormat::SopsAge,
+            ExportFormat::SealedSecret,
+        ]
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ExportFormat::Dotenv => ".env",
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Json => "JSON",
+            ExportFormat::SopsAge => "SOPS (age)",
+            ExportFormat::SealedSecret => "Sealed Kubernetes Secret",
+        }
+    }
+
+    pub fn from_index(index: u32) -> Self {
+        Self::all()
+            .get(index as usize)
+            .copied()
+            .unwrap_or(ExportFormat::Dotenv)
+    }
+
+    /// Suggested file name for the export
+    pub fn file_name(&self, name: &str) -> String {
+        match self {
+            ExportFormat::Dotenv => ".env".to_string(),
+            ExportFormat::Csv => format!("{}.csv", name),
+            ExportFormat::Json => format!("{}.json", name),
+            ExportFormat::SopsAge => format!("{}.sops.env", name),
+            ExportFormat::SealedSecret => format!("{}-sealed.yaml", name),
+        }
+    }
+
+    /// Returns true if the file holds plaintext secrets
+    pub fn is_plaintext(&self) -> bool {
+        !matches!(self, ExportFormat::SopsAge | ExportFormat::SealedSecret)
+    }
+
+    /// Turn an entry title into a key valid for this format
+    pub fn output_key(&self, title: &str) -> String {
+        match self {
+            ExportFormat::Dotenv | ExportFormat::SopsAge => env_name(title),
+            ExportFormat::SealedSecret => secret_key(title),
+            ExportFormat::Csv | ExportFormat::Json => title.to_string(),
+        }
+    }
+}
+
+/// Environment variable name: `db-url` becomes `DB_URL`
+fn env_name(title: &str) -> String {
+    let mut name: String = title
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Secret data key: alphanumerics, `-`, `_` and `.` only
+fn secret_key(title: &str) -> String {
+    let key: String = title
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match key.as_str() {
+        "" | "." | ".." => "_".to_string(),
+        _ => key,
+    }
+}
+
+/// Kubernetes object name (DNS-1123 subdomain) derived from a group path
+pub fn secret_name(source: &str) -> String {
+    let last = source.rsplit('/').find(|s| !s.is_empty()).unwrap_or(source);
+    let name: String = last
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let name = name.trim_matches('-');
+    if name.is_empty() {
+        "secrets".to_string()
+    } else {
+        name.chars().take(253).collect()
+    }
+}
+
+/// One exported value and where it came from
+#[derive(Clone, PartialEq, Eq)]
+pub struct Field {
+    /// Entry path in the key store
+    pub entry: String,
+    /// Key in the exported file
+    pub key: String,
+    pub value: String,
+}
+
+// Keep secret values out of logs
+impl std::fmt::Debug for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Field")
+            .field("entry", &self.entry)
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Map `(entry path, value)` pairs to output keys
+///
+/// Entries whose keys collide after mapping get a numeric suffix.
+pub fn map_fields(entries: Vec<(String, String)>, format: ExportFormat) -> Vec<Field> {
+    let mut used = HashSet::new();
+    entries
+        .into_iter()
+        .map(|(entry, value)| {
+            let title = entry.rsplit('/').next().unwrap_or(&entry);
+            let mut key = format.output_key(title);
+            if used.contains(&key) {
+                key = (2..)
+                    .map(|n| format!("{}_{}", key, n))
+                    .find(|candidate| !used.contains(candidate))
+                    .unwrap_or(key);
+            }
+            used.insert(key.clone());
+            Field { entry, key, value }
+        })
+        .collect()
+}
+
+fn dotenv_value(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:@+,%".contains(c));
+    if plain {
+        return value.to_string();
+    }
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "\\$")
+        .replace('`', "\\`")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+pub fn render_dotenv(fields: &[Field]) -> String {
+    fields
+        .iter()
+        .map(|f| format!("{}={}\n", f.key, dotenv_value(&f.value)))
+        .collect()
+}
+
+fn csv_cell(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn render_csv(fields: &[Field]) -> String {
+    let mut out = String::from("name,value,entry\r\n");
+    for f in fields {
+        out.push_str(&format!(
+            "{},{},{}\r\n",
+            csv_cell(&f.key),
+            csv_cell(&f.value),
+            csv_cell(&f.entry)
+        ));
+    }
+    out
+}
+
+pub fn render_json(fields: &[Field]) -> String {
+    let object: serde_json::Map<String, serde_json::Value> = fields
+        .iter()
+        .map(|f| (f.key.clone(), serde_json::Value::String(f.value.clone())))
+        .collect();
+    let mut json = serde_json::to_string_pretty(&object).unwrap_or_default();
+    json.push('\n');
+    json
+}
+
+/// An opaque Kubernetes Secret manifest with base64 data
+pub fn render_secret(fields: &[Field], name: &str, namespace: Option<&str>) -> String {
+    let mut out = format!(
+        "apiVersion: v1\nkind: Secret\nmetadata:\n  name: {}\n",
+        name
+    );
+    if let Some(namespace) = namespace.filter(|n| !n.is_empty()) {
+        out.push_str(&format!("  namespace: {}\n", namespace));
+    }
+    out.push_str("type: Opaque\ndata:\n");
+    let engine = base64::engine::general_purpose::STANDARD;
+    for f in fields {
+        out.push_str(&format!("  {}: {}\n", f.key, engine.encode(&f.value)));
+    }
+    out
+}
+
+fn tool_options() -> RunOptions {
+    RunOptions {
+        timeout: TOOL_TIMEOUT,
+        retries: 0,
+        cancel: None,
+    }
+}
+
+/// The kubeseal binary, overridable like the other tools
+pub fn kubeseal_program() -> String {
+    std::env::var("REMOTE_JUGGLER_KUBESEAL_PATH").unwrap_or_else(|_| "kubeseal".to_string())
+}
+
+/// Options for the Kubernetes formats
+#[derive(Debug, Clone, Default)]
+pub struct SecretTarget {
+    pub name: String,
+    pub namespace: Option<String>,
+}
+
+/// Render fields in `format`
+///
+/// The encrypted formats run sops or kubeseal with the plaintext on stdin,
+/// so it never touches disk. Blocks while they run.
+pub fn render(
+    fields: &[Field],
+    format: ExportFormat,
+    target: &SecretTarget,
+) -> Result<String, Error> {
+    match format {
+        ExportFormat::Dotenv => Ok(render_dotenv(fields)),
+        ExportFormat::Csv => Ok(render_csv(fields)),
+        ExportFormat::Json => Ok(render_json(fields)),
+        ExportFormat::SopsAge => {
+            let recipient = age::load_secret(age::DEFAULT_IDENTITY_PATH)
+                .and_then(|secret| age::recipient_for(&secret))?;
+            let args = cli::args(&[
+                "--encrypt",
+                "--age",
+                &recipient,
+                "--input-type",
+                "dotenv",
+                "--output-type",
+                "dotenv",
+                "/dev/stdin",
+            ]);
+            cli::run_program_with_input(
+                &sops::sops_program(),
+                &args,
+                &[],
+                &render_dotenv(fields),
+                &tool_options(),
+            )
+            .map_err(Error::from_cli)
+        }
+        ExportFormat::SealedSecret => {
+            let manifest = render_secret(fields, &target.name, target.namespace.as_deref());
+            cli::run_program_with_input(
+                &kubeseal_program(),
+                &cli::args(&["--format", "yaml"]),
+                &[],
+                &manifest,
+                &tool_options(),
+            )
+            .map_err(Error::from_cli)
+        }
+    }
+}
+
+fn read_value(entry: &str) -> Result<String, Error> {
+    let value = cli::run(&cli::args(&["keys", "get", entry])).map_err(Error::from_cli)?;
+    cli::reported_error(&value).map_err(Error::from_cli)?;
+    Ok(value.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Values of every entry directly inside a KDBX group
+pub fn load_group(group: &str) -> Result<Vec<(String, String)>, Error> {
+    let group = group.trim().trim_end_matches('/');
+    backend::kdbx_entries(group)
+        .into_iter()
+        .map(|entry| {
+            let path = format!("{}/{}", group, entry);
+            read_value(&path).map(|value| (path, value))
+        })
+        .collect()
+}
+
+/// Values of the entries a key store search finds
+pub fn load_search(query: &str) -> Result<Vec<(String, String)>, Error> {
+    let output = cli::run(&databases::search_args(query)).map_err(Error::from_cli)?;
+    databases::parse_search(&output)?
+        .into_iter()
+        .map(|hit| read_value(&hit.entry_path).map(|value| (hit.entry_path, value)))
+        .collect()
+}
+
+/// Write an export readable only by the owner
+pub fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<(String, String)> {
+        vec![
+            (
+                "RemoteJuggler/API/github-token".to_string(),
+                "ghp_abc".to_string(),
+            ),
+            (
+                "RemoteJuggler/API/db url".to_string(),
+                "postgres://u:p w@db/\"x\"".to_string(),
+            ),
+            ("Other/GITHUB_TOKEN".to_string(), "second".to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_map_fields_keys() {
+        let keys = |format| -> Vec<String> {
+            map_fields(entries(), format)
+                .into_iter()
+                .map(|f| f.key)
+                .collect()
+        };
+        assert_eq!(
+            keys(ExportFormat::Dotenv),
+            vec!["GITHUB_TOKEN", "DB_URL", "GITHUB_TOKEN_2"]
+        );
+        assert_eq!(
+            keys(ExportFormat::SealedSecret),
+            vec!["github-token", "db_url", "GITHUB_TOKEN"]
+        );
+        assert_eq!(env_name("2fa"), "_2FA");
+    }
+
+    #[test]
+    fn test_render_dotenv_quotes() {
+        let fields = map_fields(entries(), ExportFormat::Dotenv);
+        assert_eq!(
+            render_dotenv(&fields),
+            "GITHUB_TOKEN=ghp_abc\nDB_URL=\"postgres://u:p w@db/\\\"x\\\"\"\nGITHUB_TOKEN_2=second\n"
+        );
+    }
+
+    #[test]
+    fn test_render_csv_escapes() {
+        let fields = map_fields(entries()[1..2].to_vec(), ExportFormat::Csv);
+        assert_eq!(
+            render_csv(&fields),
+            "name,value,entry\r\ndb url,\"postgres://u:p w@db/\"\"x\"\"\",RemoteJuggler/API/db url\r\n"
+        );
+    }
+
+    #[test]
+    fn test_render_json_and_secret() {
+        let fields = map_fields(entries()[..1].to_vec(), ExportFormat::Json);
+        assert_eq!(
+            render_json(&fields),
+            "{\n  \"github-token\": \"ghp_abc\"\n}\n"
+        );
+
+        let fields = map_fields(entries()[..1].to_vec(), ExportFormat::SealedSecret);
+        assert_eq!(
+            render_secret(&fields, &secret_name("RemoteJuggler/API/"), Some("ci")),
+            "apiVersion: v1\nkind: Secret\nmetadata:\n  name: api\n  namespace: ci\ntype: Opaque\ndata:\n  github-token: Z2hwX2FiYw==\n"
+        );
+    }
+
+    #[test]
+    fn test_debug_hides_value() {
+        let fields = map_fields(entries(), ExportFormat::Json);
+        assert!(!format!("{:?}", fields).contains("ghp_abc"));
+    }
+}
@@ -0,0 +1,435 @@
+//! C ABI for alternative frontends
+//!
+//! Exposes config parsing and the common CLI operations through a stable C
+//! interface so Qt/TUI frontends and editor plugins can reuse this crate
+//! instead of reimplementing them. The matching header lives in
+//! `include/remote_juggler.h`.
+//!
+//! Conventions:
+//! - Functions returning `char *` hand ownership to the caller, who must
+//!   release it with `rj_string_free`. NULL signals failure.
+//! - Functions returning `int` return `RJ_OK` (0) on success and `RJ_ERR` (-1)
+//!   on failure.
+//! - On failure, `rj_last_error` returns a description of the most recent
+//!   error on the calling thread.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use crate::cli;
+use crate::config::Config;
+use crate::detect;
+use crate::error::CliError;
+use crate::resolver;
+
+/// Success return code
+pub const RJ_OK: c_int = 0;
+/// Failure return code; details via `rj_last_error`
+pub const RJ_ERR: c_int = -1;
+
+static VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Opaque handle to a loaded configuration
+pub struct RjConfig {
+    config: Config,
+}
+
+fn set_last_error(message: impl Into<String>) {
+    let message = message.into().replace('\0', " ");
+    let c_message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(c_message));
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+}
+
+/// Convert an owned Rust string into a caller-owned C string
+fn into_c_string(s: String) -> *mut c_char {
+    match CString::new(s) {
+        Ok(c) => c.into_raw(),
+        Err(_) => {
+            set_last_error("Result contains an interior nul byte");
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Read a required, UTF-8 C string argument
+///
+/// # Safety
+/// `ptr` must be NULL or point to a valid nul-terminated string.
+unsafe fn arg_str<'a>(ptr: *const c_char, name: &str) -> Option<&'a str> {
+    if ptr.is_null() {
+        set_last_error(format!("{} must not be NULL", name));
+        return None;
+    }
+    match CStr::from_ptr(ptr).to_str() {
+        Ok(s) => Some(s),
+        Err(_) => {
+            set_last_error(format!("{} is not valid UTF-8", name));
+            None
+        }
+    }
+}
+
+fn cli_string(args: &[&str]) -> *mut c_char {
+    clear_last_error();
+    match cli::run(&cli::args(args)) {
+        Ok(stdout) => into_c_string(stdout),
+        Err(e) => {
+            set_last_error(e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+fn cli_status(args: &[&str]) -> c_int {
+    clear_last_error();
+    status(cli::run(&cli::args(args)))
+}
+
+fn status<T>(result: Result<T, CliError>) -> c_int {
+    match result {
+        Ok(_) => RJ_OK,
+        Err(e) => {
+            set_last_error(e.to_string());
+            RJ_ERR
+        }
+    }
+}
+
+/// Library version string. Statically allocated; do not free.
+#[no_mangle]
+pub extern "C" fn rj_version() -> *const c_char {
+    VERSION.as_ptr() as *const c_char
+}
+
+/// Copy of the last error message on this thread, or NULL if none
+#[no_mangle]
+pub extern "C" fn rj_last_error() -> *mut c_char {
+    LAST_ERROR.with(|e| match e.borrow().as_ref() {
+        Some(msg) => msg.clone().into_raw(),
+        None => ptr::null_mut(),
+    })
+}
+
+/// Free a string returned by any `rj_*` function
+///
+/// # Safety
+/// `s` must be NULL or a pointer previously returned by this library that has
+/// not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rj_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Load the configuration from `path`, or the default location if NULL
+///
+/// # Safety
+/// `path` must be NULL or point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rj_config_load(path: *const c_char) -> *mut RjConfig {
+    clear_last_error();
+    let result = if path.is_null() {
+        Config::load()
+    } else {
+        match arg_str(path, "path") {
+            Some(p) => Config::load_from(&PathBuf::from(p)),
+            None => return ptr::null_mut(),
+        }
+    };
+
+    match result {
+        Ok(config) => Box::into_raw(Box::new(RjConfig { config })),
+        Err(e) => {
+            set_last_error(e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Free a configuration handle
+///
+/// # Safety
+/// `config` must be NULL or a handle returned by `rj_config_load` that has not
+/// already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rj_config_free(config: *mut RjConfig) {
+    if !config.is_null() {
+        drop(Box::from_raw(config));
+    }
+}
+
+/// Name of the current identity, or NULL if none is active
+///
+/// # Safety
+/// `config` must be a valid handle returned by `rj_config_load`.
+#[no_mangle]
+pub unsafe extern "C" fn rj_config_current_identity(config: *const RjConfig) -> *mut c_char {
+    clear_last_error();
+    let Some(handle) = config.as_ref() else {
+        set_last_error("config must not be NULL");
+        return ptr::null_mut();
+    };
+    let current = &handle.config.state.current_identity;
+    if current.is_empty() {
+        ptr::null_mut()
+    } else {
+        into_c_string(current.clone())
+    }
+}
+
+/// All identities as a JSON object keyed by identity name
+///
+/// # Safety
+/// `config` must be a valid handle returned by `rj_config_load`.
+#[no_mangle]
+pub unsafe extern "C" fn rj_config_identities_json(config: *const RjConfig) -> *mut c_char {
+    clear_last_error();
+    let Some(handle) = config.as_ref() else {
+        set_last_error("config must not be NULL");
+        return ptr::null_mut();
+    };
+    match serde_json::to_string(&handle.config.identities) {
+        Ok(json) => into_c_string(json),
+        Err(e) => {
+            set_last_error(e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Identities grouped into profiles, as a JSON array
+///
+/// # Safety
+/// `config` must be a valid handle returned by `rj_config_load`.
+#[no_mangle]
+pub unsafe extern "C" fn rj_config_profiles_json(config: *const RjConfig) -> *mut c_char {
+    clear_last_error();
+    let Some(handle) = config.as_ref() else {
+        set_last_error("config must not be NULL");
+        return ptr::null_mut();
+    };
+    match serde_json::to_string(&handle.config.profiles()) {
+        Ok(json) => into_c_string(json),
+        Err(e) => {
+            set_last_error(e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// The identity for the repository at `repo` as a JSON object (identity,
+/// remote, url, reason), or NULL if none matches or on failure
+///
+/// # Safety
+/// `config` must be a valid handle returned by `rj_config_load`, and
+/// `repo` must point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rj_detect_json(
+    config: *const RjConfig,
+    repo: *const c_char,
+) -> *mut c_char {
+    clear_last_error();
+    let Some(handle) = config.as_ref() else {
+        set_last_error("config must not be NULL");
+        return ptr::null_mut();
+    };
+    let Some(repo) = arg_str(repo, "repo") else {
+        return ptr::null_mut();
+    };
+    match detect::detect(&handle.config, Path::new(repo)) {
+        Ok(Some(found)) => match serde_json::to_string(&found) {
+            Ok(json) => into_c_string(json),
+            Err(e) => {
+                set_last_error(e.to_string());
+                ptr::null_mut()
+            }
+        },
+        Ok(None) => ptr::null_mut(),
+        Err(e) => {
+            set_last_error(e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Switch to the named identity
+///
+/// # Safety
+/// `identity` must point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rj_switch(identity: *const c_char) -> c_int {
+    match arg_str(identity, "identity") {
+        Some(name) => cli_status(&["switch", name]),
+        None => RJ_ERR,
+    }
+}
+
+/// Key store status report as printed by `remote-juggler keys status`
+#[no_mangle]
+pub extern "C" fn rj_keys_status() -> *mut c_char {
+    cli_string(&["keys", "status"])
+}
+
+/// Retrieve a secret from the key store
+///
+/// # Safety
+/// `entry_path` must point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rj_keys_get(entry_path: *const c_char) -> *mut c_char {
+    match arg_str(entry_path, "entry_path") {
+        Some(path) => cli_string(&["keys", "get", path]),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Fuzzy search the key store
+///
+/// # Safety
+/// `query` must point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rj_keys_search(query: *const c_char) -> *mut c_char {
+    match arg_str(query, "query") {
+        Some(q) => cli_string(&["keys", "search", q]),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Store a secret in the key store
+///
+/// # Safety
+/// `entry_path` and `value` must point to valid nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rj_keys_store(entry_path: *const c_char, value: *const c_char) -> c_int {
+    let Some(path) = arg_str(entry_path, "entry_path") else {
+        return RJ_ERR;
+    };
+    let Some(value) = arg_str(value, "value") else {
+        return RJ_ERR;
+    };
+    clear_last_error();
+    status(cli::store_secret(path, value))
+}
+
+/// Delete a secret from the key store
+///
+/// # Safety
+/// `entry_path` must point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rj_keys_delete(entry_path: *const c_char) -> c_int {
+    match arg_str(entry_path, "entry_path") {
+        Some(path) => cli_status(&["keys", "delete", path]),
+        None => RJ_ERR,
+    }
+}
+
+/// Resolve a secret across every source in the configured order, e.g.
+/// `GITHUB_TOKEN`, or mint a GitHub App token for `GitHubApp/<app>/token`
+///
+/// # Safety
+/// `name` must point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rj_resolve(name: *const c_char) -> *mut c_char {
+    let Some(name) = arg_str(name, "name") else {
+        return ptr::null_mut();
+    };
+    clear_last_error();
+    let resolution = resolver::resolve(name);
+    match resolution.value {
+        Some(value) => into_c_string(value),
+        None => {
+            let trail: Vec<String> = resolution.trail.iter().map(|a| a.display_text()).collect();
+            set_last_error(format!("{} not found: {}", name, trail.join("; ")));
+            ptr::null_mut()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_matches_package() {
+        let version = unsafe { CStr::from_ptr(rj_version()) };
+        assert_eq!(version.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn test_config_load_missing_file_sets_error() {
+        let path = CString::new("/nonexistent/remote-juggler/config.json").unwrap();
+        let handle = unsafe { rj_config_load(path.as_ptr()) };
+        assert!(handle.is_null());
+
+        let err = rj_last_error();
+        assert!(!err.is_null());
+        let message = unsafe { CStr::from_ptr(err) }.to_string_lossy().to_string();
+        assert!(message.contains("Failed to read config file"));
+        unsafe { rj_string_free(err) };
+    }
+
+    #[test]
+    fn test_config_load_and_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        std::fs::write(
+            &path,
+            r#"{
+                "version": "2.0.0",
+                "generated": "2024-01-01T00:00:00Z",
+                "identities": {
+                    "github-personal": {
+                        "provider": "github",
+                        "host": "github-personal",
+                        "hostname": "github.com",
+                        "user": "octocat",
+                        "email": "octocat@example.com",
+                        "sshKeyPath": "~/.ssh/id_ed25519",
+                        "credentialSource": "none"
+                    }
+                },
+                "state": { "currentIdentity": "github-personal", "lastSwitch": "" }
+            }"#,
+        )
+        .unwrap();
+
+        let c_path = CString::new(path.to_string_lossy().as_bytes()).unwrap();
+        let handle = unsafe { rj_config_load(c_path.as_ptr()) };
+        assert!(!handle.is_null());
+
+        let current = unsafe { rj_config_current_identity(handle) };
+        assert_eq!(
+            unsafe { CStr::from_ptr(current) }.to_str().unwrap(),
+            "github-personal"
+        );
+
+        let profiles = unsafe { rj_config_profiles_json(handle) };
+        let json = unsafe { CStr::from_ptr(profiles) }.to_str().unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed[0]["provider"], "github");
+
+        unsafe {
+            rj_string_free(current);
+            rj_string_free(profiles);
+            rj_config_free(handle);
+        }
+    }
+
+    #[test]
+    fn test_null_arguments_are_rejected() {
+        assert_eq!(unsafe { rj_switch(ptr::null()) }, RJ_ERR);
+        assert!(unsafe { rj_keys_get(ptr::null()) }.is_null());
+        assert!(unsafe { rj_config_current_identity(ptr::null()) }.is_null());
+        assert!(unsafe { rj_detect_json(ptr::null(), ptr::null()) }.is_null());
+    }
+}
@@ -0,0 +1,376 @@
+//! FIDO2 resident SSH keys
+//!
+//! Resident (discoverable) keys live on the security key itself, so a new
+//! machine can fetch their key handles with `ssh-keygen -K` instead of
+//! copying files around. The handles are downloaded into a private staging
+//! directory, reviewed, and then installed under `~/.ssh`, optionally as
+//! the `-sk` variant of an identity.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::{Config, Identity};
+use crate::error::{CliError, Error};
+use crate::platform;
+
+/// Suffix that marks an identity (and its key file) as a security key
+/// variant
+pub const VARIANT_SUFFIX: &str = "-sk";
+
+/// Environment variable the askpass helper reads the PIN from
+const PIN_ENV: &str = "REMOTE_JUGGLER_FIDO2_PIN";
+
+/// The ssh-keygen binary, overridable for tests
+pub fn ssh_keygen_program() -> String {
+    std::env::var("REMOTE_JUGGLER_SSH_KEYGEN_PATH").unwrap_or_else(|_| "ssh-keygen".to_string())
+}
+
+/// A resident key downloaded from the security key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResidentKey {
+    /// FIDO application string, `ssh:` or `ssh:<name>`
+    pub application: String,
+    /// e.g. `sk-ssh-ed25519@openssh.com`
+    pub key_type: String,
+    /// The public key line
+    pub public_key: String,
+    /// Key handle file in the staging directory
+    pub staged: PathBuf,
+}
+
+impl ResidentKey {
+    /// Name after the `ssh:` prefix, empty for the default application
+    pub fn label(&self) -> &str {
+        self.application
+            .strip_prefix("ssh:")
+            .unwrap_or(&self.application)
+    }
+
+    pub fn file_name(&self) -> String {
+        self.staged
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// The application a key handle file was written for
+///
+/// `ssh-keygen -K` names files `id_<type>_rk[_<application>[_<user>]]`.
+pub fn application_from_file(name: &str) -> Option<String> {
+    let rest = ["id_ed25519_sk_rk", "id_ecdsa_sk_rk"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))?;
+    match rest {
+        "" => Some("ssh:".to_string()),
+        _ => rest.strip_prefix('_').map(|app| format!("ssh:{}", app)),
+    }
+}
+
+/// A private directory for downloaded key handles, removed on drop
+pub struct Staging {
+    dir: PathBuf,
+}
+
+impl Staging {
+    pub fn create() -> Result<Self, Error> {
+        let base = platform::runtime_dir();
+        let dir = base.join(format!(
+            "remote-juggler-fido2-{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+        ));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder.create(&dir)?;
+        Ok(Self { dir })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Key handles in the directory, with their public keys
+    pub fn keys(&self) -> Result<Vec<ResidentKey>, Error> {
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(&self.dir)?.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(application) = application_from_file(&name) else {
+                continue;
+            };
+            let public_key = fs::read_to_string(self.dir.join(format!("{}.pub", name)))
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            let key_type = public_key
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            keys.push(ResidentKey {
+                application,
+                key_type,
+                public_key,
+                staged: entry.path(),
+            });
+        }
+        keys.sort_by(|a, b| a.application.cmp(&b.application));
+        Ok(keys)
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Download the resident keys on the inserted security key; blocks
+///
+/// ssh-keygen has no terminal to prompt on, so the PIN reaches it through
+/// a one-off askpass helper in the staging directory. The handles are
+/// written without a passphrase: they are useless without the key itself.
+pub fn download(pin: &str) -> Result<(Staging, Vec<ResidentKey>), Error> {
+    let staging = Staging::create()?;
+    let askpass = staging.path().join("askpass");
+    {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o700);
+        }
+        use std::io::Write;
+        let mut file = options.open(&askpass)?;
+        writeln!(file, "#!/bin/sh\nprintf '%s\\n' \"${}\"", PIN_ENV)?;
+    }
+
+    let program = ssh_keygen_program();
+    let output = Command::new(&program)
+        .args(["-K", "-N", ""])
+        .current_dir(staging.path())
+        .env("SSH_ASKPASS", &askpass)
+        .env("SSH_ASKPASS_REQUIRE", "force")
+        .env(PIN_ENV, pin)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Error::Cli(CliError::NotFound),
+            _ => Error::Cli(CliError::Spawn(e.to_string())),
+        })?;
+    fs::remove_file(&askpass)?;
+    if !output.status.success() {
+        return Err(Error::from_cli(CliError::NonZeroExit {
+            code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }));
+    }
+    let keys = staging.keys()?;
+    Ok((staging, keys))
+}
+
+/// Identity name of the `-sk` variant for `identity`
+pub fn variant_name(identity: &str) -> String {
+    let base = identity.strip_suffix(VARIANT_SUFFIX).unwrap_or(identity);
+    format!("{}{}", base, VARIANT_SUFFIX)
+}
+
+/// Identities a key could become a variant of, best match first
+///
+/// An application named after an identity (`ssh:gitlab-work`) suggests it.
+pub fn candidate_identities(config: &Config, key: &ResidentKey) -> Vec<String> {
+    let mut names: Vec<String> = config
+        .identities
+        .keys()
+        .filter(|name| !name.ends_with(VARIANT_SUFFIX))
+        .cloned()
+        .collect();
+    names.sort();
+    let label = key.label().trim_end_matches(VARIANT_SUFFIX);
+    if let Some(pos) = names.iter().position(|n| !label.is_empty() && n == label) {
+        let matched = names.remove(pos);
+        names.insert(0, matched);
+    }
+    names
+}
+
+/// Install a key handle and its public key as `ssh_dir/file_name`
+///
+/// A different key already at that path is kept as `<name>.old`. Returns
+/// the installed private key path.
+pub fn install(key: &ResidentKey, ssh_dir: &Path, file_name: &str) -> Result<PathBuf, Error> {
+    fs::create_dir_all(ssh_dir)?;
+    let dest = ssh_dir.join(file_name);
+    let dest_pub = ssh_dir.join(format!("{}.pub", file_name));
+    let contents = fs::read(&key.staged)?;
+    if dest.exists() && fs::read(&dest)? != contents {
+        fs::rename(&dest, ssh_dir.join(format!("{}.old", file_name)))?;
+        if dest_pub.exists() {
+            fs::rename(&dest_pub, ssh_dir.join(format!("{}.pub.old", file_name)))?;
+        }
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    use std::io::Write;
+    options.open(&dest)?.write_all(&contents)?;
+    fs::write(&dest_pub, format!("{}\n", key.public_key))?;
+    Ok(dest)
+}
+
+/// Create or update the `-sk` variant of `base` to use `key_path`
+///
+/// A new variant copies the base identity, with the `-sk` host alias.
+/// Returns the variant's name and whether it already existed.
+pub fn apply_variant(
+    config: &mut Config,
+    base: &str,
+    key_path: &Path,
+) -> Result<(String, bool), Error> {
+    let name = variant_name(base);
+    let key_path = key_path.to_string_lossy().into_owned();
+    if let Some(existing) = config.identities.get_mut(&name) {
+        existing.ssh_key_path = key_path;
+        return Ok((name, true));
+    }
+    let Some(base_identity) = config.identities.get(base) else {
+        return Err(Error::InvalidOutput {
+            program: "config",
+            message: format!("no identity named {}", base),
+        });
+    };
+    let variant = Identity {
+        host: variant_name(&base_identity.host),
+        ssh_key_path: key_path,
+        ..base_identity.clone()
+    };
+    config.identities.insert(name.clone(), variant);
+    Ok((name, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{self, ConfigBuilder};
+
+    fn identity(host: &str, key: &str) -> Identity {
+        Identity {
+            ssh_key_path: key.to_string(),
+            ..testing::identity("gitlab", host, "gitlab.com", "alice", "alice@example.com")
+        }
+    }
+
+    fn config() -> Config {
+        ConfigBuilder::new()
+            .identity("gitlab-work", identity("gitlab-work", "~/.ssh/gitlab-work"))
+            .identity(
+                "github-personal",
+                identity("github-personal", "~/.ssh/github-personal"),
+            )
+            .build()
+    }
+
+    fn key(application: &str) -> ResidentKey {
+        ResidentKey {
+            application: application.to_string(),
+            key_type: "sk-ssh-ed25519@openssh.com".to_string(),
+            public_key: "sk-ssh-ed25519@openssh.com AAAA".to_string(),
+            staged: PathBuf::from("id_ed25519_sk_rk"),
+        }
+    }
+
+    #[test]
+    fn test_application_from_file() {
+        assert_eq!(
+            application_from_file("id_ed25519_sk_rk").as_deref(),
+            Some("ssh:")
+        );
+        assert_eq!(
+            application_from_file("id_ecdsa_sk_rk_gitlab-work").as_deref(),
+            Some("ssh:gitlab-work")
+        );
+        assert_eq!(application_from_file("id_ed25519_sk_rk.pub"), None);
+        assert_eq!(application_from_file("id_ed25519"), None);
+    }
+
+    #[test]
+    fn test_candidate_identities() {
+        let config = config();
+        assert_eq!(
+            candidate_identities(&config, &key("ssh:gitlab-work")),
+            vec!["gitlab-work", "github-personal"]
+        );
+        assert_eq!(
+            candidate_identities(&config, &key("ssh:")),
+            vec!["github-personal", "gitlab-work"]
+        );
+    }
+
+    #[test]
+    fn test_apply_variant() {
+        let mut config = config();
+        let path = Path::new("/home/alice/.ssh/gitlab-work-sk");
+
+        let (name, existed) = apply_variant(&mut config, "gitlab-work", path).unwrap();
+        assert_eq!((name.as_str(), existed), ("gitlab-work-sk", false));
+        let variant = &config.identities["gitlab-work-sk"];
+        assert_eq!(variant.host, "gitlab-work-sk");
+        assert_eq!(variant.user, "alice");
+        assert!(variant.is_security_key());
+
+        // Choosing the variant itself updates it in place
+        let moved = Path::new("/keys/gitlab-work-sk");
+        let (name, existed) = apply_variant(&mut config, "gitlab-work-sk", moved).unwrap();
+        assert_eq!((name.as_str(), existed), ("gitlab-work-sk", true));
+        assert_eq!(
+            config.identities["gitlab-work-sk"].ssh_key_path,
+            "/keys/gitlab-work-sk"
+        );
+
+        assert!(apply_variant(&mut config, "missing", path).is_err());
+    }
+
+    #[test]
+    fn test_install_keeps_previous_key() {
+        let staging = tempfile::tempdir().unwrap();
+        let ssh_dir = tempfile::tempdir().unwrap();
+        let staged = staging.path().join("id_ed25519_sk_rk_work");
+        fs::write(&staged, "new handle").unwrap();
+        let resident = ResidentKey {
+            staged,
+            ..key("ssh:work")
+        };
+        fs::write(ssh_dir.path().join("work-sk"), "old handle").unwrap();
+
+        let dest = install(&resident, ssh_dir.path(), "work-sk").unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "new handle");
+        assert_eq!(
+            fs::read_to_string(ssh_dir.path().join("work-sk.old")).unwrap(),
+            "old handle"
+        );
+        assert_eq!(
+            fs::read_to_string(ssh_dir.path().join("work-sk.pub")).unwrap(),
+            "sk-ssh-ed25519@openssh.com AAAA\n"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&dest).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! GCP Secret Manager backend
+//!
+//! Mounts the secrets of one GCP project into the key namespace as
+//! `GCP/{secret}`. The project comes from the active identity's
+//! `cloud.gcpProject`, falling back to the project gcloud would use, so
+//! switching identity switches projects.
+//!
+//! Authentication uses application-default credentials: the access token is
+//! minted by `gcloud auth application-default print-access-token`, and the
+//! REST API is called directly. Reads return the latest version; writes add
+//! a new version, creating the secret (with automatic replication) first if
+//! needed. Use `backend::import_into_kdbx` to mirror secrets into KDBX.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use base64::Engine;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::backend::SecretBackend;
+use crate::cli::{self, RunOptions};
+use crate::config::Config;
+use crate::error::Error;
+use crate::http;
+
+/// Namespace prefix for Secret Manager secrets
+pub const NAMESPACE: &str = "GCP";
+
+const API_BASE: &str = "https://secretmanager.googleapis.com/v1";
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The gcloud binary, overridable like the other external tools
+pub fn gcloud_program() -> String {
+    std::env::var("REMOTE_JUGGLER_GCLOUD_PATH").unwrap_or_else(|_| "gcloud".to_string())
+}
+
+/// Where application-default credentials live
+///
+/// `$GOOGLE_APPLICATION_CREDENTIALS`, else the file written by
+/// `gcloud auth application-default login`.
+pub fn adc_path() -> Option<PathBuf> {
+    std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS")
+        .map(PathBuf::from)
+        .or_else(|| {
+            dirs::config_dir().map(|c| c.join("gcloud/application_default_credentials.json"))
+        })
+}
+
+/// Project for the active identity, else the environment's default project
+pub fn current_project() -> Option<String> {
+    let from_identity = Config::load().ok().and_then(|config| {
+        config
+            .current_identity()
+            .and_then(|identity| identity.cloud.gcp_project.clone())
+    });
+    from_identity.or_else(|| {
+        ["GOOGLE_CLOUD_PROJECT", "CLOUDSDK_CORE_PROJECT"]
+            .iter()
+            .find_map(|var| std::env::var(var).ok().filter(|p| !p.is_empty()))
+    })
+}
+
+/// Returns true if `name` is a valid secret id (letters, digits, `-`, `_`)
+pub fn is_valid_secret_id(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 255
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListSecretsResponse {
+    #[serde(default)]
+    secrets: Vec<SecretResource>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SecretResource {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct AccessResponse {
+    payload: Payload,
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    data: String,
+}
+
+/// Secret id from a resource name (`projects/p/secrets/{id}`)
+pub fn secret_id(resource_name: &str) -> &str {
+    resource_name
+        .rsplit_once("/secrets/")
+        .map_or(resource_name, |(_, id)| id)
+}
+
+/// URL of one page of the secrets list; page tokens may contain `+`, `/` or `=`
+fn list_url(base: &str, page_token: Option<&str>) -> String {
+    match page_token {
+        Some(page) => format!(
+            "{}?pageSize=250&pageToken={}",
+            base,
+            utf8_percent_encode(page, NON_ALPHANUMERIC)
+        ),
+        None => format!("{}?pageSize=250", base),
+    }
+}
+
+/// Decode the base64 payload of an `:access` response
+pub fn decode_payload(data: &str) -> Result<String, Error> {
+    let invalid = |message: String| Error::InvalidOutput {
+        program: "Secret Manager",
+        message,
+    };
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| invalid(e.to_string()))?;
+    String::from_utf8(bytes).map_err(|_| invalid("secret is not UTF-8 text".to_string()))
+}
+
+/// Secret Manager in one GCP project
+///
+/// The access token is fetched once per instance.
+#[derive(Debug)]
+pub struct GcpBackend {
+    project: Option<String>,
+    token: Mutex<Option<String>>,
+}
+
+impl GcpBackend {
+    pub fn new(project: Option<String>) -> Self {
+        Self {
+            project,
+            token: Mutex::new(None),
+        }
+    }
+
+    /// The backend for the active identity's project
+    pub fn from_config() -> Self {
+        Self::new(current_project())
+    }
+
+    pub fn project(&self) -> Option<&str> {
+        self.project.as_deref()
+    }
+
+    fn project_or_err(&self) -> Result<&str, Error> {
+        self.project().ok_or_else(|| Error::InvalidOutput {
+            program: "gcloud",
+            message: "no GCP project; set cloud.gcpProject on the identity".to_string(),
+        })
+    }
+
+    fn token(&self) -> Result<String, Error> {
+        if let Some(token) = self.token.lock().ok().and_then(|t| t.clone()) {
+            return Ok(token);
+        }
+        let options = RunOptions {
+            timeout: TOKEN_TIMEOUT,
+            retries: 0,
+            cancel: None,
+        };
+        let args = cli::args(&["auth", "application-default", "print-access-token"]);
+        let token = cli::run_program(&gcloud_program(), &args, &options)
+            .map_err(Error::from_cli)?
+            .trim()
+            .to_string();
+        if let Ok(mut cached) = self.token.lock() {
+            *cached = Some(token.clone());
+        }
+        Ok(token)
+    }
+
+    fn secret_url(&self, name: &str) -> Result<String, Error> {
+        if !is_valid_secret_id(name) {
+            return Err(Error::InvalidOutput {
+                program: "Secret Manager",
+                message: format!("{} is not a valid secret id", name),
+            });
+        }
+        Ok(format!(
+            "{}/projects/{}/secrets/{}",
+            API_BASE,
+            self.project_or_err()?,
+            name
+        ))
+    }
+
+    fn create_secret(&self, name: &str, token: &str) -> Result<(), Error> {
+        let url = format!(
+            "{}/projects/{}/secrets?secretId={}",
+            API_BASE,
+            self.project_or_err()?,
+            name
+        );
+        let body = json!({ "replication": { "automatic": {} } });
+        http::post_json::<serde_json::Value>(&url, Some(token), &body).map(|_| ())
+    }
+}
+
+impl SecretBackend for GcpBackend {
+    fn namespace(&self) -> &'static str {
+        NAMESPACE
+    }
+
+    fn display_name(&self) -> &'static str {
+        "GCP Secret Manager"
+    }
+
+    fn is_available(&self) -> bool {
+        self.project.is_some()
+            && adc_path().is_some_and(|p| p.is_file())
+            && cli::find_program(&gcloud_program()).is_some()
+    }
+
+    fn list(&self) -> Result<Vec<String>, Error> {
+        let token = self.token()?;
+        let base = format!("{}/projects/{}/secrets", API_BASE, self.project_or_err()?);
+        let mut names = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let url = list_url(&base, page_token.as_deref());
+            let page: ListSecretsResponse = http::get_json(&url, Some(&token))?;
+            names.extend(page.secrets.iter().map(|s| secret_id(&s.name).to_string()));
+            match page.next_page_token.filter(|t| !t.is_empty()) {
+                Some(next) => page_token = Some(next),
+                None => break,
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn get(&self, name: &str) -> Result<String, Error> {
+        let url = format!("{}/versions/latest:access", self.secret_url(name)?);
+        let response: AccessResponse = http::get_json(&url, Some(&self.token()?))?;
+        decode_payload(&response.payload.data)
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), Error> {
+        let token = self.token()?;
+        let url = format!("{}:addVersion", self.secret_url(name)?);
+        let body = json!({
+            "payload": {
+                "data": base64::engine::general_purpose::STANDARD.encode(value)
+            }
+        });
+        match http::post_json::<serde_json::Value>(&url, Some(&token), &body) {
+            Err(Error::Http { status: 404, .. }) => {
+                self.create_secret(name, &token)?;
+                http::post_json::<serde_json::Value>(&url, Some(&token), &body).map(|_| ())
+            }
+            result => result.map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_id() {
+        assert_eq!(
+            secret_id("projects/acme-prod/secrets/GITHUB_TOKEN"),
+            "GITHUB_TOKEN"
+        );
+        assert_eq!(secret_id("GITHUB_TOKEN"), "GITHUB_TOKEN");
+    }
+
+    #[test]
+    fn test_is_valid_secret_id() {
+        assert!(is_valid_secret_id("github-token_2"));
+        assert!(!is_valid_secret_id("work/github"));
+        assert!(!is_valid_secret_id(""));
+    }
+
+    #[test]
+    fn test_list_url() {
+        let base = "https://secretmanager.googleapis.com/v1/projects/p/secrets";
+        assert_eq!(list_url(base, None), format!("{}?pageSize=250", base));
+        assert_eq!(
+            list_url(base, Some("Cg+a/b==")),
+            format!("{}?pageSize=250&pageToken=Cg%2Ba%2Fb%3D%3D", base)
+        );
+    }
+
+    #[test]
+    fn test_decode_payload() {
+        assert_eq!(decode_payload("aHVudGVyMg==").unwrap(), "hunter2");
+        assert!(decode_payload("not base64!").is_err());
+    }
+
+    #[test]
+    fn test_parse_list_response() {
+        let page: ListSecretsResponse = serde_json::from_str(
+            r#"{"secrets": [{"name": "projects/p/secrets/A", "replication": {}}],
+                "nextPageToken": "abc", "totalSize": 2}"#,
+        )
+        .unwrap();
+        assert_eq!(secret_id(&page.secrets[0].name), "A");
+        assert_eq!(page.next_page_token.as_deref(), Some("abc"));
+
+        let empty: ListSecretsResponse = serde_json::from_str("{}").unwrap();
+        assert!(empty.secrets.is_empty());
+    }
+
+    #[test]
+    fn test_missing_project_is_unavailable() {
+        let backend = GcpBackend::new(None);
+        assert!(!backend.is_available());
+        assert!(backend.get("TOKEN").is_err());
+    }
+}
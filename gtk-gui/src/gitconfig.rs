@@ -0,0 +1,329 @@
+//! Managed includeIf sections in ~/.gitconfig
+//!
+//! Each directory rule gets its identity's user.name, user.email and
+//! signing settings through git itself: the settings go in a fragment per
+//! identity under the config directory's `gitconfig/`, and a managed block
+//! in ~/.gitconfig includes it with `includeIf "gitdir:<path>/"`. Any
+//! repository under the directory then commits as that identity, even
+//! when nothing ran switch there. Rules are written from the least to the
+//! most specific path, because git lets the last include win. The block
+//! is reviewed and written like the ssh config's managed block.
+
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, DirectoryRule, Identity, SigningFormat};
+use crate::export;
+use crate::gitsign;
+use crate::platform;
+use crate::ssh_config::{self, BlockState, Markers};
+
+pub const BEGIN_MARKER: &str = "# BEGIN RemoteJuggler managed includes";
+pub const END_MARKER: &str = "# END RemoteJuggler managed includes";
+
+pub const MARKERS: Markers = Markers {
+    begin: BEGIN_MARKER,
+    end: END_MARKER,
+};
+
+/// ~/.gitconfig
+pub fn path() -> Option<PathBuf> {
+    platform::home_dir().map(|home| home.join(".gitconfig"))
+}
+
+/// Where the per-identity fragments are kept
+pub fn fragments_dir() -> Option<PathBuf> {
+    platform::config_dir().map(|dir| dir.join("gitconfig"))
+}
+
+pub fn fragment_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.gitconfig", name))
+}
+
+/// The git config an identity's fragment sets, in order
+pub fn identity_settings(identity: &Identity) -> Vec<(String, String)> {
+    let mut settings = vec![
+        ("user.name".to_string(), identity.user.clone()),
+        ("user.email".to_string(), identity.email.clone()),
+    ];
+    let gpg = &identity.gpg;
+    let format = match gpg.format {
+        SigningFormat::Gitsign => {
+            settings.extend(
+                gitsign::git_settings(gpg)
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value)),
+            );
+            return settings;
+        }
+        SigningFormat::Gpg => "openpgp",
+        SigningFormat::Ssh => "ssh",
+    };
+    match identity.signing_key() {
+        Some(key) => {
+            let key = match gpg.format {
+                SigningFormat::Ssh => platform::expand_tilde(&key).to_string_lossy().into_owned(),
+                _ => key,
+            };
+            settings.push(("user.signingkey".to_string(), key));
+            settings.push(("gpg.format".to_string(), format.to_string()));
+            settings.push(("commit.gpgsign".to_string(), gpg.sign_commits.to_string()));
+            settings.push(("tag.gpgsign".to_string(), gpg.sign_tags.to_string()));
+        }
+        None => settings.push(("commit.gpgsign".to_string(), "false".to_string())),
+    }
+    settings
+}
+
+/// A value as git config reads it back: quoted when it has comment
+/// characters, quotes, backslashes or surrounding spaces
+pub fn quote(value: &str) -> String {
+    let plain =
+        !value.contains(['#', ';', '"', '\\']) && value.trim() == value && !value.is_empty();
+    if plain {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// Settings as git config text, e.g. `gpg.x509.program` under
+/// `[gpg "x509"]`
+pub fn render_settings(settings: &[(String, String)]) -> String {
+    let mut out = String::new();
+    let mut current = String::new();
+    for (key, value) in settings {
+        let Some((section, name)) = key.rsplit_once('.') else {
+            continue;
+        };
+        let header = match section.split_once('.') {
+            Some((section, subsection)) => format!("[{} \"{}\"]", section, subsection),
+            None => format!("[{}]", section),
+        };
+        if header != current {
+            out.push_str(&header);
+            out.push('\n');
+            current = header;
+        }
+        out.push_str(&format!("\t{} = {}\n", name, quote(value)));
+    }
+    out
+}
+
+/// The fragment for one identity
+pub fn render_fragment(name: &str, identity: &Identity) -> String {
+    format!(
+        "# Generated by RemoteJuggler for {}; rewritten when the includes are\n\
+         # written\n{}",
+        name,
+        render_settings(&identity_settings(identity))
+    )
+}
+
+/// The `gitdir:` pattern for a rule: its path with a trailing slash, so it
+/// covers everything below
+pub fn gitdir(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let path = path.trim_end_matches('/');
+    format!("gitdir:{}/", path)
+}
+
+/// The rules git can apply, least specific first; the second list names
+/// the rules whose identity doesn't exist
+pub fn usable_rules(config: &Config) -> (Vec<&DirectoryRule>, Vec<String>) {
+    let (mut usable, unknown): (Vec<&DirectoryRule>, Vec<&DirectoryRule>) = config
+        .settings
+        .directory_rules
+        .iter()
+        .filter(|rule| !rule.path.is_empty())
+        .partition(|rule| config.identities.contains_key(&rule.identity));
+    usable.sort_by_key(|rule| platform::expand_tilde(&rule.path).components().count());
+    let unknown = unknown
+        .iter()
+        .map(|rule| format!("{} ({})", rule.path, rule.identity))
+        .collect();
+    (usable, unknown)
+}
+
+/// includeIf sections for every usable rule
+pub fn render_body(config: &Config, fragments_dir: &Path) -> String {
+    let (rules, _) = usable_rules(config);
+    rules
+        .into_iter()
+        .map(|rule| {
+            format!(
+                "# {}\n[includeIf \"{}\"]\n\tpath = {}\n",
+                rule.identity,
+                gitdir(&rule.path),
+                quote(&fragment_path(fragments_dir, &rule.identity).to_string_lossy())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A reviewed change to ~/.gitconfig and the fragments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub path: PathBuf,
+    /// The file as it was reviewed; empty if it doesn't exist
+    pub current: String,
+    pub proposed: String,
+    pub state: BlockState,
+    /// Fragments to write, with their contents
+    pub fragments: Vec<(PathBuf, String)>,
+    /// Whether a fragment differs from what is on disk
+    pub fragments_changed: bool,
+    /// Rules naming an identity that doesn't exist, left out
+    pub unknown: Vec<String>,
+}
+
+impl Plan {
+    pub fn is_noop(&self) -> bool {
+        self.current == self.proposed && !self.fragments_changed
+    }
+
+    pub fn diff(&self) -> String {
+        ssh_config::render_diff(&ssh_config::diff(&self.current, &self.proposed), 2)
+    }
+}
+
+fn read_or_empty(path: &Path) -> std::io::Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Work out what writing the includes to `path` would change
+pub fn plan_for(config: &Config, path: &Path, fragments_dir: &Path) -> std::io::Result<Plan> {
+    let current = read_or_empty(path)?;
+    let state = ssh_config::managed_state(&current, MARKERS);
+    let proposed = if state == BlockState::Unterminated {
+        current.clone()
+    } else {
+        let block = ssh_config::render_managed(MARKERS, &render_body(config, fragments_dir));
+        ssh_config::apply_managed(&current, &block, MARKERS)
+    };
+    let (rules, unknown) = usable_rules(config);
+    let mut fragments: Vec<(PathBuf, String)> = Vec::new();
+    for rule in rules {
+        let fragment = fragment_path(fragments_dir, &rule.identity);
+        if fragments.iter().any(|(path, _)| *path == fragment) {
+            continue;
+        }
+        let text = render_fragment(&rule.identity, &config.identities[&rule.identity]);
+        fragments.push((fragment, text));
+    }
+    let mut fragments_changed = false;
+    for (path, text) in &fragments {
+        fragments_changed |= read_or_empty(path)? != *text;
+    }
+    Ok(Plan {
+        path: path.to_path_buf(),
+        current,
+        proposed,
+        state,
+        fragments,
+        fragments_changed,
+        unknown,
+    })
+}
+
+/// [`plan_for`] the user's ~/.gitconfig
+pub fn plan(config: &Config) -> std::io::Result<Plan> {
+    let path = path().ok_or_else(|| std::io::Error::other("No home directory"))?;
+    let dir = fragments_dir().ok_or_else(|| std::io::Error::other("No config directory"))?;
+    plan_for(config, &path, &dir)
+}
+
+/// Write the fragments, then the reviewed ~/.gitconfig; fails like
+/// [`ssh_config::write`] if the file changed since it was reviewed. The
+/// previous file is kept as `.gitconfig.bak`
+pub fn write(plan: &Plan) -> std::io::Result<()> {
+    for (path, text) in &plan.fragments {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        export::write_private(path, text)?;
+    }
+    ssh_config::write_reviewed(
+        &plan.path,
+        plan.state,
+        &plan.current,
+        &plan.proposed,
+        "~/.gitconfig",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::config::GpgConfig;
+
+    #[test]
+    fn test_plan_and_write_includes() {
+        let rule = |path: &str, identity: &str| DirectoryRule {
+            path: path.to_string(),
+            identity: identity.to_string(),
+        };
+        let config = ConfigBuilder::new()
+            .identity(
+                "gitlab-work",
+                Identity {
+                    gpg: GpgConfig {
+                        key_id: "ABCD1234".to_string(),
+                        sign_commits: true,
+                        ..Default::default()
+                    },
+                    ..identity(
+                        "gitlab",
+                        "gitlab-work",
+                        "gitlab.com",
+                        "Work Me",
+                        "me@acme.dev",
+                    )
+                },
+            )
+            .identity(
+                "github-oss",
+                identity("github", "github-oss", "github.com", "Me", "me@example.com"),
+            )
+            .settings(|settings| {
+                settings.directory_rules = vec![
+                    rule("~/src/work/oss", "github-oss"),
+                    rule("~/src/work", "gitlab-work"),
+                    rule("~/src/old", "removed"),
+                ]
+            })
+            .build();
+
+        assert_eq!(
+            render_fragment("gitlab-work", &config.identities["gitlab-work"]),
+            "# Generated by RemoteJuggler for gitlab-work; rewritten when the includes are\n\
+             # written\n[user]\n\tname = Work Me\n\temail = me@acme.dev\n\
+             \tsigningkey = ABCD1234\n[gpg]\n\tformat = openpgp\n\
+             [commit]\n\tgpgsign = true\n[tag]\n\tgpgsign = false\n"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".gitconfig");
+        let fragments = dir.path().join("fragments");
+        std::fs::write(&path, "[core]\n\teditor = vim\n").unwrap();
+        let plan = plan_for(&config, &path, &fragments).unwrap();
+        assert_eq!(plan.state, BlockState::Missing);
+        assert_eq!(plan.unknown, vec!["~/src/old (removed)"]);
+        // The more specific rule comes last so it wins
+        let work = plan.proposed.find("gitdir:~/src/work/\"").unwrap();
+        let oss = plan.proposed.find("gitdir:~/src/work/oss/\"").unwrap();
+        assert!(work < oss);
+        assert!(plan.proposed.starts_with("[core]\n\teditor = vim\n\n"));
+
+        write(&plan).unwrap();
+        assert!(fragment_path(&fragments, "github-oss").exists());
+        let again = plan_for(&config, &path, &fragments).unwrap();
+        assert_eq!(again.state, BlockState::Intact);
+        assert!(again.is_noop());
+    }
+}
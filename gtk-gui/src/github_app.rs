@@ -0,0 +1,227 @@
+//! GitHub App installation tokens
+//!
+//! Automation can authenticate as a GitHub App's installation instead of
+//! with someone's personal token. The app's ID and private key are secrets
+//! like any other, kept under `RemoteJuggler/GitHubApps/<app>/` in the key
+//! store, Setec or wherever the resolver finds them. An RS256 JWT signed
+//! with the key is exchanged for an installation token, which GitHub
+//! issues for an hour; it is reused for 50 minutes, then a new one is
+//! minted. The resolver mints one for `GitHubApp/<app>/token`.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs1v15::SigningKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::signature::{RandomizedSigner, SignatureEncoding};
+use rsa::RsaPrivateKey;
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use crate::error::Error;
+use crate::http;
+use crate::pubkeys::Provider;
+
+/// Resolver names that mint a token, `GitHubApp/<app>/token`
+pub const PREFIX: &str = "GitHubApp/";
+
+/// Where an app's secrets are looked up
+pub const SECRET_GROUP: &str = "RemoteJuggler/GitHubApps";
+
+/// The app's numeric ID, or its client ID
+pub const APP_ID: &str = "app-id";
+/// The PEM private key downloaded from the app's settings
+pub const PRIVATE_KEY: &str = "private-key";
+/// Optional when the app is installed once
+pub const INSTALLATION_ID: &str = "installation-id";
+
+/// How long a minted token is reused; GitHub's last an hour
+pub const REFRESH_AFTER: Duration = Duration::from_secs(50 * 60);
+
+/// The app a resolver name mints for, e.g. `GitHubApp/ci-bot/token`
+pub fn parse_name(name: &str) -> Option<&str> {
+    let app = name.strip_prefix(PREFIX)?.strip_suffix("/token")?;
+    (!app.is_empty() && !app.contains('/')).then_some(app)
+}
+
+/// The path an app's secret is resolved under
+pub fn secret_path(app: &str, field: &str) -> String {
+    format!("{}/{}/{}", SECRET_GROUP, app, field)
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::InvalidOutput {
+        program: "GitHub App",
+        message: message.into(),
+    }
+}
+
+/// Read a PKCS#1 key, as GitHub issues them, or a PKCS#8 one
+pub fn parse_private_key(pem: &str) -> Result<RsaPrivateKey, Error> {
+    let pem = pem.trim();
+    RsaPrivateKey::from_pkcs1_pem(pem)
+        .or_else(|_| RsaPrivateKey::from_pkcs8_pem(pem))
+        .map_err(|e| invalid(format!("the private key isn't an RSA PEM key: {}", e)))
+}
+
+/// The JWT the app authenticates with, valid for nine minutes from a
+/// minute before `now` to allow for clock drift
+pub fn jwt(app_id: &str, key: &RsaPrivateKey, now: u64) -> Result<String, Error> {
+    let header = json!({ "alg": "RS256", "typ": "JWT" });
+    let claims = json!({
+        "iat": now.saturating_sub(60),
+        "exp": now + 9 * 60,
+        "iss": app_id.trim(),
+    });
+    let input = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+    let signature = SigningKey::<Sha256>::new(key.clone())
+        .try_sign_with_rng(&mut rsa::rand_core::OsRng, input.as_bytes())
+        .map_err(|e| invalid(format!("signing the JWT failed: {}", e)))?;
+    Ok(format!(
+        "{}.{}",
+        input,
+        URL_SAFE_NO_PAD.encode(signature.to_bytes())
+    ))
+}
+
+/// The installation to mint for when none is stored: the app's only one
+pub fn pick_installation(installations: &Value) -> Result<u64, Error> {
+    let ids: Vec<u64> = installations
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|i| i["id"].as_u64())
+        .collect();
+    match ids.as_slice() {
+        [id] => Ok(*id),
+        [] => Err(invalid("the app isn't installed anywhere")),
+        _ => Err(invalid(format!(
+            "the app has {} installations; store the one to use as {}",
+            ids.len(),
+            INSTALLATION_ID
+        ))),
+    }
+}
+
+/// Exchange the app's JWT for an installation token; blocks
+pub fn mint(app_id: &str, private_key: &str, installation: Option<&str>) -> Result<String, Error> {
+    let key = parse_private_key(private_key)?;
+    let jwt = jwt(app_id, &key, crate::expiry::now())?;
+    let base = Provider::GitHub.api_base("");
+    let installation = match installation.map(str::trim).filter(|id| !id.is_empty()) {
+        Some(id) => id
+            .parse::<u64>()
+            .map_err(|_| invalid(format!("{} isn't an installation ID", id)))?,
+        None => pick_installation(&http::get_json::<Value>(
+            &format!("{}/app/installations", base),
+            Some(&jwt),
+        )?)?,
+    };
+    let response: Value = http::post_json(
+        &format!("{}/app/installations/{}/access_tokens", base, installation),
+        Some(&jwt),
+        &json!({}),
+    )?;
+    response["token"]
+        .as_str()
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| invalid("GitHub returned no token"))
+}
+
+/// Minted tokens by app, until they are due for a refresh
+#[derive(Debug, Default)]
+pub struct TokenCache {
+    entries: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl TokenCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token minted less than `REFRESH_AFTER` ago
+    pub fn fresh(&self, app: &str) -> Option<String> {
+        self.fresh_at(app, Instant::now())
+    }
+
+    fn fresh_at(&self, app: &str, now: Instant) -> Option<String> {
+        let entries = self.entries.lock().ok()?;
+        let (token, minted_at) = entries.get(app)?;
+        (now.duration_since(*minted_at) < REFRESH_AFTER).then(|| token.clone())
+    }
+
+    pub fn insert(&self, app: &str, token: &str) {
+        self.insert_at(app, token, Instant::now());
+    }
+
+    fn insert_at(&self, app: &str, token: &str, now: Instant) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(app.to_string(), (token.to_string(), now));
+        }
+    }
+}
+
+/// Process-wide cache shared by every resolver
+pub fn cache() -> &'static TokenCache {
+    static CACHE: OnceLock<TokenCache> = OnceLock::new();
+    CACHE.get_or_init(TokenCache::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::pkcs1v15::{Signature, VerifyingKey};
+    use rsa::signature::Verifier;
+
+    #[test]
+    fn test_jwt_and_token_cache() {
+        assert_eq!(parse_name("GitHubApp/ci-bot/token"), Some("ci-bot"));
+        assert_eq!(parse_name("GitHubApp/ci-bot"), None);
+        assert_eq!(parse_name("GitHubApp/a/b/token"), None);
+        assert_eq!(
+            secret_path("ci-bot", PRIVATE_KEY),
+            "RemoteJuggler/GitHubApps/ci-bot/private-key"
+        );
+
+        let key = RsaPrivateKey::new(&mut rsa::rand_core::OsRng, 1024).unwrap();
+        let token = jwt("12345", &key, 1_767_225_600).unwrap();
+        let parts: Vec<&str> = token.split('.').collect();
+        assert_eq!(parts.len(), 3);
+        let claims: Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap();
+        assert_eq!(
+            claims,
+            json!({ "iat": 1_767_225_540u64, "exp": 1_767_226_140u64, "iss": "12345" })
+        );
+        let signature =
+            Signature::try_from(URL_SAFE_NO_PAD.decode(parts[2]).unwrap().as_slice()).unwrap();
+        VerifyingKey::<Sha256>::new(key.to_public_key())
+            .verify(format!("{}.{}", parts[0], parts[1]).as_bytes(), &signature)
+            .unwrap();
+        assert!(parse_private_key("not a key").is_err());
+
+        assert_eq!(pick_installation(&json!([{ "id": 7 }])).unwrap(), 7);
+        assert!(pick_installation(&json!([{ "id": 7 }, { "id": 8 }])).is_err());
+
+        let cache = TokenCache::new();
+        let minted = Instant::now();
+        cache.insert_at("ci-bot", "ghs_abc", minted);
+        assert_eq!(
+            cache.fresh_at("ci-bot", minted + Duration::from_secs(49 * 60)),
+            Some("ghs_abc".to_string())
+        );
+        assert_eq!(
+            cache.fresh_at("ci-bot", minted + Duration::from_secs(51 * 60)),
+            None
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Sigstore keyless signing with gitsign
+//!
+//! gitsign signs commits with a short-lived certificate Fulcio issues for
+//! whoever logs in through the OIDC issuer, and logs the signature in
+//! Rekor, so there is no key to keep. The CLI writes the git config on
+//! switch; this module describes what it writes and which identity the
+//! certificate is for, for the profile details.
+
+use std::time::Duration;
+
+use crate::cli::{self, RunOptions};
+use crate::config::{GpgConfig, Identity};
+use crate::error::Error;
+
+/// gitsign's defaults: the Sigstore public-good instance
+pub const DEFAULT_ISSUER: &str = "https://oauth2.sigstore.dev/auth";
+pub const DEFAULT_FULCIO: &str = "https://fulcio.sigstore.dev";
+pub const DEFAULT_REKOR: &str = "https://rekor.sigstore.dev";
+
+/// Who a gitsign certificate names and who vouches for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FulcioIdentity {
+    /// The certificate's subject: the email the OIDC login must return
+    pub subject: String,
+    pub issuer: String,
+    /// The upstream login Sigstore's Dex is sent to, if pinned
+    pub connector: Option<&'static str>,
+    pub fulcio: String,
+    pub rekor: String,
+}
+
+impl FulcioIdentity {
+    pub fn for_identity(identity: &Identity) -> Self {
+        let config = identity.gpg.gitsign.clone().unwrap_or_default();
+        let set = |value: Option<String>| value.filter(|v| !v.is_empty());
+        Self {
+            subject: identity.email.clone(),
+            connector: set(config.connector_id.clone()).map(|id| connector_name(&id)),
+            issuer: set(config.issuer).unwrap_or_else(|| DEFAULT_ISSUER.to_string()),
+            fulcio: set(config.fulcio).unwrap_or_else(|| DEFAULT_FULCIO.to_string()),
+            rekor: set(config.rekor).unwrap_or_else(|| DEFAULT_REKOR.to_string()),
+        }
+    }
+
+    /// e.g. "me@acme.dev via GitHub (https://oauth2.sigstore.dev/auth)"
+    pub fn display_text(&self) -> String {
+        match self.connector {
+            Some(connector) => format!("{} via {} ({})", self.subject, connector, self.issuer),
+            None => format!("{} via {}", self.subject, self.issuer),
+        }
+    }
+}
+
+/// The login a Dex connector id points at
+pub fn connector_name(id: &str) -> &'static str {
+    let id = id.to_lowercase();
+    if id.contains("github") {
+        "GitHub"
+    } else if id.contains("google") {
+        "Google"
+    } else if id.contains("microsoft") {
+        "Microsoft"
+    } else {
+        "a custom connector"
+    }
+}
+
+/// The git config the CLI writes on switch, in order
+pub fn git_settings(gpg: &GpgConfig) -> Vec<(&'static str, String)> {
+    let config = gpg.gitsign.clone().unwrap_or_default();
+    let mut settings = vec![
+        ("gpg.format", "x509".to_string()),
+        ("gpg.x509.program", "gitsign".to_string()),
+        ("commit.gpgsign", gpg.sign_commits.to_string()),
+        ("tag.gpgsign", gpg.sign_tags.to_string()),
+    ];
+    let optional = [
+        ("gitsign.issuer", &config.issuer),
+        ("gitsign.fulcio", &config.fulcio),
+        ("gitsign.rekor", &config.rekor),
+        ("gitsign.connectorID", &config.connector_id),
+    ];
+    settings.extend(
+        optional
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value.clone().filter(|v| !v.is_empty())?))),
+    );
+    settings
+}
+
+/// The installed gitsign's version; blocks
+pub fn version() -> Result<String, Error> {
+    let options = RunOptions {
+        timeout: Duration::from_secs(5),
+        retries: 0,
+        cancel: None,
+    };
+    let out = cli::run_program("gitsign", &cli::args(&["--version"]), &options)
+        .map_err(Error::from_cli)?;
+    // "gitsign version v0.10.1" followed by build details
+    Ok(out
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().last())
+        .unwrap_or_default()
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::identity;
+    use crate::config::{GitsignConfig, SigningFormat};
+
+    #[test]
+    fn test_fulcio_identity_and_settings() {
+        let mut identity = Identity {
+            gpg: GpgConfig {
+                format: SigningFormat::Gitsign,
+                sign_commits: true,
+                gitsign: Some(GitsignConfig {
+                    connector_id: Some("https://github.com/login/oauth".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..identity("github", "github-work", "github.com", "me", "me@acme.dev")
+        };
+        assert!(identity.has_gpg_signing());
+        let fulcio = FulcioIdentity::for_identity(&identity);
+        assert_eq!(
+            fulcio.display_text(),
+            "me@acme.dev via GitHub (https://oauth2.sigstore.dev/auth)"
+        );
+        assert_eq!(fulcio.fulcio, DEFAULT_FULCIO);
+
+        let settings = git_settings(&identity.gpg);
+        assert_eq!(settings[0], ("gpg.format", "x509".to_string()));
+        assert_eq!(settings[2], ("commit.gpgsign", "true".to_string()));
+        assert_eq!(
+            settings.last(),
+            Some(&(
+                "gitsign.connectorID",
+                "https://github.com/login/oauth".to_string()
+            ))
+        );
+        assert_eq!(settings.len(), 5);
+        identity.gpg.gitsign = None;
+        assert_eq!(
+            FulcioIdentity::for_identity(&identity).display_text(),
+            "me@acme.dev via https://oauth2.sigstore.dev/auth"
+        );
+    }
+}
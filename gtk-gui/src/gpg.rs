@@ -0,0 +1,563 @@
+//! GPG signing keys: expiry tracking and extension
+//!
+//! Reads secret keys from `gpg --with-colons`, works out when each
+//! identity's key stops being able to sign, and moves the expiry of a key
+//! and its subkeys forward with `--quick-set-expire`. The passphrase or
+//! card PIN is asked for by gpg-agent's pinentry; in Maximum Security the
+//! agent forgets it again afterwards.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, SecurityMode, SigningFormat};
+use crate::error::Error;
+use crate::expiry::{Reminder, ReminderKind, Urgency};
+
+/// A subkey of a secret key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subkey {
+    pub key_id: String,
+    pub fingerprint: String,
+    /// gpg-agent's name for the key material, as pinentry sees it
+    pub keygrip: String,
+    /// None if it never expires
+    pub expires: Option<u64>,
+    pub can_sign: bool,
+    pub can_encrypt: bool,
+    pub can_authenticate: bool,
+    pub revoked: bool,
+    /// `+` in the keyring, `#` a stub for a missing card, else the serial
+    /// of the card holding it
+    pub token: String,
+}
+
+/// A secret key and its subkeys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretKey {
+    pub key_id: String,
+    pub fingerprint: String,
+    pub keygrip: String,
+    /// None if it never expires
+    pub expires: Option<u64>,
+    pub can_sign: bool,
+    /// The first user id
+    pub uid: String,
+    /// As [`Subkey::token`]
+    pub token: String,
+    pub subkeys: Vec<Subkey>,
+}
+
+/// The earlier of two expiries, where None is never
+fn earlier(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// A key id or fingerprint in the form gpg prints: no `0x` or `!`,
+/// upper case
+pub fn normalize(id: &str) -> String {
+    id.trim()
+        .trim_end_matches('!')
+        .trim_start_matches("0x")
+        .to_uppercase()
+}
+
+/// `id` as `user.signingkey` or `--local-user` take an exact subkey:
+/// with a `!`, so gpg doesn't pick the newest signing subkey instead
+pub fn exact(id: &str) -> String {
+    format!("{}!", normalize(id))
+}
+
+impl SecretKey {
+    /// When the key can no longer sign: the latest signing subkey's expiry
+    /// (or the primary's if it has none), capped by the primary's
+    pub fn signing_expires(&self) -> Option<u64> {
+        let signing: Vec<&Subkey> = self
+            .subkeys
+            .iter()
+            .filter(|k| k.can_sign && !k.revoked)
+            .collect();
+        if signing.is_empty() {
+            return self.expires;
+        }
+        let latest = if signing.iter().any(|k| k.expires.is_none()) {
+            None
+        } else {
+            signing.iter().filter_map(|k| k.expires).max()
+        };
+        earlier(self.expires, latest)
+    }
+
+    /// Subkeys that can still sign at `now`
+    pub fn signing_subkeys(&self, now: u64) -> Vec<&Subkey> {
+        self.subkeys
+            .iter()
+            .filter(|k| k.can_sign && !k.revoked && k.expires.is_none_or(|t| t > now))
+            .collect()
+    }
+
+    /// Whether the key or one of its subkeys has this keygrip
+    pub fn has_keygrip(&self, keygrip: &str) -> bool {
+        std::iter::once(&self.keygrip)
+            .chain(self.subkeys.iter().map(|k| &k.keygrip))
+            .any(|grip| !grip.is_empty() && grip.eq_ignore_ascii_case(keygrip))
+    }
+
+    /// Whether `id` (a key id or fingerprint of the key or a subkey, as
+    /// git's `user.signingkey` takes it) names this key
+    pub fn matches(&self, id: &str) -> bool {
+        let id = normalize(id);
+        if id.is_empty() {
+            return false;
+        }
+        std::iter::once((&self.key_id, &self.fingerprint))
+            .chain(self.subkeys.iter().map(|k| (&k.key_id, &k.fingerprint)))
+            .any(|(key_id, fingerprint)| *key_id == id || fingerprint.ends_with(&id))
+    }
+}
+
+/// Parse `gpg --with-colons --list-secret-keys` output
+pub fn parse_colons(text: &str) -> Vec<SecretKey> {
+    let mut keys: Vec<SecretKey> = Vec::new();
+    // Whether the last sec/ssb record was a subkey, for the fpr after it
+    let mut in_subkey = false;
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split(':').collect();
+        let field = |i: usize| fields.get(i).copied().unwrap_or_default();
+        let expires = field(6).parse::<u64>().ok().filter(|t| *t > 0);
+        // Lowercase is what the key itself can do, uppercase the whole key
+        let can_sign = field(11).contains('s');
+        let token = field(14).to_string();
+        match field(0) {
+            "sec" => {
+                keys.push(SecretKey {
+                    key_id: field(4).to_uppercase(),
+                    fingerprint: String::new(),
+                    keygrip: String::new(),
+                    expires,
+                    can_sign,
+                    uid: String::new(),
+                    token,
+                    subkeys: Vec::new(),
+                });
+                in_subkey = false;
+            }
+            "ssb" => {
+                if let Some(key) = keys.last_mut() {
+                    key.subkeys.push(Subkey {
+                        key_id: field(4).to_uppercase(),
+                        fingerprint: String::new(),
+                        keygrip: String::new(),
+                        expires,
+                        can_sign,
+                        can_encrypt: field(11).contains('e'),
+                        can_authenticate: field(11).contains('a'),
+                        revoked: field(1) == "r",
+                        token,
+                    });
+                    in_subkey = true;
+                }
+            }
+            "fpr" | "grp" => {
+                let Some(key) = keys.last_mut() else {
+                    continue;
+                };
+                let (fingerprint, keygrip) = match key.subkeys.last_mut() {
+                    Some(subkey) if in_subkey => (&mut subkey.fingerprint, &mut subkey.keygrip),
+                    _ => (&mut key.fingerprint, &mut key.keygrip),
+                };
+                let slot = if field(0) == "fpr" {
+                    fingerprint
+                } else {
+                    keygrip
+                };
+                if slot.is_empty() {
+                    *slot = field(9).to_uppercase();
+                }
+            }
+            "uid" => {
+                if let Some(key) = keys.last_mut().filter(|k| k.uid.is_empty()) {
+                    key.uid = field(9).to_string();
+                }
+            }
+            _ => {}
+        }
+    }
+    keys
+}
+
+fn options(timeout: Duration) -> RunOptions {
+    RunOptions {
+        timeout,
+        retries: 0,
+        cancel: None,
+    }
+}
+
+/// Secret keys in the keyring; blocks
+pub fn list_secret_keys() -> Result<Vec<SecretKey>, Error> {
+    let args = cli::args(&[
+        "--batch",
+        "--with-colons",
+        "--fixed-list-mode",
+        "--list-secret-keys",
+    ]);
+    let out = cli::run_program("gpg", &args, &options(Duration::from_secs(15)))
+        .map_err(Error::from_cli)?;
+    Ok(parse_colons(&out))
+}
+
+/// The OpenPGP card in the reader
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Card {
+    pub serial: String,
+    /// e.g. "Yubico"
+    pub vendor: String,
+    /// Fingerprints in the signature, encryption and authentication
+    /// slots; None for an empty slot
+    pub slots: [Option<String>; 3],
+    /// Tries left for the PIN, Reset Code and Admin PIN; a Reset Code
+    /// that was never set shows 0
+    pub pin_retries: Option<[u32; 3]>,
+}
+
+/// Parse `gpg --card-status --with-colons`
+pub fn parse_card_status(text: &str) -> Option<Card> {
+    let mut card = Card::default();
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split(':').collect();
+        let field = |i: usize| fields.get(i).copied().unwrap_or_default();
+        match field(0) {
+            "serial" => card.serial = field(1).to_string(),
+            "vendor" => card.vendor = field(2).to_string(),
+            "pinretry" => {
+                let count = |i: usize| field(i).parse().ok();
+                card.pin_retries = count(1)
+                    .zip(count(2))
+                    .zip(count(3))
+                    .map(|((pin, reset), admin)| [pin, reset, admin]);
+            }
+            "fpr" => {
+                for (slot, fingerprint) in card.slots.iter_mut().zip(1..=3) {
+                    let fingerprint = field(fingerprint).to_uppercase();
+                    *slot = Some(fingerprint).filter(|f| f.chars().any(|c| c != '0'));
+                }
+            }
+            _ => {}
+        }
+    }
+    (!card.serial.is_empty()).then_some(card)
+}
+
+/// The card in the reader, None without a card or reader; blocks
+pub fn card_status() -> Option<Card> {
+    let args = cli::args(&["--batch", "--with-colons", "--card-status"]);
+    match cli::run_program("gpg", &args, &options(Duration::from_secs(15))) {
+        Ok(out) => parse_card_status(&out),
+        Err(e) => {
+            tracing::debug!("No OpenPGP card: {}", e);
+            None
+        }
+    }
+}
+
+/// The armored public key, without third-party signatures; blocks
+pub fn export_public(fingerprint: &str) -> Result<String, Error> {
+    let args = cli::args(&[
+        "--batch",
+        "--armor",
+        "--export-options",
+        "export-minimal",
+        "--export",
+        fingerprint,
+    ]);
+    let out = cli::run_program("gpg", &args, &options(Duration::from_secs(15)))
+        .map_err(Error::from_cli)?;
+    if out.trim().is_empty() {
+        return Err(Error::InvalidOutput {
+            program: "gpg",
+            message: format!("No public key for {}", fingerprint),
+        });
+    }
+    Ok(out)
+}
+
+/// Fingerprints of the keys and subkeys in an armored block, without
+/// importing it; blocks
+pub fn armored_fingerprints(armored: &str) -> Result<Vec<String>, Error> {
+    let args = cli::args(&[
+        "--batch",
+        "--with-colons",
+        "--import-options",
+        "show-only",
+        "--import",
+    ]);
+    let out = cli::run_program_with_input(
+        "gpg",
+        &args,
+        &[],
+        armored,
+        &options(Duration::from_secs(15)),
+    )
+    .map_err(Error::from_cli)?;
+    Ok(out
+        .lines()
+        .filter(|line| line.starts_with("fpr:"))
+        .filter_map(|line| line.split(':').nth(9))
+        .map(str::to_uppercase)
+        .collect())
+}
+
+/// Reminders for the identities' signing keys expiring within `warn_days`,
+/// one per key
+pub fn key_reminders(
+    keys: &[SecretKey],
+    config: &Config,
+    now: u64,
+    warn_days: u64,
+) -> Vec<Reminder> {
+    // Identities sharing a key get one reminder; the strictest mode wins
+    let mut users: BTreeMap<&str, (Vec<&str>, SecurityMode)> = BTreeMap::new();
+    let gpg_signers = config
+        .identities
+        .iter()
+        .filter(|(_, identity)| identity.gpg.format == SigningFormat::Gpg);
+    for (name, identity) in gpg_signers {
+        let Some(key) = keys.iter().find(|k| k.matches(&identity.gpg.key_id)) else {
+            continue;
+        };
+        let entry = users
+            .entry(key.fingerprint.as_str())
+            .or_insert_with(|| (Vec::new(), identity.gpg.security_mode.clone()));
+        entry.0.push(name);
+        if identity.gpg.security_mode.index() < entry.1.index() {
+            entry.1 = identity.gpg.security_mode.clone();
+        }
+    }
+    keys.iter()
+        .filter_map(|key| {
+            let (identities, mode) = users.get(key.fingerprint.as_str())?;
+            let mut identities = identities.clone();
+            identities.sort_unstable();
+            let due_at = key.signing_expires()?;
+            Some(Reminder {
+                name: format!("GPG key {} ({})", key.key_id, identities.join(", ")),
+                kind: ReminderKind::GpgKey {
+                    fingerprint: key.fingerprint.clone(),
+                    security_mode: mode.clone(),
+                },
+                due_at,
+                urgency: Urgency::within(due_at, now, warn_days)?,
+            })
+        })
+        .collect()
+}
+
+/// Reminders for the configured identities' signing keys; blocks
+pub fn reminders(config: &Config, now: u64) -> Vec<Reminder> {
+    let warn_days = u64::from(config.settings.gpg_expiry.warn_days);
+    match list_secret_keys() {
+        Ok(keys) => key_reminders(&keys, config, now, warn_days),
+        Err(e) => {
+            tracing::debug!("Not checking GPG key expiry: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Move a key's expiry, and its subkeys', to `days` from today, returning
+/// when it can sign until; blocks
+///
+/// gpg-agent's pinentry asks for the passphrase or card PIN (the
+/// RemoteJuggler pinentry supplies a stored PIN in Trusted Workstation).
+/// In Maximum Security the agent is reloaded afterwards so it doesn't keep
+/// the passphrase cached.
+pub fn extend(fingerprint: &str, days: u32, mode: &SecurityMode) -> Result<Option<u64>, Error> {
+    let key = list_secret_keys()?
+        .into_iter()
+        .find(|k| k.fingerprint == fingerprint)
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "gpg",
+            message: format!("no secret key {}", fingerprint),
+        })?;
+    let period = format!("{}d", days);
+    // Long enough for a PIN entry or a card touch
+    let interactive = options(Duration::from_secs(120));
+    let mut args = cli::args(&["--batch", "--quick-set-expire", fingerprint, &period]);
+    let mut result = cli::run_program("gpg", &args, &interactive).map(|_| ());
+    if result.is_ok() && key.subkeys.iter().any(|k| !k.revoked) {
+        args.push("*".to_string());
+        result = cli::run_program("gpg", &args, &interactive).map(|_| ());
+    }
+    if *mode == SecurityMode::MaximumSecurity {
+        let reload = cli::args(&["--reload", "gpg-agent"]);
+        let _ = cli::run_program("gpgconf", &reload, &options(Duration::from_secs(10)));
+    }
+    result.map_err(Error::from_cli)?;
+
+    Ok(list_secret_keys()?
+        .into_iter()
+        .find(|k| k.fingerprint == fingerprint)
+        .and_then(|k| k.signing_expires()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::config::{GpgConfig, Identity};
+
+    const DAY: u64 = 86_400;
+    const NOW: u64 = 1_767_225_600; // 2026-01-01
+
+    fn listing() -> String {
+        format!(
+            "sec:u:255:22:AAAA1111BBBB2222:1700000000:{primary}::u:::cC:::+:::ed25519:::0:\n\
+             fpr:::::::::0123456789ABCDEF0123AAAA1111BBBB2222:\n\
+             grp:::::::::KEYGRIP:\n\
+             uid:u::::1700000000::HASH::Work User <work@example.com>::::::::::0:\n\
+             ssb:e:255:22:CCCC3333DDDD4444:1700000000:1710000000:::::s:::+:::ed25519::\n\
+             fpr:::::::::FEDCBA9876543210FEDCCCCC3333DDDD4444:\n\
+             ssb:u:255:22:EEEE5555FFFF6666:1700000000:{signing}:::::s:::+:::ed25519::\n\
+             fpr:::::::::FEDCBA9876543210FEDCEEEE5555FFFF6666:\n\
+             grp:::::::::EEEE5555GRIP:\n\
+             ssb:u:255:18:9999888877776666:1700000000::::::e:::+:::cv25519::\n\
+             fpr:::::::::FEDCBA9876543210FEDC9999888877776666:\n",
+            primary = NOW + 400 * DAY,
+            signing = NOW + 10 * DAY,
+        )
+    }
+
+    #[test]
+    fn test_parse_and_signing_expiry() {
+        let keys = parse_colons(&listing());
+        assert_eq!(keys.len(), 1);
+        let key = &keys[0];
+        assert_eq!(key.fingerprint, "0123456789ABCDEF0123AAAA1111BBBB2222");
+        assert_eq!(key.uid, "Work User <work@example.com>");
+        assert!(key.has_keygrip("keygrip"));
+        assert!(key.has_keygrip("EEEE5555GRIP"));
+        assert!(!key.has_keygrip("FEDCBA9876543210FEDCEEEE5555FFFF6666"));
+        assert_eq!(key.subkeys.len(), 3);
+        assert_eq!(
+            key.subkeys[1].fingerprint,
+            "FEDCBA9876543210FEDCEEEE5555FFFF6666"
+        );
+        assert!(!key.subkeys[2].can_sign);
+        assert_eq!(key.subkeys[2].expires, None);
+        // The later of the two signing subkeys, within the primary's expiry
+        assert_eq!(key.signing_expires(), Some(NOW + 10 * DAY));
+        let signing: Vec<&str> = key
+            .signing_subkeys(NOW)
+            .iter()
+            .map(|k| k.key_id.as_str())
+            .collect();
+        assert_eq!(signing, ["EEEE5555FFFF6666"]);
+        assert_eq!(exact("0xeeee5555ffff6666!"), "EEEE5555FFFF6666!");
+
+        assert!(key.matches("AAAA1111BBBB2222"));
+        assert!(key.matches("0xeeee5555ffff6666!"));
+        assert!(key.matches("0123456789ABCDEF0123AAAA1111BBBB2222"));
+        assert!(!key.matches("1234"));
+        assert!(!key.matches(""));
+    }
+
+    #[test]
+    fn test_card_status() {
+        let status = "Reader:1050:0407:X:0:AID:D2760001240103040006123456780000:openpgpcard:\n\
+                      version:0304:\n\
+                      vendor:0006:Yubico:\n\
+                      serial:12345678:\n\
+                      pinretry:2:0:3:\n\
+                      fpr:FEDCBA9876543210FEDCEEEE5555FFFF6666::0000000000000000000000000000000000000000:\n";
+        let card = parse_card_status(status).unwrap();
+        assert_eq!(
+            (card.serial.as_str(), card.vendor.as_str()),
+            ("12345678", "Yubico")
+        );
+        assert_eq!(
+            card.slots,
+            [
+                Some("FEDCBA9876543210FEDCEEEE5555FFFF6666".to_string()),
+                None,
+                None
+            ]
+        );
+        assert_eq!(card.pin_retries, Some([2, 0, 3]));
+        assert_eq!(parse_card_status("Reader:::\n"), None);
+
+        let key = &parse_colons(&listing())[0];
+        assert_eq!(key.token, "+");
+        assert!(key.subkeys[2].can_encrypt && !key.subkeys[2].can_authenticate);
+    }
+
+    #[test]
+    fn test_key_reminders() {
+        let keys = parse_colons(&listing());
+        let gpg = |key_id: &str, security_mode: SecurityMode| GpgConfig {
+            key_id: key_id.to_string(),
+            security_mode,
+            ..Default::default()
+        };
+        let config = ConfigBuilder::new()
+            .identity(
+                "work",
+                Identity {
+                    gpg: gpg("EEEE5555FFFF6666", SecurityMode::DeveloperWorkflow),
+                    ..identity(
+                        "gitlab",
+                        "gitlab-work",
+                        "gitlab.com",
+                        "Work User",
+                        "work@example.com",
+                    )
+                },
+            )
+            .identity(
+                "work-sk",
+                Identity {
+                    gpg: gpg("AAAA1111BBBB2222", SecurityMode::MaximumSecurity),
+                    ..identity(
+                        "gitlab",
+                        "gitlab-work-sk",
+                        "gitlab.com",
+                        "Work User",
+                        "work@example.com",
+                    )
+                },
+            )
+            .identity(
+                "personal",
+                Identity {
+                    gpg: gpg("0BADC0DE", SecurityMode::DeveloperWorkflow),
+                    ..identity(
+                        "github",
+                        "github-personal",
+                        "github.com",
+                        "Me",
+                        "me@example.com",
+                    )
+                },
+            )
+            .build();
+
+        let reminders = key_reminders(&keys, &config, NOW, 30);
+        assert_eq!(reminders.len(), 1);
+        assert_eq!(
+            reminders[0].name,
+            "GPG key AAAA1111BBBB2222 (work, work-sk)"
+        );
+        assert_eq!(reminders[0].urgency, Urgency::DueSoon { days_left: 10 });
+        assert_eq!(
+            reminders[0].kind,
+            ReminderKind::GpgKey {
+                fingerprint: "0123456789ABCDEF0123AAAA1111BBBB2222".to_string(),
+                security_mode: SecurityMode::MaximumSecurity,
+            }
+        );
+        assert!(key_reminders(&keys, &config, NOW, 7).is_empty());
+    }
+}
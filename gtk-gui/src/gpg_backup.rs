@@ -0,0 +1,289 @@
+//! Encrypted GPG key backups
+//!
+//! A backup bundle carries what a new machine needs to sign as the
+//! configured identities: their definitions, the public keys of their
+//! signing keys, gpg's ownertrust and, if asked for, the secret keys. For
+//! keys on a card gpg exports stubs, which point gpg at the card again
+//! once it is inserted. The bundle is JSON encrypted with age to the
+//! stored SOPS age key or other recipients, so it can be kept alongside
+//! the key store. Restoring imports the keys and trust and adds the
+//! identities the config doesn't have yet.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::age;
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, Identity, SigningFormat};
+use crate::error::Error;
+use crate::export;
+use crate::gpg;
+
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// The file name the export dialog suggests
+pub const DEFAULT_FILE_NAME: &str = "remote-juggler-gpg-backup.age";
+
+/// A key in a bundle
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleKey {
+    pub fingerprint: String,
+    pub uid: String,
+    /// Armored
+    pub public_key: String,
+    /// Armored; stubs for keys on a card
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_key: Option<String>,
+}
+
+/// What a backup holds once decrypted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bundle {
+    pub version: u32,
+    #[serde(default)]
+    pub identities: BTreeMap<String, Identity>,
+    #[serde(default)]
+    pub keys: Vec<BundleKey>,
+    /// `gpg --export-ownertrust` output
+    #[serde(default)]
+    pub ownertrust: String,
+}
+
+impl Bundle {
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::InvalidOutput {
+            program: "gpg-backup",
+            message: e.to_string(),
+        })
+    }
+
+    pub fn parse(json: &str) -> Result<Self, Error> {
+        let bundle: Self = serde_json::from_str(json).map_err(|e| Error::InvalidOutput {
+            program: "gpg-backup",
+            message: format!("Not a backup bundle: {}", e),
+        })?;
+        if bundle.version > BUNDLE_VERSION {
+            return Err(Error::InvalidOutput {
+                program: "gpg-backup",
+                message: format!(
+                    "Bundle version {} is newer than this version understands",
+                    bundle.version
+                ),
+            });
+        }
+        Ok(bundle)
+    }
+
+    pub fn secret_key_count(&self) -> usize {
+        self.keys.iter().filter(|k| k.secret_key.is_some()).count()
+    }
+
+    /// Add the identities `config` doesn't have; returns their names
+    pub fn merge_identities(&self, config: &mut Config) -> Vec<String> {
+        let mut added = Vec::new();
+        for (name, identity) in &self.identities {
+            if !config.identities.contains_key(name) {
+                config.identities.insert(name.clone(), identity.clone());
+                added.push(name.clone());
+            }
+        }
+        added
+    }
+}
+
+fn options(timeout: Duration) -> RunOptions {
+    RunOptions {
+        timeout,
+        retries: 0,
+        cancel: None,
+    }
+}
+
+fn export_ownertrust() -> Result<String, Error> {
+    let args = cli::args(&["--batch", "--export-ownertrust"]);
+    cli::run_program("gpg", &args, &options(Duration::from_secs(15))).map_err(Error::from_cli)
+}
+
+fn export_secret(fingerprint: &str) -> Result<String, Error> {
+    let args = cli::args(&["--armor", "--export-secret-keys", fingerprint]);
+    // Long enough to type the passphrase
+    cli::run_program("gpg", &args, &options(Duration::from_secs(300))).map_err(Error::from_cli)
+}
+
+/// Gather the identities and their signing keys; blocks
+pub fn collect(config: &Config, include_secret: bool) -> Result<Bundle, Error> {
+    let secret_keys = gpg::list_secret_keys()?;
+    let mut keys: Vec<BundleKey> = Vec::new();
+    let mut names: Vec<&String> = config.identities.keys().collect();
+    names.sort();
+    for name in names {
+        let identity = &config.identities[name];
+        if identity.gpg.format != SigningFormat::Gpg || identity.gpg.key_id.is_empty() {
+            continue;
+        }
+        let Some(key) = secret_keys
+            .iter()
+            .find(|key| key.matches(&identity.gpg.key_id))
+        else {
+            tracing::debug!("No secret key {} for {}", identity.gpg.key_id, name);
+            continue;
+        };
+        if keys.iter().any(|k| k.fingerprint == key.fingerprint) {
+            continue;
+        }
+        keys.push(BundleKey {
+            fingerprint: key.fingerprint.clone(),
+            uid: key.uid.clone(),
+            public_key: gpg::export_public(&key.fingerprint)?,
+            secret_key: if include_secret {
+                Some(export_secret(&key.fingerprint)?)
+            } else {
+                None
+            },
+        });
+    }
+    Ok(Bundle {
+        version: BUNDLE_VERSION,
+        identities: config
+            .identities
+            .iter()
+            .map(|(name, identity)| (name.clone(), identity.clone()))
+            .collect(),
+        keys,
+        ownertrust: export_ownertrust()?,
+    })
+}
+
+/// The recipient of the stored SOPS age key, the default for backups;
+/// blocks
+pub fn default_recipient() -> Result<String, Error> {
+    age::recipient_for(&age::load_secret(age::DEFAULT_IDENTITY_PATH)?)
+}
+
+/// Write an encrypted backup of the configured identities to `path`;
+/// returns what it holds. Blocks
+pub fn export(path: &Path, recipients: &[String], include_secret: bool) -> Result<Bundle, Error> {
+    let config = Config::load()?;
+    let bundle = collect(&config, include_secret)?;
+    let armored = age::encrypt_text(&bundle.to_json()?, recipients)?;
+    export::write_private(path, &armored)?;
+    Ok(bundle)
+}
+
+/// What a restore brought back
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Restored {
+    pub keys: usize,
+    pub secret_keys: usize,
+    pub identities_added: Vec<String>,
+    pub ownertrust: bool,
+}
+
+impl Restored {
+    pub fn display_text(&self) -> String {
+        let mut parts = vec![format!(
+            "Imported {} key(s), {} with secret keys",
+            self.keys, self.secret_keys
+        )];
+        if self.ownertrust {
+            parts.push("restored ownertrust".to_string());
+        }
+        if self.identities_added.is_empty() {
+            parts.push("no new identities".to_string());
+        } else {
+            parts.push(format!("added {}", self.identities_added.join(", ")));
+        }
+        parts.join("; ")
+    }
+}
+
+fn import(args: &[&str], input: &str) -> Result<(), Error> {
+    let out = cli::run_program_with_input(
+        "gpg",
+        &cli::args(args),
+        &[],
+        input,
+        // Importing a secret key may ask for its passphrase
+        &options(Duration::from_secs(300)),
+    )
+    .map_err(Error::from_cli)?;
+    tracing::debug!("gpg {}: {}", args.join(" "), out.trim());
+    Ok(())
+}
+
+/// Decrypt the backup at `path` with `secret`, import its keys and trust
+/// and add its identities to the config; blocks
+pub fn restore(path: &Path, secret: &str) -> Result<Restored, Error> {
+    let armored = std::fs::read_to_string(path)?;
+    let bundle = Bundle::parse(&age::decrypt_text(&armored, secret)?)?;
+    for key in &bundle.keys {
+        import(&["--batch", "--import"], &key.public_key)?;
+        if let Some(secret_key) = &key.secret_key {
+            import(&["--batch", "--import"], secret_key)?;
+        }
+    }
+    let ownertrust = !bundle.ownertrust.trim().is_empty();
+    if ownertrust {
+        import(&["--batch", "--import-ownertrust"], &bundle.ownertrust)?;
+    }
+    let mut config = Config::load()?;
+    let identities_added = bundle.merge_identities(&mut config);
+    if !identities_added.is_empty() {
+        config.save()?;
+    }
+    Ok(Restored {
+        keys: bundle.keys.len(),
+        secret_keys: bundle.secret_key_count(),
+        identities_added,
+        ownertrust,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::config::GpgConfig;
+
+    #[test]
+    fn test_bundle_round_trip_and_merge() {
+        let identity = Identity {
+            ssh_key_path: "~/.ssh/work".to_string(),
+            gpg: GpgConfig {
+                key_id: "ABCDEF0123456789".to_string(),
+                ..Default::default()
+            },
+            ..identity("github", "github-work", "github.com", "me", "me@acme.dev")
+        };
+        let bundle = Bundle {
+            version: BUNDLE_VERSION,
+            identities: BTreeMap::from([
+                ("work".to_string(), identity.clone()),
+                ("personal".to_string(), identity.clone()),
+            ]),
+            keys: vec![BundleKey {
+                fingerprint: "0123456789ABCDEF0123456789ABCDEF01234567".to_string(),
+                uid: "Me <me@acme.dev>".to_string(),
+                public_key: "-----BEGIN PGP PUBLIC KEY BLOCK-----".to_string(),
+                secret_key: None,
+            }],
+            ownertrust: "0123456789ABCDEF0123456789ABCDEF01234567:6:\n".to_string(),
+        };
+        let json = bundle.to_json().unwrap();
+        assert!(!json.contains("secretKey"));
+        let parsed = Bundle::parse(&json).unwrap();
+        assert_eq!(parsed.keys, bundle.keys);
+        assert_eq!(parsed.ownertrust, bundle.ownertrust);
+        assert_eq!(parsed.identities["work"].gpg.key_id, "ABCDEF0123456789");
+        assert!(Bundle::parse(r#"{"version": 2}"#).is_err());
+
+        let mut config = ConfigBuilder::new().identity("work", identity).build();
+        assert_eq!(bundle.merge_identities(&mut config), vec!["personal"]);
+        assert_eq!(config.identities.len(), 2);
+    }
+}
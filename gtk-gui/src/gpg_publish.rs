@@ -0,0 +1,270 @@
+//! Publishing an identity's GPG public key
+//!
+//! GitHub and GitLab show a signed commit as Verified once the signing key
+//! is registered with the account, and keys.openpgp.org lets anyone else
+//! fetch it by email, publishing the user id only after the address is
+//! confirmed from a verification mail. This checks and publishes both for
+//! an identity.
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::{json, Value};
+
+use crate::config::{Identity, SigningFormat};
+use crate::error::Error;
+use crate::gpg::{self, SecretKey};
+use crate::http;
+use crate::pubkeys::{self, Account, Provider};
+
+pub const KEYSERVER: &str = "https://keys.openpgp.org";
+
+/// The key on keys.openpgp.org
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Keyserver {
+    /// Found by the identity's email
+    Published,
+    /// Uploaded, but the email isn't confirmed yet
+    Unverified,
+    Missing,
+    Failed(String),
+}
+
+/// The key on the identity's provider account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderKey {
+    Registered,
+    NotRegistered,
+    /// Bitbucket or a custom host: nothing to register with
+    Unsupported,
+    Failed(String),
+}
+
+/// Where an identity's key is published
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub key_id: String,
+    pub keyserver: Keyserver,
+    pub provider: ProviderKey,
+}
+
+impl Status {
+    /// Whether Publish has anything left to do
+    pub fn is_published(&self) -> bool {
+        matches!(self.keyserver, Keyserver::Published | Keyserver::Unverified)
+            && matches!(
+                self.provider,
+                ProviderKey::Registered | ProviderKey::Unsupported
+            )
+    }
+
+    pub fn display_text(&self, provider: &str) -> String {
+        let keyserver = match &self.keyserver {
+            Keyserver::Published => "published on keys.openpgp.org".to_string(),
+            Keyserver::Unverified => {
+                "on keys.openpgp.org, waiting for the email to be confirmed".to_string()
+            }
+            Keyserver::Missing => "not on keys.openpgp.org".to_string(),
+            Keyserver::Failed(message) => format!("keys.openpgp.org: {}", message),
+        };
+        let provider = match &self.provider {
+            ProviderKey::Registered => Some(format!("registered on {}", provider)),
+            ProviderKey::NotRegistered => Some(format!("not on {}", provider)),
+            ProviderKey::Unsupported => None,
+            ProviderKey::Failed(message) => Some(format!("{}: {}", provider, message)),
+        };
+        let mut parts = vec![keyserver];
+        parts.extend(provider);
+        format!("GPG key {}: {}", self.key_id, parts.join("; "))
+    }
+}
+
+/// The identity's signing key in the keyring; blocks
+pub fn signing_key(name: &str, identity: &Identity) -> Result<SecretKey, Error> {
+    if identity.gpg.format != SigningFormat::Gpg || identity.gpg.key_id.is_empty() {
+        return Err(Error::InvalidOutput {
+            program: "config",
+            message: format!("{} has no GPG signing key", name),
+        });
+    }
+    gpg::list_secret_keys()?
+        .into_iter()
+        .find(|key| key.matches(&identity.gpg.key_id))
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "gpg",
+            message: format!("No secret key {} in the keyring", identity.gpg.key_id),
+        })
+}
+
+/// The state keys.openpgp.org reports for `email` in an upload or
+/// verification response: published, unpublished, pending or revoked
+pub fn address_state<'a>(response: &'a Value, email: &str) -> Option<&'a str> {
+    response
+        .get("status")?
+        .as_object()?
+        .iter()
+        .find(|(address, _)| address.eq_ignore_ascii_case(email))?
+        .1
+        .as_str()
+}
+
+fn get_armored(url: &str) -> Result<Option<String>, Error> {
+    match http::agent().get(url).call() {
+        Ok(response) => Ok(Some(response.into_string()?)),
+        Err(ureq::Error::Status(404, _)) => Ok(None),
+        Err(e) => Err(http::map_error(e)),
+    }
+}
+
+/// Look the key up on keys.openpgp.org; blocks
+fn keyserver_status(key: &SecretKey, email: &str) -> Result<Keyserver, Error> {
+    let url = format!("{}/vks/v1/by-fingerprint/{}", KEYSERVER, key.fingerprint);
+    if get_armored(&url)?.is_none() {
+        return Ok(Keyserver::Missing);
+    }
+    let url = format!(
+        "{}/vks/v1/by-email/{}",
+        KEYSERVER,
+        utf8_percent_encode(email, NON_ALPHANUMERIC)
+    );
+    // The email may be confirmed for an older key
+    let published = match get_armored(&url)? {
+        Some(armored) => gpg::armored_fingerprints(&armored)?.contains(&key.fingerprint),
+        None => false,
+    };
+    Ok(if published {
+        Keyserver::Published
+    } else {
+        Keyserver::Unverified
+    })
+}
+
+/// Upload to keys.openpgp.org and ask for the verification mail if the
+/// email isn't confirmed yet; blocks
+fn keyserver_upload(armored: &str, email: &str) -> Result<Keyserver, Error> {
+    let response: Value = http::post_json(
+        &format!("{}/vks/v1/upload", KEYSERVER),
+        None,
+        &json!({ "keytext": armored }),
+    )?;
+    match address_state(&response, email) {
+        Some("published") => Ok(Keyserver::Published),
+        Some("pending") => Ok(Keyserver::Unverified),
+        Some("unpublished") => {
+            let token = response
+                .get("token")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let _: Value = http::post_json(
+                &format!("{}/vks/v1/request-verify", KEYSERVER),
+                None,
+                &json!({ "token": token, "addresses": [email] }),
+            )?;
+            Ok(Keyserver::Unverified)
+        }
+        Some(state) => Err(Error::InvalidOutput {
+            program: "keys.openpgp.org",
+            message: format!("{} is {}", email, state),
+        }),
+        None => Err(Error::InvalidOutput {
+            program: "keys.openpgp.org",
+            message: format!("The key has no user id for {}", email),
+        }),
+    }
+}
+
+/// Whether the provider account lists the key; blocks
+fn provider_status(account: &Account, key: &SecretKey) -> Result<ProviderKey, Error> {
+    for remote in account.list_gpg_keys()? {
+        if !remote.key_id.is_empty() {
+            if key.matches(&remote.key_id) {
+                return Ok(ProviderKey::Registered);
+            }
+        } else if !remote.armored.is_empty()
+            && gpg::armored_fingerprints(&remote.armored)?.contains(&key.fingerprint)
+        {
+            return Ok(ProviderKey::Registered);
+        }
+    }
+    Ok(ProviderKey::NotRegistered)
+}
+
+/// The identity's account, if its provider takes GPG keys; blocks
+fn gpg_account(name: &str, identity: &Identity) -> Option<Result<Account, Error>> {
+    match Provider::for_identity(identity) {
+        Some(Provider::GitHub | Provider::GitLab | Provider::Gitea | Provider::Sourcehut) => {
+            Some(Account::for_identity(name, identity))
+        }
+        _ => None,
+    }
+}
+
+/// Where the identity's key is published; blocks
+pub fn check(name: &str, identity: &Identity) -> Result<Status, Error> {
+    let key = signing_key(name, identity)?;
+    let keyserver = keyserver_status(&key, &identity.email)
+        .unwrap_or_else(|e| Keyserver::Failed(e.to_string()));
+    let provider = match gpg_account(name, identity) {
+        None => ProviderKey::Unsupported,
+        Some(account) => account
+            .and_then(|account| provider_status(&account, &key))
+            .unwrap_or_else(|e| ProviderKey::Failed(e.to_string())),
+    };
+    Ok(Status {
+        key_id: key.key_id,
+        keyserver,
+        provider,
+    })
+}
+
+/// Publish the identity's key where it's missing; blocks
+pub fn publish(name: &str, identity: &Identity) -> Result<Status, Error> {
+    let mut status = check(name, identity)?;
+    let key = signing_key(name, identity)?;
+    let armored = gpg::export_public(&key.fingerprint)?;
+    if !matches!(status.keyserver, Keyserver::Published) {
+        status.keyserver = keyserver_upload(&armored, &identity.email)
+            .unwrap_or_else(|e| Keyserver::Failed(e.to_string()));
+    }
+    if status.provider == ProviderKey::NotRegistered {
+        let title = pubkeys::default_title(name);
+        status.provider = match gpg_account(name, identity) {
+            Some(account) => account
+                .and_then(|account| account.upload_gpg_key(&title, &armored))
+                .map_or_else(
+                    |e| ProviderKey::Failed(e.to_string()),
+                    |()| ProviderKey::Registered,
+                ),
+            None => ProviderKey::Unsupported,
+        };
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_and_address_state() {
+        let response = json!({
+            "key_fpr": "AAAA", "token": "t",
+            "status": { "Me@Acme.dev": "unpublished", "old@acme.dev": "revoked" }
+        });
+        assert_eq!(address_state(&response, "me@acme.dev"), Some("unpublished"));
+        assert_eq!(address_state(&response, "other@acme.dev"), None);
+
+        let mut status = Status {
+            key_id: "AAAA1111BBBB2222".to_string(),
+            keyserver: Keyserver::Unverified,
+            provider: ProviderKey::NotRegistered,
+        };
+        assert!(!status.is_published());
+        assert_eq!(
+            status.display_text("GitHub"),
+            "GPG key AAAA1111BBBB2222: on keys.openpgp.org, waiting for the email to be \
+             confirmed; not on GitHub"
+        );
+        status.provider = ProviderKey::Unsupported;
+        assert!(status.is_published());
+        assert!(!status.display_text("Bitbucket").contains("Bitbucket"));
+    }
+}
@@ -0,0 +1,204 @@
+//! Mercurial repositories
+//!
+//! Mercurial has no git config to write and nothing like `includeIf`, so
+//! an identity reaches a Mercurial repository through its `.hg/hgrc`: a
+//! managed block with a `[ui]` section setting `username` to the
+//! identity's author and `ssh` to a command offering only its key. The
+//! block is appended, so it wins over settings earlier in the file, and
+//! everything outside the markers is left untouched. Detection reads the
+//! repository's `[paths]` instead of git remotes.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::bookmarks;
+use crate::cli::{self, RunOptions};
+use crate::config::Identity;
+use crate::error::{CliError, Error};
+use crate::ssh_config::{self, BlockState, Markers};
+
+pub const BEGIN_MARKER: &str = "# BEGIN RemoteJuggler managed identity";
+pub const END_MARKER: &str = "# END RemoteJuggler managed identity";
+
+pub const MARKERS: Markers = Markers {
+    begin: BEGIN_MARKER,
+    end: END_MARKER,
+};
+
+/// Whether `repo` is a Mercurial repository rather than a git one
+pub fn is_hg(repo: &Path) -> bool {
+    repo.join(".hg").is_dir() && !repo.join(".git").exists()
+}
+
+/// The repository's own config file
+pub fn hgrc_path(repo: &Path) -> PathBuf {
+    repo.join(".hg").join("hgrc")
+}
+
+/// `ui.username` for an identity, e.g. `Me <me@acme.dev>`
+pub fn username(identity: &Identity) -> String {
+    match (identity.user.trim(), identity.email.trim()) {
+        ("", email) => email.to_string(),
+        (user, "") => user.to_string(),
+        (user, email) => format!("{} <{}>", user, email),
+    }
+}
+
+/// Split a `ui.username` into name and email; the email is empty without
+/// one
+pub fn parse_username(username: &str) -> (String, String) {
+    let username = username.trim();
+    match username.split_once('<') {
+        Some((name, rest)) => (
+            name.trim().to_string(),
+            rest.split('>')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+        ),
+        None if username.contains('@') => (String::new(), username.to_string()),
+        None => (username.to_string(), String::new()),
+    }
+}
+
+/// The `[ui]` section for an identity
+pub fn render_body(identity: &Identity) -> String {
+    let mut body = format!("[ui]\nusername = {}\n", username(identity));
+    if let Some(command) = bookmarks::ssh_command(identity) {
+        body.push_str(&format!("ssh = {}\n", command));
+    }
+    body
+}
+
+/// `text` with its managed block set to the identity's
+pub fn apply_text(text: &str, identity: &Identity) -> Result<String, Error> {
+    if ssh_config::managed_state(text, MARKERS) == BlockState::Unterminated {
+        return Err(Error::InvalidOutput {
+            program: "hg",
+            message: BlockState::Unterminated.display_text().to_string(),
+        });
+    }
+    let block = ssh_config::render_managed(MARKERS, &render_body(identity));
+    Ok(ssh_config::apply_managed(text, &block, MARKERS))
+}
+
+/// Write the identity to the repository's hgrc; blocks
+pub fn apply(repo: &Path, identity: &Identity) -> Result<(), Error> {
+    let path = hgrc_path(repo);
+    let current = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let proposed = apply_text(&current, identity)?;
+    if proposed != current {
+        std::fs::write(&path, proposed)?;
+    }
+    Ok(())
+}
+
+fn hg(repo: &Path, args: &[&str]) -> Result<String, CliError> {
+    let mut all = vec![
+        "--repository".to_string(),
+        repo.to_string_lossy().into_owned(),
+    ];
+    all.extend(cli::args(args));
+    let options = RunOptions {
+        timeout: Duration::from_secs(10),
+        retries: 0,
+        cancel: None,
+    };
+    cli::run_program("hg", &all, &options)
+}
+
+/// The name and email the repository commits as, from whichever hgrc sets
+/// `ui.username`; empty when unset. Blocks
+pub fn identity(repo: &Path) -> Result<(String, String), Error> {
+    match hg(repo, &["config", "ui.username"]) {
+        Ok(out) => Ok(parse_username(&out)),
+        // Exit status 1: not set anywhere
+        Err(CliError::NonZeroExit { code: Some(1), .. }) => Ok((String::new(), String::new())),
+        Err(e) => Err(Error::from_cli(e)),
+    }
+}
+
+/// Parse `hg paths` into (name, URL), default first
+pub fn parse_paths(text: &str) -> Vec<(String, String)> {
+    let mut paths: Vec<(String, String)> = text
+        .lines()
+        .filter_map(|line| {
+            let (name, url) = line.split_once(" = ")?;
+            Some((name.trim().to_string(), url.trim().to_string()))
+        })
+        .collect();
+    paths.sort_by_key(|(name, _)| name != "default");
+    paths
+}
+
+/// The repository's paths, as detection reads remotes; blocks
+pub fn remotes(repo: &Path) -> Result<Vec<(String, String)>, Error> {
+    match hg(repo, &["paths"]) {
+        Ok(out) => Ok(parse_paths(&out)),
+        // Exit status 1: no paths
+        Err(CliError::NonZeroExit { code: Some(1), .. }) => Ok(Vec::new()),
+        Err(e) => Err(Error::from_cli(e)),
+    }
+}
+
+/// How many tracked files are changed, added or removed; blocks
+pub fn changes(repo: &Path) -> Result<usize, Error> {
+    let out = hg(
+        repo,
+        &["status", "--modified", "--added", "--removed", "--deleted"],
+    )
+    .map_err(Error::from_cli)?;
+    Ok(out.lines().filter(|line| !line.trim().is_empty()).count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+
+    #[test]
+    fn test_hgrc_block_and_paths() {
+        let config = ConfigBuilder::new()
+            .identity(
+                "hg-work",
+                Identity {
+                    ssh_key_path: "/keys/id_work".to_string(),
+                    ..identity("custom", "hg-work", "hg.acme.dev", "Me", "me@acme.dev")
+                },
+            )
+            .build();
+        let identity = &config.identities["hg-work"];
+        assert_eq!(
+            render_body(identity),
+            "[ui]\nusername = Me <me@acme.dev>\nssh = ssh -i '/keys/id_work' -o IdentitiesOnly=yes\n"
+        );
+
+        let text = "[paths]\ndefault = ssh://hg@hg.acme.dev/acme/api\n";
+        let written = apply_text(text, identity).unwrap();
+        assert!(written.starts_with(text));
+        assert!(written.contains("username = Me <me@acme.dev>\n"));
+        assert_eq!(apply_text(&written, identity).unwrap(), written);
+        assert!(apply_text(&format!("{}\n", BEGIN_MARKER), identity).is_err());
+
+        assert_eq!(
+            parse_username("Me <me@acme.dev>"),
+            ("Me".to_string(), "me@acme.dev".to_string())
+        );
+        assert_eq!(parse_username("me@acme.dev").1, "me@acme.dev");
+        assert_eq!(
+            parse_paths("upstream = https://hg.acme.dev/up\ndefault = ssh://hg@hg.acme.dev/api\n"),
+            vec![
+                (
+                    "default".to_string(),
+                    "ssh://hg@hg.acme.dev/api".to_string()
+                ),
+                ("upstream".to_string(), "https://hg.acme.dev/up".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,337 @@
+//! Entry version history
+//!
+//! KDBX keeps the previous versions of an entry in its `<History>`, but
+//! keepassxc-cli has no command to read them. The CLI's `keys export-xml`
+//! dumps the database as KeePass XML instead, and this module picks one
+//! entry's versions out of it, so an overwritten value can be restored by
+//! storing it again.
+//!
+//! The XML holds every secret in plaintext; it is parsed in memory and
+//! never written anywhere.
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::cli;
+use crate::error::Error;
+use crate::expiry;
+
+/// Seconds from 0001-01-01 to the Unix epoch, the base of KDBX 4 timestamps
+const KDBX_EPOCH_OFFSET: i64 = 62_135_596_800;
+
+/// One version of an entry
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Version {
+    /// When this version was last modified (Unix seconds)
+    pub modified: Option<u64>,
+    pub username: String,
+    /// The entry's password field, i.e. the secret
+    pub value: String,
+}
+
+// Keep secret values out of logs
+impl std::fmt::Debug for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Version")
+            .field("modified", &self.modified)
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Version {
+    /// Modification time as `YYYY-MM-DD HH:MM UTC`
+    pub fn modified_text(&self) -> String {
+        self.modified
+            .map(format_timestamp)
+            .unwrap_or_else(|| "Unknown time".to_string())
+    }
+}
+
+/// An entry's current value and the values it held before
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryHistory {
+    pub path: String,
+    pub current: Version,
+    /// Previous values, newest first
+    pub previous: Vec<Version>,
+}
+
+pub fn export_args() -> Vec<String> {
+    cli::args(&["keys", "export-xml"])
+}
+
+/// Format Unix seconds as `YYYY-MM-DD HH:MM UTC`
+pub fn format_timestamp(unix_secs: u64) -> String {
+    let secs_of_day = unix_secs % 86_400;
+    format!(
+        "{} {:02}:{:02} UTC",
+        expiry::format_date(unix_secs),
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60
+    )
+}
+
+/// Parse a KeePass XML timestamp
+///
+/// KDBX 3 exports use ISO 8601 (`2026-01-01T12:00:00Z`); KDBX 4 stores
+/// base64 of little-endian seconds since 0001-01-01.
+pub fn parse_time(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if let Some((date, time)) = text.split_once('T') {
+        let day = expiry::parse_date(date)?;
+        let mut hms = time.trim_end_matches('Z').splitn(3, ':');
+        let h: u64 = hms.next()?.parse().ok()?;
+        let m: u64 = hms.next()?.parse().ok()?;
+        let s: f64 = hms.next().unwrap_or("0").parse().ok()?;
+        return Some(day + h * 3600 + m * 60 + s as u64);
+    }
+    use base64::Engine;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(text)
+        .ok()?;
+    let secs = i64::from_le_bytes(bytes.try_into().ok()?);
+    u64::try_from(secs - KDBX_EPOCH_OFFSET).ok()
+}
+
+#[derive(Default)]
+struct EntryState {
+    title: String,
+    version: Version,
+    history: Vec<Version>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidOutput {
+        program: cli::CLI_PROGRAM,
+        message,
+    }
+}
+
+/// Find an entry's history in a KeePass XML export
+///
+/// Entry paths are relative to the root group, as keepassxc-cli uses them
+/// (`RemoteJuggler/API/GITHUB_TOKEN`). Returns None if there is no such
+/// entry.
+pub fn find_entry(xml: &str, path: &str) -> Result<Option<EntryHistory>, Error> {
+    let target = path.trim().trim_start_matches('/');
+    let mut reader = Reader::from_str(xml);
+
+    let mut elements: Vec<Vec<u8>> = Vec::new();
+    let mut groups: Vec<String> = Vec::new();
+    let mut entries: Vec<EntryState> = Vec::new();
+    let (mut key, mut value, mut text) = (String::new(), String::new(), String::new());
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| invalid(format!("bad XML export: {}", e)))?;
+        let (name, is_end) = match &event {
+            Event::Start(e) => (e.name().as_ref().to_vec(), false),
+            Event::Empty(e) => (e.name().as_ref().to_vec(), true),
+            Event::End(e) => (e.name().as_ref().to_vec(), true),
+            Event::Text(t) => {
+                let unescaped = t
+                    .unescape()
+                    .map_err(|e| invalid(format!("bad XML export: {}", e)))?;
+                text.push_str(&unescaped);
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        if !matches!(event, Event::End(_)) {
+            text.clear();
+            match name.as_slice() {
+                b"Group" => groups.push(String::new()),
+                b"Entry" => entries.push(EntryState::default()),
+                b"String" => {
+                    key.clear();
+                    value.clear();
+                }
+                _ => {}
+            }
+            elements.push(name.clone());
+        }
+        if !is_end {
+            continue;
+        }
+
+        elements.pop();
+        let parent = elements.last().map(Vec::as_slice);
+        let grandparent = elements
+            .len()
+            .checked_sub(2)
+            .map(|i| elements[i].as_slice());
+        match name.as_slice() {
+            b"Name" if parent == Some(b"Group") => {
+                if let Some(group) = groups.last_mut() {
+                    *group = std::mem::take(&mut text);
+                }
+            }
+            b"Key" if parent == Some(b"String") => key = std::mem::take(&mut text),
+            b"Value" if parent == Some(b"String") => value = std::mem::take(&mut text),
+            b"String" if parent == Some(b"Entry") => {
+                if let Some(entry) = entries.last_mut() {
+                    let value = std::mem::take(&mut value);
+                    match key.as_str() {
+                        "Title" => entry.title = value,
+                        "UserName" => entry.version.username = value,
+                        "Password" => entry.version.value = value,
+                        _ => {}
+                    }
+                }
+            }
+            b"LastModificationTime"
+                if parent == Some(b"Times") && grandparent == Some(b"Entry") =>
+            {
+                if let Some(entry) = entries.last_mut() {
+                    entry.version.modified = parse_time(&text);
+                }
+            }
+            b"Entry" => {
+                let Some(entry) = entries.pop() else {
+                    continue;
+                };
+                if parent == Some(b"History") {
+                    if let Some(owner) = entries.last_mut() {
+                        owner.history.push(entry.version);
+                    }
+                    continue;
+                }
+                // The root group's name isn't part of entry paths
+                let mut segments: Vec<&str> = groups.iter().skip(1).map(String::as_str).collect();
+                segments.push(&entry.title);
+                if segments.join("/") == target {
+                    return Ok(Some(EntryHistory {
+                        path: target.to_string(),
+                        previous: previous_values(&entry.version, entry.history),
+                        current: entry.version,
+                    }));
+                }
+            }
+            b"Group" => {
+                groups.pop();
+            }
+            _ => {}
+        }
+        text.clear();
+    }
+    Ok(None)
+}
+
+/// History newest first, keeping only versions where the value changed
+///
+/// KeePass snapshots an entry on every edit, so renames and note edits
+/// show up as versions with an unchanged secret; those are dropped.
+fn previous_values(current: &Version, history: Vec<Version>) -> Vec<Version> {
+    let mut previous = Vec::new();
+    let mut newer_value = current.value.as_str();
+    for version in history.iter().rev() {
+        if version.value != newer_value {
+            previous.push(version.clone());
+        }
+        newer_value = &version.value;
+    }
+    previous
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<KeePassFile>
+  <Meta><DatabaseName>Passwords</DatabaseName></Meta>
+  <Root>
+    <Group>
+      <Name>Root</Name>
+      <Group>
+        <Name>RemoteJuggler</Name>
+        <Group>
+          <Name>API</Name>
+          <Entry>
+            <Times><LastModificationTime>2026-03-01T09:30:00Z</LastModificationTime></Times>
+            <String><Key>Title</Key><Value>DATABASE_URL</Value></String>
+            <String><Key>UserName</Key><Value/></String>
+            <String><Key>Password</Key><Value ProtectInMemory="True">postgres://oops</Value></String>
+            <History>
+              <Entry>
+                <Times><LastModificationTime>2026-01-01T00:00:00Z</LastModificationTime></Times>
+                <String><Key>Title</Key><Value>DATABASE_URL</Value></String>
+                <String><Key>Password</Key><Value ProtectInMemory="True">postgres://v1</Value></String>
+              </Entry>
+              <Entry>
+                <Times><LastModificationTime>2026-02-01T00:00:00Z</LastModificationTime></Times>
+                <String><Key>Title</Key><Value>DATABASE_URL</Value></String>
+                <String><Key>Password</Key><Value ProtectInMemory="True">postgres://u:p&amp;w@db/v2</Value></String>
+              </Entry>
+              <Entry>
+                <Times><LastModificationTime>2026-02-15T00:00:00Z</LastModificationTime></Times>
+                <String><Key>Title</Key><Value>DB_URL</Value></String>
+                <String><Key>Password</Key><Value ProtectInMemory="True">postgres://u:p&amp;w@db/v2</Value></String>
+              </Entry>
+            </History>
+          </Entry>
+        </Group>
+      </Group>
+      <Entry>
+        <String><Key>Title</Key><Value>top-level</Value></String>
+        <String><Key>Password</Key><Value>x</Value></String>
+      </Entry>
+    </Group>
+  </Root>
+</KeePassFile>"#;
+
+    #[test]
+    fn test_find_entry_history() {
+        let history = find_entry(EXPORT, "RemoteJuggler/API/DATABASE_URL")
+            .unwrap()
+            .unwrap();
+        assert_eq!(history.current.value, "postgres://oops");
+        assert_eq!(history.current.modified_text(), "2026-03-01 09:30 UTC");
+        // The rename to DB_URL kept the value and is collapsed
+        let values: Vec<&str> = history.previous.iter().map(|v| v.value.as_str()).collect();
+        assert_eq!(values, vec!["postgres://u:p&w@db/v2", "postgres://v1"]);
+        assert_eq!(
+            history.previous[0].modified,
+            parse_time("2026-02-15T00:00:00Z")
+        );
+    }
+
+    #[test]
+    fn test_find_entry_paths() {
+        assert!(find_entry(EXPORT, "/top-level").unwrap().is_some());
+        assert!(find_entry(EXPORT, "RemoteJuggler/API/MISSING")
+            .unwrap()
+            .is_none());
+        // History snapshots are not entries of their own
+        assert!(find_entry(EXPORT, "RemoteJuggler/API/DB_URL")
+            .unwrap()
+            .is_none());
+        assert!(find_entry("<KeePassFile></Root>", "x").is_err());
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("1970-01-01T00:01:05Z"), Some(65));
+        // KDBX 4: base64 of seconds since 0001-01-01, little-endian
+        let kdbx4 = {
+            use base64::Engine;
+            base64::engine::general_purpose::STANDARD.encode((KDBX_EPOCH_OFFSET + 65).to_le_bytes())
+        };
+        assert_eq!(parse_time(&kdbx4), Some(65));
+        assert_eq!(parse_time("yesterday"), None);
+        assert_eq!(format_timestamp(65), "1970-01-01 00:01 UTC");
+    }
+
+    #[test]
+    fn test_debug_hides_value() {
+        let version = Version {
+            value: "hunter2".to_string(),
+            ..Default::default()
+        };
+        assert!(!format!("{:?}", version).contains("hunter2"));
+    }
+}
@@ -0,0 +1,422 @@
+//! The pre-commit identity guard
+//!
+//! A pre-commit hook that refuses commits whose author email isn't the one
+//! of the identity detected for the repository. Guarding a repository
+//! records that identity's name and email in its git config
+//! (`remote-juggler.identity`, `remote-juggler.expectedEmail`) and puts
+//! the hook in its hooks directory; a hook that was already there is kept
+//! as `pre-commit.local` and still runs first. The hook can also go in a
+//! global `core.hooksPath`, where it guards every repository that has an
+//! expected email and runs the repository's own pre-commit hook, which
+//! git would otherwise skip. A policy covering the repository is recorded
+//! too (`remote-juggler.policy`, `remote-juggler.emailDomain`,
+//! `remote-juggler.requireSigning`), and the hook refuses commits with an
+//! email outside its domain, or without commit.gpgsign when it requires
+//! signing. `REMOTE_JUGGLER_HOOKS_DISABLED=1` lets a
+//! commit through, as with the CLI's hooks. Guarded repositories are
+//! remembered so the GUI can list and update them.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::branches;
+use crate::cli::{self, RunOptions};
+use crate::config::Config;
+use crate::detect;
+use crate::error::{CliError, Error};
+use crate::platform;
+use crate::policy;
+
+/// Marks a hook as RemoteJuggler's
+pub const MARKER: &str = "# RemoteJuggler identity guard";
+/// Set to 1 to commit without the check
+pub const BYPASS_ENV: &str = "REMOTE_JUGGLER_HOOKS_DISABLED";
+pub const EXPECTED_EMAIL_KEY: &str = "remote-juggler.expectedEmail";
+pub const IDENTITY_KEY: &str = "remote-juggler.identity";
+pub const POLICY_KEY: &str = "remote-juggler.policy";
+pub const EMAIL_DOMAIN_KEY: &str = "remote-juggler.emailDomain";
+pub const REQUIRE_SIGNING_KEY: &str = "remote-juggler.requireSigning";
+
+const HOOK: &str = "pre-commit";
+/// Where a repository's own hook goes when the guard takes its place
+const LOCAL_HOOK: &str = "pre-commit.local";
+
+/// Where the hook is installed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// One repository's hooks directory
+    Repo,
+    /// A directory set as the global `core.hooksPath`
+    Global,
+}
+
+/// The hook script
+pub fn render_hook(scope: Scope) -> String {
+    let chain = match scope {
+        Scope::Repo => format!(
+            "own=\"$(dirname \"$0\")/{}\"\n\
+             if [ -x \"$own\" ]; then\n    \"$own\" \"$@\" || exit $?\nfi\n",
+            LOCAL_HOOK
+        ),
+        // core.hooksPath makes git skip the repository's own hooks
+        Scope::Global => format!(
+            "own=\"$(git rev-parse --git-common-dir)/hooks/{}\"\n\
+             if [ -x \"$own\" ] && ! grep -q \"^{}\" \"$own\"; then\n    \
+             \"$own\" \"$@\" || exit $?\nfi\n",
+            HOOK, MARKER
+        ),
+    };
+    format!(
+        "#!/bin/sh\n\
+         {marker}\n\
+         # Refuses commits whose author email isn't the identity expected for\n\
+         # the repository, or that break its organization policy. Bypass once\n\
+         # with {bypass}=1.\n\
+         \n\
+         [ \"${{{bypass}:-}}\" = \"1\" ] && exit 0\n\
+         \n\
+         {chain}\
+         \n\
+         author=$(git var GIT_AUTHOR_IDENT | sed -n 's/.*<\\(.*\\)>.*/\\1/p')\n\
+         lower() {{ printf '%s' \"$1\" | tr '[:upper:]' '[:lower:]'; }}\n\
+         refuse() {{\n    \
+             echo \"[RemoteJuggler] $1\" >&2\n    \
+             [ -n \"${{2:-}}\" ] && echo \"$2\" >&2\n    \
+             echo \"Or commit once with {bypass}=1\" >&2\n    \
+             exit 1\n\
+         }}\n\
+         \n\
+         expected=$(git config --get {email_key})\n\
+         if [ -n \"$expected\" ] && [ \"$(lower \"$author\")\" != \"$(lower \"$expected\")\" ]; then\n    \
+             identity=$(git config --get {identity_key})\n    \
+             refuse \"Author <$author> isn't $identity <$expected>\" \\\n        \
+                 \"Switch with: remote-juggler switch $identity\"\n\
+         fi\n\
+         policy=$(git config --get {policy_key})\n\
+         domain=$(lower \"$(git config --get {domain_key})\")\n\
+         if [ -n \"$domain\" ]; then\n    \
+             case \"$(lower \"$author\")\" in\n        \
+                 *@\"$domain\") ;;\n        \
+                 *) refuse \"Policy $policy requires an @$domain email, not <$author>\" ;;\n    \
+             esac\n\
+         fi\n\
+         if [ \"$(git config --bool --get {signing_key})\" = \"true\" ] &&\n    \
+             [ \"$(git config --bool --get commit.gpgsign)\" != \"true\" ]; then\n    \
+             refuse \"Policy $policy requires signed commits; set commit.gpgsign\"\n\
+         fi\n",
+        marker = MARKER,
+        bypass = BYPASS_ENV,
+        chain = chain,
+        email_key = EXPECTED_EMAIL_KEY,
+        identity_key = IDENTITY_KEY,
+        policy_key = POLICY_KEY,
+        domain_key = EMAIL_DOMAIN_KEY,
+        signing_key = REQUIRE_SIGNING_KEY,
+    )
+}
+
+/// Whether the file at `path` is the guard
+pub fn is_guard(path: &Path) -> bool {
+    std::fs::read_to_string(path).is_ok_and(|text| text.contains(MARKER))
+}
+
+fn make_executable(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Write the hook into `dir`, moving a hook that isn't the guard to
+/// `pre-commit.local`
+pub fn place_hook(dir: &Path, scope: Scope) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let hook = dir.join(HOOK);
+    if hook.exists() && !is_guard(&hook) {
+        let local = dir.join(LOCAL_HOOK);
+        if local.exists() {
+            return Err(std::io::Error::other(format!(
+                "Both {} and {} exist; merge them by hand",
+                HOOK, LOCAL_HOOK
+            )));
+        }
+        std::fs::rename(&hook, &local)?;
+    }
+    std::fs::write(&hook, render_hook(scope))?;
+    make_executable(&hook)
+}
+
+/// Remove the guard from `dir`, putting a moved hook back
+pub fn remove_hook(dir: &Path) -> std::io::Result<()> {
+    let hook = dir.join(HOOK);
+    if !is_guard(&hook) {
+        return Ok(());
+    }
+    std::fs::remove_file(&hook)?;
+    let local = dir.join(LOCAL_HOOK);
+    if local.exists() {
+        std::fs::rename(&local, &hook)?;
+    }
+    Ok(())
+}
+
+fn git(repo: Option<&Path>, args: &[&str]) -> Result<String, CliError> {
+    let mut all = match repo {
+        Some(repo) => vec!["-C".to_string(), repo.to_string_lossy().into_owned()],
+        None => Vec::new(),
+    };
+    all.extend(cli::args(args));
+    let options = RunOptions {
+        timeout: Duration::from_secs(10),
+        retries: 0,
+        cancel: None,
+    };
+    cli::run_program("git", &all, &options)
+}
+
+/// The repository's own hooks directory, shared by its worktrees; a
+/// submodule's is in the superproject's `.git/modules`
+pub fn hooks_dir(repo: &Path) -> Result<PathBuf, Error> {
+    Ok(detect::layout(repo)?.common_dir.join("hooks"))
+}
+
+/// The path a repository is remembered by: the main checkout for a
+/// worktree or subdirectory, so each repository is listed once
+pub fn guarded_path(repo: &Path) -> Result<PathBuf, Error> {
+    let layout = detect::layout(repo)?;
+    Ok(match layout.checkout {
+        detect::Checkout::Submodule { .. } => layout.toplevel,
+        _ => layout.main,
+    })
+}
+
+/// A guarded repository
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Guarded {
+    pub path: PathBuf,
+    pub identity: String,
+    pub email: String,
+}
+
+/// The repositories with the guard, persisted locally
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuardStore {
+    #[serde(default)]
+    pub repos: Vec<Guarded>,
+}
+
+impl GuardStore {
+    pub fn path() -> Option<PathBuf> {
+        platform::data_dir().map(|d| d.join("guarded-repos.json"))
+    }
+
+    pub fn load() -> Self {
+        platform::load_json_state(Self::path())
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        platform::save_json_state(Self::path(), self)
+    }
+
+    /// Add or replace the entry for a repository
+    pub fn set(&mut self, guarded: Guarded) {
+        self.repos.retain(|r| r.path != guarded.path);
+        self.repos.push(guarded);
+        self.repos.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+}
+
+/// Record the identity detected for `repo` as the expected one, and the
+/// policy covering it; branch rules are applied so the branches they
+/// cover expect their own identity. Blocks
+pub fn expect(config: &Config, repo: &Path) -> Result<Guarded, Error> {
+    let found = detect::detect_default(config, repo)?.ok_or_else(|| Error::InvalidOutput {
+        program: "detect",
+        message: "No identity matches the repository".to_string(),
+    })?;
+    let identity = &config.identities[&found.identity];
+    if identity.email.is_empty() {
+        return Err(Error::InvalidOutput {
+            program: "config",
+            message: format!("{} has no email", found.identity),
+        });
+    }
+    git(Some(repo), &["config", IDENTITY_KEY, &found.identity]).map_err(Error::from_cli)?;
+    git(Some(repo), &["config", EXPECTED_EMAIL_KEY, &identity.email]).map_err(Error::from_cli)?;
+    match policy::applicable(config, repo)? {
+        Some((policy, _)) => {
+            let domain = policy::domain(&policy);
+            let signing = policy.require_signing.to_string();
+            for (key, value) in [
+                (POLICY_KEY, policy.pattern.as_str()),
+                (EMAIL_DOMAIN_KEY, domain.as_str()),
+                (REQUIRE_SIGNING_KEY, signing.as_str()),
+            ] {
+                git(Some(repo), &["config", key, value]).map_err(Error::from_cli)?;
+            }
+        }
+        None => unset(repo, &[POLICY_KEY, EMAIL_DOMAIN_KEY, REQUIRE_SIGNING_KEY]),
+    }
+    branches::apply(config, repo)?;
+    Ok(Guarded {
+        path: repo.to_path_buf(),
+        identity: found.identity,
+        email: identity.email.clone(),
+    })
+}
+
+/// Guard a repository: record its expected identity and install the
+/// hook; blocks. Worktrees share the main checkout's config and hooks, so
+/// guarding one guards them all
+pub fn install(config: &Config, repo: &Path) -> Result<Guarded, Error> {
+    let repo = guarded_path(&repo.canonicalize()?)?;
+    let guarded = expect(config, &repo)?;
+    place_hook(&hooks_dir(&repo)?, Scope::Repo)?;
+    let mut store = GuardStore::load();
+    store.set(guarded.clone());
+    store.save()?;
+    Ok(guarded)
+}
+
+/// Detect the identities of the guarded repositories again, e.g. after
+/// identities or rules changed; returns the repositories that failed.
+/// Blocks
+pub fn update_all(config: &Config) -> Result<Vec<(PathBuf, Error)>, Error> {
+    let mut store = GuardStore::load();
+    let mut failed = Vec::new();
+    for guarded in store.repos.clone() {
+        match expect(config, &guarded.path) {
+            Ok(updated) => store.set(updated),
+            Err(e) => failed.push((guarded.path, e)),
+        }
+    }
+    store.save()?;
+    Ok(failed)
+}
+
+/// Unset the keys in the repository's config
+fn unset(repo: &Path, keys: &[&str]) {
+    for key in keys {
+        match git(Some(repo), &["config", "--unset", key]) {
+            // Exit status 5: the key wasn't set
+            Ok(_) | Err(CliError::NonZeroExit { code: Some(5), .. }) => {}
+            // The repository is gone; forget it anyway
+            Err(e) => tracing::debug!("Unsetting {} failed: {}", key, e),
+        }
+    }
+}
+
+/// Remove the guard and the recorded identity from a repository; blocks
+pub fn uninstall(repo: &Path) -> Result<(), Error> {
+    let path = guarded_path(repo).unwrap_or_else(|_| repo.to_path_buf());
+    if let Ok(dir) = hooks_dir(repo) {
+        remove_hook(&dir)?;
+    }
+    unset(
+        repo,
+        &[
+            IDENTITY_KEY,
+            EXPECTED_EMAIL_KEY,
+            POLICY_KEY,
+            EMAIL_DOMAIN_KEY,
+            REQUIRE_SIGNING_KEY,
+        ],
+    );
+    let mut store = GuardStore::load();
+    store.repos.retain(|r| r.path != repo && r.path != path);
+    store.save()?;
+    Ok(())
+}
+
+/// The directory the global hook goes in
+pub fn global_dir() -> Option<PathBuf> {
+    platform::config_dir().map(|dir| dir.join("hooks"))
+}
+
+/// The global `core.hooksPath`, if set; blocks
+pub fn global_hooks_path() -> Option<PathBuf> {
+    git(None, &["config", "--global", "--get", "core.hooksPath"])
+        .ok()
+        .map(|out| platform::expand_tilde(out.trim()))
+        .filter(|path| !path.as_os_str().is_empty())
+}
+
+/// Whether the guard is the global pre-commit hook; blocks
+pub fn global_installed() -> bool {
+    global_hooks_path().is_some_and(|dir| is_guard(&dir.join(HOOK)))
+}
+
+/// Install the guard in a global `core.hooksPath`: the one already set,
+/// or our own directory; blocks
+pub fn install_global() -> Result<PathBuf, Error> {
+    if let Some(dir) = global_hooks_path() {
+        place_hook(&dir, Scope::Global)?;
+        return Ok(dir);
+    }
+    let dir = global_dir().ok_or_else(|| std::io::Error::other("No config directory"))?;
+    place_hook(&dir, Scope::Global)?;
+    git(
+        None,
+        &[
+            "config",
+            "--global",
+            "core.hooksPath",
+            &dir.to_string_lossy(),
+        ],
+    )
+    .map_err(Error::from_cli)?;
+    Ok(dir)
+}
+
+/// Remove the global guard, and `core.hooksPath` if it is our directory;
+/// blocks
+pub fn uninstall_global() -> Result<(), Error> {
+    let Some(dir) = global_hooks_path() else {
+        return Ok(());
+    };
+    remove_hook(&dir)?;
+    if Some(&dir) == global_dir().as_ref() {
+        git(None, &["config", "--global", "--unset", "core.hooksPath"]).map_err(Error::from_cli)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_place_and_remove_hook() {
+        let dir = tempfile::tempdir().unwrap();
+        let own = "#!/bin/sh\nmake lint\n";
+        std::fs::write(dir.path().join(HOOK), own).unwrap();
+
+        place_hook(dir.path(), Scope::Repo).unwrap();
+        let hook = std::fs::read_to_string(dir.path().join(HOOK)).unwrap();
+        assert!(hook.contains(MARKER));
+        assert!(hook.contains("[ \"${REMOTE_JUGGLER_HOOKS_DISABLED:-}\" = \"1\" ] && exit 0"));
+        assert!(hook.contains("git config --get remote-juggler.expectedEmail"));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(LOCAL_HOOK)).unwrap(),
+            own
+        );
+        // Installing again leaves the moved hook alone
+        place_hook(dir.path(), Scope::Repo).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(LOCAL_HOOK)).unwrap(),
+            own
+        );
+
+        remove_hook(dir.path()).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join(HOOK)).unwrap(), own);
+        assert!(!dir.path().join(LOCAL_HOOK).exists());
+        assert!(render_hook(Scope::Global).contains("--git-common-dir"));
+    }
+}
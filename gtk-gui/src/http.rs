@@ -0,0 +1,122 @@
+//! Blocking HTTP client for remote secret stores and provider APIs
+//!
+//! A thin wrapper over a shared ureq agent with sane timeouts and error
+//! mapping.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::error::Error;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Shared agent (connection pool) for all requests
+pub fn agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+    AGENT.get_or_init(|| {
+        ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("remote-juggler-gui/", env!("CARGO_PKG_VERSION")))
+            .build()
+    })
+}
+
+/// Convert a ureq error, keeping the response body for API errors
+pub fn map_error(err: ureq::Error) -> Error {
+    match err {
+        ureq::Error::Status(status, response) => {
+            let body = response.into_string().unwrap_or_default();
+            Error::Http {
+                status,
+                message: api_error_message(&body).unwrap_or(body),
+            }
+        }
+        ureq::Error::Transport(t) => Error::Network(t.to_string()),
+    }
+}
+
+/// Pull a human-readable message out of a JSON error body
+///
+/// Handles the common `{"error": {"message": ...}}` (Google, Azure) and
+/// `{"message": ...}` (GitHub) shapes.
+pub fn api_error_message(body: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(body).ok()?;
+    value
+        .pointer("/error/message")
+        .or_else(|| value.get("message"))
+        .or_else(|| value.get("error_description"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+fn with_auth(request: ureq::Request, bearer: Option<&str>) -> ureq::Request {
+    match bearer {
+        Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+        None => request,
+    }
+}
+
+fn read_json<T: DeserializeOwned>(response: ureq::Response) -> Result<T, Error> {
+    response.into_json().map_err(|e| Error::InvalidOutput {
+        program: "http",
+        message: e.to_string(),
+    })
+}
+
+/// GET a JSON document
+pub fn get_json<T: DeserializeOwned>(url: &str, bearer: Option<&str>) -> Result<T, Error> {
+    let response = with_auth(agent().get(url), bearer)
+        .set("Accept", "application/json")
+        .call()
+        .map_err(map_error)?;
+    read_json(response)
+}
+
+/// POST a JSON body and parse the JSON response
+pub fn post_json<T: DeserializeOwned>(
+    url: &str,
+    bearer: Option<&str>,
+    body: &Value,
+) -> Result<T, Error> {
+    let response = with_auth(agent().post(url), bearer)
+        .set("Accept", "application/json")
+        .send_json(body)
+        .map_err(map_error)?;
+    read_json(response)
+}
+
+/// PUT a JSON body and parse the JSON response
+pub fn put_json<T: DeserializeOwned>(
+    url: &str,
+    bearer: Option<&str>,
+    body: &Value,
+) -> Result<T, Error> {
+    let response = with_auth(agent().put(url), bearer)
+        .set("Accept", "application/json")
+        .send_json(body)
+        .map_err(map_error)?;
+    read_json(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_error_message() {
+        assert_eq!(
+            api_error_message(r#"{"error": {"code": 404, "message": "Secret not found"}}"#),
+            Some("Secret not found".to_string())
+        );
+        assert_eq!(
+            api_error_message(r#"{"message": "Bad credentials"}"#),
+            Some("Bad credentials".to_string())
+        );
+        assert_eq!(api_error_message("<html>"), None);
+    }
+}
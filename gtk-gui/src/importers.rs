@@ -0,0 +1,730 @@
+//! Importing other password managers' exports into the key store
+//!
+//! Each supported export format has a parser that turns it into
+//! `ForeignEntry` values. Those are mapped into the key store under
+//! `RemoteJuggler/Imported/{source}/{folder...}/{title}` and checked for
+//! duplicates, both within the file (the same login exported twice) and
+//! against what the key store already holds. The resulting plan is the
+//! dry-run report; applying it stores each entry's secret with
+//! `keys store`, following the same collision policy as backend imports.
+//!
+//! Only the secret is stored: `keys store` has no username or URL fields.
+//! Secure notes without a password are imported with the note as value.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::backend::{self, CollisionPolicy, ImportReport};
+use crate::cli;
+use crate::cli_cache;
+use crate::error::Error;
+use crate::progress::ProgressEvent;
+
+/// Key store group imports land under
+pub const IMPORT_ROOT: &str = "RemoteJuggler/Imported";
+
+/// A supported export format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// 1Password export (`.1pux`, a zip with `export.data`)
+    OnePux,
+    LastPassCsv,
+    /// Unencrypted Bitwarden JSON export
+    BitwardenJson,
+    /// Chrome, Firefox, Edge, or Safari passwords CSV
+    BrowserCsv,
+}
+
+impl ImportFormat {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ImportFormat::OnePux => "1Password",
+            ImportFormat::LastPassCsv => "LastPass",
+            ImportFormat::BitwardenJson => "Bitwarden",
+            ImportFormat::BrowserCsv => "Browser",
+        }
+    }
+
+    /// Detect the format from the file name and its first bytes
+    pub fn detect(path: &Path, head: &[u8]) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "1pux" => Some(ImportFormat::OnePux),
+            "json" => Some(ImportFormat::BitwardenJson),
+            "csv" => {
+                let header = String::from_utf8_lossy(head);
+                let header = header.lines().next()?.trim_start_matches('\u{feff}');
+                let columns: Vec<String> = header
+                    .split(',')
+                    .map(|c| c.trim().to_ascii_lowercase())
+                    .collect();
+                if columns.iter().any(|c| c == "grouping") {
+                    Some(ImportFormat::LastPassCsv)
+                } else if columns.iter().any(|c| c == "password") {
+                    Some(ImportFormat::BrowserCsv)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// An entry as exported by another password manager
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct ForeignEntry {
+    /// Folder path, outermost first
+    pub folder: Vec<String>,
+    pub title: String,
+    pub username: String,
+    pub url: String,
+    pub password: String,
+    pub notes: String,
+}
+
+// Keep secret values out of logs
+impl std::fmt::Debug for ForeignEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ForeignEntry")
+            .field("folder", &self.folder)
+            .field("title", &self.title)
+            .field("username", &self.username)
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ForeignEntry {
+    /// The value to store: the password, or the note of a secure note
+    pub fn value(&self) -> &str {
+        if self.password.is_empty() {
+            &self.notes
+        } else {
+            &self.password
+        }
+    }
+
+    /// Title, falling back to the URL's host
+    fn display_title(&self) -> String {
+        if !self.title.trim().is_empty() {
+            return self.title.trim().to_string();
+        }
+        let host = self
+            .url
+            .split("://")
+            .last()
+            .unwrap_or_default()
+            .split(['/', '?', '#'])
+            .next()
+            .unwrap_or_default();
+        if host.is_empty() {
+            "Untitled".to_string()
+        } else {
+            host.to_string()
+        }
+    }
+}
+
+fn invalid(format: ImportFormat, message: impl std::fmt::Display) -> Error {
+    Error::InvalidOutput {
+        program: format.display_name(),
+        message: message.to_string(),
+    }
+}
+
+/// Read CSV rows as column-name (lowercased) to value maps
+fn csv_records(data: &[u8], format: ImportFormat) -> Result<Vec<HashMap<String, String>>, Error> {
+    let data = data.strip_prefix("\u{feff}".as_bytes()).unwrap_or(data);
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(data);
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| invalid(format, e))?
+        .iter()
+        .map(|h| h.trim().to_ascii_lowercase())
+        .collect();
+    reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| invalid(format, e))?;
+            Ok(headers
+                .iter()
+                .cloned()
+                .zip(record.iter().map(str::to_string))
+                .collect())
+        })
+        .collect()
+}
+
+fn column(record: &HashMap<String, String>, names: &[&str]) -> String {
+    names
+        .iter()
+        .find_map(|name| record.get(*name).filter(|v| !v.is_empty()))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Parse a LastPass CSV export
+///
+/// Folders are in `grouping`, nested with backslashes.
+pub fn parse_lastpass(data: &[u8]) -> Result<Vec<ForeignEntry>, Error> {
+    Ok(csv_records(data, ImportFormat::LastPassCsv)?
+        .iter()
+        .map(|r| {
+            let url = column(r, &["url"]);
+            ForeignEntry {
+                folder: column(r, &["grouping"])
+                    .split('\\')
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                title: column(r, &["name"]),
+                username: column(r, &["username"]),
+                password: column(r, &["password"]),
+                notes: column(r, &["extra"]),
+                // Secure notes carry this placeholder URL
+                url: if url == "http://sn" {
+                    String::new()
+                } else {
+                    url
+                },
+            }
+        })
+        .collect())
+}
+
+/// Parse a browser passwords CSV (Chrome, Edge, Firefox, Safari)
+pub fn parse_browser_csv(data: &[u8]) -> Result<Vec<ForeignEntry>, Error> {
+    Ok(csv_records(data, ImportFormat::BrowserCsv)?
+        .iter()
+        .map(|r| ForeignEntry {
+            folder: Vec::new(),
+            title: column(r, &["name", "title"]),
+            username: column(r, &["username"]),
+            url: column(r, &["url", "origin"]),
+            password: column(r, &["password"]),
+            notes: column(r, &["note", "notes"]),
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenExport {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<BitwardenFolder>,
+    #[serde(default)]
+    items: Vec<BitwardenItem>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenFolder {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenItem {
+    #[serde(default)]
+    name: String,
+    folder_id: Option<String>,
+    notes: Option<String>,
+    login: Option<BitwardenLogin>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenLogin {
+    username: Option<String>,
+    password: Option<String>,
+    #[serde(default)]
+    uris: Vec<BitwardenUri>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenUri {
+    uri: Option<String>,
+}
+
+/// Parse an unencrypted Bitwarden JSON export
+///
+/// Nested folders are named with slashes (`Work/Clients`).
+pub fn parse_bitwarden(data: &[u8]) -> Result<Vec<ForeignEntry>, Error> {
+    let format = ImportFormat::BitwardenJson;
+    let export: BitwardenExport = serde_json::from_slice(data).map_err(|e| invalid(format, e))?;
+    if export.encrypted {
+        return Err(invalid(
+            format,
+            "the export is encrypted; export as unencrypted JSON",
+        ));
+    }
+    let folders: HashMap<String, String> =
+        export.folders.into_iter().map(|f| (f.id, f.name)).collect();
+    Ok(export
+        .items
+        .into_iter()
+        .map(|item| {
+            let login = item.login;
+            ForeignEntry {
+                folder: item
+                    .folder_id
+                    .and_then(|id| folders.get(&id))
+                    .map(|name| name.split('/').map(str::to_string).collect())
+                    .unwrap_or_default(),
+                title: item.name,
+                username: login
+                    .as_ref()
+                    .and_then(|l| l.username.clone())
+                    .unwrap_or_default(),
+                url: login
+                    .as_ref()
+                    .and_then(|l| l.uris.iter().find_map(|u| u.uri.clone()))
+                    .unwrap_or_default(),
+                password: login.and_then(|l| l.password).unwrap_or_default(),
+                notes: item.notes.unwrap_or_default(),
+            }
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct OnePuxData {
+    accounts: Vec<OnePuxAccount>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxAccount {
+    vaults: Vec<OnePuxVault>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxVault {
+    attrs: OnePuxAttrs,
+    #[serde(default)]
+    items: Vec<OnePuxItem>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxAttrs {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnePuxItem {
+    #[serde(default)]
+    state: String,
+    overview: OnePuxOverview,
+    details: OnePuxDetails,
+}
+
+#[derive(Deserialize)]
+struct OnePuxOverview {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnePuxDetails {
+    #[serde(default)]
+    login_fields: Vec<OnePuxLoginField>,
+    #[serde(default)]
+    notes_plain: String,
+    password: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxLoginField {
+    #[serde(default)]
+    value: String,
+    #[serde(default)]
+    designation: String,
+}
+
+/// Parse the `export.data` JSON of a 1PUX export
+///
+/// Each vault becomes a folder; archived and deleted items are left out.
+pub fn parse_1pux_data(data: &[u8]) -> Result<Vec<ForeignEntry>, Error> {
+    let format = ImportFormat::OnePux;
+    let export: OnePuxData = serde_json::from_slice(data).map_err(|e| invalid(format, e))?;
+    let mut entries = Vec::new();
+    for vault in export.accounts.into_iter().flat_map(|a| a.vaults) {
+        for item in vault.items {
+            if !matches!(item.state.as_str(), "" | "active") {
+                continue;
+            }
+            let field = |designation: &str| {
+                item.details
+                    .login_fields
+                    .iter()
+                    .find(|f| f.designation == designation)
+                    .map(|f| f.value.clone())
+            };
+            entries.push(ForeignEntry {
+                folder: vec![vault.attrs.name.clone()],
+                username: field("username").unwrap_or_default(),
+                password: field("password")
+                    .or_else(|| item.details.password.clone())
+                    .unwrap_or_default(),
+                title: item.overview.title,
+                url: item.overview.url,
+                notes: item.details.notes_plain,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Parse a `.1pux` archive
+pub fn parse_1pux(data: &[u8]) -> Result<Vec<ForeignEntry>, Error> {
+    let format = ImportFormat::OnePux;
+    let mut archive =
+        zip::ZipArchive::new(std::io::Cursor::new(data)).map_err(|e| invalid(format, e))?;
+    let mut file = archive
+        .by_name("export.data")
+        .map_err(|e| invalid(format, e))?;
+    let mut json = Vec::new();
+    file.read_to_end(&mut json)?;
+    parse_1pux_data(&json)
+}
+
+/// Parse an export file in the given format
+pub fn parse(format: ImportFormat, data: &[u8]) -> Result<Vec<ForeignEntry>, Error> {
+    match format {
+        ImportFormat::OnePux => parse_1pux(data),
+        ImportFormat::LastPassCsv => parse_lastpass(data),
+        ImportFormat::BitwardenJson => parse_bitwarden(data),
+        ImportFormat::BrowserCsv => parse_browser_csv(data),
+    }
+}
+
+/// What applying the import will do with one entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportAction {
+    /// Stored as a new entry
+    New,
+    /// The same login appeared earlier in the file; not imported
+    DuplicateInFile { of: String },
+    /// The key store already holds this entry with the same value
+    Unchanged,
+    /// The key store holds a different value; the collision policy decides
+    Collision,
+    /// Neither a password nor a note to store
+    Empty,
+}
+
+/// One entry of the import plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedImport {
+    pub entry: ForeignEntry,
+    /// Key store path the entry maps to
+    pub target: String,
+    pub action: ImportAction,
+}
+
+/// Turn a name into a single key store path segment
+fn segment(name: &str) -> String {
+    let cleaned = name.trim().replace('/', "-");
+    if cleaned.is_empty() {
+        "Untitled".to_string()
+    } else {
+        cleaned
+    }
+}
+
+/// Key store path for an imported entry
+pub fn target_path(source: ImportFormat, entry: &ForeignEntry) -> String {
+    let mut parts = vec![IMPORT_ROOT.to_string(), source.display_name().to_string()];
+    parts.extend(entry.folder.iter().map(|f| segment(f)));
+    parts.push(segment(&entry.display_title()));
+    parts.join("/")
+}
+
+/// Plan an import
+///
+/// `existing` returns the value of a key store entry, or None if it doesn't
+/// exist. Entries mapping to the same path get `-2`, `-3`, ... suffixes.
+pub fn plan(
+    source: ImportFormat,
+    entries: Vec<ForeignEntry>,
+    mut existing: impl FnMut(&str) -> Option<String>,
+) -> Vec<PlannedImport> {
+    let mut seen_logins: HashMap<(String, String, String), String> = HashMap::new();
+    let mut taken: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut planned = Vec::new();
+
+    for entry in entries {
+        let mut target = target_path(source, &entry);
+        if let Some((group, leaf)) = target.rsplit_once('/') {
+            let names = taken.entry(group.to_string()).or_default();
+            let leaf = if names.contains(leaf) {
+                backend::unique_name(leaf, names)
+            } else {
+                leaf.to_string()
+            };
+            names.insert(leaf.clone());
+            target = format!("{}/{}", group, leaf);
+        }
+
+        let login = (
+            entry.url.trim().to_ascii_lowercase(),
+            entry.username.clone(),
+            entry.value().to_string(),
+        );
+        let action = if entry.value().is_empty() {
+            ImportAction::Empty
+        } else if let Some(of) = seen_logins.get(&login) {
+            ImportAction::DuplicateInFile { of: of.clone() }
+        } else {
+            seen_logins.insert(login, target.clone());
+            match existing(&target) {
+                None => ImportAction::New,
+                Some(value) if value == entry.value() => ImportAction::Unchanged,
+                Some(_) => ImportAction::Collision,
+            }
+        };
+        planned.push(PlannedImport {
+            entry,
+            target,
+            action,
+        });
+    }
+    planned
+}
+
+/// One-line dry-run summary of a plan
+pub fn summary(plan: &[PlannedImport]) -> String {
+    let count = |f: &dyn Fn(&ImportAction) -> bool| plan.iter().filter(|p| f(&p.action)).count();
+    let mut parts = vec![format!("{} new", count(&|a| *a == ImportAction::New))];
+    for (n, label) in [
+        (
+            count(&|a| *a == ImportAction::Collision),
+            "changed in key store",
+        ),
+        (
+            count(&|a| *a == ImportAction::Unchanged),
+            "already in key store",
+        ),
+        (
+            count(&|a| matches!(a, ImportAction::DuplicateInFile { .. })),
+            "duplicate in file",
+        ),
+        (count(&|a| *a == ImportAction::Empty), "empty"),
+    ] {
+        if n > 0 {
+            parts.push(format!("{} {}", n, label));
+        }
+    }
+    parts.join(", ")
+}
+
+/// Plan an import against the current key store
+///
+/// Blocks while the CLI lists groups and reads colliding entries.
+pub fn plan_against_kdbx(source: ImportFormat, entries: Vec<ForeignEntry>) -> Vec<PlannedImport> {
+    let mut groups: HashMap<String, HashSet<String>> = HashMap::new();
+    plan(source, entries, |path| {
+        let (group, leaf) = path.rsplit_once('/')?;
+        let names = groups
+            .entry(group.to_string())
+            .or_insert_with(|| backend::kdbx_entries(group).into_iter().collect());
+        if !names.contains(leaf) {
+            return None;
+        }
+        // Exists; an unreadable value counts as different
+        let value = cli::run(&cli::args(&["keys", "get", path])).unwrap_or_default();
+        Some(value.trim_end_matches(['\r', '\n']).to_string())
+    })
+}
+
+/// Store a planned import in the key store
+///
+/// Duplicates, unchanged, and empty entries are skipped; collisions follow
+/// `policy`. Blocks while the CLI runs; `on_event` receives progress events.
+pub fn apply(
+    plan: &[PlannedImport],
+    policy: CollisionPolicy,
+    on_event: &mut dyn FnMut(&ProgressEvent),
+) -> ImportReport {
+    let total = plan.len() as u64;
+    on_event(&ProgressEvent::Started {
+        operation: "import".to_string(),
+        total: Some(total),
+    });
+    let mut report = ImportReport::default();
+    let mut renamed: HashMap<String, HashSet<String>> = HashMap::new();
+
+    for (i, item) in plan.iter().enumerate() {
+        on_event(&ProgressEvent::Progress {
+            current: i as u64,
+            total: Some(total),
+            item: Some(item.target.clone()),
+        });
+        let target = match (&item.action, policy) {
+            (ImportAction::New, _) => item.target.clone(),
+            (ImportAction::Collision, CollisionPolicy::Overwrite) => item.target.clone(),
+            (ImportAction::Collision, CollisionPolicy::Rename) => {
+                let (group, leaf) = item.target.rsplit_once('/').unwrap_or(("", &item.target));
+                let names = renamed
+                    .entry(group.to_string())
+                    .or_insert_with(|| backend::kdbx_entries(group).into_iter().collect());
+                let leaf = backend::unique_name(leaf, names);
+                names.insert(leaf.clone());
+                format!("{}/{}", group, leaf)
+            }
+            _ => {
+                report.skipped += 1;
+                continue;
+            }
+        };
+        let stored = cli::store_secret(&target, item.entry.value()).map_err(Error::from_cli);
+        match (stored, &item.action, policy) {
+            (Err(e), _, _) => {
+                tracing::warn!("Failed to import {}: {}", item.target, e);
+                report.failed.push(item.target.clone());
+            }
+            (Ok(()), ImportAction::New, _) => report.imported += 1,
+            (Ok(()), _, CollisionPolicy::Rename) => report.renamed += 1,
+            (Ok(()), _, _) => report.overwritten += 1,
+        }
+    }
+
+    cli_cache::global().invalidate();
+    on_event(&ProgressEvent::Finished {
+        summary: Some(report.summary()),
+    });
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LASTPASS: &str = "url,username,password,totp,extra,name,grouping,fav
+https://github.com/login,alice,ghp_1,,,GitHub,Work\\Dev,0
+http://sn,,,,\"line1
+line2\",Recovery codes,Personal,0
+https://github.com/login,alice,ghp_1,,,GitHub copy,Work\\Dev,0
+";
+
+    #[test]
+    fn test_detect() {
+        let path = Path::new("export.csv");
+        assert_eq!(
+            ImportFormat::detect(path, LASTPASS.as_bytes()),
+            Some(ImportFormat::LastPassCsv)
+        );
+        assert_eq!(
+            ImportFormat::detect(path, b"\xef\xbb\xbfname,url,username,password,note\n"),
+            Some(ImportFormat::BrowserCsv)
+        );
+        assert_eq!(
+            ImportFormat::detect(Path::new("a.1pux"), b"PK"),
+            Some(ImportFormat::OnePux)
+        );
+        assert_eq!(ImportFormat::detect(path, b"a,b\n"), None);
+    }
+
+    #[test]
+    fn test_parse_lastpass() {
+        let entries = parse_lastpass(LASTPASS.as_bytes()).unwrap();
+        assert_eq!(entries[0].folder, vec!["Work", "Dev"]);
+        assert_eq!(entries[0].password, "ghp_1");
+        // Secure note: no URL, the note is the value
+        assert_eq!(entries[1].url, "");
+        assert_eq!(entries[1].value(), "line1\nline2");
+    }
+
+    #[test]
+    fn test_parse_browser_csv() {
+        let firefox = "\"url\",\"username\",\"password\",\"httpRealm\"\n\"https://gitlab.com\",\"bob\",\"pw\",\"\"\n";
+        let entries = parse_browser_csv(firefox.as_bytes()).unwrap();
+        assert_eq!(
+            target_path(ImportFormat::BrowserCsv, &entries[0]),
+            "RemoteJuggler/Imported/Browser/gitlab.com"
+        );
+    }
+
+    #[test]
+    fn test_parse_bitwarden() {
+        let json = r#"{"encrypted":false,"folders":[{"id":"f1","name":"Work/Clients"}],
+            "items":[{"type":1,"name":"npm","folderId":"f1","notes":null,
+              "login":{"username":"ci","password":"npm_x","uris":[{"uri":"https://npmjs.com"}]}},
+             {"type":2,"name":"SSH passphrase","folderId":null,"notes":"hunter2"}]}"#;
+        let entries = parse_bitwarden(json.as_bytes()).unwrap();
+        assert_eq!(
+            target_path(ImportFormat::BitwardenJson, &entries[0]),
+            "RemoteJuggler/Imported/Bitwarden/Work/Clients/npm"
+        );
+        assert_eq!(entries[1].value(), "hunter2");
+        assert!(parse_bitwarden(br#"{"encrypted":true,"items":[]}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_1pux_data() {
+        let json = r#"{"accounts":[{"attrs":{"name":"Me"},"vaults":[{"attrs":{"name":"Private"},"items":[
+            {"state":"active","overview":{"title":"AWS","url":"https://aws.amazon.com"},
+             "details":{"loginFields":[{"value":"admin","designation":"username"},{"value":"aws_pw","designation":"password"}],"notesPlain":""}},
+            {"state":"archived","overview":{"title":"Old"},"details":{"password":"x"}}]}]}]}"#;
+        let entries = parse_1pux_data(json.as_bytes()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].folder, vec!["Private"]);
+        assert_eq!(entries[0].username, "admin");
+        assert_eq!(entries[0].password, "aws_pw");
+    }
+
+    #[test]
+    fn test_plan_detects_duplicates() {
+        let entries = parse_lastpass(LASTPASS.as_bytes()).unwrap();
+        let mut more = entries.clone();
+        more.push(ForeignEntry {
+            title: "GitHub".to_string(),
+            folder: vec!["Work".to_string(), "Dev".to_string()],
+            password: "other".to_string(),
+            ..Default::default()
+        });
+        let existing = |path: &str| {
+            (path == "RemoteJuggler/Imported/LastPass/Personal/Recovery codes")
+                .then(|| "line1\nline2".to_string())
+        };
+        let planned = plan(ImportFormat::LastPassCsv, more, existing);
+        let actions: Vec<&ImportAction> = planned.iter().map(|p| &p.action).collect();
+        assert_eq!(
+            actions,
+            vec![
+                &ImportAction::New,
+                &ImportAction::Unchanged,
+                &ImportAction::DuplicateInFile {
+                    of: "RemoteJuggler/Imported/LastPass/Work/Dev/GitHub".to_string()
+                },
+                &ImportAction::New,
+            ]
+        );
+        // Same title in the same folder gets its own path
+        assert_eq!(
+            planned[3].target,
+            "RemoteJuggler/Imported/LastPass/Work/Dev/GitHub-2"
+        );
+        assert_eq!(
+            summary(&planned),
+            "2 new, 1 already in key store, 1 duplicate in file"
+        );
+    }
+
+    #[test]
+    fn test_debug_hides_password() {
+        let entry = ForeignEntry {
+            password: "hunter2".to_string(),
+            ..Default::default()
+        };
+        assert!(!format!("{:?}", entry).contains("hunter2"));
+    }
+}
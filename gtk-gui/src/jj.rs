@@ -0,0 +1,166 @@
+//! Jujutsu repositories colocated with git
+//!
+//! jj takes the author and signing settings from its own config, not
+//! git's, and knows nothing of `includeIf`, so a colocated repository
+//! (`.jj` next to `.git`) would keep committing as whoever jj was set up
+//! for. Whenever an identity is written to such a repository, the settings
+//! git ends up resolving there are mirrored into the jj repository config
+//! with `jj config set --repo`: user.name and user.email, and signing with
+//! the same key and backend when git signs commits. gitsign has no jj
+//! backend, so jj doesn't sign for gitsign identities.
+
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cli::{self, RunOptions};
+use crate::error::{CliError, Error};
+
+/// What git resolves in a repository, as far as jj cares
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitSettings {
+    pub name: String,
+    pub email: String,
+    /// commit.gpgsign is on
+    pub signs: bool,
+    /// gpg.format; empty means openpgp
+    pub format: String,
+    /// user.signingkey
+    pub key: String,
+}
+
+/// Whether `repo` is a jj repository
+pub fn is_jj(repo: &Path) -> bool {
+    repo.join(".jj").join("repo").exists()
+}
+
+/// The jj repository settings matching git's
+pub fn settings(git: &GitSettings) -> Vec<(String, String)> {
+    let mut settings: Vec<(String, String)> =
+        [("user.name", &git.name), ("user.email", &git.email)]
+            .into_iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect();
+    let backend = match git.format.as_str() {
+        "" | "openpgp" => Some("gpg"),
+        "ssh" => Some("ssh"),
+        _ => None,
+    };
+    match backend {
+        Some(backend) if git.signs && !git.key.is_empty() => {
+            settings.push(("signing.behavior".to_string(), "own".to_string()));
+            settings.push(("signing.backend".to_string(), backend.to_string()));
+            settings.push(("signing.key".to_string(), git.key.clone()));
+        }
+        _ => settings.push(("signing.behavior".to_string(), "drop".to_string())),
+    }
+    settings
+}
+
+fn options() -> RunOptions {
+    RunOptions {
+        timeout: Duration::from_secs(10),
+        retries: 0,
+        cancel: None,
+    }
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<String, CliError> {
+    let mut all = vec!["-C".to_string(), repo.to_string_lossy().into_owned()];
+    all.extend(cli::args(args));
+    cli::run_program("git", &all, &options())
+}
+
+fn jj(repo: &Path, args: &[&str]) -> Result<String, CliError> {
+    let mut all = vec![
+        "--repository".to_string(),
+        repo.to_string_lossy().into_owned(),
+        "--ignore-working-copy".to_string(),
+    ];
+    all.extend(cli::args(args));
+    cli::run_program("jj", &all, &options())
+}
+
+/// A key as git resolves it in the repository; empty when unset
+fn effective(repo: &Path, args: &[&str]) -> Result<String, Error> {
+    match git(repo, args) {
+        Ok(out) => Ok(out.trim().to_string()),
+        // Exit status 1: not set anywhere
+        Err(CliError::NonZeroExit { code: Some(1), .. }) => Ok(String::new()),
+        Err(e) => Err(Error::from_cli(e)),
+    }
+}
+
+/// Read what git resolves in the repository; blocks
+pub fn read_git(repo: &Path) -> Result<GitSettings, Error> {
+    Ok(GitSettings {
+        name: effective(repo, &["config", "--get", "user.name"])?,
+        email: effective(repo, &["config", "--get", "user.email"])?,
+        signs: effective(repo, &["config", "--bool", "--get", "commit.gpgsign"])? == "true",
+        format: effective(repo, &["config", "--get", "gpg.format"])?,
+        key: effective(repo, &["config", "--get", "user.signingkey"])?,
+    })
+}
+
+/// Mirror git's settings into the jj repository config; blocks
+pub fn sync(repo: &Path) -> Result<(), Error> {
+    for (key, value) in settings(&read_git(repo)?) {
+        jj(repo, &["config", "set", "--repo", &key, &value]).map_err(Error::from_cli)?;
+    }
+    Ok(())
+}
+
+/// The email jj commits with in the repository; None when unset. Blocks
+pub fn email(repo: &Path) -> Result<Option<String>, Error> {
+    match jj(repo, &["config", "get", "user.email"]) {
+        Ok(out) => Ok(Some(out.trim().to_string()).filter(|e| !e.is_empty())),
+        // Exit status 1: not set
+        Err(CliError::NonZeroExit { code: Some(1), .. }) => Ok(None),
+        Err(e) => Err(Error::from_cli(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_from_git() {
+        let git = GitSettings {
+            name: "Me".to_string(),
+            email: "me@acme.dev".to_string(),
+            signs: true,
+            format: "ssh".to_string(),
+            key: "/keys/id_work.pub".to_string(),
+        };
+        let pairs = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            settings(&git),
+            pairs(&[
+                ("user.name", "Me"),
+                ("user.email", "me@acme.dev"),
+                ("signing.behavior", "own"),
+                ("signing.backend", "ssh"),
+                ("signing.key", "/keys/id_work.pub"),
+            ])
+        );
+        let gitsign = GitSettings {
+            format: "x509".to_string(),
+            ..git.clone()
+        };
+        assert_eq!(
+            settings(&gitsign)[2],
+            pairs(&[("signing.behavior", "drop")])[0]
+        );
+        let unsigned = GitSettings {
+            signs: false,
+            ..git
+        };
+        assert_eq!(settings(&unsigned).len(), 3);
+    }
+}
@@ -0,0 +1,275 @@
+//! Key store master credential in the system keyring
+//!
+//! Optionally keeps the KDBX master password in the login keyring — the
+//! Secret Service (GNOME Keyring, KWallet) through `secret-tool`, or the
+//! macOS keychain through `security` — so the key store can be unlocked
+//! after login without a prompt. The keyring is unlocked together with the
+//! user session, which is what makes this safe to opt into.
+//!
+//! Unlocking hands the password to the CLI for the rest of the GUI session
+//! through `REMOTE_JUGGLER_KDBX_PASSWORD`, which the CLI prefers over the
+//! HSM-sealed copy.
+
+use std::time::Duration;
+
+use crate::cli::{self, RunOptions};
+use crate::cli_cache;
+use crate::databases;
+use crate::error::{CliError, Error};
+use crate::event_hooks::{self, Event};
+
+/// Service attribute the credential is stored under
+pub const SERVICE: &str = "remote-juggler";
+
+/// Account attribute, matching the CLI's HSM slot for the master password
+pub const ACCOUNT: &str = "kdbx-master";
+
+/// Environment variable the CLI reads the master password from
+pub const MASTER_PASSWORD_ENV: &str = "REMOTE_JUGGLER_KDBX_PASSWORD";
+
+const LABEL: &str = "RemoteJuggler key store master password";
+
+// Unlocking the keyring itself may show a system prompt
+const KEYRING_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Which keyring implementation is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keyring {
+    /// freedesktop Secret Service via libsecret's secret-tool
+    SecretService,
+    /// macOS keychain via security(1)
+    MacKeychain,
+}
+
+impl Keyring {
+    /// The keyring for this platform
+    pub fn native() -> Self {
+        if cfg!(target_os = "macos") {
+            Keyring::MacKeychain
+        } else {
+            Keyring::SecretService
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Keyring::SecretService => "system keyring",
+            Keyring::MacKeychain => "macOS keychain",
+        }
+    }
+
+    fn program(&self) -> String {
+        match self {
+            Keyring::SecretService => std::env::var("REMOTE_JUGGLER_SECRET_TOOL_PATH")
+                .unwrap_or_else(|_| "secret-tool".to_string()),
+            Keyring::MacKeychain => std::env::var("REMOTE_JUGGLER_SECURITY_PATH")
+                .unwrap_or_else(|_| "security".to_string()),
+        }
+    }
+
+    /// Arguments to look the credential up, printing it to stdout
+    pub fn lookup_args(&self) -> Vec<String> {
+        self.item_lookup_args(SERVICE, ACCOUNT)
+    }
+
+    /// Arguments to delete the credential
+    pub fn clear_args(&self) -> Vec<String> {
+        self.item_clear_args(SERVICE, ACCOUNT)
+    }
+
+    /// Arguments to look up any item by its service and account
+    pub fn item_lookup_args(&self, service: &str, account: &str) -> Vec<String> {
+        match self {
+            Keyring::SecretService => {
+                cli::args(&["lookup", "service", service, "account", account])
+            }
+            Keyring::MacKeychain => {
+                cli::args(&["find-generic-password", "-s", service, "-a", account, "-w"])
+            }
+        }
+    }
+
+    /// Arguments to delete any item by its service and account
+    pub fn item_clear_args(&self, service: &str, account: &str) -> Vec<String> {
+        match self {
+            Keyring::SecretService => cli::args(&["clear", "service", service, "account", account]),
+            Keyring::MacKeychain => {
+                cli::args(&["delete-generic-password", "-s", service, "-a", account])
+            }
+        }
+    }
+
+    /// Returns true if the keyring tool is installed
+    pub fn is_available(&self) -> bool {
+        cli::find_program(&self.program()).is_some()
+    }
+
+    fn run(&self, args: &[String], input: Option<&str>) -> Result<String, CliError> {
+        let options = RunOptions {
+            timeout: KEYRING_TIMEOUT,
+            retries: 0,
+            cancel: None,
+        };
+        match input {
+            Some(input) => cli::run_program_with_input(&self.program(), args, &[], input, &options),
+            None => cli::run_program(&self.program(), args, &options),
+        }
+    }
+
+    /// Save the master password, replacing any stored one
+    pub fn store(&self, secret: &str) -> Result<(), Error> {
+        self.store_item(SERVICE, ACCOUNT, LABEL, secret)
+    }
+
+    /// Save any item, replacing one with the same service and account
+    pub fn store_item(
+        &self,
+        service: &str,
+        account: &str,
+        label: &str,
+        secret: &str,
+    ) -> Result<(), Error> {
+        let result = match self {
+            // secret-tool reads the secret from stdin
+            Keyring::SecretService => {
+                let args = cli::args(&[
+                    "store", "--label", label, "service", service, "account", account,
+                ]);
+                self.run(&args, Some(secret))
+            }
+            // security only takes the password as an argument (or a tty
+            // prompt); it is visible in the process list while it runs
+            Keyring::MacKeychain => {
+                let args = cli::args(&[
+                    "add-generic-password",
+                    "-U",
+                    "-s",
+                    service,
+                    "-a",
+                    account,
+                    "-l",
+                    label,
+                    "-w",
+                    secret,
+                ]);
+                self.run(&args, None)
+            }
+        };
+        result.map(|_| ()).map_err(Error::from_cli)
+    }
+
+    /// The stored master password, or None if nothing is stored
+    pub fn lookup(&self) -> Result<Option<String>, Error> {
+        self.lookup_item(SERVICE, ACCOUNT)
+    }
+
+    /// Any stored item, or None if there is none
+    pub fn lookup_item(&self, service: &str, account: &str) -> Result<Option<String>, Error> {
+        match self.run(&self.item_lookup_args(service, account), None) {
+            Ok(stdout) => {
+                let secret = stdout.trim_end_matches(['\r', '\n']);
+                Ok((!secret.is_empty()).then(|| secret.to_string()))
+            }
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(Error::from_cli(e)),
+        }
+    }
+
+    /// Remove the stored master password; succeeds if none was stored
+    pub fn forget(&self) -> Result<(), Error> {
+        self.forget_item(SERVICE, ACCOUNT)
+    }
+
+    /// Remove any item; succeeds if there was none
+    pub fn forget_item(&self, service: &str, account: &str) -> Result<(), Error> {
+        match self.run(&self.item_clear_args(service, account), None) {
+            Ok(_) => Ok(()),
+            Err(e) if is_not_found(&e) => Ok(()),
+            Err(e) => Err(Error::from_cli(e)),
+        }
+    }
+
+    /// Returns true if a master password is stored
+    pub fn has_stored(&self) -> bool {
+        matches!(self.lookup(), Ok(Some(_)))
+    }
+}
+
+/// Returns true if a keyring tool failed only because nothing is stored
+///
+/// secret-tool exits 1 silently; security exits 44 with "could not be found".
+pub fn is_not_found(err: &CliError) -> bool {
+    match err {
+        CliError::NonZeroExit { code, stderr } => {
+            stderr.trim().is_empty()
+                || *code == Some(44)
+                || stderr.to_lowercase().contains("could not be found")
+        }
+        _ => false,
+    }
+}
+
+/// Unlock the key store for this session with the stored master password
+///
+/// Returns false if nothing is stored.
+pub fn unlock_with_stored(keyring: Keyring) -> Result<bool, Error> {
+    let Some(secret) = keyring.lookup()? else {
+        return Ok(false);
+    };
+    cli::set_session_env(MASTER_PASSWORD_ENV, Some(&secret));
+    cli_cache::global().invalidate();
+    event_hooks::emit(Event::KeystoreUnlocked {
+        database: databases::DEFAULT_NAME.to_string(),
+    });
+    Ok(true)
+}
+
+/// Forget the stored master password and stop using it this session
+pub fn forget(keyring: Keyring) -> Result<(), Error> {
+    cli::set_session_env(MASTER_PASSWORD_ENV, None);
+    cli_cache::global().invalidate();
+    keyring.forget()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit(code: i32, stderr: &str) -> CliError {
+        CliError::NonZeroExit {
+            code: Some(code),
+            stderr: stderr.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_is_not_found() {
+        assert!(is_not_found(&exit(1, "")));
+        assert!(is_not_found(&exit(
+            44,
+            "security: SecKeychainSearchCopyNext: The specified item could not be found in the keychain."
+        )));
+        assert!(!is_not_found(&exit(
+            1,
+            "Cannot autolaunch D-Bus without X11"
+        )));
+        assert!(!is_not_found(&CliError::NotFound));
+    }
+
+    #[test]
+    fn test_args_use_shared_attributes() {
+        for keyring in [Keyring::SecretService, Keyring::MacKeychain] {
+            for args in [keyring.lookup_args(), keyring.clear_args()] {
+                assert!(args.contains(&SERVICE.to_string()));
+                assert!(args.contains(&ACCOUNT.to_string()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_missing_tool_is_unavailable() {
+        std::env::set_var("REMOTE_JUGGLER_SECURITY_PATH", "/nonexistent/security");
+        assert!(!Keyring::MacKeychain.is_available());
+        assert!(Keyring::MacKeychain.lookup().is_err());
+    }
+}
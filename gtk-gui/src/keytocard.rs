@@ -0,0 +1,315 @@
+//! Moving a GPG key onto a YubiKey
+//!
+//! `keytocard` in `gpg --edit-key` copies a key's secret material into one
+//! of the card's OpenPGP slots, and saving replaces the keyring copy with
+//! a stub that points at the card. That can't be undone without a backup,
+//! so the flow exports one first, moves each subkey to the slot for what
+//! it does, checks that the keyring is left with stubs only, and then
+//! records the card in the identity. gpg-agent's pinentry asks for the
+//! passphrase and the card's Admin PIN.
+
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cli::{self, RunOptions};
+use crate::config::{Identity, SecurityMode};
+use crate::error::Error;
+use crate::export;
+use crate::gpg::{self, Card, SecretKey};
+use crate::gpg_publish;
+use crate::pin_storage;
+
+/// An OpenPGP card slot, numbered as gpg's keytocard prompt does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    Signature = 1,
+    Encryption = 2,
+    Authentication = 3,
+}
+
+impl Slot {
+    pub const ALL: [Slot; 3] = [Self::Signature, Self::Encryption, Self::Authentication];
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::Signature => "Signature",
+            Self::Encryption => "Encryption",
+            Self::Authentication => "Authentication",
+        }
+    }
+}
+
+/// One key to move
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub key_id: String,
+    /// Position among the subkeys in `--edit-key`, from 1; None for the
+    /// primary key
+    pub subkey: Option<usize>,
+    pub slot: Slot,
+    /// The slot already holds a different key, which is overwritten
+    pub replaces: bool,
+}
+
+impl Move {
+    pub fn display_text(&self) -> String {
+        let what = if self.subkey.is_some() {
+            "subkey"
+        } else {
+            "primary key"
+        };
+        let replaces = if self.replaces {
+            ", replacing the key there"
+        } else {
+            ""
+        };
+        format!(
+            "{} {} to the {} slot{}",
+            what,
+            self.key_id,
+            self.slot.display_name(),
+            replaces
+        )
+    }
+}
+
+/// Whether the secret material is in the keyring, not on a card or
+/// missing
+fn in_keyring(token: &str) -> bool {
+    token == "+"
+}
+
+/// Which keys go where: the newest usable subkey for each slot, and the
+/// primary key for signatures only if no subkey can sign. Keys already on
+/// a card are left out; the primary key stays in the keyring otherwise,
+/// so it can still certify.
+pub fn plan(key: &SecretKey, card: &Card) -> Vec<Move> {
+    let mut moves = Vec::new();
+    for slot in Slot::ALL {
+        let chosen = key
+            .subkeys
+            .iter()
+            .enumerate()
+            .filter(|(_, k)| !k.revoked)
+            .rfind(|(_, k)| match slot {
+                Slot::Signature => k.can_sign,
+                Slot::Encryption => k.can_encrypt,
+                Slot::Authentication => k.can_authenticate,
+            })
+            .map(|(i, k)| (Some(i + 1), k.key_id.clone(), &k.fingerprint, &k.token))
+            .or_else(|| {
+                (slot == Slot::Signature && key.can_sign)
+                    .then(|| (None, key.key_id.clone(), &key.fingerprint, &key.token))
+            });
+        let Some((subkey, key_id, fingerprint, token)) = chosen else {
+            continue;
+        };
+        if !in_keyring(token) {
+            continue;
+        }
+        let current = &card.slots[slot as usize - 1];
+        moves.push(Move {
+            key_id,
+            subkey,
+            slot,
+            replaces: current.as_ref().is_some_and(|f| f != fingerprint),
+        });
+    }
+    moves
+}
+
+/// The `--command-fd` lines that move `moves` and save
+pub fn script(moves: &[Move]) -> String {
+    let mut lines = Vec::new();
+    for m in moves {
+        if let Some(index) = m.subkey {
+            lines.push(format!("key {}", index));
+        }
+        lines.push("keytocard".to_string());
+        if m.subkey.is_none() {
+            // keyedit.keytocard.use_primary
+            lines.push("y".to_string());
+        }
+        // cardedit.genkeys.storekeytype
+        lines.push((m.slot as u8).to_string());
+        if m.replaces {
+            // cardedit.genkeys.replace_key
+            lines.push("y".to_string());
+        }
+        if let Some(index) = m.subkey {
+            lines.push(format!("key {}", index));
+        }
+    }
+    lines.push("save".to_string());
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+fn options(timeout: Duration) -> RunOptions {
+    RunOptions {
+        timeout,
+        retries: 0,
+        cancel: None,
+    }
+}
+
+/// The key by fingerprint; blocks
+fn find_key(fingerprint: &str) -> Result<SecretKey, Error> {
+    gpg::list_secret_keys()?
+        .into_iter()
+        .find(|key| key.fingerprint == fingerprint)
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "gpg",
+            message: format!("No secret key {} in the keyring", fingerprint),
+        })
+}
+
+/// What moving an identity's key would do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub key: SecretKey,
+    pub card: Card,
+    pub moves: Vec<Move>,
+}
+
+/// The identity's key, the card in the reader and what would move there;
+/// blocks
+pub fn prepare(name: &str, identity: &Identity) -> Result<Plan, Error> {
+    let key = gpg_publish::signing_key(name, identity)?;
+    let card = gpg::card_status().ok_or_else(|| Error::InvalidOutput {
+        program: "gpg",
+        message: "No OpenPGP card in the reader".to_string(),
+    })?;
+    let moves = plan(&key, &card);
+    Ok(Plan { key, card, moves })
+}
+
+/// Export the secret key and subkeys to `path`, readable only by the
+/// owner, and check the file holds them; blocks
+pub fn export_backup(fingerprint: &str, path: &Path) -> Result<(), Error> {
+    let args = cli::args(&["--armor", "--export-secret-keys", fingerprint]);
+    // Long enough to type the passphrase
+    let armored = cli::run_program("gpg", &args, &options(Duration::from_secs(300)))
+        .map_err(Error::from_cli)?;
+    if !gpg::armored_fingerprints(&armored)?
+        .iter()
+        .any(|f| f == fingerprint)
+    {
+        return Err(Error::InvalidOutput {
+            program: "gpg",
+            message: format!("The export doesn't contain {}", fingerprint),
+        });
+    }
+    export::write_private(path, &armored)?;
+    Ok(())
+}
+
+/// Move the keys to the card and save; blocks
+pub fn move_to_card(fingerprint: &str, moves: &[Move]) -> Result<(), Error> {
+    let args = cli::args(&[
+        "--batch",
+        "--command-fd",
+        "0",
+        "--status-fd",
+        "1",
+        "--edit-key",
+        fingerprint,
+    ]);
+    // Passphrase and Admin PIN for every key
+    cli::run_program_with_input(
+        "gpg",
+        &args,
+        &[],
+        &script(moves),
+        &options(Duration::from_secs(600)),
+    )
+    .map_err(Error::from_cli)?;
+    Ok(())
+}
+
+/// The moved keys still in the keyring instead of stubs for the card;
+/// blocks
+pub fn verify_stubs(fingerprint: &str, moves: &[Move], card: &Card) -> Result<Vec<String>, Error> {
+    let key = find_key(fingerprint)?;
+    Ok(moves
+        .iter()
+        .filter(|m| {
+            let token = match m.subkey {
+                Some(index) => key.subkeys.get(index - 1).map(|k| k.token.as_str()),
+                None => Some(key.token.as_str()),
+            };
+            // gpg shows the card's application id, which has the serial
+            !token.is_some_and(|t| !t.is_empty() && t.contains(&card.serial))
+        })
+        .map(|m| m.key_id.clone())
+        .collect())
+}
+
+/// Record that the identity's key is on a card, handled in `mode`; in
+/// Trusted Workstation mode, detecting where to keep the PIN may block
+pub fn update_identity(identity: &mut Identity, mode: SecurityMode) {
+    identity.gpg.hardware_key = true;
+    identity.gpg.pin_storage_method =
+        (mode == SecurityMode::TrustedWorkstation).then(pin_storage::detect);
+    identity.gpg.security_mode = mode;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::identity;
+    use crate::config::GpgConfig;
+
+    #[test]
+    fn test_plan_and_script() {
+        let key = &gpg::parse_colons(
+            "sec:u:255:22:AAAA1111BBBB2222:1700000000:::u:::cC:::+:::ed25519:::0:\n\
+             fpr:::::::::0123456789ABCDEF0123AAAA1111BBBB2222:\n\
+             ssb:u:255:22:CCCC3333DDDD4444:1700000000::::::s:::+:::ed25519::\n\
+             fpr:::::::::FEDCBA9876543210FEDCCCCC3333DDDD4444:\n\
+             ssb:u:255:18:9999888877776666:1700000000::::::e:::+:::cv25519::\n\
+             fpr:::::::::FEDCBA9876543210FEDC9999888877776666:\n\
+             ssb:u:255:22:EEEE5555FFFF6666:1700000000::::::a:::D2760001240103040006123456780000:::ed25519::\n\
+             fpr:::::::::FEDCBA9876543210FEDCEEEE5555FFFF6666:\n",
+        )[0];
+        let card = Card {
+            serial: "12345678".to_string(),
+            vendor: "Yubico".to_string(),
+            slots: [Some("0000111122223333".to_string()), None, None],
+            pin_retries: None,
+        };
+        let moves = plan(key, &card);
+        // The authentication subkey is on the card already
+        assert_eq!(moves.len(), 2);
+        assert_eq!(
+            moves[0].display_text(),
+            "subkey CCCC3333DDDD4444 to the Signature slot, replacing the key there"
+        );
+        assert_eq!(moves[1].subkey, Some(2));
+        assert_eq!(
+            script(&moves),
+            "key 1\nkeytocard\n1\ny\nkey 1\nkey 2\nkeytocard\n2\nkey 2\nsave\n"
+        );
+        assert_eq!(
+            script(&[Move {
+                key_id: "AAAA1111BBBB2222".to_string(),
+                subkey: None,
+                slot: Slot::Signature,
+                replaces: false,
+            }]),
+            "keytocard\ny\n1\nsave\n"
+        );
+
+        let mut identity = Identity {
+            gpg: GpgConfig {
+                key_id: "AAAA1111BBBB2222".to_string(),
+                ..Default::default()
+            },
+            ..identity("github", "github-work", "github.com", "me", "me@acme.dev")
+        };
+        update_identity(&mut identity, SecurityMode::TrustedWorkstation);
+        assert!(identity.gpg.hardware_key);
+        assert!(identity.gpg.pin_storage_method.is_some());
+        update_identity(&mut identity, SecurityMode::MaximumSecurity);
+        assert_eq!(identity.gpg.pin_storage_method, None);
+    }
+}
@@ -0,0 +1,498 @@
+//! known_hosts management and host key pinning
+//!
+//! Host keys for the identities' hosts are fetched with ssh-keyscan and
+//! compared with ~/.ssh/known_hosts. For hosted providers the fingerprints
+//! they publish are pinned here, so a matching key can be trusted without
+//! asking and a mismatch is flagged rather than written. Any other new or
+//! changed key needs the user to confirm it first, instead of ssh failing
+//! with "Host key verification failed" in the background.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::cli::{self, RunOptions};
+use crate::config::Config;
+use crate::error::Error;
+use crate::export;
+use crate::platform;
+
+/// A provider's published host key fingerprint
+#[derive(Debug, Clone, Copy)]
+struct Pin {
+    host: &'static str,
+    key_type: &'static str,
+    fingerprint: &'static str,
+}
+
+/// Fingerprints from the providers' documentation
+const PINNED: &[Pin] = &[
+    Pin {
+        host: "github.com",
+        key_type: "ssh-ed25519",
+        fingerprint: "SHA256:+DiY3wvvV6TuJJhbpZisF/zLDA0zPMSvHdkr4UvCOqU",
+    },
+    Pin {
+        host: "github.com",
+        key_type: "ecdsa-sha2-nistp256",
+        fingerprint: "SHA256:p2QAMXNIC1TJYWeIOttrVc98/R1BUFWu3/LiyKgUfQM",
+    },
+    Pin {
+        host: "github.com",
+        key_type: "ssh-rsa",
+        fingerprint: "SHA256:uNiVztksCsDhcc0u9e8BujQXVUpKZIDTMczCvj3tD2s",
+    },
+    Pin {
+        host: "gitlab.com",
+        key_type: "ssh-ed25519",
+        fingerprint: "SHA256:eUXGGm1YGsMAS7vkcx6JOJdOGHPem5gQp4taiCfCLB8",
+    },
+    Pin {
+        host: "gitlab.com",
+        key_type: "ecdsa-sha2-nistp256",
+        fingerprint: "SHA256:HbW3g8zUjNSksFbqTiUWPWg2Bq1x8xdGUrliXFzSnUw",
+    },
+    Pin {
+        host: "gitlab.com",
+        key_type: "ssh-rsa",
+        fingerprint: "SHA256:ROQFvPThGrW4RuWLoL9tq9I9zJ42fK4XywyRtbOz/EQ",
+    },
+    Pin {
+        host: "bitbucket.org",
+        key_type: "ssh-ed25519",
+        fingerprint: "SHA256:ybgmFkzwOSotHTHLJgHO0QN8L0xErw6vd0VhFA9m3SM",
+    },
+    Pin {
+        host: "bitbucket.org",
+        key_type: "ecdsa-sha2-nistp256",
+        fingerprint: "SHA256:FC73VB6C4OQLSCrjEayhMp9UMxS97caD/Yyi2bhW/J0",
+    },
+    Pin {
+        host: "bitbucket.org",
+        key_type: "ssh-rsa",
+        fingerprint: "SHA256:46OSHA1Rmj8E8ERTC6xkNcmGOw9oFxYr0WF6zWW8l1E",
+    },
+    Pin {
+        host: "codeberg.org",
+        key_type: "ssh-ed25519",
+        fingerprint: "SHA256:6QQmYi4ppFS4/+zSZ5S4IU+4sa6rwvQ4PbhCtPEBekQ",
+    },
+    Pin {
+        host: "codeberg.org",
+        key_type: "ecdsa-sha2-nistp256",
+        fingerprint: "SHA256:T9FYDEHELhVkulEKKwge5aVhVTbqCW0MIRwAfpARs/E",
+    },
+    Pin {
+        host: "codeberg.org",
+        key_type: "ssh-rsa",
+        fingerprint: "SHA256:mIlxA9k46MmM6qdJOdMnAQpzGxF4WIVVL+fj+wZbw0g",
+    },
+];
+
+/// The published fingerprint for a host's key type, if it is pinned
+pub fn pin_for(host: &str, key_type: &str) -> Option<&'static str> {
+    PINNED
+        .iter()
+        .find(|p| p.host == host && p.key_type == key_type)
+        .map(|p| p.fingerprint)
+}
+
+pub fn ssh_keyscan_program() -> String {
+    std::env::var("REMOTE_JUGGLER_SSH_KEYSCAN_PATH").unwrap_or_else(|_| "ssh-keyscan".to_string())
+}
+
+pub fn path() -> Option<PathBuf> {
+    platform::ssh_dir().map(|dir| dir.join("known_hosts"))
+}
+
+/// e.g. "ED25519" for `ssh-ed25519`
+pub fn key_type_name(key_type: &str) -> &str {
+    match key_type {
+        "ssh-ed25519" => "ED25519",
+        "ssh-rsa" => "RSA",
+        t if t.starts_with("ecdsa-sha2-") => "ECDSA",
+        t if t.starts_with("sk-ssh-ed25519") => "ED25519-SK",
+        t if t.starts_with("sk-ecdsa-") => "ECDSA-SK",
+        t => t,
+    }
+}
+
+/// A host's public key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostKey {
+    pub host: String,
+    /// e.g. `ssh-ed25519`
+    pub key_type: String,
+    /// Base64 key blob
+    pub key: String,
+}
+
+impl HostKey {
+    /// `SHA256:...` fingerprint, as ssh prints it
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.key)
+    }
+
+    /// The known_hosts line for the key
+    pub fn line(&self) -> String {
+        format!("{} {} {}", self.host, self.key_type, self.key)
+    }
+}
+
+/// `SHA256:...` fingerprint of a base64 key blob
+pub fn fingerprint(key: &str) -> String {
+    let blob = base64::engine::general_purpose::STANDARD
+        .decode(key)
+        .unwrap_or_else(|_| key.as_bytes().to_vec());
+    fingerprint_blob(&blob)
+}
+
+/// `SHA256:...` fingerprint of a decoded key blob
+pub fn fingerprint_blob(blob: &[u8]) -> String {
+    format!(
+        "SHA256:{}",
+        base64::engine::general_purpose::STANDARD_NO_PAD.encode(Sha256::digest(blob))
+    )
+}
+
+/// Parse `ssh-keyscan` output; its comment lines are skipped
+pub fn parse_keyscan(output: &str) -> Vec<HostKey> {
+    output
+        .lines()
+        .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some(HostKey {
+                host: fields.next()?.to_string(),
+                key_type: fields.next()?.to_string(),
+                key: fields.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Returns true if a known_hosts host field names `host` on the default port
+///
+/// Handles comma-separated names and hashed entries (`|1|salt|hash`);
+/// wildcard patterns aren't expanded.
+pub fn matches_host(field: &str, host: &str) -> bool {
+    if let Some(hashed) = field.strip_prefix("|1|") {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let Some((salt, hash)) = hashed.split_once('|') else {
+            return false;
+        };
+        let (Ok(salt), Ok(hash)) = (engine.decode(salt), engine.decode(hash)) else {
+            return false;
+        };
+        let Ok(mut mac) = Hmac::<sha1::Sha1>::new_from_slice(&salt) else {
+            return false;
+        };
+        mac.update(host.as_bytes());
+        return mac.verify_slice(&hash).is_ok();
+    }
+    field.split(',').any(|name| name == host)
+}
+
+/// Keys known_hosts trusts for `host`, with the line each is on
+fn known_keys<'a>(known_hosts: &'a str, host: &str) -> Vec<(usize, &'a str, &'a str)> {
+    known_hosts
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line = line.trim();
+            // @cert-authority and @revoked lines aren't plain trusted keys
+            if line.is_empty() || line.starts_with('#') || line.starts_with('@') {
+                return None;
+            }
+            let mut fields = line.split_whitespace();
+            let hosts = fields.next()?;
+            let key_type = fields.next()?;
+            let key = fields.next()?;
+            matches_host(hosts, host).then_some((i, key_type, key))
+        })
+        .collect()
+}
+
+/// How a scanned key compares with the pins and known_hosts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// known_hosts already trusts this key
+    Trusted,
+    /// Matches the provider's published key; safe to add, replacing the
+    /// fingerprint in `replaces` if known_hosts holds an old key
+    Pinned { replaces: Option<String> },
+    /// Doesn't match the provider's published key; never written
+    PinMismatch { expected: String },
+    /// Not seen before and not pinned; the user must check it
+    Unverified,
+    /// Differs from the key known_hosts trusts, fingerprint `previous`
+    Changed { previous: String },
+}
+
+impl Status {
+    /// Returns true if the user has to look at this key
+    pub fn needs_attention(&self) -> bool {
+        matches!(
+            self,
+            Self::PinMismatch { .. } | Self::Unverified | Self::Changed { .. }
+        )
+    }
+}
+
+/// Compare a scanned key with its pin and with known_hosts
+pub fn assess(known_hosts: &str, scanned: &HostKey) -> Status {
+    let fingerprint = scanned.fingerprint();
+    let known = known_keys(known_hosts, &scanned.host);
+    if known
+        .iter()
+        .any(|(_, t, k)| *t == scanned.key_type && *k == scanned.key)
+    {
+        return match pin_for(&scanned.host, &scanned.key_type) {
+            Some(pin) if pin != fingerprint => Status::PinMismatch {
+                expected: pin.to_string(),
+            },
+            _ => Status::Trusted,
+        };
+    }
+    let previous = known
+        .iter()
+        .find(|(_, t, _)| *t == scanned.key_type)
+        .map(|(_, _, k)| self::fingerprint(k));
+    match (pin_for(&scanned.host, &scanned.key_type), previous) {
+        (Some(pin), _) if pin != fingerprint => Status::PinMismatch {
+            expected: pin.to_string(),
+        },
+        (Some(_), replaces) => Status::Pinned { replaces },
+        (None, Some(previous)) => Status::Changed { previous },
+        (None, None) => Status::Unverified,
+    }
+}
+
+/// `known_hosts` with `keys` trusted, replacing other keys of the same type
+/// for the same hosts
+pub fn with_trusted(known_hosts: &str, keys: &[HostKey]) -> String {
+    let stale: Vec<usize> = keys
+        .iter()
+        .flat_map(|key| {
+            known_keys(known_hosts, &key.host)
+                .into_iter()
+                .filter(|(_, t, _)| *t == key.key_type)
+                .map(|(i, _, _)| i)
+        })
+        .collect();
+    let mut out: String = known_hosts
+        .lines()
+        .enumerate()
+        .filter(|(i, _)| !stale.contains(i))
+        .map(|(_, line)| format!("{}\n", line))
+        .collect();
+    for key in keys {
+        out.push_str(&key.line());
+        out.push('\n');
+    }
+    out
+}
+
+fn read_known_hosts() -> std::io::Result<String> {
+    let path = path().ok_or_else(|| std::io::Error::other("No home directory"))?;
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Add `keys` to ~/.ssh/known_hosts, replacing stale keys for their hosts
+pub fn trust(keys: &[HostKey]) -> std::io::Result<()> {
+    if keys.is_empty() {
+        return Ok(());
+    }
+    let path = path().ok_or_else(|| std::io::Error::other("No home directory"))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let updated = with_trusted(&read_known_hosts()?, keys);
+    let tmp = path.with_extension("tmp");
+    export::write_private(&tmp, &updated)?;
+    std::fs::rename(&tmp, &path)
+}
+
+/// Fetch a host's keys; blocks
+pub fn keyscan(host: &str) -> Result<Vec<HostKey>, Error> {
+    let options = RunOptions {
+        timeout: Duration::from_secs(15),
+        retries: 0,
+        cancel: None,
+    };
+    let args = cli::args(&["-T", "5", host]);
+    let out = cli::run_program(&ssh_keyscan_program(), &args, &options).map_err(Error::from_cli)?;
+    let keys = parse_keyscan(&out);
+    if keys.is_empty() {
+        return Err(Error::Network(format!("No host keys from {}", host)));
+    }
+    Ok(keys)
+}
+
+/// SSH hosts the identities connect to, deduplicated
+pub fn hosts(config: &Config) -> Vec<String> {
+    let mut hosts: Vec<String> = config
+        .identities
+        .values()
+        .map(|identity| identity.hostname.clone())
+        .filter(|host| !host.is_empty())
+        .collect();
+    hosts.sort();
+    hosts.dedup();
+    hosts
+}
+
+/// A scanned host key and how it compares
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assessed {
+    pub key: HostKey,
+    pub status: Status,
+}
+
+/// The outcome of checking one host
+#[derive(Debug)]
+pub struct HostReport {
+    pub host: String,
+    pub keys: Result<Vec<Assessed>, Error>,
+}
+
+/// Scan `hosts`, adding keys that match their pins; blocks
+///
+/// A host that can't be reached gets its error in its report rather than
+/// failing the whole check.
+pub fn check(hosts: &[String]) -> Result<Vec<HostReport>, Error> {
+    let known_hosts = read_known_hosts()?;
+    let reports: Vec<HostReport> = hosts
+        .iter()
+        .map(|host| HostReport {
+            host: host.clone(),
+            keys: keyscan(host).map(|keys| {
+                keys.into_iter()
+                    .map(|key| Assessed {
+                        status: assess(&known_hosts, &key),
+                        key,
+                    })
+                    .collect()
+            }),
+        })
+        .collect();
+    let pinned: Vec<HostKey> = reports
+        .iter()
+        .filter_map(|r| r.keys.as_ref().ok())
+        .flatten()
+        .filter(|a| matches!(a.status, Status::Pinned { .. }))
+        .map(|a| a.key.clone())
+        .collect();
+    trust(&pinned)?;
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// GitHub's published ed25519 host key
+    const GITHUB_ED25519: &str =
+        "AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl";
+    const OTHER_ED25519: &str =
+        "AAAAC3NzaC1lZDI1NTE5AAAAIAfuCHKVTjquxvt6CM6tdG4SLp1Btn/nOeHHE5UOzRdf";
+
+    fn key(host: &str, blob: &str) -> HostKey {
+        HostKey {
+            host: host.to_string(),
+            key_type: "ssh-ed25519".to_string(),
+            key: blob.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_fingerprint_matches_pin() {
+        assert_eq!(
+            key("github.com", GITHUB_ED25519).fingerprint(),
+            pin_for("github.com", "ssh-ed25519").unwrap()
+        );
+        assert_eq!(pin_for("git.example.com", "ssh-ed25519"), None);
+    }
+
+    #[test]
+    fn test_parse_keyscan() {
+        let out = format!(
+            "# github.com:22 SSH-2.0-babeld\ngithub.com ssh-ed25519 {}\n\n",
+            GITHUB_ED25519
+        );
+        assert_eq!(parse_keyscan(&out), vec![key("github.com", GITHUB_ED25519)]);
+    }
+
+    #[test]
+    fn test_matches_hashed_host() {
+        // ssh-keygen -H output for github.com
+        let engine = base64::engine::general_purpose::STANDARD;
+        let salt = b"0123456789abcdefghij";
+        let mut mac = Hmac::<sha1::Sha1>::new_from_slice(salt).unwrap();
+        mac.update(b"github.com");
+        let field = format!(
+            "|1|{}|{}",
+            engine.encode(salt),
+            engine.encode(mac.finalize().into_bytes())
+        );
+        assert!(matches_host(&field, "github.com"));
+        assert!(!matches_host(&field, "gitlab.com"));
+        assert!(matches_host("git.example.com,10.0.0.1", "10.0.0.1"));
+        assert!(!matches_host("[git.example.com]:2222", "git.example.com"));
+    }
+
+    #[test]
+    fn test_assess() {
+        let github = key("github.com", GITHUB_ED25519);
+        assert_eq!(assess("", &github), Status::Pinned { replaces: None });
+        let current = format!("github.com ssh-ed25519 {}\n", GITHUB_ED25519);
+        assert_eq!(assess(&current, &github), Status::Trusted);
+
+        // A stale entry is replaced when the new key matches the pin
+        let stale = format!("github.com ssh-ed25519 {}\n", OTHER_ED25519);
+        assert_eq!(
+            assess(&stale, &github),
+            Status::Pinned {
+                replaces: Some(fingerprint(OTHER_ED25519))
+            }
+        );
+
+        // A key that doesn't match the pin is never trusted
+        let spoofed = key("github.com", OTHER_ED25519);
+        assert!(matches!(assess("", &spoofed), Status::PinMismatch { .. }));
+
+        let custom = key("git.example.com", OTHER_ED25519);
+        assert_eq!(assess("", &custom), Status::Unverified);
+        let changed = format!("git.example.com ssh-ed25519 {}\n", GITHUB_ED25519);
+        assert_eq!(
+            assess(&changed, &custom),
+            Status::Changed {
+                previous: fingerprint(GITHUB_ED25519)
+            }
+        );
+        assert!(assess(&changed, &custom).needs_attention());
+    }
+
+    #[test]
+    fn test_with_trusted_replaces_stale_keys() {
+        let existing = format!(
+            "# comment\ngit.example.com ssh-ed25519 {}\ngit.example.com ssh-rsa AAAArsa\n",
+            GITHUB_ED25519
+        );
+        let updated = with_trusted(&existing, &[key("git.example.com", OTHER_ED25519)]);
+        assert_eq!(
+            updated,
+            format!(
+                "# comment\ngit.example.com ssh-rsa AAAArsa\ngit.example.com ssh-ed25519 {}\n",
+                OTHER_ED25519
+            )
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! Leased secrets that expire automatically
+//!
+//! A secret can be stored with a lease, e.g. a temporary cloud token that is
+//! only valid for an hour anyway. Leases are kept in the local data
+//! directory like expiry metadata. When one runs out, its renewal hook (a
+//! shell command printing a fresh value) is tried first; without a hook, or
+//! if it fails, the entry is deleted or its value masked.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{self, RunOptions};
+use crate::cli_cache;
+use crate::error::Error;
+use crate::expiry;
+use crate::platform;
+
+/// Value left in an entry whose lease ran out with `LeaseAction::Mask`
+pub const MASKED_VALUE: &str = "[lease expired]";
+
+const HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What happens to an entry when its lease runs out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LeaseAction {
+    #[default]
+    Delete,
+    /// Keep the entry but replace its value with `MASKED_VALUE`
+    Mask,
+}
+
+impl LeaseAction {
+    pub fn all() -> &'static [LeaseAction] {
+        &[LeaseAction::Delete, LeaseAction::Mask]
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            LeaseAction::Delete => "Delete entry",
+            LeaseAction::Mask => "Mask value",
+        }
+    }
+
+    pub fn from_index(index: u32) -> Self {
+        Self::all().get(index as usize).copied().unwrap_or_default()
+    }
+}
+
+/// A lease on one key store entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lease {
+    /// Unix seconds
+    pub expires_at: u64,
+    /// Length of the lease, reused when it is renewed
+    pub duration_secs: u64,
+    #[serde(default)]
+    pub on_expiry: LeaseAction,
+    /// Shell command printing a fresh value on stdout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renew_command: Option<String>,
+}
+
+impl Lease {
+    pub fn remaining(&self, now: u64) -> u64 {
+        self.expires_at.saturating_sub(now)
+    }
+}
+
+/// Parse a lease duration like `90s`, `15m`, `8h`, or `1d`
+///
+/// A bare number is minutes.
+pub fn parse_duration(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => text.split_at(i),
+        None => (text, "m"),
+    };
+    let number: u64 = number.parse().ok()?;
+    let unit = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => return None,
+    };
+    (number > 0).then(|| number * unit)
+}
+
+/// Countdown text: `2d 4h`, `1h 05m`, `4m 30s`, or `12s`
+pub fn format_remaining(secs: u64) -> String {
+    let (d, h, m, s) = (
+        secs / 86_400,
+        secs % 86_400 / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+    );
+    if d > 0 {
+        format!("{}d {}h", d, h)
+    } else if h > 0 {
+        format!("{}h {:02}m", h, m)
+    } else if m > 0 {
+        format!("{}m {:02}s", m, s)
+    } else {
+        format!("{}s", s)
+    }
+}
+
+/// Leases on key store entries, persisted locally
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeaseStore {
+    #[serde(default)]
+    pub leases: BTreeMap<String, Lease>,
+}
+
+impl LeaseStore {
+    pub fn path() -> Option<PathBuf> {
+        platform::data_dir().map(|d| d.join("leases.json"))
+    }
+
+    /// The leases; an unreadable file is an error rather than none, so a
+    /// save can't drop leases that still have to end
+    pub fn load() -> std::io::Result<Self> {
+        platform::try_load_json_state(Self::path())
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        platform::save_json_state(Self::path(), self)
+    }
+
+    /// Start (or restart) a lease on an entry
+    pub fn grant(
+        &mut self,
+        path: &str,
+        duration_secs: u64,
+        on_expiry: LeaseAction,
+        renew_command: Option<String>,
+        now: u64,
+    ) {
+        self.leases.insert(
+            path.to_string(),
+            Lease {
+                expires_at: now + duration_secs,
+                duration_secs,
+                on_expiry,
+                renew_command: renew_command.filter(|c| !c.trim().is_empty()),
+            },
+        );
+    }
+
+    /// Leases that have run out, by entry path
+    pub fn expired(&self, now: u64) -> Vec<(String, Lease)> {
+        self.leases
+            .iter()
+            .filter(|(_, lease)| lease.expires_at <= now)
+            .map(|(path, lease)| (path.clone(), lease.clone()))
+            .collect()
+    }
+
+    /// Active leases, soonest to expire first
+    pub fn by_expiry(&self) -> Vec<(String, Lease)> {
+        let mut leases: Vec<(String, Lease)> = self
+            .leases
+            .iter()
+            .map(|(path, lease)| (path.clone(), lease.clone()))
+            .collect();
+        leases.sort_by_key(|(_, lease)| lease.expires_at);
+        leases
+    }
+}
+
+/// What happened to an entry whose lease ran out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Renewed,
+    Deleted,
+    Masked,
+    Failed(String),
+}
+
+/// One entry handled by `enforce`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expiry {
+    pub path: String,
+    pub outcome: Outcome,
+}
+
+impl Expiry {
+    pub fn display_text(&self) -> String {
+        match &self.outcome {
+            Outcome::Renewed => format!("Lease on {} renewed", self.path),
+            Outcome::Deleted => format!("Lease on {} ended; entry deleted", self.path),
+            Outcome::Masked => format!("Lease on {} ended; value masked", self.path),
+            Outcome::Failed(e) => format!("Lease on {} ended, but: {}", self.path, e),
+        }
+    }
+}
+
+fn store_value(path: &str, value: &str) -> Result<(), Error> {
+    cli::store_secret(path, value).map_err(Error::from_cli)
+}
+
+/// Run a renewal hook and return the value it printed
+pub fn run_renew_hook(command: &str) -> Result<String, Error> {
+    let options = RunOptions {
+        timeout: HOOK_TIMEOUT,
+        retries: 0,
+        cancel: None,
+    };
+    let output =
+        cli::run_program("sh", &cli::args(&["-c", command]), &options).map_err(Error::from_cli)?;
+    let value = output.trim_end_matches(['\r', '\n']);
+    if value.is_empty() {
+        return Err(Error::InvalidOutput {
+            program: "renewal hook",
+            message: "printed no value".to_string(),
+        });
+    }
+    Ok(value.to_string())
+}
+
+/// End one lease: renew through its hook, or delete or mask the entry
+fn end_lease(path: &str, lease: &Lease) -> Outcome {
+    if let Some(command) = &lease.renew_command {
+        match run_renew_hook(command).and_then(|value| store_value(path, &value)) {
+            Ok(()) => return Outcome::Renewed,
+            Err(e) => tracing::warn!("Renewal hook for {} failed: {}", path, e),
+        }
+    }
+    let result = match lease.on_expiry {
+        LeaseAction::Delete => cli::run(&cli::args(&["keys", "delete", path]))
+            .map_err(Error::from_cli)
+            .and_then(|output| cli::reported_error(&output).map_err(Error::from_cli)),
+        LeaseAction::Mask => store_value(path, MASKED_VALUE),
+    };
+    match (result, lease.on_expiry) {
+        (Ok(()), LeaseAction::Delete) => Outcome::Deleted,
+        (Ok(()), LeaseAction::Mask) => Outcome::Masked,
+        (Err(e), _) => Outcome::Failed(e.to_string()),
+    }
+}
+
+/// End every lease that ran out by `now`
+///
+/// Renewed leases start over with their original duration; the others are
+/// dropped, including failed ones, so a broken entry isn't retried forever.
+/// Blocks while hooks and the CLI run; call it from a worker thread.
+pub fn enforce(now: u64) -> Vec<Expiry> {
+    let mut store = match LeaseStore::load() {
+        Ok(store) => store,
+        Err(e) => {
+            tracing::warn!("Failed to load leases: {}", e);
+            return Vec::new();
+        }
+    };
+    let expired = store.expired(now);
+    if expired.is_empty() {
+        return Vec::new();
+    }
+    let mut handled = Vec::new();
+    for (path, lease) in expired {
+        let outcome = end_lease(&path, &lease);
+        if outcome == Outcome::Renewed {
+            store.grant(
+                &path,
+                lease.duration_secs,
+                lease.on_expiry,
+                lease.renew_command.clone(),
+                expiry::now(),
+            );
+        } else {
+            store.leases.remove(&path);
+        }
+        handled.push(Expiry { path, outcome });
+    }
+    cli_cache::global().invalidate();
+    if let Err(e) = store.save() {
+        tracing::warn!("Failed to save leases: {}", e);
+    }
+    handled
+}
+
+/// Record a lease for an entry that was just stored
+pub fn grant(
+    path: &str,
+    duration_secs: u64,
+    on_expiry: LeaseAction,
+    renew_command: Option<String>,
+) -> std::io::Result<()> {
+    let mut store = LeaseStore::load()?;
+    store.grant(path, duration_secs, on_expiry, renew_command, expiry::now());
+    store.save()
+}
+
+/// End a lease now, as if it had run out
+pub fn revoke(path: &str) -> Option<Expiry> {
+    let mut store = match LeaseStore::load() {
+        Ok(store) => store,
+        Err(e) => {
+            tracing::warn!("Failed to load leases: {}", e);
+            return None;
+        }
+    };
+    let lease = store.leases.remove(path)?;
+    // Revoking shouldn't renew
+    let lease = Lease {
+        renew_command: None,
+        ..lease
+    };
+    let outcome = end_lease(path, &lease);
+    cli_cache::global().invalidate();
+    if let Err(e) = store.save() {
+        tracing::warn!("Failed to save leases: {}", e);
+    }
+    Some(Expiry {
+        path: path.to_string(),
+        outcome,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s"), Some(90));
+        assert_eq!(parse_duration("15m"), Some(900));
+        assert_eq!(parse_duration(" 8h "), Some(28_800));
+        assert_eq!(parse_duration("1d"), Some(86_400));
+        assert_eq!(parse_duration("30"), Some(1800));
+        assert_eq!(parse_duration("0h"), None);
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration("2w"), None);
+    }
+
+    #[test]
+    fn test_format_remaining() {
+        assert_eq!(format_remaining(12), "12s");
+        assert_eq!(format_remaining(270), "4m 30s");
+        assert_eq!(format_remaining(3900), "1h 05m");
+        assert_eq!(format_remaining(187_200), "2d 4h");
+    }
+
+    #[test]
+    fn test_store_expired_and_order() {
+        let mut store = LeaseStore::default();
+        store.grant("A/aws", 3600, LeaseAction::Delete, None, 1000);
+        store.grant("A/gcp", 60, LeaseAction::Mask, Some("  ".to_string()), 1000);
+        // A blank hook is no hook
+        assert_eq!(store.leases["A/gcp"].renew_command, None);
+
+        let order: Vec<String> = store.by_expiry().into_iter().map(|(p, _)| p).collect();
+        assert_eq!(order, vec!["A/gcp", "A/aws"]);
+
+        assert!(store.expired(1059).is_empty());
+        let expired = store.expired(1060);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, "A/gcp");
+        assert_eq!(expired[0].1.remaining(1060), 0);
+        assert_eq!(store.leases["A/aws"].remaining(1060), 3540);
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut store = LeaseStore::default();
+        store.grant(
+            "A/token",
+            900,
+            LeaseAction::Mask,
+            Some("vault read -field=token x".to_string()),
+            0,
+        );
+        let json = serde_json::to_string(&store).unwrap();
+        assert!(json.contains("\"onExpiry\":\"mask\""));
+        assert_eq!(serde_json::from_str::<LeaseStore>(&json).unwrap(), store);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_renew_hook() {
+        assert_eq!(
+            run_renew_hook("printf 'new-token\\n'").unwrap(),
+            "new-token"
+        );
+        assert!(run_renew_hook("true").is_err());
+        assert!(run_renew_hook("exit 3").is_err());
+    }
+}
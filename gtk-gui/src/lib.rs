@@ -0,0 +1,98 @@
+//! RemoteJuggler GUI core library
+//!
+//! Toolkit-independent logic shared by the GTK frontend and the C ABI
+//! (`ffi`), which alternative frontends link against as a cdylib.
+
+pub mod age;
+pub mod agent;
+pub mod allowed_signers;
+pub mod askpass;
+pub mod attachments;
+pub mod authorship;
+pub mod autolock;
+pub mod azure;
+pub mod backend;
+pub mod bitwarden;
+pub mod bookmarks;
+pub mod branches;
+pub mod chalresp;
+pub mod ci_secrets;
+pub mod cli;
+pub mod cli_cache;
+pub mod config;
+pub mod databases;
+pub mod detect;
+pub mod editor_server;
+pub mod error;
+pub mod event_hooks;
+pub mod expiry;
+pub mod export;
+pub mod ffi;
+pub mod fido2;
+pub mod gcp;
+pub mod gitconfig;
+pub mod github_app;
+pub mod gitsign;
+pub mod gpg;
+pub mod gpg_backup;
+pub mod gpg_publish;
+pub mod hg;
+pub mod history;
+pub mod hooks;
+pub mod http;
+pub mod hygiene;
+pub mod importers;
+pub mod jj;
+pub mod key_audit;
+pub mod keyring;
+pub mod keytocard;
+pub mod known_hosts;
+pub mod leases;
+pub mod lockout;
+pub mod merge;
+pub mod metrics;
+pub mod oauth;
+pub mod orgs;
+pub mod pass;
+pub mod passphrase;
+pub mod pat;
+pub mod pin_storage;
+pub mod pinentry;
+pub mod piv;
+pub mod platform;
+pub mod policy;
+pub mod progress;
+pub mod provider_api;
+pub mod provider_cli;
+pub mod provider_hosts;
+pub mod pubkeys;
+pub mod registration;
+pub mod rekey;
+pub mod remotes;
+pub mod resolver;
+pub mod rest_api;
+pub mod revocation;
+pub mod scanner;
+pub mod secret_service;
+pub mod setec;
+pub mod shell;
+pub mod signature;
+pub mod sops;
+pub mod ssh_cert;
+pub mod ssh_config;
+pub mod switch_guard;
+pub mod systemd;
+pub mod tailscale;
+pub mod telemetry;
+pub mod token_audit;
+pub mod totp;
+pub mod touch;
+pub mod tpm;
+pub mod url_rewrites;
+pub mod verified_commits;
+pub mod verify;
+pub mod workspace;
+pub mod yubikeys;
+
+#[cfg(test)]
+mod config_properties;
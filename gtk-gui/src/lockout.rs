@@ -0,0 +1,514 @@
+//! YubiKey PIN retry counters and unblocking
+//!
+//! A YubiKey blocks a PIN after a few wrong entries, 3 unless changed. The
+//! OpenPGP applet counts the PIN, Reset Code and Admin PIN separately and
+//! PIV counts its PIN and PUK; a correct entry resets the counter, so one
+//! below its maximum means wrong entries since. This reads the counters
+//! from `gpg --card-status` and `ykman piv info`, remembers the highest
+//! seen for each as its maximum, and unblocks a PIN with the Admin PIN or
+//! Reset Code through gpg-agent's pinentry, or with the PUK through ykman.
+//! When the Admin PIN or PUK is blocked as well, only resetting the applet
+//! helps, and that erases its keys.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chalresp;
+use crate::cli::{self, RunOptions};
+use crate::error::Error;
+use crate::gpg;
+use crate::platform;
+
+/// Tries a YubiKey allows until set otherwise
+pub const DEFAULT_TRIES: u32 = 3;
+
+/// A YubiKey application with its own PINs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Applet {
+    OpenPgp,
+    Piv,
+}
+
+impl Applet {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::OpenPgp => "OpenPGP",
+            Self::Piv => "PIV",
+        }
+    }
+
+    fn id(&self) -> &'static str {
+        match self {
+            Self::OpenPgp => "openpgp",
+            Self::Piv => "piv",
+        }
+    }
+}
+
+/// What a counter counts tries of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Secret {
+    Pin,
+    /// OpenPGP only; unblocks the PIN without the Admin PIN
+    ResetCode,
+    /// OpenPGP only; unblocks the PIN
+    AdminPin,
+    /// PIV only; unblocks the PIN
+    Puk,
+}
+
+impl Secret {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::Pin => "PIN",
+            Self::ResetCode => "Reset Code",
+            Self::AdminPin => "Admin PIN",
+            Self::Puk => "PUK",
+        }
+    }
+
+    fn id(&self) -> &'static str {
+        match self {
+            Self::Pin => "pin",
+            Self::ResetCode => "reset_code",
+            Self::AdminPin => "admin_pin",
+            Self::Puk => "puk",
+        }
+    }
+}
+
+/// How close a counter is to blocking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Full,
+    /// Wrong entries since the last correct one
+    Dropped,
+    /// One more wrong entry blocks it
+    Last,
+    Blocked,
+}
+
+/// One retry counter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Counter {
+    pub applet: Applet,
+    pub secret: Secret,
+    pub remaining: u32,
+    /// The most tries seen for this counter; 0 for a Reset Code that was
+    /// never set
+    pub max: u32,
+}
+
+impl Counter {
+    /// Whether the secret is set at all
+    pub fn is_set(&self) -> bool {
+        self.max > 0
+    }
+
+    pub fn level(&self) -> Level {
+        match self.remaining {
+            0 => Level::Blocked,
+            1 if self.max > 1 => Level::Last,
+            n if n < self.max => Level::Dropped,
+            _ => Level::Full,
+        }
+    }
+
+    pub fn display_text(&self) -> String {
+        format!(
+            "{} {} {}/{}",
+            self.applet.display_name(),
+            self.secret.display_name(),
+            self.remaining,
+            self.max
+        )
+    }
+
+    fn key(&self, serial: &str) -> String {
+        format!("{}/{}/{}", serial, self.applet.id(), self.secret.id())
+    }
+}
+
+/// Parse `ykman piv info`: "PIN tries remaining: 2/3" in ykman 5, without
+/// the maximum before
+pub fn parse_piv_info(text: &str) -> Vec<Counter> {
+    text.lines()
+        .filter_map(|line| {
+            let (name, value) = line.trim().split_once(" tries remaining:")?;
+            let secret = match name {
+                "PIN" => Secret::Pin,
+                "PUK" => Secret::Puk,
+                _ => return None,
+            };
+            let (remaining, max) = match value.trim().split_once('/') {
+                Some((remaining, max)) => (remaining.parse().ok()?, max.parse().ok()?),
+                None => (value.trim().parse().ok()?, 0),
+            };
+            Some(Counter {
+                applet: Applet::Piv,
+                secret,
+                remaining,
+                max,
+            })
+        })
+        .collect()
+}
+
+/// The OpenPGP counters of a card
+pub fn openpgp_counters(card: &gpg::Card) -> Vec<Counter> {
+    let Some(retries) = card.pin_retries else {
+        return Vec::new();
+    };
+    [Secret::Pin, Secret::ResetCode, Secret::AdminPin]
+        .into_iter()
+        .zip(retries)
+        .map(|(secret, remaining)| Counter {
+            applet: Applet::OpenPgp,
+            secret,
+            remaining,
+            // Filled in from what was seen before
+            max: 0,
+        })
+        .collect()
+}
+
+/// The most tries seen for each counter, persisted locally, so a drop
+/// shows even where the card doesn't report its maximum
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryStore {
+    /// By `serial/applet/secret`
+    #[serde(default)]
+    pub maxima: BTreeMap<String, u32>,
+}
+
+impl RetryStore {
+    pub fn path() -> Option<PathBuf> {
+        platform::data_dir().map(|d| d.join("pin-retries.json"))
+    }
+
+    pub fn load() -> Self {
+        platform::load_json_state(Self::path())
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        platform::save_json_state(Self::path(), self)
+    }
+
+    /// Fill in each counter's maximum from the card, what was seen before
+    /// and the default, and remember it. A Reset Code is only counted once
+    /// it has been seen set.
+    pub fn update(&mut self, serial: &str, counters: &mut [Counter]) {
+        for counter in counters {
+            let seen = self
+                .maxima
+                .get(&counter.key(serial))
+                .copied()
+                .unwrap_or_default();
+            let default = match counter.secret {
+                Secret::ResetCode => 0,
+                _ => DEFAULT_TRIES,
+            };
+            let reported = if counter.max > 0 {
+                counter.max
+            } else {
+                default
+            };
+            counter.max = reported.max(seen).max(counter.remaining);
+            if counter.max > 0 {
+                self.maxima.insert(counter.key(serial), counter.max);
+            }
+        }
+    }
+}
+
+/// A way to unblock a blocked PIN
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// OpenPGP, through pinentry
+    AdminPin,
+    /// OpenPGP, through pinentry
+    ResetCode,
+    /// PIV, with the PUK and a new PIN
+    Puk,
+}
+
+impl Recovery {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::AdminPin => "OpenPGP PIN with the Admin PIN",
+            Self::ResetCode => "OpenPGP PIN with the Reset Code",
+            Self::Puk => "PIV PIN with the PUK",
+        }
+    }
+
+    /// The `--card-edit` commands for an OpenPGP recovery
+    pub fn script(&self) -> Option<&'static str> {
+        match self {
+            // passwd menu: 2 unblocks the PIN
+            Self::AdminPin => Some("admin\npasswd\n2\nq\nquit\n"),
+            Self::ResetCode => Some("unblock\nquit\n"),
+            Self::Puk => None,
+        }
+    }
+}
+
+/// The retry counters of the inserted YubiKey
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Status {
+    pub serial: String,
+    pub counters: Vec<Counter>,
+}
+
+impl Status {
+    pub fn counter(&self, applet: Applet, secret: Secret) -> Option<&Counter> {
+        self.counters
+            .iter()
+            .find(|c| c.applet == applet && c.secret == secret)
+    }
+
+    fn remaining(&self, applet: Applet, secret: Secret) -> u32 {
+        self.counter(applet, secret).map_or(0, |c| c.remaining)
+    }
+
+    /// The worst level among the counters that are set
+    pub fn level(&self) -> Level {
+        self.counters
+            .iter()
+            .filter(|c| c.is_set())
+            .map(Counter::level)
+            .max()
+            .unwrap_or(Level::Full)
+    }
+
+    pub fn display_text(&self) -> String {
+        if self.counters.is_empty() {
+            return "No YubiKey found".to_string();
+        }
+        self.counters
+            .iter()
+            .filter(|c| c.is_set())
+            .map(Counter::display_text)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Ways to unblock the blocked PINs that still have tries left
+    pub fn recoveries(&self) -> Vec<Recovery> {
+        let mut recoveries = Vec::new();
+        let blocked = |applet| {
+            self.counter(applet, Secret::Pin)
+                .is_some_and(|c| c.level() == Level::Blocked)
+        };
+        if blocked(Applet::OpenPgp) {
+            if self.remaining(Applet::OpenPgp, Secret::AdminPin) > 0 {
+                recoveries.push(Recovery::AdminPin);
+            }
+            if self.remaining(Applet::OpenPgp, Secret::ResetCode) > 0 {
+                recoveries.push(Recovery::ResetCode);
+            }
+        }
+        if blocked(Applet::Piv) && self.remaining(Applet::Piv, Secret::Puk) > 0 {
+            recoveries.push(Recovery::Puk);
+        }
+        recoveries
+    }
+
+    /// What to do about the counters, worst first; empty when all are full
+    pub fn guidance(&self) -> Vec<String> {
+        let mut guidance = Vec::new();
+        for applet in [Applet::OpenPgp, Applet::Piv] {
+            let Some(pin) = self.counter(applet, Secret::Pin) else {
+                continue;
+            };
+            let (unblocker, reset) = match applet {
+                Applet::OpenPgp => (Secret::AdminPin, "ykman openpgp reset"),
+                Applet::Piv => (Secret::Puk, "ykman piv reset"),
+            };
+            let unblock_left = self.remaining(applet, unblocker);
+            let name = applet.display_name();
+            match pin.level() {
+                Level::Blocked if unblock_left == 0 => guidance.push(format!(
+                    "The {} PIN and {} are blocked. Only `{}` helps now, which erases the \
+                     keys on the card; restore them from a backup afterwards.",
+                    name,
+                    unblocker.display_name(),
+                    reset
+                )),
+                Level::Blocked => guidance.push(format!(
+                    "The {} PIN is blocked. Unblock it with the {} ({} tries left) and \
+                     set a new PIN.",
+                    name,
+                    unblocker.display_name(),
+                    unblock_left
+                )),
+                Level::Last => guidance.push(format!(
+                    "One {} PIN try left. If a stored PIN is failing, store the right one \
+                     before signing again.",
+                    name
+                )),
+                Level::Dropped => guidance.push(format!(
+                    "{} of {} {} PIN tries left; the next correct PIN resets the count.",
+                    pin.remaining, pin.max, name
+                )),
+                Level::Full => {}
+            }
+            if let Some(admin) = self.counter(applet, unblocker) {
+                if admin.level() >= Level::Last && pin.level() != Level::Blocked {
+                    guidance.push(format!(
+                        "The {} {} has {} tries left; once it is blocked too, the applet \
+                         can only be reset.",
+                        name,
+                        unblocker.display_name(),
+                        admin.remaining
+                    ));
+                }
+            }
+        }
+        guidance
+    }
+}
+
+fn options(timeout: Duration) -> RunOptions {
+    RunOptions {
+        timeout,
+        retries: 0,
+        cancel: None,
+    }
+}
+
+/// The PIV counters, empty without ykman or a PIV applet; blocks
+fn piv_counters() -> Vec<Counter> {
+    let args = cli::args(&["piv", "info"]);
+    match cli::run_program(
+        &chalresp::ykman_program(),
+        &args,
+        &options(Duration::from_secs(15)),
+    ) {
+        Ok(out) => parse_piv_info(&out),
+        Err(e) => {
+            tracing::debug!("No PIV counters: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Read the inserted YubiKey's counters and remember their maxima;
+/// blocks
+pub fn check() -> Status {
+    let card = gpg::card_status();
+    let mut counters = card.as_ref().map(openpgp_counters).unwrap_or_default();
+    counters.extend(piv_counters());
+    let serial = card.map(|c| c.serial).unwrap_or_else(|| "yubikey".into());
+    let mut store = RetryStore::load();
+    store.update(&serial, &mut counters);
+    if let Err(e) = store.save() {
+        tracing::warn!("Can't save PIN retry counters: {}", e);
+    }
+    Status { serial, counters }
+}
+
+/// Unblock the OpenPGP PIN; pinentry asks for the Admin PIN or Reset Code
+/// and the new PIN. Blocks
+pub fn unblock_openpgp(recovery: Recovery) -> Result<(), Error> {
+    let Some(script) = recovery.script() else {
+        return Err(Error::InvalidOutput {
+            program: "gpg",
+            message: format!("{} isn't an OpenPGP recovery", recovery.display_name()),
+        });
+    };
+    let args = cli::args(&[
+        "--batch",
+        "--command-fd",
+        "0",
+        "--status-fd",
+        "1",
+        "--card-edit",
+    ]);
+    // Long enough to type both PINs
+    cli::run_program_with_input(
+        "gpg",
+        &args,
+        &[],
+        script,
+        &options(Duration::from_secs(300)),
+    )
+    .map_err(Error::from_cli)?;
+    // gpg exits 0 when a PIN is wrong, so check the counter
+    let unblocked = gpg::card_status()
+        .and_then(|card| card.pin_retries)
+        .is_some_and(|[pin, ..]| pin > 0);
+    if !unblocked {
+        return Err(Error::InvalidOutput {
+            program: "gpg",
+            message: "The PIN is still blocked".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Unblock the PIV PIN with the PUK and set `new_pin`; blocks
+///
+/// ykman only takes both on the command line when it has no terminal to
+/// prompt on, so they are briefly visible to other processes of the user.
+pub fn unblock_piv(puk: &str, new_pin: &str) -> Result<(), Error> {
+    let args = cli::args(&[
+        "piv",
+        "access",
+        "unblock-pin",
+        "--puk",
+        puk,
+        "--new-pin",
+        new_pin,
+    ]);
+    cli::run_program(
+        &chalresp::ykman_program(),
+        &args,
+        &options(Duration::from_secs(30)),
+    )
+    .map(|_| ())
+    .map_err(Error::from_cli)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_and_guidance() {
+        let card = gpg::parse_card_status("serial:12345678:\npinretry:0:0:2:\n").unwrap();
+        let mut counters = openpgp_counters(&card);
+        counters.extend(parse_piv_info(
+            "PIV version:              5.4.3\n\
+             PIN tries remaining:      1/3\n\
+             PUK tries remaining:      3/3\n",
+        ));
+        let mut store = RetryStore::default();
+        store.update("12345678", &mut counters);
+        // The Reset Code was never set, so it isn't remembered
+        assert_eq!(store.maxima.len(), 4);
+        let status = Status {
+            serial: "12345678".to_string(),
+            counters,
+        };
+        assert_eq!(status.level(), Level::Blocked);
+        assert_eq!(
+            status.display_text(),
+            "OpenPGP PIN 0/3, OpenPGP Admin PIN 2/3, PIV PIN 1/3, PIV PUK 3/3"
+        );
+        assert_eq!(status.recoveries(), vec![Recovery::AdminPin]);
+        let guidance = status.guidance();
+        assert!(guidance[0].starts_with(
+            "The OpenPGP PIN is blocked. Unblock it with the Admin PIN (2 tries left)"
+        ));
+        assert!(guidance[1].starts_with("One PIV PIN try left"));
+
+        // Older ykman without the maximum: the remembered one applies
+        let mut older = parse_piv_info("PIN tries remaining: 2\n");
+        store.update("12345678", &mut older);
+        assert_eq!(older[0].level(), Level::Dropped);
+        assert_eq!(Recovery::ResetCode.script(), Some("unblock\nquit\n"));
+    }
+}
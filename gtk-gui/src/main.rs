@@ -1,23 +1,57 @@
-mod config;
+mod portal;
+mod secret_service_bus;
 mod window;
 
-#[cfg(test)]
-mod config_properties;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use remote_juggler_gui::cli;
+use remote_juggler_gui::config::Config;
+use remote_juggler_gui::editor_server;
+use remote_juggler_gui::event_hooks;
+use remote_juggler_gui::systemd::{self, Task};
+use remote_juggler_gui::telemetry;
 
-use gtk4::glib;
 use gtk4::prelude::*;
+use gtk4::{gio, glib};
 use libadwaita as adw;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
 
 const APP_ID: &str = "dev.tinyland.RemoteJuggler";
 
+/// How long a task or the editor server waits for its event hooks before
+/// exiting
+const EVENT_HOOKS_TIMEOUT: Duration = Duration::from_secs(30);
+
 fn main() -> glib::ExitCode {
-    // Initialize logging
-    tracing_subscriber::fmt::init();
+    let args: Vec<String> = std::env::args().collect();
+
+    // The editor server's stdout carries only protocol messages
+    if args.iter().skip(1).any(|arg| arg == "--editor-server") {
+        let _telemetry = init_logging(BoxMakeWriter::new(std::io::stderr));
+        let result = editor_server::run();
+        event_hooks::flush(EVENT_HOOKS_TIMEOUT);
+        return match result {
+            Ok(()) => glib::ExitCode::SUCCESS,
+            Err(e) => {
+                tracing::error!("Editor server failed: {}", e);
+                glib::ExitCode::FAILURE
+            }
+        };
+    }
+
+    // Initialize logging; exporting the last spans waits for this to drop
+    let _telemetry = init_logging(BoxMakeWriter::new(std::io::stdout));
 
     // Parse CLI flags before GTK takes over
-    let args: Vec<String> = std::env::args().collect();
     let mut initial_view = InitialView::Default;
     let mut switch_identity: Option<String> = None;
+    let mut background = false;
 
     let mut i = 1;
     while i < args.len() {
@@ -44,12 +78,28 @@ fn main() -> glib::ExitCode {
                 switch_identity = Some(name.to_string());
                 initial_view = InitialView::Switch;
             }
+            "--background" => {
+                background = true;
+            }
+            "--task" => {
+                let Some(id) = args.get(i + 1) else {
+                    eprintln!("--task requires a task name");
+                    return glib::ExitCode::from(1);
+                };
+                return run_task(id);
+            }
+            arg if arg.starts_with("--task=") => {
+                return run_task(arg.strip_prefix("--task=").unwrap_or(""));
+            }
             "--help" | "-h" => {
                 println!("Usage: remote-juggler-gui [OPTIONS]");
                 println!();
                 println!("Options:");
                 println!("  --status           Open to status view");
                 println!("  --switch <NAME>    Switch identity and open GUI");
+                println!("  --background       Run without opening the window");
+                println!("  --task <TASK>      Run a periodic task once and exit");
+                println!("  --editor-server    Serve JSON-RPC to an editor on stdin/stdout");
                 println!("  --help, -h         Show this help");
                 return glib::ExitCode::SUCCESS;
             }
@@ -62,19 +112,12 @@ fn main() -> glib::ExitCode {
     // If --switch was given, perform the switch before launching the GUI
     if let Some(ref identity) = switch_identity {
         tracing::info!("Pre-launch switch to identity: {}", identity);
-        let output = std::process::Command::new("remote-juggler")
-            .args(["switch", identity])
-            .output();
-        match output {
-            Ok(o) if o.status.success() => {
+        match cli::run(&cli::args(&["switch", identity])) {
+            Ok(_) => {
                 tracing::info!("Switched to {}", identity);
             }
-            Ok(o) => {
-                let stderr = String::from_utf8_lossy(&o.stderr);
-                tracing::error!("Switch failed: {}", stderr);
-            }
             Err(e) => {
-                tracing::error!("Failed to run remote-juggler: {}", e);
+                tracing::error!("Switch failed: {}", e);
             }
         }
     }
@@ -83,15 +126,21 @@ fn main() -> glib::ExitCode {
     let app = adw::Application::builder().application_id(APP_ID).build();
 
     let view = initial_view;
+    let first = Cell::new(true);
     app.connect_activate(move |app| {
-        build_ui(app, &view);
+        let hidden = background && first.replace(false);
+        build_ui(app, &view, hidden);
     });
 
     // Pass only non-RemoteJuggler args to GTK
     let gtk_args: Vec<String> = args
         .iter()
         .filter(|a| {
-            !a.starts_with("--status") && !a.starts_with("--switch") && *a != "--help" && *a != "-h"
+            !a.starts_with("--status")
+                && !a.starts_with("--switch")
+                && *a != "--background"
+                && *a != "--help"
+                && *a != "-h"
         })
         .cloned()
         .collect();
@@ -106,7 +155,100 @@ enum InitialView {
     Switch,
 }
 
-fn build_ui(app: &adw::Application, _view: &InitialView) {
+fn build_ui(app: &adw::Application, _view: &InitialView, hidden: bool) {
+    // Launching the GUI while it runs in the background shows its window
+    if let Some(window) = app.windows().into_iter().next() {
+        window.present();
+        return;
+    }
     let window = window::RemoteJugglerWindow::new(app);
+    if hidden {
+        // Closing only hides the window, so its services keep running
+        window.set_hide_on_close(true);
+        std::mem::forget(app.hold());
+        return;
+    }
     window.present();
 }
+
+/// Log INFO and above to `writer`, and export spans if an OTLP endpoint
+/// is configured
+fn init_logging(writer: BoxMakeWriter) -> Option<telemetry::Guard> {
+    let settings = Config::load()
+        .map(|config| config.settings.telemetry)
+        .unwrap_or_default();
+    let (otlp, guard) = telemetry::layer(&settings).unzip();
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_filter(LevelFilter::INFO),
+        )
+        .with(otlp.with_filter(LevelFilter::INFO))
+        .init();
+    guard
+}
+
+/// `--task <TASK>`: what a systemd timer runs. Prints the outcome for the
+/// journal and sends a notification when something needs attention
+fn run_task(id: &str) -> glib::ExitCode {
+    let Some(task) = Task::from_id(id) else {
+        let ids: Vec<&str> = Task::ALL.iter().map(|task| task.id()).collect();
+        eprintln!("Unknown task '{}'; one of {}", id, ids.join(", "));
+        return glib::ExitCode::from(1);
+    };
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load config: {}", e);
+            return glib::ExitCode::FAILURE;
+        }
+    };
+    let report = systemd::run(task, &config);
+    println!("{}", report.summary);
+    for line in &report.attention {
+        println!("  {}", line);
+    }
+    if !report.attention.is_empty() {
+        notify(task.display_name(), &report.attention.join("\n"));
+    }
+    event_hooks::flush(EVENT_HOOKS_TIMEOUT);
+    glib::ExitCode::SUCCESS
+}
+
+/// A desktop notification straight over D-Bus, as a task has no
+/// GApplication to send one through
+fn notify(summary: &str, body: &str) {
+    let bus = match gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE) {
+        Ok(bus) => bus,
+        Err(e) => {
+            tracing::warn!("No session bus for a notification: {}", e);
+            return;
+        }
+    };
+    let hints: HashMap<String, glib::Variant> = HashMap::new();
+    let args = (
+        "RemoteJuggler",
+        0u32,
+        APP_ID,
+        summary,
+        body,
+        Vec::<String>::new(),
+        hints,
+        -1i32,
+    )
+        .to_variant();
+    if let Err(e) = bus.call_sync(
+        Some("org.freedesktop.Notifications"),
+        "/org/freedesktop/Notifications",
+        "org.freedesktop.Notifications",
+        "Notify",
+        Some(&args),
+        None,
+        gio::DBusCallFlags::NONE,
+        5000,
+        gio::Cancellable::NONE,
+    ) {
+        tracing::warn!("Sending a notification failed: {}", e);
+    }
+}
@@ -0,0 +1,217 @@
+//! Merging another KDBX database into the key store
+//!
+//! `keys sync <other.kdbx>` wraps `keepassxc-cli merge`, which matches
+//! entries by UUID and keeps the newer version of each; the older one goes
+//! into the entry's history, where the History viewer can restore it. A dry
+//! run lists the changes first so overwrites of local values can be
+//! reviewed before anything is written.
+
+use std::path::Path;
+
+use crate::cli;
+use crate::error::Error;
+
+/// Environment variable the CLI reads the other database's password from
+pub const SYNC_PASSWORD_ENV: &str = "REMOTE_JUGGLER_SYNC_PASSWORD";
+
+/// Arguments to merge `other` into the key store
+pub fn sync_args(other: &Path, dry_run: bool, same_credentials: bool) -> Vec<String> {
+    let mut args = cli::args(&["keys", "sync", &other.to_string_lossy()]);
+    if dry_run {
+        args.push("--dry-run".to_string());
+    }
+    if same_credentials {
+        args.push("--same-credentials".to_string());
+    }
+    args
+}
+
+/// What a merge does to one entry or group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// Only in the other database; copied over
+    Added,
+    /// Moved to the group it has in the other database
+    Relocated,
+    /// The other database's version is newer and replaces the local one
+    Replaced,
+    /// The local version is newer; the other one is kept in history
+    KeptLocal,
+    /// Edited on both sides; histories are interleaved, newest wins
+    Conflict,
+    Deleted,
+    /// Metadata such as icons or custom data
+    Other,
+}
+
+impl ChangeKind {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "Added",
+            ChangeKind::Relocated => "Moved",
+            ChangeKind::Replaced => "Replaced by newer",
+            ChangeKind::KeptLocal => "Local kept",
+            ChangeKind::Conflict => "Conflict",
+            ChangeKind::Deleted => "Deleted",
+            ChangeKind::Other => "Changed",
+        }
+    }
+}
+
+// Merger messages of keepassxc-cli, most specific first
+const PREFIXES: &[(&str, ChangeKind)] = &[
+    ("Creating missing ", ChangeKind::Added),
+    ("Relocating ", ChangeKind::Relocated),
+    ("Overwriting ", ChangeKind::Replaced),
+    ("Synchronizing from newer source ", ChangeKind::Replaced),
+    ("Synchronizing from older source ", ChangeKind::KeptLocal),
+    ("Adding backup for older target ", ChangeKind::Replaced),
+    ("Adding backup for older source ", ChangeKind::KeptLocal),
+    (
+        "Reapplying older target entry on top of newer source ",
+        ChangeKind::Conflict,
+    ),
+    (
+        "Reapplying older source entry on top of newer target ",
+        ChangeKind::Conflict,
+    ),
+    ("Deleting child ", ChangeKind::Deleted),
+    ("Deleting orphan ", ChangeKind::Deleted),
+];
+
+/// One line of the merge change list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// Entry or group title
+    pub title: String,
+    pub uuid: Option<String>,
+}
+
+impl Change {
+    /// Returns true if the change loses or overrides a local edit
+    pub fn needs_review(&self) -> bool {
+        matches!(
+            self.kind,
+            ChangeKind::Replaced | ChangeKind::Conflict | ChangeKind::Deleted
+        )
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty()
+            || line.contains("[INFO]")
+            || line.contains("[OK]")
+            || line.starts_with("Database was not modified")
+            || line.starts_with("Successfully merged")
+        {
+            return None;
+        }
+        let (kind, rest) = PREFIXES
+            .iter()
+            .find_map(|(prefix, kind)| line.strip_prefix(prefix).map(|rest| (*kind, rest)))
+            .unwrap_or((ChangeKind::Other, line));
+        let (title, uuid) = match rest.rsplit_once(" [") {
+            Some((title, uuid)) if uuid.ends_with(']') => (
+                title.to_string(),
+                Some(uuid.trim_end_matches(']').to_string()),
+            ),
+            _ => (rest.to_string(), None),
+        };
+        Some(Change { kind, title, uuid })
+    }
+}
+
+/// Parse the change list printed by `keys sync`
+pub fn parse_changes(output: &str) -> Result<Vec<Change>, Error> {
+    cli::reported_error(output).map_err(Error::from_cli)?;
+    Ok(output.lines().filter_map(Change::parse).collect())
+}
+
+/// One-line summary of a change list, e.g. "3 added, 1 replaced by newer"
+pub fn summary(changes: &[Change]) -> String {
+    if changes.is_empty() {
+        return "Already in sync".to_string();
+    }
+    let kinds = [
+        ChangeKind::Added,
+        ChangeKind::Replaced,
+        ChangeKind::Conflict,
+        ChangeKind::KeptLocal,
+        ChangeKind::Relocated,
+        ChangeKind::Deleted,
+        ChangeKind::Other,
+    ];
+    kinds
+        .iter()
+        .filter_map(|kind| {
+            let count = changes.iter().filter(|c| c.kind == *kind).count();
+            (count > 0).then(|| format!("{} {}", count, kind.display_name().to_lowercase()))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRY_RUN: &str = "  Creating missing GITHUB_TOKEN [6f1c2a]
+  Synchronizing from newer source DATABASE_URL [9ab0]
+  Synchronizing from older source AWS_KEY [77aa]
+  Reapplying older target entry on top of newer source NPM_TOKEN [1234]
+  Changed deleted objects
+  Database was not modified by merge operation.
+\x1b[34m[INFO]\x1b[0m Dry run; the key store was not modified
+";
+
+    #[test]
+    fn test_parse_changes() {
+        let changes = parse_changes(DRY_RUN).unwrap();
+        assert_eq!(changes.len(), 5);
+        assert_eq!(
+            changes[0],
+            Change {
+                kind: ChangeKind::Added,
+                title: "GITHUB_TOKEN".to_string(),
+                uuid: Some("6f1c2a".to_string()),
+            }
+        );
+        assert_eq!(changes[1].kind, ChangeKind::Replaced);
+        assert_eq!(changes[2].kind, ChangeKind::KeptLocal);
+        assert_eq!(changes[3].kind, ChangeKind::Conflict);
+        assert_eq!(changes[3].title, "NPM_TOKEN");
+        assert_eq!(changes[4].kind, ChangeKind::Other);
+        assert_eq!(changes[4].uuid, None);
+
+        let review: Vec<&str> = changes
+            .iter()
+            .filter(|c| c.needs_review())
+            .map(|c| c.title.as_str())
+            .collect();
+        assert_eq!(review, vec!["DATABASE_URL", "NPM_TOKEN"]);
+    }
+
+    #[test]
+    fn test_parse_reported_error() {
+        assert!(parse_changes("[ERROR] Merge failed: Invalid credentials").is_err());
+    }
+
+    #[test]
+    fn test_summary() {
+        let changes = parse_changes(DRY_RUN).unwrap();
+        assert_eq!(
+            summary(&changes),
+            "1 added, 1 replaced by newer, 1 conflict, 1 local kept, 1 changed"
+        );
+        assert_eq!(summary(&[]), "Already in sync");
+    }
+
+    #[test]
+    fn test_sync_args() {
+        assert_eq!(
+            sync_args(Path::new("/media/usb/keys.kdbx"), true, false),
+            cli::args(&["keys", "sync", "/media/usb/keys.kdbx", "--dry-run"])
+        );
+    }
+}
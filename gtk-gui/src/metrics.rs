@@ -0,0 +1,373 @@
+//! Prometheus metrics
+//!
+//! While the GUI runs, in the background service too, counters are kept in
+//! memory: switches, CLI calls by command with their latency, lookups in
+//! the CLI result cache and the resolver's caches, and failures of the
+//! secret stores. With `settings.metrics.enabled` they are served as
+//! Prometheus text on `http://127.0.0.1:<port>/metrics`, along with the key
+//! store's state, so a homelab can alert on the credential setup:
+//!
+//! ```text
+//! remote_juggler_keystore_state{state="locked"} == 1
+//! rate(remote_juggler_backend_errors_total[1h]) > 0
+//! ```
+//!
+//! The counters cover this process only, not CLI runs from a terminal. No
+//! secret, identity or entry name ends up in a label. The endpoint answers
+//! without a token, since nothing in it is secret, but like the local HTTP
+//! API it refuses requests from browser pages. Scraping runs `keys status` (cached for 10 seconds),
+//! so it blocks; the server answers on its own thread.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufReader, Write};
+use std::net::TcpStream;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::cli;
+use crate::config::MetricsSettings;
+use crate::error::Error;
+use crate::rest_api::{self, Request, Response, Server};
+use crate::shell::KeystoreState;
+
+/// Upper bounds of the CLI latency buckets, in seconds
+pub const BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// CLI commands whose second word is a subcommand, e.g. `keys status`
+const COMMAND_GROUPS: &[&str] = &["auth", "config", "gpg", "keys", "otp", "pin", "yubikey"];
+
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Histogram {
+    /// Per bucket, not cumulative; the last is +Inf
+    counts: [u64; BUCKETS.len() + 1],
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += seconds;
+    }
+}
+
+/// Everything counted so far
+#[derive(Debug, Default)]
+pub struct Registry {
+    /// By result, "ok" or "error"
+    switches: BTreeMap<&'static str, u64>,
+    /// By command and result
+    cli: BTreeMap<(String, &'static str), Histogram>,
+    /// By cache and whether it hit
+    cache: BTreeMap<(&'static str, bool), u64>,
+    /// By store
+    backend_errors: BTreeMap<String, u64>,
+}
+
+fn result_label(ok: bool) -> &'static str {
+    if ok {
+        "ok"
+    } else {
+        "error"
+    }
+}
+
+/// The command a CLI call runs, without its arguments: `switch`,
+/// `keys get`
+pub fn command_label(args: &[String]) -> String {
+    let Some(first) = args.first() else {
+        return String::new();
+    };
+    match args.get(1) {
+        Some(second)
+            if COMMAND_GROUPS.contains(&first.as_str())
+                && second.chars().all(|c| c.is_ascii_lowercase() || c == '-') =>
+        {
+            format!("{} {}", first, second)
+        }
+        _ if first.starts_with('-') => "other".to_string(),
+        _ => first.clone(),
+    }
+}
+
+/// Escape a label value
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Registry {
+    pub fn record_cli(&mut self, args: &[String], elapsed: Duration, ok: bool) {
+        let result = result_label(ok);
+        if args.first().map(String::as_str) == Some("switch") {
+            *self.switches.entry(result).or_default() += 1;
+        }
+        self.cli
+            .entry((command_label(args), result))
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn record_cache(&mut self, cache: &'static str, hit: bool) {
+        *self.cache.entry((cache, hit)).or_default() += 1;
+    }
+
+    pub fn record_backend_error(&mut self, backend: &str) {
+        *self.backend_errors.entry(backend.to_string()).or_default() += 1;
+    }
+
+    /// Prometheus text exposition format
+    pub fn render(&self, keystore: KeystoreState) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP remote_juggler_build_info Version of the running GUI\n\
+             # TYPE remote_juggler_build_info gauge\n\
+             remote_juggler_build_info{{version=\"{}\"}} 1",
+            env!("CARGO_PKG_VERSION")
+        );
+
+        out.push_str(
+            "# HELP remote_juggler_switches_total Identity switches\n\
+             # TYPE remote_juggler_switches_total counter\n",
+        );
+        for result in ["ok", "error"] {
+            let count = self.switches.get(result).copied().unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "remote_juggler_switches_total{{result=\"{}\"}} {}",
+                result, count
+            );
+        }
+
+        out.push_str(
+            "# HELP remote_juggler_cli_duration_seconds Time CLI commands took\n\
+             # TYPE remote_juggler_cli_duration_seconds histogram\n",
+        );
+        for ((command, result), histogram) in &self.cli {
+            let labels = format!("command=\"{}\",result=\"{}\"", label(command), result);
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(&histogram.counts) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "remote_juggler_cli_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulative
+                );
+            }
+            let total: u64 = histogram.counts.iter().sum();
+            let _ = writeln!(
+                out,
+                "remote_juggler_cli_duration_seconds_bucket{{{},le=\"+Inf\"}} {}\n\
+                 remote_juggler_cli_duration_seconds_sum{{{}}} {}\n\
+                 remote_juggler_cli_duration_seconds_count{{{}}} {}",
+                labels, total, labels, histogram.sum, labels, total
+            );
+        }
+
+        out.push_str(
+            "# HELP remote_juggler_cache_lookups_total Lookups in the CLI result cache and \
+             the resolver's caches\n\
+             # TYPE remote_juggler_cache_lookups_total counter\n",
+        );
+        for ((cache, hit), count) in &self.cache {
+            let _ = writeln!(
+                out,
+                "remote_juggler_cache_lookups_total{{cache=\"{}\",result=\"{}\"}} {}",
+                cache,
+                if *hit { "hit" } else { "miss" },
+                count
+            );
+        }
+
+        out.push_str(
+            "# HELP remote_juggler_backend_errors_total Failures reaching a secret store\n\
+             # TYPE remote_juggler_backend_errors_total counter\n",
+        );
+        for (backend, count) in &self.backend_errors {
+            let _ = writeln!(
+                out,
+                "remote_juggler_backend_errors_total{{backend=\"{}\"}} {}",
+                label(backend),
+                count
+            );
+        }
+
+        out.push_str(
+            "# HELP remote_juggler_keystore_state State of the KeePassXC key store\n\
+             # TYPE remote_juggler_keystore_state gauge\n",
+        );
+        for state in [
+            KeystoreState::Unlocked,
+            KeystoreState::Locked,
+            KeystoreState::Uninitialized,
+            KeystoreState::Unknown,
+        ] {
+            let _ = writeln!(
+                out,
+                "remote_juggler_keystore_state{{state=\"{}\"}} {}",
+                state.as_str(),
+                u8::from(state == keystore)
+            );
+        }
+        out
+    }
+}
+
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(Mutex::default)
+}
+
+fn with_registry(record: impl FnOnce(&mut Registry)) {
+    record(&mut registry().lock().unwrap_or_else(|e| e.into_inner()));
+}
+
+/// Count a CLI call, and a switch if it was one
+pub fn record_cli(args: &[String], elapsed: Duration, ok: bool) {
+    with_registry(|r| r.record_cli(args, elapsed, ok));
+}
+
+/// Count a lookup in `cache`: "cli", "setec" or "github_app"
+pub fn record_cache(cache: &'static str, hit: bool) {
+    with_registry(|r| r.record_cache(cache, hit));
+}
+
+/// Count a failure of a store, by its display name, e.g. "Setec" or "KDBX"
+pub fn record_backend_error(backend: &str) {
+    with_registry(|r| r.record_backend_error(backend));
+}
+
+/// The current metrics, with the key store's state; blocks
+pub fn scrape() -> String {
+    let keystore = match cli::run_cached(&cli::args(&["keys", "status"])) {
+        Ok(output) => KeystoreState::parse(&output),
+        Err(_) => KeystoreState::Unknown,
+    };
+    registry()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .render(keystore)
+}
+
+/// The body for a request, or the error to answer with
+pub fn respond(
+    request: &Request,
+    port: u16,
+    scrape: impl FnOnce() -> String,
+) -> Result<String, Response> {
+    if !rest_api::is_local(request, port) {
+        return Err(Response::error(403, "only local clients may read metrics"));
+    }
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/metrics") => Ok(scrape()),
+        (_, "/metrics") => Err(Response::error(405, "method not allowed")),
+        _ => Err(Response::error(404, "metrics are at /metrics")),
+    }
+}
+
+fn serve(stream: TcpStream, port: u16) {
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let result = rest_api::read_request(&mut BufReader::new(&stream))
+        .and_then(|request| respond(&request, port, scrape));
+    let written = match result {
+        Ok(body) => write!(
+            &mut &stream,
+            "HTTP/1.1 200 OK\r\n\
+             Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            body.len(),
+            body
+        ),
+        Err(response) => response.write_to(&mut &stream),
+    };
+    if let Err(e) = written {
+        tracing::debug!("Writing metrics failed: {}", e);
+    }
+}
+
+/// Listen on 127.0.0.1 at the configured port
+pub fn start(settings: &MetricsSettings) -> Result<Server, Error> {
+    let server = rest_api::listen(settings.port, "metrics", serve)?;
+    tracing::info!("Metrics on http://127.0.0.1:{}/metrics", server.port());
+    Ok(server)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_label() {
+        let label = |parts: &[&str]| command_label(&cli::args(parts));
+        assert_eq!(label(&["switch", "work"]), "switch");
+        assert_eq!(
+            label(&["keys", "get", "RemoteJuggler/API/token"]),
+            "keys get"
+        );
+        assert_eq!(label(&["keys", "RemoteJuggler/API/token"]), "keys");
+        assert_eq!(label(&["--version"]), "other");
+        assert_eq!(label(&[]), "");
+    }
+
+    #[test]
+    fn test_render() {
+        let mut registry = Registry::default();
+        let switch = cli::args(&["switch", "work"]);
+        registry.record_cli(&switch, Duration::from_millis(300), true);
+        registry.record_cli(&switch, Duration::from_secs(60), false);
+        registry.record_cli(
+            &cli::args(&["keys", "status"]),
+            Duration::from_millis(20),
+            true,
+        );
+        registry.record_cache("cli", true);
+        registry.record_cache("cli", true);
+        registry.record_cache("setec", false);
+        registry.record_backend_error("Setec");
+
+        let text = registry.render(KeystoreState::Locked);
+        for line in [
+            "remote_juggler_switches_total{result=\"ok\"} 1",
+            "remote_juggler_switches_total{result=\"error\"} 1",
+            "remote_juggler_cli_duration_seconds_bucket{command=\"switch\",result=\"ok\",le=\"0.25\"} 0",
+            "remote_juggler_cli_duration_seconds_bucket{command=\"switch\",result=\"ok\",le=\"0.5\"} 1",
+            "remote_juggler_cli_duration_seconds_bucket{command=\"switch\",result=\"error\",le=\"30\"} 0",
+            "remote_juggler_cli_duration_seconds_bucket{command=\"switch\",result=\"error\",le=\"+Inf\"} 1",
+            "remote_juggler_cli_duration_seconds_count{command=\"keys status\",result=\"ok\"} 1",
+            "remote_juggler_cache_lookups_total{cache=\"cli\",result=\"hit\"} 2",
+            "remote_juggler_cache_lookups_total{cache=\"setec\",result=\"miss\"} 1",
+            "remote_juggler_backend_errors_total{backend=\"Setec\"} 1",
+            "remote_juggler_keystore_state{state=\"locked\"} 1",
+            "remote_juggler_keystore_state{state=\"unlocked\"} 0",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {}", line);
+        }
+        assert!(!text.contains("work"));
+    }
+
+    #[test]
+    fn test_respond() {
+        let request = |raw: &str| rest_api::read_request(&mut raw.as_bytes()).unwrap();
+        let scrape = || "up 1\n".to_string();
+        let get = request("GET /metrics HTTP/1.1\r\nHost: 127.0.0.1:7828\r\n\r\n");
+        assert_eq!(respond(&get, 7828, scrape).unwrap(), "up 1\n");
+        let page = request("GET /metrics HTTP/1.1\r\nOrigin: https://evil.example\r\n\r\n");
+        assert_eq!(respond(&page, 7828, scrape).unwrap_err().status, 403);
+        let rebound = request("GET /metrics HTTP/1.1\r\nHost: evil.example:7828\r\n\r\n");
+        assert_eq!(respond(&rebound, 7828, scrape).unwrap_err().status, 403);
+        let post = request("POST /metrics HTTP/1.1\r\n\r\n");
+        assert_eq!(respond(&post, 7828, scrape).unwrap_err().status, 405);
+        let other = request("GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(respond(&other, 7828, scrape).unwrap_err().status, 404);
+    }
+}
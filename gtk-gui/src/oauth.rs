@@ -0,0 +1,417 @@
+//! Signing in with the OAuth device flow
+//!
+//! Instead of creating a personal access token by hand, an identity can
+//! sign in: the provider hands out a short code, the user enters it on
+//! the provider's page in a browser, and the token the provider then
+//! grants is stored at the identity's token entry, where every API call
+//! already looks. GitHub and GitLab support the device grant; both need
+//! an OAuth application, whose client ID is set on the host's
+//! `providerHosts` entry. Tokens that expire come with a refresh token,
+//! kept under `RemoteJuggler/Tokens/<Provider>/Refresh/`, and are renewed
+//! when an API call finds them about to run out.
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::cli::{self, CancelToken};
+use crate::config::{Identity, ProviderHost};
+use crate::error::Error;
+use crate::expiry;
+use crate::http;
+use crate::pat::{self, TokenInfo};
+use crate::provider_hosts;
+use crate::pubkeys::{self, Provider};
+
+/// Scopes asked for: repositories and the key APIs
+const GITHUB_SCOPES: &str = "repo read:org admin:public_key admin:gpg_key";
+const GITLAB_SCOPES: &str = "api";
+
+/// Tokens this close to expiring are refreshed before use
+pub const REFRESH_MARGIN: u64 = 5 * 60;
+
+/// Whether the provider supports the device grant
+pub fn supports(provider: Provider) -> bool {
+    matches!(provider, Provider::GitHub | Provider::GitLab)
+}
+
+/// The hostname an identity signs in on
+pub fn hostname(provider: Provider, identity: &Identity) -> String {
+    match identity.hostname.trim() {
+        "" if provider == Provider::GitLab => "gitlab.com".to_string(),
+        "" => "github.com".to_string(),
+        hostname => hostname.to_lowercase(),
+    }
+}
+
+/// The OAuth application's client ID for the host, if one is configured
+pub fn client_id(hosts: &[ProviderHost], hostname: &str) -> Option<String> {
+    provider_hosts::find(hosts, hostname)
+        .map(|host| host.oauth_client_id.trim().to_string())
+        .filter(|id| !id.is_empty())
+}
+
+/// The device authorization and token endpoints
+pub fn endpoints(provider: Provider, hostname: &str) -> Option<(String, String)> {
+    match provider {
+        Provider::GitHub => Some((
+            format!("https://{}/login/device/code", hostname),
+            format!("https://{}/login/oauth/access_token", hostname),
+        )),
+        Provider::GitLab => Some((
+            format!("https://{}/oauth/authorize_device", hostname),
+            format!("https://{}/oauth/token", hostname),
+        )),
+        _ => None,
+    }
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::InvalidOutput {
+        program: "OAuth",
+        message: message.into(),
+    }
+}
+
+/// A pending sign-in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceCode {
+    pub device_code: String,
+    /// What the user enters, e.g. `WDJB-MJHT`
+    pub user_code: String,
+    pub verification_uri: String,
+    /// The page with the code filled in, where the provider offers one
+    pub verification_uri_complete: Option<String>,
+    pub expires_in: u64,
+    /// Seconds between polls
+    pub interval: u64,
+}
+
+/// Parse the device authorization response
+pub fn parse_device_code(body: &Value) -> Result<DeviceCode, Error> {
+    let text = |key: &str| body[key].as_str().map(str::to_string);
+    let (Some(device_code), Some(user_code), Some(verification_uri)) = (
+        text("device_code"),
+        text("user_code"),
+        text("verification_uri"),
+    ) else {
+        return Err(invalid(error_text(body).unwrap_or_else(|| {
+            "the provider didn't return a device code".to_string()
+        })));
+    };
+    Ok(DeviceCode {
+        device_code,
+        user_code,
+        verification_uri,
+        verification_uri_complete: text("verification_uri_complete"),
+        expires_in: body["expires_in"].as_u64().unwrap_or(900),
+        interval: body["interval"].as_u64().unwrap_or(5).max(1),
+    })
+}
+
+/// A granted token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grant {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// Seconds until the access token expires; None if it doesn't
+    pub expires_in: Option<u64>,
+    pub scopes: Vec<String>,
+}
+
+/// How a token request was answered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Poll {
+    /// The user hasn't entered the code yet
+    Pending,
+    /// Polling too often; wait longer
+    SlowDown,
+    Granted(Grant),
+}
+
+fn error_text(body: &Value) -> Option<String> {
+    let error = body["error"].as_str()?;
+    Some(
+        body["error_description"]
+            .as_str()
+            .map_or_else(|| error.to_string(), str::to_string),
+    )
+}
+
+/// Parse a token response, success or OAuth error
+pub fn parse_token_response(body: &Value) -> Result<Poll, Error> {
+    match body["error"].as_str() {
+        Some("authorization_pending") => return Ok(Poll::Pending),
+        Some("slow_down") => return Ok(Poll::SlowDown),
+        Some("expired_token") => return Err(invalid("the code expired; sign in again")),
+        Some("access_denied") => return Err(invalid("the sign-in was denied")),
+        Some(_) => return Err(invalid(error_text(body).unwrap_or_default())),
+        None => {}
+    }
+    let access_token = body["access_token"]
+        .as_str()
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| invalid("the provider returned no token"))?;
+    // GitHub separates scopes with commas, GitLab with spaces
+    let scopes = body["scope"]
+        .as_str()
+        .unwrap_or_default()
+        .split([',', ' '])
+        .filter(|scope| !scope.is_empty())
+        .map(str::to_string)
+        .collect();
+    Ok(Poll::Granted(Grant {
+        access_token: access_token.to_string(),
+        refresh_token: body["refresh_token"]
+            .as_str()
+            .filter(|token| !token.is_empty())
+            .map(str::to_string),
+        expires_in: body["expires_in"].as_u64().filter(|&secs| secs > 0),
+        scopes,
+    }))
+}
+
+/// POST a form and read the JSON answer; OAuth errors come back with a
+/// 400 status from GitLab and a 200 from GitHub
+fn post_form(url: &str, form: &[(&str, &str)]) -> Result<Value, Error> {
+    let response = match http::agent()
+        .post(url)
+        .set("Accept", "application/json")
+        .send_form(form)
+    {
+        Ok(response) => response,
+        Err(ureq::Error::Status(400 | 401, response)) => response,
+        Err(e) => return Err(http::map_error(e)),
+    };
+    response.into_json().map_err(|e| invalid(e.to_string()))
+}
+
+/// The identity's provider, client ID and endpoints
+struct Target {
+    provider: Provider,
+    client_id: String,
+    device_url: String,
+    token_url: String,
+}
+
+fn target(identity: &Identity) -> Result<Target, Error> {
+    let provider = Provider::for_identity(identity)
+        .filter(|&provider| supports(provider))
+        .ok_or_else(|| invalid(format!("{} has no device sign-in", identity.provider)))?;
+    let hostname = hostname(provider, identity);
+    let client_id = client_id(&provider_hosts::configured(), &hostname).ok_or_else(|| {
+        invalid(format!(
+            "no OAuth client ID for {}; add one to its provider host",
+            hostname
+        ))
+    })?;
+    let (device_url, token_url) = endpoints(provider, &hostname).unwrap_or_default();
+    Ok(Target {
+        provider,
+        client_id,
+        device_url,
+        token_url,
+    })
+}
+
+/// Ask the provider for a code to show the user; blocks
+pub fn start(identity: &Identity) -> Result<DeviceCode, Error> {
+    let target = target(identity)?;
+    let scopes = match target.provider {
+        Provider::GitHub => GITHUB_SCOPES,
+        _ => GITLAB_SCOPES,
+    };
+    parse_device_code(&post_form(
+        &target.device_url,
+        &[("client_id", &target.client_id), ("scope", scopes)],
+    )?)
+}
+
+/// Where the refresh token for an identity is kept
+pub fn refresh_path(provider: Provider, name: &str) -> String {
+    format!(
+        "RemoteJuggler/Tokens/{}/Refresh/{}",
+        provider.display_name(),
+        name
+    )
+}
+
+fn store(path: &str, value: &str) -> Result<(), Error> {
+    cli::store_secret(path, value).map_err(Error::from_cli)
+}
+
+/// Store a grant at the identity's token entry and record its metadata;
+/// the entry written
+fn save(
+    name: &str,
+    identity: &Identity,
+    provider: Provider,
+    grant: &Grant,
+) -> Result<String, Error> {
+    let entry = pubkeys::token_paths(name, identity, provider)
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    store(&entry, &grant.access_token)?;
+    if let Some(refresh) = &grant.refresh_token {
+        store(&refresh_path(provider, name), refresh)?;
+    }
+    expiry::record_rotation(&entry);
+    let info = TokenInfo {
+        scopes: grant.scopes.clone(),
+        expires_at: grant.expires_in.map(|secs| expiry::now() + secs),
+    };
+    pat::record(provider, name, &info, Some(&entry), Some("oauth"))?;
+    Ok(entry)
+}
+
+/// Poll until the user has entered the code, then store the token; the
+/// entry it was stored at. Blocks
+pub fn finish(
+    name: &str,
+    identity: &Identity,
+    code: &DeviceCode,
+    cancel: &CancelToken,
+) -> Result<String, Error> {
+    let target = target(identity)?;
+    let mut interval = code.interval;
+    let mut waited = 0;
+    loop {
+        // Sleep in steps so cancelling doesn't wait out the interval
+        for _ in 0..interval {
+            if cancel.is_cancelled() {
+                return Err(invalid("the sign-in was cancelled"));
+            }
+            std::thread::sleep(Duration::from_secs(1));
+        }
+        waited += interval;
+        let body = post_form(
+            &target.token_url,
+            &[
+                ("client_id", &target.client_id),
+                ("device_code", &code.device_code),
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+            ],
+        )?;
+        match parse_token_response(&body)? {
+            Poll::Granted(grant) => return save(name, identity, target.provider, &grant),
+            Poll::SlowDown => interval += 5,
+            Poll::Pending => {}
+        }
+        if waited >= code.expires_in {
+            return Err(invalid("the code expired; sign in again"));
+        }
+    }
+}
+
+/// Exchange the stored refresh token for a new access token; blocks
+pub fn refresh(name: &str, identity: &Identity) -> Result<String, Error> {
+    let target = target(identity)?;
+    let path = refresh_path(target.provider, name);
+    let refresh_token = cli::run(&cli::args(&["keys", "get", &path]))
+        .map_err(Error::from_cli)?
+        .trim()
+        .to_string();
+    if refresh_token.is_empty() {
+        return Err(invalid(format!("no refresh token at {}", path)));
+    }
+    let body = post_form(
+        &target.token_url,
+        &[
+            ("client_id", &target.client_id),
+            ("grant_type", "refresh_token"),
+            ("refresh_token", &refresh_token),
+        ],
+    )?;
+    match parse_token_response(&body)? {
+        Poll::Granted(grant) => save(name, identity, target.provider, &grant),
+        _ => Err(invalid("the refresh wasn't granted")),
+    }
+}
+
+/// Whether recorded metadata says an OAuth token is about to run out
+pub fn is_due(meta: &pat::TokenMeta, now: u64) -> bool {
+    meta.token_type == "oauth" && meta.expires().is_some_and(|at| at <= now + REFRESH_MARGIN)
+}
+
+/// Refresh the identity's token first if it is a signed-in one about to
+/// expire; blocks
+pub fn refresh_if_due(name: &str, identity: &Identity, provider: Provider) -> Result<(), Error> {
+    let due = pat::TokenFile::load()
+        .get(provider, name)
+        .is_some_and(|meta| is_due(meta, expiry::now()));
+    if due {
+        refresh(name, identity)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_device_flow_responses() {
+        let code = parse_device_code(&json!({
+            "device_code": "3584d83530557fdd1f46af8289938c8ef79f9dc5",
+            "user_code": "WDJB-MJHT",
+            "verification_uri": "https://github.com/login/device",
+            "expires_in": 900, "interval": 5
+        }))
+        .unwrap();
+        assert_eq!(code.user_code, "WDJB-MJHT");
+        assert_eq!(code.verification_uri_complete, None);
+        assert!(parse_device_code(&json!({ "error": "unauthorized_client" })).is_err());
+
+        assert_eq!(
+            parse_token_response(&json!({ "error": "authorization_pending" })).unwrap(),
+            Poll::Pending
+        );
+        assert_eq!(
+            parse_token_response(&json!({ "error": "slow_down", "interval": 10 })).unwrap(),
+            Poll::SlowDown
+        );
+        assert!(parse_token_response(&json!({ "error": "access_denied" })).is_err());
+        assert_eq!(
+            parse_token_response(&json!({
+                "access_token": "glo-abc", "token_type": "Bearer", "expires_in": 7200,
+                "refresh_token": "glr-def", "scope": "api read_user"
+            }))
+            .unwrap(),
+            Poll::Granted(Grant {
+                access_token: "glo-abc".to_string(),
+                refresh_token: Some("glr-def".to_string()),
+                expires_in: Some(7200),
+                scopes: vec!["api".to_string(), "read_user".to_string()],
+            })
+        );
+        let Poll::Granted(github) = parse_token_response(&json!({
+            "access_token": "gho_abc", "token_type": "bearer", "scope": "repo,read:org"
+        }))
+        .unwrap() else {
+            panic!("expected a grant");
+        };
+        assert_eq!((github.refresh_token, github.expires_in), (None, None));
+
+        let hosts: Vec<ProviderHost> = serde_json::from_value(json!([
+            { "hostname": "gitlab.com", "provider": "gitlab", "oauthClientId": " abc123 " }
+        ]))
+        .unwrap();
+        assert_eq!(client_id(&hosts, "GitLab.com").as_deref(), Some("abc123"));
+        assert_eq!(client_id(&hosts, "github.com"), None);
+        assert_eq!(
+            endpoints(Provider::GitLab, "gitlab.com").unwrap().1,
+            "https://gitlab.com/oauth/token"
+        );
+        assert_eq!(endpoints(Provider::Gitea, "codeberg.org"), None);
+
+        let meta = pat::TokenMeta {
+            token_type: "oauth".to_string(),
+            expires_at: 1_000_000.0,
+            ..Default::default()
+        };
+        assert!(is_due(&meta, 1_000_000 - 60));
+        assert!(!is_due(&meta, 1_000_000 - 3600));
+    }
+}
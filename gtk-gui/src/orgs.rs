@@ -0,0 +1,369 @@
+//! Organization membership discovery
+//!
+//! Detection matches a remote to the identity listing its organization,
+//! so the list has to be kept up to date by hand as accounts join orgs and
+//! groups. Discovery asks each identity's provider which organizations
+//! (GitLab groups, Bitbucket workspaces) its account belongs to and adds
+//! the missing ones; an organization another identity on the same host
+//! also belongs to is left for the user to assign, since either could
+//! own it. Manually listed organizations are never removed. From the
+//! repositories under the workspace roots it then suggests directory
+//! rules: a directory whose clones all belong to one identity's
+//! organizations can be given to that identity.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::config::{Config, DirectoryRule, Identity};
+use crate::detect::{self, Reason};
+use crate::error::Error;
+use crate::platform;
+use crate::provider_api;
+use crate::provider_hosts;
+use crate::pubkeys::{self, Provider};
+use crate::workspace;
+
+/// The memberships listing for a provider, relative to its API root;
+/// sourcehut has no organizations
+pub fn orgs_path(provider: Provider) -> Option<&'static str> {
+    match provider {
+        Provider::GitHub => Some("/user/orgs?per_page=100"),
+        // Guest access and up, so every group the account can see into
+        Provider::GitLab => Some("/groups?min_access_level=10&per_page=100"),
+        Provider::Gitea => Some("/user/orgs?limit=50"),
+        Provider::Bitbucket => Some("/user/permissions/workspaces?pagelen=100"),
+        Provider::Sourcehut => None,
+    }
+}
+
+fn normalize(org: &str) -> String {
+    org.trim()
+        .trim_matches('/')
+        .trim_start_matches('~')
+        .to_lowercase()
+}
+
+/// Whether a listed organization already covers `org`, which is the case
+/// for a GitLab subgroup of a listed group
+pub fn covers(listed: &str, org: &str) -> bool {
+    let (listed, org) = (normalize(listed), normalize(org));
+    !listed.is_empty() && (org == listed || org.starts_with(&format!("{}/", listed)))
+}
+
+/// Organization names in a memberships response, sorted; subgroups of a
+/// group that is also listed are left out
+pub fn parse_orgs(provider: Provider, body: &Value) -> Vec<String> {
+    let items = match provider {
+        Provider::Bitbucket => body["values"].as_array(),
+        _ => body.as_array(),
+    };
+    let mut orgs: Vec<String> = items
+        .into_iter()
+        .flatten()
+        .filter_map(|item| match provider {
+            Provider::GitHub => item["login"].as_str(),
+            Provider::GitLab => item["full_path"].as_str(),
+            Provider::Gitea => item["username"].as_str().or(item["name"].as_str()),
+            Provider::Bitbucket => item["workspace"]["slug"].as_str(),
+            Provider::Sourcehut => None,
+        })
+        .map(|org| org.trim_matches('/').to_string())
+        .filter(|org| !org.is_empty())
+        .collect();
+    orgs.sort();
+    orgs.dedup();
+    let all = orgs.clone();
+    orgs.retain(|org| !all.iter().any(|other| other != org && covers(other, org)));
+    orgs
+}
+
+/// The organizations an identity's account belongs to; blocks
+pub fn memberships(name: &str, identity: &Identity) -> Result<Vec<String>, Error> {
+    let provider = Provider::for_identity(identity).ok_or_else(|| Error::InvalidOutput {
+        program: "config",
+        message: format!("{} has no organization API", identity.provider),
+    })?;
+    let Some(path) = orgs_path(provider) else {
+        return Ok(Vec::new());
+    };
+    let token = pubkeys::find_token(name, identity, provider)?;
+    let base =
+        provider_hosts::api_base(&provider_hosts::configured(), provider, &identity.hostname);
+    let body = provider_api::Client::new(provider, &base, Some(&token))
+        .get_json(&format!("{}{}", base, path))?;
+    Ok(parse_orgs(provider, &body))
+}
+
+/// What discovery found for one identity
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Discovery {
+    pub identity: String,
+    /// Organizations to add to the identity
+    pub added: Vec<String>,
+    /// Organizations another identity on the same host also belongs to or
+    /// already lists; left alone
+    pub shared: Vec<String>,
+}
+
+impl Discovery {
+    pub fn display_text(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.added.is_empty() {
+            parts.push(format!("adds {}", self.added.join(", ")));
+        }
+        if !self.shared.is_empty() {
+            parts.push(format!("shared, not added: {}", self.shared.join(", ")));
+        }
+        if parts.is_empty() {
+            return format!("{}: up to date", self.identity);
+        }
+        format!("{}: {}", self.identity, parts.join("; "))
+    }
+}
+
+/// Decide what to add from each identity's memberships, by identity name
+pub fn plan(config: &Config, found: &BTreeMap<String, Vec<String>>) -> Vec<Discovery> {
+    let same_host = |a: &str, b: &str| {
+        let (a, b) = (&config.identities[a], &config.identities[b]);
+        a.hostname.eq_ignore_ascii_case(&b.hostname)
+    };
+    found
+        .iter()
+        .filter(|(name, _)| config.identities.contains_key(name.as_str()))
+        .map(|(name, orgs)| {
+            let identity = &config.identities[name];
+            let mut discovery = Discovery {
+                identity: name.clone(),
+                ..Default::default()
+            };
+            for org in orgs {
+                if identity.organizations.iter().any(|o| covers(o, org)) {
+                    continue;
+                }
+                let claimed = config.identities.iter().any(|(other, i)| {
+                    other != name
+                        && same_host(name, other)
+                        && (i.organizations.iter().any(|o| covers(o, org))
+                            || found
+                                .get(other)
+                                .is_some_and(|orgs| orgs.iter().any(|o| covers(o, org))))
+                });
+                if claimed {
+                    discovery.shared.push(org.clone());
+                } else {
+                    discovery.added.push(org.clone());
+                }
+            }
+            discovery
+        })
+        .collect()
+}
+
+/// Add the discovered organizations to their identities
+pub fn apply(config: &mut Config, discoveries: &[Discovery]) {
+    for discovery in discoveries {
+        if let Some(identity) = config.identities.get_mut(&discovery.identity) {
+            for org in &discovery.added {
+                if !identity.organizations.iter().any(|o| covers(o, org)) {
+                    identity.organizations.push(org.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Ask every identity's provider for its memberships; identities without
+/// a provider API or a token are skipped, other failures reported by
+/// identity. Blocks
+pub fn discover(config: &Config) -> (Vec<Discovery>, Vec<(String, Error)>) {
+    let mut found = BTreeMap::new();
+    let mut failed = Vec::new();
+    for name in config.identity_names() {
+        let identity = &config.identities[&name];
+        if Provider::for_identity(identity).is_none() {
+            continue;
+        }
+        match memberships(&name, identity) {
+            Ok(orgs) => {
+                found.insert(name, orgs);
+            }
+            Err(Error::InvalidOutput {
+                program: "token lookup",
+                ..
+            }) => {}
+            Err(e) => failed.push((name, e)),
+        }
+    }
+    (plan(config, &found), failed)
+}
+
+/// Directory rules suggested by the clones in each directory: one whose
+/// repositories are all matched to the same identity by organization,
+/// isn't already covered by a rule and isn't a workspace root or home
+pub fn suggest_rules(
+    config: &Config,
+    repos: &[(PathBuf, Vec<(String, String)>)],
+    hostnames: &BTreeMap<String, String>,
+) -> Vec<DirectoryRule> {
+    let mut excluded: Vec<PathBuf> = config
+        .settings
+        .workspace_roots
+        .iter()
+        .map(|root| platform::expand_tilde(root))
+        .collect();
+    excluded.extend(platform::home_dir());
+
+    let mut by_dir: BTreeMap<&Path, Vec<Option<String>>> = BTreeMap::new();
+    for (repo, remotes) in repos {
+        let Some(dir) = repo.parent() else {
+            continue;
+        };
+        if config.settings.directory_rule(repo).is_some() || excluded.iter().any(|e| e == dir) {
+            continue;
+        }
+        let owner = detect::detect_with(config, remotes, hostnames)
+            .filter(|found| found.reason == Reason::Organization)
+            .map(|found| found.identity);
+        by_dir.entry(dir).or_default().push(owner);
+    }
+    by_dir
+        .into_iter()
+        .filter_map(|(dir, owners)| {
+            let first = owners.first()?.clone()?;
+            owners
+                .iter()
+                .all(|owner| owner.as_ref() == Some(&first))
+                .then(|| DirectoryRule {
+                    path: platform::tilde_path(dir),
+                    identity: first,
+                })
+        })
+        .collect()
+}
+
+/// Suggest directory rules from the repositories under the workspace
+/// roots; repositories git can't read are skipped. Blocks
+pub fn suggest(config: &Config) -> Vec<DirectoryRule> {
+    let repos: Vec<(PathBuf, Vec<(String, String)>)> = workspace::repos(config)
+        .into_iter()
+        .filter_map(|repo| {
+            let remotes = detect::remotes(&repo).ok()?;
+            Some((repo, remotes))
+        })
+        .collect();
+    suggest_rules(config, &repos, &detect::ssh_hostnames())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use serde_json::json;
+
+    #[test]
+    fn test_discovery_and_rule_suggestions() {
+        let groups = json!([
+            { "full_path": "acme" },
+            { "full_path": "acme/platform" },
+            { "full_path": "oss-tools" },
+        ]);
+        assert_eq!(
+            parse_orgs(Provider::GitLab, &groups),
+            vec!["acme".to_string(), "oss-tools".to_string()]
+        );
+        let workspaces = json!({ "values": [{ "workspace": { "slug": "acme" } }] });
+        assert_eq!(parse_orgs(Provider::Bitbucket, &workspaces), vec!["acme"]);
+        assert!(covers("Acme", "acme/platform"));
+        assert!(!covers("acme", "acme-labs"));
+
+        let mut config = ConfigBuilder::new()
+            .identity(
+                "work",
+                Identity {
+                    organizations: vec!["acme".to_string()],
+                    ..identity("gitlab", "gitlab-work", "gitlab.com", "Work", "me@acme.dev")
+                },
+            )
+            .identity(
+                "personal",
+                identity(
+                    "gitlab",
+                    "gitlab-personal",
+                    "gitlab.com",
+                    "Me",
+                    "me@example.com",
+                ),
+            )
+            .build();
+        let found = BTreeMap::from([
+            (
+                "work".to_string(),
+                vec!["acme".to_string(), "shared-ci".to_string()],
+            ),
+            (
+                "personal".to_string(),
+                vec![
+                    "acme".to_string(),
+                    "dotfiles".to_string(),
+                    "shared-ci".to_string(),
+                ],
+            ),
+        ]);
+        let discoveries = plan(&config, &found);
+        assert_eq!(
+            discoveries,
+            vec![
+                Discovery {
+                    identity: "personal".to_string(),
+                    added: vec!["dotfiles".to_string()],
+                    shared: vec!["acme".to_string(), "shared-ci".to_string()],
+                },
+                Discovery {
+                    identity: "work".to_string(),
+                    added: vec![],
+                    shared: vec!["shared-ci".to_string()],
+                },
+            ]
+        );
+        apply(&mut config, &discoveries);
+        assert_eq!(
+            config.identities["personal"].organizations,
+            vec!["dotfiles"]
+        );
+        assert_eq!(config.identities["work"].organizations, vec!["acme"]);
+
+        let remote = |url: &str| vec![("origin".to_string(), url.to_string())];
+        let repos = vec![
+            (
+                PathBuf::from("/src/acme/api"),
+                remote("git@gitlab.com:acme/api.git"),
+            ),
+            (
+                PathBuf::from("/src/acme/web"),
+                remote("https://gitlab.com/acme/platform/web"),
+            ),
+            (
+                PathBuf::from("/src/mixed/api"),
+                remote("git@gitlab.com:acme/api.git"),
+            ),
+            (
+                PathBuf::from("/src/mixed/notes"),
+                remote("git@gitlab.com:dotfiles/notes.git"),
+            ),
+        ];
+        assert_eq!(
+            suggest_rules(&config, &repos, &BTreeMap::new()),
+            vec![DirectoryRule {
+                path: "/src/acme".to_string(),
+                identity: "work".to_string(),
+            }]
+        );
+        config.settings.directory_rules.push(DirectoryRule {
+            path: "/src".to_string(),
+            identity: "personal".to_string(),
+        });
+        assert!(suggest_rules(&config, &repos, &BTreeMap::new()).is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+//! pass (password-store) backend
+//!
+//! Reads and writes a gpg-encrypted password-store, mounted into the key
+//! namespace as `Pass/...`, so users migrating from pass can search and copy
+//! their secrets from the same place as KDBX entries.
+//!
+//! Entries are listed by walking the store directly; reads and writes go
+//! through the `pass` binary so gpg-agent prompts, `.gpg-id` recipients, and
+//! the store's git history keep working as usual.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::backend::SecretBackend;
+use crate::cli::{self, RunOptions};
+use crate::error::Error;
+
+/// Namespace prefix for password-store entries
+pub const NAMESPACE: &str = "Pass";
+
+// Decrypting may wait on a pinentry prompt or a YubiKey touch
+const PASS_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A password-store directory
+#[derive(Debug, Clone)]
+pub struct PassBackend {
+    store_dir: PathBuf,
+}
+
+impl PassBackend {
+    pub fn new(store_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            store_dir: store_dir.into(),
+        }
+    }
+
+    /// The store pass itself would use: `$PASSWORD_STORE_DIR` or
+    /// `~/.password-store`
+    pub fn from_env() -> Self {
+        let dir = std::env::var_os("PASSWORD_STORE_DIR")
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|h| h.join(".password-store")))
+            .unwrap_or_else(|| PathBuf::from(".password-store"));
+        Self::new(dir)
+    }
+
+    pub fn store_dir(&self) -> &Path {
+        &self.store_dir
+    }
+
+    fn run_pass(&self, args: &[String], input: Option<&str>) -> Result<String, Error> {
+        let program =
+            std::env::var("REMOTE_JUGGLER_PASS_PATH").unwrap_or_else(|_| "pass".to_string());
+        let options = RunOptions {
+            timeout: PASS_TIMEOUT,
+            retries: 0,
+            cancel: None,
+        };
+        // pass reads the store location from the environment
+        let store = self.store_dir.to_string_lossy().to_string();
+        let env = [("PASSWORD_STORE_DIR", store.as_str())];
+        let result = match input {
+            Some(input) => cli::run_program_with_input(&program, args, &env, input, &options),
+            None => async_io::block_on(cli::run_program_streaming(
+                &program,
+                args,
+                &env,
+                &options,
+                |_| {},
+            )),
+        };
+        result.map_err(Error::from_cli)
+    }
+}
+
+/// Collect `*.gpg` entries below `dir`, skipping hidden directories (`.git`)
+fn collect_entries(root: &Path, dir: &Path, out: &mut Vec<String>) {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if path.is_dir() {
+            if !hidden {
+                collect_entries(root, &path, out);
+            }
+        } else if path.extension().is_some_and(|e| e == "gpg") {
+            if let Ok(relative) = path.with_extension("").strip_prefix(root) {
+                out.push(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+}
+
+/// Replace the first line (the password, by pass convention) of an entry,
+/// keeping any metadata lines below it
+pub fn replace_password(existing: &str, password: &str) -> String {
+    match existing.split_once('\n') {
+        Some((_, rest)) if !rest.is_empty() => format!("{}\n{}", password, rest),
+        _ => format!("{}\n", password),
+    }
+}
+
+impl SecretBackend for PassBackend {
+    fn namespace(&self) -> &'static str {
+        NAMESPACE
+    }
+
+    fn display_name(&self) -> &'static str {
+        "pass"
+    }
+
+    fn is_available(&self) -> bool {
+        self.store_dir.join(".gpg-id").is_file()
+    }
+
+    fn list(&self) -> Result<Vec<String>, Error> {
+        let mut entries = Vec::new();
+        collect_entries(&self.store_dir, &self.store_dir, &mut entries);
+        entries.sort();
+        Ok(entries)
+    }
+
+    /// The first line of the entry, as `pass show -c` would copy
+    fn get(&self, name: &str) -> Result<String, Error> {
+        let output = self.run_pass(&cli::args(&["show", name]), None)?;
+        Ok(output.lines().next().unwrap_or_default().to_string())
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), Error> {
+        let existing = if self.store_dir.join(format!("{}.gpg", name)).is_file() {
+            self.run_pass(&cli::args(&["show", name]), None)?
+        } else {
+            String::new()
+        };
+        let contents = replace_password(&existing, value);
+        self.run_pass(
+            &cli::args(&["insert", "--multiline", "--force", name]),
+            Some(&contents),
+        )
+        .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join(".gpg-id"), "ABCDEF\n").unwrap();
+        std::fs::create_dir_all(root.join("work")).unwrap();
+        std::fs::create_dir_all(root.join(".git/objects")).unwrap();
+        std::fs::write(root.join("work/github.gpg"), b"").unwrap();
+        std::fs::write(root.join("personal.gpg"), b"").unwrap();
+        std::fs::write(root.join(".git/objects/stray.gpg"), b"").unwrap();
+        std::fs::write(root.join("work/notes.txt"), b"").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_list_entries() {
+        let dir = store();
+        let backend = PassBackend::new(dir.path());
+        assert!(backend.is_available());
+        assert_eq!(backend.list().unwrap(), vec!["personal", "work/github"]);
+    }
+
+    #[test]
+    fn test_search_entries() {
+        let dir = store();
+        let backend = PassBackend::new(dir.path());
+        assert_eq!(backend.search("gh").unwrap(), vec!["work/github"]);
+    }
+
+    #[test]
+    fn test_missing_store_is_unavailable() {
+        let backend = PassBackend::new("/nonexistent/password-store");
+        assert!(!backend.is_available());
+        assert!(backend.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_replace_password_keeps_metadata() {
+        assert_eq!(
+            replace_password("old\nuser: octocat\n", "new"),
+            "new\nuser: octocat\n"
+        );
+        assert_eq!(replace_password("", "new"), "new\n");
+        assert_eq!(replace_password("old\n", "new"), "new\n");
+    }
+}
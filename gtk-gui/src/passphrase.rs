@@ -0,0 +1,251 @@
+//! SSH key passphrase rotation
+//!
+//! The key is rewrapped with `ssh-keygen -p` in a private copy next to the
+//! original, and the copy only replaces the original once the new
+//! passphrase is shown to unlock it to the same public key. Passphrases
+//! reach ssh-keygen and ssh-add through a one-off askpass helper, never the
+//! command line.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::agent::{self, Constraints};
+use crate::cli::{self, RunOptions};
+use crate::config::Identity;
+use crate::databases::expand_tilde;
+use crate::error::Error;
+use crate::fido2::ssh_keygen_program;
+use crate::pubkeys::key_id;
+
+/// Environment variables the askpass helper reads passphrases from
+const OLD_ENV: &str = "REMOTE_JUGGLER_OLD_PASSPHRASE";
+const NEW_ENV: &str = "REMOTE_JUGGLER_NEW_PASSPHRASE";
+
+/// Answers ssh-keygen's "Enter old passphrase" with the old one and every
+/// other prompt with the new one
+const ASKPASS: &str = "#!/bin/sh\n\
+    case \"$1\" in\n\
+    *[Oo]ld*) printf '%s\\n' \"$REMOTE_JUGGLER_OLD_PASSPHRASE\" ;;\n\
+    *) printf '%s\\n' \"$REMOTE_JUGGLER_NEW_PASSPHRASE\" ;;\n\
+    esac\n";
+
+/// Where a rotated passphrase is stored in the key store
+pub fn entry_path(key: &Path) -> String {
+    let name = key
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    format!("RemoteJuggler/SSH/Passphrases/{}", name)
+}
+
+/// Check a new passphrase before touching the key
+pub fn validate(old: &str, new: &str, confirm: &str) -> Result<(), &'static str> {
+    if new.is_empty() {
+        return Err("Enter a new passphrase");
+    }
+    if new != confirm {
+        return Err("The new passphrases don't match");
+    }
+    if new == old {
+        return Err("The new passphrase is the same as the current one");
+    }
+    Ok(())
+}
+
+/// A private directory next to the key, so the rewrapped copy can be
+/// renamed over it; removed on drop
+pub struct Workspace {
+    dir: PathBuf,
+}
+
+impl Workspace {
+    pub fn create(key: &Path) -> Result<Self, Error> {
+        let parent = key.parent().unwrap_or(Path::new("."));
+        let dir = parent.join(format!(".remote-juggler-rekey-{}", std::process::id()));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder.create(&dir)?;
+        let workspace = Self { dir };
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o700);
+        }
+        use std::io::Write;
+        options
+            .open(workspace.askpass())?
+            .write_all(ASKPASS.as_bytes())?;
+        Ok(workspace)
+    }
+
+    fn askpass(&self) -> PathBuf {
+        self.dir.join("askpass")
+    }
+
+    /// Scratch path for a key inside the workspace
+    pub fn key(&self) -> PathBuf {
+        self.dir.join("key")
+    }
+
+    /// Run a program that prompts through the askpass helper
+    pub fn run(
+        &self,
+        program: &str,
+        args: &[String],
+        old: &str,
+        new: &str,
+    ) -> Result<String, Error> {
+        let askpass = self.askpass().to_string_lossy().into_owned();
+        let env = [
+            ("SSH_ASKPASS", askpass.as_str()),
+            ("SSH_ASKPASS_REQUIRE", "force"),
+            (OLD_ENV, old),
+            (NEW_ENV, new),
+        ];
+        let options = RunOptions {
+            timeout: Duration::from_secs(60),
+            retries: 0,
+            cancel: None,
+        };
+        async_io::block_on(cli::run_program_streaming(
+            program,
+            args,
+            &env,
+            &options,
+            |_| {},
+        ))
+        .map_err(Error::from_cli)
+    }
+
+    /// The public key `passphrase` unlocks `key` to
+    pub fn public_key(&self, key: &Path, passphrase: &str) -> Result<String, Error> {
+        let args = vec!["-y".to_string(), "-f".to_string(), path_arg(key)];
+        self.run(&ssh_keygen_program(), &args, "", passphrase)
+            .map(|out| out.trim().to_string())
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn path_arg(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// What a rotation did besides rewrapping the key
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rotation {
+    /// The agent held the key and it was re-added with the new passphrase
+    pub agent_reloaded: bool,
+    /// Key store entry the new passphrase was saved to
+    pub stored: Option<String>,
+}
+
+impl Rotation {
+    pub fn summary(&self) -> String {
+        let mut text = "Passphrase changed".to_string();
+        if self.agent_reloaded {
+            text.push_str("; key reloaded into ssh-agent");
+        }
+        if let Some(path) = &self.stored {
+            text.push_str(&format!("; saved to {}", path));
+        }
+        text
+    }
+}
+
+/// Rewrap the identity's private key with a new passphrase; blocks
+///
+/// The original file is untouched unless the old passphrase unlocks it and
+/// the new one unlocks the rewrapped copy to the same key.
+pub fn rotate(identity: &Identity, old: &str, new: &str, store: bool) -> Result<Rotation, Error> {
+    let key = expand_tilde(&identity.ssh_key_path);
+    let workspace = Workspace::create(&key)?;
+
+    let original = workspace
+        .public_key(&key, old)
+        .map_err(|_| Error::InvalidOutput {
+            program: "ssh-keygen",
+            message: "the current passphrase doesn't unlock the key".to_string(),
+        })?;
+
+    let copy = workspace.key();
+    fs::copy(&key, &copy)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&copy, fs::Permissions::from_mode(0o600))?;
+    }
+    let args = vec!["-p".to_string(), "-f".to_string(), path_arg(&copy)];
+    workspace.run(&ssh_keygen_program(), &args, old, new)?;
+
+    let rewrapped = workspace.public_key(&copy, new)?;
+    if key_id(&rewrapped).is_none() || key_id(&rewrapped) != key_id(&original) {
+        return Err(Error::InvalidOutput {
+            program: "ssh-keygen",
+            message: "the new passphrase doesn't unlock the rewrapped key".to_string(),
+        });
+    }
+    fs::rename(&copy, &key)?;
+
+    let mut rotation = Rotation::default();
+    // The agent holds the unwrapped key, so reloading it is only a check
+    // that the new passphrase works the way ssh will use it
+    let fingerprint = agent::fingerprint(&key)?;
+    if agent::list()?.is_some_and(|keys| keys.iter().any(|k| k.fingerprint == fingerprint)) {
+        let mut args = Constraints::for_identity(identity).args();
+        args.push(path_arg(&key));
+        workspace.run(&agent::ssh_add_program(), &args, "", new)?;
+        rotation.agent_reloaded = true;
+    }
+    if store {
+        let path = entry_path(&key);
+        cli::store_secret(&path, new).map_err(Error::from_cli)?;
+        rotation.stored = Some(path);
+    }
+    Ok(rotation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("old", "", "").is_err());
+        assert_eq!(
+            validate("old", "new pass", "new pas"),
+            Err("The new passphrases don't match")
+        );
+        assert!(validate("same", "same", "same").is_err());
+        assert_eq!(validate("", "new pass", "new pass"), Ok(()));
+    }
+
+    #[test]
+    fn test_entry_path_and_summary() {
+        assert_eq!(
+            entry_path(Path::new("/home/a/.ssh/id_ed25519_work")),
+            "RemoteJuggler/SSH/Passphrases/id_ed25519_work"
+        );
+        let rotation = Rotation {
+            agent_reloaded: true,
+            stored: Some("RemoteJuggler/SSH/Passphrases/k".to_string()),
+        };
+        assert_eq!(
+            rotation.summary(),
+            "Passphrase changed; key reloaded into ssh-agent; saved to \
+             RemoteJuggler/SSH/Passphrases/k"
+        );
+        assert_eq!(Rotation::default().summary(), "Passphrase changed");
+    }
+}
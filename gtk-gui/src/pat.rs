@@ -0,0 +1,514 @@
+//! Personal access token lifecycle
+//!
+//! An identity's token lives in the key store; what is known about it,
+//! its scopes and expiry, is kept with the CLI's token metadata in
+//! `~/.config/remote-juggler/tokens.json`, which the rotation reminders
+//! already read. A check asks the provider what the token can do and when
+//! it runs out. Rotating replaces it everywhere the old one is stored, the
+//! identity's entry and any other identity's entry holding the same
+//! token: GitLab issues the replacement through its API, other providers
+//! take one created on their settings page.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::cli;
+use crate::config::{Config, Identity};
+use crate::error::Error;
+use crate::expiry;
+use crate::platform;
+use crate::provider_api;
+use crate::provider_hosts;
+use crate::pubkeys::{self, Provider};
+
+/// What tokens.json records for one token
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TokenMeta {
+    pub identity_name: String,
+    pub provider: String,
+    /// Unix seconds, as the CLI writes them
+    pub created_at: f64,
+    pub last_verified: f64,
+    /// 0 when unknown
+    pub expires_at: f64,
+    pub scopes: Vec<String>,
+    pub token_type: String,
+    pub is_valid: bool,
+    pub warning_issued: f64,
+    /// The key store entry the token was last stored at
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub entry: String,
+    /// Fields another version wrote, kept as they are
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl TokenMeta {
+    pub fn expires(&self) -> Option<u64> {
+        (self.expires_at > 0.0).then_some(self.expires_at as u64)
+    }
+
+    /// e.g. "Expires 2026-03-01 (Due in 12 days) · api, read_user"
+    pub fn display_text(&self, now: u64) -> String {
+        let mut text = match self.expires() {
+            Some(at) => match expiry::Urgency::within(at, now, 30) {
+                Some(urgency) => format!(
+                    "Expires {} ({})",
+                    expiry::format_date(at),
+                    urgency.display_text()
+                ),
+                None => format!("Expires {}", expiry::format_date(at)),
+            },
+            None => "No known expiry".to_string(),
+        };
+        if !self.is_valid {
+            text.push_str(", rejected at the last check");
+        }
+        if !self.scopes.is_empty() {
+            text.push_str(&format!(" · {}", self.scopes.join(", ")));
+        }
+        text
+    }
+}
+
+/// tokens.json
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenFile {
+    pub version: String,
+    /// Keyed by `provider:identity`
+    pub tokens: BTreeMap<String, TokenMeta>,
+}
+
+impl TokenFile {
+    pub fn load() -> Self {
+        platform::load_json_state(expiry::token_metadata_path())
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let mut file = self.clone();
+        if file.version.is_empty() {
+            file.version = "1.0".to_string();
+        }
+        Ok(platform::save_json_state(
+            expiry::token_metadata_path(),
+            &file,
+        )?)
+    }
+
+    pub fn get(&self, provider: Provider, name: &str) -> Option<&TokenMeta> {
+        self.tokens.get(&key(provider, name))
+    }
+}
+
+/// The tokens.json key for an identity, e.g. "github:personal"
+pub fn key(provider: Provider, name: &str) -> String {
+    format!("{}:{}", provider.name(), name)
+}
+
+/// What a provider says about a token
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenInfo {
+    pub scopes: Vec<String>,
+    /// None when the provider doesn't say or it never expires
+    pub expires_at: Option<u64>,
+}
+
+/// GitHub's `github-authentication-token-expiration` header, e.g.
+/// `2026-03-01 12:00:00 UTC`
+pub fn parse_github_expiry(header: &str) -> Option<u64> {
+    expiry::parse_date(header.trim().get(..10)?)
+}
+
+/// A comma-separated scope list, as GitHub's `x-oauth-scopes`
+pub fn parse_scope_list(header: &str) -> Vec<String> {
+    header
+        .split(',')
+        .map(str::trim)
+        .filter(|scope| !scope.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// GitLab's token description, from `personal_access_tokens/self` or a
+/// rotation
+pub fn parse_gitlab_token(body: &Value) -> TokenInfo {
+    TokenInfo {
+        scopes: body["scopes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        expires_at: body["expires_at"].as_str().and_then(expiry::parse_date),
+    }
+}
+
+fn api_base(provider: Provider, identity: &Identity) -> String {
+    provider_hosts::api_base(&provider_hosts::configured(), provider, &identity.hostname)
+}
+
+/// Ask the provider about a token; fails if it is rejected. Blocks
+pub fn inspect(provider: Provider, base: &str, token: &str) -> Result<TokenInfo, Error> {
+    let client = provider_api::Client::new(provider, base, Some(token));
+    let get = |path: &str| client.get(&format!("{}{}", base, path));
+    match provider {
+        Provider::GitHub => {
+            let response = get("/user")?;
+            Ok(TokenInfo {
+                // Fine-grained tokens have no scope header
+                scopes: response
+                    .header("x-oauth-scopes")
+                    .map(parse_scope_list)
+                    .unwrap_or_default(),
+                expires_at: response
+                    .header("github-authentication-token-expiration")
+                    .and_then(parse_github_expiry),
+            })
+        }
+        // OAuth tokens aren't personal access tokens, but work for /user
+        Provider::GitLab => match get("/personal_access_tokens/self") {
+            Ok(response) => Ok(parse_gitlab_token(&response.body)),
+            Err(Error::Http { .. }) => {
+                get("/user")?;
+                Ok(TokenInfo::default())
+            }
+            Err(e) => Err(e),
+        },
+        Provider::Bitbucket | Provider::Gitea => {
+            get("/user")?;
+            Ok(TokenInfo::default())
+        }
+        Provider::Sourcehut => {
+            let body = client.send(
+                "POST",
+                &format!("{}/query", base),
+                Some(&json!({ "query": "query { me { canonicalName } }" })),
+            )?;
+            if body.pointer("/data/me").is_none_or(Value::is_null) {
+                return Err(Error::InvalidOutput {
+                    program: "sourcehut",
+                    message: "the token was rejected".to_string(),
+                });
+            }
+            Ok(TokenInfo::default())
+        }
+    }
+}
+
+/// Whether the provider issues a replacement token through its API
+pub fn rotates_via_api(provider: Provider) -> bool {
+    provider == Provider::GitLab
+}
+
+/// Where to create a token by hand
+pub fn new_token_url(provider: Provider, hostname: &str) -> String {
+    let host = |default: &str| {
+        let hostname = hostname.trim();
+        if hostname.is_empty() {
+            default.to_string()
+        } else {
+            hostname.to_string()
+        }
+    };
+    match provider {
+        Provider::GitHub => format!("https://{}/settings/tokens/new", host("github.com")),
+        Provider::GitLab => format!(
+            "https://{}/-/user_settings/personal_access_tokens",
+            host("gitlab.com")
+        ),
+        Provider::Bitbucket => {
+            "https://bitbucket.org/account/settings/app-passwords/new".to_string()
+        }
+        Provider::Gitea => format!(
+            "https://{}/user/settings/applications",
+            host("codeberg.org")
+        ),
+        Provider::Sourcehut => {
+            let base = Provider::Sourcehut.api_base(hostname.trim());
+            format!("{}/oauth2/personal-token", base)
+        }
+    }
+}
+
+fn provider(identity: &Identity) -> Result<Provider, Error> {
+    Provider::for_identity(identity).ok_or_else(|| Error::InvalidOutput {
+        program: "config",
+        message: format!("{} has no token API", identity.provider),
+    })
+}
+
+/// Record what the provider said about the identity's token; a
+/// `token_type` ("pat", "oauth") marks a newly stored one
+pub fn record(
+    provider: Provider,
+    name: &str,
+    info: &TokenInfo,
+    entry: Option<&str>,
+    token_type: Option<&str>,
+) -> Result<TokenMeta, Error> {
+    let now = expiry::now() as f64;
+    let mut file = TokenFile::load();
+    let meta = file.tokens.entry(key(provider, name)).or_default();
+    meta.identity_name = name.to_string();
+    meta.provider = provider.name().to_string();
+    if token_type.is_some() || meta.created_at == 0.0 {
+        meta.created_at = now;
+        meta.warning_issued = 0.0;
+    }
+    meta.last_verified = now;
+    match (info.expires_at, token_type) {
+        (Some(at), _) => meta.expires_at = at as f64,
+        // An OAuth token's expiry comes with the grant, not from a check
+        (None, None) if meta.token_type == "oauth" => {}
+        (None, _) => meta.expires_at = 0.0,
+    }
+    if !info.scopes.is_empty() || token_type.is_some() {
+        meta.scopes = info.scopes.clone();
+    }
+    if let Some(token_type) = token_type {
+        meta.token_type = token_type.to_string();
+    } else if meta.token_type.is_empty() {
+        meta.token_type = "pat".to_string();
+    }
+    meta.is_valid = true;
+    if let Some(entry) = entry {
+        meta.entry = entry.to_string();
+    }
+    let meta = meta.clone();
+    file.save()?;
+    Ok(meta)
+}
+
+/// Whether a failed call means the provider refused the token itself,
+/// rather than the network or the budget failing
+pub fn is_rejection(e: &Error) -> bool {
+    match e {
+        Error::Http { status, .. } => *status == 401,
+        Error::InvalidOutput { program, message } => {
+            *program == "sourcehut" && message.contains("rejected")
+        }
+        _ => false,
+    }
+}
+
+/// Check the identity's token with its provider and record its scopes and
+/// expiry, or that it was rejected; blocks
+pub fn check(name: &str, identity: &Identity) -> Result<TokenMeta, Error> {
+    let provider = provider(identity)?;
+    let token = pubkeys::find_token(name, identity, provider)?;
+    match inspect(provider, &api_base(provider, identity), &token) {
+        Ok(info) => record(provider, name, &info, None, None),
+        Err(e) if is_rejection(&e) => {
+            let mut file = TokenFile::load();
+            let meta = file.tokens.entry(key(provider, name)).or_default();
+            meta.identity_name = name.to_string();
+            meta.provider = provider.name().to_string();
+            meta.is_valid = false;
+            meta.last_verified = expiry::now() as f64;
+            file.save()?;
+            Err(e)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// The stored value at a key store path; None if unset or unreadable
+fn stored(path: &str) -> Result<Option<String>, Error> {
+    match cli::run(&cli::args(&["keys", "get", path])).map_err(Error::from_cli) {
+        Ok(value) => Ok(Some(value.trim().to_string()).filter(|v| !v.is_empty())),
+        Err(Error::KeystoreLocked) => Err(Error::KeystoreLocked),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Key store entries holding `token` for any identity on the provider,
+/// the identity's own first; blocks
+pub fn dependents(
+    config: &Config,
+    provider: Provider,
+    name: &str,
+    token: &str,
+) -> Result<Vec<String>, Error> {
+    let mut names: Vec<&String> = config.identities.keys().collect();
+    names.sort_by_key(|other| (other.as_str() != name, other.as_str()));
+    let mut paths: Vec<String> = Vec::new();
+    for other in names {
+        let identity = &config.identities[other.as_str()];
+        if Provider::for_identity(identity) != Some(provider) {
+            continue;
+        }
+        for path in pubkeys::token_paths(other, identity, provider) {
+            if !paths.contains(&path) && stored(&path)?.as_deref() == Some(token) {
+                paths.push(path);
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// A finished rotation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rotation {
+    /// The entries now holding the new token
+    pub entries: Vec<String>,
+    pub meta: TokenMeta,
+}
+
+/// Store `new` wherever `old` was, or at the identity's own entry
+fn store_replacement(
+    provider: Provider,
+    name: &str,
+    identity: &Identity,
+    old: Option<&str>,
+    new: &str,
+    info: &TokenInfo,
+) -> Result<Rotation, Error> {
+    let mut entries = match old {
+        Some(old) => dependents(&Config::load()?, provider, name, old)?,
+        None => Vec::new(),
+    };
+    if entries.is_empty() {
+        // The identity's own entry, not a provider-wide default
+        entries.extend(
+            pubkeys::token_paths(name, identity, provider)
+                .into_iter()
+                .take(1),
+        );
+    }
+    for path in &entries {
+        cli::store_secret(path, new).map_err(Error::from_cli)?;
+        expiry::record_rotation(path);
+    }
+    let meta = record(
+        provider,
+        name,
+        info,
+        entries.first().map(String::as_str),
+        Some("pat"),
+    )?;
+    Ok(Rotation { entries, meta })
+}
+
+/// The identity's current token, if any is found
+fn current(name: &str, identity: &Identity, provider: Provider) -> Result<Option<String>, Error> {
+    match pubkeys::find_token(name, identity, provider) {
+        Ok(token) => Ok(Some(token)),
+        Err(Error::KeystoreLocked) => Err(Error::KeystoreLocked),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Replace the identity's token with one created by hand, after the
+/// provider accepts it; blocks
+pub fn replace(name: &str, identity: &Identity, new: &str) -> Result<Rotation, Error> {
+    let provider = provider(identity)?;
+    let new = new.trim();
+    if new.is_empty() {
+        return Err(Error::InvalidOutput {
+            program: "token",
+            message: "the new token is empty".to_string(),
+        });
+    }
+    let info = inspect(provider, &api_base(provider, identity), new)?;
+    let old = current(name, identity, provider)?;
+    store_replacement(provider, name, identity, old.as_deref(), new, &info)
+}
+
+/// Have the provider issue a replacement for the identity's token, which
+/// revokes the old one, keeping its expiry period; blocks
+pub fn rotate(name: &str, identity: &Identity) -> Result<Rotation, Error> {
+    let provider = provider(identity)?;
+    if !rotates_via_api(provider) {
+        return Err(Error::InvalidOutput {
+            program: provider.display_name(),
+            message: "tokens can't be rotated through the API; create one and replace it"
+                .to_string(),
+        });
+    }
+    let old = pubkeys::find_token(name, identity, provider)?;
+    let base = api_base(provider, identity);
+    // Keep the old token's lifetime, counted from now
+    let previous = TokenFile::load()
+        .get(provider, name)
+        .map(|meta| (meta.created_at as u64, meta.expires()));
+    let mut body = json!({});
+    if let Some((created, Some(expires))) = previous {
+        if created > 0 && expires > created {
+            let at = expiry::now() + (expires - created);
+            body = json!({ "expires_at": expiry::format_date(at) });
+        }
+    }
+    let response = provider_api::Client::new(provider, &base, Some(&old)).send(
+        "POST",
+        &format!("{}/personal_access_tokens/self/rotate", base),
+        Some(&body),
+    )?;
+    let new = response["token"]
+        .as_str()
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "GitLab",
+            message: "the rotation returned no token".to_string(),
+        })?;
+    let info = parse_gitlab_token(&response);
+    store_replacement(provider, name, identity, Some(&old), new, &info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_metadata_and_provider_answers() {
+        let json = r#"{
+          "version": "1.0",
+          "tokens": {
+            "gitlab:work": {
+              "identityName": "work", "provider": "gitlab", "createdAt": 1767225600.0,
+              "lastVerified": 0.0, "expiresAt": 1769904000.0, "tokenType": "pat",
+              "isValid": true, "warningIssued": 0.0, "scopes": ["api", "read_user"],
+              "note": "kept"
+            }
+          }
+        }"#;
+        let file: TokenFile = serde_json::from_str(json).unwrap();
+        let meta = file.get(Provider::GitLab, "work").unwrap();
+        assert_eq!(
+            meta.display_text(1_767_225_600),
+            "Expires 2026-02-01 · api, read_user"
+        );
+        assert_eq!(
+            meta.display_text(1_769_040_000),
+            "Expires 2026-02-01 (Due in 10 days) · api, read_user"
+        );
+        let saved = serde_json::to_value(&file).unwrap();
+        assert_eq!(saved["tokens"]["gitlab:work"]["note"], "kept");
+        assert_eq!(
+            expiry::parse_token_metadata(&saved.to_string()),
+            vec![("gitlab:work".to_string(), 1_769_904_000)]
+        );
+
+        assert_eq!(
+            parse_github_expiry("2026-03-01 12:00:00 UTC"),
+            expiry::parse_date("2026-03-01")
+        );
+        assert_eq!(parse_scope_list("repo, read:org"), vec!["repo", "read:org"]);
+        let gitlab = json!({ "scopes": ["api"], "expires_at": "2026-03-01", "token": "glpat-new" });
+        assert_eq!(
+            parse_gitlab_token(&gitlab),
+            TokenInfo {
+                scopes: vec!["api".to_string()],
+                expires_at: expiry::parse_date("2026-03-01"),
+            }
+        );
+        assert_eq!(
+            new_token_url(Provider::Sourcehut, "git.sr.ht"),
+            "https://meta.sr.ht/oauth2/personal-token"
+        );
+    }
+}
@@ -0,0 +1,438 @@
+//! Where Trusted Workstation mode keeps YubiKey PINs
+//!
+//! Each `PinStorageMethod` has a backend here, so storing and reading a
+//! PIN doesn't go through the CLI: the TPM through tpm2-tools, sealed to
+//! PCR 7 like the CLI's own sealing; the macOS keychain, which the Secure
+//! Enclave protects on T2 and Apple silicon Macs; the Secret Service; and
+//! an age-encrypted file where none of those exist. The encrypted file is
+//! only as safe as the permissions on its identity file, which lives next
+//! to it. An identity without a configured method uses the best one
+//! available.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use crate::age;
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, Identity, PinStorageMethod};
+use crate::error::{CliError, Error};
+use crate::export;
+use crate::keyring::Keyring;
+use crate::platform;
+use crate::tpm;
+
+/// Keyring service PINs are stored under, apart from the master password
+pub const KEYRING_SERVICE: &str = "remote-juggler-pin";
+
+/// PCRs a TPM-sealed PIN is bound to, as tpm2-tools writes them
+pub const PCR_POLICY: &str = "sha256:7";
+
+const TPM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A place to keep PINs
+pub trait PinStorage: Send + Sync {
+    fn method(&self) -> PinStorageMethod;
+
+    /// Returns true if the hardware and tooling are there
+    fn is_available(&self) -> bool;
+
+    /// Save a PIN, replacing a stored one
+    fn store(&self, name: &str, pin: &str) -> Result<(), Error>;
+
+    /// The stored PIN, or None if there is none
+    fn retrieve(&self, name: &str) -> Result<Option<String>, Error>;
+
+    /// Remove a stored PIN; succeeds if there was none
+    fn clear(&self, name: &str) -> Result<(), Error>;
+}
+
+/// The backend for a method
+pub fn backend(method: PinStorageMethod) -> Box<dyn PinStorage> {
+    match method {
+        PinStorageMethod::Tpm => Box::new(TpmStorage),
+        PinStorageMethod::SecureEnclave | PinStorageMethod::Keychain => {
+            Box::new(KeyringStorage::new(method, Keyring::MacKeychain))
+        }
+        PinStorageMethod::SecretService => {
+            Box::new(KeyringStorage::new(method, Keyring::SecretService))
+        }
+        PinStorageMethod::EncryptedFile => Box::new(EncryptedFileStorage),
+    }
+}
+
+/// The best method this machine offers
+pub fn detect() -> PinStorageMethod {
+    let preferred: &[PinStorageMethod] = if cfg!(target_os = "macos") {
+        &[PinStorageMethod::SecureEnclave]
+    } else if cfg!(target_os = "linux") {
+        &[PinStorageMethod::Tpm, PinStorageMethod::SecretService]
+    } else {
+        &[]
+    };
+    preferred
+        .iter()
+        .copied()
+        .find(|method| backend(*method).is_available())
+        .unwrap_or(PinStorageMethod::EncryptedFile)
+}
+
+/// The identity's configured backend, or the detected one
+pub fn for_identity(identity: &Identity) -> Box<dyn PinStorage> {
+    backend(identity.gpg.pin_storage_method.unwrap_or_else(detect))
+}
+
+/// Store the identity's PIN and record the method in its config, so it
+/// is read back from the same place; blocks
+pub fn store_pin(name: &str, pin: &str) -> Result<PinStorageMethod, Error> {
+    let mut config = Config::load()?;
+    let identity = config
+        .identities
+        .get_mut(name)
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "config",
+            message: format!("No identity {}", name),
+        })?;
+    let storage = for_identity(identity);
+    storage.store(name, pin)?;
+    let method = storage.method();
+    if identity.gpg.pin_storage_method != Some(method) {
+        identity.gpg.pin_storage_method = Some(method);
+        config.save()?;
+    }
+    Ok(method)
+}
+
+/// A name that is safe as a file name
+fn file_stem(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Where file-based backends keep PINs
+fn pin_dir() -> Result<PathBuf, Error> {
+    let dir = platform::data_dir()
+        .ok_or_else(|| std::io::Error::other("No data directory"))?
+        .join("pins");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), Error> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// PINs in the macOS keychain or the Secret Service
+pub struct KeyringStorage {
+    method: PinStorageMethod,
+    keyring: Keyring,
+}
+
+impl KeyringStorage {
+    pub fn new(method: PinStorageMethod, keyring: Keyring) -> Self {
+        Self { method, keyring }
+    }
+}
+
+impl PinStorage for KeyringStorage {
+    fn method(&self) -> PinStorageMethod {
+        self.method
+    }
+
+    fn is_available(&self) -> bool {
+        let native = match self.keyring {
+            Keyring::MacKeychain => cfg!(target_os = "macos"),
+            Keyring::SecretService => cfg!(unix) && !cfg!(target_os = "macos"),
+        };
+        native && self.keyring.is_available()
+    }
+
+    fn store(&self, name: &str, pin: &str) -> Result<(), Error> {
+        let label = format!("RemoteJuggler YubiKey PIN for {}", name);
+        self.keyring.store_item(KEYRING_SERVICE, name, &label, pin)
+    }
+
+    fn retrieve(&self, name: &str) -> Result<Option<String>, Error> {
+        self.keyring.lookup_item(KEYRING_SERVICE, name)
+    }
+
+    fn clear(&self, name: &str) -> Result<(), Error> {
+        self.keyring.forget_item(KEYRING_SERVICE, name)
+    }
+}
+
+/// PINs sealed with the TPM through tpm2-tools, as `<name>.pub` and
+/// `<name>.priv` under the pin directory. The sealed object has no
+/// password, so only the PCR policy unseals it.
+pub struct TpmStorage;
+
+/// A private directory for tpm2-tools context files, removed on drop
+struct ContextDir(PathBuf);
+
+impl ContextDir {
+    fn create() -> Result<Self, Error> {
+        static COUNT: AtomicU32 = AtomicU32::new(0);
+        let dir = platform::runtime_dir().join(format!(
+            "remote-juggler-tpm-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+        }
+        Ok(Self(dir))
+    }
+
+    fn file(&self, name: &str) -> String {
+        self.0.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for ContextDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn tpm2(tool: &str, args: &[&str], input: Option<&str>) -> Result<String, CliError> {
+    let program = format!("tpm2_{}", tool);
+    let options = RunOptions {
+        timeout: TPM_TIMEOUT,
+        retries: 0,
+        cancel: None,
+    };
+    let args = cli::args(args);
+    match input {
+        Some(input) => cli::run_program_with_input(&program, &args, &[], input, &options),
+        None => cli::run_program(&program, &args, &options),
+    }
+}
+
+impl TpmStorage {
+    fn blob_paths(name: &str) -> Result<(PathBuf, PathBuf), Error> {
+        let dir = pin_dir()?;
+        let stem = file_stem(name);
+        Ok((
+            dir.join(format!("{}.pub", stem)),
+            dir.join(format!("{}.priv", stem)),
+        ))
+    }
+
+    /// The owner hierarchy's primary key; the same template gives the same
+    /// key every time, so it doesn't need to be persisted
+    fn create_primary(context: &ContextDir) -> Result<String, Error> {
+        let primary = context.file("primary.ctx");
+        tpm2("createprimary", &["-Q", "-C", "o", "-c", &primary], None).map_err(Error::from_cli)?;
+        Ok(primary)
+    }
+}
+
+impl PinStorage for TpmStorage {
+    fn method(&self) -> PinStorageMethod {
+        PinStorageMethod::Tpm
+    }
+
+    fn is_available(&self) -> bool {
+        tpm::device().is_some_and(|device| device.version_major != Some(1))
+            && cli::find_program("tpm2_unseal").is_some()
+    }
+
+    fn store(&self, name: &str, pin: &str) -> Result<(), Error> {
+        let (public, private) = Self::blob_paths(name)?;
+        let context = ContextDir::create()?;
+        let primary = Self::create_primary(&context)?;
+        let policy = context.file("policy.dat");
+        tpm2(
+            "createpolicy",
+            &["-Q", "--policy-pcr", "-l", PCR_POLICY, "-L", &policy],
+            None,
+        )
+        .map_err(Error::from_cli)?;
+        tpm2(
+            "create",
+            &[
+                "-Q",
+                "-C",
+                &primary,
+                "-L",
+                &policy,
+                // No userwithauth: the policy is the only way to unseal
+                "-a",
+                "fixedtpm|fixedparent|noda",
+                "-i",
+                "-",
+                "-u",
+                &public.to_string_lossy(),
+                "-r",
+                &private.to_string_lossy(),
+            ],
+            Some(pin),
+        )
+        .map_err(Error::from_cli)?;
+        Ok(())
+    }
+
+    fn retrieve(&self, name: &str) -> Result<Option<String>, Error> {
+        let (public, private) = Self::blob_paths(name)?;
+        if !public.exists() || !private.exists() {
+            return Ok(None);
+        }
+        let context = ContextDir::create()?;
+        let primary = Self::create_primary(&context)?;
+        let sealed = context.file("sealed.ctx");
+        tpm2(
+            "load",
+            &[
+                "-Q",
+                "-C",
+                &primary,
+                "-u",
+                &public.to_string_lossy(),
+                "-r",
+                &private.to_string_lossy(),
+                "-c",
+                &sealed,
+            ],
+            None,
+        )
+        .map_err(Error::from_cli)?;
+        let auth = format!("pcr:{}", PCR_POLICY);
+        let pin = tpm2("unseal", &["-c", &sealed, "-p", &auth], None).map_err(|e| match e {
+            CliError::NonZeroExit { stderr, .. } if stderr.contains("POLICY_FAIL") => {
+                Error::InvalidOutput {
+                    program: "tpm2_unseal",
+                    message: "The firmware or Secure Boot state changed since the PIN was \
+                              sealed; store it again"
+                        .to_string(),
+                }
+            }
+            e => Error::from_cli(e),
+        })?;
+        Ok(Some(pin).filter(|pin| !pin.is_empty()))
+    }
+
+    fn clear(&self, name: &str) -> Result<(), Error> {
+        let (public, private) = Self::blob_paths(name)?;
+        remove_if_exists(&public)?;
+        remove_if_exists(&private)
+    }
+}
+
+/// PINs encrypted with age to an identity kept in the pin directory
+pub struct EncryptedFileStorage;
+
+impl EncryptedFileStorage {
+    fn identity_path() -> Result<PathBuf, Error> {
+        Ok(pin_dir()?.join("identity.txt"))
+    }
+
+    /// The recipient, generating the identity on first use
+    fn recipient() -> Result<String, Error> {
+        let path = Self::identity_path()?;
+        if let Ok(contents) = fs::read_to_string(&path) {
+            if let Some(identity) = age::parse_keygen_output(&contents) {
+                return Ok(identity.recipient);
+            }
+        }
+        let identity = age::generate()?;
+        export::write_private(
+            &path,
+            &format!(
+                "# public key: {}\n{}\n",
+                identity.recipient, identity.secret
+            ),
+        )?;
+        Ok(identity.recipient)
+    }
+
+    fn pin_path(name: &str) -> Result<PathBuf, Error> {
+        Ok(pin_dir()?.join(format!("{}.age", file_stem(name))))
+    }
+
+    fn run_age(args: &[&str], input: &str) -> Result<String, Error> {
+        let options = RunOptions {
+            timeout: TPM_TIMEOUT,
+            retries: 0,
+            cancel: None,
+        };
+        cli::run_program_with_input(&age::age_program(), &cli::args(args), &[], input, &options)
+            .map_err(Error::from_cli)
+    }
+}
+
+impl PinStorage for EncryptedFileStorage {
+    fn method(&self) -> PinStorageMethod {
+        PinStorageMethod::EncryptedFile
+    }
+
+    fn is_available(&self) -> bool {
+        cli::find_program(&age::age_program()).is_some()
+            && cli::find_program(&age::age_keygen_program()).is_some()
+    }
+
+    fn store(&self, name: &str, pin: &str) -> Result<(), Error> {
+        let armored = Self::run_age(&["-a", "-r", &Self::recipient()?], pin)?;
+        export::write_private(&Self::pin_path(name)?, &armored)?;
+        Ok(())
+    }
+
+    fn retrieve(&self, name: &str) -> Result<Option<String>, Error> {
+        let Ok(armored) = fs::read_to_string(Self::pin_path(name)?) else {
+            return Ok(None);
+        };
+        let identity = Self::identity_path()?;
+        let pin = Self::run_age(&["-d", "-i", &identity.to_string_lossy()], &armored)?;
+        Ok(Some(pin).filter(|pin| !pin.is_empty()))
+    }
+
+    fn clear(&self, name: &str) -> Result<(), Error> {
+        remove_if_exists(&Self::pin_path(name)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_methods_and_backends() {
+        for (value, method) in [
+            ("tpm", Some(PinStorageMethod::Tpm)),
+            ("TPM 2.0", Some(PinStorageMethod::Tpm)),
+            ("Secure Enclave", Some(PinStorageMethod::SecureEnclave)),
+            ("secret_service", Some(PinStorageMethod::SecretService)),
+            ("none", None),
+            ("", None),
+        ] {
+            assert_eq!(PinStorageMethod::parse(value), method, "{}", value);
+        }
+        let gpg: crate::config::GpgConfig =
+            serde_json::from_str(r#"{"keyId": "", "pinStorageMethod": ""}"#).unwrap();
+        assert_eq!(gpg.pin_storage_method, None);
+
+        for method in [
+            PinStorageMethod::Tpm,
+            PinStorageMethod::SecureEnclave,
+            PinStorageMethod::Keychain,
+            PinStorageMethod::SecretService,
+            PinStorageMethod::EncryptedFile,
+        ] {
+            assert_eq!(backend(method).method(), method);
+        }
+        assert_eq!(file_stem("gitlab-work/../x"), "gitlab-work_.._x");
+    }
+}
@@ -0,0 +1,368 @@
+//! The Assuan pinentry protocol, for `pinentry-remote-juggler`
+//!
+//! gpg-agent starts the pinentry and sends it the prompt piece by piece
+//! (SETDESC, SETPROMPT, SETKEYINFO, ...) before GETPIN, CONFIRM or
+//! MESSAGE. This module keeps that state and answers everything except
+//! the prompt itself, which the binary draws with GTK. In Trusted
+//! Workstation mode the PIN sealed in the TPM or Secure Enclave is handed
+//! over instead of asking, unless gpg-agent reports the last PIN was
+//! wrong; the other modes always ask, and gpg-agent's cache settings
+//! decide how often.
+
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, SecurityMode};
+use crate::gpg::SecretKey;
+use crate::pin_storage;
+
+/// Error codes gpg-agent understands (GPG_ERR_SOURCE_PINENTRY << 24)
+pub const ERR_CANCELED: u32 = 83_886_179;
+pub const ERR_TIMEOUT: u32 = 83_886_142;
+pub const ERR_NOT_CONFIRMED: u32 = 83_886_194;
+const ERR_UNKNOWN_COMMAND: u32 = 83_887_131;
+
+/// What gpg-agent has told the pinentry so far
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Request {
+    pub title: String,
+    pub description: String,
+    pub prompt: String,
+    /// Why the previous attempt failed, e.g. "Bad PIN"
+    pub error: String,
+    pub ok: String,
+    pub cancel: String,
+    pub not_ok: String,
+    /// `s/SERIALNO/KEYGRIP` for a card key, `n/KEYGRIP` otherwise
+    pub key_info: String,
+    /// Ask twice, for a new passphrase
+    pub repeat: String,
+    /// Seconds before giving up; 0 waits forever
+    pub timeout: u32,
+}
+
+impl Request {
+    /// The keygrip from SETKEYINFO
+    pub fn keygrip(&self) -> Option<&str> {
+        let grip = self.key_info.rsplit('/').next()?;
+        (grip.len() == 40 && grip.chars().all(|c| c.is_ascii_hexdigit())).then_some(grip)
+    }
+
+    /// PIN tries left on the card, from scdaemon's "Remaining attempts: 2"
+    /// in the description
+    pub fn remaining_attempts(&self) -> Option<u32> {
+        let (_, rest) = self.description.split_once("Remaining attempts:")?;
+        rest.split_whitespace().next()?.parse().ok()
+    }
+
+    /// A key id mentioned in the description, e.g. "ID 8547785CA25F0AA8"
+    pub fn key_id(&self) -> Option<&str> {
+        self.description
+            .split(|c: char| !c.is_ascii_hexdigit())
+            .find(|word| word.len() == 16 || word.len() == 8)
+    }
+}
+
+/// What the binary has to do for a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Send these lines
+    Reply(Vec<String>),
+    /// Ask for a PIN or passphrase
+    GetPin,
+    /// Ask a yes/no question; `one_button` is just an acknowledgement
+    Confirm { one_button: bool },
+    /// Show a message
+    Message,
+    /// Say goodbye and exit
+    Bye,
+}
+
+/// One pinentry conversation
+#[derive(Debug, Default)]
+pub struct Session {
+    pub request: Request,
+}
+
+fn ok() -> Step {
+    Step::Reply(vec!["OK".to_string()])
+}
+
+/// `ERR` line for a code
+pub fn err(code: u32, message: &str) -> String {
+    format!("ERR {} {} <Pinentry>", code, message)
+}
+
+impl Session {
+    /// Handle one line from gpg-agent
+    pub fn handle(&mut self, line: &str) -> Step {
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let text = decode(arg);
+        let request = &mut self.request;
+        match command.to_ascii_uppercase().as_str() {
+            "SETTITLE" => request.title = text,
+            "SETDESC" => request.description = text,
+            "SETPROMPT" => request.prompt = text,
+            "SETERROR" => request.error = text,
+            "SETOK" => request.ok = text,
+            "SETCANCEL" => request.cancel = text,
+            "SETNOTOK" => request.not_ok = text,
+            "SETKEYINFO" => request.key_info = text,
+            "SETREPEAT" => {
+                request.repeat = if text.is_empty() {
+                    "Repeat".into()
+                } else {
+                    text
+                }
+            }
+            "SETTIMEOUT" => request.timeout = arg.trim().parse().unwrap_or(0),
+            // Quality bars, generated passphrases, tty and locale options
+            // don't apply to a GUI prompt
+            "SETQUALITYBAR" | "SETQUALITYBAR_TT" | "SETGENPIN" | "SETGENPIN_TT"
+            | "SETREPEATERROR" | "SETREPEATOK" | "OPTION" | "NOP" => {}
+            "RESET" => *request = Request::default(),
+            "GETINFO" => return getinfo(arg.trim()),
+            "GETPIN" => return Step::GetPin,
+            "CONFIRM" => {
+                return Step::Confirm {
+                    one_button: arg.trim() == "--one-button",
+                }
+            }
+            "MESSAGE" => return Step::Message,
+            "BYE" => return Step::Bye,
+            _ => {
+                return Step::Reply(vec![err(ERR_UNKNOWN_COMMAND, "Unknown IPC command")]);
+            }
+        }
+        ok()
+    }
+
+    /// Lines answering GETPIN with `pin`
+    pub fn pin_reply(&self, pin: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        if !self.request.repeat.is_empty() {
+            lines.push("S PIN_REPEATED".to_string());
+        }
+        lines.push(format!("D {}", encode(pin)));
+        lines.push("OK".to_string());
+        lines
+    }
+
+    /// Clear the per-prompt state once a prompt was answered
+    pub fn finish(&mut self) {
+        self.request.error.clear();
+    }
+}
+
+fn getinfo(what: &str) -> Step {
+    let value = match what {
+        "flavor" => "remote-juggler".to_string(),
+        "version" => env!("CARGO_PKG_VERSION").to_string(),
+        "pid" => std::process::id().to_string(),
+        "ttyinfo" => "- - -".to_string(),
+        _ => return ok(),
+    };
+    Step::Reply(vec![format!("D {}", encode(&value)), "OK".to_string()])
+}
+
+/// Undo Assuan's percent-escaping
+pub fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Escape `%`, CR and LF for a data line
+pub fn encode(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// The identity whose GPG key the request is for: by keygrip when
+/// gpg-agent sent one, else by the key id in the description
+pub fn identity_for(config: &Config, keys: &[SecretKey], request: &Request) -> Option<String> {
+    let key = match request.keygrip() {
+        Some(grip) => keys.iter().find(|k| k.has_keygrip(grip)),
+        None => request
+            .key_id()
+            .and_then(|id| keys.iter().find(|k| k.matches(id))),
+    };
+    let mut names: Vec<&String> = config
+        .identities
+        .iter()
+        .filter(|(_, identity)| match key {
+            Some(key) => key.matches(&identity.gpg.key_id),
+            // No keyring match: compare the description's id directly
+            None => request.key_id().is_some_and(|id| {
+                let configured = identity.gpg.key_id.trim_start_matches("0x").to_uppercase();
+                !configured.is_empty() && configured.ends_with(&id.to_uppercase())
+            }),
+        })
+        .map(|(name, _)| name)
+        .collect();
+    // The current identity wins when several share the key
+    names.sort_by_key(|name| (**name != config.state.current_identity, *name));
+    names.first().map(|name| name.to_string())
+}
+
+/// The identity's stored PIN, if it is in Trusted Workstation mode: from
+/// its PIN storage, or for PINs the CLI sealed, from `unseal-pin`; blocks
+pub fn stored_pin(config: &Config, name: &str) -> Option<String> {
+    let identity = config.identities.get(name)?;
+    if identity.gpg.security_mode != SecurityMode::TrustedWorkstation {
+        return None;
+    }
+    if let Some(method) = identity.gpg.pin_storage_method {
+        match pin_storage::backend(method).retrieve(name) {
+            Ok(Some(pin)) => return Some(pin),
+            Ok(None) => {}
+            Err(e) => tracing::info!(
+                "Can't read the PIN for {} from {}: {}",
+                name,
+                method.display_name(),
+                e
+            ),
+        }
+    }
+    let args = cli::args(&["unseal-pin", name]);
+    let options = RunOptions::for_args(&args);
+    match cli::run_with_options(&args, &[], &options) {
+        Ok(out) => Some(out.trim_end_matches(['\r', '\n']).to_string()).filter(|p| !p.is_empty()),
+        Err(e) => {
+            tracing::info!("No stored PIN for {}: {}", name, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::config::{GpgConfig, Identity};
+    use crate::gpg;
+
+    #[test]
+    fn test_session_protocol() {
+        let mut session = Session::default();
+        assert_eq!(session.handle("OPTION ttyname=/dev/pts/1"), ok());
+        assert_eq!(
+            session.handle("SETDESC Please enter the PIN%0A%0AKey ID 8547785CA25F0AA8"),
+            ok()
+        );
+        assert_eq!(
+            session.request.description,
+            "Please enter the PIN\n\nKey ID 8547785CA25F0AA8"
+        );
+        assert_eq!(session.request.key_id(), Some("8547785CA25F0AA8"));
+        assert_eq!(session.request.remaining_attempts(), None);
+        let retrying = Request {
+            description: "Please unlock the card\n\nRemaining attempts: 2".to_string(),
+            ..Request::default()
+        };
+        assert_eq!(retrying.remaining_attempts(), Some(2));
+        session.handle("SETKEYINFO s/D2760001240103040006012345670000/0123456789ABCDEF0123456789ABCDEF01234567");
+        assert_eq!(
+            session.request.keygrip(),
+            Some("0123456789ABCDEF0123456789ABCDEF01234567")
+        );
+        session.handle("SETTIMEOUT 30");
+        assert_eq!(session.request.timeout, 30);
+        assert_eq!(session.handle("GETPIN"), Step::GetPin);
+        assert_eq!(
+            session.handle("CONFIRM --one-button"),
+            Step::Confirm { one_button: true }
+        );
+        assert_eq!(session.pin_reply("12%34\n"), vec!["D 12%2534%0A", "OK"]);
+        session.handle("SETREPEAT");
+        assert_eq!(session.pin_reply("x")[0], "S PIN_REPEATED");
+        assert!(
+            matches!(session.handle("FROB"), Step::Reply(lines) if lines[0].starts_with("ERR "))
+        );
+        session.handle("RESET");
+        assert_eq!(session.request, Request::default());
+        assert_eq!(session.handle("BYE"), Step::Bye);
+        assert_eq!(decode("100%"), "100%");
+    }
+
+    #[test]
+    fn test_identity_for_keygrip() {
+        let keys = gpg::parse_colons(
+            "sec:u:255:22:AAAA1111BBBB2222:1700000000:::u:::scSC:::+:::ed25519:::0:\n\
+             fpr:::::::::0123456789ABCDEF0123AAAA1111BBBB2222:\n\
+             grp:::::::::0123456789ABCDEF0123456789ABCDEF01234567:\n",
+        );
+        let config = ConfigBuilder::new()
+            .identity(
+                "gitlab-work",
+                Identity {
+                    gpg: GpgConfig {
+                        key_id: "AAAA1111BBBB2222".to_string(),
+                        security_mode: SecurityMode::TrustedWorkstation,
+                        ..Default::default()
+                    },
+                    ..identity(
+                        "gitlab",
+                        "gitlab-work",
+                        "gitlab.com",
+                        "Work",
+                        "w@example.com",
+                    )
+                },
+            )
+            .identity(
+                "github-personal",
+                Identity {
+                    gpg: GpgConfig {
+                        key_id: "CCCC3333DDDD4444".to_string(),
+                        ..Default::default()
+                    },
+                    ..identity(
+                        "github",
+                        "github-personal",
+                        "github.com",
+                        "Me",
+                        "me@example.com",
+                    )
+                },
+            )
+            .build();
+
+        let request = Request {
+            key_info: "n/0123456789abcdef0123456789abcdef01234567".to_string(),
+            ..Request::default()
+        };
+        assert_eq!(
+            identity_for(&config, &keys, &request).as_deref(),
+            Some("gitlab-work")
+        );
+        // Not in the keyring, but named in the description
+        let request = Request {
+            description: "PIN for key CCCC3333DDDD4444".to_string(),
+            ..Request::default()
+        };
+        assert_eq!(
+            identity_for(&config, &keys, &request).as_deref(),
+            Some("github-personal")
+        );
+        assert_eq!(identity_for(&config, &keys, &Request::default()), None);
+        // Only Trusted Workstation identities have a stored PIN to use
+        assert_eq!(stored_pin(&config, "github-personal"), None);
+    }
+}
@@ -0,0 +1,392 @@
+//! YubiKey PIV slots for SSH
+//!
+//! Some teams require SSH keys in the PIV applet rather than FIDO2 keys.
+//! ssh reaches those through a PKCS#11 library (Yubico's ykcs11 or
+//! OpenSC), which only lists a slot's key once the slot also holds a
+//! certificate, so generating or importing a key here adds a self-signed
+//! one. The slot's SSH public key is written next to the other keys and
+//! the identity points at it, with the library as its `PKCS11Provider`;
+//! ssh then picks that key from the card. ykman only takes the PIN on the
+//! command line when it has no terminal to prompt on, so it is briefly
+//! visible to other processes of the user.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::chalresp;
+use crate::cli::{self, RunOptions};
+use crate::config::Identity;
+use crate::error::Error;
+use crate::platform;
+
+/// PKCS#11 libraries that expose the PIV applet, Yubico's first
+pub const LIBRARIES: [&str; 8] = [
+    "/usr/lib64/libykcs11.so",
+    "/usr/lib/x86_64-linux-gnu/libykcs11.so",
+    "/usr/lib/libykcs11.so",
+    "/opt/homebrew/lib/libykcs11.dylib",
+    "/usr/local/lib/libykcs11.dylib",
+    "/usr/lib64/opensc-pkcs11.so",
+    "/usr/lib/x86_64-linux-gnu/opensc-pkcs11.so",
+    "/usr/lib/opensc-pkcs11.so",
+];
+
+/// A PIV key slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    /// 9a, the slot meant for SSH and logins
+    Authentication,
+    /// 9c, asks for the PIN on every use
+    Signature,
+    /// 9d
+    KeyManagement,
+    /// 9e, used without the PIN
+    CardAuthentication,
+}
+
+impl Slot {
+    pub const ALL: [Slot; 4] = [
+        Self::Authentication,
+        Self::Signature,
+        Self::KeyManagement,
+        Self::CardAuthentication,
+    ];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Authentication => "9a",
+            Self::Signature => "9c",
+            Self::KeyManagement => "9d",
+            Self::CardAuthentication => "9e",
+        }
+    }
+
+    pub fn parse(id: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|slot| slot.id().eq_ignore_ascii_case(id))
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::Authentication => "9a Authentication",
+            Self::Signature => "9c Digital Signature",
+            Self::KeyManagement => "9d Key Management",
+            Self::CardAuthentication => "9e Card Authentication (no PIN)",
+        }
+    }
+}
+
+/// Key algorithms ssh can use from a PIV slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    EccP256,
+    EccP384,
+    Rsa2048,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 3] = [Self::EccP256, Self::EccP384, Self::Rsa2048];
+
+    /// ykman's name
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::EccP256 => "ECCP256",
+            Self::EccP384 => "ECCP384",
+            Self::Rsa2048 => "RSA2048",
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::EccP256 => "ECDSA P-256",
+            Self::EccP384 => "ECDSA P-384",
+            Self::Rsa2048 => "RSA 2048",
+        }
+    }
+}
+
+/// What a slot holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotInfo {
+    pub slot: Slot,
+    /// e.g. "ECCP256"; empty when ykman doesn't say
+    pub algorithm: String,
+    /// The certificate's subject, e.g. "CN=SSH key"
+    pub subject: String,
+}
+
+impl SlotInfo {
+    pub fn display_text(&self) -> String {
+        let mut parts = vec![self.slot.id().to_string()];
+        if !self.algorithm.is_empty() {
+            parts.push(self.algorithm.clone());
+        }
+        if !self.subject.is_empty() {
+            parts.push(self.subject.clone());
+        }
+        parts.join(" ")
+    }
+}
+
+/// Parse the slots in `ykman piv info`: "Slot 9A (AUTHENTICATION):" with
+/// "Private key type:" lines in ykman 5, "Slot 9a:" with "Algorithm:"
+/// before
+pub fn parse_info(text: &str) -> Vec<SlotInfo> {
+    let mut slots: Vec<SlotInfo> = Vec::new();
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if let Some(rest) = trimmed.strip_prefix("Slot ") {
+            let id: String = rest.chars().take(2).collect();
+            if let Some(slot) = Slot::parse(&id) {
+                slots.push(SlotInfo {
+                    slot,
+                    algorithm: String::new(),
+                    subject: String::new(),
+                });
+            }
+            continue;
+        }
+        let Some(current) = slots.last_mut() else {
+            continue;
+        };
+        let Some((key, value)) = trimmed.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match key.trim() {
+            "Algorithm" | "Private key type" if current.algorithm.is_empty() => {
+                current.algorithm = value
+            }
+            "Public key type" if current.algorithm.is_empty() => current.algorithm = value,
+            "Subject DN" => current.subject = value,
+            _ => {}
+        }
+    }
+    slots
+}
+
+fn options(timeout: Duration) -> RunOptions {
+    RunOptions {
+        timeout,
+        retries: 0,
+        cancel: None,
+    }
+}
+
+fn ykman(args: &[&str], timeout: Duration) -> Result<String, Error> {
+    cli::run_program(
+        &chalresp::ykman_program(),
+        &cli::args(args),
+        &options(timeout),
+    )
+    .map_err(Error::from_cli)
+}
+
+/// The slots holding keys or certificates; blocks
+pub fn info() -> Result<Vec<SlotInfo>, Error> {
+    Ok(parse_info(&ykman(
+        &["piv", "info"],
+        Duration::from_secs(15),
+    )?))
+}
+
+/// The first PKCS#11 library installed
+pub fn library() -> Option<PathBuf> {
+    LIBRARIES
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+}
+
+/// A scratch file for a public key, removed on drop
+struct ScratchFile(PathBuf);
+
+impl ScratchFile {
+    fn new(slot: Slot) -> Self {
+        Self(platform::runtime_dir().join(format!(
+            "remote-juggler-piv-{}-{}.pem",
+            std::process::id(),
+            slot.id()
+        )))
+    }
+
+    fn path(&self) -> String {
+        self.0.to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for ScratchFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// The SSH public key line for a PEM public key; blocks
+pub fn ssh_public_key(pem: &Path) -> Result<String, Error> {
+    let args = cli::args(&["-i", "-m", "PKCS8", "-f", &pem.to_string_lossy()]);
+    let out = cli::run_program("ssh-keygen", &args, &options(Duration::from_secs(10)))
+        .map_err(Error::from_cli)?;
+    out.lines()
+        .find(|line| !line.trim().is_empty())
+        .map(|line| line.trim().to_string())
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "ssh-keygen",
+            message: "No public key in the output".to_string(),
+        })
+}
+
+/// Add a self-signed certificate for the slot's key, so PKCS#11 lists it
+fn certify(slot: Slot, public: &ScratchFile, pin: &str, subject: &str) -> Result<(), Error> {
+    ykman(
+        &[
+            "piv",
+            "certificates",
+            "generate",
+            "--subject",
+            &format!("CN={}", subject),
+            "--pin",
+            pin,
+            slot.id(),
+            &public.path(),
+        ],
+        // Long enough for a touch
+        Duration::from_secs(60),
+    )?;
+    Ok(())
+}
+
+/// Generate a key in the slot, replacing what it held, and certify it;
+/// returns the SSH public key. Blocks
+pub fn generate(
+    slot: Slot,
+    algorithm: Algorithm,
+    touch: bool,
+    pin: &str,
+    subject: &str,
+) -> Result<String, Error> {
+    let public = ScratchFile::new(slot);
+    ykman(
+        &[
+            "piv",
+            "keys",
+            "generate",
+            "--algorithm",
+            algorithm.id(),
+            "--pin-policy",
+            "ONCE",
+            "--touch-policy",
+            if touch { "CACHED" } else { "NEVER" },
+            "--pin",
+            pin,
+            slot.id(),
+            &public.path(),
+        ],
+        // RSA generation on the card takes a while
+        Duration::from_secs(120),
+    )?;
+    certify(slot, &public, pin, subject)?;
+    ssh_public_key(&public.0)
+}
+
+/// Import an unencrypted PEM private key into the slot and certify it;
+/// returns the SSH public key. Blocks
+pub fn import(slot: Slot, key: &Path, pin: &str, subject: &str) -> Result<String, Error> {
+    ykman(
+        &[
+            "piv",
+            "keys",
+            "import",
+            "--pin-policy",
+            "ONCE",
+            "--pin",
+            pin,
+            slot.id(),
+            &key.to_string_lossy(),
+        ],
+        Duration::from_secs(30),
+    )?;
+    let public = ScratchFile::new(slot);
+    export_public(slot, &public)?;
+    certify(slot, &public, pin, subject)?;
+    ssh_public_key(&public.0)
+}
+
+/// Read the slot's public key (YubiKey 5.3 and later)
+fn export_public(slot: Slot, public: &ScratchFile) -> Result<(), Error> {
+    ykman(
+        &["piv", "keys", "export", slot.id(), &public.path()],
+        Duration::from_secs(15),
+    )?;
+    Ok(())
+}
+
+/// The SSH public key of a key already in the slot; blocks
+pub fn existing(slot: Slot) -> Result<String, Error> {
+    let public = ScratchFile::new(slot);
+    export_public(slot, &public)?;
+    ssh_public_key(&public.0)
+}
+
+/// Where an identity's PIV public key is kept
+pub fn key_path(name: &str, slot: Slot) -> Option<PathBuf> {
+    platform::ssh_dir().map(|dir| dir.join(format!("{}-piv-{}", name, slot.id())))
+}
+
+/// Write the public key as `<key path>.pub` and point the identity at it
+/// and the PKCS#11 library
+pub fn attach(
+    name: &str,
+    identity: &mut Identity,
+    slot: Slot,
+    public_key: &str,
+    library: &Path,
+) -> Result<(), Error> {
+    let path = key_path(name, slot).ok_or_else(|| std::io::Error::other("No ~/.ssh"))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut public = path.clone().into_os_string();
+    public.push(".pub");
+    std::fs::write(
+        &public,
+        format!("{} {} PIV {}\n", public_key, name, slot.id()),
+    )?;
+    identity.ssh_key_path = platform::tilde_path(&path);
+    identity.pkcs11_provider = Some(library.to_string_lossy().into_owned());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_info() {
+        let ykman5 = "PIV version:              5.4.3\n\
+                      PIN tries remaining:      3/3\n\
+                      Slot 9A (AUTHENTICATION):\n  \
+                        Private key type: ECCP256\n  \
+                        Public key type:  ECCP256\n  \
+                        Subject DN:       CN=SSH key\n  \
+                        Issuer DN:        CN=SSH key\n\
+                      Slot 9E (CARD_AUTH):\n  \
+                        Private key type: RSA2048\n";
+        let slots = parse_info(ykman5);
+        assert_eq!(slots.len(), 2);
+        assert_eq!(slots[0].display_text(), "9a ECCP256 CN=SSH key");
+        assert_eq!(slots[1].slot, Slot::CardAuthentication);
+
+        let ykman4 =
+            "PIV version: 4.3.7\nSlot 9c:\n\tAlgorithm:\tRSA2048\n\tSubject DN:\tCN=sign\n";
+        assert_eq!(
+            parse_info(ykman4),
+            vec![SlotInfo {
+                slot: Slot::Signature,
+                algorithm: "RSA2048".to_string(),
+                subject: "CN=sign".to_string(),
+            }]
+        );
+        assert_eq!(Slot::parse("9D"), Some(Slot::KeyManagement));
+    }
+}
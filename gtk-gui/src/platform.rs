@@ -0,0 +1,316 @@
+//! Where things live on Linux, macOS and Windows
+//!
+//! The CLI keeps its config in `~/.config/remote-juggler` everywhere, so
+//! the GUI reads it from there too instead of the platform's own config
+//! directory (`~/Library/Application Support` on macOS, `%APPDATA%` on
+//! Windows); only on Linux is `$XDG_CONFIG_HOME` honored. State only the
+//! GUI uses goes in the platform's local data directory. ssh-agent is a
+//! Unix socket on Linux and macOS, where launchd hands it out, and a named
+//! pipe on Windows.
+//!
+//! Inside a Flatpak sandbox `$XDG_CONFIG_HOME` is the app's own directory
+//! under `~/.var/app`, so the CLI's config is found through the host's
+//! instead, and short-lived files go in the app's runtime directory, the
+//! part of `$XDG_RUNTIME_DIR` the host sees too. Files picked in a file
+//! chooser arrive as document portal paths, `$XDG_RUNTIME_DIR/doc/<id>/`.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::cli::{self, RunOptions};
+use crate::export;
+
+/// Directory name under the config and data directories
+pub const APP_DIR: &str = "remote-juggler";
+
+/// The Windows OpenSSH agent's pipe, also used by 1Password on Windows
+pub const WINDOWS_AGENT_PIPE: &str = r"\\.\pipe\openssh-ssh-agent";
+
+pub fn home_dir() -> Option<PathBuf> {
+    dirs::home_dir()
+}
+
+/// Whether the GUI runs inside a Flatpak sandbox
+pub fn is_flatpak() -> bool {
+    static FLATPAK: OnceLock<bool> = OnceLock::new();
+    *FLATPAK.get_or_init(|| cfg!(target_os = "linux") && Path::new("/.flatpak-info").exists())
+}
+
+/// The CLI's config directory, `~/.config/remote-juggler`
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if is_flatpak() {
+        std::env::var_os("HOST_XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home_dir().map(|home| home.join(".config")))
+    } else if cfg!(target_os = "linux") {
+        dirs::config_dir()
+    } else {
+        home_dir().map(|home| home.join(".config"))
+    };
+    base.map(|dir| dir.join(APP_DIR))
+}
+
+/// The GUI's own state directory
+pub fn data_dir() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join(APP_DIR))
+}
+
+/// The GUI's cache directory; anything in it can be fetched again
+pub fn cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join(APP_DIR))
+}
+
+/// Read a JSON state file; a missing or unreadable one is the default
+pub fn load_json_state<T: DeserializeOwned + Default>(path: Option<PathBuf>) -> T {
+    try_load_json_state(path).unwrap_or_else(|e| {
+        tracing::warn!("{}", e);
+        T::default()
+    })
+}
+
+/// Read a JSON state file; a missing one is the default, but one that
+/// can't be read or parsed is an error, for state that saving a fresh
+/// default over would lose (e.g. IDs already handed out)
+pub fn try_load_json_state<T: DeserializeOwned + Default>(
+    path: Option<PathBuf>,
+) -> std::io::Result<T> {
+    let Some(path) = path else {
+        return Ok(T::default());
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => {
+            return Err(std::io::Error::new(
+                e.kind(),
+                format!("Can't read {}: {}", path.display(), e),
+            ))
+        }
+    };
+    serde_json::from_str(&text).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Can't parse {}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Write a JSON state file readable only by the owner, creating its
+/// directory; it is replaced whole, so a crash leaves the old one. Without
+/// a path nothing is written
+pub fn save_json_state<T: Serialize + ?Sized>(
+    path: Option<PathBuf>,
+    state: &T,
+) -> std::io::Result<()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(state).map_err(std::io::Error::other)?;
+    let tmp = path.with_extension("json.tmp");
+    export::write_private(&tmp, &json)?;
+    std::fs::rename(&tmp, &path)
+}
+
+/// `~/.ssh`, which is also where Windows OpenSSH looks
+pub fn ssh_dir() -> Option<PathBuf> {
+    home_dir().map(|home| home.join(".ssh"))
+}
+
+/// A private place for short-lived files: `$XDG_RUNTIME_DIR` where there
+/// is one (memory-backed, removed at logout), else the per-user temp
+/// directory macOS and Windows provide. Under Flatpak it is the app's
+/// directory in it, which programs run on the host can read
+pub fn runtime_dir() -> PathBuf {
+    let Some(runtime) = dirs::runtime_dir() else {
+        return std::env::temp_dir();
+    };
+    match std::env::var("FLATPAK_ID") {
+        Ok(id) if is_flatpak() && !id.is_empty() => runtime.join("app").join(id),
+        _ => runtime,
+    }
+}
+
+/// Where the document portal mounts files shared with the sandbox
+pub fn document_portal_dir() -> Option<PathBuf> {
+    dirs::runtime_dir().map(|dir| dir.join("doc"))
+}
+
+/// Split a document portal path, `<portal>/<id>/<name>/<rest>`, into the
+/// document ID and the part below the shared file or directory, which is
+/// empty for the file itself
+pub fn split_document_path(portal: &Path, path: &Path) -> Option<(String, PathBuf)> {
+    let mut components = path.strip_prefix(portal).ok()?.components();
+    let id = match components.next()? {
+        Component::Normal(id) => id.to_str()?.to_string(),
+        _ => return None,
+    };
+    // "by-app" is the portal's per-app view, not a document
+    if id == "by-app" || !matches!(components.next()?, Component::Normal(_)) {
+        return None;
+    }
+    Some((id, components.as_path().to_path_buf()))
+}
+
+/// Expand a leading `~` (`~/`, or `~\` on Windows) to the home directory
+pub fn expand_tilde(path: &str) -> PathBuf {
+    let rest = if path == "~" {
+        Some("")
+    } else {
+        path.strip_prefix("~/")
+            .or_else(|| path.strip_prefix("~\\").filter(|_| cfg!(windows)))
+    };
+    match (rest, home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// A path as config files write it: `~/...` with forward slashes under
+/// the home directory, which OpenSSH accepts on every platform
+pub fn tilde_path(path: &Path) -> String {
+    match home_dir().and_then(|home| path.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(relative) => {
+            let parts: Vec<String> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            format!("~/{}", parts.join("/"))
+        }
+        None => path.display().to_string(),
+    }
+}
+
+fn options() -> RunOptions {
+    RunOptions {
+        timeout: Duration::from_secs(5),
+        retries: 0,
+        cancel: None,
+    }
+}
+
+/// This machine's name, e.g. for titles of uploaded keys
+pub fn machine_name() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| cli::run_program("hostname", &[], &options()).ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "workstation".to_string())
+}
+
+/// The session's ssh-agent: `SSH_AUTH_SOCK`, else the socket launchd
+/// gives the login session on macOS (apps started from the Dock may not
+/// inherit it), or the OpenSSH pipe on Windows
+pub fn agent_socket() -> Option<PathBuf> {
+    if let Some(socket) = std::env::var_os("SSH_AUTH_SOCK").filter(|s| !s.is_empty()) {
+        return Some(PathBuf::from(socket));
+    }
+    if cfg!(target_os = "macos") {
+        let args = cli::args(&["getenv", "SSH_AUTH_SOCK"]);
+        return cli::run_program("launchctl", &args, &options())
+            .ok()
+            .map(|out| out.trim().to_string())
+            .filter(|out| !out.is_empty())
+            .map(PathBuf::from);
+    }
+    if cfg!(windows) {
+        return Some(PathBuf::from(WINDOWS_AGENT_PIPE));
+    }
+    None
+}
+
+/// 1Password's agent, as its docs write it in ssh config
+pub fn one_password_agent() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "~/Library/Group Containers/2BUA8C4S2C.com.1password/t/agent.sock"
+    } else if cfg!(windows) {
+        WINDOWS_AGENT_PIPE
+    } else {
+        "~/.1password/agent.sock"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_tilde_round_trip() {
+        let home = home_dir().unwrap();
+        let key = home.join(".ssh").join("id_ed25519_work");
+        assert_eq!(tilde_path(&key), "~/.ssh/id_ed25519_work");
+        assert_eq!(expand_tilde("~/.ssh/id_ed25519_work"), key);
+        assert_eq!(expand_tilde("~"), home);
+        assert_eq!(expand_tilde("/etc/ssh"), PathBuf::from("/etc/ssh"));
+        assert_eq!(expand_tilde("~other/x"), PathBuf::from("~other/x"));
+        assert_eq!(tilde_path(Path::new("/srv/keys/k")), "/srv/keys/k");
+    }
+
+    #[test]
+    fn test_split_document_path() {
+        let portal = Path::new("/run/user/1000/doc");
+        assert_eq!(
+            split_document_path(portal, Path::new("/run/user/1000/doc/8f3a2c1d/work.kdbx")),
+            Some(("8f3a2c1d".to_string(), PathBuf::new()))
+        );
+        assert_eq!(
+            split_document_path(portal, Path::new("/run/user/1000/doc/5e7b/repos/api/.git")),
+            Some(("5e7b".to_string(), PathBuf::from("api/.git")))
+        );
+        assert_eq!(
+            split_document_path(portal, Path::new("/run/user/1000/doc/5e7b")),
+            None
+        );
+        assert_eq!(
+            split_document_path(portal, Path::new("/run/user/1000/doc/by-app/x/y")),
+            None
+        );
+        assert_eq!(
+            split_document_path(portal, Path::new("/home/me/work.kdbx")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_json_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("counts.json");
+        let missing: BTreeMap<String, u32> = load_json_state(Some(path.clone()));
+        assert!(missing.is_empty());
+
+        let counts = BTreeMap::from([("work".to_string(), 3u32)]);
+        save_json_state(Some(path.clone()), &counts).unwrap();
+        assert_eq!(
+            load_json_state::<BTreeMap<String, u32>>(Some(path.clone())),
+            counts
+        );
+        assert!(!path.with_extension("json.tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(load_json_state::<BTreeMap<String, u32>>(Some(path)).is_empty());
+        save_json_state(None, &counts).unwrap();
+    }
+
+    #[test]
+    fn test_dirs() {
+        assert!(config_dir().unwrap().ends_with("remote-juggler"));
+        assert_eq!(ssh_dir(), home_dir().map(|h| h.join(".ssh")));
+    }
+}
@@ -0,0 +1,296 @@
+//! Email and signing policies by organization
+//!
+//! A policy covers the repositories whose remote, written as
+//! `hostname/path` (e.g. `github.com/acme-corp/api`), starts with path
+//! segments its pattern matches; `*` stands for any part of one segment, so
+//! `github.com/acme-*` covers every acme- organization. Where several
+//! cover a repository the one with the most segments wins. Commits there
+//! must use an email at the policy's domain, and be signed if it says so.
+//! Detection prefers an identity that complies over one merely on the same
+//! hostname, the commit guard enforces the policy recorded in a guarded
+//! repository's config, and the GUI lists repositories breaking one.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, Identity, OrgPolicy};
+use crate::detect::{self, RemoteUrl};
+use crate::error::{CliError, Error};
+use crate::gitconfig;
+
+/// Whether `segment` matches a pattern segment, ignoring case
+fn segment_matches(pattern: &str, segment: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let segment = segment.to_lowercase();
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let [first, middle @ .., last] = parts.as_slice() else {
+        return pattern == segment;
+    };
+    let Some(mut rest) = segment.strip_prefix(first) else {
+        return false;
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Whether `pattern` covers `target`, a `hostname/path`
+pub fn covers(pattern: &str, target: &str) -> bool {
+    let pattern: Vec<&str> = pattern.trim_matches('/').split('/').collect();
+    let target: Vec<&str> = target.trim_matches('/').split('/').collect();
+    !pattern[0].is_empty()
+        && target.len() >= pattern.len()
+        && pattern
+            .iter()
+            .zip(&target)
+            .all(|(pattern, segment)| segment_matches(pattern, segment))
+}
+
+/// A remote URL as policies see it, e.g. "github.com/acme-corp/api";
+/// `hostnames` maps SSH aliases to their `HostName`
+pub fn target(url: &str, hostnames: &BTreeMap<String, String>) -> Option<String> {
+    let remote = RemoteUrl::parse(url)?;
+    let hostname = hostnames
+        .get(&remote.host)
+        .map(|h| h.to_lowercase())
+        .unwrap_or(remote.host);
+    Some(format!("{}/{}", hostname, remote.path))
+}
+
+/// The most specific policy covering `target`
+pub fn policy_for<'a>(config: &'a Config, target: &str) -> Option<&'a OrgPolicy> {
+    config
+        .settings
+        .policies
+        .iter()
+        .filter(|policy| covers(&policy.pattern, target))
+        .max_by_key(|policy| policy.pattern.trim_matches('/').split('/').count())
+}
+
+/// The policy's domain without a leading `@`, lowercased
+pub fn domain(policy: &OrgPolicy) -> String {
+    policy
+        .email_domain
+        .trim()
+        .trim_start_matches('@')
+        .to_lowercase()
+}
+
+/// Whether the policy allows `email`
+pub fn email_allowed(policy: &OrgPolicy, email: &str) -> bool {
+    let domain = domain(policy);
+    domain.is_empty() || email.to_lowercase().ends_with(&format!("@{}", domain))
+}
+
+/// Whether switching to the identity signs commits
+pub fn identity_signs(identity: &Identity) -> bool {
+    gitconfig::identity_settings(identity)
+        .iter()
+        .any(|(key, value)| key == "commit.gpgsign" && value == "true")
+}
+
+/// Whether commits made as the identity satisfy the policy
+pub fn identity_complies(policy: &OrgPolicy, identity: &Identity) -> bool {
+    email_allowed(policy, &identity.email) && (!policy.require_signing || identity_signs(identity))
+}
+
+/// One way a repository breaks its policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The email isn't at the required domain
+    Email { email: String, domain: String },
+    /// No user.email applies
+    NoEmail { domain: String },
+    /// Signing is required but commit.gpgsign isn't on
+    Unsigned,
+}
+
+impl Violation {
+    pub fn display_text(&self) -> String {
+        match self {
+            Self::Email { email, domain } => format!("<{}> isn't an @{} email", email, domain),
+            Self::NoEmail { domain } => format!("no user.email, an @{} one is required", domain),
+            Self::Unsigned => "commits aren't signed".to_string(),
+        }
+    }
+}
+
+/// How a repository's effective settings break the policy
+pub fn violations(policy: &OrgPolicy, email: &str, signs: bool) -> Vec<Violation> {
+    let mut found = Vec::new();
+    let domain = domain(policy);
+    if email.is_empty() && !domain.is_empty() {
+        found.push(Violation::NoEmail { domain });
+    } else if !email_allowed(policy, email) {
+        found.push(Violation::Email {
+            email: email.to_string(),
+            domain,
+        });
+    }
+    if policy.require_signing && !signs {
+        found.push(Violation::Unsigned);
+    }
+    found
+}
+
+/// A repository covered by a policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoReport {
+    pub path: PathBuf,
+    /// The remote URL the policy matched
+    pub url: String,
+    pub policy: OrgPolicy,
+    pub violations: Vec<Violation>,
+}
+
+impl RepoReport {
+    pub fn display_text(&self) -> String {
+        if self.violations.is_empty() {
+            return format!("Complies with {}", self.policy.pattern);
+        }
+        let texts: Vec<String> = self
+            .violations
+            .iter()
+            .map(Violation::display_text)
+            .collect();
+        format!("{}: {}", self.policy.pattern, texts.join("; "))
+    }
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<String, CliError> {
+    let mut all = vec!["-C".to_string(), repo.to_string_lossy().into_owned()];
+    all.extend(cli::args(args));
+    let options = RunOptions {
+        timeout: Duration::from_secs(10),
+        retries: 0,
+        cancel: None,
+    };
+    cli::run_program("git", &all, &options)
+}
+
+/// A key as git resolves it in the repository; empty when unset
+fn effective(repo: &Path, args: &[&str]) -> Result<String, Error> {
+    match git(repo, args) {
+        Ok(out) => Ok(out.trim().to_string()),
+        // Exit status 1: not set anywhere
+        Err(CliError::NonZeroExit { code: Some(1), .. }) => Ok(String::new()),
+        Err(e) => Err(Error::from_cli(e)),
+    }
+}
+
+/// The policy covering one of the repository's remotes, origin first, and
+/// that remote's URL; blocks
+pub fn applicable(config: &Config, repo: &Path) -> Result<Option<(OrgPolicy, String)>, Error> {
+    if config.settings.policies.is_empty() {
+        return Ok(None);
+    }
+    let hostnames = detect::ssh_hostnames();
+    Ok(detect::remotes(repo)?.into_iter().find_map(|(_, url)| {
+        let policy = policy_for(config, &target(&url, &hostnames)?)?;
+        Some((policy.clone(), url))
+    }))
+}
+
+/// Check a repository's effective email and signing against its policy;
+/// None if no policy covers it. Blocks
+pub fn check(config: &Config, repo: &Path) -> Result<Option<RepoReport>, Error> {
+    let Some((policy, url)) = applicable(config, repo)? else {
+        return Ok(None);
+    };
+    let email = effective(repo, &["config", "--get", "user.email"])?;
+    let signs = effective(repo, &["config", "--bool", "--get", "commit.gpgsign"])? == "true";
+    Ok(Some(RepoReport {
+        path: repo.to_path_buf(),
+        url,
+        violations: violations(&policy, &email, signs),
+        policy,
+    }))
+}
+
+/// The repositories breaking a policy; ones git can't read are skipped.
+/// Blocks
+pub fn scan(config: &Config, repos: &[PathBuf]) -> Vec<RepoReport> {
+    repos
+        .iter()
+        .filter_map(|repo| match check(config, repo) {
+            Ok(report) => report,
+            Err(e) => {
+                tracing::debug!("Skipping {}: {}", repo.display(), e);
+                None
+            }
+        })
+        .filter(|report| !report.violations.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::ConfigBuilder;
+
+    #[test]
+    fn test_policy_matching_and_violations() {
+        assert!(covers("github.com/acme-*", "github.com/acme-corp/api"));
+        assert!(covers("GitHub.com/acme-*/api", "github.com/acme-labs/api"));
+        assert!(!covers("github.com/acme-*", "github.com/acme"));
+        assert!(!covers("github.com/acme-*", "gitlab.com/acme-corp/api"));
+        assert!(!covers("github.com/acme-*/api", "github.com/acme-corp"));
+        assert!(covers("*.acme.dev", "git.acme.dev/team/api"));
+        assert!(!covers("", "github.com/acme"));
+
+        let hostnames = BTreeMap::from([("github-work".to_string(), "github.com".to_string())]);
+        assert_eq!(
+            target("git@github-work:acme-corp/api.git", &hostnames).as_deref(),
+            Some("github.com/acme-corp/api")
+        );
+
+        let config = ConfigBuilder::new()
+            .settings(|settings| {
+                settings.policies = vec![
+                    OrgPolicy {
+                        pattern: "github.com/acme-*".to_string(),
+                        email_domain: "@Acme.com".to_string(),
+                        require_signing: false,
+                    },
+                    OrgPolicy {
+                        pattern: "github.com/acme-corp/*".to_string(),
+                        email_domain: "acme.com".to_string(),
+                        require_signing: true,
+                    },
+                ]
+            })
+            .build();
+        let policy = policy_for(&config, "github.com/acme-corp/api").unwrap();
+        assert!(policy.require_signing);
+        assert!(
+            !policy_for(&config, "github.com/acme-labs/api")
+                .unwrap()
+                .require_signing
+        );
+        assert!(email_allowed(policy, "Me@ACME.com"));
+        assert!(!email_allowed(policy, "me@notacme.com"));
+
+        let found = violations(policy, "me@example.com", false);
+        assert_eq!(
+            found,
+            vec![
+                Violation::Email {
+                    email: "me@example.com".to_string(),
+                    domain: "acme.com".to_string()
+                },
+                Violation::Unsigned
+            ]
+        );
+        assert_eq!(
+            found[0].display_text(),
+            "<me@example.com> isn't an @acme.com email"
+        );
+        assert!(violations(policy, "me@acme.com", true).is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+//! Paths of files picked in a file chooser
+//!
+//! Under Flatpak the file chooser is a portal, and a picked file arrives
+//! as a document portal path, `$XDG_RUNTIME_DIR/doc/<id>/<name>`, which
+//! works for reading and writing it but isn't where the file is. Saved in
+//! config, or compared with a repository's location, a path needs to be
+//! the host's, so the document portal is asked for that. It is used when
+//! the sandbox can reach it, e.g. with home directory access, and the
+//! document path otherwise.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use gtk4::prelude::*;
+use gtk4::{gio, glib};
+use remote_juggler_gui::platform;
+
+const DOCUMENTS_NAME: &str = "org.freedesktop.portal.Documents";
+const DOCUMENTS_PATH: &str = "/org/freedesktop/portal/documents";
+
+/// How long to wait for the document portal, in milliseconds
+const TIMEOUT_MS: i32 = 2000;
+
+/// A picked file's local path, translated to the host's where possible
+pub fn file_path(file: &gio::File) -> Option<PathBuf> {
+    let path = file.path()?;
+    if !platform::is_flatpak() {
+        return Some(path);
+    }
+    Some(
+        host_path(&path)
+            .filter(|host| host.exists())
+            .unwrap_or(path),
+    )
+}
+
+/// Where a document portal path is on the host; None for other paths, or
+/// if the portal predates `GetHostPaths`
+fn host_path(path: &Path) -> Option<PathBuf> {
+    let portal = platform::document_portal_dir()?;
+    let (id, rest) = platform::split_document_path(&portal, path)?;
+    let bus = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE).ok()?;
+    let reply = bus
+        .call_sync(
+            Some(DOCUMENTS_NAME),
+            DOCUMENTS_PATH,
+            DOCUMENTS_NAME,
+            "GetHostPaths",
+            Some(&(vec![id.clone()],).to_variant()),
+            Some(glib::VariantTy::new("(a{say})").ok()?),
+            gio::DBusCallFlags::NONE,
+            TIMEOUT_MS,
+            gio::Cancellable::NONE,
+        )
+        .map_err(|e| tracing::debug!("Document portal has no host path for {}: {}", id, e))
+        .ok()?;
+    let (paths,) = reply.get::<(HashMap<String, Vec<u8>>,)>()?;
+    // A bytestring, NUL-terminated
+    let mut bytes = paths.get(&id)?.clone();
+    if bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    let host = String::from_utf8(bytes).ok()?;
+    Some(PathBuf::from(host).join(rest))
+}
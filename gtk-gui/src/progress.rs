@@ -0,0 +1,198 @@
+//! Progress reporting protocol for long-running operations
+//!
+//! Core operations (discover, crawl, ingest, export) emit one JSON object per
+//! line on stdout when `REMOTE_JUGGLER_PROGRESS=json` is set:
+//!
+//! ```text
+//! {"event":"started","operation":"crawl","total":42}
+//! {"event":"progress","current":3,"total":42,"item":"~/code/app/.env"}
+//! {"event":"finished","summary":"Ingested 12 entries"}
+//! ```
+//!
+//! Any other line is ordinary output and is passed through untouched, so an
+//! older CLI that doesn't speak the protocol still works.
+
+use serde::{Deserialize, Serialize};
+
+/// Environment variable that asks the CLI to emit progress events
+pub const PROGRESS_ENV: &str = "REMOTE_JUGGLER_PROGRESS";
+
+/// A single progress event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    /// The operation began; `total` is known up front for some operations
+    Started {
+        operation: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        total: Option<u64>,
+    },
+    /// One more item was processed
+    Progress {
+        current: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        total: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        item: Option<String>,
+    },
+    /// The operation completed
+    Finished {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        summary: Option<String>,
+    },
+}
+
+impl ProgressEvent {
+    /// Parse a stdout line, returning None for ordinary output
+    pub fn parse_line(line: &str) -> Option<Self> {
+        let trimmed = line.trim();
+        if !trimmed.starts_with('{') {
+            return None;
+        }
+        serde_json::from_str(trimmed).ok()
+    }
+
+    /// Serialize as a single protocol line (without trailing newline)
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Accumulated state of an operation, built from its events
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgressState {
+    pub operation: String,
+    pub current: u64,
+    pub total: Option<u64>,
+    pub item: Option<String>,
+    pub finished: bool,
+    pub summary: Option<String>,
+}
+
+impl ProgressState {
+    /// Fold an event into the state
+    pub fn apply(&mut self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::Started { operation, total } => {
+                *self = ProgressState {
+                    operation: operation.clone(),
+                    total: *total,
+                    ..Default::default()
+                };
+            }
+            ProgressEvent::Progress {
+                current,
+                total,
+                item,
+            } => {
+                self.current = *current;
+                if total.is_some() {
+                    self.total = *total;
+                }
+                self.item = item.clone();
+            }
+            ProgressEvent::Finished { summary } => {
+                self.finished = true;
+                self.summary = summary.clone();
+            }
+        }
+    }
+
+    /// Completed fraction in 0.0..=1.0, or None if the total is unknown
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.current as f64 / total as f64).min(1.0)),
+            None => None,
+        }
+    }
+
+    /// Short human-readable description, e.g. "3 / 42"
+    pub fn counter_text(&self) -> String {
+        match self.total {
+            Some(total) => format!("{} / {}", self.current, total),
+            None => format!("{}", self.current),
+        }
+    }
+}
+
+/// Remove protocol lines from captured stdout, keeping ordinary output
+pub fn strip_events(stdout: &str) -> String {
+    stdout
+        .lines()
+        .filter(|line| ProgressEvent::parse_line(line).is_none())
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_events() {
+        assert_eq!(
+            ProgressEvent::parse_line(r#"{"event":"started","operation":"crawl","total":2}"#),
+            Some(ProgressEvent::Started {
+                operation: "crawl".to_string(),
+                total: Some(2)
+            })
+        );
+        assert_eq!(
+            ProgressEvent::parse_line(r#"{"event":"progress","current":1,"item":"a/.env"}"#),
+            Some(ProgressEvent::Progress {
+                current: 1,
+                total: None,
+                item: Some("a/.env".to_string())
+            })
+        );
+        assert_eq!(ProgressEvent::parse_line("Found 3 credentials"), None);
+        assert_eq!(ProgressEvent::parse_line("{not json"), None);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let event = ProgressEvent::Finished {
+            summary: Some("done".to_string()),
+        };
+        assert_eq!(ProgressEvent::parse_line(&event.to_line()), Some(event));
+    }
+
+    #[test]
+    fn test_state_fraction() {
+        let mut state = ProgressState::default();
+        state.apply(&ProgressEvent::Started {
+            operation: "ingest".to_string(),
+            total: Some(4),
+        });
+        state.apply(&ProgressEvent::Progress {
+            current: 1,
+            total: None,
+            item: None,
+        });
+        assert_eq!(state.fraction(), Some(0.25));
+        assert_eq!(state.counter_text(), "1 / 4");
+
+        state.apply(&ProgressEvent::Finished { summary: None });
+        assert!(state.finished);
+    }
+
+    #[test]
+    fn test_unknown_total_has_no_fraction() {
+        let mut state = ProgressState::default();
+        state.apply(&ProgressEvent::Progress {
+            current: 7,
+            total: None,
+            item: None,
+        });
+        assert_eq!(state.fraction(), None);
+        assert_eq!(state.counter_text(), "7");
+    }
+
+    #[test]
+    fn test_strip_events() {
+        let stdout =
+            "{\"event\":\"started\",\"operation\":\"x\"}\nhello\n{\"event\":\"finished\"}\n";
+        assert_eq!(strip_events(stdout), "hello\n");
+    }
+}
@@ -0,0 +1,513 @@
+//! Provider API client with conditional requests and rate-limit budgets
+//!
+//! Key registration badges, token checks, organization discovery and
+//! collaborator key lookups all call the same provider APIs, often again
+//! on every page rebuild. GitHub allows 5,000 authenticated requests an
+//! hour and 60 anonymous ones, so every provider call goes through this
+//! client. GET responses are cached on disk per API and token, under
+//! `~/.cache/remote-juggler/api`; a response is reused while its
+//! `Cache-Control: max-age` lasts, then revalidated with its ETag or
+//! Last-Modified date, and a 304 answer doesn't count against GitHub's
+//! limit. The rate-limit headers of every answer are recorded with the
+//! cache. Once less than a tenth of the budget is left, cached responses
+//! are served however old they are; once it is used up, nothing is sent
+//! until it resets. A write clears the API's cached responses for the
+//! token, since it changes what they would say.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::expiry;
+use crate::http;
+use crate::platform;
+use crate::pubkeys::Provider;
+
+/// A provider's request budget, as its last answer reported it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    /// Unix time the budget is refilled
+    pub reset_at: u64,
+}
+
+impl RateLimit {
+    /// Read GitHub's and Gitea's `X-RateLimit-*` or GitLab's
+    /// `RateLimit-*` headers; the reset is a Unix time or, if small, a
+    /// number of seconds from now
+    pub fn from_headers(header: impl Fn(&str) -> Option<String>, now: u64) -> Option<Self> {
+        let number = |name: &str| {
+            header(&format!("x-ratelimit-{}", name))
+                .or_else(|| header(&format!("ratelimit-{}", name)))
+                .and_then(|value| value.trim().parse::<u64>().ok())
+        };
+        let reset = number("reset")?;
+        Some(Self {
+            limit: number("limit")?,
+            remaining: number("remaining")?,
+            reset_at: if reset < 1_000_000_000 {
+                now + reset
+            } else {
+                reset
+            },
+        })
+    }
+
+    fn is_current(&self, now: u64) -> bool {
+        self.reset_at > now
+    }
+
+    /// Less than a tenth of the budget is left
+    pub fn is_low(&self, now: u64) -> bool {
+        self.is_current(now) && self.remaining < self.limit.div_ceil(10)
+    }
+
+    pub fn is_exhausted(&self, now: u64) -> bool {
+        self.is_current(now) && self.remaining == 0
+    }
+
+    pub fn display_text(&self, now: u64) -> String {
+        if !self.is_current(now) {
+            return format!("{} API calls an hour", self.limit);
+        }
+        format!(
+            "{} of {} API calls left, reset in {} min",
+            self.remaining,
+            self.limit,
+            self.reset_at.saturating_sub(now).div_ceil(60)
+        )
+    }
+}
+
+/// The seconds `Cache-Control` allows a response to be reused; none
+/// unless it says so
+pub fn max_age(cache_control: Option<&str>) -> u64 {
+    let Some(value) = cache_control else {
+        return 0;
+    };
+    let directives: Vec<&str> = value.split(',').map(str::trim).collect();
+    if directives.contains(&"no-store") || directives.contains(&"no-cache") {
+        return 0;
+    }
+    directives
+        .iter()
+        .find_map(|d| d.strip_prefix("max-age="))
+        .and_then(|age| age.parse().ok())
+        .unwrap_or(0)
+}
+
+/// An API answer, fresh or cached
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+    pub body: Value,
+    /// By lowercase name
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
+}
+
+/// A cached GET response
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub response: Response,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    pub fetched_at: u64,
+    /// Seconds it can be reused without asking
+    #[serde(default)]
+    pub max_age: u64,
+}
+
+impl Entry {
+    pub fn is_fresh(&self, now: u64) -> bool {
+        now < self.fetched_at + self.max_age
+    }
+
+    fn revalidated(&mut self, max_age: u64, now: u64) {
+        self.fetched_at = now;
+        self.max_age = max_age;
+    }
+}
+
+/// Everything cached for one API and token
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimit>,
+    /// By URL
+    #[serde(default)]
+    pub entries: BTreeMap<String, Entry>,
+}
+
+impl CacheFile {
+    /// An unreadable cache is an empty one
+    fn load(path: &Option<PathBuf>) -> Self {
+        platform::load_json_state(path.clone())
+    }
+
+    /// Best effort; the next call fetches again if it fails
+    fn save(&self, path: &Option<PathBuf>) {
+        if let (Some(path), Err(e)) = (path, platform::save_json_state(path.clone(), self)) {
+            tracing::warn!("Writing the API cache {} failed: {}", path.display(), e);
+        }
+    }
+}
+
+/// Serializes reads and writes of the cache files between threads;
+/// requests themselves run unlocked
+fn lock() -> MutexGuard<'static, ()> {
+    static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+    LOCK.get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The budget each API last reported, whichever token it was for
+fn budgets() -> &'static Mutex<HashMap<String, RateLimit>> {
+    static BUDGETS: OnceLock<Mutex<HashMap<String, RateLimit>>> = OnceLock::new();
+    BUDGETS.get_or_init(Mutex::default)
+}
+
+/// The budget an API last reported in this process, for display
+pub fn last_rate_limit(api_base: &str) -> Option<RateLimit> {
+    let budgets = budgets().lock().ok()?;
+    budgets.get(api_base.trim_end_matches('/')).copied()
+}
+
+/// The cache file name for an API and token; the token itself isn't kept
+pub fn scope(api_base: &str, token: Option<&str>) -> String {
+    let digest = Sha256::digest(format!("{}\n{}", api_base, token.unwrap_or_default()));
+    digest[..16].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The host part of an API root, for messages
+fn host(api_base: &str) -> &str {
+    let rest = api_base
+        .split_once("://")
+        .map_or(api_base, |(_, rest)| rest);
+    rest.split('/').next().unwrap_or(rest)
+}
+
+/// Calls to one provider API, as one account or anonymously
+#[derive(Debug, Clone)]
+pub struct Client {
+    pub provider: Provider,
+    pub api_base: String,
+    token: Option<String>,
+    path: Option<PathBuf>,
+}
+
+impl Client {
+    pub fn new(provider: Provider, api_base: &str, token: Option<&str>) -> Self {
+        let api_base = api_base.trim_end_matches('/').to_string();
+        let path = platform::cache_dir().map(|dir| {
+            dir.join("api")
+                .join(format!("{}.json", scope(&api_base, token)))
+        });
+        Self {
+            provider,
+            api_base,
+            token: token.map(str::to_string),
+            path,
+        }
+    }
+
+    /// The budget the API last reported for this token
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        let _guard = lock();
+        CacheFile::load(&self.path).rate_limit
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let request = http::agent()
+            .request(method, url)
+            .set("Accept", "application/json");
+        match &self.token {
+            Some(token) => {
+                let (header, value) = self.provider.auth_header(token);
+                request.set(header, &value)
+            }
+            None => request,
+        }
+    }
+
+    fn limited(&self, limit: &RateLimit) -> Error {
+        Error::RateLimited {
+            host: host(&self.api_base).to_string(),
+            reset_at: limit.reset_at,
+        }
+    }
+
+    /// Record an answer's budget, and clear the cached responses after a
+    /// write; returns the updated cache
+    fn record(&self, response: &ureq::Response, clear: bool) -> CacheFile {
+        let _guard = lock();
+        let mut file = CacheFile::load(&self.path);
+        let header = |name: &str| response.header(name).map(str::to_string);
+        if let Some(limit) = RateLimit::from_headers(header, expiry::now()) {
+            file.rate_limit = Some(limit);
+            if let Ok(mut budgets) = budgets().lock() {
+                budgets.insert(self.api_base.clone(), limit);
+            }
+        }
+        if clear {
+            file.entries.clear();
+        }
+        file.save(&self.path);
+        file
+    }
+
+    /// Convert an error answer, recognizing a used-up budget
+    fn failed(&self, err: ureq::Error, clear: bool) -> Error {
+        if let ureq::Error::Status(status, response) = err {
+            let file = self.record(&response, clear);
+            let now = expiry::now();
+            if let Some(limit) = file.rate_limit {
+                if matches!(status, 403 | 429) && limit.is_exhausted(now) {
+                    return self.limited(&limit);
+                }
+            }
+            return http::map_error(ureq::Error::Status(status, response));
+        }
+        http::map_error(err)
+    }
+
+    /// Run a call in a span naming the API host, the path and, if cached
+    /// responses answered it, `cached`
+    fn traced<T>(
+        &self,
+        method: &str,
+        url: &str,
+        call: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let host = host(&self.api_base);
+        let path = url.split('?').next().unwrap_or(url);
+        let span = tracing::info_span!(
+            "provider_api",
+            otel.name = %format!("{} {}", method, host),
+            http.request.method = method,
+            server.address = host,
+            url.path = path.strip_prefix(&self.api_base).unwrap_or(path),
+            cached = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        let result = span.in_scope(call);
+        if let Err(e) = &result {
+            span.record("error", tracing::field::display(e));
+        }
+        result
+    }
+
+    /// GET a JSON document, from the cache when it allows; blocks
+    pub fn get(&self, url: &str) -> Result<Response, Error> {
+        self.traced("GET", url, || self.get_untraced(url))
+    }
+
+    fn get_untraced(&self, url: &str) -> Result<Response, Error> {
+        let now = expiry::now();
+        let (cached, limit) = {
+            let _guard = lock();
+            let file = CacheFile::load(&self.path);
+            (file.entries.get(url).cloned(), file.rate_limit)
+        };
+        if let Some(entry) = &cached {
+            if entry.is_fresh(now) || limit.is_some_and(|l| l.is_low(now)) {
+                tracing::Span::current().record("cached", true);
+                return Ok(entry.response.clone());
+            }
+        }
+        if let Some(limit) = limit.filter(|l| l.is_exhausted(now)) {
+            return Err(self.limited(&limit));
+        }
+
+        let mut request = self.request("GET", url);
+        if let Some(entry) = &cached {
+            if let Some(etag) = &entry.etag {
+                request = request.set("If-None-Match", etag);
+            }
+            if let Some(date) = &entry.last_modified {
+                request = request.set("If-Modified-Since", date);
+            }
+        }
+        let response = request.call().map_err(|e| self.failed(e, false))?;
+        let age = max_age(response.header("cache-control"));
+        let now = expiry::now();
+        if response.status() == 304 {
+            if let Some(mut entry) = cached {
+                tracing::Span::current().record("cached", true);
+                entry.revalidated(age, now);
+                let _ = self.record(&response, false);
+                self.store(url, &entry);
+                return Ok(entry.response);
+            }
+        }
+
+        let _ = self.record(&response, false);
+        let etag = response.header("etag").map(str::to_string);
+        let last_modified = response.header("last-modified").map(str::to_string);
+        let headers = response
+            .headers_names()
+            .into_iter()
+            .filter(|name| !name.eq_ignore_ascii_case("set-cookie"))
+            .filter_map(|name| {
+                let value = response.header(&name)?.to_string();
+                Some((name.to_lowercase(), value))
+            })
+            .collect();
+        let body = read_body(response)?;
+        let entry = Entry {
+            response: Response { body, headers },
+            etag,
+            last_modified,
+            fetched_at: now,
+            max_age: age,
+        };
+        if entry.etag.is_some() || entry.last_modified.is_some() || entry.max_age > 0 {
+            self.store(url, &entry);
+        }
+        Ok(entry.response)
+    }
+
+    /// GET a JSON document's body; blocks
+    pub fn get_json(&self, url: &str) -> Result<Value, Error> {
+        self.get(url).map(|response| response.body)
+    }
+
+    /// GET a JSON document without caching it, for answers holding
+    /// secrets; blocks
+    pub fn get_uncached(&self, url: &str) -> Result<Value, Error> {
+        self.traced("GET", url, || {
+            if let Some(limit) = self.rate_limit().filter(|l| l.is_exhausted(expiry::now())) {
+                return Err(self.limited(&limit));
+            }
+            let response = self
+                .request("GET", url)
+                .call()
+                .map_err(|e| self.failed(e, false))?;
+            let _ = self.record(&response, false);
+            read_body(response)
+        })
+    }
+
+    fn store(&self, url: &str, entry: &Entry) {
+        let _guard = lock();
+        let mut file = CacheFile::load(&self.path);
+        file.entries.insert(url.to_string(), entry.clone());
+        file.save(&self.path);
+    }
+
+    /// POST, PUT, PATCH or DELETE, with an optional JSON body; an empty
+    /// answer is `Value::Null`. Clears the cached responses. Blocks
+    pub fn send(&self, method: &str, url: &str, body: Option<&Value>) -> Result<Value, Error> {
+        self.traced(method, url, || {
+            if let Some(limit) = self.rate_limit().filter(|l| l.is_exhausted(expiry::now())) {
+                return Err(self.limited(&limit));
+            }
+            let request = self.request(method, url);
+            let response = match body {
+                Some(body) => request.send_json(body),
+                None => request.call(),
+            }
+            .map_err(|e| self.failed(e, true))?;
+            let _ = self.record(&response, true);
+            read_body(response)
+        })
+    }
+}
+
+fn read_body(response: ureq::Response) -> Result<Value, Error> {
+    let text = response.into_string().map_err(|e| Error::InvalidOutput {
+        program: "http",
+        message: e.to_string(),
+    })?;
+    if text.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_str(&text).map_err(|e| Error::InvalidOutput {
+        program: "http",
+        message: e.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limits_and_freshness() {
+        let now = 1_767_225_600;
+        let github = BTreeMap::from([
+            ("x-ratelimit-limit", "5000"),
+            ("x-ratelimit-remaining", "420"),
+            ("x-ratelimit-reset", "1767227400"),
+        ]);
+        let limit =
+            RateLimit::from_headers(|name| github.get(name).map(|v| v.to_string()), now).unwrap();
+        assert_eq!(
+            limit,
+            RateLimit {
+                limit: 5000,
+                remaining: 420,
+                reset_at: 1_767_227_400
+            }
+        );
+        assert!(limit.is_low(now));
+        assert!(!limit.is_exhausted(now));
+        assert!(!limit.is_low(limit.reset_at));
+        assert_eq!(
+            limit.display_text(now),
+            "420 of 5000 API calls left, reset in 30 min"
+        );
+
+        // A relative reset, as the IETF draft headers give it
+        let gitlab = BTreeMap::from([
+            ("ratelimit-limit", "2000"),
+            ("ratelimit-remaining", "0"),
+            ("ratelimit-reset", "30"),
+        ]);
+        let limit =
+            RateLimit::from_headers(|name| gitlab.get(name).map(|v| v.to_string()), now).unwrap();
+        assert_eq!(limit.reset_at, now + 30);
+        assert!(limit.is_exhausted(now));
+        assert!(RateLimit::from_headers(|_| None, now).is_none());
+
+        assert_eq!(max_age(Some("private, max-age=60, s-maxage=60")), 60);
+        assert_eq!(max_age(Some("max-age=0, private, must-revalidate")), 0);
+        assert_eq!(max_age(Some("no-cache, max-age=60")), 0);
+        assert_eq!(max_age(None), 0);
+
+        let entry = Entry {
+            fetched_at: now,
+            max_age: 60,
+            ..Default::default()
+        };
+        assert!(entry.is_fresh(now + 59));
+        assert!(!entry.is_fresh(now + 60));
+
+        assert_eq!(scope("https://api.github.com", Some("a")).len(), 32);
+        assert_ne!(
+            scope("https://api.github.com", Some("a")),
+            scope("https://api.github.com", Some("b"))
+        );
+        assert_eq!(
+            host("https://gitlab.example.com/api/v4"),
+            "gitlab.example.com"
+        );
+    }
+}
@@ -0,0 +1,280 @@
+//! gh and glab accounts following the identity
+//!
+//! The GitHub and GitLab CLIs keep a login of their own, so after a switch
+//! `gh pr create` or `glab mr create` would still act as whoever was
+//! logged in before. `switch` logs the CLI in with the identity's token
+//! from the key store or its environment variable, which also makes that
+//! account the active one. gh keeps several accounts per host, so without
+//! a token it switches to one it already has under the identity's user;
+//! glab keeps one. The state shown is the account each CLI has active on
+//! the identity's hostname.
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::cli::{self, RunOptions};
+use crate::config::Identity;
+use crate::error::{CliError, Error};
+use crate::pubkeys::{self, Provider};
+
+/// A provider CLI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Gh,
+    Glab,
+}
+
+impl Tool {
+    /// The CLI for the identity's provider, if it has one
+    pub fn for_identity(identity: &Identity) -> Option<Self> {
+        match Provider::for_identity(identity)? {
+            Provider::GitHub => Some(Self::Gh),
+            Provider::GitLab => Some(Self::Glab),
+            Provider::Bitbucket | Provider::Gitea | Provider::Sourcehut => None,
+        }
+    }
+
+    pub fn program(&self) -> &'static str {
+        match self {
+            Self::Gh => "gh",
+            Self::Glab => "glab",
+        }
+    }
+
+    fn provider(&self) -> Provider {
+        match self {
+            Self::Gh => Provider::GitHub,
+            Self::Glab => Provider::GitLab,
+        }
+    }
+
+    fn default_hostname(&self) -> &'static str {
+        match self {
+            Self::Gh => "github.com",
+            Self::Glab => "gitlab.com",
+        }
+    }
+}
+
+/// The accounts a CLI has on the identity's hostname
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthState {
+    pub tool: Tool,
+    pub hostname: String,
+    /// The account commands run as; None when logged out
+    pub active: Option<String>,
+    /// Every account logged in on the hostname, active one included
+    pub accounts: Vec<String>,
+}
+
+impl AuthState {
+    /// Whether the active account is the identity's user
+    pub fn is_identity(&self, identity: &Identity) -> bool {
+        self.active
+            .as_deref()
+            .is_some_and(|active| active.eq_ignore_ascii_case(identity.user.trim()))
+    }
+
+    pub fn display_text(&self, identity: &Identity) -> String {
+        let Some(active) = &self.active else {
+            return format!("Not logged in to {}", self.hostname);
+        };
+        let mut text = if self.is_identity(identity) {
+            format!("{} on {}", active, self.hostname)
+        } else {
+            format!(
+                "{} on {}, not the identity's user {}",
+                active, self.hostname, identity.user
+            )
+        };
+        let others = self.accounts.len().saturating_sub(1);
+        if others > 0 {
+            text.push_str(&format!(
+                " ({} other {})",
+                others,
+                if others == 1 { "account" } else { "accounts" }
+            ));
+        }
+        text
+    }
+}
+
+/// The hostname the identity's account is on
+pub fn hostname(tool: Tool, identity: &Identity) -> String {
+    let hostname = identity.hostname.trim();
+    if hostname.is_empty() {
+        tool.default_hostname().to_string()
+    } else {
+        hostname.to_lowercase()
+    }
+}
+
+/// Parse `gh auth status` into (active account, all accounts); output of
+/// gh before 2.40 has one account, `as <login>`
+pub fn parse_gh_status(text: &str) -> (Option<String>, Vec<String>) {
+    let mut accounts: Vec<String> = Vec::new();
+    let mut active = None;
+    let mut marked = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some((_, rest)) = line.split_once("Logged in to ") {
+            let login = rest
+                .split_once(" account ")
+                .or_else(|| rest.split_once(" as "))
+                .and_then(|(_, after)| after.split_whitespace().next());
+            if let Some(login) = login {
+                accounts.push(login.to_string());
+            }
+        } else if let Some(value) = line.strip_prefix("- Active account:") {
+            marked = true;
+            if value.trim() == "true" {
+                active = accounts.last().cloned();
+            }
+        }
+    }
+    if !marked && accounts.len() == 1 {
+        active = accounts.first().cloned();
+    }
+    (active, accounts)
+}
+
+fn options() -> RunOptions {
+    RunOptions {
+        timeout: Duration::from_secs(20),
+        retries: 0,
+        cancel: None,
+    }
+}
+
+fn run(tool: Tool, args: &[&str]) -> Result<String, CliError> {
+    cli::run_program(tool.program(), &cli::args(args), &options())
+}
+
+/// What the identity's CLI is logged in as; None without a CLI for its
+/// provider or with the CLI not installed. Blocks
+pub fn status(identity: &Identity) -> Result<Option<AuthState>, Error> {
+    let Some(tool) = Tool::for_identity(identity) else {
+        return Ok(None);
+    };
+    if cli::find_program(tool.program()).is_none() {
+        return Ok(None);
+    }
+    let hostname = hostname(tool, identity);
+    let (active, accounts) = match tool {
+        Tool::Gh => match run(tool, &["auth", "status", "--hostname", &hostname]) {
+            Ok(out) => parse_gh_status(&out),
+            // Exit status 1: not logged in, or a token no longer works
+            Err(CliError::NonZeroExit { code: Some(1), .. }) => (None, Vec::new()),
+            Err(e) => return Err(Error::from_cli(e)),
+        },
+        // glab reports its status on stderr, so ask the API who it is
+        Tool::Glab => match run(tool, &["api", "user", "--hostname", &hostname]) {
+            Ok(out) => {
+                let login = serde_json::from_str::<Value>(&out)
+                    .ok()
+                    .and_then(|user| user["username"].as_str().map(str::to_string));
+                (login.clone(), login.into_iter().collect())
+            }
+            Err(CliError::NonZeroExit { .. }) => (None, Vec::new()),
+            Err(e) => return Err(Error::from_cli(e)),
+        },
+    };
+    Ok(Some(AuthState {
+        tool,
+        hostname,
+        active,
+        accounts,
+    }))
+}
+
+/// Make the identity's account the CLI's active one: log in with its
+/// token, else switch gh to an account it already has for the identity's
+/// user. Returns the state afterwards; blocks
+pub fn switch(name: &str, identity: &Identity) -> Result<AuthState, Error> {
+    let tool = Tool::for_identity(identity).ok_or_else(|| Error::InvalidOutput {
+        program: "provider CLI",
+        message: format!("{} has no gh or glab provider", name),
+    })?;
+    if cli::find_program(tool.program()).is_none() {
+        return Err(Error::InvalidOutput {
+            program: tool.program(),
+            message: format!("{} isn't installed", tool.program()),
+        });
+    }
+    let hostname = hostname(tool, identity);
+    match pubkeys::find_token(name, identity, tool.provider()) {
+        Ok(token) => {
+            let args = match tool {
+                Tool::Gh => cli::args(&["auth", "login", "--hostname", &hostname, "--with-token"]),
+                Tool::Glab => cli::args(&["auth", "login", "--hostname", &hostname, "--stdin"]),
+            };
+            cli::run_program_with_input(
+                tool.program(),
+                &args,
+                &[],
+                &format!("{}\n", token),
+                &options(),
+            )
+            .map_err(Error::from_cli)?;
+        }
+        Err(Error::KeystoreLocked) => return Err(Error::KeystoreLocked),
+        Err(e) if tool == Tool::Gh && !identity.user.trim().is_empty() => {
+            let user = identity.user.trim();
+            run(
+                tool,
+                &["auth", "switch", "--hostname", &hostname, "--user", user],
+            )
+            // Neither a token nor an account to switch to
+            .map_err(|_| e)?;
+        }
+        Err(e) => return Err(e),
+    }
+    status(identity)?.ok_or_else(|| Error::InvalidOutput {
+        program: tool.program(),
+        message: format!("{} isn't installed", tool.program()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::identity;
+
+    #[test]
+    fn test_parse_gh_status() {
+        let current = "github.com\n  \
+            ✓ Logged in to github.com account octocat (keyring)\n  \
+            - Active account: false\n  \
+            - Git operations protocol: ssh\n  \
+            ✓ Logged in to github.com account octo-work (keyring)\n  \
+            - Active account: true\n";
+        let (active, accounts) = parse_gh_status(current);
+        assert_eq!(active.as_deref(), Some("octo-work"));
+        assert_eq!(accounts, vec!["octocat", "octo-work"]);
+
+        let old = "github.com\n  ✓ Logged in to github.com as octocat (oauth_token)\n";
+        assert_eq!(parse_gh_status(old).0.as_deref(), Some("octocat"));
+        assert_eq!(parse_gh_status("").0, None);
+
+        let identity = identity(
+            "github",
+            "github-work",
+            "github.com",
+            "Octo-Work",
+            "me@acme.dev",
+        );
+        assert_eq!(Tool::for_identity(&identity), Some(Tool::Gh));
+        let state = AuthState {
+            tool: Tool::Gh,
+            hostname: "github.com".to_string(),
+            active,
+            accounts,
+        };
+        assert!(state.is_identity(&identity));
+        assert_eq!(
+            state.display_text(&identity),
+            "octo-work on github.com (1 other account)"
+        );
+    }
+}
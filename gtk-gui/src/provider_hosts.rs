@@ -0,0 +1,78 @@
+//! Self-hosted provider hostnames
+//!
+//! Providers are otherwise recognized by name, so `github.com` and an
+//! alias like `github-work` are GitHub but `ghe.acme.corp` or
+//! `git.acme.dev` are nothing. `providerHosts` maps such hostnames to the
+//! provider they run, and optionally to an API root that isn't the
+//! provider's usual one. Detection uses the mapping to match identities
+//! by provider, and identities whose provider is unknown, e.g. "custom",
+//! get the key APIs of their hostname's provider.
+
+use crate::config::{Config, ProviderHost};
+use crate::pubkeys::Provider;
+
+/// The configured instances; empty without a readable config
+pub fn configured() -> Vec<ProviderHost> {
+    Config::load()
+        .map(|config| config.settings.provider_hosts)
+        .unwrap_or_default()
+}
+
+/// The instance at `hostname`, ignoring case
+pub fn find<'a>(hosts: &'a [ProviderHost], hostname: &str) -> Option<&'a ProviderHost> {
+    let hostname = hostname.trim();
+    hosts
+        .iter()
+        .find(|host| !hostname.is_empty() && host.hostname.trim().eq_ignore_ascii_case(hostname))
+}
+
+/// The provider name of the instance at `hostname`, lowercased
+pub fn provider_name(hosts: &[ProviderHost], hostname: &str) -> Option<String> {
+    find(hosts, hostname)
+        .map(|host| host.provider.trim().to_lowercase())
+        .filter(|provider| !provider.is_empty())
+}
+
+/// The provider with a key API running at `hostname`
+pub fn provider(hosts: &[ProviderHost], hostname: &str) -> Option<Provider> {
+    Provider::from_name(&provider_name(hosts, hostname)?)
+}
+
+/// The API root for `provider` at `hostname`: the instance's own if it
+/// sets one
+pub fn api_base(hosts: &[ProviderHost], provider: Provider, hostname: &str) -> String {
+    find(hosts, hostname)
+        .map(|host| host.api_url.trim().trim_end_matches('/'))
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| provider.api_base(hostname))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_hosts() {
+        let hosts: Vec<ProviderHost> = serde_json::from_value(serde_json::json!([
+            {"hostname": "GHE.acme.corp", "provider": "GitHub"},
+            {"hostname": "git.acme.dev", "provider": "gitlab",
+             "apiUrl": "https://git.acme.dev/gitlab/api/v4/"}
+        ]))
+        .unwrap();
+        assert_eq!(
+            provider_name(&hosts, "ghe.acme.corp").as_deref(),
+            Some("github")
+        );
+        assert_eq!(provider(&hosts, "git.acme.dev"), Some(Provider::GitLab));
+        assert_eq!(provider(&hosts, "github.com"), None);
+        assert_eq!(
+            api_base(&hosts, Provider::GitHub, "ghe.acme.corp"),
+            "https://ghe.acme.corp/api/v3"
+        );
+        assert_eq!(
+            api_base(&hosts, Provider::GitLab, "git.acme.dev"),
+            "https://git.acme.dev/gitlab/api/v4"
+        );
+    }
+}
@@ -0,0 +1,746 @@
+//! Public key registration with GitHub, GitLab, Bitbucket, Gitea and
+//! sourcehut
+//!
+//! Checks whether an identity's SSH public key is registered with its
+//! provider account and uploads it if not, using the provider's REST API
+//! (sourcehut's GraphQL one on meta.sr.ht) with the identity's token from
+//! the environment or the key store. Gitea covers Forgejo and Codeberg,
+//! which share its API. The same accounts take GPG keys for `gpg_publish`.
+
+use std::path::PathBuf;
+
+use base64::Engine;
+use serde_json::{json, Value};
+
+use crate::cli;
+use crate::config::{Identity, SigningFormat};
+use crate::databases::expand_tilde;
+use crate::error::{CliError, Error};
+use crate::oauth;
+use crate::platform;
+use crate::provider_api;
+use crate::provider_hosts;
+
+/// A git host with a key API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    GitHub,
+    GitLab,
+    Bitbucket,
+    /// Gitea, Forgejo and Codeberg
+    Gitea,
+    Sourcehut,
+}
+
+impl Provider {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "github" => Some(Self::GitHub),
+            "gitlab" => Some(Self::GitLab),
+            "bitbucket" => Some(Self::Bitbucket),
+            "gitea" | "forgejo" | "codeberg" => Some(Self::Gitea),
+            "sourcehut" | "srht" => Some(Self::Sourcehut),
+            _ => None,
+        }
+    }
+
+    /// The name the config uses, e.g. "gitea" for Codeberg
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::GitHub => "github",
+            Self::GitLab => "gitlab",
+            Self::Bitbucket => "bitbucket",
+            Self::Gitea => "gitea",
+            Self::Sourcehut => "sourcehut",
+        }
+    }
+
+    /// The identity's provider, else the one configured for its hostname
+    pub fn for_identity(identity: &Identity) -> Option<Self> {
+        Self::from_name(&identity.provider)
+            .or_else(|| provider_hosts::provider(&provider_hosts::configured(), &identity.hostname))
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::GitHub => "GitHub",
+            Self::GitLab => "GitLab",
+            Self::Bitbucket => "Bitbucket",
+            Self::Gitea => "Gitea",
+            Self::Sourcehut => "sourcehut",
+        }
+    }
+
+    /// API root for a host, e.g. GitHub Enterprise's `/api/v3`; a
+    /// sourcehut instance keeps its keys on the meta service next to git
+    pub fn api_base(&self, hostname: &str) -> String {
+        match self {
+            Self::GitHub if hostname.is_empty() || hostname == "github.com" => {
+                "https://api.github.com".to_string()
+            }
+            Self::GitHub => format!("https://{}/api/v3", hostname),
+            Self::GitLab if hostname.is_empty() => "https://gitlab.com/api/v4".to_string(),
+            Self::GitLab => format!("https://{}/api/v4", hostname),
+            Self::Bitbucket => "https://api.bitbucket.org/2.0".to_string(),
+            Self::Gitea if hostname.is_empty() => "https://codeberg.org/api/v1".to_string(),
+            Self::Gitea => format!("https://{}/api/v1", hostname),
+            Self::Sourcehut => {
+                let domain = hostname.strip_prefix("git.").unwrap_or(hostname);
+                let domain = if domain.is_empty() { "sr.ht" } else { domain };
+                format!("https://meta.{}", domain)
+            }
+        }
+    }
+
+    /// Provider-wide token variable, as the CLI reads it
+    fn token_env(&self) -> &'static str {
+        match self {
+            Self::GitHub => "GITHUB_TOKEN",
+            Self::GitLab => "GITLAB_TOKEN",
+            Self::Bitbucket => "BITBUCKET_TOKEN",
+            Self::Gitea => "GITEA_TOKEN",
+            Self::Sourcehut => "SRHT_TOKEN",
+        }
+    }
+
+    /// Authorization header for a token; a Bitbucket app password is
+    /// stored as `username:password` and sent with basic auth. GitLab
+    /// takes personal access and OAuth tokens alike as bearer tokens
+    pub fn auth_header(&self, token: &str) -> (&'static str, String) {
+        match self {
+            Self::Gitea => ("Authorization", format!("token {}", token)),
+            Self::Bitbucket if token.contains(':') => (
+                "Authorization",
+                format!(
+                    "Basic {}",
+                    base64::engine::general_purpose::STANDARD.encode(token)
+                ),
+            ),
+            _ => ("Authorization", format!("Bearer {}", token)),
+        }
+    }
+}
+
+/// Environment variables checked for an identity's token, most specific
+/// first
+pub fn token_envs(identity: &Identity, provider: Provider) -> Vec<String> {
+    identity
+        .token_env_var
+        .iter()
+        .cloned()
+        .chain([provider.token_env().to_string()])
+        .collect()
+}
+
+/// Key store entries checked for an identity's token, most specific first
+pub fn token_paths(name: &str, identity: &Identity, provider: Provider) -> Vec<String> {
+    let group = format!("RemoteJuggler/Tokens/{}", provider.display_name());
+    identity
+        .keepassxc_entry
+        .iter()
+        .cloned()
+        .chain([format!("{}/{}", group, name), format!("{}/default", group)])
+        .collect()
+}
+
+/// Find the identity's API token, in the CLI's order: key store, then
+/// environment; blocks
+pub fn find_token(name: &str, identity: &Identity, provider: Provider) -> Result<String, Error> {
+    // A signed-in token about to expire is renewed first
+    if let Err(e) = oauth::refresh_if_due(name, identity, provider) {
+        tracing::warn!("Refreshing {}'s token failed: {}", name, e);
+    }
+    let mut locked = false;
+    for path in token_paths(name, identity, provider) {
+        match cli::run(&cli::args(&["keys", "get", &path])).map_err(Error::from_cli) {
+            Ok(token) if !token.trim().is_empty() => return Ok(token.trim().to_string()),
+            Ok(_) => {}
+            Err(Error::KeystoreLocked) => {
+                locked = true;
+                break;
+            }
+            Err(e @ Error::Cli(CliError::NotFound)) => return Err(e),
+            Err(_) => {}
+        }
+    }
+    for var in token_envs(identity, provider) {
+        if let Ok(token) = std::env::var(&var) {
+            if !token.trim().is_empty() {
+                return Ok(token.trim().to_string());
+            }
+        }
+    }
+    if locked {
+        return Err(Error::KeystoreLocked);
+    }
+    Err(Error::InvalidOutput {
+        program: "token lookup",
+        message: format!(
+            "no {} token; store one at RemoteJuggler/Tokens/{}/{} or set {}",
+            provider.display_name(),
+            provider.display_name(),
+            name,
+            provider.token_env()
+        ),
+    })
+}
+
+/// The identity's `.pub` file
+pub fn public_key_path(identity: &Identity) -> Option<PathBuf> {
+    if identity.ssh_key_path.is_empty() {
+        return None;
+    }
+    let mut path = expand_tilde(&identity.ssh_key_path).into_os_string();
+    path.push(".pub");
+    Some(PathBuf::from(path))
+}
+
+/// Key type and blob of a public key line, without its comment
+pub fn key_id(line: &str) -> Option<(&str, &str)> {
+    let mut fields = line.split_whitespace();
+    Some((fields.next()?, fields.next()?))
+}
+
+/// A key registered with the provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteKey {
+    /// The provider's id for the key, used to delete it
+    pub id: String,
+    pub title: String,
+    pub key: String,
+}
+
+/// Parse a provider's key list; sourcehut's is the GraphQL response
+pub fn parse_keys(provider: Provider, body: &Value) -> Vec<RemoteKey> {
+    let (list, title_field, id_field) = match provider {
+        Provider::GitHub | Provider::GitLab | Provider::Gitea => (body.as_array(), "title", "id"),
+        Provider::Bitbucket => (
+            body.get("values").and_then(Value::as_array),
+            "label",
+            "uuid",
+        ),
+        Provider::Sourcehut => (
+            body.pointer("/data/me/sshKeys/results")
+                .and_then(Value::as_array),
+            "comment",
+            "id",
+        ),
+    };
+    list.into_iter()
+        .flatten()
+        .filter_map(|item| {
+            Some(RemoteKey {
+                id: match item.get(id_field) {
+                    Some(Value::String(id)) => id.clone(),
+                    Some(Value::Number(id)) => id.to_string(),
+                    _ => String::new(),
+                },
+                title: item
+                    .get(title_field)
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                key: item.get("key")?.as_str()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// A GPG key registered with the provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteGpgKey {
+    pub id: String,
+    /// The primary key's long id; GitHub and Gitea list it, sourcehut
+    /// its fingerprint, GitLab neither
+    pub key_id: String,
+    /// The armored key as uploaded
+    pub armored: String,
+}
+
+/// Parse a provider's GPG key list; sourcehut's is the GraphQL response
+pub fn parse_gpg_keys(body: &Value) -> Vec<RemoteGpgKey> {
+    body.pointer("/data/me/pgpKeys/results")
+        .and_then(Value::as_array)
+        .or_else(|| body.as_array())
+        .into_iter()
+        .flatten()
+        .map(|item| {
+            let text = |field: &str| {
+                item.get(field)
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let fingerprint = text("fingerprint").replace(' ', "");
+            let key_id = Some(text("key_id"))
+                .filter(|k| !k.is_empty())
+                .unwrap_or_else(|| {
+                    let start = fingerprint.len().saturating_sub(16);
+                    fingerprint.get(start..).unwrap_or_default().to_string()
+                });
+            RemoteGpgKey {
+                id: item.get("id").map(Value::to_string).unwrap_or_default(),
+                key_id: key_id.to_uppercase(),
+                // GitHub's raw_key, Gitea's public_key, GitLab's and
+                // sourcehut's key
+                armored: ["raw_key", "public_key", "key"]
+                    .into_iter()
+                    .map(text)
+                    .find(|k| !k.is_empty())
+                    .unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+/// Credentials for one provider account
+pub struct Account {
+    pub provider: Provider,
+    pub hostname: String,
+    client: provider_api::Client,
+}
+
+impl Account {
+    /// The identity's account, with its token looked up; blocks
+    pub fn for_identity(name: &str, identity: &Identity) -> Result<Self, Error> {
+        let provider = Provider::for_identity(identity).ok_or_else(|| Error::InvalidOutput {
+            program: "config",
+            message: format!("{} has no key API", identity.provider),
+        })?;
+        let token = find_token(name, identity, provider)?;
+        Ok(Self {
+            provider,
+            hostname: identity.hostname.clone(),
+            client: provider_api::Client::new(
+                provider,
+                &provider_hosts::api_base(
+                    &provider_hosts::configured(),
+                    provider,
+                    &identity.hostname,
+                ),
+                Some(&token),
+            ),
+        })
+    }
+
+    /// The budget the account's API last reported
+    pub fn rate_limit(&self) -> Option<provider_api::RateLimit> {
+        self.client.rate_limit()
+    }
+
+    /// Run a sourcehut GraphQL query; GraphQL reports failures in the
+    /// body, not the status
+    fn query(&self, query: &str, variables: Value) -> Result<Value, Error> {
+        let body = self.client.send(
+            "POST",
+            &format!("{}/query", self.client.api_base),
+            Some(&json!({ "query": query, "variables": variables })),
+        )?;
+        if let Some(error) = body
+            .get("errors")
+            .and_then(Value::as_array)
+            .and_then(|errors| errors.first())
+        {
+            return Err(Error::InvalidOutput {
+                program: "sourcehut",
+                message: error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("query failed")
+                    .to_string(),
+            });
+        }
+        Ok(body)
+    }
+
+    /// The keys URL; Bitbucket's is per user, so it looks the user up first
+    fn keys_url(&self) -> Result<String, Error> {
+        let base = &self.client.api_base;
+        if self.provider != Provider::Bitbucket {
+            return Ok(format!("{}/user/keys", base));
+        }
+        let user = self.client.get_json(&format!("{}/user", base))?;
+        let uuid =
+            user.get("uuid")
+                .and_then(Value::as_str)
+                .ok_or_else(|| Error::InvalidOutput {
+                    program: "Bitbucket",
+                    message: "no user uuid".to_string(),
+                })?;
+        let uuid = percent_encoding::utf8_percent_encode(uuid, percent_encoding::NON_ALPHANUMERIC);
+        Ok(format!("{}/users/{}/ssh-keys", base, uuid))
+    }
+
+    /// Keys registered with the account; blocks
+    pub fn list_keys(&self) -> Result<Vec<RemoteKey>, Error> {
+        if self.provider == Provider::Sourcehut {
+            let body = self.query(
+                "query { me { sshKeys { results { id key comment } } } }",
+                json!({}),
+            )?;
+            return Ok(parse_keys(self.provider, &body));
+        }
+        let url = self.keys_url()?;
+        let page = match self.provider {
+            Provider::Bitbucket => format!("{}?pagelen=100", url),
+            Provider::Gitea => format!("{}?limit=50", url),
+            _ => format!("{}?per_page=100", url),
+        };
+        let body = self.client.get_json(&page)?;
+        Ok(parse_keys(self.provider, &body))
+    }
+
+    /// Register a public key with the account; blocks
+    pub fn upload_key(&self, title: &str, key: &str) -> Result<(), Error> {
+        if self.provider == Provider::Sourcehut {
+            // sourcehut has no titles; the key's comment is shown instead
+            self.query(
+                "mutation($key: String!) { createSSHKey(key: $key) { id } }",
+                json!({ "key": format!("{} {}", key, title) }),
+            )?;
+            return Ok(());
+        }
+        let url = self.keys_url()?;
+        let body = match self.provider {
+            Provider::Bitbucket => json!({ "label": title, "key": key }),
+            _ => json!({ "title": title, "key": key }),
+        };
+        self.client.send("POST", &url, Some(&body))?;
+        Ok(())
+    }
+
+    /// Register a public key for verifying SSH-signed commits. GitHub
+    /// keeps signing keys apart from authentication keys and GitLab marks
+    /// a key's use; the others verify with any registered key. Blocks
+    pub fn upload_signing_key(&self, title: &str, key: &str) -> Result<(), Error> {
+        let base = &self.client.api_base;
+        let (url, body) = match self.provider {
+            Provider::GitHub => (
+                format!("{}/user/ssh_signing_keys", base),
+                json!({ "title": title, "key": key }),
+            ),
+            Provider::GitLab => (
+                format!("{}/user/keys", base),
+                json!({ "title": title, "key": key, "usage_type": "signing" }),
+            ),
+            _ => return self.upload_key(title, key),
+        };
+        self.client.send("POST", &url, Some(&body))?;
+        Ok(())
+    }
+
+    /// The GPG keys URL; Bitbucket has none, sourcehut's are GraphQL
+    fn gpg_keys_url(&self) -> Result<String, Error> {
+        match self.provider {
+            Provider::GitHub | Provider::GitLab | Provider::Gitea => {
+                Ok(format!("{}/user/gpg_keys", self.client.api_base))
+            }
+            Provider::Bitbucket | Provider::Sourcehut => Err(Error::InvalidOutput {
+                program: self.provider.display_name(),
+                message: "no GPG key REST API".to_string(),
+            }),
+        }
+    }
+
+    /// GPG keys registered with the account; blocks
+    pub fn list_gpg_keys(&self) -> Result<Vec<RemoteGpgKey>, Error> {
+        if self.provider == Provider::Sourcehut {
+            let body = self.query(
+                "query { me { pgpKeys { results { id key fingerprint } } } }",
+                json!({}),
+            )?;
+            return Ok(parse_gpg_keys(&body));
+        }
+        let page = match self.provider {
+            Provider::Gitea => format!("{}?limit=50", self.gpg_keys_url()?),
+            _ => format!("{}?per_page=100", self.gpg_keys_url()?),
+        };
+        let body = self.client.get_json(&page)?;
+        Ok(parse_gpg_keys(&body))
+    }
+
+    /// Register an armored GPG public key with the account; blocks
+    pub fn upload_gpg_key(&self, title: &str, armored: &str) -> Result<(), Error> {
+        if self.provider == Provider::Sourcehut {
+            self.query(
+                "mutation($key: String!) { createPGPKey(key: $key) { id } }",
+                json!({ "key": armored }),
+            )?;
+            return Ok(());
+        }
+        let url = self.gpg_keys_url()?;
+        let body = match self.provider {
+            Provider::GitHub => json!({ "name": title, "armored_public_key": armored }),
+            Provider::Gitea => json!({ "armored_public_key": armored }),
+            _ => json!({ "key": armored }),
+        };
+        self.client.send("POST", &url, Some(&body))?;
+        Ok(())
+    }
+
+    /// Remove a registered GPG key from the account; blocks
+    pub fn delete_gpg_key(&self, key: &RemoteGpgKey) -> Result<(), Error> {
+        if self.provider == Provider::Sourcehut {
+            let id: i64 = key.id.parse().map_err(|_| Error::InvalidOutput {
+                program: "sourcehut",
+                message: format!("bad id for GPG key {}", key.key_id),
+            })?;
+            self.query(
+                "mutation($id: Int!) { deletePGPKey(id: $id) { id } }",
+                json!({ "id": id }),
+            )?;
+            return Ok(());
+        }
+        let url = format!("{}/{}", self.gpg_keys_url()?, key.id);
+        self.client.send("DELETE", &url, None)?;
+        Ok(())
+    }
+
+    /// Remove a registered key from the account; blocks
+    pub fn delete_key(&self, key: &RemoteKey) -> Result<(), Error> {
+        if key.id.is_empty() {
+            return Err(Error::InvalidOutput {
+                program: self.provider.display_name(),
+                message: format!("no id for key \"{}\"", key.title),
+            });
+        }
+        if self.provider == Provider::Sourcehut {
+            let id: i64 = key.id.parse().map_err(|_| Error::InvalidOutput {
+                program: "sourcehut",
+                message: format!("bad id for key \"{}\"", key.title),
+            })?;
+            self.query(
+                "mutation($id: Int!) { deleteSSHKey(id: $id) { id } }",
+                json!({ "id": id }),
+            )?;
+            return Ok(());
+        }
+        let id = percent_encoding::utf8_percent_encode(&key.id, percent_encoding::NON_ALPHANUMERIC);
+        let url = format!("{}/{}", self.keys_url()?, id);
+        self.client.send("DELETE", &url, None)?;
+        Ok(())
+    }
+}
+
+/// Title for an uploaded key, e.g. "gitlab-work on laptop (RemoteJuggler)"
+pub fn default_title(name: &str) -> String {
+    format!("{} on {} (RemoteJuggler)", name, platform::machine_name())
+}
+
+/// Whether the identity's key is registered upstream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Registration {
+    /// Registered, under this title
+    Registered(String),
+    NotRegistered,
+}
+
+/// The identity's public key line
+fn local_key(name: &str, identity: &Identity) -> Result<String, Error> {
+    let path = public_key_path(identity).ok_or_else(|| Error::InvalidOutput {
+        program: "config",
+        message: format!("{} has no SSH key", name),
+    })?;
+    Ok(std::fs::read_to_string(path)?.trim().to_string())
+}
+
+/// Check whether the identity's key is registered; blocks
+pub fn check(name: &str, identity: &Identity) -> Result<Registration, Error> {
+    let public_key = local_key(name, identity)?;
+    let local = key_id(&public_key);
+    let keys = Account::for_identity(name, identity)?.list_keys()?;
+    Ok(keys
+        .into_iter()
+        .find(|remote| key_id(&remote.key) == local)
+        .map_or(Registration::NotRegistered, |remote| {
+            Registration::Registered(remote.title)
+        }))
+}
+
+/// Upload the identity's key under `title`; blocks
+pub fn upload(name: &str, identity: &Identity, title: &str) -> Result<(), Error> {
+    let public_key = local_key(name, identity)?;
+    // Upload without the local comment, which may name the machine's user
+    let key = key_id(&public_key)
+        .map(|(key_type, blob)| format!("{} {}", key_type, blob))
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "ssh key",
+            message: "not a public key".to_string(),
+        })?;
+    Account::for_identity(name, identity)?.upload_key(title, &key)
+}
+
+/// Register the identity's SSH signing key, so the provider shows its
+/// commits as verified; blocks
+pub fn upload_signing(name: &str, identity: &Identity, title: &str) -> Result<(), Error> {
+    let path = identity
+        .signing_key()
+        .filter(|_| identity.gpg.format == SigningFormat::Ssh)
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "config",
+            message: format!("{} doesn't sign with an SSH key", name),
+        })?;
+    let public_key = std::fs::read_to_string(expand_tilde(&path))?;
+    let key = key_id(&public_key)
+        .map(|(key_type, blob)| format!("{} {}", key_type, blob))
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "ssh key",
+            message: "not a public key".to_string(),
+        })?;
+    Account::for_identity(name, identity)?.upload_signing_key(title, &key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing;
+
+    fn identity(provider: &str, entry: Option<&str>) -> Identity {
+        Identity {
+            ssh_key_path: "/k/id_work".to_string(),
+            keepassxc_entry: entry.map(str::to_string),
+            ..testing::identity(
+                provider,
+                "work",
+                "gitlab.example.com",
+                "alice",
+                "alice@example.com",
+            )
+        }
+    }
+
+    #[test]
+    fn test_api_base() {
+        assert_eq!(
+            Provider::GitHub.api_base("github.com"),
+            "https://api.github.com"
+        );
+        assert_eq!(
+            Provider::GitHub.api_base("ghe.corp"),
+            "https://ghe.corp/api/v3"
+        );
+        assert_eq!(
+            Provider::GitLab.api_base("gitlab.example.com"),
+            "https://gitlab.example.com/api/v4"
+        );
+        assert_eq!(
+            Provider::for_identity(&identity("Bitbucket", None)),
+            Some(Provider::Bitbucket)
+        );
+        assert_eq!(Provider::for_identity(&identity("custom", None)), None);
+        assert_eq!(Provider::from_name("Codeberg"), Some(Provider::Gitea));
+        assert_eq!(Provider::Gitea.api_base(""), "https://codeberg.org/api/v1");
+        assert_eq!(
+            Provider::Gitea.api_base("git.acme.dev"),
+            "https://git.acme.dev/api/v1"
+        );
+        assert_eq!(
+            Provider::Sourcehut.api_base("git.sr.ht"),
+            "https://meta.sr.ht"
+        );
+        assert_eq!(
+            Provider::Sourcehut.api_base("git.srht.acme.dev"),
+            "https://meta.srht.acme.dev"
+        );
+    }
+
+    #[test]
+    fn test_token_sources() {
+        assert_eq!(
+            token_envs(
+                &Identity {
+                    token_env_var: Some("GITLAB_WORK_TOKEN".to_string()),
+                    ..identity("gitlab", None)
+                },
+                Provider::GitLab
+            ),
+            vec!["GITLAB_WORK_TOKEN", "GITLAB_TOKEN"]
+        );
+        assert_eq!(
+            token_paths(
+                "work",
+                &identity("gitlab", Some("Custom/token")),
+                Provider::GitLab
+            ),
+            vec![
+                "Custom/token",
+                "RemoteJuggler/Tokens/GitLab/work",
+                "RemoteJuggler/Tokens/GitLab/default"
+            ]
+        );
+        assert_eq!(
+            public_key_path(&identity("gitlab", None)),
+            Some(PathBuf::from("/k/id_work.pub"))
+        );
+    }
+
+    #[test]
+    fn test_auth_header() {
+        assert_eq!(
+            Provider::GitLab.auth_header("glpat-x"),
+            ("Authorization", "Bearer glpat-x".to_string())
+        );
+        assert_eq!(Provider::GitHub.auth_header("ghp_x").1, "Bearer ghp_x");
+        assert_eq!(
+            Provider::Bitbucket.auth_header("alice:app").1,
+            "Basic YWxpY2U6YXBw"
+        );
+        assert_eq!(Provider::Bitbucket.auth_header("atat").1, "Bearer atat");
+        assert_eq!(Provider::Gitea.auth_header("cb").1, "token cb");
+    }
+
+    #[test]
+    fn test_parse_keys_and_match() {
+        let github = json!([
+            { "id": 1, "key": "ssh-ed25519 AAAAlocal", "title": "laptop" },
+            { "id": 2, "key": "ssh-rsa AAAAother", "title": "old" }
+        ]);
+        let keys = parse_keys(Provider::GitHub, &github);
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].title, "laptop");
+        assert_eq!(keys[1].id, "2");
+        assert_eq!(
+            key_id(&keys[0].key),
+            key_id("ssh-ed25519 AAAAlocal alice@laptop")
+        );
+
+        let bitbucket = json!({
+            "values": [{ "uuid": "{k}", "key": "ssh-ed25519 AAAAbb me", "label": "desk" }]
+        });
+        let keys = parse_keys(Provider::Bitbucket, &bitbucket);
+        assert_eq!(keys[0].title, "desk");
+        assert_eq!(keys[0].id, "{k}");
+        assert!(parse_keys(Provider::GitLab, &json!({ "message": "401" })).is_empty());
+
+        let sourcehut = json!({ "data": { "me": { "sshKeys": { "results": [
+            { "id": 42, "key": "ssh-ed25519 AAAAsr", "comment": "work on laptop" }
+        ] } } } });
+        let keys = parse_keys(Provider::Sourcehut, &sourcehut);
+        assert_eq!(
+            (keys[0].id.as_str(), keys[0].title.as_str()),
+            ("42", "work on laptop")
+        );
+    }
+
+    #[test]
+    fn test_parse_gpg_keys() {
+        let github = json!([{
+            "id": 3, "name": "laptop", "key_id": "3262eff25ba0d270",
+            "raw_key": "-----BEGIN PGP PUBLIC KEY BLOCK-----\ngh"
+        }]);
+        let keys = parse_gpg_keys(&github);
+        assert_eq!(keys[0].key_id, "3262EFF25BA0D270");
+        assert!(keys[0].armored.ends_with("gh"));
+        let gitlab = json!([{ "id": 1, "key": "-----BEGIN PGP PUBLIC KEY BLOCK-----\ngl" }]);
+        let keys = parse_gpg_keys(&gitlab);
+        assert_eq!((keys[0].id.as_str(), keys[0].key_id.as_str()), ("1", ""));
+        assert!(keys[0].armored.ends_with("gl"));
+        let sourcehut = json!({ "data": { "me": { "pgpKeys": { "results": [{
+            "id": 7, "fingerprint": "1234 5678 9ABC DEF0 3262 EFF2 5BA0 D270",
+            "key": "-----BEGIN PGP PUBLIC KEY BLOCK-----\nsr"
+        }] } } } });
+        let keys = parse_gpg_keys(&sourcehut);
+        assert_eq!(keys[0].key_id, "3262EFF25BA0D270");
+        assert!(keys[0].armored.ends_with("sr"));
+    }
+}
@@ -0,0 +1,462 @@
+//! Which of an identity's keys its provider account has
+//!
+//! Lists the SSH and GPG keys registered with the identity's account,
+//! using its token from the key store or environment, and sets them
+//! against the local ones: the identity's key is registered or missing,
+//! and a registered key is stale when it is one RemoteJuggler retired, an
+//! archived SSH key or a GPG key that can no longer sign, or when
+//! RemoteJuggler uploaded it for the identity and it isn't the current key.
+//! Any other registered key is unknown unless it matches a key on this
+//! machine, a `.pub` file in `~/.ssh` or a key the agent holds, or a GPG
+//! key in the keyring: typically an old laptop's or a revoked YubiKey's.
+//! Unknown keys can be pruned too, after confirming, since the key may be
+//! in use on another machine. Keys known here are only counted.
+
+use std::path::{Path, PathBuf};
+
+use crate::agent;
+use crate::config::{Identity, SigningFormat};
+use crate::error::Error;
+use crate::gpg::{self, SecretKey};
+use crate::gpg_publish;
+use crate::hygiene;
+use crate::platform;
+use crate::pubkeys::{self, key_id, Account, Provider, RemoteGpgKey, RemoteKey};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    Ssh,
+    Gpg,
+}
+
+impl KeyKind {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::Ssh => "SSH",
+            Self::Gpg => "GPG",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Badge {
+    Registered,
+    /// The identity's key isn't on the account
+    Missing,
+    /// On the account, but retired or replaced
+    Stale,
+    /// On the account, but matches no key on this machine
+    Unknown,
+}
+
+impl Badge {
+    pub fn display_text(&self) -> &'static str {
+        match self {
+            Self::Registered => "registered",
+            Self::Missing => "missing",
+            Self::Stale => "stale",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// The style class the badge is shown with
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            Self::Registered => "success",
+            Self::Missing => "error",
+            Self::Stale | Self::Unknown => "warning",
+        }
+    }
+}
+
+/// A key as the account lists it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Registered {
+    Ssh(RemoteKey),
+    Gpg(RemoteGpgKey),
+}
+
+/// One key and how it stands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub kind: KeyKind,
+    pub badge: Badge,
+    /// The key's title on the account, its file or its key id
+    pub label: String,
+    /// None for a missing key
+    pub remote: Option<Registered>,
+}
+
+/// An identity's keys on its account
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub entries: Vec<Entry>,
+    /// Registered keys of this machine's that aren't the identity's
+    pub others: usize,
+}
+
+impl Report {
+    pub fn count(&self, badge: Badge) -> usize {
+        self.entries.iter().filter(|e| e.badge == badge).count()
+    }
+
+    /// e.g. "SSH registered, GPG missing, 1 stale, 1 unknown, 2 other keys"
+    pub fn summary(&self) -> String {
+        let mut parts: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|e| matches!(e.badge, Badge::Registered | Badge::Missing))
+            .map(|e| format!("{} {}", e.kind.display_name(), e.badge.display_text()))
+            .collect();
+        for badge in [Badge::Stale, Badge::Unknown] {
+            let count = self.count(badge);
+            if count > 0 {
+                parts.push(format!("{} {}", count, badge.display_text()));
+            }
+        }
+        if self.others > 0 {
+            parts.push(format!(
+                "{} other {}",
+                self.others,
+                if self.others == 1 { "key" } else { "keys" }
+            ));
+        }
+        if parts.is_empty() {
+            "No keys to compare".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// Whether RemoteJuggler uploaded a key under this title for the
+/// identity, as [`pubkeys::default_title`] names them
+pub fn is_own_title(name: &str, title: &str) -> bool {
+    title.starts_with(&format!("{} on ", name)) && title.ends_with("(RemoteJuggler)")
+}
+
+/// Set the account's SSH keys against the identity's `local` one, given as
+/// (label, public key line), its `archived` public keys and the rest of
+/// the machine's `known` ones
+pub fn classify_ssh(
+    name: &str,
+    local: Option<(&str, &str)>,
+    archived: &[String],
+    known: &[String],
+    remote: &[RemoteKey],
+) -> (Vec<Entry>, usize) {
+    let local_id = local.and_then(|(_, line)| key_id(line));
+    let mut entries = Vec::new();
+    let mut others = 0;
+    let mut found = false;
+    for key in remote {
+        let id = key_id(&key.key);
+        let badge = if id.is_some() && id == local_id {
+            found = true;
+            Badge::Registered
+        } else if archived
+            .iter()
+            .any(|line| id.is_some() && key_id(line) == id)
+            || is_own_title(name, &key.title)
+        {
+            Badge::Stale
+        } else if known.iter().any(|line| id.is_some() && key_id(line) == id) {
+            others += 1;
+            continue;
+        } else {
+            Badge::Unknown
+        };
+        entries.push(Entry {
+            kind: KeyKind::Ssh,
+            badge,
+            label: key.title.clone(),
+            remote: Some(Registered::Ssh(key.clone())),
+        });
+    }
+    if let (Some((label, _)), false) = (local, found) {
+        entries.insert(
+            0,
+            Entry {
+                kind: KeyKind::Ssh,
+                badge: Badge::Missing,
+                label: label.to_string(),
+                remote: None,
+            },
+        );
+    }
+    (entries, others)
+}
+
+/// Whether a secret key can still sign at `now`
+fn can_sign(key: &SecretKey, now: u64) -> bool {
+    key.signing_expires().is_none_or(|t| t > now)
+}
+
+/// Set the account's GPG keys against the identity's `signing` key and the
+/// rest of the `keyring`; a remote key's `key_id` may be a fingerprint
+pub fn classify_gpg(
+    signing: Option<&SecretKey>,
+    keyring: &[SecretKey],
+    remote: &[RemoteGpgKey],
+    now: u64,
+) -> (Vec<Entry>, usize) {
+    let mut entries = Vec::new();
+    let mut others = 0;
+    let mut found = false;
+    for key in remote {
+        let local = keyring
+            .iter()
+            .chain(signing)
+            .find(|local| local.matches(&key.key_id));
+        // The identity's own key counts as registered even once expired;
+        // the expiry reminders cover renewing it
+        let badge = match local {
+            Some(local) if signing.is_some_and(|s| s.fingerprint == local.fingerprint) => {
+                found = true;
+                Badge::Registered
+            }
+            Some(local) if !can_sign(local, now) => Badge::Stale,
+            Some(_) => {
+                others += 1;
+                continue;
+            }
+            None => Badge::Unknown,
+        };
+        entries.push(Entry {
+            kind: KeyKind::Gpg,
+            badge,
+            label: local.map_or(key.key_id.clone(), |l| l.key_id.clone()),
+            remote: Some(Registered::Gpg(key.clone())),
+        });
+    }
+    if let (Some(signing), false) = (signing, found) {
+        entries.insert(
+            0,
+            Entry {
+                kind: KeyKind::Gpg,
+                badge: Badge::Missing,
+                label: signing.key_id.clone(),
+                remote: None,
+            },
+        );
+    }
+    (entries, others)
+}
+
+/// Public key lines in a directory's `.pub` files; empty if unreadable
+fn public_keys_in(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "pub"))
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .map(|text| text.trim().to_string())
+        .collect()
+}
+
+/// Keys retired to the archive directories: `~/.ssh/archive` and the one
+/// next to the identity's key
+pub fn archived_keys(identity: &Identity) -> Vec<String> {
+    let mut dirs: Vec<PathBuf> = platform::ssh_dir()
+        .map(|dir| dir.join(hygiene::ARCHIVE_DIR))
+        .into_iter()
+        .collect();
+    if let Some(parent) = pubkeys::public_key_path(identity)
+        .as_deref()
+        .and_then(Path::parent)
+    {
+        let dir = parent.join(hygiene::ARCHIVE_DIR);
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs.iter().flat_map(|dir| public_keys_in(dir)).collect()
+}
+
+/// Public keys this machine has: `.pub` files in `~/.ssh` and the keys
+/// the agent holds, such as a YubiKey's; blocks
+pub fn known_keys() -> Vec<String> {
+    let mut keys: Vec<String> = platform::ssh_dir()
+        .map(|dir| public_keys_in(&dir))
+        .unwrap_or_default();
+    // Without an agent there is nothing more to know
+    if let Ok(Some(loaded)) = agent::list() {
+        keys.extend(loaded.into_iter().map(|key| key.public_key));
+    }
+    keys
+}
+
+/// Whether the identity signs with a GPG key its provider takes
+fn compares_gpg(identity: &Identity, provider: Provider) -> bool {
+    identity.gpg.format == SigningFormat::Gpg
+        && !identity.gpg.key_id.is_empty()
+        && provider != Provider::Bitbucket
+}
+
+/// List the account's keys and compare; blocks
+pub fn check(name: &str, identity: &Identity) -> Result<Report, Error> {
+    let account = Account::for_identity(name, identity)?;
+    let local = pubkeys::public_key_path(identity).and_then(|path| {
+        let line = std::fs::read_to_string(&path).ok()?;
+        let label = path.file_name()?.to_string_lossy().into_owned();
+        Some((label, line.trim().to_string()))
+    });
+    let (mut entries, mut others) = classify_ssh(
+        name,
+        local
+            .as_ref()
+            .map(|(label, line)| (label.as_str(), line.as_str())),
+        &archived_keys(identity),
+        &known_keys(),
+        &account.list_keys()?,
+    );
+
+    if compares_gpg(identity, account.provider) {
+        let keyring = gpg::list_secret_keys()?;
+        let signing = keyring
+            .iter()
+            .find(|key| key.matches(&identity.gpg.key_id))
+            .cloned();
+        let mut remote = account.list_gpg_keys()?;
+        // GitLab lists the armored key only
+        for key in remote.iter_mut().filter(|k| k.key_id.is_empty()) {
+            if !key.armored.is_empty() {
+                key.key_id = gpg::armored_fingerprints(&key.armored)?
+                    .into_iter()
+                    .next()
+                    .unwrap_or_default();
+            }
+        }
+        let (gpg_entries, gpg_others) =
+            classify_gpg(signing.as_ref(), &keyring, &remote, crate::expiry::now());
+        entries.extend(gpg_entries);
+        others += gpg_others;
+    }
+    Ok(Report { entries, others })
+}
+
+/// Upload the identity's missing key of `kind`; blocks
+pub fn upload(name: &str, identity: &Identity, kind: KeyKind) -> Result<(), Error> {
+    let title = pubkeys::default_title(name);
+    match kind {
+        KeyKind::Ssh => pubkeys::upload(name, identity, &title),
+        KeyKind::Gpg => {
+            let key = gpg_publish::signing_key(name, identity)?;
+            let armored = gpg::export_public(&key.fingerprint)?;
+            Account::for_identity(name, identity)?.upload_gpg_key(&title, &armored)
+        }
+    }
+}
+
+/// Remove a stale or unknown key from the account; other keys are
+/// refused. Blocks
+pub fn prune(name: &str, identity: &Identity, entry: &Entry) -> Result<(), Error> {
+    let remote = match (&entry.remote, entry.badge) {
+        (Some(remote), Badge::Stale | Badge::Unknown) => remote,
+        _ => {
+            return Err(Error::InvalidOutput {
+                program: "registration",
+                message: format!("{} isn't a stale or unknown key", entry.label),
+            })
+        }
+    };
+    let account = Account::for_identity(name, identity)?;
+    match remote {
+        Registered::Ssh(key) => account.delete_key(key),
+        Registered::Gpg(key) => account.delete_gpg_key(key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_ssh_and_gpg() {
+        let remote = |id: &str, title: &str, key: &str| RemoteKey {
+            id: id.to_string(),
+            title: title.to_string(),
+            key: key.to_string(),
+        };
+        let keys = [
+            remote("1", "laptop", "ssh-ed25519 AAAAcurrent"),
+            remote(
+                "2",
+                "work on old (RemoteJuggler)",
+                "ssh-ed25519 AAAAreplaced",
+            ),
+            remote("3", "desk", "ssh-ed25519 AAAAarchived"),
+            remote("4", "ci", "ssh-ed25519 AAAAci"),
+            remote("5", "old laptop", "ssh-ed25519 AAAAgone"),
+        ];
+        let archived = vec!["ssh-ed25519 AAAAarchived me@old".to_string()];
+        let known = vec!["ssh-ed25519 AAAAci ci@build".to_string()];
+        let (entries, others) = classify_ssh(
+            "work",
+            Some(("id_work.pub", "ssh-ed25519 AAAAcurrent me@laptop")),
+            &archived,
+            &known,
+            &keys,
+        );
+        let badges: Vec<_> = entries
+            .iter()
+            .map(|e| (e.label.as_str(), e.badge))
+            .collect();
+        assert_eq!(
+            badges,
+            vec![
+                ("laptop", Badge::Registered),
+                ("work on old (RemoteJuggler)", Badge::Stale),
+                ("desk", Badge::Stale),
+                ("old laptop", Badge::Unknown),
+            ]
+        );
+        assert_eq!(others, 1);
+        let (entries, _) = classify_ssh(
+            "work",
+            Some(("id_work.pub", "ssh-ed25519 AAAAnew")),
+            &[],
+            &[],
+            &keys[..1],
+        );
+        assert_eq!(entries[0].badge, Badge::Missing);
+
+        let keyring = gpg::parse_colons(
+            "sec:u:255:22:AAAA1111AAAA1111:1700000000:::u:::scESC:::+:::ed25519:::0:\n\
+             fpr:::::::::0000AAAA1111AAAA1111AAAA1111AAAA1111AAAA1111:\n\
+             uid:u::::1700000000::X::Me <me@acme.dev>::::::::::0:\n\
+             sec:e:255:22:BBBB2222BBBB2222:1600000000:1650000000::u:::scESC:::+:::ed25519:::0:\n\
+             fpr:::::::::0000BBBB2222BBBB2222BBBB2222BBBB2222BBBB2222:\n\
+             uid:e::::1600000000::Y::Me <me@acme.dev>::::::::::0:\n",
+        );
+        let gpg_remote = |id: &str, key_id: &str| RemoteGpgKey {
+            id: id.to_string(),
+            key_id: key_id.to_string(),
+            armored: String::new(),
+        };
+        let remote = [
+            gpg_remote("7", "AAAA1111AAAA1111"),
+            gpg_remote("8", "0000BBBB2222BBBB2222BBBB2222BBBB2222BBBB2222"),
+            gpg_remote("9", "CCCC3333CCCC3333"),
+        ];
+        let (entries, others) = classify_gpg(keyring.first(), &keyring, &remote, 1_750_000_000);
+        assert_eq!(entries[0].badge, Badge::Registered);
+        assert_eq!(
+            (entries[1].label.as_str(), entries[1].badge),
+            ("BBBB2222BBBB2222", Badge::Stale)
+        );
+        assert_eq!(
+            (entries[2].label.as_str(), entries[2].badge),
+            ("CCCC3333CCCC3333", Badge::Unknown)
+        );
+        assert_eq!(others, 0);
+        let report = Report { entries, others: 2 };
+        assert_eq!(
+            report.summary(),
+            "GPG registered, 1 stale, 1 unknown, 2 other keys"
+        );
+    }
+}
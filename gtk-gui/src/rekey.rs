@@ -0,0 +1,514 @@
+//! Guided SSH key rotation
+//!
+//! Replaces an identity's key in steps: generate an ed25519 key, register
+//! it with the provider, point config.json and the managed ssh config at
+//! it, prove it authenticates, then revoke the old key upstream and move
+//! it to `~/.ssh/archive`. Progress is saved after every step, so a
+//! rotation that fails part way (no network, an edited ssh config) resumes
+//! where it stopped. Every step is safe to repeat.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::agent;
+use crate::config::{Config, SshKeyType};
+use crate::databases::expand_tilde;
+use crate::error::{CliError, Error};
+use crate::expiry;
+use crate::fido2::ssh_keygen_program;
+use crate::hygiene;
+use crate::passphrase::Workspace;
+use crate::platform;
+use crate::pubkeys::{self, key_id, Account};
+use crate::ssh_config::{self, BlockState};
+
+/// What ssh prints once a git host accepts the key; hosts without a
+/// shell close the session, so ssh often exits non-zero anyway
+const AUTHENTICATED: &[&str] = &[
+    "successfully authenticated",
+    "welcome to gitlab",
+    "authenticated via ssh key",
+    "logged in as",
+];
+
+/// A rotation step, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    Generate,
+    Upload,
+    UpdateConfig,
+    Verify,
+    Revoke,
+    Archive,
+    Done,
+}
+
+impl Step {
+    pub const ALL: [Step; 7] = [
+        Step::Generate,
+        Step::Upload,
+        Step::UpdateConfig,
+        Step::Verify,
+        Step::Revoke,
+        Step::Archive,
+        Step::Done,
+    ];
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::Generate => "Generate new key",
+            Self::Upload => "Register new key",
+            Self::UpdateConfig => "Update config",
+            Self::Verify => "Test connection",
+            Self::Revoke => "Revoke old key",
+            Self::Archive => "Archive old key",
+            Self::Done => "Done",
+        }
+    }
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|s| *s == self).unwrap_or(0);
+        Self::ALL.get(index + 1).copied().unwrap_or(Self::Done)
+    }
+}
+
+/// One identity's rotation in progress
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rotation {
+    pub old_key: PathBuf,
+    /// The old public key line, kept so it can be revoked after the
+    /// config stops pointing at it
+    pub old_public: String,
+    pub new_key: PathBuf,
+    /// Title the new key is registered under
+    pub title: String,
+    /// The next step to run
+    pub step: Step,
+    pub started: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+impl Rotation {
+    /// Plan a rotation for `name`; checks the key can be rotated but
+    /// changes nothing
+    pub fn plan(config: &Config, name: &str, now: u64) -> Result<Self, Error> {
+        let identity = config
+            .identities
+            .get(name)
+            .ok_or_else(|| invalid("config", format!("no identity named {}", name)))?;
+        if identity.key_type() != SshKeyType::Regular {
+            return Err(invalid(
+                "config",
+                format!(
+                    "{} uses a {} key; rotate it on the device",
+                    name,
+                    identity.key_type().display_name()
+                ),
+            ));
+        }
+        if pubkeys::Provider::for_identity(identity).is_none() {
+            return Err(invalid(
+                "config",
+                format!("{} has no key API", identity.provider),
+            ));
+        }
+        let old_key = expand_tilde(&identity.ssh_key_path);
+        if identity.ssh_key_path.is_empty() || !old_key.exists() {
+            return Err(invalid("config", format!("{} has no SSH key", name)));
+        }
+        let shared: Vec<&str> = config
+            .identities
+            .iter()
+            .filter(|(other, i)| other.as_str() != name && expand_tilde(&i.ssh_key_path) == old_key)
+            .map(|(other, _)| other.as_str())
+            .collect();
+        if !shared.is_empty() {
+            return Err(invalid(
+                "config",
+                format!(
+                    "the key is shared with {}; give each identity its own key first",
+                    shared.join(", ")
+                ),
+            ));
+        }
+        let public_path = pubkeys::public_key_path(identity).unwrap_or_default();
+        let old_public = fs::read_to_string(&public_path)
+            .map_err(|_| invalid("ssh key", format!("no {}", public_path.display())))?
+            .trim()
+            .to_string();
+        if key_id(&old_public).is_none() {
+            return Err(invalid("ssh key", "not a public key".to_string()));
+        }
+
+        let dir = old_key.parent().unwrap_or(Path::new(".")).to_path_buf();
+        Ok(Self {
+            new_key: new_key_path(&dir, name, now),
+            old_key,
+            old_public,
+            title: pubkeys::default_title(name),
+            step: Step::Generate,
+            started: now,
+            last_error: None,
+        })
+    }
+
+    /// Whether it can still be abandoned without touching the config
+    pub fn can_abandon(&self) -> bool {
+        self.step <= Step::UpdateConfig
+    }
+
+    pub fn status_text(&self) -> String {
+        match (&self.last_error, self.step) {
+            (_, Step::Done) => format!("Rotated to {}", self.new_key.display()),
+            (Some(error), step) => format!("{} failed: {}", step.display_name(), error),
+            (None, step) => format!("Next: {}", step.display_name()),
+        }
+    }
+}
+
+fn invalid(program: &'static str, message: String) -> Error {
+    Error::InvalidOutput { program, message }
+}
+
+/// `id_ed25519_<name>_<date>` in `dir`, numbered if taken
+pub fn new_key_path(dir: &Path, name: &str, now: u64) -> PathBuf {
+    let safe: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let base = format!(
+        "id_ed25519_{}_{}",
+        safe,
+        expiry::format_date(now).replace('-', "")
+    );
+    let mut file = base.clone();
+    let mut n = 1;
+    while dir.join(&file).exists() || dir.join(format!("{}.pub", file)).exists() {
+        n += 1;
+        file = format!("{}_{}", base, n);
+    }
+    dir.join(file)
+}
+
+/// Whether ssh's output shows the host accepted the key
+pub fn authenticated(output: &str) -> bool {
+    let lower = output.to_lowercase();
+    !lower.contains("permission denied") && AUTHENTICATED.iter().any(|m| lower.contains(m))
+}
+
+/// Rotations in progress, persisted locally
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RekeyStore {
+    #[serde(default)]
+    pub rotations: BTreeMap<String, Rotation>,
+}
+
+impl RekeyStore {
+    pub fn path() -> Option<PathBuf> {
+        platform::data_dir().map(|d| d.join("rekey.json"))
+    }
+
+    pub fn load() -> Self {
+        platform::load_json_state(Self::path())
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        platform::save_json_state(Self::path(), self)
+    }
+}
+
+fn public_line(key: &Path) -> Result<String, Error> {
+    let mut path = key.as_os_str().to_owned();
+    path.push(".pub");
+    Ok(fs::read_to_string(PathBuf::from(path))?.trim().to_string())
+}
+
+/// Run the rotation's next step and advance it; `passphrase` protects the
+/// new key (empty for none). Blocks
+pub fn run_step(name: &str, rotation: &mut Rotation, passphrase: &str) -> Result<(), Error> {
+    match rotation.step {
+        Step::Generate => generate(&rotation.new_key, name, passphrase)?,
+        Step::Upload => {
+            let config = Config::load()?;
+            let identity = config
+                .identities
+                .get(name)
+                .ok_or_else(|| invalid("config", format!("no identity named {}", name)))?;
+            let account = Account::for_identity(name, identity)?;
+            let new_public = public_line(&rotation.new_key)?;
+            let new_id = key_id(&new_public);
+            if !account
+                .list_keys()?
+                .iter()
+                .any(|k| key_id(&k.key) == new_id)
+            {
+                let (key_type, blob) =
+                    new_id.ok_or_else(|| invalid("ssh key", "not a public key".to_string()))?;
+                account.upload_key(&rotation.title, &format!("{} {}", key_type, blob))?;
+            }
+        }
+        Step::UpdateConfig => {
+            let mut config = Config::load()?;
+            let identity = config
+                .identities
+                .get_mut(name)
+                .ok_or_else(|| invalid("config", format!("no identity named {}", name)))?;
+            identity.ssh_key_path = platform::tilde_path(&rotation.new_key);
+            // Checked before config.json changes, so a refusal leaves the
+            // rotation abandonable
+            let plan = ssh_config::plan(&config)?;
+            if matches!(plan.state, BlockState::Edited | BlockState::Unterminated) {
+                return Err(invalid(
+                    "ssh config",
+                    format!(
+                        "{}; review and write it under SSH Config, then resume",
+                        plan.state.display_text()
+                    ),
+                ));
+            }
+            config.save()?;
+            // A missing block means the user's own Host entries, which are
+            // left alone
+            if plan.state == BlockState::Intact && !plan.is_noop() {
+                ssh_config::write(&plan)?;
+            }
+        }
+        Step::Verify => {
+            let config = Config::load()?;
+            let hostname = config
+                .identities
+                .get(name)
+                .map(|i| i.hostname.clone())
+                .filter(|h| !h.is_empty())
+                .ok_or_else(|| invalid("config", format!("{} has no hostname", name)))?;
+            verify(&rotation.new_key, &hostname, passphrase)?;
+        }
+        Step::Revoke => {
+            let config = Config::load()?;
+            let identity = config
+                .identities
+                .get(name)
+                .ok_or_else(|| invalid("config", format!("no identity named {}", name)))?;
+            let account = Account::for_identity(name, identity)?;
+            let old_id = key_id(&rotation.old_public);
+            for key in account.list_keys()? {
+                if key_id(&key.key) == old_id {
+                    account.delete_key(&key)?;
+                }
+            }
+        }
+        Step::Archive => {
+            let old_id = key_id(&rotation.old_public);
+            if let Ok(Some(keys)) = agent::list() {
+                for key in keys.iter().filter(|k| key_id(&k.public_key) == old_id) {
+                    agent::remove(key)?;
+                }
+            }
+            if rotation.old_key.exists() {
+                hygiene::archive(&rotation.old_key)?;
+            }
+        }
+        Step::Done => return Ok(()),
+    }
+    rotation.step = rotation.step.next();
+    Ok(())
+}
+
+/// Generate the key in a private workspace and move it into place, so a
+/// half-written key never sits at the final path
+fn generate(key: &Path, name: &str, passphrase: &str) -> Result<(), Error> {
+    if key.exists() && public_line(key).is_ok() {
+        return Ok(());
+    }
+    let workspace = Workspace::create(key)?;
+    let scratch = workspace.key();
+    let args = vec![
+        "-q".to_string(),
+        "-t".to_string(),
+        "ed25519".to_string(),
+        "-C".to_string(),
+        pubkeys::default_title(name),
+        "-f".to_string(),
+        scratch.to_string_lossy().into_owned(),
+    ];
+    workspace.run(&ssh_keygen_program(), &args, "", passphrase)?;
+    let mut public = scratch.as_os_str().to_owned();
+    public.push(".pub");
+    let mut target = key.as_os_str().to_owned();
+    target.push(".pub");
+    fs::rename(&scratch, key)?;
+    fs::rename(PathBuf::from(public), PathBuf::from(target))?;
+    Ok(())
+}
+
+/// Authenticate to the host with only the new key, ignoring ssh config
+/// so nothing else can answer for it
+fn verify(key: &Path, hostname: &str, passphrase: &str) -> Result<(), Error> {
+    let workspace = Workspace::create(key)?;
+    let args = vec![
+        "-T".to_string(),
+        "-F".to_string(),
+        "none".to_string(),
+        "-o".to_string(),
+        "IdentitiesOnly=yes".to_string(),
+        "-o".to_string(),
+        "StrictHostKeyChecking=yes".to_string(),
+        "-o".to_string(),
+        "ConnectTimeout=10".to_string(),
+        "-i".to_string(),
+        key.to_string_lossy().into_owned(),
+        format!("{}@{}", ssh_config::SSH_USER, hostname),
+    ];
+    match workspace.run("ssh", &args, "", passphrase) {
+        Ok(_) => Ok(()),
+        Err(Error::Cli(CliError::NonZeroExit { stderr, .. })) if authenticated(&stderr) => Ok(()),
+        Err(Error::Cli(CliError::NonZeroExit { stderr, .. })) => Err(invalid(
+            "ssh",
+            format!("{} didn't accept the new key: {}", hostname, stderr.trim()),
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+/// Run a rotation's remaining steps, saving progress after each; the
+/// rotation is recorded with its error if a step fails. Blocks
+pub fn resume(name: &str, passphrase: &str) -> Result<Rotation, Error> {
+    let mut store = RekeyStore::load();
+    let mut rotation = store
+        .rotations
+        .get(name)
+        .cloned()
+        .ok_or_else(|| invalid("rekey", format!("no rotation for {}", name)))?;
+    while rotation.step != Step::Done {
+        let result = run_step(name, &mut rotation, passphrase);
+        rotation.last_error = result.as_ref().err().map(ToString::to_string);
+        store.rotations.insert(name.to_string(), rotation.clone());
+        store.save()?;
+        result?;
+    }
+    store.rotations.remove(name);
+    store.save()?;
+    Ok(rotation)
+}
+
+/// Plan and run a new rotation; blocks
+pub fn start(config: &Config, name: &str, passphrase: &str) -> Result<Rotation, Error> {
+    let mut store = RekeyStore::load();
+    if store.rotations.contains_key(name) {
+        return Err(invalid(
+            "rekey",
+            format!("{} is already being rotated; resume it", name),
+        ));
+    }
+    let rotation = Rotation::plan(config, name, expiry::now())?;
+    store.rotations.insert(name.to_string(), rotation);
+    store.save()?;
+    resume(name, passphrase)
+}
+
+/// Drop a rotation that hasn't switched the config yet, deleting the new
+/// key locally and upstream. Blocks
+pub fn abandon(name: &str) -> Result<(), Error> {
+    let mut store = RekeyStore::load();
+    let Some(rotation) = store.rotations.get(name) else {
+        return Ok(());
+    };
+    if !rotation.can_abandon() {
+        return Err(invalid(
+            "rekey",
+            "the config already uses the new key; resume instead".to_string(),
+        ));
+    }
+    let config = Config::load()?;
+    let identity = config.identities.get(name);
+    if identity.is_some_and(|i| expand_tilde(&i.ssh_key_path) == rotation.new_key) {
+        return Err(invalid(
+            "rekey",
+            "the config already uses the new key; resume instead".to_string(),
+        ));
+    }
+    if rotation.step > Step::Upload {
+        if let (Some(identity), Ok(public)) = (identity, public_line(&rotation.new_key)) {
+            let account = Account::for_identity(name, identity)?;
+            for key in account.list_keys()? {
+                if key_id(&key.key) == key_id(&public) {
+                    account.delete_key(&key)?;
+                }
+            }
+        }
+    }
+    hygiene::delete(&rotation.new_key)?;
+    store.rotations.remove(name);
+    store.save()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_and_status() {
+        assert_eq!(Step::Generate.next(), Step::Upload);
+        assert_eq!(Step::Archive.next(), Step::Done);
+        assert_eq!(Step::Done.next(), Step::Done);
+
+        let mut rotation = Rotation {
+            old_key: PathBuf::from("/k/id_work"),
+            old_public: "ssh-rsa AAAA".to_string(),
+            new_key: PathBuf::from("/k/id_ed25519_work_20261016"),
+            title: "work".to_string(),
+            step: Step::Verify,
+            started: 0,
+            last_error: Some("Permission denied".to_string()),
+        };
+        assert!(!rotation.can_abandon());
+        assert_eq!(
+            rotation.status_text(),
+            "Test connection failed: Permission denied"
+        );
+        rotation.step = Step::Upload;
+        rotation.last_error = None;
+        assert!(rotation.can_abandon());
+        assert_eq!(rotation.status_text(), "Next: Register new key");
+
+        let json = serde_json::to_value(&rotation).unwrap();
+        assert_eq!(json["step"], "upload");
+        assert!(json.get("lastError").is_none());
+    }
+
+    #[test]
+    fn test_new_key_path_and_authenticated() {
+        let dir = tempfile::tempdir().unwrap();
+        // 2026-10-16
+        let now = 1_792_108_800;
+        let first = new_key_path(dir.path(), "gitlab work", now);
+        assert_eq!(first, dir.path().join("id_ed25519_gitlab_work_20261016"));
+        fs::write(&first, "").unwrap();
+        assert_eq!(
+            new_key_path(dir.path(), "gitlab work", now),
+            dir.path().join("id_ed25519_gitlab_work_20261016_2")
+        );
+
+        assert!(authenticated(
+            "Hi alice! You've successfully authenticated, but GitHub does not provide shell access."
+        ));
+        assert!(authenticated("Welcome to GitLab, @alice!"));
+        assert!(!authenticated(
+            "git@github.com: Permission denied (publickey)."
+        ));
+        assert!(!authenticated("Connection closed by remote host"));
+    }
+}
@@ -0,0 +1,210 @@
+//! Remote URLs pointed at an identity's host alias
+//!
+//! Switching between identities on the same hostname, e.g. github-personal
+//! and github-work, only changes which SSH key git uses if the remotes go
+//! through the identity's host alias. This works out which fetch and push
+//! URLs of a repository would change, so they can be previewed before
+//! being applied. Like the CLI's switch, only origin is touched, and only
+//! when it is on the identity's hostname; an HTTPS origin becomes an SSH
+//! one, and `git://` and local origins are left alone.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, Identity};
+use crate::detect::RemoteUrl;
+use crate::error::{CliError, Error};
+use crate::ssh_config;
+
+/// One URL that would change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub remote: String,
+    /// Whether it is the push URL rather than the fetch URL
+    pub push: bool,
+    pub from: String,
+    pub to: String,
+}
+
+impl Change {
+    pub fn display_text(&self) -> String {
+        format!(
+            "{}{}: {} → {}",
+            self.remote,
+            if self.push { " (push)" } else { "" },
+            self.from,
+            self.to
+        )
+    }
+}
+
+/// `url` rewritten to use the identity's host alias; None if it already
+/// does, or isn't a remote on the identity's hostname. `hostnames` maps SSH
+/// aliases to their `HostName`
+pub fn rewrite_url(
+    url: &str,
+    identity: &Identity,
+    hostnames: &BTreeMap<String, String>,
+) -> Option<String> {
+    if identity.host.is_empty() || url.starts_with("git://") {
+        return None;
+    }
+    let remote = RemoteUrl::parse(url)?;
+    if remote.host.eq_ignore_ascii_case(&identity.host) {
+        return None;
+    }
+    let hostname = hostnames.get(&remote.host).unwrap_or(&remote.host);
+    if !hostname.eq_ignore_ascii_case(&identity.hostname) {
+        return None;
+    }
+    Some(if url.starts_with("ssh://") {
+        format!("ssh://git@{}/{}.git", identity.host, remote.path)
+    } else {
+        format!("git@{}:{}.git", identity.host, remote.path)
+    })
+}
+
+/// Parse `git config --get-regexp '^remote\..*\.(url|pushurl)$'` into
+/// (remote, push, URL)
+pub fn parse_urls(text: &str) -> Vec<(String, bool, String)> {
+    text.lines()
+        .filter_map(|line| {
+            let (key, url) = line.trim().split_once(char::is_whitespace)?;
+            let key = key.strip_prefix("remote.")?;
+            let (name, push) = match key.strip_suffix(".pushurl") {
+                Some(name) => (name, true),
+                None => (key.strip_suffix(".url")?, false),
+            };
+            Some((name.to_string(), push, url.trim().to_string()))
+        })
+        .collect()
+}
+
+/// The remote URLs are rewritten for
+pub const REMOTE: &str = "origin";
+
+/// The changes for the URLs from [`parse_urls`]
+pub fn plan_with(
+    identity: &Identity,
+    urls: &[(String, bool, String)],
+    hostnames: &BTreeMap<String, String>,
+) -> Vec<Change> {
+    urls.iter()
+        .filter(|(remote, _, _)| remote == REMOTE)
+        .filter_map(|(remote, push, url)| {
+            Some(Change {
+                remote: remote.clone(),
+                push: *push,
+                from: url.clone(),
+                to: rewrite_url(url, identity, hostnames)?,
+            })
+        })
+        .collect()
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<String, CliError> {
+    let mut all = vec!["-C".to_string(), repo.to_string_lossy().into_owned()];
+    all.extend(cli::args(args));
+    let options = RunOptions {
+        timeout: Duration::from_secs(10),
+        retries: 0,
+        cancel: None,
+    };
+    cli::run_program("git", &all, &options)
+}
+
+/// The URLs of `repo` that would change for the identity `name`; blocks
+pub fn plan(config: &Config, name: &str, repo: &Path) -> Result<Vec<Change>, Error> {
+    let identity = config
+        .identities
+        .get(name)
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "config",
+            message: format!("No identity named {}", name),
+        })?;
+    let urls = match git(
+        repo,
+        &["config", "--get-regexp", r"^remote\..*\.(url|pushurl)$"],
+    ) {
+        Ok(out) => parse_urls(&out),
+        // Exit status 1: no remotes
+        Err(CliError::NonZeroExit { code: Some(1), .. }) => Vec::new(),
+        Err(e) => return Err(Error::from_cli(e)),
+    };
+    let hostnames = ssh_config::path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .map(|text| ssh_config::host_names(&text))
+        .unwrap_or_default();
+    Ok(plan_with(identity, &urls, &hostnames))
+}
+
+/// A regex matching exactly `text`, for `git remote set-url`'s old URL
+fn exact_regex(text: &str) -> String {
+    let mut out = String::from("^");
+    for c in text.chars() {
+        if r"\.^$|?*+()[]{}".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('$');
+    out
+}
+
+/// Apply previewed changes; blocks
+pub fn apply(repo: &Path, changes: &[Change]) -> Result<(), Error> {
+    for change in changes {
+        let old = exact_regex(&change.from);
+        let mut args = vec!["remote", "set-url"];
+        if change.push {
+            args.push("--push");
+        }
+        args.extend([change.remote.as_str(), change.to.as_str(), old.as_str()]);
+        git(repo, &args).map_err(Error::from_cli)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::identity;
+
+    #[test]
+    fn test_plan_rewrites_only_the_identity_hostname() {
+        let identity = identity("github", "github-work", "github.com", "Work", "me@acme.dev");
+        let hostnames = BTreeMap::from([
+            ("github-personal".to_string(), "github.com".to_string()),
+            ("gitlab-work".to_string(), "gitlab.com".to_string()),
+        ]);
+        let urls = parse_urls(
+            "remote.origin.url git@github-personal:acme/api.git\n\
+             remote.origin.pushurl ssh://git@github.com:22/acme/api.git\n\
+             remote.upstream.url https://github.com/someone/api\n",
+        );
+        assert_eq!(urls.len(), 3);
+        assert!(urls[1].1);
+
+        let changes = plan_with(&identity, &urls, &hostnames);
+        let texts: Vec<String> = changes.iter().map(Change::display_text).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "origin: git@github-personal:acme/api.git → git@github-work:acme/api.git",
+                "origin (push): ssh://git@github.com:22/acme/api.git → \
+                 ssh://git@github-work/acme/api.git",
+            ]
+        );
+        let rewrite = |url| rewrite_url(url, &identity, &hostnames);
+        assert_eq!(
+            rewrite("https://github.com/acme/api").as_deref(),
+            Some("git@github-work:acme/api.git")
+        );
+        assert_eq!(rewrite("git@gitlab-work:acme/api.git"), None);
+        assert_eq!(rewrite("git@github-work:acme/api.git"), None);
+        assert_eq!(rewrite("/srv/git/api.git"), None);
+        assert_eq!(exact_regex("git@h:a/b.git"), r"^git@h:a/b\.git$");
+    }
+}
@@ -0,0 +1,558 @@
+//! Composite secret resolver
+//!
+//! Looks a secret up across every place it may live — environment
+//! variables, SOPS files, the KDBX store, and the mounted remote backends —
+//! in a configurable order, and returns the first match together with an
+//! audit trail of each source checked. The order comes from
+//! `$REMOTE_JUGGLER_RESOLVE_ORDER` or `settings.resolveOrder` in config.json,
+//! e.g. `"kdbx,env,sops,remote"`.
+//!
+//! SOPS is only consulted for files listed in `$REMOTE_JUGGLER_SOPS_FILES`
+//! (colon-separated, like `PATH`), since decrypting means running sops.
+//!
+//! `GitHubApp/<app>/token` isn't looked up but minted: the app's ID and
+//! private key are resolved like any secret and exchanged for an
+//! installation token (see `github_app`).
+//!
+//! Setec and stores marked tailnet-only are skipped while Tailscale says
+//! the tailnet is down (see `tailscale`).
+
+use std::path::PathBuf;
+
+use crate::backend::{self, SecretBackend};
+use crate::cli;
+use crate::config::Config;
+use crate::error::{CliError, Error};
+use crate::event_hooks::{self, Event};
+use crate::github_app;
+use crate::metrics;
+use crate::sops;
+use crate::tailscale;
+
+/// Environment variable overriding the configured order
+pub const ORDER_ENV: &str = "REMOTE_JUGGLER_RESOLVE_ORDER";
+
+/// Environment variable listing SOPS files to consult
+pub const SOPS_FILES_ENV: &str = "REMOTE_JUGGLER_SOPS_FILES";
+
+/// A place a secret can come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    Env,
+    Sops,
+    Kdbx,
+    /// Any mounted backend (pass, Bitwarden, cloud stores, Setec)
+    Remote,
+    /// Minted from a GitHub App's key; not part of the order
+    GitHubApp,
+}
+
+/// Order used when none is configured
+pub const DEFAULT_ORDER: [Source; 4] = [Source::Env, Source::Sops, Source::Kdbx, Source::Remote];
+
+impl Source {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Source::Env => "Environment",
+            Source::Sops => "SOPS",
+            Source::Kdbx => "KDBX",
+            Source::Remote => "Remote stores",
+            Source::GitHubApp => "GitHub App",
+        }
+    }
+
+    /// Parse a source name as written in the order setting
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "env" | "environment" => Some(Source::Env),
+            "sops" => Some(Source::Sops),
+            "kdbx" | "keepassxc" | "keys" => Some(Source::Kdbx),
+            "remote" | "backends" => Some(Source::Remote),
+            _ => None,
+        }
+    }
+}
+
+/// Parse an order like `"kdbx, env"`, dropping unknown and repeated names
+///
+/// Returns the default order if nothing valid is listed.
+pub fn parse_order<S: AsRef<str>>(parts: &[S]) -> Vec<Source> {
+    let mut order = Vec::new();
+    for part in parts.iter().flat_map(|p| p.as_ref().split(',')) {
+        if part.trim().is_empty() {
+            continue;
+        }
+        match Source::parse(part) {
+            Some(source) if !order.contains(&source) => order.push(source),
+            Some(_) => {}
+            None => tracing::warn!("Unknown resolve source: {}", part.trim()),
+        }
+    }
+    if order.is_empty() {
+        DEFAULT_ORDER.to_vec()
+    } else {
+        order
+    }
+}
+
+/// The order from the environment, else config.json, else the default
+pub fn configured_order() -> Vec<Source> {
+    if let Ok(spec) = std::env::var(ORDER_ENV) {
+        return parse_order(&[spec]);
+    }
+    let configured = Config::load()
+        .map(|c| c.settings.resolve_order)
+        .unwrap_or_default();
+    parse_order(&configured)
+}
+
+/// Environment variable names tried for a secret
+///
+/// The name itself if it is a valid variable name, then its last path
+/// segment upper-cased with other characters as `_`
+/// (`RemoteJuggler/API/github-token` -> `GITHUB_TOKEN`).
+pub fn env_names(name: &str) -> Vec<String> {
+    let is_var = |s: &str| {
+        !s.is_empty()
+            && !s.starts_with(|c: char| c.is_ascii_digit())
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    let leaf = name.rsplit('/').next().unwrap_or(name);
+    let normalized: String = leaf
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let mut names = Vec::new();
+    if is_var(name) {
+        names.push(name.to_string());
+    }
+    if is_var(&normalized) && !names.contains(&normalized) {
+        names.push(normalized);
+    }
+    names
+}
+
+/// Find `name` among a SOPS file's flattened keys
+///
+/// Matches the dotted key, the key store path it is ingested under
+/// (`RemoteJuggler/SOPS/{file}/{key}`), or a bare key equal to the last
+/// path segment of `name`.
+pub fn match_sops_key<'a>(
+    file: &str,
+    values: &'a std::collections::BTreeMap<String, String>,
+    name: &str,
+) -> Option<(&'a String, &'a String)> {
+    let group = sops::group_path(file);
+    let leaf = name.rsplit('/').next().unwrap_or(name);
+    values.iter().find(|(key, _)| {
+        key.as_str() == name || format!("{}/{}", group, key) == name || key.as_str() == leaf
+    })
+}
+
+/// How a single source answered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Found,
+    Missing,
+    /// Not consulted, e.g. a locked store or no SOPS files configured
+    Skipped(String),
+    /// The source errored; resolution moved on
+    Failed(String),
+}
+
+/// One step of the audit trail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attempt {
+    pub source: Source,
+    /// What was looked up, e.g. `$GITHUB_TOKEN` or `Pass/work/github`
+    pub location: String,
+    pub outcome: Outcome,
+}
+
+impl Attempt {
+    fn new(source: Source, location: impl Into<String>, outcome: Outcome) -> Self {
+        Self {
+            source,
+            location: location.into(),
+            outcome,
+        }
+    }
+
+    pub fn display_text(&self) -> String {
+        let outcome = match &self.outcome {
+            Outcome::Found => "found".to_string(),
+            Outcome::Missing => "not found".to_string(),
+            Outcome::Skipped(reason) => format!("skipped ({})", reason),
+            Outcome::Failed(reason) => format!("failed ({})", reason),
+        };
+        format!(
+            "{}: {} — {}",
+            self.source.display_name(),
+            self.location,
+            outcome
+        )
+    }
+}
+
+/// The result of resolving a secret
+#[derive(Debug, Clone, Default)]
+pub struct Resolution {
+    pub value: Option<String>,
+    /// Every source checked, in order; the last is the winner if found
+    pub trail: Vec<Attempt>,
+}
+
+impl Resolution {
+    /// The attempt that produced the value
+    pub fn winner(&self) -> Option<&Attempt> {
+        self.trail.iter().find(|a| a.outcome == Outcome::Found)
+    }
+}
+
+/// Queries sources in order until one has the secret
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    order: Vec<Source>,
+    sops_files: Vec<PathBuf>,
+}
+
+impl Resolver {
+    pub fn new(order: Vec<Source>, sops_files: Vec<PathBuf>) -> Self {
+        Self { order, sops_files }
+    }
+
+    /// The configured order and SOPS files
+    pub fn from_config() -> Self {
+        let sops_files = std::env::var_os(SOPS_FILES_ENV)
+            .map(|v| std::env::split_paths(&v).collect())
+            .unwrap_or_default();
+        Self::new(configured_order(), sops_files)
+    }
+
+    pub fn order(&self) -> &[Source] {
+        &self.order
+    }
+
+    /// Resolve a secret, blocking while sources are queried
+    pub fn resolve(&self, name: &str) -> Resolution {
+        let span = tracing::info_span!("resolve", name = %name, source = tracing::field::Empty);
+        let resolution = span.in_scope(|| self.resolve_in_order(name));
+        if let Some(winner) = resolution.winner() {
+            span.record("source", winner.source.display_name());
+        }
+        // The key store's reads are reported by the CLI call, and a
+        // variable isn't a store
+        let found = resolution
+            .trail
+            .last()
+            .filter(|attempt| attempt.outcome == Outcome::Found);
+        if let Some(attempt) = found.filter(|a| !matches!(a.source, Source::Kdbx | Source::Env)) {
+            event_hooks::emit(Event::SecretAccessed {
+                name: name.to_string(),
+                source: attempt.source.display_name().to_string(),
+            });
+        }
+        resolution
+    }
+
+    fn resolve_in_order(&self, name: &str) -> Resolution {
+        let mut resolution = Resolution::default();
+        if let Some(app) = github_app::parse_name(name) {
+            resolution.value = self.try_github_app(app, &mut resolution.trail);
+            return resolution;
+        }
+        for source in &self.order {
+            let value = match source {
+                Source::Env => self.try_env(name, &mut resolution.trail),
+                Source::Sops => self.try_sops(name, &mut resolution.trail),
+                Source::Kdbx => self.try_kdbx(name, &mut resolution.trail),
+                Source::Remote => self.try_remote(name, &mut resolution.trail),
+                Source::GitHubApp => None,
+            };
+            if value.is_some() {
+                resolution.value = value;
+                break;
+            }
+        }
+        resolution
+    }
+
+    fn try_env(&self, name: &str, trail: &mut Vec<Attempt>) -> Option<String> {
+        let candidates = env_names(name);
+        if candidates.is_empty() {
+            trail.push(Attempt::new(
+                Source::Env,
+                name,
+                Outcome::Skipped("not a variable name".to_string()),
+            ));
+        }
+        for var in candidates {
+            let location = format!("${}", var);
+            match std::env::var(&var) {
+                Ok(value) if !value.is_empty() => {
+                    trail.push(Attempt::new(Source::Env, location, Outcome::Found));
+                    return Some(value);
+                }
+                _ => trail.push(Attempt::new(Source::Env, location, Outcome::Missing)),
+            }
+        }
+        None
+    }
+
+    fn try_sops(&self, name: &str, trail: &mut Vec<Attempt>) -> Option<String> {
+        if self.sops_files.is_empty() {
+            trail.push(Attempt::new(
+                Source::Sops,
+                name,
+                Outcome::Skipped(format!("{} not set", SOPS_FILES_ENV)),
+            ));
+        }
+        for file in &self.sops_files {
+            let file_str = file.to_string_lossy().to_string();
+            let values = match sops::decrypt(file) {
+                Ok(json) => sops::flatten(&json),
+                Err(e) => {
+                    trail.push(Attempt::new(
+                        Source::Sops,
+                        file_str,
+                        Outcome::Failed(e.to_string()),
+                    ));
+                    continue;
+                }
+            };
+            match match_sops_key(&file_str, &values, name) {
+                Some((key, value)) => {
+                    let location = format!("{}:{}", file_str, key);
+                    trail.push(Attempt::new(Source::Sops, location, Outcome::Found));
+                    return Some(value.clone());
+                }
+                None => trail.push(Attempt::new(Source::Sops, file_str, Outcome::Missing)),
+            }
+        }
+        None
+    }
+
+    fn try_kdbx(&self, name: &str, trail: &mut Vec<Attempt>) -> Option<String> {
+        if let Some((store, _)) = backend::route(name) {
+            let reason = format!("path belongs to {}", store.display_name());
+            trail.push(Attempt::new(Source::Kdbx, name, Outcome::Skipped(reason)));
+            return None;
+        }
+        match cli::run(&cli::args(&["keys", "get", name])).map_err(Error::from_cli) {
+            Ok(value) => {
+                trail.push(Attempt::new(Source::Kdbx, name, Outcome::Found));
+                Some(value.trim_end_matches(['\r', '\n']).to_string())
+            }
+            Err(Error::Cli(CliError::NonZeroExit { .. })) => {
+                trail.push(Attempt::new(Source::Kdbx, name, Outcome::Missing));
+                None
+            }
+            Err(e) => {
+                metrics::record_backend_error("KDBX");
+                trail.push(Attempt::new(
+                    Source::Kdbx,
+                    name,
+                    Outcome::Failed(e.to_string()),
+                ));
+                None
+            }
+        }
+    }
+
+    fn try_remote(&self, name: &str, trail: &mut Vec<Attempt>) -> Option<String> {
+        // A namespaced path names its backend; a bare name is tried in each
+        let targets: Vec<(Box<dyn SecretBackend>, String)> = match backend::route(name) {
+            Some(routed) => vec![routed],
+            None => backend::all()
+                .into_iter()
+                .filter(|b| b.is_available())
+                .map(|b| (b, name.to_string()))
+                .collect(),
+        };
+        if targets.is_empty() {
+            trail.push(Attempt::new(
+                Source::Remote,
+                name,
+                Outcome::Skipped("no stores available".to_string()),
+            ));
+        }
+        let marked = backend::tailnet_only_marked();
+        for (store, relative) in targets {
+            let location = backend::qualified(store.as_ref(), &relative);
+            if !store.is_available() {
+                trail.push(Attempt::new(
+                    Source::Remote,
+                    location,
+                    Outcome::Skipped("not available".to_string()),
+                ));
+                continue;
+            }
+            if backend::is_tailnet_only(store.as_ref(), &marked) && tailscale::is_offline() {
+                trail.push(Attempt::new(
+                    Source::Remote,
+                    location,
+                    Outcome::Skipped("tailnet offline".to_string()),
+                ));
+                continue;
+            }
+            if store.is_locked() {
+                trail.push(Attempt::new(
+                    Source::Remote,
+                    location,
+                    Outcome::Skipped("locked".to_string()),
+                ));
+                continue;
+            }
+            match store.get(&relative) {
+                Ok(value) => {
+                    trail.push(Attempt::new(Source::Remote, location, Outcome::Found));
+                    return Some(value);
+                }
+                Err(Error::Network(reason)) => {
+                    metrics::record_backend_error(store.display_name());
+                    trail.push(Attempt::new(
+                        Source::Remote,
+                        location,
+                        Outcome::Failed(reason),
+                    ));
+                }
+                Err(_) => trail.push(Attempt::new(Source::Remote, location, Outcome::Missing)),
+            }
+        }
+        None
+    }
+
+    /// Mint an installation token, or reuse one minted in the last 50
+    /// minutes; the trail shows where the app's secrets came from
+    fn try_github_app(&self, app: &str, trail: &mut Vec<Attempt>) -> Option<String> {
+        let location = format!("{}{}", github_app::PREFIX, app);
+        let cached = github_app::cache().fresh(app);
+        metrics::record_cache("github_app", cached.is_some());
+        if let Some(token) = cached {
+            let location = format!("{} (cached)", location);
+            trail.push(Attempt::new(Source::GitHubApp, location, Outcome::Found));
+            return Some(token);
+        }
+        let mut secret = |field: &str| {
+            let resolution = self.resolve(&github_app::secret_path(app, field));
+            trail.extend(resolution.trail);
+            resolution.value
+        };
+        let (Some(app_id), Some(private_key)) =
+            (secret(github_app::APP_ID), secret(github_app::PRIVATE_KEY))
+        else {
+            let reason = format!(
+                "{} and {} are needed",
+                github_app::APP_ID,
+                github_app::PRIVATE_KEY
+            );
+            trail.push(Attempt::new(
+                Source::GitHubApp,
+                location,
+                Outcome::Skipped(reason),
+            ));
+            return None;
+        };
+        let installation = secret(github_app::INSTALLATION_ID);
+        match github_app::mint(&app_id, &private_key, installation.as_deref()) {
+            Ok(token) => {
+                github_app::cache().insert(app, &token);
+                trail.push(Attempt::new(Source::GitHubApp, location, Outcome::Found));
+                Some(token)
+            }
+            Err(e) => {
+                trail.push(Attempt::new(
+                    Source::GitHubApp,
+                    location,
+                    Outcome::Failed(e.to_string()),
+                ));
+                None
+            }
+        }
+    }
+}
+
+/// Resolve with the configured order; see `Resolver::resolve`
+pub fn resolve(name: &str) -> Resolution {
+    Resolver::from_config().resolve(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_parse_order() {
+        assert_eq!(
+            parse_order(&["kdbx, env", "kdbx", "bogus"]),
+            vec![Source::Kdbx, Source::Env]
+        );
+        assert_eq!(parse_order::<&str>(&[]), DEFAULT_ORDER.to_vec());
+        assert_eq!(parse_order(&["bogus"]), DEFAULT_ORDER.to_vec());
+    }
+
+    #[test]
+    fn test_env_names() {
+        assert_eq!(env_names("GITHUB_TOKEN"), vec!["GITHUB_TOKEN"]);
+        assert_eq!(
+            env_names("RemoteJuggler/API/github-token"),
+            vec!["GITHUB_TOKEN"]
+        );
+        assert_eq!(env_names("api_key"), vec!["api_key", "API_KEY"]);
+        assert!(env_names("Work/9lives").is_empty());
+    }
+
+    #[test]
+    fn test_match_sops_key() {
+        let values: BTreeMap<String, String> = [
+            ("db.password".to_string(), "hunter2".to_string()),
+            ("API_KEY".to_string(), "abc".to_string()),
+        ]
+        .into();
+        let file = "secrets.enc.yaml";
+        assert_eq!(
+            match_sops_key(file, &values, "db.password").map(|(_, v)| v.as_str()),
+            Some("hunter2")
+        );
+        assert_eq!(
+            match_sops_key(
+                file,
+                &values,
+                "RemoteJuggler/SOPS/secrets.enc.yaml/db.password"
+            )
+            .map(|(k, _)| k.as_str()),
+            Some("db.password")
+        );
+        assert!(match_sops_key(file, &values, "Other/API_KEY").is_some());
+        assert!(match_sops_key(file, &values, "missing").is_none());
+    }
+
+    #[test]
+    fn test_resolve_from_env_records_trail() {
+        std::env::set_var("RJ_RESOLVER_TEST_TOKEN", "from-env");
+        let resolver = Resolver::new(vec![Source::Sops, Source::Env], Vec::new());
+        let resolution = resolver.resolve("RJ_RESOLVER_TEST_TOKEN");
+        assert_eq!(resolution.value.as_deref(), Some("from-env"));
+        assert!(matches!(resolution.trail[0].outcome, Outcome::Skipped(_)));
+        let winner = resolution.winner().unwrap();
+        assert_eq!(winner.source, Source::Env);
+        assert_eq!(winner.location, "$RJ_RESOLVER_TEST_TOKEN");
+    }
+
+    #[test]
+    fn test_resolve_missing() {
+        let resolver = Resolver::new(vec![Source::Env], Vec::new());
+        let resolution = resolver.resolve("RJ_RESOLVER_TEST_UNSET");
+        assert_eq!(resolution.value, None);
+        assert!(resolution.winner().is_none());
+        assert_eq!(resolution.trail[0].outcome, Outcome::Missing);
+    }
+}
@@ -0,0 +1,658 @@
+//! Local HTTP API
+//!
+//! Editor plugins and scripts use the D-Bus interface on Linux; where there
+//! is no session bus (macOS, WSL, containers) they can use this instead. It
+//! is off unless `settings.restApi.enabled` is set, listens on 127.0.0.1
+//! only, and answers JSON:
+//!
+//! - `GET /status`: the current identity and the configured identities
+//! - `POST /switch` with `{"identity": "work"}`: switch identity
+//! - `GET /resolve?name=...`: a secret, through the composite resolver
+//! - `GET /keys/search?q=...`: a fuzzy search of the key store
+//!
+//! Every request needs `Authorization: Bearer <token>`, the token being the
+//! content of `rest-api-token` next to config.json (created on first start,
+//! readable only by the user). Requests naming another host than the
+//! loopback address, or carrying an `Origin`, are refused, so a web page
+//! can't reach the API through DNS rebinding or a cross-site request. The
+//! server accepts on its own thread and serves each connection on another.
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use percent_encoding::percent_decode_str;
+use rsa::rand_core::{OsRng, RngCore};
+use serde_json::{json, Value};
+
+use crate::cli;
+use crate::config::{Config, RestApiSettings};
+use crate::databases::{self, SearchHit};
+use crate::error::{ConfigError, Error};
+use crate::export;
+use crate::platform;
+use crate::resolver::{self, Resolution};
+
+/// File next to config.json holding the bearer token
+pub const TOKEN_FILE: &str = "rest-api-token";
+
+/// Request line and headers together
+const MAX_HEAD: usize = 16 * 1024;
+const MAX_BODY: usize = 64 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A parsed HTTP request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Decoded query parameters
+    pub query: BTreeMap<String, String>,
+    /// Keyed by the lowercased header name
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    /// A non-empty query parameter
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .get(name)
+            .map(String::as_str)
+            .filter(|v| !v.is_empty())
+    }
+}
+
+/// A JSON response
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    pub(crate) fn error(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.into() }),
+        }
+    }
+
+    fn from_error(e: &Error) -> Self {
+        let status = match e {
+            Error::KeystoreLocked => 423,
+            _ => 500,
+        };
+        Self::error(status, e.to_string())
+    }
+
+    /// Write as an HTTP/1.1 response that closes the connection
+    pub fn write_to(&self, out: &mut impl Write) -> std::io::Result<()> {
+        let body = self.body.to_string();
+        write!(
+            out,
+            "HTTP/1.1 {} {}\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\
+             Cache-Control: no-store\r\n\
+             Connection: close\r\n\r\n{}",
+            self.status,
+            reason_phrase(self.status),
+            body.len(),
+            body
+        )?;
+        out.flush()
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        423 => "Locked",
+        _ => "Internal Server Error",
+    }
+}
+
+/// One line of the request head, without its line ending
+fn read_line(reader: &mut impl BufRead, used: &mut usize) -> Result<String, Response> {
+    let mut line = String::new();
+    let limit = (MAX_HEAD - *used) as u64;
+    let read = (&mut *reader)
+        .take(limit)
+        .read_line(&mut line)
+        .map_err(|_| Response::error(400, "unreadable request"))?;
+    *used += read;
+    if !line.ends_with('\n') {
+        return Err(if *used >= MAX_HEAD {
+            Response::error(413, "request head too large")
+        } else {
+            Response::error(400, "incomplete request")
+        });
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn decode(component: &str) -> String {
+    percent_decode_str(&component.replace('+', " "))
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Read one request; the error is the response to send instead
+pub fn read_request(reader: &mut impl BufRead) -> Result<Request, Response> {
+    let mut used = 0;
+    let line = read_line(reader, &mut used)?;
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(Response::error(400, "malformed request line"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(Response::error(400, "unsupported HTTP version"));
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = Request {
+        method: method.to_string(),
+        path: decode(path),
+        query: query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode(key), decode(value))
+            })
+            .collect(),
+        ..Default::default()
+    };
+
+    loop {
+        let line = read_line(reader, &mut used)?;
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(Response::error(400, "malformed header"));
+        };
+        request
+            .headers
+            .insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    if request.header("transfer-encoding").is_some() {
+        return Err(Response::error(400, "send a Content-Length instead"));
+    }
+    let length = match request.header("content-length") {
+        None => 0,
+        Some(v) => v
+            .parse::<usize>()
+            .map_err(|_| Response::error(400, "invalid Content-Length"))?,
+    };
+    if length > MAX_BODY {
+        return Err(Response::error(413, "request body too large"));
+    }
+    request.body = vec![0; length];
+    reader
+        .read_exact(&mut request.body)
+        .map_err(|_| Response::error(400, "incomplete body"))?;
+    Ok(request)
+}
+
+/// Whether the request carries the token, compared in constant time
+pub fn is_authorized(request: &Request, token: &str) -> bool {
+    let Some(given) = request
+        .header("authorization")
+        .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Whether the request is addressed to the loopback address rather than a
+/// name some page made resolve to it, and doesn't come from a browser page
+pub fn is_local(request: &Request, port: u16) -> bool {
+    if request.header("origin").is_some() {
+        return false;
+    }
+    let Some(host) = request.header("host") else {
+        return true;
+    };
+    let suffix = format!(":{}", port);
+    let name = host.strip_suffix(&suffix).unwrap_or(host);
+    matches!(name, "127.0.0.1" | "localhost" | "[::1]")
+}
+
+/// What the endpoints do; [`Live`] runs them against config.json and the CLI
+pub trait Operations {
+    fn config(&self) -> Result<Config, Error>;
+    fn switch(&self, identity: &str) -> Result<(), Error>;
+    fn resolve(&self, name: &str) -> Resolution;
+    fn search(&self, query: &str) -> Result<Vec<SearchHit>, Error>;
+}
+
+/// The real operations. Blocks
+pub struct Live;
+
+impl Operations for Live {
+    fn config(&self) -> Result<Config, Error> {
+        Ok(Config::load()?)
+    }
+
+    fn switch(&self, identity: &str) -> Result<(), Error> {
+        cli::run(&cli::args(&["switch", identity]))
+            .map(|_| ())
+            .map_err(Error::from_cli)
+    }
+
+    fn resolve(&self, name: &str) -> Resolution {
+        resolver::resolve(name)
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<SearchHit>, Error> {
+        let output = cli::run(&databases::search_args(query)).map_err(Error::from_cli)?;
+        databases::parse_search(&output)
+    }
+}
+
+/// The answer to `GET /status`, shared with the editor server
+pub(crate) fn status_json(config: &Config) -> Value {
+    let identities: Vec<Value> = config
+        .identity_names()
+        .into_iter()
+        .map(|name| {
+            let identity = &config.identities[&name];
+            json!({
+                "name": name,
+                "provider": identity.provider,
+                "host": identity.host,
+                "user": identity.user,
+                "email": identity.email,
+            })
+        })
+        .collect();
+    let current = &config.state.current_identity;
+    json!({
+        "currentIdentity": (!current.is_empty()).then_some(current),
+        "lastSwitch": config.state.last_switch,
+        "identities": identities,
+    })
+}
+
+fn switch(request: &Request, ops: &impl Operations) -> Response {
+    let Some(name) = serde_json::from_slice::<Value>(&request.body)
+        .ok()
+        .and_then(|body| body["identity"].as_str().map(str::to_string))
+    else {
+        return Response::error(400, "expected {\"identity\": \"<name>\"}");
+    };
+    match ops.config() {
+        Ok(config) if !config.identities.contains_key(&name) => {
+            Response::error(404, format!("no identity named {}", name))
+        }
+        Ok(_) => match ops.switch(&name) {
+            Ok(()) => Response::ok(json!({ "currentIdentity": name })),
+            Err(e) => Response::from_error(&e),
+        },
+        Err(e) => Response::from_error(&e),
+    }
+}
+
+fn resolve(name: &str, ops: &impl Operations) -> Response {
+    let resolution = ops.resolve(name);
+    let trail: Vec<String> = resolution.trail.iter().map(|a| a.display_text()).collect();
+    match (&resolution.value, resolution.winner()) {
+        (Some(value), winner) => Response::ok(json!({
+            "name": name,
+            "value": value,
+            "source": winner.map(|a| a.source.display_name()),
+            "trail": trail,
+        })),
+        (None, _) => Response {
+            status: 404,
+            body: json!({ "error": format!("{} not found", name), "trail": trail }),
+        },
+    }
+}
+
+fn search(query: &str, ops: &impl Operations) -> Response {
+    match ops.search(query) {
+        Ok(hits) => Response::ok(json!({
+            "results": hits
+                .iter()
+                .map(|hit| json!({
+                    "entryPath": hit.entry_path,
+                    "score": hit.score,
+                    "matchContext": hit.match_context,
+                }))
+                .collect::<Vec<_>>(),
+        })),
+        Err(e) => Response::from_error(&e),
+    }
+}
+
+/// Answer an authorized request
+pub fn route(request: &Request, ops: &impl Operations) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => match ops.config() {
+            Ok(config) => Response::ok(status_json(&config)),
+            Err(e) => Response::from_error(&e),
+        },
+        ("POST", "/switch") => switch(request, ops),
+        ("GET", "/resolve") => match request.param("name") {
+            Some(name) => resolve(name, ops),
+            None => Response::error(400, "missing name parameter"),
+        },
+        ("GET", "/keys/search") => match request.param("q") {
+            Some(query) => search(query, ops),
+            None => Response::error(400, "missing q parameter"),
+        },
+        (_, "/status" | "/switch" | "/resolve" | "/keys/search") => {
+            Response::error(405, "method not allowed")
+        }
+        _ => Response::error(404, "no such endpoint"),
+    }
+}
+
+/// Check where a request comes from and its token, then route it
+pub fn handle(request: &Request, token: &str, port: u16, ops: &impl Operations) -> Response {
+    if !is_local(request, port) {
+        return Response::error(403, "only local clients may use the API");
+    }
+    if !is_authorized(request, token) {
+        return Response::error(401, "missing or wrong bearer token");
+    }
+    route(request, ops)
+}
+
+fn serve(stream: TcpStream, token: &str, port: u16) {
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let response = match read_request(&mut BufReader::new(&stream)) {
+        Ok(request) => handle(&request, token, port, &Live),
+        Err(response) => response,
+    };
+    if let Err(e) = response.write_to(&mut &stream) {
+        tracing::debug!("Writing API response failed: {}", e);
+    }
+}
+
+/// Where the bearer token is kept
+pub fn token_path() -> Option<PathBuf> {
+    platform::config_dir().map(|dir| dir.join(TOKEN_FILE))
+}
+
+/// 32 random bytes as hex
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The bearer token, created on first use
+pub fn load_or_create_token() -> Result<String, Error> {
+    let path = token_path().ok_or(ConfigError::NoConfigDir)?;
+    if let Ok(contents) = std::fs::read_to_string(&path) {
+        let token = contents.trim();
+        if !token.is_empty() {
+            return Ok(token.to_string());
+        }
+    }
+    let token = generate_token();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    export::write_private(&path, &format!("{}\n", token))?;
+    Ok(token)
+}
+
+/// A listening server; it stops when dropped
+pub struct Server {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+}
+
+impl Server {
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake the accept loop so it sees the flag
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+/// Listen on 127.0.0.1 at `port`, serving each connection on its own
+/// thread with `serve`, which is also given the port listened on
+pub(crate) fn listen(
+    port: u16,
+    thread_name: &str,
+    serve: impl Fn(TcpStream, u16) + Send + Sync + 'static,
+) -> Result<Server, Error> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    let addr = listener.local_addr()?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = Arc::clone(&stop);
+    let serve = Arc::new(serve);
+    std::thread::Builder::new()
+        .name(thread_name.to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let serve = Arc::clone(&serve);
+                std::thread::spawn(move || serve(stream, addr.port()));
+            }
+        })?;
+    Ok(Server { addr, stop })
+}
+
+/// Listen on 127.0.0.1 at the configured port
+pub fn start(settings: &RestApiSettings) -> Result<Server, Error> {
+    let token = load_or_create_token()?;
+    let server = listen(settings.port, "rest-api", move |stream, port| {
+        serve(stream, &token, port)
+    })?;
+    tracing::info!("Local HTTP API listening on {}", server.addr);
+    Ok(server)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::resolver::{Attempt, Outcome, Source};
+
+    struct Fake;
+
+    impl Operations for Fake {
+        fn config(&self) -> Result<Config, Error> {
+            Ok(ConfigBuilder::new()
+                .identity(
+                    "work",
+                    identity("gitlab", "gitlab-work", "gitlab.com", "Work", "me@acme.dev"),
+                )
+                .current("work")
+                .build())
+        }
+
+        fn switch(&self, _identity: &str) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn resolve(&self, name: &str) -> Resolution {
+            let found = name == "GITHUB_TOKEN";
+            Resolution {
+                value: found.then(|| "ghp_abc".to_string()),
+                trail: vec![Attempt {
+                    source: Source::Env,
+                    location: format!("${}", name),
+                    outcome: if found {
+                        Outcome::Found
+                    } else {
+                        Outcome::Missing
+                    },
+                }],
+            }
+        }
+
+        fn search(&self, _query: &str) -> Result<Vec<SearchHit>, Error> {
+            Err(Error::KeystoreLocked)
+        }
+    }
+
+    fn parse(raw: &str) -> Result<Request, Response> {
+        read_request(&mut raw.as_bytes())
+    }
+
+    #[test]
+    fn test_read_request() {
+        let request = parse(
+            "POST /switch?x=a%20b+c HTTP/1.1\r\nHost: 127.0.0.1:7827\r\n\
+             Content-Length: 20\r\n\r\n{\"identity\":\"work\"}\n",
+        )
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/switch");
+        assert_eq!(request.param("x"), Some("a b c"));
+        assert_eq!(request.header("host"), Some("127.0.0.1:7827"));
+        assert_eq!(request.body, b"{\"identity\":\"work\"}\n");
+
+        assert_eq!(parse("GET /status\r\n\r\n").unwrap_err().status, 400);
+        assert_eq!(
+            parse("GET /status HTTP/1.1\r\nHost").unwrap_err().status,
+            400
+        );
+        let huge = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_HEAD));
+        assert_eq!(parse(&huge).unwrap_err().status, 413);
+        let chunked = "POST /switch HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n";
+        assert_eq!(parse(chunked).unwrap_err().status, 400);
+    }
+
+    #[test]
+    fn test_handle_checks_origin_and_token() {
+        let request =
+            |headers: &str| parse(&format!("GET /status HTTP/1.1\r\n{}\r\n", headers)).unwrap();
+        let token = "0123abcd";
+        let status = |headers: &str| handle(&request(headers), token, 7827, &Fake).status;
+
+        assert_eq!(
+            status("Host: 127.0.0.1:7827\r\nAuthorization: Bearer 0123abcd\r\n"),
+            200
+        );
+        assert_eq!(status("Authorization: Bearer 0123abcd\r\n"), 200);
+        assert_eq!(
+            status("Host: localhost:7827\r\nAuthorization: Bearer 0123abcd\r\n"),
+            200
+        );
+        assert_eq!(
+            status("Host: 127.0.0.1:7827\r\nAuthorization: Bearer 0123abce\r\n"),
+            401
+        );
+        assert_eq!(
+            status("Host: 127.0.0.1:7827\r\nAuthorization: Bearer 0123\r\n"),
+            401
+        );
+        assert_eq!(status("Host: 127.0.0.1:7827\r\n"), 401);
+        assert_eq!(
+            status("Host: evil.example:7827\r\nAuthorization: Bearer 0123abcd\r\n"),
+            403
+        );
+        assert_eq!(
+            status(
+                "Host: 127.0.0.1:7827\r\nOrigin: https://evil.example\r\n\
+                 Authorization: Bearer 0123abcd\r\n"
+            ),
+            403
+        );
+    }
+
+    #[test]
+    fn test_route() {
+        let get = |target: &str| {
+            route(
+                &parse(&format!("GET {} HTTP/1.1\r\n\r\n", target)).unwrap(),
+                &Fake,
+            )
+        };
+        let post = |target: &str, body: &str| {
+            let raw = format!(
+                "POST {} HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                target,
+                body.len(),
+                body
+            );
+            route(&parse(&raw).unwrap(), &Fake)
+        };
+
+        let status = get("/status");
+        assert_eq!(status.body["currentIdentity"], "work");
+        assert_eq!(status.body["identities"][0]["email"], "me@acme.dev");
+
+        assert_eq!(post("/switch", r#"{"identity":"work"}"#).status, 200);
+        assert_eq!(post("/switch", r#"{"identity":"home"}"#).status, 404);
+        assert_eq!(post("/switch", "work").status, 400);
+        assert_eq!(get("/switch").status, 405);
+
+        let found = get("/resolve?name=GITHUB_TOKEN");
+        assert_eq!(found.status, 200);
+        assert_eq!(found.body["value"], "ghp_abc");
+        assert_eq!(found.body["source"], "Environment");
+        let missing = get("/resolve?name=NPM_TOKEN");
+        assert_eq!(missing.status, 404);
+        assert_eq!(missing.body["trail"].as_array().unwrap().len(), 1);
+        assert_eq!(get("/resolve").status, 400);
+
+        assert_eq!(get("/keys/search?q=github").status, 423);
+        assert_eq!(get("/keys/search").status, 400);
+        assert_eq!(get("/nope").status, 404);
+    }
+
+    #[test]
+    fn test_response_and_token() {
+        let mut out = Vec::new();
+        Response::error(404, "no such endpoint")
+            .write_to(&mut out)
+            .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(text.contains("Content-Length: 28\r\n"));
+        assert!(text.ends_with("\r\n\r\n{\"error\":\"no such endpoint\"}"));
+
+        let token = generate_token();
+        assert_eq!(token.len(), 64);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, generate_token());
+    }
+}
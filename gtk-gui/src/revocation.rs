@@ -0,0 +1,221 @@
+//! Revoked and expired token monitoring
+//!
+//! A token revoked on the provider, or past its expiry, otherwise only
+//! shows up as a push or key upload failing with a bare 401. The monitor
+//! validates each identity's stored token with the cheapest authenticated
+//! call its provider has, the one [`pat::check`] makes, at most every
+//! [`CHECK_INTERVAL_SECS`], and `tokens.json` records the answer. Identities
+//! whose token was rejected or has expired are reported, so the GUI can
+//! ask for signing in again or a new token before git needs it. A network
+//! failure says nothing about the token and changes nothing.
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::expiry;
+use crate::oauth;
+use crate::pat::{self, TokenFile, TokenMeta};
+use crate::pubkeys::{self, Provider};
+use crate::tailscale;
+
+/// How long a token's last check counts before it is validated again
+pub const CHECK_INTERVAL_SECS: u64 = 6 * 3600;
+
+/// Why an identity needs to authenticate again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// The provider refused the token at the last check
+    Rejected,
+    /// Past the expiry the provider reported, in Unix seconds
+    Expired(u64),
+}
+
+/// An identity whose stored token no longer works
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub identity: String,
+    pub provider: Provider,
+    pub reason: Reason,
+}
+
+impl Problem {
+    /// e.g. "GitHub rejected the token; sign in again"
+    pub fn display_text(&self) -> String {
+        let remedy = if oauth::supports(self.provider) {
+            "sign in again or replace it"
+        } else {
+            "replace it"
+        };
+        match self.reason {
+            Reason::Rejected => format!(
+                "{} rejected the token; {}",
+                self.provider.display_name(),
+                remedy
+            ),
+            Reason::Expired(at) => format!(
+                "The token expired on {}; {}",
+                expiry::format_date(at),
+                remedy
+            ),
+        }
+    }
+}
+
+/// Whether a token's metadata is stale enough to validate again
+pub fn is_due(meta: Option<&TokenMeta>, now: u64) -> bool {
+    meta.is_none_or(|meta| meta.last_verified as u64 + CHECK_INTERVAL_SECS <= now)
+}
+
+/// The problem with an identity's token, from its recorded metadata
+pub fn problem(name: &str, provider: Provider, meta: &TokenMeta, now: u64) -> Option<Problem> {
+    let reason = if !meta.is_valid && meta.last_verified > 0.0 {
+        Reason::Rejected
+    } else {
+        Reason::Expired(meta.expires().filter(|at| *at <= now)?)
+    };
+    // Signed-in tokens are renewed on their next use
+    if meta.token_type == "oauth" && matches!(reason, Reason::Expired(_)) {
+        return None;
+    }
+    Some(Problem {
+        identity: name.to_string(),
+        provider,
+        reason,
+    })
+}
+
+/// Problems recorded for the configured identities
+pub fn problems(config: &Config, file: &TokenFile, now: u64) -> Vec<Problem> {
+    config
+        .identity_names()
+        .into_iter()
+        .filter_map(|name| {
+            let provider = Provider::for_identity(&config.identities[&name])?;
+            problem(&name, provider, file.get(provider, &name)?, now)
+        })
+        .collect()
+}
+
+/// The warning for switching to an identity whose token no longer works
+pub fn warning(config: &Config, name: &str) -> Option<String> {
+    problems(config, &TokenFile::load(), expiry::now())
+        .into_iter()
+        .find(|problem| problem.identity == name)
+        .map(|problem| problem.display_text())
+}
+
+/// Validate the stored tokens due for it, or all of them with `force`, and
+/// report the identities that need to authenticate again. Identities
+/// without a token are skipped, and tailnet-only ones while the tailnet is
+/// down. Blocks
+pub fn sweep(config: &Config, force: bool) -> Vec<Problem> {
+    let now = expiry::now();
+    let file = TokenFile::load();
+    for name in config.identity_names() {
+        let identity = &config.identities[&name];
+        let Some(provider) = Provider::for_identity(identity) else {
+            continue;
+        };
+        if !force && !is_due(file.get(provider, &name), now) {
+            continue;
+        }
+        if identity.tailnet_only && tailscale::is_offline() {
+            continue;
+        }
+        // Only a stored token is worth asking about
+        match pubkeys::find_token(&name, identity, provider) {
+            Ok(_) => {}
+            Err(Error::KeystoreLocked) => break,
+            Err(_) => continue,
+        }
+        if let Err(e) = pat::check(&name, identity) {
+            if !pat::is_rejection(&e) {
+                tracing::debug!("Validating {}'s token failed: {}", name, e);
+            }
+        }
+    }
+    problems(config, &TokenFile::load(), now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use serde_json::json;
+
+    #[test]
+    fn test_problems_from_recorded_checks() {
+        let now = 1_767_225_600;
+        let config = ConfigBuilder::new()
+            .identity(
+                "work",
+                identity("gitlab", "gitlab-work", "gitlab.com", "Work", "me@acme.dev"),
+            )
+            .identity(
+                "personal",
+                identity(
+                    "github",
+                    "github-personal",
+                    "github.com",
+                    "Me",
+                    "me@example.com",
+                ),
+            )
+            .identity(
+                "oss",
+                identity(
+                    "github",
+                    "github-oss",
+                    "github.com",
+                    "Oss",
+                    "oss@example.com",
+                ),
+            )
+            .build();
+        let file: TokenFile = serde_json::from_value(json!({
+            "version": "1.0",
+            "tokens": {
+                "gitlab:work": {
+                    "lastVerified": (now - 60) as f64, "isValid": false, "tokenType": "pat"
+                },
+                "github:personal": {
+                    "lastVerified": (now - 7200) as f64, "isValid": true,
+                    "expiresAt": (now - 86_400) as f64, "tokenType": "pat"
+                },
+                "github:oss": {
+                    "lastVerified": (now - 60) as f64, "isValid": true,
+                    "expiresAt": (now - 60) as f64, "tokenType": "oauth"
+                },
+                "github:removed": { "lastVerified": 1.0, "isValid": false }
+            }
+        }))
+        .unwrap();
+
+        let problems = problems(&config, &file, now);
+        assert_eq!(
+            problems,
+            vec![
+                Problem {
+                    identity: "personal".to_string(),
+                    provider: Provider::GitHub,
+                    reason: Reason::Expired(now - 86_400),
+                },
+                Problem {
+                    identity: "work".to_string(),
+                    provider: Provider::GitLab,
+                    reason: Reason::Rejected,
+                },
+            ]
+        );
+        assert_eq!(
+            problems[1].display_text(),
+            "GitLab rejected the token; sign in again or replace it"
+        );
+
+        assert!(is_due(None, now));
+        assert!(!is_due(file.get(Provider::GitLab, "work"), now));
+        assert!(is_due(
+            file.get(Provider::GitHub, "personal"),
+            now + CHECK_INTERVAL_SECS
+        ));
+    }
+}
@@ -0,0 +1,366 @@
+//! Freedesktop Secret Service backed by the key store
+//!
+//! libsecret applications, and anything else speaking org.freedesktop.secrets,
+//! keep passwords as items: a label, lookup attributes such as
+//! `{"service": "smtp.acme.dev", "username": "me"}`, and the secret. When
+//! the GUI serves the interface, those items land in the KeePassXC store,
+//! the credential authority, instead of GNOME Keyring. Each secret is the
+//! entry `RemoteJuggler/SecretService/<id>`. The labels and attributes apps
+//! search by are kept in `secret-service.json` in the data directory. This
+//! module is the toolkit-independent part: the index, the object paths, and
+//! reading and writing items.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::cli;
+use crate::error::Error;
+use crate::expiry;
+use crate::platform;
+
+/// Well-known bus name of the service
+pub const BUS_NAME: &str = "org.freedesktop.secrets";
+
+/// Object path of the service
+pub const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+
+/// The only collection; every item lives in it
+pub const COLLECTION_PATH: &str = "/org/freedesktop/secrets/collection/remotejuggler";
+
+/// The collection again, under the alias apps store to by default
+pub const DEFAULT_ALIAS_PATH: &str = "/org/freedesktop/secrets/aliases/default";
+
+/// Key store group holding the secrets
+pub const GROUP: &str = "RemoteJuggler/SecretService";
+
+/// Label of the collection
+pub const COLLECTION_LABEL: &str = "RemoteJuggler";
+
+/// An item's metadata; the secret itself is in the key store
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Item {
+    pub label: String,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+    /// e.g. `text/plain`
+    #[serde(default)]
+    pub content_type: String,
+    /// Unix seconds
+    pub created: u64,
+    pub modified: u64,
+    /// The entry holds the secret base64-encoded, as it wasn't plain text
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub base64: bool,
+}
+
+/// Metadata of every item, persisted locally
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Index {
+    /// Last item ID handed out
+    #[serde(default)]
+    pub next_id: u64,
+    #[serde(default)]
+    pub items: BTreeMap<String, Item>,
+}
+
+impl Index {
+    pub fn path() -> Option<PathBuf> {
+        platform::data_dir().map(|d| d.join("secret-service.json"))
+    }
+
+    /// The index; an unreadable one is an error rather than empty, since
+    /// starting over would hand out IDs whose entries still exist
+    pub fn load() -> Result<Self, Error> {
+        Self::load_from(Self::path())
+    }
+
+    fn load_from(path: Option<PathBuf>) -> Result<Self, Error> {
+        Ok(platform::try_load_json_state(path)?)
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        Ok(platform::save_json_state(Self::path(), self)?)
+    }
+
+    /// IDs of the items having every one of `attributes`
+    pub fn search(&self, attributes: &BTreeMap<String, String>) -> Vec<String> {
+        self.items
+            .iter()
+            .filter(|(_, item)| {
+                attributes
+                    .iter()
+                    .all(|(key, value)| item.attributes.get(key) == Some(value))
+            })
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    /// The item with exactly these attributes, which creating an item with
+    /// `replace` overwrites
+    pub fn find_exact(&self, attributes: &BTreeMap<String, String>) -> Option<String> {
+        self.items
+            .iter()
+            .find(|(_, item)| &item.attributes == attributes)
+            .map(|(id, _)| id.clone())
+    }
+
+    fn allocate(&mut self) -> String {
+        self.next_id += 1;
+        self.next_id.to_string()
+    }
+}
+
+/// Held while the index is read, changed and saved, so concurrent calls
+/// don't lose each other's items
+fn index_lock() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Object path of an item
+pub fn item_path(id: &str) -> String {
+    format!("{}/{}", COLLECTION_PATH, id)
+}
+
+/// The item ID in an item's object path, under the collection or its alias
+pub fn item_id(path: &str) -> Option<&str> {
+    let id = path
+        .strip_prefix(COLLECTION_PATH)
+        .or_else(|| path.strip_prefix(DEFAULT_ALIAS_PATH))?
+        .strip_prefix('/')?;
+    (!id.is_empty() && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')).then_some(id)
+}
+
+/// Whether an object path names the collection
+pub fn is_collection(path: &str) -> bool {
+    path == COLLECTION_PATH || path == DEFAULT_ALIAS_PATH
+}
+
+/// Key store path of an item's secret
+pub fn entry_path(id: &str) -> String {
+    format!("{}/{}", GROUP, id)
+}
+
+/// A secret as stored in an entry. Anything that wouldn't survive the CLI
+/// unchanged (not UTF-8, empty, with surrounding whitespace, or more than
+/// the one line it reads from stdin) is base64
+pub fn encode(secret: &[u8]) -> (String, bool) {
+    match std::str::from_utf8(secret) {
+        Ok(text) if !text.is_empty() && text.trim() == text && !text.contains(['\n', '\r']) => {
+            (text.to_string(), false)
+        }
+        _ => (
+            base64::engine::general_purpose::STANDARD.encode(secret),
+            true,
+        ),
+    }
+}
+
+/// The secret back from an entry's value
+pub fn decode(value: &str, base64: bool) -> Result<Vec<u8>, Error> {
+    if !base64 {
+        return Ok(value.as_bytes().to_vec());
+    }
+    base64::engine::general_purpose::STANDARD
+        .decode(value)
+        .map_err(|e| Error::InvalidOutput {
+            program: "key store",
+            message: format!("Secret Service entry isn't base64: {}", e),
+        })
+}
+
+fn store(path: &str, value: &str) -> Result<(), Error> {
+    cli::store_secret(path, value).map_err(Error::from_cli)
+}
+
+/// Store a new item, or with `replace` overwrite the one with the same
+/// attributes. Returns its ID and whether it is new. Blocks
+pub fn create_item(
+    label: &str,
+    attributes: BTreeMap<String, String>,
+    secret: &[u8],
+    content_type: &str,
+    replace: bool,
+) -> Result<(String, bool), Error> {
+    let _guard = index_lock();
+    let mut index = Index::load()?;
+    let existing = replace.then(|| index.find_exact(&attributes)).flatten();
+    let id = existing.clone().unwrap_or_else(|| index.allocate());
+    let (value, base64) = encode(secret);
+    store(&entry_path(&id), &value)?;
+
+    let now = expiry::now();
+    let created = existing
+        .as_ref()
+        .and_then(|id| index.items.get(id))
+        .map_or(now, |item| item.created);
+    index.items.insert(
+        id.clone(),
+        Item {
+            label: label.to_string(),
+            attributes,
+            content_type: content_type.to_string(),
+            created,
+            modified: now,
+            base64,
+        },
+    );
+    index.save()?;
+    Ok((id, existing.is_none()))
+}
+
+/// An item's secret and its content type; None for an unknown item. Blocks
+pub fn get_secret(id: &str) -> Result<Option<(Vec<u8>, String)>, Error> {
+    let Some(item) = Index::load()?.items.remove(id) else {
+        return Ok(None);
+    };
+    let value = cli::run(&cli::args(&["keys", "get", &entry_path(id)])).map_err(Error::from_cli)?;
+    let secret = decode(value.trim_end_matches(['\r', '\n']), item.base64)?;
+    Ok(Some((secret, item.content_type)))
+}
+
+/// Replace an item's secret; false for an unknown item. Blocks
+pub fn set_secret(id: &str, secret: &[u8], content_type: &str) -> Result<bool, Error> {
+    let _guard = index_lock();
+    let mut index = Index::load()?;
+    let Some(item) = index.items.get_mut(id) else {
+        return Ok(false);
+    };
+    let (value, base64) = encode(secret);
+    store(&entry_path(id), &value)?;
+    item.base64 = base64;
+    item.content_type = content_type.to_string();
+    item.modified = expiry::now();
+    index.save()?;
+    Ok(true)
+}
+
+/// Change an item's label or attributes; false for an unknown item
+pub fn update_item(id: &str, change: impl FnOnce(&mut Item)) -> Result<bool, Error> {
+    let _guard = index_lock();
+    let mut index = Index::load()?;
+    let Some(item) = index.items.get_mut(id) else {
+        return Ok(false);
+    };
+    change(item);
+    item.modified = expiry::now();
+    index.save()?;
+    Ok(true)
+}
+
+/// Delete an item and its entry; false for an unknown item. Blocks
+pub fn delete_item(id: &str) -> Result<bool, Error> {
+    let _guard = index_lock();
+    let mut index = Index::load()?;
+    if !index.items.contains_key(id) {
+        return Ok(false);
+    }
+    cli::run(&cli::args(&["keys", "delete", &entry_path(id)])).map_err(Error::from_cli)?;
+    index.items.remove(id);
+    index.save()?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_index_search() {
+        let mut index = Index::default();
+        for (label, pairs) in [
+            ("SMTP", &[("service", "smtp"), ("username", "me")][..]),
+            ("IMAP", &[("service", "imap"), ("username", "me")][..]),
+        ] {
+            let id = index.allocate();
+            index.items.insert(
+                id,
+                Item {
+                    label: label.to_string(),
+                    attributes: attributes(pairs),
+                    ..Default::default()
+                },
+            );
+        }
+
+        assert_eq!(index.search(&attributes(&[("username", "me")])), ["1", "2"]);
+        assert_eq!(index.search(&attributes(&[("service", "imap")])), ["2"]);
+        assert!(index
+            .search(&attributes(&[("service", "imap"), ("username", "you")]))
+            .is_empty());
+        assert_eq!(index.search(&BTreeMap::new()).len(), 2);
+
+        assert_eq!(
+            index.find_exact(&attributes(&[("service", "smtp"), ("username", "me")])),
+            Some("1".to_string())
+        );
+        assert_eq!(index.find_exact(&attributes(&[("service", "smtp")])), None);
+        assert_eq!(index.allocate(), "3");
+    }
+
+    #[test]
+    fn test_paths() {
+        assert_eq!(
+            item_path("12"),
+            "/org/freedesktop/secrets/collection/remotejuggler/12"
+        );
+        assert_eq!(item_id(&item_path("12")), Some("12"));
+        assert_eq!(
+            item_id("/org/freedesktop/secrets/aliases/default/7"),
+            Some("7")
+        );
+        assert_eq!(item_id(COLLECTION_PATH), None);
+        assert_eq!(item_id("/org/freedesktop/secrets/collection/login/3"), None);
+        assert_eq!(item_id(&item_path("a/b")), None);
+        assert!(is_collection(DEFAULT_ALIAS_PATH));
+        assert_eq!(entry_path("12"), "RemoteJuggler/SecretService/12");
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        assert_eq!(encode(b"hunter2"), ("hunter2".to_string(), false));
+        for secret in [
+            &b"\xff\x00binary"[..],
+            b"",
+            b"trailing\n",
+            b" padded",
+            b"two\nlines",
+        ] {
+            let (value, base64) = encode(secret);
+            assert!(base64);
+            assert_eq!(decode(&value, base64).unwrap(), secret);
+        }
+        assert!(decode("not base64!", true).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_index_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret-service.json");
+        assert_eq!(
+            Index::load_from(Some(path.clone())).unwrap(),
+            Index::default()
+        );
+
+        // Cut off mid-write; an empty index would hand out ID 1 again
+        std::fs::write(&path, r#"{"nextId": 3, "items": {"1": {"label": "#).unwrap();
+        assert!(Index::load_from(Some(path.clone())).is_err());
+
+        std::fs::write(&path, r#"{"nextId": 3}"#).unwrap();
+        let mut index = Index::load_from(Some(path)).unwrap();
+        assert_eq!(index.allocate(), "4");
+    }
+}
@@ -0,0 +1,861 @@
+//! org.freedesktop.secrets on the session bus
+//!
+//! The D-Bus side of [`secret_service`]: owns the bus name and exports the
+//! service, the collection (also as the `default` alias), one object per
+//! item, and the sessions and unlock prompts clients open. Only the `plain`
+//! session algorithm is offered; libsecret falls back to it, and secrets
+//! never leave the user's session bus. Key store calls run on worker
+//! threads so a slow CLI doesn't stall the window.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use gtk4::gio;
+use gtk4::glib::{self, variant::ObjectPath, Variant, VariantDict};
+use gtk4::prelude::*;
+use remote_juggler_gui::autolock;
+use remote_juggler_gui::error::Error;
+use remote_juggler_gui::secret_service::{self, Index, COLLECTION_PATH, SERVICE_PATH};
+
+const SERVICE_IFACE: &str = "org.freedesktop.Secret.Service";
+const COLLECTION_IFACE: &str = "org.freedesktop.Secret.Collection";
+const ITEM_IFACE: &str = "org.freedesktop.Secret.Item";
+const SESSION_IFACE: &str = "org.freedesktop.Secret.Session";
+const PROMPT_IFACE: &str = "org.freedesktop.Secret.Prompt";
+
+const LABEL_PROPERTY: &str = "org.freedesktop.Secret.Item.Label";
+const ATTRIBUTES_PROPERTY: &str = "org.freedesktop.Secret.Item.Attributes";
+
+const IS_LOCKED: &str = "org.freedesktop.Secret.Error.IsLocked";
+const NO_SESSION: &str = "org.freedesktop.Secret.Error.NoSession";
+const NO_SUCH_OBJECT: &str = "org.freedesktop.Secret.Error.NoSuchObject";
+const NOT_SUPPORTED: &str = "org.freedesktop.DBus.Error.NotSupported";
+const FAILED: &str = "org.freedesktop.DBus.Error.Failed";
+
+/// The interfaces of the Secret Service API, version 0.2
+const INTERFACES: &str = r#"<node>
+  <interface name="org.freedesktop.Secret.Service">
+    <method name="OpenSession">
+      <arg name="algorithm" type="s" direction="in"/>
+      <arg name="input" type="v" direction="in"/>
+      <arg name="output" type="v" direction="out"/>
+      <arg name="result" type="o" direction="out"/>
+    </method>
+    <method name="CreateCollection">
+      <arg name="properties" type="a{sv}" direction="in"/>
+      <arg name="alias" type="s" direction="in"/>
+      <arg name="collection" type="o" direction="out"/>
+      <arg name="prompt" type="o" direction="out"/>
+    </method>
+    <method name="SearchItems">
+      <arg name="attributes" type="a{ss}" direction="in"/>
+      <arg name="unlocked" type="ao" direction="out"/>
+      <arg name="locked" type="ao" direction="out"/>
+    </method>
+    <method name="Unlock">
+      <arg name="objects" type="ao" direction="in"/>
+      <arg name="unlocked" type="ao" direction="out"/>
+      <arg name="prompt" type="o" direction="out"/>
+    </method>
+    <method name="Lock">
+      <arg name="objects" type="ao" direction="in"/>
+      <arg name="locked" type="ao" direction="out"/>
+      <arg name="Prompt" type="o" direction="out"/>
+    </method>
+    <method name="GetSecrets">
+      <arg name="items" type="ao" direction="in"/>
+      <arg name="session" type="o" direction="in"/>
+      <arg name="secrets" type="a{o(oayays)}" direction="out"/>
+    </method>
+    <method name="ReadAlias">
+      <arg name="name" type="s" direction="in"/>
+      <arg name="collection" type="o" direction="out"/>
+    </method>
+    <method name="SetAlias">
+      <arg name="name" type="s" direction="in"/>
+      <arg name="collection" type="o" direction="in"/>
+    </method>
+    <signal name="CollectionCreated"><arg name="collection" type="o"/></signal>
+    <signal name="CollectionDeleted"><arg name="collection" type="o"/></signal>
+    <signal name="CollectionChanged"><arg name="collection" type="o"/></signal>
+    <property name="Collections" type="ao" access="read"/>
+  </interface>
+  <interface name="org.freedesktop.Secret.Collection">
+    <method name="Delete">
+      <arg name="prompt" type="o" direction="out"/>
+    </method>
+    <method name="SearchItems">
+      <arg name="attributes" type="a{ss}" direction="in"/>
+      <arg name="results" type="ao" direction="out"/>
+    </method>
+    <method name="CreateItem">
+      <arg name="properties" type="a{sv}" direction="in"/>
+      <arg name="secret" type="(oayays)" direction="in"/>
+      <arg name="replace" type="b" direction="in"/>
+      <arg name="item" type="o" direction="out"/>
+      <arg name="prompt" type="o" direction="out"/>
+    </method>
+    <signal name="ItemCreated"><arg name="item" type="o"/></signal>
+    <signal name="ItemDeleted"><arg name="item" type="o"/></signal>
+    <signal name="ItemChanged"><arg name="item" type="o"/></signal>
+    <property name="Items" type="ao" access="read"/>
+    <property name="Label" type="s" access="read"/>
+    <property name="Locked" type="b" access="read"/>
+    <property name="Created" type="t" access="read"/>
+    <property name="Modified" type="t" access="read"/>
+  </interface>
+  <interface name="org.freedesktop.Secret.Item">
+    <method name="Delete">
+      <arg name="Prompt" type="o" direction="out"/>
+    </method>
+    <method name="GetSecret">
+      <arg name="session" type="o" direction="in"/>
+      <arg name="secret" type="(oayays)" direction="out"/>
+    </method>
+    <method name="SetSecret">
+      <arg name="secret" type="(oayays)" direction="in"/>
+    </method>
+    <property name="Locked" type="b" access="read"/>
+    <property name="Attributes" type="a{ss}" access="readwrite"/>
+    <property name="Label" type="s" access="readwrite"/>
+    <property name="Created" type="t" access="read"/>
+    <property name="Modified" type="t" access="read"/>
+  </interface>
+  <interface name="org.freedesktop.Secret.Session">
+    <method name="Close"/>
+  </interface>
+  <interface name="org.freedesktop.Secret.Prompt">
+    <method name="Prompt">
+      <arg name="window-id" type="s" direction="in"/>
+    </method>
+    <method name="Dismiss"/>
+    <signal name="Completed">
+      <arg name="dismissed" type="b"/>
+      <arg name="result" type="v"/>
+    </signal>
+  </interface>
+</node>"#;
+
+/// A secret on the wire: session, parameters, value and content type
+type WireSecret = (ObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// A path generated here or checked by [`secret_service::item_id`]
+fn object_path(path: &str) -> ObjectPath {
+    ObjectPath::try_from(path).expect("valid object path")
+}
+
+/// The "no prompt needed" path
+fn no_prompt() -> ObjectPath {
+    object_path("/")
+}
+
+fn return_error(invocation: gio::DBusMethodInvocation, e: &Error) {
+    let name = match e {
+        Error::KeystoreLocked => IS_LOCKED,
+        _ => FAILED,
+    };
+    invocation.return_dbus_error(name, &e.to_string());
+}
+
+/// Run `work` on a worker thread and answer the call with `done`
+fn spawn_call<T: Send + 'static>(
+    invocation: gio::DBusMethodInvocation,
+    work: impl FnOnce() -> Result<T, Error> + Send + 'static,
+    done: impl FnOnce(T, gio::DBusMethodInvocation) + 'static,
+) {
+    glib::spawn_future_local(async move {
+        let result = gio::spawn_blocking(work)
+            .await
+            .unwrap_or_else(|_| Err(Error::Io(std::io::Error::other("Background task panicked"))));
+        match result {
+            Ok(value) => done(value, invocation),
+            Err(e) => return_error(invocation, &e),
+        }
+    });
+}
+
+/// Serves the key store as org.freedesktop.secrets until dropped
+pub struct SecretServiceBus {
+    owner: Option<gio::OwnerId>,
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    interfaces: gio::DBusNodeInfo,
+    connection: RefCell<Option<gio::DBusConnection>>,
+    /// Service, collection and alias objects
+    registrations: RefCell<Vec<gio::RegistrationId>>,
+    /// Watches for clients leaving the bus, to close their sessions
+    subscription: RefCell<Option<gio::SignalSubscriptionId>>,
+    items: RefCell<BTreeMap<String, gio::RegistrationId>>,
+    /// Open sessions by object path, with the client that opened each
+    sessions: RefCell<HashMap<String, (String, gio::RegistrationId)>>,
+    /// Unlock prompts by object path, with the objects to report unlocked
+    prompts: RefCell<HashMap<String, (gio::RegistrationId, Vec<ObjectPath>)>>,
+    next_object: Cell<u64>,
+    /// None until the bus answers whether the name is ours
+    serving: Cell<Option<bool>>,
+    present: Box<dyn Fn()>,
+    changed: Box<dyn Fn()>,
+}
+
+impl SecretServiceBus {
+    /// Claim the bus name. `present` shows the window for an unlock prompt;
+    /// `changed` runs when the key store was locked from the bus or the
+    /// name was claimed or lost
+    pub fn start(present: impl Fn() + 'static, changed: impl Fn() + 'static) -> Self {
+        let inner = Rc::new(Inner {
+            interfaces: gio::DBusNodeInfo::for_xml(INTERFACES).expect("valid introspection XML"),
+            connection: RefCell::default(),
+            registrations: RefCell::default(),
+            subscription: RefCell::default(),
+            items: RefCell::default(),
+            sessions: RefCell::default(),
+            prompts: RefCell::default(),
+            next_object: Cell::new(0),
+            serving: Cell::new(None),
+            present: Box::new(present),
+            changed: Box::new(changed),
+        });
+        let on_bus = Rc::downgrade(&inner);
+        let on_acquired = Rc::downgrade(&inner);
+        let on_lost = Rc::downgrade(&inner);
+        let owner = gio::bus_own_name(
+            gio::BusType::Session,
+            secret_service::BUS_NAME,
+            gio::BusNameOwnerFlags::DO_NOT_QUEUE,
+            move |connection, _| {
+                if let Some(inner) = on_bus.upgrade() {
+                    inner.export(&connection);
+                }
+            },
+            move |_, name| {
+                if let Some(inner) = on_acquired.upgrade() {
+                    tracing::info!("Serving {} from the key store", name);
+                    inner.serving.set(Some(true));
+                    (inner.changed)();
+                }
+            },
+            move |_, name| {
+                if let Some(inner) = on_lost.upgrade() {
+                    tracing::warn!("{} is owned by another service", name);
+                    inner.unexport();
+                    inner.serving.set(Some(false));
+                    (inner.changed)();
+                }
+            },
+        );
+        Self {
+            owner: Some(owner),
+            inner,
+        }
+    }
+
+    /// Whether the bus name is ours; None while that isn't known yet
+    pub fn is_serving(&self) -> Option<bool> {
+        self.inner.serving.get()
+    }
+}
+
+impl Drop for SecretServiceBus {
+    fn drop(&mut self) {
+        self.inner.unexport();
+        if let Some(owner) = self.owner.take() {
+            gio::bus_unown_name(owner);
+        }
+    }
+}
+
+impl Inner {
+    fn export(self: &Rc<Self>, connection: &gio::DBusConnection) {
+        *self.connection.borrow_mut() = Some(connection.clone());
+        let exported = [
+            (SERVICE_PATH, SERVICE_IFACE),
+            (COLLECTION_PATH, COLLECTION_IFACE),
+            (secret_service::DEFAULT_ALIAS_PATH, COLLECTION_IFACE),
+        ]
+        .into_iter()
+        .filter_map(|(path, interface)| self.register(path, interface));
+        self.registrations.borrow_mut().extend(exported);
+
+        let weak = Rc::downgrade(self);
+        let subscription = connection.signal_subscribe(
+            Some("org.freedesktop.DBus"),
+            Some("org.freedesktop.DBus"),
+            Some("NameOwnerChanged"),
+            Some("/org/freedesktop/DBus"),
+            None,
+            gio::DBusSignalFlags::NONE,
+            move |_, _, _, _, _, params| {
+                let (Some(inner), Some((name, _, new_owner))) =
+                    (weak.upgrade(), params.get::<(String, String, String)>())
+                else {
+                    return;
+                };
+                if new_owner.is_empty() {
+                    inner.close_sessions_of(&name);
+                }
+            },
+        );
+        *self.subscription.borrow_mut() = Some(subscription);
+
+        match Index::load() {
+            Ok(index) => {
+                for id in index.items.keys() {
+                    self.register_item(id);
+                }
+            }
+            Err(e) => tracing::warn!("Not exporting Secret Service items: {}", e),
+        }
+    }
+
+    fn unexport(&self) {
+        let Some(connection) = self.connection.borrow_mut().take() else {
+            return;
+        };
+        let registrations = self
+            .registrations
+            .take()
+            .into_iter()
+            .chain(self.items.take().into_values())
+            .chain(self.sessions.take().into_values().map(|(_, id)| id))
+            .chain(self.prompts.take().into_values().map(|(id, _)| id));
+        for id in registrations {
+            let _ = connection.unregister_object(id);
+        }
+        if let Some(subscription) = self.subscription.take() {
+            connection.signal_unsubscribe(subscription);
+        }
+    }
+
+    fn register(self: &Rc<Self>, path: &str, interface: &str) -> Option<gio::RegistrationId> {
+        let connection = self.connection.borrow().clone()?;
+        let info = self.interfaces.lookup_interface(interface)?;
+        let calls = Rc::downgrade(self);
+        let reads = Rc::downgrade(self);
+        let writes = Rc::downgrade(self);
+        connection
+            .register_object(path, &info)
+            .method_call(
+                move |_, sender, path, interface, method, params, invocation| {
+                    if let Some(inner) = calls.upgrade() {
+                        inner.call(sender, path, interface, method, &params, invocation);
+                    }
+                },
+            )
+            .property(move |_, _, path, interface, property| {
+                reads.upgrade().map_or_else(
+                    || false.to_variant(),
+                    |inner| inner.property(path, interface, property),
+                )
+            })
+            .set_property(move |_, _, path, interface, property, value| {
+                writes
+                    .upgrade()
+                    .is_some_and(|inner| inner.set_property(path, interface, property, &value))
+            })
+            .build()
+            .map_err(|e| tracing::error!("Exporting {} failed: {}", path, e))
+            .ok()
+    }
+
+    fn register_item(self: &Rc<Self>, id: &str) {
+        let path = secret_service::item_path(id);
+        if secret_service::item_id(&path) != Some(id) || self.items.borrow().contains_key(id) {
+            return;
+        }
+        if let Some(registration) = self.register(&path, ITEM_IFACE) {
+            self.items.borrow_mut().insert(id.to_string(), registration);
+        }
+    }
+
+    /// A new object path under the service, e.g. for a session
+    fn new_path(&self, kind: &str) -> String {
+        let n = self.next_object.get() + 1;
+        self.next_object.set(n);
+        format!("{}/{}/{}", SERVICE_PATH, kind, n)
+    }
+
+    fn emit(&self, path: &str, interface: &str, signal: &str, params: &Variant) {
+        let Some(connection) = self.connection.borrow().clone() else {
+            return;
+        };
+        if let Err(e) = connection.emit_signal(None, path, interface, signal, Some(params)) {
+            tracing::debug!("Emitting {} failed: {}", signal, e);
+        }
+    }
+
+    fn item_signal(&self, signal: &str, id: &str) {
+        let item = (object_path(&secret_service::item_path(id)),).to_variant();
+        for collection in [COLLECTION_PATH, secret_service::DEFAULT_ALIAS_PATH] {
+            self.emit(collection, COLLECTION_IFACE, signal, &item);
+        }
+    }
+
+    /// Exported items among `attributes`' matches
+    fn search(&self, attributes: &BTreeMap<String, String>) -> Vec<ObjectPath> {
+        let items = self.items.borrow();
+        Index::load()
+            .unwrap_or_default()
+            .search(attributes)
+            .into_iter()
+            .filter(|id| items.contains_key(id))
+            .map(|id| object_path(&secret_service::item_path(&id)))
+            .collect()
+    }
+
+    /// The exported item an object path names
+    fn exported_item(&self, path: &str) -> Option<String> {
+        secret_service::item_id(path)
+            .filter(|id| self.items.borrow().contains_key(*id))
+            .map(str::to_string)
+    }
+
+    /// Whether `session` is open and belongs to `sender`
+    fn owns_session(&self, sender: &str, session: &str) -> bool {
+        self.sessions
+            .borrow()
+            .get(session)
+            .is_some_and(|(owner, _)| owner == sender)
+    }
+
+    fn close_session(&self, path: &str) {
+        let Some((_, registration)) = self.sessions.borrow_mut().remove(path) else {
+            return;
+        };
+        if let Some(connection) = self.connection.borrow().as_ref() {
+            let _ = connection.unregister_object(registration);
+        }
+    }
+
+    fn close_sessions_of(&self, sender: &str) {
+        let paths: Vec<String> = self
+            .sessions
+            .borrow()
+            .iter()
+            .filter(|(_, (owner, _))| owner == sender)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in paths {
+            self.close_session(&path);
+        }
+    }
+
+    fn call(
+        self: &Rc<Self>,
+        sender: &str,
+        path: &str,
+        interface: &str,
+        method: &str,
+        params: &Variant,
+        invocation: gio::DBusMethodInvocation,
+    ) {
+        match (interface, method) {
+            (SERVICE_IFACE, "OpenSession") => self.open_session(sender, params, invocation),
+            (SERVICE_IFACE, "CreateCollection") => {
+                // Everything is stored in the one collection
+                let reply = (object_path(COLLECTION_PATH), no_prompt());
+                invocation.return_value(Some(&reply.to_variant()));
+            }
+            (SERVICE_IFACE, "SearchItems") => {
+                let attributes = params.child_value(0).get().unwrap_or_default();
+                let found = self.search(&attributes);
+                let reply = if autolock::is_locked() {
+                    (Vec::new(), found)
+                } else {
+                    (found, Vec::new())
+                };
+                invocation.return_value(Some(&reply.to_variant()));
+            }
+            (SERVICE_IFACE, "Unlock") => self.unlock(params, invocation),
+            (SERVICE_IFACE, "Lock") => {
+                autolock::lock();
+                (self.changed)();
+                let objects = self.ours(params);
+                invocation.return_value(Some(&(objects, no_prompt()).to_variant()));
+            }
+            (SERVICE_IFACE, "GetSecrets") => self.get_secrets(sender, params, invocation),
+            (SERVICE_IFACE, "ReadAlias") => {
+                let name = params.child_value(0).get::<String>().unwrap_or_default();
+                let path = if name == "default" {
+                    object_path(COLLECTION_PATH)
+                } else {
+                    no_prompt()
+                };
+                invocation.return_value(Some(&(path,).to_variant()));
+            }
+            (SERVICE_IFACE, "SetAlias") => {
+                let collection = params.child_value(1).get::<ObjectPath>();
+                if collection.is_some_and(|c| secret_service::is_collection(&c)) {
+                    invocation.return_value(None);
+                } else {
+                    invocation.return_dbus_error(NOT_SUPPORTED, "Only the key store collection");
+                }
+            }
+            (COLLECTION_IFACE, "Delete") => invocation
+                .return_dbus_error(NOT_SUPPORTED, "The key store collection can't be deleted"),
+            (COLLECTION_IFACE, "SearchItems") => {
+                let attributes = params.child_value(0).get().unwrap_or_default();
+                invocation.return_value(Some(&(self.search(&attributes),).to_variant()));
+            }
+            (COLLECTION_IFACE, "CreateItem") => self.create_item(sender, params, invocation),
+            (ITEM_IFACE, "Delete") => self.delete_item(path, invocation),
+            (ITEM_IFACE, "GetSecret") => self.get_secret(sender, path, params, invocation),
+            (ITEM_IFACE, "SetSecret") => self.set_secret(sender, path, params, invocation),
+            (SESSION_IFACE, "Close") => {
+                self.close_session(path);
+                invocation.return_value(None);
+            }
+            (PROMPT_IFACE, "Prompt") => {
+                invocation.return_value(None);
+                self.prompt(path);
+            }
+            (PROMPT_IFACE, "Dismiss") => {
+                invocation.return_value(None);
+                self.complete_prompt(path, true);
+            }
+            _ => invocation.return_dbus_error(
+                "org.freedesktop.DBus.Error.UnknownMethod",
+                &format!("No method {}.{}", interface, method),
+            ),
+        }
+    }
+
+    fn open_session(
+        self: &Rc<Self>,
+        sender: &str,
+        params: &Variant,
+        invocation: gio::DBusMethodInvocation,
+    ) {
+        let algorithm = params.child_value(0).get::<String>().unwrap_or_default();
+        if algorithm != "plain" {
+            invocation.return_dbus_error(
+                NOT_SUPPORTED,
+                &format!("Algorithm {} isn't supported; use plain", algorithm),
+            );
+            return;
+        }
+        let path = self.new_path("session");
+        let Some(registration) = self.register(&path, SESSION_IFACE) else {
+            invocation.return_dbus_error(FAILED, "Couldn't open a session");
+            return;
+        };
+        self.sessions
+            .borrow_mut()
+            .insert(path.clone(), (sender.to_string(), registration));
+        let reply = ("".to_variant().to_variant(), object_path(&path));
+        invocation.return_value(Some(&reply.to_variant()));
+    }
+
+    /// The objects in an `ao` argument that are the collection or an item
+    fn ours(&self, params: &Variant) -> Vec<ObjectPath> {
+        params
+            .child_value(0)
+            .get::<Vec<ObjectPath>>()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|p| secret_service::is_collection(p) || self.exported_item(p).is_some())
+            .collect()
+    }
+
+    /// Unlocked objects answer at once; otherwise a prompt shows the window
+    /// and completes once the key store is unlocked there
+    fn unlock(self: &Rc<Self>, params: &Variant, invocation: gio::DBusMethodInvocation) {
+        let objects = self.ours(params);
+        if !autolock::is_locked() {
+            invocation.return_value(Some(&(objects, no_prompt()).to_variant()));
+            return;
+        }
+        let path = self.new_path("prompt");
+        let Some(registration) = self.register(&path, PROMPT_IFACE) else {
+            invocation.return_dbus_error(FAILED, "Couldn't create an unlock prompt");
+            return;
+        };
+        self.prompts
+            .borrow_mut()
+            .insert(path.clone(), (registration, objects));
+        let reply = (Vec::<ObjectPath>::new(), object_path(&path));
+        invocation.return_value(Some(&reply.to_variant()));
+    }
+
+    fn prompt(self: &Rc<Self>, path: &str) {
+        if !self.prompts.borrow().contains_key(path) {
+            return;
+        }
+        (self.present)();
+        let weak = Rc::downgrade(self);
+        let path = path.to_string();
+        glib::timeout_add_seconds_local(1, move || {
+            let Some(inner) = weak.upgrade() else {
+                return glib::ControlFlow::Break;
+            };
+            if !inner.prompts.borrow().contains_key(&path) {
+                return glib::ControlFlow::Break;
+            }
+            if autolock::is_locked() {
+                return glib::ControlFlow::Continue;
+            }
+            inner.complete_prompt(&path, false);
+            glib::ControlFlow::Break
+        });
+    }
+
+    fn complete_prompt(&self, path: &str, dismissed: bool) {
+        let Some((registration, objects)) = self.prompts.borrow_mut().remove(path) else {
+            return;
+        };
+        let objects = if dismissed { Vec::new() } else { objects };
+        let params = (dismissed, objects.to_variant()).to_variant();
+        self.emit(path, PROMPT_IFACE, "Completed", &params);
+        if let Some(connection) = self.connection.borrow().as_ref() {
+            let _ = connection.unregister_object(registration);
+        }
+    }
+
+    /// The checks before a secret is read or written
+    fn refuse(&self, sender: &str, session: &str) -> Option<(&'static str, &'static str)> {
+        if !self.owns_session(sender, session) {
+            Some((NO_SESSION, "Open a session first"))
+        } else if autolock::is_locked() {
+            Some((IS_LOCKED, "The key store is locked"))
+        } else {
+            None
+        }
+    }
+
+    fn get_secrets(&self, sender: &str, params: &Variant, invocation: gio::DBusMethodInvocation) {
+        let session = params.child_value(1).get::<ObjectPath>();
+        let Some(session) = session else {
+            invocation.return_dbus_error(NO_SESSION, "Open a session first");
+            return;
+        };
+        if let Some((name, message)) = self.refuse(sender, &session) {
+            invocation.return_dbus_error(name, message);
+            return;
+        }
+        let ids: Vec<String> = params
+            .child_value(0)
+            .get::<Vec<ObjectPath>>()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|path| self.exported_item(path))
+            .collect();
+        spawn_call(
+            invocation,
+            move || {
+                let mut secrets = HashMap::new();
+                for id in ids {
+                    if let Some((value, content_type)) = secret_service::get_secret(&id)? {
+                        let secret: WireSecret = (session.clone(), Vec::new(), value, content_type);
+                        secrets.insert(object_path(&secret_service::item_path(&id)), secret);
+                    }
+                }
+                Ok(secrets)
+            },
+            |secrets, invocation| invocation.return_value(Some(&(secrets,).to_variant())),
+        );
+    }
+
+    fn create_item(
+        self: &Rc<Self>,
+        sender: &str,
+        params: &Variant,
+        invocation: gio::DBusMethodInvocation,
+    ) {
+        let properties = VariantDict::new(Some(&params.child_value(0)));
+        let label: String = properties
+            .lookup(LABEL_PROPERTY)
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        let attributes: BTreeMap<String, String> = properties
+            .lookup(ATTRIBUTES_PROPERTY)
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        let Some((session, _, value, content_type)) = params.child_value(1).get::<WireSecret>()
+        else {
+            invocation.return_dbus_error(FAILED, "Malformed secret");
+            return;
+        };
+        if let Some((name, message)) = self.refuse(sender, &session) {
+            invocation.return_dbus_error(name, message);
+            return;
+        }
+        let replace = params.child_value(2).get::<bool>().unwrap_or(false);
+        let weak = Rc::downgrade(self);
+        spawn_call(
+            invocation,
+            move || secret_service::create_item(&label, attributes, &value, &content_type, replace),
+            move |(id, new), invocation| {
+                if let Some(inner) = weak.upgrade() {
+                    inner.register_item(&id);
+                    inner.item_signal(if new { "ItemCreated" } else { "ItemChanged" }, &id);
+                }
+                let reply = (object_path(&secret_service::item_path(&id)), no_prompt());
+                invocation.return_value(Some(&reply.to_variant()));
+            },
+        );
+    }
+
+    fn delete_item(self: &Rc<Self>, path: &str, invocation: gio::DBusMethodInvocation) {
+        let Some(id) = self.exported_item(path) else {
+            invocation.return_dbus_error(NO_SUCH_OBJECT, "No such item");
+            return;
+        };
+        let weak = Rc::downgrade(self);
+        spawn_call(
+            invocation,
+            {
+                let id = id.clone();
+                move || secret_service::delete_item(&id)
+            },
+            move |_, invocation| {
+                if let Some(inner) = weak.upgrade() {
+                    let registration = inner.items.borrow_mut().remove(&id);
+                    if let (Some(registration), Some(connection)) =
+                        (registration, inner.connection.borrow().as_ref())
+                    {
+                        let _ = connection.unregister_object(registration);
+                    }
+                    inner.item_signal("ItemDeleted", &id);
+                }
+                invocation.return_value(Some(&(no_prompt(),).to_variant()));
+            },
+        );
+    }
+
+    fn get_secret(
+        &self,
+        sender: &str,
+        path: &str,
+        params: &Variant,
+        invocation: gio::DBusMethodInvocation,
+    ) {
+        let (Some(id), Some(session)) = (
+            self.exported_item(path),
+            params.child_value(0).get::<ObjectPath>(),
+        ) else {
+            invocation.return_dbus_error(NO_SUCH_OBJECT, "No such item");
+            return;
+        };
+        if let Some((name, message)) = self.refuse(sender, &session) {
+            invocation.return_dbus_error(name, message);
+            return;
+        }
+        spawn_call(
+            invocation,
+            move || secret_service::get_secret(&id),
+            move |secret, invocation| match secret {
+                Some((value, content_type)) => {
+                    let secret: WireSecret = (session, Vec::new(), value, content_type);
+                    invocation.return_value(Some(&(secret,).to_variant()));
+                }
+                None => invocation.return_dbus_error(NO_SUCH_OBJECT, "No such item"),
+            },
+        );
+    }
+
+    fn set_secret(
+        self: &Rc<Self>,
+        sender: &str,
+        path: &str,
+        params: &Variant,
+        invocation: gio::DBusMethodInvocation,
+    ) {
+        let (Some(id), Some((session, _, value, content_type))) = (
+            self.exported_item(path),
+            params.child_value(0).get::<WireSecret>(),
+        ) else {
+            invocation.return_dbus_error(NO_SUCH_OBJECT, "No such item");
+            return;
+        };
+        if let Some((name, message)) = self.refuse(sender, &session) {
+            invocation.return_dbus_error(name, message);
+            return;
+        }
+        let weak = Rc::downgrade(self);
+        spawn_call(
+            invocation,
+            {
+                let id = id.clone();
+                move || secret_service::set_secret(&id, &value, &content_type)
+            },
+            move |_, invocation| {
+                if let Some(inner) = weak.upgrade() {
+                    inner.item_signal("ItemChanged", &id);
+                }
+                invocation.return_value(None);
+            },
+        );
+    }
+
+    fn property(&self, path: &str, interface: &str, property: &str) -> Variant {
+        let locked = autolock::is_locked();
+        if interface == SERVICE_IFACE {
+            return vec![object_path(COLLECTION_PATH)].to_variant();
+        }
+        let index = Index::load().unwrap_or_default();
+        if interface == COLLECTION_IFACE {
+            let items = self.items.borrow();
+            let exported = || index.items.iter().filter(|(id, _)| items.contains_key(*id));
+            return match property {
+                "Items" => items
+                    .keys()
+                    .map(|id| object_path(&secret_service::item_path(id)))
+                    .collect::<Vec<_>>()
+                    .to_variant(),
+                "Label" => secret_service::COLLECTION_LABEL.to_variant(),
+                "Locked" => locked.to_variant(),
+                "Created" => exported()
+                    .map(|(_, item)| item.created)
+                    .min()
+                    .unwrap_or(0)
+                    .to_variant(),
+                _ => exported()
+                    .map(|(_, item)| item.modified)
+                    .max()
+                    .unwrap_or(0)
+                    .to_variant(),
+            };
+        }
+        let item = secret_service::item_id(path)
+            .and_then(|id| index.items.get(id))
+            .cloned()
+            .unwrap_or_default();
+        match property {
+            "Locked" => locked.to_variant(),
+            "Attributes" => item.attributes.to_variant(),
+            "Label" => item.label.to_variant(),
+            "Created" => item.created.to_variant(),
+            _ => item.modified.to_variant(),
+        }
+    }
+
+    fn set_property(&self, path: &str, interface: &str, property: &str, value: &Variant) -> bool {
+        let Some(id) = self.exported_item(path).filter(|_| interface == ITEM_IFACE) else {
+            return false;
+        };
+        let updated = match property {
+            "Label" => value
+                .get::<String>()
+                .map(|label| secret_service::update_item(&id, |item| item.label = label)),
+            "Attributes" => value.get::<BTreeMap<String, String>>().map(|attributes| {
+                secret_service::update_item(&id, |item| item.attributes = attributes)
+            }),
+            _ => None,
+        };
+        match updated {
+            Some(Ok(true)) => {
+                self.item_signal("ItemChanged", &id);
+                true
+            }
+            Some(Err(e)) => {
+                tracing::warn!("Updating Secret Service item {} failed: {}", id, e);
+                false
+            }
+            _ => false,
+        }
+    }
+}
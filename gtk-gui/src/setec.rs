@@ -0,0 +1,376 @@
+//! Setec client over the tailnet
+//!
+//! Talks to a [Setec](https://github.com/tailscale/setec) server directly
+//! over HTTP, mounted into the key namespace as `Setec/{name}`. Setec
+//! authorizes callers by their Tailscale identity, so there are no
+//! credentials to manage here: the machine just has to be on the tailnet.
+//!
+//! Fetched values are cached in memory. After the TTL a read asks the server
+//! for changes since the cached version; if the server can't be reached the
+//! cached value keeps being served, so a flaky tailnet doesn't break a
+//! session that already fetched e.g. the GitHub App private key.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::backend::SecretBackend;
+use crate::error::Error;
+use crate::http;
+use crate::metrics;
+use crate::tailscale;
+
+/// Namespace prefix for Setec secrets
+pub const NAMESPACE: &str = "Setec";
+
+/// Environment variable holding the server URL, as used by the setec CLI
+pub const SERVER_ENV: &str = "SETEC_SERVER";
+
+/// How long a fetched value is served without asking the server
+pub const CACHE_TTL: Duration = Duration::from_secs(300);
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Setec rejects requests without this header (CSRF protection)
+const NO_BROWSERS_HEADER: (&str, &str) = ("Sec-X-Tailscale-No-Browsers", "setec");
+
+/// Server URL from the environment, without a trailing slash
+pub fn server_from_env() -> Option<String> {
+    std::env::var(SERVER_ENV)
+        .ok()
+        .map(|s| s.trim().trim_end_matches('/').to_string())
+        .filter(|s| !s.is_empty())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SecretInfo {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SecretValue {
+    value: String,
+    version: u64,
+}
+
+/// Decode a base64 secret value
+pub fn decode_value(data: &str) -> Result<String, Error> {
+    let invalid = |message: String| Error::InvalidOutput {
+        program: "setec",
+        message,
+    };
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| invalid(e.to_string()))?;
+    String::from_utf8(bytes).map_err(|_| invalid("secret is not UTF-8 text".to_string()))
+}
+
+/// A cached secret value and the version it came from
+#[derive(Debug, Clone)]
+struct CachedSecret {
+    value: String,
+    version: u64,
+    fetched_at: Instant,
+}
+
+/// TTL cache of fetched secret values, keyed by server and name
+#[derive(Debug, Default)]
+pub struct SecretCache {
+    entries: Mutex<HashMap<(String, String), CachedSecret>>,
+}
+
+impl SecretCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A value that is still within its TTL
+    pub fn fresh(&self, server: &str, name: &str) -> Option<String> {
+        self.fresh_at(server, name, Instant::now())
+    }
+
+    fn fresh_at(&self, server: &str, name: &str, now: Instant) -> Option<String> {
+        let entries = self.entries.lock().ok()?;
+        let cached = entries.get(&(server.to_string(), name.to_string()))?;
+        (now.duration_since(cached.fetched_at) < CACHE_TTL).then(|| cached.value.clone())
+    }
+
+    /// The cached value and version regardless of age
+    pub fn stale(&self, server: &str, name: &str) -> Option<(String, u64)> {
+        let entries = self.entries.lock().ok()?;
+        entries
+            .get(&(server.to_string(), name.to_string()))
+            .map(|c| (c.value.clone(), c.version))
+    }
+
+    pub fn insert(&self, server: &str, name: &str, value: &str, version: u64) {
+        self.insert_at(server, name, value, version, Instant::now());
+    }
+
+    fn insert_at(&self, server: &str, name: &str, value: &str, version: u64, now: Instant) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(
+                (server.to_string(), name.to_string()),
+                CachedSecret {
+                    value: value.to_string(),
+                    version,
+                    fetched_at: now,
+                },
+            );
+        }
+    }
+
+    /// Drop one entry, e.g. after writing a new version
+    pub fn remove(&self, server: &str, name: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(&(server.to_string(), name.to_string()));
+        }
+    }
+}
+
+/// Process-wide cache shared by every `SetecBackend`
+pub fn cache() -> &'static SecretCache {
+    static CACHE: OnceLock<SecretCache> = OnceLock::new();
+    CACHE.get_or_init(SecretCache::new)
+}
+
+/// Whether the Setec server answers over the tailnet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reachability {
+    /// No server URL is configured
+    NotConfigured,
+    Reachable {
+        latency: Duration,
+    },
+    Unreachable(String),
+}
+
+impl Reachability {
+    pub fn display_text(&self) -> String {
+        match self {
+            Reachability::NotConfigured => format!("Set {} to your Setec server URL", SERVER_ENV),
+            Reachability::Reachable { latency } => {
+                format!("Reachable over the tailnet ({} ms)", latency.as_millis())
+            }
+            Reachability::Unreachable(reason) => format!("Unreachable: {}", reason),
+        }
+    }
+}
+
+/// A Setec server
+#[derive(Debug, Clone)]
+pub struct SetecBackend {
+    server: Option<String>,
+}
+
+impl SetecBackend {
+    pub fn new(server: Option<String>) -> Self {
+        Self { server }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(server_from_env())
+    }
+
+    pub fn server(&self) -> Option<&str> {
+        self.server.as_deref()
+    }
+
+    fn server_or_err(&self) -> Result<&str, Error> {
+        self.server().ok_or_else(|| Error::InvalidOutput {
+            program: "setec",
+            message: format!("{} is not set", SERVER_ENV),
+        })
+    }
+
+    /// POST a JSON request to an API method; None for 304 Not Modified
+    fn call<T: DeserializeOwned>(&self, method: &str, body: &Value) -> Result<Option<T>, Error> {
+        let url = format!("{}/api/{}", self.server_or_err()?, method);
+        let response = http::agent()
+            .post(&url)
+            .set(NO_BROWSERS_HEADER.0, NO_BROWSERS_HEADER.1)
+            .send_json(body)
+            .map_err(http::map_error)?;
+        if response.status() == 304 {
+            return Ok(None);
+        }
+        response
+            .into_json()
+            .map(Some)
+            .map_err(|e| Error::InvalidOutput {
+                program: "setec",
+                message: e.to_string(),
+            })
+    }
+
+    /// Check that the server answers; any HTTP response counts
+    pub fn probe(&self) -> Reachability {
+        let Some(server) = self.server() else {
+            return Reachability::NotConfigured;
+        };
+        let connectivity = tailscale::status();
+        if connectivity.is_offline() {
+            return Reachability::Unreachable(connectivity.display_text());
+        }
+        let started = Instant::now();
+        let result = http::agent()
+            .get(server)
+            .timeout(PROBE_TIMEOUT)
+            .set(NO_BROWSERS_HEADER.0, NO_BROWSERS_HEADER.1)
+            .call();
+        match result {
+            Ok(_) | Err(ureq::Error::Status(..)) => Reachability::Reachable {
+                latency: started.elapsed(),
+            },
+            Err(ureq::Error::Transport(t)) => Reachability::Unreachable(t.to_string()),
+        }
+    }
+
+    fn fetch(&self, server: &str, name: &str) -> Result<String, Error> {
+        let cached = cache().stale(server, name);
+        let request = match &cached {
+            Some((_, version)) => {
+                json!({ "Name": name, "Version": version, "UpdateIfChanged": true })
+            }
+            None => json!({ "Name": name, "Version": 0 }),
+        };
+        match self.call::<SecretValue>("get", &request) {
+            Ok(Some(secret)) => {
+                let value = decode_value(&secret.value)?;
+                cache().insert(server, name, &value, secret.version);
+                Ok(value)
+            }
+            // Unchanged since the cached version; restart its TTL
+            Ok(None) => {
+                let (value, version) = cached.ok_or_else(|| Error::InvalidOutput {
+                    program: "setec",
+                    message: "not modified, but nothing cached".to_string(),
+                })?;
+                cache().insert(server, name, &value, version);
+                Ok(value)
+            }
+            Err(Error::Network(reason)) => match cached {
+                Some((value, _)) => {
+                    tracing::warn!("Setec unreachable, serving cached {}: {}", name, reason);
+                    Ok(value)
+                }
+                None => Err(Error::Network(reason)),
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl SecretBackend for SetecBackend {
+    fn namespace(&self) -> &'static str {
+        NAMESPACE
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Setec"
+    }
+
+    fn is_available(&self) -> bool {
+        self.server.is_some()
+    }
+
+    fn needs_tailnet(&self) -> bool {
+        true
+    }
+
+    fn list(&self) -> Result<Vec<String>, Error> {
+        let infos: Vec<SecretInfo> = self.call("list", &json!({}))?.unwrap_or_default();
+        let mut names: Vec<String> = infos.into_iter().map(|i| i.name).collect();
+        names.sort();
+        Ok(names)
+    }
+
+    fn get(&self, name: &str) -> Result<String, Error> {
+        let server = self.server_or_err()?;
+        let cached = cache().fresh(server, name);
+        metrics::record_cache("setec", cached.is_some());
+        if let Some(value) = cached {
+            return Ok(value);
+        }
+        self.fetch(server, name)
+    }
+
+    /// Put a new version and make it the active one
+    fn set(&self, name: &str, value: &str) -> Result<(), Error> {
+        let server = self.server_or_err()?;
+        let encoded = base64::engine::general_purpose::STANDARD.encode(value);
+        let version: u64 = self
+            .call("put", &json!({ "Name": name, "Value": encoded }))?
+            .ok_or_else(|| Error::InvalidOutput {
+                program: "setec",
+                message: "put returned no version".to_string(),
+            })?;
+        self.call::<Value>("activate", &json!({ "Name": name, "Version": version }))?;
+        cache().insert(server, name, value, version);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_value() {
+        assert_eq!(decode_value("aHVudGVyMg==").unwrap(), "hunter2");
+        assert!(decode_value("%%%").is_err());
+    }
+
+    #[test]
+    fn test_parse_responses() {
+        let infos: Vec<SecretInfo> = serde_json::from_str(
+            r#"[{"Name": "prod/github-app/key", "Versions": [1, 2], "ActiveVersion": 2}]"#,
+        )
+        .unwrap();
+        assert_eq!(infos[0].name, "prod/github-app/key");
+
+        let secret: SecretValue =
+            serde_json::from_str(r#"{"Value": "aHVudGVyMg==", "Version": 2}"#).unwrap();
+        assert_eq!(secret.version, 2);
+        assert_eq!(decode_value(&secret.value).unwrap(), "hunter2");
+    }
+
+    #[test]
+    fn test_cache_ttl() {
+        let cache = SecretCache::new();
+        let start = Instant::now();
+        cache.insert_at("https://setec", "key", "v1", 1, start);
+        assert_eq!(
+            cache.fresh_at("https://setec", "key", start).as_deref(),
+            Some("v1")
+        );
+        assert_eq!(
+            cache.fresh_at("https://setec", "key", start + CACHE_TTL),
+            None
+        );
+        // Expired entries remain available as a fallback
+        assert_eq!(
+            cache.stale("https://setec", "key"),
+            Some(("v1".to_string(), 1))
+        );
+        assert_eq!(cache.stale("https://other", "key"), None);
+
+        cache.remove("https://setec", "key");
+        assert_eq!(cache.stale("https://setec", "key"), None);
+    }
+
+    #[test]
+    fn test_not_configured() {
+        let backend = SetecBackend::new(None);
+        assert!(!backend.is_available());
+        assert_eq!(backend.probe(), Reachability::NotConfigured);
+        assert!(backend.get("key").is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! Shell prompt segment and directory hooks, for `remote-juggler-shell`
+//!
+//! The prompt segment runs on every prompt, so it never waits on the key
+//! store: the current identity comes straight from config.json, and the
+//! key store's state from a cache in the runtime directory that a
+//! detached `prompt --refresh` renews once it is [`CACHE_TTL_SECS`] old.
+//! The hooks for bash, zsh and fish run `on-cd` when the directory
+//! changes; entering the area of a different directory rule switches to
+//! its identity, the way direnv loads an `.envrc`. Moving around inside
+//! one rule's directory leaves a manual switch alone.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, DirectoryRule};
+use crate::platform;
+
+/// How long a cached key store state is shown before it is checked again
+pub const CACHE_TTL_SECS: u64 = 60;
+
+/// The prompt segment unless `--format` says otherwise
+pub const DEFAULT_FORMAT: &str = "{identity}{locked}";
+
+/// What `keys status` says about the key store
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeystoreState {
+    Unlocked,
+    Locked,
+    Uninitialized,
+    #[default]
+    Unknown,
+}
+
+impl KeystoreState {
+    /// From `keys status` output
+    pub fn parse(output: &str) -> Self {
+        let has = |key: &str, value: &str| {
+            output.lines().any(|line| {
+                line.trim()
+                    .strip_prefix(key)
+                    .is_some_and(|rest| rest.trim_start() == value)
+            })
+        };
+        if has("Auto-Unlock:", "ready") {
+            Self::Unlocked
+        } else if has("Exists:", "yes") {
+            Self::Locked
+        } else {
+            Self::Uninitialized
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Unlocked => "unlocked",
+            Self::Locked => "locked",
+            Self::Uninitialized => "none",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// The key store state as last checked
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptCache {
+    pub keystore: KeystoreState,
+    /// Unix seconds
+    pub checked_at: u64,
+}
+
+impl PromptCache {
+    pub fn path() -> PathBuf {
+        platform::runtime_dir().join("remote-juggler-prompt.json")
+    }
+
+    /// Load the cache; a missing or unreadable one is stale
+    pub fn load() -> Self {
+        platform::load_json_state(Some(Self::path()))
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        platform::save_json_state(Some(Self::path()), self)
+    }
+
+    pub fn is_stale(&self, now: u64) -> bool {
+        self.checked_at + CACHE_TTL_SECS <= now
+    }
+}
+
+/// Fill in a prompt format: `{identity}`, `{provider}`, `{keystore}`
+/// (unlocked, locked, none or unknown) and `{locked}`, which is " [locked]"
+/// while the key store is locked and empty otherwise
+pub fn render(format: &str, config: &Config, keystore: KeystoreState) -> String {
+    let name = &config.state.current_identity;
+    let provider = config
+        .identities
+        .get(name)
+        .map(|identity| identity.provider.as_str())
+        .unwrap_or_default();
+    let locked = if keystore == KeystoreState::Locked {
+        " [locked]"
+    } else {
+        ""
+    };
+    format
+        .replace("{identity}", name)
+        .replace("{provider}", provider)
+        .replace("{keystore}", keystore.as_str())
+        .replace("{locked}", locked)
+}
+
+/// The most specific usable directory rule covering `dir`
+pub fn rule_for<'a>(config: &'a Config, dir: &Path) -> Option<&'a DirectoryRule> {
+    config
+        .settings
+        .directory_rule(dir)
+        .filter(|rule| config.identities.contains_key(&rule.identity))
+}
+
+/// The identity to switch to on moving from `previous` to `dir`: only on
+/// entering a different rule's directory, and only if it isn't current
+pub fn switch_for(config: &Config, previous: Option<&Path>, dir: &Path) -> Option<String> {
+    let rule = rule_for(config, dir)?;
+    if previous.and_then(|previous| rule_for(config, previous)) == Some(rule) {
+        return None;
+    }
+    (rule.identity != config.state.current_identity).then(|| rule.identity.clone())
+}
+
+/// Shells with a hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "bash" => Some(Self::Bash),
+            "zsh" => Some(Self::Zsh),
+            "fish" => Some(Self::Fish),
+            _ => None,
+        }
+    }
+
+    /// The snippet for the shell's rc file to evaluate, calling `program`
+    pub fn hook(self, program: &str) -> String {
+        let program = quote(program);
+        match self {
+            Self::Bash => format!(
+                "_remote_juggler_hook() {{\n\
+                 \x20 if [[ \"$PWD\" != \"$_REMOTE_JUGGLER_DIR\" ]]; then\n\
+                 \x20   {program} on-cd \"$PWD\" \"$_REMOTE_JUGGLER_DIR\"\n\
+                 \x20   _REMOTE_JUGGLER_DIR=\"$PWD\"\n\
+                 \x20 fi\n\
+                 }}\n\
+                 if [[ \";${{PROMPT_COMMAND:-}};\" != *\";_remote_juggler_hook;\"* ]]; then\n\
+                 \x20 PROMPT_COMMAND=\"_remote_juggler_hook${{PROMPT_COMMAND:+;$PROMPT_COMMAND}}\"\n\
+                 fi\n"
+            ),
+            Self::Zsh => format!(
+                "_remote_juggler_hook() {{\n\
+                 \x20 {program} on-cd \"$PWD\" \"$_REMOTE_JUGGLER_DIR\"\n\
+                 \x20 _REMOTE_JUGGLER_DIR=\"$PWD\"\n\
+                 }}\n\
+                 typeset -ag chpwd_functions\n\
+                 if (( ! ${{chpwd_functions[(I)_remote_juggler_hook]}} )); then\n\
+                 \x20 chpwd_functions+=(_remote_juggler_hook)\n\
+                 fi\n\
+                 _remote_juggler_hook\n"
+            ),
+            Self::Fish => format!(
+                "function _remote_juggler_hook --on-variable PWD\n\
+                 \x20   {program} on-cd \"$PWD\" \"$_remote_juggler_dir\"\n\
+                 \x20   set -g _remote_juggler_dir \"$PWD\"\n\
+                 end\n\
+                 _remote_juggler_hook\n"
+            ),
+        }
+    }
+}
+
+/// Single-quote for bash, zsh and fish
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+
+    fn config() -> Config {
+        let rule = |path: &str, identity: &str| DirectoryRule {
+            path: path.to_string(),
+            identity: identity.to_string(),
+        };
+        ConfigBuilder::new()
+            .identity(
+                "work",
+                identity("gitlab", "gitlab-work", "gitlab.com", "Work", "w@acme.dev"),
+            )
+            .identity(
+                "client",
+                identity(
+                    "github",
+                    "github-client",
+                    "github.com",
+                    "Me",
+                    "me@client.dev",
+                ),
+            )
+            .settings(|settings| {
+                settings.directory_rules = vec![
+                    rule("/src/work", "work"),
+                    rule("/src/work/client", "client"),
+                    rule("/src/gone", "removed"),
+                ]
+            })
+            .current("work")
+            .build()
+    }
+
+    #[test]
+    fn test_keystore_state_and_render() {
+        assert_eq!(
+            KeystoreState::parse("Key Store\n  Exists:      yes\n  Auto-Unlock:   ready\n"),
+            KeystoreState::Unlocked
+        );
+        assert_eq!(
+            KeystoreState::parse("  Exists: yes\n  Auto-Unlock: no master password\n"),
+            KeystoreState::Locked
+        );
+        assert_eq!(
+            KeystoreState::parse("  Exists: no\n"),
+            KeystoreState::Uninitialized
+        );
+
+        let config = config();
+        assert_eq!(
+            render(DEFAULT_FORMAT, &config, KeystoreState::Locked),
+            "work [locked]"
+        );
+        assert_eq!(
+            render(
+                "{provider}:{identity} {keystore}",
+                &config,
+                KeystoreState::Unlocked
+            ),
+            "gitlab:work unlocked"
+        );
+
+        let cache = PromptCache {
+            keystore: KeystoreState::Locked,
+            checked_at: 1000,
+        };
+        assert!(!cache.is_stale(1000 + CACHE_TTL_SECS - 1));
+        assert!(cache.is_stale(1000 + CACHE_TTL_SECS));
+        assert!(PromptCache::default().is_stale(1000));
+    }
+
+    #[test]
+    fn test_switch_on_entering_a_rule() {
+        let config = config();
+        let work = Path::new("/src/work/api");
+        let client = Path::new("/src/work/client/site");
+        assert_eq!(rule_for(&config, client).unwrap().identity, "client");
+        assert_eq!(rule_for(&config, Path::new("/src/workshop")), None);
+        assert_eq!(rule_for(&config, Path::new("/src/gone/x")), None);
+
+        assert_eq!(
+            switch_for(&config, Some(work), client).as_deref(),
+            Some("client")
+        );
+        assert_eq!(switch_for(&config, None, client).as_deref(), Some("client"));
+        // Already current, or still inside the same rule's directory
+        assert_eq!(switch_for(&config, None, work), None);
+        assert_eq!(
+            switch_for(&config, Some(Path::new("/src/work/client")), client),
+            None
+        );
+        assert_eq!(switch_for(&config, Some(work), Path::new("/tmp")), None);
+    }
+
+    #[test]
+    fn test_hooks() {
+        let bash = Shell::Bash.hook("/opt/rj/remote-juggler-shell");
+        assert!(bash.contains("'/opt/rj/remote-juggler-shell' on-cd \"$PWD\""));
+        assert!(bash.contains("PROMPT_COMMAND=\"_remote_juggler_hook${PROMPT_COMMAND:+;"));
+        assert!(Shell::parse("zsh")
+            .unwrap()
+            .hook("rj")
+            .contains("chpwd_functions+="));
+        assert!(Shell::Fish.hook("rj").contains("--on-variable PWD"));
+        assert_eq!(quote("it's"), "'it'\\''s'");
+        assert_eq!(Shell::parse("tcsh"), None);
+    }
+}
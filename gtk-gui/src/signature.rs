@@ -0,0 +1,301 @@
+//! HEAD commit signature check
+//!
+//! Runs `git verify-commit` on a repository's HEAD and compares the signer
+//! with the identity detected for the repository's remotes, so a commit made
+//! while the wrong identity was active stands out even when its signature
+//! is valid.
+
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, Identity, SigningFormat};
+use crate::detect;
+use crate::error::{CliError, Error};
+use crate::gpg;
+
+/// What `git log --format=%G?...` says about HEAD
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeadSignature {
+    /// Abbreviated commit hash
+    pub commit: String,
+    /// git's `%G?`: G good, B bad, U unknown validity, X/Y expired
+    /// signature/key, R revoked, E can't check, N unsigned
+    pub status: char,
+    /// `%GS`: the signer's user id, or the SSH principal
+    pub signer: String,
+    /// `%GK`: the key id, or the SSH key fingerprint
+    pub key: String,
+    /// `%GF` and `%GP`: signing key and primary key fingerprints
+    pub fingerprint: String,
+    pub primary_fingerprint: String,
+}
+
+/// Parse the `\x1f`-separated `git log` line from [`head_signature`]
+pub fn parse_head(line: &str) -> Option<HeadSignature> {
+    let fields: Vec<&str> = line.trim_end_matches(['\r', '\n']).split('\x1f').collect();
+    let [commit, status, signer, key, fingerprint, primary] = fields[..] else {
+        return None;
+    };
+    Some(HeadSignature {
+        commit: commit.to_string(),
+        status: status.chars().next().unwrap_or('N'),
+        signer: signer.to_string(),
+        key: key.to_string(),
+        fingerprint: fingerprint.to_string(),
+        primary_fingerprint: primary.to_string(),
+    })
+}
+
+impl HeadSignature {
+    /// Whether the signature was made by `identity`: its GPG key, or its
+    /// email as the signer (SSH principals and gitsign certificates)
+    pub fn signed_by(&self, identity: &Identity) -> bool {
+        if identity.gpg.format == SigningFormat::Gpg {
+            let key_id = gpg::normalize(&identity.gpg.key_id);
+            if !key_id.is_empty() && key_id != "AUTO" {
+                return [&self.key, &self.fingerprint, &self.primary_fingerprint]
+                    .iter()
+                    .any(|id| !id.is_empty() && gpg::normalize(id).ends_with(&key_id));
+            }
+        }
+        let email = identity.email.to_lowercase();
+        !email.is_empty() && self.signer.to_lowercase().contains(&email)
+    }
+}
+
+/// The outcome of a check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// A valid signature from the expected identity
+    Expected,
+    /// A valid signature, but not from the expected identity; names the
+    /// identity that did sign, if it is one of ours
+    WrongIdentity { signer: Option<String> },
+    /// A valid signature, but no identity is detected for the repository
+    NoExpectation,
+    /// HEAD isn't signed
+    Unsigned,
+    /// git verify-commit rejected the signature
+    Invalid(String),
+}
+
+/// A repository's HEAD signature, checked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub head: HeadSignature,
+    /// The identity detected for the repository
+    pub expected: Option<String>,
+    pub verdict: Verdict,
+}
+
+impl Report {
+    /// Whether the commit needs attention
+    pub fn is_problem(&self) -> bool {
+        !matches!(self.verdict, Verdict::Expected | Verdict::NoExpectation)
+    }
+
+    pub fn display_text(&self) -> String {
+        let signer = if self.head.signer.is_empty() {
+            self.head.key.clone()
+        } else {
+            self.head.signer.clone()
+        };
+        let expected = self.expected.as_deref().unwrap_or_default();
+        let text = match &self.verdict {
+            Verdict::Expected => format!("Signed by {} ({})", expected, signer),
+            Verdict::WrongIdentity { signer: Some(name) } => {
+                format!("Signed by {}, but the repository uses {}", name, expected)
+            }
+            Verdict::WrongIdentity { signer: None } => {
+                format!("Signed by {}, not by {}", signer, expected)
+            }
+            Verdict::NoExpectation => format!("Signed by {}; no identity detected", signer),
+            Verdict::Unsigned => "Not signed".to_string(),
+            Verdict::Invalid(message) => format!("Signature not valid: {}", message),
+        };
+        format!("{}: {}", self.head.commit, text)
+    }
+}
+
+/// Decide the verdict from the verification result and the expected identity
+pub fn judge(
+    config: &Config,
+    head: &HeadSignature,
+    verified: Result<(), String>,
+    expected: Option<&str>,
+) -> Verdict {
+    if head.status == 'N' {
+        return Verdict::Unsigned;
+    }
+    if let Err(message) = verified {
+        return Verdict::Invalid(message);
+    }
+    let Some(expected) = expected.and_then(|name| config.identities.get(name)) else {
+        return Verdict::NoExpectation;
+    };
+    if head.signed_by(expected) {
+        return Verdict::Expected;
+    }
+    let mut names: Vec<&String> = config
+        .identities
+        .iter()
+        .filter(|(_, identity)| head.signed_by(identity))
+        .map(|(name, _)| name)
+        .collect();
+    names.sort();
+    Verdict::WrongIdentity {
+        signer: names.first().map(|name| name.to_string()),
+    }
+}
+
+fn git_options() -> RunOptions {
+    RunOptions {
+        timeout: Duration::from_secs(15),
+        retries: 0,
+        cancel: None,
+    }
+}
+
+fn git_args(repo: &Path, args: &[&str]) -> Vec<String> {
+    let mut all = vec!["-C".to_string(), repo.to_string_lossy().into_owned()];
+    all.extend(cli::args(args));
+    all
+}
+
+/// HEAD's signature details; blocks
+pub fn head_signature(repo: &Path) -> Result<HeadSignature, Error> {
+    let args = git_args(
+        repo,
+        &[
+            "log",
+            "-1",
+            "--format=%h%x1f%G?%x1f%GS%x1f%GK%x1f%GF%x1f%GP",
+            "HEAD",
+        ],
+    );
+    let out = cli::run_program("git", &args, &git_options()).map_err(Error::from_cli)?;
+    parse_head(&out).ok_or_else(|| Error::InvalidOutput {
+        program: "git",
+        message: format!("Unexpected git log output: {}", out.trim()),
+    })
+}
+
+/// `git verify-commit HEAD`; a rejected signature is `Ok(Err(reason))`;
+/// blocks
+pub fn verify_head(repo: &Path) -> Result<Result<(), String>, Error> {
+    let args = git_args(repo, &["verify-commit", "HEAD"]);
+    match cli::run_program("git", &args, &git_options()) {
+        Ok(_) => Ok(Ok(())),
+        Err(CliError::NonZeroExit { stderr, .. }) => {
+            // gpg and ssh-keygen explain themselves on the last line
+            let reason = stderr
+                .lines()
+                .rev()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .unwrap_or("verification failed")
+                .to_string();
+            Ok(Err(reason))
+        }
+        Err(e) => Err(Error::from_cli(e)),
+    }
+}
+
+/// Verify HEAD and cross-check the signer; blocks
+pub fn check(config: &Config, repo: &Path) -> Result<Report, Error> {
+    let head = head_signature(repo)?;
+    let verified = if head.status == 'N' {
+        Ok(())
+    } else {
+        verify_head(repo)?
+    };
+    let expected = detect::detect(config, repo)
+        .unwrap_or_else(|e| {
+            tracing::debug!("Detecting the identity failed: {}", e);
+            None
+        })
+        .map(|found| found.identity);
+    let verdict = judge(config, &head, verified, expected.as_deref());
+    Ok(Report {
+        head,
+        expected,
+        verdict,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::config::GpgConfig;
+
+    #[test]
+    fn test_judge_signer_against_expected_identity() {
+        let config = ConfigBuilder::new()
+            .identity(
+                "gitlab-work",
+                Identity {
+                    gpg: GpgConfig {
+                        key_id: "0xAAAA1111BBBB2222".to_string(),
+                        ..Default::default()
+                    },
+                    ..identity("gitlab", "gitlab-work", "gitlab.com", "Work", "me@acme.dev")
+                },
+            )
+            .identity(
+                "github-personal",
+                Identity {
+                    gpg: GpgConfig {
+                        format: SigningFormat::Ssh,
+                        ..Default::default()
+                    },
+                    ..identity(
+                        "github",
+                        "github-personal",
+                        "github.com",
+                        "Me",
+                        "me@example.com",
+                    )
+                },
+            )
+            .build();
+
+        let head = parse_head(
+            "1a2b3c4\x1fG\x1fWork <me@acme.dev>\x1fCCCC3333DDDD4444\x1f\
+             0123CCCC3333DDDD4444\x1f0123456789ABCDEFAAAA1111BBBB2222\n",
+        )
+        .unwrap();
+        // A subkey of the configured primary key
+        assert_eq!(
+            judge(&config, &head, Ok(()), Some("gitlab-work")),
+            Verdict::Expected
+        );
+        assert_eq!(
+            judge(&config, &head, Ok(()), Some("github-personal")),
+            Verdict::WrongIdentity {
+                signer: Some("gitlab-work".to_string())
+            }
+        );
+        assert_eq!(
+            judge(&config, &head, Err("BAD signature".into()), None),
+            Verdict::Invalid("BAD signature".to_string())
+        );
+
+        let ssh = parse_head("9f8e7d6\x1fG\x1fme@example.com\x1fSHA256:abc\x1f\x1f").unwrap();
+        assert!(ssh.signed_by(&config.identities["github-personal"]));
+        let report = Report {
+            verdict: judge(&config, &ssh, Ok(()), Some("gitlab-work")),
+            expected: Some("gitlab-work".to_string()),
+            head: ssh,
+        };
+        assert!(report.is_problem());
+        assert_eq!(
+            report.display_text(),
+            "9f8e7d6: Signed by github-personal, but the repository uses gitlab-work"
+        );
+
+        let unsigned = parse_head("5555555\x1fN\x1f\x1f\x1f\x1f").unwrap();
+        assert_eq!(judge(&config, &unsigned, Ok(()), None), Verdict::Unsigned);
+    }
+}
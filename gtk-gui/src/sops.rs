@@ -0,0 +1,563 @@
+//! SOPS-encrypted file ingestion and round-trip
+//!
+//! `remote-juggler keys sops-ingest` imports a SOPS file (YAML, JSON, dotenv
+//! or INI) into the key store under `RemoteJuggler/SOPS/{canonical-path}/`.
+//! This module adds the reverse direction: after values are edited on the
+//! KDBX side, `round_trip` writes them back into the SOPS file with
+//! `sops --set`, which re-encrypts it for the file's existing recipients.
+//!
+//! To tell which side changed a value, the last synced state is remembered
+//! as salted SHA-256 hashes (never plaintext) under the local data dir. A
+//! value changed on both sides is a conflict; by default the SOPS file wins.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::backend;
+use crate::cli::{self, RunOptions};
+use crate::error::Error;
+use crate::platform;
+
+/// Key store group that holds ingested SOPS files
+pub const GROUP_PREFIX: &str = "RemoteJuggler/SOPS";
+
+/// File name patterns recognized as SOPS-encrypted (matches the CLI)
+pub const FILE_PATTERNS: &[&str] = &[
+    "*.sops.yaml",
+    "*.sops.yml",
+    "*.sops.json",
+    "*.sops.env",
+    "*.sops.ini",
+    "*.sops.toml",
+    "*.enc.yaml",
+    "*.enc.yml",
+    "*.enc.json",
+    "*.encrypted.yaml",
+    "*.encrypted.yml",
+];
+
+const SOPS_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Storage format of a SOPS file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SopsFormat {
+    Yaml,
+    Json,
+    Env,
+    Ini,
+}
+
+impl SopsFormat {
+    /// Infer the format from the file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "yaml" | "yml" => Some(Self::Yaml),
+            "json" => Some(Self::Json),
+            "env" => Some(Self::Env),
+            "ini" => Some(Self::Ini),
+            _ => None,
+        }
+    }
+}
+
+/// Returns true if the file name matches a SOPS naming convention
+///
+/// The bare `.sops.yaml` is the SOPS creation-rules file, not secrets.
+pub fn is_sops_file(name: &str) -> bool {
+    if name == ".sops.yaml" || name == ".sops.yml" {
+        return false;
+    }
+    FILE_PATTERNS
+        .iter()
+        .any(|pattern| name.ends_with(pattern.trim_start_matches('*')))
+}
+
+/// Returns true if the file contents carry SOPS metadata
+pub fn has_sops_metadata(contents: &str, format: SopsFormat) -> bool {
+    match format {
+        SopsFormat::Yaml => contents.lines().any(|line| line.trim_end() == "sops:"),
+        SopsFormat::Json => serde_json::from_str::<Value>(contents)
+            .map(|v| v.get("sops").is_some_and(Value::is_object))
+            .unwrap_or(false),
+        SopsFormat::Env => contents
+            .lines()
+            .any(|line| line.starts_with("sops_version=")),
+        SopsFormat::Ini => contents
+            .lines()
+            .any(|line| line.trim() == "[sops]" || line.starts_with("sops_version")),
+    }
+}
+
+/// Detect whether `path` is a SOPS-encrypted file, by name or by contents
+pub fn detect(path: &Path) -> Option<SopsFormat> {
+    let format = SopsFormat::from_path(path)?;
+    let name = path.file_name()?.to_str()?;
+    if is_sops_file(name) {
+        return Some(format);
+    }
+    let contents = std::fs::read_to_string(path).ok()?;
+    has_sops_metadata(&contents, format).then_some(format)
+}
+
+/// Key store group for a SOPS file, canonicalized the same way as the CLI
+pub fn group_path(file: &str) -> String {
+    format!(
+        "{}/{}",
+        GROUP_PREFIX,
+        file.replace('/', "_").replace('~', "home")
+    )
+}
+
+/// Flatten decrypted SOPS JSON into dotted keys, matching the CLI
+///
+/// Strings are stored as-is; other scalars and arrays as their JSON text.
+pub fn flatten(value: &Value) -> BTreeMap<String, String> {
+    let mut out = BTreeMap::new();
+    if let Value::Object(map) = value {
+        for (key, value) in map {
+            flatten_into(key, value, &mut out);
+        }
+    }
+    out
+}
+
+fn flatten_into(prefix: &str, value: &Value, out: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten_into(&format!("{}.{}", prefix, key), value, out);
+            }
+        }
+        Value::String(s) => {
+            out.insert(prefix.to_string(), s.clone());
+        }
+        other => {
+            out.insert(prefix.to_string(), other.to_string());
+        }
+    }
+}
+
+/// Look up the original JSON value for a dotted key
+fn lookup<'a>(root: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.').try_fold(root, |node, part| node.get(part))
+}
+
+/// `sops --set` index expression for a dotted key, e.g. `["db"]["password"]`
+fn set_index(key: &str) -> String {
+    key.split('.')
+        .map(|part| format!("[{}]", Value::String(part.to_string())))
+        .collect()
+}
+
+/// Encode a value for `sops --set`, keeping the original type of non-strings
+fn set_value(original: Option<&Value>, value: &str) -> String {
+    match original {
+        Some(Value::String(_)) | None => Value::String(value.to_string()).to_string(),
+        Some(_) => serde_json::from_str::<Value>(value)
+            .unwrap_or_else(|_| Value::String(value.to_string()))
+            .to_string(),
+    }
+}
+
+/// Which side wins when a value was edited both in the file and the key store
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    #[default]
+    SopsWins,
+    KdbxWins,
+}
+
+impl ConflictPolicy {
+    pub fn all() -> &'static [ConflictPolicy] {
+        &[ConflictPolicy::SopsWins, ConflictPolicy::KdbxWins]
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ConflictPolicy::SopsWins => "SOPS file wins",
+            ConflictPolicy::KdbxWins => "Key store wins",
+        }
+    }
+
+    pub fn from_index(index: u32) -> Self {
+        Self::all().get(index as usize).copied().unwrap_or_default()
+    }
+}
+
+/// Hashes of the values as of the last successful sync
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Baseline {
+    hashes: BTreeMap<String, String>,
+}
+
+impl Baseline {
+    /// Build a baseline from the values both sides agree on
+    pub fn from_values(group: &str, values: &BTreeMap<String, String>) -> Self {
+        Self {
+            hashes: values
+                .iter()
+                .map(|(k, v)| (k.clone(), hash_value(group, k, v)))
+                .collect(),
+        }
+    }
+
+    /// Returns true if `value` is what `key` held at the last sync
+    pub fn matches(&self, group: &str, key: &str, value: &str) -> bool {
+        self.hashes.get(key) == Some(&hash_value(group, key, value))
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.hashes.contains_key(key)
+    }
+
+    /// Where the baseline for a SOPS file is kept
+    pub fn path_for(file: &str) -> Option<PathBuf> {
+        let name = file.replace(['/', '\\', ':'], "_").replace('~', "home");
+        platform::data_dir().map(|d| d.join("sops").join(format!("{}.json", name)))
+    }
+
+    /// Load the baseline for a file; a missing or unreadable one is empty
+    pub fn load(file: &str) -> Self {
+        Self {
+            hashes: platform::load_json_state(Self::path_for(file)),
+        }
+    }
+
+    pub fn save(&self, file: &str) -> std::io::Result<()> {
+        platform::save_json_state(Self::path_for(file), &self.hashes)
+    }
+}
+
+fn hash_value(group: &str, key: &str, value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(group.as_bytes());
+    hasher.update([0]);
+    hasher.update(key.as_bytes());
+    hasher.update([0]);
+    hasher.update(value.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Changes needed to bring the file and the key store back in agreement
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncPlan {
+    /// Values to store in the key store
+    pub to_kdbx: Vec<(String, String)>,
+    /// Values to write into the SOPS file
+    pub to_sops: Vec<(String, String)>,
+    /// Keys edited on both sides, resolved by the policy
+    pub conflicts: Vec<String>,
+}
+
+impl SyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.to_kdbx.is_empty() && self.to_sops.is_empty()
+    }
+}
+
+/// Work out what to copy in which direction
+///
+/// Keys removed from the SOPS file are left alone here; `keys sops-sync`
+/// handles deletions.
+pub fn plan(
+    group: &str,
+    sops: &BTreeMap<String, String>,
+    kdbx: &BTreeMap<String, String>,
+    baseline: &Baseline,
+    policy: ConflictPolicy,
+) -> SyncPlan {
+    let mut plan = SyncPlan::default();
+    let keys: BTreeSet<&String> = sops.keys().chain(kdbx.keys()).collect();
+
+    for key in keys {
+        match (sops.get(key), kdbx.get(key)) {
+            (Some(s), None) => plan.to_kdbx.push((key.clone(), s.clone())),
+            (None, Some(d)) => {
+                // Known at the last sync means it was removed from the file
+                if !baseline.contains(key) {
+                    plan.to_sops.push((key.clone(), d.clone()));
+                }
+            }
+            (Some(s), Some(d)) if s == d => {}
+            (Some(s), Some(d)) => {
+                let sops_changed = !baseline.matches(group, key, s);
+                let kdbx_changed = !baseline.matches(group, key, d);
+                let take_kdbx = match (sops_changed, kdbx_changed) {
+                    (false, true) => true,
+                    (true, false) => false,
+                    _ => {
+                        plan.conflicts.push(key.clone());
+                        policy == ConflictPolicy::KdbxWins
+                    }
+                };
+                if take_kdbx {
+                    plan.to_sops.push((key.clone(), d.clone()));
+                } else {
+                    plan.to_kdbx.push((key.clone(), s.clone()));
+                }
+            }
+            (None, None) => {}
+        }
+    }
+    plan
+}
+
+/// Outcome of a round-trip
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Values copied from the file into the key store
+    pub pulled: usize,
+    /// Values written back into the file
+    pub pushed: usize,
+    /// Keys that were edited on both sides
+    pub conflicts: Vec<String>,
+}
+
+impl SyncReport {
+    /// One-line summary for a status label
+    pub fn summary(&self) -> String {
+        let mut text = format!(
+            "{} pulled into key store, {} written to file",
+            self.pulled, self.pushed
+        );
+        if !self.conflicts.is_empty() {
+            text.push_str(&format!(
+                "; {} conflict(s): {}",
+                self.conflicts.len(),
+                self.conflicts.join(", ")
+            ));
+        }
+        text
+    }
+}
+
+/// The sops binary, overridable like in the CLI
+pub fn sops_program() -> String {
+    std::env::var("REMOTE_JUGGLER_SOPS_PATH").unwrap_or_else(|_| "sops".to_string())
+}
+
+fn sops_options() -> RunOptions {
+    RunOptions {
+        timeout: SOPS_TIMEOUT,
+        retries: 0,
+        cancel: None,
+    }
+}
+
+/// Decrypt a SOPS file to JSON
+pub fn decrypt(file: &Path) -> Result<Value, Error> {
+    let args = vec![
+        "-d".to_string(),
+        "--output-type".to_string(),
+        "json".to_string(),
+        file.to_string_lossy().to_string(),
+    ];
+    let stdout =
+        cli::run_program(&sops_program(), &args, &sops_options()).map_err(Error::from_cli)?;
+    serde_json::from_str(&stdout).map_err(|e| Error::InvalidOutput {
+        program: "sops",
+        message: e.to_string(),
+    })
+}
+
+/// Read the values currently stored for a SOPS file in the key store
+fn read_kdbx(group: &str) -> Result<BTreeMap<String, String>, Error> {
+    let mut values = BTreeMap::new();
+    // A group that doesn't exist yet just means nothing was ingested
+    for entry in backend::kdbx_entries(group) {
+        let path = format!("{}/{}", group, entry);
+        let value = cli::run(&cli::args(&["keys", "get", &path])).map_err(Error::from_cli)?;
+        values.insert(entry, value.trim_end_matches(['\r', '\n']).to_string());
+    }
+    Ok(values)
+}
+
+/// Sync a SOPS file and its key store group in both directions
+///
+/// Blocks while sops and the CLI run; call it from a worker thread.
+pub fn round_trip(file: &Path, policy: ConflictPolicy) -> Result<SyncReport, Error> {
+    let file_str = file.to_string_lossy().to_string();
+    let group = group_path(&file_str);
+
+    let decrypted = decrypt(file)?;
+    let mut sops_values = flatten(&decrypted);
+    let kdbx_values = read_kdbx(&group)?;
+    let baseline = Baseline::load(&file_str);
+    let plan = plan(&group, &sops_values, &kdbx_values, &baseline, policy);
+
+    for (key, value) in &plan.to_kdbx {
+        let path = format!("{}/{}", group, key);
+        cli::store_secret(&path, value).map_err(Error::from_cli)?;
+    }
+
+    for (key, value) in &plan.to_sops {
+        let expression = format!(
+            "{} {}",
+            set_index(key),
+            set_value(lookup(&decrypted, key), value)
+        );
+        let args = vec!["--set".to_string(), expression, file_str.clone()];
+        cli::run_program(&sops_program(), &args, &sops_options()).map_err(Error::from_cli)?;
+        sops_values.insert(key.clone(), value.clone());
+    }
+
+    if let Err(e) = Baseline::from_values(&group, &sops_values).save(&file_str) {
+        tracing::warn!("Failed to save SOPS sync state for {}: {}", file_str, e);
+    }
+
+    Ok(SyncReport {
+        pulled: plan.to_kdbx.len(),
+        pushed: plan.to_sops.len(),
+        conflicts: plan.conflicts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GROUP: &str = "RemoteJuggler/SOPS/_tmp_secrets.sops.yaml";
+
+    fn map(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_is_sops_file() {
+        assert!(is_sops_file("secrets.sops.yaml"));
+        assert!(is_sops_file("prod.enc.json"));
+        assert!(is_sops_file("app.sops.env"));
+        assert!(!is_sops_file(".sops.yaml"));
+        assert!(!is_sops_file("config.yaml"));
+    }
+
+    #[test]
+    fn test_has_sops_metadata() {
+        let yaml = "api_key: ENC[AES256_GCM,data:abc]\nsops:\n    version: 3.8.1\n";
+        assert!(has_sops_metadata(yaml, SopsFormat::Yaml));
+        assert!(has_sops_metadata(
+            r#"{"a": "ENC[...]", "sops": {"version": "3.8.1"}}"#,
+            SopsFormat::Json
+        ));
+        assert!(has_sops_metadata(
+            "A=ENC[...]\nsops_version=3.8.1\n",
+            SopsFormat::Env
+        ));
+        assert!(!has_sops_metadata("a: b\n", SopsFormat::Yaml));
+    }
+
+    #[test]
+    fn test_group_path_matches_cli() {
+        assert_eq!(
+            group_path("~/code/app/secrets.sops.yaml"),
+            "RemoteJuggler/SOPS/home_code_app_secrets.sops.yaml"
+        );
+    }
+
+    #[test]
+    fn test_flatten_nested() {
+        let value: Value =
+            serde_json::from_str(r#"{"db": {"user": "app", "port": 5432}, "token": "t"}"#).unwrap();
+        assert_eq!(
+            flatten(&value),
+            map(&[("db.port", "5432"), ("db.user", "app"), ("token", "t")])
+        );
+    }
+
+    #[test]
+    fn test_set_expression_keeps_types() {
+        let value: Value = serde_json::from_str(r#"{"db": {"port": 5432}}"#).unwrap();
+        assert_eq!(set_index("db.port"), r#"["db"]["port"]"#);
+        assert_eq!(set_value(lookup(&value, "db.port"), "6543"), "6543");
+        assert_eq!(set_value(None, "say \"hi\""), r#""say \"hi\"""#);
+    }
+
+    #[test]
+    fn test_plan_kdbx_edit_is_written_back() {
+        let synced = map(&[("A", "1")]);
+        let baseline = Baseline::from_values(GROUP, &synced);
+        let plan = plan(
+            GROUP,
+            &synced,
+            &map(&[("A", "2")]),
+            &baseline,
+            ConflictPolicy::default(),
+        );
+        assert_eq!(plan.to_sops, vec![("A".to_string(), "2".to_string())]);
+        assert!(plan.to_kdbx.is_empty());
+        assert!(plan.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_plan_sops_edit_is_pulled() {
+        let baseline = Baseline::from_values(GROUP, &map(&[("A", "1")]));
+        let plan = plan(
+            GROUP,
+            &map(&[("A", "3")]),
+            &map(&[("A", "1")]),
+            &baseline,
+            ConflictPolicy::KdbxWins,
+        );
+        assert_eq!(plan.to_kdbx, vec![("A".to_string(), "3".to_string())]);
+        assert!(plan.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_plan_conflict_sops_wins_by_default() {
+        let baseline = Baseline::from_values(GROUP, &map(&[("A", "1")]));
+        let sops = map(&[("A", "file")]);
+        let kdbx = map(&[("A", "store")]);
+
+        let plan_default = plan(GROUP, &sops, &kdbx, &baseline, ConflictPolicy::default());
+        assert_eq!(plan_default.conflicts, vec!["A".to_string()]);
+        assert_eq!(
+            plan_default.to_kdbx,
+            vec![("A".to_string(), "file".to_string())]
+        );
+
+        let plan_kdbx = plan(GROUP, &sops, &kdbx, &baseline, ConflictPolicy::KdbxWins);
+        assert_eq!(
+            plan_kdbx.to_sops,
+            vec![("A".to_string(), "store".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_plan_new_and_removed_keys() {
+        let baseline = Baseline::from_values(GROUP, &map(&[("OLD", "x")]));
+        let plan = plan(
+            GROUP,
+            &map(&[("FROM_FILE", "f")]),
+            &map(&[("OLD", "x"), ("FROM_STORE", "s")]),
+            &baseline,
+            ConflictPolicy::default(),
+        );
+        assert_eq!(
+            plan.to_kdbx,
+            vec![("FROM_FILE".to_string(), "f".to_string())]
+        );
+        // OLD was removed from the file, so it isn't written back
+        assert_eq!(
+            plan.to_sops,
+            vec![("FROM_STORE".to_string(), "s".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_conflict_policy_index() {
+        assert_eq!(ConflictPolicy::from_index(0), ConflictPolicy::SopsWins);
+        assert_eq!(ConflictPolicy::from_index(1), ConflictPolicy::KdbxWins);
+        assert_eq!(ConflictPolicy::from_index(9), ConflictPolicy::SopsWins);
+    }
+}
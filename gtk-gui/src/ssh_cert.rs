@@ -0,0 +1,469 @@
+//! SSH certificates (CA-signed keys)
+//!
+//! An identity whose key has a `<key>-cert.pub` next to it authenticates
+//! with the certificate. The certificate is decoded here for its
+//! principals, validity window and signing CA, and renewed by running the
+//! identity's `sshCertificate.signCommand` (e.g. `vault write -field=signed_key
+//! ssh/sign/dev public_key=@{pubkey}` or `step ssh certificate ... --sign`).
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use base64::Engine;
+
+use crate::cli::{self, RunOptions};
+use crate::config::Identity;
+use crate::databases::expand_tilde;
+use crate::error::Error;
+use crate::expiry;
+use crate::known_hosts;
+use crate::leases;
+
+const CERT_SUFFIX: &str = "-cert-v01@openssh.com";
+
+/// `valid_before` of a certificate that never expires
+const FOREVER: u64 = u64::MAX;
+
+/// Renewal warnings start this close to expiry at most
+const MAX_WARN_SECS: u64 = 7 * 86_400;
+
+/// Signing commands may open a browser for an SSO login
+const SIGN_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// A decoded OpenSSH certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    /// e.g. `ssh-ed25519-cert-v01@openssh.com`
+    pub cert_type: String,
+    pub serial: u64,
+    /// Host rather than user certificate
+    pub is_host: bool,
+    pub key_id: String,
+    /// Empty means valid for any principal
+    pub principals: Vec<String>,
+    pub valid_after: u64,
+    pub valid_before: u64,
+    /// Key type and fingerprint of the signing CA
+    pub ca_key_type: String,
+    pub ca_fingerprint: String,
+}
+
+/// Where a certificate is in its validity window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validity {
+    NotYetValid {
+        starts_in: u64,
+    },
+    Valid {
+        remaining: u64,
+    },
+    /// Close enough to expiry that it should be renewed
+    ExpiringSoon {
+        remaining: u64,
+    },
+    Expired {
+        ago: u64,
+    },
+    Forever,
+}
+
+impl Validity {
+    pub fn display_text(&self) -> String {
+        match self {
+            Self::NotYetValid { starts_in } => {
+                format!("Valid in {}", leases::format_remaining(*starts_in))
+            }
+            Self::Valid { remaining } | Self::ExpiringSoon { remaining } => {
+                format!("Expires in {}", leases::format_remaining(*remaining))
+            }
+            Self::Expired { ago } => format!("Expired {} ago", leases::format_remaining(*ago)),
+            Self::Forever => "Never expires".to_string(),
+        }
+    }
+
+    /// Returns true if the certificate should be renewed now
+    pub fn needs_renewal(&self) -> bool {
+        matches!(self, Self::ExpiringSoon { .. } | Self::Expired { .. })
+    }
+}
+
+impl Certificate {
+    /// Validity at `now`; renewal is due once a quarter of the window (at
+    /// most a week) is left
+    pub fn validity(&self, now: u64) -> Validity {
+        if self.valid_before == FOREVER {
+            return Validity::Forever;
+        }
+        if now < self.valid_after {
+            return Validity::NotYetValid {
+                starts_in: self.valid_after - now,
+            };
+        }
+        if now >= self.valid_before {
+            return Validity::Expired {
+                ago: now - self.valid_before,
+            };
+        }
+        let remaining = self.valid_before - now;
+        let lifetime = self.valid_before.saturating_sub(self.valid_after);
+        if remaining <= (lifetime / 4).min(MAX_WARN_SECS) {
+            Validity::ExpiringSoon { remaining }
+        } else {
+            Validity::Valid { remaining }
+        }
+    }
+
+    /// e.g. "alice, git" or "any principal"
+    pub fn principals_text(&self) -> String {
+        if self.principals.is_empty() {
+            "any principal".to_string()
+        } else {
+            self.principals.join(", ")
+        }
+    }
+
+    /// e.g. "2025-03-01 09:00 UTC to 2025-03-01 17:00 UTC"
+    pub fn window_text(&self) -> String {
+        if self.valid_before == FOREVER {
+            return format!("From {}", format_time(self.valid_after));
+        }
+        format!(
+            "{} to {}",
+            format_time(self.valid_after),
+            format_time(self.valid_before)
+        )
+    }
+
+    /// e.g. "ED25519 SHA256:..."
+    pub fn ca_text(&self) -> String {
+        format!(
+            "{} {}",
+            known_hosts::key_type_name(&self.ca_key_type),
+            self.ca_fingerprint
+        )
+    }
+}
+
+/// Format Unix seconds as `YYYY-MM-DD HH:MM UTC`
+pub fn format_time(unix_secs: u64) -> String {
+    format!(
+        "{} {:02}:{:02} UTC",
+        expiry::format_date(unix_secs),
+        unix_secs % 86_400 / 3600,
+        unix_secs % 3600 / 60
+    )
+}
+
+/// SSH wire-format reader
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.data.len() < n {
+            return None;
+        }
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Some(head)
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    pub fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    pub fn string(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn text(&mut self) -> Option<String> {
+        String::from_utf8(self.string()?.to_vec()).ok()
+    }
+}
+
+/// Public key fields that precede the serial, by certificate type
+fn key_field_count(cert_type: &str) -> Option<usize> {
+    match cert_type.strip_suffix(CERT_SUFFIX)? {
+        "ssh-ed25519" => Some(1),
+        "ssh-rsa" => Some(2),
+        "ssh-dss" => Some(4),
+        "sk-ssh-ed25519" => Some(2),
+        t if t.starts_with("sk-ecdsa-sha2-") => Some(3),
+        t if t.starts_with("ecdsa-sha2-") => Some(2),
+        _ => None,
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidOutput {
+        program: "ssh certificate",
+        message: message.to_string(),
+    }
+}
+
+/// Decode a certificate line, `<type> <base64> [comment]`
+pub fn parse(line: &str) -> Result<Certificate, Error> {
+    let encoded = line
+        .split_whitespace()
+        .nth(1)
+        .ok_or_else(|| invalid("not a certificate"))?;
+    let blob = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|_| invalid("not base64"))?;
+    let mut reader = Reader { data: &blob };
+    decode(&mut reader).ok_or_else(|| invalid("truncated or unsupported certificate"))
+}
+
+fn decode(reader: &mut Reader) -> Option<Certificate> {
+    let cert_type = reader.text()?;
+    let fields = key_field_count(&cert_type)?;
+    reader.string()?; // nonce
+    for _ in 0..fields {
+        reader.string()?;
+    }
+    let serial = reader.u64()?;
+    let is_host = reader.u32()? == 2;
+    let key_id = reader.text()?;
+    let mut packed = Reader {
+        data: reader.string()?,
+    };
+    let mut principals = Vec::new();
+    while !packed.data.is_empty() {
+        principals.push(packed.text()?);
+    }
+    let valid_after = reader.u64()?;
+    let valid_before = reader.u64()?;
+    reader.string()?; // critical options
+    reader.string()?; // extensions
+    reader.string()?; // reserved
+    let ca_key = reader.string()?;
+    let ca_key_type = Reader { data: ca_key }.text()?;
+    Some(Certificate {
+        cert_type,
+        serial,
+        is_host,
+        key_id,
+        principals,
+        valid_after,
+        valid_before,
+        ca_key_type,
+        ca_fingerprint: known_hosts::fingerprint_blob(ca_key),
+    })
+}
+
+/// Where ssh looks for a key's certificate, `<key>-cert.pub`
+pub fn cert_path(identity: &Identity) -> Option<PathBuf> {
+    if identity.ssh_key_path.is_empty() {
+        return None;
+    }
+    let key = expand_tilde(&identity.ssh_key_path);
+    let mut name = key.file_name()?.to_os_string();
+    name.push("-cert.pub");
+    Some(key.with_file_name(name))
+}
+
+/// The identity's certificate, or None if it has none
+pub fn load(identity: &Identity) -> Result<Option<Certificate>, Error> {
+    let Some(path) = cert_path(identity) else {
+        return Ok(None);
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(text) => parse(text.trim()).map(Some),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The identity's signing command, if one is set
+pub fn sign_command(identity: &Identity) -> Option<&str> {
+    identity
+        .ssh_certificate
+        .as_ref()
+        .map(|c| c.sign_command.trim())
+        .filter(|c| !c.is_empty())
+}
+
+/// Quote a value for `sh`
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// The signing command with its placeholders filled in
+pub fn expand_command(template: &str, name: &str, identity: &Identity) -> Option<String> {
+    let key = expand_tilde(&identity.ssh_key_path);
+    let cert = cert_path(identity)?;
+    let mut pubkey = key.clone().into_os_string();
+    pubkey.push(".pub");
+    Some(
+        template
+            .replace(
+                "{pubkey}",
+                &shell_quote(&PathBuf::from(pubkey).to_string_lossy()),
+            )
+            .replace("{key}", &shell_quote(&key.to_string_lossy()))
+            .replace("{cert}", &shell_quote(&cert.to_string_lossy()))
+            .replace("{identity}", &shell_quote(name)),
+    )
+}
+
+/// The certificate line in a signing command's output, if it printed one
+fn printed_certificate(output: &str) -> Option<&str> {
+    output.lines().map(str::trim).find(|line| {
+        line.split_whitespace()
+            .next()
+            .is_some_and(|t| t.ends_with(CERT_SUFFIX))
+    })
+}
+
+/// Run the identity's signing command and return the new certificate; blocks
+pub fn renew(name: &str, identity: &Identity) -> Result<Certificate, Error> {
+    let template =
+        sign_command(identity).ok_or_else(|| invalid("no signCommand set for this identity"))?;
+    let command =
+        expand_command(template, name, identity).ok_or_else(|| invalid("identity has no key"))?;
+    let options = RunOptions {
+        timeout: SIGN_TIMEOUT,
+        retries: 0,
+        cancel: None,
+    };
+    let output =
+        cli::run_program("sh", &cli::args(&["-c", &command]), &options).map_err(Error::from_cli)?;
+    if let (Some(line), Some(path)) = (printed_certificate(&output), cert_path(identity)) {
+        std::fs::write(path, format!("{}\n", line))?;
+    }
+    load(identity)?.ok_or_else(|| invalid("the signing command wrote no certificate"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::identity;
+    use crate::config::SshCertificateConfig;
+
+    fn put(out: &mut Vec<u8>, bytes: &[u8]) {
+        out.extend((bytes.len() as u32).to_be_bytes());
+        out.extend(bytes);
+    }
+
+    fn cert_line(principals: &[&str], valid_after: u64, valid_before: u64) -> String {
+        let mut ca_key = Vec::new();
+        put(&mut ca_key, b"ssh-ed25519");
+        put(&mut ca_key, &[7; 32]);
+
+        let mut blob = Vec::new();
+        put(&mut blob, b"ssh-ed25519-cert-v01@openssh.com");
+        put(&mut blob, &[1; 32]); // nonce
+        put(&mut blob, &[2; 32]); // public key
+        blob.extend(42u64.to_be_bytes());
+        blob.extend(1u32.to_be_bytes());
+        put(&mut blob, b"alice@laptop");
+        let mut packed = Vec::new();
+        for principal in principals {
+            put(&mut packed, principal.as_bytes());
+        }
+        put(&mut blob, &packed);
+        blob.extend(valid_after.to_be_bytes());
+        blob.extend(valid_before.to_be_bytes());
+        put(&mut blob, b"");
+        put(&mut blob, b"");
+        put(&mut blob, b"");
+        put(&mut blob, &ca_key);
+        put(&mut blob, &[0; 16]); // signature
+        format!(
+            "ssh-ed25519-cert-v01@openssh.com {} alice",
+            base64::engine::general_purpose::STANDARD.encode(blob)
+        )
+    }
+
+    #[test]
+    fn test_parse_certificate() {
+        let cert = parse(&cert_line(&["alice", "git"], 1_700_000_000, 1_700_028_800)).unwrap();
+        assert_eq!(cert.serial, 42);
+        assert!(!cert.is_host);
+        assert_eq!(cert.key_id, "alice@laptop");
+        assert_eq!(cert.principals_text(), "alice, git");
+        assert_eq!(
+            cert.window_text(),
+            "2023-11-14 22:13 UTC to 2023-11-15 06:13 UTC"
+        );
+        assert!(cert.ca_text().starts_with("ED25519 SHA256:"));
+
+        assert!(parse("ssh-ed25519 AAAA").is_err());
+        assert!(parse("garbage").is_err());
+    }
+
+    #[test]
+    fn test_validity() {
+        let start = 1_700_000_000;
+        let cert = parse(&cert_line(&[], start, start + 8 * 3600)).unwrap();
+        assert_eq!(cert.principals_text(), "any principal");
+        assert_eq!(
+            cert.validity(start - 60),
+            Validity::NotYetValid { starts_in: 60 }
+        );
+        assert_eq!(
+            cert.validity(start + 3600),
+            Validity::Valid {
+                remaining: 7 * 3600
+            }
+        );
+        // The last quarter of an 8 hour certificate
+        let soon = cert.validity(start + 6 * 3600);
+        assert_eq!(
+            soon,
+            Validity::ExpiringSoon {
+                remaining: 2 * 3600
+            }
+        );
+        assert!(soon.needs_renewal());
+        assert_eq!(soon.display_text(), "Expires in 2h 00m");
+        assert!(cert.validity(start + 9 * 3600).needs_renewal());
+
+        let forever = parse(&cert_line(&[], 0, u64::MAX)).unwrap();
+        assert_eq!(forever.validity(start), Validity::Forever);
+    }
+
+    #[test]
+    fn test_expand_command() {
+        let identity = Identity {
+            ssh_key_path: "/home/alice/.ssh/it's".to_string(),
+            ssh_certificate: Some(SshCertificateConfig {
+                sign_command: "step ssh certificate {identity} {pubkey} --sign".to_string(),
+            }),
+            ..identity(
+                "gitlab",
+                "gitlab-work",
+                "gitlab.com",
+                "alice",
+                "alice@example.com",
+            )
+        };
+        let command = expand_command(sign_command(&identity).unwrap(), "work", &identity);
+        assert_eq!(
+            command.as_deref(),
+            Some(r"step ssh certificate 'work' '/home/alice/.ssh/it'\''s.pub' --sign")
+        );
+        assert_eq!(
+            cert_path(&identity),
+            Some(PathBuf::from("/home/alice/.ssh/it's-cert.pub"))
+        );
+    }
+
+    #[test]
+    fn test_printed_certificate() {
+        let line = cert_line(&["alice"], 0, 1);
+        let output = format!("Signing...\n{}\n", line);
+        assert_eq!(printed_certificate(&output), Some(line.as_str()));
+        assert_eq!(printed_certificate("ok\n"), None);
+    }
+}
@@ -0,0 +1,694 @@
+//! Managed Host blocks in ~/.ssh/config
+//!
+//! Each identity gets a Host block (HostName, User, IdentityFile or
+//! PKCS11Provider, IdentitiesOnly, and its agent policy) inside a
+//! delimited block that RemoteJuggler owns. The block carries a checksum of
+//! its body so edits made by hand are noticed before they are overwritten.
+//! Everything outside the markers is left untouched.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::agent::{self, Constraints};
+use crate::config::{AddKeysToAgent, Config, Identity};
+use crate::export;
+use crate::platform;
+
+pub const BEGIN_MARKER: &str = "# BEGIN RemoteJuggler managed hosts";
+pub const END_MARKER: &str = "# END RemoteJuggler managed hosts";
+const CHECKSUM_PREFIX: &str = "# checksum: ";
+const NOTICE: &str = "# Generated by RemoteJuggler; edits inside this block are overwritten";
+
+/// The lines delimiting a block RemoteJuggler owns in a config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Markers {
+    pub begin: &'static str,
+    pub end: &'static str,
+}
+
+/// This file's managed block
+pub const MARKERS: Markers = Markers {
+    begin: BEGIN_MARKER,
+    end: END_MARKER,
+};
+
+/// SSH login on git hosts; an identity's `user` is its commit author name
+pub const SSH_USER: &str = "git";
+
+pub fn path() -> Option<PathBuf> {
+    platform::ssh_dir().map(|dir| dir.join("config"))
+}
+
+/// The Host block for one identity, or None if it has no host alias
+pub fn host_block(name: &str, identity: &Identity) -> Option<String> {
+    if identity.host.is_empty() {
+        return None;
+    }
+    let hostname = if identity.hostname.is_empty() {
+        &identity.host
+    } else {
+        &identity.hostname
+    };
+    let mut block = format!(
+        "# {}\nHost {}\n    HostName {}\n    User {}\n",
+        name, identity.host, hostname, SSH_USER
+    );
+    if !identity.ssh_key_path.is_empty() {
+        block.push_str(&format!("    IdentityFile {}\n", identity.ssh_key_path));
+    }
+    if let Some(library) = identity.pkcs11_library() {
+        block.push_str(&format!("    PKCS11Provider {}\n", library));
+    }
+    block.push_str("    IdentitiesOnly yes\n");
+    let policy = &identity.ssh_policy;
+    if let Some(agent) = &policy.agent {
+        block.push_str(&format!(
+            "    IdentityAgent {}\n",
+            agent::identity_agent_value(agent)
+        ));
+    }
+    if let Some(forward) = policy.forward_agent {
+        block.push_str(&format!(
+            "    ForwardAgent {}\n",
+            if forward { "yes" } else { "no" }
+        ));
+    }
+    if let Some(add) = policy.add_keys_to_agent {
+        let value = match add {
+            AddKeysToAgent::No => "no",
+            AddKeysToAgent::Ask => "ask",
+            // Keys ssh adds itself get the same confirmation as on switch
+            AddKeysToAgent::Yes if Constraints::for_identity(identity).confirm => "confirm",
+            AddKeysToAgent::Yes => "yes",
+        };
+        block.push_str(&format!("    AddKeysToAgent {}\n", value));
+    }
+    Some(block)
+}
+
+/// Host blocks for every identity, sorted by identity name
+pub fn render_body(config: &Config) -> String {
+    let identities: BTreeMap<_, _> = config.identities.iter().collect();
+    identities
+        .into_iter()
+        .filter_map(|(name, identity)| host_block(name, identity))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn checksum(body: &str) -> String {
+    Sha256::digest(body.as_bytes())
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// A managed block around `body`, markers and checksum included
+pub fn render_managed(markers: Markers, body: &str) -> String {
+    format!(
+        "{}\n{}\n{}{}\n{}{}\n",
+        markers.begin,
+        NOTICE,
+        CHECKSUM_PREFIX,
+        checksum(body),
+        body,
+        markers.end
+    )
+}
+
+/// The whole managed block, markers included
+pub fn render_block(config: &Config) -> String {
+    render_managed(MARKERS, &render_body(config))
+}
+
+/// The managed block's state in a config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockState {
+    /// No managed block yet
+    Missing,
+    /// The block is as RemoteJuggler last wrote it
+    Intact,
+    /// Lines inside the block were changed by hand
+    Edited,
+    /// A begin marker without its end marker; the file isn't written
+    Unterminated,
+}
+
+impl BlockState {
+    pub fn display_text(&self) -> &'static str {
+        match self {
+            Self::Missing => "No managed block yet",
+            Self::Intact => "Managed block unchanged since it was written",
+            Self::Edited => "Managed block was edited by hand; writing replaces those edits",
+            Self::Unterminated => "Managed block has no end marker; fix the file by hand",
+        }
+    }
+}
+
+/// Where the managed block sits in a config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Located {
+    /// Line indexes of the begin and end markers
+    begin: usize,
+    end: usize,
+    state: BlockState,
+}
+
+fn locate(lines: &[&str], markers: Markers) -> Option<Located> {
+    let begin = lines.iter().position(|l| l.trim() == markers.begin)?;
+    let Some(end) = lines[begin..]
+        .iter()
+        .position(|l| l.trim() == markers.end)
+        .map(|i| begin + i)
+    else {
+        return Some(Located {
+            begin,
+            end: lines.len(),
+            state: BlockState::Unterminated,
+        });
+    };
+
+    let inner = &lines[begin + 1..end];
+    let recorded = inner
+        .iter()
+        .find_map(|l| l.strip_prefix(CHECKSUM_PREFIX))
+        .map(str::trim);
+    let body: String = inner
+        .iter()
+        .filter(|l| **l != NOTICE && !l.starts_with(CHECKSUM_PREFIX))
+        .map(|l| format!("{}\n", l))
+        .collect();
+    let state = if recorded == Some(checksum(&body).as_str()) {
+        BlockState::Intact
+    } else {
+        BlockState::Edited
+    };
+    Some(Located { begin, end, state })
+}
+
+/// State of the block between `markers` in `text`
+pub fn managed_state(text: &str, markers: Markers) -> BlockState {
+    let lines: Vec<&str> = text.lines().collect();
+    locate(&lines, markers).map_or(BlockState::Missing, |l| l.state)
+}
+
+/// State of the managed block in `text`
+pub fn block_state(text: &str) -> BlockState {
+    managed_state(text, MARKERS)
+}
+
+/// `text` with its managed block replaced by `block`, or `block` appended
+pub fn apply(text: &str, block: &str) -> String {
+    apply_managed(text, block, MARKERS)
+}
+
+/// `text` with the block between `markers` replaced by `block`, or
+/// `block` appended
+pub fn apply_managed(text: &str, block: &str, markers: Markers) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let block_lines = block.lines();
+    let mut out: Vec<&str> = match locate(&lines, markers) {
+        Some(located) if located.state != BlockState::Unterminated => lines[..located.begin]
+            .iter()
+            .copied()
+            .chain(block_lines)
+            .chain(lines[located.end + 1..].iter().copied())
+            .collect(),
+        _ => {
+            let mut out = lines.clone();
+            if out.last().is_some_and(|l| !l.trim().is_empty()) {
+                out.push("");
+            }
+            out.extend(block_lines);
+            out
+        }
+    };
+    if out.is_empty() {
+        return String::new();
+    }
+    out.push("");
+    out.join("\n")
+}
+
+/// The lines of `text` outside the block between `markers`
+pub fn outside_managed(text: &str, markers: Markers) -> Vec<&str> {
+    let lines: Vec<&str> = text.lines().collect();
+    match locate(&lines, markers) {
+        Some(located) => lines[..located.begin]
+            .iter()
+            .chain(lines.get(located.end + 1..).unwrap_or_default())
+            .copied()
+            .collect(),
+        None => lines,
+    }
+}
+
+/// Host aliases defined outside the managed block that it also defines
+///
+/// ssh uses the first match, so an earlier hand-written block shadows the
+/// managed one.
+pub fn shadowed_hosts(text: &str, config: &Config) -> Vec<String> {
+    let outside = outside_managed(text, MARKERS);
+    let defined: Vec<&str> = outside
+        .iter()
+        .filter_map(|l| {
+            let (keyword, patterns) = l.trim().split_once(char::is_whitespace)?;
+            keyword.eq_ignore_ascii_case("host").then_some(patterns)
+        })
+        .flat_map(str::split_whitespace)
+        .collect();
+    let mut shadowed: Vec<String> = config
+        .identities
+        .values()
+        .map(|identity| identity.host.clone())
+        .filter(|host| !host.is_empty() && defined.contains(&host.as_str()))
+        .collect();
+    shadowed.sort();
+    shadowed.dedup();
+    shadowed
+}
+
+/// The `HostName` of each alias in an ssh config; wildcard patterns and
+/// aliases without one are left out
+pub fn host_names(text: &str) -> BTreeMap<String, String> {
+    let mut names = BTreeMap::new();
+    let mut aliases: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let Some((keyword, value)) = line.trim().split_once(char::is_whitespace) else {
+            continue;
+        };
+        let value = value.trim().trim_start_matches('=').trim();
+        if keyword.eq_ignore_ascii_case("host") {
+            aliases = value
+                .split_whitespace()
+                .filter(|alias| !alias.contains(['*', '?', '!']))
+                .map(str::to_lowercase)
+                .collect();
+        } else if keyword.eq_ignore_ascii_case("match") {
+            aliases.clear();
+        } else if keyword.eq_ignore_ascii_case("hostname") {
+            for alias in aliases.drain(..) {
+                names.entry(alias).or_insert_with(|| value.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// A line of a diff between two texts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+/// Line diff of `old` against `new` (longest common subsequence)
+pub fn diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    // lcs[i][j]: common lines between a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut out = Vec::new();
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out.push(DiffLine::Same(a[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push(DiffLine::Removed(a[i].to_string()));
+            i += 1;
+        } else {
+            out.push(DiffLine::Added(b[j].to_string()));
+            j += 1;
+        }
+    }
+    out
+}
+
+/// Changed lines with `context` unchanged lines around them, prefixed
+/// `+`/`-`/` ` as in a unified diff
+pub fn render_diff(lines: &[DiffLine], context: usize) -> String {
+    let changed: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| !matches!(l, DiffLine::Same(_)))
+        .map(|(i, _)| i)
+        .collect();
+    let near = |i: usize| {
+        changed
+            .iter()
+            .any(|&c| i + context >= c && i <= c + context)
+    };
+
+    let mut out = String::new();
+    let mut skipped = false;
+    for (i, line) in lines.iter().enumerate() {
+        if !near(i) {
+            skipped = true;
+            continue;
+        }
+        if skipped && !out.is_empty() {
+            out.push_str("...\n");
+        }
+        skipped = false;
+        let (prefix, text) = match line {
+            DiffLine::Same(text) => (' ', text),
+            DiffLine::Added(text) => ('+', text),
+            DiffLine::Removed(text) => ('-', text),
+        };
+        out.push(prefix);
+        out.push_str(text);
+        out.push('\n');
+    }
+    out
+}
+
+/// A reviewed change to the ssh config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub path: PathBuf,
+    /// The file as it was reviewed; empty if it doesn't exist
+    pub current: String,
+    pub proposed: String,
+    pub state: BlockState,
+    pub shadowed: Vec<String>,
+}
+
+impl Plan {
+    pub fn is_noop(&self) -> bool {
+        self.current == self.proposed
+    }
+
+    pub fn diff(&self) -> String {
+        render_diff(&diff(&self.current, &self.proposed), 2)
+    }
+}
+
+/// Work out what writing the managed block to `path` would change
+pub fn plan_for(config: &Config, path: &Path) -> std::io::Result<Plan> {
+    let current = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let state = block_state(&current);
+    let proposed = if state == BlockState::Unterminated {
+        current.clone()
+    } else {
+        apply(&current, &render_block(config))
+    };
+    Ok(Plan {
+        path: path.to_path_buf(),
+        shadowed: shadowed_hosts(&current, config),
+        current,
+        proposed,
+        state,
+    })
+}
+
+/// [`plan_for`] the user's ~/.ssh/config
+pub fn plan(config: &Config) -> std::io::Result<Plan> {
+    let path = path().ok_or_else(|| std::io::Error::other("No home directory"))?;
+    plan_for(config, &path)
+}
+
+/// Write a reviewed plan
+///
+/// Fails if the file changed since it was reviewed, so edits made in the
+/// meantime aren't lost. The previous file is kept as `config.bak`.
+pub fn write(plan: &Plan) -> std::io::Result<()> {
+    write_reviewed(
+        &plan.path,
+        plan.state,
+        &plan.current,
+        &plan.proposed,
+        "The ssh config",
+    )
+}
+
+/// Replace `path` with `proposed` unless it no longer reads `current`,
+/// keeping the old file with a `.bak` extension; `what` names the file in
+/// the error
+pub fn write_reviewed(
+    path: &Path,
+    state: BlockState,
+    current: &str,
+    proposed: &str,
+    what: &str,
+) -> std::io::Result<()> {
+    if state == BlockState::Unterminated {
+        return Err(std::io::Error::other(
+            BlockState::Unterminated.display_text(),
+        ));
+    }
+    let now = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    if now != current {
+        return Err(std::io::Error::other(format!(
+            "{} changed since it was reviewed; review again",
+            what
+        )));
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if !current.is_empty() {
+        export::write_private(&path.with_extension("bak"), current)?;
+    }
+    let tmp = path.with_extension("tmp");
+    export::write_private(&tmp, proposed)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::config::{IdentityAgent, SshPolicy};
+
+    fn config() -> Config {
+        ConfigBuilder::new()
+            .identity(
+                "personal",
+                Identity {
+                    ssh_key_path: "~/.ssh/id_ed25519_personal".to_string(),
+                    ..identity(
+                        "github",
+                        "github-personal",
+                        "github.com",
+                        "Alice",
+                        "a@example.com",
+                    )
+                },
+            )
+            .identity(
+                "work",
+                identity("gitlab", "gitlab-work", "gitlab.com", "", "a@work.example"),
+            )
+            .build()
+    }
+
+    #[test]
+    fn test_render_block() {
+        let block = render_block(&config());
+        assert!(block.starts_with(BEGIN_MARKER));
+        assert!(block.ends_with(&format!("{}\n", END_MARKER)));
+        assert!(block.contains(
+            "# personal\nHost github-personal\n    HostName github.com\n    User git\n    \
+             IdentityFile ~/.ssh/id_ed25519_personal\n    IdentitiesOnly yes\n"
+        ));
+        // No key file
+        assert!(block.contains(
+            "Host gitlab-work\n    HostName gitlab.com\n    User git\n    IdentitiesOnly yes\n"
+        ));
+        assert_eq!(block_state(&block), BlockState::Intact);
+    }
+
+    #[test]
+    fn test_agent_policy() {
+        let mut config = config();
+        let identity = config.identities.get_mut("personal").unwrap();
+        identity.ssh_policy = SshPolicy {
+            forward_agent: Some(false),
+            add_keys_to_agent: Some(AddKeysToAgent::Yes),
+            confirm: Some(true),
+            agent: None,
+        };
+        let block = host_block("personal", identity).unwrap();
+        assert!(block
+            .ends_with("IdentitiesOnly yes\n    ForwardAgent no\n    AddKeysToAgent confirm\n"));
+
+        identity.ssh_policy.confirm = Some(false);
+        identity.ssh_policy.forward_agent = None;
+        let block = host_block("personal", identity).unwrap();
+        assert!(block.ends_with("IdentitiesOnly yes\n    AddKeysToAgent yes\n"));
+    }
+
+    #[test]
+    fn test_identity_agent() {
+        let mut config = config();
+        let identity = config.identities.get_mut("personal").unwrap();
+        let policy: SshPolicy =
+            serde_json::from_str(r#"{"agent": "~/.ssh/agents/work agent.sock"}"#).unwrap();
+        assert_eq!(
+            policy.agent,
+            Some(IdentityAgent::Socket(
+                "~/.ssh/agents/work agent.sock".to_string()
+            ))
+        );
+        identity.ssh_policy = policy;
+        let block = host_block("personal", identity).unwrap();
+        assert!(block.ends_with(
+            "IdentitiesOnly yes\n    IdentityAgent \"~/.ssh/agents/work agent.sock\"\n"
+        ));
+
+        for (json, line) in [
+            (r#""system""#, "IdentityAgent SSH_AUTH_SOCK\n"),
+            (r#""none""#, "IdentityAgent none\n"),
+        ] {
+            identity.ssh_policy.agent = Some(serde_json::from_str(json).unwrap());
+            assert!(host_block("personal", identity).unwrap().ends_with(line));
+        }
+        let agent: IdentityAgent = serde_json::from_str(r#""1password""#).unwrap();
+        assert_eq!(agent, IdentityAgent::OnePassword);
+        assert!(!agent.accepts_keys());
+        assert_eq!(
+            serde_json::to_string(&IdentityAgent::GpgAgent).unwrap(),
+            r#""gpg-agent""#
+        );
+    }
+
+    #[test]
+    fn test_pkcs11_provider() {
+        let mut config = config();
+        let identity = config.identities.get_mut("work").unwrap();
+        identity.pkcs11_provider = Some("/usr/lib/opensc-pkcs11.so".to_string());
+        let block = host_block("work", identity).unwrap();
+        assert!(block.ends_with(
+            "User git\n    PKCS11Provider /usr/lib/opensc-pkcs11.so\n    IdentitiesOnly yes\n"
+        ));
+    }
+
+    #[test]
+    fn test_apply_keeps_surrounding_text() {
+        let config = config();
+        let block = render_block(&config);
+        let existing = "Host *\n    ServerAliveInterval 60\n";
+        let written = apply(existing, &block);
+        assert!(written.starts_with("Host *\n    ServerAliveInterval 60\n\n# BEGIN"));
+
+        // Rewriting replaces the block in place
+        let user_after = format!("{}Host extra\n    User me\n", written);
+        let rewritten = apply(&user_after, &block);
+        assert_eq!(rewritten, user_after);
+        assert_eq!(rewritten.matches(BEGIN_MARKER).count(), 1);
+
+        assert_eq!(apply("", &block), block);
+    }
+
+    #[test]
+    fn test_detects_manual_edits() {
+        let block = render_block(&config());
+        let edited = block.replace("User git", "User someone");
+        assert_eq!(block_state(&edited), BlockState::Edited);
+        assert_eq!(block_state("Host *\n"), BlockState::Missing);
+
+        let unterminated = block.replace(END_MARKER, "");
+        assert_eq!(block_state(&unterminated), BlockState::Unterminated);
+    }
+
+    #[test]
+    fn test_shadowed_hosts() {
+        let config = config();
+        let text = format!(
+            "Host github-personal other\n    User me\n\n{}",
+            render_block(&config)
+        );
+        assert_eq!(shadowed_hosts(&text, &config), vec!["github-personal"]);
+        assert!(shadowed_hosts(&render_block(&config), &config).is_empty());
+    }
+
+    #[test]
+    fn test_host_names() {
+        let text = "Host work gitlab-work\n  HostName gitlab.com\n\
+                    Host *.internal\n  HostName bastion\n\
+                    Host plain\n  User git\n";
+        let names = host_names(text);
+        assert_eq!(names.get("work").map(String::as_str), Some("gitlab.com"));
+        assert_eq!(
+            names.get("gitlab-work").map(String::as_str),
+            Some("gitlab.com")
+        );
+        assert_eq!(names.len(), 2);
+    }
+
+    #[test]
+    fn test_diff() {
+        let lines = diff("a\nb\nc\n", "a\nc\nd\n");
+        assert_eq!(
+            lines,
+            vec![
+                DiffLine::Same("a".into()),
+                DiffLine::Removed("b".into()),
+                DiffLine::Same("c".into()),
+                DiffLine::Added("d".into()),
+            ]
+        );
+        assert_eq!(render_diff(&lines, 0), "-b\n...\n+d\n");
+
+        let long: String = (0..20).map(|i| format!("{}\n", i)).collect();
+        let changed = long.replace("10\n", "ten\n");
+        assert_eq!(
+            render_diff(&diff(&long, &changed), 1),
+            " 9\n-10\n+ten\n 11\n"
+        );
+        let two = changed.replacen("2\n", "two\n", 1);
+        assert_eq!(
+            render_diff(&diff(&long, &two), 0),
+            "-2\n+two\n...\n-10\n+ten\n"
+        );
+    }
+
+    #[test]
+    fn test_write_refuses_stale_plan() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+        std::fs::write(&path, "Host *\n").unwrap();
+
+        let config = config();
+        let plan = plan_for(&config, &path).unwrap();
+        assert_eq!(plan.state, BlockState::Missing);
+        assert!(!plan.is_noop());
+
+        std::fs::write(&path, "Host *\n    User changed\n").unwrap();
+        assert!(write(&plan).is_err());
+
+        let plan = plan_for(&config, &path).unwrap();
+        write(&plan).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("config.bak")).unwrap(),
+            "Host *\n    User changed\n"
+        );
+        let again = plan_for(&config, &path).unwrap();
+        assert_eq!(again.state, BlockState::Intact);
+        assert!(again.is_noop());
+    }
+}
@@ -0,0 +1,187 @@
+//! Uncommitted work under the identity being switched away from
+//!
+//! `switch` rewrites the git identity of the repository it runs in, and
+//! the shell and direnv hooks run it on every `cd`, so work left
+//! uncommitted under one identity is easily committed as the next one.
+//! Before switching, the repositories under the workspace roots are
+//! checked for tracked changes while they commit as the outgoing identity,
+//! and the switch warns about them. Repositories can be excluded from
+//! switching instead (`switchExcludedRepos`), so `switch` leaves their git
+//! config alone.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cli::{self, RunOptions};
+use crate::config::Config;
+use crate::error::{CliError, Error};
+use crate::hg;
+use crate::platform;
+use crate::workspace;
+
+/// A repository with uncommitted work under the outgoing identity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirtyRepo {
+    pub path: PathBuf,
+    /// How many tracked files are changed or staged
+    pub changes: usize,
+}
+
+impl DirtyRepo {
+    pub fn display_text(&self) -> String {
+        format!(
+            "{} uncommitted {}",
+            self.changes,
+            if self.changes == 1 { "file" } else { "files" }
+        )
+    }
+}
+
+/// Count the entries of `git status --porcelain`
+pub fn parse_status(text: &str) -> usize {
+    text.lines().filter(|line| !line.trim().is_empty()).count()
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<String, CliError> {
+    let mut all = vec!["-C".to_string(), repo.to_string_lossy().into_owned()];
+    all.extend(cli::args(args));
+    let options = RunOptions {
+        timeout: Duration::from_secs(10),
+        retries: 0,
+        cancel: None,
+    };
+    cli::run_program("git", &all, &options)
+}
+
+/// How many tracked files are changed or staged in `repo`; blocks
+pub fn changes(repo: &Path) -> Result<usize, Error> {
+    let out =
+        git(repo, &["status", "--porcelain", "--untracked-files=no"]).map_err(Error::from_cli)?;
+    Ok(parse_status(&out))
+}
+
+/// The effective user.email of `repo`; empty when unset
+fn email(repo: &Path) -> Result<String, Error> {
+    match git(repo, &["config", "--get", "user.email"]) {
+        Ok(out) => Ok(out.trim().to_string()),
+        // Exit status 1: not set anywhere
+        Err(CliError::NonZeroExit { code: Some(1), .. }) => Ok(String::new()),
+        Err(e) => Err(Error::from_cli(e)),
+    }
+}
+
+/// Check one repository against the outgoing identity's email; blocks
+pub fn check(repo: &Path, outgoing_email: &str) -> Result<Option<DirtyRepo>, Error> {
+    let hg = hg::is_hg(repo);
+    let email = if hg {
+        hg::identity(repo)?.1
+    } else {
+        email(repo)?
+    };
+    if outgoing_email.is_empty() || !email.eq_ignore_ascii_case(outgoing_email) {
+        return Ok(None);
+    }
+    let changes = if hg {
+        hg::changes(repo)?
+    } else {
+        changes(repo)?
+    };
+    Ok((changes > 0).then(|| DirtyRepo {
+        path: repo.to_path_buf(),
+        changes,
+    }))
+}
+
+/// The repositories under the workspace roots, excluded ones aside, with
+/// uncommitted work under the identity `outgoing`; ones git can't read are
+/// skipped. Blocks
+pub fn scan(config: &Config, outgoing: &str) -> Vec<DirtyRepo> {
+    let Some(identity) = config.identities.get(outgoing) else {
+        return Vec::new();
+    };
+    workspace::repos(config)
+        .into_iter()
+        .filter(|repo| !config.settings.is_switch_excluded(repo))
+        .filter_map(|repo| match check(&repo, &identity.email) {
+            Ok(found) => found,
+            Err(e) => {
+                tracing::debug!("Skipping {}: {}", repo.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// The warning shown when switching from `outgoing` to `incoming`
+pub fn warning_text(repos: &[DirtyRepo], outgoing: &str, incoming: &str) -> Option<String> {
+    let names: Vec<String> = repos
+        .iter()
+        .map(|r| platform::tilde_path(&r.path))
+        .collect();
+    match names.len() {
+        0 => None,
+        1 => Some(format!(
+            "{} has uncommitted work as {}; commits there after switching are authored as {}",
+            names[0], outgoing, incoming
+        )),
+        n => Some(format!(
+            "{} repositories have uncommitted work as {} ({}); commits there after switching \
+             are authored as {}",
+            n,
+            outgoing,
+            names.join(", "),
+            incoming
+        )),
+    }
+}
+
+/// The warning for switching from the current identity to `incoming`, if
+/// work is left uncommitted under the current one; blocks
+pub fn warning(config: &Config, incoming: &str) -> Option<String> {
+    let outgoing = config.state.current_identity.as_str();
+    let same_email = match (
+        config.identities.get(outgoing),
+        config.identities.get(incoming),
+    ) {
+        (Some(old), Some(new)) => old.email.eq_ignore_ascii_case(&new.email),
+        _ => false,
+    };
+    if outgoing.is_empty() || outgoing == incoming || same_email {
+        return None;
+    }
+    warning_text(&scan(config, outgoing), outgoing, incoming)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::ConfigBuilder;
+
+    #[test]
+    fn test_status_and_warning() {
+        assert_eq!(parse_status(" M src/lib.rs\nA  README.md\n\n"), 2);
+        assert_eq!(parse_status(""), 0);
+
+        let repos = vec![DirtyRepo {
+            path: PathBuf::from("/src/api"),
+            changes: 1,
+        }];
+        assert_eq!(repos[0].display_text(), "1 uncommitted file");
+        assert_eq!(
+            warning_text(&repos, "gitlab-work", "github-personal").as_deref(),
+            Some(
+                "/src/api has uncommitted work as gitlab-work; commits there after switching \
+                 are authored as github-personal"
+            )
+        );
+        assert_eq!(warning_text(&[], "a", "b"), None);
+
+        let config = ConfigBuilder::new()
+            .settings(|settings| settings.switch_excluded_repos = vec!["/src/api".to_string()])
+            .build();
+        assert!(config
+            .settings
+            .is_switch_excluded(Path::new("/src/api/sub")));
+        assert!(!config.settings.is_switch_excluded(Path::new("/src/apiary")));
+    }
+}
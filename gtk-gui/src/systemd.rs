@@ -0,0 +1,423 @@
+//! systemd user units for running in the background
+//!
+//! `remote-juggler.service` keeps the GUI running without a window
+//! (`remote-juggler-gui --background`), so the local HTTP API, the Secret
+//! Service, auto-lock and reminders carry on with the window closed. Each
+//! periodic [`Task`] is a oneshot service started by a timer of the same
+//! name, running `remote-juggler-gui --task <id>`, which works without a
+//! display and sends a desktop notification when something needs
+//! attention. The units are written to `~/.config/systemd/user` and
+//! enabled, disabled and queried with `systemctl --user`; the timers run
+//! whether or not the GUI does.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::ci_secrets::{self, Change};
+use crate::cli::{self, RunOptions};
+use crate::config::Config;
+use crate::error::{ConfigError, Error};
+use crate::event_hooks::{self, Event};
+use crate::expiry;
+use crate::export;
+use crate::gpg;
+use crate::platform;
+use crate::revocation;
+
+/// The background service
+pub const SERVICE: &str = "remote-juggler.service";
+
+/// Name of the GUI binary the units run
+pub const GUI_PROGRAM: &str = "remote-juggler-gui";
+
+/// A periodic task with its own timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Task {
+    /// Push key store entries that changed to their CI secrets
+    KeystoreSync,
+    /// Validate stored tokens with their providers
+    TokenValidation,
+    /// Look for secrets, tokens and signing keys due to expire
+    KeyExpiry,
+}
+
+impl Task {
+    pub const ALL: [Task; 3] = [Task::KeystoreSync, Task::TokenValidation, Task::KeyExpiry];
+
+    /// Name on the command line and in the unit names
+    pub fn id(self) -> &'static str {
+        match self {
+            Task::KeystoreSync => "keystore-sync",
+            Task::TokenValidation => "token-validation",
+            Task::KeyExpiry => "key-expiry",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Task::ALL.into_iter().find(|task| task.id() == id)
+    }
+
+    pub fn display_name(self) -> &'static str {
+        match self {
+            Task::KeystoreSync => "Key store sync",
+            Task::TokenValidation => "Token validation",
+            Task::KeyExpiry => "Key expiry check",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Task::KeystoreSync => "Push changed key store entries to CI secrets, hourly",
+            Task::TokenValidation => "Check stored tokens with their providers every 6 hours",
+            Task::KeyExpiry => "Look for secrets, tokens and GPG keys due to expire, daily",
+        }
+    }
+
+    /// The timer's `OnCalendar=`
+    pub fn schedule(self) -> &'static str {
+        match self {
+            Task::KeystoreSync => "hourly",
+            Task::TokenValidation => "*-*-* 00/6:00:00",
+            Task::KeyExpiry => "daily",
+        }
+    }
+
+    /// e.g. `remote-juggler-key-expiry.service`
+    pub fn service(self) -> String {
+        format!("remote-juggler-{}.service", self.id())
+    }
+
+    /// e.g. `remote-juggler-key-expiry.timer`
+    pub fn timer(self) -> String {
+        format!("remote-juggler-{}.timer", self.id())
+    }
+}
+
+/// Whether systemd user units can be managed here
+pub fn is_available() -> bool {
+    cfg!(target_os = "linux")
+        && (platform::is_flatpak() || cli::find_program("systemctl").is_some())
+}
+
+/// `~/.config/systemd/user`, on the host under Flatpak
+pub fn unit_dir() -> Option<PathBuf> {
+    let config = platform::config_dir()?;
+    Some(config.parent()?.join("systemd").join("user"))
+}
+
+/// How the units start the GUI: the binary running now, or through
+/// `flatpak run` inside a sandbox
+pub fn command() -> String {
+    if platform::is_flatpak() {
+        if let Ok(id) = std::env::var("FLATPAK_ID") {
+            return format!("/usr/bin/flatpak run --command={} {}", GUI_PROGRAM, id);
+        }
+    }
+    let exe = std::env::current_exe()
+        .ok()
+        .filter(|exe| exe.file_stem().is_some_and(|stem| stem == GUI_PROGRAM))
+        .or_else(|| cli::find_program(GUI_PROGRAM))
+        .unwrap_or_else(|| PathBuf::from(GUI_PROGRAM));
+    let exe = exe.to_string_lossy();
+    if exe.contains(char::is_whitespace) {
+        format!("\"{}\"", exe)
+    } else {
+        exe.into_owned()
+    }
+}
+
+const HEADER: &str = "# Written by RemoteJuggler; changes here are overwritten\n";
+
+/// The background service unit
+pub fn service_unit(command: &str) -> String {
+    format!(
+        "{HEADER}[Unit]\n\
+         Description=RemoteJuggler in the background\n\
+         PartOf=graphical-session.target\n\
+         After=graphical-session.target\n\
+         \n\
+         [Service]\n\
+         ExecStart={command} --background\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=graphical-session.target\n"
+    )
+}
+
+/// The oneshot service a task's timer starts
+pub fn task_service_unit(task: Task, command: &str) -> String {
+    format!(
+        "{HEADER}[Unit]\n\
+         Description=RemoteJuggler: {}\n\
+         \n\
+         [Service]\n\
+         Type=oneshot\n\
+         ExecStart={command} --task {}\n",
+        task.display_name(),
+        task.id()
+    )
+}
+
+/// A task's timer
+pub fn timer_unit(task: Task) -> String {
+    format!(
+        "{HEADER}[Unit]\n\
+         Description=RemoteJuggler: {}\n\
+         \n\
+         [Timer]\n\
+         OnCalendar={}\n\
+         Persistent=true\n\
+         RandomizedDelaySec=5min\n\
+         \n\
+         [Install]\n\
+         WantedBy=timers.target\n",
+        task.display_name(),
+        task.schedule()
+    )
+}
+
+fn options() -> RunOptions {
+    RunOptions {
+        timeout: Duration::from_secs(30),
+        retries: 0,
+        cancel: None,
+    }
+}
+
+fn systemctl(args: &[&str]) -> Result<String, Error> {
+    let mut full = cli::args(&["--user"]);
+    full.extend(cli::args(args));
+    cli::run_program("systemctl", &full, &options()).map_err(Error::from_cli)
+}
+
+/// Write every unit, replacing ones from an earlier version, and have
+/// systemd read them again. Blocks
+pub fn install() -> Result<(), Error> {
+    let dir = unit_dir().ok_or(ConfigError::NoConfigDir)?;
+    std::fs::create_dir_all(&dir)?;
+    let command = command();
+    let mut units = vec![(SERVICE.to_string(), service_unit(&command))];
+    for task in Task::ALL {
+        units.push((task.service(), task_service_unit(task, &command)));
+        units.push((task.timer(), timer_unit(task)));
+    }
+    let mut changed = false;
+    for (name, text) in units {
+        let path = dir.join(name);
+        if std::fs::read_to_string(&path).ok().as_deref() != Some(text.as_str()) {
+            export::write_private(&path, &text)?;
+            changed = true;
+        }
+    }
+    if changed {
+        systemctl(&["daemon-reload"])?;
+    }
+    Ok(())
+}
+
+/// Enable and start a unit, installing the units first, or stop and
+/// disable it. Blocks
+pub fn set_enabled(unit: &str, enabled: bool) -> Result<(), Error> {
+    if enabled {
+        install()?;
+        systemctl(&["enable", "--now", unit])?;
+    } else {
+        systemctl(&["disable", "--now", unit])?;
+    }
+    Ok(())
+}
+
+/// Start a task now instead of waiting for its timer. Blocks
+pub fn run_now(task: Task) -> Result<(), Error> {
+    install()?;
+    systemctl(&["start", "--no-block", &task.service()])?;
+    Ok(())
+}
+
+/// What systemd says about a unit
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnitStatus {
+    /// The unit file exists
+    pub installed: bool,
+    pub enabled: bool,
+    /// Running, or for a timer, waiting to elapse
+    pub active: bool,
+    /// A timer's next run, as systemd formats it
+    pub next_run: Option<String>,
+    /// Exited with a failure last time
+    pub failed: bool,
+}
+
+impl UnitStatus {
+    /// Parse `systemctl show` output
+    pub fn parse(text: &str) -> Self {
+        let mut status = Self::default();
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            match key {
+                "LoadState" => status.installed = value == "loaded",
+                "UnitFileState" => status.enabled = value == "enabled",
+                "ActiveState" => {
+                    status.active = value == "active" || value == "activating";
+                    status.failed = value == "failed";
+                }
+                "NextElapseUSecRealtime" if !value.is_empty() && value != "n/a" => {
+                    status.next_run = Some(value.to_string())
+                }
+                _ => {}
+            }
+        }
+        status
+    }
+
+    /// e.g. "Enabled, next run Fri 2026-10-16 18:00:00 CEST"
+    pub fn display_text(&self) -> String {
+        match self {
+            Self {
+                installed: false, ..
+            } => "Not installed".to_string(),
+            Self { failed: true, .. } => "Failed; see journalctl --user".to_string(),
+            Self { enabled: false, .. } => "Disabled".to_string(),
+            Self {
+                next_run: Some(next),
+                ..
+            } => format!("Enabled, next run {}", next),
+            Self { active: true, .. } => "Enabled and running".to_string(),
+            Self { .. } => "Enabled, not running".to_string(),
+        }
+    }
+}
+
+/// A unit's status; an unknown unit is not installed. Blocks
+pub fn status(unit: &str) -> Result<UnitStatus, Error> {
+    let out = systemctl(&[
+        "show",
+        unit,
+        "--property=LoadState,UnitFileState,ActiveState,NextElapseUSecRealtime",
+    ])?;
+    Ok(UnitStatus::parse(&out))
+}
+
+/// What a task run found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub summary: String,
+    /// Lines for a notification; empty when nothing needs attention
+    pub attention: Vec<String>,
+}
+
+/// Run a task once. Blocks
+pub fn run(task: Task, config: &Config) -> Report {
+    match task {
+        Task::KeystoreSync => {
+            let plan = ci_secrets::plan(config);
+            // Drifted secrets were changed on the provider; overwriting
+            // them waits for someone to look at the preview
+            let pushable: Vec<_> = plan
+                .planned
+                .iter()
+                .filter(|p| matches!(p.change, Change::Create | Change::Update))
+                .map(|p| p.secret.clone())
+                .collect();
+            let (pushed, failed) = ci_secrets::apply(config, &pushable);
+            let mut attention: Vec<String> = plan
+                .planned
+                .iter()
+                .filter(|p| p.change == Change::Drifted)
+                .map(|p| p.display_text())
+                .collect();
+            attention.extend(
+                plan.failed
+                    .iter()
+                    .chain(&failed)
+                    .map(|(secret, e)| format!("{}: {}", secret.name, e)),
+            );
+            Report {
+                summary: format!("Pushed {} CI secrets; {}", pushed, plan.summary()),
+                attention,
+            }
+        }
+        Task::TokenValidation => {
+            let problems = revocation::sweep(config, false);
+            Report {
+                summary: format!("{} tokens need attention", problems.len()),
+                attention: problems
+                    .iter()
+                    .map(|p| format!("{}: {}", p.identity, p.display_text()))
+                    .collect(),
+            }
+        }
+        Task::KeyExpiry => {
+            let now = expiry::now();
+            let mut reminders = expiry::check(now);
+            reminders.extend(gpg::reminders(config, now));
+            reminders.sort_by_key(|r| r.due_at);
+            for reminder in &reminders {
+                event_hooks::emit(Event::TokenExpiring {
+                    name: reminder.name.clone(),
+                    detail: reminder.display_text(),
+                });
+            }
+            Report {
+                summary: format!("{} secrets or keys due for rotation", reminders.len()),
+                attention: reminders
+                    .iter()
+                    .map(|r| format!("{}: {}", r.name, r.display_text()))
+                    .collect(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_units() {
+        let service = service_unit("/usr/bin/remote-juggler-gui");
+        assert!(service.contains("ExecStart=/usr/bin/remote-juggler-gui --background\n"));
+        assert!(service.contains("WantedBy=graphical-session.target\n"));
+
+        let task = Task::from_id("token-validation").unwrap();
+        assert_eq!(task.timer(), "remote-juggler-token-validation.timer");
+        let oneshot = task_service_unit(task, "\"/opt/my apps/remote-juggler-gui\"");
+        assert!(oneshot.contains("Type=oneshot\n"));
+        assert!(oneshot
+            .contains("ExecStart=\"/opt/my apps/remote-juggler-gui\" --task token-validation\n"));
+        let timer = timer_unit(task);
+        assert!(timer.contains("OnCalendar=*-*-* 00/6:00:00\n"));
+        assert!(timer.contains("Persistent=true\n"));
+        assert_eq!(Task::from_id("nope"), None);
+    }
+
+    #[test]
+    fn test_unit_status() {
+        let status = UnitStatus::parse(
+            "LoadState=loaded\nUnitFileState=enabled\nActiveState=active\n\
+             NextElapseUSecRealtime=Fri 2026-10-16 18:00:00 CEST\n",
+        );
+        assert!(status.installed && status.enabled && status.active);
+        assert_eq!(
+            status.display_text(),
+            "Enabled, next run Fri 2026-10-16 18:00:00 CEST"
+        );
+
+        let missing = UnitStatus::parse("LoadState=not-found\nActiveState=inactive\n");
+        assert_eq!(missing.display_text(), "Not installed");
+
+        let disabled = UnitStatus::parse(
+            "LoadState=loaded\nUnitFileState=disabled\nActiveState=inactive\n\
+             NextElapseUSecRealtime=n/a\n",
+        );
+        assert_eq!(disabled.next_run, None);
+        assert_eq!(disabled.display_text(), "Disabled");
+
+        let failed =
+            UnitStatus::parse("LoadState=loaded\nUnitFileState=enabled\nActiveState=failed\n");
+        assert!(failed.display_text().starts_with("Failed"));
+    }
+}
@@ -0,0 +1,377 @@
+//! Tailscale connectivity
+//!
+//! Asks tailscaled's local API over its Unix socket, or runs `tailscale
+//! status --json` where there is no socket to reach (the macOS app,
+//! Windows, a Flatpak sandbox). Setec, and the stores and identities marked
+//! tailnet-only, depend on the answer: the resolver and the token checks
+//! skip them while the tailnet is down instead of waiting for a connection
+//! to time out. Not being able to ask isn't taken as offline, since the
+//! network may reach the tailnet some other way, e.g. through a subnet
+//! router. The answer is cached for [`CACHE_TTL`].
+
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::cli::{self, RunOptions};
+use crate::config::Config;
+use crate::error::Error;
+
+/// Environment variable overriding the tailscaled socket
+pub const SOCKET_ENV: &str = "REMOTE_JUGGLER_TAILSCALE_SOCKET";
+
+/// Where tailscaled listens on Linux
+const SOCKETS: &[&str] = &[
+    "/var/run/tailscale/tailscaled.sock",
+    "/run/tailscale/tailscaled.sock",
+];
+
+const STATUS_PATH: &str = "/localapi/v0/status?peers=false";
+
+/// How long an answer is reused
+pub const CACHE_TTL: Duration = Duration::from_secs(10);
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The tailscale binary, overridable like the other tools
+pub fn tailscale_program() -> String {
+    std::env::var("REMOTE_JUGGLER_TAILSCALE_PATH").unwrap_or_else(|_| "tailscale".to_string())
+}
+
+/// This machine on the tailnet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    /// e.g. "example.com" or "tail1234.ts.net"
+    pub tailnet: String,
+    pub host_name: String,
+    /// MagicDNS name, without the trailing dot
+    pub dns_name: String,
+    pub ips: Vec<String>,
+    /// Problems tailscaled reports, e.g. "not connected to home DERP region"
+    pub health: Vec<String>,
+}
+
+/// Whether the tailnet can be reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Connectivity {
+    Connected(Node),
+    /// tailscaled runs but isn't connected; its backend state, e.g.
+    /// "Stopped" or "NeedsLogin"
+    Offline(String),
+    /// tailscaled couldn't be asked, e.g. Tailscale isn't installed
+    Unknown(String),
+}
+
+impl Connectivity {
+    /// Only a definite answer counts; see the module docs
+    pub fn is_offline(&self) -> bool {
+        matches!(self, Connectivity::Offline(_))
+    }
+
+    pub fn display_text(&self) -> String {
+        match self {
+            Connectivity::Connected(node) => {
+                let mut text = format!("Connected to {} as {}", node.tailnet, node.host_name);
+                if let Some(ip) = node.ips.first() {
+                    text.push_str(&format!(" ({})", ip));
+                }
+                if let Some(problem) = node.health.first() {
+                    text.push_str(&format!("; {}", problem));
+                }
+                text
+            }
+            Connectivity::Offline(state) => match state.as_str() {
+                "NeedsLogin" => "Tailscale needs you to log in".to_string(),
+                "NeedsMachineAuth" => {
+                    "Tailscale is waiting for an admin to approve this machine".to_string()
+                }
+                "NoState" => "Tailscale is not running".to_string(),
+                state => format!("Tailscale is {}", state.to_lowercase()),
+            },
+            Connectivity::Unknown(reason) => format!("Can't ask Tailscale: {}", reason),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StatusJson {
+    backend_state: String,
+    #[serde(rename = "Self")]
+    self_node: Option<SelfJson>,
+    current_tailnet: Option<TailnetJson>,
+    health: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct SelfJson {
+    #[serde(rename = "HostName", default)]
+    host_name: String,
+    #[serde(rename = "DNSName", default)]
+    dns_name: String,
+    #[serde(rename = "TailscaleIPs", default)]
+    tailscale_ips: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TailnetJson {
+    name: String,
+}
+
+/// Parse the local API's status, as `tailscale status --json` prints it
+pub fn parse_status(json: &str) -> Result<Connectivity, Error> {
+    let status: StatusJson = serde_json::from_str(json).map_err(|e| Error::InvalidOutput {
+        program: "tailscale",
+        message: e.to_string(),
+    })?;
+    if status.backend_state != "Running" {
+        return Ok(Connectivity::Offline(status.backend_state));
+    }
+    let node = status.self_node.unwrap_or(SelfJson {
+        host_name: String::new(),
+        dns_name: String::new(),
+        tailscale_ips: None,
+    });
+    let dns_name = node.dns_name.trim_end_matches('.').to_string();
+    let tailnet = status.current_tailnet.map(|t| t.name).unwrap_or_else(|| {
+        dns_name
+            .split_once('.')
+            .map(|(_, suffix)| suffix.to_string())
+            .unwrap_or_default()
+    });
+    Ok(Connectivity::Connected(Node {
+        tailnet,
+        host_name: node.host_name,
+        dns_name,
+        ips: node.tailscale_ips.unwrap_or_default(),
+        health: status.health.unwrap_or_default(),
+    }))
+}
+
+/// The body of an HTTP/1.0 response, if it is a 200
+pub fn parse_response(raw: &str) -> Result<&str, Error> {
+    let (head, body) = raw
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "tailscaled",
+            message: "incomplete response".to_string(),
+        })?;
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .unwrap_or_default();
+    if status != 200 {
+        return Err(Error::Http {
+            status,
+            message: body.trim().to_string(),
+        });
+    }
+    Ok(body)
+}
+
+fn socket_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(SOCKET_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    SOCKETS.iter().map(PathBuf::from).find(|path| path.exists())
+}
+
+#[cfg(unix)]
+fn query_socket(path: &std::path::Path) -> Result<String, Error> {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    let network = |e: std::io::Error| Error::Network(format!("{}: {}", path.display(), e));
+    let mut stream = UnixStream::connect(path).map_err(network)?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(network)?;
+    stream.set_write_timeout(Some(TIMEOUT)).map_err(network)?;
+    // HTTP/1.0, so the body is neither chunked nor kept alive
+    write!(
+        stream,
+        "GET {} HTTP/1.0\r\nHost: local-tailscaled.sock\r\n\r\n",
+        STATUS_PATH
+    )
+    .map_err(network)?;
+    let mut raw = String::new();
+    stream.read_to_string(&mut raw).map_err(network)?;
+    parse_response(&raw).map(str::to_string)
+}
+
+#[cfg(not(unix))]
+fn query_socket(path: &std::path::Path) -> Result<String, Error> {
+    Err(Error::Network(format!(
+        "{}: no Unix sockets",
+        path.display()
+    )))
+}
+
+fn query_cli() -> Result<String, Error> {
+    let args = cli::args(&["status", "--json", "--peers=false"]);
+    let options = RunOptions {
+        timeout: TIMEOUT,
+        retries: 0,
+        cancel: None,
+    };
+    cli::run_program(&tailscale_program(), &args, &options).map_err(Error::from_cli)
+}
+
+/// Ask tailscaled now; blocks
+pub fn check() -> Connectivity {
+    let json = match socket_path() {
+        Some(path) => query_socket(&path).or_else(|e| {
+            tracing::debug!("Tailscale local API failed, trying the CLI: {}", e);
+            query_cli()
+        }),
+        None => query_cli(),
+    };
+    match json.and_then(|json| parse_status(&json)) {
+        Ok(connectivity) => connectivity,
+        Err(e) => Connectivity::Unknown(e.to_string()),
+    }
+}
+
+/// [`check`], reusing an answer younger than [`CACHE_TTL`]; blocks
+pub fn status() -> Connectivity {
+    static CACHE: OnceLock<Mutex<Option<(Instant, Connectivity)>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(Mutex::default);
+    if let Some((at, connectivity)) = cache.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        if at.elapsed() < CACHE_TTL {
+            return connectivity.clone();
+        }
+    }
+    let connectivity = check();
+    *cache.lock().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), connectivity.clone()));
+    connectivity
+}
+
+/// True while tailscaled says the tailnet is down; blocks
+pub fn is_offline() -> bool {
+    status().is_offline()
+}
+
+/// Why switching to `name` won't get far with `connectivity`
+pub fn warning_for(config: &Config, name: &str, connectivity: &Connectivity) -> Option<String> {
+    let identity = config.identities.get(name)?;
+    (identity.tailnet_only && connectivity.is_offline()).then(|| {
+        format!(
+            "{} is only reachable over the tailnet ({})",
+            identity.hostname,
+            connectivity.display_text()
+        )
+    })
+}
+
+/// [`warning_for`] with the current connectivity; blocks
+pub fn warning(config: &Config, name: &str) -> Option<String> {
+    let tailnet_only = config
+        .identities
+        .get(name)
+        .is_some_and(|identity| identity.tailnet_only);
+    if !tailnet_only {
+        return None;
+    }
+    warning_for(config, name, &status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::config::Identity;
+
+    #[test]
+    fn test_parse_status() {
+        let running = r#"{
+            "Version": "1.80.0", "BackendState": "Running",
+            "Self": {
+                "HostName": "laptop", "DNSName": "laptop.tail1234.ts.net.",
+                "TailscaleIPs": ["100.64.0.7", "fd7a:115c:a1e0::7"], "Online": true
+            },
+            "CurrentTailnet": { "Name": "acme.dev", "MagicDNSSuffix": "tail1234.ts.net" },
+            "Health": null
+        }"#;
+        let Connectivity::Connected(node) = parse_status(running).unwrap() else {
+            panic!("expected connected");
+        };
+        assert_eq!(node.tailnet, "acme.dev");
+        assert_eq!(node.dns_name, "laptop.tail1234.ts.net");
+        assert!(node.health.is_empty());
+        assert_eq!(
+            Connectivity::Connected(node).display_text(),
+            "Connected to acme.dev as laptop (100.64.0.7)"
+        );
+
+        let no_tailnet = r#"{"BackendState": "Running",
+            "Self": {"HostName": "ci", "DNSName": "ci.tail1234.ts.net.", "TailscaleIPs": null},
+            "Health": ["not connected to home DERP region 1"]}"#;
+        let Connectivity::Connected(node) = parse_status(no_tailnet).unwrap() else {
+            panic!("expected connected");
+        };
+        assert_eq!(node.tailnet, "tail1234.ts.net");
+        assert_eq!(node.health.len(), 1);
+
+        let stopped = parse_status(r#"{"BackendState": "NeedsLogin", "Self": null}"#).unwrap();
+        assert!(stopped.is_offline());
+        assert_eq!(stopped.display_text(), "Tailscale needs you to log in");
+        assert_eq!(
+            Connectivity::Offline("Stopped".to_string()).display_text(),
+            "Tailscale is stopped"
+        );
+        assert!(!Connectivity::Unknown("not installed".to_string()).is_offline());
+        assert!(parse_status("tailscaled is not running").is_err());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response("HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{}").unwrap(),
+            "{}"
+        );
+        match parse_response("HTTP/1.0 403 Forbidden\r\n\r\naccess denied\n") {
+            Err(Error::Http { status, message }) => {
+                assert_eq!(status, 403);
+                assert_eq!(message, "access denied");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse_response("HTTP/1.0 200 OK\r\n").is_err());
+    }
+
+    #[test]
+    fn test_warning() {
+        let config = ConfigBuilder::new()
+            .identity(
+                "homelab",
+                Identity {
+                    tailnet_only: true,
+                    ..identity(
+                        "gitea",
+                        "gitea-homelab",
+                        "git.tail1234.ts.net",
+                        "me",
+                        "me@home.dev",
+                    )
+                },
+            )
+            .identity(
+                "oss",
+                identity("github", "github-oss", "github.com", "me", "me@home.dev"),
+            )
+            .current("oss")
+            .build();
+        let stopped = Connectivity::Offline("Stopped".to_string());
+        assert_eq!(
+            warning_for(&config, "homelab", &stopped).as_deref(),
+            Some("git.tail1234.ts.net is only reachable over the tailnet (Tailscale is stopped)")
+        );
+        assert_eq!(warning_for(&config, "oss", &stopped), None);
+        let unknown = Connectivity::Unknown("not installed".to_string());
+        assert_eq!(warning_for(&config, "homelab", &unknown), None);
+        assert_eq!(warning(&config, "oss"), None);
+    }
+}
@@ -0,0 +1,486 @@
+//! OpenTelemetry traces over OTLP/HTTP
+//!
+//! Switches, secret resolution, imports, provider API calls and every CLI
+//! call run inside `tracing` spans. With `settings.telemetry.otlpEndpoint`
+//! set (or `OTEL_EXPORTER_OTLP_ENDPOINT`), [`OtlpLayer`] sends the INFO
+//! spans to that collector as OTLP JSON, so a slow switch shows which CLI
+//! call or API request it waited on. Spans are batched on an exporter
+//! thread and posted every few seconds; when the collector is down they
+//! are dropped rather than held, and a full queue drops new spans instead
+//! of blocking the caller. The `otel.name` field names a span in the
+//! trace, and an `error` field or an ERROR event inside it marks it
+//! failed. Secret values are never put in fields.
+
+use std::collections::BTreeMap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rsa::rand_core::{OsRng, RngCore};
+use serde_json::{json, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::config::TelemetrySettings;
+use crate::http;
+
+/// The standard variable, used when the settings name no endpoint
+pub const ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// `service.name` of the exported spans
+pub const SERVICE_NAME: &str = "remote-juggler";
+
+/// Finished spans waiting for the exporter; more are dropped
+const QUEUE: usize = 4096;
+const MAX_BATCH: usize = 512;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+/// How long exiting waits for the last spans to be sent
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// SPAN_KIND_INTERNAL
+const KIND_INTERNAL: u8 = 1;
+/// STATUS_CODE_ERROR
+const STATUS_ERROR: u8 = 2;
+
+/// A span or event attribute
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    Str(String),
+    Int(i64),
+    Double(f64),
+    Bool(bool),
+}
+
+impl AttributeValue {
+    fn to_json(&self) -> Value {
+        match self {
+            Self::Str(s) => json!({ "stringValue": s }),
+            // OTLP JSON carries 64-bit integers as strings
+            Self::Int(i) => json!({ "intValue": i.to_string() }),
+            Self::Double(d) => json!({ "doubleValue": d }),
+            Self::Bool(b) => json!({ "boolValue": b }),
+        }
+    }
+}
+
+/// An event recorded inside a span
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanEvent {
+    pub name: String,
+    pub time: u64,
+    pub attributes: Vec<(String, AttributeValue)>,
+}
+
+/// A span on its way to the collector
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpanData {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub parent_span_id: Option<[u8; 8]>,
+    pub name: String,
+    /// Unix nanoseconds
+    pub start: u64,
+    pub end: u64,
+    pub attributes: Vec<(String, AttributeValue)>,
+    pub events: Vec<SpanEvent>,
+    /// Set when the span failed
+    pub error: Option<String>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn attributes_json(attributes: &[(String, AttributeValue)]) -> Vec<Value> {
+    attributes
+        .iter()
+        .map(|(key, value)| json!({ "key": key, "value": value.to_json() }))
+        .collect()
+}
+
+impl SpanData {
+    fn to_json(&self) -> Value {
+        let mut span = json!({
+            "traceId": hex(&self.trace_id),
+            "spanId": hex(&self.span_id),
+            "name": self.name,
+            "kind": KIND_INTERNAL,
+            "startTimeUnixNano": self.start.to_string(),
+            "endTimeUnixNano": self.end.to_string(),
+            "attributes": attributes_json(&self.attributes),
+            "events": self.events.iter().map(|event| json!({
+                "timeUnixNano": event.time.to_string(),
+                "name": event.name,
+                "attributes": attributes_json(&event.attributes),
+            })).collect::<Vec<_>>(),
+        });
+        if let Some(parent) = &self.parent_span_id {
+            span["parentSpanId"] = json!(hex(parent));
+        }
+        if let Some(message) = &self.error {
+            span["status"] = json!({ "code": STATUS_ERROR, "message": message });
+        }
+        span
+    }
+}
+
+/// An OTLP `ExportTraceServiceRequest` for `spans`
+pub fn export_request(spans: &[SpanData]) -> Value {
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": attributes_json(&[
+                    ("service.name".to_string(), AttributeValue::Str(SERVICE_NAME.to_string())),
+                    (
+                        "service.version".to_string(),
+                        AttributeValue::Str(env!("CARGO_PKG_VERSION").to_string()),
+                    ),
+                ]),
+            },
+            "scopeSpans": [{
+                "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans.iter().map(SpanData::to_json).collect::<Vec<_>>(),
+            }],
+        }],
+    })
+}
+
+/// Where traces are posted: the endpoint's `/v1/traces`
+pub fn traces_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim().trim_end_matches('/');
+    if endpoint.ends_with("/v1/traces") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/traces", endpoint)
+    }
+}
+
+/// The configured endpoint, or the environment's
+pub fn endpoint(settings: &TelemetrySettings) -> Option<String> {
+    Some(settings.otlp_endpoint.trim().to_string())
+        .filter(|endpoint| !endpoint.is_empty())
+        .or_else(|| std::env::var(ENDPOINT_ENV).ok().filter(|e| !e.is_empty()))
+}
+
+fn now_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// Collects fields into attributes, taking out the ones with a meaning
+#[derive(Default)]
+struct FieldVisitor {
+    attributes: Vec<(String, AttributeValue)>,
+    name: Option<String>,
+    message: Option<String>,
+    error: Option<String>,
+}
+
+impl FieldVisitor {
+    fn add(&mut self, field: &Field, value: AttributeValue) {
+        let text = || match &value {
+            AttributeValue::Str(s) => s.clone(),
+            AttributeValue::Int(i) => i.to_string(),
+            AttributeValue::Double(d) => d.to_string(),
+            AttributeValue::Bool(b) => b.to_string(),
+        };
+        match field.name() {
+            "otel.name" => self.name = Some(text()),
+            "message" => self.message = Some(text()),
+            "error" => self.error = Some(text()),
+            name => {
+                self.attributes.retain(|(key, _)| key != name);
+                self.attributes.push((name.to_string(), value));
+            }
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.add(field, AttributeValue::Str(value.to_string()));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.add(field, AttributeValue::Int(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.add(field, AttributeValue::Int(value as i64));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.add(field, AttributeValue::Double(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.add(field, AttributeValue::Bool(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.add(field, AttributeValue::Str(format!("{:?}", value)));
+    }
+}
+
+impl SpanData {
+    fn apply(&mut self, visitor: FieldVisitor) {
+        if let Some(name) = visitor.name {
+            self.name = name;
+        }
+        if visitor.error.is_some() {
+            self.error = visitor.error;
+        }
+        for (key, value) in visitor.attributes {
+            self.attributes.retain(|(k, _)| *k != key);
+            self.attributes.push((key, value));
+        }
+    }
+}
+
+enum Message {
+    Span(Box<SpanData>),
+    Flush(mpsc::Sender<()>),
+}
+
+/// Turns closed spans into [`SpanData`] for the exporter thread
+pub struct OtlpLayer {
+    sender: SyncSender<Message>,
+}
+
+/// Sends the spans still queued when dropped, so a one-off run like
+/// `--task` doesn't lose its trace
+pub struct Guard {
+    sender: SyncSender<Message>,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let (done, flushed) = mpsc::channel();
+        if self.sender.send(Message::Flush(done)).is_ok() {
+            let _ = flushed.recv_timeout(FLUSH_TIMEOUT);
+        }
+    }
+}
+
+fn post(url: &str, headers: &BTreeMap<String, String>, spans: &[SpanData]) {
+    let mut request = http::agent()
+        .post(url)
+        .timeout(FLUSH_TIMEOUT)
+        .set("Content-Type", "application/json");
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    // Logged at debug so a down collector doesn't flood the log
+    if let Err(e) = request.send_string(&export_request(spans).to_string()) {
+        tracing::debug!("Exporting {} spans failed: {}", spans.len(), e);
+    }
+}
+
+fn export(receiver: Receiver<Message>, url: String, headers: BTreeMap<String, String>) {
+    let mut batch: Vec<SpanData> = Vec::new();
+    loop {
+        match receiver.recv_timeout(FLUSH_INTERVAL) {
+            Ok(Message::Span(span)) => {
+                batch.push(*span);
+                if batch.len() < MAX_BATCH {
+                    continue;
+                }
+            }
+            Ok(Message::Flush(done)) => {
+                if !batch.is_empty() {
+                    post(&url, &headers, &std::mem::take(&mut batch));
+                }
+                let _ = done.send(());
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if !batch.is_empty() {
+            post(&url, &headers, &std::mem::take(&mut batch));
+        }
+    }
+}
+
+/// The layer and its flush guard, if an endpoint is configured; starts the
+/// exporter thread
+pub fn layer(settings: &TelemetrySettings) -> Option<(OtlpLayer, Guard)> {
+    let url = traces_url(&endpoint(settings)?);
+    let (sender, receiver) = mpsc::sync_channel(QUEUE);
+    let headers = settings.headers.clone();
+    std::thread::Builder::new()
+        .name("otlp-export".to_string())
+        .spawn(move || export(receiver, url, headers))
+        .ok()?;
+    Some((
+        OtlpLayer {
+            sender: sender.clone(),
+        },
+        Guard { sender },
+    ))
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let parent = span
+            .parent()
+            .and_then(|parent| parent.extensions().get::<SpanData>().cloned());
+        let mut data = SpanData {
+            name: attrs.metadata().name().to_string(),
+            start: now_nanos(),
+            ..SpanData::default()
+        };
+        OsRng.fill_bytes(&mut data.span_id);
+        match parent {
+            Some(parent) => {
+                data.trace_id = parent.trace_id;
+                data.parent_span_id = Some(parent.span_id);
+            }
+            None => OsRng.fill_bytes(&mut data.trace_id),
+        }
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        data.apply(visitor);
+        span.extensions_mut().insert(data);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        values.record(&mut visitor);
+        let mut extensions = span.extensions_mut();
+        if let Some(data) = extensions.get_mut::<SpanData>() {
+            data.apply(visitor);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.event_span(event) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let mut extensions = span.extensions_mut();
+        let Some(data) = extensions.get_mut::<SpanData>() else {
+            return;
+        };
+        let name = visitor
+            .message
+            .unwrap_or_else(|| event.metadata().name().to_string());
+        if *event.metadata().level() == Level::ERROR && data.error.is_none() {
+            data.error = Some(name.clone());
+        }
+        let mut attributes = visitor.attributes;
+        attributes.push((
+            "level".to_string(),
+            AttributeValue::Str(event.metadata().level().to_string()),
+        ));
+        data.events.push(SpanEvent {
+            name,
+            time: now_nanos(),
+            attributes,
+        });
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(mut data) = span.extensions_mut().remove::<SpanData>() else {
+            return;
+        };
+        data.end = now_nanos();
+        // A full queue means the collector can't keep up; drop the span
+        let _ = self.sender.try_send(Message::Span(Box::new(data)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_traces_url_and_endpoint() {
+        assert_eq!(
+            traces_url("http://127.0.0.1:4318/"),
+            "http://127.0.0.1:4318/v1/traces"
+        );
+        assert_eq!(
+            traces_url("https://otel.home.arpa/v1/traces"),
+            "https://otel.home.arpa/v1/traces"
+        );
+        let settings = TelemetrySettings {
+            otlp_endpoint: " http://collector:4318 ".to_string(),
+            headers: BTreeMap::new(),
+        };
+        assert_eq!(
+            endpoint(&settings).as_deref(),
+            Some("http://collector:4318")
+        );
+    }
+
+    #[test]
+    fn test_spans_nest_and_export() {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE);
+        let subscriber = tracing_subscriber::registry().with(OtlpLayer { sender });
+        tracing::subscriber::with_default(subscriber, || {
+            let outer = tracing::info_span!("switch", identity = "work").entered();
+            let inner = tracing::info_span!(
+                "cli",
+                otel.name = "remote-juggler keys get",
+                exit = tracing::field::Empty
+            );
+            inner.in_scope(|| tracing::error!(code = 2, "timed out"));
+            inner.record("exit", 1);
+            drop(inner);
+            drop(outer);
+        });
+
+        let spans: Vec<SpanData> = receiver
+            .try_iter()
+            .map(|message| match message {
+                Message::Span(span) => *span,
+                Message::Flush(_) => panic!("unexpected flush"),
+            })
+            .collect();
+        let [inner, outer] = spans.as_slice() else {
+            panic!("expected two spans, got {:?}", spans);
+        };
+        assert_eq!(outer.name, "switch");
+        assert_eq!(outer.parent_span_id, None);
+        assert_eq!(inner.name, "remote-juggler keys get");
+        assert_eq!(inner.trace_id, outer.trace_id);
+        assert_eq!(inner.parent_span_id, Some(outer.span_id));
+        assert_eq!(inner.error.as_deref(), Some("timed out"));
+        assert!(inner
+            .attributes
+            .contains(&("exit".to_string(), AttributeValue::Int(1))));
+        assert!(inner.end >= inner.start);
+
+        let request = export_request(spans.as_slice());
+        let exported = &request["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(exported[0]["traceId"].as_str().unwrap().len(), 32);
+        assert_eq!(exported[0]["parentSpanId"], json!(hex(&outer.span_id)));
+        assert_eq!(exported[0]["status"]["code"], json!(STATUS_ERROR));
+        assert_eq!(
+            exported[0]["events"][0]["attributes"][0],
+            json!({ "key": "code", "value": { "intValue": "2" } })
+        );
+        assert_eq!(exported[1]["attributes"][0]["key"], "identity");
+        assert!(exported[1].get("parentSpanId").is_none());
+    }
+}
@@ -0,0 +1,573 @@
+//! Access token scope audit
+//!
+//! Tokens tend to be created with more than they are used for: a classic
+//! GitHub token with `repo` and `admin:org` to be safe, or a GitLab `api`
+//! token where pushing needs only `write_repository`. The audit asks the
+//! provider what each stored token can do and when it expires (see
+//! [`pat::check`]), works out from the identity's configuration what the
+//! token is actually used for, and flags scopes beyond that, scopes it
+//! lacks and tokens that never expire, recommending the minimal
+//! replacement. GitHub fine-grained tokens don't report their permissions,
+//! so for those only the expiry is checked.
+
+use crate::config::{Config, Identity, SigningFormat};
+use crate::error::Error;
+use crate::expiry;
+use crate::key_audit::Severity;
+use crate::orgs;
+use crate::pat;
+use crate::pubkeys::{self, Provider};
+
+/// Tokens valid for longer than this from now are flagged
+pub const MAX_LIFETIME_DAYS: u64 = 366;
+
+const DAY: u64 = 86_400;
+
+/// What an identity's token is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Use {
+    /// Reading repositories and pushing over HTTPS
+    Repositories,
+    /// Organization discovery
+    Organizations,
+    /// SSH key registration
+    SshKeys,
+    /// GPG key registration
+    GpgKeys,
+    /// SSH signing key registration
+    SigningKeys,
+    /// Rotation through the API
+    Rotation,
+    /// Writing repository CI secrets
+    CiSecrets,
+    /// Writing organization or group CI secrets
+    OrgSecrets,
+}
+
+impl Use {
+    pub fn display_text(&self) -> &'static str {
+        match self {
+            Self::Repositories => "repositories",
+            Self::Organizations => "organization discovery",
+            Self::SshKeys => "SSH key registration",
+            Self::GpgKeys => "GPG key registration",
+            Self::SigningKeys => "signing key registration",
+            Self::Rotation => "rotation",
+            Self::CiSecrets => "CI secrets",
+            Self::OrgSecrets => "organization CI secrets",
+        }
+    }
+
+    /// The fine-grained GitHub permission it needs
+    pub fn github_permission(&self) -> Option<&'static str> {
+        match self {
+            Self::Repositories => Some("Contents: read and write"),
+            Self::Organizations => Some("Members: read-only"),
+            Self::SshKeys => Some("Git SSH keys: read and write"),
+            Self::GpgKeys => Some("GPG keys: read and write"),
+            Self::SigningKeys => Some("SSH signing keys: read and write"),
+            Self::Rotation => None,
+            Self::CiSecrets => Some("Secrets: read and write"),
+            Self::OrgSecrets => Some("Organization secrets: read and write"),
+        }
+    }
+}
+
+/// What the configuration uses the identity's token for
+pub fn uses(config: &Config, name: &str, provider: Provider, identity: &Identity) -> Vec<Use> {
+    let mut uses = vec![Use::Repositories];
+    if !identity.organizations.is_empty() && orgs::orgs_path(provider).is_some() {
+        uses.push(Use::Organizations);
+    }
+    if !identity.ssh_key_path.trim().is_empty() {
+        uses.push(Use::SshKeys);
+    }
+    if identity.signing_key().is_some() {
+        match identity.gpg.format {
+            SigningFormat::Gpg => uses.push(Use::GpgKeys),
+            SigningFormat::Ssh => uses.push(Use::SigningKeys),
+            SigningFormat::Gitsign => {}
+        }
+    }
+    if pat::rotates_via_api(provider) {
+        uses.push(Use::Rotation);
+    }
+    for secret in &config.settings.ci_secrets {
+        let used = match secret.organization {
+            false => Use::CiSecrets,
+            true => Use::OrgSecrets,
+        };
+        if secret.identity == name && !uses.contains(&used) {
+            uses.push(used);
+        }
+    }
+    uses
+}
+
+/// The narrowest scope granting a use; None where the provider doesn't
+/// report scopes or needs none for it
+pub fn scope_for(provider: Provider, used: Use) -> Option<&'static str> {
+    match (provider, used) {
+        (Provider::GitHub, Use::Repositories) => Some("repo"),
+        (Provider::GitHub, Use::Organizations) => Some("read:org"),
+        (Provider::GitHub, Use::SshKeys) => Some("admin:public_key"),
+        (Provider::GitHub, Use::GpgKeys) => Some("admin:gpg_key"),
+        (Provider::GitHub, Use::SigningKeys) => Some("admin:ssh_signing_key"),
+        (Provider::GitHub, Use::CiSecrets) => Some("repo"),
+        (Provider::GitHub, Use::OrgSecrets) => Some("admin:org"),
+        (Provider::GitLab, Use::Repositories) => Some("write_repository"),
+        (Provider::GitLab, Use::Organizations) => Some("read_api"),
+        // GitLab has no narrower scope for managing keys or variables
+        (
+            Provider::GitLab,
+            Use::SshKeys | Use::GpgKeys | Use::SigningKeys | Use::CiSecrets | Use::OrgSecrets,
+        ) => Some("api"),
+        (Provider::GitLab, Use::Rotation) => Some("self_rotate"),
+        _ => None,
+    }
+}
+
+/// Whether holding `held` grants `needed`
+pub fn grants(provider: Provider, held: &str, needed: &str) -> bool {
+    if held == needed {
+        return true;
+    }
+    match provider {
+        Provider::GitHub => {
+            let (held_level, held_area) = held.split_once(':').unwrap_or(("", held));
+            let (needed_level, needed_area) = needed.split_once(':').unwrap_or(("", needed));
+            match held {
+                "repo" => needed == "public_repo" || needed.starts_with("repo:"),
+                "user" => needed.starts_with("user:") || needed == "read:user",
+                _ => {
+                    held_area == needed_area
+                        && matches!(
+                            (held_level, needed_level),
+                            ("admin", "write" | "read") | ("write", "read")
+                        )
+                }
+            }
+        }
+        Provider::GitLab => match held {
+            "api" => !matches!(needed, "sudo" | "admin_mode"),
+            "write_repository" => needed == "read_repository",
+            "read_api" => needed == "read_user",
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Scopes that reach well beyond the identity's own repositories
+pub fn is_risky(provider: Provider, scope: &str) -> bool {
+    match provider {
+        Provider::GitHub => matches!(
+            scope,
+            "delete_repo"
+                | "admin:org"
+                | "admin:enterprise"
+                | "admin:org_hook"
+                | "admin:repo_hook"
+                | "site_admin"
+                | "workflow"
+                | "write:packages"
+                | "delete:packages"
+                | "user"
+        ),
+        Provider::GitLab => matches!(
+            scope,
+            "sudo" | "admin_mode" | "create_runner" | "manage_runner" | "k8s_proxy"
+        ),
+        _ => false,
+    }
+}
+
+/// The kind of token, from its prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A classic personal access token, scoped by the lists above
+    Classic,
+    /// A GitHub fine-grained token, whose permissions aren't reported
+    FineGrained,
+    /// Issued by signing in
+    OAuth,
+    Other,
+}
+
+impl TokenKind {
+    pub fn of(provider: Provider, token: &str) -> Self {
+        match provider {
+            Provider::GitHub if token.starts_with("github_pat_") => Self::FineGrained,
+            Provider::GitHub if token.starts_with("ghp_") => Self::Classic,
+            Provider::GitHub if token.starts_with("gho_") || token.starts_with("ghu_") => {
+                Self::OAuth
+            }
+            Provider::GitLab if token.starts_with("glpat-") => Self::Classic,
+            Provider::GitLab if token.starts_with("gloas-") => Self::OAuth,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn display_text(&self) -> &'static str {
+        match self {
+            Self::Classic => "Personal access token",
+            Self::FineGrained => "Fine-grained token",
+            Self::OAuth => "Signed-in token",
+            Self::Other => "Token",
+        }
+    }
+}
+
+/// A problem with a token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    /// Scopes beyond what the identity uses
+    Excess(Vec<String>),
+    /// Scopes the identity uses but the token lacks
+    Missing(Vec<String>),
+    NoExpiry,
+    /// Valid for this many more days, over [`MAX_LIFETIME_DAYS`]
+    LongLived(u64),
+    /// The provider doesn't say what the token can do
+    Unreported,
+}
+
+impl Finding {
+    pub fn severity(&self, provider: Provider) -> Severity {
+        match self {
+            Self::Excess(scopes) if scopes.iter().any(|s| is_risky(provider, s)) => Severity::High,
+            Self::Excess(_) | Self::NoExpiry => Severity::Medium,
+            Self::Missing(_) | Self::LongLived(_) | Self::Unreported => Severity::Low,
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match self {
+            Self::Excess(scopes) => format!("Over-privileged: {}", scopes.join(", ")),
+            Self::Missing(scopes) => format!("Missing: {}", scopes.join(", ")),
+            Self::NoExpiry => "Never expires".to_string(),
+            Self::LongLived(days) => format!("Valid for {} more days", days),
+            Self::Unreported => "Permissions not reported".to_string(),
+        }
+    }
+
+    pub fn remediation(&self) -> &'static str {
+        match self {
+            Self::Excess(_) => {
+                "Replace it with a token limited to the recommended scopes, then revoke this one"
+            }
+            Self::Missing(_) => {
+                "Key registration or discovery will be refused; add the scopes to a new token"
+            }
+            Self::NoExpiry => {
+                "A leaked token stays usable until revoked; give the next one an expiry"
+            }
+            Self::LongLived(_) => "Prefer tokens that expire within a year",
+            Self::Unreported => {
+                "Check its repository access and permissions on the provider's settings page"
+            }
+        }
+    }
+}
+
+/// The smallest set of scopes covering the uses
+pub fn minimal_scopes(provider: Provider, uses: &[Use]) -> Vec<String> {
+    let mut scopes: Vec<&str> = uses
+        .iter()
+        .filter_map(|used| scope_for(provider, *used))
+        .collect();
+    scopes.sort();
+    scopes.dedup();
+    let all = scopes.clone();
+    scopes.retain(|scope| {
+        !all.iter()
+            .any(|other| other != scope && grants(provider, other, scope))
+    });
+    scopes.into_iter().map(str::to_string).collect()
+}
+
+/// The findings for a token with `scopes` (as reported) expiring at
+/// `expires_at`
+pub fn assess(
+    provider: Provider,
+    kind: TokenKind,
+    scopes: &[String],
+    expires_at: Option<u64>,
+    uses: &[Use],
+    now: u64,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let recommended = minimal_scopes(provider, uses);
+    // A classic token without scopes reports an empty list
+    if scopes.is_empty() && kind != TokenKind::Classic {
+        if kind == TokenKind::FineGrained || !recommended.is_empty() {
+            findings.push(Finding::Unreported);
+        }
+    } else {
+        let excess: Vec<String> = scopes
+            .iter()
+            .filter(|held| {
+                !recommended
+                    .iter()
+                    .any(|needed| grants(provider, needed, held))
+            })
+            .cloned()
+            .collect();
+        if !excess.is_empty() {
+            findings.push(Finding::Excess(excess));
+        }
+        let missing: Vec<String> = recommended
+            .iter()
+            .filter(|needed| !scopes.iter().any(|held| grants(provider, held, needed)))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            findings.push(Finding::Missing(missing));
+        }
+    }
+    match expires_at {
+        // Signed-in tokens are refreshed rather than living on
+        None if kind != TokenKind::OAuth => findings.push(Finding::NoExpiry),
+        Some(at) if at > now + MAX_LIFETIME_DAYS * DAY => {
+            findings.push(Finding::LongLived((at - now) / DAY))
+        }
+        _ => {}
+    }
+    findings
+}
+
+/// One identity's token, as the audit found it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenAudit {
+    pub identity: String,
+    pub provider: Provider,
+    pub hostname: String,
+    pub kind: TokenKind,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<u64>,
+    pub uses: Vec<Use>,
+    pub findings: Vec<Finding>,
+}
+
+impl TokenAudit {
+    /// e.g. "Personal access token · repo, admin:org"
+    pub fn token_text(&self) -> String {
+        if self.scopes.is_empty() {
+            return self.kind.display_text().to_string();
+        }
+        format!("{} · {}", self.kind.display_text(), self.scopes.join(", "))
+    }
+
+    /// What the replacement should be limited to: fine-grained
+    /// permissions on GitHub, scopes elsewhere
+    pub fn recommendation(&self) -> Vec<String> {
+        match self.provider {
+            Provider::GitHub => self
+                .uses
+                .iter()
+                .filter_map(Use::github_permission)
+                .map(str::to_string)
+                .collect(),
+            _ => minimal_scopes(self.provider, &self.uses),
+        }
+    }
+
+    /// Where to create the replacement, filled in where the provider
+    /// allows
+    pub fn replacement_url(&self) -> String {
+        let host = self.hostname.trim();
+        let scopes = minimal_scopes(self.provider, &self.uses);
+        match self.provider {
+            Provider::GitHub => format!(
+                "https://{}/settings/personal-access-tokens/new",
+                if host.is_empty() { "github.com" } else { host }
+            ),
+            Provider::GitLab if !scopes.is_empty() => format!(
+                "{}?name=remote-juggler-{}&scopes={}",
+                pat::new_token_url(self.provider, host),
+                self.identity,
+                scopes.join(",")
+            ),
+            _ => pat::new_token_url(self.provider, host),
+        }
+    }
+
+    pub fn severity(&self) -> Option<Severity> {
+        self.findings
+            .iter()
+            .map(|finding| finding.severity(self.provider))
+            .max()
+    }
+}
+
+/// Check an identity's stored token with its provider and audit it;
+/// None when the identity has no provider API or no token. Blocks
+pub fn audit_identity(config: &Config, name: &str) -> Result<Option<TokenAudit>, Error> {
+    let Some(identity) = config.identities.get(name) else {
+        return Ok(None);
+    };
+    let Some(provider) = Provider::for_identity(identity) else {
+        return Ok(None);
+    };
+    let token = match pubkeys::find_token(name, identity, provider) {
+        Ok(token) => token,
+        Err(Error::InvalidOutput {
+            program: "token lookup",
+            ..
+        }) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let meta = pat::check(name, identity)?;
+    let kind = match meta.token_type.as_str() {
+        "oauth" => TokenKind::OAuth,
+        _ => TokenKind::of(provider, &token),
+    };
+    let uses = uses(config, name, provider, identity);
+    let findings = assess(
+        provider,
+        kind,
+        &meta.scopes,
+        meta.expires(),
+        &uses,
+        expiry::now(),
+    );
+    Ok(Some(TokenAudit {
+        identity: name.to_string(),
+        provider,
+        hostname: identity.hostname.clone(),
+        kind,
+        expires_at: meta.expires(),
+        scopes: meta.scopes,
+        uses,
+        findings,
+    }))
+}
+
+/// Audit every identity's stored token, worst first; failures are
+/// reported by identity. Blocks
+pub fn audit(config: &Config) -> (Vec<TokenAudit>, Vec<(String, Error)>) {
+    let mut audits = Vec::new();
+    let mut failed = Vec::new();
+    for name in config.identity_names() {
+        match audit_identity(config, &name) {
+            Ok(Some(audit)) => audits.push(audit),
+            Ok(None) => {}
+            Err(e) => failed.push((name, e)),
+        }
+    }
+    audits.sort_by_key(|audit| std::cmp::Reverse(audit.severity()));
+    (audits, failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_findings_and_recommendations() {
+        let now = 1_767_225_600;
+        let scopes = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        // A classic token made broad, used only for repositories
+        let findings = assess(
+            Provider::GitHub,
+            TokenKind::Classic,
+            &scopes(&["repo", "admin:org", "gist"]),
+            None,
+            &[Use::Repositories],
+            now,
+        );
+        assert_eq!(
+            findings,
+            vec![
+                Finding::Excess(scopes(&["admin:org", "gist"])),
+                Finding::NoExpiry,
+            ]
+        );
+        assert_eq!(findings[0].severity(Provider::GitHub), Severity::High);
+
+        // Narrower and broader key scopes
+        let uses = [Use::Repositories, Use::Organizations, Use::SshKeys];
+        assert_eq!(
+            assess(
+                Provider::GitHub,
+                TokenKind::Classic,
+                &scopes(&["repo", "admin:org", "write:public_key"]),
+                Some(now + 30 * DAY),
+                &uses,
+                now,
+            ),
+            vec![
+                Finding::Excess(scopes(&["admin:org"])),
+                Finding::Missing(scopes(&["admin:public_key"])),
+            ]
+        );
+        assert!(grants(
+            Provider::GitHub,
+            "admin:public_key",
+            "read:public_key"
+        ));
+        assert!(!grants(Provider::GitHub, "read:org", "admin:org"));
+
+        // GitLab folds everything into api once keys are registered
+        assert_eq!(
+            minimal_scopes(Provider::GitLab, &[Use::Repositories, Use::Rotation]),
+            scopes(&["self_rotate", "write_repository"])
+        );
+        assert_eq!(
+            minimal_scopes(
+                Provider::GitLab,
+                &[Use::Repositories, Use::GpgKeys, Use::Rotation]
+            ),
+            scopes(&["api"])
+        );
+        assert_eq!(
+            assess(
+                Provider::GitLab,
+                TokenKind::Classic,
+                &scopes(&["api", "sudo"]),
+                Some(now + 800 * DAY),
+                &[Use::Repositories, Use::Rotation],
+                now,
+            ),
+            vec![
+                Finding::Excess(scopes(&["api", "sudo"])),
+                Finding::LongLived(800),
+            ]
+        );
+
+        // Fine-grained tokens only get their expiry checked
+        assert_eq!(
+            TokenKind::of(Provider::GitHub, "github_pat_11ABC"),
+            TokenKind::FineGrained
+        );
+        assert_eq!(
+            assess(
+                Provider::GitHub,
+                TokenKind::FineGrained,
+                &[],
+                Some(now + DAY),
+                &[Use::Repositories],
+                now,
+            ),
+            vec![Finding::Unreported]
+        );
+
+        let audit = TokenAudit {
+            identity: "work".to_string(),
+            provider: Provider::GitLab,
+            hostname: "gitlab.com".to_string(),
+            kind: TokenKind::Classic,
+            scopes: scopes(&["api"]),
+            expires_at: None,
+            uses: vec![Use::Repositories],
+            findings: vec![],
+        };
+        assert_eq!(
+            audit.replacement_url(),
+            "https://gitlab.com/-/user_settings/personal_access_tokens\
+             ?name=remote-juggler-work&scopes=write_repository"
+        );
+        assert_eq!(audit.token_text(), "Personal access token · api");
+    }
+}
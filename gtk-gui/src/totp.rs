@@ -0,0 +1,282 @@
+//! TOTP codes for key store entries
+//!
+//! Entries whose value is an `otpauth://totp/...` URI (the format authenticator
+//! apps export and KeePassXC stores) yield RFC 6238 codes computed locally,
+//! so a 2FA prompt can be answered from the same window as the credential.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use percent_encoding::percent_decode_str;
+
+use crate::error::Error;
+
+/// Hash function used for the HMAC
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn parse(s: &str) -> Option<Self> {
+        match s.to_uppercase().as_str() {
+            "SHA1" => Some(Algorithm::Sha1),
+            "SHA256" => Some(Algorithm::Sha256),
+            "SHA512" => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+}
+
+/// Parameters of an otpauth:// TOTP URI
+#[derive(Clone, PartialEq, Eq)]
+pub struct Totp {
+    secret: Vec<u8>,
+    pub digits: u32,
+    /// Seconds each code is valid for
+    pub period: u64,
+    pub algorithm: Algorithm,
+    pub issuer: Option<String>,
+    pub account: Option<String>,
+}
+
+// Keep the shared secret out of logs
+impl std::fmt::Debug for Totp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Totp")
+            .field("digits", &self.digits)
+            .field("period", &self.period)
+            .field("algorithm", &self.algorithm)
+            .field("issuer", &self.issuer)
+            .field("account", &self.account)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns true if a value looks like an otpauth URI
+pub fn is_otpauth_uri(value: &str) -> bool {
+    value.trim().to_lowercase().starts_with("otpauth://")
+}
+
+/// Decode a base32 secret, tolerating lowercase, spaces, and padding
+pub fn decode_secret(secret: &str) -> Option<Vec<u8>> {
+    let cleaned: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=' && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if cleaned.is_empty() {
+        return None;
+    }
+    data_encoding::BASE32_NOPAD.decode(cleaned.as_bytes()).ok()
+}
+
+fn decode(s: &str) -> String {
+    percent_decode_str(&s.replace('+', " "))
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+impl Totp {
+    pub fn new(secret: Vec<u8>) -> Self {
+        Self {
+            secret,
+            digits: 6,
+            period: 30,
+            algorithm: Algorithm::Sha1,
+            issuer: None,
+            account: None,
+        }
+    }
+
+    /// Parse `otpauth://totp/Issuer:account?secret=...&issuer=...`
+    pub fn parse_uri(uri: &str) -> Result<Self, Error> {
+        let invalid = |message: &str| Error::InvalidOtpUri(message.to_string());
+        let uri = uri.trim();
+        let rest = uri
+            .get(..10)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("otpauth://"))
+            .map(|_| &uri[10..])
+            .ok_or_else(|| invalid("not an otpauth:// URI"))?;
+        let (kind, rest) = rest
+            .split_once('/')
+            .ok_or_else(|| invalid("missing label"))?;
+        if !kind.eq_ignore_ascii_case("totp") {
+            return Err(invalid("only TOTP is supported"));
+        }
+        let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        let label = decode(label);
+        let (label_issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (Some(issuer.trim().to_string()), account.trim()),
+            None => (None, label.trim()),
+        };
+
+        let mut secret = None;
+        let mut totp = Totp::new(Vec::new());
+        totp.account = (!account.is_empty()).then(|| account.to_string());
+        totp.issuer = label_issuer.filter(|i| !i.is_empty());
+
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = decode(value);
+            match key.to_lowercase().as_str() {
+                "secret" => secret = decode_secret(&value),
+                "digits" => {
+                    totp.digits = value
+                        .parse()
+                        .ok()
+                        .filter(|d| (6..=10).contains(d))
+                        .ok_or_else(|| invalid("digits must be 6 to 10"))?;
+                }
+                "period" => {
+                    totp.period = value
+                        .parse()
+                        .ok()
+                        .filter(|p| *p > 0)
+                        .ok_or_else(|| invalid("period must be a positive number"))?;
+                }
+                "algorithm" => {
+                    totp.algorithm =
+                        Algorithm::parse(&value).ok_or_else(|| invalid("unsupported algorithm"))?;
+                }
+                // The query parameter wins over the label prefix
+                "issuer" if !value.is_empty() => totp.issuer = Some(value),
+                _ => {}
+            }
+        }
+
+        totp.secret = secret.ok_or_else(|| invalid("missing or invalid secret"))?;
+        Ok(totp)
+    }
+
+    /// `Issuer (account)`, or whichever of the two is known
+    pub fn label(&self) -> String {
+        match (&self.issuer, &self.account) {
+            (Some(issuer), Some(account)) => format!("{} ({})", issuer, account),
+            (Some(name), None) | (None, Some(name)) => name.clone(),
+            (None, None) => "TOTP".to_string(),
+        }
+    }
+
+    fn hmac(&self, message: &[u8]) -> Vec<u8> {
+        fn compute<M: Mac + hmac::digest::KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+            // HMAC accepts keys of any length
+            let mut mac = <M as hmac::digest::KeyInit>::new_from_slice(key)
+                .expect("HMAC takes any key length");
+            mac.update(message);
+            mac.finalize().into_bytes().to_vec()
+        }
+        match self.algorithm {
+            Algorithm::Sha1 => compute::<Hmac<sha1::Sha1>>(&self.secret, message),
+            Algorithm::Sha256 => compute::<Hmac<sha2::Sha256>>(&self.secret, message),
+            Algorithm::Sha512 => compute::<Hmac<sha2::Sha512>>(&self.secret, message),
+        }
+    }
+
+    /// The code for a Unix timestamp
+    pub fn code_at(&self, unix_secs: u64) -> String {
+        let counter = unix_secs / self.period;
+        let digest = self.hmac(&counter.to_be_bytes());
+        // Dynamic truncation (RFC 4226 section 5.3)
+        let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            digest[offset] & 0x7f,
+            digest[offset + 1],
+            digest[offset + 2],
+            digest[offset + 3],
+        ]);
+        let code = u64::from(binary) % 10u64.pow(self.digits);
+        format!("{:0width$}", code, width = self.digits as usize)
+    }
+
+    /// Seconds until the code for `unix_secs` expires
+    pub fn remaining_at(&self, unix_secs: u64) -> u64 {
+        self.period - unix_secs % self.period
+    }
+
+    /// The current code and the seconds it stays valid for
+    pub fn now(&self) -> (String, u64) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        (self.code_at(now), self.remaining_at(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rfc6238(algorithm: Algorithm, secret: &[u8]) -> Totp {
+        Totp {
+            digits: 8,
+            algorithm,
+            ..Totp::new(secret.to_vec())
+        }
+    }
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        let sha1 = rfc6238(Algorithm::Sha1, b"12345678901234567890");
+        let sha256 = rfc6238(Algorithm::Sha256, b"12345678901234567890123456789012");
+        let sha512 = rfc6238(
+            Algorithm::Sha512,
+            b"1234567890123456789012345678901234567890123456789012345678901234",
+        );
+        assert_eq!(sha1.code_at(59), "94287082");
+        assert_eq!(sha1.code_at(1111111109), "07081804");
+        assert_eq!(sha1.code_at(20000000000), "65353130");
+        assert_eq!(sha256.code_at(59), "46119246");
+        assert_eq!(sha256.code_at(1234567890), "91819424");
+        assert_eq!(sha512.code_at(59), "90693936");
+        assert_eq!(sha512.code_at(2000000000), "38618901");
+    }
+
+    #[test]
+    fn test_parse_uri() {
+        let totp = Totp::parse_uri(
+            "otpauth://totp/ACME%20Co:jane@acme.dev?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=ACME+Co&digits=8&period=60&algorithm=SHA256",
+        )
+        .unwrap();
+        assert_eq!(totp.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(totp.account.as_deref(), Some("jane@acme.dev"));
+        assert_eq!(totp.digits, 8);
+        assert_eq!(totp.period, 60);
+        assert_eq!(totp.algorithm, Algorithm::Sha256);
+        assert_eq!(totp.label(), "ACME Co (jane@acme.dev)");
+        assert_eq!(totp.secret, b"12345678901234567890");
+    }
+
+    #[test]
+    fn test_parse_uri_defaults() {
+        let totp = Totp::parse_uri("otpauth://totp/github?secret=gezd gnbv gy3t qojq").unwrap();
+        assert_eq!(totp.digits, 6);
+        assert_eq!(totp.period, 30);
+        assert_eq!(totp.algorithm, Algorithm::Sha1);
+        assert_eq!(totp.label(), "github");
+        assert_eq!(totp.code_at(59).len(), 6);
+    }
+
+    #[test]
+    fn test_parse_uri_rejects_invalid() {
+        assert!(Totp::parse_uri("https://example.com").is_err());
+        assert!(Totp::parse_uri("otpauth://hotp/x?secret=GEZDGNBV&counter=1").is_err());
+        assert!(Totp::parse_uri("otpauth://totp/x?issuer=y").is_err());
+        assert!(Totp::parse_uri("otpauth://totp/x?secret=!!!").is_err());
+        assert!(Totp::parse_uri("otpauth://totp/x?secret=GEZDGNBV&digits=3").is_err());
+    }
+
+    #[test]
+    fn test_remaining() {
+        let totp = Totp::new(b"secret".to_vec());
+        assert_eq!(totp.remaining_at(0), 30);
+        assert_eq!(totp.remaining_at(59), 1);
+        assert!(is_otpauth_uri("  OTPAUTH://totp/x"));
+        assert!(!format!("{:?}", totp).contains("secret"));
+    }
+}
@@ -0,0 +1,237 @@
+//! TPM-sealed PINs and key store password
+//!
+//! In Trusted Workstation mode the CLI seals YubiKey PINs, and optionally
+//! the key store master password, with the TPM 2.0 through tpm2-tss, bound
+//! to PCR 7 (the Secure Boot state). A firmware or Secure Boot update
+//! changes that PCR, after which the sealed copies no longer unseal and
+//! have to be sealed again from the secret entered once more. This finds
+//! the TPM device natively and reads what `pin status --json` reports for
+//! each sealed secret.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::cli;
+use crate::error::Error;
+use crate::keyring;
+
+/// Environment variable the CLI reads a PIN to seal from
+pub const PIN_ENV: &str = "REMOTE_JUGGLER_PIN";
+
+/// TPM character devices, the kernel resource manager first
+const DEVICES: [&str; 2] = ["/dev/tpmrm0", "/dev/tpm0"];
+
+const SYSFS: &str = "/sys/class/tpm/tpm0";
+
+/// The TPM the kernel exposes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    pub path: PathBuf,
+    /// 2 for TPM 2.0; None on kernels without `tpm_version_major`
+    pub version_major: Option<u32>,
+}
+
+/// The TPM device, if there is one; Linux only
+pub fn device() -> Option<Device> {
+    let path = DEVICES.iter().map(Path::new).find(|p| p.exists())?;
+    let version_major = std::fs::read_to_string(Path::new(SYSFS).join("tpm_version_major"))
+        .ok()
+        .and_then(|v| v.trim().parse().ok());
+    Some(Device {
+        path: path.to_path_buf(),
+        version_major,
+    })
+}
+
+/// Whether a sealed secret still unseals
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SealState {
+    Ok,
+    /// The PCR values changed since sealing
+    PcrMismatch,
+    Failed,
+}
+
+/// A secret sealed by the CLI
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Sealed {
+    /// The identity, or `keyring::ACCOUNT` for the key store password
+    pub name: String,
+    #[serde(default)]
+    pub keystore: bool,
+    pub state: SealState,
+    #[serde(default)]
+    pub message: String,
+}
+
+impl Sealed {
+    pub fn display_name(&self) -> String {
+        if self.keystore {
+            "the key store password".to_string()
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+/// What `pin status --json` reports
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Status {
+    /// "TPM 2.0", "Secure Enclave", "Keychain" or "None"
+    pub backend: String,
+    pub available: bool,
+    /// The CLI was built with the tpm2-tss library
+    #[serde(default)]
+    pub native: bool,
+    /// What sealed secrets are bound to, e.g. "PCR 7 (Secure Boot state)"
+    #[serde(default)]
+    pub policy: String,
+    #[serde(default)]
+    pub sealed: Vec<Sealed>,
+}
+
+impl Status {
+    pub fn is_tpm(&self) -> bool {
+        self.backend == "TPM 2.0"
+    }
+
+    /// Sealed secrets that no longer unseal
+    pub fn needs_reseal(&self) -> Vec<&Sealed> {
+        self.sealed
+            .iter()
+            .filter(|s| s.state != SealState::Ok)
+            .collect()
+    }
+
+    pub fn keystore_sealed(&self) -> bool {
+        self.sealed.iter().any(|s| s.keystore)
+    }
+
+    pub fn display_text(&self, device: Option<&Device>) -> String {
+        let found = match device {
+            Some(device) if device.version_major == Some(1) => {
+                format!("TPM 1.2 at {}, which can't seal", device.path.display())
+            }
+            Some(device) => format!("TPM 2.0 at {}", device.path.display()),
+            None => "No TPM found".to_string(),
+        };
+        if !self.is_tpm() {
+            return match (device, self.available) {
+                (Some(_), _) if !self.native => {
+                    format!("{}, but the CLI was built without TPM support", found)
+                }
+                (_, true) => format!("{}; PINs are kept in the {}", found, self.backend),
+                (_, false) => format!("{}; Trusted Workstation mode needs one", found),
+            };
+        }
+        let mut text = found;
+        if !self.policy.is_empty() {
+            text.push_str(&format!(", sealing to {}", self.policy));
+        }
+        let stale: Vec<String> = self
+            .needs_reseal()
+            .iter()
+            .map(|s| s.display_name())
+            .collect();
+        if !stale.is_empty() {
+            text.push_str(&format!(
+                "; {} can't be unsealed since firmware or Secure Boot changed, reseal",
+                stale.join(", ")
+            ));
+        } else if self.sealed.is_empty() {
+            text.push_str("; nothing sealed yet");
+        } else {
+            text.push_str(&format!("; {} sealed", self.sealed.len()));
+        }
+        text
+    }
+}
+
+/// Parse the output of `pin status --json`
+pub fn parse_status(output: &str) -> Result<Status, Error> {
+    cli::reported_error(output).map_err(Error::from_cli)?;
+    let json = output
+        .lines()
+        .find(|l| l.trim_start().starts_with('{'))
+        .unwrap_or_default();
+    serde_json::from_str(json).map_err(|e| Error::InvalidOutput {
+        program: cli::CLI_PROGRAM,
+        message: e.to_string(),
+    })
+}
+
+/// The CLI's HSM backend and sealed secrets; blocks, and unseals each
+/// secret to check it
+pub fn status() -> Result<Status, Error> {
+    let output = cli::run(&cli::args(&["pin", "status", "--json"])).map_err(Error::from_cli)?;
+    parse_status(&output)
+}
+
+fn run_reporting(args: &[String], env: &[(&str, &str)]) -> Result<(), Error> {
+    let output = cli::run_with_env(args, env).map_err(Error::from_cli)?;
+    cli::reported_error(&output).map_err(Error::from_cli)
+}
+
+/// Seal the key store master password; without `password`, the one the
+/// GUI unlocked the key store with is used. Blocks
+pub fn seal_keystore(password: Option<&str>) -> Result<(), Error> {
+    let env: Vec<(&str, &str)> = password
+        .map(|p| (keyring::MASTER_PASSWORD_ENV, p))
+        .into_iter()
+        .collect();
+    run_reporting(&cli::args(&["pin", "store", "--keystore"]), &env)
+}
+
+/// Seal a secret again under the current PCR values. One that still
+/// unseals is resealed as is; otherwise `secret`, the PIN or for the key
+/// store the master password, is sealed in its place. Blocks
+pub fn reseal(sealed: &Sealed, secret: Option<&str>) -> Result<(), Error> {
+    let (target, variable) = if sealed.keystore {
+        ("--keystore", keyring::MASTER_PASSWORD_ENV)
+    } else {
+        (sealed.name.as_str(), PIN_ENV)
+    };
+    let env: Vec<(&str, &str)> = secret.map(|s| (variable, s)).into_iter().collect();
+    run_reporting(&cli::args(&["pin", "reseal", target]), &env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        let status = parse_status(
+            "{\"backend\":\"TPM 2.0\",\"available\":true,\"native\":true,\
+             \"policy\":\"PCR 7 (Secure Boot state)\",\"sealed\":[\
+             {\"name\":\"gitlab-work\",\"keystore\":false,\"state\":\"ok\",\"message\":\"\"},\
+             {\"name\":\"kdbx-master\",\"keystore\":true,\"state\":\"pcr_mismatch\",\
+             \"message\":\"Platform state changed\"}]}\n",
+        )
+        .unwrap();
+        assert!(status.is_tpm());
+        assert!(status.keystore_sealed());
+        assert_eq!(status.needs_reseal()[0].name, keyring::ACCOUNT);
+        let device = Device {
+            path: PathBuf::from("/dev/tpmrm0"),
+            version_major: Some(2),
+        };
+        assert_eq!(
+            status.display_text(Some(&device)),
+            "TPM 2.0 at /dev/tpmrm0, sealing to PCR 7 (Secure Boot state); the key store \
+             password can't be unsealed since firmware or Secure Boot changed, reseal"
+        );
+
+        // A CLI built without tpm2-tss falls back to the keychain
+        let stub = parse_status(
+            "{\"backend\":\"Keychain\",\"available\":true,\"native\":false,\"policy\":\"\",\"sealed\":[]}",
+        )
+        .unwrap();
+        assert!(stub
+            .display_text(Some(&device))
+            .ends_with("without TPM support"));
+        assert!(parse_status("[ERROR] Unknown pin subcommand: status").is_err());
+    }
+}
@@ -0,0 +1,312 @@
+//! Managed insteadOf rules in ~/.gitconfig
+//!
+//! Each identity's organizations become `url.<alias>.insteadOf` rules, so
+//! cloning `git@github.com:acme/api.git` goes through the host alias of
+//! the identity owning acme, and its key, without editing the URL. The
+//! organizations are the ones detection already matches remotes with.
+//! HTTPS URLs are left alone. Rules written by hand elsewhere in the file
+//! that cover the same prefix are reported, since git uses the longest
+//! matching prefix whoever wrote it. The block is reviewed and written
+//! like the includes block.
+
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::gitconfig;
+use crate::ssh_config::{self, BlockState, Markers};
+
+pub const BEGIN_MARKER: &str = "# BEGIN RemoteJuggler managed URL rewrites";
+pub const END_MARKER: &str = "# END RemoteJuggler managed URL rewrites";
+
+pub const MARKERS: Markers = Markers {
+    begin: BEGIN_MARKER,
+    end: END_MARKER,
+};
+
+/// One organization routed through an identity's host alias
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rewrite {
+    pub identity: String,
+    pub organization: String,
+    /// e.g. "git@github-work:acme/"
+    pub base: String,
+    /// The prefixes replaced by `base`
+    pub prefixes: Vec<String>,
+}
+
+/// The rules for every identity's organizations, by identity; the second
+/// list names organizations another identity on the same hostname already
+/// claimed
+pub fn rewrites(config: &Config) -> (Vec<Rewrite>, Vec<String>) {
+    let mut rewrites: Vec<Rewrite> = Vec::new();
+    let mut duplicates = Vec::new();
+    for name in config.identity_names() {
+        let identity = &config.identities[&name];
+        let hostname = identity.hostname.to_lowercase();
+        if identity.host.is_empty() || hostname.is_empty() || identity.host == hostname {
+            continue;
+        }
+        for org in &identity.organizations {
+            let org = org.trim_matches('/');
+            if org.is_empty() {
+                continue;
+            }
+            let prefixes = vec![
+                format!("git@{}:{}/", hostname, org),
+                format!("ssh://git@{}/{}/", hostname, org),
+            ];
+            if let Some(other) = rewrites.iter().find(|r| r.prefixes == prefixes) {
+                duplicates.push(format!("{} ({} and {})", org, other.identity, name));
+                continue;
+            }
+            rewrites.push(Rewrite {
+                identity: name.clone(),
+                organization: org.to_string(),
+                base: format!("git@{}:{}/", identity.host, org),
+                prefixes,
+            });
+        }
+    }
+    (rewrites, duplicates)
+}
+
+/// The `[url]` sections for the rules
+pub fn render_body(rewrites: &[Rewrite]) -> String {
+    rewrites
+        .iter()
+        .map(|rewrite| {
+            let values: String = rewrite
+                .prefixes
+                .iter()
+                .map(|prefix| format!("\tinsteadOf = {}\n", gitconfig::quote(prefix)))
+                .collect();
+            format!(
+                "# {}: {}\n[url \"{}\"]\n{}",
+                rewrite.identity, rewrite.organization, rewrite.base, values
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// An insteadOf rule written outside the managed block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandRule {
+    pub base: String,
+    pub prefix: String,
+}
+
+/// The insteadOf rules in `text` outside the managed block
+pub fn hand_written(text: &str) -> Vec<HandRule> {
+    let mut rules = Vec::new();
+    let mut base: Option<String> = None;
+    for line in ssh_config::outside_managed(text, MARKERS) {
+        let line = line.trim();
+        if line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header.split(']').next().unwrap_or_default().trim();
+            base = header
+                .strip_prefix("url")
+                .map(str::trim)
+                .filter(|rest| rest.starts_with('"'))
+                .map(|rest| rest.trim_matches('"').to_string());
+            continue;
+        }
+        let (Some(base), Some((key, value))) = (&base, line.split_once('=')) else {
+            continue;
+        };
+        if key.trim().eq_ignore_ascii_case("insteadOf") {
+            rules.push(HandRule {
+                base: base.clone(),
+                prefix: value.trim().trim_matches('"').to_string(),
+            });
+        }
+    }
+    rules
+}
+
+/// How a hand-written rule collides with a managed one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Both replace the same prefix
+    Same,
+    /// The hand-written prefix is longer, so it wins for part of the
+    /// organization
+    Shadows,
+}
+
+/// A hand-written rule colliding with a managed prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    /// The managed prefix
+    pub prefix: String,
+    pub hand: HandRule,
+}
+
+impl Conflict {
+    pub fn display_text(&self) -> String {
+        match self.kind {
+            ConflictKind::Same => format!(
+                "{} is also rewritten by hand to {}",
+                self.prefix, self.hand.base
+            ),
+            ConflictKind::Shadows => format!(
+                "{} is rewritten by hand to {}, overriding the managed rule for {}",
+                self.hand.prefix, self.hand.base, self.prefix
+            ),
+        }
+    }
+}
+
+/// Hand-written rules that collide with the managed ones
+pub fn conflicts(rewrites: &[Rewrite], hand: &[HandRule]) -> Vec<Conflict> {
+    let mut found = Vec::new();
+    for rewrite in rewrites {
+        for prefix in &rewrite.prefixes {
+            for rule in hand {
+                let kind = if rule.prefix == *prefix {
+                    ConflictKind::Same
+                } else if rule.prefix.starts_with(prefix.as_str()) {
+                    ConflictKind::Shadows
+                } else {
+                    continue;
+                };
+                found.push(Conflict {
+                    kind,
+                    prefix: prefix.clone(),
+                    hand: rule.clone(),
+                });
+            }
+        }
+    }
+    found
+}
+
+/// A reviewed change to ~/.gitconfig's URL rewrites
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub path: PathBuf,
+    /// The file as it was reviewed; empty if it doesn't exist
+    pub current: String,
+    pub proposed: String,
+    pub state: BlockState,
+    pub conflicts: Vec<Conflict>,
+    /// Organizations claimed by two identities, left to the first
+    pub duplicates: Vec<String>,
+}
+
+impl Plan {
+    pub fn is_noop(&self) -> bool {
+        self.current == self.proposed
+    }
+
+    pub fn diff(&self) -> String {
+        ssh_config::render_diff(&ssh_config::diff(&self.current, &self.proposed), 2)
+    }
+}
+
+/// Work out what writing the rules to `path` would change
+pub fn plan_for(config: &Config, path: &Path) -> std::io::Result<Plan> {
+    let current = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let (rewrites, duplicates) = rewrites(config);
+    let state = ssh_config::managed_state(&current, MARKERS);
+    let proposed = if state == BlockState::Unterminated {
+        current.clone()
+    } else {
+        let block = ssh_config::render_managed(MARKERS, &render_body(&rewrites));
+        ssh_config::apply_managed(&current, &block, MARKERS)
+    };
+    Ok(Plan {
+        path: path.to_path_buf(),
+        conflicts: conflicts(&rewrites, &hand_written(&current)),
+        current,
+        proposed,
+        state,
+        duplicates,
+    })
+}
+
+/// [`plan_for`] the user's ~/.gitconfig
+pub fn plan(config: &Config) -> std::io::Result<Plan> {
+    let path = gitconfig::path().ok_or_else(|| std::io::Error::other("No home directory"))?;
+    plan_for(config, &path)
+}
+
+/// Write a reviewed plan; fails like [`ssh_config::write`] if the file
+/// changed since it was reviewed
+pub fn write(plan: &Plan) -> std::io::Result<()> {
+    ssh_config::write_reviewed(
+        &plan.path,
+        plan.state,
+        &plan.current,
+        &plan.proposed,
+        "~/.gitconfig",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::config::Identity;
+
+    #[test]
+    fn test_plan_rewrites_and_conflicts() {
+        let config = ConfigBuilder::new()
+            .identity(
+                "github-work",
+                Identity {
+                    organizations: vec!["acme".to_string(), "acme-labs/".to_string()],
+                    ..identity("github", "github-work", "github.com", "Work", "me@acme.dev")
+                },
+            )
+            .identity(
+                "github-x",
+                Identity {
+                    organizations: vec!["acme".to_string()],
+                    ..identity("github", "github-x", "github.com", "X", "x@example.com")
+                },
+            )
+            .build();
+        let (rules, duplicates) = rewrites(&config);
+        assert_eq!(rules.len(), 2);
+        assert_eq!(duplicates, vec!["acme (github-work and github-x)"]);
+        assert_eq!(
+            render_body(&rules[..1]),
+            "# github-work: acme\n[url \"git@github-work:acme/\"]\n\
+             \tinsteadOf = git@github.com:acme/\n\
+             \tinsteadOf = ssh://git@github.com/acme/\n"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".gitconfig");
+        std::fs::write(
+            &path,
+            "[url \"git@github-personal:acme/dotfiles\"]\n\
+             \tinsteadOf = git@github.com:acme/dotfiles\n\
+             [url \"https://mirror.example/\"]\n\tinsteadof = https://github.com/\n",
+        )
+        .unwrap();
+        let plan = plan_for(&config, &path).unwrap();
+        assert_eq!(plan.state, BlockState::Missing);
+        assert_eq!(plan.conflicts.len(), 1);
+        assert_eq!(plan.conflicts[0].kind, ConflictKind::Shadows);
+        assert!(plan
+            .proposed
+            .contains("[url \"git@github-work:acme-labs/\"]"));
+
+        write(&plan).unwrap();
+        let again = plan_for(&config, &path).unwrap();
+        assert_eq!(again.state, BlockState::Intact);
+        assert!(again.is_noop());
+        // The managed rules aren't hand-written ones
+        assert_eq!(hand_written(&again.current).len(), 2);
+    }
+}
@@ -0,0 +1,498 @@
+//! Commit verification as the provider shows it
+//!
+//! A commit git verifies locally can still show as unverified on the
+//! provider: its key was never uploaded (or, on GitHub, not as a signing
+//! key), or its email isn't one of the account's. This asks the provider
+//! about the repository's recent pushed commits, from the branch's
+//! upstream, and attributes each unverified one to the identity
+//! committing with its email. From the reason the provider gives it
+//! suggests the fix: uploading the identity's GPG or SSH signing key, or
+//! turning signing on. GitHub and Gitea report verification with the
+//! commit list; GitLab has a signature call per commit. Bitbucket and
+//! sourcehut don't verify signatures.
+
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, Identity, SigningFormat};
+use crate::detect::{self, RemoteUrl};
+use crate::error::{CliError, Error};
+use crate::gpg;
+use crate::gpg_publish;
+use crate::provider_api;
+use crate::provider_hosts;
+use crate::pubkeys::{self, Account, Provider};
+use crate::workspace;
+
+/// How many recent commits are asked about
+pub const RECENT: usize = 10;
+
+/// A commit's verification on the provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Verified,
+    Unsigned,
+    /// The signing key isn't on the committer's account
+    UnknownKey,
+    /// The committer email isn't a verified email of the key's account
+    UnverifiedEmail,
+    /// The key has expired or been revoked
+    BadKey,
+    /// Any other reason, as the provider gives it
+    Invalid(String),
+}
+
+impl Status {
+    pub fn display_text(&self) -> String {
+        match self {
+            Self::Verified => "verified".to_string(),
+            Self::Unsigned => "unsigned".to_string(),
+            Self::UnknownKey => "signing key not on the account".to_string(),
+            Self::UnverifiedEmail => "email not verified on the account".to_string(),
+            Self::BadKey => "key expired or revoked".to_string(),
+            Self::Invalid(reason) => format!("unverified ({})", reason),
+        }
+    }
+
+    /// GitHub's `verification.reason`
+    pub fn from_github(verified: bool, reason: &str) -> Self {
+        if verified {
+            return Self::Verified;
+        }
+        match reason {
+            "unsigned" => Self::Unsigned,
+            "unknown_key" | "not_signing_key" => Self::UnknownKey,
+            "unverified_email" | "bad_email" | "no_user" => Self::UnverifiedEmail,
+            "expired_key" => Self::BadKey,
+            _ => Self::Invalid(reason.to_string()),
+        }
+    }
+
+    /// Gitea's `verification.reason`, a translation key such as
+    /// `gpg.error.not_signed_commit`
+    pub fn from_gitea(verified: bool, reason: &str) -> Self {
+        if verified {
+            return Self::Verified;
+        }
+        if reason.contains("not_signed") {
+            Self::Unsigned
+        } else if reason.contains("no_gpg_keys_found") || reason.contains("no_ssh_keys_found") {
+            Self::UnknownKey
+        } else if reason.contains("no_committer_account") || reason.contains("email") {
+            Self::UnverifiedEmail
+        } else {
+            Self::Invalid(reason.to_string())
+        }
+    }
+
+    /// GitLab's `verification_status`; None when the commit has no
+    /// signature
+    pub fn from_gitlab(status: Option<&str>) -> Self {
+        match status {
+            None => Self::Unsigned,
+            Some("verified" | "verified_system") => Self::Verified,
+            Some("unknown_key" | "unverified_key") => Self::UnknownKey,
+            Some("same_user_different_email" | "other_user") => Self::UnverifiedEmail,
+            Some("revoked_key") => Self::BadKey,
+            Some(other) => Self::Invalid(other.to_string()),
+        }
+    }
+}
+
+/// One pushed commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub sha: String,
+    pub email: String,
+    pub status: Status,
+    /// The identity committing with `email`
+    pub identity: Option<String>,
+}
+
+impl Commit {
+    pub fn short_sha(&self) -> &str {
+        &self.sha[..self.sha.len().min(7)]
+    }
+}
+
+/// Parse a GitHub or Gitea commit list, which carry their verification
+pub fn parse_commits(provider: Provider, body: &Value) -> Vec<Commit> {
+    body.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| {
+            let commit = &item["commit"];
+            let verification = &commit["verification"];
+            let verified = verification["verified"].as_bool().unwrap_or(false);
+            let reason = verification["reason"].as_str().unwrap_or_default();
+            Some(Commit {
+                sha: item["sha"].as_str()?.to_string(),
+                email: commit["committer"]["email"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                status: match provider {
+                    Provider::Gitea => Status::from_gitea(verified, reason),
+                    _ => Status::from_github(verified, reason),
+                },
+                identity: None,
+            })
+        })
+        .collect()
+}
+
+/// What would make an identity's commits show as verified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fix {
+    UploadGpgKey,
+    UploadSigningKey,
+    EnableSigning,
+}
+
+impl Fix {
+    pub fn display_text(&self) -> &'static str {
+        match self {
+            Self::UploadGpgKey => "Upload the GPG key",
+            Self::UploadSigningKey => "Upload the SSH signing key",
+            Self::EnableSigning => "Sign commits",
+        }
+    }
+
+    pub fn done_text(&self) -> &'static str {
+        match self {
+            Self::UploadGpgKey => "GPG key uploaded",
+            Self::UploadSigningKey => "SSH signing key uploaded",
+            Self::EnableSigning => "commit signing turned on",
+        }
+    }
+}
+
+/// A fix for one identity, or what to do by hand
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub identity: String,
+    pub fix: Option<Fix>,
+    pub detail: String,
+}
+
+/// The fix for an identity's commit, given why it isn't verified
+pub fn suggest(name: &str, identity: &Identity, status: &Status) -> Option<Suggestion> {
+    let (fix, detail) = match status {
+        Status::Verified => return None,
+        Status::Unsigned if identity.gpg.sign_commits => (
+            None,
+            "Signing is on; commits made before it stay unsigned".to_string(),
+        ),
+        Status::Unsigned
+            if identity.signing_key().is_none()
+                && identity.gpg.format != SigningFormat::Gitsign =>
+        {
+            (
+                None,
+                "Set up a GPG or SSH signing key for the identity first".to_string(),
+            )
+        }
+        Status::Unsigned => (
+            Some(Fix::EnableSigning),
+            "Commits aren't signed".to_string(),
+        ),
+        Status::UnknownKey => match identity.gpg.format {
+            SigningFormat::Gpg => (
+                Some(Fix::UploadGpgKey),
+                "The GPG key isn't on the account".to_string(),
+            ),
+            SigningFormat::Ssh => (
+                Some(Fix::UploadSigningKey),
+                "The SSH key isn't on the account as a signing key".to_string(),
+            ),
+            SigningFormat::Gitsign => (
+                None,
+                "The provider doesn't verify gitsign signatures".to_string(),
+            ),
+        },
+        Status::UnverifiedEmail => (
+            None,
+            format!("Add and verify {} on the account", identity.email),
+        ),
+        Status::BadKey => (None, "Renew the key, then upload it again".to_string()),
+        Status::Invalid(reason) => (None, format!("The provider says: {}", reason)),
+    };
+    Some(Suggestion {
+        identity: name.to_string(),
+        fix,
+        detail,
+    })
+}
+
+/// The provider's view of a repository's recent commits
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub commits: Vec<Commit>,
+    /// One per identity and fix
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Report {
+    pub fn summary(&self) -> String {
+        let verified = self
+            .commits
+            .iter()
+            .filter(|c| c.status == Status::Verified)
+            .count();
+        format!(
+            "{} of {} recent commits verified",
+            verified,
+            self.commits.len()
+        )
+    }
+}
+
+/// Attribute the commits to identities and collect the fixes
+pub fn correlate(config: &Config, mut commits: Vec<Commit>) -> Report {
+    let mut suggestions: Vec<Suggestion> = Vec::new();
+    for commit in &mut commits {
+        commit.identity = workspace::identity_for_email(config, &commit.email);
+        let Some(name) = &commit.identity else {
+            continue;
+        };
+        if let Some(suggestion) = suggest(name, &config.identities[name], &commit.status) {
+            if !suggestions.contains(&suggestion) {
+                suggestions.push(suggestion);
+            }
+        }
+    }
+    Report {
+        commits,
+        suggestions,
+    }
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<String, CliError> {
+    let mut all = vec!["-C".to_string(), repo.to_string_lossy().into_owned()];
+    all.extend(cli::args(args));
+    let options = RunOptions {
+        timeout: Duration::from_secs(10),
+        retries: 0,
+        cancel: None,
+    };
+    cli::run_program("git", &all, &options)
+}
+
+/// The pushed commit to start from: the branch's upstream, else HEAD
+fn pushed_head(repo: &Path) -> Result<String, Error> {
+    git(repo, &["rev-parse", "--verify", "-q", "@{upstream}"])
+        .or_else(|_| git(repo, &["rev-parse", "--verify", "HEAD"]))
+        .map(|sha| sha.trim().to_string())
+        .map_err(Error::from_cli)
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::InvalidOutput {
+        program: "verification",
+        message: message.into(),
+    }
+}
+
+/// Ask the provider about the repository's recent pushed commits, as the
+/// identity detected for it; blocks
+pub fn check(config: &Config, repo: &Path) -> Result<Report, Error> {
+    let found = detect::detect(config, repo)?
+        .ok_or_else(|| invalid("no identity matches the repository's remotes"))?;
+    if found.remote.is_empty() {
+        return Err(invalid(format!(
+            "{} applies by a rule; no remote to ask",
+            found.identity
+        )));
+    }
+    let remote = RemoteUrl::parse(&found.url)
+        .ok_or_else(|| invalid(format!("can't read the remote URL {}", found.url)))?;
+    let identity = &config.identities[&found.identity];
+    let provider = Provider::for_identity(identity)
+        .ok_or_else(|| invalid(format!("{} has no provider API", identity.provider)))?;
+    let token = pubkeys::find_token(&found.identity, identity, provider)?;
+    let base =
+        provider_hosts::api_base(&provider_hosts::configured(), provider, &identity.hostname);
+    let client = provider_api::Client::new(provider, &base, Some(&token));
+    let sha = pushed_head(repo)?;
+
+    let commits = match provider {
+        Provider::GitHub => parse_commits(
+            provider,
+            &client.get_json(&format!(
+                "{}/repos/{}/commits?sha={}&per_page={}",
+                base, remote.path, sha, RECENT
+            ))?,
+        ),
+        Provider::Gitea => parse_commits(
+            provider,
+            &client.get_json(&format!(
+                "{}/repos/{}/commits?sha={}&limit={}&stat=false",
+                base, remote.path, sha, RECENT
+            ))?,
+        ),
+        Provider::GitLab => {
+            let project = percent_encoding::utf8_percent_encode(
+                &remote.path,
+                percent_encoding::NON_ALPHANUMERIC,
+            )
+            .to_string();
+            let list = client.get_json(&format!(
+                "{}/projects/{}/repository/commits?ref_name={}&per_page={}",
+                base, project, sha, RECENT
+            ))?;
+            let mut commits = Vec::new();
+            for item in list.as_array().into_iter().flatten() {
+                let Some(id) = item["id"].as_str() else {
+                    continue;
+                };
+                let signature = match client.get_json(&format!(
+                    "{}/projects/{}/repository/commits/{}/signature",
+                    base, project, id
+                )) {
+                    Ok(signature) => Some(signature),
+                    Err(Error::Http { status: 404, .. }) => None,
+                    Err(e) => return Err(e),
+                };
+                commits.push(Commit {
+                    sha: id.to_string(),
+                    email: item["committer_email"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    status: Status::from_gitlab(
+                        signature
+                            .as_ref()
+                            .map(|s| s["verification_status"].as_str().unwrap_or("unverified")),
+                    ),
+                    identity: None,
+                });
+            }
+            commits
+        }
+        Provider::Bitbucket | Provider::Sourcehut => {
+            return Err(invalid(format!(
+                "{} doesn't verify commit signatures",
+                provider.display_name()
+            )))
+        }
+    };
+    Ok(correlate(config, commits))
+}
+
+/// Apply a fix for the identity; turning signing on also writes it to the
+/// repository's git config. Blocks
+pub fn apply(repo: &Path, name: &str, fix: Fix) -> Result<(), Error> {
+    let mut config = Config::load()?;
+    let identity = config
+        .identities
+        .get_mut(name)
+        .ok_or_else(|| invalid(format!("no identity {}", name)))?;
+    let title = pubkeys::default_title(name);
+    match fix {
+        Fix::UploadGpgKey => {
+            let key = gpg_publish::signing_key(name, identity)?;
+            let armored = gpg::export_public(&key.fingerprint)?;
+            Account::for_identity(name, identity)?.upload_gpg_key(&title, &armored)
+        }
+        Fix::UploadSigningKey => pubkeys::upload_signing(name, identity, &title),
+        Fix::EnableSigning => {
+            identity.gpg.sign_commits = true;
+            let identity = identity.clone();
+            config.save()?;
+            workspace::apply_identity(repo, &identity)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::config::GpgConfig;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_and_correlate() {
+        let config = ConfigBuilder::new()
+            .identity(
+                "github-work",
+                Identity {
+                    ssh_key_path: "~/.ssh/id_work".to_string(),
+                    gpg: GpgConfig {
+                        format: SigningFormat::Ssh,
+                        ..Default::default()
+                    },
+                    ..identity("github", "github-work", "github.com", "Work", "me@acme.dev")
+                },
+            )
+            .identity(
+                "github-personal",
+                Identity {
+                    ssh_key_path: "~/.ssh/id_me".to_string(),
+                    gpg: GpgConfig {
+                        key_id: "AAAA1111".to_string(),
+                        sign_commits: true,
+                        ..Default::default()
+                    },
+                    ..identity(
+                        "github",
+                        "github-personal",
+                        "github.com",
+                        "Me",
+                        "me@example.com",
+                    )
+                },
+            )
+            .build();
+        let body = json!([
+            { "sha": "1111111aaaa", "commit": {
+                "committer": { "email": "me@acme.dev" },
+                "verification": { "verified": false, "reason": "not_signing_key" } } },
+            { "sha": "2222222bbbb", "commit": {
+                "committer": { "email": "me@acme.dev" },
+                "verification": { "verified": false, "reason": "unsigned" } } },
+            { "sha": "3333333cccc", "commit": {
+                "committer": { "email": "me@example.com" },
+                "verification": { "verified": true, "reason": "valid" } } },
+            { "sha": "4444444dddd", "commit": {
+                "committer": { "email": "me@example.com" },
+                "verification": { "verified": false, "reason": "unsigned" } } },
+            { "sha": "5555555eeee", "commit": {
+                "committer": { "email": "bot@ci.dev" },
+                "verification": { "verified": false, "reason": "unsigned" } } },
+        ]);
+        let commits = parse_commits(Provider::GitHub, &body);
+        assert_eq!(commits[0].status, Status::UnknownKey);
+        assert_eq!(commits[0].short_sha(), "1111111");
+        let report = correlate(&config, commits);
+        assert_eq!(report.summary(), "1 of 5 recent commits verified");
+        assert_eq!(report.commits[4].identity, None);
+        let fixes: Vec<_> = report
+            .suggestions
+            .iter()
+            .map(|s| (s.identity.as_str(), s.fix))
+            .collect();
+        assert_eq!(
+            fixes,
+            vec![
+                ("github-work", Some(Fix::UploadSigningKey)),
+                ("github-work", Some(Fix::EnableSigning)),
+                // Already signing; the old commit stays unsigned
+                ("github-personal", None),
+            ]
+        );
+
+        assert_eq!(
+            Status::from_gitea(false, "gpg.error.not_signed_commit"),
+            Status::Unsigned
+        );
+        assert_eq!(
+            Status::from_gitlab(Some("other_user")),
+            Status::UnverifiedEmail
+        );
+        assert_eq!(Status::from_gitlab(None), Status::Unsigned);
+    }
+}
@@ -0,0 +1,452 @@
+//! End-to-end identity verification
+//!
+//! A dry run of everything a push as the identity depends on, one check at
+//! a time: the config entry, how ssh resolves the host alias, the agent,
+//! the key's registration upstream, commit signing, and finally a
+//! `git ls-remote` through the alias. Nothing is changed.
+
+use std::time::Duration;
+
+use crate::agent;
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, Identity, IdentityAgent, SigningFormat, SshKeyType};
+use crate::databases::expand_tilde;
+use crate::error::Error;
+use crate::gitsign::{self, FulcioIdentity};
+use crate::platform;
+use crate::pubkeys::{self, key_id, Registration};
+use crate::ssh_config::{self, BlockState};
+
+/// A verification check, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Config,
+    SshConfig,
+    Agent,
+    Registration,
+    Signing,
+    LsRemote,
+}
+
+impl Step {
+    pub const ALL: [Step; 6] = [
+        Step::Config,
+        Step::SshConfig,
+        Step::Agent,
+        Step::Registration,
+        Step::Signing,
+        Step::LsRemote,
+    ];
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::Config => "Identity Config",
+            Self::SshConfig => "SSH Config",
+            Self::Agent => "SSH Agent",
+            Self::Registration => "Provider Key",
+            Self::Signing => "Commit Signing",
+            Self::LsRemote => "Repository Access",
+        }
+    }
+}
+
+/// How a check went
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail,
+    /// Not applicable to this identity
+    Skipped,
+}
+
+impl Outcome {
+    pub fn icon_name(&self) -> &'static str {
+        match self {
+            Self::Pass => "emblem-ok-symbolic",
+            Self::Fail => "dialog-error-symbolic",
+            Self::Skipped => "action-unavailable-symbolic",
+        }
+    }
+}
+
+/// A check's result and what it found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub step: Step,
+    pub outcome: Outcome,
+    pub detail: String,
+}
+
+fn pass(step: Step, detail: impl Into<String>) -> Check {
+    Check {
+        step,
+        outcome: Outcome::Pass,
+        detail: detail.into(),
+    }
+}
+
+fn fail(step: Step, detail: impl Into<String>) -> Check {
+    Check {
+        step,
+        outcome: Outcome::Fail,
+        detail: detail.into(),
+    }
+}
+
+fn skipped(step: Step, detail: impl Into<String>) -> Check {
+    Check {
+        step,
+        outcome: Outcome::Skipped,
+        detail: detail.into(),
+    }
+}
+
+fn options(timeout: Duration) -> RunOptions {
+    RunOptions {
+        timeout,
+        retries: 0,
+        cancel: None,
+    }
+}
+
+/// Hostname and identity files from `ssh -G` output
+pub fn parse_resolved(text: &str) -> (Option<String>, Vec<String>) {
+    let mut hostname = None;
+    let mut identity_files = Vec::new();
+    for line in text.lines() {
+        match line.split_once(' ') {
+            Some(("hostname", value)) => hostname = Some(value.trim().to_string()),
+            Some(("identityfile", value)) => identity_files.push(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    (hostname, identity_files)
+}
+
+/// The URL `git ls-remote` is pointed at: `owner/repo` goes through the
+/// identity's host alias, anything with a scheme or host is used as is
+pub fn repo_url(identity: &Identity, repo: &str) -> String {
+    if repo.contains("://") || repo.contains(':') {
+        repo.to_string()
+    } else {
+        format!("{}:{}", identity.host, repo)
+    }
+}
+
+/// Run one check for the identity `name`; blocks
+pub fn run_check(config: &Config, name: &str, step: Step) -> Check {
+    let Some(identity) = config.identities.get(name) else {
+        return fail(step, format!("No identity named {}", name));
+    };
+    match step {
+        Step::Config => check_config(identity),
+        Step::SshConfig => check_ssh_config(config, identity),
+        Step::Agent => check_agent(config, identity),
+        Step::Registration => check_registration(name, identity),
+        Step::Signing => check_signing(identity),
+        Step::LsRemote => check_ls_remote(identity),
+    }
+}
+
+fn check_config(identity: &Identity) -> Check {
+    let step = Step::Config;
+    let mut missing = Vec::new();
+    if identity.host.is_empty() {
+        missing.push("host");
+    }
+    if identity.hostname.is_empty() {
+        missing.push("hostname");
+    }
+    if identity.user.is_empty() {
+        missing.push("user");
+    }
+    if identity.email.is_empty() {
+        missing.push("email");
+    }
+    if !missing.is_empty() {
+        return fail(step, format!("Missing {}", missing.join(", ")));
+    }
+    if let Some(library) = identity.pkcs11_library() {
+        return if std::path::Path::new(library).exists() {
+            pass(step, format!("Smart card via {}", library))
+        } else {
+            fail(step, format!("PKCS#11 library {} not found", library))
+        };
+    }
+    if identity.ssh_key_path.is_empty() {
+        return fail(step, "No SSH key configured");
+    }
+    if !expand_tilde(&identity.ssh_key_path).exists() {
+        return fail(
+            step,
+            format!("Key file {} not found", identity.ssh_key_path),
+        );
+    }
+    pass(
+        step,
+        format!(
+            "{} <{}>, key {}",
+            identity.user, identity.email, identity.ssh_key_path
+        ),
+    )
+}
+
+fn check_ssh_config(config: &Config, identity: &Identity) -> Check {
+    let step = Step::SshConfig;
+    let plan = match ssh_config::plan(config) {
+        Ok(plan) => plan,
+        Err(e) => return fail(step, e.to_string()),
+    };
+    match plan.state {
+        BlockState::Edited | BlockState::Unterminated => {
+            return fail(step, plan.state.display_text());
+        }
+        BlockState::Intact if !plan.is_noop() => {
+            return fail(
+                step,
+                "Managed block is out of date; write it under SSH Config",
+            );
+        }
+        _ => {}
+    }
+    if plan.shadowed.contains(&identity.host) {
+        return fail(
+            step,
+            format!(
+                "An earlier Host {} block shadows the managed one",
+                identity.host
+            ),
+        );
+    }
+
+    let args = cli::args(&["-G", &identity.host]);
+    let resolved = match cli::run_program("ssh", &args, &options(Duration::from_secs(10))) {
+        Ok(out) => out,
+        Err(e) => return fail(step, Error::from_cli(e).to_string()),
+    };
+    let (hostname, identity_files) = parse_resolved(&resolved);
+    if !hostname
+        .as_deref()
+        .is_some_and(|h| h.eq_ignore_ascii_case(&identity.hostname))
+    {
+        return fail(
+            step,
+            format!(
+                "{} resolves to {}, not {}",
+                identity.host,
+                hostname.unwrap_or_default(),
+                identity.hostname
+            ),
+        );
+    }
+    if identity.key_type() != SshKeyType::Pkcs11 {
+        let key = expand_tilde(&identity.ssh_key_path);
+        if !identity_files.iter().any(|f| expand_tilde(f) == key) {
+            return fail(
+                step,
+                format!("{} doesn't use {}", identity.host, identity.ssh_key_path),
+            );
+        }
+    }
+    pass(step, format!("{} → {}", identity.host, identity.hostname))
+}
+
+fn check_agent(config: &Config, identity: &Identity) -> Check {
+    let step = Step::Agent;
+    match &identity.ssh_policy.agent {
+        None | Some(IdentityAgent::System) => {}
+        Some(IdentityAgent::None) => return pass(step, "No agent; ssh reads the key file"),
+        Some(other) => {
+            return match agent::socket(other) {
+                Some(socket) if socket.exists() => pass(
+                    step,
+                    format!("Uses {} at {}", other.display_name(), socket.display()),
+                ),
+                _ => fail(step, format!("{} isn't running", other.display_name())),
+            };
+        }
+    }
+    let keys = match agent::list() {
+        Ok(Some(keys)) => keys,
+        Ok(None) => return fail(step, "No ssh-agent running"),
+        Err(e) => return fail(step, e.to_string()),
+    };
+    let loaded = match identity.pkcs11_library() {
+        Some(library) => keys.iter().any(|k| k.comment == library),
+        None => {
+            let public = pubkeys::public_key_path(identity)
+                .and_then(|path| std::fs::read_to_string(path).ok())
+                .unwrap_or_default();
+            key_id(&public).is_some_and(|id| keys.iter().any(|k| key_id(&k.public_key) == Some(id)))
+        }
+    };
+    match (loaded, config.settings.ssh_agent.add_on_switch) {
+        (true, _) => pass(step, "Key loaded"),
+        (false, true) => fail(step, "Key not loaded; switch to the identity to add it"),
+        (false, false) => pass(step, "Key not loaded; ssh reads it from disk"),
+    }
+}
+
+fn check_registration(name: &str, identity: &Identity) -> Check {
+    let step = Step::Registration;
+    if identity.key_type() == SshKeyType::Pkcs11 {
+        return skipped(step, "Smart card keys are registered by hand");
+    }
+    let Some(provider) = pubkeys::Provider::for_identity(identity) else {
+        return skipped(step, format!("{} has no key API", identity.provider));
+    };
+    match pubkeys::check(name, identity) {
+        Ok(Registration::Registered(title)) => pass(
+            step,
+            format!(
+                "Registered with {} as \"{}\"",
+                provider.display_name(),
+                title
+            ),
+        ),
+        Ok(Registration::NotRegistered) => fail(
+            step,
+            format!("Not registered with {}", provider.display_name()),
+        ),
+        Err(e) => fail(step, e.to_string()),
+    }
+}
+
+fn check_signing(identity: &Identity) -> Check {
+    let step = Step::Signing;
+    if !identity.has_gpg_signing() {
+        return skipped(step, "Commit signing is off");
+    }
+    // Keyless signing needs a browser login, so only check gitsign is there
+    if identity.gpg.format == SigningFormat::Gitsign {
+        return match gitsign::version() {
+            Ok(version) => pass(
+                step,
+                format!(
+                    "gitsign {} signs as {}",
+                    version,
+                    FulcioIdentity::for_identity(identity).display_text()
+                ),
+            ),
+            Err(e) => fail(step, format!("gitsign not usable: {}", e)),
+        };
+    }
+    let Some(key) = identity.signing_key() else {
+        return skipped(step, "No signing key");
+    };
+    let ssh = identity.gpg.format == SigningFormat::Ssh;
+    // What git runs for the format, reading the commit on stdin
+    let (program, args, armor) = if ssh {
+        let path = expand_tilde(&key).to_string_lossy().into_owned();
+        let args = cli::args(&["-Y", "sign", "-n", "git", "-f", &path]);
+        (agent::ssh_keygen_program(), args, "BEGIN SSH SIGNATURE")
+    } else {
+        let args = cli::args(&["--batch", "--local-user", &key, "--armor", "--detach-sign"]);
+        ("gpg".to_string(), args, "BEGIN PGP SIGNATURE")
+    };
+    // ssh-keygen signs with a public key through the identity's agent
+    let socket = identity
+        .ssh_policy
+        .agent
+        .as_ref()
+        .map_or_else(platform::agent_socket, agent::socket)
+        .map(|s| s.to_string_lossy().into_owned());
+    let env: Vec<(&str, &str)> = socket
+        .iter()
+        .filter(|_| ssh)
+        .map(|s| ("SSH_AUTH_SOCK", s.as_str()))
+        .collect();
+    // Signing may wait on a PIN entry or a YubiKey touch
+    match cli::run_program_with_input(
+        &program,
+        &args,
+        &env,
+        "remote-juggler identity verification\n",
+        &options(Duration::from_secs(60)),
+    ) {
+        Ok(signature) if signature.contains(armor) => pass(step, format!("Signed with {}", key)),
+        Ok(_) => fail(step, format!("{} produced no signature", program)),
+        Err(e) => fail(step, Error::from_cli(e).to_string()),
+    }
+}
+
+fn check_ls_remote(identity: &Identity) -> Check {
+    let step = Step::LsRemote;
+    let Some(repo) = identity.verify_repo.as_deref().filter(|r| !r.is_empty()) else {
+        return skipped(step, "Set verifyRepo to test a repository");
+    };
+    let url = repo_url(identity, repo);
+    let args = cli::args(&["ls-remote", "--heads", &url]);
+    let env = [
+        ("GIT_TERMINAL_PROMPT", "0"),
+        ("GIT_SSH_COMMAND", "ssh -o BatchMode=yes"),
+    ];
+    match async_io::block_on(cli::run_program_streaming(
+        "git",
+        &args,
+        &env,
+        &options(Duration::from_secs(30)),
+        |_| {},
+    )) {
+        Ok(out) => {
+            let heads = out.lines().filter(|l| !l.trim().is_empty()).count();
+            pass(step, format!("Read {} ({} branches)", url, heads))
+        }
+        Err(e) => fail(step, Error::from_cli(e).to_string()),
+    }
+}
+
+/// Run every check in order; blocks
+pub fn verify(config: &Config, name: &str) -> Vec<Check> {
+    Step::ALL
+        .iter()
+        .map(|step| run_check(config, name, *step))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+
+    #[test]
+    fn test_parse_resolved() {
+        let out = "host gitlab-work\nhostname gitlab.com\nport 22\n\
+                   identityfile ~/.ssh/id_ed25519_work\nidentityfile ~/.ssh/id_rsa\n";
+        let (hostname, files) = parse_resolved(out);
+        assert_eq!(hostname.as_deref(), Some("gitlab.com"));
+        assert_eq!(files, vec!["~/.ssh/id_ed25519_work", "~/.ssh/id_rsa"]);
+    }
+
+    #[test]
+    fn test_repo_url_and_missing_identity() {
+        let config = ConfigBuilder::new()
+            .identity(
+                "work",
+                identity("gitlab", "gitlab-work", "gitlab.com", "Work User", ""),
+            )
+            .build();
+        let identity = &config.identities["work"];
+        assert_eq!(repo_url(identity, "team/app"), "gitlab-work:team/app");
+        assert_eq!(
+            repo_url(identity, "https://gitlab.com/team/app.git"),
+            "https://gitlab.com/team/app.git"
+        );
+        assert_eq!(
+            repo_url(identity, "git@gitlab.com:team/app.git"),
+            "git@gitlab.com:team/app.git"
+        );
+
+        let check = run_check(&config, "work", Step::Config);
+        assert_eq!(check.outcome, Outcome::Fail);
+        assert_eq!(check.detail, "Missing email");
+        assert_eq!(
+            run_check(&config, "work", Step::LsRemote).outcome,
+            Outcome::Skipped
+        );
+        assert_eq!(
+            run_check(&config, "nobody", Step::Agent).detail,
+            "No identity named nobody"
+        );
+    }
+}
@@ -10,7 +10,92 @@ use gtk4::{gdk, gio, glib};
 use libadwaita as adw;
 use libadwaita::prelude::*;
 
-use crate::config::{Config, SecurityMode, SshKeyType};
+use crate::portal;
+use crate::secret_service_bus::SecretServiceBus;
+use remote_juggler_gui::age;
+use remote_juggler_gui::agent::{self, LoadedKey, SwitchReport};
+use remote_juggler_gui::allowed_signers;
+use remote_juggler_gui::askpass;
+use remote_juggler_gui::attachments::{self, Attachment};
+use remote_juggler_gui::authorship::{self, Analysis};
+use remote_juggler_gui::autolock;
+use remote_juggler_gui::backend::{self, CollisionPolicy};
+use remote_juggler_gui::bookmarks;
+use remote_juggler_gui::branches;
+use remote_juggler_gui::chalresp::{self, ChallengeResponse, YubiKeyInfo};
+use remote_juggler_gui::ci_secrets;
+use remote_juggler_gui::cli::{self, CancelToken, RunOptions};
+use remote_juggler_gui::cli_cache;
+use remote_juggler_gui::config::{
+    AddKeysToAgent, Bookmark, BranchRule, CiSecret, Config, DirectoryRule, GpgExpirySettings,
+    Identity, IdentityAgent, KeystoreDatabase, MetricsSettings, OrgPolicy, PinStorageMethod,
+    ProviderHost, RestApiSettings, SecurityMode, SigningFormat, SshAgentSettings,
+    SshCertificateConfig, SshKeyType, SshPolicy,
+};
+use remote_juggler_gui::databases;
+use remote_juggler_gui::detect;
+use remote_juggler_gui::error::Error;
+use remote_juggler_gui::event_hooks;
+use remote_juggler_gui::expiry::{self, EntryExpiry, ExpiryStore, Reminder, ReminderKind, Urgency};
+use remote_juggler_gui::export;
+use remote_juggler_gui::fido2::{self, ResidentKey};
+use remote_juggler_gui::gitconfig;
+use remote_juggler_gui::gitsign;
+use remote_juggler_gui::gpg;
+use remote_juggler_gui::gpg_backup;
+use remote_juggler_gui::gpg_publish;
+use remote_juggler_gui::history::{self, Version};
+use remote_juggler_gui::hooks::{self, GuardStore};
+use remote_juggler_gui::hygiene;
+use remote_juggler_gui::importers;
+use remote_juggler_gui::key_audit;
+use remote_juggler_gui::keyring::{self, Keyring};
+use remote_juggler_gui::keytocard::{self, Plan};
+use remote_juggler_gui::known_hosts::{self, HostKey, Status as HostKeyStatus};
+use remote_juggler_gui::leases::{self, LeaseStore};
+use remote_juggler_gui::lockout;
+use remote_juggler_gui::merge::{self, ChangeKind};
+use remote_juggler_gui::metrics;
+use remote_juggler_gui::oauth;
+use remote_juggler_gui::orgs;
+use remote_juggler_gui::passphrase;
+use remote_juggler_gui::pat;
+use remote_juggler_gui::pin_storage;
+use remote_juggler_gui::piv;
+use remote_juggler_gui::platform;
+use remote_juggler_gui::policy;
+use remote_juggler_gui::progress::{self, ProgressEvent, ProgressState};
+use remote_juggler_gui::provider_api;
+use remote_juggler_gui::provider_cli;
+use remote_juggler_gui::provider_hosts;
+use remote_juggler_gui::pubkeys;
+use remote_juggler_gui::registration::{self, Badge};
+use remote_juggler_gui::rekey;
+use remote_juggler_gui::remotes;
+use remote_juggler_gui::resolver;
+use remote_juggler_gui::rest_api;
+use remote_juggler_gui::revocation;
+use remote_juggler_gui::scanner::{self, Finding};
+use remote_juggler_gui::secret_service;
+use remote_juggler_gui::setec;
+use remote_juggler_gui::shell;
+use remote_juggler_gui::signature;
+use remote_juggler_gui::sops::{self, ConflictPolicy};
+use remote_juggler_gui::ssh_cert::{self, Validity};
+use remote_juggler_gui::ssh_config::{self, BlockState};
+use remote_juggler_gui::switch_guard;
+use remote_juggler_gui::systemd::{self, Task};
+use remote_juggler_gui::tailscale;
+use remote_juggler_gui::telemetry;
+use remote_juggler_gui::token_audit;
+use remote_juggler_gui::totp::Totp;
+use remote_juggler_gui::touch;
+use remote_juggler_gui::tpm::{self, SealState};
+use remote_juggler_gui::url_rewrites;
+use remote_juggler_gui::verified_commits;
+use remote_juggler_gui::verify;
+use remote_juggler_gui::workspace;
+use remote_juggler_gui::yubikeys;
 
 glib::wrapper! {
     pub struct RemoteJugglerWindow(ObjectSubclass<imp::RemoteJugglerWindow>)
@@ -28,13 +113,100 @@ mod imp {
     use super::*;
     use gtk4::subclass::prelude::*;
     use libadwaita::subclass::prelude::*;
-    use std::cell::RefCell;
-    use std::process::Command;
+    use std::cell::{Cell, RefCell};
+    use std::collections::{BTreeSet, HashMap, HashSet};
+    use std::future::Future;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+    use std::time::Duration;
+    use tracing::Instrument;
+
+    /// Delay between consecutive background probes scheduled during a rebuild
+    const PROBE_STAGGER: Duration = Duration::from_millis(150);
+
+    /// How often secrets are checked for expiry while the window is open
+    const ROTATION_CHECK_SECS: u32 = 3600;
+
+    /// How often stored tokens due for it are validated with their provider
+    const TOKEN_CHECK_SECS: u32 = 900;
+
+    /// How often lease countdowns are updated
+    const LEASE_TICK_SECS: u32 = 1;
+
+    /// How often the key store is checked for the auto-lock timeout
+    const AUTO_LOCK_CHECK_SECS: u32 = 5;
+
+    /// Import plan entries listed in the dry-run review
+    const IMPORT_PREVIEW_ROWS: usize = 200;
+
+    /// Secret scanner findings listed for review
+    const SCAN_PREVIEW_ROWS: usize = 200;
+
+    /// The agent's keys (None without an agent) and their owning identities
+    type AgentListing = Result<(Option<Vec<LoadedKey>>, HashMap<String, String>), Error>;
+
+    /// Sets one field of the ssh-agent settings from a switch
+    type AgentSetting = fn(&mut SshAgentSettings, bool);
+
+    /// A change to the directory rules, made on a worker thread
+    type RulesChange = Box<dyn FnOnce(&mut Vec<DirectoryRule>) + Send>;
+
+    /// A change to the commit guard, made on a worker thread
+    type GuardChange = Box<dyn FnOnce() -> Result<(), Error> + Send>;
+
+    /// A change to the workspace roots, made on a worker thread
+    type RootsChange = Box<dyn FnOnce(&mut Vec<String>) + Send>;
+
+    /// Previewed origin URL changes and the repository they are for
+    type RemotesPlan = (PathBuf, Vec<remotes::Change>);
+
+    /// A change to the config, made on a worker thread
+    type ConfigChange = Box<dyn FnOnce(&mut Config) + Send>;
 
     #[derive(Default)]
     pub struct RemoteJugglerWindow {
         config: RefCell<Option<Config>>,
+        /// Why the last config load failed, shown on the error page
+        config_error: RefCell<Option<Error>>,
         scrolled: RefCell<Option<gtk4::ScrolledWindow>>,
+        /// Cancelled when the window closes; drops pending and in-flight probes
+        cancellable: gio::Cancellable,
+        /// True while a config load task is running
+        loading: Cell<bool>,
+        /// Number of probes scheduled since the last content rebuild
+        probe_slots: Cell<u32>,
+        /// Needs Rotation group in the current content and its reminder rows
+        rotation_group: RefCell<Option<adw::PreferencesGroup>>,
+        rotation_rows: RefCell<Vec<adw::ActionRow>>,
+        /// Reminders already sent as a desktop notification
+        rotation_notified: RefCell<BTreeSet<String>>,
+        /// Reauthenticate group in the current content and its rows
+        token_problems_group: RefCell<Option<adw::PreferencesGroup>>,
+        token_problem_rows: RefCell<Vec<adw::ActionRow>>,
+        /// Identities whose rejected token was already notified
+        token_problems_notified: RefCell<BTreeSet<String>>,
+        /// The local HTTP API, while settings.restApi has it enabled
+        rest_api_server: RefCell<Option<rest_api::Server>>,
+        /// The Prometheus endpoint, while settings.metrics has it enabled
+        metrics_server: RefCell<Option<rest_api::Server>>,
+        /// org.freedesktop.secrets, while settings.secretService is on
+        secret_service: RefCell<Option<SecretServiceBus>>,
+        /// Leased Secrets group in the current content, its rows, and the
+        /// countdown badges with the time each lease runs out
+        lease_group: RefCell<Option<adw::PreferencesGroup>>,
+        lease_rows: RefCell<Vec<adw::ActionRow>>,
+        lease_badges: RefCell<Vec<(gtk4::Label, u64)>>,
+        /// True while expired leases are being ended
+        lease_enforcing: Cell<bool>,
+        /// Idle time before the key store locks, for the current security mode
+        auto_lock_timeout: Cell<Option<Duration>>,
+        /// SSH Agent group in the current content and its loaded-key rows
+        agent_group: RefCell<Option<adw::PreferencesGroup>>,
+        agent_rows: RefCell<Vec<adw::ActionRow>>,
+        /// "Touch your key" prompt over the content
+        touch_prompt: RefCell<Option<gtk4::Revealer>>,
+        /// Touch requirements found for each identity's keys
+        touch_requirements: RefCell<HashMap<String, touch::Requirements>>,
     }
 
     #[glib::object_subclass]
@@ -52,19 +224,80 @@ mod imp {
             window.set_title(Some("RemoteJuggler"));
             window.set_default_size(400, 500);
 
-            // Load config
-            self.load_config();
-
-            // Build UI
+            // Show skeleton rows immediately; the real content replaces them
+            // once the config has loaded in the background
             self.build_ui();
+            self.spawn_config_load(true);
 
             // Reload config when window gains focus
             let imp = self.downgrade();
-            window.connect_is_active_notify(move |_win| {
+            window.connect_is_active_notify(move |win| {
+                if !win.is_active() {
+                    return;
+                }
                 if let Some(imp) = imp.upgrade() {
                     imp.reload_config_and_ui();
                 }
             });
+
+            // Re-check secret expiry periodically; the check also runs on
+            // every rebuild
+            let imp = self.downgrade();
+            glib::timeout_add_seconds_local(ROTATION_CHECK_SECS, move || match imp.upgrade() {
+                Some(imp) => {
+                    imp.check_rotation();
+                    glib::ControlFlow::Continue
+                }
+                None => glib::ControlFlow::Break,
+            });
+
+            // Validate stored tokens whose last check is old enough
+            let imp = self.downgrade();
+            glib::timeout_add_seconds_local(TOKEN_CHECK_SECS, move || match imp.upgrade() {
+                Some(imp) => {
+                    imp.check_tokens(false);
+                    glib::ControlFlow::Continue
+                }
+                None => glib::ControlFlow::Break,
+            });
+
+            // Count leases down and end them as they run out
+            let imp = self.downgrade();
+            glib::timeout_add_seconds_local(LEASE_TICK_SECS, move || match imp.upgrade() {
+                Some(imp) => {
+                    imp.tick_leases();
+                    glib::ControlFlow::Continue
+                }
+                None => glib::ControlFlow::Break,
+            });
+
+            // Any input counts as activity for the auto-lock timer
+            let activity = gtk4::EventControllerLegacy::new();
+            activity.set_propagation_phase(gtk4::PropagationPhase::Capture);
+            activity.connect_event(|_, _| {
+                autolock::touch();
+                glib::Propagation::Proceed
+            });
+            window.add_controller(activity);
+
+            let imp = self.downgrade();
+            glib::timeout_add_seconds_local(AUTO_LOCK_CHECK_SECS, move || match imp.upgrade() {
+                Some(imp) => {
+                    imp.check_auto_lock();
+                    glib::ControlFlow::Continue
+                }
+                None => glib::ControlFlow::Break,
+            });
+
+            // Drop any outstanding probes when the window goes away
+            window.connect_close_request(|win| {
+                win.imp().cancellable.cancel();
+                // Opened attachments shouldn't outlive the session
+                if let Err(e) = attachments::wipe_downloads() {
+                    tracing::warn!("Failed to wipe downloaded attachments: {}", e);
+                }
+                glib::Propagation::Proceed
+            });
         }
     }
 
@@ -74,662 +307,9632 @@ mod imp {
     impl AdwApplicationWindowImpl for RemoteJugglerWindow {}
 
     impl RemoteJugglerWindow {
-        fn load_config(&self) {
-            match Config::load() {
-                Ok(config) => {
-                    *self.config.borrow_mut() = Some(config);
+        /// Load the config on a worker thread, optionally rebuilding the UI
+        ///
+        /// Concurrent requests are coalesced: if a load is already running,
+        /// this is a no-op.
+        fn spawn_config_load(&self, rebuild: bool) {
+            if self.loading.replace(true) {
+                return;
+            }
+
+            let imp_weak = self.downgrade();
+            let cancellable = self.cancellable.clone();
+            glib::spawn_future_local(async move {
+                let result = gio::spawn_blocking(Config::load).await;
+
+                let Some(imp) = imp_weak.upgrade() else {
+                    return;
+                };
+                imp.loading.set(false);
+                if cancellable.is_cancelled() {
+                    return;
+                }
+
+                match result {
+                    Ok(Ok(config)) => {
+                        imp.sync_rest_api(&config.settings.rest_api);
+                        imp.sync_metrics(&config.settings.metrics);
+                        imp.sync_secret_service(config.settings.secret_service);
+                        let helper = askpass::program_path().filter(|_| config.settings.askpass);
+                        askpass::apply_to_cli(helper.as_deref());
+                        *imp.config.borrow_mut() = Some(config);
+                        *imp.config_error.borrow_mut() = None;
+                    }
+                    Ok(Err(e)) => {
+                        tracing::error!("Failed to load config: {}", e);
+                        *imp.config_error.borrow_mut() = Some(Error::Config(e));
+                    }
+                    Err(e) => {
+                        tracing::error!("Config load task failed: {:?}", e);
+                    }
+                }
+
+                if rebuild {
+                    imp.rebuild_content();
                 }
-                Err(e) => {
-                    tracing::error!("Failed to load config: {}", e);
+            });
+        }
+
+        /// Start, move or stop the local HTTP API to match the settings
+        fn sync_rest_api(&self, settings: &RestApiSettings) {
+            let mut server = self.rest_api_server.borrow_mut();
+            let running = server.as_ref().map(|s| s.port());
+            if !settings.enabled {
+                *server = None;
+            } else if running != Some(settings.port) {
+                // Release the old port before binding the new one
+                *server = None;
+                match rest_api::start(settings) {
+                    Ok(started) => *server = Some(started),
+                    Err(e) => tracing::error!("Starting the local HTTP API failed: {}", e),
+                }
+            }
+        }
+
+        /// Start, move or stop the metrics endpoint to match the settings
+        fn sync_metrics(&self, settings: &MetricsSettings) {
+            let mut server = self.metrics_server.borrow_mut();
+            let running = server.as_ref().map(|s| s.port());
+            if !settings.enabled {
+                *server = None;
+            } else if running != Some(settings.port) {
+                *server = None;
+                match metrics::start(settings) {
+                    Ok(started) => *server = Some(started),
+                    Err(e) => tracing::error!("Starting the metrics endpoint failed: {}", e),
                 }
             }
         }
 
+        /// Claim or release org.freedesktop.secrets to match the settings
+        fn sync_secret_service(&self, enabled: bool) {
+            let running = self.secret_service.borrow().is_some();
+            if !enabled || !cfg!(target_os = "linux") {
+                self.secret_service.replace(None);
+            } else if !running {
+                let window = self.obj().downgrade();
+                let imp_weak = self.downgrade();
+                let service = SecretServiceBus::start(
+                    move || {
+                        if let Some(window) = window.upgrade() {
+                            window.present();
+                        }
+                    },
+                    move || {
+                        if let Some(imp) = imp_weak.upgrade() {
+                            imp.rebuild_content();
+                        }
+                    },
+                );
+                self.secret_service.replace(Some(service));
+            }
+        }
+
+        /// Refresh the cached config after a CLI operation changed it
+        fn load_config(&self) {
+            self.spawn_config_load(false);
+        }
+
         fn reload_config_and_ui(&self) {
-            self.load_config();
-            // Rebuild the content inside the scrolled window
+            self.spawn_config_load(true);
+        }
+
+        /// Replace the content inside the scrolled window from the current config
+        fn rebuild_content(&self) {
+            self.probe_slots.set(0);
             if let Some(ref scrolled) = *self.scrolled.borrow() {
                 let main_box = self.build_main_content();
                 scrolled.set_child(Some(&main_box));
             }
         }
 
-        fn build_ui(&self) {
-            let window = self.obj();
+        /// Lock the key store once the session has been idle long enough
+        fn check_auto_lock(&self) {
+            if autolock::lock_if_idle(self.auto_lock_timeout.get()) {
+                tracing::info!("Key store locked after inactivity");
+                self.rebuild_content();
+            }
+        }
 
-            // Create header bar
-            let header = adw::HeaderBar::new();
+        /// Schedule a background CLI probe
+        ///
+        /// Probes are staggered so a rebuild doesn't spawn every subprocess at
+        /// once, and are dropped when the window closes.
+        fn schedule_probe<F>(&self, probe: F)
+        where
+            F: Future<Output = ()> + 'static,
+        {
+            let slot = self.probe_slots.get();
+            self.probe_slots.set(slot + 1);
+            let delay = PROBE_STAGGER * slot;
+            let cancellable = self.cancellable.clone();
 
-            // Create main vertical box
-            let vbox = gtk4::Box::new(gtk4::Orientation::Vertical, 0);
-            vbox.append(&header);
+            glib::spawn_future_local(gio::CancellableFuture::new(
+                async move {
+                    if !delay.is_zero() {
+                        glib::timeout_future(delay).await;
+                    }
+                    probe.await;
+                },
+                cancellable,
+            ));
+        }
 
-            // Create scrolled window for content
-            let scrolled = gtk4::ScrolledWindow::new();
-            scrolled.set_vexpand(true);
+        /// List the agent's keys and refresh the SSH Agent group
+        fn refresh_agent(&self) {
+            let config = self.config.borrow().clone();
+            let imp_weak = self.downgrade();
+            let cancellable = self.cancellable.clone();
+            glib::spawn_future_local(async move {
+                let listing = gio::spawn_blocking(move || {
+                    let keys = agent::list()?;
+                    let owners = match (&keys, &config) {
+                        (Some(keys), Some(config)) if !keys.is_empty() => agent::owners(config),
+                        _ => HashMap::new(),
+                    };
+                    Ok((keys, owners))
+                })
+                .await
+                .unwrap_or_else(|_| Err(worker_panicked()));
+                let Some(imp) = imp_weak.upgrade() else {
+                    return;
+                };
+                if cancellable.is_cancelled() {
+                    return;
+                }
+                imp.show_agent_keys(listing);
+            });
+        }
 
-            // Build main content
-            let main_box = self.build_main_content();
-            scrolled.set_child(Some(&main_box));
+        /// Replace the loaded-key rows of the SSH Agent group
+        fn show_agent_keys(&self, listing: AgentListing) {
+            let Some(group) = self.agent_group.borrow().clone() else {
+                return;
+            };
+            for row in self.agent_rows.borrow_mut().drain(..) {
+                group.remove(&row);
+            }
 
-            *self.scrolled.borrow_mut() = Some(scrolled.clone());
+            let placeholder = |title: &str, subtitle: &str| {
+                let row = adw::ActionRow::new();
+                row.set_title(title);
+                row.set_subtitle(&glib::markup_escape_text(subtitle));
+                row.add_css_class("dim-label");
+                row
+            };
+            let mut rows = Vec::new();
+            match listing {
+                Err(e) => rows.push(placeholder(
+                    "Agent Unavailable",
+                    &error_text("Listing keys failed", &e),
+                )),
+                Ok((None, _)) => rows.push(placeholder(
+                    "No Agent Running",
+                    "Start ssh-agent and set SSH_AUTH_SOCK",
+                )),
+                Ok((Some(keys), _)) if keys.is_empty() => {
+                    rows.push(placeholder("No Keys Loaded", "The agent holds no keys"))
+                }
+                Ok((Some(keys), owners)) => {
+                    for key in keys {
+                        let row = adw::ActionRow::new();
+                        let title = match owners.get(&key.fingerprint) {
+                            Some(owner) => owner.clone(),
+                            None if key.comment.is_empty() => "Unknown key".to_string(),
+                            None => key.comment.clone(),
+                        };
+                        row.set_title(&glib::markup_escape_text(&title));
+                        row.set_subtitle(&glib::markup_escape_text(&format!(
+                            "{} {}",
+                            key.key_type, key.fingerprint
+                        )));
 
-            vbox.append(&scrolled);
-            window.set_content(Some(&vbox));
+                        let button = gtk4::Button::with_label("Remove");
+                        button.set_valign(gtk4::Align::Center);
+                        row.add_suffix(&button);
+                        let imp_weak = self.downgrade();
+                        let row_clone = row.clone();
+                        button.connect_clicked(move |button| {
+                            button.set_sensitive(false);
+                            let btn = button.clone();
+                            let key = key.clone();
+                            let row = row_clone.clone();
+                            let imp_weak = imp_weak.clone();
+                            glib::spawn_future_local(async move {
+                                let result = gio::spawn_blocking(move || agent::remove(&key))
+                                    .await
+                                    .unwrap_or_else(|_| Err(worker_panicked()));
+                                match result {
+                                    Ok(()) => {
+                                        if let Some(imp) = imp_weak.upgrade() {
+                                            imp.refresh_agent();
+                                        }
+                                    }
+                                    Err(e) => {
+                                        row.set_subtitle(&glib::markup_escape_text(&error_text(
+                                            "Removing failed",
+                                            &e,
+                                        )));
+                                        btn.set_sensitive(true);
+                                    }
+                                }
+                            });
+                        });
+                        rows.push(row);
+                    }
+                }
+            }
+            for row in &rows {
+                group.add(row);
+            }
+            *self.agent_rows.borrow_mut() = rows;
         }
 
-        fn build_main_content(&self) -> gtk4::Box {
-            // Create main content box
-            let main_box = gtk4::Box::new(gtk4::Orientation::Vertical, 12);
-            main_box.set_margin_top(24);
-            main_box.set_margin_bottom(24);
-            main_box.set_margin_start(24);
-            main_box.set_margin_end(24);
+        /// Load the identity just switched to into ssh-agent, adding the
+        /// outcome to the switch message in `status`; with the askpass
+        /// setting on, the session is pointed at the helper first
+        fn switch_agent(&self, identity: String, status: gtk4::Label) {
+            let Some(config) = self.config.borrow().clone() else {
+                return;
+            };
+            let imp_weak = self.downgrade();
+            glib::spawn_future_local(async move {
+                let result = gio::spawn_blocking(move || {
+                    if let Err(e) = askpass::on_switch(&config) {
+                        tracing::warn!("Setting askpass for the session failed: {}", e);
+                    }
+                    agent::on_switch(&config, &identity)
+                })
+                .await
+                .unwrap_or_else(|_| Err(worker_panicked()));
+                let switched = status.text();
+                match result {
+                    Ok(report) if report == SwitchReport::default() => {}
+                    Ok(report) => show_status(
+                        &status,
+                        &format!("{}; ssh-agent {}", switched, report.summary()),
+                        Some("success"),
+                    ),
+                    Err(e) => show_status(
+                        &status,
+                        &error_text(&format!("{}, but ssh-agent failed", switched), &e),
+                        Some("error"),
+                    ),
+                }
+                if let Some(imp) = imp_weak.upgrade() {
+                    imp.refresh_agent();
+                }
+            });
+        }
 
-            let config = self.config.borrow();
-            if let Some(config) = config.as_ref() {
-                let profiles = config.profiles();
+        /// SSH Certificate rows for the current identity: principals,
+        /// validity and CA, plus the signing command and a Renew button
+        fn build_certificate_rows(
+            &self,
+            name: &str,
+            identity: &Identity,
+            status: &gtk4::Label,
+        ) -> adw::ExpanderRow {
+            let expander = adw::ExpanderRow::new();
+            expander.set_title("SSH Certificate");
 
-                // Status label for feedback
-                let status_label = gtk4::Label::new(None);
-                status_label.set_wrap(true);
-                status_label.set_xalign(0.0);
-                status_label.add_css_class("dim-label");
-                status_label.set_visible(false);
+            match ssh_cert::load(identity) {
+                Ok(Some(cert)) => {
+                    let validity = cert.validity(expiry::now());
+                    expander.set_subtitle(&glib::markup_escape_text(&format!(
+                        "{} ({})",
+                        cert.principals_text(),
+                        validity.display_text()
+                    )));
 
-                // Create profile selector group
-                let profile_group = adw::PreferencesGroup::new();
-                profile_group.set_title("Git Identity");
-                profile_group.set_description(Some("Select your active git identity profile"));
+                    let valid_row = adw::ActionRow::new();
+                    valid_row.set_title("Valid");
+                    valid_row.set_subtitle(&cert.window_text());
+                    let badge = gtk4::Label::new(Some(&validity.display_text()));
+                    badge.add_css_class(match validity {
+                        Validity::Expired { .. } | Validity::NotYetValid { .. } => "error",
+                        Validity::ExpiringSoon { .. } => "warning",
+                        Validity::Valid { .. } | Validity::Forever => "success",
+                    });
+                    valid_row.add_suffix(&badge);
+                    expander.add_row(&valid_row);
 
-                // Create combo row for profile selection
-                let profile_row = adw::ComboRow::new();
-                profile_row.set_title("Active Profile");
+                    let principals_row = adw::ActionRow::new();
+                    principals_row.set_title("Principals");
+                    principals_row.set_subtitle(&glib::markup_escape_text(&cert.principals_text()));
+                    expander.add_row(&principals_row);
 
-                let profile_names: Vec<String> =
-                    profiles.iter().map(|p| p.display_name()).collect();
-                let profile_names_strs: Vec<&str> =
-                    profile_names.iter().map(|s| s.as_str()).collect();
-                let profile_list = gtk4::StringList::new(&profile_names_strs);
-                profile_row.set_model(Some(&profile_list));
+                    let ca_row = adw::ActionRow::new();
+                    ca_row.set_title("Certificate Authority");
+                    ca_row.set_subtitle(&cert.ca_text());
+                    expander.add_row(&ca_row);
 
-                // Set current selection based on current identity's profile
-                if let Some(current_profile) = config.current_profile() {
-                    if let Some(pos) = profiles.iter().position(|p| p.name == current_profile.name)
-                    {
-                        profile_row.set_selected(pos as u32);
+                    let id_row = adw::ActionRow::new();
+                    id_row.set_title("Key ID");
+                    id_row.set_subtitle(&glib::markup_escape_text(&format!(
+                        "{} (serial {})",
+                        cert.key_id, cert.serial
+                    )));
+                    expander.add_row(&id_row);
+
+                    if validity.needs_renewal() {
+                        show_status(
+                            status,
+                            &format!(
+                                "SSH certificate for {}: {}; renew it under Current Profile Details",
+                                name,
+                                validity.display_text()
+                            ),
+                            Some("error"),
+                        );
                     }
                 }
+                Ok(None) => expander.set_subtitle("No certificate next to the key"),
+                Err(e) => expander.set_subtitle(&glib::markup_escape_text(&error_text(
+                    "Unreadable certificate",
+                    &e,
+                ))),
+            }
 
-                // Wire profile ComboRow handler (2a)
-                {
-                    let profiles_for_handler = profiles.clone();
-                    let status_clone = status_label.clone();
-                    let imp_weak = self.downgrade();
-                    profile_row.connect_selected_notify(move |row| {
-                        let selected = row.selected() as usize;
-                        if selected >= profiles_for_handler.len() {
-                            return;
-                        }
-                        let profile = &profiles_for_handler[selected];
-                        // Use default variant (prefer FIDO2)
-                        let identity_name = profile
-                            .default_variant()
-                            .map(|v| v.identity_name.clone())
-                            .unwrap_or_else(|| profile.name.clone());
+            let command_row = adw::ActionRow::new();
+            command_row.set_title("Signing Command");
+            command_row.set_subtitle("{pubkey}, {key}, {cert} and {identity} are filled in");
+            let command_entry = gtk4::Entry::new();
+            command_entry
+                .set_placeholder_text(Some("e.g. step ssh certificate {identity} {pubkey} --sign"));
+            command_entry.set_text(ssh_cert::sign_command(identity).unwrap_or_default());
+            command_entry.set_hexpand(true);
+            command_entry.set_valign(gtk4::Align::Center);
+            let save_button = gtk4::Button::with_label("Save");
+            save_button.set_valign(gtk4::Align::Center);
+            command_row.add_suffix(&command_entry);
+            command_row.add_suffix(&save_button);
+            expander.add_row(&command_row);
 
-                        let status = status_clone.clone();
-                        let name = identity_name.clone();
-                        let imp = imp_weak.clone();
-                        status.set_text(&format!("Switching to {}...", &name));
-                        status.set_visible(true);
-                        status.remove_css_class("error");
-                        status.remove_css_class("success");
+            let renew_row = adw::ActionRow::new();
+            renew_row.set_title("Renew Certificate");
+            renew_row.set_subtitle("Run the signing command now");
+            let renew_button = gtk4::Button::with_label("Renew");
+            renew_button.set_valign(gtk4::Align::Center);
+            renew_button.add_css_class("suggested-action");
+            renew_row.add_suffix(&renew_button);
+            expander.add_row(&renew_row);
 
-                        glib::spawn_future_local(async move {
-                            let result = run_cli_async("switch", &name).await;
-                            match result {
-                                Ok(msg) => {
-                                    status.set_text(&format!("Switched to {}", &name));
-                                    status.add_css_class("success");
-                                    tracing::info!("Switched identity: {} - {}", &name, msg);
-                                }
-                                Err(e) => {
-                                    status.set_text(&format!("Failed: {}", e));
-                                    status.add_css_class("error");
-                                    tracing::error!("Switch failed: {}", e);
+            let with_command = |identity: &Identity, command: &str| {
+                let command = command.trim();
+                Identity {
+                    ssh_certificate: (!command.is_empty()).then(|| SshCertificateConfig {
+                        sign_command: command.to_string(),
+                    }),
+                    ..identity.clone()
+                }
+            };
+
+            // Wire save button: store the signing command in config.json
+            {
+                let entry = command_entry.clone();
+                let name = name.to_string();
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                save_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    let btn = button.clone();
+                    let command = entry.text().to_string();
+                    let name = name.clone();
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || {
+                            Config::update_identity(&name, |identity| {
+                                *identity = with_command(identity, &command);
+                            })?;
+                            Ok::<_, Error>(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        btn.set_sensitive(true);
+                        match result {
+                            Ok(()) => {
+                                show_status(&status, "Signing command saved", Some("success"));
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.load_config();
                                 }
                             }
-                            // Reload config after switch
-                            if let Some(imp) = imp.upgrade() {
-                                imp.load_config();
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving signing command failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                });
+            }
+
+            // Wire renew button: sign with the command as entered
+            {
+                let entry = command_entry.clone();
+                let name = name.to_string();
+                let identity = identity.clone();
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                renew_button.connect_clicked(move |button| {
+                    let identity = with_command(&identity, &entry.text());
+                    if ssh_cert::sign_command(&identity).is_none() {
+                        show_status(&status, "Enter a signing command first", Some("error"));
+                        return;
+                    }
+                    button.set_sensitive(false);
+                    show_status(&status, "Signing SSH certificate...", None);
+                    let btn = button.clone();
+                    let name = name.clone();
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || ssh_cert::renew(&name, &identity))
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                        btn.set_sensitive(true);
+                        match result {
+                            Ok(cert) => {
+                                show_status(
+                                    &status,
+                                    &format!("Certificate renewed, valid {}", cert.window_text()),
+                                    Some("success"),
+                                );
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
                             }
-                        });
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Renewing certificate failed", &e),
+                                Some("error"),
+                            ),
+                        }
                     });
-                }
+                });
+            }
 
-                profile_group.add(&profile_row);
+            expander
+        }
 
-                // Add SSH key variant selector if current profile has multiple variants
-                let current_profile = config.current_profile();
-                let current_variant = config.current_variant();
+        /// Agent forwarding, AddKeysToAgent and confirm-on-use for an
+        /// identity
+        fn build_policy_rows(
+            &self,
+            name: &str,
+            identity: &Identity,
+            status: &gtk4::Label,
+        ) -> adw::ExpanderRow {
+            let policy = &identity.ssh_policy;
+            let constraints = agent::Constraints::for_identity(identity);
+            let expander = adw::ExpanderRow::new();
+            expander.set_title("SSH Agent Policy");
+            expander.set_subtitle(&format!(
+                "{}, {}",
+                if policy.forward_agent == Some(true) {
+                    "forwarding on"
+                } else {
+                    "forwarding off"
+                },
+                constraints.describe()
+            ));
 
-                if let Some(ref profile) = current_profile {
-                    if profile.has_multiple_variants() {
-                        let variant_row = adw::ComboRow::new();
-                        variant_row.set_title("SSH Key Type");
-                        variant_row
-                            .set_subtitle("Choose between regular SSH or hardware security key");
+            let forward_row = adw::ActionRow::new();
+            forward_row.set_title("Forward Agent");
+            forward_row.set_subtitle("Let hosts reached through this identity use your agent");
+            let forward_switch = gtk4::Switch::new();
+            forward_switch.set_valign(gtk4::Align::Center);
+            forward_switch.set_active(policy.forward_agent == Some(true));
+            forward_row.add_suffix(&forward_switch);
+            expander.add_row(&forward_row);
 
-                        let variant_names: Vec<&str> = profile
-                            .variants
-                            .iter()
-                            .map(|v| v.key_type.display_name())
-                            .collect();
-                        let variant_list = gtk4::StringList::new(&variant_names);
-                        variant_row.set_model(Some(&variant_list));
+            let add_row = adw::ComboRow::new();
+            add_row.set_title("Add Keys to Agent");
+            add_row.set_subtitle("When ssh itself loads the key into the agent");
+            let add_names: Vec<&str> = AddKeysToAgent::ALL
+                .iter()
+                .map(AddKeysToAgent::display_name)
+                .collect();
+            add_row.set_model(Some(&gtk4::StringList::new(&add_names)));
+            let current = policy.add_keys_to_agent.unwrap_or_default();
+            if let Some(pos) = AddKeysToAgent::ALL.iter().position(|a| *a == current) {
+                add_row.set_selected(pos as u32);
+            }
+            expander.add_row(&add_row);
 
-                        // Set current variant selection
-                        if let Some(ref current_var) = current_variant {
-                            if let Some(pos) = profile
-                                .variants
-                                .iter()
-                                .position(|v| v.identity_name == current_var.identity_name)
-                            {
-                                variant_row.set_selected(pos as u32);
+            // Default, the named agents, then a custom socket
+            let agent_row = adw::ComboRow::new();
+            agent_row.set_title("Agent");
+            agent_row.set_subtitle("Which agent ssh asks for this key (IdentityAgent)");
+            let mut agent_names = vec!["Default"];
+            agent_names.extend(IdentityAgent::NAMED.iter().map(IdentityAgent::display_name));
+            agent_names.push("Custom Socket");
+            agent_row.set_model(Some(&gtk4::StringList::new(&agent_names)));
+            let socket_row = adw::ActionRow::new();
+            socket_row.set_title("Agent Socket");
+            let socket_entry = gtk4::Entry::new();
+            socket_entry.set_placeholder_text(Some("~/.ssh/agent.sock"));
+            socket_entry.set_hexpand(true);
+            socket_entry.set_valign(gtk4::Align::Center);
+            socket_row.add_suffix(&socket_entry);
+            let custom = IdentityAgent::NAMED.len() as u32 + 1;
+            let selected = match &policy.agent {
+                None => 0,
+                Some(IdentityAgent::Socket(path)) => {
+                    socket_entry.set_text(path);
+                    custom
+                }
+                Some(agent) => IdentityAgent::NAMED
+                    .iter()
+                    .position(|a| a == agent)
+                    .map_or(0, |pos| pos as u32 + 1),
+            };
+            agent_row.set_selected(selected);
+            socket_row.set_visible(selected == custom);
+            {
+                let socket_row = socket_row.clone();
+                agent_row.connect_selected_notify(move |row| {
+                    socket_row.set_visible(row.selected() == custom);
+                });
+            }
+            expander.add_row(&agent_row);
+            expander.add_row(&socket_row);
+
+            let confirm_row = adw::ActionRow::new();
+            confirm_row.set_title("Confirm Each Use");
+            confirm_row.set_subtitle(&format!(
+                "Ask before the agent signs with this key; {} defaults to {}",
+                identity.gpg.security_mode.display_name(),
+                if agent::Constraints::for_mode(&identity.gpg.security_mode).confirm {
+                    "on"
+                } else {
+                    "off"
+                }
+            ));
+            let confirm_switch = gtk4::Switch::new();
+            confirm_switch.set_valign(gtk4::Align::Center);
+            confirm_switch.set_active(constraints.confirm);
+            confirm_row.add_suffix(&confirm_switch);
+            expander.add_row(&confirm_row);
+
+            let save_row = adw::ActionRow::new();
+            save_row.set_title("Save Policy");
+            save_row.set_subtitle(
+                "Applies to the managed host block once written, and to the agent on next switch",
+            );
+            let save_button = gtk4::Button::with_label("Save");
+            save_button.set_valign(gtk4::Align::Center);
+            save_row.add_suffix(&save_button);
+            expander.add_row(&save_row);
+
+            // Wire save button: store the policy in config.json
+            let name = name.to_string();
+            let status = status.clone();
+            let imp_weak = self.downgrade();
+            save_button.connect_clicked(move |button| {
+                let policy = SshPolicy {
+                    forward_agent: Some(forward_switch.is_active()),
+                    add_keys_to_agent: AddKeysToAgent::ALL
+                        .get(add_row.selected() as usize)
+                        .copied(),
+                    confirm: Some(confirm_switch.is_active()),
+                    agent: match agent_row.selected() {
+                        0 => None,
+                        n if n == custom => Some(socket_entry.text().trim().to_string())
+                            .filter(|path| !path.is_empty())
+                            .map(IdentityAgent::Socket),
+                        n => IdentityAgent::NAMED.get(n as usize - 1).cloned(),
+                    },
+                };
+                button.set_sensitive(false);
+                let btn = button.clone();
+                let name = name.clone();
+                let status = status.clone();
+                let imp_weak = imp_weak.clone();
+                glib::spawn_future_local(async move {
+                    let result = gio::spawn_blocking(move || {
+                        Config::update_identity(&name, |identity| {
+                            identity.ssh_policy = policy;
+                        })?;
+                        Ok::<_, Error>(())
+                    })
+                    .await
+                    .unwrap_or_else(|_| Err(worker_panicked()));
+                    btn.set_sensitive(true);
+                    match result {
+                        Ok(()) => {
+                            show_status(
+                                &status,
+                                "Agent policy saved; write the managed host blocks under SSH \
+                                 Config to apply it",
+                                Some("success"),
+                            );
+                            if let Some(imp) = imp_weak.upgrade() {
+                                imp.reload_config_and_ui();
                             }
                         }
+                        Err(e) => show_status(
+                            &status,
+                            &error_text("Saving agent policy failed", &e),
+                            Some("error"),
+                        ),
+                    }
+                });
+            });
 
-                        // Wire variant ComboRow handler (2b)
-                        {
-                            let variants_for_handler: Vec<String> = profile
-                                .variants
-                                .iter()
-                                .map(|v| v.identity_name.clone())
-                                .collect();
-                            let status_clone = status_label.clone();
-                            let imp_weak = self.downgrade();
-                            variant_row.connect_selected_notify(move |row| {
-                                let selected = row.selected() as usize;
-                                if selected >= variants_for_handler.len() {
-                                    return;
-                                }
-                                let identity_name = &variants_for_handler[selected];
-                                let status = status_clone.clone();
-                                let name = identity_name.clone();
-                                let imp = imp_weak.clone();
-                                status.set_text(&format!("Switching to variant {}...", &name));
-                                status.set_visible(true);
-                                status.remove_css_class("error");
-                                status.remove_css_class("success");
+            expander
+        }
 
-                                glib::spawn_future_local(async move {
-                                    let result = run_cli_async("switch", &name).await;
-                                    match result {
-                                        Ok(_) => {
-                                            status.set_text(&format!(
-                                                "Switched to variant {}",
-                                                &name
-                                            ));
-                                            status.add_css_class("success");
-                                        }
-                                        Err(e) => {
-                                            status.set_text(&format!("Failed: {}", e));
-                                            status.add_css_class("error");
-                                        }
-                                    }
-                                    if let Some(imp) = imp.upgrade() {
-                                        imp.load_config();
-                                    }
-                                });
-                            });
+        /// Whether and how the identity signs commits: a GPG key or its SSH
+        /// key (`gpg.format=ssh`), applied by the CLI on switch
+        fn build_signing_rows(
+            &self,
+            name: &str,
+            identity: &Identity,
+            status: &gtk4::Label,
+        ) -> adw::ExpanderRow {
+            let expander = adw::ExpanderRow::new();
+            expander.set_title("Commit Signing");
+            let fulcio = gitsign::FulcioIdentity::for_identity(identity);
+            expander.set_subtitle(&glib::markup_escape_text(&match identity.signing_key() {
+                _ if !identity.has_gpg_signing() => "Disabled".to_string(),
+                _ if identity.gpg.format == SigningFormat::Gitsign => {
+                    format!("Gitsign as {}", fulcio.display_text())
+                }
+                Some(key) => format!("{} key {}", identity.gpg.format.display_name(), key),
+                None => "Disabled".to_string(),
+            }));
+
+            let sign_row = adw::ActionRow::new();
+            sign_row.set_title("Sign Commits");
+            sign_row.set_subtitle("Set commit.gpgsign when switching to this identity");
+            let sign_switch = gtk4::Switch::new();
+            sign_switch.set_valign(gtk4::Align::Center);
+            sign_switch.set_active(identity.gpg.sign_commits);
+            sign_row.add_suffix(&sign_switch);
+            expander.add_row(&sign_row);
+
+            let format_row = adw::ComboRow::new();
+            format_row.set_title("Signing Format");
+            format_row.set_subtitle(
+                "Sign with a GPG key, this identity's SSH key, or keyless through Sigstore",
+            );
+            let format_names: Vec<&str> = SigningFormat::ALL
+                .iter()
+                .map(SigningFormat::display_name)
+                .collect();
+            format_row.set_model(Some(&gtk4::StringList::new(&format_names)));
+            if let Some(pos) = SigningFormat::ALL
+                .iter()
+                .position(|f| *f == identity.gpg.format)
+            {
+                format_row.set_selected(pos as u32);
+            }
+            expander.add_row(&format_row);
+
+            let key_row = adw::ActionRow::new();
+            key_row.set_title("SSH Signing Key");
+            key_row.set_subtitle("Public key for user.signingkey");
+            let key_entry = gtk4::Entry::new();
+            if !identity.ssh_key_path.is_empty() {
+                key_entry.set_placeholder_text(Some(&format!("{}.pub", identity.ssh_key_path)));
+            }
+            key_entry.set_text(identity.gpg.ssh_key_path.as_deref().unwrap_or_default());
+            key_entry.set_hexpand(true);
+            key_entry.set_valign(gtk4::Align::Center);
+            key_row.add_suffix(&key_entry);
+            key_row.set_visible(identity.gpg.format == SigningFormat::Ssh);
+            {
+                let key_row = key_row.clone();
+                format_row.connect_selected_notify(move |row| {
+                    key_row.set_visible(
+                        SigningFormat::ALL.get(row.selected() as usize)
+                            == Some(&SigningFormat::Ssh),
+                    );
+                });
+            }
+            expander.add_row(&key_row);
+
+            // Signing subkeys of the GPG key, listed from the keyring; the
+            // first entry leaves the choice to gpg
+            let subkey_row = adw::ComboRow::new();
+            subkey_row.set_title("Signing Subkey");
+            subkey_row.set_subtitle("Written as user.signingkey with ! to pin it");
+            let subkey_ids: Rc<RefCell<Vec<Option<String>>>> = Rc::new(RefCell::new(vec![None]));
+            let subkey_names = gtk4::StringList::new(&["Newest signing subkey"]);
+            if let Some(subkey) = identity.gpg.signing_subkey.clone() {
+                subkey_names.append(&subkey);
+                subkey_ids.borrow_mut().push(Some(subkey));
+                subkey_row.set_selected(1);
+            }
+            subkey_row.set_model(Some(&subkey_names));
+            subkey_row.set_visible(
+                identity.gpg.format == SigningFormat::Gpg && !identity.gpg.key_id.is_empty(),
+            );
+            {
+                let subkey_row = subkey_row.clone();
+                let has_key = !identity.gpg.key_id.is_empty();
+                format_row.connect_selected_notify(move |row| {
+                    subkey_row.set_visible(
+                        has_key
+                            && SigningFormat::ALL.get(row.selected() as usize)
+                                == Some(&SigningFormat::Gpg),
+                    );
+                });
+            }
+            if identity.gpg.format == SigningFormat::Gpg && !identity.gpg.key_id.is_empty() {
+                let key_id = identity.gpg.key_id.clone();
+                let configured = identity.gpg.signing_subkey.clone();
+                let subkey_row = subkey_row.clone();
+                let subkey_ids = subkey_ids.clone();
+                glib::spawn_future_local(async move {
+                    let keys = gio::spawn_blocking(gpg::list_secret_keys)
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                    let key = match keys {
+                        Ok(keys) => keys.into_iter().find(|key| key.matches(&key_id)),
+                        Err(e) => {
+                            tracing::info!("Listing GPG subkeys failed: {}", e);
+                            return;
+                        }
+                    };
+                    let Some(key) = key else {
+                        subkey_row.set_subtitle(&format!("{} is not in the keyring", key_id));
+                        return;
+                    };
+                    let names = gtk4::StringList::new(&["Newest signing subkey"]);
+                    let mut ids = vec![None];
+                    let mut selected = 0;
+                    for subkey in key.signing_subkeys(expiry::now()) {
+                        let expires = subkey.expires.map_or("no expiry".to_string(), |t| {
+                            format!("expires {}", expiry::format_date(t))
+                        });
+                        names.append(&format!("{} ({})", subkey.key_id, expires));
+                        if configured.as_deref().is_some_and(|id| {
+                            gpg::normalize(id) == subkey.key_id
+                                || subkey.fingerprint.ends_with(&gpg::normalize(id))
+                        }) {
+                            selected = ids.len() as u32;
                         }
+                        ids.push(Some(subkey.key_id.clone()));
+                    }
+                    // Keep a configured subkey that can't sign any more
+                    if let (0, Some(configured)) = (selected, configured) {
+                        names.append(&format!("{} (cannot sign)", configured));
+                        selected = ids.len() as u32;
+                        ids.push(Some(configured));
+                    }
+                    *subkey_ids.borrow_mut() = ids;
+                    subkey_row.set_model(Some(&names));
+                    subkey_row.set_selected(selected);
+                });
+            }
+            expander.add_row(&subkey_row);
 
-                        profile_group.add(&variant_row);
+            // Whether signatures and SSH logins wait for a YubiKey touch
+            let touch_row = adw::ActionRow::new();
+            touch_row.set_title("Hardware Touch");
+            touch_row.set_subtitle("Checking...");
+            expander.add_row(&touch_row);
+            {
+                let name = name.to_string();
+                let identity = identity.clone();
+                let imp_weak = self.downgrade();
+                glib::spawn_future_local(async move {
+                    let result = gio::spawn_blocking(move || touch::check(&identity))
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                    match result {
+                        Ok(requirements) => {
+                            touch_row.set_subtitle(&glib::markup_escape_text(
+                                &requirements.display_text(),
+                            ));
+                            if let Some(imp) = imp_weak.upgrade() {
+                                imp.touch_requirements
+                                    .borrow_mut()
+                                    .insert(name, requirements);
+                            }
+                        }
+                        Err(e) => touch_row.set_subtitle(&glib::markup_escape_text(&error_text(
+                            "Touch policy unknown",
+                            &e,
+                        ))),
                     }
-                }
+                });
+            }
 
-                main_box.append(&profile_group);
+            // The certificate Fulcio issues for the OIDC login
+            let fulcio_rows = [
+                ("Fulcio Identity", fulcio.subject.clone()),
+                (
+                    "OIDC Issuer",
+                    match fulcio.connector {
+                        Some(connector) => format!("{} via {}", fulcio.issuer, connector),
+                        None => fulcio.issuer.clone(),
+                    },
+                ),
+                ("Fulcio", fulcio.fulcio.clone()),
+                ("Rekor", fulcio.rekor.clone()),
+            ]
+            .map(|(title, value)| {
+                let row = adw::ActionRow::new();
+                row.set_title(title);
+                row.set_subtitle(&glib::markup_escape_text(&value));
+                row.set_visible(identity.gpg.format == SigningFormat::Gitsign);
+                expander.add_row(&row);
+                row
+            });
+            let settings: Vec<String> = gitsign::git_settings(&identity.gpg)
+                .into_iter()
+                .map(|(key, value)| format!("{} = {}", key, value))
+                .collect();
+            fulcio_rows[0].set_tooltip_text(Some(&format!(
+                "Written on switch:\n{}",
+                settings.join("\n")
+            )));
+            {
+                let fulcio_rows = fulcio_rows.clone();
+                format_row.connect_selected_notify(move |row| {
+                    let gitsign = SigningFormat::ALL.get(row.selected() as usize)
+                        == Some(&SigningFormat::Gitsign);
+                    for fulcio_row in &fulcio_rows {
+                        fulcio_row.set_visible(gitsign);
+                    }
+                });
+            }
 
-                // Status feedback label
-                main_box.append(&status_label);
+            let save_row = adw::ActionRow::new();
+            save_row.set_title("Save Signing");
+            save_row.set_subtitle("Written to the repository's git config on next switch");
+            let save_button = gtk4::Button::with_label("Save");
+            save_button.set_valign(gtk4::Align::Center);
+            save_row.add_suffix(&save_button);
+            expander.add_row(&save_row);
 
-                // Add current profile details if available
-                if let Some(ref profile) = current_profile {
-                    let details_group = adw::PreferencesGroup::new();
-                    details_group.set_title("Current Profile Details");
+            // Wire save button: store the signing settings in config.json
+            let name = name.to_string();
+            let status = status.clone();
+            let imp_weak = self.downgrade();
+            save_button.connect_clicked(move |button| {
+                let sign_commits = sign_switch.is_active();
+                let format = SigningFormat::ALL
+                    .get(format_row.selected() as usize)
+                    .copied()
+                    .unwrap_or_default();
+                let ssh_key_path =
+                    Some(key_entry.text().trim().to_string()).filter(|path| !path.is_empty());
+                let signing_subkey = subkey_ids
+                    .borrow()
+                    .get(subkey_row.selected() as usize)
+                    .cloned()
+                    .flatten();
+                button.set_sensitive(false);
+                let btn = button.clone();
+                let name = name.clone();
+                let status = status.clone();
+                let imp_weak = imp_weak.clone();
+                glib::spawn_future_local(async move {
+                    let result = gio::spawn_blocking(move || {
+                        Config::update_identity(&name, |identity| {
+                            identity.gpg.sign_commits = sign_commits;
+                            identity.gpg.format = format;
+                            identity.gpg.ssh_key_path = ssh_key_path;
+                            identity.gpg.signing_subkey = signing_subkey;
+                        })?;
+                        Ok::<_, Error>(())
+                    })
+                    .await
+                    .unwrap_or_else(|_| Err(worker_panicked()));
+                    btn.set_sensitive(true);
+                    match result {
+                        Ok(()) => {
+                            show_status(
+                                &status,
+                                "Signing saved; switch to the identity again to apply it",
+                                Some("success"),
+                            );
+                            if let Some(imp) = imp_weak.upgrade() {
+                                imp.reload_config_and_ui();
+                            }
+                        }
+                        Err(e) => show_status(
+                            &status,
+                            &error_text("Saving signing settings failed", &e),
+                            Some("error"),
+                        ),
+                    }
+                });
+            });
 
-                    // Provider row
-                    let provider_row = adw::ActionRow::new();
-                    provider_row.set_title("Provider");
-                    provider_row.set_subtitle(&profile.provider);
-                    details_group.add(&provider_row);
+            expander
+        }
 
-                    // User row
-                    let user_row = adw::ActionRow::new();
-                    user_row.set_title("Username");
-                    user_row.set_subtitle(&profile.user);
-                    details_group.add(&user_row);
+        /// Move the identity's GPG key onto a YubiKey: check the card, export
+        /// a backup, move, verify the stubs and record the card
+        fn build_keytocard_rows(
+            &self,
+            name: &str,
+            identity: &Identity,
+            status: &gtk4::Label,
+        ) -> adw::ExpanderRow {
+            let expander = adw::ExpanderRow::new();
+            expander.set_title("Move Key to YubiKey");
+            expander.set_subtitle(if identity.gpg.hardware_key {
+                "The key is on a card"
+            } else {
+                "Store the signing key on the card instead of on disk"
+            });
 
-                    // Email row
-                    let email_row = adw::ActionRow::new();
-                    email_row.set_title("Email");
-                    email_row.set_subtitle(&profile.email);
-                    details_group.add(&email_row);
+            let card_row = adw::ActionRow::new();
+            card_row.set_title("Card");
+            card_row.set_subtitle("Insert the YubiKey and check what would move");
+            let check_button = gtk4::Button::with_label("Check");
+            check_button.set_valign(gtk4::Align::Center);
+            card_row.add_suffix(&check_button);
+            expander.add_row(&card_row);
 
-                    // SSH Key variant info
-                    if let Some(ref variant) = current_variant {
-                        let ssh_row = adw::ActionRow::new();
-                        ssh_row.set_title("SSH Key");
-                        let ssh_info = if variant.identity.ssh_key_path.is_empty() {
-                            format!("{} (default)", variant.key_type.display_name())
-                        } else {
-                            format!(
-                                "{} ({})",
-                                variant.key_type.display_name(),
-                                variant
-                                    .identity
-                                    .ssh_key_path
-                                    .rsplit('/')
-                                    .next()
-                                    .unwrap_or(&variant.identity.ssh_key_path)
-                            )
+            let backup_row = adw::ActionRow::new();
+            backup_row.set_title("Backup");
+            backup_row.set_subtitle(
+                "Export the secret key first: the keyring only keeps a stub afterwards",
+            );
+            let backup_button = gtk4::Button::with_label("Export...");
+            backup_button.set_valign(gtk4::Align::Center);
+            backup_button.set_sensitive(false);
+            backup_row.add_suffix(&backup_button);
+            expander.add_row(&backup_row);
+
+            let kept_row = adw::ActionRow::new();
+            kept_row.set_title("Backup Kept Elsewhere");
+            kept_row.set_subtitle("Move without exporting a backup here");
+            let kept_switch = gtk4::Switch::new();
+            kept_switch.set_valign(gtk4::Align::Center);
+            kept_row.add_suffix(&kept_switch);
+            kept_row.set_activatable_widget(Some(&kept_switch));
+            expander.add_row(&kept_row);
+
+            let mode_row = adw::ComboRow::new();
+            mode_row.set_title("Security Mode Afterwards");
+            mode_row.set_subtitle("How the card PIN is handled once the key is on it");
+            let mode_names: Vec<&str> = SecurityMode::all()
+                .iter()
+                .map(|m| m.display_name())
+                .collect();
+            mode_row.set_model(Some(&gtk4::StringList::new(&mode_names)));
+            mode_row.set_selected(identity.gpg.security_mode.index());
+            expander.add_row(&mode_row);
+
+            let move_row = adw::ActionRow::new();
+            move_row.set_title("Move to YubiKey");
+            move_row.set_subtitle("Asks for the key passphrase and the card's Admin PIN");
+            let move_button = gtk4::Button::with_label("Move");
+            move_button.set_valign(gtk4::Align::Center);
+            move_button.add_css_class("destructive-action");
+            move_button.set_sensitive(false);
+            move_row.add_suffix(&move_button);
+            expander.add_row(&move_row);
+
+            let plan: Rc<RefCell<Option<Plan>>> = Rc::default();
+            let backed_up = Rc::new(Cell::new(false));
+            // Moving needs something to move and a backup
+            let update_move = {
+                let plan = plan.clone();
+                let backed_up = backed_up.clone();
+                let kept_switch = kept_switch.clone();
+                let move_button = move_button.clone();
+                Rc::new(move || {
+                    let ready = plan.borrow().as_ref().is_some_and(|p| !p.moves.is_empty());
+                    move_button
+                        .set_sensitive(ready && (backed_up.get() || kept_switch.is_active()));
+                })
+            };
+            {
+                let update_move = update_move.clone();
+                kept_switch.connect_active_notify(move |_| update_move());
+            }
+
+            // Wire check button: read the card and plan the moves
+            {
+                let name = name.to_string();
+                let identity = identity.clone();
+                let plan = plan.clone();
+                let backup_button = backup_button.clone();
+                let update_move = update_move.clone();
+                check_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    card_row.set_subtitle("Reading the card...");
+                    let btn = button.clone();
+                    let card_row = card_row.clone();
+                    let (name, identity) = (name.clone(), identity.clone());
+                    let plan = plan.clone();
+                    let backup_button = backup_button.clone();
+                    let update_move = update_move.clone();
+                    glib::spawn_future_local(async move {
+                        let result =
+                            gio::spawn_blocking(move || keytocard::prepare(&name, &identity))
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                        btn.set_sensitive(true);
+                        let text = match &result {
+                            Ok(found) if found.moves.is_empty() => format!(
+                                "{} card {}: nothing to move, the keys are on a card already",
+                                found.card.vendor, found.card.serial
+                            ),
+                            Ok(found) => {
+                                let moves: Vec<String> =
+                                    found.moves.iter().map(|m| m.display_text()).collect();
+                                format!(
+                                    "{} card {}: moves {}",
+                                    found.card.vendor,
+                                    found.card.serial,
+                                    moves.join("; ")
+                                )
+                            }
+                            Err(e) => error_text("Checking the card failed", e),
                         };
-                        ssh_row.set_subtitle(&ssh_info);
+                        card_row.set_subtitle(&glib::markup_escape_text(&text));
+                        backup_button.set_sensitive(result.is_ok());
+                        *plan.borrow_mut() = result.ok();
+                        update_move();
+                    });
+                });
+            }
 
-                        // Add badge for security key
-                        if variant.key_type == SshKeyType::Fido2 {
-                            let badge = gtk4::Label::new(Some("HW"));
-                            badge.add_css_class("heading");
-                            badge.add_css_class("accent");
-                            ssh_row.add_suffix(&badge);
-                        }
+            // Wire backup button: export the secret key to a chosen file
+            {
+                let window_ref = self.obj().clone();
+                let plan = plan.clone();
+                let backed_up = backed_up.clone();
+                let update_move = update_move.clone();
+                backup_button.connect_clicked(move |button| {
+                    let Some(key) = plan.borrow().as_ref().map(|p| p.key.clone()) else {
+                        return;
+                    };
+                    let dialog = gtk4::FileDialog::new();
+                    dialog.set_title("Save Secret Key Backup");
+                    dialog.set_initial_name(Some(&format!("{}-secret.asc", key.key_id)));
+                    let btn = button.clone();
+                    let backup_row = backup_row.clone();
+                    let backed_up = backed_up.clone();
+                    let update_move = update_move.clone();
+                    dialog.save(Some(&window_ref), gio::Cancellable::NONE, move |result| {
+                        let Some(path) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                            return;
+                        };
+                        btn.set_sensitive(false);
+                        backup_row.set_subtitle("Exporting...");
+                        glib::spawn_future_local(async move {
+                            let target = path.clone();
+                            let result = gio::spawn_blocking(move || {
+                                keytocard::export_backup(&key.fingerprint, &target)
+                            })
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                            btn.set_sensitive(true);
+                            let text = match &result {
+                                Ok(()) => format!("Saved to {}; keep it offline", path.display()),
+                                Err(e) => error_text("Export failed", e),
+                            };
+                            backup_row.set_subtitle(&glib::markup_escape_text(&text));
+                            backed_up.set(result.is_ok());
+                            update_move();
+                        });
+                    });
+                });
+            }
 
-                        details_group.add(&ssh_row);
+            // Wire move button: keytocard, verify the stubs, update config
+            let name = name.to_string();
+            let status = status.clone();
+            let imp_weak = self.downgrade();
+            move_button.connect_clicked(move |button| {
+                let Some(found) = plan.borrow().clone() else {
+                    return;
+                };
+                let mode = SecurityMode::from_index(mode_row.selected());
+                button.set_sensitive(false);
+                show_status(&status, "Moving the key to the card...", None);
+                let btn = button.clone();
+                let name = name.clone();
+                let status = status.clone();
+                let imp_weak = imp_weak.clone();
+                glib::spawn_future_local(async move {
+                    let result = gio::spawn_blocking(move || {
+                        let fingerprint = &found.key.fingerprint;
+                        keytocard::move_to_card(fingerprint, &found.moves)?;
+                        let left = keytocard::verify_stubs(fingerprint, &found.moves, &found.card)?;
+                        if !left.is_empty() {
+                            return Ok(left);
+                        }
+                        Config::update_identity(&name, |identity| {
+                            keytocard::update_identity(identity, mode);
+                        })?;
+                        Ok(left)
+                    })
+                    .await
+                    .unwrap_or_else(|_| Err(worker_panicked()));
+                    btn.set_sensitive(true);
+                    match result {
+                        Ok(left) if left.is_empty() => {
+                            show_status(
+                                &status,
+                                "The key is on the YubiKey; the keyring keeps stubs",
+                                Some("success"),
+                            );
+                            if let Some(imp) = imp_weak.upgrade() {
+                                imp.reload_config_and_ui();
+                            }
+                        }
+                        Ok(left) => show_status(
+                            &status,
+                            &format!("Still in the keyring, not on the card: {}", left.join(", ")),
+                            Some("error"),
+                        ),
+                        Err(e) => show_status(
+                            &status,
+                            &error_text("Moving the key failed", &e),
+                            Some("error"),
+                        ),
                     }
+                });
+            });
 
-                    // GPG row
-                    let gpg_row = adw::ActionRow::new();
-                    gpg_row.set_title("GPG Signing");
-                    if profile.has_gpg_signing() {
-                        gpg_row.set_subtitle(&format!("Enabled ({})", &profile.gpg.key_id));
-                    } else {
-                        gpg_row.set_subtitle("Disabled");
-                    }
-                    details_group.add(&gpg_row);
+            expander
+        }
 
-                    // Available variants summary
-                    let variants_row = adw::ActionRow::new();
-                    variants_row.set_title("Available Key Types");
-                    let variant_summary: Vec<&str> = profile
-                        .variants
-                        .iter()
-                        .map(|v| v.key_type.short_name())
-                        .collect();
-                    variants_row.set_subtitle(&variant_summary.join(", "));
-                    details_group.add(&variants_row);
+        /// Whether the tailnet is up, and marking the identity as only
+        /// reachable over it
+        fn build_tailnet_row(
+            &self,
+            name: &str,
+            identity: &Identity,
+            status: &gtk4::Label,
+        ) -> adw::ActionRow {
+            let row = adw::ActionRow::new();
+            row.set_title("Tailnet");
+            row.set_subtitle("Checking...");
+            let switch = gtk4::Switch::new();
+            switch.set_valign(gtk4::Align::Center);
+            switch.set_active(identity.tailnet_only);
+            switch.set_tooltip_text(Some("Only reachable over the tailnet"));
+            row.add_suffix(&switch);
 
-                    main_box.append(&details_group);
+            let connectivity: Rc<RefCell<Option<tailscale::Connectivity>>> = Rc::default();
+            let show = {
+                let row = row.clone();
+                let connectivity = connectivity.clone();
+                move |tailnet_only: bool| {
+                    let Some(connectivity) = connectivity.borrow().clone() else {
+                        return;
+                    };
+                    let mut text = connectivity.display_text();
+                    if tailnet_only {
+                        text.push_str("; this identity needs it");
+                    }
+                    row.set_subtitle(&glib::markup_escape_text(&text));
+                    if tailnet_only && connectivity.is_offline() {
+                        row.add_css_class("warning");
+                    } else {
+                        row.remove_css_class("warning");
+                    }
                 }
+            };
+            let show = Rc::new(show);
 
-                // Add GPG status group
-                let gpg_group = adw::PreferencesGroup::new();
-                gpg_group.set_title("GPG Status");
+            {
+                let show = show.clone();
+                let switch = switch.clone();
+                self.schedule_probe(async move {
+                    let result = gio::spawn_blocking(tailscale::status).await;
+                    connectivity.replace(Some(result.unwrap_or_else(|_| {
+                        tailscale::Connectivity::Unknown("check failed".to_string())
+                    })));
+                    show(switch.is_active());
+                });
+            }
 
-                let gpg_status_row = adw::ActionRow::new();
-                gpg_status_row.set_title("Signing Ready");
-                gpg_status_row.set_subtitle("Checking...");
+            {
+                let name = name.to_string();
+                let status = status.clone();
+                switch.connect_active_notify(move |switch| {
+                    let tailnet_only = switch.is_active();
+                    show(tailnet_only);
+                    let name = name.clone();
+                    let status = status.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || {
+                            Config::update_identity(&name, |identity| {
+                                identity.tailnet_only = tailnet_only;
+                            })?;
+                            Ok::<_, Error>(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        if let Err(e) = result {
+                            show_status(
+                                &status,
+                                &error_text("Saving the identity failed", &e),
+                                Some("error"),
+                            );
+                        }
+                    });
+                });
+            }
 
-                // Add a switch for GPG signing toggle
-                let gpg_switch = gtk4::Switch::new();
-                gpg_switch.set_valign(gtk4::Align::Center);
-                gpg_switch.set_active(config.settings.gpg_sign);
-                gpg_status_row.add_suffix(&gpg_switch);
+            row
+        }
 
-                gpg_group.add(&gpg_status_row);
-                main_box.append(&gpg_group);
+        /// The YubiKey the identity's keys are on: the enrolled keys, which
+        /// of them are inserted, and binding the identity to one
+        fn build_yubikey_row(
+            &self,
+            name: &str,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::ComboRow {
+            let row = adw::ComboRow::new();
+            row.set_title("YubiKey");
+            row.set_subtitle("Checking inserted keys...");
+            let enrolled = yubikeys::enrolled(config);
+            let bound = config.identities.get(name).and_then(|i| i.yubikey_serial);
+            let identity = config.identities.get(name).cloned();
 
-                // Add Security Mode group
-                let security_group = adw::PreferencesGroup::new();
-                security_group.set_title("Security");
-                security_group.set_description(Some("YubiKey PIN handling mode"));
+            // Index 0 is "Any YubiKey"; the rest follow `serials`
+            let serials: Rc<RefCell<Vec<u32>>> = Rc::default();
+            let updating = Rc::new(Cell::new(false));
+            let inserted: Rc<RefCell<Vec<yubikeys::Inserted>>> = Rc::default();
+            let fill = {
+                let row = row.clone();
+                let serials = serials.clone();
+                let updating = updating.clone();
+                let inserted = inserted.clone();
+                move |selected: Option<u32>| {
+                    let inserted = inserted.borrow();
+                    let mut all: Vec<u32> = enrolled.keys().copied().collect();
+                    all.extend(inserted.iter().filter_map(|key| key.serial));
+                    all.extend(selected);
+                    all.sort_unstable();
+                    all.dedup();
+                    let mut names = vec!["Any YubiKey".to_string()];
+                    for serial in &all {
+                        let mut notes = Vec::new();
+                        match inserted.iter().find(|key| key.serial == Some(*serial)) {
+                            Some(key) => notes.push(format!("{}, inserted", key.name)),
+                            None => notes.push("not inserted".to_string()),
+                        }
+                        if let Some(identities) = enrolled.get(serial) {
+                            notes.push(identities.join(", "));
+                        }
+                        names.push(format!("{} ({})", serial, notes.join("; ")));
+                    }
+                    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+                    updating.set(true);
+                    row.set_model(Some(&gtk4::StringList::new(&names)));
+                    let position = selected
+                        .and_then(|s| all.iter().position(|x| *x == s))
+                        .map_or(0, |i| i as u32 + 1);
+                    row.set_selected(position);
+                    updating.set(false);
+                    *serials.borrow_mut() = all;
+                }
+            };
+            fill(bound);
 
-                // Security Mode combo row
-                let security_mode_row = adw::ComboRow::new();
-                security_mode_row.set_title("Security Mode");
-                security_mode_row.set_subtitle("How YubiKey PIN is handled during signing");
+            let presence_text = {
+                let inserted = inserted.clone();
+                move |serial: Option<u32>| match identity.clone() {
+                    Some(mut identity) => {
+                        identity.yubikey_serial = serial;
+                        yubikeys::Presence::check(&identity, &inserted.borrow()).display_text()
+                    }
+                    None => String::new(),
+                }
+            };
+            let presence_text = Rc::new(presence_text);
 
-                // Create string list for security modes
-                let mode_names: Vec<&str> = SecurityMode::all()
-                    .iter()
-                    .map(|m| m.display_name())
-                    .collect();
-                let mode_list = gtk4::StringList::new(&mode_names);
-                security_mode_row.set_model(Some(&mode_list));
+            {
+                let row = row.clone();
+                let inserted = inserted.clone();
+                let presence_text = presence_text.clone();
+                self.schedule_probe(async move {
+                    match gio::spawn_blocking(yubikeys::inserted).await {
+                        Ok(Ok(found)) => {
+                            *inserted.borrow_mut() = found;
+                            fill(bound);
+                            row.set_subtitle(&presence_text(bound));
+                        }
+                        Ok(Err(e)) => row.set_subtitle(&glib::markup_escape_text(&error_text(
+                            "Listing YubiKeys failed",
+                            &e,
+                        ))),
+                        Err(_) => row.set_subtitle("Listing YubiKeys failed"),
+                    }
+                });
+            }
 
-                // Get current security mode from the current profile's GPG config
-                let current_security_mode = current_profile
-                    .as_ref()
-                    .map(|p| p.gpg.security_mode.clone())
-                    .unwrap_or_default();
-                security_mode_row.set_selected(current_security_mode.index());
+            // Wire selection: bind the identity to the chosen key
+            {
+                let name = name.to_string();
+                let status = status.clone();
+                row.connect_selected_notify(move |row| {
+                    if updating.get() {
+                        return;
+                    }
+                    let serial = (row.selected() as usize)
+                        .checked_sub(1)
+                        .and_then(|i| serials.borrow().get(i).copied());
+                    row.set_subtitle(&presence_text(serial));
+                    let name = name.clone();
+                    let status = status.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || {
+                            Config::update_identity(&name, |identity| {
+                                identity.yubikey_serial = serial;
+                            })?;
+                            Ok::<_, Error>(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match (result, serial) {
+                            (Ok(()), Some(serial)) => show_status(
+                                &status,
+                                &format!("Bound to YubiKey {}", serial),
+                                Some("success"),
+                            ),
+                            (Ok(()), None) => {
+                                show_status(&status, "Works with any YubiKey", Some("success"))
+                            }
+                            (Err(e), _) => show_status(
+                                &status,
+                                &error_text("Saving the YubiKey failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                });
+            }
 
-                security_group.add(&security_mode_row);
+            row
+        }
 
-                // YubiKey PIN Storage group (only visible in TrustedWorkstation mode)
-                let pin_group = adw::PreferencesGroup::new();
-                pin_group.set_title("YubiKey PIN Storage");
-                pin_group.set_description(Some("Store PIN in hardware security module"));
+        /// Keep the identity's SSH key in a YubiKey PIV slot: generate or
+        /// import one, or use the slot's key, and point the identity at it
+        fn build_piv_rows(
+            &self,
+            name: &str,
+            identity: &Identity,
+            status: &gtk4::Label,
+        ) -> adw::ExpanderRow {
+            let expander = adw::ExpanderRow::new();
+            expander.set_title("PIV Slots");
+            let subtitle = match identity.pkcs11_library() {
+                Some(library) => format!("The SSH key is on a smart card, through {}", library),
+                None => "Keep the SSH key in a YubiKey PIV slot".to_string(),
+            };
+            expander.set_subtitle(&glib::markup_escape_text(&subtitle));
 
-                // PIN entry row using gtk4::PasswordEntry inside an ActionRow
-                let pin_entry = gtk4::PasswordEntry::new();
-                pin_entry.set_show_peek_icon(true);
-                pin_entry.set_hexpand(true);
-                pin_entry.set_valign(gtk4::Align::Center);
+            let slots_row = adw::ActionRow::new();
+            slots_row.set_title("Slots");
+            slots_row.set_subtitle("Insert the YubiKey and check what its slots hold");
+            let check_button = gtk4::Button::with_label("Check");
+            check_button.set_valign(gtk4::Align::Center);
+            slots_row.add_suffix(&check_button);
+            expander.add_row(&slots_row);
 
-                let pin_entry_row = adw::ActionRow::new();
-                pin_entry_row.set_title("Enter PIN");
-                pin_entry_row.add_suffix(&pin_entry);
-                pin_entry_row.set_activatable_widget(Some(&pin_entry));
-                pin_group.add(&pin_entry_row);
+            let slot_row = adw::ComboRow::new();
+            slot_row.set_title("Slot");
+            let slot_names: Vec<&str> = piv::Slot::ALL.iter().map(|s| s.display_name()).collect();
+            slot_row.set_model(Some(&gtk4::StringList::new(&slot_names)));
+            expander.add_row(&slot_row);
 
-                // Store PIN button and status row
-                let store_pin_row = adw::ActionRow::new();
-                store_pin_row.set_title("Store PIN in HSM");
+            let algorithm_row = adw::ComboRow::new();
+            algorithm_row.set_title("Algorithm");
+            algorithm_row.set_subtitle("For generated keys");
+            let algorithm_names: Vec<&str> = piv::Algorithm::ALL
+                .iter()
+                .map(|a| a.display_name())
+                .collect();
+            algorithm_row.set_model(Some(&gtk4::StringList::new(&algorithm_names)));
+            expander.add_row(&algorithm_row);
 
-                // Status indicator
-                let pin_status_label = gtk4::Label::new(Some("Not stored"));
-                pin_status_label.add_css_class("dim-label");
-                store_pin_row.add_suffix(&pin_status_label);
+            let touch_row = adw::ActionRow::new();
+            touch_row.set_title("Require Touch");
+            touch_row.set_subtitle("For generated keys; a touch covers 15 seconds");
+            let touch_switch = gtk4::Switch::new();
+            touch_switch.set_valign(gtk4::Align::Center);
+            touch_switch.set_active(true);
+            touch_row.add_suffix(&touch_switch);
+            touch_row.set_activatable_widget(Some(&touch_switch));
+            expander.add_row(&touch_row);
 
-                // Store button
-                let store_button = gtk4::Button::with_label("Store PIN");
-                store_button.set_valign(gtk4::Align::Center);
-                store_button.add_css_class("suggested-action");
-                store_pin_row.add_suffix(&store_button);
-                store_pin_row.set_activatable_widget(Some(&store_button));
+            let pin_entry = gtk4::PasswordEntry::new();
+            pin_entry.set_show_peek_icon(true);
+            pin_entry.set_valign(gtk4::Align::Center);
+            let pin_row = adw::ActionRow::new();
+            pin_row.set_title("PIV PIN");
+            pin_row.set_subtitle("Needed to generate or import");
+            pin_row.add_suffix(&pin_entry);
+            pin_row.set_activatable_widget(Some(&pin_entry));
+            expander.add_row(&pin_row);
 
-                pin_group.add(&store_pin_row);
+            let key_row = adw::ActionRow::new();
+            key_row.set_title("SSH Key");
+            key_row.set_subtitle("Generating or importing replaces the slot's key");
+            let existing_button = gtk4::Button::with_label("Use Existing");
+            existing_button.set_valign(gtk4::Align::Center);
+            key_row.add_suffix(&existing_button);
+            let import_button = gtk4::Button::with_label("Import...");
+            import_button.set_valign(gtk4::Align::Center);
+            key_row.add_suffix(&import_button);
+            let generate_button = gtk4::Button::with_label("Generate");
+            generate_button.set_valign(gtk4::Align::Center);
+            generate_button.add_css_class("destructive-action");
+            key_row.add_suffix(&generate_button);
+            expander.add_row(&key_row);
 
-                // Set initial visibility based on security mode
-                let show_pin_storage = current_security_mode == SecurityMode::TrustedWorkstation;
-                pin_group.set_visible(show_pin_storage);
+            // Wire check button: list the slots and the PKCS#11 library
+            check_button.connect_clicked(move |button| {
+                button.set_sensitive(false);
+                slots_row.set_subtitle("Reading the card...");
+                let btn = button.clone();
+                let slots_row = slots_row.clone();
+                glib::spawn_future_local(async move {
+                    let (library, result) = gio::spawn_blocking(|| (piv::library(), piv::info()))
+                        .await
+                        .unwrap_or_else(|_| (None, Err(worker_panicked())));
+                    btn.set_sensitive(true);
+                    let library = match library {
+                        Some(path) => format!("ssh uses {}", path.display()),
+                        None => "no PKCS#11 library found; install ykcs11 or OpenSC".to_string(),
+                    };
+                    let text = match result {
+                        Ok(slots) if slots.is_empty() => format!("All slots empty; {}", library),
+                        Ok(slots) => {
+                            let slots: Vec<String> =
+                                slots.iter().map(|s| s.display_text()).collect();
+                            format!("{}; {}", slots.join(", "), library)
+                        }
+                        Err(e) => error_text("Reading the PIV applet failed", &e),
+                    };
+                    slots_row.set_subtitle(&glib::markup_escape_text(&text));
+                });
+            });
 
-                main_box.append(&security_group);
-                main_box.append(&pin_group);
+            // Get the slot's public key in the background, attach it and
+            // copy it for registering with the provider
+            type Produce = Box<dyn FnOnce(piv::Slot) -> Result<String, Error> + Send>;
+            let attach_key: Rc<dyn Fn(Produce)> = {
+                let name = name.to_string();
+                let slot_row = slot_row.clone();
+                let key_row = key_row.clone();
+                let pin_entry = pin_entry.clone();
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |produce: Produce| {
+                    let slot = piv::Slot::ALL[slot_row.selected() as usize % piv::Slot::ALL.len()];
+                    key_row.set_sensitive(false);
+                    show_status(&status, &format!("Setting up slot {}...", slot.id()), None);
+                    let name = name.clone();
+                    let key_row = key_row.clone();
+                    let pin_entry = pin_entry.clone();
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || {
+                            let library = piv::library().ok_or_else(|| Error::InvalidOutput {
+                                program: "ssh",
+                                message: "No PKCS#11 library; install ykcs11 or OpenSC".to_string(),
+                            })?;
+                            let public_key = produce(slot)?;
+                            let mut attached = Ok(());
+                            Config::update_identity(&name, |identity| {
+                                attached =
+                                    piv::attach(&name, identity, slot, &public_key, &library);
+                            })?;
+                            attached?;
+                            Ok(public_key)
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        key_row.set_sensitive(true);
+                        pin_entry.set_text("");
+                        match result {
+                            Ok(public_key) => {
+                                if let Some(display) = gdk::Display::default() {
+                                    display.clipboard().set_text(&public_key);
+                                }
+                                show_status(
+                                    &status,
+                                    &format!(
+                                        "Slot {} is the identity's SSH key; public key copied, \
+                                         register it with the provider",
+                                        slot.id()
+                                    ),
+                                    Some("success"),
+                                );
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Setting up the PIV slot failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                })
+            };
 
-                // Wire security mode change handler (2c)
-                {
-                    let pin_group_clone = pin_group.clone();
-                    let status_clone = status_label.clone();
-                    security_mode_row.connect_selected_notify(move |row| {
-                        let selected = row.selected();
-                        let mode = SecurityMode::from_index(selected);
-                        let show = mode == SecurityMode::TrustedWorkstation;
-                        pin_group_clone.set_visible(show);
+            {
+                let attach_key = attach_key.clone();
+                existing_button.connect_clicked(move |_| attach_key(Box::new(piv::existing)));
+            }
 
-                        // Call CLI to persist the security mode change
-                        let mode_str = match mode {
-                            SecurityMode::MaximumSecurity => "maximum_security",
-                            SecurityMode::DeveloperWorkflow => "developer_workflow",
-                            SecurityMode::TrustedWorkstation => "trusted_workstation",
-                        };
-                        let status = status_clone.clone();
-                        let mode_display = mode.display_name().to_string();
-                        let mode_arg = mode_str.to_string();
-                        status.set_visible(true);
-                        status.remove_css_class("error");
-                        status.remove_css_class("success");
-                        status.set_text(&format!("Setting security mode to {}...", &mode_display));
+            // Wire generate button: a new key from the chosen algorithm
+            {
+                let attach_key = attach_key.clone();
+                let pin_entry = pin_entry.clone();
+                let status = status.clone();
+                let subject = format!("{} SSH", name);
+                generate_button.connect_clicked(move |_| {
+                    let pin = pin_entry.text().to_string();
+                    if pin.is_empty() {
+                        show_status(&status, "Enter the PIV PIN", Some("error"));
+                        return;
+                    }
+                    let algorithm = piv::Algorithm::ALL
+                        [algorithm_row.selected() as usize % piv::Algorithm::ALL.len()];
+                    let touch = touch_switch.is_active();
+                    let subject = subject.clone();
+                    attach_key(Box::new(move |slot| {
+                        piv::generate(slot, algorithm, touch, &pin, &subject)
+                    }));
+                });
+            }
 
-                        glib::spawn_future_local(async move {
-                            let result = run_cli_async("security-mode", &mode_arg).await;
-                            match result {
-                                Ok(_) => {
-                                    status.set_text(&format!("Security mode: {}", &mode_display));
-                                    status.add_css_class("success");
-                                    tracing::info!("Security mode changed to: {}", &mode_display);
-                                }
-                                Err(e) => {
-                                    status.set_text(&format!("Failed: {}", e));
-                                    status.add_css_class("error");
-                                    tracing::error!("Security mode change failed: {}", e);
-                                }
-                            }
-                        });
+            // Wire import button: an unencrypted PEM private key
+            {
+                let window_ref = self.obj().clone();
+                let status = status.clone();
+                let subject = format!("{} SSH", name);
+                import_button.connect_clicked(move |_| {
+                    let pin = pin_entry.text().to_string();
+                    if pin.is_empty() {
+                        show_status(&status, "Enter the PIV PIN", Some("error"));
+                        return;
+                    }
+                    let dialog = gtk4::FileDialog::new();
+                    dialog.set_title("Import Private Key (PEM)");
+                    let attach_key = attach_key.clone();
+                    let subject = subject.clone();
+                    dialog.open(Some(&window_ref), gio::Cancellable::NONE, move |result| {
+                        let Some(path) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                            return;
+                        };
+                        attach_key(Box::new(move |slot| {
+                            piv::import(slot, &path, &pin, &subject)
+                        }));
                     });
-                }
+                });
+            }
 
-                // ============================================================
-                // KeePassXC Key Store Group
-                // ============================================================
-                let keys_group = adw::PreferencesGroup::new();
-                keys_group.set_title("Key Store (KeePassXC)");
-                keys_group.set_description(Some("Credential authority for secrets management"));
+            expander
+        }
 
-                // Key store status row
-                let keys_status_row = adw::ActionRow::new();
-                keys_status_row.set_title("Key Store");
-                let keys_status_label = gtk4::Label::new(Some("Checking..."));
-                keys_status_label.add_css_class("dim-label");
-                keys_status_row.add_suffix(&keys_status_label);
-                keys_group.add(&keys_status_row);
+        /// Directory rules and the includeIf block they become in
+        /// ~/.gitconfig
+        fn build_git_includes_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Directory Rules");
+            group.set_description(Some(
+                "Repositories under a directory use its identity; git follows the rules \
+                 through includeIf sections in ~/.gitconfig, even without a switch",
+            ));
 
-                // Check key store status async
-                {
-                    let label = keys_status_label.clone();
+            // Save a changed rule list and rebuild the page
+            let save_rules = {
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |change: RulesChange| {
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
                     glib::spawn_future_local(async move {
-                        let result = run_cli_async("keys", "status").await;
+                        let result = gio::spawn_blocking(move || -> Result<(), Error> {
+                            Config::update(|config| change(&mut config.settings.directory_rules))?;
+                            Ok(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
                         match result {
-                            Ok(output) => {
-                                if output.contains("Auto-Unlock:   ready")
-                                    || output.contains("Auto-Unlock: ready")
-                                {
-                                    label.set_text("Unlocked");
-                                    label.remove_css_class("dim-label");
-                                    label.add_css_class("success");
-                                } else if output.contains("Exists:      yes")
-                                    || output.contains("Exists: yes")
-                                {
-                                    label.set_text("Locked");
-                                    label.remove_css_class("dim-label");
-                                    label.add_css_class("warning");
-                                } else {
-                                    label.set_text("Not initialized");
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
                                 }
                             }
-                            Err(_) => {
-                                label.set_text("Unavailable");
-                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving directory rules failed", &e),
+                                Some("error"),
+                            ),
                         }
                     });
+                })
+            };
+
+            for rule in &config.settings.directory_rules {
+                let row = adw::ActionRow::new();
+                row.set_title(&glib::markup_escape_text(&rule.path));
+                row.set_subtitle(&glib::markup_escape_text(&rule.identity));
+                if !config.identities.contains_key(&rule.identity) {
+                    row.add_css_class("error");
+                    row.set_subtitle(&glib::markup_escape_text(&format!(
+                        "{} (no such identity)",
+                        rule.identity
+                    )));
                 }
+                let remove_button = gtk4::Button::from_icon_name("user-trash-symbolic");
+                remove_button.set_valign(gtk4::Align::Center);
+                remove_button.set_tooltip_text(Some("Remove rule"));
+                row.add_suffix(&remove_button);
+                group.add(&row);
 
-                // Initialize key store button row
-                let init_row = adw::ActionRow::new();
-                init_row.set_title("Initialize Key Store");
-                init_row.set_subtitle("Create a new kdbx credential database");
-                let init_button = gtk4::Button::with_label("Initialize");
-                init_button.set_valign(gtk4::Align::Center);
-                init_button.add_css_class("suggested-action");
-                init_row.add_suffix(&init_button);
-                init_row.set_activatable_widget(Some(&init_button));
-                keys_group.add(&init_row);
+                let save_rules = save_rules.clone();
+                let rule = rule.clone();
+                remove_button.connect_clicked(move |_| {
+                    let rule = rule.clone();
+                    save_rules(Box::new(move |rules| rules.retain(|r| *r != rule)));
+                });
+            }
 
-                // Wire init button
-                {
-                    let status_clone = status_label.clone();
-                    let keys_label = keys_status_label.clone();
-                    init_button.connect_clicked(move |button| {
-                        button.set_sensitive(false);
-                        let status = status_clone.clone();
-                        let klabel = keys_label.clone();
-                        let btn = button.clone();
-                        status.set_text("Initializing key store...");
-                        status.set_visible(true);
-                        status.remove_css_class("error");
-                        status.remove_css_class("success");
+            let names = config.identity_names();
+            let identity_row = adw::ComboRow::new();
+            identity_row.set_title("New Rule");
+            identity_row.set_subtitle("Identity for the directory");
+            let name_refs: Vec<&str> = names.iter().map(String::as_str).collect();
+            identity_row.set_model(Some(&gtk4::StringList::new(&name_refs)));
+            let add_button = gtk4::Button::with_label("Directory…");
+            add_button.set_valign(gtk4::Align::Center);
+            add_button.set_sensitive(!names.is_empty());
+            identity_row.add_suffix(&add_button);
+            group.add(&identity_row);
 
-                        glib::spawn_future_local(async move {
-                            let result = run_cli_async("keys", "init").await;
-                            match result {
-                                Ok(_) => {
-                                    status.set_text("Key store initialized");
-                                    status.add_css_class("success");
-                                    klabel.set_text("Ready");
-                                    klabel.remove_css_class("dim-label");
-                                    klabel.add_css_class("success");
-                                }
-                                Err(e) => {
-                                    status.set_text(&format!("Init failed: {}", e));
-                                    status.add_css_class("error");
-                                }
+            let suggest_row = adw::ExpanderRow::new();
+            suggest_row.set_title("Suggested Rules");
+            suggest_row.set_subtitle("From the organizations of clones under the workspace roots");
+            let suggest_button = gtk4::Button::with_label("Suggest");
+            suggest_button.set_valign(gtk4::Align::Center);
+            suggest_button.set_sensitive(!config.settings.workspace_roots.is_empty());
+            suggest_row.add_action(&suggest_button);
+            group.add(&suggest_row);
+
+            let includes_row = adw::ActionRow::new();
+            includes_row.set_title("~/.gitconfig Includes");
+            includes_row.set_subtitle("Review the diff before writing");
+            let review_button = gtk4::Button::with_label("Review");
+            review_button.set_valign(gtk4::Align::Center);
+            let write_button = gtk4::Button::with_label("Write");
+            write_button.set_valign(gtk4::Align::Center);
+            write_button.add_css_class("suggested-action");
+            write_button.set_sensitive(false);
+            includes_row.add_suffix(&review_button);
+            includes_row.add_suffix(&write_button);
+            group.add(&includes_row);
+
+            let diff = gtk4::Label::new(None);
+            diff.add_css_class("monospace");
+            diff.set_xalign(0.0);
+            diff.set_selectable(true);
+            diff.set_margin_start(12);
+            diff.set_margin_end(12);
+            diff.set_margin_top(6);
+            diff.set_margin_bottom(6);
+            diff.set_visible(false);
+            group.add(&diff);
+
+            // Wire suggest button: list a rule for each directory whose
+            // clones all belong to one identity's organizations
+            {
+                let config = config.clone();
+                let status = status.clone();
+                let save_rules = save_rules.clone();
+                let shown: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::default();
+                suggest_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    let btn = button.clone();
+                    let config = config.clone();
+                    let status = status.clone();
+                    let save_rules = save_rules.clone();
+                    let shown = shown.clone();
+                    let expander = suggest_row.clone();
+                    glib::spawn_future_local(async move {
+                        let rules = gio::spawn_blocking(move || orgs::suggest(&config))
+                            .await
+                            .unwrap_or_default();
+                        btn.set_sensitive(true);
+                        for row in shown.borrow_mut().drain(..) {
+                            expander.remove(&row);
+                        }
+                        expander.set_subtitle(&match rules.len() {
+                            0 => {
+                                "No suggestions; add organizations to identities first".to_string()
                             }
-                            btn.set_sensitive(true);
+                            1 => "1 suggestion".to_string(),
+                            n => format!("{} suggestions", n),
                         });
-                    });
-                }
+                        for rule in rules {
+                            let row = adw::ActionRow::new();
+                            row.set_title(&glib::markup_escape_text(&rule.path));
+                            row.set_subtitle(&glib::markup_escape_text(&rule.identity));
+                            let add = gtk4::Button::with_label("Add");
+                            add.set_valign(gtk4::Align::Center);
+                            row.add_suffix(&add);
+                            expander.add_row(&row);
+                            shown.borrow_mut().push(row);
 
-                // Search entry row
-                let search_row = adw::ActionRow::new();
-                search_row.set_title("Search Keys");
-                search_row.set_subtitle("Fuzzy search across all stored credentials");
-                let search_entry = gtk4::Entry::new();
-                search_entry.set_placeholder_text(Some("Search..."));
-                search_entry.set_hexpand(true);
-                search_entry.set_valign(gtk4::Align::Center);
-                search_row.add_suffix(&search_entry);
-                search_row.set_activatable_widget(Some(&search_entry));
-                keys_group.add(&search_row);
+                            let save_rules = save_rules.clone();
+                            add.connect_clicked(move |_| {
+                                let rule = rule.clone();
+                                save_rules(Box::new(move |rules| {
+                                    rules.retain(|r| r.path != rule.path);
+                                    rules.push(rule);
+                                }));
+                            });
+                        }
+                        expander.set_expanded(!shown.borrow().is_empty());
+                        if shown.borrow().is_empty() {
+                            show_status(&status, "No directory rules to suggest", None);
+                        }
+                    });
+                });
+            }
 
-                // Search results label (hidden initially)
-                let search_results_label = gtk4::Label::new(None);
-                search_results_label.set_wrap(true);
-                search_results_label.set_xalign(0.0);
-                search_results_label.add_css_class("dim-label");
-                search_results_label.add_css_class("monospace");
-                search_results_label.set_visible(false);
+            // Wire add button: choose the directory for the selected identity
+            {
+                let window_ref = self.obj().clone();
+                add_button.connect_clicked(move |_| {
+                    let Some(identity) = names.get(identity_row.selected() as usize).cloned()
+                    else {
+                        return;
+                    };
+                    let dialog = gtk4::FileDialog::new();
+                    dialog.set_title("Select Directory");
+                    let save_rules = save_rules.clone();
+                    dialog.select_folder(
+                        Some(&window_ref),
+                        gio::Cancellable::NONE,
+                        move |result| {
+                            let Some(dir) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                                return;
+                            };
+                            let rule = DirectoryRule {
+                                path: platform::tilde_path(&dir),
+                                identity,
+                            };
+                            save_rules(Box::new(move |rules| {
+                                rules.retain(|r| r.path != rule.path);
+                                rules.push(rule);
+                            }));
+                        },
+                    );
+                });
+            }
 
-                // Wire search entry activate
-                {
-                    let results_label = search_results_label.clone();
-                    search_entry.connect_activate(move |entry| {
-                        let query = entry.text().to_string();
-                        if query.is_empty() {
-                            return;
-                        }
-                        let label = results_label.clone();
-                        label.set_text("Searching...");
-                        label.set_visible(true);
+            let plan: Rc<RefCell<Option<gitconfig::Plan>>> = Rc::default();
 
-                        glib::spawn_future_local(async move {
-                            let result =
-                                run_cli_args_async(vec!["keys".into(), "search".into(), query])
-                                    .await;
-                            match result {
-                                Ok(output) => {
-                                    label.set_text(&output);
-                                }
-                                Err(e) => {
-                                    label.set_text(&format!("Search error: {}", e));
-                                }
+            // Wire review button: show what writing the includes would change
+            {
+                let plan = plan.clone();
+                let row = includes_row.clone();
+                let diff = diff.clone();
+                let write_button = write_button.clone();
+                let config = config.clone();
+                let status = status.clone();
+                review_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    write_button.set_sensitive(false);
+                    let btn = button.clone();
+                    let plan = plan.clone();
+                    let row = row.clone();
+                    let diff = diff.clone();
+                    let write_button = write_button.clone();
+                    let config = config.clone();
+                    let status = status.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || {
+                            gitconfig::plan(&config).map_err(Error::from)
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        btn.set_sensitive(true);
+                        let reviewed = match result {
+                            Ok(reviewed) => reviewed,
+                            Err(e) => {
+                                show_status(
+                                    &status,
+                                    &error_text("Reading ~/.gitconfig failed", &e),
+                                    Some("error"),
+                                );
+                                return;
                             }
-                        });
+                        };
+                        row.set_subtitle(reviewed.state.display_text());
+                        if reviewed.is_noop() {
+                            diff.set_visible(false);
+                            show_status(&status, "~/.gitconfig includes are up to date", None);
+                        } else {
+                            let text = reviewed.diff();
+                            diff.set_text(if text.is_empty() {
+                                "Only the identity fragments change"
+                            } else {
+                                &text
+                            });
+                            diff.set_visible(true);
+                            let edited = reviewed.state == BlockState::Edited;
+                            write_button.set_label(if edited { "Overwrite" } else { "Write" });
+                            if edited {
+                                write_button.remove_css_class("suggested-action");
+                                write_button.add_css_class("destructive-action");
+                            } else {
+                                write_button.remove_css_class("destructive-action");
+                                write_button.add_css_class("suggested-action");
+                            }
+                            write_button.set_sensitive(reviewed.state != BlockState::Unterminated);
+                        }
+                        if !reviewed.unknown.is_empty() {
+                            show_status(
+                                &status,
+                                &format!(
+                                    "Left out, the identity doesn't exist: {}",
+                                    reviewed.unknown.join(", ")
+                                ),
+                                Some("warning"),
+                            );
+                        }
+                        *plan.borrow_mut() = Some(reviewed);
                     });
-                }
+                });
+            }
 
-                // Ingest .env row
-                let ingest_row = adw::ActionRow::new();
-                ingest_row.set_title("Ingest .env File");
-                ingest_row.set_subtitle("Import environment variables into key store");
-                let ingest_button = gtk4::Button::with_label("Choose File");
-                ingest_button.set_valign(gtk4::Align::Center);
-                ingest_row.add_suffix(&ingest_button);
-                ingest_row.set_activatable_widget(Some(&ingest_button));
-                keys_group.add(&ingest_row);
+            // Wire write button: write the fragments and the reviewed block
+            let status = status.clone();
+            write_button.connect_clicked(move |button| {
+                let Some(reviewed) = plan.borrow_mut().take() else {
+                    return;
+                };
+                button.set_sensitive(false);
+                let row = includes_row.clone();
+                let diff = diff.clone();
+                let status = status.clone();
+                glib::spawn_future_local(async move {
+                    let result = gio::spawn_blocking(move || {
+                        gitconfig::write(&reviewed).map_err(Error::from)
+                    })
+                    .await
+                    .unwrap_or_else(|_| Err(worker_panicked()));
+                    diff.set_visible(false);
+                    match result {
+                        Ok(()) => {
+                            row.set_subtitle(BlockState::Intact.display_text());
+                            show_status(
+                                &status,
+                                "Wrote includes to ~/.gitconfig (previous copy in .gitconfig.bak)",
+                                Some("success"),
+                            );
+                        }
+                        Err(e) => show_status(
+                            &status,
+                            &error_text("Writing ~/.gitconfig failed", &e),
+                            Some("error"),
+                        ),
+                    }
+                });
+            });
 
-                // Wire ingest button to open file chooser
-                {
-                    let status_clone = status_label.clone();
-                    let window_ref = self.obj().clone();
-                    ingest_button.connect_clicked(move |_button| {
-                        let dialog = gtk4::FileDialog::new();
-                        dialog.set_title("Select .env file");
-                        let filter = gtk4::FileFilter::new();
-                        filter.add_pattern("*.env");
-                        filter.add_pattern(".env*");
-                        filter.set_name(Some("Environment files"));
-                        let filters = gio::ListStore::new::<gtk4::FileFilter>();
-                        filters.append(&filter);
-                        dialog.set_filters(Some(&filters));
+            group
+        }
 
-                        let status = status_clone.clone();
-                        dialog.open(Some(&window_ref), gio::Cancellable::NONE, move |result| {
-                            if let Ok(file) = result {
-                                if let Some(path) = file.path() {
-                                    let path_str = path.to_string_lossy().to_string();
-                                    let st = status.clone();
-                                    st.set_text(&format!("Ingesting {}...", &path_str));
-                                    st.set_visible(true);
-                                    st.remove_css_class("error");
-                                    st.remove_css_class("success");
+        /// Organizations routed through their identity's host alias by
+        /// managed insteadOf rules in ~/.gitconfig
+        fn build_url_rewrites_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("URL Rewrites");
+            group.set_description(Some(
+                "SSH clones of an identity's organizations go through its host alias via \
+                 insteadOf rules in ~/.gitconfig",
+            ));
 
-                                    glib::spawn_future_local(async move {
-                                        let result = run_cli_args_async(vec![
-                                            "keys".into(),
-                                            "ingest".into(),
-                                            path_str.clone(),
-                                        ])
-                                        .await;
-                                        match result {
-                                            Ok(output) => {
-                                                st.set_text(&format!(
-                                                    "Ingested: {}",
-                                                    output.lines().last().unwrap_or("done")
-                                                ));
-                                                st.add_css_class("success");
-                                            }
-                                            Err(e) => {
-                                                st.set_text(&format!("Ingest failed: {}", e));
-                                                st.add_css_class("error");
-                                            }
-                                        }
-                                    });
+            // Save changed organizations and rebuild the page
+            let save_config = {
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |change: ConfigChange| {
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || -> Result<(), Error> {
+                            Config::update(change)?;
+                            Ok(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
                                 }
                             }
-                        });
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving organizations failed", &e),
+                                Some("error"),
+                            ),
+                        }
                     });
-                }
+                })
+            };
 
-                // Get/Copy credential row
-                let get_row = adw::ActionRow::new();
-                get_row.set_title("Get Credential");
-                get_row.set_subtitle("Retrieve and copy a secret to clipboard");
-                let get_entry = gtk4::Entry::new();
-                get_entry.set_placeholder_text(Some("Entry path..."));
-                get_entry.set_hexpand(true);
-                get_entry.set_valign(gtk4::Align::Center);
-                let copy_button = gtk4::Button::with_label("Copy");
-                copy_button.set_valign(gtk4::Align::Center);
-                get_row.add_suffix(&get_entry);
-                get_row.add_suffix(&copy_button);
-                keys_group.add(&get_row);
+            let (rewrites, _) = url_rewrites::rewrites(config);
+            for rewrite in rewrites {
+                let row = adw::ActionRow::new();
+                row.set_title(&glib::markup_escape_text(&rewrite.prefixes[0]));
+                row.set_subtitle(&glib::markup_escape_text(&format!(
+                    "Through {} ({})",
+                    rewrite.base, rewrite.identity
+                )));
+                let remove_button = gtk4::Button::from_icon_name("user-trash-symbolic");
+                remove_button.set_valign(gtk4::Align::Center);
+                remove_button.set_tooltip_text(Some("Remove organization from the identity"));
+                row.add_suffix(&remove_button);
+                group.add(&row);
 
-                // Wire copy button
-                {
-                    let entry_clone = get_entry.clone();
-                    let status_clone = status_label.clone();
-                    copy_button.connect_clicked(move |_| {
-                        let path = entry_clone.text().to_string();
-                        if path.is_empty() {
-                            return;
+                let save_config = save_config.clone();
+                remove_button.connect_clicked(move |_| {
+                    let identity = rewrite.identity.clone();
+                    let org = rewrite.organization.clone();
+                    save_config(Box::new(move |config| {
+                        if let Some(identity) = config.identities.get_mut(&identity) {
+                            identity
+                                .organizations
+                                .retain(|o| o.trim_matches('/') != org);
                         }
-                        let status = status_clone.clone();
-                        glib::spawn_future_local(async move {
-                            let result =
-                                run_cli_args_async(vec!["keys".into(), "get".into(), path]).await;
-                            match result {
-                                Ok(value) => {
+                    }));
+                });
+            }
+
+            let names = config.identity_names();
+            let add_row = adw::ComboRow::new();
+            add_row.set_title("New Organization");
+            add_row.set_subtitle("Identity and organization, e.g. acme");
+            let name_refs: Vec<&str> = names.iter().map(String::as_str).collect();
+            add_row.set_model(Some(&gtk4::StringList::new(&name_refs)));
+            let org_entry = gtk4::Entry::new();
+            org_entry.set_placeholder_text(Some("Organization"));
+            org_entry.set_valign(gtk4::Align::Center);
+            let add_button = gtk4::Button::with_label("Add");
+            add_button.set_valign(gtk4::Align::Center);
+            add_button.set_sensitive(!names.is_empty());
+            add_row.add_suffix(&org_entry);
+            add_row.add_suffix(&add_button);
+            group.add(&add_row);
+
+            let discover_row = adw::ActionRow::new();
+            discover_row.set_title("Discover Organizations");
+            discover_row.set_subtitle(
+                "Add the organizations and groups each identity's account belongs to",
+            );
+            let discover_button = gtk4::Button::with_label("Discover");
+            discover_button.set_valign(gtk4::Align::Center);
+            discover_button.set_sensitive(!names.is_empty());
+            discover_row.add_suffix(&discover_button);
+            group.add(&discover_row);
+
+            let rules_row = adw::ActionRow::new();
+            rules_row.set_title("~/.gitconfig Rewrites");
+            rules_row.set_subtitle("Review the diff before writing");
+            let review_button = gtk4::Button::with_label("Review");
+            review_button.set_valign(gtk4::Align::Center);
+            let write_button = gtk4::Button::with_label("Write");
+            write_button.set_valign(gtk4::Align::Center);
+            write_button.add_css_class("suggested-action");
+            write_button.set_sensitive(false);
+            rules_row.add_suffix(&review_button);
+            rules_row.add_suffix(&write_button);
+            group.add(&rules_row);
+
+            let diff = gtk4::Label::new(None);
+            diff.add_css_class("monospace");
+            diff.set_xalign(0.0);
+            diff.set_selectable(true);
+            diff.set_margin_start(12);
+            diff.set_margin_end(12);
+            diff.set_margin_top(6);
+            diff.set_margin_bottom(6);
+            diff.set_visible(false);
+            group.add(&diff);
+
+            // Wire discover button: ask each provider for the account's
+            // memberships and add the ones no other identity could own
+            {
+                let config = config.clone();
+                let status = status.clone();
+                let save_config = save_config.clone();
+                discover_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    show_status(&status, "Asking providers for memberships…", None);
+                    let btn = button.clone();
+                    let row = discover_row.clone();
+                    let config = config.clone();
+                    let status = status.clone();
+                    let save_config = save_config.clone();
+                    glib::spawn_future_local(async move {
+                        let (discoveries, failed) =
+                            gio::spawn_blocking(move || orgs::discover(&config))
+                                .await
+                                .unwrap_or_else(|_| {
+                                    (Vec::new(), vec![(String::new(), worker_panicked())])
+                                });
+                        btn.set_sensitive(true);
+                        let summary: Vec<String> =
+                            discoveries.iter().map(|d| d.display_text()).collect();
+                        row.set_subtitle(&glib::markup_escape_text(&if summary.is_empty() {
+                            "No identity has a token to ask with".to_string()
+                        } else {
+                            summary.join("\n")
+                        }));
+                        if let Some((name, e)) = failed.first() {
+                            show_status(
+                                &status,
+                                &error_text(
+                                    &format!("Discovering {}'s organizations failed", name),
+                                    e,
+                                ),
+                                Some("error"),
+                            );
+                        }
+                        let added: usize = discoveries.iter().map(|d| d.added.len()).sum();
+                        if added == 0 {
+                            if failed.is_empty() {
+                                show_status(&status, "No new organizations", None);
+                            }
+                            return;
+                        }
+                        save_config(Box::new(move |config| orgs::apply(config, &discoveries)));
+                    });
+                });
+            }
+
+            // Wire add button: add the organization to the selected identity
+            {
+                let status = status.clone();
+                add_button.connect_clicked(move |_| {
+                    let Some(name) = names.get(add_row.selected() as usize).cloned() else {
+                        return;
+                    };
+                    let org = org_entry.text().trim().trim_matches('/').to_string();
+                    if org.is_empty() {
+                        show_status(&status, "Enter an organization", Some("error"));
+                        return;
+                    }
+                    save_config(Box::new(move |config| {
+                        if let Some(identity) = config.identities.get_mut(&name) {
+                            if !identity.organizations.contains(&org) {
+                                identity.organizations.push(org);
+                            }
+                        }
+                    }));
+                });
+            }
+
+            let plan: Rc<RefCell<Option<url_rewrites::Plan>>> = Rc::default();
+
+            // Wire review button: show what writing the rules would change
+            {
+                let plan = plan.clone();
+                let row = rules_row.clone();
+                let diff = diff.clone();
+                let write_button = write_button.clone();
+                let config = config.clone();
+                let status = status.clone();
+                review_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    write_button.set_sensitive(false);
+                    let btn = button.clone();
+                    let plan = plan.clone();
+                    let row = row.clone();
+                    let diff = diff.clone();
+                    let write_button = write_button.clone();
+                    let config = config.clone();
+                    let status = status.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || {
+                            url_rewrites::plan(&config).map_err(Error::from)
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        btn.set_sensitive(true);
+                        let reviewed = match result {
+                            Ok(reviewed) => reviewed,
+                            Err(e) => {
+                                show_status(
+                                    &status,
+                                    &error_text("Reading ~/.gitconfig failed", &e),
+                                    Some("error"),
+                                );
+                                return;
+                            }
+                        };
+                        row.set_subtitle(reviewed.state.display_text());
+                        if reviewed.is_noop() {
+                            diff.set_visible(false);
+                            show_status(&status, "~/.gitconfig rewrites are up to date", None);
+                        } else {
+                            diff.set_text(&reviewed.diff());
+                            diff.set_visible(true);
+                            let edited = reviewed.state == BlockState::Edited;
+                            write_button.set_label(if edited { "Overwrite" } else { "Write" });
+                            if edited {
+                                write_button.remove_css_class("suggested-action");
+                                write_button.add_css_class("destructive-action");
+                            } else {
+                                write_button.remove_css_class("destructive-action");
+                                write_button.add_css_class("suggested-action");
+                            }
+                            write_button.set_sensitive(reviewed.state != BlockState::Unterminated);
+                        }
+                        let warnings: Vec<String> = reviewed
+                            .conflicts
+                            .iter()
+                            .map(url_rewrites::Conflict::display_text)
+                            .chain(
+                                reviewed
+                                    .duplicates
+                                    .iter()
+                                    .map(|d| format!("Claimed twice, left to the first: {}", d)),
+                            )
+                            .collect();
+                        if !warnings.is_empty() {
+                            show_status(&status, &warnings.join("; "), Some("warning"));
+                        }
+                        *plan.borrow_mut() = Some(reviewed);
+                    });
+                });
+            }
+
+            // Wire write button: write the reviewed block
+            let status = status.clone();
+            write_button.connect_clicked(move |button| {
+                let Some(reviewed) = plan.borrow_mut().take() else {
+                    return;
+                };
+                button.set_sensitive(false);
+                let row = rules_row.clone();
+                let diff = diff.clone();
+                let status = status.clone();
+                glib::spawn_future_local(async move {
+                    let result = gio::spawn_blocking(move || {
+                        url_rewrites::write(&reviewed).map_err(Error::from)
+                    })
+                    .await
+                    .unwrap_or_else(|_| Err(worker_panicked()));
+                    diff.set_visible(false);
+                    match result {
+                        Ok(()) => {
+                            row.set_subtitle(BlockState::Intact.display_text());
+                            show_status(
+                                &status,
+                                "Wrote URL rewrites to ~/.gitconfig (previous copy in .gitconfig.bak)",
+                                Some("success"),
+                            );
+                        }
+                        Err(e) => show_status(
+                            &status,
+                            &error_text("Writing ~/.gitconfig failed", &e),
+                            Some("error"),
+                        ),
+                    }
+                });
+            });
+
+            group
+        }
+
+        /// Repositories with the pre-commit identity guard, and the
+        /// global core.hooksPath guard
+        fn build_commit_guard_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Commit Guard");
+            group.set_description(Some(
+                "A pre-commit hook refuses commits whose author isn't the identity detected \
+                 for the repository; commit once anyway with REMOTE_JUGGLER_HOOKS_DISABLED=1",
+            ));
+
+            // Run a blocking guard change and rebuild the page
+            let run_change = {
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |change: GuardChange, failure: &'static str| {
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(change)
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => show_status(&status, &error_text(failure, &e), Some("error")),
+                        }
+                    });
+                })
+            };
+
+            let global_row = adw::ActionRow::new();
+            global_row.set_title("All Repositories");
+            global_row.set_subtitle(
+                "Guard every repository with an expected identity through core.hooksPath",
+            );
+            let global_switch = gtk4::Switch::new();
+            global_switch.set_valign(gtk4::Align::Center);
+            global_switch.set_sensitive(false);
+            global_row.add_suffix(&global_switch);
+            global_row.set_activatable_widget(Some(&global_switch));
+            group.add(&global_row);
+
+            // Probe the global hook, then let the switch install or remove it
+            {
+                let global_switch = global_switch.clone();
+                let run_change = run_change.clone();
+                self.schedule_probe(async move {
+                    let installed = gio::spawn_blocking(hooks::global_installed)
+                        .await
+                        .unwrap_or(false);
+                    global_switch.set_active(installed);
+                    global_switch.set_sensitive(true);
+                    global_switch.connect_state_set(move |_, active| {
+                        if active {
+                            run_change(
+                                Box::new(|| hooks::install_global().map(|_| ())),
+                                "Installing the global guard failed",
+                            );
+                        } else {
+                            run_change(
+                                Box::new(hooks::uninstall_global),
+                                "Removing the global guard failed",
+                            );
+                        }
+                        glib::Propagation::Proceed
+                    });
+                });
+            }
+
+            let store = GuardStore::load();
+            for guarded in store.repos {
+                let row = adw::ActionRow::new();
+                row.set_title(&glib::markup_escape_text(&platform::tilde_path(
+                    &guarded.path,
+                )));
+                row.set_subtitle(&glib::markup_escape_text(&format!(
+                    "{} <{}>",
+                    guarded.identity, guarded.email
+                )));
+                if !config.identities.contains_key(&guarded.identity) {
+                    row.add_css_class("error");
+                }
+                let remove_button = gtk4::Button::from_icon_name("user-trash-symbolic");
+                remove_button.set_valign(gtk4::Align::Center);
+                remove_button.set_tooltip_text(Some("Remove guard"));
+                row.add_suffix(&remove_button);
+                group.add(&row);
+
+                let run_change = run_change.clone();
+                remove_button.connect_clicked(move |_| {
+                    let path = guarded.path.clone();
+                    run_change(
+                        Box::new(move || hooks::uninstall(&path)),
+                        "Removing the guard failed",
+                    );
+                });
+            }
+
+            let actions_row = adw::ActionRow::new();
+            actions_row.set_title("Guarded Repositories");
+            actions_row.set_subtitle("Update after changing identities or rules");
+            let update_button = gtk4::Button::with_label("Update");
+            update_button.set_valign(gtk4::Align::Center);
+            let add_button = gtk4::Button::with_label("Add Repository…");
+            add_button.set_valign(gtk4::Align::Center);
+            actions_row.add_suffix(&update_button);
+            actions_row.add_suffix(&add_button);
+            group.add(&actions_row);
+
+            // Wire update button: detect every guarded repository's identity again
+            {
+                let run_change = run_change.clone();
+                let config = config.clone();
+                update_button.connect_clicked(move |_| {
+                    let config = config.clone();
+                    run_change(
+                        Box::new(move || {
+                            let failed = hooks::update_all(&config)?;
+                            if failed.is_empty() {
+                                return Ok(());
+                            }
+                            let paths: Vec<String> = failed
+                                .iter()
+                                .map(|(path, e)| format!("{} ({})", platform::tilde_path(path), e))
+                                .collect();
+                            Err(Error::InvalidOutput {
+                                program: "detect",
+                                message: paths.join(", "),
+                            })
+                        }),
+                        "Updating some repositories failed",
+                    );
+                });
+            }
+
+            // Wire add button: guard a chosen repository
+            let window_ref = self.obj().clone();
+            let config = config.clone();
+            add_button.connect_clicked(move |_| {
+                let dialog = gtk4::FileDialog::new();
+                dialog.set_title("Select Repository");
+                let run_change = run_change.clone();
+                let config = config.clone();
+                dialog.select_folder(Some(&window_ref), gio::Cancellable::NONE, move |result| {
+                    let Some(dir) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                        return;
+                    };
+                    run_change(
+                        Box::new(move || hooks::install(&config, &dir).map(|_| ())),
+                        "Guarding the repository failed",
+                    );
+                });
+            });
+
+            group
+        }
+
+        /// Recent commits made with another identity's email, with an amend
+        /// for HEAD and rewrite commands for older ones
+        fn build_authorship_group(&self, status: &gtk4::Label) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Commit Authors");
+            group.set_description(Some(
+                "Recent commits authored with another identity's email than the one \
+                 detected for the repository",
+            ));
+
+            let check_row = adw::ActionRow::new();
+            check_row.set_title("Check Recent Commits");
+            check_row.set_subtitle("No repository chosen");
+            let check_button = gtk4::Button::with_label("Repository…");
+            check_button.set_valign(gtk4::Align::Center);
+            check_row.add_suffix(&check_button);
+            group.add(&check_row);
+
+            let commits = gtk4::Label::new(None);
+            commits.add_css_class("monospace");
+            commits.set_xalign(0.0);
+            commits.set_selectable(true);
+            commits.set_wrap(true);
+            commits.set_margin_start(12);
+            commits.set_margin_end(12);
+            commits.set_margin_top(6);
+            commits.set_margin_bottom(6);
+            commits.set_visible(false);
+            group.add(&commits);
+
+            let fix_row = adw::ActionRow::new();
+            fix_row.set_title("Fix Authors");
+            fix_row.set_subtitle("Amend HEAD, or copy commands that rewrite older commits");
+            let amend_button = gtk4::Button::with_label("Amend HEAD");
+            amend_button.set_valign(gtk4::Align::Center);
+            let copy_button = gtk4::Button::with_label("Copy Commands");
+            copy_button.set_valign(gtk4::Align::Center);
+            fix_row.add_suffix(&amend_button);
+            fix_row.add_suffix(&copy_button);
+            fix_row.set_visible(false);
+            group.add(&fix_row);
+
+            let checked: Rc<RefCell<Option<(PathBuf, Analysis)>>> = Rc::default();
+
+            // Check a repository and show what was found
+            let analyze = {
+                let checked = checked.clone();
+                let row = check_row.clone();
+                let button = check_button.clone();
+                let commits = commits.clone();
+                let fix_row = fix_row.clone();
+                let amend_button = amend_button.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |repo: PathBuf| {
+                    let Some(config) = imp_weak
+                        .upgrade()
+                        .and_then(|imp| imp.config.borrow().clone())
+                    else {
+                        return;
+                    };
+                    button.set_sensitive(false);
+                    row.set_title(&glib::markup_escape_text(&scanner::repo_name(&repo)));
+                    row.set_subtitle("Checking commits...");
+                    row.remove_css_class("error");
+                    commits.set_visible(false);
+                    fix_row.set_visible(false);
+                    let checked = checked.clone();
+                    let row = row.clone();
+                    let button = button.clone();
+                    let commits = commits.clone();
+                    let fix_row = fix_row.clone();
+                    let amend_button = amend_button.clone();
+                    glib::spawn_future_local(async move {
+                        let path = repo.clone();
+                        let result = gio::spawn_blocking(move || {
+                            authorship::analyze(&config, &path, authorship::DEFAULT_LIMIT)
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        button.set_sensitive(true);
+                        match result {
+                            Ok(analysis) => {
+                                row.set_subtitle(&glib::markup_escape_text(
+                                    &analysis.display_text(),
+                                ));
+                                if !analysis.wrong.is_empty() {
+                                    row.add_css_class("error");
+                                    commits.set_text(&analysis.commit_lines());
+                                    commits.set_visible(true);
+                                    amend_button.set_sensitive(analysis.head_is_wrong());
+                                    fix_row.set_visible(true);
+                                }
+                                *checked.borrow_mut() = Some((repo, analysis));
+                            }
+                            Err(e) => {
+                                row.set_subtitle(&glib::markup_escape_text(&error_text(
+                                    "Checking commits failed",
+                                    &e,
+                                )));
+                                row.add_css_class("error");
+                            }
+                        }
+                    });
+                })
+            };
+
+            // Wire check button: choose a repository
+            {
+                let window_ref = self.obj().clone();
+                let analyze = analyze.clone();
+                check_button.connect_clicked(move |_| {
+                    let dialog = gtk4::FileDialog::new();
+                    dialog.set_title("Select Repository");
+                    let analyze = analyze.clone();
+                    dialog.select_folder(
+                        Some(&window_ref),
+                        gio::Cancellable::NONE,
+                        move |result| {
+                            if let Some(repo) = result.ok().and_then(|f| portal::file_path(&f)) {
+                                analyze(repo);
+                            }
+                        },
+                    );
+                });
+            }
+
+            // Wire amend button: fix HEAD's author, then check again
+            {
+                let checked = checked.clone();
+                let status = status.clone();
+                amend_button.connect_clicked(move |button| {
+                    let Some((repo, analysis)) = checked.borrow().clone() else {
+                        return;
+                    };
+                    button.set_sensitive(false);
+                    let analyze = analyze.clone();
+                    let status = status.clone();
+                    glib::spawn_future_local(async move {
+                        let path = repo.clone();
+                        let pushed = analysis.wrong.first().is_some_and(|c| c.pushed);
+                        let result =
+                            gio::spawn_blocking(move || authorship::amend_head(&path, &analysis))
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => show_status(
+                                &status,
+                                if pushed {
+                                    "Amended HEAD; it was pushed, so the branch needs a force push"
+                                } else {
+                                    "Amended HEAD with the expected author"
+                                },
+                                Some(if pushed { "warning" } else { "success" }),
+                            ),
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Amending HEAD failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                        analyze(repo);
+                    });
+                });
+            }
+
+            // Wire copy button: the rewrite commands for the whole range
+            let status = status.clone();
+            copy_button.connect_clicked(move |_| {
+                let Some((_, analysis)) = &*checked.borrow() else {
+                    return;
+                };
+                if let Some(display) = gdk::Display::default() {
+                    display.clipboard().set_text(&analysis.rewrite_commands());
+                }
+                show_status(
+                    &status,
+                    "Rewrite commands copied; review them and run them in the repository",
+                    None,
+                );
+            });
+
+            group
+        }
+
+        /// Repositories under the workspace roots, with the identity each
+        /// commits as and the one it should
+        fn build_workspace_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Workspace");
+            group.set_description(Some(
+                "Every repository under the workspace roots, the identity it commits as and \
+                 the one RemoteJuggler would choose",
+            ));
+
+            // Save a changed root list and rebuild the page
+            let save_roots = {
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |change: RootsChange| {
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || -> Result<(), Error> {
+                            Config::update(|config| change(&mut config.settings.workspace_roots))?;
+                            Ok(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving workspace roots failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                })
+            };
+
+            for root in &config.settings.workspace_roots {
+                let row = adw::ActionRow::new();
+                row.set_title(&glib::markup_escape_text(root));
+                row.set_subtitle("Workspace root");
+                let remove_button = gtk4::Button::from_icon_name("user-trash-symbolic");
+                remove_button.set_valign(gtk4::Align::Center);
+                remove_button.set_tooltip_text(Some("Remove root"));
+                row.add_suffix(&remove_button);
+                group.add(&row);
+
+                let save_roots = save_roots.clone();
+                let root = root.clone();
+                remove_button.connect_clicked(move |_| {
+                    let root = root.clone();
+                    save_roots(Box::new(move |roots| roots.retain(|r| *r != root)));
+                });
+            }
+
+            let scan_row = adw::ActionRow::new();
+            scan_row.set_title("Scan Repositories");
+            scan_row.set_subtitle(if config.settings.workspace_roots.is_empty() {
+                "Add a root such as ~/code first"
+            } else {
+                "Not scanned yet"
+            });
+            let add_button = gtk4::Button::with_label("Add Root…");
+            add_button.set_valign(gtk4::Align::Center);
+            let scan_button = gtk4::Button::with_label("Scan");
+            scan_button.set_valign(gtk4::Align::Center);
+            scan_button.set_sensitive(!config.settings.workspace_roots.is_empty());
+            scan_row.add_suffix(&add_button);
+            scan_row.add_suffix(&scan_button);
+            group.add(&scan_row);
+
+            let fix_row = adw::ActionRow::new();
+            fix_row.set_title("Fix Mismatches");
+            fix_row.set_subtitle("Write the expected identity to each repository's own git config");
+            let fix_all_button = gtk4::Button::with_label("Fix All");
+            fix_all_button.set_valign(gtk4::Align::Center);
+            fix_all_button.add_css_class("suggested-action");
+            let copy_button = gtk4::Button::with_label("Copy Report");
+            copy_button.set_valign(gtk4::Align::Center);
+            fix_row.add_suffix(&copy_button);
+            fix_row.add_suffix(&fix_all_button);
+            fix_row.set_visible(false);
+            group.add(&fix_row);
+
+            // Wire add button: choose another root
+            {
+                let window_ref = self.obj().clone();
+                add_button.connect_clicked(move |_| {
+                    let dialog = gtk4::FileDialog::new();
+                    dialog.set_title("Select Workspace Root");
+                    let save_roots = save_roots.clone();
+                    dialog.select_folder(
+                        Some(&window_ref),
+                        gio::Cancellable::NONE,
+                        move |result| {
+                            let Some(dir) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                                return;
+                            };
+                            let root = platform::tilde_path(&dir);
+                            save_roots(Box::new(move |roots| {
+                                if !roots.contains(&root) {
+                                    roots.push(root);
+                                }
+                            }));
+                        },
+                    );
+                });
+            }
+
+            let statuses: Rc<RefCell<Vec<workspace::RepoStatus>>> = Rc::default();
+            let rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::default();
+
+            // Scan the roots and list every repository, problems first
+            let scan = {
+                let config = config.clone();
+                let group = group.clone();
+                let scan_row = scan_row.clone();
+                let scan_button = scan_button.clone();
+                let fix_row = fix_row.clone();
+                let statuses = statuses.clone();
+                let rows = rows.clone();
+                let status = status.clone();
+                Rc::new(move || {
+                    scan_button.set_sensitive(false);
+                    scan_row.set_subtitle("Scanning...");
+                    let config = config.clone();
+                    let group = group.clone();
+                    let scan_row = scan_row.clone();
+                    let scan_button = scan_button.clone();
+                    let fix_row = fix_row.clone();
+                    let statuses = statuses.clone();
+                    let rows = rows.clone();
+                    let status = status.clone();
+                    glib::spawn_future_local(async move {
+                        let scan_config = config.clone();
+                        let found = gio::spawn_blocking(move || workspace::scan(&scan_config))
+                            .await
+                            .unwrap_or_default();
+                        scan_button.set_sensitive(true);
+                        for row in rows.borrow_mut().drain(..) {
+                            group.remove(&row);
+                        }
+                        let fixable = found.iter().filter(|s| s.state.is_fixable()).count();
+                        scan_row.set_subtitle(&match (found.len(), fixable) {
+                            (0, _) => "No repositories found".to_string(),
+                            (n, 0) => format!("{} repositories, no mismatches", n),
+                            (n, f) => format!("{} repositories, {} to fix", n, f),
+                        });
+                        fix_row.set_visible(!found.is_empty());
+                        for repo in &found {
+                            let row = workspace_row(repo, &config, &status);
+                            group.add(&row);
+                            rows.borrow_mut().push(row);
+                        }
+                        *statuses.borrow_mut() = found;
+                    });
+                })
+            };
+
+            // Wire scan button
+            {
+                let scan = scan.clone();
+                scan_button.connect_clicked(move |_| scan());
+            }
+
+            // Wire fix all button: fix every mismatch, then scan again
+            {
+                let statuses = statuses.clone();
+                let config = config.clone();
+                let status = status.clone();
+                fix_all_button.connect_clicked(move |button| {
+                    let fixable: Vec<workspace::RepoStatus> = statuses
+                        .borrow()
+                        .iter()
+                        .filter(|s| s.state.is_fixable())
+                        .cloned()
+                        .collect();
+                    if fixable.is_empty() {
+                        show_status(&status, "Every repository matches", None);
+                        return;
+                    }
+                    button.set_sensitive(false);
+                    let btn = button.clone();
+                    let config = config.clone();
+                    let status = status.clone();
+                    let scan = scan.clone();
+                    glib::spawn_future_local(async move {
+                        let failed = gio::spawn_blocking(move || {
+                            fixable
+                                .iter()
+                                .filter_map(|repo| {
+                                    workspace::fix(&config, repo).err().map(|e| {
+                                        format!("{}: {}", platform::tilde_path(&repo.path), e)
+                                    })
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                        .await
+                        .unwrap_or_else(|_| vec!["The worker panicked".to_string()]);
+                        btn.set_sensitive(true);
+                        if failed.is_empty() {
+                            show_status(&status, "Fixed every mismatch", Some("success"));
+                        } else {
+                            show_status(
+                                &status,
+                                &format!("Some fixes failed: {}", failed.join("; ")),
+                                Some("error"),
+                            );
+                        }
+                        scan();
+                    });
+                });
+            }
+
+            // Wire copy button: the report as text
+            let status = status.clone();
+            copy_button.connect_clicked(move |_| {
+                if let Some(display) = gdk::Display::default() {
+                    display
+                        .clipboard()
+                        .set_text(&workspace::report_text(&statuses.borrow()));
+                }
+                show_status(&status, "Workspace report copied", None);
+            });
+
+            group
+        }
+
+        /// Previewing and applying origin URLs for an identity's host alias,
+        /// and whether the CLI's switch rewrites them
+        fn build_remote_urls_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Remote URLs");
+            group.set_description(Some(
+                "Point a repository's origin at an identity's SSH host alias, so git uses \
+                 that identity's key",
+            ));
+
+            let rewrite_row = adw::ActionRow::new();
+            rewrite_row.set_title("Rewrite Origin on Switch");
+            rewrite_row.set_subtitle("The CLI's switch updates origin in the current repository");
+            let rewrite_switch = gtk4::Switch::new();
+            rewrite_switch.set_valign(gtk4::Align::Center);
+            rewrite_switch.set_active(!config.settings.keep_remote_urls);
+            rewrite_row.add_suffix(&rewrite_switch);
+            rewrite_row.set_activatable_widget(Some(&rewrite_switch));
+            group.add(&rewrite_row);
+            {
+                let status = status.clone();
+                rewrite_switch.connect_active_notify(move |switch| {
+                    let keep = !switch.is_active();
+                    let status = status.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || {
+                            Config::update(|config| config.settings.keep_remote_urls = keep)?;
+                            Ok::<_, Error>(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        if let Err(e) = result {
+                            show_status(
+                                &status,
+                                &error_text("Saving setting failed", &e),
+                                Some("error"),
+                            );
+                        }
+                    });
+                });
+            }
+
+            let names = config.identity_names();
+            let identity_row = adw::ComboRow::new();
+            identity_row.set_title("Identity");
+            let name_refs: Vec<&str> = names.iter().map(String::as_str).collect();
+            identity_row.set_model(Some(&gtk4::StringList::new(&name_refs)));
+            group.add(&identity_row);
+
+            let preview_row = adw::ActionRow::new();
+            preview_row.set_title("Preview");
+            preview_row.set_subtitle("No repository chosen");
+            let choose_button = gtk4::Button::with_label("Repository…");
+            choose_button.set_valign(gtk4::Align::Center);
+            choose_button.set_sensitive(!names.is_empty());
+            let apply_button = gtk4::Button::with_label("Apply");
+            apply_button.set_valign(gtk4::Align::Center);
+            apply_button.add_css_class("suggested-action");
+            apply_button.set_sensitive(false);
+            preview_row.add_suffix(&choose_button);
+            preview_row.add_suffix(&apply_button);
+            group.add(&preview_row);
+
+            let preview = gtk4::Label::new(None);
+            preview.add_css_class("monospace");
+            preview.set_xalign(0.0);
+            preview.set_selectable(true);
+            preview.set_wrap(true);
+            preview.set_margin_start(12);
+            preview.set_margin_end(12);
+            preview.set_margin_top(6);
+            preview.set_margin_bottom(6);
+            preview.set_visible(false);
+            group.add(&preview);
+
+            let planned: Rc<RefCell<Option<RemotesPlan>>> = Rc::default();
+
+            // Wire choose button: preview the changes for the selected identity
+            {
+                let window_ref = self.obj().clone();
+                let planned = planned.clone();
+                let row = preview_row.clone();
+                let preview = preview.clone();
+                let apply_button = apply_button.clone();
+                let config = config.clone();
+                choose_button.connect_clicked(move |_| {
+                    let Some(name) = names.get(identity_row.selected() as usize).cloned() else {
+                        return;
+                    };
+                    let dialog = gtk4::FileDialog::new();
+                    dialog.set_title("Select Repository");
+                    let planned = planned.clone();
+                    let row = row.clone();
+                    let preview = preview.clone();
+                    let apply_button = apply_button.clone();
+                    let config = config.clone();
+                    dialog.select_folder(
+                        Some(&window_ref),
+                        gio::Cancellable::NONE,
+                        move |result| {
+                            let Some(repo) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                                return;
+                            };
+                            row.set_subtitle("Reading remotes...");
+                            apply_button.set_sensitive(false);
+                            preview.set_visible(false);
+                            let planned = planned.clone();
+                            let row = row.clone();
+                            let preview = preview.clone();
+                            let apply_button = apply_button.clone();
+                            let config = config.clone();
+                            glib::spawn_future_local(async move {
+                                let path = repo.clone();
+                                let result = gio::spawn_blocking(move || {
+                                    remotes::plan(&config, &name, &path)
+                                })
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                                let changes = match result {
+                                    Ok(changes) => changes,
+                                    Err(e) => {
+                                        row.set_subtitle(&glib::markup_escape_text(&error_text(
+                                            "Reading remotes failed",
+                                            &e,
+                                        )));
+                                        return;
+                                    }
+                                };
+                                let repo_name = scanner::repo_name(&repo);
+                                if changes.is_empty() {
+                                    row.set_subtitle(&glib::markup_escape_text(&format!(
+                                        "{}: origin already fits, or isn't on the identity's host",
+                                        repo_name
+                                    )));
+                                    return;
+                                }
+                                row.set_subtitle(&glib::markup_escape_text(&repo_name));
+                                let lines: Vec<String> =
+                                    changes.iter().map(remotes::Change::display_text).collect();
+                                preview.set_text(&lines.join("\n"));
+                                preview.set_visible(true);
+                                apply_button.set_sensitive(true);
+                                *planned.borrow_mut() = Some((repo, changes));
+                            });
+                        },
+                    );
+                });
+            }
+
+            // Wire apply button: set the previewed URLs
+            let status = status.clone();
+            apply_button.connect_clicked(move |button| {
+                let Some((repo, changes)) = planned.borrow_mut().take() else {
+                    return;
+                };
+                button.set_sensitive(false);
+                let preview = preview.clone();
+                let status = status.clone();
+                glib::spawn_future_local(async move {
+                    let result = gio::spawn_blocking(move || remotes::apply(&repo, &changes))
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                    match result {
+                        Ok(()) => {
+                            preview.set_visible(false);
+                            show_status(&status, "Updated origin", Some("success"));
+                        }
+                        Err(e) => show_status(
+                            &status,
+                            &error_text("Updating origin failed", &e),
+                            Some("error"),
+                        ),
+                    }
+                });
+            });
+
+            group
+        }
+
+        /// Email and signing policies by remote, and the repositories
+        /// breaking them
+        fn build_policies_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Organization Policies");
+            group.set_description(Some(
+                "Required email domain and signing for repositories whose remote matches a \
+                 pattern; detection and the commit guard follow them",
+            ));
+
+            // Save changed policies, bring the guarded repositories' copies
+            // up to date and rebuild the page
+            let save_config = {
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |change: ConfigChange| {
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || -> Result<(), Error> {
+                            let config = Config::update(change)?;
+                            for (path, e) in hooks::update_all(&config)? {
+                                tracing::warn!(
+                                    "Updating the guard of {} failed: {}",
+                                    path.display(),
+                                    e
+                                );
+                            }
+                            Ok(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving policies failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                })
+            };
+
+            for (index, policy) in config.settings.policies.iter().enumerate() {
+                let row = adw::ActionRow::new();
+                row.set_title(&glib::markup_escape_text(&policy.pattern));
+                let mut requires = Vec::new();
+                let domain = policy::domain(policy);
+                if !domain.is_empty() {
+                    requires.push(format!("an @{} email", domain));
+                }
+                if policy.require_signing {
+                    requires.push("signed commits".to_string());
+                }
+                row.set_subtitle(&glib::markup_escape_text(&if requires.is_empty() {
+                    "No requirements".to_string()
+                } else {
+                    format!("Requires {}", requires.join(" and "))
+                }));
+                let remove_button = gtk4::Button::from_icon_name("user-trash-symbolic");
+                remove_button.set_valign(gtk4::Align::Center);
+                remove_button.set_tooltip_text(Some("Remove policy"));
+                row.add_suffix(&remove_button);
+                group.add(&row);
+
+                let save_config = save_config.clone();
+                let policy = policy.clone();
+                remove_button.connect_clicked(move |_| {
+                    let policy = policy.clone();
+                    save_config(Box::new(move |config| {
+                        let policies = &mut config.settings.policies;
+                        if policies.get(index) == Some(&policy) {
+                            policies.remove(index);
+                        }
+                    }));
+                });
+            }
+
+            let add_row = adw::ActionRow::new();
+            add_row.set_title("New Policy");
+            add_row.set_subtitle("Remote pattern, e.g. github.com/acme-*, and email domain");
+            let pattern_entry = gtk4::Entry::new();
+            pattern_entry.set_placeholder_text(Some("Pattern"));
+            pattern_entry.set_valign(gtk4::Align::Center);
+            let domain_entry = gtk4::Entry::new();
+            domain_entry.set_placeholder_text(Some("Domain"));
+            domain_entry.set_valign(gtk4::Align::Center);
+            let signing_check = gtk4::CheckButton::with_label("Signed");
+            signing_check.set_valign(gtk4::Align::Center);
+            let add_button = gtk4::Button::with_label("Add");
+            add_button.set_valign(gtk4::Align::Center);
+            add_row.add_suffix(&pattern_entry);
+            add_row.add_suffix(&domain_entry);
+            add_row.add_suffix(&signing_check);
+            add_row.add_suffix(&add_button);
+            group.add(&add_row);
+
+            let check_row = adw::ActionRow::new();
+            check_row.set_title("Check Repositories");
+            check_row.set_subtitle(if config.settings.policies.is_empty() {
+                "Add a policy first"
+            } else {
+                "Workspace and guarded repositories, not checked yet"
+            });
+            let check_button = gtk4::Button::with_label("Check");
+            check_button.set_valign(gtk4::Align::Center);
+            check_button.set_sensitive(!config.settings.policies.is_empty());
+            check_row.add_suffix(&check_button);
+            group.add(&check_row);
+
+            // Wire add button
+            {
+                let status = status.clone();
+                add_button.connect_clicked(move |_| {
+                    let pattern = pattern_entry.text().trim().trim_matches('/').to_string();
+                    let email_domain = domain_entry.text().trim().to_string();
+                    let require_signing = signing_check.is_active();
+                    if pattern.is_empty() {
+                        show_status(&status, "Enter a remote pattern", Some("error"));
+                        return;
+                    }
+                    if email_domain.is_empty() && !require_signing {
+                        show_status(
+                            &status,
+                            "Enter an email domain or require signing",
+                            Some("error"),
+                        );
+                        return;
+                    }
+                    save_config(Box::new(move |config| {
+                        config.settings.policies.retain(|p| p.pattern != pattern);
+                        config.settings.policies.push(OrgPolicy {
+                            pattern,
+                            email_domain,
+                            require_signing,
+                        });
+                    }));
+                });
+            }
+
+            // Wire check button: list the repositories breaking a policy
+            {
+                let config = config.clone();
+                let group = group.clone();
+                let check_row = check_row.clone();
+                let rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::default();
+                check_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    check_row.set_subtitle("Checking...");
+                    let btn = button.clone();
+                    let config = config.clone();
+                    let group = group.clone();
+                    let check_row = check_row.clone();
+                    let rows = rows.clone();
+                    glib::spawn_future_local(async move {
+                        let found = gio::spawn_blocking(move || {
+                            let mut repos = workspace::repos(&config);
+                            repos.extend(GuardStore::load().repos.into_iter().map(|g| g.path));
+                            repos.sort();
+                            repos.dedup();
+                            (repos.len(), policy::scan(&config, &repos))
+                        })
+                        .await;
+                        btn.set_sensitive(true);
+                        for row in rows.borrow_mut().drain(..) {
+                            group.remove(&row);
+                        }
+                        let Ok((checked, found)) = found else {
+                            check_row.set_subtitle("Checking failed");
+                            return;
+                        };
+                        check_row.set_subtitle(&match found.len() {
+                            0 => format!("{} repositories, no violations", checked),
+                            n => format!("{} repositories, {} breaking a policy", checked, n),
+                        });
+                        for report in &found {
+                            let row = adw::ActionRow::new();
+                            row.set_title(&glib::markup_escape_text(&platform::tilde_path(
+                                &report.path,
+                            )));
+                            row.set_subtitle(&glib::markup_escape_text(&report.display_text()));
+                            row.add_css_class("error");
+                            group.add(&row);
+                            rows.borrow_mut().push(row);
+                        }
+                    });
+                });
+            }
+
+            group
+        }
+
+        /// Identities for branch patterns, and applying them to a
+        /// repository's config
+        fn build_branch_rules_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Branch Identities");
+            group.set_description(Some(
+                "Branches matching a pattern commit as another identity; guarded repositories \
+                 get the rules automatically",
+            ));
+
+            // Save changed rules, apply them to the guarded repositories and
+            // rebuild the page
+            let save_config = {
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |change: ConfigChange| {
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || -> Result<(), Error> {
+                            let config = Config::update(change)?;
+                            for (path, e) in hooks::update_all(&config)? {
+                                tracing::warn!(
+                                    "Updating the guard of {} failed: {}",
+                                    path.display(),
+                                    e
+                                );
+                            }
+                            Ok(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving branch rules failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                })
+            };
+
+            for (index, rule) in config.settings.branch_rules.iter().enumerate() {
+                let row = adw::ActionRow::new();
+                row.set_title(&glib::markup_escape_text(&format!(
+                    "{} → {}",
+                    rule.branch, rule.identity
+                )));
+                row.set_subtitle(&glib::markup_escape_text(&if rule.path.is_empty() {
+                    "Every repository".to_string()
+                } else {
+                    format!("Repositories under {}", rule.path)
+                }));
+                if !config.identities.contains_key(&rule.identity) {
+                    row.set_subtitle("Unknown identity, ignored");
+                    row.add_css_class("error");
+                }
+                let remove_button = gtk4::Button::from_icon_name("user-trash-symbolic");
+                remove_button.set_valign(gtk4::Align::Center);
+                remove_button.set_tooltip_text(Some("Remove rule"));
+                row.add_suffix(&remove_button);
+                group.add(&row);
+
+                let save_config = save_config.clone();
+                let rule = rule.clone();
+                remove_button.connect_clicked(move |_| {
+                    let rule = rule.clone();
+                    save_config(Box::new(move |config| {
+                        let rules = &mut config.settings.branch_rules;
+                        if rules.get(index) == Some(&rule) {
+                            rules.remove(index);
+                        }
+                    }));
+                });
+            }
+
+            let names = config.identity_names();
+            let add_row = adw::ComboRow::new();
+            add_row.set_title("New Rule");
+            add_row.set_subtitle("Identity and branch pattern, e.g. client-x/");
+            let name_refs: Vec<&str> = names.iter().map(String::as_str).collect();
+            add_row.set_model(Some(&gtk4::StringList::new(&name_refs)));
+            let branch_entry = gtk4::Entry::new();
+            branch_entry.set_placeholder_text(Some("Branch"));
+            branch_entry.set_valign(gtk4::Align::Center);
+            let path_entry = gtk4::Entry::new();
+            path_entry.set_placeholder_text(Some("Directory (optional)"));
+            path_entry.set_valign(gtk4::Align::Center);
+            let add_button = gtk4::Button::with_label("Add");
+            add_button.set_valign(gtk4::Align::Center);
+            add_button.set_sensitive(!names.is_empty());
+            add_row.add_suffix(&branch_entry);
+            add_row.add_suffix(&path_entry);
+            add_row.add_suffix(&add_button);
+            group.add(&add_row);
+
+            let apply_row = adw::ActionRow::new();
+            apply_row.set_title("Apply to Repository");
+            apply_row.set_subtitle(
+                "Add onbranch includes to a repository's own git config so git commits as \
+                 the branch's identity",
+            );
+            let apply_button = gtk4::Button::with_label("Repository…");
+            apply_button.set_valign(gtk4::Align::Center);
+            apply_button.set_sensitive(!config.settings.branch_rules.is_empty());
+            apply_row.add_suffix(&apply_button);
+            group.add(&apply_row);
+
+            // Wire add button
+            {
+                let status = status.clone();
+                add_button.connect_clicked(move |_| {
+                    let Some(identity) = names.get(add_row.selected() as usize).cloned() else {
+                        return;
+                    };
+                    let branch = branch_entry.text().trim().to_string();
+                    let path = path_entry.text().trim().to_string();
+                    if branch.is_empty() {
+                        show_status(&status, "Enter a branch pattern", Some("error"));
+                        return;
+                    }
+                    save_config(Box::new(move |config| {
+                        let rules = &mut config.settings.branch_rules;
+                        rules.retain(|r| r.branch != branch || r.path != path);
+                        rules.push(BranchRule {
+                            branch,
+                            identity,
+                            path,
+                        });
+                    }));
+                });
+            }
+
+            // Wire apply button: choose a repository, then write the rules
+            {
+                let window_ref = self.obj().clone();
+                let config = config.clone();
+                let status = status.clone();
+                apply_button.connect_clicked(move |button| {
+                    let dialog = gtk4::FileDialog::new();
+                    dialog.set_title("Select Repository");
+                    let btn = button.clone();
+                    let config = config.clone();
+                    let status = status.clone();
+                    dialog.select_folder(
+                        Some(&window_ref),
+                        gio::Cancellable::NONE,
+                        move |result| {
+                            let Some(repo) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                                return;
+                            };
+                            btn.set_sensitive(false);
+                            let btn = btn.clone();
+                            let config = config.clone();
+                            let status = status.clone();
+                            glib::spawn_future_local(async move {
+                                let name = scanner::repo_name(&repo);
+                                let result = gio::spawn_blocking(move || {
+                                    let repo = repo.canonicalize()?;
+                                    branches::apply(&config, &repo)
+                                })
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                                btn.set_sensitive(true);
+                                match result {
+                                    Ok(rules) if rules.is_empty() => show_status(
+                                        &status,
+                                        &format!("No branch rule covers {}", name),
+                                        Some("warning"),
+                                    ),
+                                    Ok(rules) => show_status(
+                                        &status,
+                                        &format!(
+                                            "{} branch rules applied to {}",
+                                            rules.len(),
+                                            name
+                                        ),
+                                        Some("success"),
+                                    ),
+                                    Err(e) => show_status(
+                                        &status,
+                                        &error_text("Applying branch rules failed", &e),
+                                        Some("error"),
+                                    ),
+                                }
+                            });
+                        },
+                    );
+                });
+            }
+
+            group
+        }
+
+        /// Repositories excluded from switching, and the ones with work
+        /// uncommitted under the current identity
+        fn build_switch_guard_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Switch Guard");
+            group.set_description(Some(
+                "Switching warns about workspace repositories with uncommitted work under the \
+                 current identity; excluded repositories keep their git identity when switching",
+            ));
+
+            // Save changed exclusions and rebuild the page
+            let save_config = {
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |change: ConfigChange| {
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || -> Result<(), Error> {
+                            Config::update(change)?;
+                            Ok(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving exclusions failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                })
+            };
+
+            for path in &config.settings.switch_excluded_repos {
+                let row = adw::ActionRow::new();
+                row.set_title(&glib::markup_escape_text(path));
+                row.set_subtitle("Excluded from switching");
+                let remove_button = gtk4::Button::from_icon_name("user-trash-symbolic");
+                remove_button.set_valign(gtk4::Align::Center);
+                remove_button.set_tooltip_text(Some("Include in switching again"));
+                row.add_suffix(&remove_button);
+                group.add(&row);
+
+                let save_config = save_config.clone();
+                let path = path.clone();
+                remove_button.connect_clicked(move |_| {
+                    let path = path.clone();
+                    save_config(Box::new(move |config| {
+                        config.settings.switch_excluded_repos.retain(|p| *p != path);
+                    }));
+                });
+            }
+
+            let current = config.state.current_identity.clone();
+            let check_row = adw::ActionRow::new();
+            check_row.set_title("Uncommitted Work");
+            check_row.set_subtitle(&if current.is_empty() {
+                "No current identity".to_string()
+            } else {
+                glib::markup_escape_text(&format!(
+                    "Workspace repositories with changes as {}, not checked yet",
+                    current
+                ))
+                .to_string()
+            });
+            let check_button = gtk4::Button::with_label("Check");
+            check_button.set_valign(gtk4::Align::Center);
+            check_button.set_sensitive(!current.is_empty());
+            check_row.add_suffix(&check_button);
+            group.add(&check_row);
+
+            // Wire check button: list the dirty repositories, each of which
+            // can be excluded
+            {
+                let config = config.clone();
+                let group = group.clone();
+                let check_row = check_row.clone();
+                let rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::default();
+                check_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    check_row.set_subtitle("Checking...");
+                    let btn = button.clone();
+                    let config = config.clone();
+                    let current = current.clone();
+                    let group = group.clone();
+                    let check_row = check_row.clone();
+                    let rows = rows.clone();
+                    let save_config = save_config.clone();
+                    glib::spawn_future_local(async move {
+                        let found =
+                            gio::spawn_blocking(move || switch_guard::scan(&config, &current))
+                                .await;
+                        btn.set_sensitive(true);
+                        for row in rows.borrow_mut().drain(..) {
+                            group.remove(&row);
+                        }
+                        let Ok(found) = found else {
+                            check_row.set_subtitle("Checking failed");
+                            return;
+                        };
+                        check_row.set_subtitle(&match found.len() {
+                            0 => "No uncommitted work".to_string(),
+                            1 => "1 repository with uncommitted work".to_string(),
+                            n => format!("{} repositories with uncommitted work", n),
+                        });
+                        for dirty in &found {
+                            let path = platform::tilde_path(&dirty.path);
+                            let row = adw::ActionRow::new();
+                            row.set_title(&glib::markup_escape_text(&path));
+                            row.set_subtitle(&dirty.display_text());
+                            row.add_css_class("warning");
+                            let exclude_button = gtk4::Button::with_label("Exclude");
+                            exclude_button.set_valign(gtk4::Align::Center);
+                            exclude_button
+                                .set_tooltip_text(Some("Keep this repository's git identity"));
+                            row.add_suffix(&exclude_button);
+                            group.add(&row);
+                            rows.borrow_mut().push(row);
+
+                            let save_config = save_config.clone();
+                            exclude_button.connect_clicked(move |_| {
+                                let path = path.clone();
+                                save_config(Box::new(move |config| {
+                                    let excluded = &mut config.settings.switch_excluded_repos;
+                                    if !excluded.contains(&path) {
+                                        excluded.push(path);
+                                    }
+                                }));
+                            });
+                        }
+                    });
+                });
+            }
+
+            group
+        }
+
+        /// Bookmarked repositories, each opening a terminal or editor as
+        /// its identity
+        fn build_bookmarks_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Bookmarks");
+            group.set_description(Some(
+                "Open a terminal or editor in a repository after switching to its identity; \
+                 the program also gets the identity's author and SSH key in its environment",
+            ));
+
+            // Save changed bookmarks and rebuild the page
+            let save_config = {
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |change: ConfigChange| {
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || -> Result<(), Error> {
+                            Config::update(change)?;
+                            Ok(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving bookmarks failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                })
+            };
+
+            let mut rows = Vec::new();
+            for (index, bookmark) in config.settings.bookmarks.iter().enumerate() {
+                let repo = platform::expand_tilde(&bookmark.path);
+                let row = adw::ActionRow::new();
+                row.set_title(&glib::markup_escape_text(&scanner::repo_name(&repo)));
+                row.set_subtitle(&glib::markup_escape_text(&format!(
+                    "{} · detecting identity...",
+                    bookmark.path
+                )));
+                for (launch, icon) in [
+                    (bookmarks::Launch::Terminal, "utilities-terminal-symbolic"),
+                    (
+                        bookmarks::Launch::Editor,
+                        "accessories-text-editor-symbolic",
+                    ),
+                ] {
+                    let button = gtk4::Button::from_icon_name(icon);
+                    button.set_valign(gtk4::Align::Center);
+                    button.set_tooltip_text(Some(&format!(
+                        "Open {} here as the identity",
+                        launch.display_text()
+                    )));
+                    row.add_suffix(&button);
+
+                    let config = config.clone();
+                    let bookmark = bookmark.clone();
+                    let repo = repo.clone();
+                    let status = status.clone();
+                    let imp_weak = self.downgrade();
+                    button.connect_clicked(move |button| {
+                        button.set_sensitive(false);
+                        let btn = button.clone();
+                        let config = config.clone();
+                        let bookmark = bookmark.clone();
+                        let repo = repo.clone();
+                        let status = status.clone();
+                        let imp_weak = imp_weak.clone();
+                        glib::spawn_future_local(async move {
+                            let mapping = {
+                                let config = config.clone();
+                                gio::spawn_blocking(move || bookmarks::mapping(&config, &bookmark))
+                                    .await
+                                    .unwrap_or_else(|_| Err(worker_panicked()))
+                            };
+                            let name = match mapping {
+                                Ok(mapping) => mapping.identity().map(str::to_string),
+                                Err(e) => {
+                                    btn.set_sensitive(true);
+                                    show_status(
+                                        &status,
+                                        &error_text("Detecting the identity failed", &e),
+                                        Some("error"),
+                                    );
+                                    return;
+                                }
+                            };
+                            let Some(name) = name else {
+                                btn.set_sensitive(true);
+                                show_status(
+                                    &status,
+                                    "No identity detected; set one on the bookmark",
+                                    Some("error"),
+                                );
+                                return;
+                            };
+                            show_status(&status, &format!("Switching to {}...", name), None);
+                            let Some(warning) = yubikey_guard_async(&name, &status).await else {
+                                btn.set_sensitive(true);
+                                return;
+                            };
+                            let warning =
+                                join_warnings(warning, switch_warnings_async(&name).await);
+                            if let Err(e) = run_cli_async("switch", &name).await {
+                                btn.set_sensitive(true);
+                                show_status(&status, &error_text("Failed", &e), Some("error"));
+                                return;
+                            }
+                            let opened = {
+                                let name = name.clone();
+                                let repo = repo.clone();
+                                gio::spawn_blocking(move || {
+                                    let applied = bookmarks::apply(&config, &repo, &name)?;
+                                    let program = bookmarks::open(&config, launch, &repo, &name)?;
+                                    Ok::<_, Error>((applied, program))
+                                })
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()))
+                            };
+                            btn.set_sensitive(true);
+                            match opened {
+                                Ok((applied, program)) => {
+                                    let program = program
+                                        .file_name()
+                                        .map(|p| p.to_string_lossy().into_owned())
+                                        .unwrap_or_default();
+                                    let text = format!(
+                                        "Opened {} in {} as {}{}",
+                                        program,
+                                        scanner::repo_name(&repo),
+                                        name,
+                                        if applied {
+                                            ""
+                                        } else {
+                                            " (repository excluded, git config left alone)"
+                                        }
+                                    );
+                                    switched_text(&status, &text, warning);
+                                    if let Some(imp) = imp_weak.upgrade() {
+                                        imp.switch_agent(name.clone(), status.clone());
+                                    }
+                                }
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text(
+                                        &format!("Switched to {}, opening failed", name),
+                                        &e,
+                                    ),
+                                    Some("error"),
+                                ),
+                            }
+                            if let Some(imp) = imp_weak.upgrade() {
+                                imp.load_config();
+                            }
+                        });
+                    });
+                }
+                let remove_button = gtk4::Button::from_icon_name("user-trash-symbolic");
+                remove_button.set_valign(gtk4::Align::Center);
+                remove_button.set_tooltip_text(Some("Remove bookmark"));
+                row.add_suffix(&remove_button);
+                group.add(&row);
+                rows.push((row, bookmark.clone()));
+
+                let save_config = save_config.clone();
+                let bookmark = bookmark.clone();
+                remove_button.connect_clicked(move |_| {
+                    let bookmark = bookmark.clone();
+                    save_config(Box::new(move |config| {
+                        let bookmarks = &mut config.settings.bookmarks;
+                        if bookmarks.get(index) == Some(&bookmark) {
+                            bookmarks.remove(index);
+                        }
+                    }));
+                });
+            }
+
+            // Fill in each bookmark's identity
+            if !rows.is_empty() {
+                let config = config.clone();
+                let bookmarks: Vec<Bookmark> = rows.iter().map(|(_, b)| b.clone()).collect();
+                glib::spawn_future_local(async move {
+                    let Ok(mappings) = gio::spawn_blocking(move || {
+                        bookmarks
+                            .iter()
+                            .map(|bookmark| bookmarks::mapping(&config, bookmark))
+                            .collect::<Vec<_>>()
+                    })
+                    .await
+                    else {
+                        return;
+                    };
+                    for ((row, bookmark), mapping) in rows.iter().zip(mappings) {
+                        let text = match &mapping {
+                            Ok(mapping) => mapping.display_text(),
+                            Err(e) => format!("Not readable: {}", e),
+                        };
+                        row.set_subtitle(&glib::markup_escape_text(&format!(
+                            "{} · {}",
+                            bookmark.path, text
+                        )));
+                        if !matches!(mapping, Ok(ref m) if m.identity().is_some()) {
+                            row.add_css_class("warning");
+                        }
+                    }
+                });
+            }
+
+            let names = config.identity_names();
+            let add_row = adw::ComboRow::new();
+            add_row.set_title("New Bookmark");
+            add_row.set_subtitle("Identity to open the repository as, and the repository");
+            let mut choices = vec!["Detected"];
+            choices.extend(names.iter().map(String::as_str));
+            add_row.set_model(Some(&gtk4::StringList::new(&choices)));
+            let add_button = gtk4::Button::with_label("Repository…");
+            add_button.set_valign(gtk4::Align::Center);
+            add_row.add_suffix(&add_button);
+            group.add(&add_row);
+
+            // Wire add button: choose a repository, then save the bookmark
+            {
+                let window_ref = self.obj().clone();
+                add_button.connect_clicked(move |_| {
+                    let identity = match add_row.selected() as usize {
+                        0 => String::new(),
+                        selected => names.get(selected - 1).cloned().unwrap_or_default(),
+                    };
+                    let dialog = gtk4::FileDialog::new();
+                    dialog.set_title("Select Repository");
+                    let save_config = save_config.clone();
+                    dialog.select_folder(
+                        Some(&window_ref),
+                        gio::Cancellable::NONE,
+                        move |result| {
+                            let Some(repo) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                                return;
+                            };
+                            let path = platform::tilde_path(&repo);
+                            save_config(Box::new(move |config| {
+                                let bookmarks = &mut config.settings.bookmarks;
+                                bookmarks.retain(|b| b.path != path);
+                                bookmarks.push(Bookmark { path, identity });
+                            }));
+                        },
+                    );
+                });
+            }
+
+            group
+        }
+
+        /// Hostnames of self-hosted provider instances
+        fn build_provider_hosts_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Self-Hosted Providers");
+            group.set_description(Some(
+                "GitHub Enterprise, GitLab, Bitbucket, Gitea/Forgejo and sourcehut instances \
+                 on their own hostnames; detection and the key APIs treat them as their \
+                 provider",
+            ));
+
+            // Save changed instances and rebuild the page
+            let save_config = {
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |change: ConfigChange| {
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || -> Result<(), Error> {
+                            Config::update(change)?;
+                            Ok(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving providers failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                })
+            };
+
+            for (index, host) in config.settings.provider_hosts.iter().enumerate() {
+                let row = adw::ActionRow::new();
+                row.set_title(&glib::markup_escape_text(&host.hostname));
+                let provider = pubkeys::Provider::from_name(&host.provider);
+                let mut subtitle = provider
+                    .map(|p| p.display_name().to_string())
+                    .unwrap_or_else(|| format!("Unknown provider {}, ignored", host.provider));
+                if let Some(provider) = provider {
+                    subtitle = format!(
+                        "{} · API {}",
+                        subtitle,
+                        provider_hosts::api_base(
+                            &config.settings.provider_hosts,
+                            provider,
+                            &host.hostname
+                        )
+                    );
+                    if !host.oauth_client_id.is_empty() {
+                        subtitle.push_str(" · OAuth sign-in");
+                    }
+                } else {
+                    row.add_css_class("error");
+                }
+                row.set_subtitle(&glib::markup_escape_text(&subtitle));
+                let remove_button = gtk4::Button::from_icon_name("user-trash-symbolic");
+                remove_button.set_valign(gtk4::Align::Center);
+                remove_button.set_tooltip_text(Some("Remove provider host"));
+                row.add_suffix(&remove_button);
+                group.add(&row);
+
+                let save_config = save_config.clone();
+                let host = host.clone();
+                remove_button.connect_clicked(move |_| {
+                    let host = host.clone();
+                    save_config(Box::new(move |config| {
+                        let hosts = &mut config.settings.provider_hosts;
+                        if hosts.get(index) == Some(&host) {
+                            hosts.remove(index);
+                        }
+                    }));
+                });
+            }
+
+            let providers = ["github", "gitlab", "bitbucket", "gitea", "sourcehut"];
+            let add_row = adw::ComboRow::new();
+            add_row.set_title("New Provider Host");
+            add_row.set_subtitle(
+                "Provider, hostname and, if it isn't the usual one, API URL; an OAuth client ID enables signing in",
+            );
+            add_row.set_model(Some(&gtk4::StringList::new(&[
+                "GitHub",
+                "GitLab",
+                "Bitbucket",
+                "Gitea / Forgejo",
+                "sourcehut",
+            ])));
+            let hostname_entry = gtk4::Entry::new();
+            hostname_entry.set_placeholder_text(Some("Hostname"));
+            hostname_entry.set_valign(gtk4::Align::Center);
+            let api_entry = gtk4::Entry::new();
+            api_entry.set_placeholder_text(Some("API URL (optional)"));
+            api_entry.set_valign(gtk4::Align::Center);
+            let client_entry = gtk4::Entry::new();
+            client_entry.set_placeholder_text(Some("OAuth client ID (optional)"));
+            client_entry.set_valign(gtk4::Align::Center);
+            let add_button = gtk4::Button::with_label("Add");
+            add_button.set_valign(gtk4::Align::Center);
+            add_row.add_suffix(&hostname_entry);
+            add_row.add_suffix(&api_entry);
+            add_row.add_suffix(&client_entry);
+            add_row.add_suffix(&add_button);
+            group.add(&add_row);
+
+            // Wire add button
+            {
+                let status = status.clone();
+                add_button.connect_clicked(move |_| {
+                    let Some(provider) = providers.get(add_row.selected() as usize) else {
+                        return;
+                    };
+                    let provider = provider.to_string();
+                    let hostname = hostname_entry.text().trim().to_lowercase();
+                    let api_url = api_entry.text().trim().to_string();
+                    let oauth_client_id = client_entry.text().trim().to_string();
+                    if hostname.is_empty() || hostname.contains(['/', ':', ' ']) {
+                        show_status(
+                            &status,
+                            "Enter a hostname, e.g. ghe.acme.corp",
+                            Some("error"),
+                        );
+                        return;
+                    }
+                    if !api_url.is_empty() && !api_url.starts_with("https://") {
+                        show_status(
+                            &status,
+                            "The API URL must start with https://",
+                            Some("error"),
+                        );
+                        return;
+                    }
+                    save_config(Box::new(move |config| {
+                        let hosts = &mut config.settings.provider_hosts;
+                        hosts.retain(|h| !h.hostname.eq_ignore_ascii_case(&hostname));
+                        hosts.push(ProviderHost {
+                            hostname,
+                            provider,
+                            api_url,
+                            oauth_client_id,
+                        });
+                    }));
+                });
+            }
+
+            group
+        }
+
+        /// Key store entries pushed to GitHub Actions secrets and GitLab CI
+        /// variables, previewed before anything is written
+        fn build_ci_secrets_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("CI Secrets");
+            group.set_description(Some(
+                "Key store entries pushed to GitHub Actions secrets and GitLab CI/CD \
+                 variables with the identity's token; Preview shows what would change, \
+                 including secrets edited on the provider since the last push",
+            ));
+
+            // Save changed secrets and rebuild the page
+            let save_config = {
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |change: ConfigChange| {
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || -> Result<(), Error> {
+                            Config::update(change)?;
+                            Ok(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving CI secrets failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                })
+            };
+
+            // Each secret's row, to show its planned change
+            let mut rows: Vec<(adw::ActionRow, CiSecret)> = Vec::new();
+            for (index, secret) in config.settings.ci_secrets.iter().enumerate() {
+                let row = adw::ActionRow::new();
+                row.set_title(&glib::markup_escape_text(&format!(
+                    "{} → {}{}",
+                    secret.name,
+                    if secret.organization { "org " } else { "" },
+                    secret.target
+                )));
+                row.set_subtitle(&glib::markup_escape_text(&format!(
+                    "{} · as {}",
+                    secret.entry, secret.identity
+                )));
+                let remove_button = gtk4::Button::from_icon_name("user-trash-symbolic");
+                remove_button.set_valign(gtk4::Align::Center);
+                remove_button.set_tooltip_text(Some("Stop syncing; the provider's secret stays"));
+                row.add_suffix(&remove_button);
+                group.add(&row);
+                rows.push((row, secret.clone()));
+
+                let save_config = save_config.clone();
+                let secret = secret.clone();
+                remove_button.connect_clicked(move |_| {
+                    let secret = secret.clone();
+                    save_config(Box::new(move |config| {
+                        let secrets = &mut config.settings.ci_secrets;
+                        if secrets.get(index) == Some(&secret) {
+                            secrets.remove(index);
+                        }
+                    }));
+                });
+            }
+
+            let sync_row = adw::ActionRow::new();
+            sync_row.set_title("Sync");
+            sync_row.set_subtitle("Preview, then push the secrets that would change");
+            let preview_button = gtk4::Button::with_label("Preview");
+            preview_button.set_valign(gtk4::Align::Center);
+            preview_button.set_sensitive(!rows.is_empty());
+            let apply_button = gtk4::Button::with_label("Apply");
+            apply_button.set_valign(gtk4::Align::Center);
+            apply_button.add_css_class("suggested-action");
+            apply_button.set_sensitive(false);
+            sync_row.add_suffix(&preview_button);
+            sync_row.add_suffix(&apply_button);
+            group.add(&sync_row);
+
+            // The previewed secrets applying would write
+            let pending: Rc<RefCell<Vec<CiSecret>>> = Rc::default();
+            let rows = Rc::new(rows);
+
+            // Wire preview button: plan, and mark each row with its change
+            {
+                let sync_row = sync_row.clone();
+                let apply_button = apply_button.clone();
+                let pending = pending.clone();
+                let rows = rows.clone();
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                preview_button.connect_clicked(move |button| {
+                    let Some(config) = imp_weak
+                        .upgrade()
+                        .and_then(|imp| imp.config.borrow().clone())
+                    else {
+                        return;
+                    };
+                    button.set_sensitive(false);
+                    apply_button.set_sensitive(false);
+                    let btn = button.clone();
+                    let sync_row = sync_row.clone();
+                    let apply_button = apply_button.clone();
+                    let pending = pending.clone();
+                    let rows = rows.clone();
+                    let status = status.clone();
+                    glib::spawn_future_local(async move {
+                        let Ok(plan) = gio::spawn_blocking(move || ci_secrets::plan(&config)).await
+                        else {
+                            btn.set_sensitive(true);
+                            return;
+                        };
+                        btn.set_sensitive(true);
+                        sync_row.set_subtitle(&glib::markup_escape_text(&plan.summary()));
+                        for (row, secret) in rows.iter() {
+                            for css in ["success", "warning", "error"] {
+                                row.remove_css_class(css);
+                            }
+                            let text = if let Some(planned) =
+                                plan.planned.iter().find(|p| &p.secret == secret)
+                            {
+                                row.add_css_class(match planned.change {
+                                    ci_secrets::Change::Unchanged => "success",
+                                    ci_secrets::Change::Drifted => "error",
+                                    _ => "warning",
+                                });
+                                planned.change.display_text().to_string()
+                            } else if let Some((_, e)) =
+                                plan.failed.iter().find(|(s, _)| s == secret)
+                            {
+                                row.add_css_class("error");
+                                e.to_string()
+                            } else {
+                                continue;
+                            };
+                            row.set_subtitle(&glib::markup_escape_text(&format!(
+                                "{} · as {} · {}",
+                                secret.entry, secret.identity, text
+                            )));
+                        }
+                        let changes = plan.changes();
+                        apply_button.set_sensitive(!changes.is_empty());
+                        pending.replace(changes);
+                        if let Some((secret, e)) = plan.failed.first() {
+                            show_status(
+                                &status,
+                                &error_text(&format!("{}: Reading failed", secret.name), e),
+                                Some("error"),
+                            );
+                        }
+                    });
+                });
+            }
+
+            // Wire apply button: push what the preview showed
+            {
+                let sync_row = sync_row.clone();
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                apply_button.connect_clicked(move |button| {
+                    let Some(config) = imp_weak
+                        .upgrade()
+                        .and_then(|imp| imp.config.borrow().clone())
+                    else {
+                        return;
+                    };
+                    let secrets = pending.take();
+                    if secrets.is_empty() {
+                        return;
+                    }
+                    button.set_sensitive(false);
+                    let sync_row = sync_row.clone();
+                    let status = status.clone();
+                    glib::spawn_future_local(async move {
+                        let (pushed, failed) =
+                            gio::spawn_blocking(move || ci_secrets::apply(&config, &secrets))
+                                .await
+                                .unwrap_or_else(|_| (0, Vec::new()));
+                        sync_row.set_subtitle("Preview again to see the current state");
+                        match failed.first() {
+                            None => show_status(
+                                &status,
+                                &format!("Pushed {} CI secrets", pushed),
+                                Some("success"),
+                            ),
+                            Some((secret, e)) => show_status(
+                                &status,
+                                &error_text(
+                                    &format!(
+                                        "Pushed {}, {} failed; {}",
+                                        pushed,
+                                        failed.len(),
+                                        secret.name
+                                    ),
+                                    e,
+                                ),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                });
+            }
+
+            let names = config.identity_names();
+            let add_row = adw::ComboRow::new();
+            add_row.set_title("New CI Secret");
+            add_row.set_subtitle(
+                "Identity whose token writes it, key store entry, secret name, and \
+                 owner/repo, project path, or organization or group",
+            );
+            add_row.set_model(Some(&gtk4::StringList::new(
+                &names.iter().map(String::as_str).collect::<Vec<_>>(),
+            )));
+            let entry_entry = gtk4::Entry::new();
+            entry_entry.set_placeholder_text(Some("Key store entry"));
+            entry_entry.set_valign(gtk4::Align::Center);
+            let name_entry = gtk4::Entry::new();
+            name_entry.set_placeholder_text(Some("Secret name"));
+            name_entry.set_valign(gtk4::Align::Center);
+            let target_entry = gtk4::Entry::new();
+            target_entry.set_placeholder_text(Some("owner/repo"));
+            target_entry.set_valign(gtk4::Align::Center);
+            let org_check = gtk4::CheckButton::with_label("Organization");
+            org_check.set_valign(gtk4::Align::Center);
+            let add_button = gtk4::Button::with_label("Add");
+            add_button.set_valign(gtk4::Align::Center);
+            add_row.add_suffix(&entry_entry);
+            add_row.add_suffix(&name_entry);
+            add_row.add_suffix(&target_entry);
+            add_row.add_suffix(&org_check);
+            add_row.add_suffix(&add_button);
+            group.add(&add_row);
+
+            // Wire add button
+            {
+                let status = status.clone();
+                add_button.connect_clicked(move |_| {
+                    let Some(identity) = names.get(add_row.selected() as usize).cloned() else {
+                        return;
+                    };
+                    let entry = entry_entry.text().trim().to_string();
+                    let name = name_entry.text().trim().to_string();
+                    let target = target_entry.text().trim().trim_matches('/').to_string();
+                    let organization = org_check.is_active();
+                    if entry.is_empty() || name.is_empty() || target.is_empty() {
+                        show_status(
+                            &status,
+                            "Enter the key store entry, the secret name and where it goes",
+                            Some("error"),
+                        );
+                        return;
+                    }
+                    if !organization && !target.contains('/') {
+                        show_status(
+                            &status,
+                            "Enter the repository as owner/repo, or tick Organization",
+                            Some("error"),
+                        );
+                        return;
+                    }
+                    save_config(Box::new(move |config| {
+                        let secrets = &mut config.settings.ci_secrets;
+                        secrets.retain(|s| {
+                            !(s.name == name
+                                && s.target == target
+                                && s.organization == organization)
+                        });
+                        secrets.push(CiSecret {
+                            entry,
+                            name,
+                            target,
+                            identity,
+                            organization,
+                        });
+                    }));
+                });
+            }
+
+            group
+        }
+
+        /// The token-authenticated HTTP API on localhost
+        fn build_rest_api_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Local HTTP API");
+            group.set_description(Some(
+                "For editor plugins and scripts where D-Bus isn't available: /status, \
+                 /switch, /resolve and /keys/search on 127.0.0.1, for clients sending the \
+                 bearer token",
+            ));
+
+            // Save the settings; reloading starts or stops the server
+            let save_config = {
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |change: ConfigChange| {
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || -> Result<(), Error> {
+                            Config::update(change)?;
+                            Ok(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving API settings failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                })
+            };
+
+            let settings = &config.settings.rest_api;
+            let running = self.rest_api_server.borrow().as_ref().map(|s| s.port());
+            let listen_row = adw::ActionRow::new();
+            listen_row.set_title("Listen on Localhost");
+            listen_row.set_subtitle(&match running {
+                Some(port) => format!("Listening on 127.0.0.1:{}", port),
+                None if settings.enabled => {
+                    listen_row.add_css_class("error");
+                    format!("Not listening; is port {} in use?", settings.port)
+                }
+                None => "Off".to_string(),
+            });
+            let port_entry = gtk4::Entry::new();
+            port_entry.set_text(&settings.port.to_string());
+            port_entry.set_placeholder_text(Some("Port"));
+            port_entry.set_width_chars(6);
+            port_entry.set_valign(gtk4::Align::Center);
+            let listen_switch = gtk4::Switch::new();
+            listen_switch.set_valign(gtk4::Align::Center);
+            listen_switch.set_active(settings.enabled);
+            listen_row.add_suffix(&port_entry);
+            listen_row.add_suffix(&listen_switch);
+            group.add(&listen_row);
+
+            let token_row = adw::ActionRow::new();
+            token_row.set_title("Bearer Token");
+            token_row.set_subtitle(&glib::markup_escape_text(&format!(
+                "Sent as Authorization: Bearer <token>; kept in {}, delete it for a new one",
+                rest_api::token_path()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_else(|| rest_api::TOKEN_FILE.to_string())
+            )));
+            let copy_button = gtk4::Button::with_label("Copy");
+            copy_button.set_valign(gtk4::Align::Center);
+            token_row.add_suffix(&copy_button);
+            group.add(&token_row);
+
+            // Wire switch and port entry: both save the port
+            let apply = {
+                let status = status.clone();
+                let port_entry = port_entry.clone();
+                Rc::new(move |enabled: bool| {
+                    let Some(port) = port_entry
+                        .text()
+                        .trim()
+                        .parse::<u16>()
+                        .ok()
+                        .filter(|port| *port > 0)
+                    else {
+                        show_status(&status, "Enter a port from 1 to 65535", Some("error"));
+                        return;
+                    };
+                    save_config(Box::new(move |config| {
+                        config.settings.rest_api.enabled = enabled;
+                        config.settings.rest_api.port = port;
+                    }));
+                })
+            };
+            {
+                let apply = apply.clone();
+                listen_switch.connect_active_notify(move |switch| apply(switch.is_active()));
+            }
+            {
+                let listen_switch = listen_switch.clone();
+                port_entry.connect_activate(move |_| apply(listen_switch.is_active()));
+            }
+
+            // Wire copy button
+            {
+                let status = status.clone();
+                copy_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    let btn = button.clone();
+                    let status = status.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(rest_api::load_or_create_token)
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                        btn.set_sensitive(true);
+                        match result {
+                            Ok(token) => {
+                                if let Some(display) = gdk::Display::default() {
+                                    display.clipboard().set_text(&token);
+                                }
+                                show_status(&status, "API token copied", Some("success"));
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Reading the API token failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                });
+            }
+
+            group
+        }
+
+        /// The Prometheus endpoint on localhost
+        fn build_metrics_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Metrics");
+            group.set_description(Some(
+                "Switches, CLI latency, cache hit rates, store errors and the key store's \
+                 state in Prometheus format at /metrics on 127.0.0.1, for alerting",
+            ));
+
+            let settings = &config.settings.metrics;
+            let running = self.metrics_server.borrow().as_ref().map(|s| s.port());
+            let serve_row = adw::ActionRow::new();
+            serve_row.set_title("Serve Metrics");
+            serve_row.set_subtitle(&match running {
+                Some(port) => format!("Scrape http://127.0.0.1:{}/metrics", port),
+                None if settings.enabled => {
+                    serve_row.add_css_class("error");
+                    format!("Not listening; is port {} in use?", settings.port)
+                }
+                None => "Off".to_string(),
+            });
+            let port_entry = gtk4::Entry::new();
+            port_entry.set_text(&settings.port.to_string());
+            port_entry.set_placeholder_text(Some("Port"));
+            port_entry.set_width_chars(6);
+            port_entry.set_valign(gtk4::Align::Center);
+            let serve_switch = gtk4::Switch::new();
+            serve_switch.set_valign(gtk4::Align::Center);
+            serve_switch.set_active(settings.enabled);
+            serve_row.add_suffix(&port_entry);
+            serve_row.add_suffix(&serve_switch);
+            group.add(&serve_row);
+
+            // Wire switch and port entry: both save the port; reloading
+            // starts or stops the server
+            let apply = {
+                let status = status.clone();
+                let port_entry = port_entry.clone();
+                let imp_weak = self.downgrade();
+                Rc::new(move |enabled: bool| {
+                    let Some(port) = port_entry
+                        .text()
+                        .trim()
+                        .parse::<u16>()
+                        .ok()
+                        .filter(|port| *port > 0)
+                    else {
+                        show_status(&status, "Enter a port from 1 to 65535", Some("error"));
+                        return;
+                    };
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || -> Result<(), Error> {
+                            Config::update(|config| {
+                                config.settings.metrics.enabled = enabled;
+                                config.settings.metrics.port = port;
+                            })?;
+                            Ok(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving metrics settings failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                })
+            };
+            {
+                let apply = apply.clone();
+                serve_switch.connect_active_notify(move |switch| apply(switch.is_active()));
+            }
+            {
+                let serve_switch = serve_switch.clone();
+                port_entry.connect_activate(move |_| apply(serve_switch.is_active()));
+            }
+
+            group
+        }
+
+        /// Where OpenTelemetry traces go
+        fn build_telemetry_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Tracing");
+            group.set_description(Some(
+                "Spans for switches, secret lookups, imports, provider API requests and CLI \
+                 calls, sent to an OpenTelemetry collector to find out what a slow switch \
+                 waited on",
+            ));
+
+            let endpoint_row = adw::ActionRow::new();
+            endpoint_row.set_title("OTLP Endpoint");
+            endpoint_row.set_subtitle(&match telemetry::endpoint(&config.settings.telemetry) {
+                Some(_) if config.settings.telemetry.otlp_endpoint.is_empty() => format!(
+                    "Set by {}; read when RemoteJuggler starts",
+                    telemetry::ENDPOINT_ENV
+                ),
+                Some(_) => "Read when RemoteJuggler starts".to_string(),
+                None => "Off".to_string(),
+            });
+            let endpoint_entry = gtk4::Entry::new();
+            endpoint_entry.set_text(&config.settings.telemetry.otlp_endpoint);
+            endpoint_entry.set_placeholder_text(Some("http://127.0.0.1:4318"));
+            endpoint_entry.set_width_chars(24);
+            endpoint_entry.set_valign(gtk4::Align::Center);
+            endpoint_row.add_suffix(&endpoint_entry);
+            group.add(&endpoint_row);
+
+            // Wire entry: save, to take effect on the next start
+            {
+                let status = status.clone();
+                endpoint_entry.connect_activate(move |entry| {
+                    let endpoint = entry.text().trim().to_string();
+                    if !endpoint.is_empty()
+                        && !endpoint.starts_with("http://")
+                        && !endpoint.starts_with("https://")
+                    {
+                        show_status(&status, "Enter an http:// or https:// URL", Some("error"));
+                        return;
+                    }
+                    let status = status.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || -> Result<(), Error> {
+                            Config::update(|config| {
+                                config.settings.telemetry.otlp_endpoint = endpoint
+                            })?;
+                            Ok(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => show_status(
+                                &status,
+                                "Saved; restart RemoteJuggler to apply",
+                                Some("success"),
+                            ),
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving the OTLP endpoint failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                });
+            }
+
+            group
+        }
+
+        /// The hooks in settings.eventHooks, each with a test button
+        fn build_event_hooks_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Event Hooks");
+            group.set_description(Some(
+                "Commands, webhooks and Matrix rooms told when an identity is switched, a \
+                 secret is read, the key store is unlocked or a token is due for rotation; \
+                 add them to eventHooks in config.json",
+            ));
+
+            if config.settings.event_hooks.is_empty() {
+                let empty_row = adw::ActionRow::new();
+                empty_row.set_title("No event hooks");
+                empty_row.add_css_class("dim-label");
+                group.add(&empty_row);
+                return group;
+            }
+
+            for hook in &config.settings.event_hooks {
+                let row = adw::ActionRow::new();
+                row.set_title(&glib::markup_escape_text(&event_hooks::describe(hook)));
+                row.set_subtitle(&if hook.events.is_empty() {
+                    "Every event".to_string()
+                } else {
+                    glib::markup_escape_text(&hook.events.join(", ")).to_string()
+                });
+                let test_button = gtk4::Button::with_label("Send Test");
+                test_button.set_valign(gtk4::Align::Center);
+                row.add_suffix(&test_button);
+                group.add(&row);
+
+                // Wire test button
+                let hook = hook.clone();
+                let status = status.clone();
+                test_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    let button = button.clone();
+                    let hook = hook.clone();
+                    let status = status.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || {
+                            event_hooks::deliver(&hook, &event_hooks::Event::Test)
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        button.set_sensitive(true);
+                        match result {
+                            Ok(()) => show_status(&status, "Test event delivered", Some("success")),
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Delivering the test event failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                });
+            }
+
+            group
+        }
+
+        /// The systemd user units: the background service and the timers
+        /// of the periodic tasks, with what systemd reports for each
+        fn build_background_group(&self, status: &gtk4::Label) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Background");
+            group.set_description(Some(
+                "systemd user units keep RemoteJuggler running without the window and run \
+                 periodic tasks, notifying you when something needs attention",
+            ));
+
+            let mut rows: Vec<(String, adw::ActionRow, gtk4::Switch, String)> = Vec::new();
+            let service_row = adw::ActionRow::new();
+            service_row.set_title("Run in the background");
+            let service_text =
+                "The HTTP API, metrics, Secret Service and reminders keep running with the window closed";
+            rows.push((
+                systemd::SERVICE.to_string(),
+                service_row,
+                gtk4::Switch::new(),
+                service_text.to_string(),
+            ));
+            for task in Task::ALL {
+                let row = adw::ActionRow::new();
+                row.set_title(task.display_name());
+                let run_button = gtk4::Button::with_label("Run Now");
+                run_button.set_valign(gtk4::Align::Center);
+                row.add_suffix(&run_button);
+                {
+                    let status = status.clone();
+                    run_button.connect_clicked(move |button| {
+                        button.set_sensitive(false);
+                        let button = button.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || systemd::run_now(task))
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                            button.set_sensitive(true);
+                            match result {
+                                Ok(()) => show_status(
+                                    &status,
+                                    &format!("Started {}", task.display_name().to_lowercase()),
+                                    Some("success"),
+                                ),
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text(&format!("Starting {} failed", task.id()), &e),
+                                    Some("error"),
+                                ),
+                            }
+                        });
+                    });
+                }
+                rows.push((
+                    task.timer(),
+                    row,
+                    gtk4::Switch::new(),
+                    task.description().to_string(),
+                ));
+            }
+            for (_, row, switch, text) in &rows {
+                row.set_subtitle(&format!("{} · Checking…", text));
+                switch.set_valign(gtk4::Align::Center);
+                switch.set_sensitive(false);
+                row.add_suffix(switch);
+                row.set_activatable_widget(Some(switch));
+                group.add(row);
+            }
+
+            // Switches are wired once systemd has said what is enabled
+            let units: Vec<String> = rows.iter().map(|(unit, ..)| unit.clone()).collect();
+            let status = status.clone();
+            let imp_weak = self.downgrade();
+            glib::spawn_future_local(async move {
+                let statuses = gio::spawn_blocking(move || {
+                    units
+                        .iter()
+                        .map(|unit| systemd::status(unit))
+                        .collect::<Vec<_>>()
+                })
+                .await
+                .unwrap_or_default();
+                for ((unit, row, switch, text), unit_status) in rows.into_iter().zip(statuses) {
+                    let unit_status = match unit_status {
+                        Ok(unit_status) => unit_status,
+                        Err(e) => {
+                            row.set_subtitle(&glib::markup_escape_text(&error_text(
+                                "systemctl failed",
+                                &e,
+                            )));
+                            continue;
+                        }
+                    };
+                    row.set_subtitle(&glib::markup_escape_text(&format!(
+                        "{} · {}",
+                        text,
+                        unit_status.display_text()
+                    )));
+                    if unit_status.failed {
+                        row.add_css_class("error");
+                    }
+                    switch.set_active(unit_status.enabled);
+                    switch.set_sensitive(true);
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    switch.connect_active_notify(move |switch| {
+                        let on = switch.is_active();
+                        let unit = unit.clone();
+                        let status = status.clone();
+                        let imp_weak = imp_weak.clone();
+                        glib::spawn_future_local(async move {
+                            let name = unit.clone();
+                            let result =
+                                gio::spawn_blocking(move || systemd::set_enabled(&unit, on))
+                                    .await
+                                    .unwrap_or_else(|_| Err(worker_panicked()));
+                            match result {
+                                Ok(()) => show_status(
+                                    &status,
+                                    &format!(
+                                        "{} {}",
+                                        if on { "Enabled" } else { "Disabled" },
+                                        name
+                                    ),
+                                    Some("success"),
+                                ),
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text(&format!("Changing {} failed", name), &e),
+                                    Some("error"),
+                                ),
+                            }
+                            if let Some(imp) = imp_weak.upgrade() {
+                                imp.rebuild_content();
+                            }
+                        });
+                    });
+                }
+            });
+
+            group
+        }
+
+        /// Pointing ssh and git at remote-juggler-askpass on a switch
+        fn build_askpass_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Askpass");
+            group.set_description(Some(
+                "ssh and git started without a terminal ask for passphrases and HTTPS \
+                 credentials through remote-juggler-askpass; Trusted Workstation identities \
+                 are answered from the key store",
+            ));
+
+            let askpass_row = adw::ActionRow::new();
+            askpass_row.set_title("Set askpass on switch");
+            match askpass::program_path() {
+                Some(path) => askpass_row.set_subtitle(&format!(
+                    "SSH_ASKPASS and GIT_ASKPASS for the CLI and the desktop session: {}",
+                    path.display()
+                )),
+                None => {
+                    askpass_row.set_subtitle(&format!("{} not found", askpass::PROGRAM));
+                    askpass_row.add_css_class("error");
+                }
+            }
+            let askpass_switch = gtk4::Switch::new();
+            askpass_switch.set_valign(gtk4::Align::Center);
+            askpass_switch.set_active(config.settings.askpass);
+            askpass_row.add_suffix(&askpass_switch);
+            askpass_row.set_activatable_widget(Some(&askpass_switch));
+            group.add(&askpass_row);
+
+            {
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                askpass_switch.connect_active_notify(move |switch| {
+                    let on = switch.is_active();
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || {
+                            Config::update(|config| config.settings.askpass = on)?;
+                            Ok::<_, Error>(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving setting failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                });
+            }
+
+            group
+        }
+
+        /// Serving org.freedesktop.secrets from the key store
+        fn build_secret_service_group(
+            &self,
+            config: &Config,
+            status: &gtk4::Label,
+        ) -> adw::PreferencesGroup {
+            let group = adw::PreferencesGroup::new();
+            group.set_title("Secret Service");
+            group.set_description(Some(
+                "Apps using libsecret store their passwords in the key store under \
+                 RemoteJuggler/SecretService instead of GNOME Keyring, while this window runs",
+            ));
+
+            let serve_row = adw::ActionRow::new();
+            serve_row.set_title("Serve org.freedesktop.secrets");
+            let serving = self
+                .secret_service
+                .borrow()
+                .as_ref()
+                .map(|service| service.is_serving());
+            serve_row.set_subtitle(&match serving {
+                None => "Off".to_string(),
+                Some(None) => "Starting".to_string(),
+                Some(Some(true)) => format!(
+                    "Serving {} items",
+                    secret_service::Index::load().map_or(0, |index| index.items.len())
+                ),
+                Some(Some(false)) => {
+                    serve_row.add_css_class("error");
+                    "Another service, probably GNOME Keyring, owns the name; stop its \
+                     secrets component first"
+                        .to_string()
+                }
+            });
+            let serve_switch = gtk4::Switch::new();
+            serve_switch.set_valign(gtk4::Align::Center);
+            serve_switch.set_active(config.settings.secret_service);
+            serve_row.add_suffix(&serve_switch);
+            serve_row.set_activatable_widget(Some(&serve_switch));
+            group.add(&serve_row);
+
+            // Wire switch: save, then reloading claims or releases the name
+            {
+                let status = status.clone();
+                let imp_weak = self.downgrade();
+                serve_switch.connect_active_notify(move |switch| {
+                    let on = switch.is_active();
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || {
+                            Config::update(|config| config.settings.secret_service = on)?;
+                            Ok::<_, Error>(())
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(()) => {
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Saving setting failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                });
+            }
+
+            group
+        }
+
+        /// Exporting the identities and their GPG keys to an age-encrypted
+        /// bundle, and restoring one on a new machine
+        fn build_gpg_backup_rows(&self, status: &gtk4::Label) -> adw::ExpanderRow {
+            let expander = adw::ExpanderRow::new();
+            expander.set_title("Backup and Restore");
+            expander.set_subtitle("Identities, public keys and ownertrust in one encrypted bundle");
+
+            let secret_switch = gtk4::Switch::new();
+            secret_switch.set_valign(gtk4::Align::Center);
+            let secret_row = adw::ActionRow::new();
+            secret_row.set_title("Include Secret Keys");
+            secret_row.set_subtitle("Stubs for keys on a card; gpg asks for passphrases");
+            secret_row.add_suffix(&secret_switch);
+            secret_row.set_activatable_widget(Some(&secret_switch));
+            expander.add_row(&secret_row);
+
+            let recipient_entry = gtk4::Entry::new();
+            recipient_entry.set_valign(gtk4::Align::Center);
+            recipient_entry.set_hexpand(true);
+            recipient_entry.set_placeholder_text(Some("age1... (default: the SOPS age key)"));
+            let recipient_row = adw::ActionRow::new();
+            recipient_row.set_title("Recipient");
+            recipient_row.add_suffix(&recipient_entry);
+            expander.add_row(&recipient_row);
+
+            let actions_row = adw::ActionRow::new();
+            actions_row.set_title("Bundle");
+            actions_row.set_subtitle("Restoring decrypts with the SOPS age key");
+            let export_button = gtk4::Button::with_label("Export…");
+            export_button.set_valign(gtk4::Align::Center);
+            let restore_button = gtk4::Button::with_label("Restore…");
+            restore_button.set_valign(gtk4::Align::Center);
+            actions_row.add_suffix(&export_button);
+            actions_row.add_suffix(&restore_button);
+            expander.add_row(&actions_row);
+
+            // Wire export button: choose a file, then collect and encrypt
+            {
+                let window_ref = self.obj().clone();
+                let status = status.clone();
+                export_button.connect_clicked(move |button| {
+                    let dialog = gtk4::FileDialog::new();
+                    dialog.set_title("Save GPG Backup");
+                    dialog.set_initial_name(Some(gpg_backup::DEFAULT_FILE_NAME));
+                    let btn = button.clone();
+                    let status = status.clone();
+                    let include_secret = secret_switch.is_active();
+                    let recipient = recipient_entry.text().trim().to_string();
+                    dialog.save(Some(&window_ref), gio::Cancellable::NONE, move |result| {
+                        let Some(path) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                            return;
+                        };
+                        btn.set_sensitive(false);
+                        show_status(&status, "Exporting GPG backup...", None);
+                        let btn = btn.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let target = path.clone();
+                            let result = gio::spawn_blocking(move || {
+                                let recipient = if recipient.is_empty() {
+                                    gpg_backup::default_recipient()?
+                                } else {
+                                    recipient
+                                };
+                                gpg_backup::export(&target, &[recipient], include_secret)
+                            })
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                            btn.set_sensitive(true);
+                            match result {
+                                Ok(bundle) => show_status(
+                                    &status,
+                                    &format!(
+                                        "Saved {} identities and {} key(s), {} with secret keys, \
+                                         to {}",
+                                        bundle.identities.len(),
+                                        bundle.keys.len(),
+                                        bundle.secret_key_count(),
+                                        path.display()
+                                    ),
+                                    Some("success"),
+                                ),
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("GPG backup failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                        });
+                    });
+                });
+            }
+
+            // Wire restore button: decrypt, import, then show new identities
+            let window_ref = self.obj().clone();
+            let status = status.clone();
+            let imp_weak = self.downgrade();
+            restore_button.connect_clicked(move |button| {
+                let dialog = gtk4::FileDialog::new();
+                dialog.set_title("Restore GPG Backup");
+                let btn = button.clone();
+                let status = status.clone();
+                let imp_weak = imp_weak.clone();
+                dialog.open(Some(&window_ref), gio::Cancellable::NONE, move |result| {
+                    let Some(path) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                        return;
+                    };
+                    btn.set_sensitive(false);
+                    show_status(&status, "Restoring GPG backup...", None);
+                    let btn = btn.clone();
+                    let status = status.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || {
+                            let secret = age::load_secret(age::DEFAULT_IDENTITY_PATH)?;
+                            gpg_backup::restore(&path, &secret)
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        btn.set_sensitive(true);
+                        match result {
+                            Ok(restored) => {
+                                show_status(&status, &restored.display_text(), Some("success"));
+                                if !restored.identities_added.is_empty() {
+                                    if let Some(imp) = imp_weak.upgrade() {
+                                        imp.reload_config_and_ui();
+                                    }
+                                }
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Restore failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                });
+            });
+
+            expander
+        }
+
+        /// The YubiKey's PIN retry counters, what to do when they drop and
+        /// unblocking a blocked PIN
+        fn build_lockout_rows(&self, status: &gtk4::Label) -> adw::ExpanderRow {
+            let expander = adw::ExpanderRow::new();
+            expander.set_title("PIN Retries");
+            expander.set_subtitle("Checking...");
+            let check_button = gtk4::Button::from_icon_name("view-refresh-symbolic");
+            check_button.set_valign(gtk4::Align::Center);
+            check_button.set_tooltip_text(Some("Read the counters again"));
+            expander.add_action(&check_button);
+
+            let guidance_row = adw::ActionRow::new();
+            guidance_row.set_title("What to Do");
+            guidance_row.set_visible(false);
+            expander.add_row(&guidance_row);
+
+            let recovery_row = adw::ComboRow::new();
+            recovery_row.set_title("Unblock");
+            recovery_row.set_visible(false);
+            expander.add_row(&recovery_row);
+
+            let puk_entry = gtk4::PasswordEntry::new();
+            puk_entry.set_show_peek_icon(true);
+            puk_entry.set_valign(gtk4::Align::Center);
+            let puk_row = adw::ActionRow::new();
+            puk_row.set_title("PUK");
+            puk_row.add_suffix(&puk_entry);
+            puk_row.set_activatable_widget(Some(&puk_entry));
+            expander.add_row(&puk_row);
+
+            let new_pin_entry = gtk4::PasswordEntry::new();
+            new_pin_entry.set_show_peek_icon(true);
+            new_pin_entry.set_valign(gtk4::Align::Center);
+            let new_pin_row = adw::ActionRow::new();
+            new_pin_row.set_title("New PIN");
+            new_pin_row.add_suffix(&new_pin_entry);
+            new_pin_row.set_activatable_widget(Some(&new_pin_entry));
+            expander.add_row(&new_pin_row);
+
+            let unblock_row = adw::ActionRow::new();
+            unblock_row.set_title("Unblock PIN");
+            let unblock_button = gtk4::Button::with_label("Unblock");
+            unblock_button.set_valign(gtk4::Align::Center);
+            unblock_button.add_css_class("suggested-action");
+            unblock_row.add_suffix(&unblock_button);
+            expander.add_row(&unblock_row);
+
+            let recoveries: Rc<RefCell<Vec<lockout::Recovery>>> = Rc::default();
+            // The PUK entries only apply to PIV; the OpenPGP recoveries ask
+            // through pinentry
+            let update_recovery = {
+                let recoveries = recoveries.clone();
+                let recovery_row = recovery_row.clone();
+                let (puk_row, new_pin_row) = (puk_row.clone(), new_pin_row.clone());
+                let unblock_row = unblock_row.clone();
+                Rc::new(move || {
+                    let selected = recoveries
+                        .borrow()
+                        .get(recovery_row.selected() as usize)
+                        .copied();
+                    let piv = selected == Some(lockout::Recovery::Puk);
+                    puk_row.set_visible(piv);
+                    new_pin_row.set_visible(piv);
+                    unblock_row.set_visible(selected.is_some());
+                    unblock_row.set_subtitle(if piv {
+                        "Sets the new PIN on the PIV applet"
+                    } else {
+                        "pinentry asks for the Admin PIN or Reset Code, then the new PIN"
+                    });
+                })
+            };
+            update_recovery();
+            {
+                let update_recovery = update_recovery.clone();
+                recovery_row.connect_selected_notify(move |_| update_recovery());
+            }
+
+            let refresh: Rc<dyn Fn()> = {
+                let expander = expander.clone();
+                let guidance_row = guidance_row.clone();
+                let recovery_row = recovery_row.clone();
+                let recoveries = recoveries.clone();
+                let update_recovery = update_recovery.clone();
+                let status = status.clone();
+                Rc::new(move || {
+                    expander.set_subtitle("Checking...");
+                    let expander = expander.clone();
+                    let guidance_row = guidance_row.clone();
+                    let recovery_row = recovery_row.clone();
+                    let recoveries = recoveries.clone();
+                    let update_recovery = update_recovery.clone();
+                    let status = status.clone();
+                    glib::spawn_future_local(async move {
+                        let Ok(counters) = gio::spawn_blocking(lockout::check).await else {
+                            expander.set_subtitle("Reading the counters failed");
+                            return;
+                        };
+                        expander.set_subtitle(&glib::markup_escape_text(&counters.display_text()));
+                        expander.remove_css_class("warning");
+                        expander.remove_css_class("error");
+                        let level = counters.level();
+                        match level {
+                            lockout::Level::Full => {}
+                            lockout::Level::Dropped => expander.add_css_class("warning"),
+                            _ => expander.add_css_class("error"),
+                        }
+                        let guidance = counters.guidance();
+                        guidance_row.set_visible(!guidance.is_empty());
+                        guidance_row.set_subtitle(&glib::markup_escape_text(&guidance.join("\n")));
+                        if level >= lockout::Level::Last {
+                            show_status(&status, &guidance.join(" "), Some("error"));
+                        }
+                        let found = counters.recoveries();
+                        let names: Vec<&str> = found.iter().map(|r| r.display_name()).collect();
+                        recovery_row.set_model(Some(&gtk4::StringList::new(&names)));
+                        recovery_row.set_visible(!found.is_empty());
+                        *recoveries.borrow_mut() = found;
+                        update_recovery();
+                    });
+                })
+            };
+            {
+                let refresh = refresh.clone();
+                check_button.connect_clicked(move |_| refresh());
+            }
+            {
+                let refresh = refresh.clone();
+                self.schedule_probe(async move { refresh() });
+            }
+
+            // Wire unblock button: pinentry for OpenPGP, the entries for PIV
+            let status = status.clone();
+            unblock_button.connect_clicked(move |button| {
+                let Some(recovery) = recoveries
+                    .borrow()
+                    .get(recovery_row.selected() as usize)
+                    .copied()
+                else {
+                    return;
+                };
+                let (puk, new_pin) = (
+                    puk_entry.text().to_string(),
+                    new_pin_entry.text().to_string(),
+                );
+                if recovery == lockout::Recovery::Puk && (puk.is_empty() || new_pin.is_empty()) {
+                    show_status(&status, "Enter the PUK and a new PIN", Some("error"));
+                    return;
+                }
+                button.set_sensitive(false);
+                show_status(&status, "Unblocking...", None);
+                let btn = button.clone();
+                let (puk_entry, new_pin_entry) = (puk_entry.clone(), new_pin_entry.clone());
+                let refresh = refresh.clone();
+                let status = status.clone();
+                glib::spawn_future_local(async move {
+                    let result = gio::spawn_blocking(move || match recovery {
+                        lockout::Recovery::Puk => lockout::unblock_piv(&puk, &new_pin),
+                        _ => lockout::unblock_openpgp(recovery),
+                    })
+                    .await
+                    .unwrap_or_else(|_| Err(worker_panicked()));
+                    btn.set_sensitive(true);
+                    puk_entry.set_text("");
+                    new_pin_entry.set_text("");
+                    match result {
+                        Ok(()) => show_status(
+                            &status,
+                            "PIN unblocked; store the new PIN if Trusted Workstation keeps one",
+                            Some("success"),
+                        ),
+                        Err(e) => show_status(
+                            &status,
+                            &error_text("Unblocking the PIN failed", &e),
+                            Some("error"),
+                        ),
+                    }
+                    refresh();
+                });
+            });
+
+            expander
+        }
+
+        /// Change the passphrase on an identity's private key
+        fn build_passphrase_rows(
+            &self,
+            identity: &Identity,
+            status: &gtk4::Label,
+        ) -> adw::ExpanderRow {
+            let expander = adw::ExpanderRow::new();
+            expander.set_title("Key Passphrase");
+            expander.set_subtitle("Rewrap the private key with a new passphrase");
+
+            let passphrase_row = |title: &str| {
+                let row = adw::ActionRow::new();
+                row.set_title(title);
+                let entry = gtk4::PasswordEntry::new();
+                entry.set_show_peek_icon(true);
+                entry.set_hexpand(true);
+                entry.set_valign(gtk4::Align::Center);
+                row.add_suffix(&entry);
+                expander.add_row(&row);
+                entry
+            };
+            let old_entry = passphrase_row("Current Passphrase");
+            let new_entry = passphrase_row("New Passphrase");
+            let confirm_entry = passphrase_row("Confirm New Passphrase");
+
+            let store_row = adw::ActionRow::new();
+            store_row.set_title("Save to Key Store");
+            let key_path = databases::expand_tilde(&identity.ssh_key_path);
+            store_row.set_subtitle(&glib::markup_escape_text(&passphrase::entry_path(
+                &key_path,
+            )));
+            let store_switch = gtk4::Switch::new();
+            store_switch.set_valign(gtk4::Align::Center);
+            store_row.add_suffix(&store_switch);
+            expander.add_row(&store_row);
+
+            let change_row = adw::ActionRow::new();
+            change_row.set_title("Change Passphrase");
+            change_row.set_subtitle(
+                "The key is only replaced once the new passphrase is shown to unlock it",
+            );
+            let change_button = gtk4::Button::with_label("Change");
+            change_button.set_valign(gtk4::Align::Center);
+            change_button.add_css_class("suggested-action");
+            change_row.add_suffix(&change_button);
+            expander.add_row(&change_row);
+
+            // Wire change button: rotate on a worker, then clear the entries
+            let identity = identity.clone();
+            let status = status.clone();
+            change_button.connect_clicked(move |button| {
+                let old = old_entry.text().to_string();
+                let new = new_entry.text().to_string();
+                if let Err(message) = passphrase::validate(&old, &new, &confirm_entry.text()) {
+                    show_status(&status, message, Some("error"));
+                    return;
+                }
+                button.set_sensitive(false);
+                show_status(&status, "Changing key passphrase...", None);
+                let btn = button.clone();
+                let identity = identity.clone();
+                let store = store_switch.is_active();
+                let status = status.clone();
+                let entries = [old_entry.clone(), new_entry.clone(), confirm_entry.clone()];
+                glib::spawn_future_local(async move {
+                    let result = gio::spawn_blocking(move || {
+                        passphrase::rotate(&identity, &old, &new, store)
+                    })
+                    .await
+                    .unwrap_or_else(|_| Err(worker_panicked()));
+                    btn.set_sensitive(true);
+                    match result {
+                        Ok(rotation) => {
+                            for entry in &entries {
+                                entry.set_text("");
+                            }
+                            show_status(&status, &rotation.summary(), Some("success"));
+                        }
+                        Err(e) => show_status(
+                            &status,
+                            &error_text("Changing passphrase failed", &e),
+                            Some("error"),
+                        ),
+                    }
+                });
+            });
+
+            expander
+        }
+
+        /// Replace an identity's key end to end, resuming a rotation that
+        /// stopped part way
+        fn build_rekey_rows(&self, name: &str, status: &gtk4::Label) -> adw::ExpanderRow {
+            let expander = adw::ExpanderRow::new();
+            expander.set_title("Rotate Key");
+            let progress_row = adw::ActionRow::new();
+            progress_row.set_title("Progress");
+            expander.add_row(&progress_row);
+
+            let passphrase_row = adw::ActionRow::new();
+            passphrase_row.set_title("New Key Passphrase");
+            passphrase_row.set_subtitle("Leave empty for no passphrase");
+            let passphrase_entry = gtk4::PasswordEntry::new();
+            passphrase_entry.set_show_peek_icon(true);
+            passphrase_entry.set_hexpand(true);
+            passphrase_entry.set_valign(gtk4::Align::Center);
+            passphrase_row.add_suffix(&passphrase_entry);
+            expander.add_row(&passphrase_row);
+
+            let action_row = adw::ActionRow::new();
+            action_row.set_title("Rotate");
+            action_row.set_subtitle(
+                "Generate, register, switch, test, then revoke and archive the old key",
+            );
+            let abandon_button = gtk4::Button::with_label("Abandon");
+            abandon_button.set_valign(gtk4::Align::Center);
+            action_row.add_suffix(&abandon_button);
+            let run_button = gtk4::Button::with_label("Start");
+            run_button.set_valign(gtk4::Align::Center);
+            run_button.add_css_class("suggested-action");
+            action_row.add_suffix(&run_button);
+            expander.add_row(&action_row);
+
+            // Show the saved rotation, if any
+            let refresh = {
+                let name = name.to_string();
+                let expander = expander.clone();
+                let progress_row = progress_row.clone();
+                let run_button = run_button.clone();
+                let abandon_button = abandon_button.clone();
+                move || {
+                    let saved = rekey::RekeyStore::load().rotations.get(&name).cloned();
+                    match &saved {
+                        Some(rotation) => {
+                            expander
+                                .set_subtitle(&glib::markup_escape_text(&rotation.status_text()));
+                            let steps: Vec<String> = rekey::Step::ALL
+                                .iter()
+                                .filter(|step| **step != rekey::Step::Done)
+                                .map(|step| {
+                                    let mark = if *step < rotation.step { "✓" } else { "○" };
+                                    format!("{} {}", mark, step.display_name())
+                                })
+                                .collect();
+                            progress_row.set_subtitle(&steps.join("\n"));
+                            run_button.set_label("Resume");
+                        }
+                        None => {
+                            expander.set_subtitle("Replace the key with a new ed25519 key");
+                            progress_row.set_subtitle("No rotation in progress");
+                            run_button.set_label("Start");
+                        }
+                    }
+                    abandon_button.set_visible(saved.is_some_and(|r| r.can_abandon()));
+                }
+            };
+            refresh();
+
+            // Wire run button: start or resume on a worker, then reload,
+            // since a finished rotation changed the config
+            {
+                let name = name.to_string();
+                let status = status.clone();
+                let refresh = refresh.clone();
+                let imp_weak = self.downgrade();
+                run_button.connect_clicked(move |button| {
+                    let Some(config) = imp_weak
+                        .upgrade()
+                        .and_then(|imp| imp.config.borrow().clone())
+                    else {
+                        return;
+                    };
+                    button.set_sensitive(false);
+                    show_status(&status, "Rotating key...", None);
+                    let btn = button.clone();
+                    let name = name.clone();
+                    let passphrase = passphrase_entry.text().to_string();
+                    let status = status.clone();
+                    let refresh = refresh.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || {
+                            if rekey::RekeyStore::load().rotations.contains_key(&name) {
+                                rekey::resume(&name, &passphrase)
+                            } else {
+                                rekey::start(&config, &name, &passphrase)
+                            }
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                        btn.set_sensitive(true);
+                        match result {
+                            Ok(rotation) => {
+                                show_status(&status, &rotation.status_text(), Some("success"));
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.reload_config_and_ui();
+                                }
+                            }
+                            Err(e) => {
+                                refresh();
+                                show_status(
+                                    &status,
+                                    &error_text("Key rotation stopped", &e),
+                                    Some("error"),
+                                );
+                            }
+                        }
+                    });
+                });
+            }
+
+            // Wire abandon button: delete the unused new key
+            {
+                let name = name.to_string();
+                let status = status.clone();
+                abandon_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    let btn = button.clone();
+                    let name = name.clone();
+                    let status = status.clone();
+                    let refresh = refresh.clone();
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || rekey::abandon(&name))
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                        btn.set_sensitive(true);
+                        refresh();
+                        match result {
+                            Ok(()) => show_status(&status, "Key rotation abandoned", None),
+                            Err(e) => show_status(
+                                &status,
+                                &error_text("Abandoning rotation failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                });
+            }
+
+            expander
+        }
+
+        /// The touch prompt, if `step` uses a key of `name` that waits for
+        /// a touch
+        fn touch_prompt_for(&self, name: &str, step: verify::Step) -> Option<gtk4::Revealer> {
+            let requirements = self.touch_requirements.borrow().get(name).copied()?;
+            let requirement = match step {
+                verify::Step::Signing => requirements.signing,
+                verify::Step::LsRemote => requirements.ssh,
+                _ => None,
+            }?;
+            requirement
+                .requires_touch()
+                .then(|| self.touch_prompt.borrow().clone())
+                .flatten()
+        }
+
+        /// The account gh or glab is logged in as, with a button making
+        /// it the identity's
+        fn build_cli_auth_row(
+            &self,
+            name: &str,
+            identity: &Identity,
+            status: &gtk4::Label,
+        ) -> Option<adw::ActionRow> {
+            let tool = provider_cli::Tool::for_identity(identity)?;
+            let row = adw::ActionRow::new();
+            row.set_title(&format!("{} Account", tool.program()));
+            row.set_subtitle("Checking...");
+            let switch_button = gtk4::Button::with_label("Switch");
+            switch_button.set_valign(gtk4::Align::Center);
+            switch_button.set_tooltip_text(Some(&format!(
+                "Log {} in as this identity with its stored token",
+                tool.program()
+            )));
+            row.add_suffix(&switch_button);
+
+            {
+                let row = row.clone();
+                let identity = identity.clone();
+                self.schedule_probe(async move {
+                    let worker_identity = identity.clone();
+                    let result =
+                        gio::spawn_blocking(move || provider_cli::status(&worker_identity))
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                    let text = match result {
+                        Ok(Some(state)) => state.display_text(&identity),
+                        Ok(None) => format!("{} isn't installed", tool.program()),
+                        Err(e) => error_text("Checking the login failed", &e),
+                    };
+                    row.set_subtitle(&glib::markup_escape_text(&text));
+                });
+            }
+
+            let name = name.to_string();
+            let identity = identity.clone();
+            let status = status.clone();
+            let subtitle_row = row.clone();
+            switch_button.connect_clicked(move |button| {
+                button.set_sensitive(false);
+                let btn = button.clone();
+                let row = subtitle_row.clone();
+                let status = status.clone();
+                let (name, identity) = (name.clone(), identity.clone());
+                glib::spawn_future_local(async move {
+                    let worker_identity = identity.clone();
+                    let result =
+                        gio::spawn_blocking(move || provider_cli::switch(&name, &worker_identity))
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                    btn.set_sensitive(true);
+                    match result {
+                        Ok(state) => {
+                            row.set_subtitle(&glib::markup_escape_text(
+                                &state.display_text(&identity),
+                            ));
+                            show_status(
+                                &status,
+                                &format!(
+                                    "{} is logged in as {}",
+                                    tool.program(),
+                                    state.active.as_deref().unwrap_or("nobody")
+                                ),
+                                Some("success"),
+                            );
+                        }
+                        Err(e) => show_status(
+                            &status,
+                            &error_text(&format!("Switching {} failed", tool.program()), &e),
+                            Some("error"),
+                        ),
+                    }
+                });
+            });
+
+            Some(row)
+        }
+
+        /// The identity's SSH and GPG keys on its provider account, each
+        /// with a badge; Check lists them, missing keys can be uploaded and
+        /// stale and unknown ones removed
+        fn build_registration_rows(
+            &self,
+            name: &str,
+            identity: &Identity,
+            status: &gtk4::Label,
+        ) -> Option<adw::ExpanderRow> {
+            let provider = pubkeys::Provider::for_identity(identity)?;
+            let expander = adw::ExpanderRow::new();
+            expander.set_title("Provider Keys");
+            expander.set_subtitle(&format!(
+                "Keys on the {} account; not checked yet",
+                provider.display_name()
+            ));
+
+            let check_row = adw::ActionRow::new();
+            check_row.set_title("Compare Keys");
+            check_row.set_subtitle("Lists the account's SSH and GPG keys with its API token");
+            let check_button = gtk4::Button::with_label("Check");
+            check_button.set_valign(gtk4::Align::Center);
+            check_row.add_suffix(&check_button);
+            expander.add_row(&check_row);
+
+            // Rows from the last check, replaced by the next
+            let entry_rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::new(RefCell::new(Vec::new()));
+
+            let name = name.to_string();
+            let identity = identity.clone();
+            let status = status.clone();
+            let expander_ref = expander.clone();
+            check_button.connect_clicked(move |button| {
+                button.set_sensitive(false);
+                let btn = button.clone();
+                let expander = expander_ref.clone();
+                let entry_rows = entry_rows.clone();
+                let status = status.clone();
+                let (name, identity) = (name.clone(), identity.clone());
+                glib::spawn_future_local(async move {
+                    let result = {
+                        let (name, identity) = (name.clone(), identity.clone());
+                        gio::spawn_blocking(move || registration::check(&name, &identity))
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()))
+                    };
+                    btn.set_sensitive(true);
+                    for row in entry_rows.borrow_mut().drain(..) {
+                        expander.remove(&row);
+                    }
+                    let report = match result {
+                        Ok(report) => report,
+                        Err(e) => {
+                            show_status(
+                                &status,
+                                &error_text(&format!("{}: Listing keys failed", name), &e),
+                                Some("error"),
+                            );
+                            return;
+                        }
+                    };
+                    expander.set_subtitle(&glib::markup_escape_text(&report.summary()));
+                    expander.set_expanded(true);
+                    for entry in report.entries {
+                        let row = adw::ActionRow::new();
+                        row.set_title(&format!("{} Key", entry.kind.display_name()));
+                        row.set_subtitle(&glib::markup_escape_text(&entry.label));
+                        let badge = gtk4::Label::new(Some(entry.badge.display_text()));
+                        badge.add_css_class(entry.badge.css_class());
+                        row.add_suffix(&badge);
+                        let action = match entry.badge {
+                            Badge::Registered => None,
+                            Badge::Missing => Some(("Upload", "suggested-action")),
+                            Badge::Stale => Some(("Prune", "destructive-action")),
+                            Badge::Unknown => Some(("Remove", "destructive-action")),
+                        };
+                        if let Some((label, class)) = action {
+                            let action_button = gtk4::Button::with_label(label);
+                            action_button.set_valign(gtk4::Align::Center);
+                            action_button.add_css_class(class);
+                            row.add_suffix(&action_button);
+
+                            let row_ref = row.clone();
+                            let expander = expander.clone();
+                            let status = status.clone();
+                            let (name, identity) = (name.clone(), identity.clone());
+                            // An unknown key may still be in use elsewhere
+                            let armed = Rc::new(Cell::new(entry.badge != Badge::Unknown));
+                            action_button.connect_clicked(move |button| {
+                                if !armed.replace(true) {
+                                    button.set_label("Remove from Account");
+                                    show_status(
+                                        &status,
+                                        &format!(
+                                            "No key here matches \"{}\"; if no other machine \
+                                             uses it, click again to remove it from {}",
+                                            entry.label,
+                                            provider.display_name()
+                                        ),
+                                        Some("warning"),
+                                    );
+                                    return;
+                                }
+                                button.set_sensitive(false);
+                                let btn = button.clone();
+                                let row = row_ref.clone();
+                                let badge = badge.clone();
+                                let expander = expander.clone();
+                                let status = status.clone();
+                                let entry = entry.clone();
+                                let (name, identity) = (name.clone(), identity.clone());
+                                glib::spawn_future_local(async move {
+                                    let result = {
+                                        let (name, entry) = (name.clone(), entry.clone());
+                                        gio::spawn_blocking(move || match entry.badge {
+                                            Badge::Stale | Badge::Unknown => {
+                                                registration::prune(&name, &identity, &entry)
+                                            }
+                                            _ => registration::upload(&name, &identity, entry.kind),
+                                        })
+                                        .await
+                                        .unwrap_or_else(|_| Err(worker_panicked()))
+                                    };
+                                    let kind = entry.kind.display_name();
+                                    match (result, entry.badge) {
+                                        (Ok(()), Badge::Stale | Badge::Unknown) => {
+                                            expander.remove(&row);
+                                            show_status(
+                                                &status,
+                                                &format!(
+                                                    "Removed {} key \"{}\" from {}",
+                                                    kind,
+                                                    entry.label,
+                                                    provider.display_name()
+                                                ),
+                                                Some("success"),
+                                            );
+                                        }
+                                        (Ok(()), _) => {
+                                            btn.set_visible(false);
+                                            badge.set_label(Badge::Registered.display_text());
+                                            badge.remove_css_class(Badge::Missing.css_class());
+                                            badge.add_css_class(Badge::Registered.css_class());
+                                            show_status(
+                                                &status,
+                                                &format!(
+                                                    "Uploaded {}'s {} key to {}",
+                                                    name,
+                                                    kind,
+                                                    provider.display_name()
+                                                ),
+                                                Some("success"),
+                                            );
+                                        }
+                                        (Err(e), badge) => {
+                                            btn.set_sensitive(true);
+                                            let prefix = if badge != Badge::Missing {
+                                                format!("Removing the {} key failed", kind)
+                                            } else {
+                                                format!("Uploading the {} key failed", kind)
+                                            };
+                                            show_status(
+                                                &status,
+                                                &error_text(&prefix, &e),
+                                                Some("error"),
+                                            );
+                                        }
+                                    }
+                                });
+                            });
+                        }
+                        expander.add_row(&row);
+                        entry_rows.borrow_mut().push(row);
+                    }
+                });
+            });
+
+            Some(expander)
+        }
+
+        /// Whether the chosen repository's recent pushed commits show as
+        /// verified on the provider, with a fix for each identity whose
+        /// commits don't
+        fn build_verified_commits_row(
+            &self,
+            chosen: Rc<RefCell<Option<PathBuf>>>,
+            status: &gtk4::Label,
+        ) -> (adw::ExpanderRow, gtk4::Button) {
+            let expander = adw::ExpanderRow::new();
+            expander.set_title("Provider Verification");
+            expander.set_subtitle("Whether recent pushed commits show as verified");
+            let check_button = gtk4::Button::with_label("Check");
+            check_button.set_valign(gtk4::Align::Center);
+            check_button.set_sensitive(false);
+            expander.add_action(&check_button);
+
+            // Rows from the last check, replaced by the next
+            let rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::default();
+
+            let imp_weak = self.downgrade();
+            let status = status.clone();
+            let expander_ref = expander.clone();
+            check_button.connect_clicked(move |button| {
+                let Some(repo) = chosen.borrow().clone() else {
+                    return;
+                };
+                let Some(imp) = imp_weak.upgrade() else {
+                    return;
+                };
+                let Some(config) = imp.config.borrow().clone() else {
+                    return;
+                };
+                button.set_sensitive(false);
+                let btn = button.clone();
+                let expander = expander_ref.clone();
+                let rows = rows.clone();
+                let status = status.clone();
+                let imp_weak = imp_weak.clone();
+                glib::spawn_future_local(async move {
+                    let result = {
+                        let repo = repo.clone();
+                        gio::spawn_blocking(move || verified_commits::check(&config, &repo))
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()))
+                    };
+                    btn.set_sensitive(true);
+                    for row in rows.borrow_mut().drain(..) {
+                        expander.remove(&row);
+                    }
+                    let report = match result {
+                        Ok(report) => report,
+                        Err(e) => {
+                            show_status(
+                                &status,
+                                &error_text("Asking the provider about commits failed", &e),
+                                Some("error"),
+                            );
+                            return;
+                        }
+                    };
+                    expander.set_subtitle(&glib::markup_escape_text(&report.summary()));
+                    expander.set_expanded(true);
+
+                    for suggestion in report.suggestions {
+                        let row = adw::ActionRow::new();
+                        row.set_title(&glib::markup_escape_text(&suggestion.identity));
+                        row.set_subtitle(&glib::markup_escape_text(&suggestion.detail));
+                        if let Some(fix) = suggestion.fix {
+                            let fix_button = gtk4::Button::with_label(fix.display_text());
+                            fix_button.set_valign(gtk4::Align::Center);
+                            fix_button.add_css_class("suggested-action");
+                            row.add_suffix(&fix_button);
+
+                            let repo = repo.clone();
+                            let status = status.clone();
+                            let imp_weak = imp_weak.clone();
+                            let name = suggestion.identity.clone();
+                            fix_button.connect_clicked(move |button| {
+                                button.set_sensitive(false);
+                                let btn = button.clone();
+                                let repo = repo.clone();
+                                let status = status.clone();
+                                let imp_weak = imp_weak.clone();
+                                let name = name.clone();
+                                glib::spawn_future_local(async move {
+                                    let result = {
+                                        let name = name.clone();
+                                        gio::spawn_blocking(move || {
+                                            verified_commits::apply(&repo, &name, fix)
+                                        })
+                                        .await
+                                        .unwrap_or_else(|_| Err(worker_panicked()))
+                                    };
+                                    match result {
+                                        Ok(()) => {
+                                            btn.set_visible(false);
+                                            show_status(
+                                                &status,
+                                                &format!("{}: {}", name, fix.done_text()),
+                                                Some("success"),
+                                            );
+                                            if fix == verified_commits::Fix::EnableSigning {
+                                                if let Some(imp) = imp_weak.upgrade() {
+                                                    imp.reload_config_and_ui();
+                                                }
+                                            }
+                                        }
+                                        Err(e) => {
+                                            btn.set_sensitive(true);
+                                            show_status(
+                                                &status,
+                                                &error_text(
+                                                    &format!(
+                                                        "{}: {} failed",
+                                                        name,
+                                                        fix.display_text()
+                                                    ),
+                                                    &e,
+                                                ),
+                                                Some("error"),
+                                            );
+                                        }
+                                    }
+                                });
+                            });
+                        }
+                        expander.add_row(&row);
+                        rows.borrow_mut().push(row);
+                    }
+
+                    for commit in report
+                        .commits
+                        .iter()
+                        .filter(|c| c.status != verified_commits::Status::Verified)
+                    {
+                        let row = adw::ActionRow::new();
+                        row.set_title(commit.short_sha());
+                        row.set_subtitle(&glib::markup_escape_text(&format!(
+                            "{} ({}): {}",
+                            commit.email,
+                            commit.identity.as_deref().unwrap_or("no identity"),
+                            commit.status.display_text()
+                        )));
+                        let badge = gtk4::Label::new(Some("unverified"));
+                        badge.add_css_class("warning");
+                        row.add_suffix(&badge);
+                        expander.add_row(&row);
+                        rows.borrow_mut().push(row);
+                    }
+                });
+            });
+
+            (expander, check_button)
+        }
+
+        /// The identity's access token: its recorded scopes and expiry,
+        /// signing in through the device flow where the provider has one,
+        /// a check with the provider, and rotation, through the API where
+        /// the provider issues replacements, otherwise with a token
+        /// created on its settings page
+        fn build_token_rows(
+            &self,
+            name: &str,
+            identity: &Identity,
+            status: &gtk4::Label,
+        ) -> Option<adw::ExpanderRow> {
+            let provider = pubkeys::Provider::for_identity(identity)?;
+            let expander = adw::ExpanderRow::new();
+            expander.set_title("Access Token");
+            let now = expiry::now();
+            let subtitle = match pat::TokenFile::load().get(provider, name) {
+                Some(meta) => match revocation::problem(name, provider, meta, now) {
+                    Some(problem) => {
+                        expander.add_css_class("error");
+                        expander.set_expanded(true);
+                        problem.display_text()
+                    }
+                    None => meta.display_text(now),
+                },
+                None => format!("{} token; not checked yet", provider.display_name()),
+            };
+            expander.set_subtitle(&glib::markup_escape_text(&subtitle));
+
+            if oauth::supports(provider) {
+                let sign_in_row = adw::ActionRow::new();
+                sign_in_row.set_title("Sign In");
+                let idle_subtitle = format!(
+                    "Authorize in the browser instead of creating a token on {}",
+                    oauth::hostname(provider, identity)
+                );
+                sign_in_row.set_subtitle(&idle_subtitle);
+                let sign_in_button = gtk4::Button::with_label("Sign In");
+                sign_in_button.set_valign(gtk4::Align::Center);
+                sign_in_row.add_suffix(&sign_in_button);
+                expander.add_row(&sign_in_row);
+
+                // Click again to cancel
+                let name = name.to_string();
+                let identity = identity.clone();
+                let status = status.clone();
+                let expander = expander.clone();
+                let slot = CancelSlot::default();
+                sign_in_button.connect_clicked(move |button| {
+                    if slot.cancel_running() {
+                        return;
+                    }
+                    let token = slot.start();
+                    button.set_label("Cancel");
+                    let btn = button.clone();
+                    let slot = slot.clone();
+                    let row = sign_in_row.clone();
+                    let idle_subtitle = idle_subtitle.clone();
+                    let expander = expander.clone();
+                    let status = status.clone();
+                    let (name, identity) = (name.clone(), identity.clone());
+                    glib::spawn_future_local(async move {
+                        let started = {
+                            let identity = identity.clone();
+                            gio::spawn_blocking(move || oauth::start(&identity))
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()))
+                        };
+                        let result = match started {
+                            Ok(code) => {
+                                row.set_subtitle(&glib::markup_escape_text(&format!(
+                                    "Enter {} at {} (copied)",
+                                    code.user_code, code.verification_uri
+                                )));
+                                if let Some(display) = gdk::Display::default() {
+                                    display.clipboard().set_text(&code.user_code);
+                                }
+                                let uri = code
+                                    .verification_uri_complete
+                                    .clone()
+                                    .unwrap_or_else(|| code.verification_uri.clone());
+                                gtk4::UriLauncher::new(&uri).launch(
+                                    None::<&gtk4::Window>,
+                                    None::<&gio::Cancellable>,
+                                    |_| {},
+                                );
+                                let name = name.clone();
+                                gio::spawn_blocking(move || {
+                                    oauth::finish(&name, &identity, &code, &token)
+                                })
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()))
+                            }
+                            Err(e) => Err(e),
+                        };
+                        slot.finish();
+                        btn.set_label("Sign In");
+                        row.set_subtitle(&idle_subtitle);
+                        match result {
+                            Ok(entry) => {
+                                if let Some(meta) = pat::TokenFile::load().get(provider, &name) {
+                                    expander.set_subtitle(&glib::markup_escape_text(
+                                        &meta.display_text(expiry::now()),
+                                    ));
+                                }
+                                show_status(
+                                    &status,
+                                    &format!("Signed in; {}'s token is stored at {}", name, entry),
+                                    Some("success"),
+                                );
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text(&format!("{}: Signing in failed", name), &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                });
+            }
+
+            let check_row = adw::ActionRow::new();
+            check_row.set_title("Scopes and Expiry");
+            check_row.set_subtitle("Asks the provider about the stored token");
+            let check_button = gtk4::Button::with_label("Check");
+            check_button.set_valign(gtk4::Align::Center);
+            check_row.add_suffix(&check_button);
+            expander.add_row(&check_row);
+
+            {
+                let name = name.to_string();
+                let identity = identity.clone();
+                let status = status.clone();
+                let expander = expander.clone();
+                check_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    let btn = button.clone();
+                    let expander = expander.clone();
+                    let status = status.clone();
+                    let (name, identity) = (name.clone(), identity.clone());
+                    glib::spawn_future_local(async move {
+                        let result = {
+                            let name = name.clone();
+                            gio::spawn_blocking(move || {
+                                let meta = pat::check(&name, &identity)?;
+                                let budget = pubkeys::Provider::for_identity(&identity)
+                                    .map(|provider| {
+                                        provider_hosts::api_base(
+                                            &provider_hosts::configured(),
+                                            provider,
+                                            &identity.hostname,
+                                        )
+                                    })
+                                    .and_then(|base| provider_api::last_rate_limit(&base));
+                                Ok((meta, budget))
+                            })
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()))
+                        };
+                        btn.set_sensitive(true);
+                        match result {
+                            Ok((meta, budget)) => {
+                                let now = expiry::now();
+                                expander.set_subtitle(&glib::markup_escape_text(
+                                    &meta.display_text(now),
+                                ));
+                                let mut text = format!("{}'s token is accepted", name);
+                                if let Some(budget) = budget {
+                                    text.push_str(&format!(" · {}", budget.display_text(now)));
+                                }
+                                show_status(&status, &text, Some("success"));
+                            }
+                            Err(e) => show_status(
+                                &status,
+                                &error_text(&format!("{}: Checking the token failed", name), &e),
+                                Some("error"),
+                            ),
+                        }
+                    });
+                });
+            }
+
+            // Shared by both ways of rotating
+            let finish = {
+                let name = name.to_string();
+                let status = status.clone();
+                let expander = expander.clone();
+                let imp_weak = self.downgrade();
+                move |result: Result<pat::Rotation, Error>| match result {
+                    Ok(rotation) => {
+                        expander.set_subtitle(&glib::markup_escape_text(
+                            &rotation.meta.display_text(expiry::now()),
+                        ));
+                        show_status(
+                            &status,
+                            &format!(
+                                "Rotated {}'s token in {}",
+                                name,
+                                rotation.entries.join(", ")
+                            ),
+                            Some("success"),
+                        );
+                        if let Some(imp) = imp_weak.upgrade() {
+                            imp.check_rotation();
+                        }
+                    }
+                    Err(e) => show_status(
+                        &status,
+                        &error_text(&format!("{}: Rotating the token failed", name), &e),
+                        Some("error"),
+                    ),
+                }
+            };
+            let finish = Rc::new(finish);
+
+            if pat::rotates_via_api(provider) {
+                let rotate_row = adw::ActionRow::new();
+                rotate_row.set_title("Rotate via API");
+                rotate_row.set_subtitle(&format!(
+                    "{} issues a replacement with the same lifetime and revokes this token",
+                    provider.display_name()
+                ));
+                let rotate_button = gtk4::Button::with_label("Rotate");
+                rotate_button.set_valign(gtk4::Align::Center);
+                rotate_button.add_css_class("suggested-action");
+                rotate_row.add_suffix(&rotate_button);
+                expander.add_row(&rotate_row);
+
+                let name = name.to_string();
+                let identity = identity.clone();
+                let finish = finish.clone();
+                rotate_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    let btn = button.clone();
+                    let finish = finish.clone();
+                    let (name, identity) = (name.clone(), identity.clone());
+                    glib::spawn_future_local(async move {
+                        let result = gio::spawn_blocking(move || pat::rotate(&name, &identity))
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                        btn.set_sensitive(true);
+                        finish(result);
+                    });
+                });
+            }
+
+            let replace_row = adw::ActionRow::new();
+            replace_row.set_title("Replace Token");
+            replace_row.set_subtitle(
+                "Stored everywhere the current token is, once the provider accepts it",
+            );
+            let token_entry = gtk4::PasswordEntry::new();
+            token_entry.set_show_peek_icon(true);
+            token_entry.set_hexpand(true);
+            token_entry.set_valign(gtk4::Align::Center);
+            replace_row.add_suffix(&token_entry);
+            let create_link = gtk4::LinkButton::with_label(
+                &pat::new_token_url(provider, &identity.hostname),
+                "Create",
+            );
+            create_link.set_valign(gtk4::Align::Center);
+            replace_row.add_suffix(&create_link);
+            let replace_button = gtk4::Button::with_label("Replace");
+            replace_button.set_valign(gtk4::Align::Center);
+            replace_row.add_suffix(&replace_button);
+            expander.add_row(&replace_row);
+
+            let name = name.to_string();
+            let identity = identity.clone();
+            replace_button.connect_clicked(move |button| {
+                let token = token_entry.text().to_string();
+                if token.trim().is_empty() {
+                    return;
+                }
+                button.set_sensitive(false);
+                let btn = button.clone();
+                let entry = token_entry.clone();
+                let finish = finish.clone();
+                let (name, identity) = (name.clone(), identity.clone());
+                glib::spawn_future_local(async move {
+                    let result =
+                        gio::spawn_blocking(move || pat::replace(&name, &identity, &token))
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                    btn.set_sensitive(true);
+                    if result.is_ok() {
+                        entry.set_text("");
+                    }
+                    finish(result);
+                });
+            });
+
+            Some(expander)
+        }
+
+        /// Dry-run everything the identity depends on, one check at a time
+        fn build_verify_rows(&self, name: &str, status: &gtk4::Label) -> adw::ExpanderRow {
+            let expander = adw::ExpanderRow::new();
+            expander.set_title("Verify Identity");
+            expander
+                .set_subtitle("Config, SSH, agent, provider key, signing and repository access");
+
+            let run_row = adw::ActionRow::new();
+            run_row.set_title("Run Checks");
+            run_row.set_subtitle("Changes nothing; signing may ask for a PIN or touch");
+            let run_button = gtk4::Button::with_label("Verify");
+            run_button.set_valign(gtk4::Align::Center);
+            run_button.add_css_class("suggested-action");
+            run_row.add_suffix(&run_button);
+            expander.add_row(&run_row);
+
+            let rows: Vec<(adw::ActionRow, gtk4::Image)> = verify::Step::ALL
+                .iter()
+                .map(|step| {
+                    let row = adw::ActionRow::new();
+                    row.set_title(step.display_name());
+                    row.set_subtitle("Not run");
+                    let icon = gtk4::Image::from_icon_name("content-loading-symbolic");
+                    icon.set_visible(false);
+                    row.add_prefix(&icon);
+                    expander.add_row(&row);
+                    (row, icon)
+                })
+                .collect();
+
+            // Wire verify button: run the checks in order on a worker,
+            // filling in each row as its check finishes
+            let name = name.to_string();
+            let status = status.clone();
+            let imp_weak = self.downgrade();
+            run_button.connect_clicked(move |button| {
+                let Some(config) = imp_weak
+                    .upgrade()
+                    .and_then(|imp| imp.config.borrow().clone())
+                else {
+                    return;
+                };
+                button.set_sensitive(false);
+                for (row, icon) in &rows {
+                    row.set_subtitle("Waiting...");
+                    icon.set_visible(false);
+                }
+                let btn = button.clone();
+                let name = name.clone();
+                let status = status.clone();
+                let rows = rows.clone();
+                let imp_weak = imp_weak.clone();
+                glib::spawn_future_local(async move {
+                    let mut failed = 0;
+                    for (step, (row, icon)) in verify::Step::ALL.iter().zip(&rows) {
+                        row.set_subtitle("Checking...");
+                        let prompt = imp_weak
+                            .upgrade()
+                            .and_then(|imp| imp.touch_prompt_for(&name, *step));
+                        let (config, name, step) = (config.clone(), name.clone(), *step);
+                        let work =
+                            gio::spawn_blocking(move || verify::run_check(&config, &name, step));
+                        let Ok(check) = with_touch_prompt(prompt, work).await else {
+                            row.set_subtitle(&worker_panicked().to_string());
+                            failed += 1;
+                            continue;
+                        };
+                        row.set_subtitle(&glib::markup_escape_text(&check.detail));
+                        icon.set_icon_name(Some(check.outcome.icon_name()));
+                        icon.remove_css_class("success");
+                        icon.remove_css_class("error");
+                        match check.outcome {
+                            verify::Outcome::Pass => icon.add_css_class("success"),
+                            verify::Outcome::Fail => {
+                                icon.add_css_class("error");
+                                failed += 1;
+                            }
+                            verify::Outcome::Skipped => {}
+                        }
+                        icon.set_visible(true);
+                    }
+                    btn.set_sensitive(true);
+                    match failed {
+                        0 => show_status(&status, "Identity verified", Some("success")),
+                        1 => show_status(&status, "1 verification check failed", Some("error")),
+                        n => show_status(
+                            &status,
+                            &format!("{} verification checks failed", n),
+                            Some("error"),
+                        ),
+                    }
+                });
+            });
+
+            expander
+        }
+
+        /// Check secrets for expiry and refresh the Needs Rotation group
+        fn check_rotation(&self) {
+            let imp_weak = self.downgrade();
+            let cancellable = self.cancellable.clone();
+            let config = self.config.borrow().clone();
+            glib::spawn_future_local(async move {
+                let reminders = gio::spawn_blocking(move || {
+                    let now = expiry::now();
+                    let mut reminders = expiry::check(now);
+                    if let Some(config) = config {
+                        reminders.extend(gpg::reminders(&config, now));
+                        reminders.sort_by_key(|r| r.due_at);
+                    }
+                    reminders
+                })
+                .await
+                .unwrap_or_default();
+                let Some(imp) = imp_weak.upgrade() else {
+                    return;
+                };
+                if cancellable.is_cancelled() {
+                    return;
+                }
+                imp.show_rotation(&reminders);
+                imp.notify_rotation(&reminders);
+            });
+        }
+
+        /// Replace the rows of the Needs Rotation group
+        fn show_rotation(&self, reminders: &[Reminder]) {
+            let Some(group) = self.rotation_group.borrow().clone() else {
+                return;
+            };
+            for row in self.rotation_rows.borrow_mut().drain(..) {
+                group.remove(&row);
+            }
+
+            let mut rows = Vec::new();
+            for reminder in reminders {
+                let row = adw::ActionRow::new();
+                row.set_title(&reminder.name);
+                row.set_subtitle(&reminder.display_text());
+                let urgency = gtk4::Label::new(Some(match reminder.urgency {
+                    Urgency::Expired { .. } => "Expired",
+                    Urgency::DueSoon { .. } => "Due soon",
+                }));
+                urgency.add_css_class(match reminder.urgency {
+                    Urgency::Expired { .. } => "error",
+                    Urgency::DueSoon { .. } => "warning",
+                });
+                row.add_suffix(&urgency);
+
+                match &reminder.kind {
+                    // Restart the rotation clock once the secret was replaced
+                    ReminderKind::Entry => {
+                        let button = gtk4::Button::with_label("Rotated");
+                        button.set_valign(gtk4::Align::Center);
+                        button.set_tooltip_text(Some("Mark this secret as rotated"));
+                        row.add_suffix(&button);
+                        let name = reminder.name.clone();
+                        let imp_weak = self.downgrade();
+                        button.connect_clicked(move |button| {
+                            button.set_sensitive(false);
+                            let name = name.clone();
+                            let imp_weak = imp_weak.clone();
+                            glib::spawn_future_local(async move {
+                                let _ = gio::spawn_blocking(move || expiry::record_rotation(&name))
+                                    .await;
+                                if let Some(imp) = imp_weak.upgrade() {
+                                    imp.check_rotation();
+                                }
+                            });
+                        });
+                    }
+                    // Replacing the token records its new expiry
+                    ReminderKind::Token => {
+                        row.set_tooltip_text(Some(
+                            "Rotate from the identity's Access Token row, or with: remote-juggler token set <identity>",
+                        ));
+                    }
+                    // gpg-agent's pinentry asks for the passphrase or PIN
+                    ReminderKind::GpgKey {
+                        fingerprint,
+                        security_mode,
+                    } => {
+                        let days = self
+                            .config
+                            .borrow()
+                            .as_ref()
+                            .map(|c| c.settings.gpg_expiry.extend_days)
+                            .unwrap_or_else(|| GpgExpirySettings::default().extend_days);
+                        let button = gtk4::Button::with_label("Extend");
+                        button.set_valign(gtk4::Align::Center);
+                        button.set_tooltip_text(Some(&format!(
+                            "Extend the key and its subkeys by {} days",
+                            days
+                        )));
+                        row.add_suffix(&button);
+                        let fingerprint = fingerprint.clone();
+                        let security_mode = security_mode.clone();
+                        let imp_weak = self.downgrade();
+                        let row_weak = row.downgrade();
+                        button.connect_clicked(move |button| {
+                            button.set_sensitive(false);
+                            let fingerprint = fingerprint.clone();
+                            let security_mode = security_mode.clone();
+                            let imp_weak = imp_weak.clone();
+                            let row_weak = row_weak.clone();
+                            let button = button.clone();
+                            glib::spawn_future_local(async move {
+                                let result = gio::spawn_blocking(move || {
+                                    gpg::extend(&fingerprint, days, &security_mode)
+                                })
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                                match result {
+                                    Ok(_) => {
+                                        if let Some(imp) = imp_weak.upgrade() {
+                                            imp.check_rotation();
+                                        }
+                                    }
+                                    Err(e) => {
+                                        button.set_sensitive(true);
+                                        if let Some(row) = row_weak.upgrade() {
+                                            row.set_subtitle(&glib::markup_escape_text(
+                                                &error_text("Extending failed", &e),
+                                            ));
+                                        }
+                                    }
+                                }
+                            });
+                        });
+                    }
+                }
+
+                group.add(&row);
+                rows.push(row);
+            }
+            group.set_visible(!rows.is_empty());
+            *self.rotation_rows.borrow_mut() = rows;
+        }
+
+        /// Send a desktop notification for reminders not notified yet
+        fn notify_rotation(&self, reminders: &[Reminder]) {
+            let mut notified = self.rotation_notified.borrow_mut();
+            // Forget rotated secrets so they notify again when next due
+            notified.retain(|name| reminders.iter().any(|r| &r.name == name));
+            let new: Vec<&Reminder> = reminders
+                .iter()
+                .filter(|r| !notified.contains(&r.name))
+                .collect();
+            if new.is_empty() {
+                return;
+            }
+            let Some(app) = self.obj().application() else {
+                return;
+            };
+
+            let notification = gio::Notification::new("Secrets need rotation");
+            let body = match new.as_slice() {
+                [reminder] => format!("{}: {}", reminder.name, reminder.display_text()),
+                _ => format!("{} secrets are expired or due soon", new.len()),
+            };
+            notification.set_body(Some(&body));
+            app.send_notification(Some("rotation"), &notification);
+
+            notified.extend(new.iter().map(|r| r.name.clone()));
+        }
+
+        /// Validate stored tokens, those due for it or all with `force`,
+        /// and refresh the Reauthenticate group
+        fn check_tokens(&self, force: bool) {
+            let imp_weak = self.downgrade();
+            let cancellable = self.cancellable.clone();
+            let Some(config) = self.config.borrow().clone() else {
+                return;
+            };
+            glib::spawn_future_local(async move {
+                let problems = gio::spawn_blocking(move || revocation::sweep(&config, force))
+                    .await
+                    .unwrap_or_default();
+                let Some(imp) = imp_weak.upgrade() else {
+                    return;
+                };
+                if cancellable.is_cancelled() {
+                    return;
+                }
+                imp.show_token_problems(&problems);
+                imp.notify_token_problems(&problems);
+            });
+        }
+
+        /// Replace the rows of the Reauthenticate group
+        fn show_token_problems(&self, problems: &[revocation::Problem]) {
+            let Some(group) = self.token_problems_group.borrow().clone() else {
+                return;
+            };
+            for row in self.token_problem_rows.borrow_mut().drain(..) {
+                group.remove(&row);
+            }
+
+            let hostnames: HashMap<String, String> = self
+                .config
+                .borrow()
+                .as_ref()
+                .map(|config| {
+                    config
+                        .identities
+                        .iter()
+                        .map(|(name, identity)| (name.clone(), identity.hostname.clone()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let mut rows = Vec::new();
+            for problem in problems {
+                let row = adw::ActionRow::new();
+                row.set_title(&glib::markup_escape_text(&problem.identity));
+                row.set_subtitle(&glib::markup_escape_text(&problem.display_text()));
+                row.set_tooltip_text(Some(
+                    "Sign in or replace the token from the identity's Access Token row",
+                ));
+                let badge = gtk4::Label::new(Some(match problem.reason {
+                    revocation::Reason::Rejected => "Rejected",
+                    revocation::Reason::Expired(_) => "Expired",
+                }));
+                badge.add_css_class("error");
+                row.add_suffix(&badge);
+
+                let create_button = gtk4::Button::with_label("Create Token");
+                create_button.set_valign(gtk4::Align::Center);
+                row.add_suffix(&create_button);
+                let url = pat::new_token_url(
+                    problem.provider,
+                    hostnames
+                        .get(&problem.identity)
+                        .map(String::as_str)
+                        .unwrap_or_default(),
+                );
+                create_button.connect_clicked(move |_| {
+                    gtk4::UriLauncher::new(&url).launch(
+                        None::<&gtk4::Window>,
+                        None::<&gio::Cancellable>,
+                        |_| {},
+                    );
+                });
+
+                // After signing in elsewhere, e.g. with the CLI
+                let check_button = gtk4::Button::with_label("Check Again");
+                check_button.set_valign(gtk4::Align::Center);
+                row.add_suffix(&check_button);
+                let imp_weak = self.downgrade();
+                check_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    if let Some(imp) = imp_weak.upgrade() {
+                        imp.check_tokens(true);
+                    }
+                });
+
+                group.add(&row);
+                rows.push(row);
+            }
+            group.set_visible(!rows.is_empty());
+            *self.token_problem_rows.borrow_mut() = rows;
+        }
+
+        /// Send a desktop notification for identities not notified yet
+        fn notify_token_problems(&self, problems: &[revocation::Problem]) {
+            let mut notified = self.token_problems_notified.borrow_mut();
+            // Forget fixed tokens so they notify again if rejected later
+            notified.retain(|name| problems.iter().any(|p| &p.identity == name));
+            let new: Vec<&revocation::Problem> = problems
+                .iter()
+                .filter(|p| !notified.contains(&p.identity))
+                .collect();
+            if new.is_empty() {
+                return;
+            }
+            let Some(app) = self.obj().application() else {
+                return;
+            };
+
+            let notification = gio::Notification::new("Tokens need attention");
+            let body = match new.as_slice() {
+                [problem] => format!("{}: {}", problem.identity, problem.display_text()),
+                _ => format!("{} identities need to sign in again", new.len()),
+            };
+            notification.set_body(Some(&body));
+            app.send_notification(Some("revocation"), &notification);
+            notified.extend(new.iter().map(|p| p.identity.clone()));
+        }
+
+        /// Replace the rows of the Leased Secrets group
+        fn show_leases(&self) {
+            let Some(group) = self.lease_group.borrow().clone() else {
+                return;
+            };
+            for row in self.lease_rows.borrow_mut().drain(..) {
+                group.remove(&row);
+            }
+
+            let now = expiry::now();
+            let mut rows = Vec::new();
+            let mut badges = Vec::new();
+            let store = LeaseStore::load().unwrap_or_else(|e| {
+                tracing::warn!("Failed to load leases: {}", e);
+                LeaseStore::default()
+            });
+            for (path, lease) in store.by_expiry() {
+                let row = adw::ActionRow::new();
+                row.set_title(&glib::markup_escape_text(&path));
+                let mut subtitle = lease.on_expiry.display_name().to_string();
+                if lease.renew_command.is_some() {
+                    subtitle.push_str(" unless renewed");
+                }
+                row.set_subtitle(&subtitle);
+                let badge = gtk4::Label::new(Some(&leases::format_remaining(lease.remaining(now))));
+                badge.add_css_class("monospace");
+                badge.add_css_class("warning");
+                row.add_suffix(&badge);
+
+                let button = gtk4::Button::with_label("End Now");
+                button.set_valign(gtk4::Align::Center);
+                button.add_css_class("destructive-action");
+                row.add_suffix(&button);
+                let imp_weak = self.downgrade();
+                button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    let path = path.clone();
+                    let imp_weak = imp_weak.clone();
+                    glib::spawn_future_local(async move {
+                        let ended = gio::spawn_blocking(move || leases::revoke(&path))
+                            .await
+                            .ok()
+                            .flatten();
+                        if let Some(imp) = imp_weak.upgrade() {
+                            imp.notify_leases(ended.as_slice());
+                            imp.show_leases();
+                        }
+                    });
+                });
+
+                group.add(&row);
+                rows.push(row);
+                badges.push((badge, lease.expires_at));
+            }
+            group.set_visible(!rows.is_empty());
+            *self.lease_rows.borrow_mut() = rows;
+            *self.lease_badges.borrow_mut() = badges;
+        }
+
+        /// Update countdowns, ending leases that ran out
+        fn tick_leases(&self) {
+            let now = expiry::now();
+            let mut due = false;
+            for (badge, expires_at) in self.lease_badges.borrow().iter() {
+                badge.set_text(&leases::format_remaining(expires_at.saturating_sub(now)));
+                due |= *expires_at <= now;
+            }
+            if due {
+                self.enforce_leases();
+            }
+        }
+
+        /// End expired leases in the background, then refresh the group
+        fn enforce_leases(&self) {
+            if self.lease_enforcing.replace(true) {
+                return;
+            }
+            let imp_weak = self.downgrade();
+            glib::spawn_future_local(async move {
+                let ended = gio::spawn_blocking(|| leases::enforce(expiry::now()))
+                    .await
+                    .unwrap_or_default();
+                let Some(imp) = imp_weak.upgrade() else {
+                    return;
+                };
+                imp.lease_enforcing.set(false);
+                imp.notify_leases(&ended);
+                imp.show_leases();
+            });
+        }
+
+        /// Tell the user which leases ended and what happened to the entries
+        fn notify_leases(&self, ended: &[leases::Expiry]) {
+            if ended.is_empty() {
+                return;
+            }
+            let Some(app) = self.obj().application() else {
+                return;
+            };
+            let notification = gio::Notification::new("Leased secrets ended");
+            let body = match ended {
+                [one] => one.display_text(),
+                _ => ended
+                    .iter()
+                    .map(leases::Expiry::display_text)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            };
+            notification.set_body(Some(&body));
+            app.send_notification(Some("leases"), &notification);
+        }
+
+        fn build_ui(&self) {
+            let window = self.obj();
+
+            // Create header bar
+            let header = adw::HeaderBar::new();
+
+            // Refresh drops cached CLI results and reloads everything
+            let refresh_button = gtk4::Button::from_icon_name("view-refresh-symbolic");
+            refresh_button.set_tooltip_text(Some("Refresh"));
+            let imp_weak = self.downgrade();
+            refresh_button.connect_clicked(move |_| {
+                cli_cache::global().invalidate();
+                if let Some(imp) = imp_weak.upgrade() {
+                    imp.reload_config_and_ui();
+                }
+            });
+            header.pack_end(&refresh_button);
+
+            // Create main vertical box
+            let vbox = gtk4::Box::new(gtk4::Orientation::Vertical, 0);
+            vbox.append(&header);
+
+            // Create scrolled window for content
+            let scrolled = gtk4::ScrolledWindow::new();
+            scrolled.set_vexpand(true);
+
+            // Placeholder content until the config task finishes
+            let skeleton = build_skeleton_content();
+            scrolled.set_child(Some(&skeleton));
+
+            *self.scrolled.borrow_mut() = Some(scrolled.clone());
+
+            // Shown over the content while an operation waits for a touch
+            let touch_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 12);
+            touch_box.add_css_class("osd");
+            touch_box.set_margin_top(12);
+            touch_box.set_margin_bottom(12);
+            touch_box.set_margin_start(12);
+            touch_box.set_margin_end(12);
+            touch_box.append(&gtk4::Image::from_icon_name("dialog-password-symbolic"));
+            let touch_label = gtk4::Label::new(Some("Touch your key"));
+            touch_label.add_css_class("heading");
+            touch_box.append(&touch_label);
+            let touch_prompt = gtk4::Revealer::new();
+            touch_prompt.set_transition_type(gtk4::RevealerTransitionType::Crossfade);
+            touch_prompt.set_child(Some(&touch_box));
+            touch_prompt.set_halign(gtk4::Align::Center);
+            touch_prompt.set_valign(gtk4::Align::End);
+            touch_prompt.set_margin_bottom(24);
+            touch_prompt.set_can_target(false);
+            *self.touch_prompt.borrow_mut() = Some(touch_prompt.clone());
+
+            let overlay = gtk4::Overlay::new();
+            overlay.set_child(Some(&scrolled));
+            overlay.add_overlay(&touch_prompt);
+
+            vbox.append(&overlay);
+            window.set_content(Some(&vbox));
+        }
+
+        fn build_main_content(&self) -> gtk4::Box {
+            // Create main content box
+            let main_box = gtk4::Box::new(gtk4::Orientation::Vertical, 12);
+            main_box.set_margin_top(24);
+            main_box.set_margin_bottom(24);
+            main_box.set_margin_start(24);
+            main_box.set_margin_end(24);
+
+            let config = self.config.borrow();
+            if let Some(config) = config.as_ref() {
+                let profiles = config.profiles();
+
+                // Status label for feedback
+                let status_label = gtk4::Label::new(None);
+                status_label.set_wrap(true);
+                status_label.set_xalign(0.0);
+                status_label.add_css_class("dim-label");
+                status_label.set_visible(false);
+
+                // Create profile selector group
+                let profile_group = adw::PreferencesGroup::new();
+                profile_group.set_title("Git Identity");
+                profile_group.set_description(Some("Select your active git identity profile"));
+
+                // Create combo row for profile selection
+                let profile_row = adw::ComboRow::new();
+                profile_row.set_title("Active Profile");
+
+                let profile_names: Vec<String> =
+                    profiles.iter().map(|p| p.display_name()).collect();
+                let profile_names_strs: Vec<&str> =
+                    profile_names.iter().map(|s| s.as_str()).collect();
+                let profile_list = gtk4::StringList::new(&profile_names_strs);
+                profile_row.set_model(Some(&profile_list));
+
+                // Set current selection based on current identity's profile
+                if let Some(current_profile) = config.current_profile() {
+                    if let Some(pos) = profiles.iter().position(|p| p.name == current_profile.name)
+                    {
+                        profile_row.set_selected(pos as u32);
+                    }
+                }
+
+                // Wire profile ComboRow handler (2a)
+                {
+                    let profiles_for_handler = profiles.clone();
+                    let status_clone = status_label.clone();
+                    let imp_weak = self.downgrade();
+                    profile_row.connect_selected_notify(move |row| {
+                        let selected = row.selected() as usize;
+                        if selected >= profiles_for_handler.len() {
+                            return;
+                        }
+                        let profile = &profiles_for_handler[selected];
+                        // Use default variant (prefer FIDO2)
+                        let identity_name = profile
+                            .default_variant()
+                            .map(|v| v.identity_name.clone())
+                            .unwrap_or_else(|| profile.name.clone());
+
+                        let status = status_clone.clone();
+                        let name = identity_name.clone();
+                        let imp = imp_weak.clone();
+                        status.set_text(&format!("Switching to {}...", &name));
+                        status.set_visible(true);
+                        status.remove_css_class("error");
+                        status.remove_css_class("success");
+
+                        let span = tracing::info_span!("switch", identity = %name);
+                        glib::spawn_future_local(
+                            async move {
+                                let Some(warning) = yubikey_guard_async(&name, &status).await
+                                else {
+                                    if let Some(imp) = imp.upgrade() {
+                                        imp.load_config();
+                                    }
+                                    return;
+                                };
+                                let warning =
+                                    join_warnings(warning, switch_warnings_async(&name).await);
+                                let result = run_cli_async("switch", &name).await;
+                                match result {
+                                    Ok(msg) => {
+                                        switched_text(
+                                            &status,
+                                            &format!("Switched to {}", &name),
+                                            warning,
+                                        );
+                                        tracing::info!("Switched identity: {} - {}", &name, msg);
+                                        if let Some(imp) = imp.upgrade() {
+                                            imp.switch_agent(name.clone(), status.clone());
+                                        }
+                                    }
+                                    Err(e) => {
+                                        status.set_text(&error_text("Failed", &e));
+                                        status.add_css_class("error");
+                                        tracing::error!("Switch failed: {}", e);
+                                    }
+                                }
+                                // Reload config after switch
+                                if let Some(imp) = imp.upgrade() {
+                                    imp.load_config();
+                                }
+                            }
+                            .instrument(span),
+                        );
+                    });
+                }
+
+                profile_group.add(&profile_row);
+
+                // Add SSH key variant selector if current profile has multiple variants
+                let current_profile = config.current_profile();
+                let current_variant = config.current_variant();
+
+                if let Some(ref profile) = current_profile {
+                    if profile.has_multiple_variants() {
+                        let variant_row = adw::ComboRow::new();
+                        variant_row.set_title("SSH Key Type");
+                        variant_row
+                            .set_subtitle("Choose between regular SSH or hardware security key");
+
+                        let variant_names: Vec<&str> = profile
+                            .variants
+                            .iter()
+                            .map(|v| v.key_type.display_name())
+                            .collect();
+                        let variant_list = gtk4::StringList::new(&variant_names);
+                        variant_row.set_model(Some(&variant_list));
+
+                        // Set current variant selection
+                        if let Some(ref current_var) = current_variant {
+                            if let Some(pos) = profile
+                                .variants
+                                .iter()
+                                .position(|v| v.identity_name == current_var.identity_name)
+                            {
+                                variant_row.set_selected(pos as u32);
+                            }
+                        }
+
+                        // Wire variant ComboRow handler (2b)
+                        {
+                            let variants_for_handler: Vec<String> = profile
+                                .variants
+                                .iter()
+                                .map(|v| v.identity_name.clone())
+                                .collect();
+                            let status_clone = status_label.clone();
+                            let imp_weak = self.downgrade();
+                            variant_row.connect_selected_notify(move |row| {
+                                let selected = row.selected() as usize;
+                                if selected >= variants_for_handler.len() {
+                                    return;
+                                }
+                                let identity_name = &variants_for_handler[selected];
+                                let status = status_clone.clone();
+                                let name = identity_name.clone();
+                                let imp = imp_weak.clone();
+                                status.set_text(&format!("Switching to variant {}...", &name));
+                                status.set_visible(true);
+                                status.remove_css_class("error");
+                                status.remove_css_class("success");
+
+                                let span = tracing::info_span!("switch", identity = %name);
+                                glib::spawn_future_local(
+                                    async move {
+                                        let Some(warning) =
+                                            yubikey_guard_async(&name, &status).await
+                                        else {
+                                            if let Some(imp) = imp.upgrade() {
+                                                imp.load_config();
+                                            }
+                                            return;
+                                        };
+                                        let warning = join_warnings(
+                                            warning,
+                                            switch_warnings_async(&name).await,
+                                        );
+                                        let result = run_cli_async("switch", &name).await;
+                                        match result {
+                                            Ok(_) => {
+                                                switched_text(
+                                                    &status,
+                                                    &format!("Switched to variant {}", &name),
+                                                    warning,
+                                                );
+                                                if let Some(imp) = imp.upgrade() {
+                                                    imp.switch_agent(name.clone(), status.clone());
+                                                }
+                                            }
+                                            Err(e) => {
+                                                status.set_text(&error_text("Failed", &e));
+                                                status.add_css_class("error");
+                                            }
+                                        }
+                                        if let Some(imp) = imp.upgrade() {
+                                            imp.load_config();
+                                        }
+                                    }
+                                    .instrument(span),
+                                );
+                            });
+                        }
+
+                        profile_group.add(&variant_row);
+                    }
+                }
+
+                main_box.append(&profile_group);
+
+                // Status feedback label
+                main_box.append(&status_label);
+
+                // Add current profile details if available
+                if let Some(ref profile) = current_profile {
+                    let details_group = adw::PreferencesGroup::new();
+                    details_group.set_title("Current Profile Details");
+
+                    // Provider row
+                    let provider_row = adw::ActionRow::new();
+                    provider_row.set_title("Provider");
+                    provider_row.set_subtitle(&profile.provider);
+                    details_group.add(&provider_row);
+
+                    // User row
+                    let user_row = adw::ActionRow::new();
+                    user_row.set_title("Username");
+                    user_row.set_subtitle(&profile.user);
+                    details_group.add(&user_row);
+
+                    // Email row
+                    let email_row = adw::ActionRow::new();
+                    email_row.set_title("Email");
+                    email_row.set_subtitle(&profile.email);
+                    details_group.add(&email_row);
+
+                    // SSH Key variant info
+                    if let Some(ref variant) = current_variant {
+                        let ssh_row = adw::ActionRow::new();
+                        ssh_row.set_title("SSH Key");
+                        let ssh_info = if let Some(library) = variant.identity.pkcs11_library() {
+                            format!("{} ({})", variant.key_type.display_name(), library)
+                        } else if variant.identity.ssh_key_path.is_empty() {
+                            format!("{} (default)", variant.key_type.display_name())
+                        } else {
+                            format!(
+                                "{} ({})",
+                                variant.key_type.display_name(),
+                                variant
+                                    .identity
+                                    .ssh_key_path
+                                    .rsplit('/')
+                                    .next()
+                                    .unwrap_or(&variant.identity.ssh_key_path)
+                            )
+                        };
+                        ssh_row.set_subtitle(&ssh_info);
+
+                        // Add badge for security key or smart card
+                        if variant.key_type != SshKeyType::Regular {
+                            let badge = gtk4::Label::new(Some("HW"));
+                            badge.add_css_class("heading");
+                            badge.add_css_class("accent");
+                            ssh_row.add_suffix(&badge);
+                        }
+
+                        details_group.add(&ssh_row);
+                    }
+
+                    // Commit signing, editable once the identity is known
+                    if let Some(identity) = config.current_identity() {
+                        let signing_expander = self.build_signing_rows(
+                            &config.state.current_identity,
+                            identity,
+                            &status_label,
+                        );
+                        details_group.add(&signing_expander);
+                        if identity.gpg.format == SigningFormat::Gpg
+                            && !identity.gpg.key_id.is_empty()
+                        {
+                            details_group.add(&self.build_keytocard_rows(
+                                &config.state.current_identity,
+                                identity,
+                                &status_label,
+                            ));
+                        }
+                        details_group.add(&self.build_yubikey_row(
+                            &config.state.current_identity,
+                            config,
+                            &status_label,
+                        ));
+                        details_group.add(&self.build_piv_rows(
+                            &config.state.current_identity,
+                            identity,
+                            &status_label,
+                        ));
+                        details_group.add(&self.build_tailnet_row(
+                            &config.state.current_identity,
+                            identity,
+                            &status_label,
+                        ));
+                    } else {
+                        let gpg_row = adw::ActionRow::new();
+                        gpg_row.set_title("Commit Signing");
+                        if profile.has_gpg_signing() {
+                            gpg_row.set_subtitle(&format!("Enabled ({})", &profile.gpg.key_id));
+                        } else {
+                            gpg_row.set_subtitle("Disabled");
+                        }
+                        details_group.add(&gpg_row);
+                    }
+
+                    // SSH certificate, when the key is CA-signed or can be
+                    if let Some(identity) = config.current_identity() {
+                        if ssh_cert::cert_path(identity).is_some() {
+                            let cert_expander = self.build_certificate_rows(
+                                &config.state.current_identity,
+                                identity,
+                                &status_label,
+                            );
+                            details_group.add(&cert_expander);
+                        }
+                        if let Some(cli_row) = self.build_cli_auth_row(
+                            &config.state.current_identity,
+                            identity,
+                            &status_label,
+                        ) {
+                            details_group.add(&cli_row);
+                        }
+                        if let Some(registration_expander) = self.build_registration_rows(
+                            &config.state.current_identity,
+                            identity,
+                            &status_label,
+                        ) {
+                            details_group.add(&registration_expander);
+                        }
+                        if let Some(token_expander) = self.build_token_rows(
+                            &config.state.current_identity,
+                            identity,
+                            &status_label,
+                        ) {
+                            details_group.add(&token_expander);
+                        }
+                        let verify_expander =
+                            self.build_verify_rows(&config.state.current_identity, &status_label);
+                        details_group.add(&verify_expander);
+                        let policy_expander = self.build_policy_rows(
+                            &config.state.current_identity,
+                            identity,
+                            &status_label,
+                        );
+                        details_group.add(&policy_expander);
+                        if !identity.ssh_key_path.is_empty() {
+                            let passphrase_expander =
+                                self.build_passphrase_rows(identity, &status_label);
+                            details_group.add(&passphrase_expander);
+                        }
+                        if identity.key_type() == SshKeyType::Regular
+                            && !identity.ssh_key_path.is_empty()
+                        {
+                            let rekey_expander = self
+                                .build_rekey_rows(&config.state.current_identity, &status_label);
+                            details_group.add(&rekey_expander);
+                        }
+                    }
+
+                    // Available variants summary
+                    let variants_row = adw::ActionRow::new();
+                    variants_row.set_title("Available Key Types");
+                    let variant_summary: Vec<&str> = profile
+                        .variants
+                        .iter()
+                        .map(|v| v.key_type.short_name())
+                        .collect();
+                    variants_row.set_subtitle(&variant_summary.join(", "));
+                    details_group.add(&variants_row);
+
+                    main_box.append(&details_group);
+                }
+
+                // ============================================================
+                // Security Keys Group
+                // ============================================================
+                let sk_group = adw::PreferencesGroup::new();
+                sk_group.set_title("Security Keys");
+                sk_group.set_description(Some(
+                    "Resident SSH keys stored on a FIDO2 token (ssh-keygen -K)",
+                ));
+
+                let sk_load_row = adw::ActionRow::new();
+                sk_load_row.set_title("Load Keys From Security Key");
+                sk_load_row.set_subtitle("Insert the key; its FIDO2 PIN is required");
+                let sk_pin = gtk4::PasswordEntry::new();
+                sk_pin.set_placeholder_text(Some("PIN"));
+                sk_pin.set_show_peek_icon(true);
+                sk_pin.set_valign(gtk4::Align::Center);
+                let sk_load_button = gtk4::Button::with_label("Load");
+                sk_load_button.set_valign(gtk4::Align::Center);
+                sk_load_row.add_suffix(&sk_pin);
+                sk_load_row.add_suffix(&sk_load_button);
+                sk_group.add(&sk_load_row);
+                main_box.append(&sk_group);
+
+                let sk_staging: Rc<RefCell<Option<fido2::Staging>>> = Rc::default();
+                let sk_rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::default();
+
+                // Wire load button: download the handles, then offer each key
+                // as its own file or as an identity's -sk variant
+                {
+                    let pin = sk_pin.clone();
+                    let group = sk_group.clone();
+                    let staging = sk_staging.clone();
+                    let rows = sk_rows.clone();
+                    let config = config.clone();
+                    let status = status_label.clone();
+                    let imp_weak = self.downgrade();
+                    let load = Rc::new(move |button: &gtk4::Button| {
+                        let secret = pin.text().to_string();
+                        if secret.is_empty() {
+                            show_status(&status, "Enter the security key's PIN", Some("error"));
+                            return;
+                        }
+                        pin.set_text("");
+                        button.set_sensitive(false);
+                        show_status(&status, "Reading resident keys...", None);
+                        let btn = button.clone();
+                        let group = group.clone();
+                        let staging = staging.clone();
+                        let rows = rows.clone();
+                        let config = config.clone();
+                        let status = status.clone();
+                        let imp_weak = imp_weak.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || fido2::download(&secret))
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                            for row in rows.borrow_mut().drain(..) {
+                                group.remove(&row);
+                            }
+                            btn.set_sensitive(true);
+                            let (downloaded, keys) = match result {
+                                Ok(found) => found,
+                                Err(e) => {
+                                    show_status(
+                                        &status,
+                                        &error_text("Loading keys failed", &e),
+                                        Some("error"),
+                                    );
+                                    return;
+                                }
+                            };
+                            *staging.borrow_mut() = Some(downloaded);
+                            show_status(
+                                &status,
+                                &match keys.len() {
+                                    0 => "No resident SSH keys on this security key".to_string(),
+                                    1 => "Found 1 resident key".to_string(),
+                                    n => format!("Found {} resident keys", n),
+                                },
+                                None,
+                            );
+                            for key in keys {
+                                let row = security_key_row(
+                                    &key,
+                                    fido2::candidate_identities(&config, &key),
+                                    &staging,
+                                    &status,
+                                    &imp_weak,
+                                );
+                                group.add(&row);
+                                rows.borrow_mut().push(row);
+                            }
+                        });
+                    });
+                    let on_activate = load.clone();
+                    let button = sk_load_button.clone();
+                    sk_pin.connect_activate(move |_| on_activate(&button));
+                    sk_load_button.connect_clicked(move |button| load(button));
+                }
+
+                // ============================================================
+                // SSH Agent Group
+                // ============================================================
+                let agent_group = adw::PreferencesGroup::new();
+                agent_group.set_title("SSH Agent");
+                agent_group.set_description(Some(
+                    "Keys loaded in ssh-agent; switching identity loads its key",
+                ));
+
+                let agent_add_row = adw::ActionRow::new();
+                agent_add_row.set_title("Add Key on Switch");
+                agent_add_row.set_subtitle(
+                    "Maximum Security confirms each use and expires after 1 hour; \
+                     Developer Workflow expires after 8 hours",
+                );
+                let agent_add_switch = gtk4::Switch::new();
+                agent_add_switch.set_valign(gtk4::Align::Center);
+                agent_add_switch.set_active(config.settings.ssh_agent.add_on_switch);
+                agent_add_row.add_suffix(&agent_add_switch);
+                agent_group.add(&agent_add_row);
+
+                let agent_others_row = adw::ActionRow::new();
+                agent_others_row.set_title("Remove Other Identities' Keys");
+                agent_others_row.set_subtitle("So ssh can't offer another identity's key first");
+                let agent_others_switch = gtk4::Switch::new();
+                agent_others_switch.set_valign(gtk4::Align::Center);
+                agent_others_switch.set_active(config.settings.ssh_agent.remove_others);
+                agent_others_row.add_suffix(&agent_others_switch);
+                agent_group.add(&agent_others_row);
+
+                let agent_clear_row = adw::ActionRow::new();
+                agent_clear_row.set_title("Remove All Keys");
+                agent_clear_row.set_subtitle("Empty the agent, including keys added elsewhere");
+                let agent_clear_button = gtk4::Button::with_label("Remove All");
+                agent_clear_button.set_valign(gtk4::Align::Center);
+                agent_clear_button.add_css_class("destructive-action");
+                agent_clear_row.add_suffix(&agent_clear_button);
+                agent_group.add(&agent_clear_row);
+                main_box.append(&agent_group);
+
+                *self.agent_group.borrow_mut() = Some(agent_group);
+                self.agent_rows.borrow_mut().clear();
+                self.refresh_agent();
+
+                // Wire setting switches: write the change to config.json
+                let agent_settings: [(&gtk4::Switch, AgentSetting); 2] = [
+                    (&agent_add_switch, |s, on| s.add_on_switch = on),
+                    (&agent_others_switch, |s, on| s.remove_others = on),
+                ];
+                for (switch, set) in agent_settings {
+                    let status = status_label.clone();
+                    let imp_weak = self.downgrade();
+                    switch.connect_active_notify(move |switch| {
+                        let on = switch.is_active();
+                        let status = status.clone();
+                        let imp_weak = imp_weak.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || {
+                                Config::update(|config| set(&mut config.settings.ssh_agent, on))?;
+                                Ok::<_, Error>(())
+                            })
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                            if let Err(e) = result {
+                                show_status(
+                                    &status,
+                                    &error_text("Saving setting failed", &e),
+                                    Some("error"),
+                                );
+                            }
+                            if let Some(imp) = imp_weak.upgrade() {
+                                imp.load_config();
+                            }
+                        });
+                    });
+                }
+
+                // Wire remove all button
+                {
+                    let status = status_label.clone();
+                    let imp_weak = self.downgrade();
+                    agent_clear_button.connect_clicked(move |button| {
+                        button.set_sensitive(false);
+                        let btn = button.clone();
+                        let status = status.clone();
+                        let imp_weak = imp_weak.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(agent::remove_all)
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                            btn.set_sensitive(true);
+                            match result {
+                                Ok(()) => show_status(
+                                    &status,
+                                    "Removed all keys from ssh-agent",
+                                    Some("success"),
+                                ),
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Removing keys failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                            if let Some(imp) = imp_weak.upgrade() {
+                                imp.refresh_agent();
+                            }
+                        });
+                    });
+                }
+
+                // ============================================================
+                // SSH Config Group
+                // ============================================================
+                let ssh_config_group = adw::PreferencesGroup::new();
+                ssh_config_group.set_title("SSH Config");
+
+                let ssh_config_expander = adw::ExpanderRow::new();
+                ssh_config_expander.set_title("Managed Host Blocks");
+                ssh_config_expander.set_subtitle(
+                    "HostName, User, IdentityFile and IdentitiesOnly for each identity \
+                     in ~/.ssh/config",
+                );
+                ssh_config_group.add(&ssh_config_expander);
+
+                let ssh_config_action_row = adw::ActionRow::new();
+                ssh_config_action_row.set_title("Changes");
+                ssh_config_action_row.set_subtitle("Review the diff before writing");
+                let ssh_config_review_button = gtk4::Button::with_label("Review");
+                ssh_config_review_button.set_valign(gtk4::Align::Center);
+                let ssh_config_write_button = gtk4::Button::with_label("Write");
+                ssh_config_write_button.set_valign(gtk4::Align::Center);
+                ssh_config_write_button.add_css_class("suggested-action");
+                ssh_config_write_button.set_sensitive(false);
+                ssh_config_action_row.add_suffix(&ssh_config_review_button);
+                ssh_config_action_row.add_suffix(&ssh_config_write_button);
+                ssh_config_expander.add_row(&ssh_config_action_row);
+
+                let ssh_config_diff = gtk4::Label::new(None);
+                ssh_config_diff.add_css_class("monospace");
+                ssh_config_diff.set_xalign(0.0);
+                ssh_config_diff.set_selectable(true);
+                ssh_config_diff.set_margin_start(12);
+                ssh_config_diff.set_margin_end(12);
+                ssh_config_diff.set_margin_top(6);
+                ssh_config_diff.set_margin_bottom(6);
+                ssh_config_diff.set_visible(false);
+                ssh_config_expander.add_row(&ssh_config_diff);
+                main_box.append(&ssh_config_group);
+
+                let ssh_config_plan: Rc<RefCell<Option<ssh_config::Plan>>> = Rc::default();
+
+                // Wire review button: show what writing the block would change
+                {
+                    let plan = ssh_config_plan.clone();
+                    let row = ssh_config_action_row.clone();
+                    let diff = ssh_config_diff.clone();
+                    let write_button = ssh_config_write_button.clone();
+                    let config = config.clone();
+                    let status = status_label.clone();
+                    ssh_config_review_button.connect_clicked(move |button| {
+                        button.set_sensitive(false);
+                        write_button.set_sensitive(false);
+                        let btn = button.clone();
+                        let plan = plan.clone();
+                        let row = row.clone();
+                        let diff = diff.clone();
+                        let write_button = write_button.clone();
+                        let config = config.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || ssh_config::plan(&config).map_err(Error::from))
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                            btn.set_sensitive(true);
+                            let reviewed = match result {
+                                Ok(reviewed) => reviewed,
+                                Err(e) => {
+                                    show_status(
+                                        &status,
+                                        &error_text("Reading ssh config failed", &e),
+                                        Some("error"),
+                                    );
+                                    return;
+                                }
+                            };
+                            row.set_subtitle(reviewed.state.display_text());
+                            if reviewed.is_noop() {
+                                diff.set_visible(false);
+                                show_status(&status, "~/.ssh/config is up to date", None);
+                            } else {
+                                diff.set_text(&reviewed.diff());
+                                diff.set_visible(true);
+                                let edited = reviewed.state == BlockState::Edited;
+                                write_button.set_label(if edited { "Overwrite" } else { "Write" });
+                                if edited {
+                                    write_button.remove_css_class("suggested-action");
+                                    write_button.add_css_class("destructive-action");
+                                } else {
+                                    write_button.remove_css_class("destructive-action");
+                                    write_button.add_css_class("suggested-action");
+                                }
+                                write_button.set_sensitive(true);
+                            }
+                            if !reviewed.shadowed.is_empty() {
+                                show_status(
+                                    &status,
+                                    &format!(
+                                        "Defined earlier in ~/.ssh/config, which takes precedence: {}",
+                                        reviewed.shadowed.join(", ")
+                                    ),
+                                    Some("error"),
+                                );
+                            }
+                            *plan.borrow_mut() = Some(reviewed);
+                        });
+                    });
+                }
+
+                // Wire write button: write the reviewed plan
+                {
+                    let plan = ssh_config_plan.clone();
+                    let row = ssh_config_action_row.clone();
+                    let diff = ssh_config_diff.clone();
+                    let status = status_label.clone();
+                    ssh_config_write_button.connect_clicked(move |button| {
+                        let Some(reviewed) = plan.borrow_mut().take() else {
+                            return;
+                        };
+                        button.set_sensitive(false);
+                        let row = row.clone();
+                        let diff = diff.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || ssh_config::write(&reviewed).map_err(Error::from))
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                            diff.set_visible(false);
+                            match result {
+                                Ok(()) => {
+                                    row.set_subtitle(BlockState::Intact.display_text());
+                                    show_status(
+                                        &status,
+                                        "Wrote managed hosts to ~/.ssh/config (previous copy in config.bak)",
+                                        Some("success"),
+                                    );
+                                }
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Writing ssh config failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                        });
+                    });
+                }
+
+                main_box.append(&self.build_git_includes_group(config, &status_label));
+                main_box.append(&self.build_url_rewrites_group(config, &status_label));
+                main_box.append(&self.build_commit_guard_group(config, &status_label));
+                main_box.append(&self.build_workspace_group(config, &status_label));
+                main_box.append(&self.build_policies_group(config, &status_label));
+                main_box.append(&self.build_branch_rules_group(config, &status_label));
+                main_box.append(&self.build_switch_guard_group(config, &status_label));
+                main_box.append(&self.build_bookmarks_group(config, &status_label));
+                main_box.append(&self.build_provider_hosts_group(config, &status_label));
+                main_box.append(&self.build_ci_secrets_group(config, &status_label));
+                main_box.append(&self.build_rest_api_group(config, &status_label));
+                main_box.append(&self.build_metrics_group(config, &status_label));
+                main_box.append(&self.build_telemetry_group(config, &status_label));
+                main_box.append(&self.build_event_hooks_group(config, &status_label));
+                if cfg!(target_os = "linux") {
+                    main_box.append(&self.build_secret_service_group(config, &status_label));
+                }
+                if !cfg!(windows) {
+                    main_box.append(&self.build_askpass_group(config, &status_label));
+                }
+                if systemd::is_available() {
+                    main_box.append(&self.build_background_group(&status_label));
+                }
+                main_box.append(&self.build_remote_urls_group(config, &status_label));
+
+                // ============================================================
+                // Host Keys Group
+                // ============================================================
+                let host_keys_group = adw::PreferencesGroup::new();
+                host_keys_group.set_title("Host Keys");
+                host_keys_group.set_description(Some(
+                    "known_hosts entries for your identities' hosts; GitHub, GitLab, \
+                     Bitbucket and Codeberg keys are checked against their published \
+                     fingerprints",
+                ));
+
+                let host_keys_row = adw::ActionRow::new();
+                host_keys_row.set_title("Check Host Keys");
+                let host_names = known_hosts::hosts(config);
+                host_keys_row.set_subtitle(&if host_names.is_empty() {
+                    "No identity has a host name".to_string()
+                } else {
+                    glib::markup_escape_text(&host_names.join(", ")).to_string()
+                });
+                let host_keys_button = gtk4::Button::with_label("Check");
+                host_keys_button.set_valign(gtk4::Align::Center);
+                host_keys_button.set_sensitive(!host_names.is_empty());
+                host_keys_row.add_suffix(&host_keys_button);
+                host_keys_group.add(&host_keys_row);
+                main_box.append(&host_keys_group);
+
+                // Wire check button: scan each host, add pinned keys, and ask
+                // about the rest
+                {
+                    let group = host_keys_group.clone();
+                    let rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::default();
+                    let status = status_label.clone();
+                    host_keys_button.connect_clicked(move |button| {
+                        button.set_sensitive(false);
+                        show_status(&status, "Fetching host keys...", None);
+                        let btn = button.clone();
+                        let hosts = host_names.clone();
+                        let group = group.clone();
+                        let rows = rows.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || known_hosts::check(&hosts))
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                            btn.set_sensitive(true);
+                            for row in rows.borrow_mut().drain(..) {
+                                group.remove(&row);
+                            }
+                            let reports = match result {
+                                Ok(reports) => reports,
+                                Err(e) => {
+                                    show_status(
+                                        &status,
+                                        &error_text("Checking host keys failed", &e),
+                                        Some("error"),
+                                    );
+                                    return;
+                                }
+                            };
+                            let mut attention = 0;
+                            let mut pinned = 0;
+                            for report in reports {
+                                let keys = match report.keys {
+                                    Ok(keys) => keys,
+                                    Err(e) => {
+                                        let row = adw::ActionRow::new();
+                                        row.set_title(&glib::markup_escape_text(&report.host));
+                                        row.set_subtitle(&glib::markup_escape_text(&error_text(
+                                            "Unreachable",
+                                            &e,
+                                        )));
+                                        group.add(&row);
+                                        rows.borrow_mut().push(row);
+                                        continue;
+                                    }
+                                };
+                                for assessed in keys {
+                                    match assessed.status {
+                                        HostKeyStatus::Pinned { .. } => pinned += 1,
+                                        ref s if s.needs_attention() => attention += 1,
+                                        _ => {}
+                                    }
+                                    let row = host_key_row(assessed.key, assessed.status, &status);
+                                    group.add(&row);
+                                    rows.borrow_mut().push(row);
+                                }
+                            }
+                            let mut text = match attention {
+                                0 => "Host keys are trusted".to_string(),
+                                1 => "1 host key needs checking".to_string(),
+                                n => format!("{} host keys need checking", n),
+                            };
+                            if pinned > 0 {
+                                text.push_str(&format!(
+                                    "; added {} published key(s) to known_hosts",
+                                    pinned
+                                ));
+                            }
+                            show_status(
+                                &status,
+                                &text,
+                                Some(if attention > 0 { "error" } else { "success" }),
+                            );
+                        });
+                    });
+                }
+
+                // ============================================================
+                // Public Keys Group
+                // ============================================================
+                let public_keys_group = adw::PreferencesGroup::new();
+                public_keys_group.set_title("Public Keys");
+                public_keys_group.set_description(Some(
+                    "Whether each identity's SSH key is registered with its provider \
+                     account (GitHub, GitLab, Bitbucket, Gitea or sourcehut), using the \
+                     identity's API token",
+                ));
+                let mut uploadable: Vec<_> = config
+                    .identities
+                    .iter()
+                    .filter(|(_, identity)| {
+                        pubkeys::Provider::for_identity(identity).is_some()
+                            && !identity.ssh_key_path.is_empty()
+                    })
+                    .collect();
+                uploadable.sort_by(|a, b| a.0.cmp(b.0));
+                if uploadable.is_empty() {
+                    let row = adw::ActionRow::new();
+                    row.set_title("No identities with an SSH key on a provider with a key API");
+                    public_keys_group.add(&row);
+                } else {
+                    let check_all_row = adw::ActionRow::new();
+                    check_all_row.set_title("Check All Identities");
+                    let check_all = gtk4::Button::with_label("Check All");
+                    check_all.set_valign(gtk4::Align::Center);
+                    check_all_row.add_suffix(&check_all);
+                    public_keys_group.add(&check_all_row);
+                    let mut checks = Vec::new();
+                    for (name, identity) in uploadable {
+                        let (row, check) = public_key_row(name, identity, &status_label);
+                        public_keys_group.add(&row);
+                        checks.push(check);
+                    }
+                    check_all.connect_clicked(move |_| {
+                        for check in &checks {
+                            if check.is_sensitive() {
+                                check.emit_clicked();
+                            }
+                        }
+                    });
+                }
+                main_box.append(&public_keys_group);
+
+                // ============================================================
+                // GPG Public Keys Group
+                // ============================================================
+                let mut gpg_signers: Vec<_> = config
+                    .identities
+                    .iter()
+                    .filter(|(_, identity)| {
+                        identity.gpg.format == SigningFormat::Gpg && !identity.gpg.key_id.is_empty()
+                    })
+                    .collect();
+                gpg_signers.sort_by(|a, b| a.0.cmp(b.0));
+                if !gpg_signers.is_empty() {
+                    let gpg_keys_group = adw::PreferencesGroup::new();
+                    gpg_keys_group.set_title("GPG Public Keys");
+                    gpg_keys_group.set_description(Some(
+                        "Each identity's GPG key on keys.openpgp.org and its GitHub or GitLab \
+                         account, so signed commits show as Verified",
+                    ));
+                    for (name, identity) in gpg_signers {
+                        gpg_keys_group.add(&gpg_publish_row(name, identity, &status_label));
+                    }
+                    main_box.append(&gpg_keys_group);
+                }
+
+                // ============================================================
+                // SSH Hygiene Group
+                // ============================================================
+                let hygiene_group = adw::PreferencesGroup::new();
+                hygiene_group.set_title("SSH Hygiene");
+                hygiene_group.set_description(Some(
+                    "Private keys in ~/.ssh that no identity uses, and identities whose key \
+                     file is missing",
+                ));
+                let hygiene_row = adw::ActionRow::new();
+                hygiene_row.set_title("Scan ~/.ssh");
+                let hygiene_button = gtk4::Button::with_label("Scan");
+                hygiene_button.set_valign(gtk4::Align::Center);
+                hygiene_row.add_suffix(&hygiene_button);
+                hygiene_group.add(&hygiene_row);
+                main_box.append(&hygiene_group);
+
+                // Wire scan button: list orphans with their actions
+                {
+                    let group = hygiene_group.clone();
+                    let summary_row = hygiene_row.clone();
+                    let rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::default();
+                    let mut identities: Vec<String> = config.identities.keys().cloned().collect();
+                    identities.sort();
+                    let status = status_label.clone();
+                    let imp_weak = self.downgrade();
+                    hygiene_button.connect_clicked(move |button| {
+                        let Some(config) = imp_weak
+                            .upgrade()
+                            .and_then(|imp| imp.config.borrow().clone())
+                        else {
+                            return;
+                        };
+                        button.set_sensitive(false);
+                        let btn = button.clone();
+                        let group = group.clone();
+                        let summary_row = summary_row.clone();
+                        let rows = rows.clone();
+                        let identities = identities.clone();
+                        let status = status.clone();
+                        let imp_weak = imp_weak.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || hygiene::scan(&config))
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                            btn.set_sensitive(true);
+                            for row in rows.borrow_mut().drain(..) {
+                                group.remove(&row);
+                            }
+                            let report = match result {
+                                Ok(report) => report,
+                                Err(e) => {
+                                    show_status(
+                                        &status,
+                                        &error_text("Scanning ~/.ssh failed", &e),
+                                        Some("error"),
+                                    );
+                                    return;
+                                }
+                            };
+                            summary_row.set_subtitle(&report.summary());
+                            for missing in &report.missing {
+                                let row = adw::ActionRow::new();
+                                row.set_title(&glib::markup_escape_text(&missing.identity));
+                                row.set_subtitle(&glib::markup_escape_text(&format!(
+                                    "Key file {} is missing; attach a key below or fix sshKeyPath",
+                                    missing.path.display()
+                                )));
+                                let badge = gtk4::Label::new(Some("Missing key"));
+                                badge.add_css_class("error");
+                                row.add_suffix(&badge);
+                                group.add(&row);
+                                rows.borrow_mut().push(row);
+                            }
+                            for key in report.orphans {
+                                let row = orphan_key_row(key, &identities, &status, &imp_weak);
+                                group.add(&row);
+                                rows.borrow_mut().push(row);
+                            }
+                        });
+                    });
+                }
+
+                // ============================================================
+                // Key Audit Group
+                // ============================================================
+                let audit_group = adw::PreferencesGroup::new();
+                audit_group.set_title("Key Audit");
+                audit_group.set_description(Some(&format!(
+                    "Flags DSA, RSA under {} bits, keys without a passphrase, and keys older \
+                     than {} days (settings.keyAudit.maxAgeDays)",
+                    key_audit::MIN_RSA_BITS,
+                    config.settings.key_audit.max_age_days
+                )));
+                let audit_row = adw::ActionRow::new();
+                audit_row.set_title("Audit SSH Keys");
+                audit_row.set_subtitle("Scores each identity's key out of 100");
+                let audit_button = gtk4::Button::with_label("Audit");
+                audit_button.set_valign(gtk4::Align::Center);
+                audit_row.add_suffix(&audit_button);
+                audit_group.add(&audit_row);
+                main_box.append(&audit_group);
+
+                // Wire audit button: one expander per identity, worst first
+                {
+                    let group = audit_group.clone();
+                    let summary_row = audit_row.clone();
+                    let rows: Rc<RefCell<Vec<adw::ExpanderRow>>> = Rc::default();
+                    let imp_weak = self.downgrade();
+                    audit_button.connect_clicked(move |button| {
+                        let Some(config) = imp_weak
+                            .upgrade()
+                            .and_then(|imp| imp.config.borrow().clone())
+                        else {
+                            return;
+                        };
+                        button.set_sensitive(false);
+                        let btn = button.clone();
+                        let group = group.clone();
+                        let summary_row = summary_row.clone();
+                        let rows = rows.clone();
+                        glib::spawn_future_local(async move {
+                            let Ok(audit) =
+                                gio::spawn_blocking(move || key_audit::audit(&config)).await
+                            else {
+                                btn.set_sensitive(true);
+                                return;
+                            };
+                            btn.set_sensitive(true);
+                            for row in rows.borrow_mut().drain(..) {
+                                group.remove(&row);
+                            }
+                            summary_row.set_subtitle(&match audit.finding_count() {
+                                0 => format!("Score {}; no findings", audit.score()),
+                                1 => format!("Score {}; 1 finding", audit.score()),
+                                n => format!("Score {}; {} findings", audit.score(), n),
+                            });
+                            for identity in &audit.identities {
+                                let row = key_audit_row(identity);
+                                group.add(&row);
+                                rows.borrow_mut().push(row);
+                            }
+                        });
+                    });
+                }
+
+                // ============================================================
+                // Token Audit Group
+                // ============================================================
+                let token_audit_group = adw::PreferencesGroup::new();
+                token_audit_group.set_title("Token Audit");
+                token_audit_group.set_description(Some(&format!(
+                    "Compares each stored token's scopes with what the identity uses it for, \
+                     and flags tokens that never expire or run longer than {} days",
+                    token_audit::MAX_LIFETIME_DAYS
+                )));
+                let audit_tokens_row = adw::ActionRow::new();
+                audit_tokens_row.set_title("Audit Tokens");
+                audit_tokens_row.set_subtitle("Asks each provider about the stored tokens");
+                let token_audit_button = gtk4::Button::with_label("Audit");
+                token_audit_button.set_valign(gtk4::Align::Center);
+                audit_tokens_row.add_suffix(&token_audit_button);
+                token_audit_group.add(&audit_tokens_row);
+                main_box.append(&token_audit_group);
+
+                // Wire audit button: one expander per token, worst first
+                {
+                    let group = token_audit_group.clone();
+                    let summary_row = audit_tokens_row.clone();
+                    let rows: Rc<RefCell<Vec<adw::ExpanderRow>>> = Rc::default();
+                    let status = status_label.clone();
+                    let imp_weak = self.downgrade();
+                    token_audit_button.connect_clicked(move |button| {
+                        let Some(config) = imp_weak
+                            .upgrade()
+                            .and_then(|imp| imp.config.borrow().clone())
+                        else {
+                            return;
+                        };
+                        button.set_sensitive(false);
+                        let btn = button.clone();
+                        let group = group.clone();
+                        let summary_row = summary_row.clone();
+                        let rows = rows.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let Ok((audits, failed)) =
+                                gio::spawn_blocking(move || token_audit::audit(&config)).await
+                            else {
+                                btn.set_sensitive(true);
+                                return;
+                            };
+                            btn.set_sensitive(true);
+                            for row in rows.borrow_mut().drain(..) {
+                                group.remove(&row);
+                            }
+                            let flagged = audits.iter().filter(|a| !a.findings.is_empty()).count();
+                            summary_row.set_subtitle(&match (audits.len(), flagged) {
+                                (0, _) => "No stored tokens".to_string(),
+                                (n, 0) => format!("{} tokens; none flagged", n),
+                                (n, flagged) => format!("{} tokens; {} flagged", n, flagged),
+                            });
+                            for audit in &audits {
+                                let row = token_audit_row(audit);
+                                group.add(&row);
+                                rows.borrow_mut().push(row);
+                            }
+                            if let Some((name, e)) = failed.first() {
+                                show_status(
+                                    &status,
+                                    &error_text(&format!("{}: Checking the token failed", name), e),
+                                    Some("error"),
+                                );
+                            }
+                        });
+                    });
+                }
+
+                // ============================================================
+                // Allowed Signers Group
+                // ============================================================
+                let signers_group = adw::PreferencesGroup::new();
+                signers_group.set_title("Allowed Signers");
+                signers_group.set_description(Some(
+                    "Trusted SSH signing keys for git log --show-signature: identities that \
+                     sign with SSH, and collaborators under settings.allowedSigners",
+                ));
+                let signers_path = allowed_signers::path().unwrap_or_default();
+                let signers_row = adw::ActionRow::new();
+                signers_row.set_title("Update allowed_signers");
+                signers_row.set_subtitle(&glib::markup_escape_text(&platform::tilde_path(
+                    &signers_path,
+                )));
+                let signers_button = gtk4::Button::with_label("Update");
+                signers_button.set_valign(gtk4::Align::Center);
+                signers_row.add_suffix(&signers_button);
+                signers_group.add(&signers_row);
+
+                let signers_git_row = adw::ActionRow::new();
+                signers_git_row.set_title("Use for Verification");
+                signers_git_row.set_subtitle("Set gpg.ssh.allowedSignersFile");
+                let signers_repo_button = gtk4::Button::with_label("Repository…");
+                signers_repo_button.set_valign(gtk4::Align::Center);
+                signers_git_row.add_suffix(&signers_repo_button);
+                let signers_global_button = gtk4::Button::with_label("Globally");
+                signers_global_button.set_valign(gtk4::Align::Center);
+                signers_git_row.add_suffix(&signers_global_button);
+                signers_group.add(&signers_git_row);
+                main_box.append(&signers_group);
+
+                // Wire update button: rewrite the managed block
+                {
+                    let row = signers_row.clone();
+                    let status = status_label.clone();
+                    let imp_weak = self.downgrade();
+                    signers_button.connect_clicked(move |button| {
+                        let Some(config) = imp_weak
+                            .upgrade()
+                            .and_then(|imp| imp.config.borrow().clone())
+                        else {
+                            return;
+                        };
+                        button.set_sensitive(false);
+                        let btn = button.clone();
+                        let row = row.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result =
+                                gio::spawn_blocking(move || allowed_signers::update(&config))
+                                    .await
+                                    .unwrap_or_else(|_| Err(worker_panicked()));
+                            btn.set_sensitive(true);
+                            match result {
+                                Ok(report) => {
+                                    row.set_subtitle(&glib::markup_escape_text(
+                                        &report.display_text(),
+                                    ));
+                                    for (name, reason) in &report.failures {
+                                        tracing::warn!(
+                                            "allowed_signers skipped {}: {}",
+                                            name,
+                                            reason
+                                        );
+                                    }
+                                    show_status(
+                                        &status,
+                                        "allowed_signers updated",
+                                        Some("success"),
+                                    );
+                                }
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Updating allowed_signers failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                        });
+                    });
+                }
+
+                // Wire git config buttons: a chosen repository, or --global
+                {
+                    let status = status_label.clone();
+                    let path = signers_path.clone();
+                    signers_global_button.connect_clicked(move |_| {
+                        let status = status.clone();
+                        let path = path.clone();
+                        glib::spawn_future_local(async move {
+                            let result =
+                                gio::spawn_blocking(move || allowed_signers::use_file(&path, None))
+                                    .await
+                                    .unwrap_or_else(|_| Err(worker_panicked()));
+                            match result {
+                                Ok(()) => show_status(
+                                    &status,
+                                    "Git verifies SSH signatures with allowed_signers everywhere",
+                                    Some("success"),
+                                ),
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Setting allowedSignersFile failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                        });
+                    });
+                }
+                {
+                    let window_ref = self.obj().clone();
+                    let status = status_label.clone();
+                    let path = signers_path.clone();
+                    signers_repo_button.connect_clicked(move |_| {
+                        let dialog = gtk4::FileDialog::new();
+                        dialog.set_title("Select Repository");
+                        let status = status.clone();
+                        let path = path.clone();
+                        dialog.select_folder(
+                            Some(&window_ref),
+                            gio::Cancellable::NONE,
+                            move |result| {
+                                let Some(repo) = result.ok().and_then(|f| portal::file_path(&f))
+                                else {
+                                    return;
+                                };
+                                let status = status.clone();
+                                let path = path.clone();
+                                glib::spawn_future_local(async move {
+                                    let result = gio::spawn_blocking(move || {
+                                        allowed_signers::use_file(&path, Some(&repo))
+                                    })
+                                    .await
+                                    .unwrap_or_else(|_| Err(worker_panicked()));
+                                    match result {
+                                        Ok(()) => show_status(
+                                            &status,
+                                            "Repository verifies SSH signatures with \
+                                             allowed_signers",
+                                            Some("success"),
+                                        ),
+                                        Err(e) => show_status(
+                                            &status,
+                                            &error_text("Setting allowedSignersFile failed", &e),
+                                            Some("error"),
+                                        ),
+                                    }
+                                });
+                            },
+                        );
+                    });
+                }
+
+                // ============================================================
+                // Commit Signature Group
+                // ============================================================
+                let commit_sig_group = adw::PreferencesGroup::new();
+                commit_sig_group.set_title("Commit Signature");
+                commit_sig_group.set_description(Some(
+                    "git verify-commit on a repository's HEAD, checked against the identity \
+                     detected for the repository",
+                ));
+                let commit_sig_row = adw::ActionRow::new();
+                commit_sig_row.set_title("Verify HEAD Signature");
+                commit_sig_row.set_subtitle("No repository chosen");
+                let commit_sig_button = gtk4::Button::with_label("Repository…");
+                commit_sig_button.set_valign(gtk4::Align::Center);
+                commit_sig_row.add_suffix(&commit_sig_button);
+                commit_sig_group.add(&commit_sig_row);
+                // The repository last chosen, for the provider's view of it
+                let chosen_repo: Rc<RefCell<Option<PathBuf>>> = Rc::default();
+                let (verified_row, verified_button) =
+                    self.build_verified_commits_row(chosen_repo.clone(), &status_label);
+                let detected_row = adw::ActionRow::new();
+                detected_row.set_title("Detected Identity");
+                detected_row.set_subtitle("No repository chosen");
+                let submodules_button = gtk4::Button::with_label("Apply to Submodules");
+                submodules_button.set_valign(gtk4::Align::Center);
+                submodules_button.set_tooltip_text(Some(
+                    "Write the detected identity's name, email and signing settings to \
+                     every submodule's own git config",
+                ));
+                submodules_button.set_sensitive(false);
+                detected_row.add_suffix(&submodules_button);
+                commit_sig_group.add(&detected_row);
+                commit_sig_group.add(&verified_row);
+                main_box.append(&commit_sig_group);
+                main_box.append(&self.build_authorship_group(&status_label));
+
+                // The repository and identity submodules get
+                let detected: Rc<RefCell<Option<(PathBuf, String)>>> = Rc::new(RefCell::new(None));
+                {
+                    let detected = detected.clone();
+                    let status = status_label.clone();
+                    let imp_weak = self.downgrade();
+                    submodules_button.connect_clicked(move |button| {
+                        let Some((repo, identity)) = detected.borrow().clone() else {
+                            return;
+                        };
+                        let Some(config) = imp_weak
+                            .upgrade()
+                            .and_then(|imp| imp.config.borrow().clone())
+                        else {
+                            return;
+                        };
+                        button.set_sensitive(false);
+                        let button = button.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let name = identity.clone();
+                            let result = gio::spawn_blocking(move || {
+                                workspace::propagate(&config, &name, &repo)
+                            })
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                            button.set_sensitive(true);
+                            match result {
+                                Ok(written) => show_status(
+                                    &status,
+                                    &format!(
+                                        "{} written to {} submodules",
+                                        identity,
+                                        written.len()
+                                    ),
+                                    Some("success"),
+                                ),
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Updating submodules failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                        });
+                    });
+                }
+
+                // Wire verify button: choose a repository, then check HEAD
+                {
+                    let window_ref = self.obj().clone();
+                    let row = commit_sig_row.clone();
+                    let detected_row = detected_row.clone();
+                    let submodules_button = submodules_button.clone();
+                    let detected = detected.clone();
+                    let imp_weak = self.downgrade();
+                    commit_sig_button.connect_clicked(move |button| {
+                        let dialog = gtk4::FileDialog::new();
+                        dialog.set_title("Select Repository");
+                        let btn = button.clone();
+                        let row = row.clone();
+                        let detected_row = detected_row.clone();
+                        let submodules_button = submodules_button.clone();
+                        let detected = detected.clone();
+                        let imp_weak = imp_weak.clone();
+                        let chosen_repo = chosen_repo.clone();
+                        let verified_button = verified_button.clone();
+                        dialog.select_folder(
+                            Some(&window_ref),
+                            gio::Cancellable::NONE,
+                            move |result| {
+                                let Some(repo) = result.ok().and_then(|f| portal::file_path(&f))
+                                else {
+                                    return;
+                                };
+                                chosen_repo.replace(Some(repo.clone()));
+                                verified_button.set_sensitive(true);
+                                let Some(config) = imp_weak
+                                    .upgrade()
+                                    .and_then(|imp| imp.config.borrow().clone())
+                                else {
+                                    return;
+                                };
+                                btn.set_sensitive(false);
+                                row.set_title(&glib::markup_escape_text(&scanner::repo_name(
+                                    &repo,
+                                )));
+                                row.set_subtitle("Verifying HEAD...");
+                                row.remove_css_class("error");
+                                row.remove_css_class("success");
+                                detected_row.set_subtitle("Detecting...");
+                                submodules_button.set_sensitive(false);
+                                detected.replace(None);
+                                {
+                                    let detected_row = detected_row.clone();
+                                    let submodules_button = submodules_button.clone();
+                                    let detected = detected.clone();
+                                    let config = config.clone();
+                                    let repo = repo.clone();
+                                    glib::spawn_future_local(async move {
+                                        let worker_repo = repo.clone();
+                                        let result = gio::spawn_blocking(move || {
+                                            let found = detect::detect(&config, &worker_repo)?;
+                                            let layout = detect::layout(&worker_repo).ok();
+                                            let submodules = workspace::submodules(&worker_repo)
+                                                .map(|s| s.len())
+                                                .unwrap_or(0);
+                                            Ok::<_, Error>((found, layout, submodules))
+                                        })
+                                        .await
+                                        .unwrap_or_else(|_| Err(worker_panicked()));
+                                        let text = match result {
+                                            Ok((found, layout, submodules)) => {
+                                                let mut text = match &found {
+                                                    Some(found) => format!(
+                                                        "{}: {}",
+                                                        found.display_text(),
+                                                        found.url
+                                                    ),
+                                                    None => "No identity matches the remotes"
+                                                        .to_string(),
+                                                };
+                                                if let Some(layout) = layout.filter(|l| {
+                                                    l.checkout != detect::Checkout::Main
+                                                }) {
+                                                    text.push_str(&format!(
+                                                        " ({})",
+                                                        layout.display_text()
+                                                    ));
+                                                }
+                                                if let Some(found) =
+                                                    found.filter(|_| submodules > 0)
+                                                {
+                                                    text.push_str(&format!(
+                                                        "; {} submodules",
+                                                        submodules
+                                                    ));
+                                                    detected.replace(Some((
+                                                        repo.clone(),
+                                                        found.identity,
+                                                    )));
+                                                    submodules_button.set_sensitive(true);
+                                                }
+                                                text
+                                            }
+                                            Err(e) => error_text("Detection failed", &e),
+                                        };
+                                        detected_row.set_subtitle(&glib::markup_escape_text(&text));
+                                    });
+                                }
+                                let btn = btn.clone();
+                                let row = row.clone();
+                                glib::spawn_future_local(async move {
+                                    let result = gio::spawn_blocking(move || {
+                                        signature::check(&config, &repo)
+                                    })
+                                    .await
+                                    .unwrap_or_else(|_| Err(worker_panicked()));
+                                    btn.set_sensitive(true);
+                                    match result {
+                                        Ok(report) => {
+                                            row.set_subtitle(&glib::markup_escape_text(
+                                                &report.display_text(),
+                                            ));
+                                            row.add_css_class(if report.is_problem() {
+                                                "error"
+                                            } else {
+                                                "success"
+                                            });
+                                        }
+                                        Err(e) => {
+                                            row.set_subtitle(&glib::markup_escape_text(
+                                                &error_text("Verification failed", &e),
+                                            ));
+                                            row.add_css_class("error");
+                                        }
+                                    }
+                                });
+                            },
+                        );
+                    });
+                }
+
+                // Add GPG status group
+                let gpg_group = adw::PreferencesGroup::new();
+                gpg_group.set_title("GPG Status");
+
+                let gpg_status_row = adw::ActionRow::new();
+                gpg_status_row.set_title("Signing Ready");
+                gpg_status_row.set_subtitle("Checking...");
+
+                // Add a switch for GPG signing toggle
+                let gpg_switch = gtk4::Switch::new();
+                gpg_switch.set_valign(gtk4::Align::Center);
+                gpg_switch.set_active(config.settings.gpg_sign);
+                gpg_status_row.add_suffix(&gpg_switch);
+
+                gpg_group.add(&gpg_status_row);
+                gpg_group.add(&self.build_gpg_backup_rows(&status_label));
+                main_box.append(&gpg_group);
+
+                // Add Security Mode group
+                let security_group = adw::PreferencesGroup::new();
+                security_group.set_title("Security");
+                security_group.set_description(Some("YubiKey PIN handling mode"));
+
+                // Security Mode combo row
+                let security_mode_row = adw::ComboRow::new();
+                security_mode_row.set_title("Security Mode");
+                security_mode_row.set_subtitle("How YubiKey PIN is handled during signing");
+
+                // Create string list for security modes
+                let mode_names: Vec<&str> = SecurityMode::all()
+                    .iter()
+                    .map(|m| m.display_name())
+                    .collect();
+                let mode_list = gtk4::StringList::new(&mode_names);
+                security_mode_row.set_model(Some(&mode_list));
+
+                // Get current security mode from the current profile's GPG config
+                let current_security_mode = current_profile
+                    .as_ref()
+                    .map(|p| p.gpg.security_mode.clone())
+                    .unwrap_or_default();
+                security_mode_row.set_selected(current_security_mode.index());
+                let auto_lock = config.settings.auto_lock.clone();
+                self.auto_lock_timeout
+                    .set(auto_lock.timeout(&current_security_mode));
+
+                security_group.add(&security_mode_row);
+                security_group.add(&self.build_lockout_rows(&status_label));
+
+                let refuse_row = adw::ActionRow::new();
+                refuse_row.set_title("Refuse Wrong YubiKey");
+                refuse_row.set_subtitle(
+                    "Don't switch to an identity bound to another YubiKey than the one inserted",
+                );
+                let refuse_switch = gtk4::Switch::new();
+                refuse_switch.set_valign(gtk4::Align::Center);
+                refuse_switch.set_active(config.settings.refuse_wrong_yubikey);
+                refuse_row.add_suffix(&refuse_switch);
+                refuse_row.set_activatable_widget(Some(&refuse_switch));
+                security_group.add(&refuse_row);
+                {
+                    let status = status_label.clone();
+                    refuse_switch.connect_active_notify(move |switch| {
+                        let on = switch.is_active();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || {
+                                Config::update(|config| config.settings.refuse_wrong_yubikey = on)?;
+                                Ok::<_, Error>(())
+                            })
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                            if let Err(e) = result {
+                                show_status(
+                                    &status,
+                                    &error_text("Saving setting failed", &e),
+                                    Some("error"),
+                                );
+                            }
+                        });
+                    });
+                }
+
+                // YubiKey PIN Storage group (only visible in TrustedWorkstation mode)
+                let pin_group = adw::PreferencesGroup::new();
+                pin_group.set_title("YubiKey PIN Storage");
+                pin_group.set_description(Some("Store PIN in hardware security module"));
+
+                // PIN entry row using gtk4::PasswordEntry inside an ActionRow
+                let pin_entry = gtk4::PasswordEntry::new();
+                pin_entry.set_show_peek_icon(true);
+                pin_entry.set_hexpand(true);
+                pin_entry.set_valign(gtk4::Align::Center);
+
+                let pin_entry_row = adw::ActionRow::new();
+                pin_entry_row.set_title("Enter PIN");
+                pin_entry_row.add_suffix(&pin_entry);
+                pin_entry_row.set_activatable_widget(Some(&pin_entry));
+                pin_group.add(&pin_entry_row);
+
+                // Store PIN button and status row
+                let store_pin_row = adw::ActionRow::new();
+                store_pin_row.set_title("Store PIN in HSM");
+
+                // Status indicator
+                let pin_status_label = gtk4::Label::new(Some("Not stored"));
+                pin_status_label.add_css_class("dim-label");
+                store_pin_row.add_suffix(&pin_status_label);
+
+                // Store button
+                let store_button = gtk4::Button::with_label("Store PIN");
+                store_button.set_valign(gtk4::Align::Center);
+                store_button.add_css_class("suggested-action");
+                store_pin_row.add_suffix(&store_button);
+                store_pin_row.set_activatable_widget(Some(&store_button));
+
+                pin_group.add(&store_pin_row);
+
+                // TPM availability and sealed secrets that need resealing
+                let tpm_row = adw::ActionRow::new();
+                tpm_row.set_title("TPM");
+                tpm_row.set_subtitle("Checking...");
+                let reseal_button = gtk4::Button::with_label("Reseal");
+                reseal_button.set_valign(gtk4::Align::Center);
+                reseal_button.set_tooltip_text(Some(
+                    "Seal again under the current firmware state, using the PIN entered above",
+                ));
+                reseal_button.set_visible(false);
+                tpm_row.add_suffix(&reseal_button);
+                pin_group.add(&tpm_row);
+
+                let keystore_seal_row = adw::ActionRow::new();
+                keystore_seal_row.set_title("Seal Key Store Password");
+                keystore_seal_row
+                    .set_subtitle("Unlock the key store on this device without a prompt");
+                let keystore_seal_button = gtk4::Button::with_label("Seal");
+                keystore_seal_button.set_valign(gtk4::Align::Center);
+                keystore_seal_row.add_suffix(&keystore_seal_button);
+                pin_group.add(&keystore_seal_row);
+
+                let tpm_status: Rc<RefCell<Option<tpm::Status>>> = Rc::default();
+                let refresh_tpm = {
+                    let tpm_row = tpm_row.clone();
+                    let reseal_button = reseal_button.clone();
+                    let keystore_seal_row = keystore_seal_row.clone();
+                    let tpm_status = tpm_status.clone();
+                    Rc::new(move || {
+                        tpm_row.set_subtitle("Checking...");
+                        tpm_row.remove_css_class("error");
+                        let tpm_row = tpm_row.clone();
+                        let reseal_button = reseal_button.clone();
+                        let keystore_seal_row = keystore_seal_row.clone();
+                        let tpm_status = tpm_status.clone();
+                        glib::spawn_future_local(async move {
+                            let (device, result) =
+                                gio::spawn_blocking(|| (tpm::device(), tpm::status()))
+                                    .await
+                                    .unwrap_or_else(|_| (None, Err(worker_panicked())));
+                            let text = match &result {
+                                Ok(status) => status.display_text(device.as_ref()),
+                                Err(e) => error_text("Checking the TPM failed", e),
+                            };
+                            tpm_row.set_subtitle(&glib::markup_escape_text(&text));
+                            let stale = result
+                                .as_ref()
+                                .is_ok_and(|status| !status.needs_reseal().is_empty());
+                            if stale {
+                                tpm_row.add_css_class("error");
+                            }
+                            reseal_button.set_visible(
+                                result
+                                    .as_ref()
+                                    .is_ok_and(|status| !status.sealed.is_empty()),
+                            );
+                            if let Ok(status) = &result {
+                                keystore_seal_row.set_visible(status.available);
+                                if status.keystore_sealed() {
+                                    keystore_seal_row.set_subtitle("Sealed on this device");
+                                }
+                            }
+                            *tpm_status.borrow_mut() = result.ok();
+                        });
+                    })
+                };
+
+                // Wire reseal button: every sealed secret, stale ones from
+                // the PIN entry or the unlocked key store
+                {
+                    let pin_entry = pin_entry.clone();
+                    let tpm_status = tpm_status.clone();
+                    let refresh_tpm = refresh_tpm.clone();
+                    let status = status_label.clone();
+                    reseal_button.connect_clicked(move |button| {
+                        let Some(sealed) = tpm_status.borrow().as_ref().map(|s| s.sealed.clone())
+                        else {
+                            return;
+                        };
+                        let pin = Some(pin_entry.text().to_string()).filter(|p| !p.is_empty());
+                        button.set_sensitive(false);
+                        show_status(&status, "Resealing...", None);
+                        let btn = button.clone();
+                        let pin_entry = pin_entry.clone();
+                        let refresh_tpm = refresh_tpm.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let failures = gio::spawn_blocking(move || {
+                                sealed
+                                    .iter()
+                                    .filter_map(|s| {
+                                        let secret = match s.state {
+                                            SealState::Ok => None,
+                                            _ if s.keystore => None,
+                                            _ => pin.as_deref(),
+                                        };
+                                        tpm::reseal(s, secret)
+                                            .err()
+                                            .map(|e| format!("{}: {}", s.display_name(), e))
+                                    })
+                                    .collect::<Vec<_>>()
+                            })
+                            .await
+                            .unwrap_or_else(|_| vec![worker_panicked().to_string()]);
+                            btn.set_sensitive(true);
+                            if failures.is_empty() {
+                                pin_entry.set_text("");
+                                show_status(&status, "Sealed again", Some("success"));
+                            } else {
+                                show_status(
+                                    &status,
+                                    &format!("Resealing failed for {}", failures.join("; ")),
+                                    Some("error"),
+                                );
+                            }
+                            refresh_tpm();
+                        });
+                    });
+                }
+
+                // Wire keystore seal button: the password the GUI unlocked
+                // the key store with
+                {
+                    let refresh_tpm = refresh_tpm.clone();
+                    let status = status_label.clone();
+                    keystore_seal_button.connect_clicked(move |button| {
+                        button.set_sensitive(false);
+                        let btn = button.clone();
+                        let refresh_tpm = refresh_tpm.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(|| tpm::seal_keystore(None))
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                            btn.set_sensitive(true);
+                            match result {
+                                Ok(()) => show_status(
+                                    &status,
+                                    "Key store password sealed",
+                                    Some("success"),
+                                ),
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Sealing the key store password failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                            refresh_tpm();
+                        });
+                    });
+                }
+
+                // Set initial visibility based on security mode
+                let show_pin_storage = current_security_mode == SecurityMode::TrustedWorkstation;
+                pin_group.set_visible(show_pin_storage);
+                if show_pin_storage {
+                    let refresh_tpm = refresh_tpm.clone();
+                    self.schedule_probe(async move { refresh_tpm() });
+                }
+
+                main_box.append(&security_group);
+                main_box.append(&pin_group);
+
+                // Wire security mode change handler (2c)
+                {
+                    let pin_group_clone = pin_group.clone();
+                    let status_clone = status_label.clone();
+                    let auto_lock = auto_lock.clone();
+                    let imp_weak = self.downgrade();
+                    security_mode_row.connect_selected_notify(move |row| {
+                        let selected = row.selected();
+                        let mode = SecurityMode::from_index(selected);
+                        let show = mode == SecurityMode::TrustedWorkstation;
+                        pin_group_clone.set_visible(show);
+                        if show {
+                            refresh_tpm();
+                        }
+                        if let Some(imp) = imp_weak.upgrade() {
+                            imp.auto_lock_timeout.set(auto_lock.timeout(&mode));
+                        }
+
+                        // Call CLI to persist the security mode change
+                        let mode_str = match mode {
+                            SecurityMode::MaximumSecurity => "maximum_security",
+                            SecurityMode::DeveloperWorkflow => "developer_workflow",
+                            SecurityMode::TrustedWorkstation => "trusted_workstation",
+                        };
+                        let status = status_clone.clone();
+                        let mode_display = mode.display_name().to_string();
+                        let mode_arg = mode_str.to_string();
+                        status.set_visible(true);
+                        status.remove_css_class("error");
+                        status.remove_css_class("success");
+                        status.set_text(&format!("Setting security mode to {}...", &mode_display));
+
+                        glib::spawn_future_local(async move {
+                            let result = run_cli_async("security-mode", &mode_arg).await;
+                            match result {
+                                Ok(_) => {
+                                    status.set_text(&format!("Security mode: {}", &mode_display));
+                                    status.add_css_class("success");
+                                    tracing::info!("Security mode changed to: {}", &mode_display);
+                                }
+                                Err(e) => {
+                                    status.set_text(&error_text("Failed", &e));
+                                    status.add_css_class("error");
+                                    tracing::error!("Security mode change failed: {}", e);
+                                }
+                            }
+                        });
+                    });
+                }
+
+                // ============================================================
+                // Needs Rotation Group
+                // ============================================================
+                let rotation_group = adw::PreferencesGroup::new();
+                rotation_group.set_title("Needs Rotation");
+                rotation_group.set_description(Some(&format!(
+                    "Secrets that expired or are due within {} days",
+                    expiry::WARN_DAYS
+                )));
+                rotation_group.set_visible(false);
+                main_box.append(&rotation_group);
+                *self.rotation_group.borrow_mut() = Some(rotation_group);
+                self.rotation_rows.borrow_mut().clear();
+                {
+                    let imp_weak = self.downgrade();
+                    self.schedule_probe(async move {
+                        if let Some(imp) = imp_weak.upgrade() {
+                            imp.check_rotation();
+                        }
+                    });
+                }
+
+                // ============================================================
+                // Reauthenticate Group
+                // ============================================================
+                let token_problems_group = adw::PreferencesGroup::new();
+                token_problems_group.set_title("Reauthenticate");
+                token_problems_group.set_description(Some(
+                    "Identities whose provider rejected their token or whose token expired; \
+                     git over HTTPS and the key APIs fail until it is replaced",
+                ));
+                token_problems_group.set_visible(false);
+                main_box.append(&token_problems_group);
+                *self.token_problems_group.borrow_mut() = Some(token_problems_group);
+                self.token_problem_rows.borrow_mut().clear();
+                {
+                    let imp_weak = self.downgrade();
+                    self.schedule_probe(async move {
+                        if let Some(imp) = imp_weak.upgrade() {
+                            imp.check_tokens(false);
+                        }
+                    });
+                }
+
+                // ============================================================
+                // Leased Secrets Group
+                // ============================================================
+                let lease_group = adw::PreferencesGroup::new();
+                lease_group.set_title("Leased Secrets");
+                lease_group.set_description(Some(
+                    "Temporary secrets that are deleted or masked when their lease runs out",
+                ));
+                main_box.append(&lease_group);
+
+                let lease_expander = adw::ExpanderRow::new();
+                lease_expander.set_title("Store Leased Secret");
+                lease_expander
+                    .set_subtitle("For short-lived tokens, e.g. temporary cloud credentials");
+                lease_group.add(&lease_expander);
+
+                let lease_secret_row = adw::ActionRow::new();
+                lease_secret_row.set_title("Secret");
+                let lease_path_entry = gtk4::Entry::new();
+                lease_path_entry.set_placeholder_text(Some("Path (e.g. RemoteJuggler/Cloud/AWS)"));
+                lease_path_entry.set_hexpand(true);
+                lease_path_entry.set_valign(gtk4::Align::Center);
+                let lease_value_entry = gtk4::PasswordEntry::new();
+                lease_value_entry.set_placeholder_text(Some("Secret value"));
+                lease_value_entry.set_show_peek_icon(true);
+                lease_value_entry.set_valign(gtk4::Align::Center);
+                lease_secret_row.add_suffix(&lease_path_entry);
+                lease_secret_row.add_suffix(&lease_value_entry);
+                lease_expander.add_row(&lease_secret_row);
+
+                let lease_duration_row = adw::ActionRow::new();
+                lease_duration_row.set_title("Lease");
+                lease_duration_row.set_subtitle("Duration such as 90s, 15m, 8h, or 1d");
+                let lease_duration_entry = gtk4::Entry::new();
+                lease_duration_entry.set_text("1h");
+                lease_duration_entry.set_valign(gtk4::Align::Center);
+                lease_duration_row.add_suffix(&lease_duration_entry);
+                lease_expander.add_row(&lease_duration_row);
+
+                let lease_action_row = adw::ComboRow::new();
+                lease_action_row.set_title("When It Ends");
+                let action_names: Vec<&str> = leases::LeaseAction::all()
+                    .iter()
+                    .map(|a| a.display_name())
+                    .collect();
+                lease_action_row.set_model(Some(&gtk4::StringList::new(&action_names)));
+                lease_expander.add_row(&lease_action_row);
+
+                let lease_hook_row = adw::ActionRow::new();
+                lease_hook_row.set_title("Renewal Hook");
+                lease_hook_row.set_subtitle("Optional command that prints a fresh value");
+                let lease_hook_entry = gtk4::Entry::new();
+                lease_hook_entry.set_placeholder_text(Some("e.g. vault read -field=token ..."));
+                lease_hook_entry.set_hexpand(true);
+                lease_hook_entry.set_valign(gtk4::Align::Center);
+                let lease_store_button = gtk4::Button::with_label("Store");
+                lease_store_button.set_valign(gtk4::Align::Center);
+                lease_store_button.add_css_class("suggested-action");
+                lease_hook_row.add_suffix(&lease_hook_entry);
+                lease_hook_row.add_suffix(&lease_store_button);
+                lease_expander.add_row(&lease_hook_row);
+
+                *self.lease_group.borrow_mut() = Some(lease_group);
+                self.lease_rows.borrow_mut().clear();
+                self.show_leases();
+                // Leases that ran out while the app was closed end now
+                self.enforce_leases();
+
+                // Wire store button: store the value, then start the lease
+                {
+                    let path_entry = lease_path_entry.clone();
+                    let value_entry = lease_value_entry.clone();
+                    let duration_entry = lease_duration_entry.clone();
+                    let action_row = lease_action_row.clone();
+                    let hook_entry = lease_hook_entry.clone();
+                    let status = status_label.clone();
+                    let imp_weak = self.downgrade();
+                    lease_store_button.connect_clicked(move |button| {
+                        let path = path_entry.text().trim().to_string();
+                        let value = value_entry.text().to_string();
+                        if path.is_empty() || value.is_empty() {
+                            return;
+                        }
+                        let Some(duration) = leases::parse_duration(&duration_entry.text()) else {
+                            show_status(
+                                &status,
+                                "Lease must be a duration like 15m or 8h",
+                                Some("error"),
+                            );
+                            return;
+                        };
+                        let action = leases::LeaseAction::from_index(action_row.selected());
+                        let hook = Some(hook_entry.text().trim().to_string());
+                        button.set_sensitive(false);
+                        let btn = button.clone();
+                        let value_entry = value_entry.clone();
+                        let status = status.clone();
+                        let imp_weak = imp_weak.clone();
+                        glib::spawn_future_local(async move {
+                            let result = match store_secret_async(path.clone(), value).await {
+                                Ok(()) => {
+                                    let path = path.clone();
+                                    gio::spawn_blocking(move || {
+                                        leases::grant(&path, duration, action, hook)
+                                            .map_err(Error::Io)
+                                    })
+                                    .await
+                                    .unwrap_or_else(|_| Err(worker_panicked()))
+                                }
+                                Err(e) => Err(e),
+                            };
+                            match result {
+                                Ok(()) => {
+                                    value_entry.set_text("");
+                                    show_status(
+                                        &status,
+                                        &format!(
+                                            "Stored {} for {}",
+                                            path,
+                                            leases::format_remaining(duration)
+                                        ),
+                                        Some("success"),
+                                    );
+                                    if let Some(imp) = imp_weak.upgrade() {
+                                        imp.show_leases();
+                                    }
+                                }
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Store failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                            btn.set_sensitive(true);
+                        });
+                    });
+                }
+
+                // ============================================================
+                // KeePassXC Key Store Group
+                // ============================================================
+                let keys_group = adw::PreferencesGroup::new();
+                keys_group.set_title("Key Store (KeePassXC)");
+                keys_group.set_description(Some("Credential authority for secrets management"));
+
+                // Key store status row
+                let keys_status_row = adw::ActionRow::new();
+                keys_status_row.set_title("Key Store");
+                let keys_status_label = gtk4::Label::new(Some("Checking..."));
+                keys_status_label.add_css_class("dim-label");
+                keys_status_row.add_suffix(&keys_status_label);
+                keys_group.add(&keys_status_row);
+
+                // After an auto-lock, the status row asks for the password again
+                let auto_locked = autolock::is_locked();
+                let relock_password = gtk4::PasswordEntry::new();
+                relock_password.set_placeholder_text(Some("Master password"));
+                relock_password.set_show_peek_icon(true);
+                relock_password.set_valign(gtk4::Align::Center);
+                let relock_button = gtk4::Button::with_label("Unlock");
+                relock_button.set_valign(gtk4::Align::Center);
+                relock_button.add_css_class("suggested-action");
+                relock_password.set_visible(auto_locked);
+                relock_button.set_visible(auto_locked);
+                keys_status_row.add_suffix(&relock_password);
+                keys_status_row.add_suffix(&relock_button);
+
+                // Database selector, shown when more than one is configured
+                let keystores = databases::from_config(Some(config));
+                let mut active_database = databases::active_name();
+                if !keystores.iter().any(|db| db.name == active_database) {
+                    // The active database was removed from the config
+                    databases::activate(&keystores[0]);
+                    active_database = keystores[0].name.clone();
+                }
+                let default_active = databases::is_default_active();
+                chalresp::apply(&active_database);
+                let database_row = adw::ComboRow::new();
+                database_row.set_title("Database");
+                let database_names: Vec<&str> =
+                    keystores.iter().map(|db| db.name.as_str()).collect();
+                database_row.set_model(Some(&gtk4::StringList::new(&database_names)));
+                if let Some(pos) = keystores.iter().position(|db| db.name == active_database) {
+                    database_row.set_selected(pos as u32);
+                    database_row.set_subtitle(&glib::markup_escape_text(&keystores[pos].path));
+                }
+                database_row.set_visible(keystores.len() > 1);
+                keys_group.add(&database_row);
+
+                {
+                    let keystores = keystores.clone();
+                    let imp_weak = self.downgrade();
+                    database_row.connect_selected_notify(move |row| {
+                        let Some(db) = keystores.get(row.selected() as usize) else {
+                            return;
+                        };
+                        databases::activate(db);
+                        if let Some(imp) = imp_weak.upgrade() {
+                            imp.reload_config_and_ui();
+                        }
+                    });
+                }
+
+                // Master password for a non-default database, for this session
+                let database_unlock_row = adw::ActionRow::new();
+                database_unlock_row.set_title(&glib::markup_escape_text(&format!(
+                    "Unlock {}",
+                    active_database
+                )));
+                database_unlock_row.set_subtitle("Master password, kept until the app quits");
+                let database_password = gtk4::PasswordEntry::new();
+                database_password.set_placeholder_text(Some("Master password"));
+                database_password.set_show_peek_icon(true);
+                database_password.set_valign(gtk4::Align::Center);
+                let database_unlock_button = gtk4::Button::with_label("Unlock");
+                database_unlock_button.set_valign(gtk4::Align::Center);
+                database_unlock_button.add_css_class("suggested-action");
+                database_unlock_row.add_suffix(&database_password);
+                database_unlock_row.add_suffix(&database_unlock_button);
+                database_unlock_row.set_visible(
+                    !auto_locked && !default_active && !databases::has_password(&active_database),
+                );
+                keys_group.add(&database_unlock_row);
+
+                // Unlock after an auto-lock: the entered password, or the
+                // keyring's for the default database
+                {
+                    let entry = relock_password.clone();
+                    let name = active_database.clone();
+                    let status = status_label.clone();
+                    let imp_weak = self.downgrade();
+                    let unlock = move || {
+                        let password = entry.text().to_string();
+                        entry.set_text("");
+                        let name = name.clone();
+                        let status = status.clone();
+                        let imp_weak = imp_weak.clone();
+                        glib::spawn_future_local(async move {
+                            if !password.is_empty() {
+                                databases::unlock(&name, &password);
+                            } else if default_active {
+                                let stored = gio::spawn_blocking(|| {
+                                    keyring::unlock_with_stored(Keyring::native())
+                                })
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                                if !matches!(stored, Ok(true)) {
+                                    show_status(
+                                        &status,
+                                        "Enter the master password to unlock",
+                                        Some("error"),
+                                    );
+                                    return;
+                                }
+                            } else {
+                                show_status(
+                                    &status,
+                                    "Enter the master password to unlock",
+                                    Some("error"),
+                                );
+                                return;
+                            }
+                            autolock::unlocked();
+                            if let Some(imp) = imp_weak.upgrade() {
+                                imp.reload_config_and_ui();
+                            }
+                        });
+                    };
+                    let unlock = Rc::new(unlock);
+                    let on_activate = unlock.clone();
+                    relock_password.connect_activate(move |_| on_activate());
+                    relock_button.connect_clicked(move |_| unlock());
+                }
+
+                // Auto-lock timeout and a way to lock right away
+                let auto_lock_row = adw::ActionRow::new();
+                auto_lock_row.set_title("Auto-Lock");
+                auto_lock_row.set_subtitle(&match self.auto_lock_timeout.get() {
+                    Some(timeout) => format!(
+                        "After {} idle in {}",
+                        autolock::describe(Some(timeout)),
+                        current_security_mode.display_name()
+                    ),
+                    None => format!("Off in {}", current_security_mode.display_name()),
+                });
+                let lock_button = gtk4::Button::with_label("Lock Now");
+                lock_button.set_valign(gtk4::Align::Center);
+                lock_button.set_sensitive(!auto_locked);
+                auto_lock_row.add_suffix(&lock_button);
+                keys_group.add(&auto_lock_row);
+
+                {
+                    let imp_weak = self.downgrade();
+                    lock_button.connect_clicked(move |_| {
+                        autolock::lock();
+                        if let Some(imp) = imp_weak.upgrade() {
+                            imp.rebuild_content();
+                        }
+                    });
+                }
+
+                {
+                    let entry = database_password.clone();
+                    let name = active_database.clone();
+                    let imp_weak = self.downgrade();
+                    let unlock = move || {
+                        let password = entry.text().to_string();
+                        if password.is_empty() {
+                            return;
+                        }
+                        databases::unlock(&name, &password);
+                        entry.set_text("");
+                        if let Some(imp) = imp_weak.upgrade() {
+                            imp.reload_config_and_ui();
+                        }
+                    };
+                    let unlock = Rc::new(unlock);
+                    let on_activate = unlock.clone();
+                    database_password.connect_activate(move |_| on_activate());
+                    database_unlock_button.connect_clicked(move |_| unlock());
+                }
+
+                // Check key store status async, unlocking from the system
+                // keyring first when a master password is stored there
+                if auto_locked {
+                    keys_status_label.set_text("Locked");
+                    keys_status_label.remove_css_class("dim-label");
+                    keys_status_label.add_css_class("warning");
+                } else {
+                    let label = keys_status_label.clone();
+                    self.schedule_probe(async move {
+                        let _ = gio::spawn_blocking(move || {
+                            // The keyring holds the default database's password
+                            if default_active && !cli::has_session_env(keyring::MASTER_PASSWORD_ENV)
+                            {
+                                if let Err(e) = keyring::unlock_with_stored(Keyring::native()) {
+                                    tracing::warn!("Keyring unlock failed: {}", e);
+                                }
+                            }
+                        })
+                        .await;
+                        let result = run_cli_async("keys", "status").await;
+                        match result {
+                            Ok(output) => match shell::KeystoreState::parse(&output) {
+                                shell::KeystoreState::Unlocked => {
+                                    label.set_text("Unlocked");
+                                    label.remove_css_class("dim-label");
+                                    label.add_css_class("success");
+                                }
+                                shell::KeystoreState::Locked => {
+                                    label.set_text("Locked");
+                                    label.remove_css_class("dim-label");
+                                    label.add_css_class("warning");
+                                }
+                                _ => label.set_text("Not initialized"),
+                            },
+                            Err(_) => {
+                                label.set_text("Unavailable");
+                            }
+                        }
+                    });
+                }
+
+                // Remember the master password in the system keyring
+                let keyring = Keyring::native();
+                let keyring_row = adw::ActionRow::new();
+                keyring_row.set_title("Unlock After Login");
+                keyring_row.set_subtitle(&format!(
+                    "Keep the master password in the {}",
+                    keyring.display_name()
+                ));
+                let master_entry = gtk4::PasswordEntry::new();
+                master_entry.set_placeholder_text(Some("Master password"));
+                master_entry.set_show_peek_icon(true);
+                master_entry.set_valign(gtk4::Align::Center);
+                master_entry.set_visible(false);
+                let keyring_switch = gtk4::Switch::new();
+                keyring_switch.set_valign(gtk4::Align::Center);
+                keyring_switch.set_sensitive(false);
+                keyring_row.add_suffix(&master_entry);
+                keyring_row.add_suffix(&keyring_switch);
+                keyring_row.set_visible(default_active);
+                keys_group.add(&keyring_row);
+
+                let (forget_row, forget_button) = button_row(
+                    "Forget Stored Credential",
+                    "Remove the master password from the keyring",
+                    "Forget",
+                );
+                forget_button.add_css_class("destructive-action");
+                forget_row.set_visible(false);
+                keys_group.add(&forget_row);
+
+                // Probe whether a credential is already stored
+                {
+                    let switch = keyring_switch.clone();
+                    let entry = master_entry.clone();
+                    let forget_row = forget_row.clone();
+                    let row = keyring_row.clone();
+                    self.schedule_probe(async move {
+                        let state = gio::spawn_blocking(move || {
+                            (keyring.is_available(), keyring.has_stored())
+                        })
+                        .await;
+                        match state {
+                            Ok((true, stored)) => {
+                                // Show the forget row first so the switch
+                                // handler treats this as already stored
+                                forget_row.set_visible(stored && default_active);
+                                entry.set_visible(!stored);
+                                switch.set_active(stored);
+                                switch.set_sensitive(true);
+                            }
+                            _ => row.set_subtitle("No system keyring found"),
+                        }
+                    });
+                }
+
+                // Wire keyring switch: on stores the entered password
+                {
+                    let entry = master_entry.clone();
+                    let forget_row = forget_row.clone();
+                    let forget = forget_button.clone();
+                    let status = status_label.clone();
+                    keyring_switch.connect_state_set(move |switch, active| {
+                        if !active {
+                            // Turning off forgets the stored password
+                            if forget_row.is_visible() {
+                                forget.emit_clicked();
+                            }
+                            return glib::Propagation::Proceed;
+                        }
+                        if forget_row.is_visible() {
+                            return glib::Propagation::Proceed;
+                        }
+                        let secret = entry.text().to_string();
+                        if secret.is_empty() {
+                            show_status(&status, "Enter the master password first", Some("error"));
+                            switch.set_active(false);
+                            return glib::Propagation::Stop;
+                        }
+                        switch.set_sensitive(false);
+                        let switch = switch.clone();
+                        let entry = entry.clone();
+                        let forget_row = forget_row.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || {
+                                keyring.store(&secret)?;
+                                keyring::unlock_with_stored(keyring)
+                            })
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                            match result {
+                                Ok(_) => {
+                                    entry.set_text("");
+                                    entry.set_visible(false);
+                                    forget_row.set_visible(true);
+                                    switch.set_state(true);
+                                    show_status(
+                                        &status,
+                                        "Master password stored in the keyring",
+                                        Some("success"),
+                                    );
+                                }
+                                Err(e) => {
+                                    switch.set_active(false);
+                                    show_status(
+                                        &status,
+                                        &error_text("Keyring store failed", &e),
+                                        Some("error"),
+                                    );
+                                }
+                            }
+                            switch.set_sensitive(true);
+                        });
+                        glib::Propagation::Stop
+                    });
+                }
+
+                // Wire forget button
+                {
+                    let switch = keyring_switch.clone();
+                    let entry = master_entry.clone();
+                    let forget_row_clone = forget_row.clone();
+                    let status = status_label.clone();
+                    forget_button.connect_clicked(move |button| {
+                        button.set_sensitive(false);
+                        let btn = button.clone();
+                        let switch = switch.clone();
+                        let entry = entry.clone();
+                        let forget_row = forget_row_clone.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || keyring::forget(keyring))
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                            match result {
+                                Ok(()) => {
+                                    forget_row.set_visible(false);
+                                    entry.set_visible(true);
+                                    switch.set_active(false);
+                                    show_status(&status, "Stored credential forgotten", None);
+                                }
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Keyring forget failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                            btn.set_sensitive(true);
+                        });
+                    });
+                }
+
+                // YubiKey challenge-response, required in addition to the
+                // master password
+                let challenge = chalresp::ChallengeStore::load()
+                    .databases
+                    .get(&active_database)
+                    .copied();
+                let chalresp_expander = adw::ExpanderRow::new();
+                chalresp_expander.set_title("Hardware Key Unlock");
+                chalresp_expander.set_subtitle(&match challenge {
+                    Some(c) => format!("YubiKey slot {} required with the password", c.slot),
+                    None => "Also require a YubiKey challenge-response".to_string(),
+                });
+                keys_group.add(&chalresp_expander);
+
+                let yubikey_row = adw::ActionRow::new();
+                yubikey_row.set_title("YubiKey");
+                yubikey_row.set_subtitle("Checking...");
+                chalresp_expander.add_row(&yubikey_row);
+
+                let slot_row = adw::ComboRow::new();
+                slot_row.set_title("Slot");
+                let slot_names: Vec<String> = chalresp::SLOTS
+                    .iter()
+                    .map(|slot| format!("Slot {}", slot))
+                    .collect();
+                let slot_names: Vec<&str> = slot_names.iter().map(String::as_str).collect();
+                slot_row.set_model(Some(&gtk4::StringList::new(&slot_names)));
+                let initial_slot = challenge.map_or(chalresp::DEFAULT_SLOT, |c| c.slot);
+                if let Some(pos) = chalresp::SLOTS.iter().position(|&s| s == initial_slot) {
+                    slot_row.set_selected(pos as u32);
+                }
+                slot_row.set_sensitive(challenge.is_none());
+                chalresp_expander.add_row(&slot_row);
+
+                let program_row = adw::ActionRow::new();
+                program_row.set_title("Program Slot");
+                program_row.set_subtitle("Generate a new HMAC-SHA1 secret on the YubiKey");
+                let touch_check = gtk4::CheckButton::with_label("Require touch");
+                touch_check.set_active(true);
+                touch_check.set_valign(gtk4::Align::Center);
+                let program_button = gtk4::Button::with_label("Program");
+                program_button.set_valign(gtk4::Align::Center);
+                program_button.add_css_class("destructive-action");
+                program_button.set_sensitive(false);
+                program_row.add_suffix(&touch_check);
+                program_row.add_suffix(&program_button);
+                chalresp_expander.add_row(&program_row);
+
+                let chalresp_use_row = adw::ActionRow::new();
+                chalresp_use_row.set_title("Use for Unlock");
+                chalresp_use_row
+                    .set_subtitle("Enroll the slot in KeePassXC under Database Security first");
+                let chalresp_switch = gtk4::Switch::new();
+                chalresp_switch.set_valign(gtk4::Align::Center);
+                chalresp_switch.set_active(challenge.is_some());
+                chalresp_use_row.add_suffix(&chalresp_switch);
+                chalresp_use_row.set_activatable_widget(Some(&chalresp_switch));
+                chalresp_expander.add_row(&chalresp_use_row);
+
+                let yubikey_info: Rc<RefCell<Option<YubiKeyInfo>>> = Rc::default();
+
+                // Warn before overwriting a programmed slot
+                let update_program_row = {
+                    let info = yubikey_info.clone();
+                    let slot_row = slot_row.clone();
+                    let row = program_row.clone();
+                    let button = program_button.clone();
+                    Rc::new(move || {
+                        let slot = chalresp::slot_from_index(slot_row.selected());
+                        let info = info.borrow();
+                        let programmed = info.as_ref().is_some_and(|i| i.is_programmed(slot));
+                        button.set_sensitive(info.is_some());
+                        if programmed {
+                            button.set_label("Overwrite");
+                            row.set_subtitle(&format!(
+                                "Slot {} is in use; overwriting it locks out databases enrolled with it",
+                                slot
+                            ));
+                        } else {
+                            button.set_label("Program");
+                            row.set_subtitle("Generate a new HMAC-SHA1 secret on the YubiKey");
+                        }
+                    })
+                };
+                {
+                    let update = update_program_row.clone();
+                    slot_row.connect_selected_notify(move |_| update());
+                }
+
+                // Probe the inserted YubiKey
+                {
+                    let row = yubikey_row.clone();
+                    let info = yubikey_info.clone();
+                    let update = update_program_row.clone();
+                    self.schedule_probe(async move {
+                        let result = gio::spawn_blocking(chalresp::probe)
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                        match result {
+                            Ok(key) => {
+                                row.set_subtitle(&key.summary());
+                                *info.borrow_mut() = Some(key);
+                            }
+                            Err(e) => row.set_subtitle(&glib::markup_escape_text(&error_text(
+                                "Not available",
+                                &e,
+                            ))),
+                        }
+                        update();
+                    });
+                }
+
+                // Wire program button
+                {
+                    let slot_row = slot_row.clone();
+                    let touch = touch_check.clone();
+                    let row = yubikey_row.clone();
+                    let info = yubikey_info.clone();
+                    let update = update_program_row.clone();
+                    let status = status_label.clone();
+                    program_button.connect_clicked(move |button| {
+                        let slot = chalresp::slot_from_index(slot_row.selected());
+                        let touch = touch.is_active();
+                        button.set_sensitive(false);
+                        show_status(
+                            &status,
+                            &format!("Programming slot {}...", slot),
+                            None,
+                        );
+                        let row = row.clone();
+                        let info = info.clone();
+                        let update = update.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || {
+                                chalresp::program_slot(slot, touch)?;
+                                chalresp::probe()
+                            })
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                            match result {
+                                Ok(key) => {
+                                    row.set_subtitle(&key.summary());
+                                    *info.borrow_mut() = Some(key);
+                                    show_status(
+                                        &status,
+                                        &format!(
+                                            "Slot {} programmed; enroll it in KeePassXC, then turn on Use for Unlock",
+                                            slot
+                                        ),
+                                        Some("success"),
+                                    );
+                                }
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Programming failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                            update();
+                        });
+                    });
+                }
+
+                // Wire use switch: remember the slot for this database
+                {
+                    let name = active_database.clone();
+                    let slot_row = slot_row.clone();
+                    let status = status_label.clone();
+                    let imp_weak = self.downgrade();
+                    chalresp_switch.connect_state_set(move |_, active| {
+                        let response = active.then(|| ChallengeResponse {
+                            slot: chalresp::slot_from_index(slot_row.selected()),
+                            serial: None,
+                        });
+                        if let Err(e) = chalresp::configure(&name, response) {
+                            show_status(
+                                &status,
+                                &error_text("Saving challenge-response failed", &e),
+                                Some("error"),
+                            );
+                            return glib::Propagation::Stop;
+                        }
+                        // The status row re-checks the unlock with the change
+                        if let Some(imp) = imp_weak.upgrade() {
+                            imp.reload_config_and_ui();
+                        }
+                        glib::Propagation::Proceed
+                    });
+                }
+
+                // Initialize key store button row
+                let init_row = adw::ActionRow::new();
+                init_row.set_title("Initialize Key Store");
+                init_row.set_subtitle("Create a new kdbx credential database");
+                let init_button = gtk4::Button::with_label("Initialize");
+                init_button.set_valign(gtk4::Align::Center);
+                init_button.add_css_class("suggested-action");
+                init_row.add_suffix(&init_button);
+                init_row.set_activatable_widget(Some(&init_button));
+                keys_group.add(&init_row);
+
+                // Wire init button (click again to cancel)
+                {
+                    let status_clone = status_label.clone();
+                    let keys_label = keys_status_label.clone();
+                    let slot = CancelSlot::default();
+                    init_button.connect_clicked(move |button| {
+                        if slot.cancel_running() {
+                            return;
+                        }
+                        let token = slot.start();
+                        button.set_label("Cancel");
+                        let status = status_clone.clone();
+                        let klabel = keys_label.clone();
+                        let btn = button.clone();
+                        let slot = slot.clone();
+                        status.set_text("Initializing key store...");
+                        status.set_visible(true);
+                        status.remove_css_class("error");
+                        status.remove_css_class("success");
+
+                        glib::spawn_future_local(async move {
+                            let result =
+                                run_cli_cancellable_async(cli::args(&["keys", "init"]), token)
+                                    .await;
+                            match result {
+                                Ok(_) => {
+                                    status.set_text("Key store initialized");
+                                    status.add_css_class("success");
+                                    klabel.set_text("Ready");
+                                    klabel.remove_css_class("dim-label");
+                                    klabel.add_css_class("success");
+                                }
+                                Err(e) if e.is_cancelled() => {
+                                    status.set_text("Initialization cancelled");
+                                }
+                                Err(e) => {
+                                    status.set_text(&error_text("Init failed", &e));
+                                    status.add_css_class("error");
+                                }
+                            }
+                            slot.finish();
+                            btn.set_label("Initialize");
+                        });
+                    });
+                }
+
+                // Search entry row
+                let search_row = adw::ActionRow::new();
+                search_row.set_title("Search Keys");
+                search_row.set_subtitle("Fuzzy search across all stored credentials");
+                let search_entry = gtk4::Entry::new();
+                search_entry.set_placeholder_text(Some("Search..."));
+                search_entry.set_hexpand(true);
+                search_entry.set_valign(gtk4::Align::Center);
+                search_row.add_suffix(&search_entry);
+                let search_all_check = gtk4::CheckButton::with_label("All Databases");
+                search_all_check.set_valign(gtk4::Align::Center);
+                search_all_check.set_tooltip_text(Some("Also search the unlocked databases"));
+                search_all_check.set_visible(keystores.len() > 1);
+                search_row.add_suffix(&search_all_check);
+                search_row.set_activatable_widget(Some(&search_entry));
+                keys_group.add(&search_row);
+
+                // Search results label (hidden initially)
+                let search_results_label = gtk4::Label::new(None);
+                search_results_label.set_wrap(true);
+                search_results_label.set_xalign(0.0);
+                search_results_label.add_css_class("dim-label");
+                search_results_label.add_css_class("monospace");
+                search_results_label.set_visible(false);
+
+                // Wire search entry activate
+                {
+                    let results_label = search_results_label.clone();
+                    let search_all = search_all_check.clone();
+                    let keystores = keystores.clone();
+                    search_entry.connect_activate(move |entry| {
+                        let query = entry.text().to_string();
+                        if query.is_empty() {
+                            return;
+                        }
+                        let label = results_label.clone();
+                        label.set_text("Searching...");
+                        label.set_visible(true);
+                        let spanned = search_all.is_active().then(|| keystores.clone());
+
+                        glib::spawn_future_local(async move {
+                            let mut text = match spanned {
+                                Some(keystores) => {
+                                    search_databases_async(query.clone(), keystores).await
+                                }
+                                None => match run_cli_args_async(vec![
+                                    "keys".into(),
+                                    "search".into(),
+                                    query.clone(),
+                                ])
+                                .await
+                                {
+                                    Ok(output) => output,
+                                    Err(e) => error_text("Search error", &e),
+                                },
+                            };
+                            // Other mounted stores (pass, ...) are searched too
+                            let backend_hits =
+                                gio::spawn_blocking(move || backend::search_all(&query))
+                                    .await
+                                    .unwrap_or_default();
+
+                            if !backend_hits.is_empty() {
+                                text.push_str("\nOther stores:\n");
+                                for hit in backend_hits {
+                                    text.push_str(&format!("  {}\n", hit));
+                                }
+                            }
+                            label.set_text(text.trim_end());
+                        });
+                    });
+                }
+
+                // Ingest .env row
+                let ingest_row = adw::ActionRow::new();
+                ingest_row.set_title("Ingest .env File");
+                ingest_row.set_subtitle("Import environment variables into key store");
+                let ingest_button = gtk4::Button::with_label("Choose File");
+                ingest_button.set_valign(gtk4::Align::Center);
+                ingest_row.add_suffix(&ingest_button);
+                ingest_row.set_activatable_widget(Some(&ingest_button));
+                keys_group.add(&ingest_row);
+
+                // Shared progress row for discover, crawl and ingest
+                let progress_row = ProgressRow::new();
+
+                // Wire ingest button to open file chooser
+                {
+                    let status_clone = status_label.clone();
+                    let progress_clone = progress_row.clone();
+                    let window_ref = self.obj().clone();
+                    ingest_button.connect_clicked(move |_button| {
+                        let dialog = gtk4::FileDialog::new();
+                        dialog.set_title("Select .env file");
+                        let filter = gtk4::FileFilter::new();
+                        filter.add_pattern("*.env");
+                        filter.add_pattern(".env*");
+                        filter.set_name(Some("Environment files"));
+                        let filters = gio::ListStore::new::<gtk4::FileFilter>();
+                        filters.append(&filter);
+                        dialog.set_filters(Some(&filters));
+
+                        let status = status_clone.clone();
+                        let progress = progress_clone.clone();
+                        dialog.open(Some(&window_ref), gio::Cancellable::NONE, move |result| {
+                            if let Ok(file) = result {
+                                if let Some(path) = portal::file_path(&file) {
+                                    let path_str = path.to_string_lossy().to_string();
+                                    let st = status.clone();
+                                    st.set_text(&format!("Ingesting {}...", &path_str));
+                                    st.set_visible(true);
+                                    st.remove_css_class("error");
+                                    st.remove_css_class("success");
+                                    let progress = progress.clone();
+
+                                    glib::spawn_future_local(async move {
+                                        let result = run_cli_progress_async(
+                                            cli::args(&["keys", "ingest", &path_str]),
+                                            CancelToken::new(),
+                                            &progress,
+                                        )
+                                        .await;
+                                        match result {
+                                            Ok(output) => {
+                                                st.set_text(&format!(
+                                                    "Ingested: {}",
+                                                    output.lines().last().unwrap_or("done")
+                                                ));
+                                                st.add_css_class("success");
+                                            }
+                                            Err(e) => {
+                                                st.set_text(&error_text("Ingest failed", &e));
+                                                st.add_css_class("error");
+                                            }
+                                        }
+                                    });
+                                }
+                            }
+                        });
+                    });
+                }
+
+                // Get/Copy credential row
+                let get_row = adw::ActionRow::new();
+                get_row.set_title("Get Credential");
+                get_row.set_subtitle("Retrieve and copy a secret to clipboard");
+                let get_entry = gtk4::Entry::new();
+                get_entry.set_placeholder_text(Some("Entry path..."));
+                get_entry.set_hexpand(true);
+                get_entry.set_valign(gtk4::Align::Center);
+                let copy_button = gtk4::Button::with_label("Copy");
+                copy_button.set_valign(gtk4::Align::Center);
+                get_row.add_suffix(&get_entry);
+                get_row.add_suffix(&copy_button);
+                keys_group.add(&get_row);
+
+                // Wire copy button
+                {
+                    let entry_clone = get_entry.clone();
+                    let status_clone = status_label.clone();
+                    copy_button.connect_clicked(move |_| {
+                        let path = entry_clone.text().to_string();
+                        if path.is_empty() {
+                            return;
+                        }
+                        let status = status_clone.clone();
+                        glib::spawn_future_local(async move {
+                            let result = get_secret_async(path).await;
+                            match result {
+                                Ok(value) => {
                                     let display = gdk::Display::default().unwrap();
                                     let clipboard = display.clipboard();
                                     clipboard.set_text(value.trim());
@@ -738,308 +9941,3701 @@ mod imp {
                                     status.remove_css_class("error");
                                     status.add_css_class("success");
                                 }
-                                Err(e) => {
-                                    status.set_text(&format!("Get failed: {}", e));
-                                    status.set_visible(true);
-                                    status.remove_css_class("success");
-                                    status.add_css_class("error");
+                                Err(e) => {
+                                    status.set_text(&error_text("Get failed", &e));
+                                    status.set_visible(true);
+                                    status.remove_css_class("success");
+                                    status.add_css_class("error");
+                                }
+                            }
+                        });
+                    });
+                }
+
+                // TOTP row: live code for an entry holding an otpauth:// URI
+                let totp_row = adw::ActionRow::new();
+                totp_row.set_title("One-Time Code");
+                totp_row.set_subtitle("Live TOTP code for an entry with an otpauth:// URI");
+                let totp_entry = gtk4::Entry::new();
+                totp_entry.set_placeholder_text(Some("Entry path..."));
+                totp_entry.set_hexpand(true);
+                totp_entry.set_valign(gtk4::Align::Center);
+                let totp_button = gtk4::Button::with_label("Show");
+                totp_button.set_valign(gtk4::Align::Center);
+                totp_row.add_suffix(&totp_entry);
+                totp_row.add_suffix(&totp_button);
+                keys_group.add(&totp_row);
+
+                let code_row = adw::ActionRow::new();
+                code_row.add_css_class("property");
+                let code_copy_button = gtk4::Button::from_icon_name("edit-copy-symbolic");
+                code_copy_button.set_valign(gtk4::Align::Center);
+                code_copy_button.set_tooltip_text(Some("Copy code"));
+                code_copy_button.add_css_class("flat");
+                code_row.add_suffix(&code_copy_button);
+                code_row.set_visible(false);
+                keys_group.add(&code_row);
+
+                let current_totp: Rc<RefCell<Option<Totp>>> = Rc::new(RefCell::new(None));
+
+                // Refresh the code and countdown every second while shown
+                let update_code = {
+                    let code_row = code_row.clone();
+                    let current = current_totp.clone();
+                    move || {
+                        if let Some(totp) = current.borrow().as_ref() {
+                            let (code, remaining) = totp.now();
+                            code_row.set_title(&code);
+                            code_row.set_subtitle(&format!(
+                                "{} · {}s left",
+                                totp.label(),
+                                remaining
+                            ));
+                        }
+                    }
+                };
+                {
+                    let code_row = code_row.clone();
+                    let update = update_code.clone();
+                    glib::timeout_add_seconds_local(1, move || {
+                        // Stop once the window is rebuilt or closed
+                        if code_row.root().is_none() {
+                            return glib::ControlFlow::Break;
+                        }
+                        if code_row.is_visible() {
+                            update();
+                        }
+                        glib::ControlFlow::Continue
+                    });
+                }
+
+                // Wire show button
+                {
+                    let entry = totp_entry.clone();
+                    let code_row = code_row.clone();
+                    let current = current_totp.clone();
+                    let status = status_label.clone();
+                    totp_button.connect_clicked(move |button| {
+                        let path = entry.text().trim().to_string();
+                        if path.is_empty() {
+                            return;
+                        }
+                        button.set_sensitive(false);
+                        let btn = button.clone();
+                        let code_row = code_row.clone();
+                        let current = current.clone();
+                        let status = status.clone();
+                        let update = update_code.clone();
+                        glib::spawn_future_local(async move {
+                            let result = get_secret_async(path)
+                                .await
+                                .and_then(|value| Totp::parse_uri(&value));
+                            match result {
+                                Ok(totp) => {
+                                    *current.borrow_mut() = Some(totp);
+                                    update();
+                                    code_row.set_visible(true);
+                                }
+                                Err(e) => {
+                                    *current.borrow_mut() = None;
+                                    code_row.set_visible(false);
+                                    show_status(
+                                        &status,
+                                        &error_text("No one-time code", &e),
+                                        Some("error"),
+                                    );
+                                }
+                            }
+                            btn.set_sensitive(true);
+                        });
+                    });
+                }
+
+                // Wire code copy button
+                {
+                    let current = current_totp.clone();
+                    let status = status_label.clone();
+                    code_copy_button.connect_clicked(move |_| {
+                        if let Some(totp) = current.borrow().as_ref() {
+                            let display = gdk::Display::default().unwrap();
+                            display.clipboard().set_text(&totp.now().0);
+                            show_status(&status, "Code copied to clipboard", Some("success"));
+                        }
+                    });
+                }
+
+                // Store credential row
+                let store_row = adw::ActionRow::new();
+                store_row.set_title("Store Credential");
+                store_row.set_subtitle("Store a new secret in the key store");
+                let store_path_entry = gtk4::Entry::new();
+                store_path_entry.set_placeholder_text(Some("Path (e.g. RemoteJuggler/API/KEY)"));
+                store_path_entry.set_hexpand(true);
+                store_path_entry.set_valign(gtk4::Align::Center);
+                let store_value_entry = gtk4::PasswordEntry::new();
+                store_value_entry.set_placeholder_text(Some("Secret value"));
+                store_value_entry.set_hexpand(true);
+                store_value_entry.set_valign(gtk4::Align::Center);
+                store_value_entry.set_show_peek_icon(true);
+                let store_cred_button = gtk4::Button::with_label("Store");
+                store_cred_button.set_valign(gtk4::Align::Center);
+                store_cred_button.add_css_class("suggested-action");
+                store_row.add_suffix(&store_path_entry);
+                store_row.add_suffix(&store_value_entry);
+                store_row.add_suffix(&store_cred_button);
+                keys_group.add(&store_row);
+
+                // Wire store credential button
+                {
+                    let path_clone = store_path_entry.clone();
+                    let value_clone = store_value_entry.clone();
+                    let status_clone = status_label.clone();
+                    store_cred_button.connect_clicked(move |button| {
+                        let path = path_clone.text().to_string();
+                        let value = value_clone.text().to_string();
+                        if path.is_empty() || value.is_empty() {
+                            return;
+                        }
+                        button.set_sensitive(false);
+                        let btn = button.clone();
+                        let status = status_clone.clone();
+                        let pc = path_clone.clone();
+                        let vc = value_clone.clone();
+                        glib::spawn_future_local(async move {
+                            let result = store_secret_async(path.clone(), value).await;
+                            match result {
+                                Ok(_) => {
+                                    status.set_text(&format!("Stored: {}", path));
+                                    status.set_visible(true);
+                                    status.remove_css_class("error");
+                                    status.add_css_class("success");
+                                    pc.set_text("");
+                                    vc.set_text("");
+                                    // A new value restarts the rotation clock
+                                    let _ =
+                                        gio::spawn_blocking(move || expiry::record_rotation(&path))
+                                            .await;
+                                }
+                                Err(e) => {
+                                    status.set_text(&error_text("Store failed", &e));
+                                    status.set_visible(true);
+                                    status.remove_css_class("success");
+                                    status.add_css_class("error");
+                                }
+                            }
+                            btn.set_sensitive(true);
+                        });
+                    });
+                }
+
+                // Expiry and rotation interval for an entry
+                let expiry_row = adw::ActionRow::new();
+                expiry_row.set_title("Expiry & Rotation");
+                expiry_row.set_subtitle(
+                    "Get reminded before a secret expires; leave both empty to stop tracking",
+                );
+                let expiry_path_entry = gtk4::Entry::new();
+                expiry_path_entry.set_placeholder_text(Some("Entry path..."));
+                expiry_path_entry.set_hexpand(true);
+                expiry_path_entry.set_valign(gtk4::Align::Center);
+                let expiry_date_entry = gtk4::Entry::new();
+                expiry_date_entry.set_placeholder_text(Some("Expires (YYYY-MM-DD)"));
+                expiry_date_entry.set_valign(gtk4::Align::Center);
+                let rotation_days_entry = gtk4::Entry::new();
+                rotation_days_entry.set_placeholder_text(Some("Rotate every N days"));
+                rotation_days_entry.set_input_purpose(gtk4::InputPurpose::Digits);
+                rotation_days_entry.set_valign(gtk4::Align::Center);
+                let expiry_button = gtk4::Button::with_label("Save");
+                expiry_button.set_valign(gtk4::Align::Center);
+                expiry_row.add_suffix(&expiry_path_entry);
+                expiry_row.add_suffix(&expiry_date_entry);
+                expiry_row.add_suffix(&rotation_days_entry);
+                expiry_row.add_suffix(&expiry_button);
+                keys_group.add(&expiry_row);
+
+                // Wire expiry save button
+                {
+                    let path_entry = expiry_path_entry.clone();
+                    let date_entry = expiry_date_entry.clone();
+                    let days_entry = rotation_days_entry.clone();
+                    let status = status_label.clone();
+                    let imp_weak = self.downgrade();
+                    expiry_button.connect_clicked(move |_| {
+                        let path = path_entry.text().trim().to_string();
+                        if path.is_empty() {
+                            return;
+                        }
+                        let date = date_entry.text().trim().to_string();
+                        let expires_at = match date.as_str() {
+                            "" => None,
+                            date => match expiry::parse_date(date) {
+                                Some(at) => Some(at),
+                                None => {
+                                    show_status(
+                                        &status,
+                                        "Expiry must be a date like 2026-12-31",
+                                        Some("error"),
+                                    );
+                                    return;
+                                }
+                            },
+                        };
+                        let days = days_entry.text().trim().to_string();
+                        let rotation_days = match days.as_str() {
+                            "" => None,
+                            days => match days.parse::<u32>().ok().filter(|d| *d > 0) {
+                                Some(d) => Some(d),
+                                None => {
+                                    show_status(
+                                        &status,
+                                        "Rotation interval must be a number of days",
+                                        Some("error"),
+                                    );
+                                    return;
+                                }
+                            },
+                        };
+
+                        let mut store = ExpiryStore::load();
+                        store.set(
+                            &path,
+                            EntryExpiry {
+                                expires_at,
+                                rotation_days,
+                                // Editing the settings keeps the rotation clock
+                                last_rotated: store.entries.get(&path).and_then(|e| e.last_rotated),
+                            },
+                            expiry::now(),
+                        );
+                        match store.save() {
+                            Ok(()) if expires_at.is_none() && rotation_days.is_none() => {
+                                show_status(
+                                    &status,
+                                    &format!("Stopped tracking expiry of {}", path),
+                                    Some("success"),
+                                );
+                            }
+                            Ok(()) => {
+                                show_status(
+                                    &status,
+                                    &format!("Expiry saved for {}", path),
+                                    Some("success"),
+                                );
+                            }
+                            Err(e) => {
+                                show_status(
+                                    &status,
+                                    &format!("Failed to save expiry: {}", e),
+                                    Some("error"),
+                                );
+                                return;
+                            }
+                        }
+                        path_entry.set_text("");
+                        date_entry.set_text("");
+                        days_entry.set_text("");
+                        if let Some(imp) = imp_weak.upgrade() {
+                            imp.check_rotation();
+                        }
+                    });
+                }
+
+                // File attachments of an entry
+                let attach_expander = adw::ExpanderRow::new();
+                attach_expander.set_title("Attachments");
+                attach_expander
+                    .set_subtitle("Files kept with an entry, like kubeconfigs or .p12 bundles");
+                keys_group.add(&attach_expander);
+
+                let attach_path_row = adw::ActionRow::new();
+                attach_path_row.set_title("Entry");
+                let attach_path_entry = gtk4::Entry::new();
+                attach_path_entry.set_placeholder_text(Some("Entry path..."));
+                attach_path_entry.set_hexpand(true);
+                attach_path_entry.set_valign(gtk4::Align::Center);
+                let attach_list_button = gtk4::Button::with_label("Show");
+                attach_list_button.set_valign(gtk4::Align::Center);
+                let attach_file_button = gtk4::Button::with_label("Attach File...");
+                attach_file_button.set_valign(gtk4::Align::Center);
+                attach_path_row.add_suffix(&attach_path_entry);
+                attach_path_row.add_suffix(&attach_list_button);
+                attach_path_row.add_suffix(&attach_file_button);
+                attach_expander.add_row(&attach_path_row);
+
+                let (wipe_row, wipe_button) = button_row(
+                    "Wipe Downloads",
+                    "Securely delete attachments opened from the key store",
+                    "Wipe",
+                );
+                attach_expander.add_row(&wipe_row);
+
+                let attachment_rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::default();
+
+                // Wire show attachments button and entry activation
+                {
+                    let list = AttachmentList {
+                        expander: attach_expander.clone(),
+                        rows: attachment_rows.clone(),
+                        status: status_label.clone(),
+                        window: self.obj().clone(),
+                    };
+                    let path_entry = attach_path_entry.clone();
+                    let show = Rc::new(move || {
+                        let entry = path_entry.text().trim().to_string();
+                        if !entry.is_empty() {
+                            list.load(entry);
+                        }
+                    });
+                    let show_clone = show.clone();
+                    attach_list_button.connect_clicked(move |_| show_clone());
+                    attach_path_entry.connect_activate(move |_| show());
+                }
+
+                // Wire attach button to open a file chooser
+                {
+                    let list = AttachmentList {
+                        expander: attach_expander.clone(),
+                        rows: attachment_rows.clone(),
+                        status: status_label.clone(),
+                        window: self.obj().clone(),
+                    };
+                    let path_entry = attach_path_entry.clone();
+                    attach_file_button.connect_clicked(move |_| {
+                        let entry = path_entry.text().trim().to_string();
+                        if entry.is_empty() {
+                            show_status(
+                                &list.status,
+                                "Enter the entry to attach the file to",
+                                Some("error"),
+                            );
+                            return;
+                        }
+                        let dialog = gtk4::FileDialog::new();
+                        dialog.set_title("Attach File");
+                        let window = list.window.clone();
+                        let list = list.clone();
+                        dialog.open(Some(&window), gio::Cancellable::NONE, move |result| {
+                            let Some(path) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                                return;
+                            };
+                            let name = path
+                                .file_name()
+                                .map(|n| n.to_string_lossy().to_string())
+                                .unwrap_or_else(|| "attachment".to_string());
+                            show_status(&list.status, &format!("Attaching {}...", name), None);
+                            glib::spawn_future_local(async move {
+                                let args = attachments::attach_args(&entry, &path, &name);
+                                match run_cli_checked_async(args).await {
+                                    Ok(_) => {
+                                        show_status(
+                                            &list.status,
+                                            &format!("Attached {} to {}", name, entry),
+                                            Some("success"),
+                                        );
+                                        list.load(entry);
+                                    }
+                                    Err(e) => show_status(
+                                        &list.status,
+                                        &error_text("Attach failed", &e),
+                                        Some("error"),
+                                    ),
+                                }
+                            });
+                        });
+                    });
+                }
+
+                // Wire wipe downloads button
+                {
+                    let status = status_label.clone();
+                    wipe_button.connect_clicked(move |button| {
+                        button.set_sensitive(false);
+                        let btn = button.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(attachments::wipe_downloads)
+                                .await
+                                .unwrap_or_else(|_| Err(std::io::Error::other("worker panicked")));
+                            match result {
+                                Ok(0) => show_status(&status, "No downloaded attachments", None),
+                                Ok(n) => show_status(
+                                    &status,
+                                    &format!("Securely deleted {} downloaded attachment(s)", n),
+                                    Some("success"),
+                                ),
+                                Err(e) => show_status(
+                                    &status,
+                                    &format!("Wipe failed: {}", e),
+                                    Some("error"),
+                                ),
+                            }
+                            btn.set_sensitive(true);
+                        });
+                    });
+                }
+
+                // Previous values of an entry, with restore
+                let history_expander = adw::ExpanderRow::new();
+                history_expander.set_title("History");
+                history_expander
+                    .set_subtitle("Previous values of an entry; restore one to undo an overwrite");
+                keys_group.add(&history_expander);
+
+                let history_path_row = adw::ActionRow::new();
+                history_path_row.set_title("Entry");
+                let history_path_entry = gtk4::Entry::new();
+                history_path_entry.set_placeholder_text(Some("Entry path..."));
+                history_path_entry.set_hexpand(true);
+                history_path_entry.set_valign(gtk4::Align::Center);
+                let history_button = gtk4::Button::with_label("Show");
+                history_button.set_valign(gtk4::Align::Center);
+                history_path_row.add_suffix(&history_path_entry);
+                history_path_row.add_suffix(&history_button);
+                history_expander.add_row(&history_path_row);
+
+                // Wire show history button and entry activation
+                {
+                    let list = HistoryList {
+                        expander: history_expander.clone(),
+                        rows: Rc::default(),
+                        status: status_label.clone(),
+                    };
+                    let path_entry = history_path_entry.clone();
+                    let show = Rc::new(move || {
+                        let entry = path_entry.text().trim().to_string();
+                        if !entry.is_empty() {
+                            list.load(entry);
+                        }
+                    });
+                    let show_clone = show.clone();
+                    history_button.connect_clicked(move |_| show_clone());
+                    history_path_entry.connect_activate(move |_| show());
+                }
+
+                // Merge another copy of the database, reviewing changes first
+                let sync_expander = adw::ExpanderRow::new();
+                sync_expander.set_title("Sync With Another Database");
+                sync_expander.set_subtitle(
+                    "Merge a .kdbx copy from Syncthing or a USB stick; newer entries win",
+                );
+                keys_group.add(&sync_expander);
+
+                let sync_file_row = adw::ActionRow::new();
+                sync_file_row.set_title("Database");
+                sync_file_row.set_subtitle("No file chosen");
+                let sync_choose_button = gtk4::Button::with_label("Choose...");
+                sync_choose_button.set_valign(gtk4::Align::Center);
+                sync_file_row.add_suffix(&sync_choose_button);
+                sync_expander.add_row(&sync_file_row);
+
+                let sync_password_row = adw::ActionRow::new();
+                sync_password_row.set_title("Password");
+                sync_password_row.set_subtitle("Leave empty if it matches the key store's");
+                let sync_password_entry = gtk4::PasswordEntry::new();
+                sync_password_entry.set_show_peek_icon(true);
+                sync_password_entry.set_valign(gtk4::Align::Center);
+                sync_password_row.add_suffix(&sync_password_entry);
+                sync_expander.add_row(&sync_password_row);
+
+                let sync_action_row = adw::ActionRow::new();
+                sync_action_row.set_title("Changes");
+                sync_action_row.set_subtitle("Review what the merge would do before applying it");
+                let sync_review_button = gtk4::Button::with_label("Review");
+                sync_review_button.set_valign(gtk4::Align::Center);
+                sync_review_button.set_sensitive(false);
+                let sync_merge_button = gtk4::Button::with_label("Merge");
+                sync_merge_button.set_valign(gtk4::Align::Center);
+                sync_merge_button.add_css_class("suggested-action");
+                sync_merge_button.set_sensitive(false);
+                sync_action_row.add_suffix(&sync_review_button);
+                sync_action_row.add_suffix(&sync_merge_button);
+                sync_expander.add_row(&sync_action_row);
+
+                let sync_file: Rc<RefCell<Option<PathBuf>>> = Rc::default();
+                let sync_rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::default();
+
+                // Wire choose button to a file chooser; a new file needs a new review
+                {
+                    let window_ref = self.obj().clone();
+                    let file = sync_file.clone();
+                    let file_row = sync_file_row.clone();
+                    let review = sync_review_button.clone();
+                    let merge_button = sync_merge_button.clone();
+                    sync_choose_button.connect_clicked(move |_| {
+                        let dialog = gtk4::FileDialog::new();
+                        dialog.set_title("Select Database to Merge");
+                        let filter = gtk4::FileFilter::new();
+                        filter.add_pattern("*.kdbx");
+                        filter.set_name(Some("KeePass databases"));
+                        let filters = gio::ListStore::new::<gtk4::FileFilter>();
+                        filters.append(&filter);
+                        dialog.set_filters(Some(&filters));
+
+                        let file = file.clone();
+                        let file_row = file_row.clone();
+                        let review = review.clone();
+                        let merge_button = merge_button.clone();
+                        dialog.open(Some(&window_ref), gio::Cancellable::NONE, move |result| {
+                            let Some(path) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                                return;
+                            };
+                            file_row
+                                .set_subtitle(&glib::markup_escape_text(&path.to_string_lossy()));
+                            *file.borrow_mut() = Some(path);
+                            review.set_sensitive(true);
+                            merge_button.set_sensitive(false);
+                        });
+                    });
+                }
+
+                // Wire review button: dry run, then list the changes
+                {
+                    let file = sync_file.clone();
+                    let password = sync_password_entry.clone();
+                    let expander = sync_expander.clone();
+                    let rows = sync_rows.clone();
+                    let merge_button = sync_merge_button.clone();
+                    let status = status_label.clone();
+                    sync_review_button.connect_clicked(move |button| {
+                        let Some(path) = file.borrow().clone() else {
+                            return;
+                        };
+                        button.set_sensitive(false);
+                        merge_button.set_sensitive(false);
+                        show_status(&status, "Comparing databases...", None);
+                        let btn = button.clone();
+                        let password = password.text().to_string();
+                        let expander = expander.clone();
+                        let rows = rows.clone();
+                        let merge_button = merge_button.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = run_sync_async(path, password, true)
+                                .await
+                                .and_then(|output| merge::parse_changes(&output));
+                            for row in rows.borrow_mut().drain(..) {
+                                expander.remove(&row);
+                            }
+                            match result {
+                                Ok(changes) => {
+                                    for change in &changes {
+                                        let row = adw::ActionRow::new();
+                                        row.set_title(&glib::markup_escape_text(&change.title));
+                                        row.set_subtitle(change.kind.display_name());
+                                        if change.needs_review() {
+                                            let label = gtk4::Label::new(Some(
+                                                if change.kind == ChangeKind::Deleted {
+                                                    "Removed locally"
+                                                } else {
+                                                    "Local value goes to history"
+                                                },
+                                            ));
+                                            label.add_css_class("warning");
+                                            row.add_suffix(&label);
+                                        }
+                                        expander.add_row(&row);
+                                        rows.borrow_mut().push(row);
+                                    }
+                                    let to_review =
+                                        changes.iter().filter(|c| c.needs_review()).count();
+                                    let mut text = merge::summary(&changes);
+                                    if to_review > 0 {
+                                        text.push_str(&format!(
+                                            "\n{} local value(s) will be replaced; \
+                                             they stay restorable from History",
+                                            to_review
+                                        ));
+                                    }
+                                    show_status(&status, &text, None);
+                                    merge_button.set_sensitive(!changes.is_empty());
+                                }
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Review failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                            btn.set_sensitive(true);
+                        });
+                    });
+                }
+
+                // Wire merge button: apply the reviewed merge
+                {
+                    let file = sync_file.clone();
+                    let password = sync_password_entry.clone();
+                    let expander = sync_expander.clone();
+                    let rows = sync_rows.clone();
+                    let status = status_label.clone();
+                    sync_merge_button.connect_clicked(move |button| {
+                        let Some(path) = file.borrow().clone() else {
+                            return;
+                        };
+                        button.set_sensitive(false);
+                        show_status(&status, "Merging...", None);
+                        let password_entry = password.clone();
+                        let password = password.text().to_string();
+                        let expander = expander.clone();
+                        let rows = rows.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            match run_sync_async(path.clone(), password, false).await {
+                                Ok(_) => {
+                                    for row in rows.borrow_mut().drain(..) {
+                                        expander.remove(&row);
+                                    }
+                                    password_entry.set_text("");
+                                    show_status(
+                                        &status,
+                                        &format!("Merged {} into the key store", path.display()),
+                                        Some("success"),
+                                    );
+                                }
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Merge failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                        });
+                    });
+                }
+
+                // Export a group or search results to another format
+                let export_expander = adw::ExpanderRow::new();
+                export_expander.set_title("Export Entries");
+                export_expander.set_subtitle(
+                    "Write a group or search results as .env, CSV, JSON, or a Secret",
+                );
+                keys_group.add(&export_expander);
+
+                let export_source_row = adw::ComboRow::new();
+                export_source_row.set_title("Source");
+                export_source_row
+                    .set_model(Some(&gtk4::StringList::new(&["Group", "Search Results"])));
+                let export_source_entry = gtk4::Entry::new();
+                export_source_entry.set_placeholder_text(Some("RemoteJuggler/API"));
+                export_source_entry.set_valign(gtk4::Align::Center);
+                export_source_row.add_suffix(&export_source_entry);
+                export_expander.add_row(&export_source_row);
+
+                let export_format_row = adw::ComboRow::new();
+                export_format_row.set_title("Format");
+                let format_names: Vec<&str> = export::ExportFormat::all()
+                    .iter()
+                    .map(|f| f.display_name())
+                    .collect();
+                export_format_row.set_model(Some(&gtk4::StringList::new(&format_names)));
+                export_expander.add_row(&export_format_row);
+
+                let export_secret_row = adw::ActionRow::new();
+                export_secret_row.set_title("Secret");
+                export_secret_row.set_subtitle("Name and namespace; sealed with kubeseal");
+                let export_secret_name = gtk4::Entry::new();
+                export_secret_name.set_placeholder_text(Some("Name"));
+                export_secret_name.set_valign(gtk4::Align::Center);
+                let export_namespace = gtk4::Entry::new();
+                export_namespace.set_placeholder_text(Some("Namespace"));
+                export_namespace.set_valign(gtk4::Align::Center);
+                export_secret_row.add_suffix(&export_secret_name);
+                export_secret_row.add_suffix(&export_namespace);
+                export_secret_row.set_visible(false);
+                export_expander.add_row(&export_secret_row);
+
+                let export_action_row = adw::ActionRow::new();
+                export_action_row.set_title("Field Mapping");
+                export_action_row.set_subtitle("Preview the keys each entry is written as");
+                let export_preview_button = gtk4::Button::with_label("Preview");
+                export_preview_button.set_valign(gtk4::Align::Center);
+                let export_save_button = gtk4::Button::with_label("Save...");
+                export_save_button.set_valign(gtk4::Align::Center);
+                export_save_button.add_css_class("suggested-action");
+                export_save_button.set_sensitive(false);
+                export_action_row.add_suffix(&export_preview_button);
+                export_action_row.add_suffix(&export_save_button);
+                export_expander.add_row(&export_action_row);
+
+                let export_fields: Rc<RefCell<Vec<export::Field>>> = Rc::default();
+                let export_rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::default();
+
+                // Any change of source or format needs a new preview
+                let export_reset = {
+                    let fields = export_fields.clone();
+                    let rows = export_rows.clone();
+                    let expander = export_expander.clone();
+                    let save = export_save_button.clone();
+                    Rc::new(move || {
+                        fields.borrow_mut().clear();
+                        for row in rows.borrow_mut().drain(..) {
+                            expander.remove(&row);
+                        }
+                        save.set_sensitive(false);
+                    })
+                };
+                {
+                    let reset = export_reset.clone();
+                    export_source_entry.connect_changed(move |_| reset());
+                    let reset = export_reset.clone();
+                    export_source_row.connect_selected_notify(move |_| reset());
+                    let reset = export_reset.clone();
+                    let secret_row = export_secret_row.clone();
+                    export_format_row.connect_selected_notify(move |row| {
+                        let format = export::ExportFormat::from_index(row.selected());
+                        secret_row.set_visible(format == export::ExportFormat::SealedSecret);
+                        reset();
+                    });
+                }
+
+                // Wire preview button: read the values and show the mapping
+                {
+                    let source_row = export_source_row.clone();
+                    let source = export_source_entry.clone();
+                    let format_row = export_format_row.clone();
+                    let secret_name = export_secret_name.clone();
+                    let fields = export_fields.clone();
+                    let rows = export_rows.clone();
+                    let expander = export_expander.clone();
+                    let save = export_save_button.clone();
+                    let reset = export_reset.clone();
+                    let status = status_label.clone();
+                    export_preview_button.connect_clicked(move |button| {
+                        let text = source.text().trim().to_string();
+                        if text.is_empty() {
+                            show_status(&status, "Enter a group or search query", Some("error"));
+                            return;
+                        }
+                        reset();
+                        let is_search = source_row.selected() == 1;
+                        let format = export::ExportFormat::from_index(format_row.selected());
+                        if secret_name.text().is_empty() {
+                            secret_name.set_text(&export::secret_name(&text));
+                        }
+                        button.set_sensitive(false);
+                        show_status(&status, "Reading entries...", None);
+                        let btn = button.clone();
+                        let fields = fields.clone();
+                        let rows = rows.clone();
+                        let expander = expander.clone();
+                        let save = save.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || {
+                                if is_search {
+                                    export::load_search(&text)
+                                } else {
+                                    export::load_group(&text)
+                                }
+                            })
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                            match result {
+                                Ok(entries) if entries.is_empty() => {
+                                    show_status(&status, "No entries to export", Some("error"))
+                                }
+                                Ok(entries) => {
+                                    let mapped = export::map_fields(entries, format);
+                                    for field in &mapped {
+                                        let row = adw::ActionRow::new();
+                                        row.set_title(&glib::markup_escape_text(&field.key));
+                                        row.set_subtitle(&glib::markup_escape_text(&field.entry));
+                                        expander.add_row(&row);
+                                        rows.borrow_mut().push(row);
+                                    }
+                                    let mut text =
+                                        format!("{} entries ready to export", mapped.len());
+                                    if format.is_plaintext() {
+                                        text.push_str("; the file will hold them in plaintext");
+                                    }
+                                    show_status(&status, &text, None);
+                                    *fields.borrow_mut() = mapped;
+                                    save.set_sensitive(true);
+                                }
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Export failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                            btn.set_sensitive(true);
+                        });
+                    });
+                }
+
+                // Wire save button: render and write to the chosen file
+                {
+                    let window_ref = self.obj().clone();
+                    let format_row = export_format_row.clone();
+                    let secret_name = export_secret_name.clone();
+                    let namespace = export_namespace.clone();
+                    let fields = export_fields.clone();
+                    let status = status_label.clone();
+                    export_save_button.connect_clicked(move |_| {
+                        let format = export::ExportFormat::from_index(format_row.selected());
+                        let target = export::SecretTarget {
+                            name: export::secret_name(&secret_name.text()),
+                            namespace: Some(namespace.text().trim().to_string())
+                                .filter(|n| !n.is_empty()),
+                        };
+                        let dialog = gtk4::FileDialog::new();
+                        dialog.set_title("Export Entries");
+                        dialog.set_initial_name(Some(&format.file_name(&target.name)));
+
+                        let fields = fields.borrow().clone();
+                        let status = status.clone();
+                        dialog.save(Some(&window_ref), gio::Cancellable::NONE, move |result| {
+                            let Some(path) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                                return;
+                            };
+                            show_status(&status, "Exporting...", None);
+                            let status = status.clone();
+                            glib::spawn_future_local(async move {
+                                let dest = path.clone();
+                                let result = gio::spawn_blocking(move || {
+                                    let contents = export::render(&fields, format, &target)?;
+                                    export::write_private(&dest, &contents).map_err(Error::Io)
+                                })
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                                match result {
+                                    Ok(()) => show_status(
+                                        &status,
+                                        &format!("Exported to {}", path.display()),
+                                        Some("success"),
+                                    ),
+                                    Err(e) => show_status(
+                                        &status,
+                                        &error_text("Export failed", &e),
+                                        Some("error"),
+                                    ),
+                                }
+                            });
+                        });
+                    });
+                }
+
+                // Import another password manager's export
+                let import_expander = adw::ExpanderRow::new();
+                import_expander.set_title("Import From Password Manager");
+                import_expander.set_subtitle(
+                    "1Password (.1pux), LastPass CSV, Bitwarden JSON, or browser CSV",
+                );
+                keys_group.add(&import_expander);
+
+                let import_file_row = adw::ActionRow::new();
+                import_file_row.set_title("Export File");
+                import_file_row.set_subtitle("No file chosen");
+                let import_choose_button = gtk4::Button::with_label("Choose...");
+                import_choose_button.set_valign(gtk4::Align::Center);
+                import_file_row.add_suffix(&import_choose_button);
+                import_expander.add_row(&import_file_row);
+
+                let import_policy_row = adw::ComboRow::new();
+                import_policy_row.set_title("On Collision");
+                import_policy_row.set_subtitle("When the key store holds a different value");
+                let policy_names: Vec<&str> = CollisionPolicy::all()
+                    .iter()
+                    .map(|p| p.display_name())
+                    .collect();
+                import_policy_row.set_model(Some(&gtk4::StringList::new(&policy_names)));
+                import_expander.add_row(&import_policy_row);
+
+                let import_action_row = adw::ActionRow::new();
+                import_action_row.set_title("Dry Run");
+                import_action_row.set_subtitle("Review what would be imported first");
+                let import_review_button = gtk4::Button::with_label("Review");
+                import_review_button.set_valign(gtk4::Align::Center);
+                import_review_button.set_sensitive(false);
+                let import_apply_button = gtk4::Button::with_label("Import");
+                import_apply_button.set_valign(gtk4::Align::Center);
+                import_apply_button.add_css_class("suggested-action");
+                import_apply_button.set_sensitive(false);
+                import_action_row.add_suffix(&import_review_button);
+                import_action_row.add_suffix(&import_apply_button);
+                import_expander.add_row(&import_action_row);
+
+                let foreign_progress = ProgressRow::new();
+                import_expander.add_row(foreign_progress.widget());
+
+                let import_file: Rc<RefCell<Option<PathBuf>>> = Rc::default();
+                let import_plan: Rc<RefCell<Vec<importers::PlannedImport>>> = Rc::default();
+                let import_rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::default();
+
+                // Wire choose button; a new file needs a new review
+                {
+                    let window_ref = self.obj().clone();
+                    let file = import_file.clone();
+                    let file_row = import_file_row.clone();
+                    let review = import_review_button.clone();
+                    let apply = import_apply_button.clone();
+                    import_choose_button.connect_clicked(move |_| {
+                        let dialog = gtk4::FileDialog::new();
+                        dialog.set_title("Select Export to Import");
+                        let filter = gtk4::FileFilter::new();
+                        for pattern in ["*.1pux", "*.csv", "*.json"] {
+                            filter.add_pattern(pattern);
+                        }
+                        filter.set_name(Some("Password manager exports"));
+                        let filters = gio::ListStore::new::<gtk4::FileFilter>();
+                        filters.append(&filter);
+                        dialog.set_filters(Some(&filters));
+
+                        let file = file.clone();
+                        let file_row = file_row.clone();
+                        let review = review.clone();
+                        let apply = apply.clone();
+                        dialog.open(Some(&window_ref), gio::Cancellable::NONE, move |result| {
+                            let Some(path) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                                return;
+                            };
+                            file_row
+                                .set_subtitle(&glib::markup_escape_text(&path.to_string_lossy()));
+                            *file.borrow_mut() = Some(path);
+                            review.set_sensitive(true);
+                            apply.set_sensitive(false);
+                        });
+                    });
+                }
+
+                // Wire review button: parse and plan, then list the result
+                {
+                    let file = import_file.clone();
+                    let plan = import_plan.clone();
+                    let rows = import_rows.clone();
+                    let expander = import_expander.clone();
+                    let file_row = import_file_row.clone();
+                    let apply = import_apply_button.clone();
+                    let status = status_label.clone();
+                    import_review_button.connect_clicked(move |button| {
+                        let Some(path) = file.borrow().clone() else {
+                            return;
+                        };
+                        button.set_sensitive(false);
+                        apply.set_sensitive(false);
+                        show_status(&status, "Reading export...", None);
+                        let btn = button.clone();
+                        let plan = plan.clone();
+                        let rows = rows.clone();
+                        let expander = expander.clone();
+                        let file_row = file_row.clone();
+                        let apply = apply.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || {
+                                let data = std::fs::read(&path)?;
+                                let format = importers::ImportFormat::detect(&path, &data)
+                                    .ok_or_else(|| Error::InvalidOutput {
+                                        program: "import",
+                                        message: "unrecognized export format".to_string(),
+                                    })?;
+                                let entries = importers::parse(format, &data)?;
+                                Ok::<_, Error>((
+                                    format,
+                                    importers::plan_against_kdbx(format, entries),
+                                ))
+                            })
+                            .await
+                            .unwrap_or_else(|_| Err(worker_panicked()));
+                            for row in rows.borrow_mut().drain(..) {
+                                expander.remove(&row);
+                            }
+                            match result {
+                                Ok((format, planned)) => {
+                                    file_row.set_subtitle(&format!(
+                                        "{} export, {} entries",
+                                        format.display_name(),
+                                        planned.len()
+                                    ));
+                                    for item in planned.iter().take(IMPORT_PREVIEW_ROWS) {
+                                        let row = adw::ActionRow::new();
+                                        row.set_title(&glib::markup_escape_text(&item.target));
+                                        let note = match &item.action {
+                                            importers::ImportAction::New => "New".to_string(),
+                                            importers::ImportAction::DuplicateInFile { of } => {
+                                                format!("Duplicate of {}", of)
+                                            }
+                                            importers::ImportAction::Unchanged => {
+                                                "Already in key store".to_string()
+                                            }
+                                            importers::ImportAction::Collision => {
+                                                "Differs from key store".to_string()
+                                            }
+                                            importers::ImportAction::Empty => {
+                                                "Nothing to import".to_string()
+                                            }
+                                        };
+                                        row.set_subtitle(&glib::markup_escape_text(&note));
+                                        if item.action == importers::ImportAction::Collision {
+                                            row.add_css_class("warning");
+                                        }
+                                        expander.add_row(&row);
+                                        rows.borrow_mut().push(row);
+                                    }
+                                    let mut text = importers::summary(&planned);
+                                    if planned.len() > IMPORT_PREVIEW_ROWS {
+                                        text.push_str(&format!(
+                                            "\nShowing the first {}",
+                                            IMPORT_PREVIEW_ROWS
+                                        ));
+                                    }
+                                    show_status(&status, &text, None);
+                                    apply.set_sensitive(!planned.is_empty());
+                                    *plan.borrow_mut() = planned;
+                                }
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Review failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                            btn.set_sensitive(true);
+                        });
+                    });
+                }
+
+                // Wire import button: apply the reviewed plan
+                {
+                    let plan = import_plan.clone();
+                    let rows = import_rows.clone();
+                    let expander = import_expander.clone();
+                    let policy_row = import_policy_row.clone();
+                    let progress = foreign_progress.clone();
+                    let status = status_label.clone();
+                    import_apply_button.connect_clicked(move |button| {
+                        let planned = std::mem::take(&mut *plan.borrow_mut());
+                        if planned.is_empty() {
+                            return;
+                        }
+                        let policy = CollisionPolicy::from_index(policy_row.selected());
+                        button.set_sensitive(false);
+                        status.set_visible(false);
+                        for row in rows.borrow_mut().drain(..) {
+                            expander.remove(&row);
+                        }
+                        let status = status.clone();
+                        let progress = progress.clone();
+                        let (sender, receiver) = async_channel::unbounded();
+
+                        glib::spawn_future_local(async move {
+                            let worker = gio::spawn_blocking(move || {
+                                importers::apply(&planned, policy, &mut |event| {
+                                    let _ = sender.send_blocking(event.clone());
+                                })
+                            });
+
+                            // The channel closes when the worker drops its sender
+                            let mut state = ProgressState::default();
+                            while let Ok(event) = receiver.recv().await {
+                                state.apply(&event);
+                                progress.show(&state);
+                            }
+                            progress.hide();
+
+                            match worker.await {
+                                Ok(report) => show_status(
+                                    &status,
+                                    &report.summary(),
+                                    Some(if report.failed.is_empty() {
+                                        "success"
+                                    } else {
+                                        "error"
+                                    }),
+                                ),
+                                Err(_) => show_status(
+                                    &status,
+                                    &error_text("Import failed", &worker_panicked()),
+                                    Some("error"),
+                                ),
+                            }
+                        });
+                    });
+                }
+
+                // Scan a repository for committed secrets
+                let scan_expander = adw::ExpanderRow::new();
+                scan_expander.set_title("Scan Repository for Secrets");
+                scan_expander.set_subtitle("Find committed tokens, private keys, and .env files");
+                keys_group.add(&scan_expander);
+
+                let scan_repo_row = adw::ActionRow::new();
+                scan_repo_row.set_title("Repository");
+                scan_repo_row.set_subtitle("No folder chosen");
+                let scan_choose_button = gtk4::Button::with_label("Choose...");
+                scan_choose_button.set_valign(gtk4::Align::Center);
+                let scan_button = gtk4::Button::with_label("Scan");
+                scan_button.set_valign(gtk4::Align::Center);
+                scan_button.set_sensitive(false);
+                scan_repo_row.add_suffix(&scan_choose_button);
+                scan_repo_row.add_suffix(&scan_button);
+                scan_expander.add_row(&scan_repo_row);
+
+                let scan_action_row = adw::ActionRow::new();
+                scan_action_row.set_title("Findings");
+                scan_action_row.set_subtitle("Ingest checked secrets into the key store");
+                let scan_ingest_button = gtk4::Button::with_label("Ingest");
+                scan_ingest_button.set_valign(gtk4::Align::Center);
+                scan_ingest_button.add_css_class("suggested-action");
+                scan_ingest_button.set_sensitive(false);
+                let scan_report_button = gtk4::Button::with_label("Save Report...");
+                scan_report_button.set_valign(gtk4::Align::Center);
+                scan_report_button.set_sensitive(false);
+                scan_action_row.add_suffix(&scan_ingest_button);
+                scan_action_row.add_suffix(&scan_report_button);
+                scan_expander.add_row(&scan_action_row);
+
+                let scan_repo: Rc<RefCell<Option<PathBuf>>> = Rc::default();
+                let scan_findings: Rc<RefCell<Vec<Finding>>> = Rc::default();
+                let scan_rows: Rc<RefCell<Vec<(adw::ActionRow, gtk4::CheckButton)>>> =
+                    Rc::default();
+
+                // Wire choose button
+                {
+                    let window_ref = self.obj().clone();
+                    let repo = scan_repo.clone();
+                    let repo_row = scan_repo_row.clone();
+                    let scan = scan_button.clone();
+                    scan_choose_button.connect_clicked(move |_| {
+                        let dialog = gtk4::FileDialog::new();
+                        dialog.set_title("Select Repository");
+                        let repo = repo.clone();
+                        let repo_row = repo_row.clone();
+                        let scan = scan.clone();
+                        dialog.select_folder(
+                            Some(&window_ref),
+                            gio::Cancellable::NONE,
+                            move |result| {
+                                let Some(path) = result.ok().and_then(|f| portal::file_path(&f))
+                                else {
+                                    return;
+                                };
+                                repo_row.set_subtitle(&glib::markup_escape_text(
+                                    &path.to_string_lossy(),
+                                ));
+                                *repo.borrow_mut() = Some(path);
+                                scan.set_sensitive(true);
+                            },
+                        );
+                    });
+                }
+
+                // Wire scan button: list findings, checked for ingestion
+                {
+                    let repo = scan_repo.clone();
+                    let findings = scan_findings.clone();
+                    let rows = scan_rows.clone();
+                    let expander = scan_expander.clone();
+                    let ingest = scan_ingest_button.clone();
+                    let report = scan_report_button.clone();
+                    let status = status_label.clone();
+                    scan_button.connect_clicked(move |button| {
+                        let Some(root) = repo.borrow().clone() else {
+                            return;
+                        };
+                        button.set_sensitive(false);
+                        show_status(&status, "Scanning...", None);
+                        let btn = button.clone();
+                        let findings = findings.clone();
+                        let rows = rows.clone();
+                        let expander = expander.clone();
+                        let ingest = ingest.clone();
+                        let report = report.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || scanner::scan(&root))
+                                .await
+                                .unwrap_or_else(|_| Err(worker_panicked()));
+                            for (row, _) in rows.borrow_mut().drain(..) {
+                                expander.remove(&row);
+                            }
+                            match result {
+                                Ok(found) => {
+                                    for finding in found.iter().take(SCAN_PREVIEW_ROWS) {
+                                        let row = adw::ActionRow::new();
+                                        row.set_title(&glib::markup_escape_text(
+                                            &finding.location(),
+                                        ));
+                                        let key = finding
+                                            .key
+                                            .as_deref()
+                                            .map(|k| format!(" {}", k))
+                                            .unwrap_or_default();
+                                        row.set_subtitle(&glib::markup_escape_text(&format!(
+                                            "{}{}: {}",
+                                            finding.kind.display_name(),
+                                            key,
+                                            finding.masked()
+                                        )));
+                                        let check = gtk4::CheckButton::new();
+                                        check.set_active(true);
+                                        check.set_valign(gtk4::Align::Center);
+                                        row.add_prefix(&check);
+                                        row.set_activatable_widget(Some(&check));
+                                        expander.add_row(&row);
+                                        rows.borrow_mut().push((row, check));
+                                    }
+                                    let mut text = match found.len() {
+                                        0 => "No secrets found".to_string(),
+                                        1 => "Found 1 secret".to_string(),
+                                        n => format!("Found {} secrets", n),
+                                    };
+                                    if found.len() > SCAN_PREVIEW_ROWS {
+                                        text.push_str(&format!(
+                                            "\nShowing the first {}",
+                                            SCAN_PREVIEW_ROWS
+                                        ));
+                                    }
+                                    show_status(
+                                        &status,
+                                        &text,
+                                        Some(if found.is_empty() { "success" } else { "error" }),
+                                    );
+                                    ingest.set_sensitive(!found.is_empty());
+                                    report.set_sensitive(true);
+                                    *findings.borrow_mut() = found;
+                                }
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Scan failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                            btn.set_sensitive(true);
+                        });
+                    });
+                }
+
+                // Wire ingest button: store checked findings under the repo's group
+                {
+                    let repo = scan_repo.clone();
+                    let findings = scan_findings.clone();
+                    let rows = scan_rows.clone();
+                    let status = status_label.clone();
+                    scan_ingest_button.connect_clicked(move |button| {
+                        let Some(root) = repo.borrow().clone() else {
+                            return;
+                        };
+                        let name = scanner::repo_name(&root);
+                        let selected: Vec<Finding> = rows
+                            .borrow()
+                            .iter()
+                            .zip(findings.borrow().iter())
+                            .filter(|((_, check), _)| check.is_active())
+                            .map(|(_, finding)| finding.clone())
+                            .collect();
+                        if selected.is_empty() {
+                            return;
+                        }
+                        button.set_sensitive(false);
+                        show_status(&status, "Ingesting...", None);
+                        let btn = button.clone();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let mut used = HashSet::new();
+                            let mut stored = 0;
+                            let mut failed = Vec::new();
+                            for finding in selected {
+                                let mut path = finding.entry_path(&name);
+                                if used.contains(&path) {
+                                    path = backend::unique_name(&path, &used);
+                                }
+                                used.insert(path.clone());
+                                match store_secret_async(path.clone(), finding.secret).await {
+                                    Ok(()) => stored += 1,
+                                    Err(_) => failed.push(path),
+                                }
+                            }
+                            cli_cache::global().invalidate();
+                            if failed.is_empty() {
+                                show_status(
+                                    &status,
+                                    &format!(
+                                        "Stored {} under {}/{}; rotate them and purge the files",
+                                        stored,
+                                        scanner::INGEST_GROUP,
+                                        name
+                                    ),
+                                    Some("success"),
+                                );
+                            } else {
+                                show_status(
+                                    &status,
+                                    &format!("Stored {}, failed: {}", stored, failed.join(", ")),
+                                    Some("error"),
+                                );
+                            }
+                            btn.set_sensitive(true);
+                        });
+                    });
+                }
+
+                // Wire report button: save the files to purge
+                {
+                    let window_ref = self.obj().clone();
+                    let repo = scan_repo.clone();
+                    let findings = scan_findings.clone();
+                    let status = status_label.clone();
+                    scan_report_button.connect_clicked(move |_| {
+                        let Some(root) = repo.borrow().clone() else {
+                            return;
+                        };
+                        let name = scanner::repo_name(&root);
+                        let contents = scanner::purge_report(&name, &findings.borrow());
+                        let dialog = gtk4::FileDialog::new();
+                        dialog.set_title("Save Scan Report");
+                        dialog.set_initial_name(Some(&format!("{}-secrets.md", name)));
+                        let status = status.clone();
+                        dialog.save(Some(&window_ref), gio::Cancellable::NONE, move |result| {
+                            let Some(path) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                                return;
+                            };
+                            match export::write_private(&path, &contents) {
+                                Ok(()) => show_status(
+                                    &status,
+                                    &format!("Report saved to {}", path.display()),
+                                    Some("success"),
+                                ),
+                                Err(e) => show_status(
+                                    &status,
+                                    &error_text("Saving report failed", &Error::Io(e)),
+                                    Some("error"),
+                                ),
+                            }
+                        });
+                    });
+                }
+
+                // Delete credential row
+                let delete_row = adw::ActionRow::new();
+                delete_row.set_title("Delete Credential");
+                delete_row.set_subtitle("Remove an entry from the key store");
+                let delete_entry = gtk4::Entry::new();
+                delete_entry.set_placeholder_text(Some("Entry path..."));
+                delete_entry.set_hexpand(true);
+                delete_entry.set_valign(gtk4::Align::Center);
+                let delete_button = gtk4::Button::with_label("Delete");
+                delete_button.set_valign(gtk4::Align::Center);
+                delete_button.add_css_class("destructive-action");
+                delete_row.add_suffix(&delete_entry);
+                delete_row.add_suffix(&delete_button);
+                keys_group.add(&delete_row);
+
+                // Wire delete button
+                {
+                    let entry_clone = delete_entry.clone();
+                    let status_clone = status_label.clone();
+                    delete_button.connect_clicked(move |_| {
+                        let path = entry_clone.text().to_string();
+                        if path.is_empty() {
+                            return;
+                        }
+                        let status = status_clone.clone();
+                        let ec = entry_clone.clone();
+                        glib::spawn_future_local(async move {
+                            let result = run_cli_args_async(vec![
+                                "keys".into(),
+                                "delete".into(),
+                                path.clone(),
+                            ])
+                            .await;
+                            match result {
+                                Ok(_) => {
+                                    status.set_text(&format!("Deleted: {}", path));
+                                    status.set_visible(true);
+                                    status.remove_css_class("error");
+                                    status.add_css_class("success");
+                                    ec.set_text("");
+                                }
+                                Err(e) => {
+                                    status.set_text(&error_text("Delete failed", &e));
+                                    status.set_visible(true);
+                                    status.remove_css_class("success");
+                                    status.add_css_class("error");
+                                }
+                            }
+                        });
+                    });
+                }
+
+                // Discover credentials button row
+                let discover_row = adw::ActionRow::new();
+                discover_row.set_title("Discover Credentials");
+                discover_row.set_subtitle("Auto-discover env vars and SSH keys");
+                let discover_button = gtk4::Button::with_label("Discover");
+                discover_button.set_valign(gtk4::Align::Center);
+                discover_row.add_suffix(&discover_button);
+                discover_row.set_activatable_widget(Some(&discover_button));
+                keys_group.add(&discover_row);
+
+                // Crawl directories for .env files
+                let crawl_row = adw::ActionRow::new();
+                crawl_row.set_title("Crawl for .env Files");
+                crawl_row.set_subtitle("Scan project directories and ingest every .env found");
+                let crawl_button = gtk4::Button::with_label("Crawl");
+                crawl_button.set_valign(gtk4::Align::Center);
+                crawl_row.add_suffix(&crawl_button);
+                crawl_row.set_activatable_widget(Some(&crawl_button));
+                keys_group.add(&crawl_row);
+                keys_group.add(progress_row.widget());
+
+                // Wire crawl button (click again to cancel)
+                {
+                    let status_clone = status_label.clone();
+                    let progress = progress_row.clone();
+                    let slot = CancelSlot::default();
+                    crawl_button.connect_clicked(move |button| {
+                        if slot.cancel_running() {
+                            return;
+                        }
+                        let token = slot.start();
+                        button.set_label("Cancel");
+                        let btn = button.clone();
+                        let status = status_clone.clone();
+                        let progress = progress.clone();
+                        let slot = slot.clone();
+                        status.set_visible(false);
+                        status.remove_css_class("error");
+                        status.remove_css_class("success");
+
+                        glib::spawn_future_local(async move {
+                            let result = run_cli_progress_async(
+                                cli::args(&["keys", "crawl"]),
+                                token,
+                                &progress,
+                            )
+                            .await;
+                            status.set_visible(true);
+                            match result {
+                                Ok(output) => {
+                                    status.set_text(output.lines().last().unwrap_or("Done"));
+                                    status.add_css_class("success");
+                                }
+                                Err(e) if e.is_cancelled() => {
+                                    status.set_text("Crawl cancelled");
+                                }
+                                Err(e) => {
+                                    status.set_text(&error_text("Crawl failed", &e));
+                                    status.add_css_class("error");
+                                }
+                            }
+                            slot.finish();
+                            btn.set_label("Crawl");
+                        });
+                    });
+                }
+
+                // Wire discover button (click again to cancel)
+                {
+                    let status_clone = status_label.clone();
+                    let progress = progress_row.clone();
+                    let slot = CancelSlot::default();
+                    discover_button.connect_clicked(move |button| {
+                        if slot.cancel_running() {
+                            return;
+                        }
+                        let token = slot.start();
+                        button.set_label("Cancel");
+                        let btn = button.clone();
+                        let status = status_clone.clone();
+                        let progress = progress.clone();
+                        let slot = slot.clone();
+                        status.set_visible(false);
+                        status.remove_css_class("error");
+                        status.remove_css_class("success");
+
+                        glib::spawn_future_local(async move {
+                            let result = run_cli_progress_async(
+                                cli::args(&["keys", "discover", "--types", "all"]),
+                                token,
+                                &progress,
+                            )
+                            .await;
+                            status.set_visible(true);
+                            match result {
+                                Ok(output) => {
+                                    status.set_text(output.lines().last().unwrap_or("Done"));
+                                    status.add_css_class("success");
+                                }
+                                Err(e) if e.is_cancelled() => {
+                                    status.set_text("Discovery cancelled");
+                                }
+                                Err(e) => {
+                                    status.set_text(&error_text("Discovery failed", &e));
+                                    status.add_css_class("error");
+                                }
+                            }
+                            slot.finish();
+                            btn.set_label("Discover");
+                        });
+                    });
+                }
+
+                main_box.append(&keys_group);
+                main_box.append(&search_results_label);
+
+                // ============================================================
+                // SOPS Files Group
+                // ============================================================
+                let sops_group = adw::PreferencesGroup::new();
+                sops_group.set_title("SOPS Files");
+                sops_group.set_description(Some(
+                    "Import SOPS-encrypted files and write key store edits back",
+                ));
+
+                // Conflict policy for round-trips
+                let sops_policy_row = adw::ComboRow::new();
+                sops_policy_row.set_title("Conflict Resolution");
+                sops_policy_row.set_subtitle("When a value changed in both the file and key store");
+                let policy_names: Vec<&str> = ConflictPolicy::all()
+                    .iter()
+                    .map(|p| p.display_name())
+                    .collect();
+                sops_policy_row.set_model(Some(&gtk4::StringList::new(&policy_names)));
+                sops_group.add(&sops_policy_row);
+
+                let sops_ingest_row = adw::ActionRow::new();
+                sops_ingest_row.set_title("Ingest SOPS File");
+                sops_ingest_row.set_subtitle("Decrypt with sops and store values in the key store");
+                let sops_ingest_button = gtk4::Button::with_label("Choose File");
+                sops_ingest_button.set_valign(gtk4::Align::Center);
+                sops_ingest_row.add_suffix(&sops_ingest_button);
+                sops_ingest_row.set_activatable_widget(Some(&sops_ingest_button));
+                sops_group.add(&sops_ingest_row);
+
+                let sops_sync_row = adw::ActionRow::new();
+                sops_sync_row.set_title("Sync SOPS File");
+                sops_sync_row.set_subtitle("Pull file changes and re-encrypt key store edits");
+                let sops_sync_button = gtk4::Button::with_label("Choose File");
+                sops_sync_button.set_valign(gtk4::Align::Center);
+                sops_sync_row.add_suffix(&sops_sync_button);
+                sops_sync_row.set_activatable_widget(Some(&sops_sync_button));
+                sops_group.add(&sops_sync_row);
+
+                let sops_progress = ProgressRow::new();
+                sops_group.add(sops_progress.widget());
+
+                // Wire SOPS ingest button
+                {
+                    let status_clone = status_label.clone();
+                    let window_ref = self.obj().clone();
+                    let progress = sops_progress.clone();
+                    sops_ingest_button.connect_clicked(move |_| {
+                        let status = status_clone.clone();
+                        let progress = progress.clone();
+                        sops_file_dialog().open(
+                            Some(&window_ref),
+                            gio::Cancellable::NONE,
+                            move |result| {
+                                let Some(path) = result.ok().and_then(|f| portal::file_path(&f))
+                                else {
+                                    return;
+                                };
+                                let path_str = path.to_string_lossy().to_string();
+                                status.set_visible(false);
+                                status.remove_css_class("error");
+                                status.remove_css_class("success");
+
+                                glib::spawn_future_local(async move {
+                                    let result = run_cli_progress_async(
+                                        cli::args(&["keys", "sops-ingest", &path_str]),
+                                        CancelToken::new(),
+                                        &progress,
+                                    )
+                                    .await;
+                                    status.set_visible(true);
+                                    match result {
+                                        Ok(output) => {
+                                            status.set_text(
+                                                output.lines().last().unwrap_or("Ingested"),
+                                            );
+                                            status.add_css_class("success");
+                                        }
+                                        Err(e) => {
+                                            status.set_text(&error_text("SOPS ingest failed", &e));
+                                            status.add_css_class("error");
+                                        }
+                                    }
+                                });
+                            },
+                        );
+                    });
+                }
+
+                // Wire SOPS sync button
+                {
+                    let status_clone = status_label.clone();
+                    let window_ref = self.obj().clone();
+                    let policy_row = sops_policy_row.clone();
+                    sops_sync_button.connect_clicked(move |button| {
+                        let status = status_clone.clone();
+                        let btn = button.clone();
+                        let policy = ConflictPolicy::from_index(policy_row.selected());
+                        sops_file_dialog().open(
+                            Some(&window_ref),
+                            gio::Cancellable::NONE,
+                            move |result| {
+                                let Some(path) = result.ok().and_then(|f| portal::file_path(&f))
+                                else {
+                                    return;
+                                };
+                                btn.set_sensitive(false);
+                                status.set_text(&format!("Syncing {}...", path.display()));
+                                status.set_visible(true);
+                                status.remove_css_class("error");
+                                status.remove_css_class("success");
+
+                                glib::spawn_future_local(async move {
+                                    let result = gio::spawn_blocking(move || {
+                                        sops::round_trip(&path, policy)
+                                    })
+                                    .await;
+                                    match result {
+                                        Ok(Ok(report)) => {
+                                            status.set_text(&report.summary());
+                                            if report.conflicts.is_empty() {
+                                                status.add_css_class("success");
+                                            }
+                                        }
+                                        Ok(Err(e)) => {
+                                            status.set_text(&error_text("SOPS sync failed", &e));
+                                            status.add_css_class("error");
+                                        }
+                                        Err(e) => {
+                                            tracing::error!("SOPS sync task failed: {:?}", e);
+                                        }
+                                    }
+                                    btn.set_sensitive(true);
+                                });
+                            },
+                        );
+                    });
+                }
+
+                main_box.append(&sops_group);
+
+                // ============================================================
+                // age Encryption Group
+                // ============================================================
+                let age_group = adw::PreferencesGroup::new();
+                age_group.set_title("age Encryption");
+                age_group.set_description(Some(
+                    "Encrypt and decrypt files with age identities kept in the key store",
+                ));
+
+                let age_entry_row = adw::ActionRow::new();
+                age_entry_row.set_title("Identity Entry");
+                let age_entry = gtk4::Entry::new();
+                age_entry.set_text(age::DEFAULT_IDENTITY_PATH);
+                age_entry.set_hexpand(true);
+                age_entry.set_valign(gtk4::Align::Center);
+                age_entry_row.add_suffix(&age_entry);
+                age_group.add(&age_entry_row);
+
+                let (age_generate_row, age_generate_button) = button_row(
+                    "Generate Identity",
+                    "Create a new age key and store it at the entry above",
+                    "Generate",
+                );
+                age_group.add(&age_generate_row);
+                let (age_recipient_row, age_recipient_button) = button_row(
+                    "Public Recipient",
+                    "Copy the age1... recipient for this identity",
+                    "Copy",
+                );
+                age_group.add(&age_recipient_row);
+                let (age_encrypt_row, age_encrypt_button) = button_row(
+                    "Encrypt File",
+                    "Write an .age copy readable by this identity",
+                    "Choose File",
+                );
+                age_group.add(&age_encrypt_row);
+                let (age_decrypt_row, age_decrypt_button) = button_row(
+                    "Decrypt File",
+                    "Decrypt an .age file next to the original",
+                    "Choose File",
+                );
+                age_group.add(&age_decrypt_row);
+                let (age_sops_row, age_sops_button) = button_row(
+                    "Copy SOPS_AGE_KEY",
+                    "Environment line that lets sops decrypt with this identity",
+                    "Copy",
+                );
+                age_group.add(&age_sops_row);
+
+                // Wire generate button
+                {
+                    let status = status_label.clone();
+                    let entry = age_entry.clone();
+                    let recipient_row = age_recipient_row.clone();
+                    age_generate_button.connect_clicked(move |button| {
+                        let path = entry.text().to_string();
+                        if path.is_empty() {
+                            return;
+                        }
+                        button.set_sensitive(false);
+                        show_status(&status, "Generating age identity...", None);
+                        let btn = button.clone();
+                        let status = status.clone();
+                        let recipient_row = recipient_row.clone();
+                        glib::spawn_future_local(async move {
+                            let stored_at = path.clone();
+                            let result =
+                                gio::spawn_blocking(move || age::generate_and_store(&path)).await;
+                            match result {
+                                Ok(Ok(identity)) => {
+                                    recipient_row.set_subtitle(&identity.recipient);
+                                    show_status(
+                                        &status,
+                                        &format!("Stored new age identity at {}", stored_at),
+                                        Some("success"),
+                                    );
+                                }
+                                Ok(Err(e)) => show_status(
+                                    &status,
+                                    &error_text("Generate failed", &e),
+                                    Some("error"),
+                                ),
+                                Err(e) => tracing::error!("age generate task failed: {:?}", e),
+                            }
+                            btn.set_sensitive(true);
+                        });
+                    });
+                }
+
+                // Wire recipient copy button
+                {
+                    let status = status_label.clone();
+                    let entry = age_entry.clone();
+                    let recipient_row = age_recipient_row.clone();
+                    age_recipient_button.connect_clicked(move |_| {
+                        let path = entry.text().to_string();
+                        let status = status.clone();
+                        let recipient_row = recipient_row.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || {
+                                age::load_secret(&path).and_then(|s| age::recipient_for(&s))
+                            })
+                            .await;
+                            match result {
+                                Ok(Ok(recipient)) => {
+                                    recipient_row.set_subtitle(&recipient);
+                                    if let Some(display) = gdk::Display::default() {
+                                        display.clipboard().set_text(&recipient);
+                                    }
+                                    show_status(
+                                        &status,
+                                        "Recipient copied to clipboard",
+                                        Some("success"),
+                                    );
+                                }
+                                Ok(Err(e)) => show_status(
+                                    &status,
+                                    &error_text("Recipient lookup failed", &e),
+                                    Some("error"),
+                                ),
+                                Err(e) => tracing::error!("age recipient task failed: {:?}", e),
+                            }
+                        });
+                    });
+                }
+
+                // Wire encrypt/decrypt buttons
+                for (button, encrypting) in
+                    [(&age_encrypt_button, true), (&age_decrypt_button, false)]
+                {
+                    let status = status_label.clone();
+                    let entry = age_entry.clone();
+                    let window_ref = self.obj().clone();
+                    button.connect_clicked(move |_| {
+                        let dialog = gtk4::FileDialog::new();
+                        dialog.set_title(if encrypting {
+                            "Select file to encrypt"
+                        } else {
+                            "Select .age file to decrypt"
+                        });
+                        let status = status.clone();
+                        let path = entry.text().to_string();
+                        dialog.open(Some(&window_ref), gio::Cancellable::NONE, move |result| {
+                            let Some(input) = result.ok().and_then(|f| portal::file_path(&f))
+                            else {
+                                return;
+                            };
+                            show_status(
+                                &status,
+                                &format!("Processing {}...", input.display()),
+                                None,
+                            );
+                            let status = status.clone();
+                            glib::spawn_future_local(async move {
+                                let result = gio::spawn_blocking(move || {
+                                    if encrypting {
+                                        age::encrypt_with_stored(&path, &input)
+                                    } else {
+                                        age::decrypt_with_stored(&path, &input)
+                                    }
+                                })
+                                .await;
+                                match result {
+                                    Ok(Ok(output)) => show_status(
+                                        &status,
+                                        &format!("Wrote {}", output.display()),
+                                        Some("success"),
+                                    ),
+                                    Ok(Err(e)) => show_status(
+                                        &status,
+                                        &error_text("age failed", &e),
+                                        Some("error"),
+                                    ),
+                                    Err(e) => tracing::error!("age task failed: {:?}", e),
+                                }
+                            });
+                        });
+                    });
+                }
+
+                // Wire SOPS_AGE_KEY copy button
+                {
+                    let status = status_label.clone();
+                    let entry = age_entry.clone();
+                    age_sops_button.connect_clicked(move |_| {
+                        let path = entry.text().to_string();
+                        let status = status.clone();
+                        glib::spawn_future_local(async move {
+                            let result = gio::spawn_blocking(move || age::load_secret(&path)).await;
+                            match result {
+                                Ok(Ok(secret)) => {
+                                    if let Some(display) = gdk::Display::default() {
+                                        display.clipboard().set_text(&age::sops_age_env(&secret));
+                                    }
+                                    show_status(
+                                        &status,
+                                        "SOPS_AGE_KEY copied to clipboard",
+                                        Some("success"),
+                                    );
+                                }
+                                Ok(Err(e)) => show_status(
+                                    &status,
+                                    &error_text("Export failed", &e),
+                                    Some("error"),
+                                ),
+                                Err(e) => tracing::error!("age export task failed: {:?}", e),
+                            }
+                        });
+                    });
+                }
+
+                main_box.append(&age_group);
+
+                // ============================================================
+                // Secret Backends Group
+                // ============================================================
+                let backends_group = adw::PreferencesGroup::new();
+                backends_group.set_title("Other Secret Stores");
+                backends_group.set_description(Some(
+                    "Stores mounted into the key namespace; search and copy work across all of them",
+                ));
+
+                let collision_row = adw::ComboRow::new();
+                collision_row.set_title("On Import Collision");
+                collision_row.set_subtitle("When an imported entry already exists in KDBX");
+                let collision_names: Vec<&str> = CollisionPolicy::all()
+                    .iter()
+                    .map(|p| p.display_name())
+                    .collect();
+                collision_row.set_model(Some(&gtk4::StringList::new(&collision_names)));
+                backends_group.add(&collision_row);
+
+                // Setec is reached over the tailnet; show whether it answers
+                let setec_row = adw::ActionRow::new();
+                setec_row.set_title("Setec Server");
+                setec_row.set_subtitle("Checking...");
+                backends_group.add(&setec_row);
+                self.schedule_probe(async move {
+                    let reachability =
+                        gio::spawn_blocking(|| setec::SetecBackend::from_env().probe()).await;
+                    match reachability {
+                        Ok(reachability) => setec_row.set_subtitle(&reachability.display_text()),
+                        Err(_) => setec_row.set_subtitle("Unavailable"),
+                    }
+                });
+
+                let import_progress = ProgressRow::new();
+
+                for store in backend::all() {
+                    let namespace = store.namespace();
+                    let row = adw::ActionRow::new();
+                    row.set_title(store.display_name());
+                    row.set_subtitle("Checking...");
+                    let unlock_button = gtk4::Button::with_label("Unlock");
+                    unlock_button.set_valign(gtk4::Align::Center);
+                    unlock_button.set_visible(false);
+                    let import_button = gtk4::Button::with_label("Import");
+                    import_button.set_valign(gtk4::Align::Center);
+                    import_button.set_tooltip_text(Some(&format!(
+                        "Copy every entry into {}/{}",
+                        backend::IMPORT_GROUP,
+                        namespace
+                    )));
+                    import_button.set_sensitive(false);
+                    row.add_suffix(&unlock_button);
+                    row.add_suffix(&import_button);
+                    backends_group.add(&row);
+
+                    // Probe availability and lock state off the main thread
+                    {
+                        let row = row.clone();
+                        let unlock = unlock_button.clone();
+                        let import = import_button.clone();
+                        self.schedule_probe(async move {
+                            let state = gio::spawn_blocking(move || {
+                                backend::find(namespace)
+                                    .map(|b| (b.is_available(), b.is_locked(), b.is_writable()))
+                            })
+                            .await;
+                            let Ok(Some((available, locked, writable))) = state else {
+                                row.set_subtitle("Unavailable");
+                                return;
+                            };
+                            let access = if writable { "read/write" } else { "read-only" };
+                            if !available {
+                                row.set_subtitle("Not found");
+                            } else if locked {
+                                row.set_subtitle(&format!("Locked, {}", access));
+                                unlock.set_visible(true);
+                                import.set_sensitive(true);
+                            } else {
+                                row.set_subtitle(&format!(
+                                    "Available as {}/..., {}",
+                                    namespace, access
+                                ));
+                                import.set_sensitive(true);
+                            }
+                        });
+                    }
+
+                    // Wire unlock button
+                    {
+                        let row = row.clone();
+                        let status = status_label.clone();
+                        unlock_button.connect_clicked(move |button| {
+                            button.set_sensitive(false);
+                            let btn = button.clone();
+                            let row = row.clone();
+                            let status = status.clone();
+                            glib::spawn_future_local(async move {
+                                let result = gio::spawn_blocking(move || {
+                                    backend::find(namespace).map(|b| b.unlock())
+                                })
+                                .await;
+                                match result {
+                                    Ok(Some(Ok(()))) => {
+                                        row.set_subtitle(&format!(
+                                            "Available as {}/...",
+                                            namespace
+                                        ));
+                                        btn.set_visible(false);
+                                    }
+                                    Ok(Some(Err(e))) => show_status(
+                                        &status,
+                                        &error_text("Unlock failed", &e),
+                                        Some("error"),
+                                    ),
+                                    _ => {}
+                                }
+                                btn.set_sensitive(true);
+                            });
+                        });
+                    }
+
+                    // Wire import button
+                    {
+                        let status = status_label.clone();
+                        let policy_row = collision_row.clone();
+                        let progress = import_progress.clone();
+                        import_button.connect_clicked(move |button| {
+                            let policy = CollisionPolicy::from_index(policy_row.selected());
+                            button.set_sensitive(false);
+                            status.set_visible(false);
+                            let btn = button.clone();
+                            let status = status.clone();
+                            let progress = progress.clone();
+                            let (sender, receiver) = async_channel::unbounded();
+
+                            glib::spawn_future_local(async move {
+                                let worker = gio::spawn_blocking(move || {
+                                    let Some(store) = backend::find(namespace) else {
+                                        return Ok(backend::ImportReport::default());
+                                    };
+                                    backend::import_into_kdbx(
+                                        store.as_ref(),
+                                        policy,
+                                        &mut |event| {
+                                            let _ = sender.send_blocking(event.clone());
+                                        },
+                                    )
+                                });
+
+                                // The channel closes when the worker drops its sender
+                                let mut state = ProgressState::default();
+                                while let Ok(event) = receiver.recv().await {
+                                    state.apply(&event);
+                                    progress.show(&state);
                                 }
-                            }
+                                progress.hide();
+
+                                match worker.await {
+                                    Ok(Ok(report)) => show_status(
+                                        &status,
+                                        &report.summary(),
+                                        Some(if report.failed.is_empty() {
+                                            "success"
+                                        } else {
+                                            "error"
+                                        }),
+                                    ),
+                                    Ok(Err(e)) => show_status(
+                                        &status,
+                                        &error_text("Import failed", &e),
+                                        Some("error"),
+                                    ),
+                                    Err(_) => show_status(
+                                        &status,
+                                        &error_text("Import failed", &worker_panicked()),
+                                        Some("error"),
+                                    ),
+                                }
+                                btn.set_sensitive(true);
+                            });
                         });
-                    });
+                    }
                 }
+                backends_group.add(import_progress.widget());
 
-                // Store credential row
-                let store_row = adw::ActionRow::new();
-                store_row.set_title("Store Credential");
-                store_row.set_subtitle("Store a new secret in the key store");
-                let store_path_entry = gtk4::Entry::new();
-                store_path_entry.set_placeholder_text(Some("Path (e.g. RemoteJuggler/API/KEY)"));
-                store_path_entry.set_hexpand(true);
-                store_path_entry.set_valign(gtk4::Align::Center);
-                let store_value_entry = gtk4::PasswordEntry::new();
-                store_value_entry.set_placeholder_text(Some("Secret value"));
-                store_value_entry.set_hexpand(true);
-                store_value_entry.set_valign(gtk4::Align::Center);
-                store_value_entry.set_show_peek_icon(true);
-                let store_cred_button = gtk4::Button::with_label("Store");
-                store_cred_button.set_valign(gtk4::Align::Center);
-                store_cred_button.add_css_class("suggested-action");
-                store_row.add_suffix(&store_path_entry);
-                store_row.add_suffix(&store_value_entry);
-                store_row.add_suffix(&store_cred_button);
-                keys_group.add(&store_row);
+                main_box.append(&backends_group);
+
+                // ============================================================
+                // Resolve Group
+                // ============================================================
+                let resolve_group = adw::PreferencesGroup::new();
+                resolve_group.set_title("Resolve");
+                let order: Vec<&str> = resolver::configured_order()
+                    .iter()
+                    .map(|s| s.display_name())
+                    .collect();
+                resolve_group.set_description(Some(&format!(
+                    "Find a secret wherever it lives, checking {}",
+                    order.join(" → ")
+                )));
+
+                let resolve_row = adw::ActionRow::new();
+                resolve_row.set_title("Resolve Secret");
+                resolve_row.set_subtitle("Copies the first match and shows which source won");
+                let resolve_entry = gtk4::Entry::new();
+                resolve_entry.set_placeholder_text(Some("Name or path (e.g. GITHUB_TOKEN)"));
+                resolve_entry.set_hexpand(true);
+                resolve_entry.set_valign(gtk4::Align::Center);
+                let resolve_button = gtk4::Button::with_label("Resolve");
+                resolve_button.set_valign(gtk4::Align::Center);
+                resolve_row.add_suffix(&resolve_entry);
+                resolve_row.add_suffix(&resolve_button);
+                resolve_group.add(&resolve_row);
+
+                let trail_label = gtk4::Label::new(None);
+                trail_label.set_halign(gtk4::Align::Start);
+                trail_label.set_wrap(true);
+                trail_label.set_selectable(true);
+                trail_label.add_css_class("dim-label");
+                trail_label.set_margin_top(6);
+                trail_label.set_visible(false);
+                resolve_group.add(&trail_label);
 
-                // Wire store credential button
                 {
-                    let path_clone = store_path_entry.clone();
-                    let value_clone = store_value_entry.clone();
-                    let status_clone = status_label.clone();
-                    store_cred_button.connect_clicked(move |button| {
-                        let path = path_clone.text().to_string();
-                        let value = value_clone.text().to_string();
-                        if path.is_empty() || value.is_empty() {
+                    let entry = resolve_entry.clone();
+                    let trail_label = trail_label.clone();
+                    resolve_button.connect_clicked(move |button| {
+                        let name = entry.text().trim().to_string();
+                        if name.is_empty() {
                             return;
                         }
                         button.set_sensitive(false);
                         let btn = button.clone();
-                        let status = status_clone.clone();
-                        let pc = path_clone.clone();
-                        let vc = value_clone.clone();
+                        let trail_label = trail_label.clone();
                         glib::spawn_future_local(async move {
-                            let result = run_cli_args_async(vec![
-                                "keys".into(),
-                                "store".into(),
-                                path.clone(),
-                                "--value".into(),
-                                value,
-                            ])
-                            .await;
+                            let result =
+                                gio::spawn_blocking(move || resolver::resolve(&name)).await;
                             match result {
-                                Ok(_) => {
-                                    status.set_text(&format!("Stored: {}", path));
-                                    status.set_visible(true);
-                                    status.remove_css_class("error");
-                                    status.add_css_class("success");
-                                    pc.set_text("");
-                                    vc.set_text("");
-                                }
-                                Err(e) => {
-                                    status.set_text(&format!("Store failed: {}", e));
-                                    status.set_visible(true);
-                                    status.remove_css_class("success");
-                                    status.add_css_class("error");
+                                Ok(resolution) => {
+                                    let trail: Vec<String> =
+                                        resolution.trail.iter().map(|a| a.display_text()).collect();
+                                    let headline = match (&resolution.value, resolution.winner()) {
+                                        (Some(value), Some(winner)) => {
+                                            let display = gdk::Display::default().unwrap();
+                                            display.clipboard().set_text(value.trim());
+                                            format!(
+                                                "Copied from {} ({})",
+                                                winner.source.display_name(),
+                                                winner.location
+                                            )
+                                        }
+                                        _ => "Not found in any source".to_string(),
+                                    };
+                                    trail_label.set_text(&format!(
+                                        "{}\n\n{}",
+                                        headline,
+                                        trail.join("\n")
+                                    ));
                                 }
+                                Err(_) => trail_label.set_text(&worker_panicked().to_string()),
                             }
+                            trail_label.set_visible(true);
                             btn.set_sensitive(true);
                         });
                     });
                 }
 
-                // Delete credential row
-                let delete_row = adw::ActionRow::new();
-                delete_row.set_title("Delete Credential");
-                delete_row.set_subtitle("Remove an entry from the key store");
-                let delete_entry = gtk4::Entry::new();
-                delete_entry.set_placeholder_text(Some("Entry path..."));
-                delete_entry.set_hexpand(true);
-                delete_entry.set_valign(gtk4::Align::Center);
-                let delete_button = gtk4::Button::with_label("Delete");
-                delete_button.set_valign(gtk4::Align::Center);
-                delete_button.add_css_class("destructive-action");
-                delete_row.add_suffix(&delete_entry);
-                delete_row.add_suffix(&delete_button);
-                keys_group.add(&delete_row);
+                main_box.append(&resolve_group);
 
-                // Wire delete button
-                {
-                    let entry_clone = delete_entry.clone();
-                    let status_clone = status_label.clone();
-                    delete_button.connect_clicked(move |_| {
-                        let path = entry_clone.text().to_string();
-                        if path.is_empty() {
+                // Connect store PIN button handler
+                let pin_entry_clone = pin_entry.clone();
+                let pin_status_clone = pin_status_label.clone();
+                let current_identity = config.state.current_identity.clone();
+                store_button.connect_clicked(move |button| {
+                    let pin = pin_entry_clone.text();
+                    if pin.is_empty() {
+                        tracing::warn!("Cannot store empty PIN");
+                        return;
+                    }
+
+                    let identity = current_identity.clone();
+                    if identity.is_empty() {
+                        tracing::warn!("No identity selected");
+                        return;
+                    }
+
+                    // Disable button during operation
+                    button.set_sensitive(false);
+                    pin_status_clone.set_text("Storing...");
+
+                    // Spawn async task to store it
+                    let button_clone = button.clone();
+                    let status_clone = pin_status_clone.clone();
+                    let entry_clone = pin_entry_clone.clone();
+                    let pin = pin.to_string();
+                    glib::spawn_future_local(async move {
+                        let result = store_pin_async(&identity, &pin).await;
+
+                        // Update UI based on result
+                        match result {
+                            Ok(method) => {
+                                status_clone
+                                    .set_text(&format!("Stored in {}", method.display_name()));
+                                status_clone.remove_css_class("dim-label");
+                                status_clone.add_css_class("success");
+                                entry_clone.set_text("");
+                                tracing::info!("PIN stored successfully for {}", identity);
+                            }
+                            Err(e) => {
+                                status_clone.set_text("Failed");
+                                status_clone.remove_css_class("dim-label");
+                                status_clone.add_css_class("error");
+                                tracing::error!("Failed to store PIN: {}", e);
+                            }
+                        }
+                        button_clone.set_sensitive(true);
+                    });
+                });
+            } else {
+                // Show error status page
+                let status_page = adw::StatusPage::new();
+                status_page.set_icon_name(Some("dialog-error-symbolic"));
+                status_page.set_title("Configuration Not Found");
+                let description = match self.config_error.borrow().as_ref() {
+                    Some(err) => error_text("Could not load RemoteJuggler configuration", err),
+                    None => "Could not load RemoteJuggler configuration.\n\
+                             Please ensure ~/.config/remote-juggler/config.json exists."
+                        .to_string(),
+                };
+                status_page.set_description(Some(&description));
+                main_box.append(&status_page);
+            }
+
+            main_box
+        }
+    }
+
+    /// Placeholder rows shown while the config loads
+    fn build_skeleton_content() -> gtk4::Box {
+        let main_box = gtk4::Box::new(gtk4::Orientation::Vertical, 12);
+        main_box.set_margin_top(24);
+        main_box.set_margin_bottom(24);
+        main_box.set_margin_start(24);
+        main_box.set_margin_end(24);
+
+        for (group_title, row_title) in [
+            ("Git Identity", "Active Profile"),
+            ("GPG Status", "Signing Ready"),
+            ("Key Store (KeePassXC)", "Key Store"),
+        ] {
+            let group = adw::PreferencesGroup::new();
+            group.set_title(group_title);
+
+            let row = adw::ActionRow::new();
+            row.set_title(row_title);
+            row.set_subtitle("Loading...");
+            let spinner = gtk4::Spinner::new();
+            spinner.set_spinning(true);
+            row.add_suffix(&spinner);
+            group.add(&row);
+
+            main_box.append(&group);
+        }
+
+        main_box
+    }
+
+    /// Action row with a single suffix button
+    fn button_row(title: &str, subtitle: &str, label: &str) -> (adw::ActionRow, gtk4::Button) {
+        let row = adw::ActionRow::new();
+        row.set_title(title);
+        row.set_subtitle(subtitle);
+        let button = gtk4::Button::with_label(label);
+        button.set_valign(gtk4::Align::Center);
+        row.add_suffix(&button);
+        row.set_activatable_widget(Some(&button));
+        (row, button)
+    }
+
+    /// Show a message in a status label, styled "success", "error", or neutral
+    /// The attachment rows of the Attachments expander
+    #[derive(Clone)]
+    struct AttachmentList {
+        expander: adw::ExpanderRow,
+        rows: Rc<RefCell<Vec<adw::ActionRow>>>,
+        status: gtk4::Label,
+        window: super::RemoteJugglerWindow,
+    }
+
+    impl AttachmentList {
+        /// List an entry's attachments, replacing the rows shown before
+        fn load(&self, entry: String) {
+            let list = self.clone();
+            glib::spawn_future_local(async move {
+                let result = run_cli_args_async(attachments::list_args(&entry))
+                    .await
+                    .and_then(|output| attachments::parse_list(&output));
+                for row in list.rows.borrow_mut().drain(..) {
+                    list.expander.remove(&row);
+                }
+                match result {
+                    Ok(found) if found.is_empty() => {
+                        show_status(&list.status, &format!("No attachments on {}", entry), None);
+                    }
+                    Ok(found) => {
+                        for attachment in &found {
+                            let row = list.row(&entry, attachment);
+                            list.expander.add_row(&row);
+                            list.rows.borrow_mut().push(row);
+                        }
+                        list.expander.set_expanded(true);
+                    }
+                    Err(e) => show_status(
+                        &list.status,
+                        &error_text("Failed to list attachments", &e),
+                        Some("error"),
+                    ),
+                }
+            });
+        }
+
+        fn row(&self, entry: &str, attachment: &Attachment) -> adw::ActionRow {
+            let row = adw::ActionRow::new();
+            row.set_title(&attachment.name);
+            if !attachment.size.is_empty() {
+                row.set_subtitle(&attachment.size);
+            }
+            let open_button = gtk4::Button::with_label("Open");
+            open_button.set_valign(gtk4::Align::Center);
+            open_button.set_tooltip_text(Some("Download to a private temporary file and open it"));
+            let save_button = gtk4::Button::from_icon_name("document-save-symbolic");
+            save_button.set_valign(gtk4::Align::Center);
+            save_button.set_tooltip_text(Some("Save a copy"));
+            let remove_button = gtk4::Button::from_icon_name("user-trash-symbolic");
+            remove_button.set_valign(gtk4::Align::Center);
+            remove_button.set_tooltip_text(Some("Remove attachment"));
+            remove_button.add_css_class("destructive-action");
+            row.add_suffix(&open_button);
+            row.add_suffix(&save_button);
+            row.add_suffix(&remove_button);
+
+            // Export into the download directory, then hand it to the
+            // default application
+            {
+                let list = self.clone();
+                let entry = entry.to_string();
+                let name = attachment.name.clone();
+                open_button.connect_clicked(move |button| {
+                    let path = match attachments::download_path(&entry, &name) {
+                        Ok(path) => path,
+                        Err(e) => {
+                            show_status(
+                                &list.status,
+                                &format!("Cannot create download directory: {}", e),
+                                Some("error"),
+                            );
                             return;
                         }
-                        let status = status_clone.clone();
-                        let ec = entry_clone.clone();
+                    };
+                    button.set_sensitive(false);
+                    let btn = button.clone();
+                    let list = list.clone();
+                    let args = attachments::export_args(&entry, &name, &path);
+                    let name = name.clone();
+                    glib::spawn_future_local(async move {
+                        match run_cli_checked_async(args).await {
+                            Ok(_) => {
+                                show_status(
+                                    &list.status,
+                                    &format!("Opened {}; wipe downloads when done", name),
+                                    Some("success"),
+                                );
+                                let file = gio::File::for_path(&path);
+                                let status = list.status.clone();
+                                gtk4::FileLauncher::new(Some(&file)).launch(
+                                    Some(&list.window),
+                                    gio::Cancellable::NONE,
+                                    move |result| {
+                                        if let Err(e) = result {
+                                            show_status(
+                                                &status,
+                                                &format!("No application to open {}: {}", name, e),
+                                                Some("error"),
+                                            );
+                                        }
+                                    },
+                                );
+                            }
+                            Err(e) => show_status(
+                                &list.status,
+                                &error_text("Download failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                        btn.set_sensitive(true);
+                    });
+                });
+            }
+
+            // Save a copy wherever the user picks
+            {
+                let list = self.clone();
+                let entry = entry.to_string();
+                let name = attachment.name.clone();
+                save_button.connect_clicked(move |_| {
+                    let dialog = gtk4::FileDialog::new();
+                    dialog.set_title("Save Attachment");
+                    dialog.set_initial_name(Some(&name));
+                    let window = list.window.clone();
+                    let list = list.clone();
+                    let entry = entry.clone();
+                    let name = name.clone();
+                    dialog.save(Some(&window), gio::Cancellable::NONE, move |result| {
+                        let Some(dest) = result.ok().and_then(|f| portal::file_path(&f)) else {
+                            return;
+                        };
                         glib::spawn_future_local(async move {
-                            let result = run_cli_args_async(vec![
-                                "keys".into(),
-                                "delete".into(),
-                                path.clone(),
-                            ])
-                            .await;
-                            match result {
-                                Ok(_) => {
-                                    status.set_text(&format!("Deleted: {}", path));
-                                    status.set_visible(true);
-                                    status.remove_css_class("error");
-                                    status.add_css_class("success");
-                                    ec.set_text("");
-                                }
-                                Err(e) => {
-                                    status.set_text(&format!("Delete failed: {}", e));
-                                    status.set_visible(true);
-                                    status.remove_css_class("success");
-                                    status.add_css_class("error");
-                                }
+                            let args = attachments::export_args(&entry, &name, &dest);
+                            match run_cli_checked_async(args).await {
+                                Ok(_) => show_status(
+                                    &list.status,
+                                    &format!("Saved {} to {}", name, dest.display()),
+                                    Some("success"),
+                                ),
+                                Err(e) => show_status(
+                                    &list.status,
+                                    &error_text("Save failed", &e),
+                                    Some("error"),
+                                ),
+                            }
+                        });
+                    });
+                });
+            }
+
+            // Remove from the entry and refresh the list
+            {
+                let list = self.clone();
+                let entry = entry.to_string();
+                let name = attachment.name.clone();
+                remove_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    let list = list.clone();
+                    let entry = entry.clone();
+                    let name = name.clone();
+                    glib::spawn_future_local(async move {
+                        match run_cli_checked_async(attachments::detach_args(&entry, &name)).await {
+                            Ok(_) => show_status(
+                                &list.status,
+                                &format!("Removed {} from {}", name, entry),
+                                Some("success"),
+                            ),
+                            Err(e) => show_status(
+                                &list.status,
+                                &error_text("Remove failed", &e),
+                                Some("error"),
+                            ),
+                        }
+                        list.load(entry);
+                    });
+                });
+            }
+
+            row
+        }
+    }
+
+    /// The version rows of the History expander
+    #[derive(Clone)]
+    struct HistoryList {
+        expander: adw::ExpanderRow,
+        rows: Rc<RefCell<Vec<adw::ActionRow>>>,
+        status: gtk4::Label,
+    }
+
+    impl HistoryList {
+        /// Show an entry's previous values, replacing the rows shown before
+        fn load(&self, entry: String) {
+            let list = self.clone();
+            show_status(
+                &list.status,
+                &format!("Reading history of {}...", entry),
+                None,
+            );
+            glib::spawn_future_local(async move {
+                let path = entry.clone();
+                let result = match run_cli_checked_async(history::export_args()).await {
+                    // Parse off the main loop; the export covers the whole database
+                    Ok(xml) => gio::spawn_blocking(move || history::find_entry(&xml, &path))
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked())),
+                    Err(e) => Err(e),
+                };
+                for row in list.rows.borrow_mut().drain(..) {
+                    list.expander.remove(&row);
+                }
+                match result {
+                    Ok(None) => show_status(
+                        &list.status,
+                        &format!("Entry not found: {}", entry),
+                        Some("error"),
+                    ),
+                    Ok(Some(found)) if found.previous.is_empty() => show_status(
+                        &list.status,
+                        &format!("{} has no earlier values", entry),
+                        None,
+                    ),
+                    Ok(Some(found)) => {
+                        for version in &found.previous {
+                            let row = list.row(&entry, version);
+                            list.expander.add_row(&row);
+                            list.rows.borrow_mut().push(row);
+                        }
+                        list.expander.set_expanded(true);
+                        show_status(
+                            &list.status,
+                            &format!(
+                                "{} earlier value(s) of {}; current since {}",
+                                found.previous.len(),
+                                entry,
+                                found.current.modified_text()
+                            ),
+                            None,
+                        );
+                    }
+                    Err(e) => show_status(
+                        &list.status,
+                        &error_text("Failed to read history", &e),
+                        Some("error"),
+                    ),
+                }
+            });
+        }
+
+        fn row(&self, entry: &str, version: &Version) -> adw::ActionRow {
+            const MASK: &str = "••••••••";
+            let row = adw::ActionRow::new();
+            row.set_title(&version.modified_text());
+            row.set_subtitle(MASK);
+
+            let reveal_button = gtk4::ToggleButton::new();
+            reveal_button.set_icon_name("view-reveal-symbolic");
+            reveal_button.set_valign(gtk4::Align::Center);
+            reveal_button.set_tooltip_text(Some("Show value"));
+            let copy_button = gtk4::Button::from_icon_name("edit-copy-symbolic");
+            copy_button.set_valign(gtk4::Align::Center);
+            copy_button.set_tooltip_text(Some("Copy value"));
+            let restore_button = gtk4::Button::with_label("Restore");
+            restore_button.set_valign(gtk4::Align::Center);
+            row.add_suffix(&reveal_button);
+            row.add_suffix(&copy_button);
+            row.add_suffix(&restore_button);
+
+            {
+                let row = row.clone();
+                // Row subtitles are Pango markup
+                let value = glib::markup_escape_text(&version.value);
+                reveal_button.connect_toggled(move |button| {
+                    row.set_subtitle(if button.is_active() { &value } else { MASK });
+                });
+            }
+
+            {
+                let value = version.value.clone();
+                let status = self.status.clone();
+                copy_button.connect_clicked(move |_| {
+                    let display = gdk::Display::default().unwrap();
+                    display.clipboard().set_text(&value);
+                    show_status(&status, "Copied to clipboard", Some("success"));
+                });
+            }
+
+            // Storing the old value again keeps the current one in history,
+            // so a restore can itself be undone
+            {
+                let list = self.clone();
+                let entry = entry.to_string();
+                let value = version.value.clone();
+                let when = version.modified_text();
+                restore_button.connect_clicked(move |button| {
+                    button.set_sensitive(false);
+                    let list = list.clone();
+                    let entry = entry.clone();
+                    let value = value.clone();
+                    let when = when.clone();
+                    glib::spawn_future_local(async move {
+                        match store_secret_async(entry.clone(), value).await {
+                            Ok(()) => {
+                                list.load(entry.clone());
+                                show_status(
+                                    &list.status,
+                                    &format!("Restored {} to the value from {}", entry, when),
+                                    Some("success"),
+                                );
                             }
-                        });
+                            Err(e) => show_status(
+                                &list.status,
+                                &error_text("Restore failed", &e),
+                                Some("error"),
+                            ),
+                        }
                     });
-                }
+                });
+            }
 
-                // Discover credentials button row
-                let discover_row = adw::ActionRow::new();
-                discover_row.set_title("Discover Credentials");
-                discover_row.set_subtitle("Auto-discover env vars and SSH keys");
-                let discover_button = gtk4::Button::with_label("Discover");
-                discover_button.set_valign(gtk4::Align::Center);
-                discover_row.add_suffix(&discover_button);
-                discover_row.set_activatable_widget(Some(&discover_button));
-                keys_group.add(&discover_row);
+            row
+        }
+    }
 
-                // Wire discover button
-                {
-                    let status_clone = status_label.clone();
-                    discover_button.connect_clicked(move |button| {
-                        button.set_sensitive(false);
-                        let btn = button.clone();
-                        let status = status_clone.clone();
-                        status.set_text("Discovering credentials...");
-                        status.set_visible(true);
-                        status.remove_css_class("error");
-                        status.remove_css_class("success");
+    /// A repository from the workspace scan, with a fix button when its
+    /// identity is wrong
+    fn workspace_row(
+        repo: &workspace::RepoStatus,
+        config: &Config,
+        status: &gtk4::Label,
+    ) -> adw::ActionRow {
+        let row = adw::ActionRow::new();
+        row.set_title(&glib::markup_escape_text(&platform::tilde_path(&repo.path)));
+        row.set_subtitle(&glib::markup_escape_text(&repo.display_text()));
+        match repo.state {
+            workspace::State::Matches => row.add_css_class("success"),
+            workspace::State::Undetected => {}
+            workspace::State::Mismatch | workspace::State::NoEmail => row.add_css_class("error"),
+        }
+        if !repo.state.is_fixable() {
+            return row;
+        }
+        let fix_button = gtk4::Button::with_label("Fix");
+        fix_button.set_valign(gtk4::Align::Center);
+        row.add_suffix(&fix_button);
 
-                        glib::spawn_future_local(async move {
-                            let result = run_cli_args_async(vec![
-                                "keys".into(),
-                                "discover".into(),
-                                "--types".into(),
-                                "all".into(),
-                            ])
-                            .await;
-                            match result {
-                                Ok(output) => {
-                                    status.set_text(output.lines().last().unwrap_or("Done"));
-                                    status.add_css_class("success");
-                                }
-                                Err(e) => {
-                                    status.set_text(&format!("Discovery failed: {}", e));
-                                    status.add_css_class("error");
-                                }
-                            }
+        let repo = repo.clone();
+        let config = config.clone();
+        let status = status.clone();
+        let row_ref = row.clone();
+        fix_button.connect_clicked(move |button| {
+            button.set_sensitive(false);
+            let btn = button.clone();
+            let repo = repo.clone();
+            let config = config.clone();
+            let status = status.clone();
+            let row = row_ref.clone();
+            glib::spawn_future_local(async move {
+                let result = gio::spawn_blocking(move || {
+                    workspace::fix(&config, &repo)?;
+                    workspace::check(&config, &repo.path)
+                })
+                .await
+                .unwrap_or_else(|_| Err(worker_panicked()));
+                match result {
+                    Ok(checked) => {
+                        row.set_subtitle(&glib::markup_escape_text(&checked.display_text()));
+                        if checked.state == workspace::State::Matches {
+                            row.remove_css_class("error");
+                            row.add_css_class("success");
+                            btn.set_visible(false);
+                        } else {
                             btn.set_sensitive(true);
-                        });
-                    });
+                        }
+                    }
+                    Err(e) => {
+                        btn.set_sensitive(true);
+                        show_status(
+                            &status,
+                            &error_text("Fixing the repository failed", &e),
+                            Some("error"),
+                        );
+                    }
                 }
+            });
+        });
+        row
+    }
 
-                main_box.append(&keys_group);
-                main_box.append(&search_results_label);
+    /// A downloaded resident key, with where to install it
+    ///
+    /// The choices are the key's own file name, or the `-sk` variant of one
+    /// of `identities` (created, or updated to the new handle).
+    fn security_key_row(
+        key: &ResidentKey,
+        identities: Vec<String>,
+        staging: &Rc<RefCell<Option<fido2::Staging>>>,
+        status: &gtk4::Label,
+        imp_weak: &glib::subclass::ObjectImplWeakRef<RemoteJugglerWindow>,
+    ) -> adw::ActionRow {
+        let row = adw::ActionRow::new();
+        let label = if key.label().is_empty() {
+            "Default application".to_string()
+        } else {
+            key.label().to_string()
+        };
+        row.set_title(&glib::markup_escape_text(&label));
+        row.set_subtitle(&glib::markup_escape_text(&format!(
+            "{}, {}",
+            key.key_type,
+            key.file_name()
+        )));
 
-                // Connect store PIN button handler
-                let pin_entry_clone = pin_entry.clone();
-                let pin_status_clone = pin_status_label.clone();
-                let current_identity = config.state.current_identity.clone();
-                store_button.connect_clicked(move |button| {
-                    let pin = pin_entry_clone.text();
-                    if pin.is_empty() {
-                        tracing::warn!("Cannot store empty PIN");
-                        return;
+        let mut choices = vec![format!("Save as {}", key.file_name())];
+        choices.extend(
+            identities
+                .iter()
+                .map(|name| format!("As {}", fido2::variant_name(name))),
+        );
+        let choices: Vec<&str> = choices.iter().map(String::as_str).collect();
+        let target = gtk4::DropDown::from_strings(&choices);
+        target.set_valign(gtk4::Align::Center);
+        // Preselect the identity the application is named after
+        if !identities.is_empty() && !key.label().is_empty() {
+            let label = key.label().trim_end_matches(fido2::VARIANT_SUFFIX);
+            if identities[0] == label {
+                target.set_selected(1);
+            }
+        }
+        let save = gtk4::Button::with_label("Save");
+        save.set_valign(gtk4::Align::Center);
+        save.add_css_class("suggested-action");
+        row.add_suffix(&target);
+        row.add_suffix(&save);
+
+        let key = key.clone();
+        let staging = staging.clone();
+        let status = status.clone();
+        let imp_weak = imp_weak.clone();
+        save.connect_clicked(move |button| {
+            if staging.borrow().is_none() {
+                return;
+            }
+            let identity = (target.selected() as usize)
+                .checked_sub(1)
+                .and_then(|i| identities.get(i).cloned());
+            button.set_sensitive(false);
+            let btn = button.clone();
+            let key = key.clone();
+            let status = status.clone();
+            let imp_weak = imp_weak.clone();
+            glib::spawn_future_local(async move {
+                let result = gio::spawn_blocking(move || {
+                    let ssh_dir = platform::ssh_dir()
+                        .ok_or_else(|| Error::Io(std::io::Error::other("No home directory")))?;
+                    let Some(identity) = identity else {
+                        let path = fido2::install(&key, &ssh_dir, &key.file_name())?;
+                        return Ok(format!("Saved {}", path.display()));
+                    };
+                    let path = fido2::install(&key, &ssh_dir, &fido2::variant_name(&identity))?;
+                    let mut applied = Ok(Default::default());
+                    Config::update(|config| {
+                        applied = fido2::apply_variant(config, &identity, &path);
+                    })?;
+                    let (name, existed) = applied?;
+                    Ok::<_, Error>(if existed {
+                        format!("Updated {} to use {}", name, path.display())
+                    } else {
+                        format!(
+                            "Created {} with {}; upload it under Public Keys",
+                            name,
+                            path.display()
+                        )
+                    })
+                })
+                .await
+                .unwrap_or_else(|_| Err(worker_panicked()));
+                match result {
+                    Ok(text) => {
+                        show_status(&status, &text, Some("success"));
+                        if let Some(imp) = imp_weak.upgrade() {
+                            imp.reload_config_and_ui();
+                        }
+                    }
+                    Err(e) => {
+                        show_status(&status, &error_text("Saving key failed", &e), Some("error"));
+                        btn.set_sensitive(true);
                     }
+                }
+            });
+        });
+        row
+    }
 
-                    let identity = current_identity.clone();
-                    if identity.is_empty() {
-                        tracing::warn!("No identity selected");
-                        return;
+    /// A checked host key, with a Trust or Replace button if the user has to
+    /// confirm it
+    fn host_key_row(key: HostKey, state: HostKeyStatus, status: &gtk4::Label) -> adw::ActionRow {
+        let row = adw::ActionRow::new();
+        row.set_title(&glib::markup_escape_text(&format!(
+            "{} ({})",
+            key.host,
+            known_hosts::key_type_name(&key.key_type)
+        )));
+        let fingerprint = key.fingerprint();
+        let (subtitle, badge, css) = match &state {
+            HostKeyStatus::Trusted => (fingerprint, "Trusted", "success"),
+            HostKeyStatus::Pinned { replaces: None } => {
+                (fingerprint, "Added, matches published key", "success")
+            }
+            HostKeyStatus::Pinned {
+                replaces: Some(previous),
+            } => (
+                format!("{}\nReplaced the outdated {}", fingerprint, previous),
+                "Updated, matches published key",
+                "success",
+            ),
+            HostKeyStatus::PinMismatch { expected } => (
+                format!(
+                    "{}\nPublished key is {}; the connection may be intercepted",
+                    fingerprint, expected
+                ),
+                "Doesn't match published key",
+                "error",
+            ),
+            HostKeyStatus::Unverified => (
+                format!(
+                    "{}\nCompare with the fingerprint the host's admin publishes",
+                    fingerprint
+                ),
+                "",
+                "",
+            ),
+            HostKeyStatus::Changed { previous } => (
+                format!(
+                    "{}\nWas {}; confirm the change with the host's admin",
+                    fingerprint, previous
+                ),
+                "",
+                "",
+            ),
+        };
+        row.set_subtitle(&glib::markup_escape_text(&subtitle));
+        if !badge.is_empty() {
+            let label = gtk4::Label::new(Some(badge));
+            label.add_css_class(css);
+            row.add_suffix(&label);
+            return row;
+        }
+
+        let changed = matches!(state, HostKeyStatus::Changed { .. });
+        let button = gtk4::Button::with_label(if changed { "Replace" } else { "Trust" });
+        button.set_valign(gtk4::Align::Center);
+        button.add_css_class(if changed {
+            "destructive-action"
+        } else {
+            "suggested-action"
+        });
+        row.add_suffix(&button);
+        let status = status.clone();
+        button.connect_clicked(move |button| {
+            button.set_sensitive(false);
+            let btn = button.clone();
+            let key = key.clone();
+            let status = status.clone();
+            glib::spawn_future_local(async move {
+                let host = key.host.clone();
+                let result =
+                    gio::spawn_blocking(move || known_hosts::trust(&[key]).map_err(Error::from))
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                match result {
+                    Ok(()) => {
+                        btn.set_label("Trusted");
+                        show_status(
+                            &status,
+                            &format!("Added {}'s key to known_hosts", host),
+                            Some("success"),
+                        );
                     }
+                    Err(e) => {
+                        btn.set_sensitive(true);
+                        show_status(
+                            &status,
+                            &error_text("Updating known_hosts failed", &e),
+                            Some("error"),
+                        );
+                    }
+                }
+            });
+        });
+        row
+    }
 
-                    // Disable button during operation
-                    button.set_sensitive(false);
-                    pin_status_clone.set_text("Storing...");
+    /// An identity's registration with its provider: Check looks the key
+    /// up, and turns into Upload if it isn't there
+    fn public_key_row(
+        name: &str,
+        identity: &Identity,
+        status: &gtk4::Label,
+    ) -> (adw::ActionRow, gtk4::Button) {
+        let provider = pubkeys::Provider::for_identity(identity)
+            .map_or("", |provider| provider.display_name());
+        let row = adw::ActionRow::new();
+        row.set_title(&glib::markup_escape_text(name));
+        let key_file = Path::new(&identity.ssh_key_path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let subtitle = format!("{}.pub on {}", key_file, identity.hostname);
+        row.set_subtitle(&glib::markup_escape_text(&subtitle));
+        let button = gtk4::Button::with_label("Check");
+        button.set_valign(gtk4::Align::Center);
+        row.add_suffix(&button);
 
-                    // Spawn async task to call CLI
-                    let button_clone = button.clone();
-                    let status_clone = pin_status_clone.clone();
-                    let entry_clone = pin_entry_clone.clone();
-                    let pin = pin.to_string();
-                    glib::spawn_future_local(async move {
-                        let result = store_pin_async(&identity, &pin).await;
+        // Set once a check finds the key missing
+        let missing = Rc::new(Cell::new(false));
+        let name = name.to_string();
+        let identity = identity.clone();
+        let status = status.clone();
+        let row_ref = row.clone();
+        button.connect_clicked(move |button| {
+            button.set_sensitive(false);
+            let upload = missing.get();
+            let btn = button.clone();
+            let row = row_ref.clone();
+            let missing = missing.clone();
+            let name = name.clone();
+            let identity = identity.clone();
+            let status = status.clone();
+            let subtitle = subtitle.clone();
+            if upload {
+                show_status(&status, &format!("Uploading {}'s key...", name), None);
+            }
+            glib::spawn_future_local(async move {
+                let title = pubkeys::default_title(&name);
+                let result = {
+                    let name = name.clone();
+                    let title = title.clone();
+                    gio::spawn_blocking(move || {
+                        if upload {
+                            pubkeys::upload(&name, &identity, &title)?;
+                            return Ok(pubkeys::Registration::Registered(title));
+                        }
+                        pubkeys::check(&name, &identity)
+                    })
+                    .await
+                    .unwrap_or_else(|_| Err(worker_panicked()))
+                };
+                match result {
+                    Ok(pubkeys::Registration::Registered(title)) => {
+                        missing.set(false);
+                        btn.set_label("Registered");
+                        btn.remove_css_class("suggested-action");
+                        row.set_subtitle(&glib::markup_escape_text(&format!(
+                            "{}\nRegistered on {} as \"{}\"",
+                            subtitle, provider, title
+                        )));
+                        if upload {
+                            show_status(
+                                &status,
+                                &format!("Uploaded {}'s key to {}", name, provider),
+                                Some("success"),
+                            );
+                        }
+                    }
+                    Ok(pubkeys::Registration::NotRegistered) => {
+                        missing.set(true);
+                        btn.set_label("Upload");
+                        btn.add_css_class("suggested-action");
+                        btn.set_sensitive(true);
+                        row.set_subtitle(&glib::markup_escape_text(&format!(
+                            "{}\nNot on {}; uploads as \"{}\"",
+                            subtitle, provider, title
+                        )));
+                    }
+                    Err(e) => {
+                        btn.set_sensitive(true);
+                        let prefix = if upload {
+                            "Uploading key failed"
+                        } else {
+                            "Checking key failed"
+                        };
+                        show_status(
+                            &status,
+                            &error_text(&format!("{}: {}", name, prefix), &e),
+                            Some("error"),
+                        );
+                    }
+                }
+            });
+        });
+        (row, button)
+    }
 
-                        // Update UI based on result
-                        match result {
-                            Ok(()) => {
-                                status_clone.set_text("Stored");
-                                status_clone.remove_css_class("dim-label");
-                                status_clone.add_css_class("success");
-                                entry_clone.set_text("");
-                                tracing::info!("PIN stored successfully for {}", identity);
-                            }
-                            Err(e) => {
-                                status_clone.set_text("Failed");
-                                status_clone.remove_css_class("dim-label");
-                                status_clone.add_css_class("error");
-                                tracing::error!("Failed to store PIN: {}", e);
+    /// Where an identity's GPG key is published: Check looks, and turns
+    /// into Publish if it's missing anywhere
+    fn gpg_publish_row(name: &str, identity: &Identity, status: &gtk4::Label) -> adw::ActionRow {
+        let provider = pubkeys::Provider::for_identity(identity)
+            .map_or("", |provider| provider.display_name());
+        let row = adw::ActionRow::new();
+        row.set_title(&glib::markup_escape_text(name));
+        row.set_subtitle(&glib::markup_escape_text(&format!(
+            "{} <{}>",
+            identity.gpg.key_id, identity.email
+        )));
+        let button = gtk4::Button::with_label("Check");
+        button.set_valign(gtk4::Align::Center);
+        row.add_suffix(&button);
+
+        // Set once a check finds the key missing somewhere
+        let missing = Rc::new(Cell::new(false));
+        let name = name.to_string();
+        let identity = identity.clone();
+        let status = status.clone();
+        let row_ref = row.clone();
+        button.connect_clicked(move |button| {
+            button.set_sensitive(false);
+            let publish = missing.get();
+            let btn = button.clone();
+            let row = row_ref.clone();
+            let missing = missing.clone();
+            let name = name.clone();
+            let identity = identity.clone();
+            let status = status.clone();
+            if publish {
+                show_status(&status, &format!("Publishing {}'s GPG key...", name), None);
+            }
+            glib::spawn_future_local(async move {
+                let result = {
+                    let name = name.clone();
+                    gio::spawn_blocking(move || {
+                        if publish {
+                            gpg_publish::publish(&name, &identity)
+                        } else {
+                            gpg_publish::check(&name, &identity)
+                        }
+                    })
+                    .await
+                    .unwrap_or_else(|_| Err(worker_panicked()))
+                };
+                match result {
+                    Ok(published) => {
+                        row.set_subtitle(&glib::markup_escape_text(
+                            &published.display_text(provider),
+                        ));
+                        let done = published.is_published();
+                        missing.set(!done);
+                        if done {
+                            btn.set_label("Published");
+                            btn.remove_css_class("suggested-action");
+                        } else {
+                            btn.set_label("Publish");
+                            btn.add_css_class("suggested-action");
+                            btn.set_sensitive(true);
+                        }
+                        if publish && done {
+                            show_status(
+                                &status,
+                                &format!("Published {}'s GPG key", name),
+                                Some("success"),
+                            );
+                        } else if publish {
+                            show_status(
+                                &status,
+                                &format!("{}'s GPG key is only partly published", name),
+                                Some("error"),
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        btn.set_sensitive(true);
+                        let prefix = if publish {
+                            "Publishing GPG key failed"
+                        } else {
+                            "Checking GPG key failed"
+                        };
+                        show_status(
+                            &status,
+                            &error_text(&format!("{}: {}", name, prefix), &e),
+                            Some("error"),
+                        );
+                    }
+                }
+            });
+        });
+        row
+    }
+
+    /// A private key no identity uses, with Attach, Archive and Delete
+    ///
+    /// Delete asks for a second click, since the key can't be recovered.
+    fn orphan_key_row(
+        key: PathBuf,
+        identities: &[String],
+        status: &gtk4::Label,
+        imp_weak: &glib::subclass::ObjectImplWeakRef<RemoteJugglerWindow>,
+    ) -> adw::ActionRow {
+        let row = adw::ActionRow::new();
+        row.set_title(&glib::markup_escape_text(
+            &key.file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        ));
+        row.set_subtitle(&glib::markup_escape_text(&format!(
+            "{}, not used by any identity",
+            key.display()
+        )));
+
+        let names: Vec<&str> = identities.iter().map(String::as_str).collect();
+        let target = gtk4::DropDown::from_strings(&names);
+        target.set_valign(gtk4::Align::Center);
+        let attach = gtk4::Button::with_label("Attach");
+        attach.set_valign(gtk4::Align::Center);
+        attach.set_sensitive(!identities.is_empty());
+        let archive = gtk4::Button::with_label("Archive");
+        archive.set_valign(gtk4::Align::Center);
+        let delete = gtk4::Button::with_label("Delete");
+        delete.set_valign(gtk4::Align::Center);
+        delete.add_css_class("destructive-action");
+        row.add_suffix(&target);
+        row.add_suffix(&attach);
+        row.add_suffix(&archive);
+        row.add_suffix(&delete);
+
+        // Wire attach button: point the chosen identity at the key
+        {
+            let key = key.clone();
+            let identities = identities.to_vec();
+            let status = status.clone();
+            let imp_weak = imp_weak.clone();
+            attach.connect_clicked(move |button| {
+                let Some(identity) = identities.get(target.selected() as usize).cloned() else {
+                    return;
+                };
+                button.set_sensitive(false);
+                let btn = button.clone();
+                let key = key.clone();
+                let status = status.clone();
+                let imp_weak = imp_weak.clone();
+                glib::spawn_future_local(async move {
+                    let name = identity.clone();
+                    let path = key.clone();
+                    let result = gio::spawn_blocking(move || {
+                        Config::update_identity(&name, |identity| {
+                            hygiene::attach(identity, &path);
+                        })?;
+                        Ok::<_, Error>(())
+                    })
+                    .await
+                    .unwrap_or_else(|_| Err(worker_panicked()));
+                    match result {
+                        Ok(()) => {
+                            show_status(
+                                &status,
+                                &format!("{} now uses {}", identity, key.display()),
+                                Some("success"),
+                            );
+                            if let Some(imp) = imp_weak.upgrade() {
+                                imp.reload_config_and_ui();
                             }
                         }
-                        button_clone.set_sensitive(true);
-                    });
+                        Err(e) => {
+                            btn.set_sensitive(true);
+                            show_status(
+                                &status,
+                                &error_text("Attaching key failed", &e),
+                                Some("error"),
+                            );
+                        }
+                    }
                 });
-            } else {
-                // Show error status page
-                let status_page = adw::StatusPage::new();
-                status_page.set_icon_name(Some("dialog-error-symbolic"));
-                status_page.set_title("Configuration Not Found");
-                status_page.set_description(Some(
-                    "Could not load RemoteJuggler configuration.\n\
-                     Please ensure ~/.config/remote-juggler/config.json exists.",
-                ));
-                main_box.append(&status_page);
+            });
+        }
+
+        // Wire archive and delete buttons: both retire the row on success
+        let retire = {
+            let row = row.clone();
+            let buttons = [attach.clone(), archive.clone(), delete.clone()];
+            move |subtitle: &str| {
+                for button in &buttons {
+                    button.set_sensitive(false);
+                }
+                row.set_subtitle(&glib::markup_escape_text(subtitle));
+            }
+        };
+        {
+            let key = key.clone();
+            let status = status.clone();
+            let retire = retire.clone();
+            archive.connect_clicked(move |button| {
+                button.set_sensitive(false);
+                let btn = button.clone();
+                let key = key.clone();
+                let status = status.clone();
+                let retire = retire.clone();
+                glib::spawn_future_local(async move {
+                    let result = gio::spawn_blocking(move || hygiene::archive(&key))
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                    match result {
+                        Ok(path) => {
+                            let text = format!("Archived to {}", path.display());
+                            retire(&text);
+                            show_status(&status, &text, Some("success"));
+                        }
+                        Err(e) => {
+                            btn.set_sensitive(true);
+                            show_status(
+                                &status,
+                                &error_text("Archiving key failed", &e),
+                                Some("error"),
+                            );
+                        }
+                    }
+                });
+            });
+        }
+        {
+            let armed = Rc::new(Cell::new(false));
+            let status = status.clone();
+            delete.connect_clicked(move |button| {
+                if !armed.replace(true) {
+                    button.set_label("Delete Permanently");
+                    show_status(
+                        &status,
+                        &format!(
+                            "Deleting {} can't be undone; click again to confirm",
+                            key.display()
+                        ),
+                        Some("error"),
+                    );
+                    return;
+                }
+                button.set_sensitive(false);
+                let btn = button.clone();
+                let key = key.clone();
+                let status = status.clone();
+                let retire = retire.clone();
+                glib::spawn_future_local(async move {
+                    let path = key.clone();
+                    let result = gio::spawn_blocking(move || hygiene::delete(&path))
+                        .await
+                        .unwrap_or_else(|_| Err(worker_panicked()));
+                    match result {
+                        Ok(()) => {
+                            retire("Deleted");
+                            show_status(
+                                &status,
+                                &format!("Deleted {}", key.display()),
+                                Some("success"),
+                            );
+                        }
+                        Err(e) => {
+                            btn.set_sensitive(true);
+                            show_status(
+                                &status,
+                                &error_text("Deleting key failed", &e),
+                                Some("error"),
+                            );
+                        }
+                    }
+                });
+            });
+        }
+        row
+    }
+
+    /// An identity's audit score, expanding to its findings and what to do
+    /// about each
+    fn key_audit_row(audit: &key_audit::IdentityAudit) -> adw::ExpanderRow {
+        let row = adw::ExpanderRow::new();
+        row.set_title(&glib::markup_escape_text(&audit.identity));
+        row.set_subtitle(&glib::markup_escape_text(&audit.key_text()));
+        let badge = gtk4::Label::new(Some(&audit.score.to_string()));
+        badge.add_css_class("heading");
+        badge.add_css_class(match audit.score {
+            80.. => "success",
+            50..=79 => "warning",
+            _ => "error",
+        });
+        row.add_action(&badge);
+        if audit.findings.is_empty() {
+            let ok = adw::ActionRow::new();
+            ok.set_title("No findings");
+            row.add_row(&ok);
+        }
+        for finding in &audit.findings {
+            let finding_row = adw::ActionRow::new();
+            finding_row.set_title(&glib::markup_escape_text(&finding.title()));
+            finding_row.set_subtitle(finding.remediation());
+            if finding.severity() == key_audit::Severity::High {
+                finding_row.add_css_class("error");
             }
+            row.add_row(&finding_row);
+        }
+        row
+    }
 
-            main_box
+    /// A stored token's scopes, expanding to its findings and the
+    /// replacement to create instead
+    fn token_audit_row(audit: &token_audit::TokenAudit) -> adw::ExpanderRow {
+        let row = adw::ExpanderRow::new();
+        row.set_title(&glib::markup_escape_text(&audit.identity));
+        row.set_subtitle(&glib::markup_escape_text(&audit.token_text()));
+        let (text, css) = match audit.severity() {
+            None => ("minimal", "success"),
+            Some(key_audit::Severity::High) => ("over-privileged", "error"),
+            Some(_) => ("review", "warning"),
+        };
+        let badge = gtk4::Label::new(Some(text));
+        badge.add_css_class(css);
+        row.add_action(&badge);
+        if audit.findings.is_empty() {
+            let ok = adw::ActionRow::new();
+            ok.set_title("No findings");
+            row.add_row(&ok);
+        }
+        for finding in &audit.findings {
+            let finding_row = adw::ActionRow::new();
+            finding_row.set_title(&glib::markup_escape_text(&finding.title()));
+            finding_row.set_subtitle(finding.remediation());
+            if finding.severity(audit.provider) == key_audit::Severity::High {
+                finding_row.add_css_class("error");
+            }
+            row.add_row(&finding_row);
+        }
+        let recommendation = audit.recommendation();
+        if !audit.findings.is_empty() && !recommendation.is_empty() {
+            let uses: Vec<&str> = audit.uses.iter().map(|u| u.display_text()).collect();
+            let replace_row = adw::ActionRow::new();
+            replace_row.set_title(&glib::markup_escape_text(&format!(
+                "Recommended: {}",
+                recommendation.join(", ")
+            )));
+            replace_row.set_subtitle(&glib::markup_escape_text(&format!(
+                "Used for {}",
+                uses.join(", ")
+            )));
+            let create_button = gtk4::Button::with_label("Create Token");
+            create_button.set_valign(gtk4::Align::Center);
+            replace_row.add_suffix(&create_button);
+            let url = audit.replacement_url();
+            create_button.connect_clicked(move |_| {
+                gtk4::UriLauncher::new(&url).launch(
+                    None::<&gtk4::Window>,
+                    None::<&gio::Cancellable>,
+                    |_| {},
+                );
+            });
+            row.add_row(&replace_row);
+        }
+        row
+    }
+
+    fn show_status(label: &gtk4::Label, text: &str, css: Option<&str>) {
+        label.set_text(text);
+        label.set_visible(true);
+        label.remove_css_class("error");
+        label.remove_css_class("success");
+        label.remove_css_class("warning");
+        if let Some(css) = css {
+            label.add_css_class(css);
+        }
+    }
+
+    /// File chooser filtered to SOPS-encrypted files
+    fn sops_file_dialog() -> gtk4::FileDialog {
+        let dialog = gtk4::FileDialog::new();
+        dialog.set_title("Select SOPS file");
+        let filter = gtk4::FileFilter::new();
+        for pattern in sops::FILE_PATTERNS {
+            filter.add_pattern(pattern);
+        }
+        filter.set_name(Some("SOPS-encrypted files"));
+        let all = gtk4::FileFilter::new();
+        all.add_pattern("*");
+        all.set_name(Some("All files"));
+        let filters = gio::ListStore::new::<gtk4::FileFilter>();
+        filters.append(&filter);
+        filters.append(&all);
+        dialog.set_filters(Some(&filters));
+        dialog
+    }
+
+    /// Tracks a cancellable operation started from a button
+    ///
+    /// The first click starts the operation; clicking again while it runs
+    /// cancels it.
+    #[derive(Clone, Default)]
+    struct CancelSlot(Rc<RefCell<Option<CancelToken>>>);
+
+    impl CancelSlot {
+        /// Cancel the running operation, returning true if one was running
+        fn cancel_running(&self) -> bool {
+            match self.0.borrow().as_ref() {
+                Some(token) => {
+                    token.cancel();
+                    true
+                }
+                None => false,
+            }
+        }
+
+        /// Register a new operation and return its token
+        fn start(&self) -> CancelToken {
+            let token = CancelToken::new();
+            *self.0.borrow_mut() = Some(token.clone());
+            token
         }
+
+        fn finish(&self) {
+            *self.0.borrow_mut() = None;
+        }
+    }
+
+    /// Row with a progress bar for long-running operations
+    ///
+    /// Hidden until an operation starts. Shows a fraction when the total is
+    /// known and pulses otherwise.
+    #[derive(Clone)]
+    struct ProgressRow {
+        row: adw::ActionRow,
+        bar: gtk4::ProgressBar,
+    }
+
+    impl ProgressRow {
+        fn new() -> Self {
+            let row = adw::ActionRow::new();
+            let bar = gtk4::ProgressBar::new();
+            bar.set_valign(gtk4::Align::Center);
+            bar.set_hexpand(true);
+            bar.set_show_text(true);
+            row.add_suffix(&bar);
+            row.set_visible(false);
+            Self { row, bar }
+        }
+
+        fn widget(&self) -> &adw::ActionRow {
+            &self.row
+        }
+
+        fn show(&self, state: &ProgressState) {
+            let mut title = state.operation.clone();
+            if let Some(first) = title.get_mut(0..1) {
+                first.make_ascii_uppercase();
+            }
+            self.row.set_title(&title);
+            self.row.set_subtitle(state.item.as_deref().unwrap_or(""));
+            match state.fraction() {
+                Some(fraction) => self.bar.set_fraction(fraction),
+                None => self.bar.pulse(),
+            }
+            self.bar.set_text(Some(&state.counter_text()));
+            self.row.set_visible(true);
+        }
+
+        fn hide(&self) {
+            self.row.set_visible(false);
+            self.bar.set_fraction(0.0);
+        }
+    }
+
+    /// Format an error for a status label, appending remediation when known
+    fn error_text(prefix: &str, err: &Error) -> String {
+        match err.remediation() {
+            Some(hint) => format!("{}: {}\n{}", prefix, err, hint),
+            None => format!("{}: {}", prefix, err),
+        }
+    }
+
+    /// Await `future`, revealing `prompt` once it has run for
+    /// [`touch::PROMPT_DELAY`]: by then the key is waiting for a touch
+    async fn with_touch_prompt<T>(
+        prompt: Option<gtk4::Revealer>,
+        future: impl Future<Output = T>,
+    ) -> T {
+        let Some(prompt) = prompt else {
+            return future.await;
+        };
+        let done = Rc::new(Cell::new(false));
+        {
+            let done = done.clone();
+            let prompt = prompt.clone();
+            glib::timeout_add_local_once(touch::PROMPT_DELAY, move || {
+                if !done.get() {
+                    prompt.set_reveal_child(true);
+                }
+            });
+        }
+        let output = future.await;
+        done.set(true);
+        prompt.set_reveal_child(false);
+        output
     }
 
     /// Run a remote-juggler CLI command asynchronously with two args
-    async fn run_cli_async(command: &str, arg: &str) -> Result<String, String> {
+    async fn run_cli_async(command: &str, arg: &str) -> Result<String, Error> {
         run_cli_args_async(vec![command.to_string(), arg.to_string()]).await
     }
 
     /// Run a remote-juggler CLI command asynchronously with arbitrary args
-    async fn run_cli_args_async(args: Vec<String>) -> Result<String, String> {
-        let result = gio::spawn_blocking(move || {
-            let output = Command::new("remote-juggler").args(&args).output();
-
-            match output {
-                Ok(output) => {
-                    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                    if output.status.success() {
-                        Ok(stdout)
-                    } else {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        Err(format!("{}", stderr))
-                    }
-                }
-                Err(e) => Err(format!("Failed to execute command: {}", e)),
+    async fn run_cli_args_async(args: Vec<String>) -> Result<String, Error> {
+        let options = RunOptions::for_args(&args);
+        run_cli_with_options_async(args, options).await
+    }
+
+    /// Run a CLI command that reports failure in its output, not its exit code
+    async fn run_cli_checked_async(args: Vec<String>) -> Result<String, Error> {
+        let output = run_cli_args_async(args).await?;
+        cli::reported_error(&output).map_err(Error::from_cli)?;
+        Ok(output)
+    }
+
+    /// Merge (or with `dry_run`, compare) another database into the key store
+    ///
+    /// An empty password means the other database shares the key store's.
+    async fn run_sync_async(
+        other: PathBuf,
+        password: String,
+        dry_run: bool,
+    ) -> Result<String, Error> {
+        let args = merge::sync_args(&other, dry_run, password.is_empty());
+        let options = RunOptions::for_args(&args);
+        let env: Vec<(&str, &str)> = if password.is_empty() {
+            Vec::new()
+        } else {
+            vec![(merge::SYNC_PASSWORD_ENV, password.as_str())]
+        };
+        // Not through the cache: the password travels in the environment
+        let result = cli::run_streaming(&args, &env, &options, |_| {})
+            .await
+            .map_err(Error::from_cli);
+        if !dry_run {
+            cli_cache::global().invalidate();
+        }
+        let output = result?;
+        cli::reported_error(&output).map_err(Error::from_cli)?;
+        Ok(output)
+    }
+
+    /// Run a CLI command that the user can abort through `token`
+    async fn run_cli_cancellable_async(
+        args: Vec<String>,
+        token: CancelToken,
+    ) -> Result<String, Error> {
+        let options = RunOptions::for_args(&args).with_cancel(token);
+        run_cli_with_options_async(args, options).await
+    }
+
+    async fn run_cli_with_options_async(
+        args: Vec<String>,
+        options: RunOptions,
+    ) -> Result<String, Error> {
+        cli::run_cached_async(&args, &options)
+            .await
+            .map_err(Error::from_cli)
+    }
+
+    /// Search each unlocked database and list the hits with their source
+    ///
+    /// Locked databases are skipped and named at the end.
+    async fn search_databases_async(query: String, keystores: Vec<KeystoreDatabase>) -> String {
+        let args = databases::search_args(&query);
+        let options = RunOptions::for_args(&args);
+        let mut per_database = Vec::new();
+        let mut notes = Vec::new();
+        for db in &keystores {
+            let Some(env) = databases::env_for(db) else {
+                notes.push(format!("{}: locked, not searched", db.name));
+                continue;
+            };
+            let env: Vec<(&str, &str)> =
+                env.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+            // Not through the cache: the password travels in the environment
+            let result = cli::run_streaming(&args, &env, &options, |_| {})
+                .await
+                .map_err(Error::from_cli)
+                .and_then(|output| databases::parse_search(&output));
+            match result {
+                Ok(hits) => per_database.push((db.name.clone(), hits)),
+                Err(e) => notes.push(error_text(&db.name, &e)),
+            }
+        }
+
+        let hits = databases::merge_hits(per_database);
+        let mut text = if hits.is_empty() {
+            "No matches\n".to_string()
+        } else {
+            hits.iter()
+                .map(|(db, hit)| format!("[{}] {} ({})\n", db, hit.entry_path, hit.score))
+                .collect()
+        };
+        for note in notes {
+            text.push_str(&format!("{}\n", note));
+        }
+        text
+    }
+
+    /// Run a cancellable long-running CLI command, rendering its progress
+    /// events in `progress` as they arrive
+    ///
+    /// Returns stdout with the progress events removed. Plain output lines
+    /// from a CLI that doesn't emit events are shown as the current item.
+    async fn run_cli_progress_async(
+        args: Vec<String>,
+        token: CancelToken,
+        progress: &ProgressRow,
+    ) -> Result<String, Error> {
+        let options = RunOptions::for_args(&args).with_cancel(token);
+        let mut state = ProgressState {
+            operation: args.get(1).or(args.first()).cloned().unwrap_or_default(),
+            ..Default::default()
+        };
+        progress.show(&state);
+
+        let env = [(progress::PROGRESS_ENV, "json")];
+        let result = cli::run_streaming(&args, &env, &options, |line| {
+            match ProgressEvent::parse_line(line) {
+                Some(event) => state.apply(&event),
+                None if !line.trim().is_empty() => state.item = Some(line.trim().to_string()),
+                None => return,
             }
+            progress.show(&state);
         })
         .await;
 
-        match result {
-            Ok(inner_result) => inner_result,
-            Err(e) => Err(format!("Task join error: {:?}", e)),
+        progress.hide();
+        if cli_cache::is_mutating(&args) {
+            cli_cache::global().invalidate();
         }
+        result
+            .map(|stdout| progress::strip_events(&stdout))
+            .map_err(Error::from_cli)
     }
 
-    /// Store a PIN for an identity using the remote-juggler CLI
-    async fn store_pin_async(identity: &str, pin: &str) -> Result<(), String> {
-        // Run the command in a blocking thread to avoid blocking the UI
-        let identity = identity.to_string();
-        let pin = pin.to_string();
-
-        let result = gio::spawn_blocking(move || {
-            let output = Command::new("remote-juggler")
-                .args(["pin", "store", &identity])
-                .env("REMOTE_JUGGLER_PIN", &pin)
-                .output();
-
-            match output {
-                Ok(output) => {
-                    if output.status.success() {
-                        Ok(())
-                    } else {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        Err(format!("Command failed: {}", stderr))
-                    }
-                }
-                Err(e) => Err(format!("Failed to execute command: {}", e)),
+    /// Get a secret by key path, from KDBX or the backend mounted at its prefix
+    async fn get_secret_async(path: String) -> Result<String, Error> {
+        let Some((store, name)) = backend::route(&path) else {
+            return run_cli_args_async(cli::args(&["keys", "get", &path])).await;
+        };
+        gio::spawn_blocking(move || store.get(&name))
+            .await
+            .unwrap_or_else(|_| Err(worker_panicked()))
+    }
+
+    /// Store a secret by key path, in KDBX or the backend mounted at its prefix
+    async fn store_secret_async(path: String, value: String) -> Result<(), Error> {
+        let Some((store, name)) = backend::route(&path) else {
+            return gio::spawn_blocking(move || {
+                cli::store_secret(&path, &value).map_err(Error::from_cli)
+            })
+            .await
+            .unwrap_or_else(|_| Err(worker_panicked()));
+        };
+        gio::spawn_blocking(move || store.set(&name, &value))
+            .await
+            .unwrap_or_else(|_| Err(worker_panicked()))
+    }
+
+    fn worker_panicked() -> Error {
+        Error::Io(std::io::Error::other("Background task panicked"))
+    }
+
+    /// Check the inserted YubiKeys against the identity's before switching.
+    /// None when the switch is refused, after saying why in `status`;
+    /// otherwise a warning to show with the result
+    async fn yubikey_guard_async(name: &str, status: &gtk4::Label) -> Option<Option<String>> {
+        let name = name.to_string();
+        let guard = gio::spawn_blocking(move || yubikeys::guard(&name))
+            .await
+            .unwrap_or(yubikeys::Guard::Proceed);
+        match guard {
+            yubikeys::Guard::Proceed => Some(None),
+            yubikeys::Guard::Warn(reason) => Some(Some(reason)),
+            yubikeys::Guard::Refuse(reason) => {
+                show_status(status, &format!("Not switching: {}", reason), Some("error"));
+                None
             }
+        }
+    }
+
+    /// Check the workspace for uncommitted work under the current identity
+    /// before switching to `name`, and whether its token still works; the
+    /// warning to show with the result
+    async fn switch_warnings_async(name: &str) -> Option<String> {
+        let name = name.to_string();
+        let span = tracing::info_span!("switch checks");
+        gio::spawn_blocking(move || {
+            let _entered = span.enter();
+            let config = Config::load().ok()?;
+            join_warnings(
+                join_warnings(
+                    switch_guard::warning(&config, &name),
+                    revocation::warning(&config, &name),
+                ),
+                tailscale::warning(&config, &name),
+            )
         })
-        .await;
+        .await
+        .ok()
+        .flatten()
+    }
+
+    fn join_warnings(first: Option<String>, second: Option<String>) -> Option<String> {
+        match (first, second) {
+            (Some(first), Some(second)) => Some(format!("{}; {}", first, second)),
+            (first, second) => first.or(second),
+        }
+    }
 
-        match result {
-            Ok(inner_result) => inner_result,
-            Err(e) => Err(format!("Task join error: {:?}", e)),
+    /// Report a switch, with the guards' warnings if there are any
+    fn switched_text(status: &gtk4::Label, text: &str, warning: Option<String>) {
+        match warning {
+            Some(warning) => {
+                show_status(status, &format!("{}; {}", text, warning), Some("warning"))
+            }
+            None => show_status(status, text, Some("success")),
         }
     }
+
+    /// Store a PIN for an identity in its PIN storage
+    async fn store_pin_async(identity: &str, pin: &str) -> Result<PinStorageMethod, Error> {
+        let (identity, pin) = (identity.to_string(), pin.to_string());
+        let result = gio::spawn_blocking(move || pin_storage::store_pin(&identity, &pin))
+            .await
+            .unwrap_or_else(|_| Err(worker_panicked()));
+        cli_cache::global().invalidate();
+        result
+    }
 }
@@ -0,0 +1,390 @@
+//! Identities across the repositories in a workspace
+//!
+//! Walks the configured workspace roots for git and Mercurial
+//! repositories and puts the identity each one commits as (its effective
+//! `user.email`, whichever config file sets it) next to the identity
+//! RemoteJuggler would choose for it. Mismatches can be fixed in bulk by
+//! writing the expected identity's settings to each repository's own git
+//! config, or hgrc. Submodules keep their own config, so an identity can
+//! also be written to every submodule of a repository. Repositories
+//! colocated with Jujutsu are checked, and fixed, by what jj commits with.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::branches;
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, Identity};
+use crate::detect::{self, IdentityMatch};
+use crate::error::{CliError, Error};
+use crate::gitconfig;
+use crate::hg;
+use crate::jj;
+use crate::platform;
+
+/// How deep below a root repositories are looked for
+pub const MAX_DEPTH: usize = 4;
+
+/// Directories never descended into
+const SKIP_DIRS: &[&str] = &["node_modules", "target", "vendor", "venv"];
+
+/// The git and Mercurial repositories under `root`, sorted; repositories
+/// inside another repository (submodules, vendored checkouts) aren't
+/// listed. Blocks
+pub fn find_repos(root: &Path, max_depth: usize) -> Vec<PathBuf> {
+    let mut repos = Vec::new();
+    walk(root, max_depth, &mut repos);
+    repos.sort();
+    repos
+}
+
+fn walk(dir: &Path, depth: usize, repos: &mut Vec<PathBuf>) {
+    // .git is a directory, or a file in worktrees and submodules
+    if dir.join(".git").exists() || dir.join(".hg").is_dir() {
+        repos.push(dir.to_path_buf());
+        return;
+    }
+    if depth == 0 {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') || SKIP_DIRS.contains(&name.as_ref()) {
+            continue;
+        }
+        // Symlinks are skipped so a link back up can't loop
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            walk(&entry.path(), depth - 1, repos);
+        }
+    }
+}
+
+/// How a repository's effective identity compares with the expected one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum State {
+    /// Commits would use another email than the expected identity's
+    Mismatch,
+    /// No user.email applies, so git refuses to commit or guesses
+    NoEmail,
+    /// No identity matches the repository
+    Undetected,
+    Matches,
+}
+
+impl State {
+    pub fn is_fixable(&self) -> bool {
+        matches!(self, Self::Mismatch | Self::NoEmail)
+    }
+}
+
+/// One repository in the report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoStatus {
+    pub path: PathBuf,
+    /// The effective user.name and user.email; empty when unset
+    pub name: String,
+    pub email: String,
+    /// The identity owning the effective email, if any
+    pub current: Option<String>,
+    pub expected: Option<IdentityMatch>,
+    pub state: State,
+}
+
+impl RepoStatus {
+    pub fn display_text(&self) -> String {
+        let current = match (&self.current, self.email.is_empty()) {
+            (_, true) => "no user.email".to_string(),
+            (Some(identity), false) => format!("{} <{}>", identity, self.email),
+            (None, false) => format!("<{}>", self.email),
+        };
+        match (&self.expected, self.state) {
+            (Some(expected), State::Matches) => {
+                format!("{} ({})", current, expected.reason.display_text())
+            }
+            (Some(expected), _) => format!("{}, expected {}", current, expected.display_text()),
+            (None, _) => format!("{}, no identity matches", current),
+        }
+    }
+}
+
+/// The identity whose email is `email`, by name
+pub fn identity_for_email(config: &Config, email: &str) -> Option<String> {
+    config
+        .identity_names()
+        .into_iter()
+        .find(|name| config.identities[name].email.eq_ignore_ascii_case(email))
+}
+
+/// Compare a repository's effective email with the expected identity
+pub fn evaluate(
+    config: &Config,
+    path: PathBuf,
+    name: String,
+    email: String,
+    expected: Option<IdentityMatch>,
+) -> RepoStatus {
+    let current = identity_for_email(config, &email);
+    let state = match &expected {
+        None => State::Undetected,
+        Some(_) if email.is_empty() => State::NoEmail,
+        Some(found)
+            if config.identities[&found.identity]
+                .email
+                .eq_ignore_ascii_case(&email) =>
+        {
+            State::Matches
+        }
+        Some(_) => State::Mismatch,
+    };
+    RepoStatus {
+        path,
+        name,
+        email,
+        current,
+        expected,
+        state,
+    }
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<String, CliError> {
+    let mut all = vec!["-C".to_string(), repo.to_string_lossy().into_owned()];
+    all.extend(cli::args(args));
+    let options = RunOptions {
+        timeout: Duration::from_secs(10),
+        retries: 0,
+        cancel: None,
+    };
+    cli::run_program("git", &all, &options)
+}
+
+/// A key as git resolves it in the repository; empty when unset
+fn effective(repo: &Path, key: &str) -> Result<String, Error> {
+    match git(repo, &["config", "--get", key]) {
+        Ok(out) => Ok(out.trim().to_string()),
+        // Exit status 1: not set anywhere
+        Err(CliError::NonZeroExit { code: Some(1), .. }) => Ok(String::new()),
+        Err(e) => Err(Error::from_cli(e)),
+    }
+}
+
+/// Check one repository; in a jj repository, with the email jj commits
+/// with, and in a Mercurial one with its ui.username. Blocks
+pub fn check(config: &Config, repo: &Path) -> Result<RepoStatus, Error> {
+    if hg::is_hg(repo) {
+        let (name, email) = hg::identity(repo)?;
+        let expected = detect::detect(config, repo)?;
+        return Ok(evaluate(config, repo.to_path_buf(), name, email, expected));
+    }
+    let name = effective(repo, "user.name")?;
+    let mut email = effective(repo, "user.email")?;
+    if jj::is_jj(repo) {
+        match jj::email(repo) {
+            Ok(Some(jj_email)) => email = jj_email,
+            Ok(None) => email.clear(),
+            Err(e) => tracing::debug!("Reading jj's email in {} failed: {}", repo.display(), e),
+        }
+    }
+    let expected = detect::detect(config, repo)?;
+    Ok(evaluate(config, repo.to_path_buf(), name, email, expected))
+}
+
+/// The repositories under the configured roots; blocks
+pub fn repos(config: &Config) -> Vec<PathBuf> {
+    config
+        .settings
+        .workspace_roots
+        .iter()
+        .flat_map(|root| find_repos(&platform::expand_tilde(root), MAX_DEPTH))
+        .collect()
+}
+
+/// Check every repository under the configured roots, problems first;
+/// repositories git can't read are skipped. Blocks
+pub fn scan(config: &Config) -> Vec<RepoStatus> {
+    let mut statuses: Vec<RepoStatus> = repos(config)
+        .into_iter()
+        .filter_map(|repo| match check(config, &repo) {
+            Ok(status) => Some(status),
+            Err(e) => {
+                tracing::debug!("Skipping {}: {}", repo.display(), e);
+                None
+            }
+        })
+        .collect();
+    statuses.sort_by(|a, b| a.state.cmp(&b.state).then_with(|| a.path.cmp(&b.path)));
+    statuses.dedup_by(|a, b| a.path == b.path);
+    statuses
+}
+
+/// Write an identity's name, email and signing settings to the
+/// repository's own git config, and to its jj config if it is colocated
+/// with Jujutsu; a Mercurial repository gets the identity's hgrc block
+/// instead. Blocks
+pub fn apply_identity(repo: &Path, identity: &Identity) -> Result<(), Error> {
+    if hg::is_hg(repo) {
+        return hg::apply(repo, identity);
+    }
+    for (key, value) in gitconfig::identity_settings(identity) {
+        git(repo, &["config", "--local", &key, &value]).map_err(Error::from_cli)?;
+    }
+    if jj::is_jj(repo) {
+        jj::sync(repo)?;
+    }
+    Ok(())
+}
+
+/// Write the expected identity's settings to the repository; blocks
+pub fn fix(config: &Config, status: &RepoStatus) -> Result<(), Error> {
+    let Some(expected) = &status.expected else {
+        return Err(Error::InvalidOutput {
+            program: "detect",
+            message: "No identity matches the repository".to_string(),
+        });
+    };
+    apply_identity(&status.path, &config.identities[&expected.identity])?;
+    // A [user] section added after the branch includes would win over them
+    if !config.settings.branch_rules.is_empty() && !hg::is_hg(&status.path) {
+        branches::apply(config, &status.path)?;
+    }
+    Ok(())
+}
+
+/// The checked-out submodules of `repo`, recursively; blocks
+pub fn submodules(repo: &Path) -> Result<Vec<PathBuf>, Error> {
+    let out = git(
+        repo,
+        &["submodule", "foreach", "--quiet", "--recursive", "pwd"],
+    )
+    .map_err(Error::from_cli)?;
+    Ok(out
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Write the identity `name`'s settings to every submodule of `repo`,
+/// returning the submodules written; blocks
+pub fn propagate(config: &Config, name: &str, repo: &Path) -> Result<Vec<PathBuf>, Error> {
+    let identity = config
+        .identities
+        .get(name)
+        .ok_or_else(|| Error::InvalidOutput {
+            program: "config",
+            message: format!("No identity named {}", name),
+        })?;
+    let submodules = submodules(repo)?;
+    for submodule in &submodules {
+        apply_identity(submodule, identity)?;
+    }
+    Ok(submodules)
+}
+
+/// A plain-text report of the scan, e.g. to paste into a ticket
+pub fn report_text(statuses: &[RepoStatus]) -> String {
+    let problems = statuses
+        .iter()
+        .filter(|s| s.state != State::Matches)
+        .count();
+    let mut out = format!(
+        "{} repositories, {} needing attention\n\n",
+        statuses.len(),
+        problems
+    );
+    for status in statuses {
+        let mark = match status.state {
+            State::Matches => "ok",
+            State::Mismatch => "MISMATCH",
+            State::NoEmail => "NO EMAIL",
+            State::Undetected => "unknown",
+        };
+        out.push_str(&format!(
+            "{:<9} {}  {}\n",
+            mark,
+            platform::tilde_path(&status.path),
+            status.display_text()
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::{identity, ConfigBuilder};
+    use crate::detect::Reason;
+
+    #[test]
+    fn test_find_repos_and_evaluate() {
+        let root = tempfile::tempdir().unwrap();
+        for dir in [
+            "work/api/.git",
+            "work/api/vendor/lib/.git",
+            "work/legacy/.hg",
+            "oss/site",
+            ".cache/x/.git",
+        ] {
+            std::fs::create_dir_all(root.path().join(dir)).unwrap();
+        }
+        std::fs::write(root.path().join("oss/site/.git"), "gitdir: ../../.bare").unwrap();
+        let repos = find_repos(root.path(), MAX_DEPTH);
+        assert_eq!(
+            repos,
+            vec![
+                root.path().join("oss/site"),
+                root.path().join("work/api"),
+                root.path().join("work/legacy")
+            ]
+        );
+
+        let config = ConfigBuilder::new()
+            .identity(
+                "gitlab-work",
+                identity("gitlab", "gitlab-work", "gitlab.com", "Work", "me@acme.dev"),
+            )
+            .identity(
+                "github-personal",
+                identity(
+                    "github",
+                    "github-personal",
+                    "github.com",
+                    "Me",
+                    "me@example.com",
+                ),
+            )
+            .build();
+        let expected = IdentityMatch {
+            identity: "gitlab-work".to_string(),
+            remote: "origin".to_string(),
+            url: "gitlab-work:acme/api.git".to_string(),
+            reason: Reason::HostAlias,
+        };
+        let status = evaluate(
+            &config,
+            repos[1].clone(),
+            "Me".to_string(),
+            "Me@Example.com".to_string(),
+            Some(expected.clone()),
+        );
+        assert_eq!(status.state, State::Mismatch);
+        assert_eq!(status.current.as_deref(), Some("github-personal"));
+        assert_eq!(
+            status.display_text(),
+            "github-personal <Me@Example.com>, expected gitlab-work (SSH host alias of origin)"
+        );
+        let status = evaluate(
+            &config,
+            repos[1].clone(),
+            "Work".to_string(),
+            "me@acme.dev".to_string(),
+            Some(expected),
+        );
+        assert_eq!(status.state, State::Matches);
+        assert!(report_text(&[status]).starts_with("1 repositories, 0 needing attention"));
+    }
+}
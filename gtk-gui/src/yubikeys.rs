@@ -0,0 +1,197 @@
+//! Which YubiKey an identity's keys are on
+//!
+//! With several YubiKeys, say one per organization or a spare, the
+//! variant an identity selects only works with the one its keys were
+//! enrolled on. An identity records that key's serial number; this lists
+//! the inserted keys with `ykman list` and compares, so the GUI can show
+//! which enrolled keys are present and warn, or refuse, when switching to
+//! an identity while a different key is inserted.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::chalresp;
+use crate::cli::{self, RunOptions};
+use crate::config::{Config, Identity};
+use crate::error::Error;
+
+/// An inserted YubiKey
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inserted {
+    /// None for keys that don't report one, such as Security Key models
+    pub serial: Option<u32>,
+    /// e.g. "YubiKey 5 NFC"
+    pub name: String,
+}
+
+/// Parse `ykman list`: "YubiKey 5 NFC (5.4.3) [OTP+FIDO+CCID] Serial: 12345678"
+pub fn parse_list(text: &str) -> Vec<Inserted> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let serial = line
+                .split_once("Serial:")
+                .and_then(|(_, serial)| serial.trim().parse().ok());
+            let name = line
+                .split(' ')
+                .take_while(|word| !word.starts_with(['(', '[']) && *word != "Serial:")
+                .collect::<Vec<_>>()
+                .join(" ");
+            Inserted { serial, name }
+        })
+        .collect()
+}
+
+/// The inserted YubiKeys; blocks
+pub fn inserted() -> Result<Vec<Inserted>, Error> {
+    let options = RunOptions {
+        timeout: Duration::from_secs(15),
+        retries: 0,
+        cancel: None,
+    };
+    let out = cli::run_program(&chalresp::ykman_program(), &cli::args(&["list"]), &options)
+        .map_err(Error::from_cli)?;
+    Ok(parse_list(&out))
+}
+
+/// The serials identities are bound to, with the identities on each
+pub fn enrolled(config: &Config) -> BTreeMap<u32, Vec<String>> {
+    let mut enrolled: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for (name, identity) in &config.identities {
+        if let Some(serial) = identity.yubikey_serial {
+            enrolled.entry(serial).or_default().push(name.clone());
+        }
+    }
+    enrolled
+}
+
+/// How the inserted keys match an identity's
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Presence {
+    /// The identity isn't bound to a key
+    Unbound,
+    Present(u32),
+    /// Its key isn't inserted, and nothing else is
+    Missing(u32),
+    /// Its key isn't inserted, but these are
+    Wrong {
+        expected: u32,
+        found: Vec<u32>,
+    },
+}
+
+impl Presence {
+    pub fn check(identity: &Identity, inserted: &[Inserted]) -> Self {
+        let Some(expected) = identity.yubikey_serial else {
+            return Self::Unbound;
+        };
+        let found: Vec<u32> = inserted.iter().filter_map(|key| key.serial).collect();
+        if found.contains(&expected) {
+            Self::Present(expected)
+        } else if found.is_empty() {
+            Self::Missing(expected)
+        } else {
+            Self::Wrong { expected, found }
+        }
+    }
+
+    pub fn display_text(&self) -> String {
+        match self {
+            Self::Unbound => "Not bound to a YubiKey".to_string(),
+            Self::Present(serial) => format!("YubiKey {} is inserted", serial),
+            Self::Missing(serial) => format!("Insert YubiKey {}", serial),
+            Self::Wrong { expected, found } => format!(
+                "YubiKey {} is inserted, but this identity's keys are on {}",
+                found
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                expected
+            ),
+        }
+    }
+}
+
+/// What switching to an identity should do about the inserted keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Guard {
+    Proceed,
+    Warn(String),
+    Refuse(String),
+}
+
+/// Compare the inserted keys with the identity's before switching; an
+/// unbound identity or ykman not being installed never stands in the way.
+/// Blocks
+pub fn guard(name: &str) -> Guard {
+    let Ok(config) = Config::load() else {
+        return Guard::Proceed;
+    };
+    let Some(identity) = config.identities.get(name) else {
+        return Guard::Proceed;
+    };
+    if identity.yubikey_serial.is_none() {
+        return Guard::Proceed;
+    }
+    let inserted = match inserted() {
+        Ok(inserted) => inserted,
+        Err(e) => {
+            tracing::debug!("Can't list YubiKeys: {}", e);
+            return Guard::Proceed;
+        }
+    };
+    match Presence::check(identity, &inserted) {
+        presence @ Presence::Wrong { .. } if config.settings.refuse_wrong_yubikey => {
+            Guard::Refuse(presence.display_text())
+        }
+        presence @ (Presence::Wrong { .. } | Presence::Missing(_)) => {
+            Guard::Warn(presence.display_text())
+        }
+        _ => Guard::Proceed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::identity;
+
+    #[test]
+    fn test_list_and_presence() {
+        let inserted = parse_list(
+            "YubiKey 5 NFC (5.4.3) [OTP+FIDO+CCID] Serial: 12345678\n\
+             Security Key NFC by Yubico (5.4.3) [FIDO]\n",
+        );
+        assert_eq!(
+            inserted[0],
+            Inserted {
+                serial: Some(12345678),
+                name: "YubiKey 5 NFC".to_string()
+            }
+        );
+        assert_eq!(inserted[1].serial, None);
+
+        let mut identity = Identity {
+            yubikey_serial: Some(87654321),
+            ..identity(
+                "github",
+                "github-work-sk",
+                "github.com",
+                "me",
+                "me@acme.dev",
+            )
+        };
+        assert_eq!(
+            Presence::check(&identity, &inserted).display_text(),
+            "YubiKey 12345678 is inserted, but this identity's keys are on 87654321"
+        );
+        assert_eq!(Presence::check(&identity, &[]), Presence::Missing(87654321));
+        identity.yubikey_serial = Some(12345678);
+        assert_eq!(
+            Presence::check(&identity, &inserted),
+            Presence::Present(12345678)
+        );
+    }
+}
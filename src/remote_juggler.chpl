@@ -167,7 +167,7 @@ prototype module remote_juggler {
     writeln("  --configPath=<p>  Override config file path");
     writeln("  --useKeychain     Enable/disable keychain (Darwin, default: true)");
     writeln("  --gpgSign         Enable/disable GPG signing (default: true)");
-    writeln("  --provider=<p>    Filter by provider: gitlab, github, bitbucket, all");
+    writeln("  --provider=<p>    Filter by provider: gitlab, github, bitbucket, gitea, sourcehut, all");
     writeln();
 
     writeln(bold("COMMANDS:"));
@@ -216,8 +216,10 @@ prototype module remote_juggler {
 
     writeln("  ", bold("PIN Management (Trusted Workstation):"));
     writeln("    pin store <n>     Store YubiKey PIN in HSM (TPM/SecureEnclave)");
+    writeln("    pin store --keystore  Seal the key store master password");
+    writeln("    pin reseal <n>    Seal again, e.g. after a firmware update");
     writeln("    pin clear <n>     Remove stored PIN from HSM");
-    writeln("    pin status [n]    Check PIN storage status");
+    writeln("    pin status [n]    Check PIN storage status (--json)");
     writeln("    security-mode <m> Set security mode: maximum_security,");
     writeln("                      developer_workflow, trusted_workstation");
     writeln();
@@ -248,11 +250,17 @@ prototype module remote_juggler {
     writeln("    keys get <path>   Retrieve a secret by entry path");
     writeln("    keys store <path> Store a secret at entry path");
     writeln("    keys delete <p>   Delete an entry by path");
+    writeln("    keys attachments <p>  List file attachments of an entry");
+    writeln("    keys attach <p> <f>   Attach a file to an entry");
+    writeln("    keys attachment-export <p> <name> <dest>  Save an attachment");
+    writeln("    keys detach <p> <name>  Remove an attachment");
     writeln("    keys list [group] List entries in a group");
     writeln("    keys ingest <f>   Ingest a .env file into the key store");
     writeln("    keys crawl [dirs] Crawl directories for .env files");
     writeln("    keys discover     Auto-discover credentials (env, ssh)");
     writeln("    keys export <grp> Export group as .env or JSON");
+    writeln("    keys export-xml   Dump the database (with history) as KeePass XML");
+    writeln("    keys sync <f>     Merge another .kdbx into the key store");
     writeln("    keys sops-ingest <f>  Ingest a SOPS-encrypted file");
     writeln("    keys sops-sync <f>    Sync SOPS file (add/update/delete)");
     writeln("    keys sops-export      Export age key for SOPS recipients");
@@ -516,17 +524,26 @@ prototype module remote_juggler {
     var reasons: list(string);
     reasons.pushBack(detection.reason);
     printDetectedIdentity(detection.identity.name, detection.confidence, reasons);
+
+    const (ruleFound, ruleOk, ruleMsg) = Identity.applyDirectorySigningRule(repoPath);
+    if ruleFound {
+      if ruleOk then printInfo(ruleMsg); else printWarning(ruleMsg);
+    }
   }
 
   // Handle 'switch' / 'to' command
   proc handleSwitch(args: list(string)) {
     if args.size < 1 {
       printError("Missing identity name");
-      writeln("Usage: remote-juggler switch <identity>");
+      writeln("Usage: remote-juggler switch <identity> [--no-remote]");
       return;
     }
 
     const targetIdentity = args[0];
+    // Origin is pointed at the identity's host alias unless the flag or
+    // the keepRemoteUrls setting says otherwise
+    const updateRemote = !args.contains("--no-remote") &&
+                         !GlobalConfig.loadSettings().keepRemoteUrls;
     printDebug("Switching to identity: " + targetIdentity);
 
     // Check if identity exists
@@ -543,7 +560,7 @@ prototype module remote_juggler {
     }
 
     // Perform the switch using the switchIdentity function
-    const result = Identity.switchIdentity(targetIdentity, true, ".");
+    const result = Identity.switchIdentity(targetIdentity, updateRemote, ".");
 
     if result.success {
       printSuccess("Switched to " + result.identity.name);
@@ -571,6 +588,21 @@ prototype module remote_juggler {
         writeln("  Remote:   ", green("Updated for identity"));
       }
 
+      // Colocated Jujutsu repositories get the identity in their jj config
+      if result.jjConfigured {
+        writeln("  jj:       ", green("Repository config updated"));
+      }
+
+      // Mercurial repositories get the identity in their hgrc
+      if result.hgConfigured {
+        writeln("  hg:       ", green("hgrc identity block updated"));
+      }
+
+      // Excluded repositories keep their own identity
+      if result.repoExcluded {
+        writeln("  Repo:     ", yellow("Excluded from switching, git config left alone"));
+      }
+
       // Check for token expiry warnings
       writeln();
       TokenHealth.warnIfExpiring(result.identity);
@@ -821,6 +853,7 @@ prototype module remote_juggler {
           writeln("  gpgSign:             ", settings.gpgSign);
           writeln("  gpgVerifyWithProvider: ", settings.gpgVerifyWithProvider);
           writeln("  fallbackToSSH:       ", settings.fallbackToSSH);
+          writeln("  keepRemoteUrls:      ", settings.keepRemoteUrls);
         }
         when "ssh-hosts", "ssh" {
           const hosts = GlobalConfig.getManagedSSHHosts();
@@ -1567,7 +1600,7 @@ prototype module remote_juggler {
   proc handlePin(args: list(string)) {
     if args.size < 1 {
       printError("Missing subcommand");
-      writeln("Usage: remote-juggler pin <store|clear|status> [identity]");
+      writeln("Usage: remote-juggler pin <store|reseal|clear|status> [identity]");
       return;
     }
 
@@ -1576,21 +1609,67 @@ prototype module remote_juggler {
 
     select subcommand {
       when "store" do handlePinStore(subArgs);
+      when "reseal" do handlePinReseal(subArgs);
       when "clear", "delete", "rm" do handlePinClear(subArgs);
       when "status", "check" do handlePinStatus(subArgs);
       otherwise {
         printError("Unknown pin subcommand: " + subcommand);
-        writeln("Available: store, clear, status");
+        writeln("Available: store, reseal, clear, status");
       }
     }
   }
 
   // HSM functions are already available via public use HSM above
 
+  // Environment variable a frontend passes the PIN in instead of stdin
+  param PIN_ENV = "REMOTE_JUGGLER_PIN";
+
+  /*
+    Read a secret from `envVar` if a frontend set it, else from stdin.
+
+    :returns: (ok, secret)
+  */
+  proc readSecretInput(prompt: string, envVar: string): (bool, string) {
+    const fromEnv = getEnvVar(envVar);
+    if fromEnv != "" then return (true, fromEnv);
+
+    writeln(prompt, " (input hidden):");
+    write("> ");
+    var secret: string;
+    if !stdin.readLine(secret) then return (false, "");
+    return (true, secret.strip());
+  }
+
+  /*
+    Check a secret before sealing it: a YubiKey PIN's length, or that the
+    key store master password opens the database.
+
+    :returns: (ok, error message)
+  */
+  proc checkSecretToSeal(name: string, secret: string): (bool, string) {
+    if secret == "" then return (false, "Input cannot be empty");
+    if name == KeePassXC.KDBX_HSM_IDENTITY {
+      if !KeePassXC.databaseExists() then
+        return (false, "No key store database; run 'remote-juggler keys init'");
+      const (ok, _) = KeePassXC.listEntries(KeePassXC.getDatabasePath(), "RemoteJuggler", secret);
+      if !ok then return (false, "The master password does not open the key store");
+      return (true, "");
+    }
+    if secret.size < 6 || secret.size > 127 then
+      return (false, "PIN must be between 6 and 127 characters");
+    return (true, "");
+  }
+
   proc handlePinStore(args: list(string)) {
     if args.size < 1 {
       printError("Missing identity name");
       writeln("Usage: remote-juggler pin store <identity>");
+      writeln("       remote-juggler pin store --keystore");
+      return;
+    }
+
+    if args[0] == "--keystore" {
+      handlePinStoreKeystore();
       return;
     }
 
@@ -1617,24 +1696,16 @@ prototype module remote_juggler {
       return;
     }
 
-    // Read PIN from stdin
-    writeln("Enter YubiKey PIN for ", green(identity.name), " (input hidden):");
-    write("> ");
-
-    var pin: string;
-    if !stdin.readLine(pin) {
+    // Read PIN from the frontend's environment or stdin
+    const (read, pin) = readSecretInput("Enter YubiKey PIN for " + green(identity.name), PIN_ENV);
+    if !read {
       printError("Failed to read PIN");
       return;
     }
-    pin = pin.strip();
 
-    if pin == "" {
-      printError("PIN cannot be empty");
-      return;
-    }
-
-    if pin.size < 6 || pin.size > 127 {
-      printError("PIN must be between 6 and 127 characters");
+    const (valid, reason) = checkSecretToSeal(name, pin);
+    if !valid {
+      printError(reason);
       return;
     }
 
@@ -1655,6 +1726,91 @@ prototype module remote_juggler {
     }
   }
 
+  // Handle 'pin store --keystore': seal the key store master password so
+  // the database unlocks without a prompt on this device
+  proc handlePinStoreKeystore() {
+    const hsmType = hsm_detect_available();
+    if hsmType == HSM_TYPE_NONE {
+      printError("No HSM backend available");
+      return;
+    }
+
+    const (read, password) = readSecretInput("Enter the key store master password",
+                                             KeePassXC.KDBX_PASSWORD_ENV);
+    if !read {
+      printError("Failed to read the master password");
+      return;
+    }
+
+    const (valid, reason) = checkSecretToSeal(KeePassXC.KDBX_HSM_IDENTITY, password);
+    if !valid {
+      printError(reason);
+      return;
+    }
+
+    if KeePassXC.sealMasterPassword(KeePassXC.KDBX_HSM_IDENTITY, password) {
+      printSuccess("Key store master password sealed in " + hsm_type_name(hsmType));
+    } else {
+      printError("Failed to seal the master password");
+    }
+  }
+
+  // Handle 'pin reseal': seal a stored secret again under the current
+  // platform state. A firmware or Secure Boot update changes the PCR values
+  // a TPM-sealed secret is bound to, after which it no longer unseals; the
+  // PIN (or master password) is then read again like 'pin store' does.
+  proc handlePinReseal(args: list(string)) {
+    if args.size < 1 {
+      printError("Missing identity name");
+      writeln("Usage: remote-juggler pin reseal <identity>");
+      writeln("       remote-juggler pin reseal --keystore");
+      return;
+    }
+
+    const keystore = args[0] == "--keystore";
+    const name = if keystore then KeePassXC.KDBX_HSM_IDENTITY else args[0];
+
+    const hsmType = hsm_detect_available();
+    if hsmType == HSM_TYPE_NONE {
+      printError("No HSM backend available");
+      return;
+    }
+
+    if !keystore && GlobalConfig.getIdentity(name).name == "" {
+      printError("Identity not found: " + name);
+      return;
+    }
+
+    var (status, secret) = hsm_retrieve_pin(name);
+    if status != HSM_SUCCESS {
+      if status != HSM_ERR_NOT_FOUND then
+        printInfo("The sealed copy no longer unseals: " + hsm_error_message(status));
+      const envVar = if keystore then KeePassXC.KDBX_PASSWORD_ENV else PIN_ENV;
+      const prompt = if keystore then "Enter the key store master password"
+                     else "Enter YubiKey PIN for " + green(name);
+      var read: bool;
+      (read, secret) = readSecretInput(prompt, envVar);
+      if !read {
+        printError("Failed to read the secret to seal");
+        return;
+      }
+      const (valid, reason) = checkSecretToSeal(name, secret);
+      if !valid {
+        printError(reason);
+        return;
+      }
+    }
+
+    const result = hsm_store_pin(name, secret, secret.size);
+    secret = "";
+    if result == HSM_SUCCESS {
+      printSuccess("Sealed again in " + hsm_type_name(hsmType) +
+                   " under the current platform state");
+    } else {
+      printError("Failed to reseal: " + hsm_error_message(result));
+    }
+  }
+
   proc handlePinClear(args: list(string)) {
     if args.size < 1 {
       printError("Missing identity name");
@@ -1691,9 +1847,58 @@ prototype module remote_juggler {
     }
   }
 
+  // Unseal state of a stored secret for 'pin status --json': "ok",
+  // "pcr_mismatch" after a platform change, or "failed"
+  proc sealStateJSON(name: string, keystore: bool): string {
+    const (status, secret) = hsm_retrieve_pin(name);
+    var state = "ok";
+    var message = "";
+    if status == HSM_ERR_PCR_MISMATCH {
+      state = "pcr_mismatch";
+      message = hsm_error_message(status);
+    } else if status != HSM_SUCCESS || secret == "" {
+      state = "failed";
+      message = hsm_error_message(status);
+    }
+    return '{"name":"' + escapeJSON(name) + '","keystore":' + keystore:string +
+           ',"state":"' + escapeJSON(state) + '","message":"' + escapeJSON(message) + '"}';
+  }
+
+  // Handle 'pin status --json': the backend and whether each sealed
+  // secret still unseals, for frontends
+  proc handlePinStatusJSON() {
+    const hsmType = hsm_detect_available();
+    const policy = if hsmType == HSM_TYPE_TPM then "PCR 7 (Secure Boot state)" else "";
+    var json = '{"backend":"' + escapeJSON(hsm_type_name(hsmType)) + '"' +
+               ',"available":' + (hsmType != HSM_TYPE_NONE):string +
+               ',"native":' + hsmIsNativeAvailable():string +
+               ',"policy":"' + escapeJSON(policy) + '"' +
+               ',"sealed":[';
+    var first = true;
+    if hsmType != HSM_TYPE_NONE {
+      for identity in Identity.listIdentities() {
+        if hsm_has_pin(identity.name) == 0 then continue;
+        if !first then json += ",";
+        json += sealStateJSON(identity.name, false);
+        first = false;
+      }
+      if hsm_has_pin(KeePassXC.KDBX_HSM_IDENTITY) != 0 {
+        if !first then json += ",";
+        json += sealStateJSON(KeePassXC.KDBX_HSM_IDENTITY, true);
+      }
+    }
+    json += "]}";
+    writeln(json);
+  }
+
   proc handlePinStatus(args: list(string)) {
     printDebug("Checking PIN status");
 
+    if args.contains("--json") {
+      handlePinStatusJSON();
+      return;
+    }
+
     writeln(bold("PIN Storage Status"));
     writeln("==================");
     writeln();
@@ -1802,6 +2007,13 @@ prototype module remote_juggler {
 
     if success {
       printSuccess("Security mode set to: " + mode);
+
+      const (agentOk, agentMsg) = GPG.applyAgentCachePolicy(mode);
+      if agentOk {
+        printInfo(agentMsg);
+      } else {
+        printWarning(agentMsg);
+      }
       writeln();
 
       select mode {
@@ -2645,17 +2857,23 @@ prototype module remote_juggler {
       when "get" do handleKeysGet(subArgs);
       when "store", "set", "add" do handleKeysStore(subArgs);
       when "delete", "rm" do handleKeysDelete(subArgs);
+      when "attachments" do handleKeysAttachments(subArgs);
+      when "attach" do handleKeysAttach(subArgs);
+      when "attachment-export" do handleKeysAttachmentExport(subArgs);
+      when "detach" do handleKeysDetach(subArgs);
       when "list", "ls" do handleKeysList(subArgs);
       when "ingest", "import" do handleKeysIngest(subArgs);
       when "crawl" do handleKeysCrawl(subArgs);
       when "discover" do handleKeysDiscover(subArgs);
       when "export", "dump-env" do handleKeysExport(subArgs);
+      when "export-xml" do handleKeysExportXml();
+      when "sync", "merge" do handleKeysSync(subArgs);
       when "sops-ingest" do handleKeysSopsIngest(subArgs);
       when "sops-sync" do handleKeysSopsSync(subArgs);
       when "sops-export" do handleKeysSopsExport(subArgs);
       otherwise {
         printError("Unknown keys subcommand: " + subcommand);
-        writeln("Available: init, status, search, resolve, get, store, delete, list, ingest, crawl, discover, export, sops-ingest, sops-sync, sops-export");
+        writeln("Available: init, status, search, resolve, get, store, delete, attachments, attach, attachment-export, detach, list, ingest, crawl, discover, export, export-xml, sync, sops-ingest, sops-sync, sops-export");
       }
     }
   }
@@ -3139,7 +3357,8 @@ prototype module remote_juggler {
     writeln();
 
     const dbPath = KeePassXC.getDatabasePath();
-    const (added, updated) = KeePassXC.ingestEnvFile(dbPath, envFilePath, password);
+    const (added, updated) = KeePassXC.ingestEnvFile(dbPath, envFilePath, password,
+                                                     reportProgress=true);
 
     if added > 0 || updated > 0 {
       printSuccess("Ingested .env file");
@@ -3195,6 +3414,169 @@ prototype module remote_juggler {
     }
   }
 
+  // Unlock the key store for a keys subcommand, reporting failures
+  proc unlockKeyStore(): (bool, string) {
+    if !KeePassXC.canAutoUnlock() {
+      printError("Cannot auto-unlock key store");
+      writeln("Ensure HSM and YubiKey are available.");
+      return (false, "");
+    }
+
+    const (ok, password) = KeePassXC.autoUnlock();
+    if !ok {
+      printError("Failed to unlock key store");
+      return (false, "");
+    }
+    return (true, password);
+  }
+
+  // Handle 'keys attachments <path> [--json]' - List file attachments
+  proc handleKeysAttachments(args: list(string)) {
+    if args.size < 1 {
+      printError("Missing entry path");
+      writeln("Usage: remote-juggler keys attachments <entry-path> [--json]");
+      return;
+    }
+
+    const entryPath = args[0];
+    const jsonOutput = args.contains("--json");
+    printDebug("Listing attachments: " + entryPath);
+
+    const (unlocked, password) = unlockKeyStore();
+    if !unlocked then return;
+
+    const dbPath = KeePassXC.getDatabasePath();
+    const (ok, attachments) = KeePassXC.listAttachments(dbPath, entryPath, password);
+    if !ok {
+      printError("Entry not found: " + entryPath);
+      return;
+    }
+
+    if jsonOutput {
+      var json = '{"entryPath":"' + entryPath.replace('"', '\\"') + '","attachments":[';
+      var first = true;
+      for (name, size) in attachments {
+        if !first then json += ",";
+        json += '{"name":"' + name.replace('"', '\\"') + '","size":"' + size + '"}';
+        first = false;
+      }
+      json += "]}";
+      writeln(json);
+      return;
+    }
+
+    if attachments.size == 0 {
+      writeln(dim("No attachments on "), entryPath);
+      return;
+    }
+
+    writeln(bold("Attachments of "), entryPath, bold(":"));
+    for (name, size) in attachments {
+      if size != "" {
+        writeln("  ", name, " ", dim("(" + size + ")"));
+      } else {
+        writeln("  ", name);
+      }
+    }
+  }
+
+  // Handle 'keys attach <path> <file> [--name <name>]' - Attach a file
+  proc handleKeysAttach(args: list(string)) {
+    if args.size < 2 {
+      printError("Missing entry path or file");
+      writeln("Usage: remote-juggler keys attach <entry-path> <file> [--name <name>]");
+      writeln("Example: remote-juggler keys attach RemoteJuggler/K8s/prod ~/.kube/config --name kubeconfig");
+      return;
+    }
+
+    const entryPath = args[0];
+    const filePath = expandTilde(args[1]);
+    var name = basename(filePath);
+    for i in 2..<args.size {
+      if args[i] == "--name" && i + 1 < args.size {
+        name = args[i + 1];
+        break;
+      }
+    }
+    printDebug("Attaching " + filePath + " to " + entryPath + " as " + name);
+
+    try {
+      if !FileSystem.isFile(filePath) {
+        printError("File not found: " + filePath);
+        return;
+      }
+    } catch {
+      printError("Cannot read file: " + filePath);
+      return;
+    }
+
+    const (unlocked, password) = unlockKeyStore();
+    if !unlocked then return;
+
+    const dbPath = KeePassXC.getDatabasePath();
+
+    // Attachments need an entry to live on
+    const (exists, _) = KeePassXC.getEntry(dbPath, entryPath, password);
+    if !exists {
+      printError("Entry not found: " + entryPath);
+      writeln("Create it first with 'remote-juggler keys store " + entryPath + "'.");
+      return;
+    }
+
+    if KeePassXC.importAttachment(dbPath, entryPath, name, filePath, password) {
+      printSuccess("Attached " + name + " to " + entryPath);
+    } else {
+      printError("Failed to attach " + name + " to " + entryPath);
+    }
+  }
+
+  // Handle 'keys attachment-export <path> <name> <dest>' - Save an attachment
+  proc handleKeysAttachmentExport(args: list(string)) {
+    if args.size < 3 {
+      printError("Missing entry path, attachment name, or destination");
+      writeln("Usage: remote-juggler keys attachment-export <entry-path> <name> <dest>");
+      return;
+    }
+
+    const entryPath = args[0];
+    const name = args[1];
+    const destPath = expandTilde(args[2]);
+    printDebug("Exporting attachment " + name + " of " + entryPath + " to " + destPath);
+
+    const (unlocked, password) = unlockKeyStore();
+    if !unlocked then return;
+
+    const dbPath = KeePassXC.getDatabasePath();
+    if KeePassXC.exportAttachment(dbPath, entryPath, name, destPath, password) {
+      printSuccess("Saved " + name + " to " + destPath);
+    } else {
+      printError("Attachment not found: " + entryPath + " / " + name);
+    }
+  }
+
+  // Handle 'keys detach <path> <name>' - Remove an attachment
+  proc handleKeysDetach(args: list(string)) {
+    if args.size < 2 {
+      printError("Missing entry path or attachment name");
+      writeln("Usage: remote-juggler keys detach <entry-path> <name>");
+      return;
+    }
+
+    const entryPath = args[0];
+    const name = args[1];
+    printDebug("Removing attachment " + name + " from " + entryPath);
+
+    const (unlocked, password) = unlockKeyStore();
+    if !unlocked then return;
+
+    const dbPath = KeePassXC.getDatabasePath();
+    if KeePassXC.removeAttachment(dbPath, entryPath, name, password) {
+      printSuccess("Removed " + name + " from " + entryPath);
+    } else {
+      printError("Attachment not found: " + entryPath + " / " + name);
+    }
+  }
+
   // Handle 'keys crawl [dirs]' - Crawl directories for .env files
   proc handleKeysCrawl(args: list(string)) {
     printDebug("Crawling for .env files");
@@ -3231,7 +3613,12 @@ prototype module remote_juggler {
     writeln();
 
     const dbPath = KeePassXC.getDatabasePath();
-    const (filesFound, totalAdded, totalUpdated) = KeePassXC.crawlEnvFiles(dbPath, password, dirs);
+    KeePassXC.emitProgress(KeePassXC.progressStartedJSON("crawl"));
+    const (filesFound, totalAdded, totalUpdated) =
+      KeePassXC.crawlEnvFiles(dbPath, password, dirs, reportProgress=true);
+    KeePassXC.emitProgress(KeePassXC.progressFinishedJSON(
+      "Found " + filesFound:string + " files, added " + totalAdded:string +
+      ", updated " + totalUpdated:string + " entries"));
 
     if filesFound > 0 {
       printSuccess("Crawl complete");
@@ -3274,6 +3661,8 @@ prototype module remote_juggler {
     writeln();
 
     var totalDiscovered = 0;
+    var stepsDone = 0;
+    KeePassXC.emitProgress(KeePassXC.progressStartedJSON("discover"));
 
     if discoverTypes == "env" || discoverTypes == "all" {
       // Ensure Discovered group exists
@@ -3282,12 +3671,16 @@ prototype module remote_juggler {
       const envDiscovered = KeePassXC.discoverEnvCredentials(dbPath, password);
       writeln("  Environment variables: ", green(envDiscovered:string), " credential(s)");
       totalDiscovered += envDiscovered;
+      stepsDone += 1;
+      KeePassXC.emitProgress(KeePassXC.progressItemJSON(stepsDone, "environment variables"));
     }
 
     if discoverTypes == "ssh" || discoverTypes == "all" {
       const sshDiscovered = KeePassXC.discoverSSHKeys(dbPath, password);
       writeln("  SSH keys:              ", green(sshDiscovered:string), " key(s)");
       totalDiscovered += sshDiscovered;
+      stepsDone += 1;
+      KeePassXC.emitProgress(KeePassXC.progressItemJSON(stepsDone, "SSH keys"));
     }
 
     if discoverTypes == "sops" || discoverTypes == "all" {
@@ -3298,6 +3691,9 @@ prototype module remote_juggler {
       }
     }
 
+    KeePassXC.emitProgress(KeePassXC.progressFinishedJSON(
+      "Discovered " + totalDiscovered:string + " credential(s)"));
+
     writeln();
     if totalDiscovered > 0 {
       printSuccess("Discovered " + totalDiscovered:string + " credential(s)");
@@ -3341,7 +3737,8 @@ prototype module remote_juggler {
     }
 
     const dbPath = KeePassXC.getDatabasePath();
-    const (exportOk, content) = KeePassXC.exportEntries(dbPath, group, password, format);
+    const (exportOk, content) = KeePassXC.exportEntries(dbPath, group, password, format,
+                                                        reportProgress=true);
 
     if exportOk {
       writeln(content);
@@ -3350,6 +3747,100 @@ prototype module remote_juggler {
     }
   }
 
+  // Handle 'keys export-xml' - Dump the database as KeePass XML on stdout
+  //
+  // Used by the GUI to read entry history, which keepassxc-cli has no
+  // command for. Values are in plaintext; never redirect this to a file
+  // you keep.
+  proc handleKeysExportXml() {
+    printDebug("Exporting database as XML");
+
+    const (unlocked, password) = unlockKeyStore();
+    if !unlocked then return;
+
+    const dbPath = KeePassXC.getDatabasePath();
+    const (ok, xml) = KeePassXC.exportXml(dbPath, password);
+    if ok {
+      write(xml);
+    } else {
+      printError("Failed to export key store");
+    }
+  }
+
+  // Handle 'keys sync <other.kdbx> [--dry-run] [--same-credentials]'
+  //
+  // Merges another copy of the database (from Syncthing, a USB stick, ...)
+  // into the key store. The other database's password comes from
+  // REMOTE_JUGGLER_SYNC_PASSWORD, or the first line of stdin; with
+  // --same-credentials the key store's own password is used.
+  proc handleKeysSync(args: list(string)) {
+    if args.size < 1 {
+      printError("Missing database to merge");
+      writeln("Usage: remote-juggler keys sync <other.kdbx> [--dry-run] [--same-credentials]");
+      writeln("Review first with --dry-run; the newer version of each entry wins and");
+      writeln("the older one is kept in the entry's history.");
+      return;
+    }
+
+    const otherPath = expandTilde(args[0]);
+    const dryRun = args.contains("--dry-run");
+    const sameCredentials = args.contains("--same-credentials");
+    printDebug("Syncing from " + otherPath + (if dryRun then " (dry run)" else ""));
+
+    try {
+      if !FileSystem.isFile(otherPath) {
+        printError("Database not found: " + otherPath);
+        return;
+      }
+    } catch {
+      printError("Cannot read database: " + otherPath);
+      return;
+    }
+
+    const (unlocked, password) = unlockKeyStore();
+    if !unlocked then return;
+
+    const dbPath = KeePassXC.getDatabasePath();
+    if otherPath == dbPath {
+      printError("Cannot sync the key store with itself");
+      return;
+    }
+
+    var otherPassword = password;
+    if !sameCredentials {
+      const fromEnv = getEnvOrDefault("REMOTE_JUGGLER_SYNC_PASSWORD");
+      if fromEnv != "" {
+        otherPassword = fromEnv;
+      } else {
+        try {
+          var line: string;
+          stdin.readLine(line, stripNewline=true);
+          otherPassword = line;
+        } catch {
+          printError("No password for " + otherPath);
+          return;
+        }
+      }
+    }
+
+    const (ok, output) = KeePassXC.mergeDatabase(dbPath, otherPath, password,
+                                                 otherPassword, dryRun);
+    if !ok {
+      printError("Merge failed: " + output);
+      return;
+    }
+
+    // The change list, one change per line, for review
+    for line in output.split("\n") {
+      if line.strip() != "" then writeln("  ", line.strip());
+    }
+    if dryRun {
+      printInfo("Dry run; the key store was not modified");
+    } else {
+      printSuccess("Merged " + otherPath + " into the key store");
+    }
+  }
+
   // Handle 'keys sops-ingest <file>' - Ingest a SOPS-encrypted file
   proc handleKeysSopsIngest(args: list(string)) {
     if args.size < 1 {
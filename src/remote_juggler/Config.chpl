@@ -128,6 +128,8 @@ prototype module Config {
       return lower.find("gitlab") >= 0 ||
              lower.find("github") >= 0 ||
              lower.find("bitbucket") >= 0 ||
+             lower.find("codeberg") >= 0 ||
+             lower.find("sr.ht") >= 0 ||
              lower.find("git.") >= 0 ||
              lower.find(".git") >= 0 ||
              user == "git";
@@ -144,6 +146,10 @@ prototype module Config {
       if lower.find("gitlab") >= 0 then return Provider.GitLab;
       if lower.find("github") >= 0 then return Provider.GitHub;
       if lower.find("bitbucket") >= 0 then return Provider.Bitbucket;
+      if lower.find("codeberg") >= 0 || lower.find("forgejo") >= 0 ||
+         lower.find("gitea") >= 0 then return Provider.Gitea;
+      if lower.find("sr.ht") >= 0 || lower.find("sourcehut") >= 0 then
+        return Provider.Sourcehut;
       return Provider.Custom;
     }
   }
@@ -136,12 +136,16 @@ prototype module Core {
     - ``GitLab`` - GitLab.com and self-hosted GitLab instances
     - ``GitHub`` - GitHub.com and GitHub Enterprise
     - ``Bitbucket`` - Atlassian Bitbucket Cloud and Server
+    - ``Gitea`` - Gitea and Forgejo, including Codeberg
+    - ``Sourcehut`` - sourcehut (git.sr.ht) and self-hosted instances
     - ``Custom`` - Any other git server (e.g., src.bates.edu)
   */
   enum Provider {
     GitLab,
     GitHub,
     Bitbucket,
+    Gitea,
+    Sourcehut,
     Custom
   }
 
@@ -194,7 +198,7 @@ prototype module Core {
     particular git identity. Supports both GPG and SSH signing.
 
     :var keyId: GPG key ID or "auto" to detect from email (for GPG format)
-    :var format: Signing format - "gpg" or "ssh" (default: gpg)
+    :var format: Signing format - "gpg", "ssh" or "gitsign" (default: gpg)
     :var sshKeyPath: Path to SSH public key (for SSH format)
     :var signCommits: Enable automatic commit signing (git commit -S)
     :var signTags: Enable automatic tag signing (git tag -s)
@@ -209,7 +213,16 @@ prototype module Core {
         - "tpm": Linux TPM 2.0
         - "secure_enclave": macOS Secure Enclave
         - "keychain": System keychain (fallback)
+        - "secret_service": Secret Service keyring (Linux, GUI only)
+        - "encrypted_file": age-encrypted file (GUI only)
         - "none": No storage (auto-detected if empty)
+    :var gitsignIssuer: OIDC issuer gitsign logs in with (for gitsign format)
+    :var gitsignFulcio: Fulcio CA URL (for gitsign format)
+    :var gitsignRekor: Rekor transparency log URL (for gitsign format)
+    :var gitsignConnectorID: Dex connector that skips the provider choice,
+        e.g. "https://github.com/login/oauth" (for gitsign format)
+    :var signingSubkey: Subkey of keyId to sign with, written to
+        user.signingkey with a ``!`` so gpg uses exactly that subkey
   */
   record GPGConfig {
     var keyId: string = "";
@@ -222,6 +235,11 @@ prototype module Core {
     var touchPolicy: string = "";
     var securityMode: string = "developer_workflow";
     var pinStorageMethod: string = "";
+    var gitsignIssuer: string = "";
+    var gitsignFulcio: string = "";
+    var gitsignRekor: string = "";
+    var gitsignConnectorID: string = "";
+    var signingSubkey: string = "";
 
     /*
       Initialize with default values.
@@ -318,6 +336,31 @@ prototype module Core {
       return format.toLower() == "ssh";
     }
 
+    /*
+      Check if using Sigstore keyless signing through gitsign.
+
+      :returns: true if format is "gitsign"
+    */
+    proc isGitsignFormat(): bool {
+      return format.toLower() == "gitsign";
+    }
+
+    /*
+      The user.signingkey value for a resolved key ID.
+
+      gpg picks the newest signing subkey for a plain key ID; a trailing
+      ``!`` pins the exact subkey instead.
+
+      :arg resolvedKeyId: keyId, or the key "auto" detected
+      :returns: signingSubkey with ``!`` if set, else resolvedKeyId
+    */
+    proc signingKeySpec(resolvedKeyId: string): string {
+      if signingSubkey == "" then return resolvedKeyId;
+      var subkey = signingSubkey.strip();
+      while subkey.endsWith("!") do subkey = subkey[..<(subkey.size - 1)];
+      return subkey + "!";
+    }
+
     /*
       Check if signing requires physical touch.
 
@@ -367,7 +410,9 @@ prototype module Core {
     proc hasPinStorage(): bool {
       return pinStorageMethod == "tpm" ||
              pinStorageMethod == "secure_enclave" ||
-             pinStorageMethod == "keychain";
+             pinStorageMethod == "keychain" ||
+             pinStorageMethod == "secret_service" ||
+             pinStorageMethod == "encrypted_file";
     }
   }
 
@@ -475,12 +520,16 @@ prototype module Core {
     /*
       Check if this identity matches an organization path.
 
+      sourcehut owners carry a leading ``~``, which either side may omit.
+
       :arg orgPath: Organization or group path to check
       :returns: true if orgPath is in organizations list
     */
     proc matchesOrganization(orgPath: string): bool {
+      const path = if orgPath.startsWith("~") then orgPath[1..] else orgPath;
       for org in organizations {
-        if orgPath.startsWith(org) then return true;
+        const name = if org.startsWith("~") then org[1..] else org;
+        if name != "" && path.startsWith(name) then return true;
       }
       return false;
     }
@@ -934,6 +983,8 @@ prototype module Core {
       when Provider.GitLab do return "gitlab";
       when Provider.GitHub do return "github";
       when Provider.Bitbucket do return "bitbucket";
+      when Provider.Gitea do return "gitea";
+      when Provider.Sourcehut do return "sourcehut";
       when Provider.Custom do return "custom";
       otherwise do return "unknown";
     }
@@ -942,6 +993,8 @@ prototype module Core {
   /*
     Parse Provider from string.
 
+    Forgejo and Codeberg parse as ``Gitea``, srht as ``Sourcehut``.
+
     :arg s: String representation (case-insensitive)
     :returns: Provider enum value, defaults to Custom
   */
@@ -950,6 +1003,9 @@ prototype module Core {
     if lower == "gitlab" then return Provider.GitLab;
     if lower == "github" then return Provider.GitHub;
     if lower == "bitbucket" then return Provider.Bitbucket;
+    if lower == "gitea" || lower == "forgejo" || lower == "codeberg" then
+      return Provider.Gitea;
+    if lower == "sourcehut" || lower == "srht" then return Provider.Sourcehut;
     return Provider.Custom;
   }
 
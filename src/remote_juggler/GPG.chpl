@@ -347,6 +347,9 @@ prototype module GPG {
                        signCommits: bool, autoSignoff: bool): bool {
     var success = true;
 
+    // Back to OpenPGP in case an SSH-signing identity was active before
+    success = success && gitConfig(repoPath, "gpg.format", "openpgp");
+
     // Set signing key
     success = success && gitConfig(repoPath, "user.signingkey", keyId);
 
@@ -366,6 +369,51 @@ prototype module GPG {
     return success;
   }
 
+  /*
+   * Apply a directory signing rule to a repository
+   *
+   * Runs after the identity's own signing setup on switch, so the rule
+   * wins over both the identity and the global gpgSign setting.
+   *
+   * Args:
+   *   repoPath: Path to the git repository
+   *   sign: "true" or "false" to force signing on or off, "" to keep it
+   *   keyId: GPG key to require, "" to keep the identity's
+   *
+   * Returns:
+   *   Tuple of (success, message)
+   */
+  proc applySigningPolicy(repoPath: string, sign: string, keyId: string): (bool, string) {
+    var success = true;
+    var parts: list(string);
+
+    if keyId != "" {
+      success = success && gitConfig(repoPath, "gpg.format", "openpgp");
+      success = success && gitConfig(repoPath, "user.signingkey", keyId);
+      parts.pushBack("key " + keyId + " required");
+    }
+
+    // A required key means signing unless the rule turns it off
+    const signValue = if sign != "" then sign else if keyId != "" then "true" else "";
+    if signValue != "" {
+      success = success && gitConfig(repoPath, "commit.gpgsign", signValue);
+      success = success && gitConfig(repoPath, "tag.gpgsign", signValue);
+      parts.pushBack(if signValue == "true" then "signing forced on" else "signing forced off");
+    }
+
+    if !success {
+      return (false, "Failed to apply directory signing rule");
+    }
+    var msg = "Directory signing rule: ";
+    var first = true;
+    for part in parts {
+      if !first then msg += ", ";
+      first = false;
+      msg += part;
+    }
+    return (true, if parts.size == 0 then msg + "no overrides" else msg);
+  }
+
   /*
    * Remove GPG signing configuration from a repository
    *
@@ -599,6 +647,16 @@ prototype module GPG {
       when Provider.Bitbucket {
         return "https://bitbucket.org/account/settings/gpg-keys/";
       }
+      when Provider.Gitea {
+        const host = if identity.hostname == "" then "codeberg.org" else identity.hostname;
+        return "https://" + host + "/user/settings/keys";
+      }
+      when Provider.Sourcehut {
+        // Keys live on the meta service, e.g. meta.sr.ht for git.sr.ht
+        var domain = if identity.hostname == "" then "sr.ht" else identity.hostname;
+        if domain.startsWith("git.") then domain = domain[4..];
+        return "https://meta." + domain + "/keys";
+      }
       otherwise {
         return "";
       }
@@ -1208,6 +1266,69 @@ prototype module GPG {
     return success;
   }
 
+  /*
+   * Configure git for Sigstore keyless signing with gitsign
+   *
+   * gitsign gets a short-lived certificate from Fulcio for the OIDC
+   * identity the user logs in as and records the signature in Rekor, so
+   * there is no key to manage. git runs it as its x509 signing program.
+   *
+   * Args:
+   *   repoPath: Path to the git repository
+   *   gpg: The identity's signing configuration
+   *
+   * Returns:
+   *   true if all configurations succeeded, false otherwise
+   */
+  proc configureGitsignSigning(repoPath: string, gpg: GPGConfig): bool {
+    var success = true;
+
+    success = success && gitConfig(repoPath, "gpg.format", "x509");
+    success = success && gitConfig(repoPath, "gpg.x509.program", "gitsign");
+
+    const signValue = if gpg.signCommits then "true" else "false";
+    success = success && gitConfig(repoPath, "commit.gpgsign", signValue);
+    const tagValue = if gpg.signTags then "true" else "false";
+    success = success && gitConfig(repoPath, "tag.gpgsign", tagValue);
+
+    // Only override gitsign's public-good Sigstore defaults when set
+    if gpg.gitsignIssuer != "" then
+      success = success && gitConfig(repoPath, "gitsign.issuer", gpg.gitsignIssuer);
+    if gpg.gitsignFulcio != "" then
+      success = success && gitConfig(repoPath, "gitsign.fulcio", gpg.gitsignFulcio);
+    if gpg.gitsignRekor != "" then
+      success = success && gitConfig(repoPath, "gitsign.rekor", gpg.gitsignRekor);
+    if gpg.gitsignConnectorID != "" then
+      success = success && gitConfig(repoPath, "gitsign.connectorID", gpg.gitsignConnectorID);
+
+    return success;
+  }
+
+  /*
+   * Public key used for SSH signing
+   *
+   * The identity's gpg.sshKeyPath if set, otherwise the public half of
+   * its SSH authentication key.
+   *
+   * Args:
+   *   identity: The GitIdentity with signing configuration
+   *
+   * Returns:
+   *   Path to the public key, or "" if the identity has no SSH key
+   */
+  proc sshSigningKeyPath(identity: GitIdentity): string {
+    if identity.gpg.sshKeyPath != "" {
+      return identity.gpg.sshKeyPath;
+    }
+    if identity.sshKeyPath == "" {
+      return "";
+    }
+    if identity.sshKeyPath.endsWith(".pub") {
+      return identity.sshKeyPath;
+    }
+    return identity.sshKeyPath + ".pub";
+  }
+
   /*
    * Configure git signing based on identity configuration
    *
@@ -1222,13 +1343,29 @@ prototype module GPG {
    *   Tuple of (success, message) with configuration result
    */
   proc configureIdentitySigning(repoPath: string, identity: GitIdentity): (bool, string) {
-    if !identity.gpg.isConfigured() {
+    if !identity.gpg.isConfigured() && !identity.gpg.isSSHFormat() &&
+       !identity.gpg.isGitsignFormat() {
       return (true, "No signing configuration for this identity");
     }
 
+    if identity.gpg.isGitsignFormat() {
+      if configureGitsignSigning(repoPath, identity.gpg) {
+        const issuer = if identity.gpg.gitsignIssuer != "" then identity.gpg.gitsignIssuer
+                       else "the Sigstore public instance";
+        return (true, "Configured gitsign keyless signing as " + identity.email +
+                      " via " + issuer);
+      } else {
+        return (false, "Failed to configure gitsign signing");
+      }
+    }
+
     if identity.gpg.isSSHFormat() {
       // SSH signing
-      const keyPath = expandTilde(identity.gpg.sshKeyPath);
+      const signingKey = sshSigningKeyPath(identity);
+      if signingKey == "" {
+        return (false, "No SSH key to sign with for this identity");
+      }
+      const keyPath = expandTilde(signingKey);
       const success = configureGitSSHSigning(repoPath, keyPath, identity.gpg.signCommits);
 
       if success {
@@ -1250,6 +1387,7 @@ prototype module GPG {
         }
         keyId = autoKeyId;
       }
+      keyId = identity.gpg.signingKeySpec(keyId);
 
       const success = configureGitGPG(repoPath, keyId,
                                        identity.gpg.signCommits, identity.gpg.autoSignoff);
@@ -1271,4 +1409,150 @@ prototype module GPG {
       }
     }
   }
+
+  // ============================================================
+  // gpg-agent Cache Policy
+  // ============================================================
+
+  const agentCacheBegin = "# BEGIN RemoteJuggler cache policy";
+  const agentCacheEnd = "# END RemoteJuggler cache policy";
+
+  /*
+   * How long gpg-agent may keep PINs and passphrases for a security mode
+   */
+  record AgentCachePolicy {
+    var defaultTTL: int;              // Seconds since last use
+    var maxTTL: int;                  // Seconds since entry, however often used
+    var ignoreCacheForSigning: bool;  // Ask on every signature
+    var allowExternalCache: bool;     // Let a system keyring remember it
+  }
+
+  /*
+   * The cache policy for a security mode
+   *
+   *   maximum_security:    nothing is cached, every signature asks
+   *   developer_workflow:  cached for a working day (8h)
+   *   trusted_workstation: cached for a day; after that the pinentry
+   *                        supplies the HSM-stored PIN
+   */
+  proc agentCachePolicy(mode: string): AgentCachePolicy {
+    select mode {
+      when "maximum_security" do return new AgentCachePolicy(0, 0, true, false);
+      when "trusted_workstation" do return new AgentCachePolicy(86400, 86400, false, true);
+      otherwise do return new AgentCachePolicy(28800, 28800, false, true);
+    }
+  }
+
+  /*
+   * gpg-agent.conf lines for a cache policy
+   */
+  proc agentCacheLines(policy: AgentCachePolicy): list(string) {
+    var lines: list(string);
+    lines.pushBack("default-cache-ttl " + policy.defaultTTL:string);
+    lines.pushBack("max-cache-ttl " + policy.maxTTL:string);
+    if policy.ignoreCacheForSigning then
+      lines.pushBack("ignore-cache-for-signing");
+    if !policy.allowExternalCache then
+      lines.pushBack("no-allow-external-cache");
+    return lines;
+  }
+
+  /*
+   * Whether a gpg-agent.conf line sets an option the policy manages
+   */
+  proc isAgentCacheOption(line: string): bool {
+    for option in ["default-cache-ttl", "max-cache-ttl",
+                   "ignore-cache-for-signing", "no-allow-external-cache"] {
+      if line == option || line.startsWith(option + " ") ||
+         line.startsWith(option + "\t") {
+        return true;
+      }
+    }
+    return false;
+  }
+
+  /*
+   * Write the security mode's cache policy to gpg-agent.conf and reload
+   * the agent
+   *
+   * The policy goes in a delimited block at the end of the file. Cache
+   * options set elsewhere in the file are removed, since gpg-agent has no
+   * option that undoes ignore-cache-for-signing or no-allow-external-cache.
+   * Reloading also flushes PINs cached under the previous policy.
+   *
+   * Args:
+   *   mode: Security mode name
+   *
+   * Returns:
+   *   Tuple of (success, message)
+   */
+  proc applyAgentCachePolicy(mode: string): (bool, string) {
+    const gnupgHome = getEnvOrDefault("GNUPGHOME", expandTilde("~/.gnupg"));
+    const confPath = gnupgHome + "/gpg-agent.conf";
+    const policy = agentCachePolicy(mode);
+
+    try {
+      var content = "";
+      if exists(confPath) {
+        var f = open(confPath, ioMode.r);
+        defer { try! f.close(); }
+        var reader = f.reader(locking=false);
+        defer { try! reader.close(); }
+        reader.readAll(content);
+      } else if !exists(gnupgHome) {
+        mkdir(gnupgHome, mode=0o700, parents=true);
+      }
+
+      var newLines: list(string);
+      var inBlock = false;
+      for line in content.split("\n") {
+        const trimmed = line.strip();
+        if trimmed == agentCacheBegin {
+          inBlock = true;
+          continue;
+        }
+        if trimmed == agentCacheEnd {
+          inBlock = false;
+          continue;
+        }
+        if inBlock || isAgentCacheOption(trimmed) then continue;
+        newLines.pushBack(line);
+      }
+      while newLines.size > 0 && newLines[newLines.size - 1].strip() == "" {
+        newLines.popBack();
+      }
+
+      if newLines.size > 0 then newLines.pushBack("");
+      newLines.pushBack(agentCacheBegin);
+      for line in agentCacheLines(policy) do newLines.pushBack(line);
+      newLines.pushBack(agentCacheEnd);
+
+      var outFile = open(confPath, ioMode.cw);
+      defer { try! outFile.close(); }
+      var writer = outFile.writer(locking=false);
+      defer { try! writer.close(); }
+      for line in newLines do writer.writeln(line);
+    } catch e {
+      return (false, "Failed to write " + confPath + ": " + e.message());
+    }
+
+    var summary = if policy.ignoreCacheForSigning
+                  then "gpg-agent will ask for the PIN on every signature"
+                  else "gpg-agent will cache the PIN for " +
+                       (policy.defaultTTL / 3600):string + "h";
+
+    try {
+      var p = spawn(["gpgconf", "--reload", "gpg-agent"],
+                    stdout=pipeStyle.close,
+                    stderr=pipeStyle.close);
+      p.wait();
+      if p.exitCode != 0 {
+        return (true, summary + " once it restarts (reload failed)");
+      }
+    } catch {
+      return (true, summary + " once it restarts (gpgconf not found)");
+    }
+
+    return (true, summary);
+  }
 }
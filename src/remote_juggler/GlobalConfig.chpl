@@ -69,6 +69,38 @@ prototype module GlobalConfig {
   */
   param CONFIG_SCHEMA_VERSION = "2.0.0";
 
+  /*
+    Top-level keys the CLI reads. Saving keeps any others as they are in
+    the file, like the settings and identity fields below.
+  */
+  const TOP_LEVEL_KEYS = ["version", "generated", "identities", "settings", "state",
+                          "_managed_ssh_hosts", "_managed_gitconfig_rewrites"];
+
+  /*
+    Settings the CLI reads. The rest, such as the GUI's ``autoLock``,
+    ``directoryRules`` or ``eventHooks``, are kept from the file on save.
+  */
+  const SETTINGS_KEYS = ["defaultProvider", "autoDetect", "useKeychain", "gpgSign",
+                         "gpgVerifyWithProvider", "fallbackToSSH", "verboseLogging",
+                         "defaultSecurityMode", "hsmAvailable",
+                         "trustedWorkstationRequiresHSM", "useKeePassXC",
+                         "keepassxcDatabase", "keepassxcAutoUnlock", "keepRemoteUrls",
+                         "switchExcludedRepos", "providerHosts", "signingRules"];
+
+  /*
+    Identity fields the CLI reads. The rest, such as ``organizations``,
+    ``cloud`` or ``yubikeySerial``, are kept from the file on save.
+  */
+  const IDENTITY_KEYS = ["provider", "host", "hostname", "user", "email", "sshKeyPath",
+                         "gpg", "keePassEntry"];
+
+  /*
+    GPG fields the CLI reads.
+  */
+  const GPG_KEYS = ["keyId", "format", "sshKeyPath", "signingSubkey", "gitsign",
+                    "signCommits", "signTags", "autoSignoff", "hardwareKey",
+                    "touchPolicy", "securityMode", "pinStorageMethod"];
+
   // =========================================================================
   // Managed Block Types
   // =========================================================================
@@ -145,6 +177,35 @@ prototype module GlobalConfig {
     }
   }
 
+  /*
+    Signing override for repositories under a directory.
+
+    :var path: Directory the rule covers, with everything below it
+    :var sign: "true" or "false" to force commit and tag signing on or
+        off, "" to keep the identity's setting
+    :var keyId: GPG key the repositories must sign with, "" for the
+        identity's own
+  */
+  record SigningRule {
+    var path: string;
+    var sign: string;
+    var keyId: string;
+  }
+
+  /*
+    A self-hosted instance of a provider, e.g. GitHub Enterprise.
+
+    :var hostname: The instance's hostname
+    :var provider: "github", "gitlab", "bitbucket", "gitea" (also Forgejo) or
+      "sourcehut"
+    :var apiUrl: API root when it isn't the provider's usual one, "" otherwise
+  */
+  record ProviderHost {
+    var hostname: string;
+    var provider: string;
+    var apiUrl: string;
+  }
+
   /*
     Application settings.
 
@@ -163,6 +224,14 @@ prototype module GlobalConfig {
         - "trusted_workstation": PIN stored in TPM/SecureEnclave
     :var hsmAvailable: Whether hardware security module is available (runtime detection)
     :var trustedWorkstationRequiresHSM: Require HSM for trusted_workstation mode
+    :var signingRules: Per-directory signing overrides, applied on switch
+        and detect regardless of gpgSign
+    :var keepRemoteUrls: Leave origin alone on switch instead of pointing
+        it at the identity's host alias
+    :var switchExcludedRepos: Repositories whose git config switch leaves
+        alone, e.g. ones with work uncommitted under another identity
+    :var providerHosts: Self-hosted provider instances, recognized by
+        hostname in detection and for identities of an unknown provider
   */
   record AppSettings {
     var defaultProvider: Provider = Provider.GitLab;
@@ -178,6 +247,10 @@ prototype module GlobalConfig {
     var useKeePassXC: bool = false;
     var keepassxcDatabase: string = "~/.remotejuggler/keys.kdbx";
     var keepassxcAutoUnlock: bool = true;
+    var signingRules: list(SigningRule);
+    var keepRemoteUrls: bool = false;
+    var switchExcludedRepos: list(string);
+    var providerHosts: list(ProviderHost);
 
     /*
       Initialize with default values.
@@ -196,6 +269,7 @@ prototype module GlobalConfig {
       this.useKeePassXC = false;
       this.keepassxcDatabase = "~/.remotejuggler/keys.kdbx";
       this.keepassxcAutoUnlock = true;
+      this.keepRemoteUrls = false;
     }
 
     /*
@@ -487,7 +561,9 @@ prototype module GlobalConfig {
     Save configuration to file.
 
     Serializes the configuration to JSON and writes to the config file.
-    Creates the config directory if needed.
+    Creates the config directory if needed. Keys the CLI doesn't read are
+    carried over from the existing file (see ``mergeConfigJSON``), so
+    saving doesn't drop what the GUI stores there.
 
     :arg config: Configuration to save
     :returns: true if saved successfully
@@ -508,14 +584,30 @@ prototype module GlobalConfig {
 
     verboseLog("Saving config to: ", path);
 
+    // Generate JSON, keeping what the CLI doesn't read from the current file
+    var json = serializeConfigJSON(cfg);
+    if exists(path) {
+      try {
+        var f = open(path, ioMode.r);
+        defer { try! f.close(); }
+        var reader = f.reader(locking=false);
+        defer { try! reader.close(); }
+
+        var content: string;
+        reader.readAll(content);
+        json = mergeConfigJSON(json, content);
+      } catch e {
+        verboseLog("Error reading config to merge: ", e.message());
+        return false;
+      }
+    }
+
     try {
       var f = open(path, ioMode.cw);
       defer { try! f.close(); }
       var writer = f.writer(locking=false);
       defer { try! writer.close(); }
 
-      // Generate JSON
-      const json = serializeConfigJSON(cfg);
       writer.write(json);
 
       verboseLog("Config saved successfully");
@@ -1046,6 +1138,15 @@ prototype module GlobalConfig {
       cfg.settings = parseSettingsJSON(settingsSection);
     }
 
+    // Identities of an unknown provider on a self-hosted instance are that
+    // instance's provider
+    for identity in cfg.identities {
+      if identity.provider == Provider.Custom {
+        const (found, provider) = providerForHost(cfg.settings, identity.hostname);
+        if found then identity.provider = provider;
+      }
+    }
+
     // Extract state
     const stateSection = extractJSONSection(json, "state");
     if stateSection != "" {
@@ -1140,8 +1241,46 @@ prototype module GlobalConfig {
     json += '    "trustedWorkstationRequiresHSM": ' + cfg.settings.trustedWorkstationRequiresHSM:string + ',\n';
     json += '    "useKeePassXC": ' + cfg.settings.useKeePassXC:string + ',\n';
     json += '    "keepassxcDatabase": "' + escapeJSON(cfg.settings.keepassxcDatabase) + '",\n';
-    json += '    "keepassxcAutoUnlock": ' + cfg.settings.keepassxcAutoUnlock:string + '\n';
-    json += '  },\n';
+    json += '    "keepassxcAutoUnlock": ' + cfg.settings.keepassxcAutoUnlock:string;
+    if cfg.settings.keepRemoteUrls then
+      json += ',\n    "keepRemoteUrls": true';
+    if cfg.settings.switchExcludedRepos.size > 0 {
+      json += ',\n    "switchExcludedRepos": [';
+      first = true;
+      for path in cfg.settings.switchExcludedRepos {
+        if !first then json += ", ";
+        first = false;
+        json += '"' + escapeJSON(path) + '"';
+      }
+      json += ']';
+    }
+    if cfg.settings.providerHosts.size > 0 {
+      json += ',\n    "providerHosts": [\n';
+      first = true;
+      for host in cfg.settings.providerHosts {
+        if !first then json += ",\n";
+        first = false;
+        json += '      {"hostname": "' + escapeJSON(host.hostname) + '", "provider": "' +
+                escapeJSON(host.provider) + '"';
+        if host.apiUrl != "" then json += ', "apiUrl": "' + escapeJSON(host.apiUrl) + '"';
+        json += '}';
+      }
+      json += '\n    ]';
+    }
+    if cfg.settings.signingRules.size > 0 {
+      json += ',\n    "signingRules": [\n';
+      first = true;
+      for rule in cfg.settings.signingRules {
+        if !first then json += ",\n";
+        first = false;
+        json += '      {"path": "' + escapeJSON(rule.path) + '"';
+        if rule.sign != "" then json += ', "sign": ' + rule.sign;
+        if rule.keyId != "" then json += ', "keyId": "' + escapeJSON(rule.keyId) + '"';
+        json += '}';
+      }
+      json += '\n    ]';
+    }
+    json += '\n  },\n';
     json += '\n';
 
     // State
@@ -1194,6 +1333,19 @@ prototype module GlobalConfig {
     // GPG config
     json += indent + '  "gpg": {\n';
     json += indent + '    "keyId": "' + escapeJSON(identity.gpg.keyId) + '",\n';
+    json += indent + '    "format": "' + escapeJSON(identity.gpg.format) + '",\n';
+    if identity.gpg.sshKeyPath != "" {
+      json += indent + '    "sshKeyPath": "' + escapeJSON(identity.gpg.sshKeyPath) + '",\n';
+    }
+    if identity.gpg.signingSubkey != "" {
+      json += indent + '    "signingSubkey": "' + escapeJSON(identity.gpg.signingSubkey) + '",\n';
+    }
+    if identity.gpg.isGitsignFormat() {
+      json += indent + '    "gitsign": {"issuer": "' + escapeJSON(identity.gpg.gitsignIssuer) +
+              '", "fulcio": "' + escapeJSON(identity.gpg.gitsignFulcio) +
+              '", "rekor": "' + escapeJSON(identity.gpg.gitsignRekor) +
+              '", "connectorId": "' + escapeJSON(identity.gpg.gitsignConnectorID) + '"},\n';
+    }
     json += indent + '    "signCommits": ' + identity.gpg.signCommits:string + ',\n';
     json += indent + '    "signTags": ' + identity.gpg.signTags:string + ',\n';
     json += indent + '    "autoSignoff": ' + identity.gpg.autoSignoff:string + ',\n';
@@ -1229,6 +1381,207 @@ prototype module GlobalConfig {
     return json;
   }
 
+  // =========================================================================
+  // Merging With the Existing File
+  // =========================================================================
+
+  /*
+    Carry what the CLI doesn't read over from the file being replaced.
+
+    The top level, settings, each identity the CLI still has and its GPG
+    settings are merged with ``mergeJSONObject``. Identities the CLI
+    removed stay removed.
+
+    :arg newJSON: Config as serialized by ``serializeConfigJSON``
+    :arg oldJSON: Contents of the existing config file
+    :returns: JSON to write
+  */
+  proc mergeConfigJSON(newJSON: string, oldJSON: string): string {
+    var merged = mergeJSONObject(newJSON, oldJSON, TOP_LEVEL_KEYS, "  ");
+
+    const (hasSettings, newSettings) = jsonMember(merged, "settings");
+    const (hadSettings, oldSettings) = jsonMember(oldJSON, "settings");
+    if hasSettings && hadSettings {
+      merged = replaceJSONMember(merged, "settings",
+        mergeJSONObject(newSettings, oldSettings, SETTINGS_KEYS, "    "));
+    }
+
+    const (_, oldIdentities) = jsonMember(oldJSON, "identities");
+    var (_, identities) = jsonMember(merged, "identities");
+    for (name, _, _) in jsonObjectMembers(identities) {
+      const (found, oldIdentity) = jsonMember(oldIdentities, name);
+      if !found then continue;
+      const (_, newIdentity) = jsonMember(identities, name);
+      var identity = mergeJSONObject(newIdentity, oldIdentity, IDENTITY_KEYS, "      ");
+
+      const (hasGpg, newGpg) = jsonMember(identity, "gpg");
+      const (hadGpg, oldGpg) = jsonMember(oldIdentity, "gpg");
+      if hasGpg && hadGpg {
+        identity = replaceJSONMember(identity, "gpg",
+          mergeJSONObject(newGpg, oldGpg, GPG_KEYS, "        "));
+      }
+      identities = replaceJSONMember(identities, name, identity);
+    }
+    return replaceJSONMember(merged, "identities", identities);
+  }
+
+  /*
+    Merge one JSON object with its previous version.
+
+    Members outside ``known`` take their value from ``oldObj`` when it
+    has them, and members only ``oldObj`` has are appended. Members in
+    ``known`` are the CLI's to set, so theirs come from ``newObj``, or are
+    dropped when it leaves them out.
+
+    :arg newObj: Object as the CLI serialized it
+    :arg oldObj: The same object in the existing file
+    :arg known: Keys the CLI reads
+    :arg indent: Indentation of the object's members
+    :returns: The merged object
+  */
+  proc mergeJSONObject(newObj: string, oldObj: string, const known: [] string,
+                       indent: string): string {
+    const newMembers = jsonObjectMembers(newObj);
+    var merged = "";
+    var pos = 0;
+    if newMembers.isEmpty() {
+      while pos < newObj.size && newObj[pos] != '{' do pos += 1;
+      pos = min(pos + 1, newObj.size);
+      merged = newObj[..<pos];
+    }
+    for (key, valueStart, valueEnd) in newMembers {
+      merged += newObj[pos..<valueStart];
+      const (found, oldValue) = jsonMember(oldObj, key);
+      if found && !isKnownKey(known, key) {
+        merged += oldValue;
+      } else {
+        merged += newObj[valueStart..<valueEnd];
+      }
+      pos = valueEnd;
+    }
+
+    var added = !newMembers.isEmpty();
+    for (key, valueStart, valueEnd) in jsonObjectMembers(oldObj) {
+      if isKnownKey(known, key) then continue;
+      const (present, _) = jsonMember(newObj, key);
+      if present then continue;
+      if added then merged += ",";
+      merged += "\n" + indent + '"' + key + '": ' + oldObj[valueStart..<valueEnd];
+      added = true;
+    }
+    return merged + newObj[pos..];
+  }
+
+  /*
+    Whether ``key`` is one of ``keys``.
+  */
+  proc isKnownKey(const keys: [] string, key: string): bool {
+    for k in keys {
+      if k == key then return true;
+    }
+    return false;
+  }
+
+  /*
+    List the members of a JSON object, without descending into them.
+
+    :arg obj: JSON object string
+    :returns: Each member's key, as written, and the start and end of its
+        value in ``obj``
+  */
+  proc jsonObjectMembers(obj: string): list((string, int, int)) {
+    var members: list((string, int, int));
+    var pos = 0;
+    while pos < obj.size && obj[pos] != '{' do pos += 1;
+    pos += 1;
+
+    while pos < obj.size {
+      while pos < obj.size && (isJSONSpace(obj[pos]) || obj[pos] == ',') do pos += 1;
+      if pos >= obj.size || obj[pos] != '"' then break;
+
+      const keyEnd = skipJSONString(obj, pos);
+      const key = obj[(pos + 1)..<(keyEnd - 1)];
+      pos = keyEnd;
+      while pos < obj.size && obj[pos] != ':' do pos += 1;
+      pos += 1;
+      while pos < obj.size && isJSONSpace(obj[pos]) do pos += 1;
+
+      // The value runs to the next comma or closing brace outside it
+      const valueStart = pos;
+      var depth = 0;
+      while pos < obj.size {
+        const ch = obj[pos];
+        if ch == '"' {
+          pos = skipJSONString(obj, pos);
+          continue;
+        }
+        if ch == '{' || ch == '[' {
+          depth += 1;
+        } else if ch == '}' || ch == ']' {
+          if depth == 0 then break;
+          depth -= 1;
+        } else if ch == ',' && depth == 0 {
+          break;
+        }
+        pos += 1;
+      }
+      var valueEnd = pos;
+      while valueEnd > valueStart && isJSONSpace(obj[valueEnd - 1]) do valueEnd -= 1;
+      members.pushBack((key, valueStart, valueEnd));
+    }
+    return members;
+  }
+
+  /*
+    The value of one member of a JSON object.
+
+    :arg obj: JSON object string
+    :arg key: Member key, as written
+    :returns: Tuple of (found, value JSON)
+  */
+  proc jsonMember(obj: string, key: string): (bool, string) {
+    for (k, valueStart, valueEnd) in jsonObjectMembers(obj) {
+      if k == key then return (true, obj[valueStart..<valueEnd]);
+    }
+    return (false, "");
+  }
+
+  /*
+    Replace the value of one member of a JSON object.
+
+    :arg obj: JSON object string
+    :arg key: Member key, as written
+    :arg value: New value JSON
+    :returns: ``obj`` with the value replaced, or unchanged without the key
+  */
+  proc replaceJSONMember(obj: string, key: string, value: string): string {
+    for (k, valueStart, valueEnd) in jsonObjectMembers(obj) {
+      if k == key then return obj[..<valueStart] + value + obj[valueEnd..];
+    }
+    return obj;
+  }
+
+  /*
+    Position just past the JSON string literal starting at ``start``.
+  */
+  proc skipJSONString(json: string, start: int): int {
+    var pos = start + 1;
+    while pos < json.size {
+      if json[pos] == '\\' {
+        pos += 2;
+      } else if json[pos] == '"' {
+        return pos + 1;
+      } else {
+        pos += 1;
+      }
+    }
+    return json.size;
+  }
+
+  proc isJSONSpace(ch: string): bool {
+    return ch == ' ' || ch == '\n' || ch == '\r' || ch == '\t';
+  }
+
   // =========================================================================
   // JSON Parsing Helpers
   // =========================================================================
@@ -1406,6 +1759,16 @@ prototype module GlobalConfig {
       if gpgSection != "" {
         gpgConfig.keyId = extractJSONString(gpgSection, "keyId", "");
         gpgConfig.format = extractJSONString(gpgSection, "format", "gpg");
+        gpgConfig.sshKeyPath = extractJSONString(gpgSection, "sshKeyPath", "");
+        gpgConfig.signingSubkey = extractJSONString(gpgSection, "signingSubkey", "");
+
+        const gitsignSection = extractJSONSection(gpgSection, "gitsign");
+        if gitsignSection != "" {
+          gpgConfig.gitsignIssuer = extractJSONString(gitsignSection, "issuer", "");
+          gpgConfig.gitsignFulcio = extractJSONString(gitsignSection, "fulcio", "");
+          gpgConfig.gitsignRekor = extractJSONString(gitsignSection, "rekor", "");
+          gpgConfig.gitsignConnectorID = extractJSONString(gitsignSection, "connectorId", "");
+        }
 
         const signCommits = extractJSONString(gpgSection, "signCommits", "false");
         gpgConfig.signCommits = signCommits == "true";
@@ -1471,6 +1834,7 @@ prototype module GlobalConfig {
     settings.trustedWorkstationRequiresHSM = extractJSONBool(json, "trustedWorkstationRequiresHSM", true);
     settings.useKeePassXC = extractJSONBool(json, "useKeePassXC", false);
     settings.keepassxcAutoUnlock = extractJSONBool(json, "keepassxcAutoUnlock", true);
+    settings.keepRemoteUrls = extractJSONBool(json, "keepRemoteUrls", false);
 
     // String fields
     const defaultProvider = extractJSONString(json, "defaultProvider", "gitlab");
@@ -1478,9 +1842,154 @@ prototype module GlobalConfig {
     settings.defaultSecurityMode = extractJSONString(json, "defaultSecurityMode", "developer_workflow");
     settings.keepassxcDatabase = extractJSONString(json, "keepassxcDatabase", "~/.remotejuggler/keys.kdbx");
 
+    settings.signingRules = parseSigningRulesJSON(extractJSONSection(json, "signingRules"));
+    settings.switchExcludedRepos = parseStringListJSON(extractJSONSection(json, "switchExcludedRepos"));
+    settings.providerHosts = parseProviderHostsJSON(extractJSONSection(json, "providerHosts"));
+
     return settings;
   }
 
+  /*
+    Parse the signingRules array.
+
+    :arg json: JSON array string, e.g.
+        ``[{"path": "~/oss", "sign": false}, {"path": "~/corp", "keyId": "ABC"}]``
+    :returns: Rules with a path
+  */
+  proc parseSigningRulesJSON(json: string): list(SigningRule) {
+    var rules: list(SigningRule);
+    var depth = 0;
+    var objStart = -1;
+    for i in 0..<json.size {
+      const c = json[i];
+      if c == '{' {
+        if depth == 0 then objStart = i;
+        depth += 1;
+      } else if c == '}' && depth > 0 {
+        depth -= 1;
+        if depth == 0 && objStart >= 0 {
+          const ruleJSON = json[objStart..i];
+          var rule = new SigningRule();
+          rule.path = extractJSONString(ruleJSON, "path", "");
+          if ruleJSON.find('"sign":') >= 0 {
+            rule.sign = extractJSONBool(ruleJSON, "sign", true):string;
+          }
+          rule.keyId = extractJSONString(ruleJSON, "keyId", "");
+          if rule.path != "" then rules.pushBack(rule);
+        }
+      }
+    }
+    return rules;
+  }
+
+  /*
+    Parse the providerHosts array.
+
+    :arg json: JSON array string, e.g.
+        ``[{"hostname": "ghe.acme.corp", "provider": "github"}]``
+    :returns: Instances with a hostname and provider
+  */
+  proc parseProviderHostsJSON(json: string): list(ProviderHost) {
+    var hosts: list(ProviderHost);
+    var depth = 0;
+    var objStart = -1;
+    for i in 0..<json.size {
+      const c = json[i];
+      if c == '{' {
+        if depth == 0 then objStart = i;
+        depth += 1;
+      } else if c == '}' && depth > 0 {
+        depth -= 1;
+        if depth == 0 && objStart >= 0 {
+          const hostJSON = json[objStart..i];
+          var host = new ProviderHost();
+          host.hostname = extractJSONString(hostJSON, "hostname", "").toLower();
+          host.provider = extractJSONString(hostJSON, "provider", "").toLower();
+          host.apiUrl = extractJSONString(hostJSON, "apiUrl", "");
+          if host.hostname != "" && host.provider != "" then hosts.pushBack(host);
+        }
+      }
+    }
+    return hosts;
+  }
+
+  /*
+    The provider of a self-hosted instance.
+
+    :arg settings: Settings holding the instances
+    :arg hostname: Hostname to look up
+    :returns: Tuple of (found, provider)
+  */
+  proc providerForHost(settings: AppSettings, hostname: string): (bool, Provider) {
+    const lower = hostname.toLower();
+    for host in settings.providerHosts {
+      if host.hostname == lower then return (true, stringToProvider(host.provider));
+    }
+    return (false, Provider.Custom);
+  }
+
+  /*
+    Parse an array of strings.
+
+    :arg json: JSON array string, e.g. ``["~/src/api", "~/src/web"]``
+    :returns: The non-empty strings
+  */
+  proc parseStringListJSON(json: string): list(string) {
+    var values: list(string);
+    var valueStart = -1;
+    for i in 0..<json.size {
+      if json[i] != '"' then continue;
+      if valueStart < 0 {
+        valueStart = i + 1;
+      } else {
+        if i > valueStart then values.pushBack(json[valueStart..<i]);
+        valueStart = -1;
+      }
+    }
+    return values;
+  }
+
+  /*
+    Whether switching leaves a repository alone: it is, or is inside, one
+    of the excluded repositories.
+
+    :arg settings: Settings holding the exclusions
+    :arg repoRoot: Absolute path of the repository's top level
+    :returns: true if switch must not change the repository
+  */
+  proc isSwitchExcluded(settings: AppSettings, repoRoot: string): bool {
+    for path in settings.switchExcludedRepos {
+      var dir = expandTilde(path);
+      while dir.size > 1 && dir.endsWith("/") do dir = dir[..<(dir.size - 1)];
+      if repoRoot == dir || repoRoot.startsWith(dir + "/") then return true;
+    }
+    return false;
+  }
+
+  /*
+    The signing rule for a repository: the one with the longest path
+    containing it.
+
+    :arg settings: Settings holding the rules
+    :arg repoRoot: Absolute path of the repository's top level
+    :returns: Tuple of (found, rule)
+  */
+  proc findSigningRule(settings: AppSettings, repoRoot: string): (bool, SigningRule) {
+    var found = false;
+    var best = new SigningRule();
+    var bestLen = -1;
+    for rule in settings.signingRules {
+      var dir = expandTilde(rule.path);
+      while dir.size > 1 && dir.endsWith("/") do dir = dir[..<(dir.size - 1)];
+      if (repoRoot == dir || repoRoot.startsWith(dir + "/")) && dir.size > bestLen {
+        found = true;
+        best = rule;
+        bestLen = dir.size;
+      }
+    }
+    return (found, best);
+  }
+
   /*
     Parse state from JSON object.
 
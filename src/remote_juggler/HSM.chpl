@@ -205,6 +205,22 @@ prototype module HSM {
     }
   }
 
+  /*
+    Get the config value for an HSM method, as written to an identity's
+    pinStorageMethod.
+
+    :arg hsmMethod: HSM method constant
+    :returns: "tpm", "secure_enclave", "keychain", or "" for none
+  */
+  proc hsm_method_id(hsmMethod: c_int): string {
+    select hsmMethod {
+      when HSM_METHOD_TPM do return "tpm";
+      when HSM_METHOD_SECURE_ENCLAVE do return "secure_enclave";
+      when HSM_METHOD_KEYCHAIN do return "keychain";
+      otherwise do return "";
+    }
+  }
+
   /*
     Store a PIN securely using the HSM.
 
@@ -43,6 +43,9 @@ prototype module Identity {
     var authMode: AuthMode;      // How authentication was achieved
     var gpgConfigured: bool;     // Whether GPG was configured
     var remoteUpdated: bool;     // Whether git remote was updated
+    var repoExcluded: bool;      // Whether the repository is excluded from switching
+    var jjConfigured: bool;      // Whether a colocated jj repository was updated
+    var hgConfigured: bool;      // Whether a Mercurial repository's hgrc was updated
     var message: string;         // Human-readable status message
 
     /*
@@ -54,6 +57,9 @@ prototype module Identity {
       this.authMode = AuthMode.Failed;
       this.gpgConfigured = false;
       this.remoteUpdated = false;
+      this.repoExcluded = false;
+      this.jjConfigured = false;
+      this.hgConfigured = false;
       this.message = "";
     }
 
@@ -67,6 +73,9 @@ prototype module Identity {
       this.authMode = authMode;
       this.gpgConfigured = gpgConfigured;
       this.remoteUpdated = remoteUpdated;
+      this.repoExcluded = false;
+      this.jjConfigured = false;
+      this.hgConfigured = false;
       this.message = message;
     }
   }
@@ -356,6 +365,9 @@ prototype module Identity {
    *   4. Configures git user.name and user.email
    *   5. Configures GPG signing (if enabled)
    *   6. Verifies GPG key registration (opportunistic)
+   *   7. Applies directory signing rules
+   *   8. Mirrors the identity into a colocated jj repository's config
+   *   9. Writes the identity block to a Mercurial repository's hgrc
    *
    * Args:
    *   targetIdentity: Name of the identity to switch to
@@ -392,8 +404,17 @@ prototype module Identity {
       writeln("  Authentication: ", authResult.message);
     }
 
+    // Repositories excluded from switching keep their git config, so work
+    // uncommitted there stays with the identity it was started under
+    result.repoExcluded = isRepoExcluded(repoPath);
+    const inRepo = Remote.isGitRepository(repoPath) && !result.repoExcluded;
+
+    if verbose && result.repoExcluded {
+      writeln("  Repository excluded from switching: git config left alone");
+    }
+
     // 3. Update git remotes (if requested and we're in a repo)
-    if updateRemote && Remote.isGitRepository(repoPath) {
+    if updateRemote && inRepo {
       const (remoteOk, newURL) = Remote.updateOriginForIdentity(repoPath, identity);
       result.remoteUpdated = remoteOk;
 
@@ -407,7 +428,7 @@ prototype module Identity {
     }
 
     // 4. Configure git user
-    if Remote.isGitRepository(repoPath) {
+    if inRepo {
       Remote.setGitUser(repoPath, identity.user, identity.email);
 
       if verbose {
@@ -415,8 +436,18 @@ prototype module Identity {
       }
     }
 
-    // 5. Configure GPG signing (if enabled)
-    if gpgSign && identity.gpg.isConfigured() {
+    // 5. Configure commit signing (if enabled): SSH keys via gpg.format=ssh,
+    //    gitsign via gpg.format=x509, otherwise GPG
+    if gpgSign && (identity.gpg.isSSHFormat() || identity.gpg.isGitsignFormat()) {
+      if inRepo {
+        const (signOk, signMsg) = GPG.configureIdentitySigning(repoPath, identity);
+        result.gpgConfigured = signOk;
+
+        if verbose {
+          writeln("  ", signMsg);
+        }
+      }
+    } else if gpgSign && identity.gpg.isConfigured() {
       var gpgKeyId = identity.gpg.keyId;
 
       // Auto-detect GPG key if set to "auto"
@@ -435,7 +466,11 @@ prototype module Identity {
         }
       }
 
-      if gpgKeyId != "" && Remote.isGitRepository(repoPath) {
+      if gpgKeyId != "" {
+        gpgKeyId = identity.gpg.signingKeySpec(gpgKeyId);
+      }
+
+      if gpgKeyId != "" && inRepo {
         const gpgOk = GPG.configureGitGPG(repoPath, gpgKeyId,
                                           identity.gpg.signCommits,
                                           identity.gpg.autoSignoff);
@@ -457,11 +492,56 @@ prototype module Identity {
           }
         }
       }
-    } else if Remote.isGitRepository(repoPath) {
+    } else if inRepo {
       // Disable GPG signing if not configured for this identity
       GPG.disableGitGPG(repoPath);
     }
 
+    // 7. Directory signing rules override all of the above
+    const (ruleFound, ruleOk, ruleMsg) = if inRepo
+      then applyDirectorySigningRule(repoPath)
+      else (false, false, "");
+    if ruleFound {
+      if ruleOk then result.gpgConfigured = true;
+      if verbose {
+        writeln("  ", ruleMsg);
+      }
+    }
+
+    // 8. jj ignores git's includeIf sections and reads its own config, so
+    //    a colocated Jujutsu repository gets what git now resolves
+    if inRepo {
+      const (hasRoot, root) = Remote.getRepositoryRoot(repoPath);
+      if hasRoot && Remote.isJJRepository(root) {
+        const (jjOk, jjMsg) = Remote.syncJJIdentity(root);
+        result.jjConfigured = jjOk;
+        if !jjOk {
+          writeln("  Warning: ", jjMsg);
+        } else if verbose {
+          writeln("  ", jjMsg);
+        }
+      }
+    }
+
+    // 9. Mercurial repositories take the identity from their hgrc
+    if !Remote.isGitRepository(repoPath) {
+      const (inHg, hgRoot) = Remote.getHgRoot(repoPath);
+      if inHg && GlobalConfig.isSwitchExcluded(GlobalConfig.loadSettings(), hgRoot) {
+        result.repoExcluded = true;
+        if verbose {
+          writeln("  Repository excluded from switching: hgrc left alone");
+        }
+      } else if inHg {
+        const (hgOk, hgMsg) = Remote.setHgIdentity(hgRoot, identity);
+        result.hgConfigured = hgOk;
+        if !hgOk {
+          writeln("  Warning: ", hgMsg);
+        } else if verbose {
+          writeln("  ", hgMsg);
+        }
+      }
+    }
+
     // Build success result
     result.success = true;
     result.identity = identity;
@@ -471,6 +551,52 @@ prototype module Identity {
     return result;
   }
 
+  /*
+   * Whether the settings exclude the repository from switching
+   *
+   * Args:
+   *   repoPath: Path inside the repository
+   *
+   * Returns:
+   *   true if switch must leave the repository's git config alone
+   */
+  proc isRepoExcluded(repoPath: string): bool {
+    if !Remote.isGitRepository(repoPath) {
+      return false;
+    }
+    const (hasRoot, root) = Remote.getRepositoryRoot(repoPath);
+    if !hasRoot {
+      return false;
+    }
+    return GlobalConfig.isSwitchExcluded(GlobalConfig.loadSettings(), root);
+  }
+
+  /*
+   * Apply the settings' signing rule for the repository, if one covers it
+   *
+   * Args:
+   *   repoPath: Path inside the repository
+   *
+   * Returns:
+   *   Tuple of (rule found, applied, message)
+   */
+  proc applyDirectorySigningRule(repoPath: string): (bool, bool, string) {
+    if !Remote.isGitRepository(repoPath) {
+      return (false, false, "");
+    }
+    const (hasRoot, root) = Remote.getRepositoryRoot(repoPath);
+    if !hasRoot {
+      return (false, false, "");
+    }
+    const settings = GlobalConfig.loadSettings();
+    const (found, rule) = GlobalConfig.findSigningRule(settings, root);
+    if !found {
+      return (false, false, "");
+    }
+    const (ok, msg) = GPG.applySigningPolicy(repoPath, rule.sign, rule.keyId);
+    return (true, ok, msg + " (" + rule.path + ")");
+  }
+
   // ============================================================
   // Identity Validation
   // ============================================================
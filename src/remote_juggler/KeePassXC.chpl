@@ -32,6 +32,7 @@ prototype module KeePassXC {
   use Subprocess;
   use Path;
   use super.Core only getEnvVar, getEnvOrDefault, expandTilde, verboseLog;
+  use super.Protocol only jsonString;
   public use super.HSM;
 
   // ============================================================================
@@ -44,6 +45,18 @@ prototype module KeePassXC {
   /* HSM identity label for the kdbx master password */
   param KDBX_HSM_IDENTITY = "kdbx-master";
 
+  /* Master password handed over by a frontend (e.g. the GUI, from the
+     system keyring); used instead of the HSM when set */
+  param KDBX_PASSWORD_ENV = "REMOTE_JUGGLER_KDBX_PASSWORD";
+
+  /* Set to "json" by a frontend (e.g. the GUI) to get progress events
+     from crawl, ingest, discover and export on stdout */
+  param PROGRESS_ENV = "REMOTE_JUGGLER_PROGRESS";
+
+  /* YubiKey challenge-response slot added to the master password, as
+     keepassxc-cli's --yubikey value ("2" or "2:SERIAL"); unset for none */
+  param KDBX_YUBIKEY_ENV = "REMOTE_JUGGLER_KDBX_YUBIKEY";
+
   /* Default group hierarchy to create on bootstrap */
   const BOOTSTRAP_GROUPS = [
     "RemoteJuggler",
@@ -221,6 +234,31 @@ prototype module KeePassXC {
   // Detection & Availability
   // ============================================================================
 
+  /*
+   * Build a keepassxc-cli command line.
+   *
+   * When REMOTE_JUGGLER_KDBX_YUBIKEY names a slot, the YubiKey's
+   * challenge-response is required to open the database, in addition to
+   * the master password. db-create is left alone: keepassxc-cli can't
+   * enroll a YubiKey, that is done in KeePassXC.
+   *
+   * :arg args: Subcommand followed by its arguments
+   * :returns: Full argv for spawn
+   */
+  proc kpxc(args: [] string): [] string {
+    var argv: list(string);
+    argv.pushBack("keepassxc-cli");
+    const slot = getEnvVar(KDBX_YUBIKEY_ENV);
+    for (arg, i) in zip(args, 0..) {
+      argv.pushBack(arg);
+      if i == 0 && slot != "" && arg != "db-create" {
+        argv.pushBack("--yubikey");
+        argv.pushBack(slot);
+      }
+    }
+    return argv.toArray();
+  }
+
   /*
    * Check if keepassxc-cli is available on the system.
    *
@@ -327,9 +365,15 @@ prototype module KeePassXC {
    * 2. Master password sealed in HSM
    * 3. YubiKey present (physical security anchor)
    *
+   * or a master password passed in REMOTE_JUGGLER_KDBX_PASSWORD.
+   *
    * :returns: true if all conditions met for auto-unlock
    */
   proc canAutoUnlock(): bool {
+    if getEnvVar(KDBX_PASSWORD_ENV) != "" {
+      return true;
+    }
+
     // Check HSM
     if hsm_is_available() == 0 {
       return false;
@@ -428,6 +472,12 @@ prototype module KeePassXC {
       return (true, cachedPassword);
     }
 
+    const provided = getEnvVar(KDBX_PASSWORD_ENV);
+    if provided != "" {
+      cacheSessionPassword(provided);
+      return (true, provided);
+    }
+
     if !canAutoUnlock() {
       return (false, "");
     }
@@ -481,7 +531,7 @@ prototype module KeePassXC {
    */
   proc getEntry(dbPath: string, entryPath: string, password: string): (bool, string) {
     try {
-      var p = spawn(["keepassxc-cli", "show", "-s", "-a", "Password", dbPath, entryPath],
+      var p = spawn(kpxc(["show", "-s", "-a", "Password", dbPath, entryPath]),
                     stdin=pipeStyle.pipe, stdout=pipeStyle.pipe, stderr=pipeStyle.pipe);
       p.stdin.write(password + "\n");
       p.stdin.close();
@@ -517,7 +567,7 @@ prototype module KeePassXC {
   proc setEntry(dbPath: string, entryPath: string, password: string, value: string): bool {
     // Try to edit first (entry exists)
     try {
-      var p = spawn(["keepassxc-cli", "edit", "-p", dbPath, entryPath],
+      var p = spawn(kpxc(["edit", "-p", dbPath, entryPath]),
                     stdin=pipeStyle.pipe, stdout=pipeStyle.close, stderr=pipeStyle.pipe);
       p.stdin.write(password + "\n" + value + "\n");
       p.stdin.close();
@@ -530,7 +580,7 @@ prototype module KeePassXC {
 
     // Entry doesn't exist, create it
     try {
-      var p = spawn(["keepassxc-cli", "add", "-p", dbPath, entryPath],
+      var p = spawn(kpxc(["add", "-p", dbPath, entryPath]),
                     stdin=pipeStyle.pipe, stdout=pipeStyle.close, stderr=pipeStyle.pipe);
       p.stdin.write(password + "\n" + value + "\n");
       p.stdin.close();
@@ -553,7 +603,7 @@ prototype module KeePassXC {
    */
   proc deleteEntry(dbPath: string, entryPath: string, password: string): bool {
     try {
-      var p = spawn(["keepassxc-cli", "rm", dbPath, entryPath],
+      var p = spawn(kpxc(["rm", dbPath, entryPath]),
                     stdin=pipeStyle.pipe, stdout=pipeStyle.close, stderr=pipeStyle.pipe);
       p.stdin.write(password + "\n");
       p.stdin.close();
@@ -566,6 +616,233 @@ prototype module KeePassXC {
     }
   }
 
+  /*
+   * List the binary attachments of an entry.
+   *
+   * Parses the "Attachments:" section of `keepassxc-cli show
+   * --show-attachments`, where each attachment is listed as
+   * "  <name> (<size>)".
+   *
+   * :arg dbPath: Path to the kdbx file
+   * :arg entryPath: Entry path within the database
+   * :arg password: Master password
+   * :returns: (success, list of (name, size))
+   */
+  proc listAttachments(dbPath: string, entryPath: string,
+                       password: string): (bool, list((string, string))) {
+    var attachments: list((string, string));
+    try {
+      var p = spawn(kpxc(["show", "--show-attachments", dbPath, entryPath]),
+                    stdin=pipeStyle.pipe, stdout=pipeStyle.pipe, stderr=pipeStyle.pipe);
+      p.stdin.write(password + "\n");
+      p.stdin.close();
+
+      var output: string;
+      p.stdout.readAll(output);
+      p.wait();
+
+      if p.exitCode != 0 {
+        var errMsg: string;
+        p.stderr.readAll(errMsg);
+        verboseLog("KeePassXC listAttachments failed: ", errMsg.strip());
+        return (false, attachments);
+      }
+
+      var inSection = false;
+      for line in output.split("\n") {
+        if line.strip() == "Attachments:" {
+          inSection = true;
+          continue;
+        }
+        if !inSection then continue;
+        // The section is indented; anything else ends it
+        if !line.startsWith(" ") || line.strip() == "" then break;
+
+        const item = line.strip();
+        const sizeStart = item.rfind(" (");
+        if sizeStart != -1 && item.endsWith(")") {
+          const name = item[..<sizeStart];
+          const size = item[sizeStart+2..].strip(")", leading=false);
+          attachments.pushBack((name, size));
+        } else {
+          attachments.pushBack((item, ""));
+        }
+      }
+      return (true, attachments);
+    } catch e {
+      verboseLog("KeePassXC listAttachments error: ", e.message());
+      return (false, attachments);
+    }
+  }
+
+  /*
+   * Attach a file to an entry, replacing an attachment of the same name.
+   *
+   * :arg dbPath: Path to the kdbx file
+   * :arg entryPath: Entry path within the database
+   * :arg name: Attachment name
+   * :arg filePath: File to import
+   * :arg password: Master password
+   * :returns: true on success
+   */
+  proc importAttachment(dbPath: string, entryPath: string, name: string,
+                        filePath: string, password: string): bool {
+    try {
+      var p = spawn(kpxc(["attachment-import", "-f", dbPath, entryPath, name, filePath]),
+                    stdin=pipeStyle.pipe, stdout=pipeStyle.close, stderr=pipeStyle.pipe);
+      p.stdin.write(password + "\n");
+      p.stdin.close();
+      p.wait();
+
+      if p.exitCode != 0 {
+        var errMsg: string;
+        p.stderr.readAll(errMsg);
+        verboseLog("KeePassXC importAttachment failed: ", errMsg.strip());
+      }
+      return p.exitCode == 0;
+    } catch e {
+      verboseLog("KeePassXC importAttachment error: ", e.message());
+      return false;
+    }
+  }
+
+  /*
+   * Write an attachment to a file.
+   *
+   * :arg dbPath: Path to the kdbx file
+   * :arg entryPath: Entry path within the database
+   * :arg name: Attachment name
+   * :arg destPath: File to write
+   * :arg password: Master password
+   * :returns: true on success
+   */
+  proc exportAttachment(dbPath: string, entryPath: string, name: string,
+                        destPath: string, password: string): bool {
+    try {
+      var p = spawn(kpxc(["attachment-export", dbPath, entryPath, name, destPath]),
+                    stdin=pipeStyle.pipe, stdout=pipeStyle.close, stderr=pipeStyle.pipe);
+      p.stdin.write(password + "\n");
+      p.stdin.close();
+      p.wait();
+
+      if p.exitCode != 0 {
+        var errMsg: string;
+        p.stderr.readAll(errMsg);
+        verboseLog("KeePassXC exportAttachment failed: ", errMsg.strip());
+      }
+      return p.exitCode == 0;
+    } catch e {
+      verboseLog("KeePassXC exportAttachment error: ", e.message());
+      return false;
+    }
+  }
+
+  /*
+   * Remove an attachment from an entry.
+   *
+   * :arg dbPath: Path to the kdbx file
+   * :arg entryPath: Entry path within the database
+   * :arg name: Attachment name
+   * :arg password: Master password
+   * :returns: true on success
+   */
+  proc removeAttachment(dbPath: string, entryPath: string, name: string,
+                        password: string): bool {
+    try {
+      var p = spawn(kpxc(["attachment-rm", dbPath, entryPath, name]),
+                    stdin=pipeStyle.pipe, stdout=pipeStyle.close, stderr=pipeStyle.pipe);
+      p.stdin.write(password + "\n");
+      p.stdin.close();
+      p.wait();
+
+      return p.exitCode == 0;
+    } catch e {
+      verboseLog("KeePassXC removeAttachment error: ", e.message());
+      return false;
+    }
+  }
+
+  /*
+   * Export the whole database as KeePass XML.
+   *
+   * The XML holds every value in plaintext, including entry history, so
+   * callers must not write it to disk.
+   *
+   * :arg dbPath: Path to the kdbx file
+   * :arg password: Master password
+   * :returns: (success, xml)
+   */
+  proc exportXml(dbPath: string, password: string): (bool, string) {
+    try {
+      var p = spawn(kpxc(["export", "--format", "xml", dbPath]),
+                    stdin=pipeStyle.pipe, stdout=pipeStyle.pipe, stderr=pipeStyle.pipe);
+      p.stdin.write(password + "\n");
+      p.stdin.close();
+
+      // Read before waiting; the export can be larger than the pipe buffer
+      var xml: string;
+      p.stdout.readAll(xml);
+      p.wait();
+
+      if p.exitCode != 0 {
+        var errMsg: string;
+        p.stderr.readAll(errMsg);
+        verboseLog("KeePassXC exportXml failed: ", errMsg.strip());
+        return (false, "");
+      }
+      return (true, xml);
+    } catch e {
+      verboseLog("KeePassXC exportXml error: ", e.message());
+      return (false, "");
+    }
+  }
+
+  /*
+   * Merge another database into this one.
+   *
+   * keepassxc-cli matches entries by UUID and keeps the newer version of
+   * each, moving the older one into the entry's history, so nothing is
+   * lost. With dryRun the databases are left untouched and only the change
+   * list is returned.
+   *
+   * :arg dbPath: Path to the primary kdbx file
+   * :arg otherPath: Path to the kdbx file to merge from
+   * :arg password: Master password of the primary database
+   * :arg otherPassword: Master password of the other database
+   * :arg dryRun: Only report the changes
+   * :returns: (success, change list output)
+   */
+  proc mergeDatabase(dbPath: string, otherPath: string, password: string,
+                     otherPassword: string, dryRun: bool): (bool, string) {
+    try {
+      var args: list(string);
+      args.pushBack("merge");
+      if dryRun then args.pushBack("--dry-run");
+      args.pushBack(dbPath);
+      args.pushBack(otherPath);
+
+      var p = spawn(kpxc(args.toArray()), stdin=pipeStyle.pipe, stdout=pipeStyle.pipe, stderr=pipeStyle.pipe);
+      // Prompted for in order: primary, then the other database
+      p.stdin.write(password + "\n" + otherPassword + "\n");
+      p.stdin.close();
+
+      var output: string;
+      p.stdout.readAll(output);
+      p.wait();
+
+      if p.exitCode != 0 {
+        var errMsg: string;
+        p.stderr.readAll(errMsg);
+        verboseLog("KeePassXC mergeDatabase failed: ", errMsg.strip());
+        return (false, errMsg.strip());
+      }
+      return (true, output);
+    } catch e {
+      verboseLog("KeePassXC mergeDatabase error: ", e.message());
+      return (false, e.message());
+    }
+  }
+
   /*
    * List entries in a group.
    *
@@ -578,14 +855,13 @@ prototype module KeePassXC {
     var entries: list(string);
     try {
       var args: list(string);
-      args.pushBack("keepassxc-cli");
       args.pushBack("ls");
       args.pushBack(dbPath);
       if group != "" {
         args.pushBack(group);
       }
 
-      var p = spawn(args.toArray(),
+      var p = spawn(kpxc(args.toArray()),
                     stdin=pipeStyle.pipe, stdout=pipeStyle.pipe, stderr=pipeStyle.pipe);
       p.stdin.write(password + "\n");
       p.stdin.close();
@@ -624,7 +900,7 @@ prototype module KeePassXC {
    */
   proc getEntryMetadata(dbPath: string, entryPath: string, password: string): (bool, string, string, string) {
     try {
-      var p = spawn(["keepassxc-cli", "show", "-a", "UserName", "-a", "Notes", "-a", "URL", dbPath, entryPath],
+      var p = spawn(kpxc(["show", "-a", "UserName", "-a", "Notes", "-a", "URL", dbPath, entryPath]),
                     stdin=pipeStyle.pipe, stdout=pipeStyle.pipe, stderr=pipeStyle.pipe);
       p.stdin.write(password + "\n");
       p.stdin.close();
@@ -667,7 +943,7 @@ prototype module KeePassXC {
 
     // Step 1: keepassxc-cli search for exact/prefix matches
     try {
-      var p = spawn(["keepassxc-cli", "search", dbPath, query],
+      var p = spawn(kpxc(["search", dbPath, query]),
                     stdin=pipeStyle.pipe, stdout=pipeStyle.pipe, stderr=pipeStyle.close);
       p.stdin.write(password + "\n");
       p.stdin.close();
@@ -702,7 +978,6 @@ prototype module KeePassXC {
     if results.size < 10 {
       // Build list of all entries via recursive listing
       var lsArgs: list(string);
-      lsArgs.pushBack("keepassxc-cli");
       lsArgs.pushBack("ls");
       lsArgs.pushBack("-R");
       lsArgs.pushBack(dbPath);
@@ -711,7 +986,7 @@ prototype module KeePassXC {
       }
 
       try {
-        var p = spawn(lsArgs.toArray(),
+        var p = spawn(kpxc(lsArgs.toArray()),
                       stdin=pipeStyle.pipe, stdout=pipeStyle.pipe, stderr=pipeStyle.close);
         p.stdin.write(password + "\n");
         p.stdin.close();
@@ -930,7 +1205,7 @@ prototype module KeePassXC {
 
     // Create database
     try {
-      var p = spawn(["keepassxc-cli", "db-create", "--set-password", dbPath],
+      var p = spawn(kpxc(["db-create", "--set-password", dbPath]),
                     stdin=pipeStyle.pipe, stdout=pipeStyle.pipe, stderr=pipeStyle.pipe);
       p.stdin.write(masterPassword + "\n" + masterPassword + "\n");
       p.stdin.close();
@@ -948,7 +1223,7 @@ prototype module KeePassXC {
     // Create group hierarchy
     for group in BOOTSTRAP_GROUPS {
       try {
-        var p = spawn(["keepassxc-cli", "mkdir", dbPath, group],
+        var p = spawn(kpxc(["mkdir", dbPath, group]),
                       stdin=pipeStyle.pipe, stdout=pipeStyle.close, stderr=pipeStyle.close);
         p.stdin.write(masterPassword + "\n");
         p.stdin.close();
@@ -1010,6 +1285,47 @@ prototype module KeePassXC {
     return result == HSM_SUCCESS;
   }
 
+  // ============================================================================
+  // Progress Events
+  // ============================================================================
+
+  /*
+   * Progress events are single-line JSON objects on stdout, mixed with the
+   * ordinary output, written only when PROGRESS_ENV is "json":
+   *
+   *   {"event":"started","operation":"crawl","total":42}
+   *   {"event":"progress","current":3,"total":42,"item":"~/code/app/.env"}
+   *   {"event":"finished","summary":"Ingested 12 entries"}
+   *
+   * "total" is left out when it isn't known up front.
+   */
+
+  /* Event for an operation starting; total < 0 when unknown */
+  proc progressStartedJSON(operation: string, total: int = -1): string {
+    var json = '{"event":"started","operation":' + jsonString(operation);
+    if total >= 0 then json += ',"total":' + total:string;
+    return json + "}";
+  }
+
+  /* Event for one more item processed; total < 0 when unknown */
+  proc progressItemJSON(current: int, item: string, total: int = -1): string {
+    var json = '{"event":"progress","current":' + current:string;
+    if total >= 0 then json += ',"total":' + total:string;
+    return json + ',"item":' + jsonString(item) + "}";
+  }
+
+  /* Event for an operation finishing */
+  proc progressFinishedJSON(summary: string): string {
+    return '{"event":"finished","summary":' + jsonString(summary) + "}";
+  }
+
+  /* Write a progress event if a frontend asked for them */
+  proc emitProgress(event: string) {
+    if getEnvVar(PROGRESS_ENV) != "json" then return;
+    stdout.writeln(event);
+    stdout.flush();
+  }
+
   // ============================================================================
   // .env File Ingestion
   // ============================================================================
@@ -1025,9 +1341,11 @@ prototype module KeePassXC {
    * :arg dbPath: Path to the kdbx file
    * :arg envFilePath: Path to the .env file to ingest
    * :arg password: Master password
+   * :arg reportProgress: Emit progress events for each key
    * :returns: (entriesAdded, entriesUpdated)
    */
-  proc ingestEnvFile(dbPath: string, envFilePath: string, password: string): (int, int) {
+  proc ingestEnvFile(dbPath: string, envFilePath: string, password: string,
+                     reportProgress: bool = false): (int, int) {
     var added = 0;
     var updated = 0;
 
@@ -1050,13 +1368,24 @@ prototype module KeePassXC {
 
     // Ensure group exists
     try {
-      var p = spawn(["keepassxc-cli", "mkdir", dbPath, groupPath],
+      var p = spawn(kpxc(["mkdir", dbPath, groupPath]),
                     stdin=pipeStyle.pipe, stdout=pipeStyle.close, stderr=pipeStyle.close);
       p.stdin.write(password + "\n");
       p.stdin.close();
       p.wait();
     } catch { }
 
+    var total = 0;
+    if reportProgress {
+      for line in content.split("\n") {
+        const trimmed = line.strip();
+        if trimmed != "" && !trimmed.startsWith("#") && trimmed.find("=") >= 0 then
+          total += 1;
+      }
+      emitProgress(progressStartedJSON("ingest", total));
+    }
+    var current = 0;
+
     // Parse and store each key-value pair
     for line in content.split("\n") {
       var trimmed = line.strip();
@@ -1091,6 +1420,8 @@ prototype module KeePassXC {
       if key == "" {
         continue;
       }
+      current += 1;
+      if reportProgress then emitProgress(progressItemJSON(current, key, total));
 
       // Store entry
       const entryPath = groupPath + "/" + key;
@@ -1113,6 +1444,10 @@ prototype module KeePassXC {
       }
     }
 
+    if reportProgress {
+      emitProgress(progressFinishedJSON("Added " + added:string + ", updated " +
+                                        updated:string + " entries"));
+    }
     return (added, updated);
   }
 
@@ -1365,7 +1700,7 @@ prototype module KeePassXC {
 
     // Ensure group exists
     try {
-      var p = spawn(["keepassxc-cli", "mkdir", dbPath, groupPath],
+      var p = spawn(kpxc(["mkdir", dbPath, groupPath]),
                     stdin=pipeStyle.pipe, stdout=pipeStyle.close, stderr=pipeStyle.close);
       p.stdin.write(password + "\n");
       p.stdin.close();
@@ -1504,9 +1839,11 @@ prototype module KeePassXC {
    * :arg dbPath: Path to the kdbx file
    * :arg password: Master password
    * :arg rootDirs: Directories to search (defaults to ~, ~/git, ~/projects)
+   * :arg reportProgress: Emit a progress event for each file found
    * :returns: (filesFound, totalAdded, totalUpdated)
    */
-  proc crawlEnvFiles(dbPath: string, password: string, rootDirs: list(string) = new list(string)): (int, int, int) {
+  proc crawlEnvFiles(dbPath: string, password: string, rootDirs: list(string) = new list(string),
+                     reportProgress: bool = false): (int, int, int) {
     var dirs = rootDirs;
     if dirs.size == 0 {
       dirs.pushBack(expandTilde("~"));
@@ -1528,7 +1865,7 @@ prototype module KeePassXC {
       } catch { continue; }
 
       crawlEnvFilesRecursive(dbPath, password, rootDir, skipDirs,
-                              filesFound, totalAdded, totalUpdated, 0);
+                              filesFound, totalAdded, totalUpdated, 0, reportProgress);
     }
 
     return (filesFound, totalAdded, totalUpdated);
@@ -1538,7 +1875,8 @@ prototype module KeePassXC {
   proc crawlEnvFilesRecursive(dbPath: string, password: string, dir: string,
                                skipDirs: [] string,
                                ref filesFound: int, ref totalAdded: int,
-                               ref totalUpdated: int, depth: int) {
+                               ref totalUpdated: int, depth: int,
+                               reportProgress: bool = false) {
     if depth > 5 then return;
 
     try {
@@ -1554,12 +1892,14 @@ prototype module KeePassXC {
           }
           if !skip {
             crawlEnvFilesRecursive(dbPath, password, fullPath, skipDirs,
-                                    filesFound, totalAdded, totalUpdated, depth + 1);
+                                    filesFound, totalAdded, totalUpdated, depth + 1,
+                                    reportProgress);
           }
         } else {
           // Check if this is a .env file
           if isEnvFile(entry) {
             filesFound += 1;
+            if reportProgress then emitProgress(progressItemJSON(filesFound, fullPath));
             const (added, updated) = ingestEnvFile(dbPath, fullPath, password);
             totalAdded += added;
             totalUpdated += updated;
@@ -1567,6 +1907,7 @@ prototype module KeePassXC {
           // Check if this is a SOPS-encrypted file
           else if isSopsReady() && isSopsFile(entry) {
             filesFound += 1;
+            if reportProgress then emitProgress(progressItemJSON(filesFound, fullPath));
             const (sopsAdded, sopsUpdated) = ingestSopsFile(dbPath, fullPath, password);
             totalAdded += sopsAdded;
             totalUpdated += sopsUpdated;
@@ -1780,20 +2121,27 @@ prototype module KeePassXC {
    * :arg group: Group path to export (e.g., "RemoteJuggler/API")
    * :arg password: Master password
    * :arg format: Output format ("env" or "json")
+   * :arg reportProgress: Emit progress events for each entry
    * :returns: (success, content)
    */
   proc exportEntries(dbPath: string, group: string, password: string,
-                     format: string = "env"): (bool, string) {
+                     format: string = "env", reportProgress: bool = false): (bool, string) {
     const (listOk, entries) = listEntries(dbPath, group, password);
     if !listOk {
       return (false, "");
     }
+    var total = 0;
+    for entry in entries do if !entry.endsWith("/") then total += 1;
+    if reportProgress then emitProgress(progressStartedJSON("export", total));
+    var current = 0;
 
     if format == "json" {
       var jsonOutput = '{"group":"' + group + '","entries":[';
       var first = true;
       for entry in entries {
         if entry.endsWith("/") then continue; // Skip subgroups
+        current += 1;
+        if reportProgress then emitProgress(progressItemJSON(current, entry, total));
         const entryPath = group + "/" + entry;
         const (found, value) = getEntry(dbPath, entryPath, password);
         if found {
@@ -1805,12 +2153,15 @@ prototype module KeePassXC {
         }
       }
       jsonOutput += "]}";
+      if reportProgress then emitProgress(progressFinishedJSON("Exported " + current:string + " entries"));
       return (true, jsonOutput);
     } else {
       // .env format
       var envContent = "# Exported from KeePassXC group: " + group + "\n";
       for entry in entries {
         if entry.endsWith("/") then continue; // Skip subgroups
+        current += 1;
+        if reportProgress then emitProgress(progressItemJSON(current, entry, total));
         const entryPath = group + "/" + entry;
         const (found, value) = getEntry(dbPath, entryPath, password);
         if found {
@@ -1822,6 +2173,7 @@ prototype module KeePassXC {
           }
         }
       }
+      if reportProgress then emitProgress(progressFinishedJSON("Exported " + current:string + " entries"));
       return (true, envContent);
     }
   }
@@ -1839,7 +2191,7 @@ prototype module KeePassXC {
    */
   proc ensureGroup(dbPath: string, group: string, password: string) {
     try {
-      var p = spawn(["keepassxc-cli", "mkdir", dbPath, group],
+      var p = spawn(kpxc(["mkdir", dbPath, group]),
                     stdin=pipeStyle.pipe, stdout=pipeStyle.close, stderr=pipeStyle.close);
       p.stdin.write(password + "\n");
       p.stdin.close();
@@ -1960,6 +2312,8 @@ prototype module KeePassXC {
 
     // YubiKey
     output += "YubiKey: " + (if isYubiKeyPresent() then "present" else "not detected") + "\n";
+    const chalResp = getEnvVar(KDBX_YUBIKEY_ENV);
+    output += "Challenge-Response: " + (if chalResp != "" then "slot " + chalResp else "off") + "\n";
 
     // SOPS integration
     output += "SOPS: " + (if isSopsAvailable() then "installed" else "not found") + "\n";
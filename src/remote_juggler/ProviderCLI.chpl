@@ -6,10 +6,14 @@
  * with graceful fallback when CLIs are not available.
  *
  * Credential resolution chain:
- *   1. Darwin Keychain (macOS)
- *   2. Environment variable
- *   3. CLI stored auth (glab/gh)
- *   4. SSH-only fallback
+ *   1. KeePassXC key store
+ *   2. Darwin Keychain (macOS)
+ *   3. Environment variable
+ *   4. CLI stored auth (glab/gh)
+ *   5. SSH-only fallback
+ *
+ * Switching identity switches gh and glab to the identity's account with
+ * the first three; the CLI's own login is only a fallback.
  */
 prototype module ProviderCLI {
   use Subprocess;
@@ -20,6 +24,7 @@ prototype module ProviderCLI {
   public use super.Core;
   public use super.Keychain;
   import super.Keychain;
+  import super.KeePassXC;
 
   // ============================================================
   // CLI Availability Detection
@@ -232,6 +237,58 @@ prototype module ProviderCLI {
     }
   }
 
+  // ============================================================
+  // Key Store Tokens
+  // ============================================================
+
+  /*
+   * Look up an identity's token in the KeePassXC key store
+   *
+   * Entries are checked most specific first: the identity's keePassEntry,
+   * then RemoteJuggler/Tokens/<Provider>/<identity>, then
+   * RemoteJuggler/Tokens/<Provider>/default, the same entries the GUI
+   * reads.
+   *
+   * Args:
+   *   identity: The GitIdentity to look up a token for
+   *
+   * Returns:
+   *   Tuple of (found, token) where token is empty string if not found
+   */
+  proc getKeyStoreToken(identity: GitIdentity): (bool, string) {
+    if !KeePassXC.isAvailable() || !KeePassXC.databaseExists() then return (false, "");
+
+    const group = if identity.provider == Provider.GitHub then "RemoteJuggler/Tokens/GitHub"
+                  else if identity.provider == Provider.GitLab then "RemoteJuggler/Tokens/GitLab"
+                  else if identity.provider == Provider.Bitbucket
+                    then "RemoteJuggler/Tokens/Bitbucket"
+                  else if identity.provider == Provider.Gitea then "RemoteJuggler/Tokens/Gitea"
+                  else if identity.provider == Provider.Sourcehut
+                    then "RemoteJuggler/Tokens/sourcehut"
+                  else "";
+
+    var paths: list(string);
+    if identity.keePassEntry != "" then paths.pushBack(identity.keePassEntry);
+    if group != "" {
+      paths.pushBack(group + "/" + identity.name);
+      paths.pushBack(group + "/default");
+    }
+    if paths.isEmpty() then return (false, "");
+
+    const (unlocked, password) = KeePassXC.autoUnlock();
+    if !unlocked then return (false, "");
+
+    const dbPath = KeePassXC.getDatabasePath();
+    for path in paths {
+      const (found, token) = KeePassXC.getEntry(dbPath, path, password);
+      if found && token != "" {
+        if verbose then writeln("  Credential source: Key store (", path, ")");
+        return (true, token);
+      }
+    }
+    return (false, "");
+  }
+
   // ============================================================
   // Credential Resolution Chain
   // ============================================================
@@ -239,11 +296,11 @@ prototype module ProviderCLI {
   // Note: getEnvVar is now imported from Core module
 
   /*
-   * Resolve credential for an identity using the resolution chain:
-   *   1. Darwin Keychain (macOS only)
-   *   2. Environment variable (if tokenEnvVar is set)
-   *   3. CLI stored auth (glab/gh)
-   *   4. Return empty (SSH-only fallback)
+   * Resolve the identity's own credential, leaving out whatever the
+   * provider CLI is logged in with:
+   *   1. KeePassXC key store
+   *   2. Darwin Keychain (macOS only)
+   *   3. Environment variable (if tokenEnvVar is set)
    *
    * Args:
    *   identity: The GitIdentity to resolve credentials for
@@ -251,8 +308,12 @@ prototype module ProviderCLI {
    * Returns:
    *   Tuple of (hasToken, token) where token is empty string if not found
    */
-  proc resolveCredential(identity: GitIdentity): (bool, string) {
-    // 1. Try Keychain first (Darwin only)
+  proc resolveStoredCredential(identity: GitIdentity): (bool, string) {
+    // 1. Try the key store
+    const (stored, storedToken) = getKeyStoreToken(identity);
+    if stored then return (true, storedToken);
+
+    // 2. Try Keychain (Darwin only)
     if useKeychain && Keychain.isDarwin() {
       const providerStr = providerToString(identity.provider);
       const (found, token) = Keychain.retrieveToken(providerStr, identity.name, identity.user);
@@ -262,7 +323,7 @@ prototype module ProviderCLI {
       }
     }
 
-    // 2. Try environment variable
+    // 3. Try environment variable
     if identity.tokenEnvVar != "" {
       const token = getEnvVar(identity.tokenEnvVar);
       if token != "" {
@@ -271,7 +332,27 @@ prototype module ProviderCLI {
       }
     }
 
-    // 3. Try provider CLI stored auth
+    return (false, "");
+  }
+
+  /*
+   * Resolve credential for an identity using the resolution chain:
+   *   1. The identity's own credential (key store, Keychain, environment)
+   *   2. CLI stored auth (glab/gh)
+   *   3. Return empty (SSH-only fallback)
+   *
+   * Args:
+   *   identity: The GitIdentity to resolve credentials for
+   *
+   * Returns:
+   *   Tuple of (hasToken, token) where token is empty string if not found
+   */
+  proc resolveCredential(identity: GitIdentity): (bool, string) {
+    // 1. The identity's own credential
+    const (hasStored, storedToken) = resolveStoredCredential(identity);
+    if hasStored then return (true, storedToken);
+
+    // 2. Try provider CLI stored auth
     select identity.provider {
       when Provider.GitLab {
         const (ok, token) = getGlabToken(identity.hostname);
@@ -292,18 +373,47 @@ prototype module ProviderCLI {
       }
     }
 
-    // 4. No token found - SSH-only fallback
+    // 3. No token found - SSH-only fallback
     if verbose then writeln("  Credential source: None (SSH-only mode)");
     return (false, "");
   }
 
+  /*
+   * Make an account gh already knows the active one for a hostname
+   *
+   * gh keeps several accounts per host (gh 2.40 and later); glab keeps
+   * one, so it has no equivalent.
+   *
+   * Args:
+   *   hostname: The GitHub hostname
+   *   user: The account's login
+   *
+   * Returns:
+   *   true if gh switched to the account, false otherwise
+   */
+  proc ghSwitch(hostname: string, user: string): bool {
+    if !ghAvailable() || user == "" then return false;
+
+    try {
+      var p = spawn(["gh", "auth", "switch", "-h", hostname, "-u", user],
+                    stdout=pipeStyle.close,
+                    stderr=pipeStyle.close);
+      p.wait();
+      return p.exitCode == 0;
+    } catch {
+      return false;
+    }
+  }
+
   /*
    * Authenticate with the provider for a given identity
    *
-   * This performs the full authentication flow:
-   *   1. Resolve credential via the resolution chain
-   *   2. If token found, authenticate with provider CLI
-   *   3. Return appropriate AuthResult
+   * This switches the provider CLI to the identity's account:
+   *   1. With the identity's own token, log the CLI in with it, which also
+   *      makes that account the active one
+   *   2. Without one, switch gh to an account it already has for the
+   *      identity's user
+   *   3. Otherwise fall back to whatever the CLI is logged in with
    *
    * Args:
    *   identity: The GitIdentity to authenticate
@@ -312,49 +422,64 @@ prototype module ProviderCLI {
    *   AuthResult indicating success/failure and authentication mode
    */
   proc authenticateProvider(identity: GitIdentity): AuthResult {
-    // Get token from any available source
-    const (hasToken, token) = resolveCredential(identity);
+    const (hasStored, storedToken) = resolveStoredCredential(identity);
+
+    // 1. Log the CLI in as the identity
+    if hasStored {
+      var cliSuccess = false;
+      select identity.provider {
+        when Provider.GitLab {
+          cliSuccess = glabAuth(identity.hostname, storedToken);
+        }
+        when Provider.GitHub {
+          cliSuccess = ghAuth(identity.hostname, storedToken);
+        }
+        otherwise {
+          // Custom providers don't have CLI support
+        }
+      }
+
+      if cliSuccess {
+        return new AuthResult(
+          success = true,
+          mode = AuthMode.CLIAuthenticated,
+          message = "Authenticated via " + providerToString(identity.provider) + " CLI"
+        );
+      }
 
-    if !hasToken {
-      // SSH-only mode - still functional for git operations
+      // CLI not available but token exists - can still use for API calls
       return new AuthResult(
         success = true,
-        mode = AuthMode.SSHOnly,
-        message = "No token found - using SSH-only mode"
+        mode = AuthMode.TokenOnly,
+        message = "Token available, CLI not installed or authentication failed"
       );
     }
 
-    // Try provider CLI authentication
-    var cliSuccess = false;
-    select identity.provider {
-      when Provider.GitLab {
-        if glabAvailable() {
-          cliSuccess = glabAuth(identity.hostname, token);
-        }
-      }
-      when Provider.GitHub {
-        if ghAvailable() {
-          cliSuccess = ghAuth(identity.hostname, token);
-        }
-      }
-      otherwise {
-        // Custom providers don't have CLI support
-      }
+    // 2. Switch gh to an account it already has
+    if identity.provider == Provider.GitHub && ghSwitch(identity.hostname, identity.user) {
+      return new AuthResult(
+        success = true,
+        mode = AuthMode.CLIAuthenticated,
+        message = "Switched gh to account " + identity.user
+      );
     }
 
-    if cliSuccess {
+    // 3. Whatever the CLI is logged in with
+    const (hasToken, _) = resolveCredential(identity);
+    if hasToken {
       return new AuthResult(
         success = true,
-        mode = AuthMode.CLIAuthenticated,
-        message = "Authenticated via " + providerToString(identity.provider) + " CLI"
+        mode = AuthMode.TokenOnly,
+        message = "Using the " + providerToString(identity.provider) +
+                  " CLI's current account; no token stored for this identity"
       );
     }
 
-    // CLI not available but token exists - can still use for API calls
+    // SSH-only mode - still functional for git operations
     return new AuthResult(
       success = true,
-      mode = AuthMode.TokenOnly,
-      message = "Token available, CLI not installed or authentication failed"
+      mode = AuthMode.SSHOnly,
+      message = "No token found - using SSH-only mode"
     );
   }
 
@@ -54,10 +54,12 @@ prototype module Remote {
   use IO;
   use List;
   use Map;
+  import FileSystem;
 
   // Import types from Core module
   public use super.Core only Provider, GitIdentity, providerToString, stringToProvider,
-                       verboseLog;
+                       verboseLog, expandTilde;
+  import super.GlobalConfig;
 
   //============================================================================
   // Remote Information Records
@@ -321,6 +323,9 @@ prototype module Remote {
   private const GITLAB_HOSTS = ["gitlab.com", "gitlab"];
   private const GITHUB_HOSTS = ["github.com", "github"];
   private const BITBUCKET_HOSTS = ["bitbucket.org", "bitbucket"];
+  // Forgejo is a Gitea fork with the same API; Codeberg runs it
+  private const GITEA_HOSTS = ["codeberg.org", "codeberg", "forgejo", "gitea"];
+  private const SOURCEHUT_HOSTS = ["git.sr.ht", "sr.ht", "sourcehut"];
 
   //============================================================================
   // Remote Listing and Retrieval
@@ -680,6 +685,12 @@ prototype module Remote {
   private proc detectProvider(hostname: string): Provider {
     const lowerHost = hostname.toLower();
 
+    // Self-hosted instances named in the settings
+    const (mapped, provider) = GlobalConfig.providerForHost(GlobalConfig.loadSettings(), lowerHost);
+    if mapped {
+      return provider;
+    }
+
     // Check for GitLab
     for host in GITLAB_HOSTS {
       if lowerHost.find(host) != -1 {
@@ -701,6 +712,20 @@ prototype module Remote {
       }
     }
 
+    // Check for Gitea and Forgejo
+    for host in GITEA_HOSTS {
+      if lowerHost.find(host) != -1 {
+        return Provider.Gitea;
+      }
+    }
+
+    // Check for sourcehut
+    for host in SOURCEHUT_HOSTS {
+      if lowerHost.find(host) != -1 {
+        return Provider.Sourcehut;
+      }
+    }
+
     return Provider.Custom;
   }
 
@@ -1296,6 +1321,226 @@ prototype module Remote {
     return nameOk && emailOk;
   }
 
+  //============================================================================
+  // Jujutsu
+  //============================================================================
+
+  /*
+   * Check if a repository is colocated with Jujutsu
+   *
+   * jj reads user and signing settings from its own config and ignores
+   * git's includeIf sections, so these repositories need the identity
+   * written to the jj repository config as well.
+   *
+   * @param repoRoot Repository root directory
+   * @return True if repoRoot holds a jj repository
+   */
+  proc isJJRepository(repoRoot: string): bool {
+    try {
+      return FileSystem.isDir(repoRoot + "/.jj/repo");
+    } catch {
+      return false;
+    }
+  }
+
+  /*
+   * Set a jj repository configuration value
+   *
+   * @param repoRoot Repository root directory
+   * @param key Configuration key
+   * @param value Configuration value
+   * @return Success status
+   */
+  proc setJJConfig(repoRoot: string, key: string, value: string): bool {
+    try {
+      var sub = spawn(["jj", "--repository", repoRoot, "--ignore-working-copy",
+                       "config", "set", "--repo", key, value],
+                      stdout = pipeStyle.pipe,
+                      stderr = pipeStyle.pipe);
+      sub.wait();
+      return sub.exitCode == 0;
+    } catch {
+      return false;
+    }
+  }
+
+  /*
+   * Mirror the git identity of a repository into its jj config
+   *
+   * Copies user.name and user.email as git resolves them. When git signs
+   * commits with an OpenPGP or SSH key, jj signs with the same key and
+   * backend; otherwise jj drops signatures, as gitsign has no jj backend.
+   *
+   * @param repoRoot Repository root directory
+   * @return Tuple of (success: bool, message: string)
+   */
+  proc syncJJIdentity(repoRoot: string): (bool, string) {
+    for key in ["user.name", "user.email"] {
+      const (found, value) = getGitConfig(repoRoot, key);
+      if found && value != "" && !setJJConfig(repoRoot, key, value) {
+        return (false, "Failed to set jj " + key);
+      }
+    }
+
+    const (_, gpgSign) = getGitConfig(repoRoot, "commit.gpgsign");
+    const (_, format) = getGitConfig(repoRoot, "gpg.format");
+    const (_, signingKey) = getGitConfig(repoRoot, "user.signingkey");
+    const signs = gpgSign.toLower() == "true" || gpgSign.toLower() == "yes" ||
+                  gpgSign.toLower() == "on" || gpgSign == "1";
+    const backend = if format == "" || format == "openpgp" then "gpg"
+                    else if format == "ssh" then "ssh"
+                    else "";
+
+    var ok: bool;
+    if signs && backend != "" && signingKey != "" {
+      ok = setJJConfig(repoRoot, "signing.behavior", "own") &&
+           setJJConfig(repoRoot, "signing.backend", backend) &&
+           setJJConfig(repoRoot, "signing.key", signingKey);
+    } else {
+      ok = setJJConfig(repoRoot, "signing.behavior", "drop");
+    }
+    if !ok then return (false, "Failed to set jj signing settings");
+
+    return (true, "jj config updated");
+  }
+
+  //============================================================================
+  // Mercurial
+  //============================================================================
+
+  /* Markers of the identity block in a Mercurial repository's hgrc */
+  const hgBeginMarker = "# BEGIN RemoteJuggler managed identity";
+  const hgEndMarker = "# END RemoteJuggler managed identity";
+
+  /*
+   * Get the root directory of a Mercurial repository
+   *
+   * @param path Path inside the repository
+   * @return Tuple of (found: bool, rootPath: string)
+   */
+  proc getHgRoot(path: string): (bool, string) {
+    try {
+      var sub = spawn(["hg", "--cwd", path, "root"],
+                      stdout = pipeStyle.pipe,
+                      stderr = pipeStyle.pipe);
+
+      var rootPath: string;
+      const got = sub.stdout.readLine(rootPath);
+      sub.wait();
+      if got && sub.exitCode == 0 {
+        return (true, rootPath.strip());
+      }
+    } catch {
+      // hg not installed
+    }
+    return (false, "");
+  }
+
+  /*
+   * Check if a path is inside a Mercurial repository
+   *
+   * @param path Path to check
+   * @return True if path is inside a Mercurial repository
+   */
+  proc isHgRepository(path: string): bool {
+    const (found, _) = getHgRoot(path);
+    return found;
+  }
+
+  /*
+   * Render the identity block for a Mercurial repository's hgrc
+   *
+   * Sets [ui] username to the identity's author and, when it has a key,
+   * [ui] ssh to a command offering only that key.
+   *
+   * @param identity Identity to write
+   * @return Block lines, markers included
+   */
+  proc renderHgBlock(identity: GitIdentity): list(string) {
+    var block: list(string);
+    block.pushBack(hgBeginMarker);
+    block.pushBack("# Generated by RemoteJuggler; edits inside this block are overwritten");
+    block.pushBack("[ui]");
+    if identity.user != "" && identity.email != "" {
+      block.pushBack("username = " + identity.user + " <" + identity.email + ">");
+    } else {
+      block.pushBack("username = " + identity.user + identity.email);
+    }
+    if identity.sshKeyPath != "" {
+      const key = expandTilde(identity.sshKeyPath).replace("'", "'\\''");
+      block.pushBack("ssh = ssh -i '" + key + "' -o IdentitiesOnly=yes");
+    }
+    block.pushBack(hgEndMarker);
+    return block;
+  }
+
+  /*
+   * Write the identity block to a Mercurial repository's .hg/hgrc
+   *
+   * Replaces the block if present, or appends it so it wins over settings
+   * earlier in the file. Lines outside the markers are left untouched.
+   *
+   * @param repoRoot Repository root directory
+   * @param identity Identity to write
+   * @return Tuple of (success: bool, message: string)
+   */
+  proc setHgIdentity(repoRoot: string, identity: GitIdentity): (bool, string) {
+    const hgrcPath = repoRoot + "/.hg/hgrc";
+    try {
+      var content = "";
+      if FileSystem.exists(hgrcPath) {
+        var f = open(hgrcPath, ioMode.r);
+        defer { try! f.close(); }
+        var reader = f.reader(locking=false);
+        defer { try! reader.close(); }
+        reader.readAll(content);
+      }
+
+      var lines: list(string);
+      var inBlock = false;
+      var sawBegin = false;
+      var sawEnd = false;
+      var replaced = false;
+      for line in content.split("\n") {
+        if line.strip() == hgBeginMarker {
+          inBlock = true;
+          sawBegin = true;
+          continue;
+        }
+        if inBlock {
+          if line.strip() == hgEndMarker {
+            inBlock = false;
+            sawEnd = true;
+            for blockLine in renderHgBlock(identity) do lines.pushBack(blockLine);
+            replaced = true;
+          }
+          continue;
+        }
+        lines.pushBack(line);
+      }
+      if sawBegin && !sawEnd {
+        return (false, "hgrc block has no end marker; fix " + hgrcPath + " by hand");
+      }
+
+      // Drop the trailing empty line left by the final newline
+      while lines.size > 0 && lines.last.strip() == "" do lines.popBack();
+      if !replaced {
+        if lines.size > 0 then lines.pushBack("");
+        for blockLine in renderHgBlock(identity) do lines.pushBack(blockLine);
+      }
+
+      var outFile = open(hgrcPath, ioMode.cw);
+      defer { try! outFile.close(); }
+      var writer = outFile.writer(locking=false);
+      defer { try! writer.close(); }
+      for line in lines do writer.writeln(line);
+
+      return (true, "hgrc identity block updated");
+    } catch e {
+      return (false, "Failed to write " + hgrcPath + ": " + e.message());
+    }
+  }
+
   //============================================================================
   // Utility Functions
   //============================================================================
@@ -173,17 +173,26 @@ module Setup {
     const lowerHost = hostname.toLower();
     const lowerAlias = alias.toLower();
 
+    // Self-hosted instances named in the settings
+    const (mapped, provider) = GlobalConfig.providerForHost(GlobalConfig.loadSettings(), lowerHost);
+    if mapped then return providerToString(provider);
+
     // Check hostname first
     if lowerHost.find("gitlab") != -1 then return "gitlab";
     if lowerHost.find("github") != -1 then return "github";
     if lowerHost.find("bitbucket") != -1 then return "bitbucket";
     if lowerHost.find("azure") != -1 || lowerHost.find("visualstudio") != -1 then return "azure";
-    if lowerHost.find("codeberg") != -1 then return "codeberg";
+    if lowerHost.find("codeberg") != -1 || lowerHost.find("forgejo") != -1 ||
+       lowerHost.find("gitea") != -1 then return "gitea";
+    if lowerHost.find("sr.ht") != -1 then return "sourcehut";
 
     // Check alias
     if lowerAlias.find("gitlab") != -1 then return "gitlab";
     if lowerAlias.find("github") != -1 then return "github";
     if lowerAlias.find("bitbucket") != -1 then return "bitbucket";
+    if lowerAlias.find("codeberg") != -1 || lowerAlias.find("gitea") != -1 then return "gitea";
+    if lowerAlias.find("srht") != -1 || lowerAlias.find("sourcehut") != -1 then
+      return "sourcehut";
 
     // Default based on common patterns
     if lowerHost == "gitlab.com" then return "gitlab";
@@ -226,7 +235,7 @@ module Setup {
       const provider = detected.provider;
       if provider == "gitlab" || provider == "github" ||
          provider == "bitbucket" || provider == "azure" ||
-         provider == "codeberg" {
+         provider == "gitea" || provider == "sourcehut" {
         detected.selected = true;
       } else {
         // Still include but not selected by default
@@ -26,6 +26,7 @@ prototype module TokenHealth {
   public use super.Keychain;
   import super.ProviderCLI;
   import super.Keychain;
+  import super.GlobalConfig;
 
   // ============================================================
   // Token Metadata Records
@@ -44,6 +45,7 @@ prototype module TokenHealth {
     var tokenType: string;            // "pat" (Personal Access Token), "oauth", etc.
     var isValid: bool;                // Last verification result
     var warningIssued: real;          // Last time expiry warning was issued
+    var entry: string;                // Key store entry the token was stored at
 
     proc init() {
       this.identityName = "";
@@ -55,6 +57,7 @@ prototype module TokenHealth {
       this.tokenType = "pat";
       this.isValid = true;
       this.warningIssued = 0.0;
+      this.entry = "";
     }
   }
 
@@ -114,16 +117,10 @@ prototype module TokenHealth {
       var reader = f.reader(locking=false);
       var jsonStr: string;
       reader.readAll(jsonStr);
-
-      // Parse JSON and populate map
-      // Note: Chapel's JSON module is limited, so we do simple parsing
-      // In production, use a proper JSON parser
-
-      // For now, return empty map
-      // TODO: Implement JSON parsing when Chapel JSON module is more mature
-
       f.close();
 
+      parseMetadataJSON(GlobalConfig.extractJSONSection(jsonStr, "tokens"), metadataMap);
+
     } catch e {
       if verbose then writeln("Warning: Could not load token metadata: ", e.message());
     }
@@ -131,6 +128,82 @@ prototype module TokenHealth {
     return metadataMap;
   }
 
+  /*
+   * Extract a numeric value, e.g. "expiresAt": 1769904000.0
+   */
+  proc extractJSONReal(json: string, key: string, defaultVal: real): real {
+    const pattern = '"' + key + '":';
+    const start = json.find(pattern);
+    if start < 0 then return defaultVal;
+
+    var pos = start + pattern.size;
+    while pos < json.size && json[pos] == ' ' do pos += 1;
+    var valueEnd = pos;
+    while valueEnd < json.size && json[valueEnd] != ',' && json[valueEnd] != '}' &&
+          json[valueEnd] != '\n' {
+      valueEnd += 1;
+    }
+
+    try {
+      return json[pos..<valueEnd].strip(): real;
+    } catch {
+      return defaultVal;
+    }
+  }
+
+  /*
+   * Parse the "tokens" object of tokens.json into the map
+   *
+   * Keys written before providers were lowercased ("GitLab:work") are
+   * normalized to the form the GUI uses ("gitlab:work").
+   */
+  proc parseMetadataJSON(json: string, ref metadataMap: map(string, TokenMetadata)) {
+    var pos = 0;
+    while pos < json.size {
+      // Find the next key
+      while pos < json.size && json[pos] != '"' && json[pos] != '}' do pos += 1;
+      if pos >= json.size || json[pos] == '}' then break;
+      pos += 1;
+
+      var keyEnd = pos;
+      while keyEnd < json.size && json[keyEnd] != '"' do keyEnd += 1;
+      if keyEnd >= json.size then break;
+      var key = json[pos..<keyEnd];
+      pos = keyEnd + 1;
+
+      while pos < json.size && json[pos] != '{' do pos += 1;
+      if pos >= json.size then break;
+
+      // The token's object
+      var depth = 1;
+      const objStart = pos;
+      pos += 1;
+      while pos < json.size && depth > 0 {
+        if json[pos] == '{' then depth += 1;
+        else if json[pos] == '}' then depth -= 1;
+        pos += 1;
+      }
+      const obj = json[objStart..<pos];
+
+      const colon = key.find(":");
+      if colon > 0 then key = key[..<colon].toLower() + key[colon..];
+
+      var meta = new TokenMetadata();
+      meta.identityName = GlobalConfig.extractJSONString(obj, "identityName", "");
+      meta.provider = GlobalConfig.extractJSONString(obj, "provider", "").toLower();
+      meta.createdAt = extractJSONReal(obj, "createdAt", 0.0);
+      meta.lastVerified = extractJSONReal(obj, "lastVerified", 0.0);
+      meta.expiresAt = extractJSONReal(obj, "expiresAt", 0.0);
+      meta.scopes = GlobalConfig.parseStringListJSON(
+        GlobalConfig.extractJSONSection(obj, "scopes"));
+      meta.tokenType = GlobalConfig.extractJSONString(obj, "tokenType", "pat");
+      meta.isValid = GlobalConfig.extractJSONBool(obj, "isValid", true);
+      meta.warningIssued = extractJSONReal(obj, "warningIssued", 0.0);
+      meta.entry = GlobalConfig.extractJSONString(obj, "entry", "");
+      metadataMap[key] = meta;
+    }
+  }
+
   /*
    * Save token metadata to storage
    */
@@ -158,12 +231,21 @@ prototype module TokenHealth {
         if !first then writer.writeln(",");
         first = false;
 
-        writer.writeln("    \"", key, "\": {");
-        writer.writeln("      \"identityName\": \"", meta.identityName, "\",");
+        writer.writeln("    \"", GlobalConfig.escapeJSON(key), "\": {");
+        writer.writeln("      \"identityName\": \"", GlobalConfig.escapeJSON(meta.identityName), "\",");
         writer.writeln("      \"provider\": \"", meta.provider, "\",");
         writer.writeln("      \"createdAt\": ", meta.createdAt, ",");
         writer.writeln("      \"lastVerified\": ", meta.lastVerified, ",");
         writer.writeln("      \"expiresAt\": ", meta.expiresAt, ",");
+        var scopes = "";
+        for scope in meta.scopes {
+          if scopes != "" then scopes += ", ";
+          scopes += "\"" + GlobalConfig.escapeJSON(scope) + "\"";
+        }
+        writer.writeln("      \"scopes\": [", scopes, "],");
+        if meta.entry != "" {
+          writer.writeln("      \"entry\": \"", GlobalConfig.escapeJSON(meta.entry), "\",");
+        }
         writer.writeln("      \"tokenType\": \"", meta.tokenType, "\",");
         writer.writeln("      \"isValid\": ", meta.isValid, ",");
         writer.writeln("      \"warningIssued\": ", meta.warningIssued);
@@ -187,7 +269,7 @@ prototype module TokenHealth {
    * Get metadata key for an identity
    */
   proc getMetadataKey(identity: GitIdentity): string {
-    return identity.provider:string + ":" + identity.name;
+    return providerToString(identity.provider) + ":" + identity.name;
   }
 
   /*
@@ -200,7 +282,7 @@ prototype module TokenHealth {
     if !metadataMap.contains(key) {
       var meta = new TokenMetadata();
       meta.identityName = identity.name;
-      meta.provider = identity.provider:string;
+      meta.provider = providerToString(identity.provider);
       meta.createdAt = timeSinceEpoch().totalSeconds();
       meta.lastVerified = 0.0;
       meta.expiresAt = 0.0;
@@ -269,12 +351,11 @@ prototype module TokenHealth {
       const (ok, response) = ProviderCLI.glabAPI("personal_access_tokens/self", hostname);
 
       if ok && response != "" {
-        // Token is valid, but we can't parse expiry without proper JSON parser
-        // TODO: Implement proper JSON parsing when Chapel JSON module is more mature
-        // For now, just return valid status with unknown expiry
-        var scopes: list(string);
-        scopes.pushBack("api");
-        return (true, 0.0, scopes);  // 0.0 = unknown expiry
+        // e.g. {"scopes": ["api"], "expires_at": "2026-03-01", ...}
+        const scopes = GlobalConfig.parseStringListJSON(
+          GlobalConfig.extractJSONSection(response, "scopes"));
+        const expiresAt = dateToEpoch(GlobalConfig.extractJSONString(response, "expires_at", ""));
+        return (true, expiresAt, scopes);
       }
     } catch e {
       if verbose then writeln("GitLab token verification error: ", e.message());
@@ -283,6 +364,19 @@ prototype module TokenHealth {
     return (false, 0.0, new list(string));
   }
 
+  /*
+   * Convert a YYYY-MM-DD date to Unix seconds; 0.0 if it isn't one
+   */
+  proc dateToEpoch(value: string): real {
+    if value.size < 10 then return 0.0;
+    try {
+      const day = new date(value[0..#4]: int, value[5..#2]: int, value[8..#2]: int);
+      return (day - new date(1970, 1, 1)).days * 86400.0;
+    } catch {
+      return 0.0;
+    }
+  }
+
   /*
    * Verify token with GitHub API
    *
@@ -63,13 +63,13 @@ prototype module Tools {
     // Tool: juggler_list_identities
     tools.pushBack(new ToolDefinition(
       name = "juggler_list_identities",
-      description = "List all configured git identities with their providers (GitLab, GitHub, Bitbucket, etc.). Optionally filter by provider and include credential availability status.",
+      description = "List all configured git identities with their providers (GitLab, GitHub, Bitbucket, Gitea, sourcehut, etc.). Optionally filter by provider and include credential availability status.",
       inputSchema = '{' +
         '"type":"object",' +
         '"properties":{' +
           '"provider":{' +
             '"type":"string",' +
-            '"enum":["gitlab","github","bitbucket","all"],' +
+            '"enum":["gitlab","github","bitbucket","gitea","sourcehut","all"],' +
             '"description":"Filter identities by provider type. Use \'all\' or omit for all providers."' +
           '},' +
           '"includeCredentialStatus":{' +
@@ -1438,6 +1438,7 @@ prototype module Tools {
     var sshKeyPath = "";
     var hardwareKey = false;
     var touchPolicy = "";
+    var signingSubkey = "";
 
     // Extract signing configuration from config
     if configOk {
@@ -1451,11 +1452,13 @@ prototype module Tools {
             const (_, sshKey) = Protocol.extractJsonString(gpgJson, "sshKeyPath");
             const (_, hwKey) = Protocol.extractJsonString(gpgJson, "hardwareKey");
             const (_, touch) = Protocol.extractJsonString(gpgJson, "touchPolicy");
+            const (_, subkey) = Protocol.extractJsonString(gpgJson, "signingSubkey");
 
             signingFormat = if format != "" then format else "gpg";
             sshKeyPath = sshKey;
             hardwareKey = hwKey == "true";
             touchPolicy = touch;
+            signingSubkey = subkey;
           }
         }
       }
@@ -1493,8 +1496,12 @@ prototype module Tools {
         if setGitConfig(path, "gpg.format", "gpg") {
           output += "[OK] Set signing format: gpg\n";
         }
-        if setGitConfig(path, "user.signingkey", gpgKeyId) {
-          output += "[OK] Set GPG signing key: " + gpgKeyId + "\n";
+        // A subkey with "!" makes gpg use exactly that subkey
+        const signingKey = if signingSubkey != ""
+                           then signingSubkey.strip("!", leading=false) + "!"
+                           else gpgKeyId;
+        if setGitConfig(path, "user.signingkey", signingKey) {
+          output += "[OK] Set GPG signing key: " + signingKey + "\n";
         }
         if setGitConfig(path, "commit.gpgsign", "true") {
           output += "[OK] Enabled GPG commit signing\n";
@@ -3633,7 +3640,7 @@ prototype module Tools {
     if discoverTypes == "env" || discoverTypes == "all" {
       // Ensure Discovered group exists
       try {
-        var p = spawn(["keepassxc-cli", "mkdir", dbPath, "RemoteJuggler/Discovered"],
+        var p = spawn(KeePassXC.kpxc(["mkdir", dbPath, "RemoteJuggler/Discovered"]),
                       stdin=pipeStyle.pipe, stdout=pipeStyle.close, stderr=pipeStyle.close);
         p.stdin.write(password + "\n");
         p.stdin.close();
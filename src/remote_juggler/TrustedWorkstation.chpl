@@ -733,7 +733,7 @@ prototype module TrustedWorkstation {
     for i in 0..<cfg.identities.size {
       if cfg.identities[i].name == identity {
         cfg.identities[i].gpg.securityMode = "trusted_workstation";
-        cfg.identities[i].gpg.pinStorageMethod = hsm_type_name(hsm_detect_available());
+        cfg.identities[i].gpg.pinStorageMethod = hsm_method_id(hsm_detect_available());
         break;
       }
     }
@@ -0,0 +1,327 @@
+/*
+ * GlobalConfigTests.chpl - Unit tests for GlobalConfig module
+ *
+ * Tests that saving config.json keeps what the CLI doesn't read.
+ */
+prototype module GlobalConfigTests {
+  use remote_juggler.GlobalConfig;
+  use remote_juggler.Core;
+  use TestUtils;
+
+  config const verbose = false;
+
+  // A config.json as the GUI writes it
+  const GUI_CONFIG = """{
+  "$schema": "https://remote-juggler.dev/schema/v2.json",
+  "version": "2.0.0",
+  "generated": "2026-01-12T09:30:00Z",
+  "identities": {
+    "work": {
+      "provider": "gitlab",
+      "host": "gitlab-work",
+      "hostname": "gitlab.com",
+      "user": "Work User",
+      "email": "user@company.com",
+      "sshKeyPath": "~/.ssh/gitlab-work",
+      "credentialSource": "keychain",
+      "organizations": ["company"],
+      "gpg": {
+        "keyId": "ABCD1234",
+        "signCommits": true,
+        "signTags": false,
+        "autoSignoff": false,
+        "securityMode": "developer_workflow",
+        "signingSubkey": "EF567890"
+      },
+      "tailnetOnly": true
+    },
+    "old": {
+      "provider": "github",
+      "host": "github-old",
+      "hostname": "github.com",
+      "user": "olduser",
+      "email": "old@example.com",
+      "sshKeyPath": "~/.ssh/github-old",
+      "credentialSource": "none",
+      "yubikeySerial": 12345678
+    }
+  },
+  "settings": {
+    "defaultProvider": "gitlab",
+    "autoDetect": true,
+    "useKeychain": true,
+    "gpgSign": true,
+    "gpgVerifyWithProvider": true,
+    "fallbackToSSH": true,
+    "verboseLogging": false,
+    "keepRemoteUrls": true,
+    "autoLock": {"maximumSecurity": 1, "developerWorkflow": 10, "trustedWorkstation": 0},
+    "directoryRules": [{"path": "~/work", "identity": "work"}]
+  },
+  "state": {
+    "currentIdentity": "work",
+    "lastSwitch": "2026-01-12T09:30:00Z"
+  }
+}
+""";
+
+  // Every key the GUI added to config.json, which the CLI doesn't read
+  const GUI_ONLY_CONFIG = """{
+  "version": "2.0.0",
+  "identities": {
+    "work": {
+      "provider": "github",
+      "host": "github-work",
+      "hostname": "github.com",
+      "user": "me",
+      "email": "me@acme.dev",
+      "sshKeyPath": "~/.ssh/id_work",
+      "credentialSource": "none",
+      "cloud": {"gcpProject": "acme-prod"},
+      "sshPolicy": {"forwardAgent": false},
+      "sshCertificate": {"signCommand": "step ssh certificate me {pubkey} --sign"},
+      "yubikeySerial": 12345678,
+      "pkcs11Provider": "/usr/lib/opensc-pkcs11.so",
+      "tailnetOnly": true
+    }
+  },
+  "settings": {
+    "defaultProvider": "github",
+    "autoLock": {"maximumSecurity": 1, "developerWorkflow": 10, "trustedWorkstation": 0},
+    "sshAgent": {"addOnSwitch": false, "removeOthers": true},
+    "directoryRules": [{"path": "~/work", "identity": "work"}],
+    "branchRules": [{"branch": "oss/*", "identity": "work"}],
+    "policies": [{"pattern": "github.com/acme-*", "emailDomain": "acme.dev", "requireSigning": true}],
+    "bookmarks": [{"path": "~/work/api", "identity": "work"}],
+    "ciSecrets": [{"entry": "RemoteJuggler/CI/NPM_TOKEN", "name": "NPM_TOKEN", "target": "acme/api", "identity": "work"}],
+    "restApi": {"enabled": true, "port": 7827},
+    "metrics": {"enabled": true, "port": 7828},
+    "telemetry": {"otlpEndpoint": "http://127.0.0.1:4318"},
+    "eventHooks": [{"events": ["identitySwitched"], "webhook": "https://hooks.example/x"}]
+  },
+  "state": {"currentIdentity": "work", "lastSwitch": ""}
+}
+""";
+
+  // Two identities with nested GUI-only objects
+  const NESTED_CONFIG = """{
+  "version": "2.0.0",
+  "identities": {
+    "work": {
+      "provider": "gitlab",
+      "host": "gitlab-work",
+      "hostname": "gitlab.com",
+      "user": "worker",
+      "email": "me@company.com",
+      "sshKeyPath": "~/.ssh/gitlab-work",
+      "cloud": {"gcpProject": "company-prod"},
+      "gpg": {"keyId": "ABCD1234", "signingSubkey": "EF567890", "hardwareKey": true}
+    },
+    "old": {
+      "provider": "github",
+      "host": "github-old",
+      "hostname": "github.com",
+      "user": "olduser",
+      "email": "old@example.com",
+      "sshKeyPath": "~/.ssh/github-old",
+      "cloud": {"gcpProject": "old-project", "aws": {"profile": "old-profile"}},
+      "gpg": {"keyId": "0LD0LD00", "signingSubkey": "0LDSUB00"}
+    }
+  },
+  "settings": {"defaultProvider": "gitlab"},
+  "state": {"currentIdentity": "work", "lastSwitch": ""}
+}
+""";
+
+  proc main() {
+    writeln("=== RemoteJuggler GlobalConfig Module Tests ===\n");
+
+    var passed = 0;
+    var failed = 0;
+
+    // Test 1: Members of an object, with nested values and escapes
+    {
+      writeln("Test 1: List the members of a JSON object");
+      var allPass = true;
+
+      const obj = '{"a": {"b": [1, 2]}, "c\\"d": "x,}y", "e": true}';
+      const members = jsonObjectMembers(obj);
+      if members.size != 3 {
+        writeln("  FAIL: Expected 3 members, got ", members.size);
+        allPass = false;
+      } else {
+        const (key, valueStart, valueEnd) = members[1];
+        if key != 'c\\"d' || obj[valueStart..<valueEnd] != '"x,}y"' {
+          writeln("  FAIL: Second member was '", key, "': ", obj[valueStart..<valueEnd]);
+          allPass = false;
+        }
+      }
+      const (found, value) = jsonMember(obj, "a");
+      if !found || value != '{"b": [1, 2]}' {
+        writeln("  FAIL: Expected the nested object for 'a', got '", value, "'");
+        allPass = false;
+      }
+
+      if allPass {
+        writeln("  PASS");
+        passed += 1;
+      } else {
+        failed += 1;
+      }
+    }
+
+    // Test 2: Saving after security-mode, keepRemoteUrls and remove
+    {
+      writeln("Test 2: Merge keeps GUI keys and follows the CLI's own");
+      var allPass = true;
+
+      var cfg = parseConfigJSON(GUI_CONFIG);
+      cfg.settings.defaultSecurityMode = "maximum_security";
+      cfg.settings.keepRemoteUrls = false;
+      cfg.removeIdentity("old");
+      const merged = mergeConfigJSON(serializeConfigJSON(cfg), GUI_CONFIG);
+      const (_, settings) = jsonMember(merged, "settings");
+      const (_, identities) = jsonMember(merged, "identities");
+      const (_, work) = jsonMember(identities, "work");
+      const (_, gpg) = jsonMember(work, "gpg");
+
+      if extractJSONString(settings, "defaultSecurityMode", "") != "maximum_security" {
+        writeln("  FAIL: The new security mode wasn't written");
+        allPass = false;
+      }
+      for key in ["autoLock", "directoryRules"] {
+        const (found, _) = jsonMember(settings, key);
+        if !found {
+          writeln("  FAIL: settings.", key, " was dropped");
+          allPass = false;
+        }
+      }
+      const (keepsUrls, _) = jsonMember(settings, "keepRemoteUrls");
+      if keepsUrls {
+        writeln("  FAIL: keepRemoteUrls, turned off by the CLI, came back");
+        allPass = false;
+      }
+      const (removed, _) = jsonMember(identities, "old");
+      if removed {
+        writeln("  FAIL: The removed identity came back");
+        allPass = false;
+      }
+      const (tailnetOnly, tailnetValue) = jsonMember(work, "tailnetOnly");
+      const (_, organizations) = jsonMember(work, "organizations");
+      if !tailnetOnly || tailnetValue != "true" || organizations != '["company"]' {
+        writeln("  FAIL: Identity fields the CLI doesn't read were lost");
+        allPass = false;
+      }
+      if extractJSONString(gpg, "signingSubkey", "") != "EF567890" {
+        writeln("  FAIL: gpg.signingSubkey was lost");
+        allPass = false;
+      }
+
+      if verbose then writeln(merged);
+      if allPass {
+        writeln("  PASS");
+        passed += 1;
+      } else {
+        failed += 1;
+      }
+    }
+
+    // Test 3: Every GUI-only key survives a CLI save
+    {
+      writeln("Test 3: A CLI save keeps every GUI-only key");
+      var allPass = true;
+
+      var cfg = parseConfigJSON(GUI_ONLY_CONFIG);
+      cfg.settings.hsmAvailable = true;
+      const merged = mergeConfigJSON(serializeConfigJSON(cfg), GUI_ONLY_CONFIG);
+      const (_, settings) = jsonMember(merged, "settings");
+      const (_, oldSettings) = jsonMember(GUI_ONLY_CONFIG, "settings");
+      for key in ["autoLock", "sshAgent", "directoryRules", "branchRules", "policies",
+                  "bookmarks", "ciSecrets", "restApi", "metrics", "telemetry",
+                  "eventHooks"] {
+        const (found, value) = jsonMember(settings, key);
+        const (_, expected) = jsonMember(oldSettings, key);
+        if !found || value != expected {
+          writeln("  FAIL: settings.", key, " was '", value, "'");
+          allPass = false;
+        }
+      }
+
+      const (_, identities) = jsonMember(merged, "identities");
+      const (_, work) = jsonMember(identities, "work");
+      const (_, oldIdentities) = jsonMember(GUI_ONLY_CONFIG, "identities");
+      const (_, oldWork) = jsonMember(oldIdentities, "work");
+      for key in ["credentialSource", "cloud", "sshPolicy", "sshCertificate",
+                  "yubikeySerial", "pkcs11Provider", "tailnetOnly"] {
+        const (found, value) = jsonMember(work, key);
+        const (_, expected) = jsonMember(oldWork, key);
+        if !found || value != expected {
+          writeln("  FAIL: identities.work.", key, " was '", value, "'");
+          allPass = false;
+        }
+      }
+
+      if verbose then writeln(merged);
+      if allPass {
+        writeln("  PASS");
+        passed += 1;
+      } else {
+        failed += 1;
+      }
+    }
+
+    // Test 4: A removed identity's nested keys don't come back
+    {
+      writeln("Test 4: Removing identities drops their nested keys");
+      var allPass = true;
+
+      var cfg = parseConfigJSON(NESTED_CONFIG);
+      cfg.removeIdentity("old");
+      const merged = mergeConfigJSON(serializeConfigJSON(cfg), NESTED_CONFIG);
+      const (_, identities) = jsonMember(merged, "identities");
+      const (removed, _) = jsonMember(identities, "old");
+      if removed {
+        writeln("  FAIL: The removed identity came back");
+        allPass = false;
+      }
+      for leftover in ["old-project", "old-profile", "0LDSUB00"] {
+        if merged.find(leftover) >= 0 {
+          writeln("  FAIL: '", leftover, "' from the removed identity was kept");
+          allPass = false;
+        }
+      }
+      const (_, work) = jsonMember(identities, "work");
+      const (_, gpg) = jsonMember(work, "gpg");
+      const (_, cloud) = jsonMember(work, "cloud");
+      if extractJSONString(gpg, "signingSubkey", "") != "EF567890" ||
+         extractJSONString(cloud, "gcpProject", "") != "company-prod" {
+        writeln("  FAIL: The kept identity lost its nested keys");
+        allPass = false;
+      }
+
+      // Removing every identity leaves none, not the old ones
+      var empty = parseConfigJSON(NESTED_CONFIG);
+      empty.removeIdentity("work");
+      empty.removeIdentity("old");
+      const emptied = mergeConfigJSON(serializeConfigJSON(empty), NESTED_CONFIG);
+      const (hasIdentities, noIdentities) = jsonMember(emptied, "identities");
+      if !hasIdentities || jsonObjectMembers(noIdentities).size != 0 {
+        writeln("  FAIL: Expected no identities, got '", noIdentities, "'");
+        allPass = false;
+      }
+
+      if verbose then writeln(merged);
+      if allPass {
+        writeln("  PASS");
+        passed += 1;
+      } else {
+        failed += 1;
+      }
+    }
+
+    // Summary
+    printSummary("GlobalConfig Tests", passed, failed);
+
+    if failed > 0 then exit(1);
+  }
+}
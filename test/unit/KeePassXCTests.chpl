@@ -535,6 +535,51 @@ prototype module KeePassXCTests {
       }
     }
 
+    // Test 21: progress events match the GUI's progress protocol
+    {
+      writeln("Test 21: progress event JSON");
+      var allPass = true;
+
+      const started = progressStartedJSON("crawl", 42);
+      if started != '{"event":"started","operation":"crawl","total":42}' {
+        writeln("  FAIL: started event: ", started);
+        allPass = false;
+      }
+
+      // Unknown totals are left out
+      const noTotal = progressStartedJSON("discover");
+      if noTotal != '{"event":"started","operation":"discover"}' {
+        writeln("  FAIL: started event without total: ", noTotal);
+        allPass = false;
+      }
+
+      const item = progressItemJSON(3, "~/code/app/.env", 42);
+      if item != '{"event":"progress","current":3,"total":42,"item":"~/code/app/.env"}' {
+        writeln("  FAIL: progress event: ", item);
+        allPass = false;
+      }
+
+      // Items are escaped
+      const quoted = progressItemJSON(1, 'a "b"');
+      if quoted != '{"event":"progress","current":1,"item":"a \\"b\\""}' {
+        writeln("  FAIL: escaped progress event: ", quoted);
+        allPass = false;
+      }
+
+      const finished = progressFinishedJSON("Ingested 12 entries");
+      if finished != '{"event":"finished","summary":"Ingested 12 entries"}' {
+        writeln("  FAIL: finished event: ", finished);
+        allPass = false;
+      }
+
+      if allPass {
+        writeln("  PASS");
+        passed += 1;
+      } else {
+        failed += 1;
+      }
+    }
+
     printSummary("KeePassXCTests", passed, failed);
 
     if failed > 0 then halt("Tests failed");
@@ -331,6 +331,35 @@ prototype module RemoteTests {
       }
     }
 
+    // Test 11: Gitea/Forgejo and sourcehut hosts
+    {
+      writeln("Test 11: Gitea/Forgejo and sourcehut hosts");
+      var allPass = true;
+
+      const codeberg = parseRemoteURL("git@codeberg.org:user/repo.git");
+      if codeberg.provider != Provider.Gitea {
+        writeln("  FAIL: codeberg.org should be Gitea");
+        allPass = false;
+      }
+
+      const srht = parseRemoteURL("git@git.sr.ht:~user/repo");
+      if srht.provider != Provider.Sourcehut {
+        writeln("  FAIL: git.sr.ht should be Sourcehut");
+        allPass = false;
+      }
+      if srht.repoPath != "~user/repo" {
+        writeln("  FAIL: repoPath should be '~user/repo', got '", srht.repoPath, "'");
+        allPass = false;
+      }
+
+      if allPass {
+        writeln("  PASS");
+        passed += 1;
+      } else {
+        failed += 1;
+      }
+    }
+
     // Summary
     printSummary("Remote Tests", passed, failed);
 